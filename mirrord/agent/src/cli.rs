@@ -1,7 +1,13 @@
 #![deny(missing_docs)]
 
-use clap::{Parser, Subcommand};
-use mirrord_protocol::{MeshVendor, AGENT_NETWORK_INTERFACE_ENV, AGENT_OPERATOR_CERT_ENV};
+use std::num::{NonZeroU32, NonZeroU64};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use mirrord_protocol::{
+    MeshVendor, AGENT_DNS_RATE_LIMIT_ENV, AGENT_FD_IDLE_TIMEOUT_ENV, AGENT_FD_LIMIT_ENV,
+    AGENT_FILE_OPS_RATE_LIMIT_ENV, AGENT_NETWORK_INTERFACE_ENV, AGENT_OPERATOR_CERT_ENV,
+    AGENT_SERVICE_ACCOUNT_TOKENS_ENV, AGENT_SUBSTITUTE_TOKEN_ENV, AGENT_WRITE_SPACE_MARGIN_ENV,
+};
 
 const DEFAULT_RUNTIME: &str = "containerd";
 
@@ -50,6 +56,68 @@ pub struct Args {
         env = "MIRRORD_AGENT_IN_SERVICE_MESH"
     )]
     pub is_mesh: bool,
+
+    /// Maximum number of file operations per second served for each client.
+    ///
+    /// If not given, file operations are not rate limited.
+    #[arg(long, env = AGENT_FILE_OPS_RATE_LIMIT_ENV)]
+    pub file_ops_rate_limit: Option<NonZeroU32>,
+
+    /// Maximum number of DNS requests per second served for each client.
+    ///
+    /// If not given, DNS requests are not rate limited.
+    #[arg(long, env = AGENT_DNS_RATE_LIMIT_ENV)]
+    pub dns_rate_limit: Option<NonZeroU32>,
+
+    /// Maximum number of remote files, directories and sockets kept open for each client.
+    ///
+    /// If not given, there is no limit.
+    #[arg(long, env = AGENT_FD_LIMIT_ENV)]
+    pub fd_limit: Option<NonZeroU32>,
+
+    /// Seconds after which the remote files and directories that a client doesn't use are
+    /// reported to it, so that it can close the ones that the local process leaked.
+    ///
+    /// If not given, idle descriptors are not reported.
+    #[arg(long, env = AGENT_FD_IDLE_TIMEOUT_ENV)]
+    pub fd_idle_timeout: Option<NonZeroU64>,
+
+    /// Bytes of free space that the writes of each client must leave in the filesystem of the
+    /// written file, the writes that don't fit fail with `ENOSPC` before writing anything.
+    ///
+    /// If not given, the free space is not checked.
+    #[arg(long, env = AGENT_WRITE_SPACE_MARGIN_ENV)]
+    pub write_space_margin: Option<u64>,
+
+    /// What the agent does when a client opens the service account token of the target.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ServiceAccountTokenPolicy::Allow,
+        env = AGENT_SERVICE_ACCOUNT_TOKENS_ENV
+    )]
+    pub service_account_tokens: ServiceAccountTokenPolicy,
+
+    /// Token served in place of the service account token of the target, with
+    /// [`ServiceAccountTokenPolicy::Substitute`].
+    #[arg(long, env = AGENT_SUBSTITUTE_TOKEN_ENV, hide_env_values = true)]
+    pub substitute_token: Option<String>,
+}
+
+/// What the agent does when a client opens the service account token of the target (Kubernetes,
+/// EKS pod identity or Azure workload identity).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ServiceAccountTokenPolicy {
+    /// The token is read like any other file.
+    #[default]
+    Allow,
+    /// The token is read, and the agent logs a warning.
+    Audit,
+    /// Opening the token fails with `EACCES`, and the agent logs a warning.
+    Block,
+    /// The client reads the token given with `--substitute-token` instead, a short-lived token
+    /// with narrow permissions minted by the operator.
+    Substitute,
 }
 
 impl Args {
@@ -7,7 +7,7 @@ use std::{
 
 use actix_codec::Framed;
 use futures::{SinkExt, TryStreamExt};
-use mirrord_protocol::{ClientMessage, DaemonCodec, DaemonMessage};
+use mirrord_protocol::{framing::FrameLimits, ClientMessage, DaemonCodec, DaemonMessage};
 use thiserror::Error;
 use tokio::net::TcpStream;
 use tokio_rustls::{
@@ -177,6 +177,23 @@ impl ClientConnection {
         Ok(())
     }
 
+    /// Limits of the frames that this connection accepts, see [`mirrord_protocol::framing`].
+    pub fn frame_limits(&self) -> FrameLimits {
+        match &self.framed {
+            ConnectionFramed::Tcp(framed) => framed.codec_ref().limits(),
+            ConnectionFramed::Tls(framed) => framed.codec_ref().limits(),
+        }
+    }
+
+    /// Limits of the frames that the client accepts, [`None`] until it switches to framed
+    /// messages.
+    pub fn peer_frame_limits(&self) -> Option<FrameLimits> {
+        match &self.framed {
+            ConnectionFramed::Tcp(framed) => framed.codec_ref().peer_limits(),
+            ConnectionFramed::Tls(framed) => framed.codec_ref().peer_limits(),
+        }
+    }
+
     /// Receives a [`ClientMessage`] from the client.
     #[tracing::instrument(level = "trace", err)]
     pub async fn receive(&mut self) -> io::Result<Option<ClientMessage>> {
@@ -1,9 +1,13 @@
 use std::{future, path::PathBuf, time::Duration};
 
 use futures::{stream::FuturesOrdered, StreamExt};
-use hickory_resolver::{system_conf::parse_resolv_conf, AsyncResolver, Hosts};
+use hickory_resolver::{
+    config::{LookupIpStrategy, ServerOrderingStrategy},
+    system_conf::parse_resolv_conf,
+    AsyncResolver, Hosts,
+};
 use mirrord_protocol::{
-    dns::{DnsLookup, GetAddrInfoRequest, GetAddrInfoResponse},
+    dns::{AddrInfoFamily, AddrInfoHint, DnsLookup, GetAddrInfoRequestV2, GetAddrInfoResponse},
     DnsLookupError, RemoteResult, ResolveErrorKindInternal, ResponseError,
 };
 use tokio::{
@@ -16,14 +20,17 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 use tracing::Level;
 
+use self::resolv_conf::{Libc, ResolvOptions};
 use crate::{
     error::{AgentError, Result},
     watched_task::TaskStatus,
 };
 
+mod resolv_conf;
+
 #[derive(Debug)]
 pub(crate) struct DnsCommand {
-    request: GetAddrInfoRequest,
+    request: GetAddrInfoRequestV2,
     response_tx: oneshot::Sender<RemoteResult<DnsLookup>>,
 }
 
@@ -32,8 +39,14 @@ pub(crate) struct DnsCommand {
 pub(crate) struct DnsWorker {
     etc_path: PathBuf,
     request_rx: Receiver<DnsCommand>,
-    attempts: usize,
-    timeout: Duration,
+    /// Overrides the `attempts` option of `resolv.conf`.
+    attempts: Option<usize>,
+    /// Overrides the `timeout` option of `resolv.conf`.
+    timeout: Option<Duration>,
+    /// Detected when this worker starts running.
+    libc: Libc,
+    /// Number of lookups made so far, for the `rotate` option of `resolv.conf`.
+    queries: usize,
 }
 
 impl DnsWorker {
@@ -60,18 +73,25 @@ impl DnsWorker {
             timeout: std::env::var("MIRRORD_AGENT_DNS_TIMEOUT")
                 .ok()
                 .and_then(|timeout| timeout.parse().ok())
-                .map(Duration::from_secs)
-                .unwrap_or_else(|| Duration::from_secs(1)),
+                .map(Duration::from_secs),
             attempts: std::env::var("MIRRORD_AGENT_DNS_ATTEMPTS")
                 .ok()
-                .and_then(|attempts| attempts.parse().ok())
-                .unwrap_or(1),
+                .and_then(|attempts| attempts.parse().ok()),
+            libc: Default::default(),
+            queries: 0,
         }
     }
 
     /// Reads `/etc/resolv.conf` and `/etc/hosts` files, then uses [`AsyncResolver`] to resolve
     /// address of the given `host`.
     ///
+    /// Only the address family requested in the `hints` is resolved. When the family is not
+    /// specified, we resolve only IPv4 addresses, as most of the user applications are not ready
+    /// to handle IPv6 addresses from the cluster.
+    ///
+    /// The nameservers are queried the way the `libc` of the target would, following the options
+    /// of `resolv.conf`, see [`ResolvOptions::apply`].
+    ///
     /// # TODO
     ///
     /// We could probably cache results here.
@@ -80,8 +100,11 @@ impl DnsWorker {
     async fn do_lookup(
         etc_path: PathBuf,
         host: String,
-        attempts: usize,
-        timeout: Duration,
+        hints: Option<AddrInfoHint>,
+        attempts: Option<usize>,
+        timeout: Option<Duration>,
+        libc: Libc,
+        queries: usize,
     ) -> RemoteResult<DnsLookup> {
         // Prepares the `AsyncResolver` after reading some `/etc` DNS files.
         //
@@ -93,12 +116,20 @@ impl DnsWorker {
             let resolv_conf = fs::read(resolv_conf_path).await?;
             let hosts_conf = fs::read(hosts_path).await?;
 
-            let (config, mut options) = parse_resolv_conf(resolv_conf)?;
-            options.server_ordering_strategy =
-                hickory_resolver::config::ServerOrderingStrategy::UserProvidedOrder;
-            options.timeout = timeout;
-            options.attempts = attempts;
-            options.ip_strategy = hickory_resolver::config::LookupIpStrategy::Ipv4Only;
+            let (config, mut options) = parse_resolv_conf(&resolv_conf)?;
+            let config = ResolvOptions::parse(&resolv_conf).apply(
+                config,
+                &mut options,
+                libc,
+                timeout,
+                attempts,
+                queries,
+            );
+            options.server_ordering_strategy = ServerOrderingStrategy::UserProvidedOrder;
+            options.ip_strategy = match hints.map(|hints| hints.ai_family).unwrap_or_default() {
+                AddrInfoFamily::Ipv6 => LookupIpStrategy::Ipv6Only,
+                AddrInfoFamily::Ipv4 | AddrInfoFamily::Unspecified => LookupIpStrategy::Ipv4Only,
+            };
 
             let mut resolver = AsyncResolver::tokio(config, options);
 
@@ -120,12 +151,18 @@ impl DnsWorker {
 
     /// Handles the given [`DnsCommand`] in a separate [`tokio::task`].
     #[tracing::instrument(level = Level::TRACE, skip(self))]
-    fn handle_message(&self, message: DnsCommand) {
+    fn handle_message(&mut self, message: DnsCommand) {
         let etc_path = self.etc_path.clone();
         let timeout = self.timeout;
         let attempts = self.attempts;
+        let libc = self.libc;
+        let queries = self.queries;
+        self.queries = self.queries.wrapping_add(1);
+
         let lookup_future = async move {
-            let result = Self::do_lookup(etc_path, message.request.node, attempts, timeout).await;
+            let GetAddrInfoRequestV2 { node, hints } = message.request;
+            let result =
+                Self::do_lookup(etc_path, node, hints, attempts, timeout, libc, queries).await;
 
             if let Err(result) = message.response_tx.send(result) {
                 tracing::error!(?result, "Failed to send query response");
@@ -139,6 +176,11 @@ impl DnsWorker {
         mut self,
         cancellation_token: CancellationToken,
     ) -> Result<(), AgentError> {
+        if let Some(root_path) = self.etc_path.parent() {
+            self.libc = Libc::detect(root_path).await;
+            tracing::debug!(libc = ?self.libc, "Detected the libc of the target");
+        }
+
         loop {
             tokio::select! {
                 _ = cancellation_token.cancelled() => break Ok(()),
@@ -171,14 +213,17 @@ impl DnsApi {
 
     /// Schedules a new DNS request.
     /// Results of scheduled requests are available via [`Self::recv`] (order is preserved).
-    pub(crate) async fn make_request(
+    ///
+    /// Accepts both [`GetAddrInfoRequestV2`] and the older
+    /// [`GetAddrInfoRequest`](mirrord_protocol::dns::GetAddrInfoRequest).
+    pub(crate) async fn make_request<R: Into<GetAddrInfoRequestV2>>(
         &mut self,
-        request: GetAddrInfoRequest,
+        request: R,
     ) -> Result<(), AgentError> {
         let (response_tx, response_rx) = oneshot::channel();
 
         let command = DnsCommand {
-            request,
+            request: request.into(),
             response_tx,
         };
         if self.request_tx.send(command).await.is_err() {
@@ -190,6 +235,15 @@ impl DnsApi {
         Ok(())
     }
 
+    /// Queues a response to a request that was not passed to the [`DnsWorker`] (e.g. because it
+    /// was throttled), after the responses of the requests already scheduled.
+    pub(crate) fn push_response(&mut self, response: RemoteResult<DnsLookup>) {
+        let (response_tx, response_rx) = oneshot::channel();
+        let _ = response_tx.send(response);
+
+        self.responses.push_back(response_rx);
+    }
+
     /// Returns the result of the oldest outstanding DNS request issued with this struct (see
     /// [`Self::make_request`]).
     #[tracing::instrument(level = Level::TRACE, skip(self), ret, err)]
@@ -202,7 +256,7 @@ impl DnsApi {
             ResponseError::RemoteIO(remote_ioerror) => ResponseError::DnsLookup(DnsLookupError {
                 kind: remote_ioerror.kind.into(),
             }),
-            fail @ ResponseError::DnsLookup(_) => fail,
+            fail @ (ResponseError::DnsLookup(_) | ResponseError::Throttled { .. }) => fail,
             _ => ResponseError::DnsLookup(DnsLookupError {
                 kind: ResolveErrorKindInternal::Unknown,
             }),
@@ -0,0 +1,295 @@
+//! The `resolv.conf` options that change how the libc of the target queries its nameservers, so
+//! that the [`DnsWorker`](super::DnsWorker) resolves names the way the target would.
+//!
+//! hickory parses the nameservers, search domains and `ndots`, but queries the nameservers its
+//! own way. We make it follow the [`Libc`] of the target instead:
+//!
+//! - only the first [`MAX_NAMESERVERS`] nameservers are used;
+//! - glibc waits `timeout` for each nameserver in turn, starting from the next one on every query
+//!   with `rotate`, and only uses TCP with `use-vc`;
+//! - musl queries all the nameservers at once, and sends the queries again `attempts` times within
+//!   `timeout`.
+use std::{net::SocketAddr, path::Path, time::Duration};
+
+use hickory_resolver::config::{Protocol, ResolverConfig, ResolverOpts};
+use tokio::fs;
+
+/// glibc and musl only use this many nameservers (`MAXNS`), the others are ignored.
+const MAX_NAMESERVERS: usize = 3;
+
+/// Timeout used when neither the agent configuration nor `resolv.conf` sets it.
+///
+/// Shorter than the 5 seconds of the libcs, so that the layer doesn't give up on the agent
+/// first.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Attempts made when neither the agent configuration nor `resolv.conf` sets them.
+const DEFAULT_ATTEMPTS: usize = 1;
+
+/// The libc of the target, see the [module docs](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Libc {
+    #[default]
+    Glibc,
+    Musl,
+}
+
+impl Libc {
+    /// Finds musl by its dynamic loader, `/lib/ld-musl-<arch>.so.1`, in the `root` of the target.
+    pub(crate) async fn detect(root: &Path) -> Self {
+        let Ok(mut entries) = fs::read_dir(root.join("lib")).await else {
+            return Self::Glibc;
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name().to_string_lossy().starts_with("ld-musl-") {
+                return Self::Musl;
+            }
+        }
+
+        Self::Glibc
+    }
+
+    /// The largest `timeout` (in seconds) and `attempts` that this libc accepts.
+    fn limits(self) -> (u64, usize) {
+        match self {
+            Self::Glibc => (30, 5),
+            Self::Musl => (60, 10),
+        }
+    }
+}
+
+/// The `options` of `resolv.conf` that hickory doesn't handle the way the libc does.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct ResolvOptions {
+    /// `timeout:n`, in seconds.
+    timeout: Option<u64>,
+    /// `attempts:n`.
+    attempts: Option<usize>,
+    /// `rotate`, glibc spreads the queries over the nameservers.
+    rotate: bool,
+    /// `use-vc`, glibc queries the nameservers over TCP.
+    use_vc: bool,
+    /// `edns0`, glibc advertises a bigger UDP payload size.
+    edns0: bool,
+}
+
+impl ResolvOptions {
+    /// Parses the `options` lines of `resolv.conf`. Later options override the earlier ones, and
+    /// the unknown ones are ignored, like in glibc.
+    pub(crate) fn parse(resolv_conf: &[u8]) -> Self {
+        let mut options = Self::default();
+
+        for line in String::from_utf8_lossy(resolv_conf).lines() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("options") {
+                continue;
+            }
+
+            for option in words {
+                match option.split_once(':') {
+                    Some(("timeout", value)) => {
+                        options.timeout = value.parse().ok().or(options.timeout);
+                    }
+                    Some(("attempts", value)) => {
+                        options.attempts = value.parse().ok().or(options.attempts);
+                    }
+                    None if option == "rotate" => options.rotate = true,
+                    None if option == "use-vc" => options.use_vc = true,
+                    None if option == "edns0" => options.edns0 = true,
+                    _ => {}
+                }
+            }
+        }
+
+        options
+    }
+
+    /// Makes hickory query the nameservers of `config` the way `libc` does, see the
+    /// [module docs](self).
+    ///
+    /// The `timeout` and `attempts` from the agent configuration take precedence over the ones in
+    /// `resolv.conf`. `queries` is the number of lookups made so far, which tells which
+    /// nameserver comes first with `rotate`.
+    pub(crate) fn apply(
+        &self,
+        config: ResolverConfig,
+        opts: &mut ResolverOpts,
+        libc: Libc,
+        timeout: Option<Duration>,
+        attempts: Option<usize>,
+        queries: usize,
+    ) -> ResolverConfig {
+        let (max_timeout, max_attempts) = libc.limits();
+        let timeout = timeout
+            .or_else(|| {
+                self.timeout
+                    .map(|timeout| Duration::from_secs(timeout.clamp(1, max_timeout)))
+            })
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let attempts = attempts
+            .or_else(|| {
+                self.attempts
+                    .map(|attempts| attempts.clamp(1, max_attempts))
+            })
+            .unwrap_or(DEFAULT_ATTEMPTS)
+            .max(1);
+
+        // hickory lists every nameserver twice, for UDP and TCP.
+        let mut addresses = Vec::<SocketAddr>::new();
+        for name_server in config.name_servers() {
+            if addresses.len() < MAX_NAMESERVERS && !addresses.contains(&name_server.socket_addr) {
+                addresses.push(name_server.socket_addr);
+            }
+        }
+
+        let mut tcp_only = false;
+        match libc {
+            Libc::Glibc => {
+                if self.rotate && !addresses.is_empty() {
+                    let first = queries % addresses.len();
+                    addresses.rotate_left(first);
+                }
+
+                opts.num_concurrent_reqs = 1;
+                opts.timeout = timeout;
+                opts.attempts = attempts;
+                opts.edns0 = self.edns0;
+                tcp_only = self.use_vc;
+            }
+            Libc::Musl => {
+                opts.num_concurrent_reqs = addresses.len();
+                opts.timeout = timeout / attempts as u32;
+                opts.attempts = attempts;
+            }
+        }
+
+        let name_servers = addresses
+            .iter()
+            .flat_map(|address| {
+                config.name_servers().iter().filter(move |name_server| {
+                    name_server.socket_addr == *address
+                        && (!tcp_only || name_server.protocol == Protocol::Tcp)
+                })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        ResolverConfig::from_parts(
+            config.domain().cloned(),
+            config.search().to_vec(),
+            name_servers,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_resolver::system_conf::parse_resolv_conf;
+
+    use super::*;
+
+    const RESOLV_CONF: &str = "nameserver 10.0.0.1\n\
+        nameserver 10.0.0.2\n\
+        nameserver 10.0.0.3\n\
+        nameserver 10.0.0.4\n\
+        search default.svc.cluster.local svc.cluster.local\n\
+        options ndots:5 timeout:2 rotate\n\
+        options attempts:3 use-vc edns0\n";
+
+    fn addresses(config: &ResolverConfig) -> Vec<(String, Protocol)> {
+        config
+            .name_servers()
+            .iter()
+            .map(|name_server| {
+                (
+                    name_server.socket_addr.ip().to_string(),
+                    name_server.protocol,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(
+            ResolvOptions::parse(RESOLV_CONF.as_bytes()),
+            ResolvOptions {
+                timeout: Some(2),
+                attempts: Some(3),
+                rotate: true,
+                use_vc: true,
+                edns0: true,
+            }
+        );
+    }
+
+    /// glibc asks the nameservers one at a time, starting from the next one on every query with
+    /// `rotate`, over TCP with `use-vc`.
+    #[test]
+    fn glibc() {
+        let options = ResolvOptions::parse(RESOLV_CONF.as_bytes());
+        let (config, mut opts) = parse_resolv_conf(RESOLV_CONF).unwrap();
+
+        let config = options.apply(config, &mut opts, Libc::Glibc, None, None, 4);
+
+        assert_eq!(
+            addresses(&config),
+            [
+                ("10.0.0.2".to_string(), Protocol::Tcp),
+                ("10.0.0.3".to_string(), Protocol::Tcp),
+                ("10.0.0.1".to_string(), Protocol::Tcp),
+            ]
+        );
+        assert_eq!(config.search().len(), 2);
+        assert_eq!(opts.ndots, 5);
+        assert_eq!(opts.num_concurrent_reqs, 1);
+        assert_eq!(opts.timeout, Duration::from_secs(2));
+        assert_eq!(opts.attempts, 3);
+        assert!(opts.edns0);
+    }
+
+    /// musl asks all the nameservers at once, and sends the queries again within the timeout.
+    #[test]
+    fn musl() {
+        let options = ResolvOptions::parse(b"options timeout:6 attempts:3 use-vc");
+        let (config, mut opts) =
+            parse_resolv_conf("nameserver 10.0.0.1\nnameserver 10.0.0.2\n").unwrap();
+
+        let config = options.apply(config, &mut opts, Libc::Musl, None, None, 0);
+
+        assert_eq!(
+            addresses(&config),
+            [
+                ("10.0.0.1".to_string(), Protocol::Udp),
+                ("10.0.0.1".to_string(), Protocol::Tcp),
+                ("10.0.0.2".to_string(), Protocol::Udp),
+                ("10.0.0.2".to_string(), Protocol::Tcp),
+            ]
+        );
+        assert_eq!(opts.num_concurrent_reqs, 2);
+        assert_eq!(opts.timeout, Duration::from_secs(2));
+        assert_eq!(opts.attempts, 3);
+    }
+
+    /// The agent configuration takes precedence, and the defaults are used when neither sets the
+    /// options.
+    #[test]
+    fn agent_configuration_and_defaults() {
+        let (config, mut opts) = parse_resolv_conf("nameserver 10.0.0.1\n").unwrap();
+        ResolvOptions::parse(b"options timeout:60 attempts:9").apply(
+            config.clone(),
+            &mut opts,
+            Libc::Glibc,
+            Some(Duration::from_secs(3)),
+            None,
+            0,
+        );
+        assert_eq!(opts.timeout, Duration::from_secs(3));
+        assert_eq!(opts.attempts, 5);
+
+        ResolvOptions::default().apply(config, &mut opts, Libc::Glibc, None, None, 0);
+        assert_eq!(opts.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(opts.attempts, DEFAULT_ATTEMPTS);
+    }
+}
@@ -1,7 +1,8 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     mem,
     net::{Ipv4Addr, SocketAddrV4},
+    num::NonZeroU32,
     path::PathBuf,
     sync::{
         atomic::{AtomicU32, Ordering},
@@ -12,7 +13,23 @@ use std::{
 use client_connection::AgentTlsConnector;
 use dns::{DnsCommand, DnsWorker};
 use futures::TryFutureExt;
-use mirrord_protocol::{ClientMessage, DaemonMessage, GetEnvVarsRequest, LogMessage};
+use mirrord_protocol::{
+    clock::ClockProbeResponse,
+    fd_budget::CloseFdsRequest,
+    file::{
+        FollowChunkResponse, FollowFileRequest, PrefetchTreeRequest, ReadStreamFileRequest,
+        SendFileRequest, SendFileResponse, UnfollowFileRequest,
+    },
+    outgoing::{
+        tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
+        udp::{DaemonUdpOutgoing, LayerUdpOutgoing},
+        LayerClose, LayerWrite,
+    },
+    tcp::{DaemonTcp, LayerTcpSteal, StealType, TcpData},
+    watch::{AddWatchRequest, DaemonWatch, LayerWatch},
+    BlockedAction, ClientMessage, DaemonMessage, FileRequest, FileResponse, GetEnvVarsRequest,
+    LogMessage, Port, RemoteResult, ResponseError,
+};
 use sniffer::tcp_capture::RawSocketTcpCapture;
 use tokio::{
     net::{TcpListener, TcpStream},
@@ -33,9 +50,19 @@ use crate::{
     container_handle::ContainerHandle,
     dns::DnsApi,
     error::{AgentError, Result},
-    file::FileManager,
+    fd_budget::{ConnectAdmission, FdBudget, Outgoing},
+    file::{
+        follow::FollowManager, prefetch::PrefetchWalk, service_account::TokenAccess,
+        watch::WatchManager, FileManager,
+    },
+    freeze::{FreezeGuard, TargetFreezer},
+    interfaces,
     outgoing::{TcpOutgoingApi, UdpOutgoingApi},
+    output::TargetOutput,
+    rate_limit::ClientRateLimits,
+    reachability,
     runtime::get_container,
+    sandbox::Sandbox,
     sniffer::{api::TcpSnifferApi, messages::SnifferCommand, TcpConnectionSniffer},
     steal::{
         ip_tables::{
@@ -68,6 +95,22 @@ struct State {
     ephemeral: bool,
     /// When present, it is used to secure incoming TCP connections.
     tls_connector: Option<AgentTlsConnector>,
+    /// Per-client limit of file operations per second.
+    file_ops_rate_limit: Option<NonZeroU32>,
+    /// Per-client limit of DNS requests per second.
+    dns_rate_limit: Option<NonZeroU32>,
+    /// Per-client limit of open remote files, directories and sockets.
+    fd_limit: Option<NonZeroU32>,
+    /// Files and directories that a client doesn't use for this long are reported to it.
+    fd_idle_timeout: Option<Duration>,
+    /// Per-client free space that the remote writes must leave.
+    write_space_margin: Option<u64>,
+    /// What the clients get when they open the service account token of the target.
+    token_access: TokenAccess,
+    /// Sandboxed runtime that the agent runs in, if any.
+    sandbox: Option<Sandbox>,
+    /// Freezes the target container for the clients that sent [`ClientMessage::FreezeTarget`].
+    freezer: Option<TargetFreezer>,
 }
 
 impl State {
@@ -120,12 +163,34 @@ impl State {
             }
         };
 
+        let sandbox = Sandbox::detect();
+        if let Some(sandbox) = sandbox {
+            info!(%sandbox, "Agent is running in a sandboxed runtime");
+        }
+
+        let freezer = container
+            .as_ref()
+            .map(|container| TargetFreezer::new(container.pid()));
+
         Ok(State {
             next_client_id: Default::default(),
             container,
             env: Arc::new(env),
             ephemeral,
             tls_connector,
+            file_ops_rate_limit: args.file_ops_rate_limit,
+            dns_rate_limit: args.dns_rate_limit,
+            fd_limit: args.fd_limit,
+            fd_idle_timeout: args
+                .fd_idle_timeout
+                .map(|secs| Duration::from_secs(secs.get())),
+            write_space_margin: args.write_space_margin,
+            token_access: TokenAccess::new(
+                args.service_account_tokens,
+                args.substitute_token.clone(),
+            ),
+            sandbox,
+            freezer,
         })
     }
 
@@ -194,15 +259,37 @@ struct ClientConnectionHandler {
     id: ClientId,
     /// Handles mirrord's file operations, see [`FileManager`].
     file_manager: FileManager,
+    /// The client's watches of remote files, see [`WatchManager`].
+    watches: WatchManager,
+    /// The remote files that the client follows, see [`FollowManager`].
+    follows: FollowManager,
     connection: ClientConnection,
     tcp_sniffer_api: Option<TcpSnifferApi>,
     tcp_stealer_api: Option<TcpStealerApi>,
     tcp_outgoing_api: TcpOutgoingApi,
     udp_outgoing_api: UdpOutgoingApi,
     dns_api: DnsApi,
+    /// Throttles the client's file operations and DNS requests, see [`ClientRateLimits`].
+    rate_limits: ClientRateLimits,
+    /// Caps the client's open files, directories and sockets, see [`FdBudget`].
+    fd_budget: FdBudget,
+    /// Writes the output of the local process to the target, see [`TargetOutput`].
+    target_output: TargetOutput,
     state: State,
     /// Whether the client has sent us [`ClientMessage::ReadyForLogs`].
     ready_for_logs: bool,
+    /// Whether the client has sent us [`ClientMessage::ReadOnly`].
+    read_only: bool,
+    /// Whether the client has sent us [`ClientMessage::FreezeTarget`].
+    freeze_target: bool,
+    /// Ports that the client subscribed to with [`StealType::All`], until the agent responds to
+    /// the subscription.
+    pending_full_steal: HashSet<Port>,
+    /// Ports that the client steals all traffic from.
+    full_steal: HashSet<Port>,
+    /// Keeps the target container frozen while the client steals all traffic from any port, see
+    /// [`Self::update_freeze`].
+    freeze_guard: Option<FreezeGuard>,
 }
 
 impl ClientConnectionHandler {
@@ -215,7 +302,9 @@ impl ClientConnectionHandler {
     ) -> Result<Self> {
         let pid = state.container_pid();
 
-        let file_manager = FileManager::new(pid.or_else(|| state.ephemeral.then_some(1)));
+        let mut file_manager = FileManager::new(pid.or_else(|| state.ephemeral.then_some(1)));
+        file_manager.set_token_access(state.token_access.clone());
+        file_manager.set_write_space_margin(state.write_space_margin);
 
         let tcp_sniffer_api = Self::create_sniffer_api(id, bg_tasks.sniffer, &mut connection).await;
         let tcp_stealer_api =
@@ -225,17 +314,32 @@ impl ClientConnectionHandler {
         let tcp_outgoing_api = TcpOutgoingApi::new(pid);
         let udp_outgoing_api = UdpOutgoingApi::new(pid);
 
+        let rate_limits = ClientRateLimits::new(state.file_ops_rate_limit, state.dns_rate_limit);
+        let fd_budget = FdBudget::new(state.fd_limit, state.fd_idle_timeout);
+
+        let target_output = TargetOutput::new(pid.or_else(|| state.ephemeral.then_some(1)));
+
         let client_handler = Self {
             id,
             file_manager,
+            watches: Default::default(),
+            follows: Default::default(),
             connection,
             tcp_sniffer_api,
             tcp_stealer_api,
             tcp_outgoing_api,
             udp_outgoing_api,
             dns_api,
+            rate_limits,
+            fd_budget,
+            target_output,
             state,
             ready_for_logs: false,
+            read_only: false,
+            freeze_target: false,
+            pending_full_steal: Default::default(),
+            full_steal: Default::default(),
+            freeze_guard: None,
         };
 
         Ok(client_handler)
@@ -357,21 +461,60 @@ impl ClientConnectionHandler {
                         unreachable!()
                     }
                 }, if self.tcp_stealer_api.is_some() => match message {
-                    Ok(message) => self.respond(DaemonMessage::TcpSteal(message)).await?,
+                    Ok(message) => {
+                        if let DaemonTcp::SubscribeResult(Ok(port)) = &message
+                            && self.pending_full_steal.remove(port)
+                        {
+                            self.full_steal.insert(*port);
+                            self.update_freeze().await?;
+                        }
+
+                        self.respond(DaemonMessage::TcpSteal(message)).await?
+                    }
                     Err(e) => break e,
                 },
                 message = self.tcp_outgoing_api.recv_from_task() => match message {
-                    Ok(message) => self.respond(DaemonMessage::TcpOutgoing(message)).await?,
+                    Ok(DaemonTcpOutgoing::Connect(connect)) => {
+                        for connect in self.fd_budget.connect_response(Outgoing::Tcp, connect) {
+                            let message = DaemonTcpOutgoing::Connect(connect);
+                            self.respond(DaemonMessage::TcpOutgoing(message)).await?;
+                        }
+                    }
+                    Ok(message) => {
+                        if let DaemonTcpOutgoing::Close(connection_id) = &message {
+                            self.fd_budget.socket_closed(Outgoing::Tcp, *connection_id);
+                        }
+
+                        self.respond(DaemonMessage::TcpOutgoing(message)).await?
+                    }
                     Err(e) => break e,
                 },
                 message = self.udp_outgoing_api.daemon_message() => match message {
-                    Ok(message) => self.respond(DaemonMessage::UdpOutgoing(message)).await?,
+                    Ok(DaemonUdpOutgoing::Connect(connect)) => {
+                        for connect in self.fd_budget.connect_response(Outgoing::Udp, connect) {
+                            let message = DaemonUdpOutgoing::Connect(connect);
+                            self.respond(DaemonMessage::UdpOutgoing(message)).await?;
+                        }
+                    }
+                    Ok(message) => {
+                        if let DaemonUdpOutgoing::Close(connection_id) = &message {
+                            self.fd_budget.socket_closed(Outgoing::Udp, *connection_id);
+                        }
+
+                        self.respond(DaemonMessage::UdpOutgoing(message)).await?
+                    }
                     Err(e) => break e,
                 },
                 message = self.dns_api.recv() => match message {
                     Ok(message) => self.respond(DaemonMessage::GetAddrInfoResponse(message)).await?,
                     Err(e) => break e,
                 },
+                event = self.watches.next_event() => match event {
+                    Ok(event) => self.respond(DaemonMessage::Watch(DaemonWatch::Event(event))).await?,
+                    Err(e) => break e.into(),
+                },
+                _ = self.follows.tick() => self.send_followed().await?,
+                _ = self.fd_budget.idle_tick() => self.report_idle_fds().await?,
                 // message = self.vpn_api.daemon_message() => match message{
                 //     Ok(message) => self.respond(DaemonMessage::Vpn(message)).await?,
                 //     Err(e) => break e,
@@ -387,12 +530,143 @@ impl ClientConnectionHandler {
         Err(error)
     }
 
+    /// Reads the bytes of a [`SendFileRequest`] from the remote file.
+    ///
+    /// Empty bytes must not be written to the connection, as they would shut it down.
+    async fn read_for_send_file(&mut self, request: &SendFileRequest) -> RemoteResult<Vec<u8>> {
+        if let Some(throttled) = self.rate_limits.send_file_request().await {
+            return Err(throttled);
+        }
+
+        self.fd_budget.fd_used(request.fd);
+        self.file_manager.read_for_send_file(request)
+    }
+
+    /// Handles [`FileRequest::ReadStream`], sending every chunk as soon as it's read, so that the
+    /// whole read is never in memory.
+    async fn read_stream(&mut self, request: ReadStreamFileRequest) -> Result<()> {
+        let max_chunk_size = self.max_chunk_size();
+        let mut streamed = 0;
+
+        for sequence in 0.. {
+            let chunk =
+                self.file_manager
+                    .read_stream_chunk(&request, sequence, streamed, max_chunk_size);
+            let done = match &chunk {
+                Ok(chunk) => {
+                    streamed += chunk.bytes.len() as u64;
+                    chunk.last
+                }
+                Err(..) => true,
+            };
+
+            self.respond(DaemonMessage::File(FileResponse::ReadChunk(chunk)))
+                .await?;
+
+            if done {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles [`FileRequest::PrefetchTree`], sending every chunk as soon as it's read, so that
+    /// the client gets the first directories while we walk the rest of the tree.
+    async fn prefetch_tree(&mut self, request: PrefetchTreeRequest) -> Result<()> {
+        let max_chunk_size = self.max_chunk_size();
+        let mut walk = PrefetchWalk::new(request, &self.file_manager);
+
+        while let Some(chunk) = walk.next_chunk(&self.file_manager, max_chunk_size) {
+            self.respond(DaemonMessage::File(FileResponse::PrefetchChunk(chunk)))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the bytes appended to the files that the client follows, see [`FollowManager`].
+    async fn send_followed(&mut self) -> Result<()> {
+        let max_chunk_size = self.max_chunk_size();
+
+        for chunk in self
+            .follows
+            .read_appended(&mut self.file_manager, max_chunk_size)
+        {
+            self.respond(DaemonMessage::File(FileResponse::FollowChunk(chunk)))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the files and directories that the client didn't use for a while, see
+    /// [`FdBudget::idle_fds`].
+    async fn report_idle_fds(&mut self) -> Result<()> {
+        let idle = self.fd_budget.idle_fds(&self.file_manager);
+        if idle.is_empty() {
+            return Ok(());
+        }
+
+        self.respond(DaemonMessage::IdleFds(idle)).await
+    }
+
+    /// Most bytes of a file that fit in a single message to the client.
+    fn max_chunk_size(&self) -> u64 {
+        self.connection
+            .peer_frame_limits()
+            .map_or(u64::MAX, |limits| limits.max_chunk_size() as u64)
+    }
+
     /// Sends a [`DaemonMessage`] response to the connected client (`mirrord-layer`).
     #[tracing::instrument(level = "trace", skip(self))]
     async fn respond(&mut self, response: DaemonMessage) -> Result<()> {
         self.connection.send(response).await.map_err(Into::into)
     }
 
+    /// Freezes the target container when the client sent [`ClientMessage::FreezeTarget`] and
+    /// steals all traffic from any port, and thaws it otherwise.
+    ///
+    /// When freezing fails, the client is warned and we don't try again.
+    async fn update_freeze(&mut self) -> Result<()> {
+        if !self.freeze_target || self.full_steal.is_empty() {
+            self.freeze_guard = None;
+            return Ok(());
+        }
+
+        if self.freeze_guard.is_some() {
+            return Ok(());
+        }
+
+        let result = match &self.state.freezer {
+            Some(freezer) => freezer.freeze().map_err(|error| error.to_string()),
+            None => Err("there is no target container".to_string()),
+        };
+
+        match result {
+            Ok(guard) => {
+                info!("Client {} froze the target container", self.id);
+                self.freeze_guard = Some(guard);
+            }
+            Err(error) => {
+                self.freeze_target = false;
+
+                let message = format!(
+                    "failed to freeze the target container, it will keep running while the \
+                    traffic is stolen: {error}"
+                );
+                warn!("Client {}: {message}", self.id);
+
+                if self.ready_for_logs {
+                    self.respond(DaemonMessage::LogMessage(LogMessage::warn(message)))
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Handles incoming messages from the connected client (`mirrord-layer`).
     ///
     /// Returns `false` if the client disconnected.
@@ -400,7 +674,42 @@ impl ClientConnectionHandler {
     async fn handle_client_message(&mut self, message: ClientMessage) -> Result<bool> {
         match message {
             ClientMessage::FileRequest(req) => {
-                if let Some(response) = self.file_manager.handle_message(req)? {
+                self.fd_budget.file_request_used(&req);
+
+                let response = match (self.rate_limits.file_request(&req).await, req) {
+                    (Some(throttled), _) => Some(throttled),
+                    (None, FileRequest::ReadStream(request)) => {
+                        self.read_stream(request).await?;
+                        None
+                    }
+                    (None, FileRequest::PrefetchTree(request)) => {
+                        self.prefetch_tree(request).await?;
+                        None
+                    }
+                    // Failing to follow stops the follow right away.
+                    (None, FileRequest::Follow(FollowFileRequest { id, fd })) => self
+                        .follows
+                        .follow(id, fd, &mut self.file_manager)
+                        .err()
+                        .map(|_| {
+                            FileResponse::FollowChunk(FollowChunkResponse {
+                                id,
+                                bytes: Vec::new(),
+                                last: true,
+                            })
+                        }),
+                    (None, FileRequest::Unfollow(UnfollowFileRequest { id, consumed })) => {
+                        self.follows.unfollow(id, consumed, &mut self.file_manager);
+                        None
+                    }
+                    (None, req) => match self.fd_budget.file_request(&req, &self.file_manager) {
+                        Some(rejected) => Some(rejected),
+                        None => self.file_manager.handle_message(req)?,
+                    },
+                };
+
+                if let Some(response) = response {
+                    self.fd_budget.file_response_used(&response);
                     self.respond(DaemonMessage::File(response))
                         .await
                         .inspect_err(|fail| {
@@ -411,10 +720,77 @@ impl ClientConnectionHandler {
                         })?
                 }
             }
+            ClientMessage::TcpOutgoing(LayerTcpOutgoing::SendFile(request)) => {
+                let response = match self.read_for_send_file(&request).await {
+                    Ok(bytes) if bytes.is_empty() => Ok(SendFileResponse { sent_amount: 0 }),
+                    Ok(bytes) => {
+                        let sent_amount = bytes.len() as u64;
+                        self.tcp_outgoing_api
+                            .send_to_task(LayerTcpOutgoing::Write(LayerWrite {
+                                connection_id: request.connection_id,
+                                bytes,
+                            }))
+                            .await?;
+                        Ok(SendFileResponse { sent_amount })
+                    }
+                    Err(error) => Err(error),
+                };
+
+                self.respond(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::SendFile(
+                    response,
+                )))
+                .await?
+            }
+            ClientMessage::TcpOutgoing(LayerTcpOutgoing::Connect(connect)) => {
+                match self
+                    .fd_budget
+                    .connect_request(Outgoing::Tcp, &self.file_manager)
+                {
+                    ConnectAdmission::Allowed => {
+                        self.tcp_outgoing_api
+                            .send_to_task(LayerTcpOutgoing::Connect(connect))
+                            .await?
+                    }
+                    ConnectAdmission::Rejected(error) => {
+                        self.respond(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Connect(Err(
+                            error,
+                        ))))
+                        .await?
+                    }
+                    ConnectAdmission::Deferred => {}
+                }
+            }
             ClientMessage::TcpOutgoing(layer_message) => {
+                if let LayerTcpOutgoing::Close(LayerClose { connection_id }) = &layer_message {
+                    self.fd_budget.socket_closed(Outgoing::Tcp, *connection_id);
+                }
+
                 self.tcp_outgoing_api.send_to_task(layer_message).await?
             }
+            ClientMessage::UdpOutgoing(LayerUdpOutgoing::Connect(connect)) => {
+                match self
+                    .fd_budget
+                    .connect_request(Outgoing::Udp, &self.file_manager)
+                {
+                    ConnectAdmission::Allowed => {
+                        self.udp_outgoing_api
+                            .layer_message(LayerUdpOutgoing::Connect(connect))
+                            .await?
+                    }
+                    ConnectAdmission::Rejected(error) => {
+                        self.respond(DaemonMessage::UdpOutgoing(DaemonUdpOutgoing::Connect(Err(
+                            error,
+                        ))))
+                        .await?
+                    }
+                    ConnectAdmission::Deferred => {}
+                }
+            }
             ClientMessage::UdpOutgoing(layer_message) => {
+                if let LayerUdpOutgoing::Close(LayerClose { connection_id }) = &layer_message {
+                    self.fd_budget.socket_closed(Outgoing::Udp, *connection_id);
+                }
+
                 self.udp_outgoing_api.layer_message(layer_message).await?
             }
             ClientMessage::GetEnvVarsRequest(GetEnvVarsRequest {
@@ -433,7 +809,33 @@ impl ClientConnectionHandler {
                     .await?
             }
             ClientMessage::GetAddrInfoRequest(request) => {
-                self.dns_api.make_request(request).await?;
+                match self.rate_limits.dns_request().await {
+                    Some(throttled) => self.dns_api.push_response(Err(throttled)),
+                    None => self.dns_api.make_request(request).await?,
+                }
+            }
+            ClientMessage::GetAddrInfoRequestV2(request) => {
+                match self.rate_limits.dns_request().await {
+                    Some(throttled) => self.dns_api.push_response(Err(throttled)),
+                    None => self.dns_api.make_request(request).await?,
+                }
+            }
+            ClientMessage::Output(..) if self.read_only => {
+                trace!("Client {} is read-only, dropping its output", self.id);
+            }
+            ClientMessage::Output(output) => {
+                if let Err(error) = self.target_output.write(output).await {
+                    let message = format!(
+                        "failed to write the output of the local process to the target, \
+                        the output will not be sent to the target anymore: {error}"
+                    );
+                    warn!("Client {}: {message}", self.id);
+
+                    if self.ready_for_logs {
+                        self.respond(DaemonMessage::LogMessage(LogMessage::warn(message)))
+                            .await?;
+                    }
+                }
             }
             ClientMessage::Ping => self.respond(DaemonMessage::Pong).await?,
             ClientMessage::Tcp(message) => {
@@ -444,9 +846,78 @@ impl ClientConnectionHandler {
                     Err(AgentError::SnifferNotRunning)?
                 }
             }
+            ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(steal_type)) if self.read_only => {
+                warn!(
+                    ?steal_type,
+                    "Client {} is read-only, rejecting steal", self.id
+                );
+
+                self.respond(DaemonMessage::TcpSteal(DaemonTcp::SubscribeResult(Err(
+                    ResponseError::ReadOnlySession(BlockedAction::Steal(steal_type)),
+                ))))
+                .await?
+            }
+            ClientMessage::TcpSteal(LayerTcpSteal::ReservePort(reservation)) if self.read_only => {
+                warn!(
+                    ?reservation,
+                    "Client {} is read-only, rejecting port reservation", self.id
+                );
+
+                self.respond(DaemonMessage::TcpSteal(DaemonTcp::PortReserved(Err(
+                    ResponseError::ReadOnlySession(BlockedAction::Steal(StealType::Reserved(
+                        reservation.port,
+                    ))),
+                ))))
+                .await?
+            }
+            // Would adopt the steal subscriptions of a previous session.
+            ClientMessage::TcpSteal(LayerTcpSteal::Handoff(handoff)) if self.read_only => {
+                warn!(
+                    ?handoff,
+                    "Client {} is read-only, ignoring steal handoff", self.id
+                );
+            }
+            ClientMessage::TcpSteal(LayerTcpSteal::SendFile(request)) => {
+                if self.tcp_stealer_api.is_none() {
+                    warn!("received tcp steal request while not available");
+                    Err(AgentError::StealerNotRunning)?
+                }
+
+                let response = match self.read_for_send_file(&request).await {
+                    Ok(bytes) if bytes.is_empty() => Ok(SendFileResponse { sent_amount: 0 }),
+                    Ok(bytes) => {
+                        let sent_amount = bytes.len() as u64;
+                        if let Some(tcp_stealer_api) = self.tcp_stealer_api.as_mut() {
+                            tcp_stealer_api
+                                .handle_client_message(LayerTcpSteal::Data(TcpData {
+                                    connection_id: request.connection_id,
+                                    bytes,
+                                }))
+                                .await?;
+                        }
+                        Ok(SendFileResponse { sent_amount })
+                    }
+                    Err(error) => Err(error),
+                };
+
+                self.respond(DaemonMessage::TcpSteal(DaemonTcp::SendFile(response)))
+                    .await?
+            }
             ClientMessage::TcpSteal(message) => {
                 if let Some(tcp_stealer_api) = self.tcp_stealer_api.as_mut() {
-                    tcp_stealer_api.handle_client_message(message).await?
+                    match &message {
+                        LayerTcpSteal::PortSubscribe(StealType::All(port)) => {
+                            self.pending_full_steal.insert(*port);
+                        }
+                        LayerTcpSteal::PortUnsubscribe(port) => {
+                            self.pending_full_steal.remove(port);
+                            self.full_steal.remove(port);
+                        }
+                        _ => {}
+                    }
+
+                    tcp_stealer_api.handle_client_message(message).await?;
+                    self.update_freeze().await?;
                 } else {
                     warn!("received tcp steal request while not available");
                     Err(AgentError::StealerNotRunning)?
@@ -463,6 +934,9 @@ impl ClientConnectionHandler {
             }
             ClientMessage::SwitchProtocolVersion(client_version) => {
                 let settled_version = client_version.min(mirrord_protocol::VERSION.clone());
+                self.rate_limits.set_protocol_version(&settled_version);
+                self.fd_budget.set_protocol_version(&settled_version);
+                self.file_manager.set_protocol_version(&settled_version);
                 if let Some(tcp_stealer_api) = self.tcp_stealer_api.as_mut() {
                     tcp_stealer_api
                         .switch_protocol_version(settled_version.clone())
@@ -476,6 +950,81 @@ impl ClientConnectionHandler {
             }
             ClientMessage::ReadyForLogs => {
                 self.ready_for_logs = true;
+
+                if let Some(sandbox) = self.state.sandbox
+                    && self.state.container.is_some()
+                {
+                    let report = sandbox.report(
+                        self.tcp_sniffer_api.is_some(),
+                        self.tcp_stealer_api.is_some(),
+                    );
+                    self.respond(DaemonMessage::LogMessage(LogMessage::warn(report)))
+                        .await?;
+                }
+            }
+            ClientMessage::ReadOnly => {
+                info!("Client {} made its session read-only", self.id);
+
+                self.read_only = true;
+                self.file_manager.set_read_only();
+            }
+            ClientMessage::FreezeTarget => {
+                self.freeze_target = true;
+                self.update_freeze().await?;
+            }
+            ClientMessage::ClockProbe => {
+                self.respond(DaemonMessage::ClockProbeResponse(ClockProbeResponse::now()))
+                    .await?;
+            }
+            ClientMessage::CheckReachability(request) => {
+                let response =
+                    reachability::check_reachability(self.state.container_pid(), request).await;
+                self.respond(DaemonMessage::ReachabilityResponse(response))
+                    .await?;
+            }
+            ClientMessage::GetIfAddrsRequest(..) => {
+                let response = interfaces::get_ifaddrs(self.state.container_pid()).await;
+                self.respond(DaemonMessage::GetIfAddrsResponse(response))
+                    .await?;
+            }
+            ClientMessage::FdUsageRequest(..) => {
+                let usage = self.fd_budget.usage(&self.file_manager);
+                self.respond(DaemonMessage::FdUsage(usage)).await?;
+            }
+            ClientMessage::CloseFds(CloseFdsRequest { files, dirs }) => {
+                debug!(
+                    ?files,
+                    ?dirs,
+                    "Client {} closed its leaked descriptors",
+                    self.id
+                );
+
+                files.into_iter().for_each(|fd| self.file_manager.close(fd));
+                dirs.into_iter()
+                    .for_each(|fd| self.file_manager.close_dir(fd));
+            }
+            ClientMessage::Watch(LayerWatch::Add(AddWatchRequest { path, mask })) => {
+                let result = self
+                    .file_manager
+                    .watch_path(path)
+                    .and_then(|path| self.watches.add(path, mask));
+                self.respond(DaemonMessage::Watch(DaemonWatch::Added(result)))
+                    .await?;
+            }
+            ClientMessage::Watch(LayerWatch::Remove(id)) => self.watches.remove(id),
+            ClientMessage::SwitchFraming(limits) => {
+                debug!(?limits, "Client {} switched to framed messages", self.id);
+
+                self.respond(DaemonMessage::SwitchFramingResponse(
+                    self.connection.frame_limits(),
+                ))
+                .await?;
+            }
+            ClientMessage::SwitchCompression(settings) => {
+                debug!(?settings, "Client {} switched on compression", self.id);
+            }
+            ClientMessage::Compressed(..) => {
+                unreachable!("compressed messages are unwrapped by the codec")
             }
             ClientMessage::Vpn(_message) => {
                 unreachable!("VPN is not supported");
@@ -783,6 +1332,13 @@ async fn start_iptable_guard(args: Args) -> Result<()> {
         result = run_child_agent() => result,
     };
 
+    // The agent may have been killed, or panicked, with the target frozen.
+    if let Some(pid) = pid {
+        if let Err(error) = TargetFreezer::new(pid).thaw_abandoned() {
+            error!(%error, "start_iptable_guard -> failed to thaw the target container");
+        }
+    }
+
     let _ = run_thread_in_namespace(
         clear_iptable_chain(),
         "clear iptables".to_owned(),
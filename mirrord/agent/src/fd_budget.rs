@@ -0,0 +1,548 @@
+//! Per-client cap of the remote files, directories and sockets that the agent keeps open.
+//!
+//! The limit is set by the operator (see [`AGENT_FD_LIMIT_ENV`]), so that a local process that
+//! leaks descriptors can't exhaust the descriptors of the agent, which are shared by all of its
+//! clients.
+//!
+//! Clients that support [`FD_BUDGET_VERSION`] get a [`ResponseError::FdLimitReached`] response,
+//! older clients get `EMFILE`, like they would from the local kernel.
+//!
+//! The operator can also set an idle timeout (see [`AGENT_FD_IDLE_TIMEOUT_ENV`]), and the files
+//! and directories that the client didn't use for that long are reported to clients that support
+//! [`FD_GC_VERSION`], see [`FdBudget::idle_fds`].
+//!
+//! [`AGENT_FD_LIMIT_ENV`]: mirrord_protocol::AGENT_FD_LIMIT_ENV
+//! [`AGENT_FD_IDLE_TIMEOUT_ENV`]: mirrord_protocol::AGENT_FD_IDLE_TIMEOUT_ENV
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    num::NonZeroU32,
+    time::Duration,
+};
+
+use mirrord_protocol::{
+    fd_budget::{FdKind, FdUsage, IdleFds, FD_BUDGET_VERSION, FD_GC_VERSION},
+    file::{
+        CopyFileRangeRequest, LinkFileWithDirRequest, OpenFileResponse, RenameFileWithDirRequest,
+    },
+    outgoing::DaemonConnect,
+    ConnectionId, FileRequest, FileResponse, RemoteResult, ResponseError,
+};
+use semver::Version;
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+use tracing::Level;
+
+use crate::file::FileManager;
+
+/// The outgoing traffic feature that a socket belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outgoing {
+    Tcp,
+    Udp,
+}
+
+/// What to do with a connect request of the client, see [`FdBudget::connect_request`].
+#[derive(Debug)]
+pub(crate) enum ConnectAdmission {
+    /// Forward the request to the outgoing task.
+    Allowed,
+    /// Respond with this error right away.
+    Rejected(ResponseError),
+    /// The request is rejected, but the error is sent after the responses to the earlier
+    /// requests, see [`FdBudget::connect_response`].
+    Deferred,
+}
+
+/// The outgoing sockets of a client, for one [`Outgoing`] feature.
+#[derive(Debug, Default)]
+struct OutgoingSockets {
+    connected: HashSet<ConnectionId>,
+    /// Connect requests that didn't get a response yet, oldest first.
+    ///
+    /// The outgoing tasks respond to the requests in order, and the client relies on it, so
+    /// the rejected requests are queued here with their error, and answered right after the
+    /// requests that were sent before them.
+    pending: VecDeque<Option<ResponseError>>,
+}
+
+impl OutgoingSockets {
+    /// Connected sockets, and the ones that are connecting.
+    fn count(&self) -> u64 {
+        let connecting = self.pending.iter().filter(|error| error.is_none()).count();
+        (self.connected.len() + connecting) as u64
+    }
+}
+
+/// How often the idle files and directories are checked, when the idle timeout is longer.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When the client last used one of its files or directories.
+#[derive(Debug)]
+struct FdActivity {
+    last_used: Instant,
+    /// Whether the descriptor was already reported as idle, it's reported again only after it's
+    /// used.
+    reported: bool,
+}
+
+impl FdActivity {
+    fn new(now: Instant) -> Self {
+        Self {
+            last_used: now,
+            reported: false,
+        }
+    }
+}
+
+/// The descriptor budget of a single client.
+#[derive(Debug)]
+pub(crate) struct FdBudget {
+    limit: Option<NonZeroU32>,
+    tcp: OutgoingSockets,
+    udp: OutgoingSockets,
+    /// Whether the client can handle [`ResponseError::FdLimitReached`].
+    precise_errors: bool,
+    /// Files and directories unused for this long are reported to the client, see
+    /// [`Self::idle_fds`].
+    idle_timeout: Option<Duration>,
+    /// Whether the client can handle [`IdleFds`].
+    report_idle: bool,
+    activity: HashMap<u64, FdActivity>,
+    idle_interval: Option<Interval>,
+}
+
+impl FdBudget {
+    pub(crate) fn new(limit: Option<NonZeroU32>, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            limit,
+            tcp: Default::default(),
+            udp: Default::default(),
+            precise_errors: false,
+            idle_timeout,
+            report_idle: false,
+            activity: Default::default(),
+            idle_interval: None,
+        }
+    }
+
+    /// Called when the client and the agent agree on a protocol version.
+    pub(crate) fn set_protocol_version(&mut self, version: &Version) {
+        self.precise_errors = FD_BUDGET_VERSION.matches(version);
+        self.report_idle = FD_GC_VERSION.matches(version);
+    }
+
+    /// Marks the files and directories of the client's `request` as used.
+    pub(crate) fn file_request_used(&mut self, request: &FileRequest) {
+        let fds: [Option<u64>; 2] = match request {
+            FileRequest::Read(req) => [Some(req.remote_fd), None],
+            FileRequest::ReadLimited(req) => [Some(req.remote_fd), None],
+            FileRequest::Seek(req) => [Some(req.fd), None],
+            FileRequest::Write(req) => [Some(req.fd), None],
+            FileRequest::WriteLimited(req) => [Some(req.remote_fd), None],
+            FileRequest::Close(req) => [Some(req.fd), None],
+            FileRequest::Xstat(req) => [req.fd, None],
+            FileRequest::XstatFs(req) => [Some(req.fd), None],
+            FileRequest::FdOpenDir(req) => [Some(req.remote_fd), None],
+            FileRequest::ReadDir(req) => [Some(req.remote_fd), None],
+            FileRequest::CloseDir(req) => [Some(req.remote_fd), None],
+            FileRequest::GetDEnts64(req) => [Some(req.remote_fd), None],
+            FileRequest::ReadDirBatch(req) => [Some(req.remote_fd), None],
+            FileRequest::ReadDirPlusBatch(req) => [Some(req.remote_fd), None],
+            FileRequest::ReadDirDelta(req) => [Some(req.remote_fd), None],
+            FileRequest::OpenRelative(req) => [Some(req.relative_fd), None],
+            FileRequest::OpenAt2(req) => [req.dirfd, None],
+            FileRequest::UnlinkAt(req) => [req.dirfd, None],
+            FileRequest::RenameAt(RenameFileWithDirRequest {
+                old_dirfd,
+                new_dirfd,
+                ..
+            })
+            | FileRequest::LinkAt(LinkFileWithDirRequest {
+                old_dirfd,
+                new_dirfd,
+                ..
+            }) => [*old_dirfd, *new_dirfd],
+            FileRequest::SymlinkAt(req) => [req.new_dirfd, None],
+            FileRequest::Fchmod(req) => [Some(req.fd), None],
+            FileRequest::ChmodAt(req) => [req.dirfd, None],
+            FileRequest::Fchown(req) => [Some(req.fd), None],
+            FileRequest::ChownAt(req) => [req.dirfd, None],
+            FileRequest::Ftruncate(req) => [Some(req.fd), None],
+            FileRequest::UtimensAt(req) => [req.dirfd, None],
+            FileRequest::Futimens(req) => [Some(req.fd), None],
+            FileRequest::Fsync(req) => [Some(req.fd), None],
+            FileRequest::Statx(req) => [req.dirfd, None],
+            FileRequest::GetXattr(req) => [req.fd, None],
+            FileRequest::SetXattr(req) => [req.fd, None],
+            FileRequest::ListXattr(req) => [req.fd, None],
+            FileRequest::RemoveXattr(req) => [req.fd, None],
+            FileRequest::Flock(req) => [Some(req.fd), None],
+            FileRequest::SetLock(req) => [Some(req.fd), None],
+            FileRequest::GetLock(req) => [Some(req.fd), None],
+            FileRequest::Fallocate(req) => [Some(req.fd), None],
+            FileRequest::CopyFileRange(CopyFileRangeRequest { fd_in, fd_out, .. }) => {
+                [Some(*fd_in), Some(*fd_out)]
+            }
+            FileRequest::ReadV(req) => [Some(req.fd), None],
+            FileRequest::WriteV(req) => [Some(req.fd), None],
+            FileRequest::ReadStream(req) => [Some(req.fd), None],
+            FileRequest::Follow(req) => [Some(req.fd), None],
+            FileRequest::Open(..)
+            | FileRequest::Access(..)
+            | FileRequest::ReadLink(..)
+            | FileRequest::ScratchDir(..)
+            | FileRequest::Unlink(..)
+            | FileRequest::Rename(..)
+            | FileRequest::Chmod(..)
+            | FileRequest::Chown(..)
+            | FileRequest::ReadWhole(..)
+            | FileRequest::Truncate(..)
+            | FileRequest::Glob(..)
+            | FileRequest::Symlink(..)
+            | FileRequest::Link(..)
+            | FileRequest::Canonicalize(..)
+            | FileRequest::StatFs(..)
+            | FileRequest::Unfollow(..)
+            | FileRequest::PrefetchTree(..) => [None, None],
+        };
+
+        fds.into_iter().flatten().for_each(|fd| self.fd_used(fd));
+    }
+
+    /// Marks the file or directory that the agent opened for the client's request as used.
+    pub(crate) fn file_response_used(&mut self, response: &FileResponse) {
+        match response {
+            FileResponse::Open(Ok(OpenFileResponse { fd })) => self.fd_used(*fd),
+            FileResponse::OpenDir(Ok(open)) => self.fd_used(open.fd),
+            _ => {}
+        }
+    }
+
+    /// Marks the file or directory `fd` of the client as used.
+    pub(crate) fn fd_used(&mut self, fd: u64) {
+        if self.idle_timeout.is_none() {
+            return;
+        }
+
+        self.activity.insert(fd, FdActivity::new(Instant::now()));
+    }
+
+    /// Waits for the next check of the idle files and directories, pending forever when the
+    /// client doesn't get [`IdleFds`].
+    ///
+    /// Cancel safe.
+    pub(crate) async fn idle_tick(&mut self) {
+        let Some(timeout) = self.idle_timeout.filter(|_| self.report_idle) else {
+            return std::future::pending().await;
+        };
+
+        self.idle_interval
+            .get_or_insert_with(|| {
+                let period = timeout.clamp(Duration::from_secs(1), IDLE_CHECK_INTERVAL);
+                let mut interval = time::interval_at(Instant::now() + period, period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            })
+            .tick()
+            .await;
+    }
+
+    /// Returns the files and directories of the client that were not used for the idle timeout.
+    ///
+    /// Each descriptor is reported once, and again only if it's used and then idle again.
+    /// Descriptors that the agent doesn't know when they were used are considered used now.
+    pub(crate) fn idle_fds(&mut self, file_manager: &FileManager) -> IdleFds {
+        let timeout = self.idle_timeout.unwrap_or(Duration::MAX);
+        let now = Instant::now();
+        let (files, dirs) = file_manager.open_fds();
+
+        let open = files.iter().chain(&dirs).collect::<HashSet<_>>();
+        self.activity.retain(|fd, _| open.contains(fd));
+
+        let mut is_idle = |fd: &u64| {
+            let activity = self
+                .activity
+                .entry(*fd)
+                .or_insert_with(|| FdActivity::new(now));
+            let idle = !activity.reported && now.duration_since(activity.last_used) >= timeout;
+            activity.reported |= idle;
+            idle
+        };
+
+        let files = files.into_iter().filter(&mut is_idle).collect();
+        let dirs = dirs.into_iter().filter(&mut is_idle).collect();
+
+        IdleFds {
+            files,
+            dirs,
+            idle_timeout_secs: timeout.as_secs(),
+        }
+    }
+
+    /// Returns the descriptors that the client has open, response to
+    /// [`FdUsageRequest`](mirrord_protocol::fd_budget::FdUsageRequest).
+    pub(crate) fn usage(&self, file_manager: &FileManager) -> FdUsage {
+        let (files, dirs) = file_manager.open_descriptors();
+
+        FdUsage {
+            files,
+            dirs,
+            sockets: self.tcp.count() + self.udp.count(),
+            limit: self.limit.map(|limit| limit.get().into()),
+        }
+    }
+
+    /// Returns the response for a `request` that would open a descriptor over the limit, or
+    /// [`None`] when it should be served.
+    #[tracing::instrument(level = Level::TRACE, skip(self, file_manager), ret)]
+    pub(crate) fn file_request(
+        &self,
+        request: &FileRequest,
+        file_manager: &FileManager,
+    ) -> Option<FileResponse> {
+        match request {
+            FileRequest::Open(..) | FileRequest::OpenRelative(..) | FileRequest::OpenAt2(..) => {
+                let error = self.check(FdKind::File, file_manager)?;
+                Some(FileResponse::Open(Err(error)))
+            }
+            FileRequest::FdOpenDir(..) => {
+                let error = self.check(FdKind::Dir, file_manager)?;
+                Some(FileResponse::OpenDir(Err(error)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Called for every connect request of the client.
+    #[tracing::instrument(level = Level::TRACE, skip(self, file_manager), ret)]
+    pub(crate) fn connect_request(
+        &mut self,
+        outgoing: Outgoing,
+        file_manager: &FileManager,
+    ) -> ConnectAdmission {
+        let error = self.check(FdKind::Socket, file_manager);
+        let sockets = self.sockets_mut(outgoing);
+
+        match error {
+            None => {
+                sockets.pending.push_back(None);
+                ConnectAdmission::Allowed
+            }
+            Some(error) if sockets.pending.is_empty() => ConnectAdmission::Rejected(error),
+            Some(error) => {
+                sockets.pending.push_back(Some(error));
+                ConnectAdmission::Deferred
+            }
+        }
+    }
+
+    /// Called for every connect response of the outgoing task, returns the responses to send
+    /// to the client, in order.
+    ///
+    /// These are the `response` itself, followed by the errors of the
+    /// [`ConnectAdmission::Deferred`] requests that were sent right after it.
+    pub(crate) fn connect_response(
+        &mut self,
+        outgoing: Outgoing,
+        response: RemoteResult<DaemonConnect>,
+    ) -> Vec<RemoteResult<DaemonConnect>> {
+        let sockets = self.sockets_mut(outgoing);
+
+        // Only `None`s are left at the front.
+        sockets.pending.pop_front();
+        if let Ok(connect) = &response {
+            sockets.connected.insert(connect.connection_id);
+        }
+
+        let mut responses = vec![response];
+        while let Some(error) = sockets.pending.front_mut().and_then(Option::take) {
+            sockets.pending.pop_front();
+            responses.push(Err(error));
+        }
+
+        responses
+    }
+
+    /// Called when either the client or the outgoing task closes a socket.
+    pub(crate) fn socket_closed(&mut self, outgoing: Outgoing, connection_id: ConnectionId) {
+        self.sockets_mut(outgoing).connected.remove(&connection_id);
+    }
+
+    fn sockets_mut(&mut self, outgoing: Outgoing) -> &mut OutgoingSockets {
+        match outgoing {
+            Outgoing::Tcp => &mut self.tcp,
+            Outgoing::Udp => &mut self.udp,
+        }
+    }
+
+    /// Returns the error for opening a new descriptor of `kind`, if the client already has as
+    /// many as the limit.
+    fn check(&self, kind: FdKind, file_manager: &FileManager) -> Option<ResponseError> {
+        let limit = self.limit?;
+
+        if self.usage(file_manager).total() < u64::from(limit.get()) {
+            return None;
+        }
+
+        let error = if self.precise_errors {
+            ResponseError::FdLimitReached {
+                kind,
+                limit: limit.get().into(),
+            }
+        } else {
+            io::Error::from_raw_os_error(libc::EMFILE).into()
+        };
+
+        Some(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mirrord_protocol::{
+        file::{
+            FdOpenDirRequest, OpenDirResponse, OpenFileRequest, OpenOptionsInternal, ReadDirRequest,
+        },
+        outgoing::SocketAddress,
+    };
+
+    use super::*;
+
+    fn connected(connection_id: ConnectionId) -> RemoteResult<DaemonConnect> {
+        let address = SocketAddress::Ip("127.0.0.1:80".parse().unwrap());
+
+        Ok(DaemonConnect {
+            connection_id,
+            remote_address: address.clone(),
+            local_address: address,
+        })
+    }
+
+    #[test]
+    fn rejects_over_limit() {
+        let file_manager = FileManager::default();
+        let mut budget = FdBudget::new(NonZeroU32::new(2), None);
+        budget.set_protocol_version(&Version::new(1, 53, 0));
+
+        let open = FileRequest::Open(OpenFileRequest {
+            path: "/etc/hosts".into(),
+            open_options: Default::default(),
+        });
+        assert_eq!(budget.file_request(&open, &file_manager), None);
+
+        assert!(matches!(
+            budget.connect_request(Outgoing::Tcp, &file_manager),
+            ConnectAdmission::Allowed
+        ));
+        assert!(matches!(
+            budget.connect_request(Outgoing::Udp, &file_manager),
+            ConnectAdmission::Allowed
+        ));
+
+        // Connecting sockets count too.
+        assert_eq!(
+            budget.file_request(&open, &file_manager),
+            Some(FileResponse::Open(Err(ResponseError::FdLimitReached {
+                kind: FdKind::File,
+                limit: 2,
+            })))
+        );
+
+        budget.connect_response(Outgoing::Tcp, connected(0));
+        budget.connect_response(Outgoing::Udp, Err(ResponseError::NotImplemented));
+        assert_eq!(budget.usage(&file_manager).sockets, 1);
+
+        let open_dir = FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: 0 });
+        assert_eq!(budget.file_request(&open_dir, &file_manager), None);
+
+        budget.socket_closed(Outgoing::Tcp, 0);
+        assert_eq!(
+            budget.usage(&file_manager),
+            FdUsage {
+                files: 0,
+                dirs: 0,
+                sockets: 0,
+                limit: Some(2),
+            }
+        );
+    }
+
+    #[test]
+    fn deferred_rejection_keeps_order() {
+        let file_manager = FileManager::default();
+        let mut budget = FdBudget::new(NonZeroU32::new(1), None);
+
+        assert!(matches!(
+            budget.connect_request(Outgoing::Tcp, &file_manager),
+            ConnectAdmission::Allowed
+        ));
+        assert!(matches!(
+            budget.connect_request(Outgoing::Tcp, &file_manager),
+            ConnectAdmission::Deferred
+        ));
+
+        let responses = budget.connect_response(Outgoing::Tcp, connected(7));
+        assert_eq!(responses.len(), 2);
+        assert!(responses[0].is_ok());
+        // Old clients get `EMFILE`.
+        assert_eq!(
+            responses[1],
+            Err(io::Error::from_raw_os_error(libc::EMFILE).into())
+        );
+
+        // Nothing is pending anymore.
+        assert!(matches!(
+            budget.connect_request(Outgoing::Tcp, &file_manager),
+            ConnectAdmission::Rejected(..)
+        ));
+    }
+
+    #[test]
+    fn reports_idle_fds_once() {
+        let mut file_manager = FileManager::new(None);
+        let mut budget = FdBudget::new(None, Some(Duration::ZERO));
+        budget.set_protocol_version(&Version::new(1, 59, 0));
+
+        let open = FileRequest::Open(OpenFileRequest {
+            path: std::env::temp_dir(),
+            open_options: OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        });
+        let response = file_manager.handle_message(open).unwrap().unwrap();
+        budget.file_response_used(&response);
+        let FileResponse::Open(Ok(OpenFileResponse { fd })) = response else {
+            panic!("failed to open the temp dir: {response:?}");
+        };
+
+        let open_dir = FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: fd });
+        budget.file_request_used(&open_dir);
+        let response = file_manager.handle_message(open_dir).unwrap().unwrap();
+        budget.file_response_used(&response);
+        let FileResponse::OpenDir(Ok(OpenDirResponse { fd: dir_fd })) = response else {
+            panic!("failed to open the temp dir stream: {response:?}");
+        };
+
+        assert_eq!(
+            budget.idle_fds(&file_manager),
+            IdleFds {
+                files: vec![fd],
+                dirs: vec![dir_fd],
+                idle_timeout_secs: 0,
+            }
+        );
+        // Reported only once.
+        assert!(budget.idle_fds(&file_manager).is_empty());
+
+        // Used and idle again.
+        budget.file_request_used(&FileRequest::ReadDir(ReadDirRequest { remote_fd: dir_fd }));
+        assert_eq!(budget.idle_fds(&file_manager).dirs, [dir_fd]);
+
+        // Closed descriptors are forgotten.
+        file_manager.close_dir(dir_fd);
+        budget.fd_used(dir_fd);
+        assert!(budget.idle_fds(&file_manager).is_empty());
+        assert!(!budget.activity.contains_key(&dir_fd));
+    }
+}
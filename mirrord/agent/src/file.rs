@@ -1,21 +1,39 @@
 use std::{
     self,
+    borrow::Cow,
     collections::{hash_map::Entry, HashMap, VecDeque},
-    fs::{read_link, File, OpenOptions, ReadDir},
+    ffi::{CStr, CString},
+    fs::{read_link, File, OpenOptions, Permissions, ReadDir},
     io::{self, prelude::*, BufReader, SeekFrom},
     iter::{Enumerate, Peekable},
     ops::RangeInclusive,
-    os::unix::{fs::MetadataExt, prelude::FileExt},
+    os::unix::{
+        ffi::OsStringExt,
+        fs::{self as unix_fs, MetadataExt, OpenOptionsExt, PermissionsExt},
+        io::{AsRawFd, FromRawFd, RawFd},
+        prelude::FileExt,
+    },
     path::{Path, PathBuf},
 };
 
 use faccess::{AccessMode, PathExt};
 use libc::DT_DIR;
 use mirrord_protocol::{file::*, FileRequest, FileResponse, RemoteResult, ResponseError};
-use tracing::{error, trace, Level};
+use rand::distributions::{Alphanumeric, DistString};
+use regex::{RegexSet, RegexSetBuilder};
+use tracing::{error, trace, warn, Level};
 
+use self::service_account::TokenAccess;
 use crate::error::Result;
 
+mod canonicalize;
+pub(crate) mod follow;
+mod glob;
+mod lock;
+pub(crate) mod prefetch;
+pub(crate) mod service_account;
+pub(crate) mod watch;
+
 #[derive(Debug)]
 pub enum RemoteFile {
     File(File),
@@ -26,19 +44,251 @@ fn log_err(entry_res: io::Result<DirEntryInternal>) -> io::Result<DirEntryIntern
     entry_res.inspect_err(|err| error!("Converting DirEntry failed with {err:?}"))
 }
 
+/// Replaces the bytes of [`DirEntryInternal::name`] that are not valid UTF-8, for clients older
+/// than [`NON_UTF8_NAMES_VERSION`].
+fn lossy_name(mut entry: DirEntryInternal) -> DirEntryInternal {
+    if let Cow::Owned(name) = String::from_utf8_lossy(&entry.name) {
+        entry.name = name.into_bytes();
+    }
+    entry
+}
+
+/// The `open` flags for `open_options`, the same that [`OpenOptions`] uses.
+fn open_flags(open_options: &OpenOptionsInternal) -> libc::c_int {
+    let access = match (open_options.read, open_options.write || open_options.append) {
+        (_, false) => libc::O_RDONLY,
+        (false, true) => libc::O_WRONLY,
+        (true, true) => libc::O_RDWR,
+    };
+
+    let creation = if open_options.create_new {
+        libc::O_CREAT | libc::O_EXCL
+    } else if open_options.create {
+        libc::O_CREAT
+    } else {
+        0
+    };
+
+    let mut flags = access | creation | libc::O_CLOEXEC;
+    if open_options.append {
+        flags |= libc::O_APPEND;
+    }
+    if open_options.truncate {
+        flags |= libc::O_TRUNC;
+    }
+
+    flags
+}
+
+/// The `timespec` that `utimensat` (and `futimens`) take for `time`.
+fn file_timespec(time: FileTimeInternal) -> libc::timespec {
+    const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+    let (tv_sec, tv_nsec) = match time {
+        FileTimeInternal::Omit => (0, libc::UTIME_OMIT),
+        FileTimeInternal::Now => (0, libc::UTIME_NOW),
+        FileTimeInternal::At(nanos) => (
+            nanos.div_euclid(NANOS_PER_SEC),
+            nanos.rem_euclid(NANOS_PER_SEC),
+        ),
+    };
+
+    libc::timespec { tv_sec, tv_nsec }
+}
+
+/// Sets the access and modification times of the file at `host_path`, `flags` are the
+/// `utimensat` ones.
+fn set_file_times(
+    host_path: PathBuf,
+    access_time: FileTimeInternal,
+    modification_time: FileTimeInternal,
+    flags: u32,
+) -> RemoteResult<()> {
+    let host_path = CString::new(host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+    let times = [file_timespec(access_time), file_timespec(modification_time)];
+
+    let result = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            host_path.as_ptr(),
+            times.as_ptr(),
+            flags as libc::c_int,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+/// Nanoseconds since the epoch of a `statx` `timestamp`.
+fn statx_timestamp_nanos(timestamp: libc::statx_timestamp) -> i64 {
+    timestamp
+        .tv_sec
+        .saturating_mul(1_000_000_000)
+        .saturating_add(timestamp.tv_nsec.into())
+}
+
+/// `statx` of `host_path`, relative to the local `dirfd`, `flags` and `mask` are the `statx`
+/// ones.
+fn statx_metadata(
+    dirfd: libc::c_int,
+    host_path: &Path,
+    flags: libc::c_int,
+    mask: u32,
+) -> RemoteResult<StatxMetadataInternal> {
+    let host_path =
+        CString::new(host_path.as_os_str().as_encoded_bytes()).map_err(io::Error::from)?;
+
+    // SAFETY: all-zero statx struct is valid
+    let mut statx: libc::statx = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statx(dirfd, host_path.as_ptr(), flags, mask, &mut statx) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let metadata = MetadataInternal {
+        device_id: libc::makedev(statx.stx_dev_major, statx.stx_dev_minor),
+        inode: statx.stx_ino,
+        mode: statx.stx_mode.into(),
+        hard_links: statx.stx_nlink.into(),
+        user_id: statx.stx_uid,
+        group_id: statx.stx_gid,
+        rdevice_id: libc::makedev(statx.stx_rdev_major, statx.stx_rdev_minor),
+        size: statx.stx_size,
+        access_time: statx_timestamp_nanos(statx.stx_atime),
+        modification_time: statx_timestamp_nanos(statx.stx_mtime),
+        creation_time: statx_timestamp_nanos(statx.stx_ctime),
+        block_size: statx.stx_blksize.into(),
+        blocks: statx.stx_blocks,
+    };
+
+    Ok(StatxMetadataInternal {
+        metadata,
+        mask: statx.stx_mask,
+        attributes: statx.stx_attributes,
+        attributes_mask: statx.stx_attributes_mask,
+        birth_time: statx_timestamp_nanos(statx.stx_btime),
+        mount_id: statx.stx_mnt_id,
+        dio_mem_align: statx.stx_dio_mem_align,
+        dio_offset_align: statx.stx_dio_offset_align,
+    })
+}
+
+/// Where [`FileManager::get_xattr`] and the other extended attribute operations find the
+/// attributes.
+#[derive(Debug)]
+enum XattrFile {
+    /// A remote file the client opened, its descriptor stays in [`FileManager::open_files`] for
+    /// the whole operation.
+    Fd(RawFd),
+    /// A path on the host, and whether the symbolic links in its last component are followed.
+    Path(CString, bool),
+}
+
+impl XattrFile {
+    fn get(&self, name: &CStr, buffer: &mut [u8]) -> isize {
+        let value = buffer.as_mut_ptr().cast();
+
+        unsafe {
+            match self {
+                Self::Fd(fd) => libc::fgetxattr(*fd, name.as_ptr(), value, buffer.len()),
+                Self::Path(path, true) => {
+                    libc::getxattr(path.as_ptr(), name.as_ptr(), value, buffer.len())
+                }
+                Self::Path(path, false) => {
+                    libc::lgetxattr(path.as_ptr(), name.as_ptr(), value, buffer.len())
+                }
+            }
+        }
+    }
+
+    fn list(&self, buffer: &mut [u8]) -> isize {
+        let list = buffer.as_mut_ptr().cast();
+
+        unsafe {
+            match self {
+                Self::Fd(fd) => libc::flistxattr(*fd, list, buffer.len()),
+                Self::Path(path, true) => libc::listxattr(path.as_ptr(), list, buffer.len()),
+                Self::Path(path, false) => libc::llistxattr(path.as_ptr(), list, buffer.len()),
+            }
+        }
+    }
+
+    fn set(&self, name: &CStr, value: &[u8], flags: libc::c_int) -> libc::c_int {
+        let size = value.len();
+        let value = value.as_ptr().cast();
+
+        unsafe {
+            match self {
+                Self::Fd(fd) => libc::fsetxattr(*fd, name.as_ptr(), value, size, flags),
+                Self::Path(path, true) => {
+                    libc::setxattr(path.as_ptr(), name.as_ptr(), value, size, flags)
+                }
+                Self::Path(path, false) => {
+                    libc::lsetxattr(path.as_ptr(), name.as_ptr(), value, size, flags)
+                }
+            }
+        }
+    }
+
+    fn remove(&self, name: &CStr) -> libc::c_int {
+        unsafe {
+            match self {
+                Self::Fd(fd) => libc::fremovexattr(*fd, name.as_ptr()),
+                Self::Path(path, true) => libc::removexattr(path.as_ptr(), name.as_ptr()),
+                Self::Path(path, false) => libc::lremovexattr(path.as_ptr(), name.as_ptr()),
+            }
+        }
+    }
+}
+
+/// Reads a value of unknown size with one of the [`XattrFile`] calls: `read` is called with an
+/// empty buffer to get the size first, and again when the value grows in between (`ERANGE`).
+fn read_xattr_buffer(mut read: impl FnMut(&mut [u8]) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = read(&mut []);
+        if size == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buffer = vec![0; size as usize];
+        let read_size = read(&mut buffer);
+        if read_size == -1 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+
+            return Err(error);
+        }
+
+        buffer.truncate(read_size as usize);
+        return Ok(buffer);
+    }
+}
+
 #[derive(Debug)]
 struct GetDEnts64Stream {
     inner: std::fs::ReadDir,
     current_and_parent: VecDeque<io::Result<DirEntryInternal>>,
     current_index: usize,
+    /// Copied from [`FileManager`], the entries must have their final names before we count
+    /// their sizes.
+    non_utf8_names: bool,
 }
 
 impl GetDEnts64Stream {
-    fn new(inner: ReadDir, current_and_parent: VecDeque<io::Result<DirEntryInternal>>) -> Self {
+    fn new(
+        inner: ReadDir,
+        current_and_parent: VecDeque<io::Result<DirEntryInternal>>,
+        non_utf8_names: bool,
+    ) -> Self {
         Self {
             inner,
             current_and_parent,
             current_index: 0,
+            non_utf8_names,
         }
     }
 }
@@ -57,12 +307,96 @@ impl Iterator for GetDEnts64Stream {
             .inner
             .next()
             .map(|i| (self.current_index, i).try_into()) // Convert into DirEntryInternal.
-            .map(log_err);
+            .map(log_err)
+            .map(|entry| {
+                if self.non_utf8_names {
+                    entry
+                } else {
+                    entry.map(lossy_name)
+                }
+            });
         self.current_index += 1;
         ret
     }
 }
 
+/// Directory in the target, where the file operations on paths that match
+/// [`ScratchDirRequest::patterns`] are redirected.
+///
+/// Created for a single client, and deleted (with everything in it) when dropped, i.e. when the
+/// client's session ends.
+#[derive(Debug)]
+struct ScratchDir {
+    patterns: RegexSet,
+    /// Path of the directory, relative to the root of the target.
+    path: PathBuf,
+    /// Path of the directory, from the host perspective.
+    host_path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Creates a new directory under `/tmp` of the target.
+    fn create(root_path: &Path, patterns: Vec<String>) -> io::Result<Self> {
+        let patterns = RegexSetBuilder::new(patterns)
+            .case_insensitive(true)
+            .build()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+        let name = format!(
+            "mirrord-scratch-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 10)
+        );
+        let path = PathBuf::from("tmp").join(name);
+        let host_path = root_path.join(&path);
+        std::fs::create_dir_all(&host_path)?;
+
+        Ok(Self {
+            patterns,
+            path,
+            host_path,
+        })
+    }
+
+    /// Returns the path in this directory (relative to the root of the target) where the
+    /// operations on `path` are redirected, or [`None`] if `path` doesn't match the patterns.
+    fn redirect(&self, path: &Path) -> Option<PathBuf> {
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        let absolute = Path::new("/").join(relative);
+        self.patterns
+            .is_match(absolute.to_str()?)
+            .then(|| self.path.join(relative))
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_dir_all(&self.host_path) {
+            warn!(?self.host_path, %error, "failed to remove the scratch directory");
+        }
+    }
+}
+
+/// Max amount of bytes read for a single [`SendFileRequest`], so that a huge `count` doesn't
+/// allocate the whole file in the agent.
+pub(crate) const SEND_FILE_MAX_COUNT: u64 = 1024 * 1024;
+
+/// Max amount of directory listings kept for the [`ReadDirDeltaRequest`]s of a client, the oldest
+/// ones are dropped and listed in full again.
+const MAX_DIR_SNAPSHOTS: usize = 32;
+
+/// Amount of written bytes after which [`FileManager::write_space_rejection`] checks the free
+/// space again, so that small writes don't `fstatvfs` each time.
+const WRITE_SPACE_CHECK_THRESHOLD: u64 = 1024 * 1024;
+
+/// File written by a request that [`FileManager::write_space_rejection`] checks.
+#[derive(Debug)]
+enum WrittenFile {
+    /// Remote fd of the file.
+    Fd(u64),
+    /// Host path of the file.
+    Path(PathBuf),
+}
+
 #[derive(Debug)]
 pub(crate) struct FileManager {
     root_path: PathBuf,
@@ -70,6 +404,21 @@ pub(crate) struct FileManager {
     dir_streams: HashMap<u64, Enumerate<ReadDir>>,
     getdents_streams: HashMap<u64, Peekable<GetDEnts64Stream>>,
     fds_iter: RangeInclusive<u64>,
+    /// Set by [`FileRequest::ScratchDir`].
+    scratch_dir: Option<ScratchDir>,
+    /// Set by [`FileManager::set_read_only`].
+    read_only: bool,
+    /// Set by [`FileManager::set_protocol_version`].
+    non_utf8_names: bool,
+    /// Set by [`FileManager::set_token_access`].
+    token_access: TokenAccess,
+    /// Set by [`FileManager::set_write_space_margin`].
+    write_space_margin: Option<u64>,
+    /// Bytes written since [`FileManager::write_space_rejection`] last checked the free space.
+    unchecked_write_bytes: u64,
+    /// The last directory listings sent for [`ReadDirDeltaRequest`]s, with their cookies, oldest
+    /// first.
+    dir_snapshots: VecDeque<(u64, Vec<DirEntryInternal>)>,
 }
 
 impl Default for FileManager {
@@ -80,6 +429,13 @@ impl Default for FileManager {
             dir_streams: Default::default(),
             getdents_streams: Default::default(),
             fds_iter: (0..=u64::MAX),
+            scratch_dir: None,
+            read_only: false,
+            non_utf8_names: false,
+            token_access: Default::default(),
+            write_space_margin: None,
+            unchecked_write_bytes: 0,
+            dir_snapshots: Default::default(),
         }
     }
 }
@@ -149,6 +505,14 @@ impl FileManager {
     /// Executes the request and returns the response.
     #[tracing::instrument(level = "trace", skip(self))]
     pub fn handle_message(&mut self, request: FileRequest) -> Result<Option<FileResponse>> {
+        if self.read_only && request.is_write() {
+            return Ok(Self::read_only_rejection(&request));
+        }
+
+        if let Some(rejection) = self.write_space_rejection(&request) {
+            return Ok(Some(rejection));
+        }
+
         Ok(match request {
             FileRequest::Open(OpenFileRequest { path, open_options }) => {
                 // TODO: maybe not agent error on this?
@@ -167,6 +531,17 @@ impl FileManager {
                 let open_result = self.open_relative(relative_fd, path, open_options);
                 Some(FileResponse::Open(open_result))
             }
+            FileRequest::OpenAt2(OpenAt2Request {
+                dirfd,
+                path,
+                open_options,
+                resolve,
+            }) => Some(FileResponse::Open(self.open_at2(
+                dirfd,
+                path,
+                open_options,
+                resolve,
+            ))),
             FileRequest::Read(ReadFileRequest {
                 remote_fd,
                 buffer_size,
@@ -187,7 +562,7 @@ impl FileManager {
                 Some(FileResponse::ReadLink(self.read_link(path)))
             }
             FileRequest::Seek(SeekFileRequest { fd, seek_from }) => {
-                let seek_result = self.seek(fd, seek_from.into());
+                let seek_result = self.seek(fd, seek_from);
                 Some(FileResponse::Seek(seek_result))
             }
             FileRequest::Write(WriteFileRequest { fd, write_bytes }) => {
@@ -226,6 +601,9 @@ impl FileManager {
                 let xstat_result = self.xstatfs(fd);
                 Some(FileResponse::XstatFs(xstat_result))
             }
+            FileRequest::StatFs(StatFsRequest { path }) => {
+                Some(FileResponse::XstatFs(self.statfs(path)))
+            }
 
             // dir operations
             FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd }) => {
@@ -240,6 +618,9 @@ impl FileManager {
                 let read_dir_result = self.read_dir_batch(remote_fd, amount);
                 Some(FileResponse::ReadDirBatch(read_dir_result))
             }
+            FileRequest::ReadDirPlusBatch(ReadDirPlusBatchRequest { remote_fd, amount }) => Some(
+                FileResponse::ReadDirPlusBatch(self.read_dir_plus_batch(remote_fd, amount)),
+            ),
             FileRequest::CloseDir(CloseDirRequest { remote_fd }) => {
                 self.close_dir(remote_fd);
                 None
@@ -250,6 +631,231 @@ impl FileManager {
             }) => Some(FileResponse::GetDEnts64(
                 self.getdents64(remote_fd, buffer_size),
             )),
+            FileRequest::ScratchDir(ScratchDirRequest { patterns }) => {
+                self.create_scratch_dir(patterns);
+                None
+            }
+            FileRequest::Unlink(UnlinkFileRequest { path }) => {
+                Some(FileResponse::Unlink(self.unlink(path, false)))
+            }
+            FileRequest::UnlinkAt(UnlinkFileWithDirRequest {
+                dirfd,
+                path,
+                remove_dir,
+            }) => Some(FileResponse::Unlink(
+                self.unlink_at(dirfd, path, remove_dir),
+            )),
+            FileRequest::Rename(RenameFileRequest { old_path, new_path }) => {
+                Some(FileResponse::Rename(self.rename(old_path, new_path)))
+            }
+            FileRequest::RenameAt(RenameFileWithDirRequest {
+                old_dirfd,
+                old_path,
+                new_dirfd,
+                new_path,
+                flags,
+            }) => Some(FileResponse::Rename(
+                self.rename_at(old_dirfd, old_path, new_dirfd, new_path, flags),
+            )),
+            FileRequest::Link(LinkFileRequest { old_path, new_path }) => {
+                Some(FileResponse::Link(self.link(old_path, new_path)))
+            }
+            FileRequest::LinkAt(LinkFileWithDirRequest {
+                old_dirfd,
+                old_path,
+                new_dirfd,
+                new_path,
+                follow_symlinks,
+            }) => Some(FileResponse::Link(self.link_at(
+                old_dirfd,
+                old_path,
+                new_dirfd,
+                new_path,
+                follow_symlinks,
+            ))),
+            FileRequest::Symlink(SymlinkRequest { target, link_path }) => {
+                Some(FileResponse::Symlink(self.symlink(target, link_path)))
+            }
+            FileRequest::SymlinkAt(SymlinkAtRequest {
+                target,
+                new_dirfd,
+                link_path,
+            }) => Some(FileResponse::Symlink(
+                self.symlink_at(target, new_dirfd, link_path),
+            )),
+            FileRequest::Chmod(ChmodFileRequest { path, mode }) => {
+                Some(FileResponse::Chmod(self.chmod(path, mode)))
+            }
+            FileRequest::Fchmod(FchmodFileRequest { fd, mode }) => {
+                Some(FileResponse::Chmod(self.fchmod(fd, mode)))
+            }
+            FileRequest::ChmodAt(ChmodFileWithDirRequest {
+                dirfd,
+                path,
+                mode,
+                flags,
+            }) => Some(FileResponse::Chmod(self.chmod_at(dirfd, path, mode, flags))),
+            FileRequest::Truncate(TruncateFileRequest { path, length }) => {
+                Some(FileResponse::Truncate(self.truncate(path, length)))
+            }
+            FileRequest::Ftruncate(FtruncateFileRequest { fd, length }) => {
+                Some(FileResponse::Truncate(self.ftruncate(fd, length)))
+            }
+            FileRequest::Glob(GlobRequest {
+                pattern,
+                max_results,
+            }) => Some(FileResponse::Glob(self.glob(pattern, max_results))),
+            FileRequest::ReadWhole(ReadWholeFileRequest { path, max_size }) => {
+                Some(FileResponse::ReadWhole(self.read_whole(path, max_size)))
+            }
+            FileRequest::Chown(ChownFileRequest { path, owner, group }) => {
+                Some(FileResponse::Chown(self.chown(path, owner, group)))
+            }
+            FileRequest::Fchown(FchownFileRequest { fd, owner, group }) => {
+                Some(FileResponse::Chown(self.fchown(fd, owner, group)))
+            }
+            FileRequest::ChownAt(ChownFileWithDirRequest {
+                dirfd,
+                path,
+                owner,
+                group,
+                flags,
+            }) => Some(FileResponse::Chown(
+                self.chown_at(dirfd, path, owner, group, flags),
+            )),
+            FileRequest::UtimensAt(UtimensFileWithDirRequest {
+                dirfd,
+                path,
+                access_time,
+                modification_time,
+                flags,
+            }) => Some(FileResponse::Utimens(self.utimens_at(
+                dirfd,
+                path,
+                access_time,
+                modification_time,
+                flags,
+            ))),
+            FileRequest::Futimens(FutimensFileRequest {
+                fd,
+                access_time,
+                modification_time,
+            }) => Some(FileResponse::Utimens(self.futimens(
+                fd,
+                access_time,
+                modification_time,
+            ))),
+            FileRequest::Fsync(FsyncFileRequest { fd, data_only }) => {
+                Some(FileResponse::Fsync(self.fsync(fd, data_only)))
+            }
+            FileRequest::Statx(StatxRequest {
+                dirfd,
+                path,
+                follow_symlink,
+                mask,
+            }) => Some(FileResponse::Statx(self.statx(
+                dirfd,
+                path,
+                follow_symlink,
+                mask,
+            ))),
+            FileRequest::GetXattr(GetXattrRequest {
+                path,
+                fd,
+                follow_symlink,
+                name,
+            }) => Some(FileResponse::GetXattr(self.get_xattr(
+                path,
+                fd,
+                follow_symlink,
+                name,
+            ))),
+            FileRequest::SetXattr(SetXattrRequest {
+                path,
+                fd,
+                follow_symlink,
+                name,
+                value,
+                flags,
+            }) => Some(FileResponse::SetXattr(self.set_xattr(
+                path,
+                fd,
+                follow_symlink,
+                name,
+                value,
+                flags,
+            ))),
+            FileRequest::ListXattr(ListXattrRequest {
+                path,
+                fd,
+                follow_symlink,
+            }) => Some(FileResponse::ListXattr(self.list_xattr(
+                path,
+                fd,
+                follow_symlink,
+            ))),
+            FileRequest::RemoveXattr(RemoveXattrRequest {
+                path,
+                fd,
+                follow_symlink,
+                name,
+            }) => Some(FileResponse::RemoveXattr(self.remove_xattr(
+                path,
+                fd,
+                follow_symlink,
+                name,
+            ))),
+            FileRequest::Flock(FlockRequest { fd, lock_type }) => {
+                Some(FileResponse::Lock(self.flock(fd, lock_type)))
+            }
+            FileRequest::SetLock(SetLockRequest { fd, lock }) => {
+                Some(FileResponse::Lock(self.set_lock(fd, lock)))
+            }
+            FileRequest::GetLock(GetLockRequest { fd, lock }) => {
+                Some(FileResponse::GetLock(self.get_lock(fd, lock)))
+            }
+            FileRequest::Fallocate(FallocateFileRequest {
+                fd,
+                mode,
+                offset,
+                length,
+                posix,
+            }) => Some(FileResponse::Fallocate(
+                self.fallocate(fd, mode, offset, length, posix),
+            )),
+            FileRequest::CopyFileRange(CopyFileRangeRequest {
+                fd_in,
+                offset_in,
+                fd_out,
+                offset_out,
+                length,
+            }) => Some(FileResponse::CopyFileRange(
+                self.copy_file_range(fd_in, offset_in, fd_out, offset_out, length),
+            )),
+            FileRequest::ReadV(ReadVFileRequest {
+                fd,
+                lengths,
+                offset,
+            }) => Some(FileResponse::ReadV(self.read_vectored(fd, lengths, offset))),
+            FileRequest::WriteV(WriteVFileRequest {
+                fd,
+                buffers,
+                offset,
+            }) => Some(FileResponse::WriteV(
+                self.write_vectored(fd, buffers, offset),
+            )),
+            // Streamed by the caller, chunk by chunk, see `Self::read_stream_chunk`.
+            FileRequest::ReadStream(..) => None,
+            // Followed by the caller, see `follow::FollowManager`.
+            FileRequest::Follow(..) | FileRequest::Unfollow(..) => None,
+            // Walked by the caller, see `prefetch::PrefetchWalk`.
+            FileRequest::PrefetchTree(..) => None,
+            FileRequest::ReadDirDelta(ReadDirDeltaRequest { remote_fd, since }) => Some(
+                FileResponse::ReadDirDelta(self.read_dir_delta(remote_fd, since)),
+            ),
+            FileRequest::Canonicalize(CanonicalizePathRequest { path }) => {
+                Some(FileResponse::Canonicalize(self.canonicalize(path)))
+            }
         })
     }
 
@@ -264,14 +870,240 @@ impl FileManager {
         }
     }
 
+    /// Makes the requests that would modify the filesystem fail for the rest of this client's
+    /// session, see [`ClientMessage::ReadOnly`](mirrord_protocol::ClientMessage::ReadOnly).
+    pub(crate) fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    /// Applies the operator's policy on the service account token of the target to the files that
+    /// this client opens.
+    pub(crate) fn set_token_access(&mut self, token_access: TokenAccess) {
+        self.token_access = token_access;
+    }
+
+    /// Makes the writes that would leave less than `margin` bytes free in the filesystem of the
+    /// written file fail with `ENOSPC`, so that the client doesn't fill the storage of the node.
+    ///
+    /// Up to [`WRITE_SPACE_CHECK_THRESHOLD`] bytes are written between the checks, which the
+    /// `margin` should leave room for.
+    pub(crate) fn set_write_space_margin(&mut self, margin: Option<u64>) {
+        self.write_space_margin = margin;
+    }
+
+    /// Called when the client's [`mirrord_protocol`] version is known, directory entries with
+    /// names that are not valid UTF-8 are sent as they are only to clients that support
+    /// [`NON_UTF8_NAMES_VERSION`].
+    pub(crate) fn set_protocol_version(&mut self, version: &semver::Version) {
+        self.non_utf8_names = NON_UTF8_NAMES_VERSION.matches(version);
+    }
+
+    /// Returns the number of remote files and directories that the client has open, directory
+    /// streams included.
+    pub(crate) fn open_descriptors(&self) -> (u64, u64) {
+        let dirs = self
+            .open_files
+            .values()
+            .filter(|file| matches!(file, RemoteFile::Directory(..)))
+            .count();
+        let files = self.open_files.len() - dirs;
+
+        (files as u64, (dirs + self.dir_streams.len()) as u64)
+    }
+
+    /// Returns the descriptors of the open files (including the directories opened as files) and
+    /// of the open directory streams.
+    pub(crate) fn open_fds(&self) -> (Vec<u64>, Vec<u64>) {
+        (
+            self.open_files.keys().copied().collect(),
+            self.dir_streams.keys().copied().collect(),
+        )
+    }
+
+    /// Returns the response for a write `request` that doesn't fit in the free space of the
+    /// filesystem with the [`FileManager::set_write_space_margin`], with the error of a full
+    /// filesystem.
+    ///
+    /// Writes that overwrite bytes of the file are checked as well, we don't know which blocks of
+    /// the file are allocated. The same goes for the bytes copied with [`CopyFileRangeRequest`]
+    /// (also used for `sendfile` between remote files), allocated with [`FallocateFileRequest`]
+    /// and added by truncating a file to a larger length.
+    ///
+    /// The free space is checked only after [`WRITE_SPACE_CHECK_THRESHOLD`] bytes, see
+    /// [`FileManager::set_write_space_margin`].
+    fn write_space_rejection(&mut self, request: &FileRequest) -> Option<FileResponse> {
+        let margin = self.write_space_margin?;
+        let (file, length) = match request {
+            FileRequest::Write(WriteFileRequest { fd, write_bytes }) => {
+                (WrittenFile::Fd(*fd), write_bytes.len() as u64)
+            }
+            FileRequest::WriteLimited(WriteLimitedFileRequest {
+                remote_fd,
+                write_bytes,
+                ..
+            }) => (WrittenFile::Fd(*remote_fd), write_bytes.len() as u64),
+            FileRequest::WriteV(WriteVFileRequest { fd, buffers, .. }) => (
+                WrittenFile::Fd(*fd),
+                buffers.iter().map(|buffer| buffer.len() as u64).sum(),
+            ),
+            FileRequest::CopyFileRange(CopyFileRangeRequest { fd_out, length, .. }) => {
+                (WrittenFile::Fd(*fd_out), *length)
+            }
+            // Punching holes and collapsing ranges free the space.
+            FileRequest::Fallocate(FallocateFileRequest {
+                fd, mode, length, ..
+            }) if (*mode as libc::c_int)
+                & (libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_COLLAPSE_RANGE)
+                == 0 =>
+            {
+                (WrittenFile::Fd(*fd), *length)
+            }
+            FileRequest::Ftruncate(FtruncateFileRequest { fd, length }) => {
+                (WrittenFile::Fd(*fd), *length)
+            }
+            FileRequest::Truncate(TruncateFileRequest { path, length }) => (
+                WrittenFile::Path(self.resolve_path_at(None, path.clone()).ok()?),
+                *length,
+            ),
+            _ => return None,
+        };
+
+        // Truncating adds only the bytes past the current end of the file.
+        let size = match (&file, request) {
+            (WrittenFile::Fd(fd), FileRequest::Ftruncate(..)) => {
+                self.lock_file(*fd).ok()?.metadata().ok()?.len()
+            }
+            (WrittenFile::Path(path), _) => path.metadata().ok()?.len(),
+            _ => 0,
+        };
+        let length = length.saturating_sub(size);
+
+        self.unchecked_write_bytes = self.unchecked_write_bytes.saturating_add(length);
+        if length == 0 || self.unchecked_write_bytes < WRITE_SPACE_CHECK_THRESHOLD {
+            return None;
+        }
+        self.unchecked_write_bytes = 0;
+
+        // The write itself fails when the file is not there.
+        let statvfs = match &file {
+            WrittenFile::Fd(fd) => nix::sys::statvfs::fstatvfs(self.lock_file(*fd).ok()?),
+            WrittenFile::Path(path) => nix::sys::statvfs::statvfs(path),
+        };
+        let available = match statvfs {
+            Ok(statvfs) => {
+                (statvfs.blocks_available() as u64).saturating_mul(statvfs.fragment_size() as u64)
+            }
+            Err(error) => {
+                warn!(?file, %error, "Failed to get the free space for a remote write");
+                return None;
+            }
+        };
+
+        if available >= length.saturating_add(margin) {
+            return None;
+        }
+
+        trace!(
+            ?file,
+            length,
+            available,
+            margin,
+            "Rejected a remote write that doesn't fit"
+        );
+        let error = ResponseError::from(io::Error::from_raw_os_error(libc::ENOSPC));
+        match request {
+            FileRequest::WriteLimited(..) => Some(FileResponse::WriteLimited(Err(error))),
+            FileRequest::WriteV(..) => Some(FileResponse::WriteV(Err(error))),
+            FileRequest::CopyFileRange(..) => Some(FileResponse::CopyFileRange(Err(error))),
+            FileRequest::Fallocate(..) => Some(FileResponse::Fallocate(Err(error))),
+            FileRequest::Truncate(..) | FileRequest::Ftruncate(..) => {
+                Some(FileResponse::Truncate(Err(error)))
+            }
+            _ => Some(FileResponse::Write(Err(error))),
+        }
+    }
+
+    /// Returns the response for a `request` that would modify the filesystem in a read-only
+    /// session, with the error of a read-only filesystem.
+    ///
+    /// [`FileRequest::ScratchDir`] does not get a response, it's just ignored.
+    fn read_only_rejection(request: &FileRequest) -> Option<FileResponse> {
+        let error = ResponseError::from(io::Error::from_raw_os_error(libc::EROFS));
+
+        match request {
+            FileRequest::Open(..) | FileRequest::OpenRelative(..) | FileRequest::OpenAt2(..) => {
+                Some(FileResponse::Open(Err(error)))
+            }
+            FileRequest::Write(..) => Some(FileResponse::Write(Err(error))),
+            FileRequest::WriteLimited(..) => Some(FileResponse::WriteLimited(Err(error))),
+            FileRequest::WriteV(..) => Some(FileResponse::WriteV(Err(error))),
+            FileRequest::Unlink(..) | FileRequest::UnlinkAt(..) => {
+                Some(FileResponse::Unlink(Err(error)))
+            }
+            FileRequest::Rename(..) | FileRequest::RenameAt(..) => {
+                Some(FileResponse::Rename(Err(error)))
+            }
+            FileRequest::Chmod(..) | FileRequest::Fchmod(..) | FileRequest::ChmodAt(..) => {
+                Some(FileResponse::Chmod(Err(error)))
+            }
+            FileRequest::Chown(..) | FileRequest::Fchown(..) | FileRequest::ChownAt(..) => {
+                Some(FileResponse::Chown(Err(error)))
+            }
+            FileRequest::Truncate(..) | FileRequest::Ftruncate(..) => {
+                Some(FileResponse::Truncate(Err(error)))
+            }
+            FileRequest::Fallocate(..) => Some(FileResponse::Fallocate(Err(error))),
+            FileRequest::CopyFileRange(..) => Some(FileResponse::CopyFileRange(Err(error))),
+            FileRequest::Symlink(..) | FileRequest::SymlinkAt(..) => {
+                Some(FileResponse::Symlink(Err(error)))
+            }
+            FileRequest::Link(..) | FileRequest::LinkAt(..) => Some(FileResponse::Link(Err(error))),
+            FileRequest::UtimensAt(..) | FileRequest::Futimens(..) => {
+                Some(FileResponse::Utimens(Err(error)))
+            }
+            FileRequest::SetXattr(..) => Some(FileResponse::SetXattr(Err(error))),
+            FileRequest::RemoveXattr(..) => Some(FileResponse::RemoveXattr(Err(error))),
+            _ => None,
+        }
+    }
+
+    /// Creates the [`ScratchDir`] of this client, replacing the previous one.
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn create_scratch_dir(&mut self, patterns: Vec<String>) {
+        match ScratchDir::create(&self.root_path, patterns) {
+            Ok(scratch_dir) => self.scratch_dir = Some(scratch_dir),
+            Err(error) => error!(%error, "failed to create the scratch directory"),
+        }
+    }
+
+    /// Returns the path in the [`ScratchDir`] where the operations on `path` are redirected, if
+    /// any.
+    fn scratch_path(&self, path: &Path) -> Option<PathBuf> {
+        self.scratch_dir.as_ref()?.redirect(path)
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     fn open(
         &mut self,
         path: PathBuf,
         open_options: OpenOptionsInternal,
     ) -> RemoteResult<OpenFileResponse> {
+        let path = match self.scratch_path(&path) {
+            Some(scratch_path) => {
+                if open_options.create || open_options.create_new {
+                    if let Some(parent) = scratch_path.parent() {
+                        std::fs::create_dir_all(self.root_path.join(parent))?;
+                    }
+                }
+
+                scratch_path
+            }
+            None => path,
+        };
+
         let path = resolve_path(path, &self.root_path)?;
         let file = OpenOptions::from(open_options).open(&path)?;
+        let file = self.token_access.check(&self.root_path, &path, file)?;
 
         let fd = self
             .fds_iter
@@ -291,6 +1123,42 @@ impl FileManager {
         Ok(OpenFileResponse { fd })
     }
 
+    /// Handles [`FileRequest::ReadWhole`], like [`Self::open`] with read-only options, followed
+    /// by reading the whole file and closing it, without allocating a remote fd.
+    ///
+    /// Only regular files that are no bigger than `max_size` are read, the others are just
+    /// stat'ed (opening a FIFO would block).
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    fn read_whole(&mut self, path: PathBuf, max_size: u64) -> RemoteResult<ReadWholeFileResponse> {
+        let path = path
+            .strip_prefix("/")
+            .inspect_err(|fail| error!("file_worker -> {:#?}", fail))?;
+        let path = self.scratch_path(path).unwrap_or_else(|| path.into());
+        let path = resolve_path(path, &self.root_path)?;
+
+        let metadata = path.metadata()?;
+        if !metadata.is_file() || metadata.len() > max_size {
+            return Ok(ReadWholeFileResponse {
+                metadata: metadata.into(),
+                bytes: None,
+            });
+        }
+
+        let file = File::open(&path)?;
+        let file = self.token_access.check(&self.root_path, &path, file)?;
+        let metadata = file.metadata()?;
+
+        // Files in `/proc` report a size of 0, so we don't trust it.
+        let mut bytes = Vec::with_capacity(metadata.len() as usize);
+        file.take(max_size.saturating_add(1))
+            .read_to_end(&mut bytes)?;
+
+        Ok(ReadWholeFileResponse {
+            metadata: metadata.into(),
+            bytes: (bytes.len() as u64 <= max_size).then_some(bytes),
+        })
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     fn open_relative(
         &mut self,
@@ -307,6 +1175,7 @@ impl FileManager {
             let path = relative_dir.join(&path);
 
             let file = OpenOptions::from(open_options).open(&path)?;
+            let file = self.token_access.check(&self.root_path, &path, file)?;
 
             let fd = self.fds_iter.next().ok_or_else(|| {
                 ResponseError::IdsExhausted("FileManager::open_relative".to_string())
@@ -322,128 +1191,1182 @@ impl FileManager {
 
             self.open_files.insert(fd, remote_file);
 
-            Ok(OpenFileResponse { fd })
+            Ok(OpenFileResponse { fd })
+        } else {
+            Err(ResponseError::NotDirectory(relative_fd))
+        }
+    }
+
+    /// Handles our `openat2_detour`, the kernel enforces the `RESOLVE_*` flags in `resolve` on
+    /// `path`, which is relative to the remote directory `dirfd`.
+    ///
+    /// When `dirfd` is [`None`] (`AT_FDCWD`, the layer sends absolute paths then), `path` is
+    /// resolved in the target's root, like in [`Self::open`].
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    fn open_at2(
+        &mut self,
+        dirfd: Option<u64>,
+        path: PathBuf,
+        open_options: OpenOptionsInternal,
+        resolve: u64,
+    ) -> RemoteResult<OpenFileResponse> {
+        let (dir, path, resolve) = match dirfd {
+            Some(dirfd) => match self
+                .open_files
+                .get(&dirfd)
+                .ok_or(ResponseError::NotFound(dirfd))?
+            {
+                RemoteFile::Directory(dir) => (dir.clone(), path, resolve),
+                RemoteFile::File(..) => return Err(ResponseError::NotDirectory(dirfd)),
+            },
+            // An absolute path is never beneath the directory.
+            None if resolve & libc::RESOLVE_BENEATH != 0 => {
+                return Err(io::Error::from_raw_os_error(libc::EXDEV).into())
+            }
+            None => {
+                let path = path
+                    .strip_prefix("/")
+                    .inspect_err(|fail| error!("file_worker -> {:#?}", fail))?
+                    .to_path_buf();
+
+                (
+                    self.root_path.clone(),
+                    path,
+                    resolve | libc::RESOLVE_IN_ROOT,
+                )
+            }
+        };
+
+        let requested_path = dir.join(&path);
+        let dir = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_DIRECTORY)
+            .open(dir)?;
+        let path = CString::new(path.into_os_string().into_vec()).map_err(io::Error::from)?;
+
+        // `open_how` is `#[non_exhaustive]`.
+        let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+        how.flags = open_flags(&open_options) as u64;
+        how.resolve = resolve;
+        if open_options.create || open_options.create_new {
+            how.mode = 0o666;
+        }
+
+        // Not every libc has an `openat2` wrapper.
+        let raw_fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                dir.as_raw_fd(),
+                path.as_ptr(),
+                &how as *const libc::open_how,
+                std::mem::size_of::<libc::open_how>(),
+            )
+        };
+        if raw_fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let file = unsafe { File::from_raw_fd(raw_fd as _) };
+        let file = self
+            .token_access
+            .check(&self.root_path, &requested_path, file)?;
+
+        let fd = self
+            .fds_iter
+            .next()
+            .ok_or_else(|| ResponseError::IdsExhausted("open_at2".to_string()))?;
+
+        let remote_file = if file.metadata()?.is_dir() {
+            // The path that the kernel resolved.
+            RemoteFile::Directory(read_link(format!("/proc/self/fd/{}", file.as_raw_fd()))?)
+        } else {
+            RemoteFile::File(file)
+        };
+
+        self.open_files.insert(fd, remote_file);
+
+        Ok(OpenFileResponse { fd })
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn read(&mut self, fd: u64, buffer_size: u64) -> RemoteResult<ReadFileResponse> {
+        self.open_files
+            .get_mut(&fd)
+            .ok_or(ResponseError::NotFound(fd))
+            .and_then(|remote_file| {
+                if let RemoteFile::File(file) = remote_file {
+                    let mut buffer = vec![0; buffer_size as usize];
+                    let read_amount = file.read(&mut buffer)?;
+
+                    // Truncate the buffer based on the actual number of bytes read
+                    buffer.truncate(read_amount);
+
+                    // Create the response with the read bytes and the read amount
+                    let response = ReadFileResponse {
+                        bytes: buffer,
+                        read_amount: read_amount as u64,
+                    };
+
+                    Ok(response)
+                } else {
+                    Err(ResponseError::NotFile(fd))
+                }
+            })
+    }
+
+    /// Remote implementation of `fgets`.
+    ///
+    /// Uses `BufReader::read_line` to read a line (including `"\n"`) from a file with `fd`. The
+    /// file cursor position has to be moved manually due to this.
+    ///
+    /// `fgets` is only supposed to read `buffer_size`, so we limit moving the file's position based
+    /// on it (even though we return the full `Vec` of bytes).
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn read_line(
+        &mut self,
+        fd: u64,
+        buffer_size: u64,
+    ) -> RemoteResult<ReadFileResponse> {
+        self.open_files
+            .get_mut(&fd)
+            .ok_or(ResponseError::NotFound(fd))
+            .and_then(|remote_file| {
+                if let RemoteFile::File(file) = remote_file {
+                    let original_position = file.stream_position()?;
+                    // limit bytes read using take
+                    let mut reader = BufReader::new(std::io::Read::by_ref(file)).take(buffer_size);
+                    let mut buffer = Vec::<u8>::with_capacity(buffer_size as usize);
+                    Ok(reader
+                        .read_until(b'\n', &mut buffer)
+                        .and_then(|read_amount| {
+                            // Revert file to original position + bytes read (in case the
+                            // bufreader advanced too much)
+                            file.seek(SeekFrom::Start(original_position + read_amount as u64))?;
+
+                            // We handle the extra bytes in the `fgets` hook, so here we can
+                            // just return the full buffer.
+                            let response = ReadFileResponse {
+                                bytes: buffer,
+                                read_amount: read_amount as u64,
+                            };
+
+                            Ok(response)
+                        })?)
+                } else {
+                    Err(ResponseError::NotFile(fd))
+                }
+            })
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn read_limited(
+        &mut self,
+        fd: u64,
+        buffer_size: u64,
+        start_from: u64,
+    ) -> RemoteResult<ReadFileResponse> {
+        self.open_files
+            .get_mut(&fd)
+            .ok_or(ResponseError::NotFound(fd))
+            .and_then(|remote_file| {
+                if let RemoteFile::File(file) = remote_file {
+                    let mut buffer = vec![0; buffer_size as usize];
+
+                    let read_amount = file.read_at(&mut buffer, start_from)?;
+
+                    // Truncate the buffer based on the actual number of bytes read
+                    buffer.truncate(read_amount);
+
+                    // Further optimization: Create the response with the read bytes and the read
+                    // amount We will no longer send entire buffer filled with
+                    // zeroes
+                    let response = ReadFileResponse {
+                        bytes: buffer,
+                        read_amount: read_amount as u64,
+                    };
+
+                    Ok(response)
+                } else {
+                    Err(ResponseError::NotFile(fd))
+                }
+            })
+    }
+
+    /// Handles our `readlink_detour` with [`std::fs::read_link`].
+    #[tracing::instrument(level = Level::TRACE, skip_all)]
+    pub(crate) fn read_link(&mut self, path: PathBuf) -> RemoteResult<ReadLinkFileResponse> {
+        let path = path
+            .strip_prefix("/")
+            .inspect_err(|fail| error!("file_worker -> {:#?}", fail))?;
+
+        let path = self.scratch_path(path).unwrap_or_else(|| path.into());
+        let full_path = self.root_path.join(path);
+
+        let path = read_link(full_path)?;
+        // Would fail to encode in the response, `EILSEQ` is what the local fs gives for names
+        // it can't represent.
+        if path.to_str().is_none() {
+            return Err(io::Error::from_raw_os_error(libc::EILSEQ).into());
+        }
+
+        Ok(ReadLinkFileResponse { path })
+    }
+
+    /// Handles our `realpath_detour`, see [`canonicalize::canonicalize`].
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub(crate) fn canonicalize(&mut self, path: PathBuf) -> RemoteResult<CanonicalizePathResponse> {
+        // Like the other operations, the paths redirected to the scratch directory are resolved
+        // there.
+        let path = canonicalize::canonicalize(&path, |resolved| {
+            let relative = resolved.strip_prefix("/").unwrap_or(resolved);
+            let relative = self
+                .scratch_path(relative)
+                .unwrap_or_else(|| relative.into());
+            self.root_path.join(relative)
+        })?;
+        // Would fail to encode in the response, like in `Self::read_link`.
+        if path.to_str().is_none() {
+            return Err(io::Error::from_raw_os_error(libc::EILSEQ).into());
+        }
+
+        Ok(CanonicalizePathResponse { path })
+    }
+
+    /// Handles our `unlink_detour`, removes the file (or the empty directory, when `remove_dir`
+    /// is set) at the absolute `path`.
+    ///
+    /// Only the parent directory of `path` is resolved, so a symbolic link is removed itself, not
+    /// its destination.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn unlink(&mut self, path: PathBuf, remove_dir: bool) -> RemoteResult<()> {
+        let host_path = self.resolve_parent(&path)?;
+
+        Self::remove(&host_path, remove_dir)
+    }
+
+    /// Returns the host path of the absolute `path`, resolving only its parent directory, for the
+    /// operations that act on the directory entry itself (e.g. a symbolic link is removed or
+    /// renamed, not its destination).
+    fn resolve_parent(&self, path: &Path) -> RemoteResult<PathBuf> {
+        let path = path
+            .strip_prefix("/")
+            .inspect_err(|fail| error!("file_worker -> {:#?}", fail))?;
+
+        let path = self.scratch_path(path).unwrap_or_else(|| path.into());
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let parent = resolve_path(path.parent().unwrap_or(Path::new("")), &self.root_path)?;
+
+        Ok(parent.join(file_name))
+    }
+
+    /// Like [`Self::resolve_parent`], but a relative `path` is resolved against the remote
+    /// directory `dirfd`, unless `dirfd` is [`None`] (`AT_FDCWD`, the layer sends absolute
+    /// paths then).
+    fn resolve_parent_at(&self, dirfd: Option<u64>, path: PathBuf) -> RemoteResult<PathBuf> {
+        let Some(dirfd) = dirfd.filter(|_| path.is_relative()) else {
+            return self.resolve_parent(&path);
+        };
+
+        match self
+            .open_files
+            .get(&dirfd)
+            .ok_or(ResponseError::NotFound(dirfd))?
+        {
+            RemoteFile::Directory(dir) => Ok(dir.join(path)),
+            RemoteFile::File(..) => Err(ResponseError::NotDirectory(dirfd)),
+        }
+    }
+
+    /// Like [`Self::resolve_parent_at`], but the symbolic links in the last component of `path`
+    /// are followed too.
+    fn resolve_path_at(&self, dirfd: Option<u64>, path: PathBuf) -> RemoteResult<PathBuf> {
+        let Some(dirfd) = dirfd.filter(|_| path.is_relative()) else {
+            let path = path
+                .strip_prefix("/")
+                .inspect_err(|fail| error!("file_worker -> {:#?}", fail))?;
+            let path = self.scratch_path(path).unwrap_or_else(|| path.into());
+
+            return Ok(resolve_path(path, &self.root_path)?);
+        };
+
+        match self
+            .open_files
+            .get(&dirfd)
+            .ok_or(ResponseError::NotFound(dirfd))?
+        {
+            RemoteFile::Directory(dir) => Ok(dir.join(path)),
+            RemoteFile::File(..) => Err(ResponseError::NotDirectory(dirfd)),
+        }
+    }
+
+    /// Handles our `unlinkat_detour`, `path` is relative to the directory `dirfd`, unless it's
+    /// absolute or `dirfd` is [`None`] (`AT_FDCWD`, the layer sends absolute paths then).
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn unlink_at(
+        &mut self,
+        dirfd: Option<u64>,
+        path: PathBuf,
+        remove_dir: bool,
+    ) -> RemoteResult<()> {
+        let host_path = self.resolve_parent_at(dirfd, path)?;
+
+        Self::remove(&host_path, remove_dir)
+    }
+
+    /// Removes the file or the empty directory at `host_path`.
+    fn remove(host_path: &Path, remove_dir: bool) -> RemoteResult<()> {
+        if remove_dir {
+            std::fs::remove_dir(host_path)?;
+        } else {
+            std::fs::remove_file(host_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `rename_detour`, renames the absolute `old_path` to the absolute `new_path`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn rename(&mut self, old_path: PathBuf, new_path: PathBuf) -> RemoteResult<()> {
+        let old_host_path = self.resolve_parent(&old_path)?;
+        let new_host_path = self.resolve_parent(&new_path)?;
+
+        std::fs::rename(old_host_path, new_host_path)?;
+
+        Ok(())
+    }
+
+    /// Handles our `renameat_detour` and `renameat2_detour`, each path is relative to its own
+    /// remote directory, like in [`Self::unlink_at`].
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn rename_at(
+        &mut self,
+        old_dirfd: Option<u64>,
+        old_path: PathBuf,
+        new_dirfd: Option<u64>,
+        new_path: PathBuf,
+        flags: u32,
+    ) -> RemoteResult<()> {
+        let old_host_path = self.resolve_parent_at(old_dirfd, old_path)?;
+        let new_host_path = self.resolve_parent_at(new_dirfd, new_path)?;
+
+        if flags == 0 {
+            std::fs::rename(old_host_path, new_host_path)?;
+            return Ok(());
+        }
+
+        let old_host_path =
+            CString::new(old_host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+        let new_host_path =
+            CString::new(new_host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+
+        // Not every libc has a `renameat2` wrapper.
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_renameat2,
+                libc::AT_FDCWD,
+                old_host_path.as_ptr(),
+                libc::AT_FDCWD,
+                new_host_path.as_ptr(),
+                flags,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `link_detour`, creates a hard link at the absolute `new_path` to the file at the
+    /// absolute `old_path` (not following a symbolic link there, like `link` on Linux).
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn link(&mut self, old_path: PathBuf, new_path: PathBuf) -> RemoteResult<()> {
+        let old_host_path = self.resolve_parent(&old_path)?;
+        let new_host_path = self.resolve_parent(&new_path)?;
+
+        std::fs::hard_link(old_host_path, new_host_path)?;
+
+        Ok(())
+    }
+
+    /// Handles our `linkat_detour`, each path is relative to its own remote directory, like in
+    /// [`Self::rename_at`].
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn link_at(
+        &mut self,
+        old_dirfd: Option<u64>,
+        old_path: PathBuf,
+        new_dirfd: Option<u64>,
+        new_path: PathBuf,
+        follow_symlinks: bool,
+    ) -> RemoteResult<()> {
+        let new_host_path = self.resolve_parent_at(new_dirfd, new_path)?;
+
+        if !follow_symlinks {
+            let old_host_path = self.resolve_parent_at(old_dirfd, old_path)?;
+            std::fs::hard_link(old_host_path, new_host_path)?;
+
+            return Ok(());
+        }
+
+        let old_host_path = self.resolve_path_at(old_dirfd, old_path)?;
+
+        let old_host_path =
+            CString::new(old_host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+        let new_host_path =
+            CString::new(new_host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+
+        let result = unsafe {
+            libc::linkat(
+                libc::AT_FDCWD,
+                old_host_path.as_ptr(),
+                libc::AT_FDCWD,
+                new_host_path.as_ptr(),
+                libc::AT_SYMLINK_FOLLOW,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `symlink_detour`, creates a symbolic link at the absolute `link_path` that
+    /// points to `target`, which is stored as is.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn symlink(&mut self, target: PathBuf, link_path: PathBuf) -> RemoteResult<()> {
+        let link_host_path = self.resolve_parent(&link_path)?;
+
+        unix_fs::symlink(target, link_host_path)?;
+
+        Ok(())
+    }
+
+    /// Handles our `symlinkat_detour`, `link_path` is relative to the remote directory
+    /// `new_dirfd`, like in [`Self::unlink_at`].
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn symlink_at(
+        &mut self,
+        target: PathBuf,
+        new_dirfd: Option<u64>,
+        link_path: PathBuf,
+    ) -> RemoteResult<()> {
+        let link_host_path = self.resolve_parent_at(new_dirfd, link_path)?;
+
+        unix_fs::symlink(target, link_host_path)?;
+
+        Ok(())
+    }
+
+    /// Handles our `chmod_detour`, changes the mode of the file at the absolute `path`, following
+    /// symbolic links.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn chmod(&mut self, path: PathBuf, mode: u32) -> RemoteResult<()> {
+        let host_path = self.resolve_path_at(None, path)?;
+
+        std::fs::set_permissions(host_path, Permissions::from_mode(mode))?;
+
+        Ok(())
+    }
+
+    /// Handles our `fchmod_detour`, changes the mode of the remote file (or directory) `fd`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn fchmod(&mut self, fd: u64, mode: u32) -> RemoteResult<()> {
+        let permissions = Permissions::from_mode(mode);
+
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file.set_permissions(permissions)?,
+            RemoteFile::Directory(path) => std::fs::set_permissions(path, permissions)?,
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `fchmodat_detour`, `path` is relative to the directory `dirfd`, like in
+    /// [`Self::unlink_at`].
+    ///
+    /// With `AT_SYMLINK_NOFOLLOW` in `flags`, a symbolic link itself is changed, which Linux
+    /// doesn't support, so that fails.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn chmod_at(
+        &mut self,
+        dirfd: Option<u64>,
+        path: PathBuf,
+        mode: u32,
+        flags: u32,
+    ) -> RemoteResult<()> {
+        if flags & libc::AT_SYMLINK_NOFOLLOW as u32 != 0 {
+            let host_path = self.resolve_parent_at(dirfd, path)?;
+            let host_path =
+                CString::new(host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+
+            let result = unsafe {
+                libc::fchmodat(
+                    libc::AT_FDCWD,
+                    host_path.as_ptr(),
+                    mode,
+                    flags as libc::c_int,
+                )
+            };
+            if result == -1 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            return Ok(());
+        }
+
+        let Some(dirfd) = dirfd.filter(|_| path.is_relative()) else {
+            return self.chmod(path, mode);
+        };
+
+        match self
+            .open_files
+            .get(&dirfd)
+            .ok_or(ResponseError::NotFound(dirfd))?
+        {
+            RemoteFile::Directory(dir) => {
+                std::fs::set_permissions(dir.join(path), Permissions::from_mode(mode))?
+            }
+            RemoteFile::File(..) => return Err(ResponseError::NotDirectory(dirfd)),
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `glob_detour`, see [`glob::expand`].
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn glob(&mut self, pattern: String, max_results: u64) -> RemoteResult<GlobResponse> {
+        Ok(glob::expand(&self.root_path, &pattern, max_results)?)
+    }
+
+    /// Handles our `truncate_detour`, truncates (or extends) the file at the absolute `path` to
+    /// `length` bytes.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn truncate(&mut self, path: PathBuf, length: u64) -> RemoteResult<()> {
+        let host_path = self.resolve_path_at(None, path)?;
+
+        OpenOptions::new()
+            .write(true)
+            .open(host_path)?
+            .set_len(length)?;
+
+        Ok(())
+    }
+
+    /// Handles our `ftruncate_detour`, truncates (or extends) the remote file `fd` to `length`
+    /// bytes.
+    ///
+    /// Fails like `ftruncate` when `fd` was not opened for writing.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn ftruncate(&mut self, fd: u64, length: u64) -> RemoteResult<()> {
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file.set_len(length)?,
+            RemoteFile::Directory(..) => {
+                return Err(io::Error::from_raw_os_error(libc::EISDIR).into())
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `fallocate_detour` and `posix_fallocate_detour`, allocates (or, depending on
+    /// the `mode`, deallocates) the given byte range of the remote file `fd`.
+    ///
+    /// With `posix`, the `mode` is ignored and the range is zero-filled when the filesystem does
+    /// not support the preallocation.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn fallocate(
+        &mut self,
+        fd: u64,
+        mode: u32,
+        offset: u64,
+        length: u64,
+        posix: bool,
+    ) -> RemoteResult<()> {
+        let file = match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file,
+            RemoteFile::Directory(..) => {
+                return Err(io::Error::from_raw_os_error(libc::EISDIR).into())
+            }
+        };
+
+        let (Ok(offset), Ok(length)) =
+            (libc::off_t::try_from(offset), libc::off_t::try_from(length))
+        else {
+            return Err(io::Error::from_raw_os_error(libc::EFBIG).into());
+        };
+
+        if posix {
+            // Returns the error number instead of setting `errno`.
+            match unsafe { libc::posix_fallocate(file.as_raw_fd(), offset, length) } {
+                0 => Ok(()),
+                error => Err(io::Error::from_raw_os_error(error).into()),
+            }
+        } else if unsafe { libc::fallocate(file.as_raw_fd(), mode as libc::c_int, offset, length) }
+            == -1
+        {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Handles our `copy_file_range_detour`, copies up to `length` bytes between two remote files
+    /// without sending them to the client.
+    ///
+    /// [`None`] offsets use (and advance) the file offsets, as the kernel does.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn copy_file_range(
+        &mut self,
+        fd_in: u64,
+        offset_in: Option<u64>,
+        fd_out: u64,
+        offset_out: Option<u64>,
+        length: u64,
+    ) -> RemoteResult<CopyFileRangeResponse> {
+        let raw_fd = |fd: u64| -> RemoteResult<RawFd> {
+            match self
+                .open_files
+                .get(&fd)
+                .ok_or(ResponseError::NotFound(fd))?
+            {
+                RemoteFile::File(file) => Ok(file.as_raw_fd()),
+                RemoteFile::Directory(..) => Err(io::Error::from_raw_os_error(libc::EISDIR).into()),
+            }
+        };
+        let raw_fd_in = raw_fd(fd_in)?;
+        let raw_fd_out = raw_fd(fd_out)?;
+
+        // Offsets past `off_t::MAX` fail with `EINVAL`, as they would in the kernel.
+        let offset = |offset: Option<u64>| {
+            offset
+                .map(libc::off_t::try_from)
+                .transpose()
+                .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))
+        };
+        let mut offset_in = offset(offset_in)?;
+        let mut offset_out = offset(offset_out)?;
+
+        let offset_ptr = |offset: &mut Option<libc::off_t>| {
+            offset
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |offset| offset as *mut libc::off_t)
+        };
+        let copied_amount = unsafe {
+            libc::copy_file_range(
+                raw_fd_in,
+                offset_ptr(&mut offset_in),
+                raw_fd_out,
+                offset_ptr(&mut offset_out),
+                usize::try_from(length).unwrap_or(usize::MAX),
+                0,
+            )
+        };
+
+        match u64::try_from(copied_amount) {
+            Ok(copied_amount) => Ok(CopyFileRangeResponse { copied_amount }),
+            Err(..) => Err(io::Error::last_os_error().into()),
+        }
+    }
+
+    /// Handles our `readv_detour` and `preadv_detour`, reads into buffers of the given `lengths`
+    /// with a single `readv` (or `preadv`). A [`None`] offset uses (and advances) the file offset.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn read_vectored(
+        &mut self,
+        fd: u64,
+        lengths: Vec<u64>,
+        offset: Option<u64>,
+    ) -> RemoteResult<ReadVFileResponse> {
+        let mut file = self.lock_file(fd)?;
+
+        let mut buffers = lengths
+            .into_iter()
+            .map(|length| vec![0; length as usize])
+            .collect::<Vec<_>>();
+        let mut slices = buffers
+            .iter_mut()
+            .map(|buffer| io::IoSliceMut::new(buffer))
+            .collect::<Vec<_>>();
+
+        let read_amount = match offset {
+            None => file.read_vectored(&mut slices)?,
+            Some(offset) => {
+                let offset = libc::off_t::try_from(offset)
+                    .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?;
+                // `IoSliceMut` has the same layout as `iovec`.
+                let read_amount = unsafe {
+                    libc::preadv(
+                        file.as_raw_fd(),
+                        slices.as_ptr().cast(),
+                        libc::c_int::try_from(slices.len()).unwrap_or(libc::c_int::MAX),
+                        offset,
+                    )
+                };
+                usize::try_from(read_amount).map_err(|_| io::Error::last_os_error())?
+            }
+        };
+        drop(slices);
+
+        // Keeps only the filled buffers.
+        let mut remaining = read_amount;
+        buffers.retain_mut(|buffer| {
+            if remaining == 0 {
+                return false;
+            }
+
+            buffer.truncate(remaining);
+            remaining -= buffer.len();
+            true
+        });
+
+        Ok(ReadVFileResponse { buffers })
+    }
+
+    /// Handles our `writev_detour` and `pwritev_detour`, writes the `buffers` with a single
+    /// `writev` (or `pwritev`). A [`None`] offset uses (and advances) the file offset.
+    #[tracing::instrument(level = Level::TRACE, skip(self, buffers))]
+    pub(crate) fn write_vectored(
+        &mut self,
+        fd: u64,
+        buffers: Vec<Vec<u8>>,
+        offset: Option<u64>,
+    ) -> RemoteResult<WriteFileResponse> {
+        let mut file = self.lock_file(fd)?;
+
+        let slices = buffers
+            .iter()
+            .map(|buffer| io::IoSlice::new(buffer))
+            .collect::<Vec<_>>();
+
+        let written_amount = match offset {
+            None => file.write_vectored(&slices)?,
+            Some(offset) => {
+                let offset = libc::off_t::try_from(offset)
+                    .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?;
+                // `IoSlice` has the same layout as `iovec`.
+                let written_amount = unsafe {
+                    libc::pwritev(
+                        file.as_raw_fd(),
+                        slices.as_ptr().cast(),
+                        libc::c_int::try_from(slices.len()).unwrap_or(libc::c_int::MAX),
+                        offset,
+                    )
+                };
+                usize::try_from(written_amount).map_err(|_| io::Error::last_os_error())?
+            }
+        };
+
+        Ok(WriteFileResponse {
+            written_amount: written_amount as u64,
+        })
+    }
+
+    /// Reads the bytes of our `sendfile_detour`, to be written to the agent's connection by the
+    /// caller.
+    ///
+    /// Reads at most [`SEND_FILE_MAX_COUNT`] bytes, the client sends the rest in the next
+    /// requests, as with a partial `sendfile`. A [`None`] offset uses (and advances) the file
+    /// offset.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn read_for_send_file(
+        &mut self,
+        request: &SendFileRequest,
+    ) -> RemoteResult<Vec<u8>> {
+        let count = request.count.min(SEND_FILE_MAX_COUNT);
+
+        let response = match request.offset {
+            Some(offset) => self.read_limited(request.fd, count, offset)?,
+            None => self.read(request.fd, count)?,
+        };
+
+        Ok(response.bytes)
+    }
+
+    /// Reads the chunk `sequence` of a [`FileRequest::ReadStream`], after the `streamed` bytes of
+    /// the previous chunks, to be sent to the client by the caller.
+    ///
+    /// The chunk is no bigger than `max_chunk_size`, so that it fits in the frames of the
+    /// client. A [`None`] offset uses (and advances) the file offset.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn read_stream_chunk(
+        &mut self,
+        request: &ReadStreamFileRequest,
+        sequence: u64,
+        streamed: u64,
+        max_chunk_size: u64,
+    ) -> RemoteResult<ReadChunkResponse> {
+        let remaining = request.length.saturating_sub(streamed);
+        let count = request.chunk_size.min(max_chunk_size).max(1).min(remaining);
+
+        let response = match request.offset {
+            Some(offset) => {
+                self.read_limited(request.fd, count, offset.saturating_add(streamed))?
+            }
+            None => self.read(request.fd, count)?,
+        };
+
+        Ok(ReadChunkResponse {
+            sequence,
+            last: response.read_amount < count || response.read_amount == remaining,
+            bytes: response.bytes,
+        })
+    }
+
+    /// Handles our `chown_detour`, changes the owner and group of the file at the absolute
+    /// `path`, following symbolic links.
+    ///
+    /// [`None`] leaves the owner (or group) as is.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn chown(
+        &mut self,
+        path: PathBuf,
+        owner: Option<u32>,
+        group: Option<u32>,
+    ) -> RemoteResult<()> {
+        let host_path = self.resolve_path_at(None, path)?;
+
+        unix_fs::chown(host_path, owner, group)?;
+
+        Ok(())
+    }
+
+    /// Handles our `fchown_detour`, changes the owner and group of the remote file (or
+    /// directory) `fd`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn fchown(
+        &mut self,
+        fd: u64,
+        owner: Option<u32>,
+        group: Option<u32>,
+    ) -> RemoteResult<()> {
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => unix_fs::fchown(file, owner, group)?,
+            RemoteFile::Directory(path) => unix_fs::chown(path, owner, group)?,
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `fchownat_detour` (and `lchown_detour`), `path` is relative to the directory
+    /// `dirfd`, like in [`Self::unlink_at`].
+    ///
+    /// With `AT_SYMLINK_NOFOLLOW` in `flags`, a symbolic link itself is changed. An empty `path`
+    /// (`AT_EMPTY_PATH`) changes `dirfd` itself.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn chown_at(
+        &mut self,
+        dirfd: Option<u64>,
+        path: PathBuf,
+        owner: Option<u32>,
+        group: Option<u32>,
+        flags: u32,
+    ) -> RemoteResult<()> {
+        if let Some(dirfd) = dirfd.filter(|_| path.as_os_str().is_empty()) {
+            return self.fchown(dirfd, owner, group);
+        }
+
+        if flags & libc::AT_SYMLINK_NOFOLLOW as u32 != 0 {
+            let host_path = self.resolve_parent_at(dirfd, path)?;
+            unix_fs::lchown(host_path, owner, group)?;
+
+            return Ok(());
+        }
+
+        let Some(dirfd) = dirfd.filter(|_| path.is_relative()) else {
+            return self.chown(path, owner, group);
+        };
+
+        match self
+            .open_files
+            .get(&dirfd)
+            .ok_or(ResponseError::NotFound(dirfd))?
+        {
+            RemoteFile::Directory(dir) => unix_fs::chown(dir.join(path), owner, group)?,
+            RemoteFile::File(..) => return Err(ResponseError::NotDirectory(dirfd)),
+        }
+
+        Ok(())
+    }
+
+    /// Handles our `utimensat_detour`, sets the access and modification times of `path`, which
+    /// is relative to the directory `dirfd`, like in [`Self::unlink_at`].
+    ///
+    /// With `AT_SYMLINK_NOFOLLOW` in `flags`, the times of a symbolic link itself are set.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn utimens_at(
+        &mut self,
+        dirfd: Option<u64>,
+        path: PathBuf,
+        access_time: FileTimeInternal,
+        modification_time: FileTimeInternal,
+        flags: u32,
+    ) -> RemoteResult<()> {
+        let host_path = if flags & libc::AT_SYMLINK_NOFOLLOW as u32 != 0 {
+            self.resolve_parent_at(dirfd, path)?
+        } else {
+            self.resolve_path_at(dirfd, path)?
+        };
+
+        set_file_times(host_path, access_time, modification_time, flags)
+    }
+
+    /// Handles our `futimens_detour`, sets the access and modification times of the remote file
+    /// (or directory) `fd`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn futimens(
+        &mut self,
+        fd: u64,
+        access_time: FileTimeInternal,
+        modification_time: FileTimeInternal,
+    ) -> RemoteResult<()> {
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => {
+                let times = [file_timespec(access_time), file_timespec(modification_time)];
+
+                if unsafe { libc::futimens(file.as_raw_fd(), times.as_ptr()) } == -1 {
+                    return Err(io::Error::last_os_error().into());
+                }
+
+                Ok(())
+            }
+            RemoteFile::Directory(path) => {
+                set_file_times(path.clone(), access_time, modification_time, 0)
+            }
+        }
+    }
+
+    /// Handles our `statx_detour`, the extended metadata of `path`, which is relative to the
+    /// directory `dirfd` like in [`Self::utimens_at`], or of the remote file (or directory) `dirfd`
+    /// itself when there's no `path`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn statx(
+        &mut self,
+        dirfd: Option<u64>,
+        path: Option<PathBuf>,
+        follow_symlink: bool,
+        mask: u32,
+    ) -> RemoteResult<StatxResponse> {
+        let metadata = match (dirfd, path) {
+            (Some(fd), None) => match self
+                .open_files
+                .get(&fd)
+                .ok_or(ResponseError::NotFound(fd))?
+            {
+                RemoteFile::File(file) => {
+                    statx_metadata(file.as_raw_fd(), Path::new(""), libc::AT_EMPTY_PATH, mask)?
+                }
+                RemoteFile::Directory(path) => statx_metadata(libc::AT_FDCWD, path, 0, mask)?,
+            },
+            (dirfd, Some(path)) if follow_symlink => {
+                let host_path = self.resolve_path_at(dirfd, path)?;
+                statx_metadata(libc::AT_FDCWD, &host_path, 0, mask)?
+            }
+            (dirfd, Some(path)) => {
+                let host_path = self.resolve_parent_at(dirfd, path)?;
+                statx_metadata(libc::AT_FDCWD, &host_path, libc::AT_SYMLINK_NOFOLLOW, mask)?
+            }
+            (None, None) => return Err(io::Error::from(io::ErrorKind::InvalidInput).into()),
+        };
+
+        Ok(StatxResponse {
+            metadata: Box::new(metadata),
+        })
+    }
+
+    /// Handles our `fsync_detour` and `fdatasync_detour`, flushes the remote file (or directory)
+    /// `fd` to the disk.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn fsync(&mut self, fd: u64, data_only: bool) -> RemoteResult<()> {
+        let directory;
+        let file = match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file,
+            // Syncing a directory persists the entries that were created or renamed in it.
+            RemoteFile::Directory(path) => {
+                directory = File::open(path)?;
+                &directory
+            }
+        };
+
+        if data_only {
+            file.sync_data()?;
         } else {
-            Err(ResponseError::NotDirectory(relative_fd))
+            file.sync_all()?;
         }
+
+        Ok(())
     }
 
-    #[tracing::instrument(level = "trace", skip(self))]
-    pub(crate) fn read(&mut self, fd: u64, buffer_size: u64) -> RemoteResult<ReadFileResponse> {
-        self.open_files
-            .get_mut(&fd)
-            .ok_or(ResponseError::NotFound(fd))
-            .and_then(|remote_file| {
-                if let RemoteFile::File(file) = remote_file {
-                    let mut buffer = vec![0; buffer_size as usize];
-                    let read_amount = file.read(&mut buffer)?;
+    /// The [`XattrFile`] of `path`, which is relative to the directory `fd` like in
+    /// [`Self::utimens_at`], or of the remote file (or directory) `fd` itself when there's no
+    /// `path`.
+    fn xattr_file(
+        &self,
+        path: Option<PathBuf>,
+        fd: Option<u64>,
+        follow_symlink: bool,
+    ) -> RemoteResult<XattrFile> {
+        let host_path = match (path, fd) {
+            (None, Some(fd)) => match self
+                .open_files
+                .get(&fd)
+                .ok_or(ResponseError::NotFound(fd))?
+            {
+                RemoteFile::File(file) => return Ok(XattrFile::Fd(file.as_raw_fd())),
+                RemoteFile::Directory(path) => path.clone(),
+            },
+            (Some(path), dirfd) if follow_symlink => self.resolve_path_at(dirfd, path)?,
+            (Some(path), dirfd) => self.resolve_parent_at(dirfd, path)?,
+            (None, None) => return Err(io::Error::from(io::ErrorKind::InvalidInput).into()),
+        };
 
-                    // Truncate the buffer based on the actual number of bytes read
-                    buffer.truncate(read_amount);
+        let host_path =
+            CString::new(host_path.into_os_string().into_vec()).map_err(io::Error::from)?;
+        Ok(XattrFile::Path(host_path, follow_symlink))
+    }
 
-                    // Create the response with the read bytes and the read amount
-                    let response = ReadFileResponse {
-                        bytes: buffer,
-                        read_amount: read_amount as u64,
-                    };
+    /// Handles our `getxattr_detour` and the other `*getxattr` ones, the value of the extended
+    /// attribute `name`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn get_xattr(
+        &mut self,
+        path: Option<PathBuf>,
+        fd: Option<u64>,
+        follow_symlink: bool,
+        name: Vec<u8>,
+    ) -> RemoteResult<GetXattrResponse> {
+        let file = self.xattr_file(path, fd, follow_symlink)?;
+        let name = CString::new(name).map_err(io::Error::from)?;
 
-                    Ok(response)
-                } else {
-                    Err(ResponseError::NotFile(fd))
-                }
-            })
+        let value = read_xattr_buffer(|buffer| file.get(&name, buffer))?;
+
+        Ok(GetXattrResponse { value })
     }
 
-    /// Remote implementation of `fgets`.
-    ///
-    /// Uses `BufReader::read_line` to read a line (including `"\n"`) from a file with `fd`. The
-    /// file cursor position has to be moved manually due to this.
-    ///
-    /// `fgets` is only supposed to read `buffer_size`, so we limit moving the file's position based
-    /// on it (even though we return the full `Vec` of bytes).
-    #[tracing::instrument(level = "trace", skip(self))]
-    pub(crate) fn read_line(
+    /// Handles our `setxattr_detour` and the other `*setxattr` ones, `flags` are the
+    /// `setxattr` ones.
+    #[tracing::instrument(level = Level::TRACE, skip(self, value))]
+    pub(crate) fn set_xattr(
         &mut self,
-        fd: u64,
-        buffer_size: u64,
-    ) -> RemoteResult<ReadFileResponse> {
-        self.open_files
-            .get_mut(&fd)
-            .ok_or(ResponseError::NotFound(fd))
-            .and_then(|remote_file| {
-                if let RemoteFile::File(file) = remote_file {
-                    let original_position = file.stream_position()?;
-                    // limit bytes read using take
-                    let mut reader = BufReader::new(std::io::Read::by_ref(file)).take(buffer_size);
-                    let mut buffer = Vec::<u8>::with_capacity(buffer_size as usize);
-                    Ok(reader
-                        .read_until(b'\n', &mut buffer)
-                        .and_then(|read_amount| {
-                            // Revert file to original position + bytes read (in case the
-                            // bufreader advanced too much)
-                            file.seek(SeekFrom::Start(original_position + read_amount as u64))?;
+        path: Option<PathBuf>,
+        fd: Option<u64>,
+        follow_symlink: bool,
+        name: Vec<u8>,
+        value: Vec<u8>,
+        flags: i32,
+    ) -> RemoteResult<()> {
+        let file = self.xattr_file(path, fd, follow_symlink)?;
+        let name = CString::new(name).map_err(io::Error::from)?;
+
+        if file.set(&name, &value, flags) == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
 
-                            // We handle the extra bytes in the `fgets` hook, so here we can
-                            // just return the full buffer.
-                            let response = ReadFileResponse {
-                                bytes: buffer,
-                                read_amount: read_amount as u64,
-                            };
+        Ok(())
+    }
 
-                            Ok(response)
-                        })?)
-                } else {
-                    Err(ResponseError::NotFile(fd))
-                }
-            })
+    /// Handles our `listxattr_detour` and the other `*listxattr` ones, the names of all the
+    /// extended attributes.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn list_xattr(
+        &mut self,
+        path: Option<PathBuf>,
+        fd: Option<u64>,
+        follow_symlink: bool,
+    ) -> RemoteResult<ListXattrResponse> {
+        let file = self.xattr_file(path, fd, follow_symlink)?;
+
+        let list = read_xattr_buffer(|buffer| file.list(buffer))?;
+        // The names in the list are all nul terminated.
+        let names = list
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+
+        Ok(ListXattrResponse { names })
     }
 
-    #[tracing::instrument(level = "trace", skip(self))]
-    pub(crate) fn read_limited(
+    /// Handles our `removexattr_detour` and the other `*removexattr` ones.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn remove_xattr(
         &mut self,
-        fd: u64,
-        buffer_size: u64,
-        start_from: u64,
-    ) -> RemoteResult<ReadFileResponse> {
-        self.open_files
-            .get_mut(&fd)
-            .ok_or(ResponseError::NotFound(fd))
-            .and_then(|remote_file| {
-                if let RemoteFile::File(file) = remote_file {
-                    let mut buffer = vec![0; buffer_size as usize];
+        path: Option<PathBuf>,
+        fd: Option<u64>,
+        follow_symlink: bool,
+        name: Vec<u8>,
+    ) -> RemoteResult<()> {
+        let file = self.xattr_file(path, fd, follow_symlink)?;
+        let name = CString::new(name).map_err(io::Error::from)?;
 
-                    let read_amount = file.read_at(&mut buffer, start_from)?;
+        if file.remove(&name) == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
 
-                    // Truncate the buffer based on the actual number of bytes read
-                    buffer.truncate(read_amount);
+        Ok(())
+    }
 
-                    // Further optimization: Create the response with the read bytes and the read
-                    // amount We will no longer send entire buffer filled with
-                    // zeroes
-                    let response = ReadFileResponse {
-                        bytes: buffer,
-                        read_amount: read_amount as u64,
-                    };
+    /// The remote file `fd`, the locks can't be placed on the directories we only have the path
+    /// of.
+    fn lock_file(&self, fd: u64) -> RemoteResult<&File> {
+        match self
+            .open_files
+            .get(&fd)
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => Ok(file),
+            RemoteFile::Directory(..) => Err(ResponseError::NotFile(fd)),
+        }
+    }
 
-                    Ok(response)
-                } else {
-                    Err(ResponseError::NotFile(fd))
-                }
-            })
+    /// Handles our `flock_detour`, see [`lock`] on how the locks are placed.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn flock(&mut self, fd: u64, lock_type: LockTypeInternal) -> RemoteResult<()> {
+        Ok(lock::flock(self.lock_file(fd)?, lock_type)?)
     }
 
-    /// Handles our `readlink_detour` with [`std::fs::read_link`].
-    #[tracing::instrument(level = Level::TRACE, skip_all)]
-    pub(crate) fn read_link(&mut self, path: PathBuf) -> RemoteResult<ReadLinkFileResponse> {
-        let path = path
-            .strip_prefix("/")
-            .inspect_err(|fail| error!("file_worker -> {:#?}", fail))?;
+    /// Handles the `F_SETLK` and `F_SETLKW` of our `fcntl_detour`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn set_lock(&mut self, fd: u64, lock: FileLockInternal) -> RemoteResult<()> {
+        Ok(lock::set_lock(self.lock_file(fd)?, lock)?)
+    }
 
-        let full_path = self.root_path.join(path);
+    /// Handles the `F_GETLK` of our `fcntl_detour`.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn get_lock(
+        &mut self,
+        fd: u64,
+        lock: FileLockInternal,
+    ) -> RemoteResult<GetLockResponse> {
+        let conflict = lock::get_lock(self.lock_file(fd)?, lock)?;
 
-        read_link(full_path)
-            .map(|path| ReadLinkFileResponse { path })
-            .map_err(ResponseError::from)
+        Ok(GetLockResponse { conflict })
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
@@ -472,27 +2395,46 @@ impl FileManager {
             })
     }
 
-    pub(crate) fn seek(&mut self, fd: u64, seek_from: SeekFrom) -> RemoteResult<SeekFileResponse> {
+    /// Handles our `lseek_detour`, also with `SEEK_DATA` and `SEEK_HOLE`, so that the app sees the
+    /// holes of sparse files, e.g. when copying disk images.
+    pub(crate) fn seek(
+        &mut self,
+        fd: u64,
+        seek_from: SeekFromInternal,
+    ) -> RemoteResult<SeekFileResponse> {
         trace!(
             "FileManager::seek -> fd {:#?} | seek_from {:#?}",
             fd,
             seek_from
         );
 
-        self.open_files
+        let file = match self
+            .open_files
             .get_mut(&fd)
-            .ok_or(ResponseError::NotFound(fd))
-            .and_then(|remote_file| {
-                if let RemoteFile::File(file) = remote_file {
-                    let seek_result = file
-                        .seek(seek_from)
-                        .map(|result_offset| SeekFileResponse { result_offset })?;
+            .ok_or(ResponseError::NotFound(fd))?
+        {
+            RemoteFile::File(file) => file,
+            RemoteFile::Directory(..) => return Err(ResponseError::NotFile(fd)),
+        };
 
-                    Ok(seek_result)
-                } else {
-                    Err(ResponseError::NotFile(fd))
-                }
-            })
+        let (whence, offset) = match seek_from {
+            SeekFromInternal::Data(offset) => (libc::SEEK_DATA, offset),
+            SeekFromInternal::Hole(offset) => (libc::SEEK_HOLE, offset),
+            seek_from => {
+                let result_offset = file.seek(seek_from.try_into()?)?;
+                return Ok(SeekFileResponse { result_offset });
+            }
+        };
+
+        // Like the kernel, there's no data nor hole past the end of the file.
+        let offset =
+            libc::off_t::try_from(offset).map_err(|_| io::Error::from_raw_os_error(libc::ENXIO))?;
+        match unsafe { libc::lseek(file.as_raw_fd(), offset, whence) } {
+            -1 => Err(io::Error::last_os_error().into()),
+            result_offset => Ok(SeekFileResponse {
+                result_offset: result_offset as u64,
+            }),
+        }
     }
 
     pub(crate) fn write(
@@ -545,6 +2487,7 @@ impl FileManager {
         pathname: PathBuf,
         mode: u8,
     ) -> RemoteResult<AccessFileResponse> {
+        let pathname = self.scratch_path(&pathname).unwrap_or(pathname);
         let pathname = resolve_path(pathname, &self.root_path)?;
         trace!(
             "FileManager::access -> pathname {:#?} | mode {:#?}",
@@ -610,6 +2553,7 @@ impl FileManager {
         let path = path.strip_prefix("/").map_err(|_| {
             std::io::Error::new(std::io::ErrorKind::InvalidInput, "couldn't strip prefix")
         })?;
+        let path = self.scratch_path(path).unwrap_or_else(|| path.into());
         let res = if follow_symlink {
             resolve_path(path, &self.root_path)?.metadata()
         } else {
@@ -641,6 +2585,30 @@ impl FileManager {
         })
     }
 
+    /// Handles [`StatFsRequest`], the path is resolved in the target's root like in
+    /// [`Self::xstat`].
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn statfs(&mut self, path: PathBuf) -> RemoteResult<XstatFsResponse> {
+        let path = path
+            .strip_prefix("/")
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "couldn't strip prefix"))?;
+        let path = self.scratch_path(path).unwrap_or_else(|| path.into());
+        let path = resolve_path(path, &self.root_path)?;
+
+        let statfs = nix::sys::statfs::statfs(&path)
+            .map_err(|err| io::Error::from_raw_os_error(err as i32))?;
+
+        Ok(XstatFsResponse {
+            metadata: statfs.into(),
+        })
+    }
+
+    /// Resolves the `path` of a [`LayerWatch::Add`](mirrord_protocol::watch::LayerWatch::Add) in
+    /// the target's root, for the [`WatchManager`](watch::WatchManager).
+    pub(crate) fn watch_path(&self, path: PathBuf) -> RemoteResult<PathBuf> {
+        self.resolve_path_at(None, path)
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     pub(crate) fn fdopen_dir(&mut self, fd: u64) -> RemoteResult<OpenDirResponse> {
         let path = match self
@@ -673,7 +2641,7 @@ impl FileManager {
     fn path_to_dir_entry_internal(
         path: &Path,
         position: u64,
-        name: String,
+        name: Vec<u8>,
     ) -> io::Result<DirEntryInternal> {
         let metadata = std::fs::metadata(path)?;
         Ok(DirEntryInternal {
@@ -688,17 +2656,9 @@ impl FileManager {
     /// to chain with the iterator returned by [`std::fs::read_dir`].
     fn get_current_and_parent_entries(current: &Path) -> VecDeque<io::Result<DirEntryInternal>> {
         let mut entries = VecDeque::default();
-        entries.push_back(Self::path_to_dir_entry_internal(
-            current,
-            0,
-            ".".to_string(),
-        ));
+        entries.push_back(Self::path_to_dir_entry_internal(current, 0, b".".to_vec()));
         if let Some(parent) = current.parent() {
-            entries.push_back(Self::path_to_dir_entry_internal(
-                parent,
-                1,
-                "..".to_string(),
-            ))
+            entries.push_back(Self::path_to_dir_entry_internal(parent, 1, b"..".to_vec()))
         }
         entries
     }
@@ -718,8 +2678,12 @@ impl FileManager {
                 Some(RemoteFile::File(_file)) => Err(ResponseError::NotDirectory(fd)),
                 Some(RemoteFile::Directory(dir)) => {
                     let current_and_parent = Self::get_current_and_parent_entries(dir);
-                    let stream =
-                        GetDEnts64Stream::new(dir.read_dir()?, current_and_parent).peekable();
+                    let stream = GetDEnts64Stream::new(
+                        dir.read_dir()?,
+                        current_and_parent,
+                        self.non_utf8_names,
+                    )
+                    .peekable();
                     Ok(e.insert(stream))
                 }
             },
@@ -729,10 +2693,16 @@ impl FileManager {
 
     #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
     pub(crate) fn read_dir(&mut self, fd: u64) -> RemoteResult<ReadDirResponse> {
+        let non_utf8_names = self.non_utf8_names;
         let dir_stream = self.get_dir_stream(fd)?;
         let result = if let Some(offset_entry_pair) = dir_stream.next() {
+            let entry = DirEntryInternal::try_from(offset_entry_pair)?;
             ReadDirResponse {
-                direntry: Some(offset_entry_pair.try_into()?),
+                direntry: Some(if non_utf8_names {
+                    entry
+                } else {
+                    lossy_name(entry)
+                }),
             }
         } else {
             ReadDirResponse { direntry: None }
@@ -750,16 +2720,96 @@ impl FileManager {
         fd: u64,
         amount: usize,
     ) -> RemoteResult<ReadDirBatchResponse> {
+        let non_utf8_names = self.non_utf8_names;
         let result = self
             .get_dir_stream(fd)?
             .take(amount)
             .map(DirEntryInternal::try_from)
+            .map(|entry| {
+                if non_utf8_names {
+                    entry
+                } else {
+                    entry.map(lossy_name)
+                }
+            })
             .try_collect::<Vec<_>>()
             .map(|dir_entries| ReadDirBatchResponse { fd, dir_entries })?;
 
         Ok(result)
     }
 
+    /// [`Self::read_dir_batch`], with the metadata of the entries.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub(crate) fn read_dir_plus_batch(
+        &mut self,
+        fd: u64,
+        amount: usize,
+    ) -> RemoteResult<ReadDirPlusBatchResponse> {
+        let non_utf8_names = self.non_utf8_names;
+        let dir_entries = self
+            .get_dir_stream(fd)?
+            .take(amount)
+            .map(DirEntryPlusInternal::try_from)
+            .map(|entry| {
+                if non_utf8_names {
+                    entry
+                } else {
+                    entry.map(|entry| DirEntryPlusInternal {
+                        entry: lossy_name(entry.entry),
+                        ..entry
+                    })
+                }
+            })
+            .try_collect::<Vec<_>>()?;
+
+        Ok(ReadDirPlusBatchResponse { fd, dir_entries })
+    }
+
+    /// Reads all the remaining entries of the dir `fd`, and returns only the changes since the
+    /// listing identified by `since`, if we still have it.
+    ///
+    /// The listing is kept for the next request, under a random cookie, so that a client that
+    /// reconnects to another agent doesn't get the changes since an unrelated listing.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub(crate) fn read_dir_delta(
+        &mut self,
+        fd: u64,
+        since: Option<u64>,
+    ) -> RemoteResult<ReadDirDeltaResponse> {
+        let non_utf8_names = self.non_utf8_names;
+        let entries = self
+            .get_dir_stream(fd)?
+            .map(DirEntryInternal::try_from)
+            .map(|entry| {
+                if non_utf8_names {
+                    entry
+                } else {
+                    entry.map(lossy_name)
+                }
+            })
+            .try_collect::<Vec<_>>()?;
+
+        let previous = since.and_then(|since| {
+            let index = self
+                .dir_snapshots
+                .iter()
+                .position(|(cookie, _)| *cookie == since)?;
+            self.dir_snapshots.remove(index)
+        });
+        let delta = match previous {
+            Some((_, previous)) => DirListingDelta::between(&previous, &entries),
+            None => DirListingDelta::Full(entries.clone()),
+        };
+
+        let cookie = rand::random();
+        if self.dir_snapshots.len() >= MAX_DIR_SNAPSHOTS {
+            self.dir_snapshots.pop_front();
+        }
+        self.dir_snapshots.push_back((cookie, entries));
+
+        Ok(ReadDirDeltaResponse { fd, cookie, delta })
+    }
+
     /// The getdents64 syscall writes dir entries to a buffer, as long as they fit.
     /// If a call did not process all the entries in a dir, the result of the next call continues
     /// where the last one stopped.
@@ -817,3 +2867,277 @@ impl FileManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mirrord_protocol::RemoteIOError;
+
+    use super::*;
+
+    /// A [`FileManager`] that redirects the paths matching `pattern` to its [`ScratchDir`].
+    fn scratch_manager(pattern: &str) -> FileManager {
+        let mut file_manager = FileManager::new(None);
+        file_manager.create_scratch_dir(vec![pattern.to_string()]);
+        assert!(file_manager.scratch_dir.is_some());
+        file_manager
+    }
+
+    /// Opens the remote file `path` for writing through the [`FileManager`], creating it.
+    fn open_for_writing(file_manager: &mut FileManager, path: &Path) -> u64 {
+        let open = FileRequest::Open(OpenFileRequest {
+            path: path.to_path_buf(),
+            open_options: OpenOptionsInternal {
+                write: true,
+                create: true,
+                ..Default::default()
+            },
+        });
+        let Some(FileResponse::Open(Ok(OpenFileResponse { fd }))) =
+            file_manager.handle_message(open).unwrap()
+        else {
+            panic!("failed to open {path:?} for writing");
+        };
+        fd
+    }
+
+    /// Writes `contents` to `path` through the [`FileManager`], and returns the host path of the
+    /// copy in its [`ScratchDir`].
+    fn write_through_scratch(
+        file_manager: &mut FileManager,
+        path: &Path,
+        contents: &[u8],
+    ) -> PathBuf {
+        let fd = open_for_writing(file_manager, path);
+        file_manager.write(fd, contents.to_vec()).unwrap();
+        file_manager.close(fd);
+
+        let scratch_path = file_manager.scratch_path(path).unwrap();
+        file_manager.root_path.join(scratch_path)
+    }
+
+    /// A path that only this test uses, with its original remote contents.
+    fn remote_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("mirrord-{name}-{}", std::process::id()));
+        std::fs::write(&path, "original contents").unwrap();
+        path
+    }
+
+    #[test]
+    fn truncate_scratch_copy() {
+        let path = remote_file("truncate");
+        let mut file_manager =
+            scratch_manager(&format!("^{}$", regex::escape(&path.to_string_lossy())));
+        let scratch_path = write_through_scratch(&mut file_manager, &path, b"scratch contents");
+
+        file_manager.truncate(path.clone(), 7).unwrap();
+
+        assert_eq!(std::fs::read(&scratch_path).unwrap(), b"scratch");
+        assert_eq!(std::fs::read(&path).unwrap(), b"original contents");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn chmod_scratch_copy() {
+        let path = remote_file("chmod");
+        std::fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+        let mut file_manager =
+            scratch_manager(&format!("^{}$", regex::escape(&path.to_string_lossy())));
+        let scratch_path = write_through_scratch(&mut file_manager, &path, b"scratch contents");
+
+        file_manager.chmod(path.clone(), 0o600).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&scratch_path), 0o600);
+        assert_eq!(mode(&path), 0o644);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// The path only exists in the [`ScratchDir`], so changing the owner of the remote file would
+    /// fail.
+    #[test]
+    fn chown_scratch_copy() {
+        let path = remote_file("chown");
+        std::fs::remove_file(&path).unwrap();
+        let mut file_manager =
+            scratch_manager(&format!("^{}$", regex::escape(&path.to_string_lossy())));
+        let scratch_path = write_through_scratch(&mut file_manager, &path, b"scratch contents");
+        let metadata = std::fs::metadata(&scratch_path).unwrap();
+
+        file_manager
+            .chown(path.clone(), Some(metadata.uid()), Some(metadata.gid()))
+            .unwrap();
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn link_scratch_copy() {
+        let path = remote_file("link");
+        let mut file_manager =
+            scratch_manager(&format!("^{}$", regex::escape(&path.to_string_lossy())));
+        write_through_scratch(&mut file_manager, &path, b"scratch contents");
+        let link_path = path.with_extension("link");
+
+        file_manager
+            .link_at(None, path.clone(), None, link_path.clone(), true)
+            .unwrap();
+
+        assert_eq!(std::fs::read(&link_path).unwrap(), b"scratch contents");
+        std::fs::remove_file(link_path).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// The path only exists in the [`ScratchDir`], so resolving it in the target would fail.
+    #[test]
+    fn canonicalize_scratch_copy() {
+        let path = remote_file("canonicalize");
+        std::fs::remove_file(&path).unwrap();
+        let mut file_manager =
+            scratch_manager(&format!("^{}$", regex::escape(&path.to_string_lossy())));
+        write_through_scratch(&mut file_manager, &path, b"scratch contents");
+
+        let CanonicalizePathResponse { path: resolved } =
+            file_manager.canonicalize(path.clone()).unwrap();
+
+        let parent = std::fs::canonicalize(path.parent().unwrap()).unwrap();
+        assert_eq!(resolved, parent.join(path.file_name().unwrap()));
+        assert!(!path.exists());
+    }
+
+    /// Whether the `response` is the `ENOSPC` error of [`FileManager::write_space_rejection`].
+    fn no_space(response: Option<FileResponse>) -> bool {
+        let Some(
+            FileResponse::Write(Err(error))
+            | FileResponse::CopyFileRange(Err(error))
+            | FileResponse::Fallocate(Err(error))
+            | FileResponse::Truncate(Err(error)),
+        ) = response
+        else {
+            return false;
+        };
+
+        matches!(
+            error,
+            ResponseError::RemoteIO(RemoteIOError {
+                raw_os_error: Some(libc::ENOSPC),
+                ..
+            })
+        )
+    }
+
+    /// A [`FileManager`] with a margin that no filesystem has free, so that every checked write
+    /// is rejected.
+    fn full_manager() -> FileManager {
+        let mut file_manager = FileManager::new(None);
+        file_manager.set_write_space_margin(Some(u64::MAX / 2));
+        file_manager
+    }
+
+    fn write_request(fd: u64, length: u64) -> FileRequest {
+        FileRequest::Write(WriteFileRequest {
+            fd,
+            write_bytes: vec![0; length as usize],
+        })
+    }
+
+    #[test]
+    fn small_writes_checked_after_threshold() {
+        let path = remote_file("write-space-threshold");
+        let mut file_manager = full_manager();
+        let fd = open_for_writing(&mut file_manager, &path);
+
+        let small = WRITE_SPACE_CHECK_THRESHOLD / 4;
+        for _ in 0..3 {
+            assert!(!no_space(
+                file_manager
+                    .handle_message(write_request(fd, small))
+                    .unwrap()
+            ));
+        }
+        assert!(no_space(
+            file_manager
+                .handle_message(write_request(fd, small))
+                .unwrap()
+        ));
+
+        // Counted from the last check.
+        assert!(!no_space(
+            file_manager
+                .handle_message(write_request(fd, small))
+                .unwrap()
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_space_margin_allows_writes_that_fit() {
+        let path = remote_file("write-space-fits");
+        let mut file_manager = FileManager::new(None);
+        file_manager.set_write_space_margin(Some(0));
+        let fd = open_for_writing(&mut file_manager, &path);
+
+        let response = file_manager
+            .handle_message(write_request(fd, WRITE_SPACE_CHECK_THRESHOLD))
+            .unwrap();
+
+        assert!(matches!(response, Some(FileResponse::Write(Ok(..)))));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_space_rejects_copy_and_allocation() {
+        let path = remote_file("write-space-copy");
+        let mut file_manager = full_manager();
+        let fd = open_for_writing(&mut file_manager, &path);
+
+        let copy = FileRequest::CopyFileRange(CopyFileRangeRequest {
+            fd_in: fd,
+            offset_in: Some(0),
+            fd_out: fd,
+            offset_out: None,
+            length: WRITE_SPACE_CHECK_THRESHOLD,
+        });
+        assert!(no_space(file_manager.handle_message(copy).unwrap()));
+
+        let allocate = |mode| {
+            FileRequest::Fallocate(FallocateFileRequest {
+                fd,
+                mode,
+                offset: 0,
+                length: WRITE_SPACE_CHECK_THRESHOLD,
+                posix: false,
+            })
+        };
+        assert!(no_space(file_manager.handle_message(allocate(0)).unwrap()));
+        let punch_hole = (libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE) as u32;
+        assert!(!no_space(
+            file_manager.handle_message(allocate(punch_hole)).unwrap()
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Only the bytes past the end of the file count.
+    #[test]
+    fn write_space_rejects_truncate_to_larger() {
+        let path = remote_file("write-space-truncate");
+        let mut file_manager = full_manager();
+        let fd = open_for_writing(&mut file_manager, &path);
+        let length = 7 + WRITE_SPACE_CHECK_THRESHOLD;
+
+        let ftruncate = |length| FileRequest::Ftruncate(FtruncateFileRequest { fd, length });
+        assert!(!no_space(
+            file_manager.handle_message(ftruncate(7)).unwrap()
+        ));
+        assert!(no_space(
+            file_manager.handle_message(ftruncate(length)).unwrap()
+        ));
+
+        let truncate = FileRequest::Truncate(TruncateFileRequest {
+            path: path.clone(),
+            length,
+        });
+        assert!(no_space(file_manager.handle_message(truncate).unwrap()));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 7);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+//! Resolution of [`CanonicalizePathRequest`](mirrord_protocol::file::CanonicalizePathRequest)
+//! paths in the target's filesystem, see [`canonicalize`].
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Most symbolic links followed while resolving a path, `MAXSYMLINKS` on Linux.
+const MAX_SYMLINKS: usize = 40;
+
+/// A component of a path that is left to resolve.
+enum Step {
+    Parent,
+    Name(OsString),
+}
+
+/// Resolves the absolute `path` like `realpath(3)` does, with the symbolic links (the absolute
+/// ones too) resolved in the target's filesystem, where `host_path` finds each absolute path
+/// resolved so far.
+///
+/// `..` is applied to the path resolved so far, so `/link/..` is the parent of the destination of
+/// `link`. The result never leaves the root of the target, as `..` of the root is the root itself.
+pub(crate) fn canonicalize<F>(path: &Path, host_path: F) -> io::Result<PathBuf>
+where
+    F: Fn(&Path) -> PathBuf,
+{
+    if !path.is_absolute() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path should be absolute",
+        ));
+    }
+
+    // The next component to resolve is the last one.
+    let mut pending = Vec::new();
+    push_steps(&mut pending, path);
+
+    let mut resolved = PathBuf::from("/");
+    let mut followed = 0;
+    while let Some(step) = pending.pop() {
+        let name = match step {
+            Step::Parent => {
+                resolved.pop();
+                continue;
+            }
+            Step::Name(name) => name,
+        };
+
+        let candidate = resolved.join(name);
+        let host_path = host_path(&candidate);
+        let metadata = fs::symlink_metadata(&host_path)?;
+
+        if metadata.is_symlink() {
+            followed += 1;
+            if followed > MAX_SYMLINKS {
+                return Err(io::Error::from_raw_os_error(libc::ELOOP));
+            }
+
+            let destination = fs::read_link(&host_path)?;
+            if destination.is_absolute() {
+                resolved = PathBuf::from("/");
+            }
+            push_steps(&mut pending, &destination);
+        } else if !metadata.is_dir() && !pending.is_empty() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        } else {
+            resolved = candidate;
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Pushes the components of `path` to `pending`, the first one last.
+fn push_steps(pending: &mut Vec<Step>, path: &Path) {
+    pending.extend(
+        path.components()
+            .rev()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(Step::Name(name.to_owned())),
+                Component::ParentDir => Some(Step::Parent),
+                Component::CurDir | Component::RootDir | Component::Prefix(..) => None,
+            }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn resolve_in_root() {
+        let root_path =
+            std::env::temp_dir().join(format!("mirrord-canonicalize-{}", std::process::id()));
+        let release = root_path.join("srv/releases/v2");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join("config.yaml"), "").unwrap();
+        symlink("releases/v2", root_path.join("srv/current")).unwrap();
+        symlink("/srv/current", root_path.join("app")).unwrap();
+        symlink("loop", root_path.join("loop")).unwrap();
+
+        let resolve = |path: &str| {
+            canonicalize(Path::new(path), |resolved| {
+                root_path.join(resolved.strip_prefix("/").unwrap())
+            })
+        };
+        let through_links = resolve("/app/config.yaml");
+        let parent_of_link = resolve("/app/../v2/config.yaml");
+        let above_root = resolve("/../../srv/./current");
+        let missing = resolve("/srv/missing");
+        let looping = resolve("/loop");
+        let not_dir = resolve("/app/config.yaml/..");
+        fs::remove_dir_all(&root_path).unwrap();
+
+        assert_eq!(
+            through_links.unwrap(),
+            Path::new("/srv/releases/v2/config.yaml")
+        );
+        assert_eq!(
+            parent_of_link.unwrap(),
+            Path::new("/srv/releases/v2/config.yaml")
+        );
+        assert_eq!(above_root.unwrap(), Path::new("/srv/releases/v2"));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(looping.unwrap_err().raw_os_error(), Some(libc::ELOOP));
+        assert_eq!(not_dir.unwrap_err().raw_os_error(), Some(libc::ENOTDIR));
+    }
+}
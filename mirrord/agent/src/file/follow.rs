@@ -0,0 +1,210 @@
+//! Remote files that a client follows like `tail -f`, see [`FollowManager`].
+
+use std::{collections::HashMap, time::Duration};
+
+use mirrord_protocol::{
+    file::{FollowChunkResponse, SeekFromInternal},
+    RemoteResult,
+};
+use tokio::time::{self, Interval, MissedTickBehavior};
+use tracing::Level;
+
+use super::FileManager;
+
+/// How often the followed files are checked for appended bytes.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most bytes read from a followed file at every check.
+const FOLLOW_CHUNK_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug)]
+struct Follow {
+    fd: u64,
+    /// File offset of `fd` when the follow started.
+    start: u64,
+    /// Bytes sent to the client so far.
+    sent: u64,
+}
+
+/// The [`FollowFileRequest`](mirrord_protocol::file::FollowFileRequest)s of a single client, by
+/// their ids.
+///
+/// The followed files are read at their own offsets, so the file offsets of the descriptors only
+/// move when the follow stops, by the bytes that the client used.
+#[derive(Default)]
+pub(crate) struct FollowManager {
+    follows: HashMap<u64, Follow>,
+    /// Created with the first follow.
+    interval: Option<Interval>,
+}
+
+impl FollowManager {
+    /// Starts the follow `id` of `fd`, from the current file offset of `fd`.
+    #[tracing::instrument(level = Level::TRACE, skip(self, file_manager), ret)]
+    pub(crate) fn follow(
+        &mut self,
+        id: u64,
+        fd: u64,
+        file_manager: &mut FileManager,
+    ) -> RemoteResult<()> {
+        let start = file_manager
+            .seek(fd, SeekFromInternal::Current(0))?
+            .result_offset;
+        self.follows.insert(id, Follow { fd, start, sent: 0 });
+
+        Ok(())
+    }
+
+    /// Stops the follow `id`, and moves the file offset past the `consumed` bytes.
+    #[tracing::instrument(level = Level::TRACE, skip(self, file_manager))]
+    pub(crate) fn unfollow(&mut self, id: u64, consumed: u64, file_manager: &mut FileManager) {
+        let Some(Follow { fd, start, .. }) = self.follows.remove(&id) else {
+            return;
+        };
+
+        let offset = SeekFromInternal::Start(start.saturating_add(consumed));
+        if let Err(error) = file_manager.seek(fd, offset) {
+            tracing::debug!(id, fd, %error, "Failed to move the offset of the followed file");
+        }
+
+        if self.follows.is_empty() {
+            self.interval = None;
+        }
+    }
+
+    /// Waits for the next check of the followed files, pending forever when nothing is followed.
+    ///
+    /// Cancel safe.
+    pub(crate) async fn tick(&mut self) {
+        if self.follows.is_empty() {
+            return std::future::pending().await;
+        }
+
+        self.interval
+            .get_or_insert_with(|| {
+                let mut interval = time::interval(FOLLOW_INTERVAL);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            })
+            .tick()
+            .await;
+    }
+
+    /// Reads the bytes appended to the followed files since the last check, no more than
+    /// `max_chunk_size` of each file.
+    ///
+    /// The follows of the files that fail to read (e.g. closed by the client) are stopped, with a
+    /// `last` chunk.
+    pub(crate) fn read_appended(
+        &mut self,
+        file_manager: &mut FileManager,
+        max_chunk_size: u64,
+    ) -> Vec<FollowChunkResponse> {
+        let count = FOLLOW_CHUNK_SIZE.min(max_chunk_size).max(1);
+        let mut chunks = Vec::new();
+
+        self.follows.retain(|&id, follow| {
+            let offset = follow.start.saturating_add(follow.sent);
+            match file_manager.read_limited(follow.fd, count, offset) {
+                Ok(response) if response.bytes.is_empty() => true,
+                Ok(response) => {
+                    follow.sent += response.bytes.len() as u64;
+                    chunks.push(FollowChunkResponse {
+                        id,
+                        bytes: response.bytes,
+                        last: false,
+                    });
+                    true
+                }
+                Err(error) => {
+                    tracing::debug!(id, ?follow, %error, "Stopped following a file");
+                    chunks.push(FollowChunkResponse {
+                        id,
+                        bytes: Vec::new(),
+                        last: true,
+                    });
+                    false
+                }
+            }
+        });
+
+        if self.follows.is_empty() {
+            self.interval = None;
+        }
+
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use mirrord_protocol::{
+        file::{OpenFileRequest, OpenFileResponse, OpenOptionsInternal, ReadFileRequest},
+        FileRequest, FileResponse,
+    };
+
+    use super::*;
+
+    #[test]
+    fn follows_appended_bytes() {
+        let path = std::env::temp_dir().join(format!("mirrord-follow-{}.log", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+
+        let mut file_manager = FileManager::new(None);
+        let Some(FileResponse::Open(Ok(OpenFileResponse { fd }))) = file_manager
+            .handle_message(FileRequest::Open(OpenFileRequest {
+                path: path.clone(),
+                open_options: OpenOptionsInternal {
+                    read: true,
+                    ..Default::default()
+                },
+            }))
+            .unwrap()
+        else {
+            panic!("failed to open {path:?}");
+        };
+        file_manager.read(fd, 64).unwrap();
+
+        let mut follows = FollowManager::default();
+        follows.follow(7, fd, &mut file_manager).unwrap();
+        assert!(follows.read_appended(&mut file_manager, 4).is_empty());
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"second\nthird\n").unwrap();
+
+        let chunks = follows.read_appended(&mut file_manager, 4);
+        assert_eq!(
+            chunks,
+            [FollowChunkResponse {
+                id: 7,
+                bytes: b"seco".to_vec(),
+                last: false,
+            }]
+        );
+        assert_eq!(
+            follows.read_appended(&mut file_manager, 4)[0].bytes,
+            b"nd\nt"
+        );
+
+        // The client used only the first chunk, the file offset is right after it.
+        follows.unfollow(7, 4, &mut file_manager);
+        let response = file_manager
+            .handle_message(FileRequest::Read(ReadFileRequest {
+                remote_fd: fd,
+                buffer_size: 64,
+            }))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let Some(FileResponse::Read(Ok(response))) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(response.bytes, b"nd\nthird\n");
+        assert!(follows.follows.is_empty());
+    }
+}
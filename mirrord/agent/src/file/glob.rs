@@ -0,0 +1,253 @@
+//! Expansion of [`GlobRequest`](mirrord_protocol::file::GlobRequest) patterns in the target's
+//! filesystem, see [`expand`].
+
+use std::{
+    ffi::OsStr,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+use mirrord_protocol::file::GlobResponse;
+
+use super::resolve_path;
+
+/// Expands the absolute `pattern` like `glob(3)` does (without any flags), with the paths
+/// resolved in `root_path`.
+///
+/// Directories that can't be read are skipped, like `glob(3)` does without `GLOB_ERR`.
+pub(crate) fn expand(
+    root_path: &Path,
+    pattern: &str,
+    max_results: u64,
+) -> io::Result<GlobResponse> {
+    let pattern = Path::new(pattern);
+    if !pattern.is_absolute() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "glob pattern should be absolute",
+        ));
+    }
+
+    let mut paths = vec![PathBuf::from("/")];
+    for component in pattern.components() {
+        let component = match component {
+            Component::Normal(component) => component.as_bytes(),
+            Component::CurDir => b".",
+            Component::ParentDir => b"..",
+            Component::RootDir | Component::Prefix(..) => continue,
+        };
+
+        paths = if has_wildcards(component) {
+            paths
+                .iter()
+                .flat_map(|dir| matching_entries(root_path, dir, component))
+                .collect()
+        } else {
+            let name = unescape(component);
+            paths
+                .into_iter()
+                .map(|path| path.join(OsStr::from_bytes(&name)))
+                .collect()
+        };
+
+        if paths.is_empty() {
+            break;
+        }
+    }
+
+    // Components without wildcards are not checked while walking.
+    paths.retain(|path| exists(root_path, path));
+    paths.sort();
+
+    let truncated = paths.len() as u64 > max_results;
+    paths.truncate(max_results.try_into().unwrap_or(usize::MAX));
+
+    Ok(GlobResponse { paths, truncated })
+}
+
+/// The paths of the entries of `dir` whose names match `pattern`.
+fn matching_entries(root_path: &Path, dir: &Path, pattern: &[u8]) -> Vec<PathBuf> {
+    let Ok(entries) = resolve_path(dir, root_path).and_then(fs::read_dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name())
+        // Can't be sent in the [`GlobResponse`].
+        .filter(|name| name.to_str().is_some())
+        .filter(|name| matches(pattern, name.as_bytes()))
+        .map(|name| dir.join(name))
+        .collect()
+}
+
+/// Whether `path` exists in `root_path`, a dangling symbolic link does.
+fn exists(root_path: &Path, path: &Path) -> bool {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return true;
+    };
+
+    resolve_path(parent, root_path).is_ok_and(|parent| parent.join(name).symlink_metadata().is_ok())
+}
+
+/// Whether `pattern` has any `*`, `?` or `[` that is not escaped.
+fn has_wildcards(pattern: &[u8]) -> bool {
+    let mut escaped = false;
+    pattern.iter().any(|byte| {
+        let wildcard = !escaped && matches!(byte, b'*' | b'?' | b'[');
+        escaped = !escaped && *byte == b'\\';
+        wildcard
+    })
+}
+
+/// Removes the `\` escapes from `pattern`.
+fn unescape(pattern: &[u8]) -> Vec<u8> {
+    let mut escaped = false;
+    pattern
+        .iter()
+        .filter(|byte| {
+            escaped = !escaped && **byte == b'\\';
+            !escaped
+        })
+        .copied()
+        .collect()
+}
+
+/// `fnmatch(3)` with `FNM_PERIOD`, for a single path component: a leading `.` in `name` is only
+/// matched by a leading `.` in `pattern`.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    if name.first() == Some(&b'.') && pattern.first() != Some(&b'.') {
+        return false;
+    }
+
+    matches_from(pattern, name)
+}
+
+fn matches_from(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        [b'*', rest @ ..] => (0..=name.len()).any(|skip| matches_from(rest, &name[skip..])),
+        [b'?', rest @ ..] => name
+            .split_first()
+            .is_some_and(|(_, name)| matches_from(rest, name)),
+        [b'[', class @ ..] => match (name.split_first(), bracket(class, name.first())) {
+            (Some((_, name)), Some((true, rest))) => matches_from(rest, name),
+            (_, Some((false, _))) | (None, _) => false,
+            // No closing `]`, so the `[` is not special.
+            (Some((byte, name)), None) => *byte == b'[' && matches_from(class, name),
+        },
+        [b'\\', byte, rest @ ..] | [byte, rest @ ..] => name
+            .split_first()
+            .is_some_and(|(first, name)| first == byte && matches_from(rest, name)),
+    }
+}
+
+/// Matches `byte` against the bracket expression `class` (after the `[`), e.g. `a-z]` or `!.]`.
+///
+/// Returns whether it matched, and the rest of the pattern after the `]`, or [`None`] when there
+/// is no closing `]`.
+fn bracket<'a>(class: &'a [u8], byte: Option<&u8>) -> Option<(bool, &'a [u8])> {
+    let (negated, mut class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+
+    let byte = byte.copied();
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let (start, rest) = match class {
+            [b']', rest @ ..] if !first => return Some((matched != negated, rest)),
+            [b'\\', start, rest @ ..] | [start, rest @ ..] => (*start, rest),
+            [] => return None,
+        };
+        first = false;
+
+        class = match rest {
+            [b'-', end, rest @ ..] if *end != b']' => {
+                matched |= byte.is_some_and(|byte| (start..=*end).contains(&byte));
+                rest
+            }
+            _ => {
+                matched |= byte == Some(start);
+                rest
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("*.pem", "ca.pem", true)]
+    #[case("*.pem", "ca.crt", false)]
+    #[case("*.pem", ".hidden.pem", false)]
+    #[case(".*.pem", ".hidden.pem", true)]
+    #[case("ca-?.pem", "ca-1.pem", true)]
+    #[case("ca-?.pem", "ca-10.pem", false)]
+    #[case("ca-[0-9].pem", "ca-7.pem", true)]
+    #[case("ca-[!0-9].pem", "ca-7.pem", false)]
+    #[case("ca-[!0-9].pem", "ca-x.pem", true)]
+    #[case("[]x]", "]", true)]
+    #[case("[ab", "[ab", true)]
+    #[case("\\*", "*", true)]
+    #[case("\\*", "a", false)]
+    #[case("*", "", true)]
+    fn match_component(#[case] pattern: &str, #[case] name: &str, #[case] expected: bool) {
+        assert_eq!(matches(pattern.as_bytes(), name.as_bytes()), expected);
+    }
+
+    #[test]
+    fn wildcards_and_escapes() {
+        assert!(has_wildcards(b"*.pem"));
+        assert!(has_wildcards(b"ca[12]"));
+        assert!(!has_wildcards(b"ca\\*"));
+        assert!(has_wildcards(b"ca\\\\*"));
+        assert_eq!(unescape(b"ca\\*\\\\"), b"ca*\\");
+    }
+
+    #[test]
+    fn expand_in_root() {
+        let root_path = std::env::temp_dir().join(format!("mirrord-glob-{}", std::process::id()));
+        let certs = root_path.join("etc/ssl/certs");
+        fs::create_dir_all(&certs).unwrap();
+        for name in ["a.pem", "b.pem", "c.crt", ".d.pem"] {
+            fs::write(certs.join(name), "").unwrap();
+        }
+        std::os::unix::fs::symlink("/etc/ssl", root_path.join("ssl")).unwrap();
+
+        let response = expand(&root_path, "/ssl/certs/*.pem", 1).unwrap();
+        let literal = expand(&root_path, "/etc/ssl/certs/c.crt", 8).unwrap();
+        let missing = expand(&root_path, "/etc/*/missing/*.pem", 8).unwrap();
+        fs::remove_dir_all(&root_path).unwrap();
+
+        assert_eq!(
+            response,
+            GlobResponse {
+                paths: vec![PathBuf::from("/ssl/certs/a.pem")],
+                truncated: true,
+            }
+        );
+        assert_eq!(literal.paths, vec![PathBuf::from("/etc/ssl/certs/c.crt")]);
+        assert!(missing.paths.is_empty());
+    }
+
+    #[test]
+    fn skip_non_utf8_names() {
+        let root_path =
+            std::env::temp_dir().join(format!("mirrord-glob-utf8-{}", std::process::id()));
+        fs::create_dir_all(&root_path).unwrap();
+        fs::write(root_path.join("a.pem"), "").unwrap();
+        fs::write(root_path.join(OsStr::from_bytes(b"\xff.pem")), "").unwrap();
+
+        let response = expand(&root_path, "/*.pem", 8).unwrap();
+        fs::remove_dir_all(&root_path).unwrap();
+
+        assert_eq!(response.paths, vec![PathBuf::from("/a.pem")]);
+    }
+}
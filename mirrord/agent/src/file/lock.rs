@@ -0,0 +1,173 @@
+//! Advisory locks of the remote files, for [`FlockRequest`](mirrord_protocol::file::FlockRequest)
+//! and the `fcntl` record locks of [`SetLockRequest`](mirrord_protocol::file::SetLockRequest).
+//!
+//! The locks are taken on the agent's own descriptors of the remote files, so they conflict with
+//! the locks of the target's processes, and the kernel releases them when a remote file is closed
+//! (or its client goes away). Record locks are open file description locks (`F_OFD_SETLK`), that
+//! belong to the remote file and not to the agent process, otherwise every client would share the
+//! same locks.
+//!
+//! Nothing here waits for a lock, it would stop every other file operation of the client. The
+//! layer retries the locks it has to wait for.
+
+use std::{fs::File, io, os::unix::io::AsRawFd};
+
+use mirrord_protocol::file::{FileLockInternal, LockTypeInternal, SeekFromInternal};
+
+/// `flock` of `file`, without waiting for the lock.
+pub(crate) fn flock(file: &File, lock_type: LockTypeInternal) -> io::Result<()> {
+    let operation = match lock_type {
+        LockTypeInternal::Shared => libc::LOCK_SH,
+        LockTypeInternal::Exclusive => libc::LOCK_EX,
+        LockTypeInternal::Unlock => libc::LOCK_UN,
+    };
+
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// `F_OFD_SETLK` of `lock` on `file`.
+pub(crate) fn set_lock(file: &File, lock: FileLockInternal) -> io::Result<()> {
+    let mut flock = raw_lock(lock)?;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_SETLK, &mut flock) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// `F_OFD_GETLK` of `lock` on `file`, the first lock that conflicts with it.
+pub(crate) fn get_lock(
+    file: &File,
+    lock: FileLockInternal,
+) -> io::Result<Option<FileLockInternal>> {
+    let mut flock = raw_lock(lock)?;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_GETLK, &mut flock) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let lock_type = match flock.l_type.into() {
+        libc::F_UNLCK => return Ok(None),
+        libc::F_RDLCK => LockTypeInternal::Shared,
+        _ => LockTypeInternal::Exclusive,
+    };
+
+    // The kernel always reports the conflicting lock from the start of the file.
+    Ok(Some(FileLockInternal {
+        lock_type,
+        start: SeekFromInternal::Start(flock.l_start.try_into().unwrap_or_default()),
+        len: flock.l_len,
+    }))
+}
+
+/// The `struct flock` of `lock`.
+fn raw_lock(lock: FileLockInternal) -> io::Result<libc::flock> {
+    let lock_type = match lock.lock_type {
+        LockTypeInternal::Shared => libc::F_RDLCK,
+        LockTypeInternal::Exclusive => libc::F_WRLCK,
+        LockTypeInternal::Unlock => libc::F_UNLCK,
+    };
+
+    let (whence, start) = match lock.start {
+        SeekFromInternal::Start(start) => (
+            libc::SEEK_SET,
+            start
+                .try_into()
+                .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?,
+        ),
+        SeekFromInternal::Current(start) => (libc::SEEK_CUR, start),
+        SeekFromInternal::End(start) => (libc::SEEK_END, start),
+        SeekFromInternal::Data(..) | SeekFromInternal::Hole(..) => {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL))
+        }
+    };
+
+    // SAFETY: all-zero flock struct is valid, and `l_pid` has to be `0` for the OFD locks.
+    let mut flock: libc::flock = unsafe { std::mem::zeroed() };
+    flock.l_type = lock_type as libc::c_short;
+    flock.l_whence = whence as libc::c_short;
+    flock.l_start = start;
+    flock.l_len = lock.len;
+
+    Ok(flock)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::ErrorKind, path::PathBuf};
+
+    use super::*;
+
+    /// Two opens of the same file, like two remote files of different clients.
+    fn open_twice(name: &str) -> (PathBuf, File, File) {
+        let path = std::env::temp_dir().join(format!("mirrord-lock-{name}-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+
+        let first = File::options().read(true).write(true).open(&path).unwrap();
+        let second = File::options().read(true).write(true).open(&path).unwrap();
+
+        (path, first, second)
+    }
+
+    #[test]
+    fn flock_conflicts_between_remote_files() {
+        let (path, first, second) = open_twice("flock");
+
+        flock(&first, LockTypeInternal::Shared).unwrap();
+        flock(&second, LockTypeInternal::Shared).unwrap();
+        assert_eq!(
+            flock(&second, LockTypeInternal::Exclusive)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::WouldBlock
+        );
+
+        flock(&first, LockTypeInternal::Unlock).unwrap();
+        flock(&second, LockTypeInternal::Exclusive).unwrap();
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn record_locks_conflict_between_remote_files() {
+        let (path, first, second) = open_twice("record");
+
+        let lock = FileLockInternal {
+            lock_type: LockTypeInternal::Exclusive,
+            start: SeekFromInternal::Start(100),
+            len: 10,
+        };
+        set_lock(&first, lock).unwrap();
+
+        assert_eq!(
+            set_lock(&second, lock).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert_eq!(get_lock(&second, lock).unwrap(), Some(lock));
+        // The ranges don't overlap.
+        let after = FileLockInternal {
+            start: SeekFromInternal::Start(110),
+            ..lock
+        };
+        assert_eq!(get_lock(&second, after).unwrap(), None);
+        set_lock(&second, after).unwrap();
+
+        // The whole file is locked through the position, from the start.
+        let whole_file = FileLockInternal {
+            start: SeekFromInternal::Current(0),
+            len: 0,
+            ..lock
+        };
+        assert_eq!(
+            set_lock(&first, whole_file).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+//! Remote directory trees that a client prefetches, see [`PrefetchWalk`].
+
+use std::{
+    collections::VecDeque,
+    ffi::OsStr,
+    fs::File,
+    io::{self, Read},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use mirrord_protocol::file::{
+    DirEntryPlusInternal, MetadataInternal, PrefetchChunkResponse, PrefetchDirInternal,
+    PrefetchEntryInternal, PrefetchTreeRequest,
+};
+use tracing::Level;
+
+use super::{lossy_name, resolve_path, FileManager};
+
+/// Most bytes sent in a single [`PrefetchChunkResponse`], so that the client gets the first
+/// directories while we walk the rest of the tree.
+const PREFETCH_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Upper bound of the encoded size of an entry (or of a directory), besides its name and
+/// contents.
+const ENTRY_SIZE: u64 = 256;
+
+/// A directory read by [`FileManager::prefetch_dir`].
+struct PrefetchedDir {
+    /// The directory with its encoded size, [`None`] when we can't send it.
+    dir: Option<(PrefetchDirInternal, u64)>,
+    /// The subdirectories, by their paths in the target and in the host.
+    subdirs: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Walks the tree of a [`PrefetchTreeRequest`] breadth first, so that the directories closer to
+/// the root are the ones sent when the tree has more than [`PrefetchTreeRequest::max_entries`].
+///
+/// Only whole directories are sent, so that the client can list them without asking us. The
+/// ones that can't be read, that don't fit in a chunk, or where operations are redirected to the
+/// scratch directory are left out, their subdirectories are still walked.
+#[derive(Debug)]
+pub(crate) struct PrefetchWalk {
+    request: PrefetchTreeRequest,
+    /// Directories left to read, by their paths in the target and in the host.
+    queue: VecDeque<(PathBuf, PathBuf)>,
+    /// Directory read for the previous chunk, that didn't fit in it.
+    pending: Option<(PrefetchDirInternal, u64)>,
+    /// Entries sent so far.
+    sent: u64,
+    finished: bool,
+}
+
+impl PrefetchWalk {
+    #[tracing::instrument(level = Level::TRACE, skip(file_manager))]
+    pub(crate) fn new(request: PrefetchTreeRequest, file_manager: &FileManager) -> Self {
+        let root = request
+            .path
+            .strip_prefix("/")
+            .ok()
+            .and_then(|path| resolve_path(path, &file_manager.root_path).ok());
+
+        Self {
+            queue: root
+                .map(|root| (request.path.clone(), root))
+                .into_iter()
+                .collect(),
+            request,
+            pending: None,
+            sent: 0,
+            finished: false,
+        }
+    }
+
+    /// Reads the directories of the next chunk, no bigger than `max_chunk_size`.
+    ///
+    /// Returns [`None`] once the chunk marked as [`PrefetchChunkResponse::last`] was returned.
+    #[tracing::instrument(level = Level::TRACE, skip(self, file_manager))]
+    pub(crate) fn next_chunk(
+        &mut self,
+        file_manager: &FileManager,
+        max_chunk_size: u64,
+    ) -> Option<PrefetchChunkResponse> {
+        if self.finished {
+            return None;
+        }
+
+        let max_chunk_size = max_chunk_size.min(PREFETCH_CHUNK_SIZE);
+        let mut dirs = Vec::new();
+        let mut size = 0;
+        loop {
+            let next = self
+                .pending
+                .take()
+                .or_else(|| self.next_dir(file_manager, max_chunk_size));
+            let Some((dir, dir_size)) = next else {
+                self.finished = true;
+                break;
+            };
+
+            if !dirs.is_empty() && size + dir_size > max_chunk_size {
+                self.pending = Some((dir, dir_size));
+                break;
+            }
+
+            size += dir_size;
+            dirs.push(dir);
+        }
+
+        Some(PrefetchChunkResponse {
+            id: self.request.id,
+            dirs,
+            last: self.finished,
+        })
+    }
+
+    /// Reads the next directory that we can send, [`None`] when we're done with the tree.
+    fn next_dir(
+        &mut self,
+        file_manager: &FileManager,
+        max_size: u64,
+    ) -> Option<(PrefetchDirInternal, u64)> {
+        while let Some((path, host_path)) = self.queue.pop_front() {
+            let prefetched = match file_manager.prefetch_dir(
+                &path,
+                &host_path,
+                self.request.max_file_size,
+                max_size,
+            ) {
+                Ok(prefetched) => prefetched,
+                Err(error) => {
+                    tracing::debug!(?path, %error, "failed to prefetch a directory");
+                    continue;
+                }
+            };
+
+            let Some((dir, size)) = prefetched.dir else {
+                self.queue.extend(prefetched.subdirs);
+                continue;
+            };
+
+            let sent = self.sent.saturating_add(dir.entries.len() as u64);
+            if sent > self.request.max_entries {
+                self.queue.clear();
+                return None;
+            }
+
+            self.sent = sent;
+            self.queue.extend(prefetched.subdirs);
+            return Some((dir, size));
+        }
+
+        None
+    }
+}
+
+impl FileManager {
+    /// Reads the directory at `path` (`host_path` in the host), with the metadata of its entries
+    /// and the contents of its regular files of at most `max_file_size` bytes.
+    ///
+    /// The directory is only sent when it's no bigger than `max_size`, the contents that don't
+    /// fit are left out.
+    fn prefetch_dir(
+        &self,
+        path: &Path,
+        host_path: &Path,
+        max_file_size: u64,
+        max_size: u64,
+    ) -> io::Result<PrefetchedDir> {
+        let metadata = host_path.metadata()?;
+        let entries = std::fs::read_dir(host_path)?
+            .enumerate()
+            .map(DirEntryPlusInternal::try_from)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let subdirs = entries
+            .iter()
+            .filter(|entry| entry.entry.file_type == libc::DT_DIR)
+            .map(|entry| {
+                let name = OsStr::from_bytes(&entry.entry.name);
+                (path.join(name), host_path.join(name))
+            })
+            .collect();
+
+        let redirected = self.scratch_path(path).is_some()
+            || entries.iter().any(|entry| {
+                self.scratch_path(&path.join(OsStr::from_bytes(&entry.entry.name)))
+                    .is_some()
+            });
+        let mut size = entries
+            .iter()
+            .fold(ENTRY_SIZE + path.as_os_str().len() as u64, |size, entry| {
+                size + ENTRY_SIZE + entry.entry.name.len() as u64
+            });
+        if redirected || size > max_size {
+            return Ok(PrefetchedDir { dir: None, subdirs });
+        }
+
+        let entries = entries
+            .into_iter()
+            .map(|mut entry| {
+                let limit = max_file_size.min(max_size.saturating_sub(size));
+                let contents = (entry.entry.file_type == libc::DT_REG
+                    && entry.metadata.size <= limit)
+                    .then(|| {
+                        let path = host_path.join(OsStr::from_bytes(&entry.entry.name));
+                        self.prefetch_contents(&path, limit)
+                            .inspect_err(
+                                |error| tracing::trace!(?path, %error, "failed to prefetch a file"),
+                            )
+                            .ok()
+                            .flatten()
+                    })
+                    .flatten()
+                    .map(|(bytes, metadata)| {
+                        size += bytes.len() as u64;
+                        entry.metadata = metadata;
+                        bytes
+                    });
+
+                if !self.non_utf8_names {
+                    entry.entry = lossy_name(entry.entry);
+                }
+
+                PrefetchEntryInternal { entry, contents }
+            })
+            .collect();
+
+        let dir = PrefetchDirInternal {
+            path: path.to_path_buf(),
+            metadata: metadata.into(),
+            entries,
+        };
+
+        Ok(PrefetchedDir {
+            dir: Some((dir, size)),
+            subdirs,
+        })
+    }
+
+    /// Reads the whole file at `host_path`, like [`FileManager::read_whole`], with the metadata of
+    /// the file that the client gets.
+    ///
+    /// [`None`] if the file grew past `max_size`.
+    fn prefetch_contents(
+        &self,
+        host_path: &Path,
+        max_size: u64,
+    ) -> io::Result<Option<(Vec<u8>, MetadataInternal)>> {
+        let file = File::open(host_path)?;
+        let file = self.token_access.check(&self.root_path, host_path, file)?;
+        let metadata = file.metadata()?;
+
+        let mut bytes = Vec::with_capacity(metadata.len() as usize);
+        file.take(max_size.saturating_add(1))
+            .read_to_end(&mut bytes)?;
+
+        Ok((bytes.len() as u64 <= max_size).then(|| (bytes, metadata.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefetches_whole_directories() {
+        let root = std::env::temp_dir().join(format!("mirrord-prefetch-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("conf/nested")).unwrap();
+        std::fs::write(root.join("small.yaml"), "port: 8080\n").unwrap();
+        std::fs::write(root.join("big.bin"), [0u8; 64]).unwrap();
+        std::fs::write(root.join("conf/nested/app.properties"), "a=b\n").unwrap();
+        std::os::unix::fs::symlink("conf", root.join("link")).unwrap();
+
+        let file_manager = FileManager::new(None);
+        let mut walk = PrefetchWalk::new(
+            PrefetchTreeRequest {
+                id: 3,
+                path: root.clone(),
+                max_file_size: 16,
+                max_entries: 5,
+            },
+            &file_manager,
+        );
+        let chunk = walk.next_chunk(&file_manager, u64::MAX).unwrap();
+        assert!(walk.next_chunk(&file_manager, u64::MAX).is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(chunk.id, 3);
+        assert!(chunk.last);
+
+        // The root and `conf` fit in the 5 entries, `conf/nested` doesn't.
+        let paths = chunk.dirs.iter().map(|dir| &dir.path).collect::<Vec<_>>();
+        assert_eq!(paths, [&root, &root.join("conf")]);
+
+        let contents = |name: &str| {
+            chunk
+                .dirs
+                .first()
+                .unwrap()
+                .entries
+                .iter()
+                .find(|entry| entry.entry.entry.name == name.as_bytes())
+                .map(|entry| entry.contents.clone())
+                .unwrap()
+        };
+        assert_eq!(
+            contents("small.yaml").as_deref(),
+            Some(b"port: 8080\n".as_slice())
+        );
+        assert_eq!(contents("big.bin"), None);
+        assert_eq!(contents("link"), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Restrictions on the remote reads of the target's service account token, see
+//! [`ServiceAccountTokenPolicy`].
+//!
+//! A token is found by its inode rather than its path: the files that a client opens are compared
+//! with the token files of the target's root, so that symbolic links (like the `..data/token` of
+//! the projected volumes) or relative opens can't get around the policy.
+
+use std::{
+    ffi::CString,
+    fs::{File, Metadata, Permissions},
+    io::{self, Seek, Write},
+    os::unix::{
+        fs::{MetadataExt, PermissionsExt},
+        io::FromRawFd,
+    },
+    path::Path,
+};
+
+use tracing::{error, info, warn};
+
+use crate::cli::ServiceAccountTokenPolicy;
+
+/// Where the service account tokens are mounted, relative to the target's root.
+///
+/// `/var/run` is usually an absolute symbolic link to `/run`, which would be followed in the
+/// agent's root instead of the target's, so both are listed.
+const TOKEN_PATHS: [&str; 6] = [
+    "run/secrets/kubernetes.io/serviceaccount/token",
+    "var/run/secrets/kubernetes.io/serviceaccount/token",
+    "run/secrets/eks.amazonaws.com/serviceaccount/token",
+    "var/run/secrets/eks.amazonaws.com/serviceaccount/token",
+    "run/secrets/azure/tokens/azure-identity-token",
+    "var/run/secrets/azure/tokens/azure-identity-token",
+];
+
+/// What the agent does when a client opens a service account token, built from the
+/// [`ServiceAccountTokenPolicy`] set by the operator.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum TokenAccess {
+    #[default]
+    Allow,
+    Audit,
+    Block,
+    Substitute(String),
+}
+
+impl TokenAccess {
+    /// [`ServiceAccountTokenPolicy::Substitute`] without a `substitute_token` blocks the reads,
+    /// the target's token is never served in its place.
+    pub(crate) fn new(policy: ServiceAccountTokenPolicy, substitute_token: Option<String>) -> Self {
+        match (policy, substitute_token) {
+            (ServiceAccountTokenPolicy::Allow, _) => Self::Allow,
+            (ServiceAccountTokenPolicy::Audit, _) => Self::Audit,
+            (ServiceAccountTokenPolicy::Block, _) => Self::Block,
+            (ServiceAccountTokenPolicy::Substitute, Some(token)) => Self::Substitute(token),
+            (ServiceAccountTokenPolicy::Substitute, None) => {
+                error!("no token to substitute the service account token with, reads are blocked");
+                Self::Block
+            }
+        }
+    }
+
+    /// Applies the policy to `file`, opened by a client at `path` in the target's `root`.
+    ///
+    /// Returns the file that the client should read, which is `file` itself unless it's a
+    /// service account token that is substituted.
+    pub(crate) fn check(&self, root: &Path, path: &Path, file: File) -> io::Result<File> {
+        if matches!(self, Self::Allow) || !is_token(root, &file.metadata()?) {
+            return Ok(file);
+        }
+
+        match self {
+            Self::Allow => Ok(file),
+            Self::Audit => {
+                warn!(
+                    ?path,
+                    "a client read the service account token of the target"
+                );
+                Ok(file)
+            }
+            Self::Block => {
+                warn!(
+                    ?path,
+                    "blocked a read of the service account token of the target"
+                );
+                Err(io::Error::from_raw_os_error(libc::EACCES))
+            }
+            Self::Substitute(token) => {
+                info!(?path, "substituted the service account token of the target");
+                substitute(token)
+            }
+        }
+    }
+}
+
+/// Whether the file of `metadata` is one of the [`TOKEN_PATHS`] in `root`.
+fn is_token(root: &Path, metadata: &Metadata) -> bool {
+    metadata.is_file()
+        && TOKEN_PATHS.iter().any(|token_path| {
+            root.join(token_path)
+                .metadata()
+                .is_ok_and(|token| token.dev() == metadata.dev() && token.ino() == metadata.ino())
+        })
+}
+
+/// Creates an anonymous in-memory file with `token`, read like the original one.
+fn substitute(token: &str) -> io::Result<File> {
+    let name = CString::new("mirrord-token").expect("no nul bytes");
+    let raw_fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if raw_fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut file = unsafe { File::from_raw_fd(raw_fd) };
+    file.write_all(token.as_bytes())?;
+    file.rewind()?;
+    file.set_permissions(Permissions::from_mode(0o644))?;
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, os::unix::fs::symlink, path::PathBuf};
+
+    use rstest::rstest;
+
+    use super::*;
+
+    /// The token as a client opens it, through both links of the projected volume.
+    const TOKEN: &str = "run/secrets/kubernetes.io/serviceaccount/token";
+
+    /// The token through the `..data` link only.
+    const DATA_TOKEN: &str = "run/secrets/kubernetes.io/serviceaccount/..data/token";
+
+    /// A target's root with the projected volume layout of a service account token: `token` links
+    /// to `..data/token`, and `..data` links to the timestamped directory with the actual file.
+    struct TargetRoot(PathBuf);
+
+    impl TargetRoot {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("mirrord-token-root-{name}-{}", std::process::id()));
+            let volume = root.join("run/secrets/kubernetes.io/serviceaccount");
+            let timestamped = volume.join("..2024_01_01_00_00_00.000000000");
+            fs::create_dir_all(&timestamped).unwrap();
+            fs::write(timestamped.join("token"), "target token").unwrap();
+            symlink("..2024_01_01_00_00_00.000000000", volume.join("..data")).unwrap();
+            symlink("..data/token", volume.join("token")).unwrap();
+
+            fs::create_dir_all(root.join("app")).unwrap();
+            fs::write(root.join("app/config"), "app config").unwrap();
+
+            Self(root)
+        }
+
+        fn open(&self, path: &str) -> File {
+            File::open(self.0.join(path)).unwrap()
+        }
+    }
+
+    impl Drop for TargetRoot {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn contents(mut file: File) -> String {
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[rstest]
+    #[case::token("token", TOKEN)]
+    #[case::data_token("data-token", DATA_TOKEN)]
+    #[case::timestamped(
+        "timestamped",
+        "run/secrets/kubernetes.io/serviceaccount/..2024_01_01_00_00_00.000000000/token"
+    )]
+    fn token_found_through_links(#[case] name: &str, #[case] path: &str) {
+        let root = TargetRoot::new(name);
+
+        assert!(is_token(&root.0, &root.open(path).metadata().unwrap()));
+    }
+
+    #[test]
+    fn other_files_are_not_tokens() {
+        let root = TargetRoot::new("other-files");
+
+        assert!(!is_token(
+            &root.0,
+            &root.open("app/config").metadata().unwrap()
+        ));
+        assert!(!is_token(
+            &root.0,
+            &root
+                .open("run/secrets/kubernetes.io/serviceaccount/..data")
+                .metadata()
+                .unwrap()
+        ));
+    }
+
+    #[rstest]
+    #[case::allow("allow", TokenAccess::Allow, Some("target token"))]
+    #[case::audit("audit", TokenAccess::Audit, Some("target token"))]
+    #[case::block("block", TokenAccess::Block, None)]
+    #[case::substitute(
+        "substitute",
+        TokenAccess::Substitute("substitute token".into()),
+        Some("substitute token")
+    )]
+    fn check_token(
+        #[case] name: &str,
+        #[case] token_access: TokenAccess,
+        #[case] expected: Option<&str>,
+    ) {
+        let root = TargetRoot::new(&format!("check-{name}"));
+
+        let checked = token_access.check(&root.0, Path::new(DATA_TOKEN), root.open(DATA_TOKEN));
+
+        match expected {
+            Some(expected) => assert_eq!(contents(checked.unwrap()), expected),
+            None => assert_eq!(checked.unwrap_err().raw_os_error(), Some(libc::EACCES)),
+        }
+    }
+
+    #[rstest]
+    #[case::audit("audit", TokenAccess::Audit)]
+    #[case::block("block", TokenAccess::Block)]
+    #[case::substitute("substitute", TokenAccess::Substitute("substitute token".into()))]
+    fn check_other_file(#[case] name: &str, #[case] token_access: TokenAccess) {
+        let root = TargetRoot::new(&format!("check-other-{name}"));
+
+        let checked = token_access
+            .check(&root.0, Path::new("app/config"), root.open("app/config"))
+            .unwrap();
+
+        assert_eq!(contents(checked), "app config");
+    }
+
+    #[test]
+    fn substitute_reads_like_a_file() {
+        let file = substitute("substitute token").unwrap();
+
+        assert_eq!(file.metadata().unwrap().mode() & 0o777, 0o644);
+        assert_eq!(contents(file), "substitute token");
+    }
+
+    #[test]
+    fn substitute_without_token_blocks() {
+        assert!(matches!(
+            TokenAccess::new(ServiceAccountTokenPolicy::Substitute, None),
+            TokenAccess::Block
+        ));
+    }
+}
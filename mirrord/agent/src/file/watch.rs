@@ -0,0 +1,182 @@
+//! Remote file watches of a client, see [`WatchManager`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    os::fd::{AsFd, AsRawFd, RawFd},
+    path::PathBuf,
+};
+
+use mirrord_protocol::{
+    watch::{WatchEvent, WatchId},
+    RemoteResult,
+};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use tokio::io::unix::AsyncFd;
+use tracing::Level;
+
+/// [`Inotify`] that can be polled with [`AsyncFd`].
+struct RawInotify(Inotify);
+
+impl AsRawFd for RawInotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_fd().as_raw_fd()
+    }
+}
+
+/// The `inotify` watches of a single client, in the target's filesystem.
+///
+/// The [`Inotify`] instance is created with the first watch, so that the clients that don't watch
+/// anything don't take up a descriptor.
+#[derive(Default)]
+pub(crate) struct WatchManager {
+    inotify: Option<AsyncFd<RawInotify>>,
+    ids: HashMap<WatchDescriptor, WatchId>,
+    descriptors: HashMap<WatchId, WatchDescriptor>,
+    next_id: WatchId,
+    /// Events that were read from the [`Inotify`], but not sent to the client yet.
+    events: VecDeque<WatchEvent>,
+}
+
+impl WatchManager {
+    /// Watches `host_path`, the path of the client resolved in the target's root.
+    ///
+    /// Watching the same file again returns the same [`WatchId`], and replaces the `mask` of the
+    /// watch, like `inotify_add_watch`.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub(crate) fn add(&mut self, host_path: PathBuf, mask: u32) -> RemoteResult<WatchId> {
+        let inotify = match &mut self.inotify {
+            Some(inotify) => inotify,
+            None => {
+                let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+                    .map_err(io::Error::from)?;
+                self.inotify.insert(AsyncFd::new(RawInotify(inotify))?)
+            }
+        };
+
+        let descriptor = inotify
+            .get_ref()
+            .0
+            .add_watch(&host_path, AddWatchFlags::from_bits_retain(mask))
+            .map_err(io::Error::from)?;
+
+        let id = *self.ids.entry(descriptor).or_insert_with(|| {
+            self.next_id += 1;
+            self.next_id
+        });
+        self.descriptors.insert(id, descriptor);
+
+        Ok(id)
+    }
+
+    /// Removes the watch, its `IN_IGNORED` event is sent to the client afterwards.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub(crate) fn remove(&mut self, id: WatchId) {
+        let (Some(inotify), Some(descriptor)) = (&self.inotify, self.descriptors.get(&id)) else {
+            return;
+        };
+
+        if let Err(error) = inotify.get_ref().0.rm_watch(*descriptor) {
+            tracing::debug!(id, %error, "Failed to remove the watch, it's already gone");
+        }
+    }
+
+    /// Returns the next event of the watches, pending forever when nothing is watched.
+    ///
+    /// Cancel safe.
+    pub(crate) async fn next_event(&mut self) -> io::Result<WatchEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            let Some(inotify) = &self.inotify else {
+                return std::future::pending().await;
+            };
+
+            let mut guard = inotify.readable().await?;
+            let Ok(events) =
+                guard.try_io(|inotify| inotify.get_ref().0.read_events().map_err(io::Error::from))
+            else {
+                continue;
+            };
+
+            for event in events? {
+                self.push_event(event);
+            }
+        }
+    }
+
+    fn push_event(&mut self, event: InotifyEvent) {
+        let InotifyEvent {
+            wd,
+            mask,
+            cookie,
+            name,
+        } = event;
+
+        // `IN_Q_OVERFLOW` is not bound to any watch.
+        let Some(&watch_id) = self.ids.get(&wd) else {
+            tracing::warn!(
+                ?mask,
+                "Dropped an inotify event that is not bound to a watch"
+            );
+            return;
+        };
+
+        if mask.contains(AddWatchFlags::IN_IGNORED) {
+            self.ids.remove(&wd);
+            self.descriptors.remove(&watch_id);
+        }
+
+        self.events.push_back(WatchEvent {
+            watch_id,
+            mask: mask.bits(),
+            cookie,
+            name: name.map(PathBuf::from),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn events_of_watched_dir() {
+        let dir = std::env::temp_dir().join(format!("mirrord-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut watches = WatchManager::default();
+        let mask = (AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ONLYDIR).bits();
+        let id = watches.add(dir.clone(), mask).unwrap();
+        assert_eq!(watches.add(dir.clone(), mask).unwrap(), id);
+
+        std::fs::write(dir.join("config.yaml"), "").unwrap();
+        let created = tokio::time::timeout(Duration::from_secs(5), watches.next_event())
+            .await
+            .unwrap()
+            .unwrap();
+
+        watches.remove(id);
+        let ignored = tokio::time::timeout(Duration::from_secs(5), watches.next_event())
+            .await
+            .unwrap()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            created,
+            WatchEvent {
+                watch_id: id,
+                mask: AddWatchFlags::IN_CREATE.bits(),
+                cookie: 0,
+                name: Some("config.yaml".into()),
+            }
+        );
+        assert_eq!(ignored.mask, AddWatchFlags::IN_IGNORED.bits());
+        assert!(watches.descriptors.is_empty());
+    }
+}
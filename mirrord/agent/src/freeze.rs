@@ -0,0 +1,406 @@
+//! Freezing the target container while clients steal all of its traffic, see
+//! [`ClientMessage::FreezeTarget`](mirrord_protocol::ClientMessage::FreezeTarget).
+
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use nix::{
+    errno::Errno,
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub(crate) enum FreezeError {
+    #[error("failed to inspect the target processes: {0}")]
+    Proc(#[from] io::Error),
+    #[error("failed to signal the target processes: {0}")]
+    Signal(#[from] nix::Error),
+    #[error("the target shares the PID namespace of the agent")]
+    SharedNamespace,
+}
+
+/// How the target container was frozen, so that we thaw it the same way.
+#[derive(Debug)]
+enum Frozen {
+    /// With the `cgroup.freeze` file of the cgroup v2 freezer.
+    Cgroup(PathBuf),
+    /// By sending [`Signal::SIGSTOP`] to every process in the PID namespace with this inode.
+    Signal(u64),
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Process ID of the target container.
+    pid: u64,
+    /// Where we find the cgroup of the target, `/proc` outside of tests.
+    proc_root: PathBuf,
+    /// Number of live [`FreezeGuard`]s.
+    guards: usize,
+    frozen: Option<Frozen>,
+}
+
+/// Freezes the target container on behalf of the clients.
+///
+/// Shared between the clients, the container stays frozen as long as any [`FreezeGuard`] is
+/// alive.
+#[derive(Debug, Clone)]
+pub(crate) struct TargetFreezer(Arc<Mutex<Inner>>);
+
+impl TargetFreezer {
+    /// How many times we look for new processes when stopping them with signals, as they can
+    /// fork in the meantime.
+    const MAX_STOP_ROUNDS: usize = 8;
+
+    pub(crate) fn new(pid: u64) -> Self {
+        Self::with_proc_root(pid, "/proc".into())
+    }
+
+    fn with_proc_root(pid: u64, proc_root: PathBuf) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            pid,
+            proc_root,
+            guards: 0,
+            frozen: None,
+        })))
+    }
+
+    /// Freezes the target container, unless it's already frozen for another client.
+    ///
+    /// The container is thawed when the last [`FreezeGuard`] is dropped.
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub(crate) fn freeze(&self) -> Result<FreezeGuard, FreezeError> {
+        let mut inner = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if inner.frozen.is_none() {
+            inner.frozen = Some(Self::freeze_pid(&inner)?);
+            tracing::info!(frozen = ?inner.frozen, "Froze the target container");
+        }
+        inner.guards += 1;
+
+        Ok(FreezeGuard(self.clone()))
+    }
+
+    fn release(&self) {
+        let mut inner = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        inner.guards = inner.guards.saturating_sub(1);
+        if inner.guards > 0 {
+            return;
+        }
+
+        if let Some(frozen) = inner.frozen.take() {
+            match Self::thaw(&frozen) {
+                Ok(()) => tracing::info!(?frozen, "Thawed the target container"),
+                Err(error) => {
+                    tracing::error!(?frozen, %error, "Failed to thaw the target container")
+                }
+            }
+        }
+    }
+
+    /// Thaws the target container if an agent left it frozen, e.g. when it was killed or
+    /// panicked with live [`FreezeGuard`]s.
+    ///
+    /// Called by the iptables guard once the agent exited, so we don't know how the container was
+    /// frozen, or if it was at all. Only the processes that are stopped are continued.
+    pub(crate) fn thaw_abandoned(&self) -> Result<(), FreezeError> {
+        let inner = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let freeze_file = cgroup_freeze_file(&inner);
+        if fs::read(&freeze_file).is_ok_and(|contents| contents.trim_ascii() == b"1") {
+            tracing::info!("Thawing the target container left frozen by the agent");
+            return Ok(write_cgroup_freeze(&freeze_file, false)?);
+        }
+
+        let namespace = pid_namespace(inner.pid)?;
+        if namespace == pid_namespace(std::process::id().into())? {
+            return Ok(());
+        }
+
+        let stopped = namespace_processes(namespace)?
+            .into_iter()
+            .filter(|pid| is_stopped(*pid))
+            .collect::<Vec<_>>();
+        if !stopped.is_empty() {
+            tracing::info!(
+                ?stopped,
+                "Continuing the target processes left stopped by the agent"
+            );
+        }
+
+        Ok(continue_processes(stopped)?)
+    }
+
+    fn freeze_pid(inner: &Inner) -> Result<Frozen, FreezeError> {
+        let freeze_file = cgroup_freeze_file(inner);
+        match write_cgroup_freeze(&freeze_file, true) {
+            Ok(()) => return Ok(Frozen::Cgroup(freeze_file)),
+            Err(error) => {
+                tracing::debug!(%error, "cgroup freezer is not available, using SIGSTOP");
+            }
+        }
+
+        let namespace = pid_namespace(inner.pid)?;
+        if namespace == pid_namespace(std::process::id().into())? {
+            return Err(FreezeError::SharedNamespace);
+        }
+
+        let mut stopped = HashSet::new();
+        for _ in 0..Self::MAX_STOP_ROUNDS {
+            let new = namespace_processes(namespace)?
+                .into_iter()
+                .filter(|pid| !stopped.contains(pid))
+                .collect::<Vec<_>>();
+            if new.is_empty() {
+                break;
+            }
+
+            for pid in new {
+                if let Err(error) = send_signal(pid, Signal::SIGSTOP) {
+                    // Don't leave the container partially stopped.
+                    stopped.into_iter().for_each(|pid| {
+                        let _ = send_signal(pid, Signal::SIGCONT);
+                    });
+                    return Err(error.into());
+                }
+                stopped.insert(pid);
+            }
+        }
+
+        Ok(Frozen::Signal(namespace))
+    }
+
+    fn thaw(frozen: &Frozen) -> Result<(), FreezeError> {
+        match frozen {
+            Frozen::Cgroup(freeze_file) => write_cgroup_freeze(freeze_file, false)?,
+            Frozen::Signal(namespace) => continue_processes(namespace_processes(*namespace)?)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the target container frozen, see [`TargetFreezer::freeze`].
+#[derive(Debug)]
+pub(crate) struct FreezeGuard(TargetFreezer);
+
+impl Drop for FreezeGuard {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// The `cgroup.freeze` file of the target container, which sees its own cgroup as the root of the
+/// hierarchy.
+fn cgroup_freeze_file(inner: &Inner) -> PathBuf {
+    inner
+        .proc_root
+        .join(inner.pid.to_string())
+        .join("root/sys/fs/cgroup/cgroup.freeze")
+}
+
+fn write_cgroup_freeze(freeze_file: &Path, frozen: bool) -> io::Result<()> {
+    // Never create the file, it must come from the cgroup filesystem.
+    OpenOptions::new()
+        .write(true)
+        .open(freeze_file)?
+        .write_all(if frozen { b"1" } else { b"0" })
+}
+
+/// Inode of the PID namespace of the process.
+fn pid_namespace(pid: u64) -> io::Result<u64> {
+    fs::metadata(format!("/proc/{pid}/ns/pid")).map(|metadata| metadata.ino())
+}
+
+/// Processes in the PID namespace with the given inode.
+fn namespace_processes(namespace: u64) -> io::Result<Vec<i32>> {
+    let processes = fs::read_dir("/proc")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+        // Processes can exit while we look.
+        .filter(|pid| {
+            u64::try_from(*pid)
+                .ok()
+                .and_then(|pid| pid_namespace(pid).ok())
+                == Some(namespace)
+        })
+        .collect();
+
+    Ok(processes)
+}
+
+/// Whether the process is stopped by a signal, the `T` state in its `stat`.
+fn is_stopped(pid: i32) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+        // The state follows the command name, which can contain anything.
+        stat.rsplit_once(')')
+            .and_then(|(_, rest)| rest.trim_start().chars().next())
+            == Some('T')
+    })
+}
+
+/// Sends [`Signal::SIGCONT`] to the processes, continues the others even if one of them fails.
+fn continue_processes(processes: Vec<i32>) -> nix::Result<()> {
+    let mut result = Ok(());
+    for pid in processes {
+        if let Err(error) = send_signal(pid, Signal::SIGCONT) {
+            result = Err(error);
+        }
+    }
+
+    result
+}
+
+fn send_signal(pid: i32, signal: Signal) -> nix::Result<()> {
+    match signal::kill(Pid::from_raw(pid), signal) {
+        // The process exited in the meantime.
+        Err(Errno::ESRCH) => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fake `/proc` with the `cgroup.freeze` file of the target, which we can check to see if
+    /// the target is frozen.
+    struct FakeProc(PathBuf);
+
+    impl FakeProc {
+        /// Doesn't exist in the real `/proc`, so freezing with signals fails.
+        const PID: u64 = u32::MAX as u64;
+
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("mirrord-freeze-{name}-{}", std::process::id()));
+            let cgroup = root.join(format!("{}/root/sys/fs/cgroup", Self::PID));
+            fs::create_dir_all(&cgroup).unwrap();
+            fs::write(cgroup.join("cgroup.freeze"), "0").unwrap();
+
+            Self(root)
+        }
+
+        fn freezer(&self) -> TargetFreezer {
+            TargetFreezer::with_proc_root(Self::PID, self.0.clone())
+        }
+
+        fn freeze_file(&self) -> PathBuf {
+            self.0
+                .join(format!("{}/root/sys/fs/cgroup/cgroup.freeze", Self::PID))
+        }
+
+        fn is_frozen(&self) -> bool {
+            fs::read_to_string(self.freeze_file()).unwrap() == "1"
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn frozen_until_last_guard() {
+        let proc = FakeProc::new("last-guard");
+        let freezer = proc.freezer();
+
+        let first = freezer.freeze().unwrap();
+        assert!(proc.is_frozen());
+        let second = freezer.freeze().unwrap();
+
+        drop(first);
+        assert!(proc.is_frozen());
+        drop(second);
+        assert!(!proc.is_frozen());
+    }
+
+    /// The container is frozen once, a thaw from outside is not undone by the next guard.
+    #[test]
+    fn frozen_once_for_all_guards() {
+        let proc = FakeProc::new("once");
+        let freezer = proc.freezer();
+
+        let _first = freezer.freeze().unwrap();
+        fs::write(proc.freeze_file(), "0").unwrap();
+        let _second = freezer.freeze().unwrap();
+
+        assert!(!proc.is_frozen());
+    }
+
+    #[test]
+    fn frozen_again_after_thaw() {
+        let proc = FakeProc::new("again");
+        let freezer = proc.freezer();
+
+        drop(freezer.freeze().unwrap());
+        assert!(!proc.is_frozen());
+
+        let _guard = freezer.freeze().unwrap();
+        assert!(proc.is_frozen());
+    }
+
+    /// A failed freeze doesn't count as a guard, which would keep the container frozen forever.
+    #[test]
+    fn failed_freeze_is_not_counted() {
+        let proc = FakeProc::new("failed");
+        let freezer = proc.freezer();
+        fs::remove_file(proc.freeze_file()).unwrap();
+
+        assert!(matches!(freezer.freeze(), Err(FreezeError::Proc(..))));
+
+        fs::write(proc.freeze_file(), "0").unwrap();
+        drop(freezer.freeze().unwrap());
+        assert!(!proc.is_frozen());
+    }
+
+    #[test]
+    fn abandoned_freeze_is_thawed() {
+        let proc = FakeProc::new("abandoned");
+        let freezer = proc.freezer();
+
+        std::mem::forget(freezer.freeze().unwrap());
+        assert!(proc.is_frozen());
+
+        proc.freezer().thaw_abandoned().unwrap();
+        assert!(!proc.is_frozen());
+    }
+
+    #[test]
+    fn stopped_process() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        assert!(!is_stopped(pid));
+
+        send_signal(pid, Signal::SIGSTOP).unwrap();
+        // The signal is delivered asynchronously.
+        let stopped = (0..100).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            is_stopped(pid)
+        });
+        assert!(stopped);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+//! Handles [`GetIfAddrsRequest`](mirrord_protocol::interfaces::GetIfAddrsRequest)s, listing the
+//! network interfaces from the network namespace of the target.
+
+use std::{io, net::IpAddr};
+
+use mirrord_protocol::{
+    interfaces::{GetIfAddrsResponse, InterfaceAddress, InterfaceFlags},
+    RemoteResult,
+};
+use nix::{ifaddrs, net::if_::InterfaceFlags as RawFlags, sys::socket::SockaddrStorage};
+use tokio::sync::oneshot;
+use tracing::Level;
+
+use crate::util::run_thread_in_namespace;
+
+/// Lists the IP addresses of the network interfaces in the network namespace of the process with
+/// the given `pid`, like `getifaddrs` in the target would.
+#[tracing::instrument(level = Level::DEBUG, ret)]
+pub(crate) async fn get_ifaddrs(pid: Option<u64>) -> GetIfAddrsResponse {
+    let (result_tx, result_rx) = oneshot::channel();
+    let _ = run_thread_in_namespace(
+        async move {
+            let _ = result_tx.send(list_addresses());
+        },
+        "GetIfAddrs".to_string(),
+        pid,
+        "net",
+    );
+
+    let result = result_rx.await.unwrap_or_else(|_| {
+        tracing::error!("Listing the network interfaces failed");
+        Err(io::Error::other("the agent failed to list the network interfaces").into())
+    });
+
+    GetIfAddrsResponse(result)
+}
+
+fn list_addresses() -> RemoteResult<Vec<InterfaceAddress>> {
+    let addresses = ifaddrs::getifaddrs()
+        .map_err(io::Error::from)?
+        .filter_map(|raw| {
+            // Skips the link-layer entries, and the interfaces without an address.
+            let address = ip(raw.address)?;
+
+            Some(InterfaceAddress {
+                name: raw.interface_name,
+                flags: InterfaceFlags {
+                    up: raw.flags.contains(RawFlags::IFF_UP),
+                    running: raw.flags.contains(RawFlags::IFF_RUNNING),
+                    loopback: raw.flags.contains(RawFlags::IFF_LOOPBACK),
+                    point_to_point: raw.flags.contains(RawFlags::IFF_POINTOPOINT),
+                    broadcast: raw.flags.contains(RawFlags::IFF_BROADCAST),
+                    multicast: raw.flags.contains(RawFlags::IFF_MULTICAST),
+                },
+                address,
+                netmask: ip(raw.netmask),
+                broadcast: ip(raw.broadcast.or(raw.destination)),
+            })
+        })
+        .collect();
+
+    Ok(addresses)
+}
+
+fn ip(address: Option<SockaddrStorage>) -> Option<IpAddr> {
+    let address = address?;
+
+    address
+        .as_sockaddr_in()
+        .map(|address| IpAddr::V4(address.ip()))
+        .or_else(|| {
+            address
+                .as_sockaddr_in6()
+                .map(|address| IpAddr::V6(address.ip()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The loopback interface is always there, in the namespace of the tests.
+    #[tokio::test]
+    async fn lists_loopback() {
+        let GetIfAddrsResponse(addresses) = get_ifaddrs(None).await;
+
+        let loopback = addresses
+            .unwrap()
+            .into_iter()
+            .find(|address| address.address.is_loopback() && address.address.is_ipv4())
+            .unwrap();
+        assert!(loopback.flags.loopback);
+        assert_eq!(loopback.netmask, Some("255.0.0.0".parse().unwrap()));
+    }
+}
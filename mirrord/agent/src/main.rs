@@ -20,16 +20,30 @@ mod env;
 #[cfg(target_os = "linux")]
 mod error;
 #[cfg(target_os = "linux")]
+mod fd_budget;
+#[cfg(target_os = "linux")]
 mod file;
 #[cfg(target_os = "linux")]
+mod freeze;
+#[cfg(target_os = "linux")]
 mod http;
 #[cfg(target_os = "linux")]
+mod interfaces;
+#[cfg(target_os = "linux")]
 mod namespace;
 #[cfg(target_os = "linux")]
 mod outgoing;
 #[cfg(target_os = "linux")]
+mod output;
+#[cfg(target_os = "linux")]
+mod rate_limit;
+#[cfg(target_os = "linux")]
+mod reachability;
+#[cfg(target_os = "linux")]
 mod runtime;
 #[cfg(target_os = "linux")]
+mod sandbox;
+#[cfg(target_os = "linux")]
 mod sniffer;
 #[cfg(target_os = "linux")]
 mod steal;
@@ -373,6 +373,13 @@ impl TcpOutgoingTask {
 
                 Ok(())
             }
+
+            // Read from the remote file and sent as `LayerTcpOutgoing::Write` by the client
+            // connection.
+            LayerTcpOutgoing::SendFile(request) => {
+                tracing::trace!(?request, "SendFile is handled by the client connection");
+                Ok(())
+            }
         }
     }
 }
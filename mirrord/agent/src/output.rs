@@ -0,0 +1,81 @@
+//! Writes the output of the local process to the output of the target, so that it ends up in the
+//! target container's log stream (see [`ClientMessage::Output`]).
+//!
+//! [`ClientMessage::Output`]: mirrord_protocol::ClientMessage::Output
+use std::{io, path::PathBuf};
+
+use mirrord_protocol::output::{OutputMessage, OutputStream};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+/// Writes [`OutputMessage`]s to the stdout/stderr of the target process.
+///
+/// The streams are opened through `/proc/<pid>/fd`, when the first [`OutputMessage`] arrives.
+#[derive(Debug)]
+pub(crate) struct TargetOutput {
+    /// Pid of the target process, [`None`] when running without a target.
+    pid: Option<u64>,
+    stdout: Option<File>,
+    stderr: Option<File>,
+    /// Set after the first failure, so that we don't keep trying (and reporting the failure).
+    failed: bool,
+}
+
+impl TargetOutput {
+    pub(crate) fn new(pid: Option<u64>) -> Self {
+        Self {
+            pid,
+            stdout: None,
+            stderr: None,
+            failed: false,
+        }
+    }
+
+    /// Writes the [`OutputMessage`] to the target.
+    ///
+    /// Only the first failure is returned, following messages are dropped.
+    #[tracing::instrument(level = "trace", skip(self, bytes), fields(len = bytes.len()), err)]
+    pub(crate) async fn write(
+        &mut self,
+        OutputMessage { stream, bytes }: OutputMessage,
+    ) -> io::Result<()> {
+        if self.failed {
+            return Ok(());
+        }
+
+        let result = self.write_inner(stream, &bytes).await;
+        self.failed = result.is_err();
+        result
+    }
+
+    async fn write_inner(&mut self, stream: OutputStream, bytes: &[u8]) -> io::Result<()> {
+        let pid = self.pid.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "running without a target, there is no output to write to",
+            )
+        })?;
+
+        let (file, fd) = match stream {
+            OutputStream::Stdout => (&mut self.stdout, 1),
+            OutputStream::Stderr => (&mut self.stderr, 2),
+        };
+
+        let file = match file {
+            Some(file) => file,
+            None => {
+                let path = PathBuf::from("/proc")
+                    .join(pid.to_string())
+                    .join("fd")
+                    .join(fd.to_string());
+                let opened = OpenOptions::new().append(true).open(path).await?;
+                file.insert(opened)
+            }
+        };
+
+        file.write_all(bytes).await?;
+        file.flush().await
+    }
+}
@@ -0,0 +1,280 @@
+//! Per-client rate limiting of the file operations and DNS requests served by the agent.
+//!
+//! The limits are set by the operator (see [`AGENT_FILE_OPS_RATE_LIMIT_ENV`] and
+//! [`AGENT_DNS_RATE_LIMIT_ENV`]), to protect the node from misbehaving local processes.
+//!
+//! Clients that support [`THROTTLE_VERSION`] get a [`ResponseError::Throttled`] response, and
+//! retry the request after a backoff. Older clients can't handle it, so the agent just waits
+//! before serving their requests.
+//!
+//! [`AGENT_FILE_OPS_RATE_LIMIT_ENV`]: mirrord_protocol::AGENT_FILE_OPS_RATE_LIMIT_ENV
+//! [`AGENT_DNS_RATE_LIMIT_ENV`]: mirrord_protocol::AGENT_DNS_RATE_LIMIT_ENV
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+use mirrord_protocol::{FileRequest, FileResponse, ResponseError, THROTTLE_VERSION};
+use semver::Version;
+use tracing::Level;
+
+/// Token bucket that allows bursts of up to `rate` requests, and refills at `rate` requests per
+/// second.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    rate: NonZeroU32,
+    tokens: f64,
+    last_refill: Instant,
+    /// Once a request is throttled, every request is throttled until this instant.
+    ///
+    /// This way, the requests that the client sent before it saw the first throttled response
+    /// are throttled as well, and the client retries all of them in their original order.
+    throttled_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: NonZeroU32) -> Self {
+        Self {
+            rate,
+            tokens: rate.get().into(),
+            last_refill: Instant::now(),
+            throttled_until: None,
+        }
+    }
+
+    /// Takes a token for one request, or returns how long the client should wait before
+    /// retrying it.
+    pub(crate) fn acquire(&mut self) -> Result<(), Duration> {
+        self.acquire_at(Instant::now())
+    }
+
+    fn acquire_at(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.throttled_until {
+            if now < until {
+                return Err(until - now);
+            }
+
+            self.throttled_until = None;
+        }
+
+        let rate = f64::from(self.rate.get());
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / rate);
+            self.throttled_until = Some(now + wait);
+            Err(wait)
+        }
+    }
+}
+
+/// The [`RateLimiter`]s of a single client.
+#[derive(Debug)]
+pub(crate) struct ClientRateLimits {
+    file_ops: Option<RateLimiter>,
+    dns: Option<RateLimiter>,
+    /// Whether the client can handle [`ResponseError::Throttled`].
+    throttle_supported: bool,
+}
+
+impl ClientRateLimits {
+    pub(crate) fn new(file_ops: Option<NonZeroU32>, dns: Option<NonZeroU32>) -> Self {
+        Self {
+            file_ops: file_ops.map(RateLimiter::new),
+            dns: dns.map(RateLimiter::new),
+            throttle_supported: false,
+        }
+    }
+
+    /// Called when the client and the agent agree on a protocol version.
+    pub(crate) fn set_protocol_version(&mut self, version: &Version) {
+        self.throttle_supported = THROTTLE_VERSION.matches(version);
+    }
+
+    /// Returns the response for a throttled `request`, or [`None`] when it should be served.
+    ///
+    /// Requests that don't get a response (closing files and dirs, setting up the scratch dir,
+    /// following files, prefetching trees) are never throttled, as they only free resources, are
+    /// sent once per session, or are sent by the intproxy on its own.
+    #[tracing::instrument(level = Level::TRACE, ret)]
+    pub(crate) async fn file_request(&mut self, request: &FileRequest) -> Option<FileResponse> {
+        if matches!(
+            request,
+            FileRequest::Close(..)
+                | FileRequest::CloseDir(..)
+                | FileRequest::ScratchDir(..)
+                | FileRequest::Follow(..)
+                | FileRequest::Unfollow(..)
+                | FileRequest::PrefetchTree(..)
+        ) {
+            return None;
+        }
+
+        let error = Self::acquire(self.file_ops.as_mut(), self.throttle_supported).await?;
+
+        let response = match request {
+            FileRequest::Open(..) | FileRequest::OpenRelative(..) | FileRequest::OpenAt2(..) => {
+                FileResponse::Open(Err(error))
+            }
+            FileRequest::Read(..) => FileResponse::Read(Err(error)),
+            FileRequest::ReadLimited(..) => FileResponse::ReadLimited(Err(error)),
+            FileRequest::Seek(..) => FileResponse::Seek(Err(error)),
+            FileRequest::Write(..) => FileResponse::Write(Err(error)),
+            FileRequest::WriteLimited(..) => FileResponse::WriteLimited(Err(error)),
+            FileRequest::Access(..) => FileResponse::Access(Err(error)),
+            FileRequest::Xstat(..) => FileResponse::Xstat(Err(error)),
+            FileRequest::Statx(..) => FileResponse::Statx(Err(error)),
+            FileRequest::XstatFs(..) | FileRequest::StatFs(..) => FileResponse::XstatFs(Err(error)),
+            FileRequest::FdOpenDir(..) => FileResponse::OpenDir(Err(error)),
+            FileRequest::ReadDir(..) => FileResponse::ReadDir(Err(error)),
+            FileRequest::GetDEnts64(..) => FileResponse::GetDEnts64(Err(error)),
+            FileRequest::ReadLink(..) => FileResponse::ReadLink(Err(error)),
+            FileRequest::ReadDirBatch(..) => FileResponse::ReadDirBatch(Err(error)),
+            FileRequest::ReadDirPlusBatch(..) => FileResponse::ReadDirPlusBatch(Err(error)),
+            FileRequest::ReadDirDelta(..) => FileResponse::ReadDirDelta(Err(error)),
+            FileRequest::Canonicalize(..) => FileResponse::Canonicalize(Err(error)),
+            FileRequest::Unlink(..) | FileRequest::UnlinkAt(..) => FileResponse::Unlink(Err(error)),
+            FileRequest::Rename(..) | FileRequest::RenameAt(..) => FileResponse::Rename(Err(error)),
+            FileRequest::Chmod(..) | FileRequest::Fchmod(..) | FileRequest::ChmodAt(..) => {
+                FileResponse::Chmod(Err(error))
+            }
+            FileRequest::Chown(..) | FileRequest::Fchown(..) | FileRequest::ChownAt(..) => {
+                FileResponse::Chown(Err(error))
+            }
+            FileRequest::ReadWhole(..) => FileResponse::ReadWhole(Err(error)),
+            FileRequest::Glob(..) => FileResponse::Glob(Err(error)),
+            FileRequest::Symlink(..) | FileRequest::SymlinkAt(..) => {
+                FileResponse::Symlink(Err(error))
+            }
+            FileRequest::Link(..) | FileRequest::LinkAt(..) => FileResponse::Link(Err(error)),
+            FileRequest::UtimensAt(..) | FileRequest::Futimens(..) => {
+                FileResponse::Utimens(Err(error))
+            }
+            FileRequest::Truncate(..) | FileRequest::Ftruncate(..) => {
+                FileResponse::Truncate(Err(error))
+            }
+            FileRequest::Fsync(..) => FileResponse::Fsync(Err(error)),
+            FileRequest::Fallocate(..) => FileResponse::Fallocate(Err(error)),
+            FileRequest::CopyFileRange(..) => FileResponse::CopyFileRange(Err(error)),
+            FileRequest::ReadV(..) => FileResponse::ReadV(Err(error)),
+            FileRequest::WriteV(..) => FileResponse::WriteV(Err(error)),
+            FileRequest::ReadStream(..) => FileResponse::ReadChunk(Err(error)),
+            FileRequest::GetXattr(..) => FileResponse::GetXattr(Err(error)),
+            FileRequest::SetXattr(..) => FileResponse::SetXattr(Err(error)),
+            FileRequest::ListXattr(..) => FileResponse::ListXattr(Err(error)),
+            FileRequest::RemoveXattr(..) => FileResponse::RemoveXattr(Err(error)),
+            FileRequest::Flock(..) | FileRequest::SetLock(..) => FileResponse::Lock(Err(error)),
+            FileRequest::GetLock(..) => FileResponse::GetLock(Err(error)),
+            FileRequest::Close(..)
+            | FileRequest::CloseDir(..)
+            | FileRequest::ScratchDir(..)
+            | FileRequest::Follow(..)
+            | FileRequest::Unfollow(..)
+            | FileRequest::PrefetchTree(..) => unreachable!("never throttled"),
+        };
+
+        Some(response)
+    }
+
+    /// Returns the error for a throttled DNS request, or [`None`] when it should be served.
+    #[tracing::instrument(level = Level::TRACE, ret)]
+    pub(crate) async fn dns_request(&mut self) -> Option<ResponseError> {
+        Self::acquire(self.dns.as_mut(), self.throttle_supported).await
+    }
+
+    /// Returns the error for a throttled `sendfile` request, or [`None`] when it should be served.
+    ///
+    /// Counts as a file operation, as it reads from a remote file.
+    #[tracing::instrument(level = Level::TRACE, ret)]
+    pub(crate) async fn send_file_request(&mut self) -> Option<ResponseError> {
+        Self::acquire(self.file_ops.as_mut(), self.throttle_supported).await
+    }
+
+    /// Returns [`ResponseError::Throttled`] if the client should retry the request, waits for the
+    /// limiter if the client can't retry.
+    async fn acquire(
+        limiter: Option<&mut RateLimiter>,
+        throttle_supported: bool,
+    ) -> Option<ResponseError> {
+        let limiter = limiter?;
+
+        loop {
+            match limiter.acquire() {
+                Ok(()) => break None,
+                Err(wait) if throttle_supported => {
+                    let retry_after_ms = u64::try_from(wait.as_millis()).unwrap_or(u64::MAX).max(1);
+
+                    break Some(ResponseError::Throttled { retry_after_ms });
+                }
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mirrord_protocol::file::{CloseFileRequest, XstatRequest};
+
+    use super::*;
+
+    #[test]
+    fn allows_burst_then_throttles() {
+        let mut limiter = RateLimiter::new(NonZeroU32::new(10).unwrap());
+        let now = limiter.last_refill;
+
+        for _ in 0..10 {
+            limiter.acquire_at(now).unwrap();
+        }
+
+        let wait = limiter.acquire_at(now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn throttles_until_deadline() {
+        let mut limiter = RateLimiter::new(NonZeroU32::new(1).unwrap());
+        let now = limiter.last_refill;
+
+        limiter.acquire_at(now).unwrap();
+        assert_eq!(limiter.acquire_at(now).unwrap_err(), Duration::from_secs(1));
+
+        // Still throttled halfway through the wait.
+        let later = now + Duration::from_millis(500);
+        assert_eq!(
+            limiter.acquire_at(later).unwrap_err(),
+            Duration::from_millis(500)
+        );
+
+        limiter.acquire_at(now + Duration::from_secs(1)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn throttled_response_matches_request() {
+        let mut limits = ClientRateLimits::new(NonZeroU32::new(1), None);
+        limits.set_protocol_version(&Version::new(1, 13, 0));
+
+        let request = FileRequest::Xstat(XstatRequest {
+            path: Some("/etc/hosts".into()),
+            fd: None,
+            follow_symlink: true,
+        });
+        assert_eq!(limits.file_request(&request).await, None);
+        assert!(matches!(
+            limits.file_request(&request).await,
+            Some(FileResponse::Xstat(Err(ResponseError::Throttled { .. })))
+        ));
+
+        // Closing is never throttled.
+        let close = FileRequest::Close(CloseFileRequest { fd: 1 });
+        assert_eq!(limits.file_request(&close).await, None);
+
+        // DNS is not limited.
+        assert_eq!(limits.dns_request().await, None);
+    }
+}
@@ -0,0 +1,84 @@
+//! Handles [`ReachabilityRequest`]s, trying to connect to the destinations from the network
+//! namespace of the target.
+
+use std::{net::SocketAddr, time::Duration};
+
+use futures::future;
+use mirrord_protocol::reachability::{
+    ReachabilityRequest, ReachabilityResponse, ReachabilityResult,
+};
+use tokio::{net::TcpStream, sync::oneshot, time};
+use tracing::Level;
+
+use crate::util::run_thread_in_namespace;
+
+/// Upper limit for [`ReachabilityRequest::timeout_ms`], so that the check does not hold the client
+/// for long.
+const MAX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Upper limit for the number of [`ReachabilityRequest::addresses`] checked, the rest are reported
+/// as not checked.
+const MAX_ADDRESSES: usize = 64;
+
+/// Tries to connect to each of the [`ReachabilityRequest::addresses`] concurrently, in the network
+/// namespace of the process with the given `pid`.
+#[tracing::instrument(level = Level::DEBUG, ret)]
+pub(crate) async fn check_reachability(
+    pid: Option<u64>,
+    request: ReachabilityRequest,
+) -> ReachabilityResponse {
+    let ReachabilityRequest {
+        addresses,
+        timeout_ms,
+    } = request;
+    let timeout = Duration::from_millis(timeout_ms).min(MAX_TIMEOUT);
+
+    let (checked, skipped) = addresses.split_at(addresses.len().min(MAX_ADDRESSES));
+    let checked = checked.to_vec();
+    let skipped = skipped.iter().map(|address| ReachabilityResult {
+        address: *address,
+        error: Some("not checked, too many destinations".to_string()),
+    });
+
+    let (result_tx, result_rx) = oneshot::channel();
+    let task_addresses = checked.clone();
+    let _ = run_thread_in_namespace(
+        async move {
+            let results =
+                future::join_all(task_addresses.into_iter().map(|a| connect(a, timeout))).await;
+            let _ = result_tx.send(results);
+        },
+        "Reachability".to_string(),
+        pid,
+        "net",
+    );
+
+    let results = match result_rx.await {
+        Ok(results) => results,
+        Err(..) => {
+            tracing::error!("Reachability check task failed");
+
+            checked
+                .into_iter()
+                .map(|address| ReachabilityResult {
+                    address,
+                    error: Some("the agent failed to run the check".to_string()),
+                })
+                .collect()
+        }
+    };
+
+    ReachabilityResponse {
+        results: results.into_iter().chain(skipped).collect(),
+    }
+}
+
+async fn connect(address: SocketAddr, timeout: Duration) -> ReachabilityResult {
+    let error = match time::timeout(timeout, TcpStream::connect(address)).await {
+        Ok(Ok(..)) => None,
+        Ok(Err(error)) => Some(error.to_string()),
+        Err(..) => Some(format!("timed out after {}ms", timeout.as_millis())),
+    };
+
+    ReachabilityResult { address, error }
+}
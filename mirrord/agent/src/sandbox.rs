@@ -0,0 +1,109 @@
+//! Detection of sandboxed container runtimes, where some of the agent's features work
+//! differently, or don't work at all.
+use std::fmt;
+
+/// Contents of `/proc/version` in gVisor, which doesn't expose the version of the host kernel.
+const GVISOR_PROC_VERSION: &str = "Linux version 4.4.0 #1 SMP Sun Jan 10 15:06:54 PST 2016";
+
+/// Sandboxed runtime that the agent runs in.
+///
+/// The agent can only run in a sandbox as an ephemeral container of the target pod, as the target
+/// doesn't share the node's network stack (gVisor implements its own in userspace, Kata runs the
+/// pod in a VM).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sandbox {
+    GVisor,
+    Kata,
+}
+
+impl Sandbox {
+    /// Detects the sandbox from `/proc`, [`None`] when the agent runs directly on the node's
+    /// kernel.
+    pub(crate) fn detect() -> Option<Self> {
+        let version = std::fs::read_to_string("/proc/version").unwrap_or_default();
+        let cmdline = std::fs::read_to_string("/proc/cmdline").unwrap_or_default();
+
+        Self::from_proc(&version, &cmdline)
+    }
+
+    /// Detects the sandbox from the contents of `/proc/version` and `/proc/cmdline`.
+    ///
+    /// Kata starts the guest kernel with the options of its own agent (`agent.*`).
+    fn from_proc(version: &str, cmdline: &str) -> Option<Self> {
+        if version.starts_with(GVISOR_PROC_VERSION) {
+            Some(Self::GVisor)
+        } else if cmdline
+            .split_whitespace()
+            .any(|option| option.starts_with("agent.") || option.contains("kata-containers"))
+        {
+            Some(Self::Kata)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the sandbox supports `iptables-nft`, gVisor only implements the legacy
+    /// interface.
+    pub(crate) fn supports_nftables(self) -> bool {
+        !matches!(self, Self::GVisor)
+    }
+
+    /// Describes which traffic capture features are available to the clients.
+    pub(crate) fn report(self, mirroring: bool, stealing: bool) -> String {
+        let mirroring = if mirroring {
+            "available".to_string()
+        } else {
+            let hint = match self {
+                Self::GVisor => "raw sockets are allowed only when `runsc` runs with `--net-raw`",
+                Self::Kata => "the agent could not open a raw socket in the VM",
+            };
+            format!("unavailable, {hint}")
+        };
+        let stealing = if stealing { "available" } else { "unavailable" };
+
+        format!(
+            "agent is running in a {self} sandbox: \
+            mirroring incoming traffic is {mirroring}, stealing incoming traffic is {stealing}"
+        )
+    }
+}
+
+impl fmt::Display for Sandbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GVisor => write!(f, "gVisor"),
+            Self::Kata => write!(f, "Kata Containers"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        "Linux version 4.4.0 #1 SMP Sun Jan 10 15:06:54 PST 2016\n",
+        "",
+        Some(Sandbox::GVisor)
+    )]
+    #[case(
+        "Linux version 6.1.62 (kata) #1 SMP\n",
+        "tsc=reliable quiet agent.log=debug systemd.unit=kata-containers.target\n",
+        Some(Sandbox::Kata)
+    )]
+    #[case(
+        "Linux version 6.8.0-1015-gcp (buildd@lcy02-amd64-030) #17-Ubuntu SMP\n",
+        "BOOT_IMAGE=/vmlinuz root=/dev/sda1 ro console=ttyS0\n",
+        None
+    )]
+    fn detects_sandbox(
+        #[case] version: &str,
+        #[case] cmdline: &str,
+        #[case] expected: Option<Sandbox>,
+    ) {
+        assert_eq!(Sandbox::from_proc(version, cmdline), expected);
+    }
+}
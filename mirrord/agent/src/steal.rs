@@ -1,5 +1,8 @@
 use mirrord_protocol::{
-    tcp::{DaemonTcp, HttpResponseFallback, StealType, TcpData},
+    tcp::{
+        DaemonTcp, HttpResponseFallback, PortReservation, StealDrain, StealFallback, StealHandoff,
+        StealType, TcpData,
+    },
     ConnectionId, Port,
 };
 use tokio::sync::mpsc::Sender;
@@ -9,9 +12,12 @@ use crate::util::ClientId;
 mod api;
 mod connection;
 mod connections;
+mod database;
+mod handoff;
 mod http;
 pub mod ip_tables;
 mod orig_dst;
+mod reservations;
 mod subscriptions;
 
 pub(crate) use api::TcpStealerApi;
@@ -54,6 +60,31 @@ enum Command {
     HttpResponse(HttpResponseFallback),
 
     SwitchProtocolVersion(semver::Version),
+
+    /// A layer wants its subscriptions to be adopted by the next session with the same key, and
+    /// to adopt the subscriptions of a previous session with this key.
+    ///
+    /// The agent keeps stealing traffic from the ports of this layer for a while after it exits.
+    Handoff(StealHandoff),
+
+    /// A layer wants the requests that it fails to respond to to be handled according to the
+    /// given [`StealFallback`].
+    Fallback(StealFallback),
+
+    /// A layer is about to exit, and wants its in-flight requests and connections to finish first.
+    ///
+    /// The agent stops stealing new traffic on behalf of this layer, and removes its subscriptions
+    /// once the in-flight traffic is done.
+    Drain(StealDrain),
+
+    /// A layer wants a port reserved in the target, for a server to connect back to it.
+    ///
+    /// The agent listens on the port, and steals its connections once the layer subscribes to it
+    /// with [`StealType::Reserved`].
+    ReservePort(PortReservation),
+
+    /// A layer no longer needs the port it reserved with [`Command::ReservePort`].
+    ReleasePort(Port),
 }
 
 /// Association between a client (identified by the `client_id`) and a [`Command`].
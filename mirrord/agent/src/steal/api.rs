@@ -5,7 +5,7 @@ use hyper::body::Frame;
 use mirrord_protocol::{
     tcp::{
         ChunkedResponse, DaemonTcp, HttpResponse, HttpResponseFallback, InternalHttpResponse,
-        LayerTcpSteal, ReceiverStreamBody, TcpData,
+        LayerTcpSteal, ReceiverStreamBody, StealFallback, TcpData,
     },
     RequestId,
 };
@@ -159,6 +159,50 @@ impl TcpStealerApi {
             .await
     }
 
+    /// Handles the conversion of [`LayerTcpSteal::Handoff`], that is passed from the
+    /// agent, to an internal stealer command [`Command::Handoff`].
+    ///
+    /// The actual handling of this message is done in [`TcpConnectionStealer`].
+    pub(crate) async fn handoff(&mut self, handoff: StealHandoff) -> Result<(), AgentError> {
+        self.send_command(Command::Handoff(handoff)).await
+    }
+
+    /// Handles the conversion of [`LayerTcpSteal::Fallback`], that is passed from the
+    /// agent, to an internal stealer command [`Command::Fallback`].
+    ///
+    /// The actual handling of this message is done in [`TcpConnectionStealer`].
+    pub(crate) async fn fallback(&mut self, fallback: StealFallback) -> Result<(), AgentError> {
+        self.send_command(Command::Fallback(fallback)).await
+    }
+
+    /// Handles the conversion of [`LayerTcpSteal::Drain`], that is passed from the
+    /// agent, to an internal stealer command [`Command::Drain`].
+    ///
+    /// The actual handling of this message is done in [`TcpConnectionStealer`].
+    pub(crate) async fn drain(&mut self, drain: StealDrain) -> Result<(), AgentError> {
+        self.send_command(Command::Drain(drain)).await
+    }
+
+    /// Handles the conversion of [`LayerTcpSteal::ReservePort`], that is passed from the
+    /// agent, to an internal stealer command [`Command::ReservePort`].
+    ///
+    /// The actual handling of this message is done in [`TcpConnectionStealer`], which responds
+    /// with [`DaemonTcp::PortReserved`].
+    pub(crate) async fn reserve_port(
+        &mut self,
+        reservation: PortReservation,
+    ) -> Result<(), AgentError> {
+        self.send_command(Command::ReservePort(reservation)).await
+    }
+
+    /// Handles the conversion of [`LayerTcpSteal::ReleasePort`], that is passed from the
+    /// agent, to an internal stealer command [`Command::ReleasePort`].
+    ///
+    /// The actual handling of this message is done in [`TcpConnectionStealer`].
+    pub(crate) async fn release_port(&mut self, port: Port) -> Result<(), AgentError> {
+        self.send_command(Command::ReleasePort(port)).await
+    }
+
     pub(crate) async fn handle_client_message(&mut self, message: LayerTcpSteal) -> Result<()> {
         match message {
             LayerTcpSteal::PortSubscribe(port_steal) => self.port_subscribe(port_steal).await,
@@ -168,7 +212,18 @@ impl TcpStealerApi {
                 self.connection_unsubscribe(connection_id).await
             }
             LayerTcpSteal::PortUnsubscribe(port) => self.port_unsubscribe(port).await,
+            LayerTcpSteal::Handoff(handoff) => self.handoff(handoff).await,
+            LayerTcpSteal::Fallback(fallback) => self.fallback(fallback).await,
+            LayerTcpSteal::Drain(drain) => self.drain(drain).await,
+            LayerTcpSteal::ReservePort(reservation) => self.reserve_port(reservation).await,
+            LayerTcpSteal::ReleasePort(port) => self.release_port(port).await,
             LayerTcpSteal::Data(tcp_data) => self.client_data(tcp_data).await,
+            // Read from the remote file and sent as `LayerTcpSteal::Data` by the client
+            // connection.
+            LayerTcpSteal::SendFile(request) => {
+                tracing::trace!(?request, "SendFile is handled by the client connection");
+                Ok(())
+            }
             LayerTcpSteal::HttpResponse(response) => {
                 self.http_response(HttpResponseFallback::Fallback(response))
                     .await
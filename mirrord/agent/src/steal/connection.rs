@@ -1,32 +1,33 @@
 use std::{
     collections::{HashMap, HashSet},
+    future,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
 };
 
 use fancy_regex::Regex;
 use futures::{stream::FuturesUnordered, StreamExt};
 use http::Request;
 use http_body_util::BodyExt;
-use hyper::{
-    body::Incoming,
-    http::{header::UPGRADE, request::Parts},
-};
+use hyper::http::{header::UPGRADE, request::Parts};
 use mirrord_protocol::{
     body_chunks::{BodyExt as _, Frames},
     tcp::{
         ChunkedHttpBody, ChunkedHttpError, ChunkedRequest, DaemonTcp, HttpRequest,
         HttpResponseFallback, InternalHttpBody, InternalHttpBodyFrame, InternalHttpRequest,
-        StealType, TcpClose, TcpData, HTTP_CHUNKED_REQUEST_VERSION, HTTP_FILTERED_UPGRADE_VERSION,
-        HTTP_FRAMED_VERSION,
+        PortReservation, StealDrain, StealHandoff, StealType, TcpClose, TcpData,
+        HTTP_CHUNKED_REQUEST_VERSION, HTTP_CONNECTION_METADATA_VERSION,
+        HTTP_FILTERED_UPGRADE_VERSION, HTTP_FRAMED_VERSION,
     },
     ConnectionId, Port,
-    RemoteError::{BadHttpFilterExRegex, BadHttpFilterRegex},
+    RemoteError::{BadDatabaseFilterRegex, BadHttpFilterExRegex, BadHttpFilterRegex},
     RequestId,
 };
 use serde::Deserialize;
 use tokio::{
     net::TcpStream,
     sync::mpsc::{Receiver, Sender},
+    time::{self, Instant},
 };
 use tokio_util::sync::CancellationToken;
 use tracing::warn;
@@ -35,11 +36,15 @@ use crate::{
     error::{AgentError, Result},
     steal::{
         connections::{
-            ConnectionMessageIn, ConnectionMessageOut, StolenConnection, StolenConnections,
+            ConnectionMessageIn, ConnectionMessageOut, DynamicBody, StolenConnection,
+            StolenConnections,
         },
+        database::DatabaseFilter,
+        handoff::PendingHandoff,
         http::HttpFilter,
         orig_dst,
-        subscriptions::{IpTablesRedirector, PortSubscriptions},
+        reservations::PortReservations,
+        subscriptions::{IpTablesRedirector, PortFilter, PortSubscription, PortSubscriptions},
         Command, StealerCommand,
     },
     util::{ChannelClosedFuture, ClientId},
@@ -51,7 +56,7 @@ struct MatchedHttpRequest {
     connection_id: ConnectionId,
     port: Port,
     request_id: RequestId,
-    request: Request<Incoming>,
+    request: Request<DynamicBody>,
 }
 
 impl MatchedHttpRequest {
@@ -126,9 +131,30 @@ struct Client {
     /// Client subscriptions to stolen connections.
     /// Used to unsubscribe when the client exits.
     subscribed_connections: HashSet<ConnectionId>,
+    /// Subscriptions to unfiltered connections, subset of [`Self::subscribed_connections`].
+    ///
+    /// Unlike HTTP connections, these can't be handed off to another client.
+    tcp_connections: HashSet<ConnectionId>,
+    /// Stolen HTTP requests sent to the client, that were not responded to yet.
+    /// Used to fail them when the client's subscriptions are handed off.
+    in_flight_requests: HashSet<(ConnectionId, RequestId)>,
+    /// Set with [`Command::Handoff`], allows the next session with the same key to adopt this
+    /// client's subscriptions.
+    handoff: Option<StealHandoff>,
 }
 
 impl Client {
+    fn new(tx: Sender<DaemonTcp>, protocol_version: semver::Version) -> Self {
+        Self {
+            tx,
+            protocol_version,
+            subscribed_connections: Default::default(),
+            tcp_connections: Default::default(),
+            in_flight_requests: Default::default(),
+            handoff: None,
+        }
+    }
+
     /// Attempts to spawn a new [`tokio::task`] to transform the given [`MatchedHttpRequest`] into
     /// [`DaemonTcp::HttpRequest`], [`DaemonTcp::HttpRequestFramed`] or
     /// [`DaemonTcp::HttpRequestChunked`] and send it via cloned [`Client::tx`].
@@ -143,7 +169,7 @@ impl Client {
     ///
     /// # Why async?
     ///
-    /// This method spawns a [`tokio::task`] to read the [`Incoming`] body od the request without
+    /// This method spawns a [`tokio::task`] to read the body of the request without
     /// blocking the main [`TcpConnectionStealer`] loop.
     fn send_request_async(&self, request: MatchedHttpRequest) -> bool {
         if request.request.headers().contains_key(UPGRADE)
@@ -262,6 +288,15 @@ impl Client {
     }
 }
 
+/// Drain of a client's subscriptions, started with [`Command::Drain`].
+#[derive(Debug)]
+struct PendingDrain {
+    /// Ports that the client had subscriptions on, see [`PortSubscriptions::drain`].
+    ports: HashSet<Port>,
+    /// When we stop waiting for the client's in-flight requests and connections.
+    deadline: Instant,
+}
+
 #[derive(Deserialize, Debug, Default)]
 struct TcpStealerConfig {
     stealer_flush_connections: bool,
@@ -277,6 +312,9 @@ pub(crate) struct TcpConnectionStealer {
     /// For managing active subscriptions and port redirections.
     port_subscriptions: PortSubscriptions<IpTablesRedirector>,
 
+    /// Ports reserved by the clients (see [`Command::ReservePort`]).
+    reservations: PortReservations,
+
     /// For receiving commands.
     /// The other end of this channel belongs to [`TcpStealerApi`](super::api::TcpStealerApi).
     command_rx: Receiver<StealerCommand>,
@@ -285,15 +323,35 @@ pub(crate) struct TcpConnectionStealer {
     clients: HashMap<ClientId, Client>,
 
     /// [`Future`](std::future::Future)s that resolve when stealer clients close.
-    clients_closed: FuturesUnordered<ChannelClosedFuture<StealerCommand>>,
+    clients_closed: FuturesUnordered<ChannelClosedFuture<DaemonTcp>>,
 
     /// Set of active connections stolen by [`Self::port_subscriptions`].
     connections: StolenConnections,
+
+    /// Subscriptions of exited clients, waiting to be adopted by a new session (see
+    /// [`Command::Handoff`]).
+    ///
+    /// Also contains subscriptions that were already adopted, until the adopting client subscribes
+    /// to their ports again.
+    handoffs: HashMap<ClientId, PendingHandoff>,
+
+    /// Maps [`ClientId`]s of the clients that adopted subscriptions to the [`ClientId`]s that the
+    /// subscriptions belong to.
+    ///
+    /// Adopting clients take over the [`Client`] entry of the exited client, so that connections
+    /// and subscriptions don't need to be moved.
+    adopted: HashMap<ClientId, ClientId>,
+
+    /// Clients that are draining their subscriptions before exiting (see [`Command::Drain`]).
+    drains: HashMap<ClientId, PendingDrain>,
 }
 
 impl TcpConnectionStealer {
     pub const TASK_NAME: &'static str = "Stealer";
 
+    /// Upper bound for [`StealDrain::timeout_ms`].
+    const MAX_DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+
     /// Initializes a new [`TcpConnectionStealer`], but doesn't start the actual work.
     /// You need to call [`TcpConnectionStealer::start`] to do so.
     #[tracing::instrument(level = "trace")]
@@ -311,24 +369,33 @@ impl TcpConnectionStealer {
 
         Ok(Self {
             port_subscriptions,
+            reservations: Default::default(),
             command_rx,
             clients: HashMap::with_capacity(8),
             clients_closed: Default::default(),
             connections: StolenConnections::with_capacity(8),
+            handoffs: Default::default(),
+            adopted: Default::default(),
+            drains: Default::default(),
         })
     }
 
     /// Runs the tcp traffic stealer loop.
     ///
-    /// The loop deals with 5 different paths:
+    /// The loop deals with 7 different paths:
     ///
     /// 1. Receiving a new [`StealerCommand`];
     ///
-    /// 2. Accepting a new stolen connection based on the clients' port subscriptions;
+    /// 2. Accepting a new stolen connection based on the clients' port subscriptions, or on the
+    ///    ports they reserved;
     ///
     /// 3. Receiving an update from one of the active stolen connections;
     ///
-    /// 4. Handling the cancellation of the whole stealer thread (given `cancellation_token`).
+    /// 4. Ending a pending subscriptions handoff (see [`Command::Handoff`]);
+    ///
+    /// 5. Ending a subscriptions drain that took too long (see [`Command::Drain`]);
+    ///
+    /// 6. Handling the cancellation of the whole stealer thread (given `cancellation_token`).
     #[tracing::instrument(level = "trace", skip(self))]
     pub(crate) async fn start(
         mut self,
@@ -358,8 +425,24 @@ impl TcpConnectionStealer {
                     }
                 },
 
+                accept = self.reservations.next_connection() => match accept {
+                    Ok((client_id, stream, peer)) => self.reserved_connection(client_id, stream, peer)?,
+                    Err(error) => {
+                        tracing::warn!(?error, "Failed to accept a connection on a reserved port");
+                    }
+                },
+
                 update = self.connections.wait() => self.handle_connection_update(update).await?,
 
+                client_id = Self::next_handoff_deadline(&self.handoffs) => {
+                    self.handoff_expired(client_id).await?;
+                },
+
+                client_id = Self::next_drain_deadline(&self.drains) => {
+                    tracing::warn!(client_id, "Steal subscriptions were not drained in time");
+                    self.finish_drain(client_id).await?;
+                },
+
                 _ = cancellation_token.cancelled() => {
                     break Ok(());
                 }
@@ -398,12 +481,41 @@ impl TcpConnectionStealer {
         Ok(())
     }
 
+    /// Handles a new connection accepted on a port that the client with `client_id` reserved.
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn reserved_connection(
+        &mut self,
+        client_id: ClientId,
+        stream: TcpStream,
+        peer: SocketAddr,
+    ) -> Result<()> {
+        let stolen_connection = StolenConnection {
+            destination: stream.local_addr()?,
+            stream,
+            source: peer,
+            port_subscription: PortSubscription::Unfiltered(client_id),
+        };
+
+        self.connections.manage(stolen_connection);
+
+        Ok(())
+    }
+
     /// Handles an update from one of the connections in [`Self::connections`].
     #[tracing::instrument(level = "trace", skip(self))]
     async fn handle_connection_update(
         &mut self,
         update: ConnectionMessageOut,
     ) -> Result<(), AgentError> {
+        if let Some(handoff) = self.handoffs.get_mut(&update.client_id()) {
+            if let Err(update) = handoff.hold(update) {
+                tracing::warn!(?update, "Too many updates held for a handoff, rejecting");
+                self.reject_connection_update(update).await;
+            }
+
+            return Ok(());
+        }
+
         match update {
             ConnectionMessageOut::Closed {
                 connection_id,
@@ -414,15 +526,21 @@ impl TcpConnectionStealer {
                     return Ok(());
                 };
 
-                if !client.subscribed_connections.remove(&connection_id) {
+                client.tcp_connections.remove(&connection_id);
+                client
+                    .in_flight_requests
+                    .retain(|(id, _)| *id != connection_id);
+
+                if client.subscribed_connections.remove(&connection_id) {
+                    let _ = client
+                        .tx
+                        .send(DaemonTcp::Close(TcpClose { connection_id }))
+                        .await;
+                } else {
                     tracing::trace!(client_id, connection_id, "Client has already unsubscribed");
-                    return Ok(());
                 }
 
-                let _ = client
-                    .tx
-                    .send(DaemonTcp::Close(TcpClose { connection_id }))
-                    .await;
+                self.check_drain(client_id).await?;
             }
 
             ConnectionMessageOut::SubscribedTcp {
@@ -447,26 +565,40 @@ impl TcpConnectionStealer {
                 client
                     .subscribed_connections
                     .insert(connection.connection_id);
+                client.tcp_connections.insert(connection.connection_id);
 
                 let _ = client.tx.send(DaemonTcp::NewConnection(connection)).await;
             }
 
             ConnectionMessageOut::SubscribedHttp {
                 client_id,
-                connection_id,
+                connection,
             } => {
                 let Some(client) = self.clients.get_mut(&client_id) else {
-                    tracing::trace!(client_id, connection_id, "Client has already exited");
+                    tracing::trace!(
+                        client_id,
+                        connection_id = connection.connection_id,
+                        "Client has already exited"
+                    );
                     self.connections
                         .send(
-                            connection_id,
+                            connection.connection_id,
                             ConnectionMessageIn::Unsubscribed { client_id },
                         )
                         .await;
                     return Ok(());
                 };
 
-                client.subscribed_connections.insert(connection_id);
+                client
+                    .subscribed_connections
+                    .insert(connection.connection_id);
+
+                if HTTP_CONNECTION_METADATA_VERSION.matches(&client.protocol_version) {
+                    let _ = client
+                        .tx
+                        .send(DaemonTcp::NewHttpConnection(connection))
+                        .await;
+                }
             }
 
             ConnectionMessageOut::Raw {
@@ -500,7 +632,7 @@ impl TcpConnectionStealer {
                 id,
                 port,
             } => {
-                let Some(client) = self.clients.get(&client_id) else {
+                let Some(client) = self.clients.get_mut(&client_id) else {
                     tracing::trace!(client_id, connection_id, "Client has already exited");
                     return Ok(());
                 };
@@ -517,7 +649,9 @@ impl TcpConnectionStealer {
                     port,
                 };
 
-                if !client.send_request_async(matched_request) {
+                if client.send_request_async(matched_request) {
+                    client.in_flight_requests.insert((connection_id, id));
+                } else {
                     self.connections
                         .send(
                             connection_id,
@@ -539,17 +673,53 @@ impl TcpConnectionStealer {
     /// Inserts a subscription into [`Self::port_subscriptions`].
     #[tracing::instrument(level = "trace", skip(self))]
     async fn port_subscribe(&mut self, client_id: ClientId, port_steal: StealType) -> Result<()> {
+        // The client started a new layer while draining, so it's not exiting after all.
+        if self.drains.contains_key(&client_id) {
+            self.finish_drain(client_id).await?;
+        }
+
+        // Connections to a reserved port are not redirected.
+        if let StealType::Reserved(port) = port_steal {
+            let res = self.reservations.subscribe(client_id, port);
+            let client = self.clients.get(&client_id).expect("client not found");
+            let _ = client.tx.send(DaemonTcp::SubscribeResult(res)).await;
+            return Ok(());
+        }
+
         let spec = match port_steal {
             StealType::All(port) => Ok((port, None)),
             StealType::FilteredHttp(port, filter) => Regex::new(&format!("(?i){filter}"))
-                .map(|regex| (port, Some(HttpFilter::Header(regex))))
+                .map(|regex| (port, Some(PortFilter::Http(HttpFilter::Header(regex)))))
                 .map_err(|err| BadHttpFilterRegex(filter, err.to_string())),
             StealType::FilteredHttpEx(port, filter) => HttpFilter::try_from(&filter)
-                .map(|filter| (port, Some(filter)))
+                .map(|filter| (port, Some(PortFilter::Http(filter))))
                 .map_err(|err| BadHttpFilterExRegex(filter, err.to_string())),
+            StealType::FilteredDatabase(port, filter) => DatabaseFilter::try_from(&filter)
+                .map(|filter| (port, Some(PortFilter::Database(filter))))
+                .map_err(|err| BadDatabaseFilterRegex(filter, err.to_string())),
+            StealType::Reserved(..) => unreachable!("handled above"),
+        };
+
+        // Subscriptions adopted from a previous session are replaced, so that the port remains
+        // stolen the whole time.
+        let claimed = match (&spec, self.handoffs.get_mut(&client_id)) {
+            (Ok((port, _)), Some(handoff)) => {
+                handoff.adopted && handoff.unclaimed_ports.remove(port)
+            }
+            _ => false,
         };
 
         let res = match spec {
+            Ok((port, filter)) if claimed => {
+                let res = self
+                    .port_subscriptions
+                    .replace(client_id, port, filter)
+                    .await?;
+                if res.is_err() {
+                    self.port_subscriptions.remove(client_id, port).await?;
+                }
+                res
+            }
             Ok((port, filter)) => self.port_subscriptions.add(client_id, port, filter).await?,
             Err(e) => Err(e.into()),
         };
@@ -557,18 +727,62 @@ impl TcpConnectionStealer {
         let client = self.clients.get(&client_id).expect("client not found");
         let _ = client.tx.send(DaemonTcp::SubscribeResult(res)).await;
 
+        if claimed
+            && self
+                .handoffs
+                .get(&client_id)
+                .is_some_and(|handoff| handoff.unclaimed_ports.is_empty())
+        {
+            self.finish_handoff(client_id).await?;
+        }
+
         Ok(())
     }
 
     /// Removes the client with `client_id` from our list of clients (layers), and also removes
     /// their subscriptions from [`Self::port_subscriptions`] and all their open
     /// connections.
+    ///
+    /// If the client sent a [`Command::Handoff`], its subscriptions are kept for the next session
+    /// instead, see [`Self::park_client`].
     #[tracing::instrument(level = "trace", skip(self))]
     async fn close_client(&mut self, client_id: ClientId) -> Result<(), AgentError> {
+        let client_id = self.adopted.remove(&client_id).unwrap_or(client_id);
+        let client = self.clients.remove(&client_id).expect("client not found");
+
+        // Nothing connects to the reserved ports of a new session.
+        self.reservations.release_all(client_id);
+
+        if let Some(drain) = self.drains.remove(&client_id) {
+            self.port_subscriptions.remove_drained(drain.ports).await?;
+        }
+
+        if let Some(handoff) = client.handoff.clone() {
+            let ports = self.port_subscriptions.client_ports(client_id);
+            if !ports.is_empty() {
+                return self.park_client(client_id, client, handoff, ports).await;
+            }
+        }
+
+        let mut connections = client.subscribed_connections;
+        if let Some(handoff) = self.handoffs.remove(&client_id) {
+            connections.extend(handoff.connections());
+        }
+
+        self.remove_client(client_id, connections).await
+    }
+
+    /// Removes all subscriptions of the client with `client_id`, and unsubscribes it from the
+    /// given `connections`.
+    async fn remove_client(
+        &mut self,
+        client_id: ClientId,
+        connections: HashSet<ConnectionId>,
+    ) -> Result<(), AgentError> {
         self.port_subscriptions.remove_all(client_id).await?;
+        self.connections.remove_fallback(client_id);
 
-        let client = self.clients.remove(&client_id).expect("client not found");
-        for connection in client.subscribed_connections.into_iter() {
+        for connection in connections {
             self.connections
                 .send(connection, ConnectionMessageIn::Unsubscribed { client_id })
                 .await;
@@ -577,6 +791,275 @@ impl TcpConnectionStealer {
         Ok(())
     }
 
+    /// Keeps the subscriptions of the exited client with `client_id` on the given `ports`, waiting
+    /// for a new session to adopt them with a matching [`Command::Handoff`].
+    ///
+    /// The client's unfiltered connections and requests that it didn't respond to are lost with the
+    /// local application, so they're let go.
+    #[tracing::instrument(level = "trace", skip(self, client))]
+    async fn park_client(
+        &mut self,
+        client_id: ClientId,
+        client: Client,
+        handoff: StealHandoff,
+        ports: HashSet<Port>,
+    ) -> Result<(), AgentError> {
+        // Only the latest session with the key can be adopted.
+        let stale = self
+            .handoffs
+            .iter()
+            .find(|(id, pending)| {
+                **id != client_id && !pending.adopted && pending.key == handoff.key
+            })
+            .map(|(id, _)| *id);
+        if let Some(stale) = stale {
+            self.handoff_expired(stale).await?;
+        }
+
+        for &connection_id in &client.tcp_connections {
+            self.connections
+                .send(
+                    connection_id,
+                    ConnectionMessageIn::Unsubscribed { client_id },
+                )
+                .await;
+        }
+
+        for &(connection_id, request_id) in &client.in_flight_requests {
+            self.connections
+                .send(
+                    connection_id,
+                    ConnectionMessageIn::ResponseFailed {
+                        client_id,
+                        request_id,
+                    },
+                )
+                .await;
+        }
+
+        let subscribed_connections = client
+            .subscribed_connections
+            .difference(&client.tcp_connections)
+            .copied()
+            .collect();
+
+        let mut pending = PendingHandoff::new(
+            handoff.key,
+            PendingHandoff::deadline(handoff.timeout_ms),
+            ports,
+            subscribed_connections,
+        );
+
+        // The client adopted subscriptions, and exited before subscribing to all of their ports.
+        if let Some(previous) = self.handoffs.remove(&client_id) {
+            pending.inherit(previous);
+        }
+
+        self.handoffs.insert(client_id, pending);
+
+        Ok(())
+    }
+
+    /// Handles [`Command::Handoff`].
+    ///
+    /// If there is an exited client with the same key, the client with `client_id` adopts its
+    /// subscriptions, taking over its [`ClientId`] (see [`Self::adopted`]).
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn handoff(&mut self, client_id: ClientId, handoff: StealHandoff) {
+        let resolved_id = self.resolve_client_id(client_id);
+        let can_adopt =
+            resolved_id == client_id && self.port_subscriptions.client_ports(client_id).is_empty();
+
+        let parked = self
+            .handoffs
+            .iter_mut()
+            .find(|(_, pending)| !pending.adopted && pending.key == handoff.key);
+
+        let client_id = match parked {
+            Some((&parked_id, pending)) if can_adopt => {
+                tracing::info!(client_id, parked_id, "Client adopted steal subscriptions");
+
+                pending.adopted = true;
+                pending.deadline = PendingHandoff::deadline(handoff.timeout_ms);
+
+                let mut client = self.clients.remove(&client_id).expect("client not found");
+                client
+                    .subscribed_connections
+                    .extend(pending.subscribed_connections.drain());
+
+                self.clients.insert(parked_id, client);
+                self.adopted.insert(client_id, parked_id);
+
+                parked_id
+            }
+
+            Some((&parked_id, _)) => {
+                tracing::warn!(
+                    client_id,
+                    parked_id,
+                    "Client sent a handoff after subscribing, it cannot adopt subscriptions"
+                );
+
+                resolved_id
+            }
+
+            None => resolved_id,
+        };
+
+        self.clients
+            .get_mut(&client_id)
+            .expect("client not found")
+            .handoff = Some(handoff);
+    }
+
+    /// Ends the adopted handoff of the client with `client_id`, and replays the updates that were
+    /// held during the handoff.
+    ///
+    /// Subscriptions on the ports that the client did not subscribe to again are removed.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn finish_handoff(&mut self, client_id: ClientId) -> Result<(), AgentError> {
+        let handoff = self.handoffs.remove(&client_id).expect("handoff not found");
+        let unclaimed_ports = handoff.unclaimed_ports.clone();
+
+        for port in unclaimed_ports {
+            self.port_subscriptions.remove(client_id, port).await?;
+        }
+
+        let (dropped, replay) = handoff.finish();
+
+        for connection_id in dropped {
+            self.connections
+                .send(
+                    connection_id,
+                    ConnectionMessageIn::Unsubscribed { client_id },
+                )
+                .await;
+        }
+
+        for update in replay {
+            self.handle_connection_update(update).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles the end of the handoff deadline for the exited client with `client_id`.
+    ///
+    /// If no client adopted the subscriptions, they are removed, as if the client has just exited.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn handoff_expired(&mut self, client_id: ClientId) -> Result<(), AgentError> {
+        let adopted = self
+            .handoffs
+            .get(&client_id)
+            .expect("handoff not found")
+            .adopted;
+
+        if adopted {
+            return self.finish_handoff(client_id).await;
+        }
+
+        tracing::info!(client_id, "Steal subscriptions were not adopted in time");
+
+        let handoff = self.handoffs.remove(&client_id).expect("handoff not found");
+        self.remove_client(client_id, handoff.connections()).await
+    }
+
+    /// Resolves when the earliest handoff deadline passes, returning the [`ClientId`] of the
+    /// handoff.
+    async fn next_handoff_deadline(handoffs: &HashMap<ClientId, PendingHandoff>) -> ClientId {
+        match handoffs.iter().min_by_key(|(_, handoff)| handoff.deadline) {
+            Some((&client_id, handoff)) => {
+                time::sleep_until(handoff.deadline).await;
+                client_id
+            }
+            None => future::pending().await,
+        }
+    }
+
+    /// Handles [`Command::Drain`].
+    ///
+    /// Stops stealing new traffic on behalf of the client with `client_id`, and waits until it's
+    /// done with the requests and connections that were already stolen, see
+    /// [`Self::check_drain`].
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn drain(&mut self, client_id: ClientId, drain: StealDrain) -> Result<(), AgentError> {
+        if self.drains.contains_key(&client_id) {
+            return Ok(());
+        }
+
+        let ports = self.port_subscriptions.drain(client_id);
+        let deadline =
+            Instant::now() + Duration::from_millis(drain.timeout_ms).min(Self::MAX_DRAIN_TIMEOUT);
+        self.drains
+            .insert(client_id, PendingDrain { ports, deadline });
+
+        self.check_drain(client_id).await
+    }
+
+    /// Finishes the drain of the client with `client_id`, if there is one and the client has no
+    /// more in-flight requests or unfiltered connections.
+    async fn check_drain(&mut self, client_id: ClientId) -> Result<(), AgentError> {
+        let drained = self.drains.contains_key(&client_id)
+            && self.clients.get(&client_id).is_some_and(|client| {
+                client.in_flight_requests.is_empty() && client.tcp_connections.is_empty()
+            });
+
+        if drained {
+            self.finish_drain(client_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the redirections of the ports drained by the client with `client_id`, and notifies
+    /// the client with [`DaemonTcp::Drained`].
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn finish_drain(&mut self, client_id: ClientId) -> Result<(), AgentError> {
+        let drain = self.drains.remove(&client_id).expect("drain not found");
+        self.port_subscriptions.remove_drained(drain.ports).await?;
+
+        if let Some(client) = self.clients.get(&client_id) {
+            let _ = client.tx.send(DaemonTcp::Drained).await;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves when the earliest drain deadline passes, returning the [`ClientId`] of the
+    /// draining client.
+    async fn next_drain_deadline(drains: &HashMap<ClientId, PendingDrain>) -> ClientId {
+        match drains.iter().min_by_key(|(_, drain)| drain.deadline) {
+            Some((&client_id, drain)) => {
+                time::sleep_until(drain.deadline).await;
+                client_id
+            }
+            None => future::pending().await,
+        }
+    }
+
+    /// Handles a [`ConnectionMessageOut`] that could not be held during a handoff, as if the
+    /// client had exited.
+    async fn reject_connection_update(&mut self, update: ConnectionMessageOut) {
+        let client_id = update.client_id();
+        let connection_id = update.connection_id();
+
+        let message = match update {
+            ConnectionMessageOut::Request { id, .. } => ConnectionMessageIn::ResponseFailed {
+                client_id,
+                request_id: id,
+            },
+            _ => ConnectionMessageIn::Unsubscribed { client_id },
+        };
+
+        self.connections.send(connection_id, message).await;
+    }
+
+    /// Returns the [`ClientId`] that the client with `client_id` took over when adopting
+    /// subscriptions, or `client_id` if it did not adopt any.
+    fn resolve_client_id(&self, client_id: ClientId) -> ClientId {
+        self.adopted.get(&client_id).copied().unwrap_or(client_id)
+    }
+
     /// Converts the given [`HttpResponseFallback`] to a [`hyper::Response`] and sends it to
     /// [`StolenConnections`].
     #[tracing::instrument(level = "trace", skip(self))]
@@ -624,24 +1107,28 @@ impl TcpConnectionStealer {
     async fn handle_command(&mut self, command: StealerCommand) -> Result<(), AgentError> {
         let StealerCommand { client_id, command } = command;
 
+        if let Command::Handoff(handoff) = command {
+            self.handoff(client_id, handoff);
+            return Ok(());
+        }
+
+        let client_id = self.resolve_client_id(client_id);
+
         match command {
             Command::NewClient(daemon_tx, protocol_version) => {
-                self.clients.insert(
-                    client_id,
-                    Client {
-                        tx: daemon_tx,
-                        protocol_version,
-                        subscribed_connections: Default::default(),
-                    },
-                );
+                self.clients_closed
+                    .push(ChannelClosedFuture::new(daemon_tx.clone(), client_id));
+                self.clients
+                    .insert(client_id, Client::new(daemon_tx, protocol_version));
             }
 
             Command::ConnectionUnsubscribe(connection_id) => {
-                self.clients
-                    .get_mut(&client_id)
-                    .expect("client not found")
-                    .subscribed_connections
-                    .remove(&connection_id);
+                let client = self.clients.get_mut(&client_id).expect("client not found");
+                client.subscribed_connections.remove(&connection_id);
+                client.tcp_connections.remove(&connection_id);
+                client
+                    .in_flight_requests
+                    .retain(|(id, _)| *id != connection_id);
 
                 self.connections
                     .send(
@@ -649,6 +1136,8 @@ impl TcpConnectionStealer {
                         ConnectionMessageIn::Unsubscribed { client_id },
                     )
                     .await;
+
+                self.check_drain(client_id).await?;
             }
 
             Command::PortSubscribe(port_steal) => {
@@ -657,6 +1146,15 @@ impl TcpConnectionStealer {
 
             Command::PortUnsubscribe(port) => {
                 self.port_subscriptions.remove(client_id, port).await?;
+                self.reservations.unsubscribe(client_id, port);
+
+                if let Some(handoff) = self.handoffs.get_mut(&client_id)
+                    && handoff.adopted
+                    && handoff.unclaimed_ports.remove(&port)
+                    && handoff.unclaimed_ports.is_empty()
+                {
+                    self.finish_handoff(client_id).await?;
+                }
             }
 
             Command::ResponseData(TcpData {
@@ -675,13 +1173,34 @@ impl TcpConnectionStealer {
             }
 
             Command::HttpResponse(response) => {
+                if let Some(client) = self.clients.get_mut(&client_id) {
+                    client
+                        .in_flight_requests
+                        .remove(&(response.connection_id(), response.request_id()));
+                }
+
                 self.send_http_response(client_id, response).await;
+                self.check_drain(client_id).await?;
             }
 
             Command::SwitchProtocolVersion(new_version) => {
                 let client = self.clients.get_mut(&client_id).expect("client not found");
                 client.protocol_version = new_version;
             }
+
+            Command::Fallback(fallback) => self.connections.set_fallback(client_id, fallback),
+
+            Command::Drain(drain) => self.drain(client_id, drain).await?,
+
+            Command::ReservePort(PortReservation { port, .. }) => {
+                let res = self.reservations.reserve(client_id, port).await;
+                let client = self.clients.get(&client_id).expect("client not found");
+                let _ = client.tx.send(DaemonTcp::PortReserved(res)).await;
+            }
+
+            Command::ReleasePort(port) => self.reservations.release(client_id, port),
+
+            Command::Handoff(..) => unreachable!("handled above"),
         }
 
         Ok(())
@@ -695,7 +1214,7 @@ mod test {
     use bytes::Bytes;
     use futures::{future::BoxFuture, FutureExt};
     use http::{Method, Request, Response, Version};
-    use http_body_util::{Empty, StreamBody};
+    use http_body_util::{combinators::BoxBody, Empty, StreamBody};
     use hyper::{
         body::{Frame, Incoming},
         service::Service,
@@ -788,18 +1307,14 @@ mod test {
         );
 
         let (client_tx, mut client_rx) = mpsc::channel::<DaemonTcp>(4);
-        let client = Client {
-            tx: client_tx,
-            protocol_version: "1.7.0".parse().unwrap(),
-            subscribed_connections: Default::default(),
-        };
+        let client = Client::new(client_tx, "1.7.0".parse().unwrap());
 
         let (request, response_tx) = request_rx.recv().await.unwrap();
         client.send_request_async(MatchedHttpRequest {
             connection_id: 0,
             port: 80,
             request_id: 0,
-            request,
+            request: request.map(BoxBody::new),
         });
 
         // Verify that single-framed ChunkedRequest::Start requests are as expected, containing any
@@ -857,18 +1372,14 @@ mod test {
         );
 
         let (client_tx, mut client_rx) = mpsc::channel::<DaemonTcp>(4);
-        let client = Client {
-            tx: client_tx,
-            protocol_version: "1.7.0".parse().unwrap(),
-            subscribed_connections: Default::default(),
-        };
+        let client = Client::new(client_tx, "1.7.0".parse().unwrap());
 
         let (request, response_tx) = request_rx.recv().await.unwrap();
         client.send_request_async(MatchedHttpRequest {
             connection_id: 0,
             port: 80,
             request_id: 0,
-            request,
+            request: request.map(BoxBody::new),
         });
 
         // Verify that ChunkedRequest::Start request is as expected
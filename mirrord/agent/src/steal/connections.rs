@@ -1,19 +1,29 @@
 //! Home for [`StolenConnections`] - manager for connections that were stolen based on active port
 //! subscriptions.
 
-use std::{collections::HashMap, fmt, io, net::SocketAddr, time::Duration};
+use std::{collections::HashMap, fmt, io, net::SocketAddr, sync::Arc, time::Duration};
 
-use hyper::{body::Incoming, Request, Response};
-use mirrord_protocol::{tcp::NewTcpConnection, ConnectionId, Port, RequestId};
+use dashmap::DashMap;
+use hyper::{Request, Response};
+use mirrord_protocol::{
+    tcp::{NewTcpConnection, StealFallback},
+    ConnectionId, Port, RequestId,
+};
 use thiserror::Error;
 use tokio::{
+    io::AsyncWriteExt,
     net::TcpStream,
     sync::mpsc::{self, error::SendError, Receiver, Sender},
     task::JoinSet,
 };
 
-use self::{filtered::DynamicBody, unfiltered::UnfilteredStealTask};
-use super::{http::DefaultReversibleStream, subscriptions::PortSubscription};
+pub use self::filtered::DynamicBody;
+use self::unfiltered::UnfilteredStealTask;
+use super::{
+    database::{self, DatabaseStream},
+    http::DefaultReversibleStream,
+    subscriptions::PortSubscription,
+};
 use crate::{http::HttpVersion, steal::connections::filtered::FilteredStealTask, util::ClientId};
 
 mod filtered;
@@ -130,7 +140,7 @@ pub enum ConnectionMessageOut {
     Request {
         client_id: ClientId,
         connection_id: ConnectionId,
-        request: Request<Incoming>,
+        request: Request<DynamicBody>,
         id: RequestId,
         port: Port,
     },
@@ -150,8 +160,9 @@ pub enum ConnectionMessageOut {
     },
     /// Subscribed the client to a new filtered HTTP connection.
     ///
-    /// This variant **does not** translate to any [`DaemonTcp`](mirrord_protocol::tcp::DaemonTcp)
-    /// message. It is used only internally, to indicate that this client's
+    /// This variant translates to
+    /// [`DaemonTcp::NewHttpConnection`](mirrord_protocol::tcp::DaemonTcp::NewHttpConnection), only
+    /// for clients that support it. It indicates that this client's
     /// [`HttpFilter`](super::http::HttpFilter) matched a request for the first time in this
     /// connection ([`ConnectionMessageOut::Request`] should follow immediately).
     ///
@@ -164,7 +175,7 @@ pub enum ConnectionMessageOut {
     /// should follow.
     SubscribedHttp {
         client_id: ClientId,
-        connection_id: ConnectionId,
+        connection: NewTcpConnection,
     },
     /// The connection was closed for the given client.
     ///
@@ -216,11 +227,12 @@ impl fmt::Debug for ConnectionMessageOut {
             }
             Self::SubscribedHttp {
                 client_id,
-                connection_id,
+                connection,
             } => {
                 debug_struct.field("type", &"SubscribedHttp");
-                debug_struct.field("connection_id", connection_id);
+                debug_struct.field("connection_id", &connection.connection_id);
                 debug_struct.field("client_id", client_id);
+                debug_struct.field("connection", connection);
             }
             Self::Closed {
                 client_id,
@@ -236,6 +248,30 @@ impl fmt::Debug for ConnectionMessageOut {
     }
 }
 
+impl ConnectionMessageOut {
+    /// Returns [`ClientId`] of the client targeted by this message.
+    pub fn client_id(&self) -> ClientId {
+        match self {
+            Self::Raw { client_id, .. } => *client_id,
+            Self::Request { client_id, .. } => *client_id,
+            Self::SubscribedTcp { client_id, .. } => *client_id,
+            Self::SubscribedHttp { client_id, .. } => *client_id,
+            Self::Closed { client_id, .. } => *client_id,
+        }
+    }
+
+    /// Returns [`ConnectionId`] of the connection that produced this message.
+    pub fn connection_id(&self) -> ConnectionId {
+        match self {
+            Self::Raw { connection_id, .. } => *connection_id,
+            Self::Request { connection_id, .. } => *connection_id,
+            Self::SubscribedTcp { connection, .. } => connection.connection_id,
+            Self::SubscribedHttp { connection, .. } => connection.connection_id,
+            Self::Closed { connection_id, .. } => *connection_id,
+        }
+    }
+}
+
 /// A set of [`StolenConnection`]s, each managed in its own [`tokio::task`].
 ///
 /// Connection stealing logic is implemented in
@@ -260,6 +296,9 @@ pub struct StolenConnections {
     ///
     /// Allows for polling updates from all spawned tasks in [`Self::wait`].
     main_rx: Receiver<ConnectionMessageOut>,
+
+    /// Stealer client to [`StealFallback`] mapping, shared with all [`FilteredStealTask`]s.
+    fallbacks: Arc<DashMap<ClientId, StealFallback>>,
 }
 
 impl StolenConnections {
@@ -282,9 +321,26 @@ impl StolenConnections {
 
             main_tx,
             main_rx,
+
+            fallbacks: Default::default(),
         }
     }
 
+    /// Sets what happens with the HTTP requests that the client with the given [`ClientId`] fails
+    /// to respond to, in all filtered connections.
+    pub fn set_fallback(&self, client_id: ClientId, fallback: StealFallback) {
+        if fallback == StealFallback::default() {
+            self.fallbacks.remove(&client_id);
+        } else {
+            self.fallbacks.insert(client_id, fallback);
+        }
+    }
+
+    /// Removes the [`StealFallback`] of the client with the given [`ClientId`].
+    pub fn remove_fallback(&self, client_id: ClientId) {
+        self.fallbacks.remove(&client_id);
+    }
+
     /// Adds the given [`StolenConnection`] to this set. Spawns a new [`tokio::task`] that will
     /// manage it.
     #[tracing::instrument(level = "trace", name = "manage_stolen_connection", skip(self))]
@@ -294,6 +350,7 @@ impl StolenConnections {
 
         let (task_tx, task_rx) = mpsc::channel(Self::TASK_IN_CHANNEL_CAPACITY);
         let main_tx = self.main_tx.clone();
+        let fallbacks = self.fallbacks.clone();
 
         tracing::trace!(connection_id, "Spawning connection task");
         self.tasks.spawn(async move {
@@ -302,6 +359,7 @@ impl StolenConnections {
                 connection,
                 tx: main_tx,
                 rx: task_rx,
+                fallbacks,
             };
 
             match task.run().await {
@@ -389,7 +447,10 @@ impl fmt::Debug for StolenConnection {
             .field("destination", &self.destination)
             .field(
                 "filtered",
-                &matches!(self.port_subscription, PortSubscription::Filtered(..)),
+                &matches!(
+                    self.port_subscription,
+                    PortSubscription::Filtered(..) | PortSubscription::Database(..)
+                ),
             )
             .finish()
     }
@@ -408,6 +469,8 @@ enum ConnectionTaskError {
     IoError(#[from] io::Error),
     #[error("tokio task spawned for polling an HTTP connection panicked")]
     HttpConnectionTaskPanicked,
+    #[error("timed out while reading the startup message of a database connection")]
+    DatabaseDetectionTimeout,
 }
 
 impl<T> From<SendError<T>> for ConnectionTaskError {
@@ -428,6 +491,8 @@ struct ConnectionTask {
     /// Sending end of the channel shared between all [`ConnectionTask`]s and [`StolenConnections`]
     /// set.
     tx: Sender<ConnectionMessageOut>,
+    /// Shared [`StolenConnections::fallbacks`], used by [`FilteredStealTask`].
+    fallbacks: Arc<DashMap<ClientId, StealFallback>>,
 }
 
 impl ConnectionTask {
@@ -436,6 +501,10 @@ impl ConnectionTask {
     /// [`PortSubscription::Filtered`] is in use.
     const HTTP_DETECTION_TIMEOUT: Duration = Duration::from_secs(10);
 
+    /// Timeout for reading the startup message of the owned [`StolenConnection::stream`] when
+    /// [`PortSubscription::Database`] is in use.
+    const DATABASE_DETECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
     /// Runs this task until the connection is closed.
     ///
     /// # Note
@@ -443,18 +512,20 @@ impl ConnectionTask {
     /// This task is responsible for **always** sending [`ConnectionMessageOut::Closed`] to
     /// interested stealer clients, even when an error has occurred.
     async fn run(mut self) -> Result<(), ConnectionTaskError> {
+        let connection = NewTcpConnection {
+            connection_id: self.connection_id,
+            remote_address: self.connection.source.ip(),
+            destination_port: self.connection.destination.port(),
+            source_port: self.connection.source.port(),
+            local_address: self.connection.stream.local_addr()?.ip(),
+        };
+
         match self.connection.port_subscription {
             PortSubscription::Unfiltered(client_id) => {
                 self.tx
                     .send(ConnectionMessageOut::SubscribedTcp {
                         client_id,
-                        connection: NewTcpConnection {
-                            connection_id: self.connection_id,
-                            remote_address: self.connection.source.ip(),
-                            destination_port: self.connection.destination.port(),
-                            source_port: self.connection.source.port(),
-                            local_address: self.connection.stream.local_addr()?.ip(),
-                        },
+                        connection,
                     })
                     .await?;
 
@@ -488,8 +559,9 @@ impl ConnectionTask {
                 tracing::trace!(?http_version, "Detected HTTP version");
 
                 let task = FilteredStealTask::new(
-                    self.connection_id,
+                    connection,
                     filters,
+                    self.fallbacks,
                     self.connection.destination,
                     http_version,
                     stream,
@@ -497,6 +569,61 @@ impl ConnectionTask {
 
                 task.run(self.tx.clone(), &mut self.rx).await
             }
+
+            PortSubscription::Database(filters) => {
+                let mut stream = self.connection.stream;
+
+                // All filters on the port are for the same protocol.
+                let protocol = filters.iter().next().map(|entry| entry.protocol);
+                let handshake = match protocol {
+                    Some(protocol) => tokio::time::timeout(
+                        Self::DATABASE_DETECTION_TIMEOUT,
+                        database::handshake(protocol, &mut stream, self.connection.destination),
+                    )
+                    .await
+                    .map_err(|_| ConnectionTaskError::DatabaseDetectionTimeout)??,
+                    // Drained subscription.
+                    None => Default::default(),
+                };
+
+                let client_id = handshake.login.as_ref().and_then(|login| {
+                    filters
+                        .iter()
+                        .find(|entry| entry.value().matches(login))
+                        .map(|entry| *entry.key())
+                });
+
+                let Some(client_id) = client_id else {
+                    tracing::trace!(
+                        login = ?handshake.login,
+                        "No database filter matched, proxying the connection transparently"
+                    );
+
+                    let mut outgoing_io = match handshake.server {
+                        Some(server) => server,
+                        None => TcpStream::connect(self.connection.destination).await?,
+                    };
+                    outgoing_io.write_all(&handshake.pending).await?;
+                    tokio::io::copy_bidirectional(&mut stream, &mut outgoing_io).await?;
+
+                    return Ok(());
+                };
+
+                self.tx
+                    .send(ConnectionMessageOut::SubscribedTcp {
+                        client_id,
+                        connection,
+                    })
+                    .await?;
+
+                let task = UnfilteredStealTask {
+                    connection_id: self.connection_id,
+                    client_id,
+                    stream: DatabaseStream::new(stream, handshake.replay, handshake.skip_response),
+                };
+
+                task.run(self.tx, &mut self.rx).await
+            }
         }
     }
 }
@@ -1,13 +1,14 @@
 use std::{
-    collections::HashMap, future::Future, marker::PhantomData, net::SocketAddr, pin::Pin, sync::Arc,
+    collections::HashMap, future::Future, marker::PhantomData, net::SocketAddr, pin::Pin,
+    sync::Arc, time::Duration,
 };
 
 use bytes::Bytes;
 use dashmap::DashMap;
-use http::Version;
-use http_body_util::{combinators::BoxBody, BodyExt};
+use http::{HeaderMap, Method, Uri, Version};
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
 use hyper::{
-    body::Incoming,
+    body::{Body, Incoming},
     client::conn::{http1, http2},
     http::{Request, StatusCode},
     service::Service,
@@ -15,7 +16,10 @@ use hyper::{
     Response,
 };
 use hyper_util::rt::{TokioExecutor, TokioIo};
-use mirrord_protocol::{ConnectionId, RequestId};
+use mirrord_protocol::{
+    tcp::{NewTcpConnection, StealFallback, StealFallbackPolicy},
+    ConnectionId, RequestId,
+};
 use tokio::{
     io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
@@ -24,6 +28,7 @@ use tokio::{
         oneshot,
     },
     task::{self, JoinHandle},
+    time::{self, Instant},
 };
 use tokio_util::sync::{CancellationToken, DropGuard};
 use tracing::Level;
@@ -31,17 +36,73 @@ use tracing::Level;
 use super::{ConnectionMessageIn, ConnectionMessageOut, ConnectionTaskError};
 use crate::{
     http::HttpVersion,
-    steal::{connections::unfiltered::UnfilteredStealTask, http::HttpFilter},
+    steal::{
+        connections::unfiltered::UnfilteredStealTask,
+        http::{HttpFilter, TimeoutBody, TimeoutBodyError},
+    },
     util::ClientId,
 };
 
 /// [`Body`](hyper::body::Body) type used in [`FilteredStealTask`].
 pub type DynamicBody = BoxBody<Bytes, hyper::Error>;
 
+/// [`Body`](hyper::body::Body) type of the [`Response`]s sent by the [`FilteringService`].
+///
+/// The bodies of the stealer clients' responses are wrapped in a [`TimeoutBody`].
+type ResponseBody = BoxBody<Bytes, TimeoutBodyError>;
+
 /// Incoming [`Request`] extracted from the HTTP connection in the [`FilteringService`].
 struct ExtractedRequest {
-    request: Request<Incoming>,
+    request: Request<DynamicBody>,
+    /// Copy of the request, present only if its body was read by the [`FilteringService`].
+    buffered: Option<BufferedRequest>,
+    response_tx: oneshot::Sender<RequestHandling>,
+}
+
+/// Copy of a [`Request`] with a small body, kept in memory so that the request can be sent again
+/// when the stealer client fails to respond to it (see [`StealFallbackPolicy`]).
+#[derive(Clone)]
+struct BufferedRequest {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl BufferedRequest {
+    /// Bodies larger than this are not read by the [`FilteringService`], so their requests can't
+    /// fall back.
+    const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+    /// Creates a new [`Request`] from this copy.
+    fn to_request(&self) -> Request<DynamicBody> {
+        let mut request = Request::new(Self::body(self.body.clone()));
+        *request.method_mut() = self.method.clone();
+        *request.uri_mut() = self.uri.clone();
+        *request.version_mut() = self.version;
+        *request.headers_mut() = self.headers.clone();
+
+        request
+    }
+
+    fn body(bytes: Bytes) -> DynamicBody {
+        BoxBody::new(Full::new(bytes).map_err(|_| unreachable!()))
+    }
+}
+
+/// Request blocked on a stealer client's response.
+struct BlockedRequest {
     response_tx: oneshot::Sender<RequestHandling>,
+    /// Copy of the request, see [`ExtractedRequest::buffered`].
+    buffered: Option<BufferedRequest>,
+    /// How many times the request was sent to the stealer client.
+    attempts: usize,
+    /// When we stop waiting for the stealer client's response, see
+    /// [`StealFallback::response_timeout_ms`] and [`StealFallback::total_timeout_ms`].
+    deadline: Option<Instant>,
+    /// When the request was received, [`StealFallback::total_timeout_ms`] counts from here.
+    received_at: Instant,
 }
 
 /// Response instruction for [`FilteringService`].
@@ -50,12 +111,12 @@ enum RequestHandling {
     /// The [`Request`] should be handled by the HTTP server running at the given address.
     LetThrough {
         to: SocketAddr,
-        unchanged: Request<Incoming>,
+        unchanged: Request<DynamicBody>,
     },
     /// The [`FilteringService`] should respond immediately with the given [`Response`]
     /// on behalf of the given stealer client.
     RespondWith {
-        response: Response<DynamicBody>,
+        response: Response<ResponseBody>,
         for_client: ClientId,
     },
 }
@@ -95,12 +156,17 @@ struct FilteringService {
     /// possible). However, using a [`oneshot`] here would require a combination of an [`Arc`],
     /// a [`Mutex`](std::sync::Mutex) and an [`Option`]. [`mpsc`] is used here for simplicity.
     upgrade_tx: Sender<UpgradedConnection>,
+
+    /// Stealer client to [`StealFallback`] mapping, see [`FilteredStealTask::fallbacks`].
+    ///
+    /// Request bodies are read into memory only when some client can fall back.
+    fallbacks: Arc<DashMap<ClientId, StealFallback>>,
 }
 
 impl FilteringService {
     /// Produces a new [`StatusCode::BAD_GATEWAY`] [`Response`] with the given [`Version`] and the
     /// given `error` in body.
-    fn bad_gateway(version: Version, error: &str) -> Response<DynamicBody> {
+    fn bad_gateway(version: Version, error: &str) -> Response<ResponseBody> {
         let body = format!("mirrord: {error}");
 
         Response::builder()
@@ -118,7 +184,7 @@ impl FilteringService {
     /// Also, it does not retry the request upon failure.
     async fn send_request(
         to: SocketAddr,
-        mut request: Request<Incoming>,
+        mut request: Request<DynamicBody>,
     ) -> Result<Response<Incoming>, Box<dyn std::error::Error>> {
         let tcp_stream = TcpStream::connect(to).await.inspect_err(|error| {
             tracing::error!(?error, address = %to, "Failed connecting to request destination");
@@ -191,14 +257,14 @@ impl FilteringService {
     )]
     async fn let_through(
         &self,
-        request: Request<Incoming>,
+        request: Request<DynamicBody>,
         on_upgrade: OnUpgrade,
         to: SocketAddr,
-    ) -> Response<DynamicBody> {
+    ) -> Response<ResponseBody> {
         let version = request.version();
         let mut response = Self::send_request(to, request)
             .await
-            .map(|response| response.map(BoxBody::new))
+            .map(|response| response.map(|body| BoxBody::new(body.map_err(Into::into))))
             .unwrap_or_else(|_| {
                 Self::bad_gateway(
                     version,
@@ -240,7 +306,7 @@ impl FilteringService {
     /// and the given `on_upgrade` comes from the original [`Request`].
     async fn check_protocol_switch(
         &self,
-        response: &Response<DynamicBody>,
+        response: &Response<ResponseBody>,
         on_upgrade: OnUpgrade,
         from_client: ClientId,
     ) {
@@ -271,19 +337,64 @@ impl FilteringService {
         }
     }
 
+    /// Reads the whole body of the given [`Request`] into memory, if some client can fall back
+    /// and the body is not larger than [`BufferedRequest::MAX_BODY_SIZE`].
+    async fn buffer_request(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<(Request<DynamicBody>, Option<BufferedRequest>), hyper::Error> {
+        let can_fall_back = self
+            .fallbacks
+            .iter()
+            .any(|entry| entry.value().policy != StealFallbackPolicy::Error);
+        let small_body = request
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|size| size <= BufferedRequest::MAX_BODY_SIZE);
+
+        if !can_fall_back || !small_body {
+            return Ok((request.map(BoxBody::new), None));
+        }
+
+        let (parts, body) = request.into_parts();
+        let body = body.collect().await?.to_bytes();
+
+        let buffered = BufferedRequest {
+            method: parts.method.clone(),
+            uri: parts.uri.clone(),
+            version: parts.version,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        };
+
+        Ok((
+            Request::from_parts(parts, BufferedRequest::body(body)),
+            Some(buffered),
+        ))
+    }
+
     /// Extracts [`OnUpgrade`] from the given [`Request`] and sends it to [`FilteredStealTask`].
     /// Waits on a dynamically created [`oneshot::channel`] for [`RequestHandling`] instruction.
     async fn handle_request(
         &self,
         mut request: Request<Incoming>,
-    ) -> Result<Response<DynamicBody>, ConnectionTaskError> {
+    ) -> Result<Response<ResponseBody>, ConnectionTaskError> {
         let version = request.version();
         let on_upgrade = hyper::upgrade::on(&mut request);
 
+        let Ok((request, buffered)) = self.buffer_request(request).await else {
+            return Ok(Self::bad_gateway(
+                version,
+                "failed to read the body of the request",
+            ));
+        };
+
         let (response_tx, response_rx) = oneshot::channel();
         self.requests_tx
             .send(ExtractedRequest {
                 request,
+                buffered,
                 response_tx,
             })
             .await?;
@@ -311,7 +422,7 @@ impl FilteringService {
 }
 
 impl Service<Request<Incoming>> for FilteringService {
-    type Response = Response<BoxBody<Bytes, hyper::Error>>;
+    type Response = Response<ResponseBody>;
 
     type Error = ConnectionTaskError;
 
@@ -330,6 +441,9 @@ impl Service<Request<Incoming>> for FilteringService {
 /// stream into a series requests and provide responses.
 pub struct FilteredStealTask<T> {
     connection_id: ConnectionId,
+    /// Addresses of the stolen connection, sent to the clients in
+    /// [`ConnectionMessageOut::SubscribedHttp`].
+    connection: NewTcpConnection,
     /// Original destination of the stolen connection. Used when passing through HTTP requests that
     /// don't not match any filter in [`Self::filters`].
     original_destination: SocketAddr,
@@ -343,6 +457,13 @@ pub struct FilteredStealTask<T> {
     /// This allows for *injecting* new stealer clients into exisiting connections.
     filters: Arc<DashMap<ClientId, HttpFilter>>,
 
+    /// Stealer client to [`StealFallback`] mapping. Tells what to do with the requests that
+    /// stealer clients fail to respond to.
+    ///
+    /// Shared via [`Arc`] like [`Self::filters`]. Clients missing from this mapping use the
+    /// default [`StealFallback`].
+    fallbacks: Arc<DashMap<ClientId, StealFallback>>,
+
     /// Stealer client to subscription state mapping.
     /// 1. `true` -> client is subscribed
     /// 2. `false` -> client has unsubscribed or we sent [`ConnectionMessageOut::Closed`].
@@ -361,7 +482,7 @@ pub struct FilteredStealTask<T> {
     hyper_conn_task: Option<(JoinHandle<Option<UpgradedConnection>>, DropGuard)>,
 
     /// Requests blocked on stealer clients' responses.
-    blocked_requests: HashMap<(ClientId, RequestId), oneshot::Sender<RequestHandling>>,
+    blocked_requests: HashMap<(ClientId, RequestId), BlockedRequest>,
 
     /// Id of the next HTTP request that will be intercepted.
     next_request_id: RequestId,
@@ -377,6 +498,10 @@ where
     /// Limits the number requests served concurrently by [`FilteringService`].
     const MAX_CONCURRENT_REQUESTS: usize = 128;
 
+    /// How many times a request is sent to the stealer client again, when the client uses
+    /// [`StealFallbackPolicy::RetryLocal`].
+    const MAX_LOCAL_RETRIES: usize = 2;
+
     /// Creates a new instance of this task. The task will manage the connection given as `io` and
     /// use the provided `filters` for matching incoming [`Request`]s with stealing clients.
     ///
@@ -384,11 +509,12 @@ where
     #[tracing::instrument(
         level = "trace",
         name = "create_new_filtered_steal_task",
-        skip(filters, io)
+        skip(filters, fallbacks, io)
     )]
     pub fn new(
-        connection_id: ConnectionId,
+        connection: NewTcpConnection,
         filters: Arc<DashMap<ClientId, HttpFilter>>,
+        fallbacks: Arc<DashMap<ClientId, StealFallback>>,
         original_destination: SocketAddr,
         http_version: HttpVersion,
         io: T,
@@ -399,6 +525,7 @@ where
         let service = FilteringService {
             requests_tx,
             upgrade_tx,
+            fallbacks: fallbacks.clone(),
         };
 
         let cancellation_token = CancellationToken::new();
@@ -444,9 +571,11 @@ where
         };
 
         Self {
-            connection_id,
+            connection_id: connection.connection_id,
+            connection,
             original_destination,
             filters,
+            fallbacks,
             subscribed: Default::default(),
             requests_rx,
             hyper_conn_task: Some((task_handle, drop_guard)),
@@ -496,7 +625,7 @@ where
         request_id: RequestId,
         response: Response<DynamicBody>,
     ) {
-        let Some(tx) = self.blocked_requests.remove(&(client_id, request_id)) else {
+        let Some(blocked) = self.blocked_requests.remove(&(client_id, request_id)) else {
             tracing::warn!(
                 client_id,
                 request_id,
@@ -508,7 +637,23 @@ where
             return;
         };
 
-        if tx
+        let (idle_timeout, total_deadline) = self
+            .fallbacks
+            .get(&client_id)
+            .map(|fallback| {
+                (
+                    fallback.idle_timeout_ms.map(Duration::from_millis),
+                    fallback
+                        .total_timeout_ms
+                        .map(|timeout| blocked.received_at + Duration::from_millis(timeout)),
+                )
+            })
+            .unwrap_or_default();
+        let response =
+            response.map(|body| BoxBody::new(TimeoutBody::new(body, idle_timeout, total_deadline)));
+
+        if blocked
+            .response_tx
             .send(RequestHandling::RespondWith {
                 response,
                 for_client: client_id,
@@ -524,34 +669,175 @@ where
         }
     }
 
-    /// Notifies the [`FilteringService`] that the client failed to provide a [`Response`] for the
-    /// request with the given id. The [`FilteringService`] is notified by dropping a
-    /// [`oneshot::Sender`] from [`Self::blocked_requests`].
+    /// Handles the client's failure to provide a [`Response`] for the request with the given id,
+    /// see [`Self::fall_back`].
     ///
     /// If there is no blocked request for the given ([`ClientId`], [`RequestId`]) combination or
     /// the HTTP connection is dead, does nothing.
     #[tracing::instrument(
         level = Level::TRACE,
         name = "handle_filtered_request_response_failure",
-        skip(self),
+        skip(self, tx),
         fields(
             connection_id = self.connection_id,
             original_destination = %self.original_destination,
         )
     )]
-    fn handle_response_failure(&mut self, client_id: ClientId, request_id: RequestId) {
-        let removed = self
-            .blocked_requests
-            .remove(&(client_id, request_id))
-            .is_some();
-        if !removed {
+    async fn handle_response_failure(
+        &mut self,
+        client_id: ClientId,
+        request_id: RequestId,
+        tx: &Sender<ConnectionMessageOut>,
+    ) -> Result<(), ConnectionTaskError> {
+        let Some(blocked) = self.blocked_requests.remove(&(client_id, request_id)) else {
             tracing::warn!(
                 client_id,
                 request_id,
                 connection_id = self.connection_id,
                 "Received a response failure for an unexpected (client_id, request_id) combination",
             );
+
+            return Ok(());
+        };
+
+        self.fall_back(client_id, blocked, tx).await
+    }
+
+    /// Handles the requests of the given client that are no longer blocked on its response, as
+    /// the client unsubscribed.
+    async fn handle_unsubscribed(
+        &mut self,
+        client_id: ClientId,
+        tx: &Sender<ConnectionMessageOut>,
+    ) -> Result<(), ConnectionTaskError> {
+        self.subscribed.insert(client_id, false);
+
+        let request_ids = self
+            .blocked_requests
+            .keys()
+            .filter(|(id, _)| *id == client_id)
+            .map(|(_, request_id)| *request_id)
+            .collect::<Vec<_>>();
+
+        for request_id in request_ids {
+            if let Some(blocked) = self.blocked_requests.remove(&(client_id, request_id)) {
+                self.fall_back(client_id, blocked, tx).await?;
+            }
         }
+
+        Ok(())
+    }
+
+    /// Handles the blocked requests that reached their [`BlockedRequest::deadline`], see
+    /// [`Self::fall_back`].
+    async fn handle_timeouts(
+        &mut self,
+        tx: &Sender<ConnectionMessageOut>,
+    ) -> Result<(), ConnectionTaskError> {
+        let now = Instant::now();
+        let expired = self
+            .blocked_requests
+            .iter()
+            .filter(|(_, blocked)| blocked.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for (client_id, request_id) in expired {
+            tracing::debug!(
+                client_id,
+                request_id,
+                connection_id = self.connection_id,
+                "Stealer client did not respond to the request in time",
+            );
+
+            if let Some(blocked) = self.blocked_requests.remove(&(client_id, request_id)) {
+                self.fall_back(client_id, blocked, tx).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the client's [`StealFallbackPolicy`] to a request that it failed to respond to.
+    ///
+    /// Requests that were not buffered by the [`FilteringService`] can't fall back. The
+    /// [`FilteringService`] is notified about the failure by dropping the [`oneshot::Sender`]
+    /// from the [`BlockedRequest`], and responds with [`StatusCode::BAD_GATEWAY`].
+    async fn fall_back(
+        &mut self,
+        client_id: ClientId,
+        blocked: BlockedRequest,
+        tx: &Sender<ConnectionMessageOut>,
+    ) -> Result<(), ConnectionTaskError> {
+        let policy = self
+            .fallbacks
+            .get(&client_id)
+            .map(|fallback| fallback.policy)
+            .unwrap_or_default();
+        let Some(buffered) = blocked.buffered.as_ref() else {
+            return Ok(());
+        };
+
+        match policy {
+            StealFallbackPolicy::Error => {}
+
+            StealFallbackPolicy::PassthroughToRemote => {
+                let _ = blocked.response_tx.send(RequestHandling::LetThrough {
+                    to: self.original_destination,
+                    unchanged: buffered.to_request(),
+                });
+            }
+
+            StealFallbackPolicy::RetryLocal => {
+                let can_retry = blocked.attempts <= Self::MAX_LOCAL_RETRIES
+                    && self.subscribed.get(&client_id).copied().unwrap_or_default()
+                    && self.filters.contains_key(&client_id);
+
+                if can_retry {
+                    let request = buffered.to_request();
+                    self.send_to_client(client_id, request, blocked, tx).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the given [`Request`] to the stealer client and blocks it in
+    /// [`Self::blocked_requests`] until the client responds.
+    async fn send_to_client(
+        &mut self,
+        client_id: ClientId,
+        request: Request<DynamicBody>,
+        mut blocked: BlockedRequest,
+        tx: &Sender<ConnectionMessageOut>,
+    ) -> Result<(), ConnectionTaskError> {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+
+        tx.send(ConnectionMessageOut::Request {
+            client_id,
+            connection_id: self.connection_id,
+            request,
+            id,
+            port: self.original_destination.port(),
+        })
+        .await?;
+
+        blocked.attempts += 1;
+        blocked.deadline = self.fallbacks.get(&client_id).and_then(|fallback| {
+            let response_deadline = fallback
+                .response_timeout_ms
+                .map(|timeout| Instant::now() + Duration::from_millis(timeout));
+            let total_deadline = fallback
+                .total_timeout_ms
+                .map(|timeout| blocked.received_at + Duration::from_millis(timeout));
+
+            response_deadline.into_iter().chain(total_deadline).min()
+        });
+        self.blocked_requests.insert((client_id, id), blocked);
+
+        Ok(())
     }
 
     /// Handles a [`Request`] intercepted by the [`FilteringService`].
@@ -579,27 +865,21 @@ where
             // First time this client will receive a request from this connection.
             tx.send(ConnectionMessageOut::SubscribedHttp {
                 client_id,
-                connection_id: self.connection_id,
+                connection: self.connection.clone(),
             })
             .await?;
         }
 
-        let id = self.next_request_id;
-        self.next_request_id += 1;
-
-        tx.send(ConnectionMessageOut::Request {
-            client_id,
-            connection_id: self.connection_id,
-            request: request.request,
-            id,
-            port: self.original_destination.port(),
-        })
-        .await?;
-
-        self.blocked_requests
-            .insert((client_id, id), request.response_tx);
+        let blocked = BlockedRequest {
+            response_tx: request.response_tx,
+            buffered: request.buffered,
+            attempts: 0,
+            deadline: None,
+            received_at: Instant::now(),
+        };
 
-        Ok(())
+        self.send_to_client(client_id, request.request, blocked, tx)
+            .await
     }
 
     /// Runs this task until the HTTP connection is closed or upgraded.
@@ -620,6 +900,12 @@ where
         let mut queued_raw_data: HashMap<ClientId, Vec<Vec<u8>>> = Default::default();
 
         loop {
+            let next_deadline = self
+                .blocked_requests
+                .values()
+                .filter_map(|blocked| blocked.deadline)
+                .min();
+
             tokio::select! {
                 message = rx.recv() => match message.ok_or(ConnectionTaskError::RecvError)? {
                     ConnectionMessageIn::Raw { data, client_id } => {
@@ -632,15 +918,18 @@ where
                     },
                     ConnectionMessageIn::ResponseFailed { request_id, client_id } => {
                         queued_raw_data.remove(&client_id);
-                        self.handle_response_failure(client_id, request_id);
+                        self.handle_response_failure(client_id, request_id, &tx).await?;
                     },
                     ConnectionMessageIn::Unsubscribed { client_id } => {
                         queued_raw_data.remove(&client_id);
-                        self.subscribed.insert(client_id, false);
-                        self.blocked_requests.retain(|key, _| key.0 != client_id);
+                        self.handle_unsubscribed(client_id, &tx).await?;
                     },
                 },
 
+                _ = time::sleep_until(next_deadline.unwrap_or_else(Instant::now)), if next_deadline.is_some() => {
+                    self.handle_timeouts(&tx).await?;
+                },
+
                 request = self.requests_rx.recv() => match request {
                     Some(request) => self.handle_request(request, &tx).await?,
 
@@ -815,9 +1104,10 @@ mod test {
         header::{CONNECTION, UPGRADE},
         HeaderValue, Method,
     };
-    use http_body_util::Empty;
-    use hyper::{client::conn::http1::SendRequest, service::service_fn};
+    use http_body_util::{Empty, StreamBody};
+    use hyper::{body::Frame, client::conn::http1::SendRequest, service::service_fn};
     use tokio::{io::AsyncReadExt, net::TcpListener, task::JoinSet};
+    use tokio_stream::wrappers::ReceiverStream;
 
     use super::*;
 
@@ -825,6 +1115,8 @@ mod test {
     struct TestSetup {
         /// [`HttpFilter`]s mapping used by the task.
         filters: Arc<DashMap<ClientId, HttpFilter>>,
+        /// [`StealFallback`]s mapping used by the task.
+        fallbacks: Arc<DashMap<ClientId, StealFallback>>,
         /// Address of the original HTTP server (the one we steal from).
         original_address: SocketAddr,
         /// Stolen connection wrapped into HTTP.
@@ -921,15 +1213,15 @@ mod test {
             let original_server_token = CancellationToken::new();
             let token_clone = original_server_token.clone();
 
-            let (server_stream, client_stream) = {
+            let (server_stream, peer_address, client_stream) = {
                 let stealing_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-                let ((server_stream, _), client_stream) = tokio::try_join!(
+                let ((server_stream, peer_address), client_stream) = tokio::try_join!(
                     stealing_listener.accept(),
                     TcpStream::connect(stealing_listener.local_addr().unwrap()),
                 )
                 .unwrap();
 
-                (server_stream, client_stream)
+                (server_stream, peer_address, client_stream)
             };
 
             tasks.spawn(async move {
@@ -960,14 +1252,23 @@ mod test {
 
             let filters: Arc<DashMap<ClientId, HttpFilter>> = Default::default();
             let filters_clone = filters.clone();
+            let fallbacks: Arc<DashMap<ClientId, StealFallback>> = Default::default();
+            let fallbacks_clone = fallbacks.clone();
 
             let (in_tx, mut in_rx) = mpsc::channel(8);
             let (out_tx, out_rx) = mpsc::channel(8);
 
             tasks.spawn(async move {
                 let task = FilteredStealTask::new(
-                    Self::CONNECTION_ID,
+                    NewTcpConnection {
+                        connection_id: Self::CONNECTION_ID,
+                        remote_address: peer_address.ip(),
+                        destination_port: original_address.port(),
+                        source_port: peer_address.port(),
+                        local_address: original_address.ip(),
+                    },
                     filters_clone,
+                    fallbacks_clone,
                     original_address,
                     HttpVersion::V1,
                     server_stream,
@@ -988,6 +1289,7 @@ mod test {
 
             TestSetup {
                 filters,
+                fallbacks,
                 original_address,
                 request_sender,
                 task_in_tx: in_tx,
@@ -1076,7 +1378,11 @@ mod test {
                     match setup.task_out_rx.recv().await.unwrap() {
                         ConnectionMessageOut::SubscribedHttp {
                             client_id: received_client_id,
-                            connection_id: TestSetup::CONNECTION_ID,
+                            connection:
+                                NewTcpConnection {
+                                    connection_id: TestSetup::CONNECTION_ID,
+                                    ..
+                                },
                         } => {
                             assert_eq!(received_client_id, client_id);
                         }
@@ -1140,6 +1446,110 @@ mod test {
         assert!(rx.recv().await.is_none());
     }
 
+    /// Stolen connection receives a request that matches some client's filter, and the client
+    /// fails to respond to it.
+    /// The request is sent to the client again, and then to the original destination, according
+    /// to the client's [`StealFallbackPolicy`].
+    #[tokio::test]
+    async fn response_failure_fallback() {
+        for policy in [
+            StealFallbackPolicy::PassthroughToRemote,
+            StealFallbackPolicy::RetryLocal,
+        ] {
+            let mut setup = TestSetup::new().await;
+            setup.fallbacks.insert(
+                0,
+                StealFallback {
+                    policy,
+                    ..Default::default()
+                },
+            );
+
+            let request = setup.prepare_request(Some(0), false);
+            tokio::join!(
+                async {
+                    let response = setup.request_sender.send_request(request).await.unwrap();
+                    let expected = match policy {
+                        StealFallbackPolicy::RetryLocal => StatusCode::OK,
+                        _ => StatusCode::BAD_REQUEST,
+                    };
+                    assert_eq!(response.status(), expected);
+                },
+                async {
+                    match setup.task_out_rx.recv().await.unwrap() {
+                        ConnectionMessageOut::SubscribedHttp {
+                            client_id: 0,
+                            connection:
+                                NewTcpConnection {
+                                    connection_id: TestSetup::CONNECTION_ID,
+                                    ..
+                                },
+                        } => {}
+                        other => unreachable!("unexpected message: {other:?}"),
+                    };
+
+                    let request_id = match setup.task_out_rx.recv().await.unwrap() {
+                        ConnectionMessageOut::Request {
+                            client_id: 0,
+                            connection_id: TestSetup::CONNECTION_ID,
+                            id,
+                            ..
+                        } => id,
+                        other => unreachable!("unexpected message: {other:?}"),
+                    };
+
+                    setup
+                        .task_in_tx
+                        .send(ConnectionMessageIn::ResponseFailed {
+                            client_id: 0,
+                            request_id,
+                        })
+                        .await
+                        .unwrap();
+
+                    if policy != StealFallbackPolicy::RetryLocal {
+                        return;
+                    }
+
+                    let request_id = match setup.task_out_rx.recv().await.unwrap() {
+                        ConnectionMessageOut::Request {
+                            client_id: 0,
+                            connection_id: TestSetup::CONNECTION_ID,
+                            id,
+                            ..
+                        } => id,
+                        other => unreachable!("unexpected message: {other:?}"),
+                    };
+
+                    let response = Response::builder()
+                        .status(StatusCode::OK)
+                        .body(Empty::new().map_err(|_| unreachable!()).boxed())
+                        .unwrap();
+
+                    setup
+                        .task_in_tx
+                        .send(ConnectionMessageIn::Response {
+                            client_id: 0,
+                            request_id,
+                            response,
+                        })
+                        .await
+                        .unwrap();
+                }
+            );
+
+            let mut rx = setup.shutdown().await;
+            match rx.recv().await.unwrap() {
+                ConnectionMessageOut::Closed {
+                    client_id: 0,
+                    connection_id: TestSetup::CONNECTION_ID,
+                } => {}
+                other => unreachable!("unexpected message: {other:?}"),
+            }
+            assert!(rx.recv().await.is_none());
+        }
+    }
+
     /// Stolen connection receives 2 requests, both match the same client's filter.
     /// After processing 2 requests, client unsubscribes the connection.
     /// Then, connection is closed and the client is not notified.
@@ -1158,7 +1568,11 @@ mod test {
                 match setup.task_out_rx.recv().await.unwrap() {
                     ConnectionMessageOut::SubscribedHttp {
                         client_id: 0,
-                        connection_id: TestSetup::CONNECTION_ID,
+                        connection:
+                            NewTcpConnection {
+                                connection_id: TestSetup::CONNECTION_ID,
+                                ..
+                            },
                     } => {}
                     other => unreachable!("unexpected message: {other:?}"),
                 };
@@ -1262,7 +1676,11 @@ mod test {
                 match setup.task_out_rx.recv().await.unwrap() {
                     ConnectionMessageOut::SubscribedHttp {
                         client_id: 0,
-                        connection_id: TestSetup::CONNECTION_ID,
+                        connection:
+                            NewTcpConnection {
+                                connection_id: TestSetup::CONNECTION_ID,
+                                ..
+                            },
                     } => {}
                     other => unreachable!("unexpected message: {other:?}"),
                 };
@@ -1346,7 +1764,11 @@ mod test {
                 match setup.task_out_rx.recv().await.unwrap() {
                     ConnectionMessageOut::SubscribedHttp {
                         client_id: 0,
-                        connection_id: TestSetup::CONNECTION_ID,
+                        connection:
+                            NewTcpConnection {
+                                connection_id: TestSetup::CONNECTION_ID,
+                                ..
+                            },
                     } => {}
                     other => unreachable!("unexpected message: {other:?}"),
                 };
@@ -1397,7 +1819,11 @@ mod test {
                 match setup.task_out_rx.recv().await.unwrap() {
                     ConnectionMessageOut::SubscribedHttp {
                         client_id: 1,
-                        connection_id: TestSetup::CONNECTION_ID,
+                        connection:
+                            NewTcpConnection {
+                                connection_id: TestSetup::CONNECTION_ID,
+                                ..
+                            },
                     } => {}
                     other => unreachable!("unexpected message: {other:?}"),
                 };
@@ -1529,7 +1955,11 @@ mod test {
                 match setup.task_out_rx.recv().await.unwrap() {
                     ConnectionMessageOut::SubscribedHttp {
                         client_id: 0,
-                        connection_id: TestSetup::CONNECTION_ID,
+                        connection:
+                            NewTcpConnection {
+                                connection_id: TestSetup::CONNECTION_ID,
+                                ..
+                            },
                     } => {}
                     other => unreachable!("unexpected message: {other:?}"),
                 };
@@ -1587,7 +2017,11 @@ mod test {
                 match setup.task_out_rx.recv().await.unwrap() {
                     ConnectionMessageOut::SubscribedHttp {
                         client_id: 0,
-                        connection_id: TestSetup::CONNECTION_ID,
+                        connection:
+                            NewTcpConnection {
+                                connection_id: TestSetup::CONNECTION_ID,
+                                ..
+                            },
                     } => {}
                     other => unreachable!("unexpected message: {other:?}"),
                 };
@@ -1618,4 +2052,68 @@ mod test {
         // The task should not produce the `Closed` message - the client has unsubscribed.
         assert!(rx.recv().await.is_none());
     }
+
+    /// Stolen connection receives a request that matches some client's filter, and the client
+    /// starts streaming the response body, but stops sending frames.
+    /// The response is aborted after the client's [`StealFallback::idle_timeout_ms`].
+    #[tokio::test]
+    async fn streamed_response_idle_timeout() {
+        let mut setup = TestSetup::new().await;
+        setup.fallbacks.insert(
+            0,
+            StealFallback {
+                idle_timeout_ms: Some(200),
+                ..Default::default()
+            },
+        );
+
+        let request = setup.prepare_request(Some(0), false);
+        let (frame_tx, frame_rx) = mpsc::channel(1);
+        tokio::join!(
+            async {
+                let mut response = setup.request_sender.send_request(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+
+                let frame = response.body_mut().frame().await.unwrap().unwrap();
+                assert_eq!(frame.into_data().unwrap(), "data: ping\n\n");
+                assert!(response.body_mut().frame().await.unwrap().is_err());
+            },
+            async {
+                match setup.task_out_rx.recv().await.unwrap() {
+                    ConnectionMessageOut::SubscribedHttp { client_id: 0, .. } => {}
+                    other => unreachable!("unexpected message: {other:?}"),
+                };
+
+                let request_id = match setup.task_out_rx.recv().await.unwrap() {
+                    ConnectionMessageOut::Request {
+                        client_id: 0,
+                        connection_id: TestSetup::CONNECTION_ID,
+                        id,
+                        ..
+                    } => id,
+                    other => unreachable!("unexpected message: {other:?}"),
+                };
+
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(BoxBody::new(StreamBody::new(ReceiverStream::new(frame_rx))))
+                    .unwrap();
+
+                setup
+                    .task_in_tx
+                    .send(ConnectionMessageIn::Response {
+                        client_id: 0,
+                        request_id,
+                        response,
+                    })
+                    .await
+                    .unwrap();
+
+                frame_tx
+                    .send(Ok(Frame::data(Bytes::from_static(b"data: ping\n\n"))))
+                    .await
+                    .unwrap();
+            }
+        );
+    }
 }
@@ -0,0 +1,229 @@
+//! Utils related to stealing with a database filter.
+//!
+//! Database connections are matched with a [`DatabaseFilter`] on the [`Login`] from their startup
+//! message, and then stolen whole. To read the startup message, the agent may have to take part in
+//! the handshake that precedes it (e.g. when the client asks for TLS), which is done with the
+//! original destination of the connection, see [`handshake`].
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use fancy_regex::Regex;
+use mirrord_protocol::tcp::DatabaseProtocol;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tracing::Level;
+
+mod postgres;
+mod tds;
+
+/// Database filter, compiled from [`mirrord_protocol::tcp::DatabaseFilter`].
+#[derive(Debug)]
+pub struct DatabaseFilter {
+    pub protocol: DatabaseProtocol,
+    /// Matched against [`Login::database`].
+    database: Option<Regex>,
+    /// Matched against [`Login::user`].
+    user: Option<Regex>,
+}
+
+impl TryFrom<&mirrord_protocol::tcp::DatabaseFilter> for DatabaseFilter {
+    type Error = fancy_regex::Error;
+
+    fn try_from(filter: &mirrord_protocol::tcp::DatabaseFilter) -> Result<Self, Self::Error> {
+        let compile = |filter: &mirrord_protocol::tcp::Filter| Regex::new(&format!("(?i){filter}"));
+
+        Ok(Self {
+            protocol: filter.protocol,
+            database: filter.database.as_ref().map(compile).transpose()?,
+            user: filter.user.as_ref().map(compile).transpose()?,
+        })
+    }
+}
+
+impl DatabaseFilter {
+    /// Checks whether the given [`Login`] matches this filter.
+    ///
+    /// Fields missing from the [`Login`] don't match.
+    #[tracing::instrument(level = Level::TRACE, ret(level = "DEBUG"))]
+    pub fn matches(&self, login: &Login) -> bool {
+        let field_matches = |filter: Option<&Regex>, value: Option<&str>| match (filter, value) {
+            (None, _) => true,
+            (Some(..), None) => false,
+            (Some(filter), Some(value)) => filter
+                .is_match(value)
+                .inspect_err(|error| {
+                    tracing::error!(value, ?error, "Error while matching database filter");
+                })
+                .unwrap_or(false),
+        };
+
+        field_matches(self.database.as_ref(), login.database.as_deref())
+            && field_matches(self.user.as_ref(), login.user.as_deref())
+    }
+}
+
+/// Database and user of a connection, read from its startup message.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Login {
+    pub database: Option<String>,
+    pub user: Option<String>,
+}
+
+/// Start of a database connection, read by [`handshake`].
+#[derive(Debug, Default)]
+pub(crate) struct Handshake {
+    /// [`None`] if the startup message could not be read (e.g. the connection is encrypted).
+    pub login: Option<Login>,
+    /// Connection with the original destination, if it was made during the handshake.
+    pub server: Option<TcpStream>,
+    /// Bytes read from the client that the original destination did not receive.
+    pub pending: Vec<u8>,
+    /// Bytes that the local application should receive first if the connection is stolen.
+    pub replay: Vec<u8>,
+    /// Whether the first response of the local application should be dropped if the connection
+    /// is stolen, as the client already got it from the original destination.
+    pub skip_response: bool,
+}
+
+impl Handshake {
+    /// The connection should be passed through, with the given `pending` bytes.
+    fn passthrough(server: Option<TcpStream>, pending: Vec<u8>) -> Self {
+        Self {
+            login: None,
+            server,
+            pending,
+            replay: Default::default(),
+            skip_response: false,
+        }
+    }
+}
+
+/// Reads the [`Login`] from a new connection of the given [`DatabaseProtocol`].
+///
+/// Messages that precede the startup message are exchanged with the original `destination`.
+pub(crate) async fn handshake(
+    protocol: DatabaseProtocol,
+    client: &mut TcpStream,
+    destination: SocketAddr,
+) -> io::Result<Handshake> {
+    match protocol {
+        DatabaseProtocol::Postgres => postgres::handshake(client, destination).await,
+        DatabaseProtocol::Tds => tds::handshake(client, destination).await,
+    }
+}
+
+/// Stolen database connection, as seen by the local application.
+///
+/// Reads start with the [`Handshake::replay`], and the first response of the local application
+/// is dropped when [`Handshake::skip_response`] is set.
+#[derive(Debug)]
+pub(crate) struct DatabaseStream {
+    stream: TcpStream,
+    /// Part of the [`Handshake::replay`] that was not read yet.
+    replay: Bytes,
+    skip: Option<tds::MessageSkip>,
+}
+
+impl DatabaseStream {
+    pub(crate) fn new(stream: TcpStream, replay: Vec<u8>, skip_response: bool) -> Self {
+        Self {
+            stream,
+            replay: replay.into(),
+            skip: skip_response.then(Default::default),
+        }
+    }
+}
+
+impl AsyncRead for DatabaseStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.replay.is_empty() {
+            return Pin::new(&mut this.stream).poll_read(cx, buf);
+        }
+
+        let amount = this.replay.len().min(buf.remaining());
+        buf.put_slice(&this.replay.split_to(amount));
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for DatabaseStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if let Some(skip) = this.skip.as_mut() {
+            let skipped = skip.skip(buf);
+            if skip.is_done() {
+                this.skip = None;
+            }
+            if skipped > 0 {
+                return Poll::Ready(Ok(skipped));
+            }
+        }
+
+        Pin::new(&mut this.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_protocol::tcp::{self, Filter};
+
+    use super::*;
+
+    fn filter(database: Option<&str>, user: Option<&str>) -> DatabaseFilter {
+        let filter = tcp::DatabaseFilter {
+            protocol: DatabaseProtocol::Postgres,
+            database: database.map(|database| Filter::new(database.to_string()).unwrap()),
+            user: user.map(|user| Filter::new(user.to_string()).unwrap()),
+        };
+
+        DatabaseFilter::try_from(&filter).unwrap()
+    }
+
+    #[test]
+    fn matching_login() {
+        let login = Login {
+            database: Some("orders_test".to_string()),
+            user: Some("reporting".to_string()),
+        };
+
+        assert!(filter(Some("^orders_test$"), None).matches(&login));
+        assert!(filter(Some("^ORDERS"), Some("^reporting$")).matches(&login));
+        assert!(!filter(Some("^orders$"), None).matches(&login));
+        assert!(!filter(Some("^orders_test$"), Some("^admin$")).matches(&login));
+
+        let no_database = Login {
+            database: None,
+            user: Some("reporting".to_string()),
+        };
+        assert!(!filter(Some(".*"), None).matches(&no_database));
+        assert!(filter(None, Some("reporting")).matches(&no_database));
+    }
+}
@@ -0,0 +1,245 @@
+//! Start of a connection in the PostgreSQL frontend/backend protocol, see
+//! <https://www.postgresql.org/docs/current/protocol-flow.html#PROTOCOL-FLOW-START-UP>.
+
+use std::{io, net::SocketAddr};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::{Handshake, Login};
+
+/// Version 3.0 of the protocol, the only one that we read the `StartupMessage` of.
+const PROTOCOL_VERSION_3: u32 = 196608;
+
+/// Code of the `SSLRequest` message.
+const SSL_REQUEST_CODE: u32 = 80877103;
+
+/// Code of the `GSSENCRequest` message.
+const GSSENC_REQUEST_CODE: u32 = 80877104;
+
+/// Largest startup packet accepted by the PostgreSQL server.
+const MAX_STARTUP_PACKET_LENGTH: usize = 10_000;
+
+/// Reads a packet that the client sends before the startup completes. Unlike the later messages,
+/// these don't start with a type byte.
+async fn read_startup_packet(client: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let length = client.read_u32().await?;
+    let body_length = usize::try_from(length)
+        .ok()
+        .filter(|length| (8..=MAX_STARTUP_PACKET_LENGTH).contains(length))
+        .and_then(|length| length.checked_sub(4))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid startup packet length {length}"),
+            )
+        })?;
+
+    let mut body = vec![0; body_length];
+    client.read_exact(&mut body).await?;
+
+    let mut packet = length.to_be_bytes().to_vec();
+    packet.extend(body);
+
+    Ok(packet)
+}
+
+/// Splits a startup packet into its code (protocol version or request code) and contents.
+fn parse_startup_packet(packet: &[u8]) -> Option<(u32, &[u8])> {
+    let (_length, rest) = packet.split_first_chunk::<4>()?;
+    let (code, contents) = rest.split_first_chunk::<4>()?;
+
+    Some((u32::from_be_bytes(*code), contents))
+}
+
+/// Reads the [`Login`] from the parameters of a `StartupMessage`, null-terminated names and
+/// values, ending with an empty name.
+///
+/// As in the server, the database defaults to the name of the user.
+fn parse_startup_parameters(parameters: &[u8]) -> Login {
+    let mut login = Login::default();
+    let mut fields = parameters
+        .split(|byte| *byte == 0)
+        .map(String::from_utf8_lossy);
+
+    while let Some(name) = fields.next().filter(|name| !name.is_empty()) {
+        let Some(value) = fields.next() else {
+            break;
+        };
+
+        match name.as_ref() {
+            "user" => login.user = Some(value.into_owned()),
+            "database" => login.database = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    if login.database.is_none() {
+        login.database.clone_from(&login.user);
+    }
+
+    login
+}
+
+/// Reads the `StartupMessage` of the client.
+///
+/// An `SSLRequest` or `GSSENCRequest` that comes first is sent to the original `destination`.
+/// If the server refuses the encryption, the client sends the `StartupMessage` in plain text,
+/// otherwise the connection is passed through.
+pub(super) async fn handshake(
+    client: &mut TcpStream,
+    destination: SocketAddr,
+) -> io::Result<Handshake> {
+    let mut server = None;
+
+    loop {
+        // Length of the startup packet always starts with a zero byte, anything else is a direct
+        // TLS handshake (`sslnegotiation=direct`).
+        let mut first_byte = [0];
+        if client.peek(&mut first_byte).await? == 0 || first_byte != [0] {
+            return Ok(Handshake::passthrough(server, Vec::new()));
+        }
+
+        let packet = read_startup_packet(client).await?;
+        let Some((code, contents)) = parse_startup_packet(&packet) else {
+            return Ok(Handshake::passthrough(server, packet));
+        };
+
+        match code {
+            SSL_REQUEST_CODE | GSSENC_REQUEST_CODE => {
+                let mut stream = match server.take() {
+                    Some(stream) => stream,
+                    None => TcpStream::connect(destination).await?,
+                };
+                stream.write_all(&packet).await?;
+                let response = stream.read_u8().await?;
+                client.write_all(&[response]).await?;
+                server = Some(stream);
+
+                if response != b'N' {
+                    return Ok(Handshake::passthrough(server, Vec::new()));
+                }
+            }
+
+            PROTOCOL_VERSION_3 => {
+                return Ok(Handshake {
+                    login: Some(parse_startup_parameters(contents)),
+                    server,
+                    pending: packet.clone(),
+                    replay: packet,
+                    skip_response: false,
+                });
+            }
+
+            // `CancelRequest` or an unsupported protocol version.
+            _ => return Ok(Handshake::passthrough(server, packet)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Builds a `StartupMessage` with the given parameters.
+    fn startup_message(parameters: &[(&str, &str)]) -> Vec<u8> {
+        let mut contents = PROTOCOL_VERSION_3.to_be_bytes().to_vec();
+        for (name, value) in parameters {
+            contents.extend_from_slice(name.as_bytes());
+            contents.push(0);
+            contents.extend_from_slice(value.as_bytes());
+            contents.push(0);
+        }
+        contents.push(0);
+
+        let length = u32::try_from(contents.len() + 4).unwrap();
+        let mut message = length.to_be_bytes().to_vec();
+        message.extend(contents);
+        message
+    }
+
+    #[test]
+    fn startup_parameters() {
+        let message = startup_message(&[
+            ("user", "reporting"),
+            ("database", "orders"),
+            ("application_name", "psql"),
+        ]);
+        let (code, contents) = parse_startup_packet(&message).unwrap();
+        assert_eq!(code, PROTOCOL_VERSION_3);
+        assert_eq!(
+            parse_startup_parameters(contents),
+            Login {
+                database: Some("orders".to_string()),
+                user: Some("reporting".to_string()),
+            }
+        );
+
+        // The database defaults to the user.
+        let message = startup_message(&[("user", "reporting")]);
+        let (_, contents) = parse_startup_packet(&message).unwrap();
+        assert_eq!(
+            parse_startup_parameters(contents),
+            Login {
+                database: Some("reporting".to_string()),
+                user: Some("reporting".to_string()),
+            }
+        );
+    }
+
+    /// The `SSLRequest` is answered by the original destination, and the `StartupMessage` that
+    /// follows a refusal is read.
+    #[tokio::test]
+    async fn ssl_request_refused() {
+        let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let destination = server_listener.local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = server_listener.accept().await.unwrap();
+            let mut request = [0; 8];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(
+                request.get(4..),
+                Some(SSL_REQUEST_CODE.to_be_bytes().as_slice())
+            );
+            stream.write_all(b"N").await.unwrap();
+            stream
+        });
+
+        let message = startup_message(&[("user", "reporting"), ("database", "orders")]);
+        let client_message = message.clone();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let mut request = 8_u32.to_be_bytes().to_vec();
+            request.extend_from_slice(&SSL_REQUEST_CODE.to_be_bytes());
+            stream.write_all(&request).await.unwrap();
+            assert_eq!(stream.read_u8().await.unwrap(), b'N');
+            stream.write_all(&client_message).await.unwrap();
+            stream
+        });
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let handshake = handshake(&mut stream, destination).await.unwrap();
+
+        assert_eq!(
+            handshake.login,
+            Some(Login {
+                database: Some("orders".to_string()),
+                user: Some("reporting".to_string()),
+            })
+        );
+        assert!(handshake.server.is_some());
+        assert_eq!(handshake.pending, message);
+        assert_eq!(handshake.replay, message);
+        assert!(!handshake.skip_response);
+
+        client.await.unwrap();
+        server.await.unwrap();
+    }
+}
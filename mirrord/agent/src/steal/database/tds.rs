@@ -0,0 +1,293 @@
+//! Start of a connection in the Tabular Data Stream protocol (Microsoft SQL Server), see
+//! <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-tds/>.
+
+use std::{io, net::SocketAddr};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::{Handshake, Login};
+
+/// Length of the header of every TDS packet.
+const PACKET_HEADER_LENGTH: usize = 8;
+
+/// Type of the `PRELOGIN` message, also used to carry the TLS handshake.
+const PRELOGIN: u8 = 0x12;
+
+/// Type of the `LOGIN7` message.
+const LOGIN7: u8 = 0x10;
+
+/// Bit of the packet status that marks the last packet of a message.
+const STATUS_EOM: u8 = 0x01;
+
+/// Largest message that we read during the handshake.
+const MAX_MESSAGE_LENGTH: usize = 128 * 1024;
+
+/// Position of the `ibUserName`/`cchUserName` pair in the `LOGIN7` message.
+const LOGIN7_USER_POSITION: usize = 40;
+
+/// Position of the `ibDatabase`/`cchDatabase` pair in the `LOGIN7` message.
+const LOGIN7_DATABASE_POSITION: usize = 68;
+
+/// A TDS message, possibly split into multiple packets.
+#[derive(Debug)]
+struct Message {
+    /// Type from the packet headers.
+    kind: u8,
+    /// All the packets of this message, with headers.
+    raw: Vec<u8>,
+    /// Contents of the message, without the packet headers.
+    payload: Vec<u8>,
+}
+
+/// Reads packets from the `stream` until the end of a message.
+async fn read_message(stream: &mut TcpStream) -> io::Result<Message> {
+    let mut message = Message {
+        kind: 0,
+        raw: Vec::new(),
+        payload: Vec::new(),
+    };
+
+    loop {
+        let mut header = [0; PACKET_HEADER_LENGTH];
+        stream.read_exact(&mut header).await?;
+
+        let [kind, status, length_high, length_low, ..] = header;
+        let length = usize::from(u16::from_be_bytes([length_high, length_low]));
+        let body_length = length
+            .checked_sub(PACKET_HEADER_LENGTH)
+            .filter(|_| message.raw.len() + length <= MAX_MESSAGE_LENGTH)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid TDS packet length {length}"),
+                )
+            })?;
+
+        let mut body = vec![0; body_length];
+        stream.read_exact(&mut body).await?;
+
+        message.kind = kind;
+        message.raw.extend_from_slice(&header);
+        message.raw.extend_from_slice(&body);
+        message.payload.extend(body);
+
+        if status & STATUS_EOM != 0 {
+            return Ok(message);
+        }
+    }
+}
+
+/// Reads the [`Login`] from the payload of a `LOGIN7` message.
+///
+/// Each field is referenced by an offset from the start of the payload and a length in UTF-16
+/// characters. Empty fields are [`None`].
+fn parse_login7(payload: &[u8]) -> Login {
+    let field = |position: usize| -> Option<String> {
+        let &[offset_low, offset_high, length_low, length_high] =
+            payload.get(position..position + 4)?
+        else {
+            return None;
+        };
+
+        let offset = usize::from(u16::from_le_bytes([offset_low, offset_high]));
+        let length = usize::from(u16::from_le_bytes([length_low, length_high]));
+        if length == 0 {
+            return None;
+        }
+
+        let units = payload
+            .get(offset..offset + length * 2)?
+            .chunks_exact(2)
+            .filter_map(|unit| unit.first_chunk::<2>())
+            .map(|unit| u16::from_le_bytes(*unit))
+            .collect::<Vec<_>>();
+
+        Some(String::from_utf16_lossy(&units))
+    };
+
+    Login {
+        database: field(LOGIN7_DATABASE_POSITION),
+        user: field(LOGIN7_USER_POSITION),
+    }
+}
+
+/// Reads the `LOGIN7` message of the client.
+///
+/// The `PRELOGIN` message that comes first is answered by the original `destination`. The
+/// `LOGIN7` that follows is only readable when the server does not support encryption, otherwise
+/// the client starts a TLS handshake and the connection is passed through.
+///
+/// If the connection is stolen, the local application gets the `PRELOGIN` message as well, so its
+/// response has to be skipped, see [`Handshake::skip_response`].
+pub(super) async fn handshake(
+    client: &mut TcpStream,
+    destination: SocketAddr,
+) -> io::Result<Handshake> {
+    let mut first_byte = [0];
+    if client.peek(&mut first_byte).await? == 0 || first_byte != [PRELOGIN] {
+        return Ok(Handshake::passthrough(None, Vec::new()));
+    }
+
+    let prelogin = read_message(client).await?;
+    let mut server = TcpStream::connect(destination).await?;
+    server.write_all(&prelogin.raw).await?;
+    let response = read_message(&mut server).await?;
+    client.write_all(&response.raw).await?;
+
+    let login7 = read_message(client).await?;
+    if login7.kind != LOGIN7 {
+        return Ok(Handshake::passthrough(Some(server), login7.raw));
+    }
+
+    let login = parse_login7(&login7.payload);
+    let mut replay = prelogin.raw;
+    replay.extend_from_slice(&login7.raw);
+
+    Ok(Handshake {
+        login: Some(login),
+        server: Some(server),
+        pending: login7.raw,
+        replay,
+        skip_response: true,
+    })
+}
+
+/// Drops a single TDS message from a stream of bytes, possibly split into multiple packets and
+/// multiple writes.
+#[derive(Debug, Default)]
+pub(crate) struct MessageSkip {
+    /// Header of the current packet, may be incomplete.
+    header: Vec<u8>,
+    /// Bytes left in the body of the current packet.
+    remaining: usize,
+    /// Whether the current packet is the last one of the message.
+    last: bool,
+}
+
+impl MessageSkip {
+    /// Skips the start of the given `bytes` that belongs to the message, returns the amount of
+    /// bytes skipped.
+    pub(crate) fn skip(&mut self, mut bytes: &[u8]) -> usize {
+        let total = bytes.len();
+
+        while !self.is_done() && !bytes.is_empty() {
+            if self.header.len() == PACKET_HEADER_LENGTH && self.remaining == 0 {
+                // The previous packet is complete, but the message continues.
+                self.header.clear();
+            }
+
+            if self.header.len() < PACKET_HEADER_LENGTH {
+                let missing = PACKET_HEADER_LENGTH - self.header.len();
+                let (header, rest) = bytes.split_at(missing.min(bytes.len()));
+                self.header.extend_from_slice(header);
+                bytes = rest;
+
+                if let &[_, status, length_high, length_low, _, _, _, _] = self.header.as_slice() {
+                    self.last = status & STATUS_EOM != 0;
+                    self.remaining = usize::from(u16::from_be_bytes([length_high, length_low]))
+                        .saturating_sub(PACKET_HEADER_LENGTH);
+                }
+
+                continue;
+            }
+
+            let (_, rest) = bytes.split_at(self.remaining.min(bytes.len()));
+            self.remaining -= bytes.len() - rest.len();
+            bytes = rest;
+        }
+
+        total - bytes.len()
+    }
+
+    /// Whether the whole message was skipped.
+    pub(crate) fn is_done(&self) -> bool {
+        self.header.len() == PACKET_HEADER_LENGTH && self.remaining == 0 && self.last
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a TDS packet with the given type, status and body.
+    fn packet(kind: u8, status: u8, body: &[u8]) -> Vec<u8> {
+        let length = u16::try_from(PACKET_HEADER_LENGTH + body.len()).unwrap();
+        let mut packet = vec![kind, status];
+        packet.extend_from_slice(&length.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 1, 0]);
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    /// Builds the payload of a `LOGIN7` message with the given user and database.
+    fn login7(user: &str, database: &str) -> Vec<u8> {
+        // Fixed part, with the offset/length pairs of all the variable fields.
+        let mut payload = vec![0; 94];
+        let mut variable = Vec::new();
+
+        for (position, value) in [
+            (LOGIN7_USER_POSITION, user),
+            (LOGIN7_DATABASE_POSITION, database),
+        ] {
+            let offset = u16::try_from(payload.len() + variable.len()).unwrap();
+            let length = u16::try_from(value.encode_utf16().count()).unwrap();
+            payload
+                .get_mut(position..position + 4)
+                .unwrap()
+                .copy_from_slice(&[offset.to_le_bytes(), length.to_le_bytes()].concat());
+            variable.extend(value.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+
+        payload.extend(variable);
+        payload
+    }
+
+    #[test]
+    fn login7_fields() {
+        assert_eq!(
+            parse_login7(&login7("reporting", "orders")),
+            Login {
+                database: Some("orders".to_string()),
+                user: Some("reporting".to_string()),
+            }
+        );
+
+        assert_eq!(
+            parse_login7(&login7("", "orders")),
+            Login {
+                database: Some("orders".to_string()),
+                user: None,
+            }
+        );
+
+        assert_eq!(parse_login7(&[0; 10]), Login::default());
+    }
+
+    /// The skipped message is split into two packets, written in chunks that don't align with
+    /// the packets.
+    #[test]
+    fn skip_split_message() {
+        let mut message = packet(0x04, 0, b"first");
+        message.extend(packet(0x04, STATUS_EOM, b"second"));
+        let next = packet(0x04, STATUS_EOM, b"next");
+
+        let mut bytes = message.clone();
+        bytes.extend_from_slice(&next);
+
+        let mut skip = MessageSkip::default();
+        let (start, rest) = bytes.split_at(3);
+        assert_eq!(skip.skip(start), 3);
+        assert!(!skip.is_done());
+
+        let (middle, rest) = rest.split_at(12);
+        assert_eq!(skip.skip(middle), 12);
+        assert!(!skip.is_done());
+
+        assert_eq!(skip.skip(rest), message.len() - 15);
+        assert!(skip.is_done());
+        assert_eq!(skip.skip(&next), 0);
+    }
+}
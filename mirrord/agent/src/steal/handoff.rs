@@ -0,0 +1,230 @@
+//! Home for [`PendingHandoff`] - steal subscriptions of an exited client, waiting to be adopted by
+//! the next session (see
+//! [`LayerTcpSteal::Handoff`](mirrord_protocol::tcp::LayerTcpSteal::Handoff)).
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use mirrord_protocol::{ConnectionId, Port};
+use tokio::time::Instant;
+
+use super::connections::ConnectionMessageOut;
+
+/// Subscriptions of a client that exited after sending a
+/// [`LayerTcpSteal::Handoff`](mirrord_protocol::tcp::LayerTcpSteal::Handoff).
+///
+/// While the handoff is pending, the ports remain stolen, and updates from the stolen connections
+/// are held here, to be replayed to the adopting client once it subscribes to the ports again.
+#[derive(Debug)]
+pub(super) struct PendingHandoff {
+    /// Identifies the sessions that can adopt these subscriptions.
+    pub(super) key: String,
+    /// When the handoff ends, even if not all ports were claimed by the adopting client.
+    pub(super) deadline: Instant,
+    /// Whether a new client already adopted these subscriptions.
+    pub(super) adopted: bool,
+    /// Ports subscribed by the exited client, that the adopting client did not subscribe to yet.
+    pub(super) unclaimed_ports: HashSet<Port>,
+    /// HTTP connections that the exited client was subscribed to.
+    ///
+    /// Unfiltered connections can't be handed off, as their state lives in the exited local
+    /// application.
+    pub(super) subscribed_connections: HashSet<ConnectionId>,
+    /// Updates held until the adopting client subscribes to all [`Self::unclaimed_ports`].
+    held: VecDeque<ConnectionMessageOut>,
+    /// Ports of the connections in [`Self::held`], so that we know which ones to let go when
+    /// a port is never claimed.
+    connection_ports: HashMap<ConnectionId, Port>,
+    /// How many new connections and requests are in [`Self::held`].
+    held_count: usize,
+}
+
+impl PendingHandoff {
+    /// Maximum number of new connections and requests held for a single handoff.
+    ///
+    /// Data from the connections is not limited, as it can't be dropped without breaking them.
+    pub(super) const MAX_HELD: usize = 128;
+
+    /// Maximum time that subscriptions are kept for a handoff, regardless of the timeout requested
+    /// by the client.
+    pub(super) const MAX_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Returns the deadline of a handoff with the given timeout, requested by the client in
+    /// [`StealHandoff::timeout_ms`](mirrord_protocol::tcp::StealHandoff::timeout_ms).
+    pub(super) fn deadline(timeout_ms: u64) -> Instant {
+        Instant::now() + Duration::from_millis(timeout_ms).min(Self::MAX_TIMEOUT)
+    }
+
+    pub(super) fn new(
+        key: String,
+        deadline: Instant,
+        unclaimed_ports: HashSet<Port>,
+        subscribed_connections: HashSet<ConnectionId>,
+    ) -> Self {
+        Self {
+            key,
+            deadline,
+            adopted: false,
+            unclaimed_ports,
+            subscribed_connections,
+            held: Default::default(),
+            connection_ports: Default::default(),
+            held_count: 0,
+        }
+    }
+
+    /// Holds the given update until the handoff ends.
+    ///
+    /// Returns the update back when there is no more room for it, in which case the caller should
+    /// reject it, as if the client exited.
+    pub(super) fn hold(
+        &mut self,
+        update: ConnectionMessageOut,
+    ) -> Result<(), ConnectionMessageOut> {
+        let port = match &update {
+            ConnectionMessageOut::Request { port, .. } => Some(*port),
+            ConnectionMessageOut::SubscribedTcp { connection, .. } => {
+                Some(connection.destination_port)
+            }
+            _ => None,
+        };
+
+        if let Some(port) = port {
+            if self.held_count >= Self::MAX_HELD {
+                return Err(update);
+            }
+
+            self.held_count += 1;
+            self.connection_ports.insert(update.connection_id(), port);
+        }
+
+        self.held.push_back(update);
+
+        Ok(())
+    }
+
+    /// Moves the updates held by the `previous` handoff of the same client into this one.
+    pub(super) fn inherit(&mut self, previous: PendingHandoff) {
+        self.subscribed_connections
+            .extend(previous.subscribed_connections);
+        self.connection_ports.extend(previous.connection_ports);
+        self.held_count += previous.held_count;
+        let held = std::mem::replace(&mut self.held, previous.held);
+        self.held.extend(held);
+    }
+
+    /// Returns all connections that the client is subscribed to, including the ones that
+    /// subscribed it during the handoff.
+    pub(super) fn connections(&self) -> HashSet<ConnectionId> {
+        self.held
+            .iter()
+            .map(ConnectionMessageOut::connection_id)
+            .chain(self.subscribed_connections.iter().copied())
+            .collect()
+    }
+
+    /// Ends the handoff.
+    ///
+    /// Returns the connections that should be let go, because their port was never claimed by the
+    /// adopting client, and the held updates to replay to the adopting client.
+    pub(super) fn finish(self) -> (HashSet<ConnectionId>, Vec<ConnectionMessageOut>) {
+        let dropped: HashSet<ConnectionId> = self
+            .connection_ports
+            .into_iter()
+            .filter(|(_, port)| self.unclaimed_ports.contains(port))
+            .map(|(connection_id, _)| connection_id)
+            .collect();
+
+        let replay = self
+            .held
+            .into_iter()
+            .filter(|update| !dropped.contains(&update.connection_id()))
+            .collect();
+
+        (dropped, replay)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use mirrord_protocol::tcp::NewTcpConnection;
+
+    use super::*;
+
+    fn new_connection(connection_id: ConnectionId, port: Port) -> ConnectionMessageOut {
+        ConnectionMessageOut::SubscribedTcp {
+            client_id: 0,
+            connection: NewTcpConnection {
+                connection_id,
+                remote_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                destination_port: port,
+                source_port: 40000,
+                local_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            },
+        }
+    }
+
+    fn data(connection_id: ConnectionId) -> ConnectionMessageOut {
+        ConnectionMessageOut::Raw {
+            client_id: 0,
+            connection_id,
+            data: b"hello".to_vec(),
+        }
+    }
+
+    #[test]
+    fn unclaimed_ports_are_dropped() {
+        let mut handoff = PendingHandoff::new(
+            "user@target".into(),
+            Instant::now(),
+            [80, 81].into(),
+            [7].into(),
+        );
+
+        handoff.hold(new_connection(0, 80)).unwrap();
+        handoff.hold(data(0)).unwrap();
+        handoff.hold(new_connection(1, 81)).unwrap();
+        handoff.hold(data(1)).unwrap();
+        assert_eq!(handoff.connections(), [0, 1, 7].into());
+
+        handoff.unclaimed_ports.remove(&80);
+        let (dropped, replay) = handoff.finish();
+
+        assert_eq!(dropped, [1].into());
+        assert!(
+            matches!(
+                replay.as_slice(),
+                [
+                    ConnectionMessageOut::SubscribedTcp { .. },
+                    ConnectionMessageOut::Raw {
+                        connection_id: 0,
+                        ..
+                    },
+                ]
+            ),
+            "{replay:?}"
+        );
+    }
+
+    #[test]
+    fn new_connections_are_limited() {
+        let mut handoff = PendingHandoff::new(
+            "user@target".into(),
+            Instant::now(),
+            [80].into(),
+            Default::default(),
+        );
+
+        for connection_id in 0..PendingHandoff::MAX_HELD as u64 {
+            handoff.hold(new_connection(connection_id, 80)).unwrap();
+        }
+
+        assert!(handoff.hold(new_connection(1000, 80)).is_err());
+        // Data from held connections is never rejected.
+        handoff.hold(data(0)).unwrap();
+    }
+}
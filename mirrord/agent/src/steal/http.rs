@@ -4,10 +4,14 @@ use crate::http::HttpVersion;
 
 mod filter;
 mod reversible_stream;
+mod timeout_body;
 
 pub use filter::HttpFilter;
 
-pub(crate) use self::reversible_stream::ReversibleStream;
+pub(crate) use self::{
+    reversible_stream::ReversibleStream,
+    timeout_body::{TimeoutBody, TimeoutBodyError},
+};
 
 /// Handy alias due to [`ReversibleStream`] being generic, avoiding value mismatches.
 pub(crate) type DefaultReversibleStream = ReversibleStream<{ HttpVersion::MINIMAL_HEADER_SIZE }>;
@@ -0,0 +1,159 @@
+//! Limits on how long a stealer client can stream the body of a response, see [`TimeoutBody`].
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use thiserror::Error;
+use tokio::time::{self, Instant, Sleep};
+
+/// Errors of a [`TimeoutBody`].
+///
+/// Failing the body makes [`hyper`] abort the response, so that the HTTP client doesn't mistake
+/// a cut response for a complete one.
+#[derive(Error, Debug)]
+pub(crate) enum TimeoutBodyError {
+    #[error(transparent)]
+    Hyper(#[from] hyper::Error),
+    #[error("stealer client did not send any part of the response body for {0:?}")]
+    Idle(Duration),
+    #[error("stealer client did not finish the response in time")]
+    Total,
+}
+
+/// Wraps the body of a response from a stealer client, failing it when the client doesn't send
+/// the next frame within the idle timeout, or doesn't finish the body before the deadline.
+pub(crate) struct TimeoutBody<B> {
+    inner: B,
+    idle_timeout: Option<Duration>,
+    /// Reset with every frame from [`Self::inner`].
+    idle: Option<Pin<Box<Sleep>>>,
+    total: Option<Pin<Box<Sleep>>>,
+}
+
+impl<B> TimeoutBody<B> {
+    pub(crate) fn new(inner: B, idle_timeout: Option<Duration>, deadline: Option<Instant>) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+            idle: idle_timeout.map(|timeout| Box::pin(time::sleep(timeout))),
+            total: deadline.map(|deadline| Box::pin(time::sleep_until(deadline))),
+        }
+    }
+}
+
+impl<B> Body for TimeoutBody<B>
+where
+    B: Body<Data = Bytes, Error = hyper::Error> + Unpin,
+{
+    type Data = Bytes;
+
+    type Error = TimeoutBodyError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+
+        if this
+            .total
+            .as_mut()
+            .is_some_and(|total| total.as_mut().poll(cx).is_ready())
+        {
+            return Poll::Ready(Some(Err(TimeoutBodyError::Total)));
+        }
+
+        if let Poll::Ready(frame) = Pin::new(&mut this.inner).poll_frame(cx) {
+            if let (Some(idle), Some(timeout)) = (this.idle.as_mut(), this.idle_timeout) {
+                idle.as_mut().reset(Instant::now() + timeout);
+            }
+
+            return Poll::Ready(frame.map(|frame| frame.map_err(Into::into)));
+        }
+
+        let idle_elapsed = this
+            .idle
+            .as_mut()
+            .is_some_and(|idle| idle.as_mut().poll(cx).is_ready());
+        match this.idle_timeout {
+            Some(timeout) if idle_elapsed => {
+                Poll::Ready(Some(Err(TimeoutBodyError::Idle(timeout))))
+            }
+            _ => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::{combinators::BoxBody, BodyExt, StreamBody};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+
+    use super::*;
+    use crate::steal::connections::DynamicBody;
+
+    /// Body made of the frames sent through the returned channel.
+    fn streamed() -> (mpsc::Sender<hyper::Result<Frame<Bytes>>>, DynamicBody) {
+        let (tx, rx) = mpsc::channel(8);
+        (tx, BoxBody::new(StreamBody::new(ReceiverStream::new(rx))))
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_resets_with_frames() {
+        let (tx, body) = streamed();
+        let mut body = TimeoutBody::new(body, Some(Duration::from_millis(200)), None);
+
+        for _ in 0..3 {
+            time::sleep(Duration::from_millis(100)).await;
+            tx.send(Ok(Frame::data(Bytes::from_static(b"data: ping\n\n"))))
+                .await
+                .unwrap();
+            assert!(body.frame().await.unwrap().unwrap().is_data());
+        }
+
+        let error = body.frame().await.unwrap().unwrap_err();
+        assert!(matches!(error, TimeoutBodyError::Idle(..)), "{error}");
+    }
+
+    #[tokio::test]
+    async fn total_timeout_ends_streaming_body() {
+        let (tx, body) = streamed();
+        let mut body = TimeoutBody::new(
+            body,
+            Some(Duration::from_millis(200)),
+            Some(Instant::now() + Duration::from_millis(500)),
+        );
+
+        let mut frames = 0;
+        let error = loop {
+            tx.send(Ok(Frame::data(Bytes::from_static(b"data: ping\n\n"))))
+                .await
+                .unwrap();
+            match body.frame().await.unwrap() {
+                Ok(..) => {
+                    frames += 1;
+                    time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(error) => break error,
+            }
+        };
+
+        assert!(matches!(error, TimeoutBodyError::Total), "{error}");
+        assert!(frames >= 4, "{frames}");
+    }
+}
@@ -10,6 +10,7 @@ use tracing::warn;
 
 use crate::{
     error::{AgentError, Result},
+    sandbox::Sandbox,
     steal::ip_tables::{
         flush_connections::FlushConnections,
         mesh::{istio::AmbientRedirect, MeshRedirect, MeshVendorExt},
@@ -130,9 +131,12 @@ pub struct IPTablesWrapper {
 }
 
 /// wrapper around iptables::new that uses nft or legacy based on env
+///
+/// Always uses legacy in sandboxes that don't support nft (see [`Sandbox::supports_nftables`]).
 pub fn new_iptables() -> iptables::IPTables {
     if let Ok(val) = std::env::var("MIRRORD_AGENT_NFTABLES")
         && val.to_lowercase() == "true"
+        && Sandbox::detect().map_or(true, Sandbox::supports_nftables)
     {
         iptables::new_with_cmd("/usr/sbin/iptables-nft")
     } else {
@@ -0,0 +1,169 @@
+//! Ports reserved by the clients with
+//! [`LayerTcpSteal::ReservePort`](mirrord_protocol::tcp::LayerTcpSteal::ReservePort).
+//!
+//! Unlike the port subscriptions, nothing is redirected here: the agent listens on the reserved
+//! port itself, and the connections it accepts are stolen on behalf of the client once it
+//! subscribes to the port with [`StealType::Reserved`](mirrord_protocol::tcp::StealType::Reserved).
+//! Until then, they wait in the listener's backlog.
+
+use std::{
+    collections::HashMap,
+    future, io,
+    net::{Ipv4Addr, SocketAddr},
+    task::Poll,
+};
+
+use mirrord_protocol::{Port, RemoteResult, ResponseError};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::util::ClientId;
+
+/// A port reserved by a client.
+#[derive(Debug)]
+struct Reservation {
+    client_id: ClientId,
+    /// Listens on the reserved port, in the target's network namespace.
+    listener: TcpListener,
+    /// Whether the client subscribed to the port, and the connections should be accepted.
+    subscribed: bool,
+}
+
+/// Set of the ports reserved by the clients.
+#[derive(Debug, Default)]
+pub(crate) struct PortReservations {
+    reservations: HashMap<Port, Reservation>,
+}
+
+impl PortReservations {
+    /// Reserves the given `port` for the client, `0` picks a free port.
+    ///
+    /// Returns the reserved port.
+    pub(crate) async fn reserve(&mut self, client_id: ClientId, port: Port) -> RemoteResult<Port> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await?;
+        let port = listener.local_addr()?.port();
+
+        self.reservations.insert(
+            port,
+            Reservation {
+                client_id,
+                listener,
+                subscribed: false,
+            },
+        );
+
+        Ok(port)
+    }
+
+    /// Releases the given `port`, if the client reserved it.
+    pub(crate) fn release(&mut self, client_id: ClientId, port: Port) {
+        if self.is_reserved_by(client_id, port) {
+            self.reservations.remove(&port);
+        }
+    }
+
+    /// Releases all ports reserved by the client.
+    pub(crate) fn release_all(&mut self, client_id: ClientId) {
+        self.reservations
+            .retain(|_, reservation| reservation.client_id != client_id);
+    }
+
+    /// Starts accepting the connections on the given `port`, which the client has to have
+    /// reserved.
+    pub(crate) fn subscribe(&mut self, client_id: ClientId, port: Port) -> RemoteResult<Port> {
+        match self.reservations.get_mut(&port) {
+            Some(reservation) if reservation.client_id == client_id => {
+                reservation.subscribed = true;
+                Ok(port)
+            }
+            Some(..) => Err(ResponseError::PortAlreadyStolen(port)),
+            None => Err(io::Error::from(io::ErrorKind::AddrNotAvailable).into()),
+        }
+    }
+
+    /// Stops accepting the connections on the given `port`, if the client reserved it.
+    ///
+    /// The port stays reserved.
+    pub(crate) fn unsubscribe(&mut self, client_id: ClientId, port: Port) {
+        if let Some(reservation) = self.reservations.get_mut(&port)
+            && reservation.client_id == client_id
+        {
+            reservation.subscribed = false;
+        }
+    }
+
+    fn is_reserved_by(&self, client_id: ClientId, port: Port) -> bool {
+        self.reservations
+            .get(&port)
+            .is_some_and(|reservation| reservation.client_id == client_id)
+    }
+
+    /// Accepts the next connection on one of the subscribed ports.
+    ///
+    /// # Returns
+    ///
+    /// * [`ClientId`] - client that reserved the port
+    /// * [`TcpStream`] - accepted connection
+    /// * [`SocketAddr`] - peer address
+    ///
+    /// Never resolves when there are no subscribed ports.
+    pub(crate) async fn next_connection(&self) -> io::Result<(ClientId, TcpStream, SocketAddr)> {
+        future::poll_fn(|cx| {
+            for reservation in self.reservations.values() {
+                if !reservation.subscribed {
+                    continue;
+                }
+
+                if let Poll::Ready(result) = reservation.listener.poll_accept(cx) {
+                    return Poll::Ready(
+                        result.map(|(stream, peer)| (reservation.client_id, stream, peer)),
+                    );
+                }
+            }
+
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn connections_accepted_after_subscribe() {
+        let mut reservations = PortReservations::default();
+
+        let port = reservations.reserve(1, 0).await.unwrap();
+        assert_ne!(port, 0);
+        assert!(matches!(
+            reservations.reserve(2, port).await,
+            Err(ResponseError::RemoteIO(..))
+        ));
+        assert!(matches!(
+            reservations.subscribe(2, port),
+            Err(ResponseError::PortAlreadyStolen(..))
+        ));
+
+        // Waits in the backlog until the client subscribes.
+        let client = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        tokio::select! {
+            _ = reservations.next_connection() => panic!("accepted before subscribe"),
+            _ = tokio::time::sleep(std::time::Duration::from_millis(50)) => {}
+        }
+
+        assert_eq!(reservations.subscribe(1, port), Ok(port));
+        let (client_id, _, peer) = reservations.next_connection().await.unwrap();
+        assert_eq!(client_id, 1);
+        assert_eq!(peer, client.local_addr().unwrap());
+
+        reservations.release(2, port);
+        assert!(reservations.is_reserved_by(1, port));
+        reservations.release_all(1);
+        assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .is_err());
+    }
+}
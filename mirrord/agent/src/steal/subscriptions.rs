@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     net::{Ipv4Addr, SocketAddr},
     sync::Arc,
 };
@@ -9,6 +9,7 @@ use mirrord_protocol::{Port, RemoteResult, ResponseError};
 use tokio::net::{TcpListener, TcpStream};
 
 use super::{
+    database::DatabaseFilter,
     http::HttpFilter,
     ip_tables::{new_iptables, IPTablesWrapper, SafeIpTables},
 };
@@ -169,7 +170,7 @@ impl<R: PortRedirector> PortSubscriptions<R> {
     ///
     /// * `client_id` - identifier of the client that issued the subscription
     /// * `port` - number of the port to steal from
-    /// * `filter` - optional [`HttpFilter`]
+    /// * `filter` - optional [`PortFilter`]
     ///
     /// # Warning
     ///
@@ -180,7 +181,7 @@ impl<R: PortRedirector> PortSubscriptions<R> {
         &mut self,
         client_id: ClientId,
         port: Port,
-        filter: Option<HttpFilter>,
+        filter: Option<PortFilter>,
     ) -> Result<RemoteResult<Port>, R::Error> {
         let add_redirect = match self.subscriptions.entry(port) {
             Entry::Occupied(mut e) => {
@@ -232,9 +233,17 @@ impl<R: PortRedirector> PortSubscriptions<R> {
             }
             PortSubscription::Unfiltered(..) => false,
             PortSubscription::Filtered(filters) => {
-                filters.remove(&client_id);
-
-                if filters.is_empty() {
+                // Drained subscriptions have no filters, but they're removed only with
+                // `remove_drained`.
+                if filters.remove(&client_id).is_some() && filters.is_empty() {
+                    e.remove();
+                    true
+                } else {
+                    false
+                }
+            }
+            PortSubscription::Database(filters) => {
+                if filters.remove(&client_id).is_some() && filters.is_empty() {
                     e.remove();
                     true
                 } else {
@@ -266,19 +275,149 @@ impl<R: PortRedirector> PortSubscriptions<R> {
     /// [`PortRedirector`] and it is no longer usable. It is a caller's responsibility to clean
     /// up any external state.
     pub async fn remove_all(&mut self, client_id: ClientId) -> Result<(), R::Error> {
-        let ports = self
-            .subscriptions
-            .iter()
-            .filter_map(|(k, v)| v.has_client(client_id).then_some(*k))
-            .collect::<Vec<_>>();
+        for port in self.client_ports(client_id) {
+            self.remove(client_id, port).await?;
+        }
+
+        Ok(())
+    }
 
+    /// Stop stealing on behalf of the given client, without removing the port redirections, so
+    /// that the connections already stolen for the client are not disturbed.
+    ///
+    /// The client's filters are removed, so the requests that they would match pass through to
+    /// their original destination. The client's unfiltered subscriptions are replaced with
+    /// filtered subscriptions without any filter, which let all traffic through.
+    ///
+    /// Returns the ports that the client had subscriptions on, which should be passed to
+    /// [`Self::remove_drained`] once the client's connections are done.
+    pub fn drain(&mut self, client_id: ClientId) -> HashSet<Port> {
+        let ports = self.client_ports(client_id);
+
+        for port in &ports {
+            let Some(subscription) = self.subscriptions.get_mut(port) else {
+                continue;
+            };
+
+            match subscription {
+                PortSubscription::Unfiltered(..) => {
+                    *subscription = PortSubscription::Filtered(Default::default());
+                }
+                PortSubscription::Filtered(filters) => {
+                    filters.remove(&client_id);
+                }
+                PortSubscription::Database(filters) => {
+                    filters.remove(&client_id);
+                }
+            }
+        }
+
+        ports
+    }
+
+    /// Remove the redirections of the given `ports`, drained with [`Self::drain`], unless another
+    /// client subscribed to them in the meantime.
+    ///
+    /// # Warning
+    ///
+    /// If this method returns an [`Err`], it means that this set is out of sync with the inner
+    /// [`PortRedirector`] and it is no longer usable. It is a caller's responsibility to clean
+    /// up any external state.
+    pub async fn remove_drained(&mut self, ports: HashSet<Port>) -> Result<(), R::Error> {
         for port in ports {
-            self.remove(client_id, port).await?;
+            let Entry::Occupied(e) = self.subscriptions.entry(port) else {
+                continue;
+            };
+
+            let drained = match e.get() {
+                PortSubscription::Unfiltered(..) => false,
+                PortSubscription::Filtered(filters) => filters.is_empty(),
+                PortSubscription::Database(filters) => filters.is_empty(),
+            };
+            if !drained {
+                continue;
+            }
+
+            e.remove();
+            self.redirector.remove_redirection(port).await?;
+
+            if self.subscriptions.is_empty() {
+                self.redirector.cleanup().await?;
+            }
         }
 
         Ok(())
     }
 
+    /// Replace the subscription that the client already has on the given `port`, or add a new one
+    /// if it has none.
+    ///
+    /// Unlike removing the subscription and adding it again, this keeps the port redirected, so
+    /// that no connection is missed. Used when a client adopts the subscriptions of a previous
+    /// session (see [`LayerTcpSteal::Handoff`](mirrord_protocol::tcp::LayerTcpSteal::Handoff)).
+    ///
+    /// # Params
+    ///
+    /// * `client_id` - identifier of the client that issued the subscription
+    /// * `port` - number of the port to steal from
+    /// * `filter` - optional [`PortFilter`]
+    ///
+    /// # Warning
+    ///
+    /// If this method returns an [`Err`], it means that this set is out of sync with the inner
+    /// [`PortRedirector`] and it is no longer usable. It is a caller's responsibility to clean
+    /// up any external state.
+    pub async fn replace(
+        &mut self,
+        client_id: ClientId,
+        port: Port,
+        filter: Option<PortFilter>,
+    ) -> Result<RemoteResult<Port>, R::Error> {
+        let Some(subscription) = self
+            .subscriptions
+            .get_mut(&port)
+            .filter(|subscription| subscription.has_client(client_id))
+        else {
+            return self.add(client_id, port, filter).await;
+        };
+
+        let replacement = match (&*subscription, filter) {
+            // Connections stolen from this port share the filters, so they see the new one.
+            (PortSubscription::Filtered(filters), Some(PortFilter::Http(filter))) => {
+                filters.insert(client_id, filter);
+                None
+            }
+            (PortSubscription::Database(filters), Some(PortFilter::Database(filter)))
+                if filters.iter().all(|entry| {
+                    *entry.key() == client_id || entry.protocol == filter.protocol
+                }) =>
+            {
+                filters.insert(client_id, filter);
+                None
+            }
+            (PortSubscription::Filtered(..) | PortSubscription::Database(..), _)
+                if subscription.client_count() > 1 =>
+            {
+                return Ok(Err(ResponseError::PortAlreadyStolen(port)));
+            }
+            (_, filter) => Some(PortSubscription::new(client_id, filter)),
+        };
+
+        if let Some(replacement) = replacement {
+            *subscription = replacement;
+        }
+
+        Ok(Ok(port))
+    }
+
+    /// Return the ports on which the given client has subscriptions.
+    pub fn client_ports(&self, client_id: ClientId) -> HashSet<Port> {
+        self.subscriptions
+            .iter()
+            .filter_map(|(k, v)| v.has_client(client_id).then_some(*k))
+            .collect()
+    }
+
     /// Return a subscription for the given `port`.
     pub fn get(&self, port: Port) -> Option<&PortSubscription> {
         self.subscriptions.get(&port)
@@ -302,32 +441,69 @@ pub enum PortSubscription {
     ///
     /// Can be shared by multiple clients.
     Filtered(Arc<DashMap<ClientId, HttpFilter>>),
+    /// Only database connections with a login matching one of the [`DatabaseFilter`]s should be
+    /// stolen whole (on behalf of the filter owner).
+    ///
+    /// Can be shared by multiple clients, as long as their filters are for the same protocol.
+    Database(Arc<DashMap<ClientId, DatabaseFilter>>),
+}
+
+/// Filter of a [`PortSubscription`].
+#[derive(Debug)]
+pub enum PortFilter {
+    Http(HttpFilter),
+    Database(DatabaseFilter),
 }
 
 impl PortSubscription {
     /// Create a new instance. Variant is picked based on the optional `filter`.
-    fn new(client_id: ClientId, filter: Option<HttpFilter>) -> Self {
+    fn new(client_id: ClientId, filter: Option<PortFilter>) -> Self {
         match filter {
-            Some(filter) => Self::Filtered(Arc::new([(client_id, filter)].into_iter().collect())),
+            Some(PortFilter::Http(filter)) => {
+                Self::Filtered(Arc::new([(client_id, filter)].into_iter().collect()))
+            }
+            Some(PortFilter::Database(filter)) => {
+                Self::Database(Arc::new([(client_id, filter)].into_iter().collect()))
+            }
             None => Self::Unfiltered(client_id),
         }
     }
 
     /// Try extending this subscription with a new subscription request.
     /// Return whether extension was successful.
-    fn try_extend(&mut self, client_id: ClientId, filter: Option<HttpFilter>) -> bool {
+    fn try_extend(&mut self, client_id: ClientId, filter: Option<PortFilter>) -> bool {
         match (self, filter) {
             (_, None) => false,
 
             (Self::Unfiltered(..), _) => false,
 
-            (Self::Filtered(filters), Some(filter)) => match filters.entry(client_id) {
-                DashMapEntry::Occupied(..) => false,
-                DashMapEntry::Vacant(e) => {
-                    e.insert(filter);
-                    true
+            (Self::Filtered(filters), Some(PortFilter::Http(filter))) => {
+                match filters.entry(client_id) {
+                    DashMapEntry::Occupied(..) => false,
+                    DashMapEntry::Vacant(e) => {
+                        e.insert(filter);
+                        true
+                    }
                 }
-            },
+            }
+
+            // The protocol decides how the connections are read, so it must be the same in all
+            // filters.
+            (Self::Database(filters), Some(PortFilter::Database(filter)))
+                if filters
+                    .iter()
+                    .all(|entry| entry.protocol == filter.protocol) =>
+            {
+                match filters.entry(client_id) {
+                    DashMapEntry::Occupied(..) => false,
+                    DashMapEntry::Vacant(e) => {
+                        e.insert(filter);
+                        true
+                    }
+                }
+            }
+
+            (Self::Filtered(..) | Self::Database(..), Some(..)) => false,
         }
     }
 
@@ -335,15 +511,27 @@ impl PortSubscription {
     fn has_client(&self, client_id: ClientId) -> bool {
         match self {
             Self::Filtered(filters) => filters.contains_key(&client_id),
+            Self::Database(filters) => filters.contains_key(&client_id),
             Self::Unfiltered(subscribed_client) => *subscribed_client == client_id,
         }
     }
+
+    /// Return the number of clients that this subscription belongs to.
+    fn client_count(&self) -> usize {
+        match self {
+            Self::Filtered(filters) => filters.len(),
+            Self::Database(filters) => filters.len(),
+            Self::Unfiltered(..) => 1,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
 
+    use mirrord_protocol::tcp::{DatabaseProtocol, Filter};
+
     use super::*;
 
     /// Implementation of [`PortRedirector`] that stores redirections in memory.
@@ -411,8 +599,18 @@ mod test {
         }
     }
 
-    fn dummy_filter() -> HttpFilter {
-        HttpFilter::Header(".*".parse().unwrap())
+    fn dummy_filter() -> PortFilter {
+        PortFilter::Http(HttpFilter::Header(".*".parse().unwrap()))
+    }
+
+    fn dummy_database_filter(protocol: DatabaseProtocol) -> PortFilter {
+        let filter = mirrord_protocol::tcp::DatabaseFilter {
+            protocol,
+            database: Some(Filter::new("^orders$".to_string()).unwrap()),
+            user: None,
+        };
+
+        PortFilter::Database(DatabaseFilter::try_from(&filter).unwrap())
     }
 
     #[tokio::test]
@@ -644,4 +842,156 @@ mod test {
         let sub = subscriptions.get(81);
         assert!(sub.is_none(), "{sub:?}");
     }
+
+    #[tokio::test]
+    async fn replace_keeps_redirection() {
+        let redirector = DummyRedirector::default();
+        let mut subscriptions = PortSubscriptions::new(redirector, 8);
+
+        subscriptions.add(0, 80, None).await.unwrap().unwrap();
+        check_redirector!(subscriptions.redirector, 80);
+
+        // Unfiltered subscription replaced with a filtered one, without touching the redirection.
+        subscriptions
+            .replace(0, 80, Some(dummy_filter()))
+            .await
+            .unwrap()
+            .unwrap();
+        check_redirector!(subscriptions.redirector, 80);
+        let sub = subscriptions.get(80).unwrap();
+        assert!(
+            matches!(sub, PortSubscription::Filtered(filters) if filters.len() == 1),
+            "{sub:?}"
+        );
+
+        // Another client shares the port now.
+        subscriptions
+            .add(1, 80, Some(dummy_filter()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // So the subscription cannot become unfiltered.
+        assert_eq!(
+            subscriptions.replace(0, 80, None).await.unwrap(),
+            Err(ResponseError::PortAlreadyStolen(80)),
+        );
+        subscriptions
+            .replace(0, 80, Some(dummy_filter()))
+            .await
+            .unwrap()
+            .unwrap();
+        check_redirector!(subscriptions.redirector, 80);
+
+        // Client without a subscription on the port gets a new one.
+        subscriptions.replace(0, 81, None).await.unwrap().unwrap();
+        check_redirector!(subscriptions.redirector, 80, 81);
+
+        assert_eq!(subscriptions.client_ports(0), HashSet::from([80, 81]));
+        assert_eq!(subscriptions.client_ports(1), HashSet::from([80]));
+    }
+
+    #[tokio::test]
+    async fn drain_keeps_redirection() {
+        let redirector = DummyRedirector::default();
+        let mut subscriptions = PortSubscriptions::new(redirector, 8);
+
+        subscriptions.add(0, 80, None).await.unwrap().unwrap();
+        subscriptions
+            .add(0, 81, Some(dummy_filter()))
+            .await
+            .unwrap()
+            .unwrap();
+        subscriptions
+            .add(1, 81, Some(dummy_filter()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let drained = subscriptions.drain(0);
+        assert_eq!(drained, HashSet::from([80, 81]));
+
+        // The client no longer steals anything, but the ports remain redirected.
+        check_redirector!(subscriptions.redirector, 80, 81);
+        assert!(subscriptions.client_ports(0).is_empty());
+        let sub = subscriptions.get(80).unwrap();
+        assert!(
+            matches!(sub, PortSubscription::Filtered(filters) if filters.is_empty()),
+            "{sub:?}"
+        );
+        let sub = subscriptions.get(81).unwrap();
+        assert!(
+            matches!(sub, PortSubscription::Filtered(filters) if filters.len() == 1),
+            "{sub:?}"
+        );
+
+        // Unsubscribing during the drain does not remove the redirection.
+        subscriptions.remove(0, 80).await.unwrap();
+        check_redirector!(subscriptions.redirector, 80, 81);
+
+        // The other client keeps its subscription.
+        subscriptions.remove_drained(drained).await.unwrap();
+        check_redirector!(subscriptions.redirector, 81);
+        assert!(subscriptions.get(80).is_none());
+        assert_eq!(subscriptions.client_ports(1), HashSet::from([81]));
+
+        subscriptions.remove(1, 81).await.unwrap();
+        check_redirector!(subscriptions.redirector);
+        assert!(!subscriptions.redirector.dirty);
+    }
+
+    #[tokio::test]
+    async fn database_subscriptions() {
+        let redirector = DummyRedirector::default();
+        let mut subscriptions = PortSubscriptions::new(redirector, 8);
+
+        subscriptions
+            .add(
+                0,
+                5432,
+                Some(dummy_database_filter(DatabaseProtocol::Postgres)),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        subscriptions
+            .add(
+                1,
+                5432,
+                Some(dummy_database_filter(DatabaseProtocol::Postgres)),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        check_redirector!(subscriptions.redirector, 5432);
+        let sub = subscriptions.get(5432).unwrap();
+        assert!(
+            matches!(sub, PortSubscription::Database(filters) if filters.len() == 2),
+            "{sub:?}"
+        );
+
+        // Filters on the same port must be for the same protocol.
+        assert_eq!(
+            subscriptions
+                .add(2, 5432, Some(dummy_database_filter(DatabaseProtocol::Tds)))
+                .await
+                .unwrap(),
+            Err(ResponseError::PortAlreadyStolen(5432)),
+        );
+
+        // And cannot be mixed with HTTP filters.
+        assert_eq!(
+            subscriptions
+                .add(2, 5432, Some(dummy_filter()))
+                .await
+                .unwrap(),
+            Err(ResponseError::PortAlreadyStolen(5432)),
+        );
+
+        subscriptions.remove(0, 5432).await.unwrap();
+        check_redirector!(subscriptions.redirector, 5432);
+        subscriptions.remove(1, 5432).await.unwrap();
+        check_redirector!(subscriptions.redirector);
+        assert!(!subscriptions.redirector.dirty);
+    }
 }
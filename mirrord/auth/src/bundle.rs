@@ -0,0 +1,210 @@
+use std::{collections::HashMap, fmt::Debug, num::NonZeroU32};
+
+use kube::{Client, Resource};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    credentials::Credentials,
+    error::{CredentialBundleError, CredentialStoreError},
+    key_pair::KeyPair,
+};
+
+/// Tag of the PEM document that holds an encrypted [`CredentialBundle`].
+const PEM_TAG: &str = "MIRRORD CREDENTIAL BUNDLE";
+
+/// Version of the encrypted format, the first byte of the PEM contents.
+const FORMAT_VERSION: u8 = 1;
+
+/// Length of the random salt used to derive the encryption key from the passphrase.
+const SALT_LEN: usize = 16;
+
+/// PBKDF2 iterations used to derive the encryption key from the passphrase.
+const PBKDF2_ITERATIONS: NonZeroU32 = match NonZeroU32::new(600_000) {
+    Some(iterations) => iterations,
+    None => unreachable!(),
+};
+
+/// [`Credentials`] moved between machines, e.g. with `mirrord auth export` and `mirrord auth
+/// import`, or issued by an admin for another user with `mirrord auth bootstrap`.
+///
+/// Holds the same entries as the
+/// [`CredentialStore`](crate::credential_store::CredentialStore), and is encrypted with a
+/// passphrase (AES-256-GCM, with the key derived by PBKDF2-HMAC-SHA256) into a PEM document.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct CredentialBundle {
+    /// [`Credentials`] by operator license fingerprint.
+    #[serde(default)]
+    pub(crate) credentials: HashMap<String, Credentials>,
+    /// [`KeyPair`]s by operator subscription id.
+    #[serde(default)]
+    pub(crate) signing_keys: HashMap<String, KeyPair>,
+}
+
+impl CredentialBundle {
+    /// Requests a new [`Certificate`](crate::certificate::Certificate) from the operator for
+    /// another user, signed with a new random [`KeyPair`].
+    ///
+    /// The user gets the bundle with `mirrord auth import`, and doesn't have to request the
+    /// certificate on the first run.
+    pub async fn issue<R>(
+        client: Client,
+        common_name: &str,
+        operator_fingerprint: String,
+        operator_subscription_id: Option<String>,
+    ) -> Result<Self, CredentialStoreError>
+    where
+        R: Resource + Clone + Debug,
+        R: for<'de> Deserialize<'de>,
+        R::DynamicType: Default,
+    {
+        let credentials = Credentials::init::<R>(client, common_name, None).await?;
+
+        let signing_keys = operator_subscription_id
+            .map(|id| (id, credentials.key_pair().clone()))
+            .into_iter()
+            .collect();
+
+        Ok(Self {
+            credentials: HashMap::from([(operator_fingerprint, credentials)]),
+            signing_keys,
+        })
+    }
+
+    /// Fingerprints of the operator licenses this bundle has [`Credentials`] for.
+    pub fn operator_fingerprints(&self) -> impl Iterator<Item = &str> {
+        self.credentials.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.credentials.is_empty() && self.signing_keys.is_empty()
+    }
+
+    /// Serializes and encrypts this bundle into a PEM document.
+    pub fn encrypt(&self, passphrase: &str) -> Result<String, CredentialBundleError> {
+        let random = SystemRandom::new();
+
+        let mut salt = [0; SALT_LEN];
+        random
+            .fill(&mut salt)
+            .map_err(|_| CredentialBundleError::Encrypt)?;
+        let mut nonce = [0; NONCE_LEN];
+        random
+            .fill(&mut nonce)
+            .map_err(|_| CredentialBundleError::Encrypt)?;
+
+        let mut in_out = serde_yaml::to_string(self)?.into_bytes();
+        Self::key(passphrase, &salt)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(PEM_TAG),
+                &mut in_out,
+            )
+            .map_err(|_| CredentialBundleError::Encrypt)?;
+
+        let contents = [&[FORMAT_VERSION], &salt[..], &nonce[..], &in_out].concat();
+
+        Ok(pem::encode(&pem::Pem::new(PEM_TAG, contents)))
+    }
+
+    /// Decrypts and deserializes a bundle from a PEM document made by [`Self::encrypt`].
+    pub fn decrypt(document: &str, passphrase: &str) -> Result<Self, CredentialBundleError> {
+        let document = pem::parse(document)?;
+        if document.tag() != PEM_TAG {
+            return Err(CredentialBundleError::Malformed);
+        }
+
+        let (version, rest) = document
+            .contents()
+            .split_first()
+            .ok_or(CredentialBundleError::Malformed)?;
+        if *version != FORMAT_VERSION {
+            return Err(CredentialBundleError::UnsupportedVersion(*version));
+        }
+
+        let (salt, rest) = rest
+            .split_at_checked(SALT_LEN)
+            .ok_or(CredentialBundleError::Malformed)?;
+        let (nonce, ciphertext) = rest
+            .split_at_checked(NONCE_LEN)
+            .ok_or(CredentialBundleError::Malformed)?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| CredentialBundleError::Malformed)?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = Self::key(passphrase, salt)?
+            .open_in_place(nonce, Aad::from(PEM_TAG), &mut in_out)
+            .map_err(|_| CredentialBundleError::Decrypt)?;
+
+        Ok(serde_yaml::from_slice(plaintext)?)
+    }
+
+    /// Derives the encryption key from the passphrase.
+    fn key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey, CredentialBundleError> {
+        let mut key = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            PBKDF2_ITERATIONS,
+            salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+
+        UnboundKey::new(&AES_256_GCM, &key)
+            .map(LessSafeKey::new)
+            .map_err(|_| CredentialBundleError::Encrypt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::CredentialBundle;
+    use crate::{error::CredentialBundleError, key_pair::KeyPair};
+
+    fn bundle() -> CredentialBundle {
+        CredentialBundle {
+            credentials: HashMap::new(),
+            signing_keys: HashMap::from([("sub".to_string(), KeyPair::new_random().unwrap())]),
+        }
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let bundle = bundle();
+
+        let document = bundle.encrypt("correct horse").unwrap();
+        assert!(document.starts_with("-----BEGIN MIRRORD CREDENTIAL BUNDLE-----"));
+
+        let decrypted = CredentialBundle::decrypt(&document, "correct horse").unwrap();
+        assert_eq!(
+            decrypted.signing_keys.get("sub").unwrap().document(),
+            bundle.signing_keys.get("sub").unwrap().document()
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let document = bundle().encrypt("correct horse").unwrap();
+
+        assert!(matches!(
+            CredentialBundle::decrypt(&document, "battery staple"),
+            Err(CredentialBundleError::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn other_pem_document() {
+        let key_pair = KeyPair::new_random().unwrap();
+
+        assert!(matches!(
+            CredentialBundle::decrypt(key_pair.document(), "correct horse"),
+            Err(CredentialBundleError::Malformed)
+        ));
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     fmt::Debug,
     path::PathBuf,
     sync::LazyLock,
@@ -15,8 +15,8 @@ use tokio::{
 use whoami::fallible;
 
 use crate::{
-    certificate::Certificate, credentials::Credentials, error::CredentialStoreError,
-    key_pair::KeyPair,
+    bundle::CredentialBundle, certificate::Certificate, credentials::Credentials,
+    error::CredentialStoreError, key_pair::KeyPair,
 };
 
 /// "~/.mirrord"
@@ -41,6 +41,12 @@ pub struct CredentialStore {
     /// changes, but it belongs to the same subscription.
     #[serde(default)]
     signing_keys: HashMap<String, KeyPair>,
+    /// Operator session extensions (e.g. feature grants of operator add-ons) received from the
+    /// operator, by the operator license fingerprint, like [`Self::credentials`].
+    ///
+    /// Sent back to the operator with the next sessions.
+    #[serde(default)]
+    extensions: HashMap<String, BTreeMap<String, String>>,
 }
 
 /// Information about user gathered from the local system to be shared with the operator
@@ -161,6 +167,47 @@ impl CredentialStore {
 
         Ok(credentials)
     }
+
+    /// Copies the [`Credentials`] (all of them, or only the one for `operator_fingerprint`) and
+    /// their signing [`KeyPair`]s into a [`CredentialBundle`].
+    fn export(&self, operator_fingerprint: Option<&str>) -> CredentialBundle {
+        let credentials: HashMap<_, _> = self
+            .credentials
+            .iter()
+            .filter(|(fingerprint, _)| {
+                operator_fingerprint.is_none_or(|wanted| wanted == fingerprint.as_str())
+            })
+            .map(|(fingerprint, credentials)| (fingerprint.clone(), credentials.clone()))
+            .collect();
+
+        let signing_keys = self
+            .signing_keys
+            .iter()
+            .filter(|(_, key_pair)| {
+                credentials
+                    .values()
+                    .any(|credentials| credentials.key_pair().document() == key_pair.document())
+            })
+            .map(|(subscription_id, key_pair)| (subscription_id.clone(), key_pair.clone()))
+            .collect();
+
+        CredentialBundle {
+            credentials,
+            signing_keys,
+        }
+    }
+
+    /// Adds the contents of the [`CredentialBundle`] to this store.
+    ///
+    /// [`Credentials`] from the bundle replace the ones we have for the same operator license, but
+    /// signing [`KeyPair`]s we already have for a subscription are kept.
+    fn import(&mut self, bundle: CredentialBundle) {
+        self.credentials.extend(bundle.credentials);
+
+        for (subscription_id, key_pair) in bundle.signing_keys {
+            self.signing_keys.entry(subscription_id).or_insert(key_pair);
+        }
+    }
 }
 
 /// Exposes methods to safely access [`CredentialStore`] stored in a file.
@@ -225,6 +272,95 @@ impl CredentialStoreSync {
         Ok(value)
     }
 
+    /// Loads the [`CredentialStore`] with an exclusive lock on the file, and saves it after
+    /// `callback` is done with it.
+    async fn access_store<C, V>(&mut self, callback: C) -> Result<V, CredentialStoreError>
+    where
+        C: FnOnce(&mut CredentialStore) -> V,
+    {
+        self.store_file
+            .lock_exclusive()
+            .map_err(CredentialStoreError::Lockfile)?;
+
+        let result = async {
+            let mut store = CredentialStore::load(&mut self.store_file)
+                .await
+                .inspect_err(|error| tracing::warn!(%error, "CredentialStore load failed"))
+                .unwrap_or_default();
+
+            let value = callback(&mut store);
+
+            self.store_file
+                .seek(SeekFrom::Start(0))
+                .await
+                .map_err(CredentialStoreError::FileAccess)?;
+            self.store_file
+                .set_len(0)
+                .await
+                .map_err(CredentialStoreError::FileAccess)?;
+
+            store.save(&mut self.store_file).await?;
+
+            Ok(value)
+        }
+        .await;
+
+        self.store_file
+            .unlock()
+            .map_err(CredentialStoreError::Lockfile)?;
+
+        result
+    }
+
+    /// Returns the operator session extensions cached for the operator license
+    /// `operator_fingerprint`.
+    pub async fn cached_extensions(
+        &mut self,
+        operator_fingerprint: &str,
+    ) -> Result<BTreeMap<String, String>, CredentialStoreError> {
+        self.access_store(|store| {
+            store
+                .extensions
+                .get(operator_fingerprint)
+                .cloned()
+                .unwrap_or_default()
+        })
+        .await
+    }
+
+    /// Replaces the operator session extensions cached for the operator license
+    /// `operator_fingerprint`.
+    pub async fn cache_extensions(
+        &mut self,
+        operator_fingerprint: String,
+        extensions: BTreeMap<String, String>,
+    ) -> Result<(), CredentialStoreError> {
+        self.access_store(|store| {
+            if extensions.is_empty() {
+                store.extensions.remove(&operator_fingerprint);
+            } else {
+                store.extensions.insert(operator_fingerprint, extensions);
+            }
+        })
+        .await
+    }
+
+    /// Exports the stored [`Credentials`] (all of them, or only the one for
+    /// `operator_fingerprint`), see [`CredentialBundle`].
+    pub async fn export(
+        &mut self,
+        operator_fingerprint: Option<&str>,
+    ) -> Result<CredentialBundle, CredentialStoreError> {
+        self.access_store(|store| store.export(operator_fingerprint))
+            .await
+    }
+
+    /// Imports the [`Credentials`] from a [`CredentialBundle`], replacing the stored ones for the
+    /// same operator licenses.
+    pub async fn import(&mut self, bundle: CredentialBundle) -> Result<(), CredentialStoreError> {
+        self.access_store(|store| store.import(bundle)).await
+    }
+
     /// Get or create specific client certificate with an exclusive lock on the file.
     pub async fn get_client_certificate<R>(
         &mut self,
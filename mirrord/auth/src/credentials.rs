@@ -12,7 +12,7 @@ use crate::{certificate::Certificate, key_pair::KeyPair};
 
 /// Client credentials container for authentication with the operator.
 /// Contains a local [`KeyPair`] and an optional [`Certificate`].
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Credentials {
     /// Certificate generated by the operator based on the sent [`rfc2986::CertificationRequest`].
     certificate: Certificate,
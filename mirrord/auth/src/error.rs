@@ -24,3 +24,26 @@ pub enum CredentialStoreError {
     #[error("certification request failed: {0}")]
     Kube(#[from] kube::Error),
 }
+
+/// Errors from [`CredentialBundle`](crate::bundle::CredentialBundle) operations
+#[cfg(feature = "client")]
+#[derive(Debug, Error)]
+pub enum CredentialBundleError {
+    #[error("failed to serialize/deserialize credential bundle: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("failed to parse credential bundle PEM: {0}")]
+    Pem(#[from] pem::PemError),
+
+    #[error("credential bundle version {0} is not supported, update mirrord")]
+    UnsupportedVersion(u8),
+
+    #[error("credential bundle is malformed")]
+    Malformed,
+
+    #[error("failed to decrypt credential bundle, check the passphrase")]
+    Decrypt,
+
+    #[error("failed to encrypt credential bundle")]
+    Encrypt,
+}
@@ -3,6 +3,9 @@
 pub use pem;
 pub use x509_certificate;
 
+/// Passphrase encrypted credentials, for moving them between machines
+#[cfg(feature = "client")]
+pub mod bundle;
 /// X509 Certificate abstraction for serialization and deserialization
 pub mod certificate;
 /// FileSystem based storage for multiple credentials (default contents "~/.mirrord/credentials")
@@ -2,6 +2,24 @@ use std::process::exit;
 
 fn main() {
     println!("cargo::rerun-if-env-changed=MIRRORD_LAYER_FILE_MACOS_ARM64");
+    println!("cargo::rerun-if-env-changed=MIRRORD_LAYER_FILE_MUSL");
+    println!("cargo::rustc-check-cfg=cfg(musl_layer)");
+
+    // The layer compiled for the `*-unknown-linux-musl` target is optional, when present it's
+    // embedded next to the default one and used on musl based distros (e.g. Alpine).
+    if let Ok(musl_layer_file) = std::env::var("MIRRORD_LAYER_FILE_MUSL") {
+        println!("cargo:rustc-env=MIRRORD_LAYER_FILE_MUSL={musl_layer_file}");
+        println!("cargo::rustc-cfg=musl_layer");
+    }
+    // this check uses cargo env vars instead of conditional compilation due to cfg! not respecting
+    // the target flag on a build
+    if std::env::var("MIRRORD_LAYER_FILE").is_err()
+        && std::env::var("CARGO_CFG_TARGET_OS").is_ok_and(|t| t.eq("windows"))
+    {
+        println!("cargo::warning=No environment variable 'MIRRORD_LAYER_FILE' found - on Windows it should contain the path to the mirrord layer compiled for the `x86_64-unknown-linux-gnu` target, which is used inside WSL");
+        exit(1);
+    };
+
     if std::env::var("MIRRORD_LAYER_FILE").is_err() {
         println!(
             "cargo:rustc-env=MIRRORD_LAYER_FILE={}",
@@ -0,0 +1,178 @@
+//! Moving the operator credentials between machines, see [`auth_command`].
+//!
+//! The credentials travel in [`CredentialBundle`]s, encrypted with a passphrase, so that they can
+//! be imported on machines that can't request certificates from the operator themselves (e.g.
+//! onboarding a new user of an air-gapped cluster).
+use std::{env, path::Path};
+
+use mirrord_analytics::NullReporter;
+use mirrord_auth::{bundle::CredentialBundle, credential_store::CredentialStoreSync};
+use mirrord_operator::{client::OperatorApi, crd::MirrordOperatorCrd};
+use mirrord_progress::{Progress, ProgressTracker};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::Level;
+
+use crate::{
+    operator::load_layer_config, AuthArgs, AuthCommand, AuthPassphraseArgs, CliError, CliResult,
+};
+
+/// Env var with the passphrase of the [`CredentialBundle`], when `--passphrase-file` is not
+/// given.
+pub(crate) const PASSPHRASE_ENV: &str = "MIRRORD_AUTH_PASSPHRASE";
+
+/// Handle commands related to the operator credentials, e.g. `mirrord auth export`.
+pub(crate) async fn auth_command(args: AuthArgs) -> CliResult<()> {
+    match args.command {
+        AuthCommand::Export {
+            output,
+            license_fingerprint,
+            passphrase,
+        } => export(&output, license_fingerprint.as_deref(), &passphrase).await,
+        AuthCommand::Import { input, passphrase } => import(&input, &passphrase).await,
+        AuthCommand::Bootstrap {
+            user,
+            output,
+            config_file,
+            passphrase,
+        } => bootstrap(&user, &output, config_file.as_deref(), &passphrase).await,
+    }
+}
+
+/// Reads the passphrase from `--passphrase-file`, or from [`PASSPHRASE_ENV`].
+///
+/// Trailing newlines are not part of the passphrase.
+async fn read_passphrase(args: &AuthPassphraseArgs) -> CliResult<String> {
+    let passphrase = match &args.passphrase_file {
+        Some(path) => fs::read_to_string(path)
+            .await
+            .map_err(|error| CliError::AuthFileFailed(path.clone(), error))?,
+        None => env::var(PASSPHRASE_ENV).unwrap_or_default(),
+    };
+
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    if passphrase.is_empty() {
+        return Err(CliError::AuthPassphraseMissing);
+    }
+
+    Ok(passphrase.to_string())
+}
+
+/// Encrypts the [`CredentialBundle`] and writes it to `output`, readable only by the user.
+async fn write_bundle(bundle: &CredentialBundle, output: &Path, passphrase: &str) -> CliResult<()> {
+    let document = bundle.encrypt(passphrase)?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let result: Result<(), std::io::Error> = try {
+        let mut file = options.open(output).await?;
+        file.write_all(document.as_bytes()).await?;
+        file.flush().await?;
+    };
+
+    result.map_err(|error| CliError::AuthFileFailed(output.to_path_buf(), error))
+}
+
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn export(
+    output: &Path,
+    license_fingerprint: Option<&str>,
+    passphrase: &AuthPassphraseArgs,
+) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord auth export");
+    let passphrase = read_passphrase(passphrase).await?;
+
+    let bundle = CredentialStoreSync::open()
+        .await
+        .map_err(CliError::CredentialStoreFailed)?
+        .export(license_fingerprint)
+        .await
+        .map_err(CliError::CredentialStoreFailed)?;
+    if bundle.is_empty() {
+        return Err(CliError::AuthNothingToExport);
+    }
+
+    write_bundle(&bundle, output, &passphrase).await?;
+
+    progress.success(Some(&format!(
+        "exported credentials for {} operator license(s) to `{}`",
+        bundle.operator_fingerprints().count(),
+        output.display()
+    )));
+
+    Ok(())
+}
+
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn import(input: &Path, passphrase: &AuthPassphraseArgs) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord auth import");
+    let passphrase = read_passphrase(passphrase).await?;
+
+    let document = fs::read_to_string(input)
+        .await
+        .map_err(|error| CliError::AuthFileFailed(input.to_path_buf(), error))?;
+    let bundle = CredentialBundle::decrypt(&document, &passphrase)?;
+    let licenses = bundle.operator_fingerprints().count();
+
+    CredentialStoreSync::open()
+        .await
+        .map_err(CliError::CredentialStoreFailed)?
+        .import(bundle)
+        .await
+        .map_err(CliError::CredentialStoreFailed)?;
+
+    progress.success(Some(&format!(
+        "imported credentials for {licenses} operator license(s)"
+    )));
+
+    Ok(())
+}
+
+/// Requests a certificate from the operator for `user`, without touching the local credential
+/// store.
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn bootstrap(
+    user: &str,
+    output: &Path,
+    config_file: Option<&Path>,
+    passphrase: &AuthPassphraseArgs,
+) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord auth bootstrap");
+    // Read it before requesting the certificate, so that we don't issue one for nothing.
+    let passphrase = read_passphrase(passphrase).await?;
+
+    let layer_config = load_layer_config(config_file)?;
+
+    let mut subtask = progress.subtask("issuing credentials");
+    let Some(api) = OperatorApi::try_new(&layer_config, &mut NullReporter::default()).await? else {
+        subtask.failure(Some("operator not found"));
+        return Err(CliError::OperatorNotInstalled);
+    };
+
+    let license = &api.operator().spec.license;
+    let fingerprint = license
+        .fingerprint
+        .clone()
+        .ok_or(CliError::AuthLicenseFingerprintMissing)?;
+
+    let bundle = CredentialBundle::issue::<MirrordOperatorCrd>(
+        api.client().clone(),
+        user,
+        fingerprint,
+        license.subscription_id.clone(),
+    )
+    .await
+    .map_err(|error| CliError::AuthBootstrapFailed(user.to_string(), error))?;
+    subtask.success(Some(&format!("issued credentials for `{user}`")));
+
+    write_bundle(&bundle, output, &passphrase).await?;
+
+    progress.success(Some(&format!(
+        "wrote credentials for `{user}` to `{}`, import them with `mirrord auth import`",
+        output.display()
+    )));
+
+    Ok(())
+}
@@ -0,0 +1,304 @@
+//! Checks that the operator or agent supports the features enabled in the config, see
+//! [`check_compatibility`].
+//!
+//! The check is based on the [`mirrord_protocol`] version of the remote end of the session.
+//! Without it, an outdated component fails later on with a cryptic decode error, or
+//! silently ignores the feature.
+use std::fmt;
+
+use mirrord_config::LayerConfig;
+use mirrord_intproxy::agent_conn::AgentConnectInfo;
+use mirrord_progress::Progress;
+use mirrord_protocol::{
+    file::SCRATCH_DIR_VERSION,
+    tcp::{
+        DATABASE_FILTER_VERSION, HTTP_COMPOSITE_FILTER_VERSION, HTTP_CONNECTION_METADATA_VERSION,
+        PORT_RESERVATION_VERSION, STEAL_DRAIN_VERSION, STEAL_FALLBACK_VERSION,
+        STEAL_HANDOFF_VERSION,
+    },
+    FREEZE_TARGET_VERSION,
+};
+use semver::{Version, VersionReq};
+
+use crate::{
+    error::{CliError, CliResult},
+    internal_proxy::{freeze_target, scratch_dir, steal_drain, steal_fallback, steal_handoff},
+};
+
+/// The component that the CLI exchanges [`mirrord_protocol`] messages with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RemoteComponent {
+    Operator,
+    Agent,
+}
+
+impl RemoteComponent {
+    pub(crate) fn from_connect_info(connect_info: &AgentConnectInfo) -> Self {
+        match connect_info {
+            AgentConnectInfo::Operator(..) => Self::Operator,
+            _ => Self::Agent,
+        }
+    }
+
+    /// How to get a newer version of this component.
+    fn update_hint(self) -> &'static str {
+        match self {
+            Self::Operator => "Please update the mirrord operator in your cluster.",
+            Self::Agent => {
+                "Please update the agent image in `agent.image`, or remove it from the config \
+                to use the one that matches this mirrord version."
+            }
+        }
+    }
+}
+
+impl fmt::Display for RemoteComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Operator => f.write_str("mirrord-operator"),
+            Self::Agent => f.write_str("mirrord-agent"),
+        }
+    }
+}
+
+/// A feature enabled in the config that needs support from the [`RemoteComponent`].
+#[derive(Debug, Clone, Copy)]
+struct Requirement {
+    /// Config key that enables the feature.
+    key: &'static str,
+    /// [`mirrord_protocol`] versions that support the feature.
+    versions: &'static VersionReq,
+    /// What happens when the feature is not supported, if the session can run without it.
+    ///
+    /// [`None`] means that we can't start the session.
+    degraded: Option<&'static str>,
+}
+
+/// Returns the compatibility matrix entries for the features enabled in `config`.
+fn requirements(config: &LayerConfig) -> Vec<Requirement> {
+    let incoming = &config.feature.network.incoming;
+    let http_filter = &incoming.http_filter;
+
+    let mut requirements = Vec::new();
+
+    if http_filter.is_composite() {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.http_filter.{any_of,all_of}",
+            versions: &HTTP_COMPOSITE_FILTER_VERSION,
+            degraded: None,
+        });
+    }
+
+    if steal_handoff(config).is_some() {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.handoff",
+            versions: &STEAL_HANDOFF_VERSION,
+            degraded: Some("subscriptions will not be handed off between sessions"),
+        });
+    }
+
+    if steal_fallback(config).is_some() {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.http_filter.fallback",
+            versions: &STEAL_FALLBACK_VERSION,
+            degraded: Some("requests that the local application fails to respond to will fail"),
+        });
+    }
+
+    if incoming.is_steal()
+        && http_filter.is_filter_set()
+        && (incoming.proxy_protocol || incoming.forwarded_headers.is_some())
+    {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.{proxy_protocol,forwarded_headers}",
+            versions: &HTTP_CONNECTION_METADATA_VERSION,
+            degraded: Some("the original client address will not be passed to the application"),
+        });
+    }
+
+    if steal_drain(config).is_some() {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.drain_timeout_ms",
+            versions: &STEAL_DRAIN_VERSION,
+            degraded: Some("in-flight stolen requests will fail when the session ends"),
+        });
+    }
+
+    if freeze_target(config) {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.freeze_target",
+            versions: &FREEZE_TARGET_VERSION,
+            degraded: Some("the target will keep running while the traffic is stolen"),
+        });
+    }
+
+    if incoming.is_steal() && incoming.database_filter.is_some() {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.database_filter",
+            versions: &DATABASE_FILTER_VERSION,
+            degraded: None,
+        });
+    }
+
+    if !incoming.reserved_ports.is_empty() {
+        requirements.push(Requirement {
+            key: "feature.network.incoming.reserved_ports",
+            versions: &PORT_RESERVATION_VERSION,
+            degraded: Some("the reserved listeners will only be reachable locally"),
+        });
+    }
+
+    if scratch_dir(config).is_some() {
+        requirements.push(Requirement {
+            key: "feature.fs.scratch",
+            versions: &SCRATCH_DIR_VERSION,
+            degraded: Some("paths in `feature.fs.scratch` will be written to the target"),
+        });
+    }
+
+    requirements
+}
+
+/// Returns the [`Requirement`]s of `config` that are not met by `protocol_version`.
+fn unsupported<'a>(
+    config: &LayerConfig,
+    protocol_version: Option<&'a Version>,
+) -> impl Iterator<Item = Requirement> + 'a {
+    requirements(config).into_iter().filter(move |requirement| {
+        !protocol_version.is_some_and(|version| requirement.versions.matches(version))
+    })
+}
+
+/// Describes why `remote` does not meet `requirement`.
+fn mismatch_message(
+    remote: RemoteComponent,
+    protocol_version: Option<&Version>,
+    requirement: &Requirement,
+) -> String {
+    let found = match protocol_version {
+        Some(version) => format!("uses mirrord-protocol {version}"),
+        None => "is too old to report its mirrord-protocol version".to_string(),
+    };
+
+    format!(
+        "{remote} {found}, but `{}` requires mirrord-protocol {}",
+        requirement.key, requirement.versions
+    )
+}
+
+/// Checks the features enabled in `config` against the [`mirrord_protocol`] version of the
+/// remote end of the session.
+///
+/// Features that the session can run without produce a warning, and the first one that it
+/// can't run without produces [`CliError::RemoteComponentTooOld`].
+pub(crate) fn check_compatibility<P: Progress>(
+    config: &LayerConfig,
+    remote: RemoteComponent,
+    protocol_version: Option<&Version>,
+    progress: &P,
+) -> CliResult<()> {
+    for requirement in unsupported(config, protocol_version) {
+        let message = mismatch_message(remote, protocol_version, &requirement);
+
+        match requirement.degraded {
+            Some(degraded) => {
+                tracing::warn!(
+                    %remote,
+                    ?protocol_version,
+                    key = requirement.key,
+                    "Feature not supported by the remote component"
+                );
+                progress.warning(&format!("{message}, {degraded}. {}", remote.update_hint()));
+            }
+            None => {
+                return Err(CliError::RemoteComponentTooOld {
+                    message,
+                    hint: remote.update_hint(),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mirrord_config::{
+        config::{ConfigContext, MirrordConfig},
+        LayerFileConfig,
+    };
+    use rstest::rstest;
+
+    use super::*;
+
+    fn config(contents: &str) -> LayerConfig {
+        let file_config: LayerFileConfig = serde_json::from_str(contents).unwrap();
+        file_config
+            .generate_config(&mut ConfigContext::default())
+            .unwrap()
+    }
+
+    #[rstest]
+    #[case::supported(Some("1.20.0"), 0)]
+    #[case::handoff_supported(Some("1.14.0"), 1)]
+    #[case::old(Some("1.13.0"), 2)]
+    #[case::unknown(None, 2)]
+    fn degraded_features(#[case] version: Option<&str>, #[case] expected: usize) {
+        let config = config(
+            r#"{
+                "feature": {
+                    "network": {
+                        "incoming": {
+                            "mode": "steal",
+                            "handoff": true,
+                            "http_filter": {
+                                "header_filter": "x-user: me",
+                                "fallback": "passthrough-to-remote"
+                            }
+                        }
+                    }
+                }
+            }"#,
+        );
+        let version = version.map(|version| version.parse::<Version>().unwrap());
+
+        let unsupported = unsupported(&config, version.as_ref())
+            .filter(|requirement| requirement.degraded.is_some())
+            .count();
+
+        assert_eq!(unsupported, expected);
+    }
+
+    #[test]
+    fn composite_filter_is_fatal() {
+        let config = config(
+            r#"{
+                "feature": {
+                    "network": {
+                        "incoming": {
+                            "mode": "steal",
+                            "http_filter": {
+                                "any_of": [{ "path": "/api" }]
+                            }
+                        }
+                    }
+                }
+            }"#,
+        );
+
+        let error = check_compatibility(
+            &config,
+            RemoteComponent::Operator,
+            Some(&"1.10.0".parse().unwrap()),
+            &mirrord_progress::NullProgress,
+        )
+        .unwrap_err();
+
+        let CliError::RemoteComponentTooOld { message, .. } = error else {
+            panic!("unexpected error: {error:?}");
+        };
+        assert!(message.starts_with("mirrord-operator uses mirrord-protocol 1.10.0"));
+        assert!(message.contains("any_of,all_of"));
+    }
+}
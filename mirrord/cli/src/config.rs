@@ -6,6 +6,7 @@ use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
     str::FromStr,
+    time::Duration,
 };
 
 use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum, ValueHint};
@@ -28,6 +29,11 @@ Join our Discord server at https://discord.gg/metalbear, create a GitHub issue a
 pub(super) struct Cli {
     #[command(subcommand)]
     pub(super) commands: Commands,
+
+    /// Print progress as plain lines, without colors or spinners (same as setting
+    /// `MIRRORD_PROGRESS_MODE=plain`).
+    #[arg(long, global = true)]
+    pub(super) plain: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -85,9 +91,45 @@ pub(super) enum Commands {
     /// Diagnostic commands
     Diagnose(Box<DiagnoseArgs>),
 
+    /// Bundle information about your mirrord setup (versions, config, connection details and
+    /// logs) into an archive that can be attached to bug reports. Secrets are redacted.
+    Report(Box<ReportArgs>),
+
     /// Run mirrord vpn
     #[command(hide = true)]
     Vpn(Box<VpnArgs>),
+
+    /// Cluster DNS commands, e.g. export the names of the cluster services for local tools that
+    /// can't run with mirrord.
+    Dns(Box<DnsArgs>),
+
+    /// Change the features of a running session without ending it, e.g. stop stealing traffic
+    /// during an incident.
+    Toggle(Box<ToggleArgs>),
+
+    /// Run the executions of a CronJob locally: its remote schedule is suspended, and each tick
+    /// runs the job's command with mirrord, in the context of the job. The schedule is restored
+    /// when the command ends.
+    Cron(Box<CronArgs>),
+
+    /// Operator credential commands, e.g. move the credentials of this machine to another one,
+    /// or pre-issue credentials for a new user of an air-gapped cluster.
+    Auth(Box<AuthArgs>),
+
+    /// Check that mirrord works on this machine and in the cluster: runs a built-in test process
+    /// with mirrord, which exercises each feature, and prints which ones work.
+    Selftest(Box<SelftestArgs>),
+
+    /// Test process of `mirrord selftest`, runs with mirrord and reports what it observed.
+    #[command(hide = true, name = "selftest-probe")]
+    SelftestProbe,
+
+    /// Send synthetic HTTP load to the local application, delivered the same way as the requests
+    /// stolen by mirrord, to check how it handles the traffic before stealing the real one.
+    Loadgen(Box<LoadgenArgs>),
+
+    /// Configuration commands, e.g. explain where the file operations on a path go.
+    Config(Box<ConfigArgs>),
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
@@ -101,6 +143,9 @@ pub enum FsMode {
     Local,
     /// Read & Write from local, apart from overrides (hardcoded and configured in file)
     LocalWithOverrides,
+    /// Read from remote, Write to a local overlay, apart from overrides (hardcoded and configured
+    /// in file)
+    CopyOnWrite,
 }
 
 impl core::fmt::Display for FsMode {
@@ -110,6 +155,7 @@ impl core::fmt::Display for FsMode {
             FsMode::LocalWithOverrides => "localwithoverrides",
             FsMode::Read => "read",
             FsMode::Write => "write",
+            FsMode::CopyOnWrite => "copyonwrite",
         })
     }
 }
@@ -173,6 +219,11 @@ pub(super) struct ExecParams {
     #[arg(long = "steal")]
     pub tcp_steal: bool,
 
+    /// Join an operator session shared with `mirrord operator session share`, observing the
+    /// traffic it steals. Takes the share link printed by the owner of the session.
+    #[arg(long, value_name = "LINK", conflicts_with = "tcp_steal")]
+    pub join_session: Option<String>,
+
     /// Disable tcp/udp outgoing traffic
     #[arg(long)]
     pub no_outgoing: bool,
@@ -200,6 +251,18 @@ pub(super) struct ExecParams {
     /// Kube context to use from Kubeconfig
     #[arg(long)]
     pub context: Option<String>,
+
+    /// HTTP(S) or SOCKS5 proxy to use for connections to the Kubernetes API.
+    #[arg(long)]
+    pub kube_proxy: Option<String>,
+
+    /// PEM file with extra CA certificates to trust when connecting to the Kubernetes API.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub kube_ca_bundle: Option<PathBuf>,
+
+    /// Name used to verify the Kubernetes API server certificate.
+    #[arg(long)]
+    pub kube_tls_server_name: Option<String>,
 }
 
 impl ExecParams {
@@ -248,6 +311,10 @@ impl ExecParams {
             envs.insert("MIRRORD_FILE_MODE".into(), fs_mode.to_string().into());
         }
 
+        if let Some(link) = &self.join_session {
+            envs.insert("MIRRORD_JOIN_SESSION".into(), link.into());
+        }
+
         if let Some(override_env_vars_exclude) = &self.override_env_vars_exclude {
             envs.insert(
                 "MIRRORD_OVERRIDE_ENV_VARS_EXCLUDE".into(),
@@ -297,6 +364,24 @@ impl ExecParams {
             envs.insert("MIRRORD_KUBE_CONTEXT".into(), context.into());
         }
 
+        if let Some(kube_proxy) = &self.kube_proxy {
+            envs.insert("MIRRORD_KUBE_PROXY".into(), kube_proxy.into());
+        }
+
+        if let Some(kube_ca_bundle) = &self.kube_ca_bundle {
+            envs.insert(
+                "MIRRORD_KUBE_CA_BUNDLE".into(),
+                kube_ca_bundle.as_os_str().to_owned(),
+            );
+        }
+
+        if let Some(kube_tls_server_name) = &self.kube_tls_server_name {
+            envs.insert(
+                "MIRRORD_KUBE_TLS_SERVER_NAME".into(),
+                kube_tls_server_name.into(),
+            );
+        }
+
         if let Some(config_file) = &self.config_file {
             // Set canoncialized path to config file, in case forks/children are in different
             // working directories.
@@ -403,6 +488,18 @@ pub(super) struct PortForwardArgs {
     #[arg(long)]
     pub context: Option<String>,
 
+    /// HTTP(S) or SOCKS5 proxy to use for connections to the Kubernetes API.
+    #[arg(long)]
+    pub kube_proxy: Option<String>,
+
+    /// PEM file with extra CA certificates to trust when connecting to the Kubernetes API.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub kube_ca_bundle: Option<PathBuf>,
+
+    /// Name used to verify the Kubernetes API server certificate.
+    #[arg(long)]
+    pub kube_tls_server_name: Option<String>,
+
     /// Mappings for port forwarding.
     /// Expected format is: '-L \[local_port:\]remote_ip_or_hostname:remote_port'.
     /// If the remote is given as an ip, this is parsed as soon as mirrord starts.
@@ -560,9 +657,24 @@ pub(super) enum OperatorCommand {
         #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
         config_file: Option<PathBuf>,
     },
+    /// Print operator usage statistics: sessions per team, stolen and mirrored hours, and the most
+    /// used targets.
+    Usage {
+        /// Length of the reported period, e.g. `30d` or `12h`.
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "30d")]
+        since: Duration,
+
+        /// Specify the format of the output. The table contains only the most used targets.
+        #[arg(short = 'o', long, value_enum, default_value_t = UsageFormat::Table)]
+        output: UsageFormat,
+
+        /// Specify config file to use
+        #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+        config_file: Option<PathBuf>,
+    },
     /// Operator session management commands.
     ///
-    /// Allows the user to forcefully kill living sessions.
+    /// Allows the user to forcefully kill living sessions, and to share them with other users.
     #[command(subcommand)]
     Session(SessionCommand),
 }
@@ -633,6 +745,25 @@ pub(crate) enum SessionCommand {
     /// Kills all operator sessions.
     KillAll,
 
+    /// Shares the session specified by `id` with other users, printing the link they can join it
+    /// with (`mirrord exec --join-session <LINK>`), to observe the traffic it steals.
+    Share {
+        /// Id of the session.
+        #[arg(short, long, value_parser=hex_id)]
+        id: u64,
+
+        /// How long the link stays valid, e.g. `2h`. Defaults to the operator's setting.
+        #[arg(long, value_parser = humantime::parse_duration)]
+        ttl: Option<Duration>,
+    },
+
+    /// Revokes the share of the session specified by `id`, disconnecting the users that joined it.
+    Unshare {
+        /// Id of the session.
+        #[arg(short, long, value_parser=hex_id)]
+        id: u64,
+    },
+
     /// Kills _inactive_ sessions, might be useful if an undead session is still being stored in
     /// the session storage.
     #[clap(hide(true))]
@@ -644,6 +775,8 @@ impl core::fmt::Display for SessionCommand {
         match self {
             SessionCommand::Kill { id } => write!(f, "mirrord operator kill --id {id}"),
             SessionCommand::KillAll => write!(f, "mirrord operator kill-all"),
+            SessionCommand::Share { id, .. } => write!(f, "mirrord operator share --id {id:X}"),
+            SessionCommand::Unshare { id } => write!(f, "mirrord operator unshare --id {id:X}"),
             SessionCommand::RetainActive => write!(f, "mirrord operator retain-active"),
         }
     }
@@ -660,6 +793,17 @@ pub enum Format {
     Json,
 }
 
+/// Output format of `mirrord operator usage`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(super) enum UsageFormat {
+    /// Human readable tables.
+    Table,
+    /// One row per team and target.
+    Csv,
+    /// The usage as returned by the operator.
+    Json,
+}
+
 #[derive(Args, Debug)]
 pub(super) struct ListTargetArgs {
     /// Specify the format of the output.
@@ -728,6 +872,258 @@ pub(super) enum DiagnoseCommand {
     },
 }
 
+#[derive(Args, Debug)]
+pub(super) struct DnsArgs {
+    #[command(subcommand)]
+    pub command: DnsCommand,
+}
+
+#[derive(Subcommand, Debug)]
+/// Commands for using the cluster DNS outside of mirrord sessions.
+pub(super) enum DnsCommand {
+    /// Resolve the cluster services through the agent, and print them as an `/etc/hosts`
+    /// fragment or a dnsmasq config.
+    Export {
+        /// Specify config file to use
+        #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+        config_file: Option<PathBuf>,
+
+        /// Namespaces of the services to export, defaults to the target namespace.
+        ///
+        /// Services in the first namespace are also exported under their short name.
+        #[arg(short = 'n', long = "namespace")]
+        namespaces: Vec<String>,
+
+        /// Format of the exported names.
+        #[arg(long, value_enum, default_value_t = DnsExportFormat::Hosts)]
+        format: DnsExportFormat,
+
+        /// Write the names to this file instead of printing them. The session is kept alive
+        /// and the file is kept up to date until mirrord is interrupted, then it's removed.
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+/// Formats supported by the `mirrord dns export` command.
+pub(super) enum DnsExportFormat {
+    /// `/etc/hosts` fragment.
+    Hosts,
+    /// dnsmasq config with `host-record` entries, to use with a local DNS stub.
+    Dnsmasq,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct ToggleArgs {
+    /// What to change in the session, `status` only prints the current state.
+    #[arg(value_enum)]
+    pub action: ToggleAction,
+
+    /// Process id of the session's internal proxy, required when multiple sessions are running.
+    #[arg(long)]
+    pub session: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+/// Changes supported by the `mirrord toggle` command.
+pub(super) enum ToggleAction {
+    /// Print the current state of the toggles.
+    Status,
+    /// Stop stealing traffic, it reaches the target as if there was no session.
+    PauseSteal,
+    /// Steal traffic again, after `pause-steal` or `steal-to-mirror`.
+    ResumeSteal,
+    /// Mirror the traffic that is currently stolen.
+    StealToMirror,
+    /// Make the file operations that would modify the remote filesystem fail.
+    DisableFsWrites,
+    /// Allow the remote filesystem to be modified again.
+    EnableFsWrites,
+    /// Make the logs of the internal proxy and the layers more verbose: `debug`, then `trace`.
+    RaiseLogLevel,
+    /// Make the logs less verbose, down to the configured log level.
+    LowerLogLevel,
+    /// Log every message exchanged with the agent, and between the layers and the internal proxy.
+    EnableProtocolTracing,
+    /// Stop logging the exchanged messages.
+    DisableProtocolTracing,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct CronArgs {
+    /// Parameters for the target, which must be a CronJob, e.g. `cronjob/name`.
+    #[clap(flatten)]
+    pub target: TargetParams,
+
+    /// Specify config file to use
+    #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Stop after the first execution, instead of running every tick until interrupted.
+    #[arg(long)]
+    pub once: bool,
+
+    /// Command to run at each tick, defaults to the command of the CronJob's container.
+    #[arg(last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct SelftestArgs {
+    /// Parameters for the target, the test runs targetless by default.
+    #[clap(flatten)]
+    pub target: TargetParams,
+
+    /// Specify config file to use
+    #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Format of the compatibility matrix.
+    #[arg(short = 'o', long, value_enum, default_value_t = SelftestFormat::Table)]
+    pub output: SelftestFormat,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct LoadgenArgs {
+    /// Specify config file to use
+    #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Craft the requests to match `feature.network.incoming.http_filter` of the config,
+    /// otherwise they're plain `GET /` requests.
+    #[arg(long)]
+    pub filter_from_config: bool,
+
+    /// Requests sent per second.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rps: u32,
+
+    /// For how long the requests are sent.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub duration: Duration,
+
+    /// Local port of the application, defaults to the first port of
+    /// `feature.network.incoming.http_filter.ports`.
+    #[arg(short = 'p', long)]
+    pub port: Option<u16>,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+/// Commands for debugging the mirrord configuration.
+pub(super) enum ConfigCommand {
+    /// Explain whether the file operations that read and write a path go to the remote
+    /// filesystem, stay local or fail, and which part of `feature.fs` decides it.
+    ExplainPath {
+        /// Specify config file to use
+        #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+        config_file: Option<PathBuf>,
+
+        /// Absolute path, as the app opens it (after `feature.fs.mapping`).
+        path: String,
+    },
+}
+
+/// Output format of `mirrord selftest`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(super) enum SelftestFormat {
+    /// Human readable table.
+    Table,
+    /// One object per feature.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct AuthArgs {
+    #[command(subcommand)]
+    pub command: AuthCommand,
+}
+
+/// `mirrord auth` family of commands.
+///
+/// The credentials are moved in bundles encrypted with a passphrase, read from the
+/// `MIRRORD_AUTH_PASSPHRASE` env var or from `--passphrase-file`.
+#[derive(Subcommand, Debug)]
+pub(super) enum AuthCommand {
+    /// Export the operator credentials of this machine into an encrypted bundle, to be imported
+    /// on another machine with `mirrord auth import`.
+    Export {
+        /// Path of the bundle file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Export only the credentials for this operator license, see `mirrord operator status`.
+        #[arg(long)]
+        license_fingerprint: Option<String>,
+
+        #[clap(flatten)]
+        passphrase: AuthPassphraseArgs,
+    },
+    /// Import the operator credentials from an encrypted bundle, made with `mirrord auth export`
+    /// or `mirrord auth bootstrap`. Doesn't need access to the cluster.
+    Import {
+        /// Path of the bundle file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+
+        #[clap(flatten)]
+        passphrase: AuthPassphraseArgs,
+    },
+    /// Issue operator credentials for another user into an encrypted bundle, so that they don't
+    /// have to request them on their first run.
+    Bootstrap {
+        /// Name of the user, used as the common name of the certificate.
+        #[arg(long)]
+        user: String,
+
+        /// Path of the bundle file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Specify config file to use
+        #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+        config_file: Option<PathBuf>,
+
+        #[clap(flatten)]
+        passphrase: AuthPassphraseArgs,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(super) struct AuthPassphraseArgs {
+    /// Read the passphrase of the bundle from this file, instead of the `MIRRORD_AUTH_PASSPHRASE`
+    /// env var.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub passphrase_file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct ReportArgs {
+    /// Specify config file to use
+    #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+    pub config_file: Option<PathBuf>,
+
+    /// Path of the report archive, defaults to `mirrord-report-<TIMESTAMP>.tar.gz` in the
+    /// current directory.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+
+    /// Don't connect to the cluster, only local information is included in the report.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Internal proxy log to include, defaults to `internal_proxy.log_destination` from the
+    /// config, or the most recent internal proxy log.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub log_file: Option<PathBuf>,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
 /// Runtimes supported by the `mirrord container` command.
 pub(super) enum ContainerRuntime {
@@ -791,6 +1187,30 @@ pub(super) enum ContainerCommand {
     },
 }
 
+/// Long options of `<RUNTIME> run` that don't take a value, see [`ContainerCommand::image`].
+const RUN_LONG_FLAGS: &[&str] = &[
+    "detach",
+    "disable-content-trust",
+    "help",
+    "init",
+    "interactive",
+    "no-healthcheck",
+    "no-hosts",
+    "oom-kill-disable",
+    "privileged",
+    "publish-all",
+    "quiet",
+    "read-only",
+    "replace",
+    "rm",
+    "rmi",
+    "sig-proxy",
+    "tty",
+];
+
+/// Short options of `<RUNTIME> run` that don't take a value, see [`ContainerCommand::image`].
+const RUN_SHORT_FLAGS: &[char] = &['d', 'i', 't', 'P', 'q'];
+
 impl ContainerCommand {
     pub fn run<T: Into<String>>(runtime_args: impl IntoIterator<Item = T>) -> Self {
         ContainerCommand::Run {
@@ -809,6 +1229,40 @@ impl ContainerCommand {
             !hit_trailing_token && matches!(runtime_arg.as_str(), "-p" | "--publish")
         })
     }
+
+    /// Best-effort extraction of the image from the `<RUNTIME> run` arguments, which is the first
+    /// argument that is neither an option, nor the value of an option.
+    pub fn image(&self) -> Option<&str> {
+        let ContainerCommand::Run { runtime_args } = self;
+
+        let mut args = runtime_args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                return args.next().map(String::as_str);
+            } else if let Some(long) = arg.strip_prefix("--") {
+                if !long.contains('=') && !RUN_LONG_FLAGS.contains(&long) {
+                    args.next();
+                }
+            } else if let Some(short) = arg.strip_prefix('-') {
+                // Either a group of flags (`-it`), optionally ending with an option that takes
+                // the next argument as value (`-dp 80:80`), or an option with the value attached
+                // (`-eKEY=VALUE`).
+                let mut chars = short.chars().rev();
+                let takes_value = chars
+                    .next()
+                    .is_some_and(|last| !RUN_SHORT_FLAGS.contains(&last))
+                    && chars.all(|flag| RUN_SHORT_FLAGS.contains(&flag));
+
+                if takes_value {
+                    args.next();
+                }
+            } else {
+                return Some(arg);
+            }
+        }
+
+        None
+    }
 }
 
 #[derive(Args, Debug)]
@@ -901,6 +1355,18 @@ mod tests {
         assert_eq!(runtime_args, vec!["-it", "--rm", "debian"]);
     }
 
+    #[rstest]
+    #[case(&["-it", "--rm", "debian"], Some("debian"))]
+    #[case(&["-dp", "80:80", "-e", "KEY=VALUE", "alpine", "sh"], Some("alpine"))]
+    #[case(&["--name=test", "--network", "host", "-eKEY=VALUE", "alpine:3"], Some("alpine:3"))]
+    #[case(&["--rm", "--", "debian", "bash"], Some("debian"))]
+    #[case(&["--rm", "-v", "/tmp:/tmp"], None)]
+    fn runtime_args_image(#[case] runtime_args: &[&str], #[case] expected: Option<&str>) {
+        let command = ContainerCommand::run(runtime_args.iter().copied());
+
+        assert_eq!(command.image(), expected);
+    }
+
     #[test]
     fn runtime_args_parsing_with_seperator() {
         let command = "mirrord container -t deploy/test -- podman run -it --rm debian";
@@ -11,7 +11,7 @@ use mirrord_kube::{
 use mirrord_operator::client::{OperatorApi, OperatorSessionConnection};
 use mirrord_progress::{
     messages::{HTTP_FILTER_WARNING, MULTIPOD_WARNING},
-    IdeAction, IdeMessage, NotificationLevel, Progress,
+    IdeAction, IdeMessage, NotificationLevel, Progress, ProgressGroup,
 };
 use mirrord_protocol::{ClientMessage, DaemonMessage};
 use tokio::sync::mpsc;
@@ -21,6 +21,10 @@ use crate::{CliError, CliResult};
 
 pub const AGENT_CONNECT_INFO_ENV_KEY: &str = "MIRRORD_AGENT_CONNECT_INFO";
 
+/// Holds the [`AgentConnectInfo`]s of the additional clusters, in the order of
+/// [`LayerConfig::clusters`].
+pub const CLUSTERS_CONNECT_INFO_ENV_KEY: &str = "MIRRORD_CLUSTERS_CONNECT_INFO";
+
 pub(crate) struct AgentConnection {
     pub sender: mpsc::Sender<ClientMessage>,
     pub receiver: mpsc::Receiver<DaemonMessage>,
@@ -40,15 +44,18 @@ where
     P: Progress,
     R: Reporter,
 {
-    let mut operator_subtask = progress.subtask("checking operator");
+    let mut operator_subtask = progress.group(ProgressGroup::Operator);
     if config.operator == Some(false) {
         operator_subtask.success(Some("operator disabled"));
         return Ok(None);
     }
 
+    // Joining a shared session only works through the operator.
+    let operator_required = config.operator == Some(true) || config.join_session.is_some();
+
     let api = match OperatorApi::try_new(config, analytics).await? {
         Some(api) => api,
-        None if config.operator == Some(true) => return Err(CliError::OperatorNotInstalled),
+        None if operator_required => return Err(CliError::OperatorNotInstalled),
         None => {
             operator_subtask.success(Some("operator not found"));
             return Ok(None);
@@ -69,7 +76,7 @@ where
         Err(error) => {
             license_subtask.failure(Some("operator license expired"));
 
-            if config.operator == Some(true) {
+            if operator_required {
                 return Err(error.into());
             } else {
                 operator_subtask.failure(Some("proceeding without operator"));
@@ -82,6 +89,16 @@ where
     let api = api.prepare_client_cert(analytics).await.into_certified()?;
     user_cert_subtask.success(Some("user credentials prepared"));
 
+    if let Some(link) = &config.join_session {
+        let mut session_subtask = operator_subtask.subtask("joining shared session");
+        let connection = api.join_shared_session(link).await?;
+        session_subtask.success(Some("joined shared session, observing its stolen traffic"));
+
+        operator_subtask.success(Some("using operator"));
+
+        return Ok(Some(connection));
+    }
+
     let target = ResolvedTarget::new(
         api.client(),
         &config.target.path.clone().unwrap_or(Target::Targetless),
@@ -94,7 +111,10 @@ where
     let connection = api
         .connect_in_new_session(target, config, &session_subtask)
         .await?;
-    session_subtask.success(Some("session started"));
+    session_subtask.success(Some(&format!(
+        "session {} started",
+        connection.session.id()
+    )));
 
     operator_subtask.success(Some("using operator"));
 
@@ -160,12 +180,14 @@ where
         _ => (),
     };
 
+    let mut agent_progress = progress.group(ProgressGroup::Agent);
+
     let k8s_api = KubernetesAPI::create(config)
         .await
         .map_err(|error| CliError::friendlier_error_or_else(error, CliError::CreateAgentFailed))?;
 
     k8s_api
-        .detect_openshift(progress)
+        .detect_openshift(&agent_progress)
         .await
         .map_err(|fail| CliError::friendlier_error_or_else(fail, CliError::CreateAgentFailed))
         .inspect_err(|fail| tracing::debug!(?fail, "Failed to detect OpenShift!"))
@@ -173,7 +195,12 @@ where
 
     let agent_connect_info = tokio::time::timeout(
         Duration::from_secs(config.agent.startup_timeout),
-        k8s_api.create_agent(progress, &config.target, Some(config), Default::default()),
+        k8s_api.create_agent(
+            &mut agent_progress,
+            &config.target,
+            Some(config),
+            Default::default(),
+        ),
     )
     .await
     .unwrap_or(Err(KubeApiError::AgentReadyTimeout))
@@ -187,6 +214,7 @@ where
                 CliError::friendlier_error_or_else(error, CliError::AgentConnectionFailed)
             })?,
     );
+    agent_progress.success(Some("agent connected"));
 
     Ok((
         AgentConnectInfo::DirectKubernetes(agent_connect_info),
@@ -194,6 +222,35 @@ where
     ))
 }
 
+/// Calls [`create_and_connect`] for each of the additional clusters in [`LayerConfig::clusters`],
+/// using [`ClusterConfig::layer_config`](mirrord_config::cluster::ClusterConfig::layer_config).
+#[tracing::instrument(level = Level::TRACE, skip_all)]
+pub(crate) async fn create_and_connect_clusters<P, R: Reporter>(
+    config: &LayerConfig,
+    progress: &mut P,
+    analytics: &mut R,
+) -> CliResult<Vec<(AgentConnectInfo, AgentConnection)>>
+where
+    P: Progress + Send + Sync,
+{
+    let mut connections = Vec::with_capacity(config.clusters.len());
+
+    for cluster in &config.clusters {
+        let mut cluster_progress = progress.subtask(&format!("cluster {}", cluster.name));
+        let connection = create_and_connect(
+            &cluster.layer_config(config),
+            &mut cluster_progress,
+            analytics,
+        )
+        .await?;
+        cluster_progress.success(Some(&format!("connected to cluster {}", cluster.name)));
+
+        connections.push(connection);
+    }
+
+    Ok(connections)
+}
+
 fn user_persistent_random_message_select() -> bool {
     mid::get("mirrord")
         .inspect_err(|error| tracing::error!(%error, "failed to obtain machine ID"))
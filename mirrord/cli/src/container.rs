@@ -1,5 +1,6 @@
 use std::{io::Write, net::SocketAddr, path::Path, process::Stdio, time::Duration};
 
+#[cfg(unix)]
 use exec::execvp;
 use local_ip_address::local_ip;
 use mirrord_analytics::{
@@ -8,8 +9,8 @@ use mirrord_analytics::{
 use mirrord_config::{
     external_proxy::{MIRRORD_EXTERNAL_TLS_CERTIFICATE_ENV, MIRRORD_EXTERNAL_TLS_KEY_ENV},
     internal_proxy::{
-        MIRRORD_INTPROXY_CLIENT_TLS_CERTIFICATE_ENV, MIRRORD_INTPROXY_CLIENT_TLS_KEY_ENV,
-        MIRRORD_INTPROXY_CONTAINER_MODE_ENV,
+        MIRRORD_INTPROXY_AUTH_TOKEN_ENV, MIRRORD_INTPROXY_CLIENT_TLS_CERTIFICATE_ENV,
+        MIRRORD_INTPROXY_CLIENT_TLS_KEY_ENV, MIRRORD_INTPROXY_CONTAINER_MODE_ENV,
     },
     LayerConfig, MIRRORD_CONFIG_FILE_ENV,
 };
@@ -22,7 +23,7 @@ use tokio::{
 use tracing::Level;
 
 use crate::{
-    config::{ContainerCommand, ExecParams, RuntimeArgs},
+    config::{ContainerCommand, ContainerRuntime, ExecParams, RuntimeArgs},
     connection::AGENT_CONNECT_INFO_ENV_KEY,
     container::command_builder::RuntimeCommandBuilder,
     error::{CliResult, ContainerError},
@@ -30,6 +31,8 @@ use crate::{
         MirrordExecution, LINUX_INJECTION_ENV_VAR, MIRRORD_CONNECT_TCP_ENV,
         MIRRORD_EXECUTION_KIND_ENV,
     },
+    internal_proxy::layer_auth_token,
+    routes,
     util::MIRRORD_CONSOLE_ADDR_ENV,
 };
 
@@ -215,6 +218,38 @@ async fn create_sidecar_intproxy(
     Ok((sidecar_container_id, intproxy_address))
 }
 
+/// Checks if `image` is based on musl libc (e.g. Alpine), by running the musl dynamic loader in
+/// it.
+///
+/// The loader exits with an error, as it's given nothing valid to load, but the runtime only
+/// fails with `126`/`127` when the loader is missing from the image (`125` is an error of the
+/// runtime itself).
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn is_musl_image(runtime: &ContainerRuntime, image: &str) -> bool {
+    let loader = format!("/lib/ld-musl-{}.so.1", std::env::consts::ARCH);
+
+    Command::new(runtime.to_string())
+        .args([
+            "run",
+            "--rm",
+            "--entrypoint",
+            &loader,
+            image,
+            "--list",
+            "/dev/null",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|status| {
+            status
+                .code()
+                .is_some_and(|code| !(125..=127).contains(&code))
+        })
+}
+
 /// Main entry point for the `mirrord container` command.
 /// This spawns: "agent" - "external proxy" - "intproxy sidecar" - "execution container"
 pub(crate) async fn container_command(
@@ -250,6 +285,9 @@ pub(crate) async fn container_command(
         progress.warning(warning);
     }
 
+    // The changed config goes to the composed config file below, with the rest of the changes.
+    routes::check_target_routes(&mut config, &progress).await;
+
     let _internal_proxy_tls_guards = if config.external_proxy.tls_enable
         && (config.internal_proxy.client_tls_certificate.is_none()
             || config.internal_proxy.client_tls_key.is_none())
@@ -369,13 +407,28 @@ pub(crate) async fn container_command(
 
     runtime_command.add_envs(execution_info_env_without_connection_info);
 
+    // Shared by the sidecar and the execution container.
+    if let Some(auth_token) = layer_auth_token(&config) {
+        runtime_command.add_env(MIRRORD_INTPROXY_AUTH_TOKEN_ENV, auth_token);
+    }
+
     let (sidecar_container_id, sidecar_intproxy_address) =
         create_sidecar_intproxy(&config, &runtime_command, connection_info).await?;
 
     runtime_command.add_network(format!("container:{sidecar_container_id}"));
     runtime_command.add_volumes_from(sidecar_container_id);
 
-    runtime_command.add_env(LINUX_INJECTION_ENV_VAR, config.container.cli_image_lib_path);
+    let musl_image = match runtime_args.command.image() {
+        Some(image) => is_musl_image(&runtime_args.runtime, image).await,
+        None => false,
+    };
+    let layer_lib_path = if musl_image {
+        config.container.cli_image_musl_lib_path
+    } else {
+        config.container.cli_image_lib_path
+    };
+
+    runtime_command.add_env(LINUX_INJECTION_ENV_VAR, layer_lib_path);
     runtime_command.add_env(
         MIRRORD_CONNECT_TCP_ENV,
         sidecar_intproxy_address.to_string(),
@@ -385,8 +438,23 @@ pub(crate) async fn container_command(
         .with_command(runtime_args.command)
         .into_execvp_args();
 
-    let err = execvp(binary, binary_args);
-    tracing::error!("Couldn't execute {:?}", err);
+    #[cfg(unix)]
+    {
+        let err = execvp(binary, binary_args);
+        tracing::error!("Couldn't execute {:?}", err);
+    }
+
+    // There is no `exec` on Windows, so we run the runtime as a child process and forward its exit
+    // code.
+    #[cfg(windows)]
+    match Command::new(&binary)
+        .args(binary_args.skip(1))
+        .status()
+        .await
+    {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(err) => tracing::error!("Couldn't execute {:?}", err),
+    }
 
     analytics.set_error(AnalyticsError::BinaryExecuteFailed);
 
@@ -0,0 +1,480 @@
+//! Local runs of a CronJob's executions, see [`cron_command`].
+//!
+//! The remote schedule of the CronJob is suspended while the command runs, and each tick of its
+//! schedule starts a `mirrord exec` session that targets the CronJob, so that the local process
+//! gets the env and fs context of the job. The schedule is restored when the command ends.
+use std::{
+    process::ExitStatus,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use k8s_openapi::{
+    api::batch::v1::CronJob,
+    chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeDelta, Timelike},
+};
+use kube::{
+    api::{Patch, PatchParams},
+    Api, Client,
+};
+use mirrord_config::{
+    target::{cron_job::CronJobTarget, Target},
+    LayerConfig, MIRRORD_CONFIG_FILE_ENV,
+};
+use mirrord_kube::{api::kubernetes::create_kube_config, error::KubeApiError};
+use mirrord_progress::{Progress, ProgressTracker};
+use serde_json::json;
+use tokio::{process::Command, signal, time};
+use tracing::Level;
+
+use crate::{util::remove_proxy_env, CliError, CliResult, CronArgs};
+
+/// Parsed 5-field cron schedule, with the syntax supported by Kubernetes CronJobs.
+///
+/// Each field is a bit set of the allowed values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    /// Sunday is `0`.
+    days_of_week: u64,
+    /// Both day fields are restricted, so a day matches when any of them does.
+    days_either: bool,
+}
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+const DAYS_OF_WEEK: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// How far ahead we look for the next tick, schedules like `0 0 30 2 *` never match.
+const MAX_LOOKAHEAD: TimeDelta = TimeDelta::days(5 * 366);
+
+impl Schedule {
+    fn parse(schedule: &str) -> Result<Self, String> {
+        let schedule = match schedule.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other if other.starts_with('@') => {
+                return Err(format!("unsupported schedule `{other}`"));
+            }
+            other => other,
+        };
+
+        let [minutes, hours, days_of_month, months, days_of_week] = schedule
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| format!("schedule `{schedule}` doesn't have 5 fields"))?;
+
+        let days_of_week_bits = parse_field(days_of_week, 0, 7, DAYS_OF_WEEK)?;
+        // Both `0` and `7` are Sunday.
+        let days_of_week_bits = (days_of_week_bits | days_of_week_bits >> 7) & 0x7f;
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59, &[])?,
+            hours: parse_field(hours, 0, 23, &[])?,
+            days_of_month: parse_field(days_of_month, 1, 31, &[])?,
+            months: parse_field(months, 1, 12, MONTHS)?,
+            days_of_week: days_of_week_bits,
+            days_either: !is_wildcard(days_of_month) && !is_wildcard(days_of_week),
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day_of_month = bit(self.days_of_month, date.day());
+        let day_of_week = bit(self.days_of_week, date.weekday().num_days_from_sunday());
+
+        if self.days_either {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+
+    /// First tick of the schedule strictly after `after`, [`None`] if there is none in the
+    /// [`MAX_LOOKAHEAD`].
+    fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let limit = after + MAX_LOOKAHEAD;
+        let mut next = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+
+        while next <= limit {
+            let date = next.date();
+
+            next = if !bit(self.months, date.month()) {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+                NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?
+            } else if !self.matches_day(date) {
+                date.succ_opt()?.and_hms_opt(0, 0, 0)?
+            } else if !bit(self.hours, next.hour()) {
+                next.with_minute(0)? + TimeDelta::hours(1)
+            } else if !bit(self.minutes, next.minute()) {
+                next + TimeDelta::minutes(1)
+            } else {
+                return Some(next);
+            };
+        }
+
+        None
+    }
+}
+
+fn bit(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn is_wildcard(field: &str) -> bool {
+    field.starts_with('*') || field.starts_with('?')
+}
+
+/// Parses one field of the schedule, e.g. `1-5,10-30/5`, into a bit set of the values.
+///
+/// `names` are the alternative names of the values, starting at `min`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |value: &str| {
+        names
+            .iter()
+            .zip(min..)
+            .find_map(|(name, index)| name.eq_ignore_ascii_case(value).then_some(index))
+            .or_else(|| value.parse::<u32>().ok())
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| format!("invalid value `{value}` in schedule field `{field}`"))
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step `{step}` in schedule field `{field}`"))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" || range == "?" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/10` starts at 5 and goes up to the max.
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+
+        if start > end {
+            return Err(format!(
+                "invalid range `{range}` in schedule field `{field}`"
+            ));
+        }
+
+        bits = (start..=end)
+            .step_by(step.unwrap_or(1) as usize)
+            .fold(bits, |bits, value| bits | 1 << value);
+    }
+
+    Ok(bits)
+}
+
+/// Current time in UTC.
+fn now() -> NaiveDateTime {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    DateTime::from_timestamp(since_epoch.as_secs() as i64, since_epoch.subsec_nanos())
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// Sets `spec.suspend` of the CronJob, [`None`] removes it.
+async fn set_suspend(
+    api: &Api<CronJob>,
+    name: &str,
+    suspend: Option<bool>,
+) -> Result<(), KubeApiError> {
+    api.patch(
+        name,
+        &PatchParams::default(),
+        &Patch::Merge(json!({ "spec": { "suspend": suspend } })),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The command of the CronJob's container, used when the user doesn't give one.
+fn job_command(cron_job: &CronJob, target: &CronJobTarget) -> Result<Vec<String>, String> {
+    let containers = cron_job
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.job_template.spec.as_ref())
+        .and_then(|spec| spec.template.spec.as_ref())
+        .map(|spec| spec.containers.as_slice())
+        .unwrap_or_default();
+
+    let container = match &target.container {
+        Some(name) => containers
+            .iter()
+            .find(|container| &container.name == name)
+            .ok_or_else(|| format!("the CronJob has no container named `{name}`"))?,
+        None => containers
+            .first()
+            .ok_or_else(|| "the CronJob has no containers".to_string())?,
+    };
+
+    let command = container
+        .command
+        .iter()
+        .flatten()
+        .chain(container.args.iter().flatten())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if command.is_empty() || container.command.is_none() {
+        Err(format!(
+            "container `{}` uses the entrypoint of its image, please give the command to run \
+            after `--`",
+            container.name
+        ))
+    } else {
+        Ok(command)
+    }
+}
+
+/// Runs one execution of the job with `mirrord exec`, the target and config are passed through
+/// the environment.
+///
+/// When interrupted, waits for the execution to exit, as it gets the signal too.
+async fn run_execution(command: &[String]) -> CliResult<Option<ExitStatus>> {
+    let mut child = Command::new(std::env::current_exe().map_err(CliError::CliPathError)?)
+        .arg("exec")
+        .arg("--")
+        .args(command)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| CliError::CronJobFailed(format!("failed to run mirrord exec: {error}")))?;
+
+    tokio::select! {
+        status = child.wait() => Ok(status.ok()),
+        _ = signal::ctrl_c() => {
+            let _ = child.wait().await;
+            Ok(None)
+        }
+    }
+}
+
+/// Runs the executions of the job at the ticks of the schedule, until interrupted or after the
+/// first one with `once`.
+///
+/// Ticks that pass while an execution is running are skipped.
+async fn run_schedule(
+    progress: &ProgressTracker,
+    schedule: &Schedule,
+    command: &[String],
+    once: bool,
+) -> CliResult<()> {
+    loop {
+        let tick = schedule
+            .next_after(now())
+            .ok_or_else(|| CliError::CronJobFailed("the schedule never runs".to_string()))?;
+        progress.info(&format!(
+            "next execution at {tick} UTC, press Ctrl+C to stop"
+        ));
+
+        let wait = (tick - now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = time::sleep(wait) => {}
+            _ = signal::ctrl_c() => return Ok(()),
+        }
+
+        progress.info(&format!("running {}", command.join(" ")));
+        match run_execution(command).await? {
+            Some(status) if status.success() => progress.info("execution succeeded"),
+            Some(status) => progress.warning(&format!("execution failed with {status}")),
+            None => return Ok(()),
+        }
+
+        if once {
+            return Ok(());
+        }
+    }
+}
+
+/// Suspends the schedule of the targeted CronJob, and runs its executions locally with mirrord
+/// instead, see the module docs.
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn cron_run(once: bool, command: Vec<String>) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord cron");
+
+    let (config, mut context) = LayerConfig::from_env_with_warnings()?;
+    config.verify(&mut context)?;
+
+    if !config.use_proxy {
+        remove_proxy_env();
+    }
+
+    let Some(Target::CronJob(target)) = config.target.path.clone() else {
+        return Err(CliError::CronJobTargetRequired);
+    };
+
+    let client = create_kube_config(&config)
+        .await
+        .and_then(|config| Client::try_from(config).map_err(From::from))
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
+    let namespace = config
+        .target
+        .namespace
+        .clone()
+        .unwrap_or_else(|| client.default_namespace().to_string());
+    let api = Api::<CronJob>::namespaced(client, &namespace);
+
+    let cron_job = api
+        .get(&target.cron_job)
+        .await
+        .map_err(|error| CliError::CronJobApiFailed(target.cron_job.clone(), error.into()))?;
+    let spec = cron_job.spec.clone().unwrap_or_default();
+
+    // We don't have the time zone database, the controller uses UTC when it's not set.
+    if let Some(time_zone) = spec
+        .time_zone
+        .as_deref()
+        .filter(|time_zone| !matches!(*time_zone, "UTC" | "Etc/UTC"))
+    {
+        return Err(CliError::CronJobFailed(format!(
+            "time zone `{time_zone}` of the CronJob is not supported, only UTC is"
+        )));
+    }
+    let schedule = Schedule::parse(&spec.schedule).map_err(CliError::CronJobFailed)?;
+
+    let command = if command.is_empty() {
+        job_command(&cron_job, &target).map_err(CliError::CronJobFailed)?
+    } else {
+        command
+    };
+
+    let original_suspend = spec.suspend;
+    set_suspend(&api, &target.cron_job, Some(true))
+        .await
+        .map_err(|error| CliError::CronJobApiFailed(target.cron_job.clone(), error))?;
+    progress.info(&format!(
+        "suspended the schedule `{}` of cronjob/{} in {namespace}",
+        spec.schedule, target.cron_job
+    ));
+
+    let result = run_schedule(&progress, &schedule, &command, once).await;
+
+    set_suspend(&api, &target.cron_job, original_suspend)
+        .await
+        .map_err(|error| CliError::CronJobRestoreFailed(target.cron_job.clone(), error))?;
+    progress.success(Some(&format!(
+        "restored the schedule of cronjob/{}",
+        target.cron_job
+    )));
+
+    result
+}
+
+/// Handle `mirrord cron`.
+pub(crate) async fn cron_command(args: CronArgs) -> CliResult<()> {
+    // set_var used here as the `mirrord exec` children need these values too
+    for (name, value) in args.target.as_env_vars()? {
+        std::env::set_var(name, value);
+    }
+
+    if let Some(config_file) = &args.config_file {
+        // Canonical path, in case the children run in a different working directory.
+        let full_path = std::fs::canonicalize(config_file)
+            .map_err(|e| CliError::CanonicalizeConfigPathFailed(config_file.clone(), e))?;
+        std::env::set_var(MIRRORD_CONFIG_FILE_ENV, full_path);
+    }
+
+    cron_run(args.once, args.command).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(schedule: &str, after: &str) -> Option<NaiveDateTime> {
+        Schedule::parse(schedule).unwrap().next_after(at(after))
+    }
+
+    #[test]
+    fn parse_fields() {
+        assert_eq!(parse_field("*", 0, 5, &[]), Ok(0b111111));
+        assert_eq!(parse_field("1-3,5", 0, 5, &[]), Ok(0b101110));
+        assert_eq!(parse_field("*/2", 0, 5, &[]), Ok(0b010101));
+        assert_eq!(parse_field("1/2", 0, 5, &[]), Ok(0b101010));
+        assert_eq!(parse_field("mon-wed", 0, 7, DAYS_OF_WEEK), Ok(0b1110));
+        assert!(parse_field("6", 0, 5, &[]).is_err());
+        assert!(parse_field("3-1", 0, 5, &[]).is_err());
+        assert!(parse_field("*/0", 0, 5, &[]).is_err());
+
+        assert!(Schedule::parse("* * * *").is_err());
+        assert!(Schedule::parse("@reboot").is_err());
+        assert_eq!(
+            Schedule::parse("0 0 * * 7"),
+            Schedule::parse("@weekly"),
+            "both 0 and 7 are Sunday"
+        );
+    }
+
+    #[test]
+    fn next_ticks() {
+        assert_eq!(
+            next("*/15 * * * *", "2024-03-10 10:07"),
+            Some(at("2024-03-10 10:15"))
+        );
+        assert_eq!(
+            next("*/15 * * * *", "2024-03-10 10:15"),
+            Some(at("2024-03-10 10:30"))
+        );
+        assert_eq!(
+            next("@daily", "2024-12-31 23:59"),
+            Some(at("2025-01-01 00:00"))
+        );
+        assert_eq!(
+            next("30 9 * * mon-fri", "2024-03-08 10:00"),
+            Some(at("2024-03-11 09:30")),
+            "skips the weekend"
+        );
+        assert_eq!(
+            next("0 12 29 2 *", "2024-03-01 00:00"),
+            Some(at("2028-02-29 12:00"))
+        );
+        assert_eq!(next("0 0 30 2 *", "2024-01-01 00:00"), None);
+    }
+
+    #[test]
+    fn either_day_field() {
+        // The 13th, or any Friday.
+        let schedule = "0 0 13 * 5";
+        assert_eq!(
+            next(schedule, "2024-03-01 00:00"),
+            Some(at("2024-03-08 00:00"))
+        );
+        assert_eq!(
+            next(schedule, "2024-03-12 00:00"),
+            Some(at("2024-03-13 00:00"))
+        );
+    }
+}
@@ -16,7 +16,7 @@ use crate::{
 };
 
 /// Sends a ping the connection and expects a pong.
-async fn ping(
+pub(crate) async fn ping(
     sender: &mpsc::Sender<ClientMessage>,
     receiver: &mut mpsc::Receiver<DaemonMessage>,
 ) -> CliResult<()> {
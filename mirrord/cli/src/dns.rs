@@ -0,0 +1,336 @@
+//! Export of the cluster service names for local tools that can't run with mirrord, see
+//! [`dns_command`].
+//!
+//! The services are listed with the Kubernetes API, and their names are resolved through the
+//! agent, so that the exported addresses are the ones seen in the cluster.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use k8s_openapi::api::core::v1::Service;
+use kube::{api::ListParams, Api, Client};
+use mirrord_analytics::NullReporter;
+use mirrord_config::{
+    config::{ConfigContext, MirrordConfig},
+    LayerConfig, LayerFileConfig,
+};
+use mirrord_kube::{api::kubernetes::create_kube_config, error::KubeApiError};
+use mirrord_progress::{Progress, ProgressTracker};
+use mirrord_protocol::{
+    dns::{GetAddrInfoRequest, GetAddrInfoResponse, LookupRecord},
+    ClientMessage, DaemonMessage,
+};
+use tokio::{signal, time};
+use tracing::Level;
+
+use crate::{
+    connection::{create_and_connect, AgentConnection},
+    util::remove_proxy_env,
+    CliError, CliResult, DnsArgs, DnsCommand, DnsExportFormat,
+};
+
+/// How often the names are resolved again when exporting to a file.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A cluster service to resolve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ServiceName {
+    name: String,
+    namespace: String,
+    /// Whether the service is also exported under its short name.
+    short: bool,
+}
+
+impl ServiceName {
+    /// Name that we ask the agent to resolve, completed by the search domains of the target.
+    fn query(&self) -> String {
+        format!("{}.{}.svc", self.name, self.namespace)
+    }
+
+    /// All names of this service, including the fully qualified names of the `records`.
+    fn aliases(&self, records: &[LookupRecord]) -> BTreeSet<String> {
+        let fqdns = records
+            .iter()
+            .map(|record| record.name.trim_end_matches('.').to_string());
+
+        [format!("{}.{}", self.name, self.namespace), self.query()]
+            .into_iter()
+            .chain(self.short.then(|| self.name.clone()))
+            .chain(fqdns)
+            .collect()
+    }
+}
+
+/// Cluster addresses and the service names that resolve to them.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Zone(BTreeMap<IpAddr, BTreeSet<String>>);
+
+impl Zone {
+    fn add(&mut self, service: &ServiceName, records: &[LookupRecord]) {
+        let aliases = service.aliases(records);
+        for record in records {
+            self.0
+                .entry(record.ip)
+                .or_default()
+                .extend(aliases.iter().cloned());
+        }
+    }
+
+    fn render(&self, format: DnsExportFormat) -> String {
+        self.0
+            .iter()
+            .map(|(ip, names)| {
+                let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+                match format {
+                    DnsExportFormat::Hosts => format!("{ip} {}\n", names.join(" ")),
+                    DnsExportFormat::Dnsmasq => format!("host-record={},{ip}\n", names.join(",")),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Lists the services in the given namespaces, the first one is the default namespace.
+async fn list_services(
+    client: &Client,
+    namespaces: &[String],
+) -> Result<Vec<ServiceName>, KubeApiError> {
+    let mut services = Vec::new();
+
+    for (index, namespace) in namespaces.iter().enumerate() {
+        let list = Api::<Service>::namespaced(client.clone(), namespace)
+            .list(&ListParams::default())
+            .await?;
+
+        services.extend(
+            list.items
+                .into_iter()
+                .filter_map(|service| service.metadata.name)
+                .map(|name| ServiceName {
+                    name,
+                    namespace: namespace.clone(),
+                    short: index == 0,
+                }),
+        );
+    }
+
+    Ok(services)
+}
+
+/// Resolves `node` through the agent, returns no records when the name is not found.
+async fn resolve(connection: &mut AgentConnection, node: String) -> CliResult<Vec<LookupRecord>> {
+    connection
+        .sender
+        .send(ClientMessage::GetAddrInfoRequest(GetAddrInfoRequest {
+            node: node.clone(),
+        }))
+        .await
+        .map_err(|_| {
+            CliError::DnsExportFailed("agent unexpectedly closed connection".to_string())
+        })?;
+
+    loop {
+        return match connection.receiver.recv().await {
+            Some(DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Ok(lookup)))) => {
+                Ok(lookup.0)
+            }
+            Some(DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Err(error)))) => {
+                tracing::debug!(node, %error, "Failed to resolve service name.");
+                Ok(Vec::new())
+            }
+            Some(DaemonMessage::LogMessage(..) | DaemonMessage::Pong) => continue,
+            Some(DaemonMessage::Close(message)) => Err(CliError::DnsExportFailed(format!(
+                "agent closed connection with message: {message}"
+            ))),
+            Some(message) => Err(CliError::DnsExportFailed(format!(
+                "agent sent an unexpected message: {message:?}"
+            ))),
+            None => Err(CliError::DnsExportFailed(
+                "agent unexpectedly closed connection".to_string(),
+            )),
+        };
+    }
+}
+
+async fn resolve_zone(
+    connection: &mut AgentConnection,
+    services: &[ServiceName],
+) -> CliResult<Zone> {
+    let mut zone = Zone::default();
+
+    for service in services {
+        let records = resolve(connection, service.query()).await?;
+        zone.add(service, &records);
+    }
+
+    Ok(zone)
+}
+
+/// Writes `contents` to `path`, replacing the previous file atomically, so that the tools that
+/// read it never see a partial export.
+fn write_export(path: &Path, contents: &str) -> CliResult<()> {
+    let temp = path.with_extension("mirrord-tmp");
+    std::fs::write(&temp, contents)
+        .and_then(|_| std::fs::rename(&temp, path))
+        .map_err(|error| CliError::DnsExportWriteFailed(path.to_path_buf(), error))
+}
+
+/// Resolves the services of the cluster and prints them, or keeps them up to date in `output`
+/// until interrupted.
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn dns_export(
+    config: Option<&Path>,
+    namespaces: Vec<String>,
+    format: DnsExportFormat,
+    output: Option<PathBuf>,
+) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord dns export");
+
+    let mut cfg_context = ConfigContext::default();
+    let config: LayerConfig = if let Some(path) = config {
+        LayerFileConfig::from_path(path)?.generate_config(&mut cfg_context)
+    } else {
+        LayerFileConfig::default().generate_config(&mut cfg_context)
+    }?;
+
+    if !config.use_proxy {
+        remove_proxy_env();
+    }
+
+    let client = create_kube_config(&config)
+        .await
+        .and_then(|config| Client::try_from(config).map_err(From::from))
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
+
+    let namespaces = if namespaces.is_empty() {
+        vec![config
+            .target
+            .namespace
+            .clone()
+            .unwrap_or_else(|| client.default_namespace().to_string())]
+    } else {
+        namespaces
+    };
+
+    let mut list_progress = progress.subtask("listing services");
+    let services = list_services(&client, &namespaces)
+        .await
+        .map_err(CliError::DnsServicesListFailed)?;
+    list_progress.success(Some(&format!("found {} services", services.len())));
+
+    let mut analytics = NullReporter::default();
+    let (_, mut connection) = create_and_connect(&config, &mut progress, &mut analytics).await?;
+
+    let mut zone = resolve_zone(&mut connection, &services).await?;
+
+    let Some(output) = output else {
+        progress.success(None);
+        print!("{}", zone.render(format));
+        return Ok(());
+    };
+
+    write_export(&output, &zone.render(format))?;
+    progress.success(Some(&format!(
+        "exported {} addresses to {}, press Ctrl+C to stop",
+        zone.0.len(),
+        output.display()
+    )));
+
+    let result = loop {
+        tokio::select! {
+            _ = signal::ctrl_c() => break Ok(()),
+            _ = time::sleep(REFRESH_INTERVAL) => {}
+        }
+
+        let refreshed = match resolve_zone(&mut connection, &services).await {
+            Ok(refreshed) => refreshed,
+            Err(error) => break Err(error),
+        };
+        if refreshed != zone {
+            zone = refreshed;
+            if let Err(error) = write_export(&output, &zone.render(format)) {
+                break Err(error);
+            }
+        }
+    };
+
+    if let Err(error) = std::fs::remove_file(&output) {
+        tracing::warn!(%error, path = %output.display(), "Failed to remove the DNS export.");
+    }
+
+    result
+}
+
+/// Handle commands related to the cluster DNS `mirrord dns ...`
+pub(crate) async fn dns_command(args: DnsArgs) -> CliResult<()> {
+    match args.command {
+        DnsCommand::Export {
+            config_file,
+            namespaces,
+            format,
+            output,
+        } => dns_export(config_file.as_deref(), namespaces, format, output).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(name: &str, ip: &str) -> LookupRecord {
+        LookupRecord {
+            name: name.into(),
+            ip: ip.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn render_zone() {
+        let mut zone = Zone::default();
+        zone.add(
+            &ServiceName {
+                name: "api".into(),
+                namespace: "default".into(),
+                short: true,
+            },
+            &[record("api.default.svc.cluster.local.", "10.0.0.1")],
+        );
+        zone.add(
+            &ServiceName {
+                name: "db".into(),
+                namespace: "data".into(),
+                short: false,
+            },
+            &[
+                record("db.data.svc.cluster.local.", "10.0.0.3"),
+                record("db.data.svc.cluster.local.", "10.0.0.2"),
+            ],
+        );
+        zone.add(
+            &ServiceName {
+                name: "gone".into(),
+                namespace: "data".into(),
+                short: false,
+            },
+            &[],
+        );
+
+        assert_eq!(
+            zone.render(DnsExportFormat::Hosts),
+            "10.0.0.1 api api.default api.default.svc api.default.svc.cluster.local\n\
+            10.0.0.2 db.data db.data.svc db.data.svc.cluster.local\n\
+            10.0.0.3 db.data db.data.svc db.data.svc.cluster.local\n"
+        );
+        assert_eq!(
+            zone.render(DnsExportFormat::Dnsmasq),
+            "host-record=api,api.default,api.default.svc,api.default.svc.cluster.local,10.0.0.1\n\
+            host-record=db.data,db.data.svc,db.data.svc.cluster.local,10.0.0.2\n\
+            host-record=db.data,db.data.svc,db.data.svc.cluster.local,10.0.0.3\n"
+        );
+    }
+}
@@ -1,12 +1,23 @@
-use std::{ffi::NulError, net::SocketAddr, path::PathBuf, str::FromStr};
+use std::{
+    ffi::NulError,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+};
 
 use kube::core::ErrorResponse;
-use miette::Diagnostic;
+use miette::{Diagnostic, LabeledSpan, Severity, SourceCode};
+use mirrord_auth::error::{CredentialBundleError, CredentialStoreError};
 use mirrord_config::config::ConfigError;
 use mirrord_console::error::ConsoleError;
 use mirrord_intproxy::{agent_conn::ConnectionTlsError, error::IntProxyError};
 use mirrord_kube::error::KubeApiError;
-use mirrord_operator::client::error::{HttpError, OperatorApiError, OperatorOperation};
+use mirrord_operator::{
+    client::error::{HttpError, OperatorApiError, OperatorOperation},
+    crd::{target_allowlist::TargetNotAllowed, InvalidSessionShareLink},
+};
+use mirrord_protocol::ErrorCode;
 use mirrord_vpn::error::VpnError;
 use reqwest::StatusCode;
 use thiserror::Error;
@@ -100,6 +111,7 @@ pub(crate) enum ExternalProxyError {
     #[diagnostic(help("{GENERAL_HELP}"))]
     OpenLogFile(String, std::io::Error),
 
+    #[cfg(unix)]
     #[error("Failed to set sid: {0}")]
     #[diagnostic(help("{GENERAL_HELP}"))]
     SetSid(nix::Error),
@@ -122,6 +134,7 @@ pub(crate) enum InternalProxyError {
     #[diagnostic(help("{GENERAL_BUG}"))]
     ListenerSetup(std::io::Error),
 
+    #[cfg(unix)]
     #[error("Failed to set sid: {0}")]
     #[diagnostic(help("{GENERAL_HELP}"))]
     SetSid(nix::Error),
@@ -145,6 +158,36 @@ pub(crate) enum InternalProxyError {
     #[error("Initial ping pong with the agent failed: {0}")]
     #[diagnostic(help("{GENERAL_BUG}"))]
     InitialPingPongFailed(String),
+
+    #[error("Measuring the clock skew of the agent failed: {0}")]
+    #[diagnostic(help(
+        "You can skip the measurement with `internal_proxy.clock_skew_threshold_ms: 0`.{GENERAL_HELP}"
+    ))]
+    ClockProbeFailed(String),
+
+    #[error("Checking the reachability of the outgoing destinations failed: {0}")]
+    #[diagnostic(help(
+        "You can skip the check with `agent.check_network_policies: false`.{GENERAL_HELP}"
+    ))]
+    ReachabilityCheckFailed(String),
+
+    #[error("Failed to bind the connect proxy to port {0}: {1}")]
+    #[diagnostic(help(
+        "Make sure that `internal_proxy.connect_proxy_port` is not used by another process.{GENERAL_HELP}"
+    ))]
+    ConnectProxySetup(u16, std::io::Error),
+
+    #[error("Failed to listen for the layer connections on the pinned address `{0}`: {1}")]
+    #[diagnostic(help(
+        "Make sure that `internal_proxy.port` is not used by another process, e.g. another mirrord session with the same config.{GENERAL_HELP}"
+    ))]
+    PinnedListenerSetup(SocketAddr, std::io::Error),
+
+    #[error(
+        "Refusing to listen for the layer connections on `{0}` without an authentication token"
+    )]
+    #[diagnostic(help("{GENERAL_BUG}"))]
+    AuthTokenMissing(IpAddr),
 }
 
 /// Errors that can occur when executing the `mirrord operator setup` command.
@@ -208,6 +251,10 @@ pub(crate) enum CliError {
     #[diagnostic(help("Please check agent status and logs.{GENERAL_HELP}"))]
     InitialAgentCommFailed(String),
 
+    #[error("{message}")]
+    #[diagnostic(help("{hint}{GENERAL_HELP}"))]
+    RemoteComponentTooOld { message: String, hint: &'static str },
+
     #[error("Failed to execute binary `{0}` with args {1:?}")]
     #[diagnostic(help(
         "Please open an issue on our GitHub repository with binary information:
@@ -219,6 +266,14 @@ pub(crate) enum CliError {
     ))]
     BinaryExecuteFailed(String, Vec<String>),
 
+    #[cfg(windows)]
+    #[error("Failed to execute binary `{0}` in WSL: {1}")]
+    #[diagnostic(help(
+        "On Windows mirrord runs the binary inside WSL, make sure that WSL is installed and that \
+        `wsl.exe` is available in your `PATH`.{GENERAL_HELP}"
+    ))]
+    WslExecFailed(String, std::io::Error),
+
     #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
     #[error("Binary is SIP protected and rosetta is missing")]
     #[diagnostic(help(
@@ -360,6 +415,20 @@ pub(crate) enum CliError {
     ))]
     OperatorNotInstalled,
 
+    #[error("mirrord operator did not issue a token for the shared session.")]
+    #[diagnostic(help("{GENERAL_BUG}"))]
+    OperatorSessionShareFailed,
+
+    #[error(transparent)]
+    #[diagnostic(help("Ask the owner of the session to share it again.{GENERAL_HELP}"))]
+    InvalidSessionShareLink(InvalidSessionShareLink),
+
+    #[error("mirrord operator rejected the target: {0}")]
+    #[diagnostic(help(
+        "Choose a target that one of these allowlists allows, you can see them with `kubectl get mirrordtargetallowlists <name> -o yaml`.{GENERAL_HELP}"
+    ))]
+    OperatorTargetNotAllowed(TargetNotAllowed),
+
     #[error("mirrord returned a target resource of unknown type: {0}")]
     #[diagnostic(help("{GENERAL_BUG}"))]
     OperatorReturnedUnknownTargetType(String),
@@ -387,6 +456,13 @@ pub(crate) enum CliError {
     "))]
     KubeAuthExecFailed(String),
 
+    #[error("Authentication command specified in kubeconfig failed: {0}")]
+    #[diagnostic(help("
+        The Kube authentication command (e.g. `aws eks get-token`) ran, but failed to get the credentials.
+        This usually means that your cloud session has expired and you have to log in again, e.g. with `aws sso login`, `gcloud auth login` or `az login`.{GENERAL_HELP}
+    "))]
+    KubeAuthExecRunFailed(String),
+
     #[error("Failed while resolving target while using the mirrord-operator: {0}")]
     #[diagnostic(help(
         "
@@ -403,6 +479,127 @@ pub(crate) enum CliError {
 
     #[error("Couldn't resolve binary name '{0}': {1}")]
     BinaryWhichError(String, String),
+
+    #[error("Failed to write the report archive to `{}`: {1}", .0.display())]
+    #[diagnostic(help(
+        "Please check that you have permissions to write to that path.{GENERAL_HELP}"
+    ))]
+    ReportArchiveFailed(PathBuf, std::io::Error),
+
+    #[error("Failed to list the services of the cluster: {0}")]
+    #[diagnostic(help(
+        "Please check that your Kubernetes user is allowed to list services in the exported \
+        namespaces, e.g. with `kubectl get services`.{GENERAL_HELP}"
+    ))]
+    DnsServicesListFailed(KubeApiError),
+
+    #[error("Failed to resolve the cluster services through the agent: {0}")]
+    #[diagnostic(help("Please check agent status and logs.{GENERAL_HELP}"))]
+    DnsExportFailed(String),
+
+    #[error("Failed to write the DNS export to `{}`: {1}", .0.display())]
+    #[diagnostic(help(
+        "Please check that you have permissions to write to that path.{GENERAL_HELP}"
+    ))]
+    DnsExportWriteFailed(PathBuf, std::io::Error),
+
+    #[error(
+        "No running mirrord session{} was found",
+        .0.map(|pid| format!(" with id {pid}")).unwrap_or_default()
+    )]
+    #[diagnostic(help(
+        "Sessions started with `internal_proxy.container_mode` can't be toggled.{GENERAL_HELP}"
+    ))]
+    ToggleSessionNotFound(Option<u32>),
+
+    #[error("Multiple mirrord sessions are running: {0}")]
+    #[diagnostic(help("Please choose one of them with `--session <ID>`."))]
+    ToggleSessionAmbiguous(String),
+
+    #[error("Failed to toggle mirrord session {0}: {1}")]
+    #[diagnostic(help("Please check the internal proxy logs of the session.{GENERAL_HELP}"))]
+    ToggleFailed(u32, String),
+
+    #[error("`mirrord cron` requires a CronJob target")]
+    #[diagnostic(help(
+        "Please set the target to `cronjob/<NAME>`, with `--target` or in the config file."
+    ))]
+    CronJobTargetRequired,
+
+    #[error("Failed to access CronJob `{0}`: {1}")]
+    #[diagnostic(help(
+        "Please check that your Kubernetes user is allowed to get and patch the CronJob, e.g. \
+        with `kubectl auth can-i patch cronjobs`.{GENERAL_HELP}"
+    ))]
+    CronJobApiFailed(String, KubeApiError),
+
+    #[error("Failed to restore the schedule of CronJob `{0}`: {1}")]
+    #[diagnostic(help(
+        "The CronJob is still suspended, please restore it with \
+        `kubectl patch cronjob <NAME> -p '{{\"spec\":{{\"suspend\":false}}}}'`."
+    ))]
+    CronJobRestoreFailed(String, KubeApiError),
+
+    #[error("Failed to run the CronJob locally: {0}")]
+    #[diagnostic(help("{GENERAL_HELP}"))]
+    CronJobFailed(String),
+
+    #[error("mirrord selftest failed: {0}")]
+    #[diagnostic(help(
+        "Check the details of the failed features above, and the errors of the test session.\
+        {GENERAL_HELP}"
+    ))]
+    SelftestFailed(String),
+
+    #[error("mirrord loadgen failed: {0}")]
+    #[diagnostic(help(
+        "Check that the local application is listening on the port, and that the HTTP filter \
+        in the config is a regex that matches a plain request.{GENERAL_HELP}"
+    ))]
+    LoadgenFailed(String),
+
+    #[error("No passphrase for the credential bundle")]
+    #[diagnostic(help(
+        "Please set the passphrase in the `MIRRORD_AUTH_PASSPHRASE` env var, or pass a file \
+        that contains it with `--passphrase-file`."
+    ))]
+    AuthPassphraseMissing,
+
+    #[error("Failed to access the credential bundle file `{}`: {1}", .0.display())]
+    #[diagnostic(help("Please check that the path exists and that you have permissions to it."))]
+    AuthFileFailed(PathBuf, std::io::Error),
+
+    #[error("Failed to access the local credential store: {0}")]
+    #[diagnostic(help(
+        "Please check that you have permissions to `~/.mirrord/credentials`.{GENERAL_HELP}"
+    ))]
+    CredentialStoreFailed(CredentialStoreError),
+
+    #[error("Credential bundle error: {0}")]
+    #[diagnostic(help(
+        "Please check that the passphrase is the one used to export the bundle, and that the \
+        bundle was made with `mirrord auth export` or `mirrord auth bootstrap`."
+    ))]
+    CredentialBundleFailed(#[from] CredentialBundleError),
+
+    #[error("No operator credentials to export")]
+    #[diagnostic(help(
+        "Operator credentials are created by the first run against the operator, or imported \
+        with `mirrord auth import`. If you passed `--license-fingerprint`, please check it \
+        with `mirrord operator status`."
+    ))]
+    AuthNothingToExport,
+
+    #[error("The mirrord operator resource has no license fingerprint")]
+    #[diagnostic(help("Please upgrade the mirrord operator.{GENERAL_HELP}"))]
+    AuthLicenseFingerprintMissing,
+
+    #[error("Failed to issue operator credentials for `{0}`: {1}")]
+    #[diagnostic(help(
+        "Please check that your Kubernetes user is allowed to request certificates from the \
+        mirrord operator.{GENERAL_HELP}"
+    ))]
+    AuthBootstrapFailed(String, CredentialStoreError),
 }
 
 impl CliError {
@@ -421,6 +618,9 @@ impl CliError {
             KubeApiError::KubeError(Error::Auth(AuthError::AuthExec(error))) => {
                 Self::KubeAuthExecFailed(error.to_owned())
             }
+            KubeApiError::KubeError(Error::Auth(error @ AuthError::AuthExecRun { .. })) => {
+                Self::KubeAuthExecRunFailed(error.to_string())
+            }
             // UGH(alex): Type-erased errors are messy, and this one is especially bad.
             // See `kube_service_error_dependency_is_in_sync` for a "what's going on here".
             KubeApiError::KubeError(Error::Service(ref fail))
@@ -457,6 +657,10 @@ impl From<OperatorApiError> for CliError {
                 error: Error::Auth(AuthError::AuthExec(error)),
                 ..
             } => Self::KubeAuthExecFailed(error),
+            OperatorApiError::KubeError {
+                error: Error::Auth(error @ AuthError::AuthExecRun { .. }),
+                ..
+            } => Self::KubeAuthExecRunFailed(error.to_string()),
             OperatorApiError::KubeError { error, operation } => {
                 Self::OperatorApiFailed(operation, error)
             }
@@ -481,10 +685,301 @@ impl From<OperatorApiError> for CliError {
                 Self::OperatorReturnedUnknownTargetType(error.0)
             }
             OperatorApiError::KubeApi(error) => Self::OperatorTargetResolution(error),
+            OperatorApiError::InvalidSessionShareLink(error) => {
+                Self::InvalidSessionShareLink(error)
+            }
+            OperatorApiError::TargetNotAllowed(error) => Self::OperatorTargetNotAllowed(error),
+        }
+    }
+}
+
+impl CliError {
+    /// Classifies this error for the tools that wrap mirrord, see [`ErrorCode`].
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::CreateKubeApiFailed(error)
+            | Self::ListTargetsFailed(error)
+            | Self::DnsServicesListFailed(error)
+            | Self::CronJobApiFailed(_, error)
+            | Self::CronJobRestoreFailed(_, error) => {
+                kube_error_code(error, ErrorCode::KubeApiFailed)
+            }
+            Self::CreateAgentFailed(error) => kube_error_code(error, ErrorCode::AgentStartFailed),
+            Self::AgentConnectionFailed(error) | Self::PortForwardingSetupError(error) => {
+                kube_error_code(error, ErrorCode::AgentConnectionFailed)
+            }
+            Self::OperatorTargetResolution(error) => {
+                kube_error_code(error, ErrorCode::TargetUnavailable)
+            }
+            Self::InvalidCertificate(..) => ErrorCode::KubeCertificateInvalid,
+            Self::KubeAuthExecFailed(..) | Self::KubeAuthExecRunFailed(..) => {
+                ErrorCode::KubeAuthFailed
+            }
+            Self::InitialAgentCommFailed(..)
+            | Self::DnsExportFailed(..)
+            | Self::SelftestFailed(..) => ErrorCode::AgentFailed,
+            Self::PingPongFailed(..) | Self::AgentConnTlsError(..) => {
+                ErrorCode::AgentConnectionFailed
+            }
+            Self::RemoteComponentTooOld { .. }
+            | Self::FeatureNotSupportedInOperatorError { .. }
+            | Self::OperatorReturnedUnknownTargetType(..)
+            | Self::AuthLicenseFingerprintMissing => ErrorCode::ProtocolVersionSkew,
+            Self::BinaryExecuteFailed(..)
+            | Self::ExecNulError(..)
+            | Self::BinaryWhichError(..)
+            | Self::CronJobFailed(..) => ErrorCode::ExecFailed,
+            #[cfg(windows)]
+            Self::WslExecFailed(..) => ErrorCode::ExecFailed,
+            #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+            Self::RosettaMissing(..) => ErrorCode::ExecFailed,
+            #[cfg(target_os = "macos")]
+            Self::SipError(..) => ErrorCode::ExecFailed,
+            Self::ConfigError(..)
+            | Self::CanonicalizeConfigPathFailed(..)
+            | Self::ToggleSessionNotFound(..)
+            | Self::ToggleSessionAmbiguous(..)
+            | Self::CronJobTargetRequired
+            | Self::InvalidSessionShareLink(..) => ErrorCode::ConfigInvalid,
+            Self::OperatorSetupError(error) => match error {
+                OperatorSetupError::OperatorVersionCheck(..) => ErrorCode::OperatorFailed,
+                OperatorSetupError::OutputFileOpen(..) | OperatorSetupError::SetupWrite(..) => {
+                    ErrorCode::LocalIoFailed
+                }
+            },
+            Self::ContainerError(error) => match error {
+                ContainerError::UnableToExecuteCommand(..)
+                | ContainerError::UnableReadCommandStdout(..)
+                | ContainerError::UnableReadCommandStderr(..)
+                | ContainerError::UnsuccesfulCommandExecute(..)
+                | ContainerError::UnsuccesfulCommandOutput(..) => ErrorCode::ExecFailed,
+                ContainerError::ConfigWrite(..)
+                | ContainerError::WriteSelfSignedCertificate(..) => ErrorCode::LocalIoFailed,
+                ContainerError::ConfigSerialization(..)
+                | ContainerError::SelfSignedCertificate(..)
+                | ContainerError::UnableParseProxySocketAddr(..) => ErrorCode::Internal,
+            },
+            Self::ExternalProxyError(error) => match error {
+                ExternalProxyError::Intproxy(error) => error.code(),
+                ExternalProxyError::Tls(..) => ErrorCode::AgentConnectionFailed,
+                ExternalProxyError::OpenLogFile(..) => ErrorCode::LocalIoFailed,
+                _ => ErrorCode::Internal,
+            },
+            Self::InternalProxyError(error) => match error {
+                InternalProxyError::Intproxy(error) => error.code(),
+                InternalProxyError::Config(..) => ErrorCode::ConfigInvalid,
+                InternalProxyError::InitialPingPongFailed(..) => ErrorCode::AgentConnectionFailed,
+                InternalProxyError::ClockProbeFailed(..)
+                | InternalProxyError::ReachabilityCheckFailed(..) => ErrorCode::AgentFailed,
+                InternalProxyError::OpenLogFile(..)
+                | InternalProxyError::ConnectProxySetup(..)
+                | InternalProxyError::PinnedListenerSetup(..) => ErrorCode::LocalIoFailed,
+                _ => ErrorCode::Internal,
+            },
+            Self::VpnError(error) => match error {
+                VpnError::AgentErrorResponse(error) => error.code(),
+                VpnError::AgentProtocolVersionMismatch(..) => ErrorCode::ProtocolVersionSkew,
+                VpnError::SetupIO(..) => ErrorCode::LocalIoFailed,
+                _ => ErrorCode::AgentFailed,
+            },
+            Self::PortForwardingError(error) => match error {
+                PortForwardError::ArgsError(..)
+                | PortForwardError::PortMapSetupError(..)
+                | PortForwardError::ReversePortMapSetupError(..)
+                | PortForwardError::NoMappingsError() => ErrorCode::ConfigInvalid,
+                PortForwardError::AgentError(..) => ErrorCode::AgentFailed,
+                PortForwardError::AgentConnectionFailed | PortForwardError::PingError(..) => {
+                    ErrorCode::AgentConnectionFailed
+                }
+                PortForwardError::IncomingProxyError(error) => error.code(),
+                PortForwardError::SubscriptionError(error) => error.code(),
+                PortForwardError::TcpListenerError(..)
+                | PortForwardError::TcpStreamError(..)
+                | PortForwardError::ConnectionError(..) => ErrorCode::LocalIoFailed,
+                _ => ErrorCode::Internal,
+            },
+            Self::FeatureRequiresOperatorError(..) => ErrorCode::OperatorRequired,
+            Self::OperatorNotInstalled => ErrorCode::OperatorNotInstalled,
+            Self::OperatorLicenseExpired => ErrorCode::OperatorLicenseInvalid,
+            Self::OperatorApiForbidden(..) => ErrorCode::RbacDenied,
+            Self::OperatorTargetNotAllowed(..) => ErrorCode::PolicyForbidden,
+            Self::OperatorApiFailed(..) | Self::OperatorSessionShareFailed => {
+                ErrorCode::OperatorFailed
+            }
+            Self::OperatorClientCertError(..)
+            | Self::AuthPassphraseMissing
+            | Self::CredentialStoreFailed(..)
+            | Self::CredentialBundleFailed(..)
+            | Self::AuthNothingToExport
+            | Self::AuthBootstrapFailed(..) => ErrorCode::CredentialsFailed,
+            Self::LayerExtractError(..)
+            | Self::ConsoleConnectError(..)
+            | Self::ReportArchiveFailed(..)
+            | Self::DnsExportWriteFailed(..)
+            | Self::LoadgenFailed(..)
+            | Self::AuthFileFailed(..) => ErrorCode::LocalIoFailed,
+            Self::JsonSerializeError(..)
+            | Self::InternalProxySpawnError(..)
+            | Self::CliPathError(..)
+            | Self::InternalProxyWaitError(..)
+            | Self::RuntimeError(..)
+            | Self::ConnectRequestBuildError(..)
+            | Self::PortForwardingNoConnectionMethod
+            | Self::ToggleFailed(..) => ErrorCode::Internal,
         }
     }
 }
 
+/// Classifies the [`KubeApiError`] of an operation, `fallback` is used when the error is specific
+/// to the operation.
+fn kube_error_code(error: &KubeApiError, fallback: ErrorCode) -> ErrorCode {
+    use kube::Error;
+
+    match error {
+        KubeApiError::KubeError(Error::Api(ErrorResponse { code, .. }))
+            if *code == StatusCode::FORBIDDEN =>
+        {
+            ErrorCode::RbacDenied
+        }
+        KubeApiError::KubeError(Error::Auth(..)) => ErrorCode::KubeAuthFailed,
+        KubeApiError::InferKubeConfigError(..)
+        | KubeApiError::KubeConfigPathError(..)
+        | KubeApiError::KubeInclusterError(..)
+        | KubeApiError::ConfigPathExpansionError(..)
+        | KubeApiError::InvalidKubeProxy(..)
+        | KubeApiError::KubeCaBundleError(..) => ErrorCode::KubeConfigInvalid,
+        KubeApiError::AgentImagePullFailed { .. } => ErrorCode::AgentImagePullFailed,
+        KubeApiError::AgentPodNotRunning | KubeApiError::AgentReadyTimeout => {
+            ErrorCode::AgentStartFailed
+        }
+        KubeApiError::RetriesExhausted { source, .. } => kube_error_code(source, fallback),
+        _ => fallback,
+    }
+}
+
+/// Remediation hint of the errors with the given [`ErrorCode`], shown before the help of the
+/// error itself.
+fn remediation(code: ErrorCode) -> Option<&'static str> {
+    let hint = match code {
+        ErrorCode::KubeConfigInvalid => {
+            "Check your kubeconfig with `kubectl config view`, and the `kubeconfig` in the mirrord \
+            config."
+        }
+        ErrorCode::KubeAuthFailed => {
+            "Log in to your cluster again, e.g. with `aws sso login`, `gcloud auth login` or \
+            `az login`."
+        }
+        ErrorCode::KubeCertificateInvalid => {
+            "Check the certificate of the cluster, or enable `accept_invalid_certificates` in the \
+            mirrord config."
+        }
+        ErrorCode::RbacDenied => {
+            "Your Kubernetes user is missing permissions, check them with `kubectl auth can-i` and \
+            ask your cluster admin for the missing ones."
+        }
+        ErrorCode::KubeApiFailed => "Test your connection to the cluster with `kubectl get pods`.",
+        ErrorCode::TargetUnavailable => {
+            "Check that the target exists and is running, e.g. with `mirrord ls`."
+        }
+        ErrorCode::AgentImagePullFailed => {
+            "Check that the cluster can pull `agent.image`, private registries require \
+            `agent.image_pull_secrets`."
+        }
+        ErrorCode::AgentStartFailed => {
+            "Check the agent pod with `kubectl describe pod`, it may lack resources or be blocked \
+            by an admission policy."
+        }
+        ErrorCode::AgentConnectionFailed => {
+            "Check that the agent is running, and that you're allowed to port forward to it."
+        }
+        ErrorCode::AgentFailed => "Check the logs of the agent pod.",
+        ErrorCode::ProtocolVersionSkew => {
+            "Update the mirrord operator or the agent image to match this mirrord version."
+        }
+        ErrorCode::OperatorNotInstalled => {
+            "Install the mirrord operator, or set `\"operator\": false` in the mirrord config."
+        }
+        ErrorCode::OperatorLicenseInvalid => "Renew your license at https://app.metalbear.co.",
+        ErrorCode::OperatorFailed => {
+            "Check the mirrord operator with `mirrord operator status` and its logs."
+        }
+        ErrorCode::OperatorRequired => {
+            "The mirrord operator is part of mirrord for Teams, see \
+            https://mirrord.dev/docs/overview/teams/."
+        }
+        ErrorCode::PolicyForbidden => {
+            "Ask your mirrord admin about the policies that apply to the target."
+        }
+        ErrorCode::PortAlreadyStolen => {
+            "Another mirrord session steals the port, use an HTTP filter to share it."
+        }
+        ErrorCode::ReadOnlySession => {
+            "The session is read-only, remove `\"mode\": \"read_only\"` from the mirrord config."
+        }
+        ErrorCode::ConfigInvalid => {
+            "Check the mirrord config and the arguments, e.g. with `mirrord verify-config`."
+        }
+        ErrorCode::ExecFailed => "Check that the binary can be executed without mirrord.",
+        ErrorCode::LocalIoFailed => {
+            "Check the permissions of the local files and ports used by mirrord."
+        }
+        ErrorCode::CredentialsFailed => {
+            "Check your operator credentials with `mirrord auth`, and the passphrase of the \
+            credential bundle."
+        }
+        ErrorCode::RemoteOperationFailed | ErrorCode::Internal => return None,
+    };
+
+    Some(hint)
+}
+
+/// [`CliError`] reported with its [`ErrorCode`] as the diagnostic code, and the
+/// [`remediation`] hint of the code.
+///
+/// Tools that wrap mirrord should rely on the code, e.g. the `code` field of the JSON output,
+/// rather than on the message.
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub(crate) struct CodedCliError(pub(crate) CliError);
+
+impl Diagnostic for CodedCliError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.0.code()))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        match (remediation(self.0.code()), self.0.help()) {
+            (Some(hint), Some(help)) => Some(Box::new(format!("{hint}\n{help}"))),
+            (Some(hint), None) => Some(Box::new(hint)),
+            (None, help) => help,
+        }
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        self.0.severity()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.0.url()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.0.source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.0.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        self.0.related()
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.0.diagnostic_source()
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("unsupported runtime version")]
 pub struct UnsupportedRuntimeVariant;
@@ -507,7 +1002,13 @@ mod tests {
         server::conn::auto::Builder,
     };
     use k8s_openapi::api::core::v1::Pod;
-    use kube::{api::ListParams, Api};
+    use kube::{api::ListParams, core::ErrorResponse, Api};
+    use miette::Diagnostic;
+    use mirrord_kube::error::KubeApiError;
+    use mirrord_operator::{
+        client::error::OperatorApiError, crd::target_allowlist::TargetNotAllowed,
+    };
+    use mirrord_protocol::ErrorCode;
     use rustls::{
         crypto::aws_lc_rs::default_provider,
         pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
@@ -516,6 +1017,56 @@ mod tests {
     use tokio::{net::TcpListener, sync::Notify};
     use tokio_rustls::TlsAcceptor;
 
+    use super::{CliError, CodedCliError};
+
+    #[test]
+    fn kube_error_codes() {
+        let forbidden =
+            CliError::CreateAgentFailed(KubeApiError::KubeError(kube::Error::Api(ErrorResponse {
+                status: "Failure".to_string(),
+                message: "jobs.batch is forbidden".to_string(),
+                reason: "Forbidden".to_string(),
+                code: 403,
+            })));
+        assert_eq!(forbidden.code(), ErrorCode::RbacDenied);
+
+        let image_pull = CliError::CreateAgentFailed(KubeApiError::AgentImagePullFailed {
+            image: "ghcr.io/metalbear-co/mirrord:latest".to_string(),
+            reason: "ImagePullBackOff".to_string(),
+        });
+        assert_eq!(image_pull.code(), ErrorCode::AgentImagePullFailed);
+
+        let timeout = CliError::CreateAgentFailed(KubeApiError::AgentReadyTimeout);
+        assert_eq!(timeout.code(), ErrorCode::AgentStartFailed);
+    }
+
+    /// The code and its remediation hint are what the JSON output reports.
+    #[test]
+    fn coded_error_diagnostic() {
+        let error = CodedCliError(CliError::OperatorLicenseExpired);
+
+        assert_eq!(
+            error.code().map(|code| code.to_string()).as_deref(),
+            Some("operator_license_invalid")
+        );
+
+        let help = error.help().unwrap().to_string();
+        assert!(help.starts_with("Renew your license"), "{help}");
+    }
+
+    /// Denials by the target allowlists name the allowlists that denied the target.
+    #[test]
+    fn target_not_allowed() {
+        let error = CliError::from(OperatorApiError::TargetNotAllowed(TargetNotAllowed {
+            namespace: "search".to_string(),
+            target_path: "deploy/api".to_string(),
+            denied_by: vec!["team-payments".to_string()],
+        }));
+
+        assert_eq!(error.code(), ErrorCode::PolicyForbidden);
+        assert!(error.to_string().contains("team-payments"), "{error}");
+    }
+
     /// With this test we're trying to `assert` that our [`kube`] crate is (somewhat)
     /// version-synced with [`rustls`]. To give a friendlier error message on kube requests
     /// when there's a certificate problem, we must dig down into the [`kube::Error`].
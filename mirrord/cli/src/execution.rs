@@ -1,22 +1,24 @@
 use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
+    path::PathBuf,
     time::Duration,
 };
 
 use mirrord_analytics::{AnalyticsError, AnalyticsReporter, Reporter};
 use mirrord_config::{
-    config::ConfigError, internal_proxy::MIRRORD_INTPROXY_CONNECT_TCP_ENV, LayerConfig,
+    config::ConfigError,
+    feature::{fs::FsModeConfig, network::incoming::IncomingMode},
+    internal_proxy::{MIRRORD_INTPROXY_AUTH_TOKEN_ENV, MIRRORD_INTPROXY_CONNECT_TCP_ENV},
+    LayerConfig,
 };
 use mirrord_intproxy::agent_conn::AgentConnectInfo;
 use mirrord_operator::client::OperatorSession;
-use mirrord_progress::Progress;
-use mirrord_protocol::{
-    tcp::HTTP_COMPOSITE_FILTER_VERSION, ClientMessage, DaemonMessage, EnvVars, GetEnvVarsRequest,
-    LogLevel,
-};
+use mirrord_progress::{Progress, ProgressGroup};
+use mirrord_protocol::{ClientMessage, DaemonMessage, EnvVars, GetEnvVarsRequest, LogLevel};
 #[cfg(target_os = "macos")]
 use mirrord_sip::sip_patch;
+use rand::distributions::{Alphanumeric, DistString};
 use semver::Version;
 use serde::Serialize;
 use tokio::{
@@ -31,9 +33,14 @@ use tracing::{debug, error, trace, warn, Level};
 #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
 use crate::extract::extract_arm64;
 use crate::{
-    connection::{create_and_connect, AgentConnection, AGENT_CONNECT_INFO_ENV_KEY},
+    compatibility::{check_compatibility, RemoteComponent},
+    connection::{
+        create_and_connect, create_and_connect_clusters, AgentConnection,
+        AGENT_CONNECT_INFO_ENV_KEY, CLUSTERS_CONNECT_INFO_ENV_KEY,
+    },
     error::CliError,
     extract::extract_library,
+    internal_proxy::layer_auth_token,
     util::remove_proxy_env,
     CliResult,
 };
@@ -54,11 +61,16 @@ pub(crate) const INJECTION_ENV_VAR: &str = LINUX_INJECTION_ENV_VAR;
 #[cfg(target_os = "macos")]
 pub(crate) const INJECTION_ENV_VAR: &str = "DYLD_INSERT_LIBRARIES";
 
+/// On Windows the layer is injected into the process that runs inside WSL, see [`crate::wsl`].
+#[cfg(windows)]
+pub(crate) const INJECTION_ENV_VAR: &str = LINUX_INJECTION_ENV_VAR;
+
 /// Struct for holding the execution information.
 ///
 /// 1. Environment to set in the user process,
 /// 2. Environment to unset in the user process,
-/// 3. Path to the patched binary to `exec` into (SIP, only on macOS).
+/// 3. Path to the patched binary to `exec` into (SIP, only on macOS),
+/// 4. Process id of the internal proxy.
 #[derive(Debug, Serialize)]
 pub(crate) struct MirrordExecution {
     pub environment: HashMap<String, String>,
@@ -73,6 +85,9 @@ pub(crate) struct MirrordExecution {
 
     /// Whether this run uses mirrord operator.
     pub uses_operator: bool,
+
+    /// Process id of the internal proxy, identifies the session for `mirrord toggle`.
+    pub intproxy_pid: Option<u32>,
 }
 
 /// Struct that when dropped will cancel the token and wait on the join handle
@@ -185,36 +200,62 @@ impl MirrordExecution {
             .await
             .inspect_err(|_| analytics.set_error(AnalyticsError::AgentConnection))?;
 
-        if config.feature.network.incoming.http_filter.is_composite() {
-            let version = match &connect_info {
-                AgentConnectInfo::Operator(OperatorSession {
-                    operator_protocol_version: Some(version),
-                    ..
-                }) => Some(version.clone()),
-                AgentConnectInfo::DirectKubernetes(_) => {
-                    Some(MirrordExecution::get_agent_version(&mut connection).await?)
-                }
-                _ => None,
-            };
-            if !version
-                .map(|version| HTTP_COMPOSITE_FILTER_VERSION.matches(&version))
-                .unwrap_or(false)
-            {
-                Err(ConfigError::Conflict(format!(
-                    "Cannot use 'any_of' or 'all_of' HTTP filter types, protocol version used by mirrord-agent must match {}. Consider using a newer version of mirrord-agent",
-                    *HTTP_COMPOSITE_FILTER_VERSION
-                )))?
+        let remote = RemoteComponent::from_connect_info(&connect_info);
+        let protocol_version = match &connect_info {
+            AgentConnectInfo::Operator(OperatorSession {
+                operator_protocol_version,
+                ..
+            }) => operator_protocol_version.clone(),
+            AgentConnectInfo::DirectKubernetes(_) => {
+                Some(MirrordExecution::get_agent_version(&mut connection).await?)
             }
+            _ => None,
+        };
+        check_compatibility(config, remote, protocol_version.as_ref(), progress)?;
+
+        // Kept until the internal proxy makes its own connections.
+        let clusters = create_and_connect_clusters(config, progress, analytics)
+            .await
+            .inspect_err(|_| analytics.set_error(AnalyticsError::AgentConnection))?;
+
+        let mut network_progress = progress.group(ProgressGroup::Network);
+        network_progress.success(Some(match config.feature.network.incoming.mode {
+            IncomingMode::Mirror => "mirroring incoming traffic",
+            IncomingMode::Steal => "stealing incoming traffic",
+            IncomingMode::Off => "incoming traffic disabled",
+        }));
+
+        let mut fs_progress = progress.group(ProgressGroup::Fs);
+        fs_progress.success(Some(match config.feature.fs.mode {
+            FsModeConfig::Read => "reading files from the remote",
+            FsModeConfig::Write => "reading and writing files on the remote",
+            FsModeConfig::CopyOnWrite => "reading files from the remote, writing them locally",
+            FsModeConfig::Local | FsModeConfig::LocalWithOverrides => "files are local",
+        }));
+
+        let fs_overlay = Self::fs_overlay_dir(config);
+        if let Some(dir) = &fs_overlay {
+            progress.info(&format!(
+                "files written by the application are kept in {}",
+                dir.display()
+            ));
         }
 
         let mut env_vars = if config.feature.env.load_from_process.unwrap_or(false) {
             Default::default()
         } else {
-            Self::fetch_env_vars(config, &mut connection)
+            Self::fetch_env_vars_with_progress(config, &mut connection, progress)
                 .await
                 .inspect_err(|_| analytics.set_error(AnalyticsError::EnvFetch))?
         };
 
+        if let Some(dir) = fs_overlay {
+            env_vars.insert(
+                "MIRRORD_FILE_OVERLAY_DIR".to_string(),
+                dir.to_string_lossy().into(),
+            );
+        }
+
         #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
         env_vars.insert(
             "MIRRORD_MACOS_ARM64_LIBRARY".to_string(),
@@ -249,6 +290,22 @@ impl MirrordExecution {
             serde_json::to_string(&connect_info)?,
         );
 
+        if !clusters.is_empty() {
+            let clusters_connect_info = clusters
+                .iter()
+                .map(|(connect_info, _)| connect_info)
+                .collect::<Vec<_>>();
+            proxy_command.env(
+                CLUSTERS_CONNECT_INFO_ENV_KEY,
+                serde_json::to_string(&clusters_connect_info)?,
+            );
+        }
+
+        if let Some(auth_token) = layer_auth_token(config) {
+            proxy_command.env(MIRRORD_INTPROXY_AUTH_TOKEN_ENV, &auth_token);
+            env_vars.insert(MIRRORD_INTPROXY_AUTH_TOKEN_ENV.to_string(), auth_token);
+        }
+
         let mut proxy_process = proxy_command.spawn().map_err(|e| {
             CliError::InternalProxySpawnError(format!("failed to spawn child process: {e}"))
         })?;
@@ -277,11 +334,14 @@ impl MirrordExecution {
                 ))
             })?;
 
+        if let Some(port) = config.internal_proxy.connect_proxy_port {
+            progress.info(&format!(
+                "SOCKS5 and HTTP CONNECT proxy listening on 127.0.0.1:{port}"
+            ));
+        }
+
         // Provide details for layer to connect to agent via internal proxy
-        env_vars.insert(
-            MIRRORD_CONNECT_TCP_ENV.to_string(),
-            format!("127.0.0.1:{}", address.port()),
-        );
+        env_vars.insert(MIRRORD_CONNECT_TCP_ENV.to_string(), address.to_string());
 
         // Fixes <https://github.com/metalbear-co/mirrord/issues/1745>
         // by disabling the fork safety check in the Objective-C runtime.
@@ -310,6 +370,8 @@ impl MirrordExecution {
         #[cfg(not(target_os = "macos"))]
         let patched_path = None;
 
+        let intproxy_pid = proxy_process.id();
+
         Ok(Self {
             environment: env_vars,
             child: proxy_process,
@@ -322,10 +384,11 @@ impl MirrordExecution {
                 .map(|unset| unset.to_vec())
                 .unwrap_or_default(),
             uses_operator: matches!(connect_info, AgentConnectInfo::Operator(..)),
+            intproxy_pid,
         })
     }
 
-    async fn get_agent_version(connection: &mut AgentConnection) -> CliResult<Version> {
+    pub(crate) async fn get_agent_version(connection: &mut AgentConnection) -> CliResult<Version> {
         let Ok(_) = connection
             .sender
             .send(ClientMessage::SwitchProtocolVersion(
@@ -348,6 +411,22 @@ impl MirrordExecution {
         }
     }
 
+    /// The local [`overlay`](mirrord_config::feature::fs::FsConfig::overlay) directory for the
+    /// copy-on-write mode, a new one in the temporary directory when it's not set, so that
+    /// sessions don't mix their writes.
+    fn fs_overlay_dir(config: &LayerConfig) -> Option<PathBuf> {
+        if !config.feature.fs.mode.is_copy_on_write() {
+            return None;
+        }
+
+        let dir = config.feature.fs.overlay.clone().unwrap_or_else(|| {
+            let suffix = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+            std::env::temp_dir().join(format!("mirrord-overlay-{suffix}"))
+        });
+
+        Some(dir)
+    }
+
     /// Starts the external proxy (`extproxy`) so sidecar intproxy can connect via this to agent
     #[tracing::instrument(level = Level::TRACE, skip_all)]
     pub(crate) async fn start_external<P>(
@@ -369,7 +448,7 @@ impl MirrordExecution {
         let mut env_vars = if config.feature.env.load_from_process.unwrap_or(false) {
             Default::default()
         } else {
-            Self::fetch_env_vars(config, &mut connection)
+            Self::fetch_env_vars_with_progress(config, &mut connection, progress)
                 .await
                 .inspect_err(|_| analytics.set_error(AnalyticsError::EnvFetch))?
         };
@@ -439,9 +518,29 @@ impl MirrordExecution {
                 .map(|unset| unset.to_vec())
                 .unwrap_or_default(),
             uses_operator: matches!(connect_info, AgentConnectInfo::Operator(..)),
+            intproxy_pid: None,
         })
     }
 
+    /// [`Self::fetch_env_vars`], reported in the [`ProgressGroup::Env`].
+    async fn fetch_env_vars_with_progress<P>(
+        config: &LayerConfig,
+        connection: &mut AgentConnection,
+        progress: &P,
+    ) -> CliResult<HashMap<String, String>>
+    where
+        P: Progress + Send + Sync,
+    {
+        let mut env_progress = progress.group(ProgressGroup::Env);
+        let env_vars = Self::fetch_env_vars(config, connection).await?;
+        env_progress.success(Some(&format!(
+            "fetched {} environment variables",
+            env_vars.len()
+        )));
+
+        Ok(env_vars)
+    }
+
     /// Construct filter and retrieve remote environment from the connected agent using
     /// `MirrordExecution::get_remote_env`.
     async fn fetch_env_vars(
@@ -479,12 +578,13 @@ impl MirrordExecution {
             Duration::from_secs(config.agent.communication_timeout.unwrap_or(30).into());
 
         if !env_vars_exclude.is_empty() || !env_vars_include.is_empty() {
-            let remote_env = tokio::time::timeout(
+            let mut remote_env = tokio::time::timeout(
                 communication_timeout,
                 Self::get_remote_env(connection, env_vars_exclude, env_vars_include),
             )
             .await
             .map_err(|_| CliError::InitialAgentCommFailed("timeout".to_string()))??;
+            config.feature.env.rewrite_values(&mut remote_env);
             env_vars.extend(remote_env);
             if let Some(overrides) = &config.feature.env.r#override {
                 env_vars.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
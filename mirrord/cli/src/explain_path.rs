@@ -0,0 +1,89 @@
+//! `mirrord config explain-path [-f config] <path>` prints where the file operations on a path go,
+//! and which part of `feature.fs` decides it, see [`FsConfig::explain_path`].
+use std::path::Path;
+
+use mirrord_config::{
+    config::{ConfigContext, MirrordConfig},
+    feature::fs::{FsConfig, PathExplanation, PathOutcome, PathReason},
+    LayerConfig, LayerFileConfig,
+};
+
+use crate::{CliResult, ConfigArgs, ConfigCommand};
+
+/// Describes `explanation` in a line, e.g. `blocked (EACCES), by rule 1 of feature.fs.rules`.
+fn describe(explanation: &PathExplanation) -> String {
+    let outcome = match explanation.outcome {
+        PathOutcome::Remote => "remote",
+        PathOutcome::Local => "local",
+        PathOutcome::Blocked => "blocked (EACCES)",
+        PathOutcome::NotFound => "not found (ENOENT)",
+    };
+
+    let reason = match &explanation.reason {
+        PathReason::LocalMode => "by `feature.fs.mode: local`".to_string(),
+        PathReason::Rule { index, path } => {
+            format!("by rule {} of `feature.fs.rules` (`{path}`)", index + 1)
+        }
+        PathReason::Pattern(field) => format!("by a pattern in `feature.fs.{field}`"),
+        PathReason::Mode(mode) => format!(
+            "by `feature.fs.mode: {}`, if none of the patterns that mirrord applies by \
+            default (e.g. local `/etc` and `/tmp`) match the path",
+            format!("{mode:?}").to_lowercase()
+        ),
+    };
+
+    format!("{outcome}, {reason}")
+}
+
+fn explain_path(config: Option<&Path>, path: &str) -> CliResult<()> {
+    let mut cfg_context = ConfigContext::default();
+    let config: LayerConfig = if let Some(config) = config {
+        LayerFileConfig::from_path(config)?.generate_config(&mut cfg_context)
+    } else {
+        LayerFileConfig::default().generate_config(&mut cfg_context)
+    }?;
+    let fs: &FsConfig = &config.feature.fs;
+
+    println!("{path}");
+    println!("  read:  {}", describe(&fs.explain_path(path, false)?));
+    println!("  write: {}", describe(&fs.explain_path(path, true)?));
+
+    Ok(())
+}
+
+pub(crate) fn config_command(args: ConfigArgs) -> CliResult<()> {
+    match args.command {
+        ConfigCommand::ExplainPath { config_file, path } => {
+            explain_path(config_file.as_deref(), &path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_config::feature::fs::FsModeConfig;
+
+    use super::*;
+
+    #[test]
+    fn describe_explanations() {
+        assert_eq!(
+            describe(&PathExplanation {
+                outcome: PathOutcome::Blocked,
+                reason: PathReason::Rule {
+                    index: 0,
+                    path: "/app/config/**".into(),
+                },
+            }),
+            "blocked (EACCES), by rule 1 of `feature.fs.rules` (`/app/config/**`)"
+        );
+        assert_eq!(
+            describe(&PathExplanation {
+                outcome: PathOutcome::Local,
+                reason: PathReason::Mode(FsModeConfig::LocalWithOverrides),
+            }),
+            "local, by `feature.fs.mode: localwithoverrides`, if none of the patterns that \
+            mirrord applies by default (e.g. local `/etc` and `/tmp`) match the path"
+        );
+    }
+}
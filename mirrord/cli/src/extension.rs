@@ -4,7 +4,10 @@ use mirrord_analytics::{AnalyticsError, AnalyticsReporter, Reporter};
 use mirrord_config::{LayerConfig, MIRRORD_CONFIG_FILE_ENV};
 use mirrord_progress::{JsonProgress, Progress, ProgressTracker};
 
-use crate::{config::ExtensionExecArgs, error::CliError, execution::MirrordExecution, CliResult};
+use crate::{
+    config::ExtensionExecArgs, error::CliError, execution::MirrordExecution, preflight, routes,
+    CliResult,
+};
 
 /// Actually facilitate execution after all preparations were complete
 async fn mirrord_exec<P>(
@@ -57,7 +60,7 @@ pub(crate) async fn extension_exec(args: ExtensionExecArgs, watch: drain::Watch)
         std::env::set_var("MIRRORD_IMPERSONATED_TARGET", target.clone());
         env.insert("MIRRORD_IMPERSONATED_TARGET".into(), target.to_string());
     }
-    let (config, mut context) = LayerConfig::from_env_with_warnings()?;
+    let (mut config, mut context) = LayerConfig::from_env_with_warnings()?;
 
     let mut analytics = AnalyticsReporter::only_error(config.telemetry, Default::default(), watch);
 
@@ -66,6 +69,16 @@ pub(crate) async fn extension_exec(args: ExtensionExecArgs, watch: drain::Watch)
         progress.warning(warning);
     }
 
+    if routes::check_target_routes(&mut config, &progress).await
+        && let Some(path) = routes::use_composed_config(&config, &progress)
+    {
+        env.insert(
+            MIRRORD_CONFIG_FILE_ENV.into(),
+            path.to_string_lossy().into(),
+        );
+    }
+    preflight::check_conflicting_software(&progress);
+
     #[cfg(target_os = "macos")]
     let execution_result = mirrord_exec(
         args.executable.as_deref(),
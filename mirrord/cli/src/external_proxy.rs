@@ -36,19 +36,22 @@ use local_ip_address::local_ip;
 use mirrord_analytics::{AnalyticsReporter, CollectAnalytics, Reporter};
 use mirrord_config::LayerConfig;
 use mirrord_intproxy::agent_conn::{AgentConnection, ConnectionTlsError};
-use mirrord_protocol::DaemonCodec;
+use mirrord_protocol::{ClientMessage, DaemonCodec, DaemonMessage};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::server::TlsStream;
 use tokio_util::{either::Either, sync::CancellationToken};
 use tracing::Level;
 use tracing_subscriber::EnvFilter;
 
+#[cfg(unix)]
+use crate::util::detach_io;
 use crate::{
     connection::AGENT_CONNECT_INFO_ENV_KEY,
     error::{CliResult, ExternalProxyError},
     execution::MIRRORD_EXECUTION_KIND_ENV,
     internal_proxy::connect_and_ping,
-    util::{create_listen_socket, detach_io},
+    redaction::{RedactingMakeWriter, Redactor},
+    util::create_listen_socket,
 };
 
 /// Print the address for the caller (mirrord cli execution flow) so it can pass it
@@ -71,8 +74,11 @@ pub async fn proxy(listen_port: u16, watch: drain::Watch) -> CliResult<()> {
             .open(log_destination)
             .map_err(|e| ExternalProxyError::OpenLogFile(log_destination.clone(), e))?;
 
+        // The patterns were validated with the rest of the config, before we got here.
+        let redactor = Redactor::new(&config.redaction).unwrap_or_default();
+
         let tracing_registry = tracing_subscriber::fmt()
-            .with_writer(output_file)
+            .with_writer(RedactingMakeWriter::new(output_file, redactor))
             .with_ansi(false);
 
         if let Some(log_level) = config.external_proxy.log_level.as_ref() {
@@ -108,6 +114,7 @@ pub async fn proxy(listen_port: u16, watch: drain::Watch) -> CliResult<()> {
     .map_err(ExternalProxyError::ListenerSetup)?;
     print_addr(&listener).map_err(ExternalProxyError::ListenerSetup)?;
 
+    #[cfg(unix)]
     if let Err(error) = unsafe { detach_io() }.map_err(ExternalProxyError::SetSid) {
         tracing::warn!(%error, "unable to detach io");
     }
@@ -254,6 +261,15 @@ async fn handle_connection(
         tokio::select! {
             client_message = stream.next() => {
                 match client_message {
+                    // The connection to the agent switches its framing on its own.
+                    Some(Ok(ClientMessage::SwitchFraming(..))) => {
+                        let limits = stream.codec_ref().limits();
+                        if let Err(error) = stream.send(DaemonMessage::SwitchFramingResponse(limits)).await {
+                            tracing::error!(?peer_addr, %error, "unable to send message to intproxy");
+
+                            break;
+                        }
+                    }
                     Some(Ok(client_message)) => {
                         if let Err(error) = agent_conn.agent_tx.send(client_message).await {
                             tracing::error!(?peer_addr, %error, "unable to send message to agent");
@@ -28,6 +28,46 @@ use std::env::temp_dir;
 #[cfg(target_os = "macos")]
 use mac::temp_dir;
 
+/// Name and contents of the layer library that should be extracted.
+///
+/// On musl based distros (e.g. Alpine) we use the layer compiled for the musl target, if it was
+/// embedded in this build (see `MIRRORD_LAYER_FILE_MUSL` in `build.rs`).
+fn layer_file() -> (&'static str, &'static [u8]) {
+    #[cfg(all(target_os = "linux", musl_layer))]
+    if is_musl_host() {
+        return (
+            "libmirrord_layer_musl",
+            include_bytes!(env!("MIRRORD_LAYER_FILE_MUSL")),
+        );
+    }
+
+    (
+        "libmirrord_layer",
+        include_bytes!(env!("MIRRORD_LAYER_FILE")),
+    )
+}
+
+/// Checks if the system shell is linked against musl, by looking for the musl dynamic loader in
+/// its ELF interpreter.
+///
+/// We don't just check if the musl loader exists, as it can be installed on glibc based distros.
+#[cfg(all(target_os = "linux", musl_layer))]
+fn is_musl_host() -> bool {
+    use std::io::Read;
+
+    /// The interpreter path is stored right after the ELF program headers.
+    const ELF_HEADER_WINDOW: u64 = 4096;
+
+    let mut header = Vec::new();
+    File::open("/bin/sh")
+        .and_then(|shell| shell.take(ELF_HEADER_WINDOW).read_to_end(&mut header))
+        .is_ok_and(|_| {
+            header
+                .windows(b"/ld-musl-".len())
+                .any(|window| window == b"/ld-musl-")
+        })
+}
+
 /// Extract to given directory, or tmp by default.
 /// If prefix is true, add a random prefix to the file name that identifies the specific build
 /// of the layer. This is useful for debug purposes usually.
@@ -46,10 +86,12 @@ where
         .to_str()
         .unwrap();
 
+    let (layer_name, bytes) = layer_file();
+
     let file_name = if prefix {
-        format!("{}-libmirrord_layer.{extension}", const_random!(u64))
+        format!("{}-{layer_name}.{extension}", const_random!(u64))
     } else {
-        format!("libmirrord_layer.{extension}")
+        format!("{layer_name}.{extension}")
     };
 
     let file_path = match dest_dir {
@@ -59,7 +101,6 @@ where
     if !file_path.exists() {
         let mut file = File::create(&file_path)
             .map_err(|e| CliError::LayerExtractError(file_path.clone(), e))?;
-        let bytes = include_bytes!(env!("MIRRORD_LAYER_FILE"));
         file.write_all(bytes).unwrap();
         debug!("Extracted library file to {:?}", &file_path);
     }
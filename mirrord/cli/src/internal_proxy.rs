@@ -12,42 +12,236 @@
 
 use std::{
     env,
-    fs::OpenOptions,
-    io,
-    net::{Ipv4Addr, SocketAddr},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::PathBuf,
     time::{Duration, SystemTime},
 };
 
 use mirrord_analytics::{AnalyticsReporter, CollectAnalytics, Reporter};
-use mirrord_config::LayerConfig;
+use mirrord_config::{
+    config::ConfigError,
+    feature::network::{
+        filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter},
+        incoming::http_filter::HttpFallbackPolicy,
+    },
+    internal_proxy::MIRRORD_INTPROXY_AUTH_TOKEN_ENV,
+    LayerConfig,
+};
 use mirrord_intproxy::{
     agent_conn::{AgentConnectInfo, AgentConnection},
+    clock::ClockOffset,
     error::IntProxyError,
     IntProxy,
 };
-use mirrord_protocol::{ClientMessage, DaemonMessage, LogLevel, LogMessage};
+use mirrord_intproxy_protocol::control::LogSettings;
+use mirrord_protocol::{
+    clock::CLOCK_PROBE_VERSION,
+    compression::CompressionSettings,
+    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    file::ScratchDirRequest,
+    reachability::{ReachabilityRequest, ReachabilityResult, REACHABILITY_CHECK_VERSION},
+    tcp::{StealDrain, StealFallback, StealFallbackPolicy, StealHandoff},
+    ClientMessage, DaemonMessage, LogLevel, LogMessage,
+};
+use mirrord_sdk::control::{SessionFile, SessionInfo};
+#[cfg(unix)]
 use nix::sys::resource::{setrlimit, Resource};
-use rand::{distributions::Alphanumeric, Rng};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    Rng,
+};
+use semver::Version;
 use tokio::net::TcpListener;
 use tracing::{warn, Level};
 use tracing_subscriber::EnvFilter;
 
+#[cfg(unix)]
+use crate::util::detach_io;
 use crate::{
-    connection::AGENT_CONNECT_INFO_ENV_KEY,
+    connection::{AGENT_CONNECT_INFO_ENV_KEY, CLUSTERS_CONNECT_INFO_ENV_KEY},
     error::{CliResult, InternalProxyError},
     execution::MIRRORD_EXECUTION_KIND_ENV,
-    util::{create_listen_socket, detach_io},
+    redaction::{RedactingMakeWriter, Redactor},
+    routes::ComposedConfigFile,
+    util::create_listen_socket,
 };
 
 /// Print the address for the caller (mirrord cli execution flow) so it can pass it
 /// back to the layer instances via env var.
+///
+/// When listening on all interfaces, the layers connect through the loopback.
 fn print_addr(listener: &TcpListener) -> io::Result<()> {
-    let addr = listener.local_addr()?;
+    let mut addr = listener.local_addr()?;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr.ip() {
+            IpAddr::V4(..) => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(..) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
     println!("{addr}\n");
     Ok(())
 }
 
+/// Generates the token the layers authenticate with, when the internal proxy listens on a
+/// non-loopback
+/// [`InternalProxyConfig::listen_address`](mirrord_config::internal_proxy::InternalProxyConfig::listen_address).
+///
+/// Passed to the internal proxy and the layers in [`MIRRORD_INTPROXY_AUTH_TOKEN_ENV`].
+pub(crate) fn layer_auth_token(config: &LayerConfig) -> Option<String> {
+    if config.internal_proxy.layer_listen_address().is_loopback() {
+        return None;
+    }
+
+    Some(Alphanumeric.sample_string(&mut rand::thread_rng(), 32))
+}
+
+/// How long the agent waits for each connection in [`check_reachability`].
+const REACHABILITY_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long the agent keeps the steal subscriptions of this session after it exits, waiting for
+/// the next session to adopt them.
+const STEAL_HANDOFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builds the [`StealHandoff`] of this session, when
+/// [`IncomingConfig::handoff`](mirrord_config::feature::network::incoming::IncomingConfig::handoff)
+/// is enabled.
+///
+/// Sessions are matched by the [`handoff_secret`] of the local user and the target, so that users
+/// sharing a target don't adopt each other's subscriptions.
+pub(crate) fn steal_handoff(config: &LayerConfig) -> Option<StealHandoff> {
+    let incoming = &config.feature.network.incoming;
+    if !incoming.is_steal() || !incoming.handoff {
+        return None;
+    }
+
+    let secret = match handoff_secret() {
+        Ok(secret) => secret,
+        Err(error) => {
+            warn!(%error, "Failed to load the steal handoff secret, not handing off.");
+            return None;
+        }
+    };
+    let target = config
+        .target
+        .path
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "targetless".to_string());
+    let namespace = config.target.namespace.as_deref().unwrap_or_default();
+
+    Some(StealHandoff {
+        key: format!("{secret}@{namespace}/{target}"),
+        timeout_ms: STEAL_HANDOFF_TIMEOUT.as_millis() as u64,
+    })
+}
+
+/// Returns the secret that keys the [`StealHandoff`]s of the local user, stored in
+/// "~/.mirrord/steal-handoff-secret" (readable only by the user) and generated on first use.
+///
+/// Unlike the name of the user, it can't be guessed by other users of the target.
+fn handoff_secret() -> io::Result<String> {
+    let dir = home::home_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?
+        .join(".mirrord");
+    let path = dir.join("steal-handoff-secret");
+
+    match fs::read_to_string(&path) {
+        Ok(secret) if !secret.trim().is_empty() => return Ok(secret.trim().to_string()),
+        Ok(..) => {}
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+
+    fs::create_dir_all(&dir)?;
+    let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(secret.as_bytes())?;
+
+    Ok(secret)
+}
+
+/// Returns the [`StealFallback`] to send to the agent, when stealing with an HTTP filter that
+/// does not use the agent's defaults.
+pub(crate) fn steal_fallback(config: &LayerConfig) -> Option<StealFallback> {
+    let incoming = &config.feature.network.incoming;
+    let http_filter = &incoming.http_filter;
+    if !incoming.is_steal() || !http_filter.is_filter_set() {
+        return None;
+    }
+
+    let policy = match http_filter.fallback {
+        HttpFallbackPolicy::Error => StealFallbackPolicy::Error,
+        HttpFallbackPolicy::PassthroughToRemote => StealFallbackPolicy::PassthroughToRemote,
+        HttpFallbackPolicy::RetryLocal => StealFallbackPolicy::RetryLocal,
+    };
+
+    let fallback = StealFallback {
+        policy,
+        response_timeout_ms: http_filter.response_timeout_ms,
+        idle_timeout_ms: http_filter.response_idle_timeout_ms,
+        total_timeout_ms: http_filter.response_total_timeout_ms,
+    };
+
+    (fallback != StealFallback::default()).then_some(fallback)
+}
+
+/// Returns the [`StealDrain`] to send to the agent when the session ends, when
+/// [`IncomingConfig::drain_timeout_ms`](mirrord_config::feature::network::incoming::IncomingConfig::drain_timeout_ms)
+/// is set.
+///
+/// Handed off subscriptions are not drained, the next session takes them over instead.
+pub(crate) fn steal_drain(config: &LayerConfig) -> Option<StealDrain> {
+    let incoming = &config.feature.network.incoming;
+    if !incoming.is_steal() || incoming.handoff {
+        return None;
+    }
+
+    incoming
+        .drain_timeout_ms
+        .map(|timeout_ms| StealDrain { timeout_ms })
+}
+
+/// Whether to make the agent freeze the target container while stealing, see
+/// [`IncomingConfig::freeze_target`](mirrord_config::feature::network::incoming::IncomingConfig::freeze_target).
+pub(crate) fn freeze_target(config: &LayerConfig) -> bool {
+    let incoming = &config.feature.network.incoming;
+    incoming.is_steal() && incoming.freeze_target
+}
+
+/// Returns the [`ScratchDirRequest`] to send to the agent, when
+/// [`FsConfig::scratch`](mirrord_config::feature::fs::FsConfig::scratch) is set.
+pub(crate) fn scratch_dir(config: &LayerConfig) -> Option<ScratchDirRequest> {
+    let fs = &config.feature.fs;
+    if !fs.is_active() {
+        return None;
+    }
+
+    let patterns = fs.scratch.as_deref()?.to_vec();
+    (!patterns.is_empty()).then_some(ScratchDirRequest { patterns })
+}
+
+/// Binds the control socket of this session, and advertises it in a [`SessionFile`] for
+/// `mirrord toggle`.
+fn setup_control(
+    config: &LayerConfig,
+    clock_offset: Option<ClockOffset>,
+) -> io::Result<(TcpListener, SessionFile)> {
+    let listener = create_listen_socket(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+    let file = SessionFile::create(&SessionInfo {
+        pid: std::process::id(),
+        control_address: listener.local_addr()?,
+        target: config.target.path.as_ref().map(ToString::to_string),
+        clock_offset_ms: clock_offset.map(|offset| offset.offset_ms),
+    })?;
+
+    Ok((listener, file))
+}
+
 /// Main entry point for the internal proxy.
 /// It listens for inbound layer connect and forwards to agent.
 pub(crate) async fn proxy(
@@ -87,19 +281,34 @@ pub(crate) async fn proxy(
 
     let log_level = config.internal_proxy.log_level.as_deref().unwrap_or("info");
 
-    tracing_subscriber::fmt()
-        .with_writer(output_file)
+    // The patterns were validated with the rest of the config, before we got here.
+    let redactor = Redactor::new(&config.redaction).unwrap_or_default();
+
+    // Reloaded when the log settings change through the control socket.
+    let log_filter = LogSettings::default().filter(log_level);
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(RedactingMakeWriter::new(output_file, redactor))
         .with_ansi(false)
-        .with_env_filter(EnvFilter::builder().parse_lossy(log_level))
         .pretty()
-        .init();
+        .with_env_filter(EnvFilter::builder().parse_lossy(log_filter))
+        .with_filter_reloading();
+    let log_reload = subscriber.reload_handle();
+    subscriber.init();
 
     // According to https://wilsonmar.github.io/maximum-limits/ this is the limit on macOS
     // so we assume Linux can be higher and set to that.
+    #[cfg(unix)]
     if let Err(error) = setrlimit(Resource::RLIMIT_NOFILE, 12288, 12288) {
         warn!(?error, "Failed to set the file descriptor limit");
     }
 
+    // Set by the parent process, together with the layers.
+    let auth_token = env::var(MIRRORD_INTPROXY_AUTH_TOKEN_ENV).ok();
+    let listen_address = config.internal_proxy.layer_listen_address();
+    if !listen_address.is_loopback() && auth_token.is_none() {
+        return Err(InternalProxyError::AuthTokenMissing(listen_address));
+    }
+
     let agent_connect_info = match env::var(AGENT_CONNECT_INFO_ENV_KEY) {
         Ok(var) => {
             let deserialized = serde_json::from_str(&var)
@@ -109,6 +318,19 @@ pub(crate) async fn proxy(
         Err(..) => None,
     };
 
+    let clusters_connect_info: Vec<AgentConnectInfo> = match env::var(CLUSTERS_CONNECT_INFO_ENV_KEY)
+    {
+        Ok(var) => serde_json::from_str(&var)
+            .map_err(|e| InternalProxyError::DeseralizeConnectInfo(var, e))?,
+        Err(..) => Default::default(),
+    };
+    if clusters_connect_info.len() != config.clusters.len() {
+        warn!(
+            "The agents in the additional clusters were not created, \
+            all outgoing traffic goes through the target"
+        );
+    }
+
     let execution_kind = std::env::var(MIRRORD_EXECUTION_KIND_ENV)
         .ok()
         .and_then(|execution_kind| execution_kind.parse().ok())
@@ -127,13 +349,44 @@ pub(crate) async fn proxy(
     // **before** this happens to ensure that the agent does not prematurely exit.
     // We also perform initial ping pong round to ensure that k8s runtime actually made connection
     // with the agent (it's a must, because port forwarding may be done lazily).
-    let agent_conn = connect_and_ping(&config, agent_connect_info, &mut analytics).await?;
+    let mut agent_conn = connect_and_ping(&config, agent_connect_info, &mut analytics).await?;
+
+    let mut cluster_conns = Vec::with_capacity(clusters_connect_info.len());
+    for (cluster, connect_info) in config.clusters.iter().zip(clusters_connect_info) {
+        let cluster_config = cluster.layer_config(&config);
+        let agent_conn =
+            connect_and_ping(&cluster_config, Some(connect_info), &mut analytics).await?;
+        let routes = cluster.routes().map_err(ConfigError::from)?;
+        cluster_conns.push((cluster.name.clone(), agent_conn, routes));
+    }
 
-    // Let it assign address for us then print it for the user.
-    let listener = create_listen_socket(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), listen_port))
-        .map_err(InternalProxyError::ListenerSetup)?;
+    // Done before we print our address, so that the warning reaches the user through the parent
+    // process.
+    let clock_offset = check_clock_skew(&config, &mut agent_conn).await?;
+    check_reachability(&config, &mut agent_conn).await?;
+
+    // Let it assign address for us (unless pinned in the config) then print it for the user.
+    let listener = match (listen_port, config.internal_proxy.port) {
+        (0, Some(port)) if port != 0 => {
+            let address = SocketAddr::new(listen_address, port);
+            create_listen_socket(address)
+                .map_err(|error| InternalProxyError::PinnedListenerSetup(address, error))?
+        }
+        (port, _) => create_listen_socket(SocketAddr::new(listen_address, port))
+            .map_err(InternalProxyError::ListenerSetup)?,
+    };
+    // Bound before we print our address, so that the parent process sees the failure.
+    let connect_proxy_listener = config
+        .internal_proxy
+        .connect_proxy_port
+        .map(|port| {
+            create_listen_socket(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))
+                .map_err(|error| InternalProxyError::ConnectProxySetup(port, error))
+        })
+        .transpose()?;
     print_addr(&listener).map_err(InternalProxyError::ListenerSetup)?;
 
+    #[cfg(unix)]
     if !config.internal_proxy.container_mode {
         unsafe { detach_io() }.map_err(InternalProxyError::SetSid)?;
     }
@@ -141,7 +394,75 @@ pub(crate) async fn proxy(
     let first_connection_timeout = Duration::from_secs(config.internal_proxy.start_idle_timeout);
     let consecutive_connection_timeout = Duration::from_secs(config.internal_proxy.idle_timeout);
 
-    IntProxy::new_with_connection(agent_conn, listener)
+    let mut intproxy = IntProxy::new_with_connection(agent_conn, listener, auth_token);
+    if let Some(handoff) = steal_handoff(&config) {
+        intproxy = intproxy.with_steal_handoff(handoff);
+    }
+    if let Some(fallback) = steal_fallback(&config) {
+        intproxy = intproxy.with_steal_fallback(fallback);
+    }
+    if let Some(drain) = steal_drain(&config) {
+        intproxy = intproxy.with_steal_drain(drain);
+    }
+    if freeze_target(&config) {
+        intproxy = intproxy.with_freeze_target();
+    }
+    if let Some(startup_buffer) = config.feature.network.incoming.startup_buffer {
+        intproxy = intproxy.with_startup_buffer(startup_buffer);
+    }
+    if config.feature.network.incoming.proxy_protocol {
+        intproxy = intproxy.with_proxy_protocol();
+    }
+    if let Some(forwarded_headers) = config.feature.network.incoming.forwarded_headers {
+        intproxy = intproxy.with_forwarded_headers(forwarded_headers);
+    }
+    if let Some(scratch_dir) = scratch_dir(&config) {
+        intproxy = intproxy.with_scratch_dir(scratch_dir);
+    }
+    if let Some(threshold) = config.feature.fs.compression_threshold {
+        intproxy = intproxy.with_compression(CompressionSettings { threshold });
+    }
+    if let Some(ttl_ms) = config.feature.fs.metadata_cache_ttl_ms {
+        intproxy = intproxy.with_metadata_cache(Duration::from_millis(ttl_ms));
+    }
+    if let Some(paths) = config.feature.fs.prefetch.as_deref() {
+        intproxy = intproxy.with_prefetch(paths.iter().map(PathBuf::from).collect());
+    }
+    if config.mode.is_read_only() {
+        intproxy = intproxy.with_read_only();
+    }
+    for (name, agent_conn, routes) in cluster_conns {
+        intproxy = intproxy.with_cluster(name, agent_conn, routes);
+    }
+    if let Some(listener) = connect_proxy_listener {
+        intproxy = intproxy.with_connect_proxy(listener, &config.feature.network);
+    }
+    intproxy = intproxy.with_log_reload(log_level.to_string(), move |filter| {
+        if let Err(error) = log_reload.reload(EnvFilter::builder().parse_lossy(filter)) {
+            warn!(%error, "Failed to change the log filter");
+        }
+    });
+
+    // Kept until the proxy exits, the layers of the session may still read it.
+    let _composed_config_file = ComposedConfigFile::from_env();
+
+    // Kept until the proxy exits.
+    let _session_file = if config.internal_proxy.container_mode {
+        None
+    } else {
+        match setup_control(&config, clock_offset) {
+            Ok((listener, file)) => {
+                intproxy = intproxy.with_control(listener);
+                Some(file)
+            }
+            Err(error) => {
+                warn!(%error, "Failed to set up the control socket, `mirrord toggle` will not work");
+                None
+            }
+        }
+    };
+
+    intproxy
         .run(first_connection_timeout, consecutive_connection_timeout)
         .await
         .map_err(InternalProxyError::from)
@@ -171,9 +492,233 @@ pub(crate) async fn connect_and_ping(
             )
         })?;
 
+    match next_setup_message(&mut agent_conn)
+        .await
+        .map_err(InternalProxyError::InitialPingPongFailed)?
+    {
+        DaemonMessage::Pong => Ok(agent_conn),
+        message => Err(InternalProxyError::InitialPingPongFailed(format!(
+            "agent sent an unexpected message: {message:?}"
+        ))),
+    }
+}
+
+/// Measures the offset between the local clock and the clock of the agent, and warns when it's
+/// bigger than
+/// [`InternalProxyConfig::clock_skew_threshold_ms`](mirrord_config::internal_proxy::InternalProxyConfig::clock_skew_threshold_ms).
+///
+/// Returns [`None`] when the check is disabled, or the agent does not support
+/// [`CLOCK_PROBE_VERSION`].
+#[tracing::instrument(level = Level::TRACE, skip_all)]
+async fn check_clock_skew(
+    config: &LayerConfig,
+    agent_conn: &mut AgentConnection,
+) -> CliResult<Option<ClockOffset>, InternalProxyError> {
+    let threshold = Duration::from_millis(config.internal_proxy.clock_skew_threshold_ms);
+    if threshold.is_zero() {
+        return Ok(None);
+    }
+
+    let protocol_version = negotiate_protocol_version(agent_conn)
+        .await
+        .map_err(InternalProxyError::ClockProbeFailed)?;
+
+    if !CLOCK_PROBE_VERSION.matches(&protocol_version) {
+        tracing::debug!(%protocol_version, "Agent does not support clock probes");
+        return Ok(None);
+    }
+
+    let sent = SystemTime::now();
+    send_setup_message(agent_conn, ClientMessage::ClockProbe)
+        .await
+        .map_err(InternalProxyError::ClockProbeFailed)?;
+    let response = match next_setup_message(agent_conn)
+        .await
+        .map_err(InternalProxyError::ClockProbeFailed)?
+    {
+        DaemonMessage::ClockProbeResponse(response) => response,
+        message => {
+            return Err(InternalProxyError::ClockProbeFailed(format!(
+                "agent sent an unexpected message: {message:?}"
+            )))
+        }
+    };
+    let offset = ClockOffset::measure(sent, SystemTime::now(), response);
+
+    if offset.exceeds(threshold) {
+        warn!(?offset, "Clock of the target is skewed");
+        // Shown to the user by the parent process.
+        eprintln!(
+            "{offset}, time-sensitive checks like TLS certificate or JWT validation may fail. \
+            Consider syncing the local clock with NTP."
+        );
+    } else {
+        tracing::debug!(?offset, "Clock of the target is in sync");
+    }
+
+    Ok(Some(offset))
+}
+
+/// Asks the agent to connect to the destinations in
+/// [`OutgoingConfig::remote_destinations`](mirrord_config::feature::network::outgoing::OutgoingConfig::remote_destinations),
+/// and warns about the ones it can't reach, as a NetworkPolicy or a firewall may block the egress
+/// of the target.
+///
+/// Only TCP destinations are checked. Host names are resolved by the agent, unless
+/// `feature.network.dns` is disabled, in which case they are skipped.
+///
+/// Skipped when
+/// [`AgentConfig::check_network_policies`](mirrord_config::agent::AgentConfig::check_network_policies)
+/// is disabled, or the agent does not support [`REACHABILITY_CHECK_VERSION`].
+#[tracing::instrument(level = Level::TRACE, skip_all)]
+async fn check_reachability(
+    config: &LayerConfig,
+    agent_conn: &mut AgentConnection,
+) -> CliResult<(), InternalProxyError> {
+    if !config.agent.check_network_policies {
+        return Ok(());
+    }
+
+    let remote_dns = config.feature.network.dns.enabled;
+    let destinations = config
+        .feature
+        .network
+        .outgoing
+        .remote_destinations()
+        .into_iter()
+        .filter(|filter| filter.protocol != ProtocolFilter::Udp)
+        .filter(|filter| remote_dns || !matches!(filter.address, AddressFilter::Name(..)))
+        .collect::<Vec<_>>();
+    if destinations.is_empty() {
+        return Ok(());
+    }
+
+    let protocol_version = negotiate_protocol_version(agent_conn)
+        .await
+        .map_err(InternalProxyError::ReachabilityCheckFailed)?;
+    if !REACHABILITY_CHECK_VERSION.matches(&protocol_version) {
+        tracing::debug!(%protocol_version, "Agent does not support reachability checks");
+        return Ok(());
+    }
+
+    let mut addresses = Vec::with_capacity(destinations.len());
+    for ProtocolAndAddressFilter { address, .. } in destinations {
+        match address {
+            AddressFilter::Socket(address) => addresses.push(address),
+            AddressFilter::Name(name, port) => match resolve_remote(agent_conn, &name)
+                .await
+                .map_err(InternalProxyError::ReachabilityCheckFailed)?
+            {
+                Some(ip) => addresses.push(SocketAddr::new(ip, port)),
+                None => eprintln!(
+                    "`{name}` from `feature.network.outgoing.filter` could not be resolved in \
+                    the cluster, connections to it will fail."
+                ),
+            },
+            AddressFilter::Port(..) | AddressFilter::Subnet(..) => {}
+        }
+    }
+    if addresses.is_empty() {
+        return Ok(());
+    }
+
+    send_setup_message(
+        agent_conn,
+        ClientMessage::CheckReachability(ReachabilityRequest {
+            addresses,
+            timeout_ms: REACHABILITY_CHECK_TIMEOUT.as_millis() as u64,
+        }),
+    )
+    .await
+    .map_err(InternalProxyError::ReachabilityCheckFailed)?;
+    let response = match next_setup_message(agent_conn)
+        .await
+        .map_err(InternalProxyError::ReachabilityCheckFailed)?
+    {
+        DaemonMessage::ReachabilityResponse(response) => response,
+        message => {
+            return Err(InternalProxyError::ReachabilityCheckFailed(format!(
+                "agent sent an unexpected message: {message:?}"
+            )))
+        }
+    };
+
+    for ReachabilityResult { address, error } in response.results {
+        match error {
+            Some(error) => {
+                warn!(%address, %error, "Outgoing destination is not reachable from the target");
+                // Shown to the user by the parent process.
+                eprintln!(
+                    "The target could not connect to {address} from \
+                    `feature.network.outgoing.filter`: {error}. A NetworkPolicy may block the \
+                    egress of the target."
+                );
+            }
+            None => tracing::debug!(%address, "Outgoing destination is reachable from the target"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves the `name` with the agent, returning the first address found.
+async fn resolve_remote(
+    agent_conn: &mut AgentConnection,
+    name: &str,
+) -> Result<Option<IpAddr>, String> {
+    send_setup_message(
+        agent_conn,
+        ClientMessage::GetAddrInfoRequest(GetAddrInfoRequest {
+            node: name.to_string(),
+        }),
+    )
+    .await?;
+
+    match next_setup_message(agent_conn).await? {
+        DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Ok(lookup))) => {
+            Ok(lookup.0.first().map(|record| record.ip))
+        }
+        DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Err(error))) => {
+            tracing::debug!(name, %error, "Failed to resolve outgoing destination");
+            Ok(None)
+        }
+        message => Err(format!("agent sent an unexpected message: {message:?}")),
+    }
+}
+
+/// Negotiates the mirrord-protocol version with the agent during the setup of the connection.
+///
+/// The proxy negotiates the version again when it starts running, which is harmless.
+async fn negotiate_protocol_version(agent_conn: &mut AgentConnection) -> Result<Version, String> {
+    send_setup_message(
+        agent_conn,
+        ClientMessage::SwitchProtocolVersion(mirrord_protocol::VERSION.clone()),
+    )
+    .await?;
+
+    match next_setup_message(agent_conn).await? {
+        DaemonMessage::SwitchProtocolVersionResponse(version) => Ok(version),
+        message => Err(format!("agent sent an unexpected message: {message:?}")),
+    }
+}
+
+/// Sends a message to the agent during the setup of the connection.
+async fn send_setup_message(
+    agent_conn: &mut AgentConnection,
+    message: ClientMessage,
+) -> Result<(), String> {
+    agent_conn
+        .agent_tx
+        .send(message)
+        .await
+        .map_err(|_| "agent unexpectedly closed connection".to_string())
+}
+
+/// Receives the next message from the agent during the setup of the connection, logging the
+/// [`LogMessage`]s that come before it.
+async fn next_setup_message(agent_conn: &mut AgentConnection) -> Result<DaemonMessage, String> {
     loop {
         match agent_conn.agent_rx.recv().await {
-            Some(DaemonMessage::Pong) => break Ok(agent_conn),
             Some(DaemonMessage::LogMessage(LogMessage {
                 level: LogLevel::Error,
                 message,
@@ -187,20 +732,10 @@ pub(crate) async fn connect_and_ping(
                 tracing::warn!("agent log: {message}");
             }
             Some(DaemonMessage::Close(reason)) => {
-                break Err(InternalProxyError::InitialPingPongFailed(format!(
-                    "agent closed connection with message: {reason}"
-                )));
-            }
-            Some(message) => {
-                break Err(InternalProxyError::InitialPingPongFailed(format!(
-                    "agent sent an unexpected message: {message:?}"
-                )));
-            }
-            None => {
-                break Err(InternalProxyError::InitialPingPongFailed(
-                    "agent unexpectedly closed connection".to_string(),
-                ));
+                break Err(format!("agent closed connection with message: {reason}"));
             }
+            Some(message) => break Ok(message),
+            None => break Err("agent unexpectedly closed connection".to_string()),
         }
     }
 }
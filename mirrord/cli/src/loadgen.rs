@@ -0,0 +1,519 @@
+//! Synthetic HTTP load for the local application, see [`loadgen_command`].
+//!
+//! The requests are crafted to match `feature.network.incoming.http_filter`, and given to an
+//! [`IncomingProxy`] like the requests that the agent steals, so they reach the local application
+//! through the same interceptors as in a real session. The agent's side is played here: the port
+//! subscription is confirmed right away, and each request is sent on a new connection, which is
+//! closed when the response arrives.
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use hyper::{
+    header::{HeaderName, HeaderValue, HOST},
+    HeaderMap, Method, StatusCode, Uri, Version,
+};
+use mirrord_config::{
+    config::{ConfigContext, MirrordConfig},
+    feature::network::incoming::http_filter::{HttpFilterConfig, InnerFilter},
+    LayerConfig, LayerFileConfig,
+};
+use mirrord_intproxy::{
+    background_tasks::{BackgroundTasks, TaskUpdate},
+    error::IntProxyError,
+    main_tasks::{MainTaskId, ProxyMessage, ToLayer},
+    proxies::incoming::{IncomingProxy, IncomingProxyMessage},
+};
+use mirrord_intproxy_protocol::{
+    IncomingRequest, IncomingResponse, LayerId, PortSubscribe, PortSubscription,
+    ProxyToLayerMessage,
+};
+use mirrord_progress::{Progress, ProgressTracker};
+use mirrord_protocol::{
+    tcp::{
+        DaemonTcp, HttpRequest, InternalHttpBody, InternalHttpRequest, LayerTcpSteal, StealType,
+        TcpClose,
+    },
+    ClientMessage, ConnectionId, Port,
+};
+use prettytable::{row, Table};
+use regex_syntax::hir::{Class, Hir, HirKind};
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::Level;
+
+use crate::{CliError, CliResult, LoadgenArgs};
+
+/// How long the responses can take after the last request was sent.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Characters preferred when the filter allows a choice, they're valid anywhere in a request.
+const PREFERRED_CHARS: &str = "abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-_./ ";
+
+/// Parts of a request that matches the filter.
+#[derive(Debug, Clone)]
+struct RequestTemplate {
+    uri: Uri,
+    headers: HeaderMap,
+}
+
+impl Default for RequestTemplate {
+    /// Plain `GET /`.
+    fn default() -> Self {
+        Self {
+            uri: Uri::from_static("/"),
+            headers: Default::default(),
+        }
+    }
+}
+
+impl RequestTemplate {
+    /// Builds a request that matches all the `filters`.
+    fn new(filters: &[InnerFilter]) -> Result<Self, String> {
+        let mut template = Self::default();
+
+        for filter in filters {
+            match filter {
+                InnerFilter::Header { header } => {
+                    let generated = matching_string(header)?;
+                    // The agent matches the headers formatted as `name: value`.
+                    let (name, value) = generated.split_once(": ").ok_or_else(|| {
+                        format!("header filter `{header}` does not match a `name: value` header")
+                    })?;
+                    let name = HeaderName::try_from(name).map_err(|error| {
+                        format!("header filter `{header}` gave a bad header name: {error}")
+                    })?;
+                    let value = HeaderValue::try_from(value).map_err(|error| {
+                        format!("header filter `{header}` gave a bad header value: {error}")
+                    })?;
+                    template.headers.append(name, value);
+                }
+                InnerFilter::Path { path } => {
+                    let generated = matching_string(path)?;
+                    let generated = if generated.starts_with('/') {
+                        generated
+                    } else {
+                        format!("/{generated}")
+                    };
+                    template.uri = generated.parse().map_err(|error| {
+                        format!("path filter `{path}` gave a bad path: {error}")
+                    })?;
+                }
+            }
+        }
+
+        Ok(template)
+    }
+
+    /// The templates for the requests matching `config`, they're used in turns.
+    ///
+    /// With `any_of` there is one for each of the filters, otherwise there is just one.
+    fn from_config(config: &HttpFilterConfig) -> Result<Vec<Self>, String> {
+        if let Some(header) = &config.header_filter {
+            let filter = InnerFilter::Header {
+                header: header.clone(),
+            };
+            return Ok(vec![Self::new(&[filter])?]);
+        }
+
+        if let Some(path) = &config.path_filter {
+            let filter = InnerFilter::Path { path: path.clone() };
+            return Ok(vec![Self::new(&[filter])?]);
+        }
+
+        if let Some(filters) = &config.all_of {
+            return Ok(vec![Self::new(filters)?]);
+        }
+
+        if let Some(filters) = &config.any_of {
+            return filters
+                .iter()
+                .map(|filter| Self::new(std::slice::from_ref(filter)))
+                .collect();
+        }
+
+        Err("there is no `feature.network.incoming.http_filter` in the config".to_string())
+    }
+
+    /// The request with this template that the agent would send on the connection
+    /// `connection_id`.
+    fn request(&self, connection_id: ConnectionId, port: Port) -> HttpRequest<InternalHttpBody> {
+        let mut headers = self.headers.clone();
+        headers
+            .entry(HOST)
+            .or_insert_with(|| HeaderValue::from_static("localhost"));
+
+        HttpRequest {
+            internal_request: InternalHttpRequest {
+                method: Method::GET,
+                uri: self.uri.clone(),
+                headers,
+                version: Version::HTTP_11,
+                body: Default::default(),
+            },
+            connection_id,
+            request_id: 0,
+            port,
+        }
+    }
+}
+
+/// Builds a short string that is matched by `regex`, case-insensitive like the agent's filters.
+///
+/// Supports the regexes of the [`regex_syntax`] crate, the `fancy-regex` extensions
+/// (look-around, backreferences) are rejected.
+fn matching_string(regex: &str) -> Result<String, String> {
+    let hir = regex_syntax::parse(&format!("(?i){regex}"))
+        .map_err(|error| format!("can't craft a request for the filter `{regex}`: {error}"))?;
+
+    let mut generated = String::new();
+    write_match(&hir, &mut generated)
+        .map_err(|error| format!("can't craft a request for the filter `{regex}`: {error}"))?;
+
+    Ok(generated)
+}
+
+/// Appends to `out` the shortest match of `hir`. Anchors and word boundaries are skipped.
+fn write_match(hir: &Hir, out: &mut String) -> Result<(), &'static str> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(..) => {}
+        HirKind::Literal(literal) => {
+            let literal = std::str::from_utf8(&literal.0).map_err(|_| "it matches raw bytes")?;
+            out.push_str(literal);
+        }
+        HirKind::Class(Class::Unicode(class)) => {
+            let ranges = class
+                .ranges()
+                .iter()
+                .map(|range| (range.start(), range.end()))
+                .collect::<Vec<_>>();
+            out.push(pick_char(&ranges)?);
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            let ranges = class
+                .ranges()
+                .iter()
+                .filter(|range| range.start().is_ascii())
+                .map(|range| (char::from(range.start()), char::from(range.end().min(0x7f))))
+                .collect::<Vec<_>>();
+            out.push(pick_char(&ranges)?);
+        }
+        HirKind::Repetition(repetition) => {
+            for _ in 0..repetition.min {
+                write_match(&repetition.sub, out)?;
+            }
+        }
+        HirKind::Capture(capture) => write_match(&capture.sub, out)?,
+        HirKind::Concat(hirs) => {
+            for hir in hirs {
+                write_match(hir, out)?;
+            }
+        }
+        HirKind::Alternation(hirs) => {
+            let first = hirs.first().ok_or("it has an empty alternation")?;
+            write_match(first, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks a character in the `ranges`, one of the [`PREFERRED_CHARS`] if possible.
+fn pick_char(ranges: &[(char, char)]) -> Result<char, &'static str> {
+    let contains = |c: &char| ranges.iter().any(|(start, end)| (start..=end).contains(&c));
+
+    PREFERRED_CHARS
+        .chars()
+        .find(contains)
+        .or_else(|| ('!'..='~').find(contains))
+        .or_else(|| ranges.first().map(|(start, _)| *start))
+        .ok_or("it can't match anything")
+}
+
+/// Outcome of the load.
+#[derive(Debug, Default)]
+struct LoadReport {
+    sent: usize,
+    /// Number of responses with each status.
+    statuses: BTreeMap<u16, usize>,
+    /// Latencies of the responses, sorted in [`LoadReport::finish`].
+    latencies: Vec<Duration>,
+    /// Requests without a response in [`RESPONSE_TIMEOUT`].
+    unanswered: usize,
+}
+
+impl LoadReport {
+    fn record(&mut self, status: StatusCode, latency: Duration) {
+        *self.statuses.entry(status.as_u16()).or_default() += 1;
+        self.latencies.push(latency);
+    }
+
+    fn finish(&mut self, unanswered: usize) {
+        self.unanswered = unanswered;
+        self.latencies.sort_unstable();
+    }
+
+    /// Latency of the given `percentile` of the responses (nearest rank).
+    fn percentile(&self, percentile: usize) -> Option<Duration> {
+        let rank = (self.latencies.len() * percentile).div_ceil(100);
+        self.latencies.get(rank.saturating_sub(1)).copied()
+    }
+
+    fn print(&self) {
+        let mut table = Table::new();
+        table.add_row(row!["requests sent", self.sent]);
+        for (status, count) in &self.statuses {
+            table.add_row(row![format!("status {status}"), count]);
+        }
+        table.add_row(row!["without response", self.unanswered]);
+
+        for (name, latency) in [
+            ("p50 latency", self.percentile(50)),
+            ("p90 latency", self.percentile(90)),
+            ("p99 latency", self.percentile(99)),
+            ("max latency", self.latencies.last().copied()),
+        ] {
+            if let Some(latency) = latency {
+                table.add_row(row![name, format!("{latency:?}")]);
+            }
+        }
+
+        table.printstd();
+    }
+}
+
+/// Sends the requests to an [`IncomingProxy`], `args.rps` per second, and waits for the
+/// responses.
+async fn run_load(
+    args: &LoadgenArgs,
+    port: Port,
+    templates: &[RequestTemplate],
+) -> CliResult<LoadReport> {
+    let mut tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> = Default::default();
+    let incoming = tasks.register(IncomingProxy::default(), MainTaskId::IncomingProxy, 512);
+
+    incoming
+        .send(IncomingProxyMessage::AgentProtocolVersion(
+            mirrord_protocol::VERSION.clone(),
+        ))
+        .await;
+    incoming
+        .send(IncomingProxyMessage::LayerRequest(
+            0,
+            LayerId(0),
+            IncomingRequest::PortSubscribe(PortSubscribe {
+                listening_on: SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+                // The requests already match the filter, they're not filtered again.
+                subscription: PortSubscription::Steal(StealType::All(port)),
+            }),
+        ))
+        .await;
+
+    let total = (args.duration.as_secs_f64() * f64::from(args.rps)).ceil() as ConnectionId;
+    let mut interval = time::interval(Duration::from_secs(1) / args.rps);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut report = LoadReport::default();
+    let mut subscribed = false;
+    let mut next_connection: ConnectionId = 0;
+    let mut pending: HashMap<ConnectionId, Instant> = HashMap::new();
+    let mut deadline = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick(), if subscribed && next_connection < total => {
+                let template = &templates[next_connection as usize % templates.len()];
+                let request = template.request(next_connection, port);
+                pending.insert(next_connection, Instant::now());
+                incoming
+                    .send(IncomingProxyMessage::AgentSteal(DaemonTcp::HttpRequestFramed(request)))
+                    .await;
+
+                report.sent += 1;
+                next_connection += 1;
+                if next_connection == total {
+                    deadline = Some(Instant::now() + RESPONSE_TIMEOUT);
+                }
+            }
+
+            Some((_, update)) = tasks.next() => match update {
+                TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::TcpSteal(
+                    LayerTcpSteal::PortSubscribe(..),
+                ))) => {
+                    let result = DaemonTcp::SubscribeResult(Ok(port));
+                    incoming.send(IncomingProxyMessage::AgentSteal(result)).await;
+                }
+                TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(result)),
+                    ..
+                })) => {
+                    result.map_err(|error| {
+                        CliError::LoadgenFailed(format!("subscribing the port failed: {error}"))
+                    })?;
+                    subscribed = true;
+                }
+                TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::TcpSteal(
+                    LayerTcpSteal::HttpResponseFramed(response),
+                ))) => {
+                    if let Some(sent_at) = pending.remove(&response.connection_id) {
+                        report.record(response.internal_response.status, sent_at.elapsed());
+                    }
+                    incoming
+                        .send(IncomingProxyMessage::AgentSteal(DaemonTcp::Close(TcpClose {
+                            connection_id: response.connection_id,
+                        })))
+                        .await;
+                }
+                TaskUpdate::Message(message) => {
+                    tracing::trace!(?message, "ignoring a message of the incoming proxy");
+                }
+                TaskUpdate::Finished(result) => {
+                    return Err(CliError::LoadgenFailed(format!(
+                        "the incoming proxy exited: {result:?}"
+                    )));
+                }
+            },
+
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                break;
+            }
+        }
+
+        if next_connection == total && pending.is_empty() {
+            break;
+        }
+    }
+
+    report.finish(pending.len());
+
+    Ok(report)
+}
+
+/// Handle `mirrord loadgen`.
+///
+/// Prints the statuses and latencies of the responses, and fails when some requests didn't get
+/// one.
+#[tracing::instrument(level = Level::TRACE, ret)]
+pub(crate) async fn loadgen_command(args: LoadgenArgs) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord loadgen");
+
+    let config = if let Some(config) = &args.config_file {
+        let mut cfg_context = ConfigContext::default();
+        LayerFileConfig::from_path(config)?.generate_config(&mut cfg_context)?
+    } else {
+        LayerConfig::from_env()?
+    };
+    let http_filter = &config.feature.network.incoming.http_filter;
+
+    let templates = if args.filter_from_config {
+        RequestTemplate::from_config(http_filter).map_err(CliError::LoadgenFailed)?
+    } else {
+        vec![RequestTemplate::default()]
+    };
+    let port = args
+        .port
+        .or_else(|| http_filter.ports.first().copied())
+        .ok_or_else(|| CliError::LoadgenFailed("no port for the requests".to_string()))?;
+
+    let mut load_progress = progress.subtask(&format!(
+        "sending {} requests per second to port {port} for {}",
+        args.rps,
+        humantime::format_duration(args.duration)
+    ));
+    let report = run_load(&args, port, &templates).await.inspect_err(|_| {
+        load_progress.failure(Some("sending the requests failed"));
+    })?;
+    load_progress.success(Some("all requests sent"));
+
+    if report.unanswered == 0 {
+        progress.success(Some("all requests got a response"));
+    } else {
+        progress.failure(Some("some requests did not get a response"));
+    }
+    report.print();
+
+    if report.unanswered == 0 {
+        Ok(())
+    } else {
+        Err(CliError::LoadgenFailed(format!(
+            "{} of {} requests did not get a response",
+            report.unanswered, report.sent
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(header: &str) -> InnerFilter {
+        InnerFilter::Header {
+            header: header.to_string(),
+        }
+    }
+
+    fn path(path: &str) -> InnerFilter {
+        InnerFilter::Path {
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn header_filter() {
+        let template = RequestTemplate::new(&[header("^x-user: (alice|bob)\\d+$")]).unwrap();
+
+        assert_eq!(template.headers.get("x-user").unwrap(), "alice0");
+        assert_eq!(template.uri, "/");
+    }
+
+    #[test]
+    fn path_filter() {
+        let template = RequestTemplate::new(&[path("api/v[12]/users/.+")]).unwrap();
+
+        assert_eq!(template.uri, "/api/v1/users/a");
+        assert!(template.headers.is_empty());
+    }
+
+    #[test]
+    fn composite_filters() {
+        let all_of = HttpFilterConfig {
+            all_of: Some(vec![header("x-tenant: \\w+"), path("^/orders")]),
+            ..Default::default()
+        };
+        let any_of = HttpFilterConfig {
+            any_of: Some(vec![header("x-tenant: \\w+"), path("^/orders")]),
+            ..Default::default()
+        };
+
+        let all_of = RequestTemplate::from_config(&all_of).unwrap();
+        let any_of = RequestTemplate::from_config(&any_of).unwrap();
+
+        assert_eq!(all_of.len(), 1);
+        assert_eq!(all_of[0].headers.get("x-tenant").unwrap(), "a");
+        assert_eq!(all_of[0].uri, "/orders");
+        assert_eq!(any_of.len(), 2);
+        assert_eq!(any_of[0].uri, "/");
+        assert!(any_of[1].headers.is_empty());
+    }
+
+    #[test]
+    fn unusable_filters() {
+        assert!(RequestTemplate::new(&[header("x-user")]).is_err());
+        assert!(RequestTemplate::new(&[header("x-user: (?=alice)")]).is_err());
+        assert!(RequestTemplate::from_config(&Default::default()).is_err());
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let mut report = LoadReport::default();
+        for millis in (1..=100).rev() {
+            report.record(StatusCode::OK, Duration::from_millis(millis));
+        }
+        report.finish(0);
+
+        assert_eq!(report.percentile(50), Some(Duration::from_millis(50)));
+        assert_eq!(report.percentile(99), Some(Duration::from_millis(99)));
+        assert_eq!(report.statuses, BTreeMap::from([(200, 100)]));
+    }
+}
@@ -2,21 +2,25 @@
 #![feature(try_blocks)]
 #![warn(clippy::indexing_slicing)]
 
-use std::{
-    collections::HashMap, env::vars, ffi::CString, net::SocketAddr, os::unix::ffi::OsStrExt,
-    sync::LazyLock, time::Duration,
-};
+use std::{collections::HashMap, env::vars, net::SocketAddr, sync::LazyLock, time::Duration};
+#[cfg(unix)]
+use std::{ffi::CString, os::unix::ffi::OsStrExt};
 
+use auth::auth_command;
 use clap::{CommandFactory, Parser};
 use clap_complete::generate;
 use config::*;
 use connection::create_and_connect;
 use container::container_command;
+use cron::cron_command;
 use diagnose::diagnose_command;
+use dns::dns_command;
 use execution::MirrordExecution;
+use explain_path::config_command;
 use extension::extension_exec;
 use extract::extract_library;
 use kube::Client;
+use loadgen::loadgen_command;
 use miette::JSONReportHandler;
 use mirrord_analytics::{
     AnalyticsError, AnalyticsReporter, CollectAnalytics, ExecutionKind, NullReporter, Reporter,
@@ -40,29 +44,47 @@ use mirrord_progress::{messages::EXEC_CONTAINER_BINARY, Progress, ProgressTracke
 use nix::errno::Errno;
 use operator::operator_command;
 use port_forward::{PortForwardError, PortForwarder, ReversePortForwarder};
+use redaction::{RedactingMakeWriter, Redactor};
 use regex::Regex;
+use report::report_command;
+use selftest::{selftest_command, selftest_probe};
 use semver::{Version, VersionReq};
 use serde_json::json;
+use toggle::toggle_command;
 use tracing::{error, info, warn};
 use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter};
 use which::which;
 
+mod auth;
+mod compatibility;
 mod config;
 mod connection;
 mod container;
+mod cron;
 mod diagnose;
+mod dns;
 mod error;
 mod execution;
+mod explain_path;
 mod extension;
 mod external_proxy;
 mod extract;
 mod internal_proxy;
+mod loadgen;
 mod operator;
 pub mod port_forward;
+mod preflight;
+mod redaction;
+mod report;
+mod routes;
+mod selftest;
 mod teams;
+mod toggle;
 mod util;
 mod verify_config;
 mod vpn;
+#[cfg(windows)]
+mod wsl;
 
 pub(crate) use error::{CliError, CliResult};
 use verify_config::verify_config;
@@ -110,15 +132,19 @@ where
         env_vars.remove(key);
     }
 
+    #[cfg(unix)]
     let mut binary_args = args.binary_args.clone();
     // Put original executable in argv[0] even if actually running patched version.
+    #[cfg(unix)]
     binary_args.insert(0, args.binary.clone());
 
     // since execvpe doesn't exist on macOS, resolve path with which and use execve
+    #[cfg(unix)]
     let binary_path = match which(&binary) {
         Ok(pathbuf) => pathbuf,
         Err(error) => return Err(CliError::BinaryWhichError(binary, error.to_string())),
     };
+    #[cfg(unix)]
     let path = CString::new(binary_path.as_os_str().as_bytes())?;
 
     sub_progress.success(Some("ready to launch process"));
@@ -134,32 +160,55 @@ where
     );
     sub_progress_config.success(Some("config summary"));
 
-    let args = binary_args
-        .clone()
-        .into_iter()
-        .map(CString::new)
-        .collect::<CliResult<Vec<_>, _>>()?;
-    // env vars should be formatted as "varname=value" CStrings
-    let env = env_vars
-        .into_iter()
-        .map(|(k, v)| CString::new(format!("{k}={v}")))
-        .collect::<CliResult<Vec<_>, _>>()?;
-
-    // The execve hook is not yet active and does not hijack this call.
-    let errno = nix::unistd::execve(&path, args.as_slice(), env.as_slice())
-        .expect_err("call to execve cannot succeed");
-    error!("Couldn't execute {:?}", errno);
-    analytics.set_error(AnalyticsError::BinaryExecuteFailed);
-
-    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-    if errno == Errno::from_raw(86) {
-        // "Bad CPU type in executable"
-        if _did_sip_patch {
-            return Err(CliError::RosettaMissing(binary));
-        }
+    // The binary runs inside WSL, where only the mirrord variables are shared, and we wait for it
+    // to exit as there is no `exec`.
+    #[cfg(windows)]
+    {
+        let env_vars = env_vars
+            .into_iter()
+            .filter(|(key, _)| {
+                key.starts_with("MIRRORD_") || execution_info.environment.contains_key(key)
+            })
+            .collect();
+
+        let exit_code = wsl::exec_in_wsl(&binary, &args.binary_args, env_vars)
+            .await
+            .inspect_err(|_| analytics.set_error(AnalyticsError::BinaryExecuteFailed))?;
+
+        // The internal proxy is killed when `execution_info` is dropped.
+        drop(execution_info);
+        std::process::exit(exit_code);
     }
 
-    Err(CliError::BinaryExecuteFailed(binary, binary_args))
+    #[cfg(unix)]
+    {
+        let args = binary_args
+            .clone()
+            .into_iter()
+            .map(CString::new)
+            .collect::<CliResult<Vec<_>, _>>()?;
+        // env vars should be formatted as "varname=value" CStrings
+        let env = env_vars
+            .into_iter()
+            .map(|(k, v)| CString::new(format!("{k}={v}")))
+            .collect::<CliResult<Vec<_>, _>>()?;
+
+        // The execve hook is not yet active and does not hijack this call.
+        let errno = nix::unistd::execve(&path, args.as_slice(), env.as_slice())
+            .expect_err("call to execve cannot succeed");
+        error!("Couldn't execute {:?}", errno);
+        analytics.set_error(AnalyticsError::BinaryExecuteFailed);
+
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        if errno == Errno::from_raw(86) {
+            // "Bad CPU type in executable"
+            if _did_sip_patch {
+                return Err(CliError::RosettaMissing(binary));
+            }
+        }
+
+        Err(CliError::BinaryExecuteFailed(binary, binary_args))
+    }
 }
 
 fn print_config<P>(
@@ -218,6 +267,7 @@ fn print_config<P>(
     let fs_info = match config.feature.fs.mode {
         FsModeConfig::Read => "read only from the remote",
         FsModeConfig::Write => "read and write from the remote",
+        FsModeConfig::CopyOnWrite => "read from the remote and write to a local overlay",
         _ => "read and write locally",
     };
     messages.push(format!("fs: file operations will default to {}", fs_info));
@@ -335,6 +385,7 @@ async fn exec(args: &ExecArgs, watch: drain::Watch) -> CliResult<()> {
     if !args.params.disable_version_check {
         prompt_outdated_version(&progress).await;
     }
+    preflight::check_conflicting_software(&progress);
     info!(
         "Launching {:?} with arguments {:?}",
         args.binary, args.binary_args
@@ -355,7 +406,7 @@ async fn exec(args: &ExecArgs, watch: drain::Watch) -> CliResult<()> {
         std::env::set_var(name, value);
     }
 
-    let (config, mut context) = LayerConfig::from_env_with_warnings()?;
+    let (mut config, mut context) = LayerConfig::from_env_with_warnings()?;
 
     let mut analytics = AnalyticsReporter::only_error(config.telemetry, Default::default(), watch);
     (&config).collect_analytics(analytics.get_mut());
@@ -365,6 +416,10 @@ async fn exec(args: &ExecArgs, watch: drain::Watch) -> CliResult<()> {
         progress.warning(warning);
     }
 
+    if routes::check_target_routes(&mut config, &progress).await {
+        routes::use_composed_config(&config, &progress);
+    }
+
     let execution_result = exec_process(config, args, &progress, &mut analytics).await;
 
     if execution_result.is_err() && !analytics.has_error() {
@@ -378,14 +433,12 @@ async fn exec(args: &ExecArgs, watch: drain::Watch) -> CliResult<()> {
 /// If the operator is enabled (and we can reach it), then we list [`KubeResourceSeeker::all`]
 /// targets, otherwise we list [`KubeResourceSeeker::all_open_source`] only.
 async fn list_targets(layer_config: &LayerConfig, args: &ListTargetArgs) -> CliResult<Vec<String>> {
-    let client = create_kube_config(
-        layer_config.accept_invalid_certificates,
-        layer_config.kubeconfig.clone(),
-        layer_config.kube_context.clone(),
-    )
-    .await
-    .and_then(|config| Client::try_from(config).map_err(From::from))
-    .map_err(|error| CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed))?;
+    let client = create_kube_config(layer_config)
+        .await
+        .and_then(|config| Client::try_from(config).map_err(From::from))
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
 
     let namespace = args
         .namespace
@@ -565,6 +618,18 @@ async fn port_forward(args: &PortForwardArgs, watch: drain::Watch) -> CliResult<
         std::env::set_var("MIRRORD_KUBE_CONTEXT", context);
     }
 
+    if let Some(kube_proxy) = &args.kube_proxy {
+        std::env::set_var("MIRRORD_KUBE_PROXY", kube_proxy);
+    }
+
+    if let Some(kube_ca_bundle) = &args.kube_ca_bundle {
+        std::env::set_var("MIRRORD_KUBE_CA_BUNDLE", kube_ca_bundle);
+    }
+
+    if let Some(kube_tls_server_name) = &args.kube_tls_server_name {
+        std::env::set_var("MIRRORD_KUBE_TLS_SERVER_NAME", kube_tls_server_name);
+    }
+
     if let Some(config_file) = &args.config_file {
         std::env::set_var("MIRRORD_CONFIG_FILE", config_file);
     }
@@ -635,6 +700,15 @@ fn main() -> miette::Result<()> {
 
     let cli = Cli::parse();
 
+    if cli.plain {
+        std::env::set_var(mirrord_progress::MIRRORD_PROGRESS_ENV, "plain");
+    }
+
+    // Tools that read the JSON progress get the error in JSON too, with its code.
+    if std::env::var(mirrord_progress::MIRRORD_PROGRESS_ENV).as_deref() == Ok("json") {
+        let _ = miette::set_hook(Box::new(|_| Box::new(JSONReportHandler::new())));
+    }
+
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
@@ -653,7 +727,10 @@ fn main() -> miette::Result<()> {
             mirrord_console::init_async_logger(&console_addr, watch.clone(), 124).await?;
         } else if force_log || !init_ext_error_handler(&cli.commands) {
             registry()
-                .with(fmt::layer().with_writer(std::io::stderr))
+                .with(fmt::layer().with_writer(RedactingMakeWriter::new(
+                    std::io::stderr,
+                    Redactor::from_env(),
+                )))
                 .with(EnvFilter::from_default_env())
                 .init();
         }
@@ -680,6 +757,7 @@ fn main() -> miette::Result<()> {
             }
             Commands::Teams => teams::navigate_to_intro().await,
             Commands::Diagnose(args) => diagnose_command(*args).await?,
+            Commands::Report(args) => report_command(*args).await?,
             Commands::Container(args) => {
                 let (runtime_args, exec_params) = args.into_parts();
                 container_command(runtime_args, exec_params, watch).await?
@@ -687,6 +765,14 @@ fn main() -> miette::Result<()> {
             Commands::ExternalProxy { port } => external_proxy::proxy(port, watch).await?,
             Commands::PortForward(args) => port_forward(&args, watch).await?,
             Commands::Vpn(args) => vpn::vpn_command(*args).await?,
+            Commands::Dns(args) => dns_command(*args).await?,
+            Commands::Toggle(args) => toggle_command(*args).await?,
+            Commands::Cron(args) => cron_command(*args).await?,
+            Commands::Auth(args) => auth_command(*args).await?,
+            Commands::Selftest(args) => selftest_command(*args).await?,
+            Commands::SelftestProbe => selftest_probe().await?,
+            Commands::Loadgen(args) => loadgen_command(*args).await?,
+            Commands::Config(args) => config_command(*args)?,
         };
 
         Ok(())
@@ -701,7 +787,7 @@ fn main() -> miette::Result<()> {
             });
     });
 
-    res.map_err(Into::into)
+    res.map_err(|error| error::CodedCliError(error).into())
 }
 
 // only ls and ext commands need the errors in json format
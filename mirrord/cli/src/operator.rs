@@ -29,6 +29,7 @@ use crate::{
 };
 
 mod session;
+mod usage;
 
 #[derive(Deserialize)]
 struct OperatorVersionResponse {
@@ -122,8 +123,9 @@ async fn operator_setup(
     Ok(())
 }
 
-#[tracing::instrument(level = Level::TRACE, ret)]
-async fn get_status_api(config: Option<&Path>) -> CliResult<Api<MirrordOperatorCrd>> {
+/// Loads the [`LayerConfig`] from the given file, or from the env, removing the proxy env if
+/// needed.
+pub(crate) fn load_layer_config(config: Option<&Path>) -> CliResult<LayerConfig> {
     let layer_config = if let Some(config) = config {
         let mut cfg_context = ConfigContext::default();
         LayerFileConfig::from_path(config)?.generate_config(&mut cfg_context)?
@@ -135,14 +137,19 @@ async fn get_status_api(config: Option<&Path>) -> CliResult<Api<MirrordOperatorC
         remove_proxy_env();
     }
 
-    let client = create_kube_config(
-        layer_config.accept_invalid_certificates,
-        layer_config.kubeconfig,
-        layer_config.kube_context,
-    )
-    .await
-    .and_then(|config| Client::try_from(config).map_err(From::from))
-    .map_err(|error| CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed))?;
+    Ok(layer_config)
+}
+
+#[tracing::instrument(level = Level::TRACE, ret)]
+async fn get_status_api(config: Option<&Path>) -> CliResult<Api<MirrordOperatorCrd>> {
+    let layer_config = load_layer_config(config)?;
+
+    let client = create_kube_config(&layer_config)
+        .await
+        .and_then(|config| Client::try_from(config).map_err(From::from))
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
 
     Ok(Api::all(client))
 }
@@ -151,16 +158,7 @@ async fn get_status_api(config: Option<&Path>) -> CliResult<Api<MirrordOperatorC
 async fn operator_status(config: Option<&Path>) -> CliResult<()> {
     let mut progress = ProgressTracker::from_env("Operator Status");
 
-    let layer_config = if let Some(config) = config {
-        let mut cfg_context = ConfigContext::default();
-        LayerFileConfig::from_path(config)?.generate_config(&mut cfg_context)?
-    } else {
-        LayerConfig::from_env()?
-    };
-
-    if !layer_config.use_proxy {
-        remove_proxy_env();
-    }
+    let layer_config = load_layer_config(config)?;
 
     let mut status_progress = progress.subtask("fetching status");
     let api = OperatorApi::try_new(&layer_config, &mut NullReporter::default())
@@ -184,12 +182,14 @@ async fn operator_status(config: Option<&Path>) -> CliResult<()> {
                 name,
                 organization,
                 expire_at,
+                fingerprint,
                 ..
             },
         ..
     } = &api.operator().spec;
 
     let expire_at = expire_at.format("%e-%b-%Y");
+    let fingerprint = fingerprint.as_deref().unwrap_or("unknown");
 
     println!(
         r#"
@@ -199,6 +199,7 @@ Operator License
     name: {name}
     organization: {organization}
     expire at: {expire_at}
+    fingerprint: {fingerprint}
 "#
     );
 
@@ -242,6 +243,38 @@ Operator License
         println!();
     }
 
+    if let Some(agent_pools) = status.agent_pools.as_ref() {
+        if agent_pools.is_empty() {
+            println!("No agent pools.");
+        } else {
+            println!("Agent Pools:");
+            let mut agent_pools_table = Table::new();
+
+            agent_pools_table.add_row(row![
+                "Pool",
+                "Node/Namespace",
+                "Ready",
+                "Starting",
+                "Claimed"
+            ]);
+
+            for (pool_name, pool_status) in agent_pools {
+                for slot in &pool_status.slots {
+                    agent_pools_table.add_row(row![
+                        pool_name,
+                        &slot.name,
+                        slot.ready,
+                        slot.starting,
+                        slot.claimed,
+                    ]);
+                }
+            }
+
+            agent_pools_table.printstd();
+        }
+        println!();
+    }
+
     if let Some(statistics) = status.statistics.as_ref() {
         println!("Operator Daily Users: {}", statistics.dau);
         println!("Operator Monthly Users: {}", statistics.mau);
@@ -299,6 +332,11 @@ pub(crate) async fn operator_command(args: OperatorArgs) -> CliResult<()> {
     match args.command {
         OperatorCommand::Setup(params) => operator_setup(params).await.map_err(CliError::from),
         OperatorCommand::Status { config_file } => operator_status(config_file.as_deref()).await,
+        OperatorCommand::Usage {
+            since,
+            output,
+            config_file,
+        } => usage::operator_usage(config_file.as_deref(), since, output).await,
         OperatorCommand::Session(session_command) => {
             SessionCommandHandler::new(session_command)
                 .and_then(SessionCommandHandler::handle)
@@ -1,4 +1,6 @@
-use kube::{core::ErrorResponse, Api};
+use std::time::Duration;
+
+use kube::{api::PostParams, core::ErrorResponse, Api};
 use mirrord_analytics::NullReporter;
 use mirrord_config::LayerConfig;
 use mirrord_operator::{
@@ -6,7 +8,7 @@ use mirrord_operator::{
         error::{OperatorApiError, OperatorOperation},
         MaybeClientCert, OperatorApi,
     },
-    crd::{NewOperatorFeature, SessionCrd},
+    crd::{NewOperatorFeature, SessionCrd, SessionShareCrd, SessionShareLink, SessionShareSpec},
 };
 use mirrord_progress::{Progress, ProgressTracker};
 use tracing::Level;
@@ -76,7 +78,12 @@ impl SessionCommandHandler {
 
         sub_progress.print(&format!("executing `{command}`"));
 
+        if let SessionCommand::Share { id, ttl } = command {
+            return Self::share(progress, sub_progress, &operator_api, id, ttl).await;
+        }
+
         let session_api: Api<SessionCrd> = Api::all(operator_api.client().clone());
+        let share_api: Api<SessionShareCrd> = Api::all(operator_api.client().clone());
 
         // We're interested in the `Status`es, so we map the results into those.
         match command {
@@ -92,6 +99,11 @@ impl SessionCommandHandler {
                 .delete("inactive", &Default::default())
                 .await
                 .map(|either| either.right()),
+            SessionCommand::Unshare { id } => share_api
+                .delete(&format!("{id:X}"), &Default::default())
+                .await
+                .map(|either| either.right()),
+            SessionCommand::Share { .. } => unreachable!("handled above"),
         }
         .map_err(|kube_fail| match kube_fail {
             // The random `reason` we get when the operator returns from a "missing route".
@@ -148,4 +160,64 @@ impl SessionCommandHandler {
 
         Ok(())
     }
+
+    /// Creates the [`SessionShareCrd`] for the session `id`, and prints the
+    /// [`SessionShareLink`] to join it.
+    async fn share(
+        mut progress: ProgressTracker,
+        mut sub_progress: ProgressTracker,
+        operator_api: &OperatorApi<MaybeClientCert>,
+        id: u64,
+        ttl: Option<Duration>,
+    ) -> CliResult<()> {
+        let share = async {
+            operator_api
+                .operator()
+                .spec
+                .require_feature(NewOperatorFeature::SessionSharing)?;
+
+            let session_id = format!("{id:X}");
+            let spec = SessionShareSpec {
+                ttl_secs: ttl.as_ref().map(Duration::as_secs),
+            };
+
+            let share = Api::<SessionShareCrd>::all(operator_api.client().clone())
+                .create(
+                    &PostParams::default(),
+                    &SessionShareCrd::new(&session_id, spec),
+                )
+                .await
+                .map_err(|error| OperatorApiError::KubeError {
+                    error,
+                    operation: OperatorOperation::SharingSession,
+                })?;
+
+            let link = share.status.map(|status| SessionShareLink {
+                session_id,
+                token: status.token,
+            });
+
+            Ok::<_, OperatorApiError>(link)
+        }
+        .await
+        .inspect_err(|fail| {
+            sub_progress.failure(Some(&fail.to_string()));
+            progress.failure(Some("Session share failed!"));
+        })?;
+
+        let Some(link) = share else {
+            sub_progress.failure(Some("the operator did not issue a token"));
+            progress.failure(Some("Session share failed!"));
+            return Err(CliError::OperatorSessionShareFailed);
+        };
+
+        sub_progress.success(Some(&format!("session {id:X} shared")));
+        progress.success(Some(
+            "Others can now observe the session, revoke it with `mirrord operator session unshare`.",
+        ));
+
+        println!("mirrord exec --join-session {link} -- <command>");
+
+        Ok(())
+    }
 }
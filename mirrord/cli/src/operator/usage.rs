@@ -0,0 +1,191 @@
+use std::{fmt::Write, path::Path, time::Duration};
+
+use kube::Api;
+use mirrord_analytics::NullReporter;
+use mirrord_operator::{
+    client::{
+        error::{OperatorApiError, OperatorOperation},
+        OperatorApi,
+    },
+    crd::{NewOperatorFeature, UsageCrd, UsageEntry, UsageSpec},
+};
+use mirrord_progress::{Progress, ProgressTracker};
+use prettytable::{row, Table};
+use tracing::Level;
+
+use super::load_layer_config;
+use crate::{config::UsageFormat, CliError, CliResult};
+
+/// How many targets are printed in the [`UsageFormat::Table`] output.
+const TABLE_TOP_TARGETS: usize = 10;
+
+/// Handles the `mirrord operator usage` command.
+///
+/// Fetches the [`UsageCrd`] for the last `since` period and prints it in the given format.
+#[tracing::instrument(level = Level::TRACE, ret)]
+pub(super) async fn operator_usage(
+    config: Option<&Path>,
+    since: Duration,
+    output: UsageFormat,
+) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("Operator Usage");
+
+    let layer_config = load_layer_config(config)?;
+
+    let mut usage_progress = progress.subtask("fetching usage");
+    let api = OperatorApi::try_new(&layer_config, &mut NullReporter::default())
+        .await
+        .inspect_err(|_| {
+            usage_progress.failure(Some("failed to find operator"));
+        })?;
+    let Some(api) = api else {
+        usage_progress.failure(Some("operator not found"));
+        return Err(CliError::OperatorNotInstalled);
+    };
+
+    let usage = async {
+        api.operator()
+            .spec
+            .require_feature(NewOperatorFeature::UsageReporting)?;
+
+        Api::<UsageCrd>::all(api.client().clone())
+            .get(&since.as_secs().to_string())
+            .await
+            .map_err(|error| OperatorApiError::KubeError {
+                error,
+                operation: OperatorOperation::GettingUsage,
+            })
+    }
+    .await
+    .inspect_err(|_| {
+        usage_progress.failure(Some("failed to fetch usage"));
+    })?;
+    usage_progress.success(Some("fetched usage"));
+
+    progress.success(None);
+
+    match output {
+        UsageFormat::Table => print_tables(&usage.spec),
+        UsageFormat::Csv => print!("{}", to_csv(&usage.spec)),
+        UsageFormat::Json => println!("{}", serde_json::to_string_pretty(&usage.spec)?),
+    }
+
+    Ok(())
+}
+
+fn hours(seconds: u64) -> String {
+    format!("{:.2}", seconds as f64 / 3600.0)
+}
+
+fn print_tables(usage: &UsageSpec) {
+    println!("Usage since {}", usage.since.format("%e-%b-%Y %H:%M UTC"));
+    println!();
+
+    let mut teams = Table::new();
+    teams.add_row(row!["Team", "Sessions", "Steal Hours", "Mirror Hours"]);
+    for team in &usage.teams {
+        teams.add_row(row![
+            &team.name,
+            team.sessions,
+            hours(team.steal_seconds),
+            hours(team.mirror_seconds),
+        ]);
+    }
+    teams.printstd();
+    println!();
+
+    let mut targets = Table::new();
+    targets.add_row(row![
+        "Target",
+        "Namespace",
+        "Sessions",
+        "Steal Hours",
+        "Mirror Hours"
+    ]);
+    for target in usage.targets.iter().take(TABLE_TOP_TARGETS) {
+        targets.add_row(row![
+            &target.name,
+            target.namespace.as_deref().unwrap_or("N/A"),
+            target.sessions,
+            hours(target.steal_seconds),
+            hours(target.mirror_seconds),
+        ]);
+    }
+    targets.printstd();
+}
+
+/// Quotes the CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Renders the usage as CSV, one row per team and target.
+fn to_csv(usage: &UsageSpec) -> String {
+    let mut csv = String::from("group,name,namespace,sessions,steal_hours,mirror_hours\n");
+
+    let rows = usage
+        .teams
+        .iter()
+        .map(|team| ("team", team))
+        .chain(usage.targets.iter().map(|target| ("target", target)));
+    for (
+        group,
+        UsageEntry {
+            name,
+            namespace,
+            sessions,
+            steal_seconds,
+            mirror_seconds,
+        },
+    ) in rows
+    {
+        // Writing to a `String` never fails.
+        let _ = writeln!(
+            csv,
+            "{group},{},{},{sessions},{},{}",
+            csv_field(name),
+            csv_field(namespace.as_deref().unwrap_or_default()),
+            hours(*steal_seconds),
+            hours(*mirror_seconds),
+        );
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn usage_csv() {
+        let usage = UsageSpec {
+            since: Default::default(),
+            teams: vec![UsageEntry {
+                name: "backend, core".into(),
+                namespace: None,
+                sessions: 12,
+                steal_seconds: 5400,
+                mirror_seconds: 60,
+            }],
+            targets: vec![UsageEntry {
+                name: "deploy/api".into(),
+                namespace: Some("prod".into()),
+                sessions: 7,
+                steal_seconds: 3600,
+                mirror_seconds: 0,
+            }],
+        };
+
+        assert_eq!(
+            to_csv(&usage),
+            "group,name,namespace,sessions,steal_hours,mirror_hours\n\
+            team,\"backend, core\",,12,1.50,0.02\n\
+            target,deploy/api,prod,7,1.00,0.00\n"
+        );
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 use futures::StreamExt;
 use mirrord_config::feature::network::incoming::{
     http_filter::{HttpFilterConfig, InnerFilter},
-    IncomingConfig,
+    DatabaseFilterConfig, DatabaseProtocol as DatabaseProtocolConfig, IncomingConfig,
 };
 use mirrord_intproxy::{
     background_tasks::{BackgroundTasks, TaskError, TaskSender, TaskUpdate},
@@ -28,7 +28,9 @@ use mirrord_protocol::{
         tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
         LayerClose, LayerConnect, LayerWrite, SocketAddress,
     },
-    tcp::{Filter, HttpFilter, LayerTcp, LayerTcpSteal, StealType},
+    tcp::{
+        DatabaseFilter, DatabaseProtocol, Filter, HttpFilter, LayerTcp, LayerTcpSteal, StealType,
+    },
     ClientMessage, ConnectionId, DaemonMessage, LogLevel, Port, ResponseError,
     CLIENT_READY_FOR_LOGS,
 };
@@ -271,6 +273,12 @@ impl PortForwarder {
                         "connection closed for port mapping {local_socket}:{remote_socket}, connection {connection_id}"
                     );
                 }
+                // Port forwarding never sends files.
+                DaemonTcpOutgoing::SendFile(res) => {
+                    return Err(PortForwardError::AgentError(format!(
+                        "unexpected DaemonTcpOutgoing::SendFile {res:?}"
+                    )))
+                }
             },
             DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(message)) => match message {
                 Ok(DnsLookup(record)) if !record.is_empty() => {
@@ -858,6 +866,36 @@ struct StealHttpSettings {
     pub ports: HashSet<Port>,
 }
 
+/// Settings for handling database connections with the `steal` feature.
+#[derive(Debug)]
+struct StealDatabaseSettings {
+    /// The database filter to use.
+    pub filter: DatabaseFilter,
+    /// Ports to filter database connections on.
+    pub ports: HashSet<Port>,
+}
+
+impl StealDatabaseSettings {
+    /// Creates a new instance from the given [`DatabaseFilterConfig`].
+    fn new(config: &DatabaseFilterConfig) -> Self {
+        let protocol = match config.protocol {
+            DatabaseProtocolConfig::Postgres => DatabaseProtocol::Postgres,
+            DatabaseProtocolConfig::Tds => DatabaseProtocol::Tds,
+        };
+        let make_filter =
+            |filter: &String| Filter::new(filter.clone()).expect("invalid filter expression");
+
+        Self {
+            filter: DatabaseFilter {
+                protocol,
+                database: config.database.as_ref().map(make_filter),
+                user: config.user.as_ref().map(make_filter),
+            },
+            ports: config.get_filtered_ports(),
+        }
+    }
+}
+
 /// Operation mode for the `incoming` feature.
 #[derive(Debug)]
 enum IncomingMode {
@@ -866,7 +904,7 @@ enum IncomingMode {
     Mirror,
     /// The agent sends data only to the user application.
     /// Data coming from the layer is sent to the agent.
-    Steal(StealHttpSettings),
+    Steal(StealHttpSettings, Option<StealDatabaseSettings>),
 }
 
 impl IncomingMode {
@@ -888,6 +926,11 @@ impl IncomingMode {
                 all_of: None,
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(HttpFilter::Path(
                 Filter::new(path.into()).expect("invalid filter expression"),
             )),
@@ -898,6 +941,11 @@ impl IncomingMode {
                 all_of: None,
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(HttpFilter::Header(
                 Filter::new(header.into()).expect("invalid filter expression"),
             )),
@@ -908,6 +956,11 @@ impl IncomingMode {
                 all_of: Some(filters),
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(Self::make_composite_filter(true, filters)),
 
             HttpFilterConfig {
@@ -916,6 +969,11 @@ impl IncomingMode {
                 all_of: None,
                 any_of: Some(filters),
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(Self::make_composite_filter(false, filters)),
 
             HttpFilterConfig {
@@ -924,12 +982,22 @@ impl IncomingMode {
                 all_of: None,
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::None,
 
             _ => panic!("multiple HTTP filters specified, this is a bug"),
         };
 
-        Self::Steal(StealHttpSettings { filter, ports })
+        let database = config
+            .database_filter
+            .as_ref()
+            .map(StealDatabaseSettings::new);
+
+        Self::Steal(StealHttpSettings { filter, ports }, database)
     }
 
     fn make_composite_filter(all: bool, filters: &[InnerFilter]) -> HttpFilter {
@@ -950,10 +1018,20 @@ impl IncomingMode {
 
     /// Returns [`PortSubscription`] request to be used for the given port.
     fn subscription(&self, port: Port) -> PortSubscription {
-        let Self::Steal(steal) = self else {
+        let Self::Steal(steal, database) = self else {
             return PortSubscription::Mirror(port);
         };
 
+        if let Some(database) = database
+            .as_ref()
+            .filter(|database| database.ports.contains(&port))
+        {
+            return PortSubscription::Steal(StealType::FilteredDatabase(
+                port,
+                database.filter.clone(),
+            ));
+        }
+
         let steal_type = match &steal.filter {
             _ if !steal.ports.contains(&port) => StealType::All(port),
             StealHttpFilter::None => StealType::All(port),
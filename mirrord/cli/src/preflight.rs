@@ -0,0 +1,452 @@
+//! Pre-flight scan of the local machine for software that is known to break mirrord sessions
+//! (VPN clients, local DNS proxies, endpoint security), see [`check_conflicting_software`].
+//!
+//! Detection is best effort and based only on process names, network interfaces and the local
+//! DNS config, so we only warn and never stop the session.
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    path::Path,
+    process::Command,
+};
+
+use mirrord_progress::Progress;
+use serde::Serialize;
+use tracing::Level;
+
+/// Set to `false` to skip the scan.
+const MIRRORD_PREFLIGHT_CHECK_ENV: &str = "MIRRORD_PREFLIGHT_CHECK";
+
+/// Loopback nameservers that are set up by the OS or by the container runtime, and don't
+/// interfere with mirrord (`systemd-resolved` and the Docker embedded DNS).
+const SYSTEM_LOCAL_NAMESERVERS: [IpAddr; 2] = [
+    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 53)),
+    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 11)),
+];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConflictKind {
+    Vpn,
+    DnsProxy,
+    EndpointSecurity,
+}
+
+impl ConflictKind {
+    /// Remediation hint for software of this kind that doesn't have its own.
+    fn hint(self) -> &'static str {
+        match self {
+            Self::Vpn => {
+                "VPN clients can route the connection to the cluster or to the local internal \
+                proxy through the tunnel. If mirrord can't connect to the agent, try again with \
+                the VPN disconnected, or ask your admin to allow split tunneling for the cluster \
+                API."
+            }
+            Self::DnsProxy => {
+                "mirrord resolves names in the cluster by hooking `getaddrinfo`, but programs that \
+                query the DNS server directly send their queries to the local proxy. Make sure \
+                `feature.network.dns` is enabled and that UDP traffic to port 53 is not excluded \
+                by `feature.network.outgoing.filter`."
+            }
+            Self::EndpointSecurity => {
+                "Endpoint security software can block loading the mirrord layer \
+                (`LD_PRELOAD`/`DYLD_INSERT_LIBRARIES`) or quarantine the library mirrord extracts \
+                to the temp directory. If your process starts without mirrord, ask your IT team \
+                to allow-list mirrord."
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vpn => write!(f, "VPN"),
+            Self::DnsProxy => write!(f, "local DNS proxy"),
+            Self::EndpointSecurity => write!(f, "endpoint security"),
+        }
+    }
+}
+
+/// Software that is known to conflict with mirrord.
+struct KnownSoftware {
+    name: &'static str,
+    kind: ConflictKind,
+    /// Names of the processes of the software, compared case-insensitively.
+    processes: &'static [&'static str],
+    /// Prefixes of the names of the network interfaces that the software creates.
+    interfaces: &'static [&'static str],
+    /// Remediation hint, [`ConflictKind::hint`] when [`None`].
+    hint: Option<&'static str>,
+}
+
+const KNOWN_SOFTWARE: &[KnownSoftware] = &[
+    KnownSoftware {
+        name: "Cisco AnyConnect",
+        kind: ConflictKind::Vpn,
+        processes: &["vpnagentd", "vpnagent.exe", "csc_ui", "acwebsecagent"],
+        interfaces: &["cscotun"],
+        hint: Some(
+            "AnyConnect blocks connections to local ports unless \"Allow local (LAN) access\" is \
+            enabled in its preferences, which breaks the connection to the internal proxy.",
+        ),
+    },
+    KnownSoftware {
+        name: "GlobalProtect",
+        kind: ConflictKind::Vpn,
+        processes: &["pangps", "pangpa", "globalprotect", "pangps.exe"],
+        interfaces: &["gpd"],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "Zscaler",
+        kind: ConflictKind::Vpn,
+        processes: &["zscaler", "zsatunnel", "zsatray", "zscalerservice.exe"],
+        interfaces: &[],
+        hint: Some(
+            "Zscaler intercepts TLS, which fails the certificate check of the cluster API. Add \
+            the Zscaler root certificate to your kubeconfig, or set `accept_invalid_certificates`.",
+        ),
+    },
+    KnownSoftware {
+        name: "FortiClient",
+        kind: ConflictKind::Vpn,
+        processes: &["forticlient", "fortitray", "fctsched", "forticlient.exe"],
+        interfaces: &["fctvpn"],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "OpenVPN",
+        kind: ConflictKind::Vpn,
+        processes: &["openvpn", "openvpn.exe", "openvpn-gui.exe"],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "dnsmasq",
+        kind: ConflictKind::DnsProxy,
+        processes: &["dnsmasq"],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "dnscrypt-proxy",
+        kind: ConflictKind::DnsProxy,
+        processes: &["dnscrypt-proxy", "dnscrypt-proxy.exe"],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "CrowdStrike Falcon",
+        kind: ConflictKind::EndpointSecurity,
+        processes: &["falcond", "falcon-sensor", "csfalconservice.exe"],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "SentinelOne",
+        kind: ConflictKind::EndpointSecurity,
+        processes: &[
+            "sentinelagent",
+            "sentineld",
+            "s1-agent",
+            "sentinelagent.exe",
+        ],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "Microsoft Defender for Endpoint",
+        kind: ConflictKind::EndpointSecurity,
+        processes: &["wdavdaemon"],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "Carbon Black",
+        kind: ConflictKind::EndpointSecurity,
+        processes: &["cbagentd", "cbosxsensorservice", "repmgr.exe"],
+        interfaces: &[],
+        hint: None,
+    },
+    KnownSoftware {
+        name: "Sophos",
+        kind: ConflictKind::EndpointSecurity,
+        processes: &[
+            "sophosscand",
+            "sophos-spl",
+            "sophosantivirus",
+            "savservice.exe",
+        ],
+        interfaces: &[],
+        hint: None,
+    },
+];
+
+/// Conflicting software found on the local machine.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Conflict {
+    name: String,
+    kind: ConflictKind,
+    /// What gave the software away, e.g. process `vpnagentd`.
+    found: String,
+    hint: &'static str,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) detected, found {}. {}",
+            self.name, self.kind, self.found, self.hint
+        )
+    }
+}
+
+/// What we know about the local machine, see [`LocalEnvironment::collect`].
+#[derive(Debug, Default)]
+struct LocalEnvironment {
+    /// Names of the running processes, lowercase and without the path.
+    processes: Vec<String>,
+    /// Names of the network interfaces that have an address.
+    interfaces: Vec<String>,
+    /// Nameservers from `/etc/resolv.conf`.
+    nameservers: Vec<IpAddr>,
+}
+
+impl LocalEnvironment {
+    #[tracing::instrument(level = Level::TRACE, ret)]
+    fn collect() -> Self {
+        let interfaces = local_ip_address::list_afinet_netifas()
+            .inspect_err(|error| tracing::debug!(%error, "Failed to list network interfaces."))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        let nameservers = std::fs::read("/etc/resolv.conf")
+            .map(|resolv_conf| parse_nameservers(&resolv_conf))
+            .unwrap_or_default();
+
+        Self {
+            processes: list_processes(),
+            interfaces,
+            nameservers,
+        }
+    }
+}
+
+/// Lists the names of the running processes, empty when we fail to.
+fn list_processes() -> Vec<String> {
+    let output = if cfg!(windows) {
+        Command::new("tasklist")
+            .args(["/FO", "CSV", "/NH"])
+            .output()
+    } else {
+        Command::new("ps").args(["-A", "-o", "comm="]).output()
+    };
+
+    let output = match output {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            tracing::debug!(status = %output.status, "Failed to list processes.");
+            return Vec::new();
+        }
+        Err(error) => {
+            tracing::debug!(%error, "Failed to list processes.");
+            return Vec::new();
+        }
+    };
+
+    String::from_utf8_lossy(&output)
+        .lines()
+        .filter_map(|line| {
+            // `tasklist` prints `"name.exe","pid",...`, `ps` prints the name or the full path.
+            let name = line.split(',').next()?.trim().trim_matches('"');
+            let name = Path::new(name).file_name()?.to_str()?;
+            Some(name.to_lowercase())
+        })
+        .collect()
+}
+
+/// Addresses of the nameservers of a `resolv.conf` file, empty if it's not valid.
+fn parse_nameservers(resolv_conf: &[u8]) -> Vec<IpAddr> {
+    match resolv_conf::Config::parse(resolv_conf) {
+        Ok(config) => config.nameservers.into_iter().map(Into::into).collect(),
+        Err(error) => {
+            tracing::debug!(%error, "Failed to parse `/etc/resolv.conf`.");
+            Vec::new()
+        }
+    }
+}
+
+/// Finds the [`KNOWN_SOFTWARE`] in the given environment, and local DNS proxies that we don't
+/// know by name.
+fn scan(environment: &LocalEnvironment) -> Vec<Conflict> {
+    let mut conflicts = KNOWN_SOFTWARE
+        .iter()
+        .filter_map(|software| {
+            let found = software
+                .processes
+                .iter()
+                .find(|process| {
+                    environment
+                        .processes
+                        .iter()
+                        .any(|name| name.as_str() == **process)
+                })
+                .map(|process| format!("process `{process}`"))
+                .or_else(|| {
+                    environment
+                        .interfaces
+                        .iter()
+                        .find(|interface| {
+                            software
+                                .interfaces
+                                .iter()
+                                .any(|prefix| interface.starts_with(prefix))
+                        })
+                        .map(|interface| format!("network interface `{interface}`"))
+                })?;
+
+            Some(Conflict {
+                name: software.name.to_string(),
+                kind: software.kind,
+                found,
+                hint: software.hint.unwrap_or_else(|| software.kind.hint()),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let dns_proxy_found = conflicts
+        .iter()
+        .any(|conflict| conflict.kind == ConflictKind::DnsProxy);
+    let local_nameserver = environment.nameservers.iter().find(|nameserver| {
+        nameserver.is_loopback() && !SYSTEM_LOCAL_NAMESERVERS.contains(nameserver)
+    });
+    if let Some(nameserver) = local_nameserver
+        && !dns_proxy_found
+    {
+        conflicts.push(Conflict {
+            name: "local DNS server".to_string(),
+            kind: ConflictKind::DnsProxy,
+            found: format!("nameserver {nameserver} in /etc/resolv.conf"),
+            hint: ConflictKind::DnsProxy.hint(),
+        });
+    }
+
+    conflicts
+}
+
+/// Whether the scan is enabled with [`MIRRORD_PREFLIGHT_CHECK_ENV`].
+fn enabled() -> bool {
+    std::env::var(MIRRORD_PREFLIGHT_CHECK_ENV)
+        .map(|value| value.parse().unwrap_or(true))
+        .unwrap_or(true)
+}
+
+/// Scans the local machine for software that conflicts with mirrord, see [`KNOWN_SOFTWARE`].
+pub(crate) fn find_conflicts() -> Vec<Conflict> {
+    scan(&LocalEnvironment::collect())
+}
+
+/// Warns about software on the local machine that is known to conflict with mirrord, with a
+/// remediation hint for each.
+pub(crate) fn check_conflicting_software<P>(progress: &P)
+where
+    P: Progress,
+{
+    if !enabled() {
+        return;
+    }
+
+    let mut subtask = progress.subtask("checking for conflicting software");
+    let conflicts = find_conflicts();
+    for conflict in &conflicts {
+        subtask.warning(&conflict.to_string());
+    }
+
+    if conflicts.is_empty() {
+        subtask.success(Some("no conflicting software found"));
+    } else {
+        subtask.success(Some(&format!(
+            "found {} programs that may interfere with mirrord \
+            (set {MIRRORD_PREFLIGHT_CHECK_ENV}=false to skip this check)",
+            conflicts.len()
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn environment(
+        processes: &[&str],
+        interfaces: &[&str],
+        nameservers: &[&str],
+    ) -> LocalEnvironment {
+        LocalEnvironment {
+            processes: processes.iter().map(ToString::to_string).collect(),
+            interfaces: interfaces.iter().map(ToString::to_string).collect(),
+            nameservers: nameservers
+                .iter()
+                .map(|nameserver| nameserver.parse().unwrap())
+                .collect(),
+        }
+    }
+
+    #[rstest]
+    #[case::nothing(environment(&["bash", "zsh"], &["lo", "eth0"], &["8.8.8.8"]), &[])]
+    #[case::anyconnect_process(
+        environment(&["vpnagentd"], &[], &[]),
+        &[("Cisco AnyConnect", ConflictKind::Vpn)]
+    )]
+    #[case::anyconnect_interface(
+        environment(&[], &["cscotun0"], &[]),
+        &[("Cisco AnyConnect", ConflictKind::Vpn)]
+    )]
+    #[case::dnsmasq(
+        environment(&["dnsmasq"], &[], &["127.0.0.1"]),
+        &[("dnsmasq", ConflictKind::DnsProxy)]
+    )]
+    #[case::unknown_dns_proxy(
+        environment(&[], &[], &["127.0.0.1"]),
+        &[("local DNS server", ConflictKind::DnsProxy)]
+    )]
+    #[case::systemd_resolved(environment(&[], &[], &["127.0.0.53"]), &[])]
+    #[case::endpoint_security(
+        environment(&["falcond", "zscaler"], &[], &[]),
+        &[("Zscaler", ConflictKind::Vpn), ("CrowdStrike Falcon", ConflictKind::EndpointSecurity)]
+    )]
+    fn finds_conflicts(
+        #[case] environment: LocalEnvironment,
+        #[case] expected: &[(&str, ConflictKind)],
+    ) {
+        let conflicts = scan(&environment)
+            .into_iter()
+            .map(|conflict| (conflict.name, conflict.kind))
+            .collect::<Vec<_>>();
+        let expected = expected
+            .iter()
+            .map(|(name, kind)| (name.to_string(), *kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(conflicts, expected);
+    }
+
+    #[test]
+    fn parses_nameservers() {
+        let resolv_conf = b"# generated\nnameserver 127.0.0.1\nsearch corp\nnameserver ::1\n";
+
+        assert_eq!(
+            parse_nameservers(resolv_conf),
+            [
+                "127.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+    }
+}
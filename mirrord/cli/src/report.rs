@@ -0,0 +1,361 @@
+//! Implementation of the `mirrord report` command, which bundles information about the user's
+//! mirrord setup into a `.tar.gz` archive that can be attached to bug reports.
+//!
+//! The archive contains:
+//!
+//! 1. `report.json` - versions, the resolved target, connection details (operator/agent versions,
+//!    protocol capabilities), timing statistics and conflicting local software;
+//! 2. `config.json` - the resolved mirrord config;
+//! 3. `intproxy.log` - the tail of the most recent internal proxy log.
+//!
+//! Everything that might contain a secret is redacted before being written, see [`Redactor`].
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{Duration, SystemTime},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use mirrord_analytics::NullReporter;
+use mirrord_config::{
+    config::{ConfigContext, ConfigError, MirrordConfig},
+    LayerConfig, LayerFileConfig,
+};
+use mirrord_intproxy::agent_conn::AgentConnectInfo;
+use mirrord_operator::{client::OperatorApi, crd::NewOperatorFeature};
+use mirrord_progress::{Progress, ProgressTracker};
+use mirrord_protocol::{
+    dns::ADDRINFO_V2_VERSION,
+    file::READDIR_BATCH_VERSION,
+    tcp::{
+        HTTP_CHUNKED_REQUEST_VERSION, HTTP_CHUNKED_RESPONSE_VERSION, HTTP_COMPOSITE_FILTER_VERSION,
+        HTTP_FILTERED_UPGRADE_VERSION, HTTP_FRAMED_VERSION,
+    },
+    CLIENT_READY_FOR_LOGS,
+};
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::Level;
+
+use crate::{
+    config::ReportArgs,
+    connection::create_and_connect,
+    diagnose::ping,
+    execution::MirrordExecution,
+    preflight::{self, Conflict},
+    redaction::Redactor,
+    util::remove_proxy_env,
+    CliError, CliResult,
+};
+
+/// How many pings we send to the agent to measure the latency.
+const PING_ITERATIONS: usize = 10;
+
+/// How many lines from the end of the intproxy log we include.
+const LOG_TAIL_LINES: usize = 1000;
+
+/// Protocol features that depend on the version of the agent (or operator), checked in
+/// [`capabilities`].
+static PROTOCOL_FEATURES: LazyLock<Vec<(&'static str, &'static VersionReq)>> =
+    LazyLock::new(|| {
+        vec![
+            ("http_framed", &*HTTP_FRAMED_VERSION),
+            ("http_chunked_request", &*HTTP_CHUNKED_REQUEST_VERSION),
+            ("http_chunked_response", &*HTTP_CHUNKED_RESPONSE_VERSION),
+            ("http_filtered_upgrade", &*HTTP_FILTERED_UPGRADE_VERSION),
+            ("http_composite_filter", &*HTTP_COMPOSITE_FILTER_VERSION),
+            ("readdir_batch", &*READDIR_BATCH_VERSION),
+            ("agent_logs", &*CLIENT_READY_FOR_LOGS),
+            ("getaddrinfo_v2", &*ADDRINFO_V2_VERSION),
+        ]
+    });
+
+/// Contents of `report.json`.
+#[derive(Serialize, Debug)]
+struct Report {
+    cli_version: &'static str,
+    protocol_version: String,
+    os: &'static str,
+    arch: &'static str,
+    /// Resolved target path, [`None`] when targetless.
+    target: Option<String>,
+    target_namespace: Option<String>,
+    operator: Option<OperatorReport>,
+    /// [`None`] when running with `--offline`.
+    connection: Option<Result<ConnectionReport, String>>,
+    /// Local software that is known to conflict with mirrord.
+    conflicts: Vec<Conflict>,
+}
+
+#[derive(Serialize, Debug)]
+struct OperatorReport {
+    version: String,
+    protocol_version: Option<String>,
+    features: Vec<NewOperatorFeature>,
+}
+
+#[derive(Serialize, Debug)]
+struct ConnectionReport {
+    /// `operator` or `agent`.
+    kind: &'static str,
+    /// Protocol version of the agent, or the operator when it's used.
+    protocol_version: Option<String>,
+    /// Which of the [`PROTOCOL_FEATURES`] are supported by the remote.
+    capabilities: BTreeMap<&'static str, bool>,
+    connect_time_ms: u128,
+    ping_min_ms: u128,
+    ping_max_ms: u128,
+    ping_avg_ms: u128,
+}
+
+/// Checks which [`PROTOCOL_FEATURES`] are supported by a remote that uses `version`.
+fn capabilities(version: Option<&Version>) -> BTreeMap<&'static str, bool> {
+    PROTOCOL_FEATURES
+        .iter()
+        .map(|(name, requirement)| (*name, version.is_some_and(|v| requirement.matches(v))))
+        .collect()
+}
+
+/// Finds the intproxy log to include, either the configured one, or the most recent one in the
+/// default location (see [`crate::internal_proxy`]).
+fn intproxy_log(args: &ReportArgs, config: &LayerConfig) -> Option<PathBuf> {
+    if let Some(path) = args.log_file.clone().or_else(|| {
+        config
+            .internal_proxy
+            .log_destination
+            .clone()
+            .map(PathBuf::from)
+    }) {
+        return Some(path);
+    }
+
+    std::fs::read_dir("/tmp")
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with("mirrord-intproxy-") && name.ends_with(".log"))
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// Reads the last [`LOG_TAIL_LINES`] of the log at `path`, redacted.
+fn log_tail(path: &Path, redactor: &Redactor) -> std::io::Result<String> {
+    let log = std::fs::read_to_string(path)?;
+    let lines = log.lines().collect::<Vec<_>>();
+
+    Ok(lines
+        .iter()
+        .skip(lines.len().saturating_sub(LOG_TAIL_LINES))
+        .map(|line| redactor.redact(line))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Fetches the operator details, [`None`] if the operator is not installed or disabled.
+async fn operator_report(config: &LayerConfig) -> Option<OperatorReport> {
+    if config.operator == Some(false) {
+        return None;
+    }
+
+    let api = OperatorApi::try_new(config, &mut NullReporter::default())
+        .await
+        .inspect_err(|error| tracing::warn!(%error, "Failed to fetch the operator details"))
+        .ok()??;
+    let spec = &api.operator().spec;
+
+    Some(OperatorReport {
+        version: spec.operator_version.to_string(),
+        protocol_version: spec.protocol_version.clone(),
+        features: spec.supported_features(),
+    })
+}
+
+/// Connects to the cluster like `mirrord exec` would, and measures the connection.
+async fn connection_report<P>(config: &LayerConfig, progress: &mut P) -> CliResult<ConnectionReport>
+where
+    P: Progress + Send + Sync,
+{
+    if !config.use_proxy {
+        remove_proxy_env();
+    }
+
+    let start = Instant::now();
+    let (connect_info, mut connection) =
+        create_and_connect(config, progress, &mut NullReporter::default()).await?;
+    let connect_time = start.elapsed();
+
+    let (kind, protocol_version) = match connect_info {
+        AgentConnectInfo::Operator(session) => ("operator", session.operator_protocol_version),
+        _ => (
+            "agent",
+            Some(MirrordExecution::get_agent_version(&mut connection).await?),
+        ),
+    };
+
+    let mut statistics: Vec<Duration> = Vec::with_capacity(PING_ITERATIONS);
+    for _ in 0..PING_ITERATIONS {
+        let start = Instant::now();
+        ping(&connection.sender, &mut connection.receiver).await?;
+        statistics.push(start.elapsed());
+    }
+
+    Ok(ConnectionReport {
+        kind,
+        capabilities: capabilities(protocol_version.as_ref()),
+        protocol_version: protocol_version.map(|version| version.to_string()),
+        connect_time_ms: connect_time.as_millis(),
+        ping_min_ms: statistics.iter().min().expect("never empty").as_millis(),
+        ping_max_ms: statistics.iter().max().expect("never empty").as_millis(),
+        ping_avg_ms: (statistics.iter().sum::<Duration>() / PING_ITERATIONS as u32).as_millis(),
+    })
+}
+
+/// Appends a file with `contents` to the archive.
+fn append_file<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        SystemTime::UNIX_EPOCH
+            .elapsed()
+            .unwrap_or_default()
+            .as_secs(),
+    );
+    header.set_cksum();
+
+    archive.append_data(&mut header, name, contents)
+}
+
+/// Handle the `mirrord report` command.
+#[tracing::instrument(level = Level::TRACE, ret)]
+pub(crate) async fn report_command(args: ReportArgs) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord report");
+
+    let mut cfg_context = ConfigContext::default();
+    let config = if let Some(path) = args.config_file.as_deref() {
+        LayerFileConfig::from_path(path)?.generate_config(&mut cfg_context)
+    } else {
+        LayerFileConfig::default().generate_config(&mut cfg_context)
+    }?;
+    let redactor = Redactor::new(&config.redaction).map_err(|error| ConfigError::InvalidValue {
+        name: "redaction.patterns",
+        provided: config
+            .redaction
+            .patterns
+            .as_deref()
+            .unwrap_or_default()
+            .join(", "),
+        error: Box::new(error),
+    })?;
+
+    let mut operator_progress = progress.subtask("checking operator");
+    let operator = operator_report(&config).await;
+    operator_progress.success(None);
+
+    let connection = if args.offline {
+        None
+    } else {
+        let mut connection_progress = progress.subtask("measuring connection");
+        let connection = connection_report(&config, &mut connection_progress)
+            .await
+            .map_err(|error| error.to_string());
+        match &connection {
+            Ok(..) => connection_progress.success(None),
+            Err(error) => connection_progress.failure(Some(error.as_str())),
+        }
+
+        Some(connection)
+    };
+
+    let report = Report {
+        cli_version: env!("CARGO_PKG_VERSION"),
+        protocol_version: mirrord_protocol::VERSION.to_string(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        target: config.target.path.as_ref().map(ToString::to_string),
+        target_namespace: config.target.namespace.clone(),
+        operator,
+        connection,
+        conflicts: preflight::find_conflicts(),
+    };
+
+    let mut config_json = serde_json::to_value(&config)?;
+    redactor.redact_json(&mut config_json);
+
+    let log = intproxy_log(&args, &config).and_then(|path| {
+        log_tail(&path, &redactor)
+            .inspect_err(|error| {
+                progress.warning(&format!(
+                    "failed to read intproxy log {}: {error}",
+                    path.display()
+                ))
+            })
+            .ok()
+    });
+
+    let output = args.output.unwrap_or_else(|| {
+        let timestamp = SystemTime::UNIX_EPOCH
+            .elapsed()
+            .unwrap_or_default()
+            .as_secs();
+        PathBuf::from(format!("mirrord-report-{timestamp}.tar.gz"))
+    });
+
+    let write_archive = || -> std::io::Result<()> {
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(&output)?,
+            Compression::default(),
+        ));
+
+        append_file(
+            &mut archive,
+            "report.json",
+            &serde_json::to_vec_pretty(&report)?,
+        )?;
+        append_file(
+            &mut archive,
+            "config.json",
+            &serde_json::to_vec_pretty(&config_json)?,
+        )?;
+        if let Some(log) = &log {
+            append_file(&mut archive, "intproxy.log", log.as_bytes())?;
+        }
+
+        archive.into_inner()?.finish()?;
+
+        Ok(())
+    };
+    write_archive().map_err(|error| CliError::ReportArchiveFailed(output.clone(), error))?;
+
+    progress.success(Some(&format!(
+        "report written to {}, please review it before sharing",
+        output.display()
+    )));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_by_version() {
+        assert!(capabilities(None).values().all(|supported| !supported));
+        assert!(capabilities(Some(&mirrord_protocol::VERSION))
+            .values()
+            .all(|supported| *supported));
+    }
+}
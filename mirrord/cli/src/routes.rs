@@ -0,0 +1,364 @@
+//! Checks of the HTTP filter against the routes that send traffic to the target, see
+//! [`check_target_routes`].
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use kube::Client;
+use mirrord_config::{
+    feature::network::incoming::http_filter::{HttpFilterConfig, InnerFilter},
+    LayerConfig, MIRRORD_CONFIG_FILE_ENV,
+};
+use mirrord_kube::{
+    api::kubernetes::{
+        create_kube_config,
+        gateway::{self, TargetRoute},
+    },
+    error::KubeApiError,
+    resolved::ResolvedTarget,
+};
+use mirrord_progress::Progress;
+use mirrord_sdk::control::sessions_dir;
+use tracing::{debug, warn, Level};
+
+/// Prefix of the files written by [`use_composed_config`].
+const COMPOSED_CONFIG_PREFIX: &str = "config-";
+
+/// Finds the [`TargetRoute`]s of the target and checks the HTTP filter against them.
+///
+/// 1. Warns when the filter uses a header that is set only by some of the routes;
+/// 2. When [`HttpFilterConfig::match_route_hosts`] is enabled, adds a `host` header filter that
+///    matches the hostnames of the routes.
+///
+/// Returns whether the `config` was changed, it has to be passed to the layer and the internal
+/// proxy in a file then, see [`use_composed_config`].
+///
+/// This is best effort, the routes are not found e.g. when the user is not allowed to list them.
+#[tracing::instrument(level = Level::TRACE, skip_all)]
+pub(super) async fn check_target_routes<P>(config: &mut LayerConfig, progress: &P) -> bool
+where
+    P: Progress + Send + Sync,
+{
+    let incoming = &config.feature.network.incoming;
+    if !incoming.is_steal() || !incoming.http_filter.is_filter_set() {
+        return false;
+    }
+
+    let routes = match find_target_routes(config).await {
+        Ok(routes) => routes,
+        Err(error) => {
+            debug!(%error, "Failed to find the routes of the target.");
+            return false;
+        }
+    };
+    debug!(?routes, "Found the routes of the target.");
+    if routes.is_empty() {
+        return false;
+    }
+
+    let http_filter = &config.feature.network.incoming.http_filter;
+    for header in filter_header_names(http_filter) {
+        let (setting, missing): (Vec<_>, Vec<_>) = routes
+            .iter()
+            .partition(|route| route.set_headers.contains(&header));
+        if setting.is_empty() || missing.is_empty() {
+            continue;
+        }
+
+        progress.warning(&format!(
+            "HTTP filter uses the `{header}` header, which is set by {} but not by {}. \
+            Requests that come through the latter will not be stolen, unless the client sets \
+            the header.",
+            route_names(&setting),
+            route_names(&missing),
+        ));
+    }
+
+    if !http_filter.match_route_hosts {
+        return false;
+    }
+
+    let Some(host_filter) = host_filter(&routes) else {
+        progress.warning(
+            "`match_route_hosts` is enabled, but some routes of the target accept any host, \
+            not adding the `host` header filter.",
+        );
+        return false;
+    };
+
+    if !add_host_filter(
+        &mut config.feature.network.incoming.http_filter,
+        host_filter,
+    ) {
+        progress.warning(
+            "`match_route_hosts` cannot be used together with `any_of` HTTP filter, \
+            not adding the `host` header filter.",
+        );
+        return false;
+    }
+
+    // The filters were moved into `all_of`, they must not be set again from the env.
+    std::env::remove_var("MIRRORD_HTTP_HEADER_FILTER");
+    std::env::remove_var("MIRRORD_HTTP_PATH_FILTER");
+
+    true
+}
+
+/// Writes the `config` changed by [`check_target_routes`] to a file in the [`sessions_dir`], and
+/// passes it to the layer and the internal proxy with [`MIRRORD_CONFIG_FILE_ENV`].
+///
+/// The CLI may `exec` into the user binary, so it can't remove the file itself. The internal
+/// proxy removes it when the session ends instead, see [`ComposedConfigFile`].
+pub(super) fn use_composed_config<P>(config: &LayerConfig, progress: &P) -> Option<PathBuf>
+where
+    P: Progress + Send + Sync,
+{
+    match write_composed_config(config) {
+        Ok(path) => {
+            std::env::set_var(MIRRORD_CONFIG_FILE_ENV, &path);
+            Some(path)
+        }
+        Err(error) => {
+            progress.warning(&format!(
+                "Failed to write the config with the `host` header filter: {error}"
+            ));
+            None
+        }
+    }
+}
+
+async fn find_target_routes(config: &LayerConfig) -> Result<Vec<TargetRoute>, KubeApiError> {
+    let Some(target) = config.target.path.as_ref() else {
+        return Ok(Vec::new());
+    };
+
+    let client = create_kube_config(config)
+        .await
+        .and_then(|config| Client::try_from(config).map_err(From::from))?;
+
+    let namespace = config.target.namespace.as_deref();
+    let target = ResolvedTarget::<false>::new(&client, target, namespace).await?;
+    let Some(labels) = gateway::pod_labels(&target) else {
+        return Ok(Vec::new());
+    };
+
+    gateway::target_routes(&client, namespace, labels).await
+}
+
+fn route_names(routes: &[&TargetRoute]) -> String {
+    routes
+        .iter()
+        .map(|route| route.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the lowercase names of the headers used in the header filters, skipping `host`.
+///
+/// Only filters that start with a plain header name are recognized, e.g. `^x-user: .+`.
+fn filter_header_names(filter: &HttpFilterConfig) -> BTreeSet<String> {
+    let inner = filter
+        .all_of
+        .iter()
+        .chain(&filter.any_of)
+        .flatten()
+        .filter_map(|filter| match filter {
+            InnerFilter::Header { header } => Some(header),
+            InnerFilter::Path { .. } => None,
+        });
+
+    filter
+        .header_filter
+        .iter()
+        .chain(inner)
+        .filter_map(|filter| {
+            let filter = filter.strip_prefix('^').unwrap_or(filter);
+            let filter = filter.strip_prefix("(?i)").unwrap_or(filter);
+            let (name, _) = filter.split_once(':')?;
+            let name = name.trim();
+
+            (!name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .then(|| name.to_lowercase())
+        })
+        .filter(|name| name != "host")
+        .collect()
+}
+
+/// Builds a `host` header filter that matches the hostnames of the given routes.
+///
+/// Returns [`None`] if any of the routes accepts any host.
+fn host_filter(routes: &[TargetRoute]) -> Option<String> {
+    if routes.iter().any(|route| route.hostnames.is_empty()) {
+        return None;
+    }
+
+    let hosts = routes
+        .iter()
+        .flat_map(|route| &route.hostnames)
+        .map(|hostname| match hostname.strip_prefix("*.") {
+            Some(domain) => format!("[^.:]+\\.{}", regex::escape(domain)),
+            None => regex::escape(hostname),
+        })
+        .collect::<BTreeSet<_>>();
+
+    Some(format!(
+        "^host: ({})(:[0-9]+)?$",
+        hosts.into_iter().collect::<Vec<_>>().join("|")
+    ))
+}
+
+/// Adds the `host` header filter to the given [`HttpFilterConfig`], moving the existing filter
+/// into [`HttpFilterConfig::all_of`].
+///
+/// Returns `false` when [`HttpFilterConfig::any_of`] is used.
+fn add_host_filter(filter: &mut HttpFilterConfig, host_filter: String) -> bool {
+    if filter.any_of.is_some() {
+        return false;
+    }
+
+    let existing = filter
+        .header_filter
+        .take()
+        .map(|header| InnerFilter::Header { header })
+        .or_else(|| {
+            filter
+                .path_filter
+                .take()
+                .map(|path| InnerFilter::Path { path })
+        });
+
+    filter
+        .all_of
+        .get_or_insert_with(Vec::new)
+        .extend(existing.into_iter().chain([InnerFilter::Header {
+            header: host_filter,
+        }]));
+
+    true
+}
+
+fn write_composed_config(config: &LayerConfig) -> io::Result<PathBuf> {
+    let dir = sessions_dir();
+    fs::create_dir_all(&dir)?;
+
+    let mut file = tempfile::Builder::new()
+        .prefix(COMPOSED_CONFIG_PREFIX)
+        .suffix(".json")
+        .tempfile_in(dir)?;
+    file.write_all(&serde_json::to_vec(config)?)?;
+
+    let (_, path) = file.keep()?;
+    Ok(path)
+}
+
+/// Config file written by [`use_composed_config`], removed when dropped.
+///
+/// Held by the internal proxy, which outlives all the layers of the session.
+pub(crate) struct ComposedConfigFile(PathBuf);
+
+impl ComposedConfigFile {
+    /// Takes over the file in [`MIRRORD_CONFIG_FILE_ENV`], if it was written by
+    /// [`use_composed_config`].
+    pub(crate) fn from_env() -> Option<Self> {
+        let path = PathBuf::from(std::env::var_os(MIRRORD_CONFIG_FILE_ENV)?);
+
+        let composed = path.parent()? == sessions_dir()
+            && path
+                .file_name()?
+                .to_str()?
+                .starts_with(COMPOSED_CONFIG_PREFIX);
+
+        composed.then_some(Self(path))
+    }
+}
+
+impl Drop for ComposedConfigFile {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.0) {
+            warn!(%error, path = %self.0.display(), "Failed to remove the composed config file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn route(name: &str, hostnames: &[&str]) -> TargetRoute {
+        TargetRoute {
+            name: name.into(),
+            hostnames: hostnames.iter().map(ToString::to_string).collect(),
+            set_headers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn header_names() {
+        let filter = HttpFilterConfig {
+            all_of: Some(vec![
+                InnerFilter::Header {
+                    header: "^X-User: .+".into(),
+                },
+                InnerFilter::Header {
+                    header: "(?i)host: api".into(),
+                },
+                InnerFilter::Header {
+                    header: "(x-a|x-b): 1".into(),
+                },
+                InnerFilter::Path {
+                    path: "^/api".into(),
+                },
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            filter_header_names(&filter).into_iter().collect::<Vec<_>>(),
+            ["x-user"]
+        );
+    }
+
+    #[test]
+    fn host_filter_from_routes() {
+        let routes = [
+            route("HTTPRoute/api", &["api.example.com", "*.dev.example.com"]),
+            route("Ingress/api", &["api.example.com"]),
+        ];
+        let filter = host_filter(&routes).unwrap();
+        assert_eq!(
+            filter,
+            r"^host: ([^.:]+\.dev\.example\.com|api\.example\.com)(:[0-9]+)?$"
+        );
+
+        let regex = regex::Regex::new(&filter).unwrap();
+        assert!(regex.is_match("host: api.example.com:8080"));
+        assert!(regex.is_match("host: user.dev.example.com"));
+        assert!(!regex.is_match("host: a.b.dev.example.com"));
+        assert!(!regex.is_match("host: api.example.com.evil"));
+
+        let mut http_filter = HttpFilterConfig {
+            header_filter: Some("^x-user: .+".into()),
+            ..Default::default()
+        };
+        assert!(add_host_filter(&mut http_filter, filter.clone()));
+        assert_eq!(http_filter.header_filter, None);
+        assert_eq!(
+            http_filter.all_of,
+            Some(vec![
+                InnerFilter::Header {
+                    header: "^x-user: .+".into()
+                },
+                InnerFilter::Header { header: filter },
+            ])
+        );
+
+        assert!(host_filter(&[route("Ingress/any", &[])]).is_none());
+    }
+}
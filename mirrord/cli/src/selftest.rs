@@ -0,0 +1,345 @@
+//! Health check of mirrord on this machine and in the cluster, see [`selftest_command`].
+//!
+//! The test process is this binary itself (the hidden `mirrord selftest-probe` command), started
+//! with `mirrord exec`, so the layer is loaded into it like into any other process. The probe
+//! exercises each feature and prints what it observed as JSON, which is then compared with the
+//! local machine to build the compatibility matrix.
+use std::{process::Stdio, time::Duration};
+
+use mirrord_config::MIRRORD_CONFIG_FILE_ENV;
+use mirrord_progress::{Progress, ProgressTracker, MIRRORD_PROGRESS_ENV};
+use prettytable::{row, Table};
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpStream, process::Command, time};
+use tracing::Level;
+
+use crate::{config::SelftestFormat, CliError, CliResult, SelftestArgs};
+
+/// File that the probe reads, it's read from the target by default.
+const PROBE_FILE: &str = "/etc/hostname";
+
+/// Cluster service that the probe resolves.
+const PROBE_DNS_NAME: &str = "kubernetes.default.svc";
+
+/// Timeout of each network check of the probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the probe observed from inside the mirrord session.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProbeReport {
+    /// Whether the layer library is mapped into the probe.
+    layer_loaded: bool,
+    /// `KUBERNETES_SERVICE_HOST`, set in every pod.
+    service_host: Option<String>,
+    /// `KUBERNETES_SERVICE_PORT`, set in every pod.
+    service_port: Option<String>,
+    /// Contents of [`PROBE_FILE`].
+    file: Result<String, String>,
+    /// Addresses of [`PROBE_DNS_NAME`].
+    dns: Result<Vec<String>, String>,
+    /// Connection to the `kubernetes` service.
+    outgoing: Result<(), String>,
+}
+
+/// One feature of the compatibility matrix.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct MatrixRow {
+    feature: &'static str,
+    passed: bool,
+    details: String,
+}
+
+impl MatrixRow {
+    fn new(feature: &'static str, result: Result<String, String>) -> Self {
+        let (passed, details) = match result {
+            Ok(details) => (true, details),
+            Err(details) => (false, details),
+        };
+
+        Self {
+            feature,
+            passed,
+            details,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn layer_loaded() -> bool {
+    std::fs::read_to_string("/proc/self/maps").is_ok_and(|maps| maps.contains("mirrord_layer"))
+}
+
+#[cfg(target_os = "macos")]
+fn layer_loaded() -> bool {
+    use std::ffi::CStr;
+
+    (0..unsafe { libc::_dyld_image_count() }).any(|index| {
+        let name = unsafe { libc::_dyld_get_image_name(index) };
+
+        !name.is_null()
+            && unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .contains("mirrord_layer")
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn layer_loaded() -> bool {
+    false
+}
+
+async fn probe_dns() -> Result<Vec<String>, String> {
+    let addresses = time::timeout(
+        PROBE_TIMEOUT,
+        tokio::net::lookup_host((PROBE_DNS_NAME, 443)),
+    )
+    .await
+    .map_err(|_| "timed out".to_string())?
+    .map_err(|error| error.to_string())?;
+
+    Ok(addresses.map(|address| address.ip().to_string()).collect())
+}
+
+async fn probe_outgoing(host: Option<&str>, port: Option<&str>) -> Result<(), String> {
+    let (Some(host), Some(port)) = (host, port) else {
+        return Err("the address of the `kubernetes` service is not in the environment".into());
+    };
+    let port = port.parse::<u16>().map_err(|error| error.to_string())?;
+
+    time::timeout(PROBE_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|error| error.to_string())?;
+
+    Ok(())
+}
+
+/// Handle `mirrord selftest-probe`, the test process of [`selftest_command`].
+///
+/// Prints the [`ProbeReport`] as a single JSON line.
+pub(crate) async fn selftest_probe() -> CliResult<()> {
+    let service_host = std::env::var("KUBERNETES_SERVICE_HOST").ok();
+    let service_port = std::env::var("KUBERNETES_SERVICE_PORT").ok();
+
+    let report = ProbeReport {
+        layer_loaded: layer_loaded(),
+        file: std::fs::read_to_string(PROBE_FILE)
+            .map(|contents| contents.trim().to_string())
+            .map_err(|error| error.to_string()),
+        dns: probe_dns().await,
+        outgoing: probe_outgoing(service_host.as_deref(), service_port.as_deref()).await,
+        service_host,
+        service_port,
+    };
+
+    println!("{}", serde_json::to_string(&report)?);
+
+    Ok(())
+}
+
+/// Builds the compatibility matrix from the `report` of the probe, comparing it with the local
+/// values of the service host and the contents of [`PROBE_FILE`].
+fn matrix(
+    report: ProbeReport,
+    local_service_host: Option<String>,
+    local_file: Option<String>,
+) -> Vec<MatrixRow> {
+    let layer = if report.layer_loaded {
+        Ok("loaded into the test process".to_string())
+    } else {
+        Err("not loaded into the test process".to_string())
+    };
+
+    let env = match report.service_host {
+        Some(host) if Some(&host) != local_service_host.as_ref() => {
+            Ok(format!("KUBERNETES_SERVICE_HOST={host}"))
+        }
+        Some(..) => Err("got the local environment, check `feature.env`".to_string()),
+        None => Err("remote variables are missing, check `feature.env`".to_string()),
+    };
+
+    let fs = match report.file {
+        Ok(contents) if Some(&contents) != local_file.as_ref() => {
+            Ok(format!("read {PROBE_FILE} ({contents})"))
+        }
+        Ok(..) => Err(format!("read the local {PROBE_FILE}, check `feature.fs`")),
+        Err(error) => Err(format!("reading {PROBE_FILE} failed: {error}")),
+    };
+
+    let dns = match report.dns {
+        Ok(addresses) if !addresses.is_empty() => Ok(format!(
+            "resolved {PROBE_DNS_NAME} to {}",
+            addresses.join(", ")
+        )),
+        Ok(..) => Err(format!("no addresses for {PROBE_DNS_NAME}")),
+        Err(error) => Err(format!(
+            "resolving {PROBE_DNS_NAME} failed: {error}, check `feature.network.dns`"
+        )),
+    };
+
+    let outgoing = report
+        .outgoing
+        .map(|()| "connected to the `kubernetes` service".to_string())
+        .map_err(|error| {
+            format!(
+                "connecting to the `kubernetes` service failed: {error}, check \
+                `feature.network.outgoing`"
+            )
+        });
+
+    vec![
+        MatrixRow::new("agent", Ok("the session started".to_string())),
+        MatrixRow::new("layer", layer),
+        MatrixRow::new("environment", env),
+        MatrixRow::new("file system", fs),
+        MatrixRow::new("dns", dns),
+        MatrixRow::new("outgoing traffic", outgoing),
+    ]
+}
+
+fn print_matrix(rows: &[MatrixRow]) {
+    let mut table = Table::new();
+    table.add_row(row!["FEATURE", "RESULT", "DETAILS"]);
+
+    for row in rows {
+        let result = if row.passed { "ok" } else { "failed" };
+        table.add_row(row![row.feature, result, row.details]);
+    }
+
+    table.printstd();
+}
+
+/// Runs the probe with `mirrord exec`, returning its report.
+async fn run_probe(args: &SelftestArgs) -> CliResult<ProbeReport> {
+    let mirrord = std::env::current_exe().map_err(CliError::CliPathError)?;
+
+    let mut command = Command::new(&mirrord);
+    command
+        .arg("exec")
+        .arg("--")
+        .arg(&mirrord)
+        .arg("selftest-probe")
+        .envs(args.target.as_env_vars()?)
+        .env(MIRRORD_PROGRESS_ENV, "off")
+        .env("MIRRORD_CHECK_VERSION", "false")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true);
+
+    if let Some(config_file) = &args.config_file {
+        // Canonical path, in case the probe runs in a different working directory.
+        let full_path = std::fs::canonicalize(config_file)
+            .map_err(|e| CliError::CanonicalizeConfigPathFailed(config_file.clone(), e))?;
+        command.env(MIRRORD_CONFIG_FILE_ENV, full_path);
+    }
+
+    let output = command.output().await.map_err(|error| {
+        CliError::SelftestFailed(format!("failed to run mirrord exec: {error}"))
+    })?;
+
+    let report = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str::<ProbeReport>(line).ok());
+
+    report.ok_or_else(|| {
+        CliError::SelftestFailed(format!(
+            "the test process did not report its results ({})",
+            output.status
+        ))
+    })
+}
+
+/// Handle `mirrord selftest`.
+///
+/// Prints the compatibility matrix, and fails when a feature doesn't work.
+#[tracing::instrument(level = Level::TRACE, ret)]
+pub(crate) async fn selftest_command(args: SelftestArgs) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord selftest");
+
+    let mut probe_progress = progress.subtask("running the test process");
+    let report = run_probe(&args).await.inspect_err(|_| {
+        probe_progress.failure(Some("the test process failed"));
+    })?;
+    probe_progress.success(Some("the test process finished"));
+
+    let local_service_host = std::env::var("KUBERNETES_SERVICE_HOST").ok();
+    let local_file = std::fs::read_to_string(PROBE_FILE)
+        .ok()
+        .map(|contents| contents.trim().to_string());
+    let rows = matrix(report, local_service_host, local_file);
+
+    let failed = rows.iter().filter(|row| !row.passed).count();
+    let summary = format!("{failed} of {} features failed", rows.len());
+    if failed == 0 {
+        progress.success(Some("all features work"));
+    } else {
+        progress.failure(Some(summary.as_str()));
+    }
+
+    match args.output {
+        SelftestFormat::Table => print_matrix(&rows),
+        SelftestFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+    }
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(CliError::SelftestFailed(summary))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report() -> ProbeReport {
+        ProbeReport {
+            layer_loaded: true,
+            service_host: Some("10.96.0.1".to_string()),
+            service_port: Some("443".to_string()),
+            file: Ok("app-7d4b9c".to_string()),
+            dns: Ok(vec!["10.96.0.1".to_string()]),
+            outgoing: Ok(()),
+        }
+    }
+
+    fn failed(rows: &[MatrixRow]) -> Vec<&'static str> {
+        rows.iter()
+            .filter(|row| !row.passed)
+            .map(|row| row.feature)
+            .collect()
+    }
+
+    #[test]
+    fn all_features_pass() {
+        let rows = matrix(report(), None, Some("laptop".to_string()));
+
+        assert_eq!(failed(&rows), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn local_values_fail() {
+        let rows = matrix(
+            report(),
+            Some("10.96.0.1".to_string()),
+            Some("app-7d4b9c".to_string()),
+        );
+
+        assert_eq!(failed(&rows), ["environment", "file system"]);
+    }
+
+    #[test]
+    fn failed_checks() {
+        let report = ProbeReport {
+            layer_loaded: false,
+            dns: Err("timed out".to_string()),
+            outgoing: Err("connection refused".to_string()),
+            ..report()
+        };
+
+        let rows = matrix(report, None, None);
+
+        assert_eq!(failed(&rows), ["layer", "dns", "outgoing traffic"]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Runtime feature toggles of the running sessions, see [`toggle_command`].
+//!
+//! Each internal proxy started by the CLI accepts
+//! [`ControlRequest`]s on a localhost socket, and advertises it in a
+//! [`SessionFile`](mirrord_sdk::control::SessionFile), so that `mirrord toggle` can find the
+//! running sessions.
+use std::fs;
+
+use mirrord_intproxy_protocol::control::{ControlRequest, FeatureToggle};
+use mirrord_sdk::control::{
+    read_sessions, send_request, sessions_dir, SessionInfo, CONTROL_TIMEOUT,
+};
+use tokio::{net::TcpStream, time};
+use tracing::Level;
+
+use crate::{CliError, CliResult, ToggleAction, ToggleArgs};
+
+/// Picks the session to toggle, the one with the given `pid`, or the only one running.
+///
+/// Files of the sessions whose internal proxy no longer accepts connections are removed.
+async fn find_session(pid: Option<u32>) -> CliResult<SessionInfo> {
+    let mut running = Vec::new();
+
+    for (path, info) in read_sessions(&sessions_dir()) {
+        if pid.is_some_and(|pid| pid != info.pid) {
+            continue;
+        }
+
+        match time::timeout(CONTROL_TIMEOUT, TcpStream::connect(info.control_address)).await {
+            Ok(Ok(..)) => running.push(info),
+            _ => {
+                tracing::debug!(?info, "Removing the file of a session that is not running");
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    match (running.pop(), running.is_empty()) {
+        (Some(session), true) => Ok(session),
+        (None, _) => Err(CliError::ToggleSessionNotFound(pid)),
+        (Some(session), false) => {
+            let sessions = running
+                .into_iter()
+                .chain(std::iter::once(session))
+                .map(|session| match session.target {
+                    Some(target) => format!("{} ({target})", session.pid),
+                    None => session.pid.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(CliError::ToggleSessionAmbiguous(sessions))
+        }
+    }
+}
+
+impl From<ToggleAction> for ControlRequest {
+    fn from(action: ToggleAction) -> Self {
+        match action {
+            ToggleAction::Status => Self::Status,
+            ToggleAction::PauseSteal => Self::Toggle(FeatureToggle::PauseSteal),
+            ToggleAction::ResumeSteal => Self::Toggle(FeatureToggle::ResumeSteal),
+            ToggleAction::StealToMirror => Self::Toggle(FeatureToggle::StealToMirror),
+            ToggleAction::DisableFsWrites => Self::Toggle(FeatureToggle::DisableFsWrites),
+            ToggleAction::EnableFsWrites => Self::Toggle(FeatureToggle::EnableFsWrites),
+            ToggleAction::RaiseLogLevel => Self::Toggle(FeatureToggle::RaiseLogLevel),
+            ToggleAction::LowerLogLevel => Self::Toggle(FeatureToggle::LowerLogLevel),
+            ToggleAction::EnableProtocolTracing => {
+                Self::Toggle(FeatureToggle::EnableProtocolTracing)
+            }
+            ToggleAction::DisableProtocolTracing => {
+                Self::Toggle(FeatureToggle::DisableProtocolTracing)
+            }
+        }
+    }
+}
+
+/// Handle the `mirrord toggle` command, prints the status of the session after the change.
+#[tracing::instrument(level = Level::TRACE, ret)]
+pub(crate) async fn toggle_command(args: ToggleArgs) -> CliResult<()> {
+    let session = find_session(args.session).await?;
+
+    let status = time::timeout(
+        CONTROL_TIMEOUT,
+        send_request(session.control_address, args.action.into()),
+    )
+    .await
+    .map_err(|_| CliError::ToggleFailed(session.pid, "timed out".to_string()))?
+    .map_err(|error| CliError::ToggleFailed(session.pid, error.to_string()))?;
+
+    println!("session: {}", session.pid);
+    if let Some(target) = session.target {
+        println!("target: {target}");
+    }
+    if let Some(offset_ms) = session.clock_offset_ms {
+        println!("clock offset: {offset_ms:+}ms");
+    }
+    if status.read_only {
+        println!("mode: read-only");
+    }
+    println!("steal: {}", status.steal);
+    println!(
+        "fs writes: {}",
+        if status.fs_writes {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    println!("log level: {}", status.log.verbosity);
+    println!(
+        "protocol tracing: {}",
+        if status.log.protocol_tracing {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    if let Some(usage) = status.fd_usage {
+        let limit = usage
+            .limit
+            .map(|limit| format!(" (limit {limit})"))
+            .unwrap_or_default();
+        println!(
+            "remote fds: {}{limit}, {} files, {} directories, {} sockets",
+            usage.total(),
+            usage.files,
+            usage.dirs,
+            usage.sockets
+        );
+    }
+
+    Ok(())
+}
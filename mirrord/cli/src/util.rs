@@ -1,5 +1,8 @@
-use std::{io, io::Write, net::SocketAddr};
+#[cfg(unix)]
+use std::io::Write;
+use std::{io, net::SocketAddr};
 
+#[cfg(unix)]
 use nix::libc;
 use tokio::net::TcpListener;
 use tracing::Level;
@@ -21,6 +24,7 @@ pub(crate) fn remove_proxy_env() {
 
 /// Used to pipe std[in/out/err] to "/dev/null" to prevent any printing to prevent any unwanted
 /// side effects
+#[cfg(unix)]
 unsafe fn redirect_fd_to_dev_null(fd: libc::c_int) {
     let devnull_fd = libc::open(b"/dev/null\0" as *const [u8; 10] as _, libc::O_RDWR);
     libc::dup2(devnull_fd, fd);
@@ -30,6 +34,7 @@ unsafe fn redirect_fd_to_dev_null(fd: libc::c_int) {
 /// Create a new session for the proxy process, detaching from the original terminal.
 /// This makes the process not to receive signals from the "mirrord" process or it's parent
 /// terminal fixes some side effects such as <https://github.com/metalbear-co/mirrord/issues/1232>
+#[cfg(unix)]
 pub(crate) unsafe fn detach_io() -> Result<(), nix::Error> {
     nix::unistd::setsid()?;
 
@@ -52,7 +57,7 @@ pub(crate) unsafe fn detach_io() -> Result<(), nix::Error> {
 #[tracing::instrument(level = Level::TRACE, ret)]
 pub(crate) fn create_listen_socket(addr: SocketAddr) -> io::Result<TcpListener> {
     let socket = socket2::Socket::new(
-        socket2::Domain::IPV4,
+        socket2::Domain::for_address(addr),
         socket2::Type::STREAM,
         Some(socket2::Protocol::TCP),
     )?;
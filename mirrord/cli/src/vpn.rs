@@ -29,14 +29,12 @@ pub async fn vpn_command(args: VpnArgs) -> CliResult<()> {
     let mut config = LayerConfig::from_env()?;
     config.agent.privileged = true;
 
-    let client = create_kube_config(
-        config.accept_invalid_certificates,
-        config.kubeconfig.clone(),
-        config.kube_context.clone(),
-    )
-    .await
-    .and_then(|config| kube::Client::try_from(config).map_err(From::from))
-    .map_err(|error| CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed))?;
+    let client = create_kube_config(&config)
+        .await
+        .and_then(|config| kube::Client::try_from(config).map_err(From::from))
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
 
     let mut sub_progress = progress.subtask("fetching vpn info");
 
@@ -0,0 +1,130 @@
+//! Execution bridge for Windows, where the layer can't be injected into native processes.
+//!
+//! The user binary is executed inside WSL with `wsl.exe --exec`, and the linux layer (embedded in
+//! the Windows build, see `MIRRORD_LAYER_FILE` in `build.rs`) is injected into it with
+//! `LD_PRELOAD`. The internal proxy runs natively on Windows, and the layer reaches it on
+//! `127.0.0.1`, which requires WSL to run with `networkingMode=mirrored` (see `README.md`).
+use std::collections::HashMap;
+
+use tokio::process::Command;
+use tracing::Level;
+
+use crate::{error::CliError, execution::INJECTION_ENV_VAR, CliResult};
+
+/// Variable that lists the environment variables shared from Windows with WSL processes.
+const WSLENV: &str = "WSLENV";
+
+/// Environment variables that hold a Windows path, which WSL has to translate (`/p` flag).
+const PATH_ENV_VARS: &[&str] = &[INJECTION_ENV_VAR, mirrord_config::MIRRORD_CONFIG_FILE_ENV];
+
+/// Builds the value of [`WSLENV`], so that every variable in `env_vars` is shared with the WSL
+/// process.
+///
+/// Entries of the user's [`WSLENV`] are kept, unless they share a variable from `env_vars`, which
+/// needs our flags.
+fn wslenv(user_wslenv: Option<&str>, env_vars: &HashMap<String, String>) -> String {
+    let user_entries = user_wslenv
+        .into_iter()
+        .flat_map(|user_wslenv| user_wslenv.split(':'))
+        .filter(|entry| {
+            let name = entry.split_once('/').map_or(*entry, |(name, _)| name);
+            !name.is_empty() && !env_vars.contains_key(name)
+        })
+        .map(ToString::to_string);
+
+    let mut names = env_vars.keys().collect::<Vec<_>>();
+    names.sort();
+    let shared = names.into_iter().map(|name| {
+        if PATH_ENV_VARS.contains(&name.as_str()) {
+            format!("{name}/p")
+        } else {
+            name.clone()
+        }
+    });
+
+    user_entries.chain(shared).collect::<Vec<_>>().join(":")
+}
+
+/// Runs `binary` inside the default WSL distribution, with the mirrord `env_vars` shared with it.
+///
+/// Only `env_vars` are passed, as the rest of the Windows environment (e.g. `PATH`) makes no
+/// sense in WSL. Returns the exit code of the process.
+#[tracing::instrument(level = Level::TRACE, ret)]
+pub(crate) async fn exec_in_wsl(
+    binary: &str,
+    binary_args: &[String],
+    env_vars: HashMap<String, String>,
+) -> CliResult<i32> {
+    let wslenv = wslenv(std::env::var(WSLENV).ok().as_deref(), &env_vars);
+
+    let status = Command::new("wsl.exe")
+        .arg("--exec")
+        .arg(binary)
+        .args(binary_args)
+        .envs(env_vars)
+        .env(WSLENV, wslenv)
+        .status()
+        .await
+        .map_err(|error| CliError::WslExecFailed(binary.to_string(), error))?;
+
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_vars(names: &[&str]) -> HashMap<String, String> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), "value".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn paths_are_translated() {
+        let env_vars = env_vars(&[
+            INJECTION_ENV_VAR,
+            mirrord_config::MIRRORD_CONFIG_FILE_ENV,
+            "MIRRORD_CONNECT_TCP",
+        ]);
+
+        assert_eq!(
+            wslenv(None, &env_vars),
+            format!(
+                "{INJECTION_ENV_VAR}/p:{}/p:MIRRORD_CONNECT_TCP",
+                mirrord_config::MIRRORD_CONFIG_FILE_ENV
+            )
+        );
+    }
+
+    #[test]
+    fn merged_with_user_wslenv() {
+        let env_vars = env_vars(&[INJECTION_ENV_VAR, "MIRRORD_CONNECT_TCP"]);
+
+        assert_eq!(
+            wslenv(Some("GOPATH/l:USERPROFILE/pu"), &env_vars),
+            format!("GOPATH/l:USERPROFILE/pu:{INJECTION_ENV_VAR}/p:MIRRORD_CONNECT_TCP")
+        );
+        assert_eq!(
+            wslenv(Some(""), &env_vars),
+            format!("{INJECTION_ENV_VAR}/p:MIRRORD_CONNECT_TCP")
+        );
+    }
+
+    /// Our flags win, the variable is not listed twice.
+    #[test]
+    fn user_entries_of_shared_variables_replaced() {
+        let env_vars = env_vars(&[INJECTION_ENV_VAR, "MIRRORD_CONNECT_TCP"]);
+
+        assert_eq!(
+            wslenv(
+                Some(&format!(
+                    "{INJECTION_ENV_VAR}/u:MIRRORD_CONNECT_TCP:GOPATH/l"
+                )),
+                &env_vars
+            ),
+            format!("GOPATH/l:{INJECTION_ENV_VAR}/p:MIRRORD_CONNECT_TCP")
+        );
+    }
+}
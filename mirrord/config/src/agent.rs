@@ -186,6 +186,10 @@ pub struct AgentConfig {
     /// [ephemeral container](https://kubernetes.io/docs/concepts/workloads/pods/ephemeral-containers/)
     ///
     /// Defaults to `false`.
+    ///
+    /// The agent always runs as an ephemeral container when the target runs in a sandboxed
+    /// runtime (gVisor or Kata Containers, detected from the target's `runtimeClassName`), as
+    /// it cannot capture the traffic of these pods from the node.
     #[config(env = "MIRRORD_EPHEMERAL_CONTAINER", default = false)]
     pub ephemeral: bool,
 
@@ -301,6 +305,35 @@ pub struct AgentConfig {
     #[config(default = false)]
     pub nftables: bool,
 
+    /// ### agent.check_network_policies {#agent-check_network_policies}
+    ///
+    /// Before creating the agent, look for NetworkPolicies that block the egress of the target
+    /// (or of the agent, when running targetless) to the cluster DNS or to the destinations in
+    /// [`feature.network.outgoing.filter.remote`](#feature.network.outgoing.filter), and warn
+    /// about them.
+    ///
+    /// Requires permission to list NetworkPolicies in the namespace, the check is skipped
+    /// otherwise.
+    ///
+    /// Once the agent is running, it also tries to connect to the TCP destinations from the
+    /// network of the target, and the ones it can't reach are reported.
+    ///
+    /// Defaults to `true`.
+    #[config(env = "MIRRORD_AGENT_CHECK_NETWORK_POLICIES", default = true)]
+    pub check_network_policies: bool,
+
+    /// ### agent.pool {#agent-pool}
+    ///
+    /// When using the operator, claim a pre-warmed agent from a `MirrordAgentPool` (if the
+    /// cluster has one that matches the session), so that the session starts without waiting
+    /// for a new agent to be scheduled.
+    ///
+    /// Set to `false` to always get a new agent, e.g. when testing a custom agent image.
+    ///
+    /// Defaults to `true`.
+    #[config(env = "MIRRORD_AGENT_POOL", default = true)]
+    pub pool: bool,
+
     /// ### agent.dns {#agent-dns}
     #[config(nested)]
     pub dns: AgentDnsConfig,
@@ -452,6 +485,7 @@ impl MirrordConfig for AgentImageFileConfig {
 impl CollectAnalytics for &AgentConfig {
     fn collect_analytics(&self, analytics: &mut mirrord_analytics::Analytics) {
         analytics.add("ephemeral", self.ephemeral);
+        analytics.add("pool", self.pool);
     }
 }
 
@@ -484,13 +518,16 @@ pub struct AgentDnsConfig {
     /// ### agent.dns.timeout {#agent-dns-timeout}
     ///
     /// When agent resolves DNS, how long to wait for a response before timeout
-    /// By default this is set to 1 (in the agent).
+    /// By default, the agent uses the `timeout` option of the target's `resolv.conf`, or 1 when
+    /// it's not set there.
     /// If the value is too high, it might cause internal proxy to timeout and exit.
     pub timeout: Option<u32>,
 
     /// ### agent.dns.attempts {#agent-dns-attempts}
     ///
     /// When agent resolves DNS, how many attempts before failing.
+    /// By default, the agent uses the `attempts` option of the target's `resolv.conf`, or 1 when
+    /// it's not set there.
     /// If the value is too high, it might cause internal proxy to timeout and exit.
     pub attempts: Option<u32>,
 }
@@ -0,0 +1,254 @@
+use std::{collections::HashSet, net::IpAddr, str::FromStr};
+
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    feature::network::incoming::IncomingMode,
+    target::{make_simple_target_custom_schema, Target},
+    util::string_or_struct_option,
+    LayerConfig,
+};
+
+/// <!--${internal}-->
+/// Additional cluster that this session connects to, see [`clusters`](#root-clusters).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClusterConfig {
+    /// <!--${internal}-->
+    /// Identifies the cluster in the logs and errors.
+    pub name: String,
+
+    /// <!--${internal}-->
+    /// Context from the kubeconfig that points to the cluster.
+    ///
+    /// Defaults to the root [`kube_context`](#root-kube_context).
+    pub kube_context: Option<String>,
+
+    /// <!--${internal}-->
+    /// Path to the kubeconfig that contains the `kube_context`.
+    ///
+    /// Defaults to the root [`kubeconfig`](#root-kubeconfig).
+    pub kubeconfig: Option<String>,
+
+    /// <!--${internal}-->
+    /// Target in the cluster, e.g. `"deployment/service-b"`.
+    ///
+    /// Defaults to a targetless agent.
+    #[serde(default, deserialize_with = "string_or_struct_option")]
+    #[schemars(schema_with = "make_simple_target_custom_schema")]
+    pub target: Option<Target>,
+
+    /// <!--${internal}-->
+    /// Namespace of the `target`.
+    pub namespace: Option<String>,
+
+    /// <!--${internal}-->
+    /// Outgoing traffic that goes through the cluster, see [`ClusterRoute`].
+    pub outgoing: Vec<String>,
+}
+
+impl ClusterConfig {
+    /// Parses the [`ClusterConfig::outgoing`] rules.
+    pub fn routes(&self) -> Result<Vec<ClusterRoute>, ClusterVerificationError> {
+        self.outgoing
+            .iter()
+            .map(|rule| {
+                rule.parse()
+                    .map_err(|error| ClusterVerificationError::InvalidRoute {
+                        cluster: self.name.clone(),
+                        rule: rule.clone(),
+                        error,
+                    })
+            })
+            .collect()
+    }
+
+    /// Builds the config used to connect to this cluster, out of the config of the session.
+    ///
+    /// Only the outgoing traffic and DNS go through the additional clusters, so the incoming
+    /// traffic and the features that modify the target are disabled.
+    pub fn layer_config(&self, config: &LayerConfig) -> LayerConfig {
+        let mut config = config.clone();
+
+        if let Some(kube_context) = &self.kube_context {
+            config.kube_context = Some(kube_context.clone());
+        }
+        if let Some(kubeconfig) = &self.kubeconfig {
+            config.kubeconfig = Some(kubeconfig.clone());
+        }
+        config.target.path = self.target.clone();
+        config.target.namespace = self.namespace.clone();
+        config.feature.network.incoming.mode = IncomingMode::Off;
+        config.feature.copy_target.enabled = false;
+        config.feature.copy_target.scale_down = false;
+        config.feature.split_queues = Default::default();
+        config.clusters.clear();
+
+        config
+    }
+
+    /// Verifies the [`ClusterConfig`]s of the session.
+    pub fn verify(clusters: &[Self]) -> Result<(), ClusterVerificationError> {
+        let mut names = HashSet::new();
+
+        for cluster in clusters {
+            if !names.insert(cluster.name.as_str()) {
+                return Err(ClusterVerificationError::DuplicateName(
+                    cluster.name.clone(),
+                ));
+            }
+
+            if cluster.outgoing.is_empty() {
+                return Err(ClusterVerificationError::NoRoutes(cluster.name.clone()));
+            }
+
+            cluster.routes()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Rule from [`ClusterConfig::outgoing`], matching the outgoing traffic that goes through a
+/// cluster.
+///
+/// Parsed from:
+/// - a host name, e.g. `"service-b.default.svc.cluster.local"`;
+/// - a host name wildcard, e.g. `"*.cluster.local"`, which matches the subdomains;
+/// - an IP address or a subnet, e.g. `"10.96.0.0/12"`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClusterRoute {
+    Host(String),
+    /// Holds the suffix with the leading dot, e.g. `".cluster.local"`.
+    HostSuffix(String),
+    Subnet(IpNet),
+}
+
+impl ClusterRoute {
+    /// Whether the host name passed to `getaddrinfo` matches this rule.
+    pub fn matches_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        match self {
+            Self::Host(name) => host == *name,
+            Self::HostSuffix(suffix) => host.ends_with(suffix.as_str()),
+            Self::Subnet(..) => false,
+        }
+    }
+
+    /// Whether the address of an outgoing connection matches this rule.
+    pub fn matches_ip(&self, ip: IpAddr) -> bool {
+        match self {
+            Self::Subnet(subnet) => subnet.contains(&ip),
+            Self::Host(..) | Self::HostSuffix(..) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ClusterRouteParseError {
+    #[error("the rule is empty")]
+    Empty,
+    #[error("`{0}` is not a valid host name")]
+    InvalidHost(String),
+}
+
+impl FromStr for ClusterRoute {
+    type Err = ClusterRouteParseError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim().trim_end_matches('.');
+        if rule.is_empty() {
+            return Err(ClusterRouteParseError::Empty);
+        }
+
+        if let Ok(subnet) = rule.parse::<IpNet>() {
+            return Ok(Self::Subnet(subnet));
+        }
+
+        if let Ok(ip) = rule.parse::<IpAddr>() {
+            return Ok(Self::Subnet(ip.into()));
+        }
+
+        let (suffix, name) = match rule.strip_prefix("*.") {
+            Some(name) => (true, name),
+            None => (false, rule),
+        };
+
+        let valid = name.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+        if !valid {
+            return Err(ClusterRouteParseError::InvalidHost(rule.to_string()));
+        }
+
+        let name = name.to_ascii_lowercase();
+        Ok(if suffix {
+            Self::HostSuffix(format!(".{name}"))
+        } else {
+            Self::Host(name)
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ClusterVerificationError {
+    #[error("cluster name `{0}` is used more than once")]
+    DuplicateName(String),
+    #[error("cluster `{0}` has no `outgoing` rules, no traffic would go through it")]
+    NoRoutes(String),
+    #[error("cluster `{cluster}` has an invalid `outgoing` rule `{rule}`: {error}")]
+    InvalidRoute {
+        cluster: String,
+        rule: String,
+        error: ClusterRouteParseError,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use rstest::rstest;
+
+    use super::{ClusterRoute, ClusterRouteParseError};
+
+    #[rstest]
+    #[case("service-b.default.svc", "service-b.default.svc", true)]
+    #[case("Service-B.default.svc.", "service-b.default.svc", true)]
+    #[case("service-b.default.svc", "service-a.default.svc", false)]
+    #[case("*.cluster.local", "a.b.cluster.local", true)]
+    #[case("*.cluster.local", "cluster.local", false)]
+    #[case("*.cluster.local", "notcluster.local", false)]
+    #[case("10.0.0.0/8", "10.0.0.1", false)]
+    fn route_matches_host(#[case] rule: &str, #[case] host: &str, #[case] matches: bool) {
+        let route: ClusterRoute = rule.parse().unwrap();
+        assert_eq!(route.matches_host(host), matches);
+    }
+
+    #[rstest]
+    #[case("10.96.0.0/12", "10.100.1.2", true)]
+    #[case("10.96.0.0/12", "10.112.0.1", false)]
+    #[case("10.96.0.10", "10.96.0.10", true)]
+    #[case("fd00::/8", "fd12::1", true)]
+    #[case("*.cluster.local", "10.96.0.10", false)]
+    fn route_matches_ip(#[case] rule: &str, #[case] ip: IpAddr, #[case] matches: bool) {
+        let route: ClusterRoute = rule.parse().unwrap();
+        assert_eq!(route.matches_ip(ip), matches);
+    }
+
+    #[rstest]
+    #[case("", ClusterRouteParseError::Empty)]
+    #[case("*.", ClusterRouteParseError::InvalidHost("*".into()))]
+    #[case("a..b", ClusterRouteParseError::InvalidHost("a..b".into()))]
+    #[case("10.0.0.0/33", ClusterRouteParseError::InvalidHost("10.0.0.0/33".into()))]
+    fn route_parse_error(#[case] rule: &str, #[case] error: ClusterRouteParseError) {
+        assert_eq!(rule.parse::<ClusterRoute>().unwrap_err(), error);
+    }
+}
@@ -7,7 +7,9 @@ use std::error::Error;
 
 use thiserror::Error;
 
-use crate::feature::split_queues::QueueSplittingVerificationError;
+use crate::{
+    cluster::ClusterVerificationError, feature::split_queues::QueueSplittingVerificationError,
+};
 
 /// <!--${internal}-->
 /// Error that would be returned from [MirrordConfig::generate_config]
@@ -76,6 +78,9 @@ pub enum ConfigError {
 
     #[error("Queue splitting config is invalid: {0}")]
     QueueSplittingVerificationError(#[from] QueueSplittingVerificationError),
+
+    #[error("Clusters config is invalid: {0}")]
+    ClusterVerificationError(#[from] ClusterVerificationError),
 }
 
 impl From<tera::Error> for ConfigError {
@@ -47,4 +47,16 @@ pub struct ContainerConfig {
     /// Defaults to `"/opt/mirrord/lib/libmirrord_layer.so"`.
     #[config(default = PathBuf::from("/opt/mirrord/lib/libmirrord_layer.so"))]
     pub cli_image_lib_path: PathBuf,
+
+    /// ### container.cli_image_musl_lib_path {#container-cli_image_musl_lib_path}
+    ///
+    /// Path of the mirrord-layer lib compiled for musl libc inside the specified mirrord-cli
+    /// image.
+    ///
+    /// Used instead of [`cli_image_lib_path`](#container-cli_image) when the image of the
+    /// container is based on musl libc (e.g. Alpine).
+    ///
+    /// Defaults to `"/opt/mirrord/lib/libmirrord_layer_musl.so"`.
+    #[config(default = PathBuf::from("/opt/mirrord/lib/libmirrord_layer_musl.so"))]
+    pub cli_image_musl_lib_path: PathBuf,
 }
@@ -58,6 +58,19 @@ pub struct ExperimentalConfig {
     /// Uses /dev/null for creating local fake files (should be better than using /tmp)
     #[config(default = true)]
     pub use_dev_null: bool,
+
+    /// ### _experimental_ read_whole_max_size {#experimental-read_whole_max_size}
+    ///
+    /// Remote files up to this size (in bytes) that are opened read-only are read whole when
+    /// they're opened, in a single round trip to the agent, and then read locally. Speeds up apps
+    /// that read many small files (certificates, tokens, configuration) at startup.
+    ///
+    /// Changes made to such a file in the target after it's opened are not seen. Set to `0` to
+    /// disable it, e.g. for an app that keeps polling a small file.
+    ///
+    /// Defaults to `65536`, can be set with `MIRRORD_READ_WHOLE_MAX_SIZE`.
+    #[config(env = "MIRRORD_READ_WHOLE_MAX_SIZE", default = 65536)]
+    pub read_whole_max_size: u64,
 }
 
 impl CollectAnalytics for &ExperimentalConfig {
@@ -68,5 +81,6 @@ impl CollectAnalytics for &ExperimentalConfig {
         analytics.add("enable_exec_hooks_linux", self.enable_exec_hooks_linux);
         analytics.add("hide_ipv6_interfaces", self.hide_ipv6_interfaces);
         analytics.add("disable_reuseaddr", self.disable_reuseaddr);
+        analytics.add("read_whole_files", self.read_whole_max_size > 0);
     }
 }
@@ -3,13 +3,17 @@ use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::Serialize;
 
-use self::{copy_target::CopyTargetConfig, env::EnvConfig, fs::FsConfig, network::NetworkConfig};
+use self::{
+    copy_target::CopyTargetConfig, env::EnvConfig, fs::FsConfig, network::NetworkConfig,
+    output::OutputMode,
+};
 use crate::{config::source::MirrordConfigSource, feature::split_queues::SplitQueuesConfig};
 
 pub mod copy_target;
 pub mod env;
 pub mod fs;
 pub mod network;
+pub mod output;
 pub mod split_queues;
 
 /// Controls mirrord features.
@@ -60,7 +64,8 @@ pub mod split_queues;
 ///       "dns": false
 ///     },
 ///     "copy_target": false,
-///     "hostname": true
+///     "hostname": true,
+///     "output": "tee"
 ///   }
 /// }
 /// ```
@@ -98,6 +103,25 @@ pub struct FeatureConfig {
     #[config(default = true)]
     pub hostname: bool,
 
+    /// ## feature.output {#feature-output}
+    ///
+    /// Where the stdout and stderr of the local process go.
+    ///
+    /// Can be set to either `"local"`, `"tee"` or `"remote"`.
+    ///
+    /// - `"local"`: The output is only written locally;
+    /// - `"tee"`: The output is written locally, and also to the stdout/stderr of the target, so
+    ///   that it lands in the target container's log stream (e.g. `kubectl logs`), for log
+    ///   collectors that read it;
+    /// - `"remote"`: The output is only written to the stdout/stderr of the target.
+    ///
+    /// Only output written with `write` is sent to the target, and only when the agent supports
+    /// it. Running without a target (`targetless` mode), the output is only written locally.
+    ///
+    /// Defaults to `"local"`.
+    #[config(env = "MIRRORD_OUTPUT_MODE", default)]
+    pub output: OutputMode,
+
     /// ## feature.split_queues {#feature-split_queues}
     ///
     /// Define filters to split queues by, and make your local application consume only messages
@@ -116,6 +140,7 @@ impl CollectAnalytics for &FeatureConfig {
         analytics.add("network", &self.network);
         analytics.add("copy_target", &self.copy_target);
         analytics.add("hostname", self.hostname);
+        analytics.add("output", &self.output);
         analytics.add("split_queues", &self.split_queues);
     }
 }
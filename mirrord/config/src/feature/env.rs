@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 
+use fancy_regex::Regex;
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, Result},
+    config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError, Result},
     util::{MirrordToggleableConfig, VecOrSingle},
 };
 
@@ -27,7 +28,10 @@ use crate::{
 ///       "override": {
 ///         "DATABASE_CONNECTION": "db://localhost:7777/my-db",
 ///         "LOCAL_BEAR": "panda"
-///       }
+///       },
+///       "rewrite": [
+///         { "pattern": "redis\\.prod\\.svc", "replace": "localhost" }
+///       ]
 ///     }
 ///   }
 /// }
@@ -91,6 +95,84 @@ pub struct EnvConfig {
     /// This is case insensitive, meaning if you'd put `AWS_PROFILE` it'd unset both `AWS_PROFILE`
     /// and `Aws_Profile` and other variations.
     pub unset: Option<VecOrSingle<String>>,
+
+    /// ### feature.env.rewrite {#feature-env-rewrite}
+    ///
+    /// List of regex substitutions applied to the values of the remote environment variables,
+    /// before they're set in the local process. Substitutions are applied in order, each one to
+    /// all matches in the value. Values set with [`override`](#feature-env-override) are not
+    /// rewritten.
+    ///
+    /// Each substitution has a `pattern` (regular expression) and a `replace` string, which can
+    /// reference capture groups (`$1`, `$name`).
+    ///
+    /// For example, to point the local process at a local Redis instead of the in-cluster one,
+    /// while keeping the rest of the connection string:
+    ///
+    /// ```json
+    /// {
+    ///   "rewrite": [
+    ///     { "pattern": "redis\\.prod\\.svc(\\.cluster\\.local)?", "replace": "localhost" },
+    ///     { "pattern": "^postgres://(?<user>[^@]+)@db:5432", "replace": "postgres://$user@localhost:5433" }
+    ///   ]
+    /// }
+    /// ```
+    pub rewrite: Option<Vec<EnvRewrite>>,
+}
+
+/// <!--${internal}-->
+/// A regex substitution applied to the values of the remote environment variables, see
+/// [`feature.env.rewrite`](#feature-env-rewrite).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EnvRewrite {
+    /// <!--${internal}-->
+    /// Regular expression matched against the values.
+    pub pattern: String,
+
+    /// <!--${internal}-->
+    /// Replacement for the matches, can reference capture groups (`$1`, `$name`).
+    pub replace: String,
+}
+
+impl EnvConfig {
+    /// Verifies that all [`EnvConfig::rewrite`] patterns are valid regular expressions.
+    pub fn verify(&self, _: &mut ConfigContext) -> Result<()> {
+        for rewrite in self.rewrite.iter().flatten() {
+            if let Err(error) = Regex::new(&rewrite.pattern) {
+                return Err(ConfigError::InvalidValue {
+                    name: "feature.env.rewrite",
+                    provided: rewrite.pattern.clone(),
+                    error: Box::new(error),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies [`EnvConfig::rewrite`] to the values of the given remote environment variables.
+    ///
+    /// Patterns are checked in [`EnvConfig::verify`], invalid ones are skipped here.
+    pub fn rewrite_values(&self, env: &mut HashMap<String, String>) {
+        let rewrites = self
+            .rewrite
+            .iter()
+            .flatten()
+            .filter_map(|rewrite| Some((Regex::new(&rewrite.pattern).ok()?, &rewrite.replace)))
+            .collect::<Vec<_>>();
+        if rewrites.is_empty() {
+            return;
+        }
+
+        for value in env.values_mut() {
+            for (regex, replace) in &rewrites {
+                if let Ok(rewritten) = regex.try_replacen(value, 0, replace.as_str()) {
+                    *value = rewritten.into_owned();
+                }
+            }
+        }
+    }
 }
 
 impl MirrordToggleableConfig for EnvFileConfig {
@@ -106,6 +188,7 @@ impl MirrordToggleableConfig for EnvFileConfig {
             load_from_process: None,
             r#override: None,
             unset: None,
+            rewrite: None,
         })
     }
 }
@@ -133,6 +216,13 @@ impl CollectAnalytics for &EnvConfig {
                 .map(|v| v.len() as u32)
                 .unwrap_or_default(),
         );
+        analytics.add(
+            "rewrite_count",
+            self.rewrite
+                .as_ref()
+                .map(|v| v.len() as u32)
+                .unwrap_or_default(),
+        );
         analytics.add(
             "unset_count",
             self.unset
@@ -206,4 +296,51 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn rewrite_values() {
+        let env: EnvFileConfig = serde_json::from_str(
+            r#"{
+                "rewrite": [
+                    { "pattern": "redis\\.prod\\.svc", "replace": "localhost" },
+                    { "pattern": "^(?<scheme>\\w+)://localhost:6379", "replace": "$scheme://localhost:6380" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let env = env.generate_config(&mut ConfigContext::default()).unwrap();
+        env.verify(&mut ConfigContext::default()).unwrap();
+
+        let mut values = HashMap::from([
+            (
+                "REDIS_URL".to_string(),
+                "redis://redis.prod.svc:6379".to_string(),
+            ),
+            ("REDIS_HOST".to_string(), "redis.prod.svc".to_string()),
+            ("OTHER".to_string(), "redis-prod-svc".to_string()),
+        ]);
+        env.rewrite_values(&mut values);
+
+        assert_eq!(
+            values.get("REDIS_URL").map(String::as_str),
+            Some("redis://localhost:6380")
+        );
+        assert_eq!(
+            values.get("REDIS_HOST").map(String::as_str),
+            Some("localhost")
+        );
+        assert_eq!(
+            values.get("OTHER").map(String::as_str),
+            Some("redis-prod-svc")
+        );
+    }
+
+    #[test]
+    fn rewrite_invalid_pattern() {
+        let env: EnvFileConfig =
+            serde_json::from_str(r#"{ "rewrite": [{ "pattern": "(", "replace": "" }] }"#).unwrap();
+        let env = env.generate_config(&mut ConfigContext::default()).unwrap();
+
+        assert!(env.verify(&mut ConfigContext::default()).is_err());
+    }
 }
@@ -10,7 +10,7 @@
 use schemars::JsonSchema;
 use serde::Deserialize;
 
-pub use self::{advanced::*, mode::*};
+pub use self::{advanced::*, mode::*, rules::*};
 use crate::{
     config::{
         from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError, MirrordConfig,
@@ -20,6 +20,7 @@ use crate::{
 
 pub mod advanced;
 pub mod mode;
+pub mod rules;
 
 /// ## feature.fs {#fs}
 ///
@@ -64,7 +65,7 @@ pub enum FsUserConfig {
     /// <!--${internal}-->
     /// Allows the user to specify both [`FsModeConfig`] (as above), and configuration for the
     /// overrides.
-    Advanced(AdvancedFsUserConfig),
+    Advanced(Box<AdvancedFsUserConfig>),
 }
 
 impl Default for FsUserConfig {
@@ -90,6 +91,26 @@ impl MirrordConfig for FsUserConfig {
                     .source_value(context)
                     .transpose()?,
                 not_found: None,
+                scratch: FromEnv::new("MIRRORD_FILE_SCRATCH_PATTERN")
+                    .source_value(context)
+                    .transpose()?,
+                rules: None,
+                overlay: FromEnv::new("MIRRORD_FILE_OVERLAY_DIR")
+                    .source_value(context)
+                    .transpose()?,
+                pod_files: FromEnv::new("MIRRORD_FILE_POD_FILES")
+                    .source_value(context)
+                    .transpose()?
+                    .unwrap_or(true),
+                compression_threshold: FromEnv::new("MIRRORD_FILE_COMPRESSION_THRESHOLD")
+                    .source_value(context)
+                    .transpose()?,
+                metadata_cache_ttl_ms: FromEnv::new("MIRRORD_FILE_METADATA_CACHE_TTL_MS")
+                    .source_value(context)
+                    .transpose()?,
+                prefetch: FromEnv::new("MIRRORD_FILE_PREFETCH")
+                    .source_value(context)
+                    .transpose()?,
                 mapping: None,
             },
             FsUserConfig::Advanced(advanced) => advanced.generate_config(context)?,
@@ -111,6 +132,13 @@ impl MirrordToggleableConfig for FsUserConfig {
         let local = FromEnv::new("MIRRORD_FILE_LOCAL_PATTERN")
             .source_value(context)
             .transpose()?;
+        let overlay = FromEnv::new("MIRRORD_FILE_OVERLAY_DIR")
+            .source_value(context)
+            .transpose()?;
+        let pod_files = FromEnv::new("MIRRORD_FILE_POD_FILES")
+            .source_value(context)
+            .transpose()?
+            .unwrap_or(true);
 
         Ok(FsConfig {
             mode,
@@ -118,6 +146,13 @@ impl MirrordToggleableConfig for FsUserConfig {
             read_only,
             local,
             not_found: None,
+            scratch: None,
+            rules: None,
+            overlay,
+            pod_files,
+            compression_threshold: None,
+            metadata_cache_ttl_ms: None,
+            prefetch: None,
             mapping: None,
         })
     }
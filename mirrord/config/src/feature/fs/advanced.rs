@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use mirrord_analytics::{AnalyticValue, CollectAnalytics};
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::Serialize;
 
-use super::{FsModeConfig, FsUserConfig};
+use super::{FsModeConfig, FsRule, FsUserConfig};
 use crate::{
     config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError},
     util::{MirrordToggleableConfig, VecOrSingle},
@@ -34,6 +34,8 @@ use crate::{
 /// 3. `"local"` - List of patterns that should be read locally.
 /// 4. `"not_found"` - List of patters that should never be read nor written. These files should be
 ///    treated as non-existent.
+/// 5. `"scratch"` - List of patterns that should be read/write remotely, in a directory of the
+///    session that is deleted when the session ends, instead of their actual path.
 /// 4. `"mapping"` - Map of patterns and their corresponding replacers. The replacement happens before any specific behavior as defined above or mode (uses [`Regex::replace`](https://docs.rs/regex/latest/regex/struct.Regex.html#method.replace))
 ///
 /// The logic for choosing the behavior is as follows:
@@ -41,14 +43,16 @@ use crate::{
 ///
 /// 1. Check agains "mapping" if path needs to be replaced, if matched then continue to next step
 ///    with new path after replacements otherwise continue as usual.
-/// 2. Check if one of the patterns match the file path, do the corresponding action. There's no
+/// 2. Check the [`rules`](#feature-fs-rules) in order, the first one that matches the path and the
+///    kind of operation (read or write) decides.
+/// 3. Check if one of the patterns match the file path, do the corresponding action. There's no
 ///    specified order if two lists match the same path, we will use the first one (and we do not
 ///    guarantee what is first).
 ///
 ///     **Warning**: Specifying the same path in two lists is unsupported and can lead to undefined
 ///     behaviour.
 ///
-/// 3. There are pre-defined exceptions to the set FS mode.
+/// 4. There are pre-defined exceptions to the set FS mode.
 ///     1. Paths that match [the patterns defined here](https://github.com/metalbear-co/mirrord/tree/latest/mirrord/layer/src/file/filter/read_local_by_default.rs)
 ///        are read locally by default.
 ///     2. Paths that match [the patterns defined here](https://github.com/metalbear-co/mirrord/tree/latest/mirrord/layer/src/file/filter/read_remote_by_default.rs)
@@ -62,7 +66,7 @@ use crate::{
 ///     though it is covered by [the set of patterns that are read locally by default](https://github.com/metalbear-co/mirrord/tree/latest/mirrord/layer/src/file/filter/read_local_by_default.rs),
 ///     add `"^/etc/."` to the `read_only` set.
 ///
-/// 4. If none of the above match, use the default behavior (mode).
+/// 5. If none of the above match, use the default behavior (mode).
 ///
 /// For more information, check the file operations
 /// [technical reference](https://mirrord.dev/docs/reference/fileops/).
@@ -80,7 +84,7 @@ use crate::{
 ///   }
 /// }
 /// ```
-#[derive(MirrordConfig, Default, Clone, PartialEq, Eq, Debug, Serialize)]
+#[derive(MirrordConfig, Clone, PartialEq, Eq, Debug, Serialize)]
 #[config(
     map_to = "AdvancedFsUserConfig",
     derive = "PartialEq,Eq,JsonSchema",
@@ -114,6 +118,147 @@ pub struct FsConfig {
     /// Specify file path patterns that if matched will be treated as non-existent.
     pub not_found: Option<VecOrSingle<String>>,
 
+    /// ### feature.fs.scratch {#feature-fs-scratch}
+    ///
+    /// Specify file path patterns that if matched will be read and written to a scratch directory
+    /// in the target, instead of their actual path. The agent creates the scratch directory for
+    /// the session, and deletes it (with everything written to it) when the session ends, so that
+    /// temporary files and fixtures don't linger in the target.
+    ///
+    /// For example, with `"^/var/app/uploads/"`, writing `/var/app/uploads/report.csv` creates
+    /// the file in the scratch directory. Reading it back, in the same session, reads it from
+    /// there.
+    ///
+    /// Requires an agent that supports it, older agents read and write these paths as if they
+    /// were in [`read_write`](#feature-fs-read_write).
+    #[config(env = "MIRRORD_FILE_SCRATCH_PATTERN")]
+    pub scratch: Option<VecOrSingle<String>>,
+
+    /// ### feature.fs.rules {#feature-fs-rules}
+    ///
+    /// Rules that decide separately where the operations that read a path go, and where the ones
+    /// that write it go, for paths that match a glob. In every [`mode`](#feature-fs-mode) except
+    /// `"local"`, the rules take precedence over the mode and over the `read_write`,
+    /// `read_only`, `local`, `not_found` and `scratch` patterns.
+    ///
+    /// Each rule has a `path` glob, and a `read` and/or a `write` action:
+    ///
+    /// - `"remote"`: the operations go to the remote filesystem;
+    /// - `"local"`: the operations go to the local filesystem;
+    /// - `"block"`: the operations fail with `EACCES` (permission denied).
+    ///
+    /// The rules are checked in order, and the first one that matches the path and has an action
+    /// for the operation wins. When none does, the patterns and the mode decide.
+    ///
+    /// In the globs, `*` matches anything but `/`, `?` matches a single character, `**` matches
+    /// any number of directories (`/app/**` matches `/app` and everything under it), and `[a-z]`
+    /// matches one character of a set. Globs that don't start with `/` match at any depth, e.g.
+    /// `*.env`. Unlike the patterns, globs are case sensitive.
+    ///
+    /// For example, to read `/app/config` from the remote but never write to it, keep `/tmp`
+    /// local, and read everything else from the remote:
+    ///
+    /// ```json
+    /// {
+    ///   "feature": {
+    ///     "fs": {
+    ///       "mode": "read",
+    ///       "rules": [
+    ///         { "path": "/app/config/**", "read": "remote", "write": "block" },
+    ///         { "path": "/tmp/**", "read": "local", "write": "local" }
+    ///       ]
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// Run `mirrord config explain-path <path>` to see what decides where the operations on a
+    /// path go.
+    pub rules: Option<Vec<FsRule>>,
+
+    /// ### feature.fs.overlay {#feature-fs-overlay}
+    ///
+    /// Local directory where the writes to remote files go in the
+    /// [`"copyonwrite"`](#feature-fs-mode-copyonwrite) mode, under the same path, e.g. writing
+    /// `/var/app/data.json` writes `<overlay>/var/app/data.json`. A remote file is copied to the
+    /// overlay the first time it's written to.
+    ///
+    /// The overlay is kept when the session ends, so that the files written can be inspected,
+    /// delete the directory to discard them. When not set, mirrord creates a new directory in the
+    /// temporary directory for each session, and shows its path.
+    ///
+    /// Listing a remote directory doesn't show the files that only exist in the overlay, and
+    /// deleting a file only deletes its copy in the overlay.
+    #[config(env = "MIRRORD_FILE_OVERLAY_DIR")]
+    pub overlay: Option<PathBuf>,
+
+    /// ### feature.fs.pod_files {#feature-fs-pod_files}
+    ///
+    /// Read the files that Kubernetes mounts in the target's pod from the remote, in every
+    /// [`mode`](#feature-fs-mode) except `"local"`, so that the app can authenticate in-cluster:
+    ///
+    /// - the service account token, CA certificate and namespace, under
+    ///   `/var/run/secrets/kubernetes.io/serviceaccount/`;
+    /// - the projected tokens of EKS and AKS workload identities, under `/var/run/secrets/`;
+    /// - the downward API volume at `/etc/podinfo/`, the path used in the Kubernetes documentation
+    ///   (add the path of any other downward API volume to [`read_only`](#feature-fs-read_only)).
+    ///
+    /// These files are never written to, and they're read again every time the app opens them,
+    /// so rotated tokens are picked up.
+    ///
+    /// Set to `false` to keep the credentials of the target from reaching the local app, then
+    /// these paths are always opened locally (unless they match one of the patterns above).
+    ///
+    /// Defaults to `true`.
+    #[config(env = "MIRRORD_FILE_POD_FILES", default = true)]
+    pub pod_files: bool,
+
+    /// ### feature.fs.compression_threshold {#feature-fs-compression_threshold}
+    ///
+    /// Compress the bytes read from and written to remote files with zstd, when there are at
+    /// least this many of them in a single read or write, e.g. `65536`.
+    ///
+    /// Speeds up the remote file operations of apps that read or write big files over a slow
+    /// connection to the cluster. Requires an agent that supports compression, older agents just
+    /// keep the payloads uncompressed.
+    ///
+    /// Not set by default, which disables the compression.
+    #[config(env = "MIRRORD_FILE_COMPRESSION_THRESHOLD")]
+    pub compression_threshold: Option<u64>,
+
+    /// ### feature.fs.metadata_cache_ttl_ms {#feature-fs-metadata_cache_ttl_ms}
+    ///
+    /// Cache the remote results of `stat`, `readlink` and directory listings for this many
+    /// milliseconds, e.g. `2000`.
+    ///
+    /// Language runtimes stat the same files (and the ones that don't exist) hundreds of times at
+    /// startup, e.g. when Python, Node or Ruby resolve their imports, each time with a round trip
+    /// to the cluster. Paths that the app changes (writes, renames, deletes...) are removed from
+    /// the cache right away, but changes made by the remote target are only seen when the cached
+    /// results expire.
+    ///
+    /// Not set by default, which disables the cache.
+    #[config(env = "MIRRORD_FILE_METADATA_CACHE_TTL_MS")]
+    pub metadata_cache_ttl_ms: Option<u64>,
+
+    /// ### feature.fs.prefetch {#feature-fs-prefetch}
+    ///
+    /// Absolute paths of remote directories that are read in a single pass when the session
+    /// starts, e.g. `["/app/config", "/usr/lib/python3/site-packages"]`.
+    ///
+    /// The whole tree under each path is sent at once: the listing of every directory, the
+    /// metadata of every entry, and the contents of the small files. Apps that scan big trees at
+    /// startup (e.g. a JVM classpath, Python packages) then find them in the
+    /// [metadata cache](#feature-fs-metadata_cache_ttl_ms), instead of making a round trip to the
+    /// cluster per file. Symbolic links are not followed, and the trees are only read up to
+    /// 10000 entries.
+    ///
+    /// Requires [`metadata_cache_ttl_ms`](#feature-fs-metadata_cache_ttl_ms), and the prefetched
+    /// results expire with the rest of the cache. Older agents don't support it, the paths are
+    /// then read on demand.
+    #[config(env = "MIRRORD_FILE_PREFETCH")]
+    pub prefetch: Option<VecOrSingle<String>>,
+
     /// ### feature.fs.mapping {#feature-fs-mapping}
     ///
     /// Specify map of patterns that if matched will replace the path according to specification.
@@ -156,11 +301,43 @@ impl MirrordToggleableConfig for AdvancedFsUserConfig {
             read_only,
             local,
             not_found: None,
+            scratch: None,
+            rules: None,
+            overlay: FromEnv::new("MIRRORD_FILE_OVERLAY_DIR")
+                .source_value(context)
+                .transpose()?,
+            pod_files: FromEnv::new("MIRRORD_FILE_POD_FILES")
+                .source_value(context)
+                .transpose()?
+                .unwrap_or(true),
+            compression_threshold: None,
+            metadata_cache_ttl_ms: None,
+            prefetch: None,
             mapping: None,
         })
     }
 }
 
+impl Default for FsConfig {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            read_write: None,
+            read_only: None,
+            local: None,
+            not_found: None,
+            scratch: None,
+            rules: None,
+            overlay: None,
+            pod_files: true,
+            compression_threshold: None,
+            metadata_cache_ttl_ms: None,
+            prefetch: None,
+            mapping: None,
+        }
+    }
+}
+
 impl FsConfig {
     pub fn is_read(&self) -> bool {
         self.mode.is_read()
@@ -183,6 +360,7 @@ impl From<FsModeConfig> for AnalyticValue {
             FsModeConfig::LocalWithOverrides => Self::Number(1),
             FsModeConfig::Read => Self::Number(2),
             FsModeConfig::Write => Self::Number(3),
+            FsModeConfig::CopyOnWrite => Self::Number(4),
         }
     }
 }
@@ -215,6 +393,21 @@ impl CollectAnalytics for &FsConfig {
                 .map(<[_]>::len)
                 .unwrap_or_default(),
         );
+        analytics.add(
+            "scratch_paths",
+            self.scratch.as_deref().map(<[_]>::len).unwrap_or_default(),
+        );
+        analytics.add(
+            "rules",
+            self.rules.as_deref().map(<[_]>::len).unwrap_or_default(),
+        );
+        analytics.add("pod_files", self.pod_files);
+        analytics.add("compression", self.compression_threshold.is_some());
+        analytics.add("metadata_cache", self.metadata_cache_ttl_ms.is_some());
+        analytics.add(
+            "prefetch_paths",
+            self.prefetch.as_deref().map(<[_]>::len).unwrap_or_default(),
+        );
     }
 }
 
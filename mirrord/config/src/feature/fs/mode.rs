@@ -19,7 +19,8 @@ use crate::{
 /// can be read/write remotely based on our default/user specified.
 /// Default option for general file configuration.
 ///
-/// The accepted values are: `"local"`, `"localwithoverrides`, `"read"`, or `"write`.
+/// The accepted values are: `"local"`, `"localwithoverrides`, `"read"`, `"write"`, or
+/// `"copyonwrite"`.
 #[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug, Copy, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
 pub enum FsModeConfig {
@@ -43,6 +44,13 @@ pub enum FsModeConfig {
     ///
     /// mirrord will read/write from the remote.
     Write,
+
+    /// #### feature.fs.mode.copyonwrite {#feature-fs-mode-copyonwrite}
+    ///
+    /// mirrord will read files from the remote, but writes go to a local
+    /// [overlay](#feature-fs-overlay) directory instead, and never reach the remote. Reads check
+    /// the overlay first, so the app reads back what it wrote.
+    CopyOnWrite,
 }
 
 impl FsModeConfig {
@@ -57,6 +65,10 @@ impl FsModeConfig {
     pub fn is_write(self) -> bool {
         self == FsModeConfig::Write
     }
+
+    pub fn is_copy_on_write(self) -> bool {
+        self == FsModeConfig::CopyOnWrite
+    }
 }
 
 impl FromStr for FsModeConfig {
@@ -68,6 +80,7 @@ impl FromStr for FsModeConfig {
             "localwithoverrides" => Ok(FsModeConfig::LocalWithOverrides),
             "read" => Ok(FsModeConfig::Read),
             "write" => Ok(FsModeConfig::Write),
+            "copyonwrite" => Ok(FsModeConfig::CopyOnWrite),
             _ => Err(ConfigError::InvalidFsMode(s.to_string())),
         }
     }
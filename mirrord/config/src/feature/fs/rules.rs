@@ -0,0 +1,384 @@
+//! Rules that decide separately where the file operations that read a path go, and where the ones
+//! that write it go, see [`FsRule`] and [`FsConfig::explain_path`].
+use regex::{Regex, RegexSetBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{FsConfig, FsModeConfig};
+use crate::config::ConfigError;
+
+/// What happens to the file operations on the paths of a [`FsRule`].
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+pub enum FsRuleAction {
+    /// The operations go to the remote filesystem.
+    Remote,
+    /// The operations go to the local filesystem.
+    Local,
+    /// The operations fail with `EACCES`.
+    Block,
+}
+
+/// A rule of [`feature.fs.rules`](#feature-fs-rules).
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FsRule {
+    /// Glob of the paths that the rule applies to, e.g. `"/app/config/**"`.
+    pub path: String,
+
+    /// Where the operations that only read the paths go, e.g. `open` without write flags, `stat`
+    /// or listing a directory.
+    pub read: Option<FsRuleAction>,
+
+    /// Where the operations that modify the paths go, e.g. `open` with write flags, `unlink` or
+    /// `mkdir`.
+    pub write: Option<FsRuleAction>,
+}
+
+impl FsRule {
+    /// The action of this rule for the operations that read (or `write`) its paths.
+    pub fn action(&self, write: bool) -> Option<FsRuleAction> {
+        if write {
+            self.write
+        } else {
+            self.read
+        }
+    }
+}
+
+/// Converts the glob of a [`FsRule`] into a regex that matches whole paths:
+///
+/// - `*` matches anything but `/`, and `?` matches one character that isn't `/`;
+/// - `**` matches anything, `/` included, and a trailing `/**` also matches the directory itself;
+/// - `[abc]`, `[a-z]` and `[!abc]` match one character of (or not of) the set;
+/// - `\` makes the next character match itself.
+///
+/// Globs that don't start with `/` match at any depth, e.g. `*.env` matches `/app/.env`.
+fn glob_to_regex(glob: &str) -> String {
+    let chars = glob.chars().collect::<Vec<_>>();
+    let mut regex = String::from(if glob.starts_with('/') {
+        "^"
+    } else {
+        "^(.*/)?"
+    });
+
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        i += 1;
+
+        match c {
+            '*' if chars.get(i) == Some(&'*') => {
+                i += 1;
+
+                if chars.get(i) == Some(&'/') {
+                    i += 1;
+                    regex.push_str("(.*/)?");
+                } else if i == chars.len() && regex.ends_with('/') {
+                    regex.pop();
+                    regex.push_str("(/.*)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '\\' => {
+                let escaped = chars.get(i).copied().unwrap_or('\\');
+                i += 1;
+                regex.push_str(&regex::escape(&escaped.to_string()));
+            }
+            '[' => {
+                let negated = matches!(chars.get(i), Some('!' | '^'));
+                let start = i + usize::from(negated);
+                // A `]` right after the opening bracket is part of the set.
+                let end = chars
+                    .iter()
+                    .skip(start + 1)
+                    .position(|&c| c == ']')
+                    .map(|position| start + 1 + position);
+
+                let Some(end) = end else {
+                    regex.push_str(r"\[");
+                    continue;
+                };
+
+                regex.push('[');
+                if negated {
+                    regex.push('^');
+                }
+                let set = chars.get(start..end).unwrap_or_default();
+                for (position, &c) in set.iter().enumerate() {
+                    let range = c == '-' && position != 0 && position + 1 != set.len();
+                    if range {
+                        regex.push('-');
+                    } else {
+                        regex.push_str(&regex::escape(&c.to_string()));
+                    }
+                }
+                regex.push(']');
+
+                i = end + 1;
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+/// The [`FsRule`]s of a [`FsConfig`], with their globs compiled.
+#[derive(Debug, Default)]
+pub struct FsRules(Vec<(Regex, FsRule)>);
+
+impl FsRules {
+    pub fn new(rules: &[FsRule]) -> Result<Self, ConfigError> {
+        rules
+            .iter()
+            .map(|rule| {
+                Regex::new(&glob_to_regex(&rule.path))
+                    .map(|regex| (regex, rule.clone()))
+                    .map_err(|error| ConfigError::InvalidValue {
+                        name: "feature.fs.rules",
+                        provided: rule.path.clone(),
+                        error: Box::new(error),
+                    })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Finds the first rule that matches `path` and has an action for the operations that read
+    /// (or `write`) it, returns it with its position in the list and the action.
+    pub fn find(&self, path: &str, write: bool) -> Option<(usize, &FsRule, FsRuleAction)> {
+        self.0
+            .iter()
+            .enumerate()
+            .find_map(|(index, (regex, rule))| {
+                rule.action(write)
+                    .filter(|_| regex.is_match(path))
+                    .map(|action| (index, rule, action))
+            })
+    }
+}
+
+/// Where the file operations on a path go, see [`FsConfig::explain_path`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathOutcome {
+    Remote,
+    Local,
+    /// The operations fail with `EACCES`.
+    Blocked,
+    /// The operations fail with `ENOENT`.
+    NotFound,
+}
+
+/// What decides the [`PathOutcome`] of a path, see [`FsConfig::explain_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathReason {
+    /// [`FsModeConfig::Local`] keeps all the operations local.
+    LocalMode,
+    /// The rule at this position (from 0) of [`FsConfig::rules`], with its glob.
+    Rule { index: usize, path: String },
+    /// A pattern of this field of [`FsConfig`], e.g. `"read_only"`.
+    Pattern(&'static str),
+    /// Nothing in the configuration matches the path, so the mode decides, unless one of the
+    /// patterns that mirrord applies by default matches it.
+    Mode(FsModeConfig),
+}
+
+/// Where the file operations on a path go, and why, see [`FsConfig::explain_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathExplanation {
+    pub outcome: PathOutcome,
+    pub reason: PathReason,
+}
+
+impl FsConfig {
+    /// Explains where the operations that read (or `write`) `path` go, following the precedence of
+    /// the configuration:
+    ///
+    /// 1. the [`FsModeConfig::Local`] mode;
+    /// 2. the [`rules`](FsConfig::rules), in order;
+    /// 3. the `not_found`, `read_write`, `scratch`, `read_only` and `local` patterns;
+    /// 4. the mode.
+    ///
+    /// The patterns that mirrord applies by default (e.g. local `/etc`) depend on the environment
+    /// of the app, so they're not checked, see [`PathReason::Mode`].
+    pub fn explain_path(&self, path: &str, write: bool) -> Result<PathExplanation, ConfigError> {
+        let explanation = |outcome, reason| Ok(PathExplanation { outcome, reason });
+
+        if self.mode.is_local() {
+            return explanation(PathOutcome::Local, PathReason::LocalMode);
+        }
+
+        let rules = FsRules::new(self.rules.as_deref().unwrap_or_default())?;
+        if let Some((index, rule, action)) = rules.find(path, write) {
+            let outcome = match action {
+                FsRuleAction::Remote => PathOutcome::Remote,
+                FsRuleAction::Local => PathOutcome::Local,
+                FsRuleAction::Block => PathOutcome::Blocked,
+            };
+            let reason = PathReason::Rule {
+                index,
+                path: rule.path.clone(),
+            };
+
+            return explanation(outcome, reason);
+        }
+
+        let read_only = if write {
+            PathOutcome::Local
+        } else {
+            PathOutcome::Remote
+        };
+        let patterns = [
+            ("not_found", &self.not_found, PathOutcome::NotFound),
+            ("read_write", &self.read_write, PathOutcome::Remote),
+            ("scratch", &self.scratch, PathOutcome::Remote),
+            ("read_only", &self.read_only, read_only),
+            ("local", &self.local, PathOutcome::Local),
+        ];
+
+        for (name, patterns, outcome) in patterns {
+            let patterns = patterns.as_deref().unwrap_or_default();
+            let matches = RegexSetBuilder::new(patterns)
+                .case_insensitive(true)
+                .build()
+                .map_err(|error| ConfigError::InvalidValue {
+                    name,
+                    provided: patterns.join(", "),
+                    error: Box::new(error),
+                })?
+                .is_match(path);
+
+            if matches {
+                return explanation(outcome, PathReason::Pattern(name));
+            }
+        }
+
+        let outcome = match self.mode {
+            FsModeConfig::Local | FsModeConfig::LocalWithOverrides => PathOutcome::Local,
+            FsModeConfig::Write => PathOutcome::Remote,
+            FsModeConfig::Read | FsModeConfig::CopyOnWrite if write => PathOutcome::Local,
+            FsModeConfig::Read | FsModeConfig::CopyOnWrite => PathOutcome::Remote,
+        };
+
+        explanation(outcome, PathReason::Mode(self.mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::util::VecOrSingle;
+
+    #[rstest]
+    #[case("/app/config/**", "/app/config", true)]
+    #[case("/app/config/**", "/app/config/db/settings.yaml", true)]
+    #[case("/app/config/**", "/app/configuration", false)]
+    #[case("/app/*.yaml", "/app/settings.yaml", true)]
+    #[case("/app/*.yaml", "/app/config/settings.yaml", false)]
+    #[case("/app/**/*.yaml", "/app/settings.yaml", true)]
+    #[case("/app/**/*.yaml", "/app/config/db/settings.yaml", true)]
+    #[case("/var/log/app?.log", "/var/log/app1.log", true)]
+    #[case("/var/log/app?.log", "/var/log/app/.log", false)]
+    #[case("/data/[a-c]*", "/data/bucket", true)]
+    #[case("/data/[!a-c]*", "/data/bucket", false)]
+    #[case("/data/[]]", "/data/]", true)]
+    #[case("/data/[oops", "/data/[oops", true)]
+    #[case(r"/data/\*", "/data/*", true)]
+    #[case(r"/data/\*", "/data/file", false)]
+    #[case("*.env", "/app/.env", true)]
+    #[case("*.env", "/app/.env.local", false)]
+    #[case("/App/*", "/app/file", false)]
+    fn glob_matches(#[case] glob: &str, #[case] path: &str, #[case] matches: bool) {
+        let regex = Regex::new(&glob_to_regex(glob)).unwrap();
+        assert_eq!(regex.is_match(path), matches, "{glob} -> {regex}");
+    }
+
+    /// Remote reads and blocked writes of `/app/config`, local `/tmp`, and everything else read
+    /// from the remote.
+    #[rstest]
+    #[case("/app/config/db.yaml", false, PathOutcome::Remote, PathReason::Rule { index: 0, path: "/app/config/**".into() })]
+    #[case("/app/config/db.yaml", true, PathOutcome::Blocked, PathReason::Rule { index: 0, path: "/app/config/**".into() })]
+    #[case("/tmp/cache", false, PathOutcome::Local, PathReason::Rule { index: 1, path: "/tmp/**".into() })]
+    #[case("/tmp/cache", true, PathOutcome::Local, PathReason::Rule { index: 1, path: "/tmp/**".into() })]
+    #[case(
+        "/app/main.rs",
+        false,
+        PathOutcome::Remote,
+        PathReason::Mode(FsModeConfig::Read)
+    )]
+    #[case(
+        "/app/main.rs",
+        true,
+        PathOutcome::Local,
+        PathReason::Mode(FsModeConfig::Read)
+    )]
+    #[case(
+        "/app/data.json",
+        false,
+        PathOutcome::Remote,
+        PathReason::Pattern("read_write")
+    )]
+    #[case(
+        "/app/data.json",
+        true,
+        PathOutcome::Remote,
+        PathReason::Pattern("read_write")
+    )]
+    fn explain_path(
+        #[case] path: &str,
+        #[case] write: bool,
+        #[case] outcome: PathOutcome,
+        #[case] reason: PathReason,
+    ) {
+        let config = FsConfig {
+            mode: FsModeConfig::Read,
+            read_write: Some(VecOrSingle::Single(r"\.json$".into())),
+            rules: Some(vec![
+                FsRule {
+                    path: "/app/config/**".into(),
+                    read: Some(FsRuleAction::Remote),
+                    write: Some(FsRuleAction::Block),
+                },
+                FsRule {
+                    path: "/tmp/**".into(),
+                    read: Some(FsRuleAction::Local),
+                    write: Some(FsRuleAction::Local),
+                },
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.explain_path(path, write).unwrap(),
+            PathExplanation { outcome, reason }
+        );
+    }
+
+    /// A rule without an action for the operation lets the next rules and the patterns decide.
+    #[test]
+    fn rule_without_action() {
+        let config = FsConfig {
+            local: Some(VecOrSingle::Single("^/app/".into())),
+            rules: Some(vec![FsRule {
+                path: "/app/**".into(),
+                read: None,
+                write: Some(FsRuleAction::Block),
+            }]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.explain_path("/app/file", false).unwrap(),
+            PathExplanation {
+                outcome: PathOutcome::Local,
+                reason: PathReason::Pattern("local"),
+            }
+        );
+    }
+}
@@ -6,7 +6,7 @@ use serde::Serialize;
 
 use self::{incoming::*, outgoing::*};
 use crate::{
-    config::{ConfigContext, ConfigError},
+    config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError},
     util::MirrordToggleableConfig,
 };
 
@@ -67,6 +67,18 @@ pub struct NetworkConfig {
     /// ### feature.network.dns {#feature-network-dns}
     #[config(toggleable, nested)]
     pub dns: DnsConfig,
+
+    /// ### feature.network.interfaces {#feature-network-interfaces}
+    ///
+    /// Should mirrord return the network interfaces of the target pod when calling
+    /// `getifaddrs`, so that apps that advertise their own address (e.g. to a service mesh or a
+    /// cluster membership protocol) advertise the address of the pod instead of the local one.
+    ///
+    /// Falls back to the local interfaces when the agent doesn't support it.
+    ///
+    /// Defaults to `false`.
+    #[config(env = "MIRRORD_REMOTE_INTERFACES", default = false)]
+    pub interfaces: bool,
 }
 
 impl MirrordToggleableConfig for NetworkFileConfig {
@@ -75,6 +87,9 @@ impl MirrordToggleableConfig for NetworkFileConfig {
             incoming: IncomingFileConfig::disabled_config(context)?,
             dns: DnsFileConfig::disabled_config(context)?,
             outgoing: OutgoingFileConfig::disabled_config(context)?,
+            interfaces: FromEnv::new("MIRRORD_REMOTE_INTERFACES")
+                .source_value(context)
+                .unwrap_or(Ok(false))?,
         })
     }
 }
@@ -84,6 +99,7 @@ impl CollectAnalytics for &NetworkConfig {
         analytics.add("incoming", &self.incoming);
         analytics.add("outgoing", &self.outgoing);
         analytics.add("dns", &self.dns);
+        analytics.add("interfaces", self.interfaces);
     }
 }
 
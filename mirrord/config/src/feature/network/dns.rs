@@ -1,4 +1,9 @@
-use std::ops::Deref;
+use std::{
+    collections::HashMap,
+    net::{AddrParseError, IpAddr},
+    ops::Deref,
+    str::FromStr,
+};
 
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
@@ -75,6 +80,29 @@ pub enum DnsFilterConfig {
     Local(VecOrSingle<String>),
 }
 
+/// <!--${internal}-->
+/// Parsed value of an entry in [`DnsConfig::overrides`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DnsOverride {
+    /// The hostname is resolved by the local app, as if remote DNS was disabled for it.
+    Local,
+
+    /// The hostname resolves to this address, without asking the remote pod.
+    Address(IpAddr),
+}
+
+impl FromStr for DnsOverride {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            Ok(Self::Local)
+        } else {
+            s.parse().map(Self::Address)
+        }
+    }
+}
+
 /// Resolve DNS via the remote pod.
 ///
 /// Defaults to `true`.
@@ -82,9 +110,11 @@ pub enum DnsFilterConfig {
 /// Mind that:
 /// - DNS resolving can be done in multiple ways. Some frameworks use `getaddrinfo`/`gethostbyname`
 ///   functions, while others communicate directly with the DNS server at port `53` and perform a
-///   sort of manual resolution. Just enabling the `dns` feature in mirrord might not be enough. If
-///   you see an address resolution error, try enabling the [`fs`](#feature-fs) feature, and setting
-///   `read_only: ["/etc/resolv.conf"]`.
+///   sort of manual resolution. mirrord redirects the queries sent over UDP to the nameservers of
+///   your machine to the nameserver of the target, but this requires the
+///   [`outgoing.udp`](#feature.network.outgoing.udp) feature. If you still see an address
+///   resolution error, try enabling the [`fs`](#feature-fs) feature, and setting `read_only:
+///   ["/etc/resolv.conf"]`.
 /// - DNS filter currently works only with frameworks that use `getaddrinfo`/`gethostbyname`
 ///   functions.
 #[derive(MirrordConfig, Default, PartialEq, Eq, Clone, Debug, Serialize)]
@@ -99,10 +129,34 @@ pub struct DnsConfig {
     /// Unstable: the precise syntax of this config is subject to change.
     #[config(default, unstable)]
     pub filter: Option<DnsFilterConfig>,
+
+    /// #### feature.network.dns.overrides {#feature-network-dns-overrides}
+    ///
+    /// Spoofs the resolution of specific hostnames, taking precedence over the remote lookup and
+    /// over [`filter`](#feature-network-dns-filter).
+    ///
+    /// Maps a hostname to either an IP address, which is returned as the only result of the
+    /// query, or to `"local"`, which makes the local app resolve the hostname instead.
+    ///
+    /// ```json
+    /// {
+    ///   "overrides": {
+    ///     "payments.internal": "127.0.0.1",
+    ///     "auth.internal": "local"
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// Mind that outgoing traffic to an overridden address still follows the
+    /// [`feature.network.outgoing`](#feature-network-outgoing) config, so to reach a stub running
+    /// on your machine you may also want to add the hostname to the local outgoing filter.
+    pub overrides: Option<HashMap<String, String>>,
 }
 
 impl DnsConfig {
     pub fn verify(&self, context: &mut ConfigContext) -> Result<(), ConfigError> {
+        self.verify_overrides(context)?;
+
         let filters = match &self.filter {
             Some(..) if !self.enabled => {
                 context.add_warning(
@@ -144,6 +198,38 @@ impl DnsConfig {
 
         Ok(())
     }
+
+    fn verify_overrides(&self, context: &mut ConfigContext) -> Result<(), ConfigError> {
+        let Some(overrides) = self
+            .overrides
+            .as_ref()
+            .filter(|overrides| !overrides.is_empty())
+        else {
+            return Ok(());
+        };
+
+        if !self.enabled {
+            context.add_warning(
+                "Remote DNS resolution is disabled, provided DNS overrides will be ignored"
+                    .to_string(),
+            );
+            return Ok(());
+        }
+
+        for value in overrides.values() {
+            let Err(error) = value.parse::<DnsOverride>() else {
+                continue;
+            };
+
+            return Err(ConfigError::InvalidValue {
+                name: "feature.network.dns.overrides",
+                provided: value.to_string(),
+                error: Box::new(error),
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl MirrordToggleableConfig for DnsFileConfig {
@@ -168,5 +254,35 @@ impl CollectAnalytics for &DnsConfig {
                 DnsFilterConfig::Local(value) => analytics.add("dns_filter_local", value.len()),
             }
         }
+
+        if let Some(overrides) = self.overrides.as_ref() {
+            analytics.add("dns_overrides", overrides.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use rstest::rstest;
+
+    use super::DnsOverride;
+
+    #[rstest]
+    #[case("local", DnsOverride::Local)]
+    #[case("LOCAL", DnsOverride::Local)]
+    #[case("127.0.0.1", DnsOverride::Address(IpAddr::V4(Ipv4Addr::LOCALHOST)))]
+    #[case("::1", DnsOverride::Address(IpAddr::V6(Ipv6Addr::LOCALHOST)))]
+    fn parse_dns_override(#[case] input: &str, #[case] expected: DnsOverride) {
+        assert_eq!(input.parse::<DnsOverride>().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("remote")]
+    #[case("payments.internal")]
+    #[case("127.0.0.1:80")]
+    fn parse_invalid_dns_override(#[case] input: &str) {
+        assert!(input.parse::<DnsOverride>().is_err());
     }
 }
@@ -1,4 +1,8 @@
-use std::{collections::HashSet, fmt, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
 
 use bimap::BiMap;
 use mirrord_analytics::{AnalyticValue, Analytics, CollectAnalytics};
@@ -136,6 +140,23 @@ impl MirrordConfig for IncomingFileConfig {
                     .transpose()?
                     .unwrap_or_default(),
                 ports: advanced.ports.map(|ports| ports.into_iter().collect()),
+                handoff: advanced.handoff.unwrap_or_default(),
+                startup_buffer: advanced.startup_buffer,
+                proxy_protocol: advanced.proxy_protocol.unwrap_or_default(),
+                forwarded_headers: advanced.forwarded_headers,
+                drain_timeout_ms: advanced.drain_timeout_ms,
+                database_filter: Unstable::new(
+                    "IncomingFileConfig",
+                    "database_filter",
+                    advanced.database_filter,
+                )
+                .source_value(context)
+                .transpose()?,
+                freeze_target: advanced.freeze_target.unwrap_or_default(),
+                reserved_ports: advanced
+                    .reserved_ports
+                    .map(|m| m.into_iter().collect())
+                    .unwrap_or_default(),
             },
         };
 
@@ -308,6 +329,59 @@ pub struct IncomingAdvancedFileConfig {
     ///
     /// Mutually exclusive with [`ignore_ports`](###ignore_ports).
     pub ports: Option<Vec<u16>>,
+
+    /// ### handoff
+    ///
+    /// Lets the next session on the same target adopt the steal subscriptions of this one.
+    pub handoff: Option<bool>,
+
+    /// ### startup_buffer
+    ///
+    /// Holds the stolen requests that arrive before the local application is ready to handle
+    /// them.
+    ///
+    /// See [`startup_buffer`](##startup_buffer) for details.
+    pub startup_buffer: Option<StartupBufferConfig>,
+
+    /// ### proxy_protocol
+    ///
+    /// Prepends a PROXY protocol v2 header to the connections delivered to the local
+    /// application.
+    pub proxy_protocol: Option<bool>,
+
+    /// ### forwarded_headers
+    ///
+    /// Adds the address of the original client to the stolen HTTP requests, in the
+    /// `X-Forwarded-For`, `X-Forwarded-Proto` and `Forwarded` headers.
+    ///
+    /// See [`forwarded_headers`](##forwarded_headers) for details.
+    pub forwarded_headers: Option<ForwardedHeadersConfig>,
+
+    /// ### drain_timeout_ms
+    ///
+    /// When the session ends, how long the agent waits for the stolen requests and connections
+    /// that are still in flight, before removing the steal subscriptions.
+    pub drain_timeout_ms: Option<u64>,
+
+    /// ### database_filter
+    ///
+    /// _Experimental_: steals only the database connections that match the filter.
+    ///
+    /// See [`database_filter`](##database_filter) for details.
+    pub database_filter: Option<DatabaseFilterConfig>,
+
+    /// ### freeze_target
+    ///
+    /// Freezes the target container while stealing all traffic from any of its ports.
+    pub freeze_target: Option<bool>,
+
+    /// ### reserved_ports
+    ///
+    /// Mapping for local ports to remote ports reserved in the agent, for the servers that
+    /// connect back to the application.
+    ///
+    /// See [`reserved_ports`](#feature-network-incoming-reserved_ports) for details.
+    pub reserved_ports: Option<Vec<(u16, u16)>>,
 }
 
 fn serialize_bi_map<S>(map: &BiMap<u16, u16>, serializer: S) -> Result<S::Ok, S::Error>
@@ -451,6 +525,196 @@ pub struct IncomingConfig {
     /// Mutually exclusive with
     /// [`feature.network.incoming.ignore_ports`](#feature-network-ignore_ports).
     pub ports: Option<HashSet<u16>>,
+
+    /// #### feature.network.incoming.handoff {#feature-network-incoming-handoff}
+    ///
+    /// When stealing, lets the next mirrord session of the same user on the same target adopt
+    /// the port subscriptions of this session, for a zero-downtime restart of the local
+    /// application.
+    ///
+    /// When this session exits, the agent keeps stealing from its ports for a few seconds,
+    /// holding the incoming requests. If a new session with `handoff` enabled starts in the
+    /// meantime, the held requests are sent to it once its application listens on the ports
+    /// again.
+    ///
+    /// Requires the agent to be shared between the sessions (e.g. when using the operator).
+    ///
+    /// Defaults to `false`.
+    pub handoff: bool,
+
+    /// #### feature.network.incoming.startup_buffer {#feature-network-incoming-startup_buffer}
+    ///
+    /// When set, requests that arrive before the local application is ready to handle them (it
+    /// did not listen on the port yet, or refuses connections) are held and sent to it once it's
+    /// ready, instead of failing.
+    ///
+    /// ```json
+    /// {
+    ///   "feature": {
+    ///     "network": {
+    ///       "incoming": {
+    ///         "mode": "steal",
+    ///         "startup_buffer": {
+    ///           "max_wait_ms": 5000,
+    ///           "buffer_size": 64
+    ///         }
+    ///       }
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// - `max_wait_ms`: how long a request can wait for the local application, requests that wait
+    ///   longer get a `503 Service Unavailable` response. Defaults to `5000`;
+    /// - `buffer_size`: how many requests can wait at the same time, requests that don't fit are
+    ///   handled as if there was no buffer. Defaults to `64`.
+    ///
+    /// Disabled by default.
+    pub startup_buffer: Option<StartupBufferConfig>,
+
+    /// #### feature.network.incoming.proxy_protocol {#feature-network-incoming-proxy_protocol}
+    ///
+    /// Prepends a [PROXY protocol v2](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt)
+    /// header to every connection that mirrord makes to the local application, so that a local
+    /// proxy that understands it (e.g. nginx with `listen ... proxy_protocol`, or envoy with the
+    /// `proxy_protocol` listener filter) sees the address of the original client.
+    ///
+    /// The header carries the address of the remote client and the address in the cluster that it
+    /// connected to. When mirrord doesn't know them (e.g. a request stolen with an HTTP filter
+    /// from an agent that doesn't report the connection addresses), the header uses the `LOCAL`
+    /// command, which tells the local application to use the addresses of the connection itself.
+    ///
+    /// Only enable this when the local application expects the header on every connection, as
+    /// it's sent as the first bytes of the stream.
+    ///
+    /// Defaults to `false`.
+    pub proxy_protocol: bool,
+
+    /// #### feature.network.incoming.forwarded_headers {#feature-network-incoming-forwarded_headers}
+    ///
+    /// When set, the HTTP requests stolen with an
+    /// [`http_filter`](#feature-network-incoming-http-filter) are delivered to the local
+    /// application with the standard headers that a reverse proxy adds, so that local middleware
+    /// sees the original client like it does in production.
+    ///
+    /// ```json
+    /// {
+    ///   "feature": {
+    ///     "network": {
+    ///       "incoming": {
+    ///         "mode": "steal",
+    ///         "forwarded_headers": {
+    ///           "x_forwarded_for": true,
+    ///           "x_forwarded_proto": true,
+    ///           "forwarded": true
+    ///         }
+    ///       }
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// - `x_forwarded_for`: appends the IP of the remote client to `X-Forwarded-For`. Defaults to
+    ///   `true`;
+    /// - `x_forwarded_proto`: sets `X-Forwarded-Proto` to the scheme of the request, unless it's
+    ///   already set (e.g. by an ingress that terminates TLS). Defaults to `true`;
+    /// - `forwarded`: appends an element with the remote client, the original host and the scheme
+    ///   to `Forwarded` ([RFC 7239](https://www.rfc-editor.org/rfc/rfc7239)). Defaults to `true`.
+    ///
+    /// The address of the remote client is known only with agents that report the addresses of
+    /// filtered connections, otherwise it's left out.
+    ///
+    /// Disabled by default.
+    pub forwarded_headers: Option<ForwardedHeadersConfig>,
+
+    /// #### feature.network.incoming.drain_timeout_ms {#feature-network-incoming-drain_timeout_ms}
+    ///
+    /// When set, ending a session that steals traffic drains it gracefully: the agent stops
+    /// stealing new requests and connections, letting them through to the remote target, and
+    /// waits for the ones that are still in flight to finish, for at most this many
+    /// milliseconds. Only then it removes the steal subscriptions and the port redirections.
+    ///
+    /// Without it, the requests that the session did not respond to yet fail when it ends.
+    ///
+    /// Has no effect when [`feature.network.incoming.handoff`](#feature-network-incoming-handoff)
+    /// is enabled, as the subscriptions are handed off to the next session instead.
+    ///
+    /// Disabled by default.
+    pub drain_timeout_ms: Option<u64>,
+
+    /// #### feature.network.incoming.database_filter {#feature-network-incoming-database_filter}
+    ///
+    /// _Experimental_: when stealing, steals only the database connections whose startup message
+    /// matches the filter, letting the other connections through to the remote database.
+    ///
+    /// ```json
+    /// {
+    ///   "feature": {
+    ///     "network": {
+    ///       "incoming": {
+    ///         "mode": "steal",
+    ///         "database_filter": {
+    ///           "protocol": "postgres",
+    ///           "database": "^orders_test$",
+    ///           "user": "^reporting"
+    ///         }
+    ///       }
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// - `protocol`: wire protocol of the database, `"postgres"` or `"tds"` (Microsoft SQL
+    ///   Server);
+    /// - `database`: regex for the name of the database that the connection uses;
+    /// - `user`: regex for the name of the user that the connection logs in as;
+    /// - `ports`: ports to filter, defaults to `[5432]` for `"postgres"` and `[1433]` for `"tds"`.
+    ///
+    /// A connection is stolen when all the given regexes match, at least one of `database` and
+    /// `user` is required. Matched connections are stolen whole, with the startup message.
+    ///
+    /// The startup message can be read only from unencrypted connections, connections that
+    /// negotiate TLS (`sslmode=require` in Postgres, `Encrypt=true` in SQL Server) are always let
+    /// through.
+    ///
+    /// Disabled by default.
+    pub database_filter: Option<DatabaseFilterConfig>,
+
+    /// #### feature.network.incoming.freeze_target {#feature-network-incoming-freeze_target}
+    ///
+    /// When stealing all the traffic from a port (without an
+    /// [`http_filter`](#feature-network-incoming-http-filter), or from a port in
+    /// [`ports`](#feature-network-incoming-ports) that is not filtered), freezes the target
+    /// container for as long as the steal lasts, so that the remote replica does not do the
+    /// same background work as the local application (e.g. scheduled jobs or queue consumers).
+    ///
+    /// The agent uses the cgroup freezer of the container when it can, and stops its processes
+    /// with `SIGSTOP` otherwise. The container is thawed when the session stops stealing from
+    /// the port or exits, including when the agent is terminated.
+    ///
+    /// Frozen containers can't respond to liveness probes, so keep the session shorter than the
+    /// probe allows, or the container may be restarted. Not supported when running targetless.
+    ///
+    /// Defaults to `false`.
+    pub freeze_target: bool,
+
+    /// #### feature.network.incoming.reserved_ports {#feature-network-incoming-reserved_ports}
+    ///
+    /// Mapping for local ports to remote ports that the agent reserves in the target, for the
+    /// protocols where a server connects back to a port advertised by the application (e.g. FTP
+    /// active mode). The agent listens on the remote port and sends the connections it accepts to
+    /// the local listener, whatever the incoming [`mode`](#feature-network-incoming-mode) is, and
+    /// the application sees the remote port as the port of its listener.
+    ///
+    /// Use `0` as the local port for the listeners on ephemeral ports, and `0` as the remote port
+    /// to let the agent pick a free one, e.g. `[[0, 0]]` reserves a remote port for every
+    /// listener on an ephemeral port.
+    ///
+    /// Without this, a remote port is reserved only for the listeners bound to the address that
+    /// the application sees as the local address of its outgoing connections (the pod's address).
+    ///
+    /// Requires the agent to support port reservations.
+    pub reserved_ports: HashMap<u16, u16>,
 }
 
 impl IncomingConfig {
@@ -559,6 +823,151 @@ pub enum ConcurrentSteal {
     Abort,
 }
 
+/// <!--${internal}-->
+/// Configuration of the
+/// [`feature.network.incoming.startup_buffer`](#feature-network-incoming-startup_buffer).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub struct StartupBufferConfig {
+    /// <!--${internal}-->
+    /// ### max_wait_ms
+    ///
+    /// How long a request can wait for the local application, in milliseconds. When the wait is
+    /// over, the request gets a `503 Service Unavailable` response.
+    ///
+    /// Defaults to `5000`.
+    pub max_wait_ms: u64,
+
+    /// <!--${internal}-->
+    /// ### buffer_size
+    ///
+    /// How many requests can wait for the local application at the same time. Requests that
+    /// don't fit are handled as if there was no buffer.
+    ///
+    /// Defaults to `64`.
+    pub buffer_size: usize,
+}
+
+impl Default for StartupBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_wait_ms: 5000,
+            buffer_size: 64,
+        }
+    }
+}
+
+/// <!--${internal}-->
+/// Configuration of the
+/// [`feature.network.incoming.forwarded_headers`](#feature-network-incoming-forwarded_headers).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub struct ForwardedHeadersConfig {
+    /// <!--${internal}-->
+    /// ### x_forwarded_for
+    ///
+    /// Appends the IP of the remote client to `X-Forwarded-For`.
+    ///
+    /// Defaults to `true`.
+    pub x_forwarded_for: bool,
+
+    /// <!--${internal}-->
+    /// ### x_forwarded_proto
+    ///
+    /// Sets `X-Forwarded-Proto`, unless the request already has it.
+    ///
+    /// Defaults to `true`.
+    pub x_forwarded_proto: bool,
+
+    /// <!--${internal}-->
+    /// ### forwarded
+    ///
+    /// Appends an element to `Forwarded`.
+    ///
+    /// Defaults to `true`.
+    pub forwarded: bool,
+}
+
+impl Default for ForwardedHeadersConfig {
+    fn default() -> Self {
+        Self {
+            x_forwarded_for: true,
+            x_forwarded_proto: true,
+            forwarded: true,
+        }
+    }
+}
+
+/// <!--${internal}-->
+/// Configuration of the
+/// [`feature.network.incoming.database_filter`](#feature-network-incoming-database_filter).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseFilterConfig {
+    /// <!--${internal}-->
+    /// ### protocol
+    ///
+    /// Wire protocol of the database.
+    pub protocol: DatabaseProtocol,
+
+    /// <!--${internal}-->
+    /// ### database
+    ///
+    /// Regex for the name of the database that the connection uses.
+    pub database: Option<String>,
+
+    /// <!--${internal}-->
+    /// ### user
+    ///
+    /// Regex for the name of the user that the connection logs in as.
+    pub user: Option<String>,
+
+    /// <!--${internal}-->
+    /// ### ports
+    ///
+    /// Ports to filter, defaults to the standard port of the
+    /// [`protocol`](#feature-network-incoming-database_filter-protocol).
+    pub ports: Option<Vec<u16>>,
+}
+
+impl DatabaseFilterConfig {
+    /// Returns the configured [`Self::ports`], or the standard port of the [`Self::protocol`].
+    pub fn get_filtered_ports(&self) -> HashSet<u16> {
+        match &self.ports {
+            Some(ports) => ports.iter().copied().collect(),
+            None => HashSet::from([self.protocol.default_port()]),
+        }
+    }
+}
+
+/// <!--${internal}-->
+/// Wire protocol of the databases filtered with
+/// [`feature.network.incoming.database_filter`](#feature-network-incoming-database_filter).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+pub enum DatabaseProtocol {
+    /// <!--${internal}-->
+    /// ### postgres
+    ///
+    /// PostgreSQL.
+    Postgres,
+    /// <!--${internal}-->
+    /// ### tds
+    ///
+    /// Tabular Data Stream, used by Microsoft SQL Server.
+    Tds,
+}
+
+impl DatabaseProtocol {
+    /// Standard port of the database server.
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::Postgres => 5432,
+            Self::Tds => 1433,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 #[error("could not parse ConcurrentSteal from string, values continue/override")]
 pub struct ConcurrentStealParseError;
@@ -615,5 +1024,13 @@ impl CollectAnalytics for &IncomingConfig {
         analytics.add("ignore_localhost", self.ignore_localhost);
         analytics.add("ignore_ports_count", self.ignore_ports.len());
         analytics.add("http", &self.http_filter);
+        analytics.add("handoff", self.handoff);
+        analytics.add("startup_buffer", self.startup_buffer.is_some());
+        analytics.add("proxy_protocol", self.proxy_protocol);
+        analytics.add("forwarded_headers", self.forwarded_headers.is_some());
+        analytics.add("drain", self.drain_timeout_ms.is_some());
+        analytics.add("database_filter", self.database_filter.is_some());
+        analytics.add("freeze_target", self.freeze_target);
+        analytics.add("reserved_ports_count", self.reserved_ports.len());
     }
 }
@@ -1,6 +1,6 @@
 use std::{collections::HashSet, ops::Deref, str::FromStr};
 
-use mirrord_analytics::CollectAnalytics;
+use mirrord_analytics::{AnalyticValue, CollectAnalytics};
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -103,6 +103,78 @@ pub struct HttpFilterConfig {
     /// Set to [80, 8080] by default.
     #[config(env = "MIRRORD_HTTP_FILTER_PORTS", default)]
     pub ports: PortList,
+
+    /// ##### feature.network.incoming.http_filter.fallback {#feature-network-incoming-http_filter-fallback}
+    ///
+    /// What happens to a stolen request when the local application fails to respond to it (e.g.
+    /// the local application crashed, or
+    /// [`response_timeout_ms`](#feature-network-incoming-http_filter-response_timeout_ms)
+    /// elapsed):
+    ///
+    /// - `"error"`: the request gets a `502 Bad Gateway` response;
+    /// - `"passthrough-to-remote"`: the request is sent to its original destination, as if it did
+    ///   not match the filter;
+    /// - `"retry-local"`: the request is sent to the local application again, a few times, before
+    ///   failing with `502 Bad Gateway`.
+    ///
+    /// Only requests with a small body of known size (e.g. with the `content-length` header) can
+    /// fall back to the remote or be retried, other requests always fail with an error.
+    ///
+    /// Defaults to `"error"`.
+    #[config(default)]
+    pub fallback: HttpFallbackPolicy,
+
+    /// ##### feature.network.incoming.http_filter.response_timeout_ms {#feature-network-incoming-http_filter-response_timeout_ms}
+    ///
+    /// How long the local application has to respond to a stolen request, in milliseconds,
+    /// before the [`fallback`](#feature-network-incoming-http_filter-fallback) is applied.
+    ///
+    /// Not set by default, which means that there is no timeout.
+    ///
+    /// This includes the time the local application takes to start responding to long-polling
+    /// requests. For those, prefer
+    /// [`response_idle_timeout_ms`](#
+    /// feature-network-incoming-http_filter-response_idle_timeout_ms) and
+    /// [`response_total_timeout_ms`](#
+    /// feature-network-incoming-http_filter-response_total_timeout_ms).
+    pub response_timeout_ms: Option<u64>,
+
+    /// ##### feature.network.incoming.http_filter.response_idle_timeout_ms {#feature-network-incoming-http_filter-response_idle_timeout_ms}
+    ///
+    /// How long the local application can go without sending the next part of a streamed
+    /// response body (e.g. server-sent events, or a chunked response), in milliseconds, before the
+    /// response is aborted.
+    ///
+    /// Response bodies are passed to the remote client as the local application sends them, so
+    /// this is only needed to cut off streams that got stuck.
+    ///
+    /// Not set by default, which means that there is no timeout.
+    pub response_idle_timeout_ms: Option<u64>,
+
+    /// ##### feature.network.incoming.http_filter.response_total_timeout_ms {#feature-network-incoming-http_filter-response_total_timeout_ms}
+    ///
+    /// How long the local application has to send the whole response to a stolen request,
+    /// including its body, in milliseconds.
+    ///
+    /// When it elapses before the local application starts responding, the
+    /// [`fallback`](#feature-network-incoming-http_filter-fallback) is applied, otherwise the
+    /// response is aborted.
+    ///
+    /// Not set by default, which means that there is no timeout.
+    pub response_total_timeout_ms: Option<u64>,
+
+    /// ##### feature.network.incoming.http_filter.match_route_hosts {#feature-network-incoming-http_filter-match_route_hosts}
+    ///
+    /// Only steal requests sent to the hosts of the Gateway API `HTTPRoute`s and the `Ingress`es
+    /// that route traffic to the target.
+    ///
+    /// The `host` header filter is added on top of the other filters, and is not added when the
+    /// routes accept any host, or when
+    /// [`any_of`](#feature-network-incoming-http_filter-any_of) is used.
+    ///
+    /// Defaults to `false`.
+    #[config(default = false)]
+    pub match_route_hosts: bool,
 }
 
 impl HttpFilterConfig {
@@ -122,6 +194,24 @@ impl HttpFilterConfig {
     }
 }
 
+/// What happens to a stolen request when the local application fails to respond to it, see
+/// [`feature.network.incoming.http_filter.fallback`](#
+/// feature-network-incoming-http_filter-fallback).
+#[derive(Default, PartialEq, Eq, Clone, Copy, Debug, JsonSchema, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub enum HttpFallbackPolicy {
+    /// <!--${internal}-->
+    /// The request gets a `502 Bad Gateway` response.
+    #[default]
+    Error,
+    /// <!--${internal}-->
+    /// The request is sent to its original destination.
+    PassthroughToRemote,
+    /// <!--${internal}-->
+    /// The request is sent to the local application again.
+    RetryLocal,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, JsonSchema, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum InnerFilter {
@@ -181,6 +271,11 @@ impl MirrordToggleableConfig for HttpFilterFileConfig {
             all_of,
             any_of,
             ports,
+            fallback: Default::default(),
+            response_timeout_ms: None,
+            response_idle_timeout_ms: None,
+            response_total_timeout_ms: None,
+            match_route_hosts: false,
         })
     }
 }
@@ -219,10 +314,31 @@ impl From<PortList> for HashSet<u16> {
     }
 }
 
+impl From<&HttpFallbackPolicy> for AnalyticValue {
+    fn from(value: &HttpFallbackPolicy) -> Self {
+        match value {
+            HttpFallbackPolicy::Error => AnalyticValue::Number(0),
+            HttpFallbackPolicy::PassthroughToRemote => AnalyticValue::Number(1),
+            HttpFallbackPolicy::RetryLocal => AnalyticValue::Number(2),
+        }
+    }
+}
+
 impl CollectAnalytics for &HttpFilterConfig {
     fn collect_analytics(&self, analytics: &mut mirrord_analytics::Analytics) {
         analytics.add("header_filter", self.header_filter.is_some());
         analytics.add("path_filter", self.path_filter.is_some());
         analytics.add("ports", self.ports.len());
+        analytics.add("fallback", &self.fallback);
+        analytics.add("response_timeout", self.response_timeout_ms.is_some());
+        analytics.add(
+            "response_idle_timeout",
+            self.response_idle_timeout_ms.is_some(),
+        );
+        analytics.add(
+            "response_total_timeout",
+            self.response_total_timeout_ms.is_some(),
+        );
+        analytics.add("match_route_hosts", self.match_route_hosts);
     }
 }
@@ -5,7 +5,7 @@ use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use super::filter::ProtocolAndAddressFilter;
+use super::filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter};
 use crate::{
     config::{from_env::FromEnv, source::MirrordConfigSource, ConfigContext, ConfigError},
     util::{MirrordToggleableConfig, VecOrSingle},
@@ -200,16 +200,44 @@ impl OutgoingConfig {
 
         Ok(())
     }
+
+    /// Destinations from the `remote` filter that name a single host and port (e.g.
+    /// `"tcp://db.svc:5432"`), which the session expects to reach from the target.
+    ///
+    /// Filters that fail to parse are skipped, see [`Self::verify`].
+    pub fn remote_destinations(&self) -> Vec<ProtocolAndAddressFilter> {
+        let Some(OutgoingFilterConfig::Remote(filters)) = &self.filter else {
+            return Vec::new();
+        };
+
+        filters
+            .iter()
+            .filter_map(|filter| filter.parse::<ProtocolAndAddressFilter>().ok())
+            .filter(|filter| match filter.protocol {
+                ProtocolFilter::Any => self.tcp || self.udp,
+                ProtocolFilter::Tcp => self.tcp,
+                ProtocolFilter::Udp => self.udp,
+            })
+            .filter(|filter| match &filter.address {
+                AddressFilter::Name(_, port) => *port != 0,
+                AddressFilter::Socket(socket) => {
+                    socket.port() != 0 && !socket.ip().is_unspecified()
+                }
+                AddressFilter::Port(..) | AddressFilter::Subnet(..) => false,
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
 
+    use super::OutgoingFilterConfig;
     use crate::{
         config::{ConfigContext, MirrordConfig},
-        feature::network::OutgoingFileConfig,
-        util::{testing::with_env_vars, ToggleableConfig},
+        feature::network::{filter::AddressFilter, OutgoingFileConfig},
+        util::{testing::with_env_vars, ToggleableConfig, VecOrSingle},
     };
 
     #[rstest]
@@ -267,4 +295,39 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn remote_destinations() {
+        let mut outgoing = OutgoingFileConfig::default()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap();
+        outgoing.filter = Some(OutgoingFilterConfig::Remote(VecOrSingle::Multiple(
+            [
+                "tcp://db.svc:5432",
+                "udp://10.0.0.10:53",
+                "api.svc",
+                "0.0.0.0:80",
+                "10.0.0.0/8:443",
+                ":8080",
+            ]
+            .map(String::from)
+            .to_vec(),
+        )));
+
+        let destinations = outgoing
+            .remote_destinations()
+            .into_iter()
+            .map(|filter| filter.address)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            destinations,
+            [
+                AddressFilter::Name("db.svc".into(), 5432),
+                AddressFilter::Socket("10.0.0.10:53".parse().unwrap()),
+            ]
+        );
+
+        outgoing.udp = false;
+        assert_eq!(outgoing.remote_destinations().len(), 1);
+    }
 }
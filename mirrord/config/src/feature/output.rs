@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use mirrord_analytics::AnalyticValue;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// <!--${internal}-->
+/// Where the output of the local process goes, see
+/// [`feature.output`](#feature-output).
+#[derive(Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+pub enum OutputMode {
+    /// <!--${internal}-->
+    /// The output is only written locally.
+    #[default]
+    Local,
+
+    /// <!--${internal}-->
+    /// The output is written locally and to the target.
+    Tee,
+
+    /// <!--${internal}-->
+    /// The output is only written to the target.
+    Remote,
+}
+
+impl OutputMode {
+    /// Whether the output of the local process is sent to the target.
+    pub fn is_remote(self) -> bool {
+        matches!(self, Self::Tee | Self::Remote)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("could not parse OutputMode from string, values must be local/tee/remote")]
+pub struct OutputModeParseError;
+
+impl FromStr for OutputMode {
+    type Err = OutputModeParseError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val {
+            "local" => Ok(Self::Local),
+            "tee" => Ok(Self::Tee),
+            "remote" => Ok(Self::Remote),
+            _ => Err(OutputModeParseError),
+        }
+    }
+}
+
+impl From<&OutputMode> for AnalyticValue {
+    fn from(value: &OutputMode) -> Self {
+        match value {
+            OutputMode::Local => AnalyticValue::Number(0),
+            OutputMode::Tee => AnalyticValue::Number(1),
+            OutputMode::Remote => AnalyticValue::Number(2),
+        }
+    }
+}
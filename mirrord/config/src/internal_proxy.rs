@@ -1,4 +1,7 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
 
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
@@ -11,6 +14,9 @@ pub static MIRRORD_INTPROXY_CONTAINER_MODE_ENV: &str = "MIRRORD_INTPROXY_CONTAIN
 pub static MIRRORD_INTPROXY_CLIENT_TLS_CERTIFICATE_ENV: &str =
     "MIRRORD_INTPROXY_CLIENT_TLS_CERTIFICATE";
 pub static MIRRORD_INTPROXY_CLIENT_TLS_KEY_ENV: &str = "MIRRORD_INTPROXY_CLIENT_TLS_KEY";
+/// Token the layers authenticate with, when the internal proxy listens on a non-loopback
+/// [`InternalProxyConfig::listen_address`]. Generated for each session.
+pub static MIRRORD_INTPROXY_AUTH_TOKEN_ENV: &str = "MIRRORD_INTPROXY_AUTH_TOKEN";
 
 /// Configuration for the internal proxy mirrord spawns for each local mirrord session
 /// that local layers use to connect to the remote agent
@@ -101,6 +107,77 @@ pub struct InternalProxyConfig {
     #[config(default = 31536000)]
     pub socket_timeout: u64,
 
+    /// ### internal_proxy.clock_skew_threshold_ms {#internal_proxy-clock_skew_threshold_ms}
+    ///
+    /// When the clock of the target differs from the local clock by more than this many
+    /// milliseconds, mirrord warns about it at startup. Time-sensitive checks, like TLS
+    /// certificate or JWT validation, can fail intermittently when the clocks drift apart.
+    ///
+    /// Set to 0 to skip measuring the clock skew.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "clock_skew_threshold_ms": 2000
+    ///   }
+    /// }
+    /// ```
+    #[config(default = 1000)]
+    pub clock_skew_threshold_ms: u64,
+
+    /// ### internal_proxy.connect_proxy_port {#internal_proxy-connect_proxy_port}
+    ///
+    /// Exposes a SOCKS5 and HTTP `CONNECT` proxy on this port of `127.0.0.1`, so that programs
+    /// running without mirrord (e.g. a browser or a database client) can reach the cluster. The
+    /// connections made through it follow the
+    /// [`feature.network.dns`](#feature-network-dns) and
+    /// [`feature.network.outgoing`](#feature-network-outgoing) rules, like the connections made by
+    /// the local application.
+    ///
+    /// The proxy is available for as long as the session runs.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "connect_proxy_port": 1080
+    ///   }
+    /// }
+    /// ```
+    pub connect_proxy_port: Option<u16>,
+
+    /// ### internal_proxy.listen_address {#internal_proxy-listen_address}
+    ///
+    /// IP address the internal proxy listens on for the connections of the local processes.
+    /// Defaults to `127.0.0.1`.
+    ///
+    /// When it's not a loopback address (e.g. `0.0.0.0` inside of a container), the processes
+    /// have to authenticate with a token that mirrord generates for the session.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "listen_address": "0.0.0.0"
+    ///   }
+    /// }
+    /// ```
+    pub listen_address: Option<IpAddr>,
+
+    /// ### internal_proxy.port {#internal_proxy-port}
+    ///
+    /// Pins the port the internal proxy listens on for the connections of the local processes,
+    /// for setups that need to know it upfront (e.g. a debugger attached to the internal proxy).
+    ///
+    /// By default, a random free port is used. Only one session at a time can use a pinned port.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "port": 47000
+    ///   }
+    /// }
+    /// ```
+    pub port: Option<u16>,
+
     /// ### internal_proxy.log_level {#internal_proxy-log_level}
     ///
     /// Set the log level for the internal proxy.
@@ -119,3 +196,11 @@ pub struct InternalProxyConfig {
     #[config(default = false, env = MIRRORD_INTPROXY_CONTAINER_MODE_ENV)]
     pub container_mode: bool,
 }
+
+impl InternalProxyConfig {
+    /// Resolves [`InternalProxyConfig::listen_address`].
+    pub fn layer_listen_address(&self) -> IpAddr {
+        self.listen_address
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+}
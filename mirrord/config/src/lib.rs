@@ -7,32 +7,51 @@
 //! Remember to re-generate the `mirrord-schema.json` if you make **ANY** changes to this lib,
 //! including if you only made documentation changes.
 pub mod agent;
+pub mod cluster;
 pub mod config;
 pub mod container;
 pub mod experimental;
 pub mod external_proxy;
 pub mod feature;
 pub mod internal_proxy;
+pub mod mode;
+pub mod redaction;
 pub mod target;
 pub mod util;
 
-use std::{collections::HashSet, ops::Not, path::Path};
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr},
+    ops::Not,
+    path::Path,
+};
 
 use config::{ConfigContext, ConfigError, MirrordConfig};
 use experimental::ExperimentalConfig;
-use feature::network::outgoing::OutgoingFilterConfig;
+use feature::{
+    fs::{FsModeConfig, FsRuleAction, FsRules},
+    network::outgoing::OutgoingFilterConfig,
+};
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use target::Target;
 use tera::Tera;
 use tracing::warn;
 
 use crate::{
-    agent::AgentConfig, config::source::MirrordConfigSource, container::ContainerConfig,
-    external_proxy::ExternalProxyConfig, feature::FeatureConfig,
-    internal_proxy::InternalProxyConfig, target::TargetConfig, util::VecOrSingle,
+    agent::AgentConfig,
+    cluster::{ClusterConfig, ClusterRoute},
+    config::source::MirrordConfigSource,
+    container::ContainerConfig,
+    external_proxy::ExternalProxyConfig,
+    feature::FeatureConfig,
+    internal_proxy::InternalProxyConfig,
+    mode::SessionMode,
+    redaction::RedactionConfig,
+    target::TargetConfig,
+    util::VecOrSingle,
 };
 
 /// Env variable to load config from file (json, yaml and toml supported).
@@ -226,6 +245,91 @@ pub struct LayerConfig {
     #[config(env = "MIRRORD_OPERATOR_ENABLE")]
     pub operator: Option<bool>,
 
+    /// ## join_session {#root-join_session}
+    ///
+    /// Joins an operator session that another user shared with
+    /// `mirrord operator session share`, to observe the traffic it steals, e.g. for pair
+    /// debugging during an incident.
+    ///
+    /// The value is the share link printed by the owner of the session, in the
+    /// `<session id>:<token>` format. The session is joined in observe-only mode: the application
+    /// receives mirrored copies of the stolen traffic, and can't steal traffic itself. The owner
+    /// can revoke the share at any time with `mirrord operator session unshare`.
+    ///
+    /// Requires the operator, and the [`target`](#root-target) is ignored.
+    ///
+    /// ```json
+    /// {
+    ///   "join_session": "5A2C9B10F3E4D768:9f1c2e7a4b"
+    /// }
+    /// ```
+    #[config(env = "MIRRORD_JOIN_SESSION")]
+    pub join_session: Option<String>,
+
+    /// ## mode {#root-mode}
+    ///
+    /// What the session is allowed to do in the cluster.
+    ///
+    /// Can be set to either `"default"` or `"read_only"`.
+    ///
+    /// - `"default"`: The session is only limited by the features that are enabled;
+    /// - `"read_only"`: The session can't modify the target, for debugging against production
+    ///   targets. Remote file writes, stealing traffic, queue splitting, scaling down the copied
+    ///   target, and writing the output to the target are not allowed, and configuring them is an
+    ///   error. This is enforced by the layer, the internal proxy, and the agent, and can be
+    ///   required by the operator policies.
+    ///
+    /// Defaults to `"default"`.
+    ///
+    /// ```json
+    /// {
+    ///   "mode": "read_only"
+    /// }
+    /// ```
+    #[config(env = "MIRRORD_MODE", default)]
+    pub mode: SessionMode,
+
+    /// ## clusters {#root-clusters}
+    ///
+    /// Additional clusters that this session connects to, routing some of the outgoing traffic
+    /// through them, e.g. when the application needs a service from another cluster.
+    ///
+    /// mirrord starts an agent in each of these clusters, and the outgoing connections and DNS
+    /// queries that match one of the `outgoing` rules of a cluster go through its agent. The rest
+    /// of the session, including the incoming traffic, files and environment, uses the
+    /// [`target`](#root-target).
+    ///
+    /// Each cluster has the following fields:
+    ///
+    /// - `name`: Identifies the cluster in the logs and errors, must be unique;
+    /// - `kube_context`: Context from the kubeconfig that points to the cluster, defaults to
+    ///   [`kube_context`](#root-kube_context);
+    /// - `kubeconfig`: Path to the kubeconfig that contains the context, defaults to
+    ///   [`kubeconfig`](#root-kubeconfig);
+    /// - `target`: Target in the cluster, defaults to a targetless agent;
+    /// - `namespace`: Namespace of the `target`;
+    /// - `outgoing`: Rules matching the outgoing traffic that goes through the cluster. A rule is
+    ///   either a host name (`"service-b.default.svc.cluster.local"`), a host name wildcard that
+    ///   matches its subdomains (`"*.payments.svc.cluster.local"`), or an IP address or subnet
+    ///   (`"10.96.0.0/12"`). Host names are only matched when
+    ///   [`feature.network.dns`](#feature-network-dns) is enabled, and connections to the
+    ///   addresses resolved through the cluster go through it as well.
+    ///
+    /// ```json
+    /// {
+    ///   "clusters": [
+    ///     {
+    ///       "name": "payments",
+    ///       "kube_context": "payments-cluster",
+    ///       "namespace": "payments",
+    ///       "outgoing": ["*.payments.svc.cluster.local", "10.100.0.0/16"]
+    ///     }
+    ///   ]
+    /// }
+    /// ```
+    #[config(default)]
+    pub clusters: Vec<ClusterConfig>,
+
     /// ## kubeconfig {#root-kubeconfig}
     ///
     /// Path to a kubeconfig file, if not specified, will use `KUBECONFIG`, or `~/.kube/config`, or
@@ -293,6 +397,48 @@ pub struct LayerConfig {
     #[config(env = "MIRRORD_KUBE_CONTEXT")]
     pub kube_context: Option<String>,
 
+    /// ## kube_proxy {#root-kube_proxy}
+    ///
+    /// URL of an HTTP(S) or SOCKS5 proxy to use for all connections to the Kubernetes API
+    /// (including port forwarding to the agent), e.g. `"http://proxy.corp:3128"`.
+    ///
+    /// Overrides the `proxy-url` from the kubeconfig. Note that the `HTTPS_PROXY` env variable is
+    /// not used for the Kubernetes API connections.
+    ///
+    /// ```json
+    /// {
+    ///   "kube_proxy": "http://proxy.corp:3128"
+    /// }
+    /// ```
+    #[config(env = "MIRRORD_KUBE_PROXY")]
+    pub kube_proxy: Option<String>,
+
+    /// ## kube_ca_bundle {#root-kube_ca_bundle}
+    ///
+    /// Path to a PEM file with CA certificates to trust when connecting to the Kubernetes API,
+    /// in addition to the cluster CA from the kubeconfig.
+    ///
+    /// Useful when a corporate proxy intercepts TLS connections with its own CA.
+    ///
+    /// ```json
+    /// {
+    ///   "kube_ca_bundle": "~/corp/ca-bundle.pem"
+    /// }
+    /// ```
+    #[config(env = "MIRRORD_KUBE_CA_BUNDLE")]
+    pub kube_ca_bundle: Option<String>,
+
+    /// ## kube_tls_server_name {#root-kube_tls_server_name}
+    ///
+    /// Name used to verify the certificate of the Kubernetes API server, instead of the host from
+    /// the server URL. Overrides the `tls-server-name` from the kubeconfig.
+    ///
+    /// Useful when the API server is reached through a tunnel or a proxy with a different
+    /// hostname. To skip the verification completely, see
+    /// [`accept_invalid_certificates`](#root-accept_invalid_certificates).
+    #[config(env = "MIRRORD_KUBE_TLS_SERVER_NAME")]
+    pub kube_tls_server_name: Option<String>,
+
     /// ## internal_proxy {#root-internal_proxy}
     #[config(nested)]
     pub internal_proxy: InternalProxyConfig,
@@ -311,6 +457,10 @@ pub struct LayerConfig {
     #[config(env = "MIRRORD_PROXY", default = true)]
     pub use_proxy: bool,
 
+    /// ## redaction {#root-redaction}
+    #[config(nested)]
+    pub redaction: RedactionConfig,
+
     /// ## experimental {#root-experimental}
     #[config(nested)]
     pub experimental: ExperimentalConfig,
@@ -423,6 +573,51 @@ impl LayerConfig {
             }
         }
 
+        if let Some(database_filter) = &self.feature.network.incoming.database_filter {
+            if database_filter.database.is_none() && database_filter.user.is_none() {
+                Err(ConfigError::Conflict(
+                    "`feature.network.incoming.database_filter` requires at least one of \
+                    `database` and `user`"
+                        .to_string(),
+                ))?
+            }
+
+            for (name, filter) in [
+                (
+                    "feature.network.incoming.database_filter.database",
+                    &database_filter.database,
+                ),
+                (
+                    "feature.network.incoming.database_filter.user",
+                    &database_filter.user,
+                ),
+            ] {
+                if let Some(filter) = filter {
+                    fancy_regex::Regex::new(filter).map_err(|error| ConfigError::InvalidValue {
+                        name,
+                        provided: filter.clone(),
+                        error: Box::new(error),
+                    })?;
+                }
+            }
+
+            let database_ports = database_filter.get_filtered_ports();
+            if let Some(http_ports) = self
+                .feature
+                .network
+                .incoming
+                .http_filter
+                .get_filtered_ports()
+                .filter(|ports| ports.iter().any(|port| database_ports.contains(port)))
+            {
+                Err(ConfigError::Conflict(format!(
+                    "`feature.network.incoming.http_filter.ports` (set to {http_ports:?}) and \
+                    `feature.network.incoming.database_filter.ports` (set to {database_ports:?}) \
+                    must be disjoint."
+                )))?
+            }
+        }
+
         if !self.feature.copy_target.enabled
             && self
                 .target
@@ -520,9 +715,91 @@ impl LayerConfig {
             ));
         }
 
+        if self.mode.is_read_only() {
+            self.verify_read_only()?;
+        }
+
+        if self.join_session.is_some() {
+            if self.operator == Some(false) {
+                Err(ConfigError::Conflict(
+                    "`join_session` requires the operator, but `operator` is disabled".to_string(),
+                ))?
+            }
+
+            if self.feature.network.incoming.is_steal() {
+                Err(ConfigError::Conflict(
+                    "`feature.network.incoming.mode: steal` is not allowed with `join_session`, \
+                    the shared session is only observed"
+                        .to_string(),
+                ))?
+            }
+        }
+
+        FsRules::new(self.feature.fs.rules.as_deref().unwrap_or_default())?;
+
+        let prefetch = self.feature.fs.prefetch.as_deref().unwrap_or_default();
+        if let Some(path) = prefetch.iter().find(|path| !Path::new(path).is_absolute()) {
+            Err(ConfigError::InvalidValue {
+                name: "feature.fs.prefetch",
+                provided: path.clone(),
+                error: "the prefetched paths must be absolute".into(),
+            })?
+        }
+        if !prefetch.is_empty() && self.feature.fs.metadata_cache_ttl_ms.is_none() {
+            context.add_warning(
+                "`feature.fs.prefetch` requires `feature.fs.metadata_cache_ttl_ms`, the paths \
+                will not be prefetched."
+                    .into(),
+            );
+        }
+
+        self.feature.env.verify(context)?;
         self.feature.network.dns.verify(context)?;
         self.feature.network.outgoing.verify(context)?;
         self.feature.split_queues.verify(context)?;
+        ClusterConfig::verify(&self.clusters)?;
+
+        let cluster_host_routes = self
+            .clusters
+            .iter()
+            .filter_map(|cluster| cluster.routes().ok())
+            .flatten()
+            .any(|route| !matches!(route, ClusterRoute::Subnet(..)));
+        if cluster_host_routes && !self.feature.network.dns.enabled {
+            context.add_warning(
+                "`clusters` contain host names in their `outgoing` rules, but the remote DNS \
+                feature is disabled, so these rules are not used."
+                    .into(),
+            );
+        }
+
+        if self.internal_proxy.connect_proxy_port == Some(0) {
+            Err(ConfigError::Conflict(
+                "`internal_proxy.connect_proxy_port` must not be 0, the proxy needs a fixed port"
+                    .to_string(),
+            ))?
+        }
+
+        // The connect proxy listens on `127.0.0.1`.
+        let listen_address = self.internal_proxy.layer_listen_address();
+        if self.internal_proxy.port.is_some_and(|port| port != 0)
+            && self.internal_proxy.port == self.internal_proxy.connect_proxy_port
+            && (listen_address.is_unspecified()
+                || listen_address == IpAddr::V4(Ipv4Addr::LOCALHOST))
+        {
+            Err(ConfigError::Conflict(
+                "`internal_proxy.port` and `internal_proxy.connect_proxy_port` must be different"
+                    .to_string(),
+            ))?
+        }
+
+        for pattern in self.redaction.patterns.as_deref().unwrap_or_default() {
+            regex::Regex::new(pattern).map_err(|error| ConfigError::InvalidValue {
+                name: "redaction.patterns",
+                provided: pattern.clone(),
+                error: Box::new(error),
+            })?;
+        }
 
         if self.experimental.readlink {
             context.add_warning(
@@ -534,6 +811,44 @@ impl LayerConfig {
 
         Ok(())
     }
+
+    /// Verifies that no feature that would modify the target is enabled in the
+    /// [`SessionMode::ReadOnly`].
+    fn verify_read_only(&self) -> Result<(), ConfigError> {
+        let fs = &self.feature.fs;
+        let conflicts = [
+            (fs.mode == FsModeConfig::Write, "`feature.fs.mode: write`"),
+            (fs.read_write.is_some(), "`feature.fs.read_write`"),
+            (fs.scratch.is_some(), "`feature.fs.scratch`"),
+            (
+                fs.rules
+                    .iter()
+                    .flatten()
+                    .any(|rule| rule.write == Some(FsRuleAction::Remote)),
+                "`feature.fs.rules` with `write: remote`",
+            ),
+            (
+                self.feature.network.incoming.is_steal(),
+                "`feature.network.incoming.mode: steal`",
+            ),
+            (self.feature.output.is_remote(), "`feature.output`"),
+            (self.feature.split_queues.is_set(), "`feature.split_queues`"),
+            (
+                self.feature.copy_target.scale_down,
+                "`feature.copy_target.scale_down`",
+            ),
+        ];
+
+        match conflicts
+            .into_iter()
+            .find_map(|(conflict, name)| conflict.then_some(name))
+        {
+            Some(name) => Err(ConfigError::Conflict(format!(
+                "{name} is not allowed with `mode: read_only`, as it would modify the target"
+            ))),
+            None => Ok(()),
+        }
+    }
 }
 
 impl CollectAnalytics for &LayerConfig {
@@ -542,6 +857,12 @@ impl CollectAnalytics for &LayerConfig {
             analytics.add("accept_invalid_certificates", value);
         };
         analytics.add("use_kubeconfig", self.kubeconfig.is_some());
+        analytics.add("kube_proxy", self.kube_proxy.is_some());
+        analytics.add("kube_ca_bundle", self.kube_ca_bundle.is_some());
+        analytics.add("kube_tls_server_name", self.kube_tls_server_name.is_some());
+        analytics.add("mode", &self.mode);
+        analytics.add("clusters", self.clusters.len());
+        analytics.add("join_session", self.join_session.is_some());
         (&self.target).collect_analytics(analytics);
         (&self.agent).collect_analytics(analytics);
         (&self.feature).collect_analytics(analytics);
@@ -553,15 +874,32 @@ impl LayerFileConfig {
     pub fn from_path<P>(path: P) -> Result<Self, ConfigError>
     where
         P: AsRef<Path>,
+    {
+        Self::parse_path(path)
+    }
+
+    /// Renders and parses the config file at `path` like [`LayerFileConfig::from_path`], but
+    /// into a generic JSON value, e.g. to derive another config file from it.
+    pub fn json_from_path<P>(path: P) -> Result<serde_json::Value, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::parse_path(path)
+    }
+
+    fn parse_path<P, T>(path: P) -> Result<T, ConfigError>
+    where
+        P: AsRef<Path>,
+        T: DeserializeOwned,
     {
         let mut template_engine = Tera::default();
         template_engine.add_template_file(path.as_ref(), Some("main"))?;
         let rendered = template_engine.render("main", &tera::Context::new())?;
 
         match path.as_ref().extension().and_then(|os_val| os_val.to_str()) {
-            Some("json") => Ok(serde_json::from_str::<Self>(&rendered)?),
-            Some("toml") => Ok(toml::from_str::<Self>(&rendered)?),
-            Some("yaml" | "yml") => Ok(serde_yaml::from_str::<Self>(&rendered)?),
+            Some("json") => Ok(serde_json::from_str::<T>(&rendered)?),
+            Some("toml") => Ok(toml::from_str::<T>(&rendered)?),
+            Some("yaml" | "yml") => Ok(serde_yaml::from_str::<T>(&rendered)?),
             _ => Err(ConfigError::UnsupportedFormat),
         }
     }
@@ -792,6 +1130,118 @@ mod tests {
         assert_eq!(config, expect);
     }
 
+    /// Features that would modify the target are rejected in the read-only mode.
+    #[rstest]
+    #[case(r#"{"mode": "read_only"}"#, true)]
+    #[case(r#"{"mode": "read_only", "feature": {"fs": "write"}}"#, false)]
+    #[case(
+        r#"{"mode": "read_only", "feature": {"fs": {"read_write": "^/tmp"}}}"#,
+        false
+    )]
+    #[case(r#"{"mode": "read_only", "feature": {"output": "tee"}}"#, false)]
+    #[case(r#"{"feature": {"fs": "write"}}"#, true)]
+    fn read_only_mode(#[case] input: &str, #[case] valid: bool) {
+        let config = serde_json::from_str::<LayerFileConfig>(input)
+            .unwrap()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap();
+
+        let result = config.verify(&mut ConfigContext::default());
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+    }
+
+    /// A joined session is only observed, and can't steal.
+    #[rstest]
+    #[case(r#"{"join_session": "5A2C:token"}"#, true)]
+    #[case(r#"{"join_session": "5A2C:token", "operator": false}"#, false)]
+    #[case(
+        r#"{"join_session": "5A2C:token", "feature": {"network": {"incoming": "steal"}}}"#,
+        false
+    )]
+    fn join_session(#[case] input: &str, #[case] valid: bool) {
+        let config = serde_json::from_str::<LayerFileConfig>(input)
+            .unwrap()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap();
+
+        let result = config.verify(&mut ConfigContext::default());
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+    }
+
+    #[rstest]
+    #[case(
+        r#"{"clusters": [{"name": "b", "target": "pod/b", "outgoing": ["*.b.svc", "10.0.0.0/8"]}]}"#,
+        true
+    )]
+    #[case(
+        r#"{"clusters": [{"name": "b", "outgoing": ["b.svc"]}, {"name": "b", "outgoing": ["c.svc"]}]}"#,
+        false
+    )]
+    #[case(r#"{"clusters": [{"name": "b", "outgoing": []}]}"#, false)]
+    #[case(r#"{"clusters": [{"name": "b", "outgoing": ["10.0.0.0/40"]}]}"#, false)]
+    fn clusters(#[case] input: &str, #[case] valid: bool) {
+        let config = serde_json::from_str::<LayerFileConfig>(input)
+            .unwrap()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap();
+
+        let result = config.verify(&mut ConfigContext::default());
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+    }
+
+    /// The prefetched paths are absolute, and only warned about without the metadata cache.
+    #[rstest]
+    #[case(
+        r#"{"feature": {"fs": {"prefetch": "/app/config", "metadata_cache_ttl_ms": 2000}}}"#,
+        true,
+        0
+    )]
+    #[case(r#"{"feature": {"fs": {"prefetch": ["/app/config"]}}}"#, true, 1)]
+    #[case(
+        r#"{"feature": {"fs": {"prefetch": ["/app", "config"], "metadata_cache_ttl_ms": 2000}}}"#,
+        false,
+        0
+    )]
+    fn fs_prefetch(#[case] input: &str, #[case] valid: bool, #[case] warnings: usize) {
+        let config = serde_json::from_str::<LayerFileConfig>(input)
+            .unwrap()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap();
+
+        let mut context = ConfigContext::default();
+        let result = config.verify(&mut context);
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+        assert_eq!(context.get_warnings().len(), warnings);
+    }
+
+    /// The internal proxy can't listen on the port of the connect proxy.
+    #[rstest]
+    #[case(
+        r#"{"internal_proxy": {"port": 47000, "connect_proxy_port": 1080}}"#,
+        true
+    )]
+    #[case(
+        r#"{"internal_proxy": {"port": 1080, "connect_proxy_port": 1080}}"#,
+        false
+    )]
+    #[case(
+        r#"{"internal_proxy": {"listen_address": "0.0.0.0", "port": 1080, "connect_proxy_port": 1080}}"#,
+        false
+    )]
+    #[case(
+        r#"{"internal_proxy": {"listen_address": "10.0.0.5", "port": 1080, "connect_proxy_port": 1080}}"#,
+        true
+    )]
+    fn internal_proxy_port(#[case] input: &str, #[case] valid: bool) {
+        let config = serde_json::from_str::<LayerFileConfig>(input)
+            .unwrap()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap();
+
+        let result = config.verify(&mut ConfigContext::default());
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+    }
+
     #[rstest]
     fn full(
         #[values(ConfigType::Json, ConfigType::Toml, ConfigType::Yaml)] config_type: ConfigType,
@@ -856,6 +1306,14 @@ mod tests {
                             listen_ports: None,
                             on_concurrent_steal: None,
                             ports: None,
+                            handoff: None,
+                            startup_buffer: None,
+                            proxy_protocol: None,
+                            forwarded_headers: None,
+                            drain_timeout_ms: None,
+                            database_filter: None,
+                            freeze_target: None,
+                            reserved_ports: None,
                         }),
                     ))),
                     outgoing: Some(ToggleableConfig::Config(OutgoingFileConfig {
@@ -863,19 +1321,28 @@ mod tests {
                         udp: Some(false),
                         ..Default::default()
                     })),
+                    interfaces: None,
                 })),
                 copy_target: None,
                 hostname: None,
                 split_queues: None,
+                output: None,
             }),
             connect_tcp: None,
             container: None,
             operator: None,
+            join_session: None,
+            mode: None,
+            clusters: None,
             sip_binaries: None,
             kube_context: None,
+            kube_proxy: None,
+            kube_ca_bundle: None,
+            kube_tls_server_name: None,
             external_proxy: None,
             internal_proxy: None,
             use_proxy: None,
+            redaction: None,
             experimental: None,
         };
 
@@ -0,0 +1,53 @@
+use std::str::FromStr;
+
+use mirrord_analytics::AnalyticValue;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// <!--${internal}-->
+/// What the session is allowed to do in the cluster, see [`mode`](#root-mode).
+#[derive(Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum SessionMode {
+    /// <!--${internal}-->
+    /// The session is only limited by the features that are enabled.
+    #[default]
+    Default,
+
+    /// <!--${internal}-->
+    /// The session can't modify the target: no remote file writes, no steal, and no output
+    /// written to the target.
+    ReadOnly,
+}
+
+impl SessionMode {
+    pub fn is_read_only(self) -> bool {
+        matches!(self, Self::ReadOnly)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("could not parse SessionMode from string, values must be default/read_only")]
+pub struct SessionModeParseError;
+
+impl FromStr for SessionMode {
+    type Err = SessionModeParseError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val {
+            "default" => Ok(Self::Default),
+            "read_only" => Ok(Self::ReadOnly),
+            _ => Err(SessionModeParseError),
+        }
+    }
+}
+
+impl From<&SessionMode> for AnalyticValue {
+    fn from(value: &SessionMode) -> Self {
+        match value {
+            SessionMode::Default => AnalyticValue::Number(0),
+            SessionMode::ReadOnly => AnalyticValue::Number(1),
+        }
+    }
+}
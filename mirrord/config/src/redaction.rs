@@ -0,0 +1,47 @@
+use mirrord_config_derive::MirrordConfig;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{config::source::MirrordConfigSource, util::VecOrSingle};
+
+/// Controls the redaction of secrets in what mirrord writes locally: its own logs (including the
+/// internal proxy and external proxy log files), and the `mirrord report` bundles.
+///
+/// Built-in rules redact `Authorization` headers, bearer tokens, AWS access keys, credentials in
+/// URLs and the values of settings and environment variables with secret-looking names (e.g.
+/// `DB_PASSWORD` or `api_key`).
+///
+/// ```json
+/// {
+///   "redaction": {
+///     "patterns": ["x-internal-token: (?P<secret>\\S+)"],
+///     "env": ["DATABASE_URL"]
+///   }
+/// }
+/// ```
+#[derive(MirrordConfig, Clone, Debug, Serialize)]
+#[config(map_to = "RedactionFileConfig", derive = "JsonSchema")]
+#[cfg_attr(test, config(derive = "PartialEq"))]
+pub struct RedactionConfig {
+    /// ### redaction.enabled {#redaction-enabled}
+    ///
+    /// Disables the redaction when `false`, e.g. to debug an authentication issue.
+    ///
+    /// Defaults to `true`.
+    #[config(env = "MIRRORD_REDACTION", default = true)]
+    pub enabled: bool,
+
+    /// ### redaction.patterns {#redaction-patterns}
+    ///
+    /// Additional regexes of secrets to redact.
+    ///
+    /// The whole match is redacted, unless the regex has a group named `secret`, then only the
+    /// group is.
+    pub patterns: Option<VecOrSingle<String>>,
+
+    /// ### redaction.env {#redaction-env}
+    ///
+    /// Additional names of environment variables (and settings) whose values are secrets, on top
+    /// of the ones that look like secrets.
+    pub env: Option<VecOrSingle<String>>,
+}
@@ -45,7 +45,9 @@ pub enum TargetFileConfig {
     },
 }
 
-fn make_simple_target_custom_schema(gen: &mut SchemaGenerator) -> schemars::schema::Schema {
+pub(crate) fn make_simple_target_custom_schema(
+    gen: &mut SchemaGenerator,
+) -> schemars::schema::Schema {
     // generate the schema for the Option<Target> like usual, then just push a string type to the
     // any_of.
     let mut schema: SchemaObject = <Option<Target>>::json_schema(gen).into();
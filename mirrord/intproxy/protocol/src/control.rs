@@ -0,0 +1,241 @@
+//! Protocol used by `mirrord toggle` to change the features (and the logs) of a running session,
+//! through the control socket of the internal proxy.
+//!
+//! Each control connection carries a single [`ControlRequest`], and the internal proxy responds
+//! with the [`SessionStatus`] after handling it.
+
+use std::fmt;
+
+use bincode::{Decode, Encode};
+use mirrord_protocol::fd_budget::FdUsage;
+
+/// Feature toggles that can be flipped while the session is running.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureToggle {
+    /// Removes the steal subscriptions from the agent, the traffic reaches the target as if
+    /// there was no session.
+    PauseSteal,
+    /// Restores the steal subscriptions, after [`FeatureToggle::PauseSteal`] or
+    /// [`FeatureToggle::StealToMirror`].
+    ResumeSteal,
+    /// Replaces the steal subscriptions with mirror subscriptions.
+    StealToMirror,
+    /// Makes the file operations that would modify the remote filesystem fail.
+    DisableFsWrites,
+    /// Reverts [`FeatureToggle::DisableFsWrites`].
+    EnableFsWrites,
+    /// Makes the logs of the internal proxy and the layers more verbose, up to
+    /// [`LogVerbosity::Trace`].
+    RaiseLogLevel,
+    /// Reverts [`FeatureToggle::RaiseLogLevel`], down to the configured log level.
+    LowerLogLevel,
+    /// Logs every message exchanged with the agent (in the internal proxy) and with the internal
+    /// proxy (in the layers), see [`PROTOCOL_TRACE_TARGET`].
+    EnableProtocolTracing,
+    /// Reverts [`FeatureToggle::EnableProtocolTracing`].
+    DisableProtocolTracing,
+}
+
+/// Target of the events enabled with [`FeatureToggle::EnableProtocolTracing`].
+pub const PROTOCOL_TRACE_TARGET: &str = "mirrord_protocol_trace";
+
+/// How verbose the logs of the internal proxy and the layers are, changed with
+/// [`FeatureToggle::RaiseLogLevel`] and [`FeatureToggle::LowerLogLevel`].
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogVerbosity {
+    /// The log level the session was started with.
+    #[default]
+    Configured,
+    /// `debug` level for all mirrord crates.
+    Debug,
+    /// `trace` level for all mirrord crates.
+    Trace,
+}
+
+impl fmt::Display for LogVerbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Configured => f.write_str("configured"),
+            Self::Debug => f.write_str("debug"),
+            Self::Trace => f.write_str("trace"),
+        }
+    }
+}
+
+/// Log settings of the session, sent by the internal proxy to the layers when they change.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LogSettings {
+    pub verbosity: LogVerbosity,
+    /// Whether the events of the [`PROTOCOL_TRACE_TARGET`] are enabled.
+    pub protocol_tracing: bool,
+}
+
+impl LogSettings {
+    /// Returns the log filter (in the `RUST_LOG` format) for these settings, based on the
+    /// `configured` filter.
+    pub fn filter(&self, configured: &str) -> String {
+        let level = match self.verbosity {
+            LogVerbosity::Configured => configured.to_string(),
+            LogVerbosity::Debug => "mirrord=debug".to_string(),
+            LogVerbosity::Trace => "mirrord=trace".to_string(),
+        };
+
+        // More specific than `mirrord`, so it overrides the level in both directions.
+        let protocol_tracing = if self.protocol_tracing {
+            "trace"
+        } else {
+            "off"
+        };
+        let protocol_tracing = format!("{PROTOCOL_TRACE_TARGET}={protocol_tracing}");
+
+        if level.is_empty() {
+            protocol_tracing
+        } else {
+            format!("{level},{protocol_tracing}")
+        }
+    }
+}
+
+/// A request sent to the control socket of the internal proxy.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRequest {
+    /// Only query the [`SessionStatus`].
+    Status,
+    /// Flip a [`FeatureToggle`].
+    Toggle(FeatureToggle),
+}
+
+/// How the internal proxy makes the steal subscriptions of the layers in the agent.
+///
+/// Subscriptions made in the mirror mode are not affected.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub enum StealState {
+    /// Steal subscriptions are made as requested by the layers.
+    #[default]
+    Active,
+    /// Steal subscriptions are not made.
+    Paused,
+    /// Steal subscriptions are made as mirror subscriptions.
+    Mirrored,
+}
+
+impl fmt::Display for StealState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Active => f.write_str("active"),
+            Self::Paused => f.write_str("paused"),
+            Self::Mirrored => f.write_str("mirrored"),
+        }
+    }
+}
+
+/// State of the [`FeatureToggle`]s of the session, sent in response to every
+/// [`ControlRequest`].
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionStatus {
+    pub steal: StealState,
+    /// Whether the file operations can modify the remote filesystem.
+    pub fs_writes: bool,
+    /// Whether the session was started in the read-only mode, where the steal subscriptions are
+    /// always mirrored and the file operations can't modify the remote filesystem.
+    pub read_only: bool,
+    pub log: LogSettings,
+    /// Remote descriptors that the agent keeps open for the session, [`None`] if the agent
+    /// doesn't report them.
+    pub fd_usage: Option<FdUsage>,
+}
+
+impl Default for SessionStatus {
+    fn default() -> Self {
+        Self {
+            steal: StealState::Active,
+            fs_writes: true,
+            read_only: false,
+            log: Default::default(),
+            fd_usage: None,
+        }
+    }
+}
+
+impl SessionStatus {
+    /// Status of a session started in the read-only mode.
+    pub fn read_only() -> Self {
+        Self {
+            steal: StealState::Mirrored,
+            fs_writes: false,
+            read_only: true,
+            log: Default::default(),
+            fd_usage: None,
+        }
+    }
+
+    /// Applies the [`FeatureToggle`] to this status.
+    ///
+    /// In a read-only session, the toggles can't enable stealing or file writes.
+    pub fn apply(&mut self, toggle: FeatureToggle) {
+        match toggle {
+            FeatureToggle::PauseSteal => self.steal = StealState::Paused,
+            FeatureToggle::ResumeSteal if self.read_only => self.steal = StealState::Mirrored,
+            FeatureToggle::ResumeSteal => self.steal = StealState::Active,
+            FeatureToggle::StealToMirror => self.steal = StealState::Mirrored,
+            FeatureToggle::DisableFsWrites => self.fs_writes = false,
+            FeatureToggle::EnableFsWrites => self.fs_writes = !self.read_only,
+            FeatureToggle::RaiseLogLevel => {
+                self.log.verbosity = match self.log.verbosity {
+                    LogVerbosity::Configured => LogVerbosity::Debug,
+                    LogVerbosity::Debug | LogVerbosity::Trace => LogVerbosity::Trace,
+                }
+            }
+            FeatureToggle::LowerLogLevel => {
+                self.log.verbosity = match self.log.verbosity {
+                    LogVerbosity::Configured | LogVerbosity::Debug => LogVerbosity::Configured,
+                    LogVerbosity::Trace => LogVerbosity::Debug,
+                }
+            }
+            FeatureToggle::EnableProtocolTracing => self.log.protocol_tracing = true,
+            FeatureToggle::DisableProtocolTracing => self.log.protocol_tracing = false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_only_toggles() {
+        let mut status = SessionStatus::read_only();
+
+        status.apply(FeatureToggle::PauseSteal);
+        assert_eq!(status.steal, StealState::Paused);
+
+        status.apply(FeatureToggle::ResumeSteal);
+        assert_eq!(status.steal, StealState::Mirrored);
+
+        status.apply(FeatureToggle::EnableFsWrites);
+        assert!(!status.fs_writes);
+        assert!(status.read_only);
+    }
+
+    #[test]
+    fn log_level_toggles() {
+        let mut status = SessionStatus::default();
+        assert_eq!(status.log.filter("warn"), "warn,mirrord_protocol_trace=off");
+
+        status.apply(FeatureToggle::RaiseLogLevel);
+        status.apply(FeatureToggle::RaiseLogLevel);
+        status.apply(FeatureToggle::RaiseLogLevel);
+        status.apply(FeatureToggle::EnableProtocolTracing);
+        assert_eq!(status.log.verbosity, LogVerbosity::Trace);
+        assert_eq!(
+            status.log.filter("warn"),
+            "mirrord=trace,mirrord_protocol_trace=trace"
+        );
+
+        status.apply(FeatureToggle::LowerLogLevel);
+        status.apply(FeatureToggle::LowerLogLevel);
+        status.apply(FeatureToggle::DisableProtocolTracing);
+        assert_eq!(status.log, LogSettings::default());
+        assert_eq!(status.log.filter(""), "mirrord_protocol_trace=off");
+    }
+}
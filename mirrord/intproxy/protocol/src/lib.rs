@@ -6,19 +6,24 @@ use std::{
     collections::HashMap,
     fmt,
     net::{IpAddr, SocketAddr},
+    path::PathBuf,
 };
 
 use bincode::{Decode, Encode};
+use control::LogSettings;
 use mirrord_protocol::{
-    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    dns::{GetAddrInfoRequestV2, GetAddrInfoResponse},
     file::*,
+    interfaces::{GetIfAddrsRequest, GetIfAddrsResponse},
     outgoing::SocketAddress,
+    output::OutputMessage,
     tcp::StealType,
     FileRequest, FileResponse, GetEnvVarsRequest, Port, RemoteResult,
 };
 
 #[cfg(feature = "codec")]
 pub mod codec;
+pub mod control;
 mod macros;
 
 /// An identifier for a message sent from the layer to the internal proxy.
@@ -39,18 +44,32 @@ pub struct LocalMessage<T> {
 pub enum LayerToProxyMessage {
     /// A request to start new `layer <-> proxy` session.
     /// This should be the first message sent by the layer after opening a new connection to the
-    /// internal proxy.
+    /// internal proxy, or the second one when the proxy requires
+    /// [`LayerToProxyMessage::Authenticate`].
     NewSession(NewSessionRequest),
+    /// The token generated for the session, sent right before [`LayerToProxyMessage::NewSession`]
+    /// when the internal proxy listens on a non-loopback address.
+    ///
+    /// The internal proxy does not respond, it closes the connection when the token is wrong.
+    Authenticate(String),
     /// A file operation request.
     File(FileRequest),
     /// A DNS request.
-    GetAddrInfo(GetAddrInfoRequest),
+    GetAddrInfo(GetAddrInfoRequestV2),
     /// A request to initiate a new outgoing connection.
     OutgoingConnect(OutgoingConnectRequest),
     /// Requests related to incoming connections.
     Incoming(IncomingRequest),
     /// Fetch environment variables from the target.
     GetEnv(GetEnvVarsRequest),
+    /// List the addresses of the network interfaces of the target.
+    GetIfAddrs(GetIfAddrsRequest),
+    /// Output of the local process, to be written to the output of the target.
+    Output(OutputMessage),
+    /// A request to send the contents of a remote file through an intercepted connection.
+    SendFileToConn(SendFileToConnRequest),
+    /// Requests of the `inotify` hooks.
+    Watch(WatchRequest),
 }
 
 /// Layer process information
@@ -127,6 +146,84 @@ pub struct OutgoingConnectRequest {
     pub protocol: NetProtocol,
 }
 
+/// A request to send the contents of a remote file through an intercepted TCP connection, with
+/// [`SendFileRequest`] done by the agent.
+///
+/// The data already written to the connection by the layer is sent to the agent first. When the
+/// agent can't do it, the internal proxy responds with
+/// [`ResponseError::NotImplemented`](mirrord_protocol::ResponseError::NotImplemented).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct SendFileToConnRequest {
+    /// The connection to send the file through.
+    pub connection: InterceptedConnection,
+    /// Remote fd of the file.
+    pub fd: u64,
+    /// [`None`] uses (and advances) the remote file offset.
+    pub offset: Option<u64>,
+    /// Max amount of bytes to send.
+    pub count: u64,
+}
+
+/// Identifies an `inotify` instance of the layer, see [`WatchInit`].
+pub type WatchInstanceId = u64;
+
+/// Requests of the `inotify` hooks, handled by the internal proxy with
+/// [`LayerWatch`](mirrord_protocol::watch::LayerWatch)es for the remote paths, and with its own
+/// `inotify` for the local ones.
+#[derive(Encode, Decode, Debug)]
+pub enum WatchRequest {
+    Init(WatchInitRequest),
+    Add(WatchAddRequest),
+    Remove(WatchRemoveRequest),
+}
+
+/// `inotify_init`, responded with a new [`WatchInit`].
+///
+/// When the agent doesn't support [`WATCH_VERSION`](mirrord_protocol::watch::WATCH_VERSION), the
+/// internal proxy responds with
+/// [`ResponseError::NotImplemented`](mirrord_protocol::ResponseError::NotImplemented).
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct WatchInitRequest;
+
+/// A new `inotify` instance.
+///
+/// The layer connects to the `address`, and reads the events of the instance from the
+/// connection, in the format of `inotify(7)`. The instance is gone when the layer closes the
+/// connection.
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct WatchInit {
+    pub instance: WatchInstanceId,
+    pub address: SocketAddr,
+}
+
+/// `inotify_add_watch`, responded with the watch descriptor.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct WatchAddRequest {
+    pub instance: WatchInstanceId,
+    pub path: PathBuf,
+    /// The `IN_*` bits of `inotify_add_watch`.
+    pub mask: u32,
+    /// Whether the `path` is remote, the local paths are watched by the internal proxy.
+    pub remote: bool,
+}
+
+/// `inotify_rm_watch`.
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct WatchRemoveRequest {
+    pub instance: WatchInstanceId,
+    pub watch_descriptor: i32,
+}
+
+/// Identifies an intercepted connection by the address of the internal proxy's socket, which the
+/// layer's socket is really connected to.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub enum InterceptedConnection {
+    /// An outgoing connection, with the [`OutgoingConnectResponse::layer_address`].
+    Outgoing(SocketAddress),
+    /// An incoming connection, with the peer address of the accepted socket.
+    Incoming(SocketAddr),
+}
+
 /// Requests related to incoming connections.
 #[derive(Encode, Decode, Debug)]
 pub enum IncomingRequest {
@@ -137,6 +234,12 @@ pub enum IncomingRequest {
     /// A request made by the layer when it accepts a connection on the socket that is listening
     /// for mirrored connections.
     ConnMetadata(ConnMetadataRequest),
+    /// A request made by the layer when it binds a socket that needs a port reserved in the
+    /// agent.
+    PortReserve(PortReserve),
+    /// A request made by the layer when it closes the socket that had a port reserved in the
+    /// agent.
+    PortRelease(PortRelease),
 }
 
 /// A request for additional metadata for accepted connection.
@@ -159,17 +262,20 @@ pub struct ConnMetadataResponse {
     ///
     /// # Note
     ///
-    /// Due to limitiations of the `intproxy <-> agent` protocol, HTTP connections will send the
-    /// real source (localhost).
+    /// HTTP connections stolen with a filter send the real source (localhost), unless the agent
+    /// supports [`mirrord_protocol::tcp::HTTP_CONNECTION_METADATA_VERSION`].
     pub remote_source: SocketAddr,
     /// Address of the local socket, provided by the agent. Meant to be exposed to the user instead
     /// of real source, which will always be localhost.
     ///
     /// # Note
     ///
-    /// Due to limitations of the `intproxy <-> agent` protocol, HTTP connections will send the
-    /// real address (localhost).
+    /// HTTP connections stolen with a filter send the real address (localhost), unless the agent
+    /// supports [`mirrord_protocol::tcp::HTTP_CONNECTION_METADATA_VERSION`].
     pub local_address: IpAddr,
+    /// Port of the connection in the cluster, provided by the agent. [`None`] when not known, in
+    /// which case the layer exposes the port that the user application listens on.
+    pub local_port: Option<Port>,
 }
 
 /// A request to start proxying incoming connections.
@@ -185,7 +291,7 @@ pub struct PortSubscribe {
 }
 
 /// Instructions for the internal proxy and the agent on how to execute port mirroring.
-#[derive(Encode, Decode, Debug, Clone)]
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub enum PortSubscription {
     /// Wrapped [`StealType`] specifies how to execute port mirroring.
     Steal(StealType),
@@ -202,6 +308,24 @@ pub struct PortUnsubscribe {
     pub listening_on: SocketAddr,
 }
 
+/// A request to reserve a port in the agent, see
+/// [`PortReservation`](mirrord_protocol::tcp::PortReservation).
+///
+/// Once the layer listens on the socket, it subscribes to the reserved port with
+/// [`StealType::Reserved`].
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct PortReserve {
+    /// Port to reserve on the remote pod, `0` lets the agent pick one.
+    pub port: Port,
+}
+
+/// A request to release a port reserved with [`PortReserve`].
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct PortRelease {
+    /// Port reserved on the remote pod.
+    pub port: Port,
+}
+
 /// Messages sent by the internal proxy and handled by the layer.
 #[derive(Encode, Decode, Debug)]
 pub enum ProxyToLayerMessage {
@@ -210,7 +334,7 @@ pub enum ProxyToLayerMessage {
     NewSession(LayerId),
     /// A response to layer's [`FileRequest`].
     File(FileResponse),
-    /// A response to layer's [`GetAddrInfoRequest`].
+    /// A response to layer's [`GetAddrInfoRequestV2`].
     GetAddrInfo(GetAddrInfoResponse),
     /// A response to layer's [`OutgoingConnectRequest`].
     OutgoingConnect(RemoteResult<OutgoingConnectResponse>),
@@ -218,6 +342,16 @@ pub enum ProxyToLayerMessage {
     Incoming(IncomingResponse),
     /// A response to layer's [`LayerToProxyMessage::GetEnv`].
     GetEnv(RemoteResult<HashMap<String, String>>),
+    /// A response to layer's [`GetIfAddrsRequest`].
+    GetIfAddrs(GetIfAddrsResponse),
+    /// A response to layer's [`SendFileToConnRequest`].
+    SendFileToConn(RemoteResult<SendFileResponse>),
+    /// A response to layer's [`WatchRequest`].
+    Watch(WatchResponse),
+    /// Not a response, sent to all layers when the [`LogSettings`] of the session change through
+    /// the control socket (and to new layers, if they were changed before). The message id is
+    /// meaningless.
+    LogSettings(LogSettings),
 }
 
 /// A response to layer's [`IncomingRequest`].
@@ -231,6 +365,16 @@ pub enum IncomingResponse {
     PortSubscribe(RemoteResult<()>),
     /// A response to layers' [`ConnMetadataRequest`].
     ConnMetadata(ConnMetadataResponse),
+    /// A response to layer's [`PortReserve`], with the reserved port.
+    PortReserve(RemoteResult<Port>),
+}
+
+/// A response to layer's [`WatchRequest`].
+#[derive(Encode, Decode, Debug)]
+pub enum WatchResponse {
+    Init(RemoteResult<WatchInit>),
+    Add(RemoteResult<i32>),
+    Remove(RemoteResult<()>),
 }
 
 /// A response to layer's [`OutgoingConnectRequest`].
@@ -310,6 +454,195 @@ impl_request!(
     res_path = ProxyToLayerMessage::File => FileResponse::ReadLink,
 );
 
+impl_request!(
+    req = CanonicalizePathRequest,
+    res = RemoteResult<CanonicalizePathResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::Canonicalize,
+    res_path = ProxyToLayerMessage::File => FileResponse::Canonicalize,
+);
+
+impl_request!(
+    req = UnlinkFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Unlink,
+    res_path = ProxyToLayerMessage::File => FileResponse::Unlink,
+);
+
+impl_request!(
+    req = UnlinkFileWithDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::UnlinkAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Unlink,
+);
+
+impl_request!(
+    req = RenameFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Rename,
+    res_path = ProxyToLayerMessage::File => FileResponse::Rename,
+);
+
+impl_request!(
+    req = RenameFileWithDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::RenameAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Rename,
+);
+
+impl_request!(
+    req = OpenAt2Request,
+    res = RemoteResult<OpenFileResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::OpenAt2,
+    res_path = ProxyToLayerMessage::File => FileResponse::Open,
+);
+
+impl_request!(
+    req = ChmodFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Chmod,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chmod,
+);
+
+impl_request!(
+    req = FchmodFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Fchmod,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chmod,
+);
+
+impl_request!(
+    req = ChmodFileWithDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::ChmodAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chmod,
+);
+
+impl_request!(
+    req = ChownFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Chown,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chown,
+);
+
+impl_request!(
+    req = FchownFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Fchown,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chown,
+);
+
+impl_request!(
+    req = ChownFileWithDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::ChownAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Chown,
+);
+
+impl_request!(
+    req = ReadWholeFileRequest,
+    res = RemoteResult<ReadWholeFileResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::ReadWhole,
+    res_path = ProxyToLayerMessage::File => FileResponse::ReadWhole,
+);
+
+impl_request!(
+    req = TruncateFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Truncate,
+    res_path = ProxyToLayerMessage::File => FileResponse::Truncate,
+);
+
+impl_request!(
+    req = FtruncateFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Ftruncate,
+    res_path = ProxyToLayerMessage::File => FileResponse::Truncate,
+);
+
+impl_request!(
+    req = GlobRequest,
+    res = RemoteResult<GlobResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::Glob,
+    res_path = ProxyToLayerMessage::File => FileResponse::Glob,
+);
+
+impl_request!(
+    req = SymlinkRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Symlink,
+    res_path = ProxyToLayerMessage::File => FileResponse::Symlink,
+);
+
+impl_request!(
+    req = SymlinkAtRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::SymlinkAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Symlink,
+);
+
+impl_request!(
+    req = LinkFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Link,
+    res_path = ProxyToLayerMessage::File => FileResponse::Link,
+);
+
+impl_request!(
+    req = LinkFileWithDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::LinkAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Link,
+);
+
+impl_request!(
+    req = UtimensFileWithDirRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::UtimensAt,
+    res_path = ProxyToLayerMessage::File => FileResponse::Utimens,
+);
+
+impl_request!(
+    req = FutimensFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Futimens,
+    res_path = ProxyToLayerMessage::File => FileResponse::Utimens,
+);
+
+impl_request!(
+    req = FsyncFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Fsync,
+    res_path = ProxyToLayerMessage::File => FileResponse::Fsync,
+);
+
+impl_request!(
+    req = FallocateFileRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Fallocate,
+    res_path = ProxyToLayerMessage::File => FileResponse::Fallocate,
+);
+
+impl_request!(
+    req = CopyFileRangeRequest,
+    res = RemoteResult<CopyFileRangeResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::CopyFileRange,
+    res_path = ProxyToLayerMessage::File => FileResponse::CopyFileRange,
+);
+
+impl_request!(
+    req = ReadVFileRequest,
+    res = RemoteResult<ReadVFileResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::ReadV,
+    res_path = ProxyToLayerMessage::File => FileResponse::ReadV,
+);
+
+impl_request!(
+    req = WriteVFileRequest,
+    res = RemoteResult<WriteFileResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::WriteV,
+    res_path = ProxyToLayerMessage::File => FileResponse::WriteV,
+);
+
 impl_request!(
     req = SeekFileRequest,
     res = RemoteResult<SeekFileResponse>,
@@ -345,6 +678,62 @@ impl_request!(
     res_path = ProxyToLayerMessage::File => FileResponse::Xstat,
 );
 
+impl_request!(
+    req = StatxRequest,
+    res = RemoteResult<StatxResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::Statx,
+    res_path = ProxyToLayerMessage::File => FileResponse::Statx,
+);
+
+impl_request!(
+    req = GetXattrRequest,
+    res = RemoteResult<GetXattrResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::GetXattr,
+    res_path = ProxyToLayerMessage::File => FileResponse::GetXattr,
+);
+
+impl_request!(
+    req = SetXattrRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::SetXattr,
+    res_path = ProxyToLayerMessage::File => FileResponse::SetXattr,
+);
+
+impl_request!(
+    req = ListXattrRequest,
+    res = RemoteResult<ListXattrResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::ListXattr,
+    res_path = ProxyToLayerMessage::File => FileResponse::ListXattr,
+);
+
+impl_request!(
+    req = RemoveXattrRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::RemoveXattr,
+    res_path = ProxyToLayerMessage::File => FileResponse::RemoveXattr,
+);
+
+impl_request!(
+    req = FlockRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::Flock,
+    res_path = ProxyToLayerMessage::File => FileResponse::Lock,
+);
+
+impl_request!(
+    req = SetLockRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::File => FileRequest::SetLock,
+    res_path = ProxyToLayerMessage::File => FileResponse::Lock,
+);
+
+impl_request!(
+    req = GetLockRequest,
+    res = RemoteResult<GetLockResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::GetLock,
+    res_path = ProxyToLayerMessage::File => FileResponse::GetLock,
+);
+
 impl_request!(
     req = XstatFsRequest,
     res = RemoteResult<XstatFsResponse>,
@@ -352,6 +741,13 @@ impl_request!(
     res_path = ProxyToLayerMessage::File => FileResponse::XstatFs,
 );
 
+impl_request!(
+    req = StatFsRequest,
+    res = RemoteResult<XstatFsResponse>,
+    req_path = LayerToProxyMessage::File => FileRequest::StatFs,
+    res_path = ProxyToLayerMessage::File => FileResponse::XstatFs,
+);
+
 impl_request!(
     req = FdOpenDirRequest,
     res = RemoteResult<OpenDirResponse>,
@@ -393,12 +789,14 @@ impl_request!(
 );
 
 impl_request!(
-    req = GetAddrInfoRequest,
+    req = GetAddrInfoRequestV2,
     res = GetAddrInfoResponse,
     req_path = LayerToProxyMessage::GetAddrInfo,
     res_path = ProxyToLayerMessage::GetAddrInfo,
 );
 
+impl_request!(req = OutputMessage, req_path = LayerToProxyMessage::Output,);
+
 impl_request!(
     req = OutgoingConnectRequest,
     res = RemoteResult<OutgoingConnectResponse>,
@@ -406,6 +804,13 @@ impl_request!(
     res_path = ProxyToLayerMessage::OutgoingConnect,
 );
 
+impl_request!(
+    req = SendFileToConnRequest,
+    res = RemoteResult<SendFileResponse>,
+    req_path = LayerToProxyMessage::SendFileToConn,
+    res_path = ProxyToLayerMessage::SendFileToConn,
+);
+
 impl_request!(
     req = PortSubscribe,
     res = RemoteResult<()>,
@@ -425,9 +830,49 @@ impl_request!(
     res_path = ProxyToLayerMessage::Incoming => IncomingResponse::ConnMetadata,
 );
 
+impl_request!(
+    req = PortReserve,
+    res = RemoteResult<Port>,
+    req_path = LayerToProxyMessage::Incoming => IncomingRequest::PortReserve,
+    res_path = ProxyToLayerMessage::Incoming => IncomingResponse::PortReserve,
+);
+
+impl_request!(
+    req = PortRelease,
+    req_path = LayerToProxyMessage::Incoming => IncomingRequest::PortRelease,
+);
+
 impl_request!(
     req = GetEnvVarsRequest,
     res = RemoteResult<HashMap<String, String>>,
     req_path = LayerToProxyMessage::GetEnv,
     res_path = ProxyToLayerMessage::GetEnv,
 );
+
+impl_request!(
+    req = GetIfAddrsRequest,
+    res = GetIfAddrsResponse,
+    req_path = LayerToProxyMessage::GetIfAddrs,
+    res_path = ProxyToLayerMessage::GetIfAddrs,
+);
+
+impl_request!(
+    req = WatchInitRequest,
+    res = RemoteResult<WatchInit>,
+    req_path = LayerToProxyMessage::Watch => WatchRequest::Init,
+    res_path = ProxyToLayerMessage::Watch => WatchResponse::Init,
+);
+
+impl_request!(
+    req = WatchAddRequest,
+    res = RemoteResult<i32>,
+    req_path = LayerToProxyMessage::Watch => WatchRequest::Add,
+    res_path = ProxyToLayerMessage::Watch => WatchResponse::Add,
+);
+
+impl_request!(
+    req = WatchRemoveRequest,
+    res = RemoteResult<()>,
+    req_path = LayerToProxyMessage::Watch => WatchRequest::Remove,
+    res_path = ProxyToLayerMessage::Watch => WatchResponse::Remove,
+);
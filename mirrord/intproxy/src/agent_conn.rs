@@ -12,6 +12,7 @@ use std::{
 
 use mirrord_analytics::Reporter;
 use mirrord_config::LayerConfig;
+use mirrord_intproxy_protocol::control::PROTOCOL_TRACE_TARGET;
 use mirrord_kube::{
     api::{
         kubernetes::{AgentKubernetesConnectInfo, KubernetesAPI},
@@ -214,6 +215,7 @@ impl BackgroundTask for AgentConnection {
                         break Ok(());
                     },
                     Some(msg) => {
+                        tracing::trace!(target: PROTOCOL_TRACE_TARGET, ?msg, "Sending to the agent");
                         if let Err(error) = self.send(msg).await {
                             tracing::error!(%error, "failed to send message to the agent");
                             break Err(error);
@@ -226,7 +228,10 @@ impl BackgroundTask for AgentConnection {
                         tracing::error!("failed to receive message from the agent, inner task down");
                         break Err(AgentChannelError);
                     }
-                    Some(msg) => message_bus.send(ProxyMessage::FromAgent(msg)).await,
+                    Some(msg) => {
+                        tracing::trace!(target: PROTOCOL_TRACE_TARGET, ?msg, "Received from the agent");
+                        message_bus.send(ProxyMessage::FromAgent(msg)).await
+                    }
                 }
             }
         }
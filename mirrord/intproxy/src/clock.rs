@@ -0,0 +1,121 @@
+//! Offset between the local clock and the clock of the agent, measured with
+//! [`ClientMessage::ClockProbe`](mirrord_protocol::ClientMessage::ClockProbe).
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use mirrord_protocol::clock::ClockProbeResponse;
+
+/// Offset between the clock of the agent and the local clock, measured with a single probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockOffset {
+    /// How far ahead of the local clock the clock of the agent is, in milliseconds. Negative when
+    /// the clock of the agent is behind.
+    pub offset_ms: i64,
+    /// Time between sending the probe and receiving the response.
+    ///
+    /// The agent could have handled the probe at any point of it, so half of it is the error of
+    /// [`Self::offset_ms`].
+    pub round_trip: Duration,
+}
+
+impl ClockOffset {
+    /// Computes the offset from the local time when the probe was `sent`, the local time when the
+    /// `response` was `received`, and the time of the agent from the `response`.
+    ///
+    /// Assumes that the agent handled the probe halfway through the round trip.
+    pub fn measure(sent: SystemTime, received: SystemTime, response: ClockProbeResponse) -> Self {
+        let round_trip = received.duration_since(sent).unwrap_or_default();
+        let local_time = sent + round_trip / 2;
+
+        let offset_ms = match response.time().duration_since(local_time) {
+            Ok(ahead) => i64::try_from(ahead.as_millis()).unwrap_or(i64::MAX),
+            Err(behind) => i64::try_from(behind.duration().as_millis())
+                .map(|behind| -behind)
+                .unwrap_or(i64::MIN),
+        };
+
+        Self {
+            offset_ms,
+            round_trip,
+        }
+    }
+
+    /// Whether the clocks differ by more than the given `threshold`, even taking the error of the
+    /// measurement into account.
+    pub fn exceeds(&self, threshold: Duration) -> bool {
+        u128::from(self.offset_ms.unsigned_abs()) > (threshold + self.round_trip / 2).as_millis()
+    }
+}
+
+impl fmt::Display for ClockOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.offset_ms < 0 {
+            "behind"
+        } else {
+            "ahead of"
+        };
+
+        write!(
+            f,
+            "the clock of the target is {:.3}s {direction} the local clock (±{}ms)",
+            self.offset_ms.unsigned_abs() as f64 / 1000.0,
+            (self.round_trip / 2).as_millis(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(time: SystemTime) -> ClockProbeResponse {
+        let unix_time_nanos = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+            .try_into()
+            .unwrap();
+
+        ClockProbeResponse { unix_time_nanos }
+    }
+
+    #[test]
+    fn offset_from_midpoint() {
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+        let received = sent + Duration::from_millis(200);
+
+        // Agent handled the probe at the midpoint, with the same clock.
+        let offset =
+            ClockOffset::measure(sent, received, response(sent + Duration::from_millis(100)));
+        assert_eq!(offset.offset_ms, 0);
+        assert_eq!(offset.round_trip, Duration::from_millis(200));
+
+        let ahead = ClockOffset::measure(sent, received, response(sent + Duration::from_secs(5)));
+        assert_eq!(ahead.offset_ms, 4_900);
+        assert!(ahead.exceeds(Duration::from_secs(1)));
+        assert!(!ahead.exceeds(Duration::from_secs(5)));
+
+        let behind = ClockOffset::measure(sent, received, response(sent - Duration::from_secs(2)));
+        assert_eq!(behind.offset_ms, -2_100);
+        assert!(behind.exceeds(Duration::from_millis(1_900)));
+        assert!(!behind.exceeds(Duration::from_secs(2)));
+        assert_eq!(
+            behind.to_string(),
+            "the clock of the target is 2.100s behind the local clock (±100ms)"
+        );
+    }
+
+    /// Offsets within the error of the measurement are not reported.
+    #[test]
+    fn slow_round_trip() {
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+        let received = sent + Duration::from_secs(3);
+
+        let offset = ClockOffset::measure(sent, received, response(sent + Duration::from_secs(3)));
+        assert_eq!(offset.offset_ms, 1_500);
+        assert!(!offset.exceeds(Duration::from_secs(1)));
+    }
+}
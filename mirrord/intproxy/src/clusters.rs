@@ -0,0 +1,146 @@
+//! Routing of the outgoing traffic between the agent of the session and the agents in the
+//! additional clusters (see [`ClusterConfig`](mirrord_config::cluster::ClusterConfig)).
+
+use std::{collections::HashMap, fmt, net::IpAddr};
+
+use mirrord_config::cluster::ClusterRoute;
+use mirrord_protocol::{dns::DnsLookup, outgoing::SocketAddress};
+
+/// Index of an additional cluster, in the order of
+/// [`IntProxy::with_cluster`](crate::IntProxy::with_cluster) calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClusterId(pub usize);
+
+impl fmt::Display for ClusterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Decides which agent handles the DNS queries and the outgoing connections of the layers.
+///
+/// The traffic that does not match any [`ClusterRoute`] goes through the agent of the session.
+#[derive(Default, Debug)]
+pub struct ClusterRouter {
+    /// Checked in order, the first match wins.
+    routes: Vec<(ClusterId, ClusterRoute)>,
+    /// Addresses resolved by the agents in the additional clusters, so that the connections to
+    /// them go through the same agent.
+    resolved: HashMap<IpAddr, ClusterId>,
+}
+
+impl ClusterRouter {
+    pub fn add_routes(&mut self, cluster: ClusterId, routes: Vec<ClusterRoute>) {
+        self.routes
+            .extend(routes.into_iter().map(|route| (cluster, route)));
+    }
+
+    /// Returns the cluster that should resolve the given host name.
+    pub fn route_host(&self, host: &str) -> Option<ClusterId> {
+        self.routes
+            .iter()
+            .find(|(_, route)| route.matches_host(host))
+            .map(|(cluster, _)| *cluster)
+    }
+
+    /// Returns the cluster that should handle the outgoing connection to the given address.
+    pub fn route_address(&self, address: &SocketAddress) -> Option<ClusterId> {
+        let SocketAddress::Ip(address) = address else {
+            return None;
+        };
+
+        self.resolved.get(&address.ip()).copied().or_else(|| {
+            self.routes
+                .iter()
+                .find(|(_, route)| route.matches_ip(address.ip()))
+                .map(|(cluster, _)| *cluster)
+        })
+    }
+
+    /// Remembers the addresses resolved by the agent in the given cluster, or by the agent of the
+    /// session when `cluster` is [`None`].
+    ///
+    /// The clusters may use the same private addresses, so the latest resolution wins.
+    pub fn resolved(&mut self, cluster: Option<ClusterId>, lookup: &DnsLookup) {
+        for record in &lookup.0 {
+            match cluster {
+                Some(cluster) => {
+                    self.resolved.insert(record.ip, cluster);
+                }
+                None => {
+                    self.resolved.remove(&record.ip);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, SocketAddr};
+
+    use mirrord_protocol::{
+        dns::{DnsLookup, LookupRecord},
+        outgoing::SocketAddress,
+    };
+
+    use super::{ClusterId, ClusterRouter};
+
+    fn router() -> ClusterRouter {
+        let mut router = ClusterRouter::default();
+        router.add_routes(
+            ClusterId(0),
+            vec!["*.b.svc".parse().unwrap(), "10.1.0.0/16".parse().unwrap()],
+        );
+        router.add_routes(ClusterId(1), vec!["c.svc".parse().unwrap()]);
+        router
+    }
+
+    fn address(ip: &str) -> SocketAddress {
+        SocketAddress::Ip(SocketAddr::new(ip.parse().unwrap(), 80))
+    }
+
+    fn lookup(ip: &str) -> DnsLookup {
+        DnsLookup(vec![LookupRecord {
+            name: "host".into(),
+            ip: ip.parse::<IpAddr>().unwrap(),
+        }])
+    }
+
+    #[test]
+    fn routes_by_rules() {
+        let router = router();
+
+        assert_eq!(router.route_host("api.b.svc"), Some(ClusterId(0)));
+        assert_eq!(router.route_host("c.svc"), Some(ClusterId(1)));
+        assert_eq!(router.route_host("a.svc"), None);
+
+        assert_eq!(
+            router.route_address(&address("10.1.2.3")),
+            Some(ClusterId(0))
+        );
+        assert_eq!(router.route_address(&address("10.2.2.3")), None);
+    }
+
+    /// Connections follow the agent that resolved the address, and the agent of the session takes
+    /// the address back when it resolves it later.
+    #[test]
+    fn routes_resolved_addresses() {
+        let mut router = router();
+
+        router.resolved(Some(ClusterId(1)), &lookup("10.2.0.1"));
+        assert_eq!(
+            router.route_address(&address("10.2.0.1")),
+            Some(ClusterId(1))
+        );
+
+        router.resolved(Some(ClusterId(1)), &lookup("10.1.0.1"));
+        assert_eq!(
+            router.route_address(&address("10.1.0.1")),
+            Some(ClusterId(1))
+        );
+
+        router.resolved(None, &lookup("10.2.0.1"));
+        assert_eq!(router.route_address(&address("10.2.0.1")), None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Local SOCKS5 and HTTP `CONNECT` proxy, which lets processes that don't run with the layer (e.g.
+//! a browser or a database client) make connections through the agent.
+//!
+//! The [`ConnectProxy`] acts as one more layer instance, with the reserved
+//! [`ConnectProxy::LAYER_ID`]: it resolves the host names and makes the connections with the same
+//! [`LayerToProxyMessage`]s, so they follow the `feature.network.dns` and
+//! `feature.network.outgoing` rules, and the routing between the clusters.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use mirrord_config::feature::network::NetworkConfig;
+use mirrord_intproxy_protocol::{
+    LayerId, LayerToProxyMessage, LocalMessage, MessageId, NetProtocol, OutgoingConnectRequest,
+    OutgoingConnectResponse, ProxyToLayerMessage,
+};
+use mirrord_protocol::{
+    dns::{GetAddrInfoRequestV2, GetAddrInfoResponse},
+    outgoing::SocketAddress,
+    ResponseError,
+};
+use thiserror::Error;
+use tokio::{
+    net::{self, TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+    task::JoinSet,
+};
+use tracing::Level;
+
+use self::{
+    handshake::{ConnectFailure, Handshake, HandshakeError},
+    rules::{ConnectRules, Resolution},
+};
+use crate::{
+    background_tasks::{BackgroundTask, MessageBus},
+    main_tasks::FromLayer,
+    ProxyMessage,
+};
+
+mod handshake;
+mod rules;
+
+#[derive(Error, Debug)]
+pub enum ConnectProxyError {
+    #[error("failed to accept proxy connection: {0}")]
+    Accept(io::Error),
+}
+
+/// Errors of a single proxy client connection, only logged.
+#[derive(Error, Debug)]
+enum ConnectionError {
+    #[error("handshake failed: {0}")]
+    Handshake(#[from] HandshakeError),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("agent error: {0}")]
+    Remote(#[from] ResponseError),
+    #[error("no address found for `{0}`")]
+    NoAddress(String),
+    #[error("internal proxy sent an unexpected response: {0:?}")]
+    UnexpectedResponse(ProxyToLayerMessage),
+    #[error("connect proxy is shutting down")]
+    Closed,
+}
+
+impl ConnectionError {
+    fn failure(&self) -> ConnectFailure {
+        match self {
+            Self::Io(error) if error.kind() == io::ErrorKind::ConnectionRefused => {
+                ConnectFailure::Refused
+            }
+            Self::Remote(..) | Self::NoAddress(..) => ConnectFailure::Unreachable,
+            _ => ConnectFailure::Failed,
+        }
+    }
+}
+
+/// Host requested by a proxy client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    Name(String),
+    Ip(IpAddr),
+}
+
+/// Destination requested by a proxy client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub host: Host,
+    pub port: u16,
+}
+
+/// Accepts SOCKS5 and HTTP `CONNECT` proxy connections.
+/// Run as a [`BackgroundTask`].
+///
+/// Failures of single connections are only logged, as they don't affect the session.
+pub struct ConnectProxy {
+    listener: TcpListener,
+    rules: Arc<ConnectRules>,
+}
+
+impl ConnectProxy {
+    /// [`LayerId`] used in the requests of this proxy, never assigned to a layer instance.
+    pub const LAYER_ID: LayerId = LayerId(u64::MAX);
+
+    /// Size of the channel for requests from the client connections.
+    const CHANNEL_SIZE: usize = 64;
+
+    pub fn new(listener: TcpListener, config: &NetworkConfig) -> Self {
+        Self {
+            listener,
+            rules: Arc::new(ConnectRules::new(config)),
+        }
+    }
+}
+
+impl BackgroundTask for ConnectProxy {
+    type Error = ConnectProxyError;
+    type MessageIn = LocalMessage<ProxyToLayerMessage>;
+    type MessageOut = ProxyMessage;
+
+    async fn run(self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        let (requests_tx, mut requests_rx) = mpsc::channel(Self::CHANNEL_SIZE);
+        let connector = Connector {
+            requests: requests_tx,
+            rules: self.rules,
+        };
+
+        let mut next_message_id: MessageId = 0;
+        let mut pending: HashMap<MessageId, oneshot::Sender<ProxyToLayerMessage>> =
+            Default::default();
+        let mut connections = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                msg = message_bus.recv() => match msg {
+                    None => {
+                        tracing::trace!("message bus closed, exiting");
+                        break Ok(());
+                    }
+                    Some(LocalMessage { message_id, inner }) => {
+                        if let Some(response_tx) = pending.remove(&message_id) {
+                            let _ = response_tx.send(inner);
+                        }
+                    }
+                },
+
+                accepted = self.listener.accept() => {
+                    let (stream, peer) = accepted.map_err(ConnectProxyError::Accept)?;
+                    connections.spawn(connector.clone().serve(stream, peer));
+                }
+
+                Some((message, response_tx)) = requests_rx.recv() => {
+                    let message_id = next_message_id;
+                    next_message_id += 1;
+                    pending.insert(message_id, response_tx);
+
+                    message_bus
+                        .send(FromLayer {
+                            message_id,
+                            layer_id: Self::LAYER_ID,
+                            message,
+                        })
+                        .await;
+                }
+
+                Some(..) = connections.join_next() => {}
+            }
+        }
+    }
+}
+
+/// Handles the proxy client connections, passing the requests to the [`ConnectProxy`].
+#[derive(Clone)]
+struct Connector {
+    requests: mpsc::Sender<(LayerToProxyMessage, oneshot::Sender<ProxyToLayerMessage>)>,
+    rules: Arc<ConnectRules>,
+}
+
+impl Connector {
+    async fn serve(self, stream: TcpStream, peer: SocketAddr) {
+        if let Err(error) = self.proxy(stream).await {
+            tracing::warn!(%peer, %error, "Connect proxy connection failed");
+        }
+    }
+
+    async fn proxy(&self, stream: TcpStream) -> Result<(), ConnectionError> {
+        let (handshake, destination) = Handshake::accept(stream).await?;
+
+        match self.connect(&destination).await {
+            Ok(mut remote) => {
+                let mut client = handshake.finish(Ok(())).await?;
+                tokio::io::copy_bidirectional(&mut client, &mut remote).await?;
+                Ok(())
+            }
+            Err(error) => {
+                handshake.finish(Err(error.failure())).await?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Connects to the [`Destination`], directly or through the agent, following the
+    /// [`ConnectRules`].
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    async fn connect(&self, destination: &Destination) -> Result<TcpStream, ConnectionError> {
+        let port = destination.port;
+
+        let (ip, resolved_remotely) = match &destination.host {
+            Host::Ip(ip) => (*ip, false),
+            Host::Name(name) => match self.rules.resolution(name, port) {
+                Resolution::Remote => (self.resolve_remote(name).await?, true),
+                Resolution::Override(ip) => (ip, false),
+                Resolution::Local => {
+                    let address = net::lookup_host((name.as_str(), port))
+                        .await?
+                        .next()
+                        .ok_or_else(|| ConnectionError::NoAddress(name.clone()))?;
+                    (address.ip(), false)
+                }
+            },
+        };
+
+        let address = SocketAddr::new(ip, port);
+        if self.rules.is_remote(&destination.host, address) {
+            return self.connect_remote(address).await;
+        }
+
+        match &destination.host {
+            // The remote address may not be reachable from here.
+            Host::Name(name) if resolved_remotely => {
+                Ok(TcpStream::connect((name.as_str(), port)).await?)
+            }
+            _ => Ok(TcpStream::connect(address).await?),
+        }
+    }
+
+    async fn request(
+        &self,
+        message: LayerToProxyMessage,
+    ) -> Result<ProxyToLayerMessage, ConnectionError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.requests
+            .send((message, response_tx))
+            .await
+            .map_err(|_| ConnectionError::Closed)?;
+        response_rx.await.map_err(|_| ConnectionError::Closed)
+    }
+
+    async fn resolve_remote(&self, name: &str) -> Result<IpAddr, ConnectionError> {
+        let response = self
+            .request(LayerToProxyMessage::GetAddrInfo(GetAddrInfoRequestV2 {
+                node: name.to_string(),
+                hints: None,
+            }))
+            .await?;
+
+        let ProxyToLayerMessage::GetAddrInfo(GetAddrInfoResponse(lookup)) = response else {
+            return Err(ConnectionError::UnexpectedResponse(response));
+        };
+
+        lookup?
+            .0
+            .first()
+            .map(|record| record.ip)
+            .ok_or_else(|| ConnectionError::NoAddress(name.to_string()))
+    }
+
+    async fn connect_remote(&self, address: SocketAddr) -> Result<TcpStream, ConnectionError> {
+        let response = self
+            .request(LayerToProxyMessage::OutgoingConnect(
+                OutgoingConnectRequest {
+                    remote_address: SocketAddress::Ip(address),
+                    protocol: NetProtocol::Stream,
+                },
+            ))
+            .await?;
+
+        let ProxyToLayerMessage::OutgoingConnect(result) = response else {
+            return Err(ConnectionError::UnexpectedResponse(response));
+        };
+
+        match result? {
+            OutgoingConnectResponse {
+                layer_address: SocketAddress::Ip(layer_address),
+                ..
+            } => Ok(TcpStream::connect(layer_address).await?),
+            other => Err(ConnectionError::UnexpectedResponse(
+                ProxyToLayerMessage::OutgoingConnect(Ok(other)),
+            )),
+        }
+    }
+}
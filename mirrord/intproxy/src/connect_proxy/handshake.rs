@@ -0,0 +1,353 @@
+//! Server side of the SOCKS5 ([RFC 1928](https://www.rfc-editor.org/rfc/rfc1928)) and HTTP
+//! `CONNECT` handshakes.
+
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use super::{Destination, Host};
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+/// Limit for the request line and headers of an HTTP `CONNECT` request.
+const MAX_HTTP_HEAD: u64 = 8 * 1024;
+
+#[derive(Error, Debug)]
+pub enum HandshakeError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("client does not support connecting without authentication")]
+    NoAcceptableAuth,
+    #[error("unsupported request: {0}")]
+    Unsupported(String),
+    #[error("invalid request: {0}")]
+    Invalid(String),
+}
+
+/// Why the connection to the [`Destination`] failed, reported to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectFailure {
+    Refused,
+    Unreachable,
+    Failed,
+}
+
+impl ConnectFailure {
+    fn socks_reply(self) -> u8 {
+        match self {
+            Self::Failed => 1,
+            Self::Unreachable => 4,
+            Self::Refused => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProxyKind {
+    Socks5,
+    HttpConnect,
+}
+
+/// A proxy client connection, between reading its request and replying to it.
+pub struct Handshake<S> {
+    stream: BufReader<S>,
+    kind: ProxyKind,
+}
+
+impl<S> Handshake<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Reads the request of the client, telling SOCKS5 from HTTP by the first byte.
+    ///
+    /// Unsupported requests are rejected here.
+    pub async fn accept(stream: S) -> Result<(Self, Destination), HandshakeError> {
+        let mut stream = BufReader::new(stream);
+
+        let first = stream.fill_buf().await?.first().copied();
+        let (kind, destination) = match first {
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Some(SOCKS_VERSION) => (ProxyKind::Socks5, read_socks5(&mut stream).await?),
+            Some(..) => (ProxyKind::HttpConnect, read_http(&mut stream).await?),
+        };
+
+        Ok((Self { stream, kind }, destination))
+    }
+
+    /// Replies to the request of the client.
+    ///
+    /// Returns the client stream, with the bytes the client may have sent after the request
+    /// still buffered.
+    pub async fn finish(mut self, result: Result<(), ConnectFailure>) -> io::Result<BufReader<S>> {
+        match (self.kind, result) {
+            (ProxyKind::Socks5, result) => {
+                let reply = result.err().map(ConnectFailure::socks_reply).unwrap_or(0);
+                socks5_reply(&mut self.stream, reply).await?;
+            }
+            (ProxyKind::HttpConnect, Ok(())) => {
+                http_reply(&mut self.stream, "200 Connection Established").await?;
+            }
+            (ProxyKind::HttpConnect, Err(..)) => {
+                http_reply(&mut self.stream, "502 Bad Gateway").await?;
+            }
+        }
+
+        Ok(self.stream)
+    }
+}
+
+async fn socks5_reply<S: AsyncWrite + Unpin>(stream: &mut S, reply: u8) -> io::Result<()> {
+    // The bound address is not known to the client, so we send an unspecified one.
+    stream
+        .write_all(&[SOCKS_VERSION, reply, 0, SOCKS_IPV4, 0, 0, 0, 0, 0, 0])
+        .await?;
+    stream.flush().await
+}
+
+async fn http_reply<S: AsyncWrite + Unpin>(stream: &mut S, status: &str) -> io::Result<()> {
+    stream
+        .write_all(format!("HTTP/1.1 {status}\r\n\r\n").as_bytes())
+        .await?;
+    stream.flush().await
+}
+
+async fn read_socks5<S>(stream: &mut BufReader<S>) -> Result<Destination, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let [_, methods_len] = read_array::<_, 2>(stream).await?;
+    let mut methods = vec![0; methods_len.into()];
+    stream.read_exact(&mut methods).await?;
+
+    if !methods.contains(&SOCKS_NO_AUTH) {
+        stream
+            .write_all(&[SOCKS_VERSION, SOCKS_NO_ACCEPTABLE_METHOD])
+            .await?;
+        stream.flush().await?;
+        return Err(HandshakeError::NoAcceptableAuth);
+    }
+    stream.write_all(&[SOCKS_VERSION, SOCKS_NO_AUTH]).await?;
+    stream.flush().await?;
+
+    let [version, command, _, address_type] = read_array::<_, 4>(stream).await?;
+    if version != SOCKS_VERSION {
+        return Err(HandshakeError::Invalid(format!("SOCKS version {version}")));
+    }
+    if command != SOCKS_CONNECT {
+        // Command not supported.
+        socks5_reply(stream, 7).await?;
+        return Err(HandshakeError::Unsupported(format!(
+            "SOCKS command {command}"
+        )));
+    }
+
+    let host = match address_type {
+        SOCKS_IPV4 => Host::Ip(Ipv4Addr::from(read_array::<_, 4>(stream).await?).into()),
+        SOCKS_IPV6 => Host::Ip(Ipv6Addr::from(read_array::<_, 16>(stream).await?).into()),
+        SOCKS_DOMAIN => {
+            let [len] = read_array::<_, 1>(stream).await?;
+            let mut name = vec![0; len.into()];
+            stream.read_exact(&mut name).await?;
+            let name = String::from_utf8(name)
+                .map_err(|_| HandshakeError::Invalid("SOCKS domain name".into()))?;
+            Host::Name(name)
+        }
+        other => {
+            // Address type not supported.
+            socks5_reply(stream, 8).await?;
+            return Err(HandshakeError::Unsupported(format!(
+                "SOCKS address type {other}"
+            )));
+        }
+    };
+    let port = stream.read_u16().await?;
+
+    Ok(Destination { host, port })
+}
+
+async fn read_array<S: AsyncRead + Unpin, const N: usize>(stream: &mut S) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+async fn read_http<S>(stream: &mut BufReader<S>) -> Result<Destination, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut head = (&mut *stream).take(MAX_HTTP_HEAD);
+
+    let mut request_line = String::new();
+    head.read_line(&mut request_line).await?;
+
+    loop {
+        let mut header = String::new();
+        head.read_line(&mut header).await?;
+        if !header.ends_with('\n') {
+            return Err(HandshakeError::Invalid(
+                "incomplete HTTP request head".into(),
+            ));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(authority), Some(_version)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        http_reply(stream, "400 Bad Request").await?;
+        return Err(HandshakeError::Invalid(format!(
+            "HTTP request line `{}`",
+            request_line.trim()
+        )));
+    };
+
+    if method != "CONNECT" {
+        http_reply(stream, "405 Method Not Allowed").await?;
+        return Err(HandshakeError::Unsupported(format!("HTTP method {method}")));
+    }
+
+    match parse_authority(authority) {
+        Some(destination) => Ok(destination),
+        None => {
+            http_reply(stream, "400 Bad Request").await?;
+            Err(HandshakeError::Invalid(format!(
+                "HTTP CONNECT authority `{authority}`"
+            )))
+        }
+    }
+}
+
+/// Parses the `host:port` from an HTTP `CONNECT` request.
+fn parse_authority(authority: &str) -> Option<Destination> {
+    if let Ok(address) = authority.parse::<SocketAddr>() {
+        return Some(Destination {
+            host: Host::Ip(address.ip()),
+            port: address.port(),
+        });
+    }
+
+    let (host, port) = authority.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    if host.is_empty() || host.contains([':', '[', ']']) {
+        return None;
+    }
+
+    let host = host
+        .parse()
+        .map(Host::Ip)
+        .unwrap_or_else(|_| Host::Name(host.to_string()));
+
+    Some(Destination { host, port })
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{ConnectFailure, Handshake, HandshakeError};
+    use crate::connect_proxy::{Destination, Host};
+
+    #[tokio::test]
+    async fn socks5_domain() {
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        client
+            .write_all(&[
+                5, 1, 0, 3, 7, b'a', b'p', b'i', b'.', b's', b'v', b'c', 0, 80,
+            ])
+            .await
+            .unwrap();
+        client.write_all(b"early").await.unwrap();
+
+        let (handshake, destination) = Handshake::accept(server).await.unwrap();
+        assert_eq!(
+            destination,
+            Destination {
+                host: Host::Name("api.svc".into()),
+                port: 80
+            }
+        );
+
+        let mut stream = handshake.finish(Ok(())).await.unwrap();
+        let mut reply = [0; 12];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, 0, 5, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+        let mut early = [0; 5];
+        stream.read_exact(&mut early).await.unwrap();
+        assert_eq!(&early, b"early");
+    }
+
+    #[tokio::test]
+    async fn socks5_requires_no_auth() {
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        // Only username/password authentication.
+        client.write_all(&[5, 1, 2]).await.unwrap();
+
+        let result = Handshake::accept(server).await;
+        assert!(matches!(result, Err(HandshakeError::NoAcceptableAuth)));
+
+        let mut reply = [0; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, 0xff]);
+    }
+
+    #[tokio::test]
+    async fn http_connect() {
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        client
+            .write_all(b"CONNECT [fd00::1]:443 HTTP/1.1\r\nHost: [fd00::1]:443\r\n\r\n")
+            .await
+            .unwrap();
+
+        let (handshake, destination) = Handshake::accept(server).await.unwrap();
+        assert_eq!(
+            destination,
+            Destination {
+                host: Host::Ip("fd00::1".parse().unwrap()),
+                port: 443
+            }
+        );
+
+        handshake
+            .finish(Err(ConnectFailure::Refused))
+            .await
+            .unwrap();
+        let mut reply = vec![];
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"HTTP/1.1 502 Bad Gateway\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn http_rejects_other_methods() {
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        client
+            .write_all(b"GET http://api.svc/ HTTP/1.1\r\nHost: api.svc\r\n\r\n")
+            .await
+            .unwrap();
+
+        let result = Handshake::accept(server).await;
+        assert!(matches!(result, Err(HandshakeError::Unsupported(..))));
+
+        let mut reply = vec![];
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"HTTP/1.1 405 Method Not Allowed\r\n\r\n");
+    }
+}
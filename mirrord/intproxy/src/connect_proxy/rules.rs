@@ -0,0 +1,230 @@
+//! The `feature.network.dns` and `feature.network.outgoing` rules, as applied by the
+//! [`ConnectProxy`](super::ConnectProxy).
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    ops::Deref,
+};
+
+use mirrord_config::feature::network::{
+    dns::{DnsFilterConfig, DnsOverride},
+    filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter},
+    outgoing::OutgoingFilterConfig,
+    NetworkConfig,
+};
+
+use super::Host;
+
+/// Where the [`ConnectProxy`](super::ConnectProxy) resolves a host name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Remote,
+    Local,
+    /// Fixed in `feature.network.dns.overrides`.
+    Override(IpAddr),
+}
+
+/// Decides how the [`ConnectProxy`](super::ConnectProxy) resolves the host names and makes the
+/// connections, following the config the same way the layer does.
+///
+/// Filters that fail to parse are skipped, as they are verified in the CLI.
+#[derive(Debug)]
+pub struct ConnectRules {
+    remote_dns: bool,
+    dns_filters: Vec<AddressFilter>,
+    /// Whether a query matching one of [`Self::dns_filters`] is resolved locally.
+    dns_filter_is_local: bool,
+    dns_overrides: HashMap<String, DnsOverride>,
+    remote_tcp: bool,
+    outgoing_filters: Vec<ProtocolAndAddressFilter>,
+    /// Whether a connection matching one of [`Self::outgoing_filters`] is made locally.
+    outgoing_filter_is_local: bool,
+}
+
+impl ConnectRules {
+    pub fn new(config: &NetworkConfig) -> Self {
+        let dns = &config.dns;
+        let (dns_filters, dns_filter_is_local) = match &dns.filter {
+            Some(DnsFilterConfig::Local(filters)) => (Some(filters.deref()), true),
+            Some(DnsFilterConfig::Remote(filters)) => (Some(filters.deref()), false),
+            None => (None, true),
+        };
+
+        let outgoing = &config.outgoing;
+        let (outgoing_filters, outgoing_filter_is_local) = match &outgoing.filter {
+            Some(OutgoingFilterConfig::Local(filters)) => (Some(filters.deref()), true),
+            Some(OutgoingFilterConfig::Remote(filters)) => (Some(filters.deref()), false),
+            None => (None, true),
+        };
+
+        Self {
+            remote_dns: dns.enabled,
+            dns_filters: dns_filters
+                .into_iter()
+                .flatten()
+                .filter_map(|filter| filter.parse().ok())
+                .collect(),
+            dns_filter_is_local,
+            dns_overrides: dns
+                .overrides
+                .iter()
+                .flatten()
+                .filter_map(|(node, value)| Some((node.clone(), value.parse().ok()?)))
+                .collect(),
+            remote_tcp: outgoing.tcp,
+            outgoing_filters: outgoing_filters
+                .into_iter()
+                .flatten()
+                .filter_map(|filter| filter.parse::<ProtocolAndAddressFilter>().ok())
+                .filter(|filter| filter.protocol != ProtocolFilter::Udp)
+                .collect(),
+            outgoing_filter_is_local,
+        }
+    }
+
+    /// Where the given host name should be resolved, when connecting to the given port.
+    pub fn resolution(&self, name: &str, port: u16) -> Resolution {
+        if !self.remote_dns {
+            return Resolution::Local;
+        }
+
+        match self.dns_overrides.get(name) {
+            Some(DnsOverride::Local) => return Resolution::Local,
+            Some(DnsOverride::Address(ip)) => return Resolution::Override(*ip),
+            None => {}
+        }
+
+        let matched = self
+            .dns_filters
+            .iter()
+            .filter(|filter| filter.port() == 0 || filter.port() == port)
+            .any(|filter| match filter {
+                AddressFilter::Port(..) => true,
+                AddressFilter::Name(filter_name, _) => filter_name == name,
+                AddressFilter::Socket(..) | AddressFilter::Subnet(..) => false,
+            });
+
+        if matched == self.dns_filter_is_local {
+            Resolution::Local
+        } else {
+            Resolution::Remote
+        }
+    }
+
+    /// Whether the connection to the `host`, resolved to the `address`, should go through the
+    /// agent.
+    ///
+    /// Connections to the loopback addresses are always made locally, as they target the
+    /// machine that runs the proxy client.
+    pub fn is_remote(&self, host: &Host, address: SocketAddr) -> bool {
+        if !self.remote_tcp || address.ip().is_loopback() {
+            return false;
+        }
+
+        let matched = self
+            .outgoing_filters
+            .iter()
+            .filter(|filter| filter.address.port() == 0 || filter.address.port() == address.port())
+            .any(|filter| match &filter.address {
+                AddressFilter::Port(..) => true,
+                AddressFilter::Name(name, _) => {
+                    matches!(host, Host::Name(host) if host.eq_ignore_ascii_case(name))
+                }
+                AddressFilter::Socket(socket) => {
+                    socket.ip().is_unspecified() || socket.ip() == address.ip()
+                }
+                AddressFilter::Subnet(subnet, _) => subnet.contains(&address.ip()),
+            });
+
+        matched != self.outgoing_filter_is_local
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use mirrord_config::{
+        config::{ConfigContext, MirrordConfig},
+        feature::network::{
+            dns::DnsFilterConfig, outgoing::OutgoingFilterConfig, NetworkConfig, NetworkFileConfig,
+        },
+        util::VecOrSingle,
+    };
+
+    use super::{ConnectRules, Host, Resolution};
+
+    fn config() -> NetworkConfig {
+        NetworkFileConfig::default()
+            .generate_config(&mut ConfigContext::default())
+            .unwrap()
+    }
+
+    fn filters(filters: &[&str]) -> VecOrSingle<String> {
+        VecOrSingle::Multiple(filters.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn resolution() {
+        let rules = ConnectRules::new(&config());
+        assert_eq!(rules.resolution("db.svc", 5432), Resolution::Remote);
+
+        let mut config = config();
+        config.dns.enabled = false;
+        let rules = ConnectRules::new(&config);
+        assert_eq!(rules.resolution("db.svc", 5432), Resolution::Local);
+
+        let mut config = self::config();
+        config.dns.filter = Some(DnsFilterConfig::Local(filters(&["db.svc", ":6379"])));
+        let rules = ConnectRules::new(&config);
+        assert_eq!(rules.resolution("db.svc", 5432), Resolution::Local);
+        assert_eq!(rules.resolution("cache.svc", 6379), Resolution::Local);
+        assert_eq!(rules.resolution("api.svc", 80), Resolution::Remote);
+
+        let mut config = self::config();
+        config.dns.filter = Some(DnsFilterConfig::Remote(filters(&["db.svc"])));
+        config.dns.overrides = Some(
+            [
+                ("api.svc".to_string(), "10.0.0.1".to_string()),
+                ("db.svc".to_string(), "local".to_string()),
+            ]
+            .into(),
+        );
+        let rules = ConnectRules::new(&config);
+        assert_eq!(rules.resolution("db.svc", 5432), Resolution::Local);
+        assert_eq!(
+            rules.resolution("api.svc", 80),
+            Resolution::Override("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(rules.resolution("cache.svc", 6379), Resolution::Local);
+    }
+
+    #[test]
+    fn is_remote() {
+        let host = Host::Name("api.svc".into());
+        let address = "10.0.0.1:80".parse::<SocketAddr>().unwrap();
+
+        let rules = ConnectRules::new(&config());
+        assert!(rules.is_remote(&host, address));
+        assert!(!rules.is_remote(&host, "127.0.0.1:80".parse().unwrap()));
+
+        let mut config = config();
+        config.outgoing.tcp = false;
+        assert!(!ConnectRules::new(&config).is_remote(&host, address));
+
+        let cases = [
+            (OutgoingFilterConfig::Local(filters(&["10.0.0.0/8"])), false),
+            (OutgoingFilterConfig::Local(filters(&[":443"])), true),
+            (OutgoingFilterConfig::Remote(filters(&[":443"])), false),
+            (OutgoingFilterConfig::Remote(filters(&["api.svc"])), true),
+            (OutgoingFilterConfig::Remote(filters(&["udp://:80"])), false),
+        ];
+        for (filter, expected) in cases {
+            let mut config = self::config();
+            config.outgoing.filter = Some(filter.clone());
+            let rules = ConnectRules::new(&config);
+            assert_eq!(rules.is_remote(&host, address), expected, "{filter:?}");
+        }
+    }
+}
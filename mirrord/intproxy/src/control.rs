@@ -0,0 +1,137 @@
+//! Control socket of the internal proxy, used by `mirrord toggle` to flip the
+//! [`FeatureToggle`](mirrord_intproxy_protocol::control::FeatureToggle)s of a running session.
+
+use std::{collections::HashMap, io, time::Duration};
+
+use mirrord_intproxy_protocol::{
+    codec::{self, AsyncEncoder, CodecError},
+    control::{ControlRequest, SessionStatus},
+};
+use thiserror::Error;
+use tokio::{
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+    time,
+};
+use tracing::Level;
+
+use crate::{
+    background_tasks::{BackgroundTask, MessageBus},
+    ProxyMessage,
+};
+
+#[derive(Error, Debug)]
+pub enum ControlServerError {
+    #[error("failed to accept control connection: {0}")]
+    Accept(io::Error),
+}
+
+/// Identifies a control connection accepted by the [`ControlServer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ControlId(pub u64);
+
+/// A [`ControlRequest`] received by the [`ControlServer`], handled by the
+/// [`IntProxy`](crate::IntProxy).
+#[derive(Debug)]
+pub struct FromControl {
+    pub id: ControlId,
+    pub request: ControlRequest,
+}
+
+/// Response to [`FromControl`], sent back to the [`ControlServer`].
+#[derive(Debug)]
+pub struct ToControl {
+    pub id: ControlId,
+    pub status: SessionStatus,
+}
+
+/// Accepts control connections and passes their [`ControlRequest`]s to the
+/// [`IntProxy`](crate::IntProxy).
+/// Run as a [`BackgroundTask`].
+///
+/// Failures of single connections are only logged, as they don't affect the session.
+pub struct ControlServer {
+    listener: TcpListener,
+    next_id: u64,
+    /// Connections waiting for their [`ToControl`].
+    connections: HashMap<ControlId, AsyncEncoder<SessionStatus, OwnedWriteHalf>>,
+}
+
+impl ControlServer {
+    /// How long we wait for the [`ControlRequest`] on a new connection.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            next_id: 0,
+            connections: Default::default(),
+        }
+    }
+
+    /// Reads the [`ControlRequest`] from the new connection.
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    async fn handle_new_stream(
+        &mut self,
+        stream: TcpStream,
+    ) -> Result<Option<FromControl>, CodecError> {
+        let (encoder, mut decoder) =
+            codec::make_async_framed::<SessionStatus, ControlRequest>(stream);
+        let request = time::timeout(Self::REQUEST_TIMEOUT, decoder.receive())
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+        let Some(request) = request else {
+            return Ok(None);
+        };
+
+        let id = ControlId(self.next_id);
+        self.next_id += 1;
+        self.connections.insert(id, encoder);
+
+        Ok(Some(FromControl { id, request }))
+    }
+
+    async fn respond(&mut self, response: ToControl) {
+        let Some(mut encoder) = self.connections.remove(&response.id) else {
+            return;
+        };
+
+        let result = match encoder.send(&response.status).await {
+            Ok(()) => encoder.flush().await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            tracing::warn!(%error, "Failed to respond on a control connection");
+        }
+    }
+}
+
+impl BackgroundTask for ControlServer {
+    type Error = ControlServerError;
+    type MessageIn = ToControl;
+    type MessageOut = ProxyMessage;
+
+    async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        loop {
+            tokio::select! {
+                msg = message_bus.recv() => match msg {
+                    None => {
+                        tracing::trace!("message bus closed, exiting");
+                        break Ok(());
+                    }
+                    Some(response) => self.respond(response).await,
+                },
+
+                res = self.listener.accept() => {
+                    let (stream, peer) = res.map_err(ControlServerError::Accept)?;
+                    match self.handle_new_stream(stream).await {
+                        Ok(Some(request)) => message_bus.send(request).await,
+                        Ok(None) => {}
+                        Err(error) => {
+                            tracing::warn!(%error, %peer, "Failed to read control request");
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
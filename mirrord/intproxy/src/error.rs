@@ -1,14 +1,17 @@
 use std::io;
 
 use mirrord_intproxy_protocol::{codec::CodecError, LayerToProxyMessage};
-use mirrord_protocol::DaemonMessage;
+use mirrord_protocol::{DaemonMessage, ErrorCode};
+use semver::Version;
 use thiserror::Error;
 
 use crate::{
     agent_conn::{AgentChannelError, AgentConnectionError},
+    connect_proxy::ConnectProxyError,
+    control::ControlServerError,
     layer_initializer::LayerInitializerError,
     ping_pong::PingPongError,
-    proxies::{incoming::IncomingProxyError, outgoing::OutgoingProxyError},
+    proxies::{incoming::IncomingProxyError, outgoing::OutgoingProxyError, watch::WatchProxyError},
     request_queue::RequestQueueEmpty,
     MainTaskId,
 };
@@ -26,6 +29,18 @@ pub enum IntProxyError {
     AgentConnection(#[from] AgentConnectionError),
     #[error("agent closed connection with error: {0}")]
     AgentFailed(String),
+    #[error(
+        "agent closed connection with error: {reason}, \
+        it uses mirrord-protocol {protocol_version}, which is older than the {} used by this \
+        mirrord version, so the error may be caused by a version mismatch, consider updating it",
+        *mirrord_protocol::VERSION
+    )]
+    OutdatedAgentFailed {
+        reason: String,
+        protocol_version: Version,
+    },
+    #[error("agent in cluster `{cluster}` closed connection with error: {reason}")]
+    ClusterAgentFailed { cluster: String, reason: String },
     #[error("agent sent unexpected message: {0:?}")]
     UnexpectedAgentMessage(DaemonMessage),
 
@@ -48,6 +63,41 @@ pub enum IntProxyError {
     OutgoingProxy(#[from] OutgoingProxyError),
     #[error("incoming proxy failed: {0}")]
     IncomingProxy(#[from] IncomingProxyError),
+    #[error("watch proxy failed: {0}")]
+    WatchProxy(#[from] WatchProxyError),
+    #[error("control server failed: {0}")]
+    ControlServer(#[from] ControlServerError),
+    #[error("connect proxy failed: {0}")]
+    ConnectProxy(#[from] ConnectProxyError),
+}
+
+impl IntProxyError {
+    /// Classifies this error for the tools that wrap mirrord, see [`ErrorCode`].
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::AgentConnection(..) | Self::AgentChannel(..) | Self::PingPong(..) => {
+                ErrorCode::AgentConnectionFailed
+            }
+            Self::AgentFailed(..) | Self::ClusterAgentFailed { .. } => ErrorCode::AgentFailed,
+            Self::OutdatedAgentFailed { .. } => ErrorCode::ProtocolVersionSkew,
+            Self::IncomingProxy(IncomingProxyError::SubscriptionFailed(error))
+            | Self::OutgoingProxy(OutgoingProxyError::ResponseError(error)) => error.code(),
+            Self::ConnectProxy(..) => ErrorCode::LocalIoFailed,
+            Self::ConnectionAcceptTimeout
+            | Self::ConnectionAccept(..)
+            | Self::UnexpectedLayerMessage(..)
+            | Self::UnexpectedAgentMessage(..)
+            | Self::TaskExit(..)
+            | Self::TaskPanic(..)
+            | Self::LayerInitializer(..)
+            | Self::LayerConnection(..)
+            | Self::SimpleProxy(..)
+            | Self::IncomingProxy(..)
+            | Self::OutgoingProxy(..)
+            | Self::WatchProxy(..)
+            | Self::ControlServer(..) => ErrorCode::Internal,
+        }
+    }
 }
 
 pub type Result<T> = core::result::Result<T, IntProxyError>;
@@ -1,12 +1,14 @@
-use std::io;
+use std::{io, sync::Arc, time::Duration};
 
+use futures::{stream::FuturesUnordered, StreamExt};
 use mirrord_intproxy_protocol::{
     codec::{AsyncDecoder, AsyncEncoder, CodecError},
     LayerId, LayerToProxyMessage, LocalMessage, NewSessionRequest, ProxyToLayerMessage,
 };
+use subtle::ConstantTimeEq;
 use thiserror::Error;
 use tokio::net::{TcpListener, TcpStream};
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 
 use crate::{
     background_tasks::{BackgroundTask, MessageBus},
@@ -24,40 +26,75 @@ pub enum LayerInitializerError {
     NoMessage,
     #[error("layer sent unexpected message: {0:?}")]
     UnexpectedMessage(LayerToProxyMessage),
+    #[error("peer failed to authenticate")]
+    Unauthenticated,
 }
 
 /// Handles logic for accepting new layer connections.
 /// Run as a [`BackgroundTask`].
+///
+/// The connections are initialized concurrently, so a peer that doesn't authenticate doesn't
+/// stop the other layers from connecting.
 #[derive(Debug)]
 pub struct LayerInitializer {
     listener: TcpListener,
     next_layer_id: LayerId,
+    /// Token the layers have to send in [`LayerToProxyMessage::Authenticate`], if any.
+    auth_token: Option<Arc<str>>,
 }
 
 impl LayerInitializer {
-    pub fn new(listener: TcpListener) -> Self {
+    /// How long a peer has to authenticate, before we drop its connection.
+    const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new(listener: TcpListener, auth_token: Option<String>) -> Self {
         Self {
             listener,
             next_layer_id: LayerId(0),
+            auth_token: auth_token.map(Arc::from),
         }
     }
 
-    /// Initialize connection with the new layer, assigning fresh [`LayerId`].
-    #[tracing::instrument(level = Level::TRACE, ret)]
-    async fn handle_new_stream(
-        &mut self,
+    /// Receives the [`LayerToProxyMessage::Authenticate`] from the peer, when `auth_token` is
+    /// set.
+    ///
+    /// The peer is not trusted yet, so every failure is reported as
+    /// [`LayerInitializerError::Unauthenticated`].
+    async fn authenticate(
+        auth_token: Option<&str>,
+        decoder: &mut AsyncDecoder<LocalMessage<LayerToProxyMessage>, TcpStream>,
+    ) -> Result<(), LayerInitializerError> {
+        let Some(auth_token) = auth_token else {
+            return Ok(());
+        };
+
+        match tokio::time::timeout(Self::AUTH_TIMEOUT, decoder.receive()).await {
+            Ok(Ok(Some(LocalMessage {
+                inner: LayerToProxyMessage::Authenticate(token),
+                ..
+            }))) if bool::from(token.as_bytes().ct_eq(auth_token.as_bytes())) => Ok(()),
+            _ => Err(LayerInitializerError::Unauthenticated),
+        }
+    }
+
+    /// Initialize connection with the new layer, that gets the [`LayerId`] `id`.
+    ///
+    /// Doesn't borrow `self`, so that many connections can be initialized at the same time.
+    #[tracing::instrument(level = Level::TRACE, ret, skip(auth_token))]
+    async fn initialize(
         stream: TcpStream,
+        id: LayerId,
+        auth_token: Option<Arc<str>>,
     ) -> Result<NewLayer, LayerInitializerError> {
         let mut decoder: AsyncDecoder<LocalMessage<LayerToProxyMessage>, _> =
             AsyncDecoder::new(stream);
+        Self::authenticate(auth_token.as_deref(), &mut decoder).await?;
+
         let msg = decoder
             .receive()
             .await?
             .ok_or(LayerInitializerError::NoMessage)?;
 
-        let id = self.next_layer_id;
-        self.next_layer_id.0 += 1;
-
         let parent_id = match msg.inner {
             LayerToProxyMessage::NewSession(NewSessionRequest::New(process_info)) => {
                 info!(?process_info, "new session");
@@ -93,6 +130,8 @@ impl BackgroundTask for LayerInitializer {
     type MessageOut = ProxyMessage;
 
     async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        let mut initializing = FuturesUnordered::new();
+
         loop {
             tokio::select! {
                 None = message_bus.recv() => {
@@ -102,8 +141,19 @@ impl BackgroundTask for LayerInitializer {
 
                 res = self.listener.accept() => {
                     let (stream, peer) = res.map_err(LayerInitializerError::Accept)?;
-                    match self.handle_new_stream(stream).await {
+                    let id = self.next_layer_id;
+                    self.next_layer_id.0 += 1;
+
+                    let initialize = Self::initialize(stream, id, self.auth_token.clone());
+                    initializing.push(async move { (peer, initialize.await) });
+                },
+
+                Some((peer, res)) = initializing.next() => {
+                    match res {
                         Ok(new_layer) => message_bus.send(new_layer).await,
+                        Err(LayerInitializerError::Unauthenticated) => {
+                            warn!(%peer, "Rejected a connection that failed to authenticate");
+                        }
                         Err(e) => {
                             tracing::error!("failed to initialize connection with peer {peer}: {e}");
                             break Err(e)
@@ -114,3 +164,129 @@ impl BackgroundTask for LayerInitializer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use mirrord_intproxy_protocol::ProcessInfo;
+
+    use super::*;
+    use crate::{
+        background_tasks::{BackgroundTasks, TaskUpdate},
+        error::IntProxyError,
+        main_tasks::MainTaskId,
+    };
+
+    async fn connect(
+        listener: &TcpListener,
+        messages: Vec<LayerToProxyMessage>,
+    ) -> (TcpStream, TcpStream) {
+        let client = send_messages(listener.local_addr().unwrap(), messages).await;
+        let (stream, _) = listener.accept().await.unwrap();
+        (client, stream)
+    }
+
+    /// Connects to the [`LayerInitializer`] at `addr`, and sends it the `messages`.
+    async fn send_messages(addr: SocketAddr, messages: Vec<LayerToProxyMessage>) -> TcpStream {
+        let mut encoder: AsyncEncoder<LocalMessage<LayerToProxyMessage>, _> =
+            AsyncEncoder::new(TcpStream::connect(addr).await.unwrap());
+        for inner in messages {
+            encoder
+                .send(&LocalMessage {
+                    message_id: 0,
+                    inner,
+                })
+                .await
+                .unwrap();
+        }
+        encoder.flush().await.unwrap();
+
+        encoder.into_inner()
+    }
+
+    fn new_session() -> LayerToProxyMessage {
+        LayerToProxyMessage::NewSession(NewSessionRequest::New(ProcessInfo {
+            pid: 1,
+            name: "app".into(),
+            cmdline: vec![],
+            loaded: true,
+        }))
+    }
+
+    #[tokio::test]
+    async fn layers_authenticate_with_token() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let token = Some(Arc::<str>::from("token"));
+
+        let (_client, stream) = connect(&listener, vec![new_session()]).await;
+        assert!(matches!(
+            LayerInitializer::initialize(stream, LayerId(0), token.clone()).await,
+            Err(LayerInitializerError::Unauthenticated)
+        ));
+
+        let (_client, stream) = connect(
+            &listener,
+            vec![
+                LayerToProxyMessage::Authenticate("wrong".into()),
+                new_session(),
+            ],
+        )
+        .await;
+        assert!(matches!(
+            LayerInitializer::initialize(stream, LayerId(0), token.clone()).await,
+            Err(LayerInitializerError::Unauthenticated)
+        ));
+
+        let (_client, stream) = connect(
+            &listener,
+            vec![
+                LayerToProxyMessage::Authenticate("token".into()),
+                new_session(),
+            ],
+        )
+        .await;
+        let new_layer = LayerInitializer::initialize(stream, LayerId(0), token)
+            .await
+            .unwrap();
+        assert_eq!(new_layer.id, LayerId(0));
+    }
+
+    /// A peer that connects and sends nothing doesn't stop the layers that connect after it.
+    #[tokio::test]
+    async fn silent_peer_does_not_block_layers() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
+            Default::default();
+        let _initializer = tasks.register(
+            LayerInitializer::new(listener, Some("token".into())),
+            MainTaskId::LayerInitializer,
+            8,
+        );
+
+        let _silent = TcpStream::connect(addr).await.unwrap();
+        let _layer = send_messages(
+            addr,
+            vec![
+                LayerToProxyMessage::Authenticate("token".into()),
+                new_session(),
+            ],
+        )
+        .await;
+
+        let update = tokio::time::timeout(LayerInitializer::AUTH_TIMEOUT / 2, tasks.next())
+            .await
+            .expect("the silent peer blocked the layer");
+        assert!(
+            matches!(
+                &update,
+                Some((
+                    MainTaskId::LayerInitializer,
+                    TaskUpdate::Message(ProxyMessage::NewLayer(NewLayer { id: LayerId(1), .. }))
+                ))
+            ),
+            "{update:?}"
+        );
+    }
+}
@@ -1,21 +1,55 @@
 #![feature(map_try_insert, let_chains)]
 #![warn(clippy::indexing_slicing)]
 
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    path::PathBuf,
+    time::Duration,
+};
 
 use background_tasks::{BackgroundTasks, TaskSender, TaskUpdate};
+use clusters::{ClusterId, ClusterRouter};
+use connect_proxy::ConnectProxy;
+use control::{ControlId, ControlServer, FromControl, ToControl};
+use futures::future::OptionFuture;
 use layer_conn::LayerConnection;
 use layer_initializer::LayerInitializer;
 use main_tasks::{FromLayer, LayerForked, MainTaskId, ProxyMessage, ToLayer};
-use mirrord_intproxy_protocol::{LayerId, LayerToProxyMessage, LocalMessage};
-use mirrord_protocol::{ClientMessage, DaemonMessage, LogLevel, CLIENT_READY_FOR_LOGS};
+use mirrord_config::{
+    cluster::ClusterRoute,
+    feature::network::{
+        incoming::{ForwardedHeadersConfig, StartupBufferConfig},
+        NetworkConfig,
+    },
+};
+use mirrord_intproxy_protocol::{
+    control::{ControlRequest, LogSettings, SessionStatus},
+    InterceptedConnection, LayerId, LayerToProxyMessage, LocalMessage, ProxyToLayerMessage,
+};
+use mirrord_protocol::{
+    compression::{CompressionSettings, COMPRESSION_VERSION},
+    dns::GetAddrInfoResponse,
+    fd_budget::{FdUsage, FdUsageRequest, FD_BUDGET_VERSION},
+    file::{ScratchDirRequest, SCRATCH_DIR_VERSION},
+    tcp::{
+        DaemonTcp, LayerTcpSteal, StealDrain, StealFallback, StealHandoff, STEAL_DRAIN_VERSION,
+        STEAL_FALLBACK_VERSION, STEAL_HANDOFF_VERSION,
+    },
+    ClientMessage, DaemonMessage, FileRequest, LogLevel, CLIENT_READY_FOR_LOGS,
+    FREEZE_TARGET_VERSION, READ_ONLY_VERSION,
+};
 use ping_pong::{AgentSentPong, PingPong};
 use proxies::{
     incoming::{IncomingProxy, IncomingProxyMessage},
     outgoing::{OutgoingProxy, OutgoingProxyMessage},
     simple::{SimpleProxy, SimpleProxyMessage},
+    watch::{WatchProxy, WatchProxyMessage},
+};
+use tokio::{
+    net::TcpListener,
+    time::{self, Instant},
 };
-use tokio::{net::TcpListener, time};
 use tracing::Level;
 
 use crate::{
@@ -25,6 +59,10 @@ use crate::{
 
 pub mod agent_conn;
 pub mod background_tasks;
+pub mod clock;
+pub mod clusters;
+pub mod connect_proxy;
+pub mod control;
 pub mod error;
 mod layer_conn;
 mod layer_initializer;
@@ -42,11 +80,27 @@ struct TaskTxs {
     simple: TaskSender<SimpleProxy>,
     outgoing: TaskSender<OutgoingProxy>,
     incoming: TaskSender<IncomingProxy>,
+    watch: TaskSender<WatchProxy>,
+    ping_pong: TaskSender<PingPong>,
+    control: Option<TaskSender<ControlServer>>,
+    connect_proxy: Option<TaskSender<ConnectProxy>>,
+    /// Indexed with [`ClusterId`]s.
+    clusters: Vec<ClusterTaskTxs>,
+}
+
+/// [`TaskSender`]s for the main background tasks that serve the agent in an additional cluster.
+/// See [`IntProxy::with_cluster`].
+struct ClusterTaskTxs {
+    name: String,
+    agent: TaskSender<AgentConnection>,
+    simple: TaskSender<SimpleProxy>,
+    outgoing: TaskSender<OutgoingProxy>,
     ping_pong: TaskSender<PingPong>,
 }
 
 /// This struct contains logic for proxying between multiple layer instances and one agent.
-/// It maintains a singe agent connection.
+/// It maintains a singe agent connection, and one more for each additional cluster (see
+/// [`Self::with_cluster`]).
 ///
 /// Utilizes multiple [`BackgroundTask`](background_tasks::BackgroundTask)s to split logic of
 /// different mirrod features (e.g. file operations and incoming traffic).
@@ -54,6 +108,53 @@ pub struct IntProxy {
     any_connection_accepted: bool,
     background_tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
     task_txs: TaskTxs,
+    /// Sent to the agent once it's known to support [`STEAL_HANDOFF_VERSION`].
+    steal_handoff: Option<StealHandoff>,
+    /// Sent to the agent once it's known to support [`STEAL_FALLBACK_VERSION`].
+    steal_fallback: Option<StealFallback>,
+    /// Sent to the [`IncomingProxy`] when this proxy starts running.
+    startup_buffer: Option<StartupBufferConfig>,
+    /// Sent to the agent once it's known to support [`SCRATCH_DIR_VERSION`].
+    scratch_dir: Option<ScratchDirRequest>,
+    /// Sent to the [`IncomingProxy`] when this proxy starts running.
+    proxy_protocol: bool,
+    /// Sent to the [`IncomingProxy`] when this proxy starts running.
+    forwarded_headers: Option<ForwardedHeadersConfig>,
+    /// Sent to the agent when the last layer exits, if the agent supports
+    /// [`STEAL_DRAIN_VERSION`].
+    steal_drain: Option<StealDrain>,
+    /// Whether to send [`ClientMessage::FreezeTarget`] to the agent, once it's known to support
+    /// [`FREEZE_TARGET_VERSION`].
+    freeze_target: bool,
+    /// Sent to the agent in [`ClientMessage::SwitchCompression`], once it's known to support
+    /// [`COMPRESSION_VERSION`].
+    compression: Option<CompressionSettings>,
+    /// Sent to the [`SimpleProxy`] when this proxy starts running.
+    metadata_cache_ttl: Option<Duration>,
+    /// Sent to the [`SimpleProxy`] when this proxy starts running.
+    prefetch: Vec<PathBuf>,
+    /// Version of [`mirrord_protocol`] negotiated with the agent.
+    agent_protocol_version: Option<semver::Version>,
+    /// Set when we sent [`LayerTcpSteal::Drain`] to the agent, until it responds with
+    /// [`DaemonTcp::Drained`]. This proxy does not exit in the meantime, unless the deadline
+    /// passes.
+    drain_deadline: Option<Instant>,
+    /// Feature toggles flipped through the [`ControlServer`].
+    status: SessionStatus,
+    /// Control requests that wait for the [`FdUsage`] of the agent to get their
+    /// [`SessionStatus`], oldest first.
+    pending_fd_usage: VecDeque<ControlId>,
+    /// Routes the DNS queries and outgoing connections to the agents in the additional clusters.
+    router: ClusterRouter,
+    /// Applies the [`LogSettings`] changed through the [`ControlServer`] to the logs of this
+    /// proxy, see [`Self::with_log_reload`].
+    log_reload: Option<LogReload>,
+}
+
+/// See [`IntProxy::with_log_reload`].
+struct LogReload {
+    configured: String,
+    reload: Box<dyn Fn(&str) + Send + Sync>,
 }
 
 impl IntProxy {
@@ -61,18 +162,27 @@ impl IntProxy {
     const CHANNEL_SIZE: usize = 512;
     /// How long can the agent connection remain silent.
     const PING_INTERVAL: Duration = Duration::from_secs(30);
+    /// How long we wait for [`DaemonTcp::Drained`] on top of the [`StealDrain::timeout_ms`].
+    const DRAIN_GRACE: Duration = Duration::from_secs(1);
 
     /// Creates a new [`IntProxy`] using existing [`AgentConnection`].
     /// The returned instance will accept connections from the layers using the given
     /// [`TcpListener`].
-    pub fn new_with_connection(agent_conn: AgentConnection, listener: TcpListener) -> Self {
+    ///
+    /// With an `auth_token`, the layers have to authenticate with
+    /// [`LayerToProxyMessage::Authenticate`] first.
+    pub fn new_with_connection(
+        agent_conn: AgentConnection,
+        listener: TcpListener,
+        auth_token: Option<String>,
+    ) -> Self {
         let mut background_tasks: BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError> =
             Default::default();
 
         let agent =
             background_tasks.register(agent_conn, MainTaskId::AgentConnection, Self::CHANNEL_SIZE);
         let layer_initializer = background_tasks.register(
-            LayerInitializer::new(listener),
+            LayerInitializer::new(listener, auth_token),
             MainTaskId::LayerInitializer,
             Self::CHANNEL_SIZE,
         );
@@ -96,6 +206,11 @@ impl IntProxy {
             MainTaskId::IncomingProxy,
             Self::CHANNEL_SIZE,
         );
+        let watch = background_tasks.register(
+            WatchProxy::default(),
+            MainTaskId::WatchProxy,
+            Self::CHANNEL_SIZE,
+        );
 
         Self {
             any_connection_accepted: false,
@@ -107,14 +222,205 @@ impl IntProxy {
                 simple,
                 outgoing,
                 incoming,
+                watch,
                 ping_pong,
+                control: None,
+                connect_proxy: None,
+                clusters: Default::default(),
             },
+            steal_handoff: None,
+            steal_fallback: None,
+            startup_buffer: None,
+            scratch_dir: None,
+            proxy_protocol: false,
+            forwarded_headers: None,
+            steal_drain: None,
+            freeze_target: false,
+            compression: None,
+            metadata_cache_ttl: None,
+            prefetch: Vec::new(),
+            agent_protocol_version: None,
+            drain_deadline: None,
+            status: Default::default(),
+            pending_fd_usage: Default::default(),
+            router: Default::default(),
+            log_reload: None,
         }
     }
 
+    /// Lets this session hand its steal subscriptions off to the next one with the same
+    /// [`StealHandoff::key`], and adopt the subscriptions of the previous one.
+    pub fn with_steal_handoff(mut self, handoff: StealHandoff) -> Self {
+        self.steal_handoff = Some(handoff);
+        self
+    }
+
+    /// Sets what the agent does with the requests stolen with this session's HTTP filter, when the
+    /// local application fails to respond to them.
+    pub fn with_steal_fallback(mut self, fallback: StealFallback) -> Self {
+        self.steal_fallback = Some(fallback);
+        self
+    }
+
+    /// Makes the [`IncomingProxy`] hold stolen requests until the local application is ready to
+    /// handle them.
+    pub fn with_startup_buffer(mut self, config: StartupBufferConfig) -> Self {
+        self.startup_buffer = Some(config);
+        self
+    }
+
+    /// Makes the agent redirect file operations on the paths that match the
+    /// [`ScratchDirRequest::patterns`] into a directory that is deleted when this session ends.
+    pub fn with_scratch_dir(mut self, scratch_dir: ScratchDirRequest) -> Self {
+        self.scratch_dir = Some(scratch_dir);
+        self
+    }
+
+    /// Makes the [`IncomingProxy`] prepend a PROXY protocol v2 header to the connections it makes
+    /// to the local application.
+    pub fn with_proxy_protocol(mut self) -> Self {
+        self.proxy_protocol = true;
+        self
+    }
+
+    /// Makes the [`IncomingProxy`] add the address of the original client to the stolen HTTP
+    /// requests, in the headers enabled in the [`ForwardedHeadersConfig`].
+    pub fn with_forwarded_headers(mut self, config: ForwardedHeadersConfig) -> Self {
+        self.forwarded_headers = Some(config);
+        self
+    }
+
+    /// Makes the agent drain this session's steal subscriptions when the last layer exits, letting
+    /// the in-flight stolen requests and connections finish before the subscriptions are removed.
+    pub fn with_steal_drain(mut self, drain: StealDrain) -> Self {
+        self.steal_drain = Some(drain);
+        self
+    }
+
+    /// Makes the agent freeze the target container while this session steals all traffic from any
+    /// port.
+    pub fn with_freeze_target(mut self) -> Self {
+        self.freeze_target = true;
+        self
+    }
+
+    /// Compresses the file payloads exchanged with the agent, when they're bigger than the
+    /// [`CompressionSettings::threshold`].
+    pub fn with_compression(mut self, settings: CompressionSettings) -> Self {
+        self.compression = Some(settings);
+        self
+    }
+
+    /// Makes the [`SimpleProxy`] answer the repeated `stat`, `readlink` and directory listings of
+    /// remote paths from a cache, keeping the results for `ttl`.
+    pub fn with_metadata_cache(mut self, ttl: Duration) -> Self {
+        self.metadata_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Makes the [`SimpleProxy`] read the remote trees at the given absolute `paths` ahead of
+    /// time, into the cache of [`Self::with_metadata_cache`].
+    pub fn with_prefetch(mut self, paths: Vec<PathBuf>) -> Self {
+        self.prefetch = paths;
+        self
+    }
+
+    /// Makes this session read-only: the steal subscriptions are made as mirror subscriptions, and
+    /// the file operations that would modify the remote filesystem fail. The [`ControlRequest`]s
+    /// can't revert this.
+    ///
+    /// The agent enforces this as well, if it supports [`READ_ONLY_VERSION`].
+    pub fn with_read_only(mut self) -> Self {
+        self.status = SessionStatus::read_only();
+        self
+    }
+
+    /// Connects this session to the agent in an additional cluster. The DNS queries and outgoing
+    /// connections that match the [`ClusterRoute`]s go through this agent instead, as well as the
+    /// connections to the addresses it resolves.
+    pub fn with_cluster(
+        mut self,
+        name: String,
+        agent_conn: AgentConnection,
+        routes: Vec<ClusterRoute>,
+    ) -> Self {
+        let id = ClusterId(self.task_txs.clusters.len());
+
+        let agent = self.background_tasks.register(
+            agent_conn,
+            MainTaskId::ClusterAgentConnection(id),
+            Self::CHANNEL_SIZE,
+        );
+        let simple = self.background_tasks.register(
+            SimpleProxy::default(),
+            MainTaskId::ClusterSimpleProxy(id),
+            Self::CHANNEL_SIZE,
+        );
+        let outgoing = self.background_tasks.register(
+            OutgoingProxy::default(),
+            MainTaskId::ClusterOutgoingProxy(id),
+            Self::CHANNEL_SIZE,
+        );
+        let ping_pong = self.background_tasks.register(
+            PingPong::new(Self::PING_INTERVAL),
+            MainTaskId::ClusterPingPong(id),
+            Self::CHANNEL_SIZE,
+        );
+
+        self.router.add_routes(id, routes);
+        self.task_txs.clusters.push(ClusterTaskTxs {
+            name,
+            agent,
+            simple,
+            outgoing,
+            ping_pong,
+        });
+        self
+    }
+
+    /// Accepts [`ControlRequest`]s on the given [`TcpListener`], letting `mirrord toggle` flip the
+    /// features of this session while it runs.
+    pub fn with_control(mut self, listener: TcpListener) -> Self {
+        let control = self.background_tasks.register(
+            ControlServer::new(listener),
+            MainTaskId::ControlServer,
+            Self::CHANNEL_SIZE,
+        );
+        self.task_txs.control = Some(control);
+        self
+    }
+
+    /// Lets the [`ControlRequest`]s change the logs of this proxy, `reload` is called with the new
+    /// filter (in the `RUST_LOG` format), based on the `configured` one.
+    ///
+    /// The layers are notified either way, and change their own logs.
+    pub fn with_log_reload<F>(mut self, configured: String, reload: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.log_reload = Some(LogReload {
+            configured,
+            reload: Box::new(reload),
+        });
+        self
+    }
+
+    /// Accepts SOCKS5 and HTTP `CONNECT` proxy connections on the given [`TcpListener`], and makes
+    /// them through the agent, following the same rules as the connections made by the layers.
+    pub fn with_connect_proxy(mut self, listener: TcpListener, config: &NetworkConfig) -> Self {
+        let connect_proxy = self.background_tasks.register(
+            ConnectProxy::new(listener, config),
+            MainTaskId::ConnectProxy,
+            Self::CHANNEL_SIZE,
+        );
+        self.task_txs.connect_proxy = Some(connect_proxy);
+        self
+    }
+
     /// Runs main event loop of this proxy.
     /// Expects to accept the first layer connection within the given `first_timeout`.
-    /// Exits after `idle_timeout` when there are no more layer connections.
+    /// Exits after `idle_timeout` when there are no more layer connections, and the agent finished
+    /// draining the steal subscriptions (see [`Self::with_steal_drain`]).
     pub async fn run(
         mut self,
         first_timeout: Duration,
@@ -127,6 +433,61 @@ impl IntProxy {
             ))
             .await;
 
+        for cluster in &self.task_txs.clusters {
+            cluster
+                .agent
+                .send(ClientMessage::SwitchProtocolVersion(
+                    mirrord_protocol::VERSION.clone(),
+                ))
+                .await;
+        }
+
+        if let Some(config) = self.startup_buffer.take() {
+            self.task_txs
+                .incoming
+                .send(IncomingProxyMessage::StartupBuffer(config))
+                .await;
+        }
+
+        if self.proxy_protocol {
+            self.task_txs
+                .incoming
+                .send(IncomingProxyMessage::ProxyProtocol)
+                .await;
+        }
+
+        if let Some(config) = self.forwarded_headers.take() {
+            self.task_txs
+                .incoming
+                .send(IncomingProxyMessage::ForwardedHeaders(config))
+                .await;
+        }
+
+        if let Some(ttl) = self.metadata_cache_ttl.take() {
+            self.task_txs
+                .simple
+                .send(SimpleProxyMessage::MetadataCache(ttl))
+                .await;
+        }
+
+        if !self.prefetch.is_empty() {
+            self.task_txs
+                .simple
+                .send(SimpleProxyMessage::Prefetch(mem::take(&mut self.prefetch)))
+                .await;
+        }
+
+        if self.status.read_only {
+            self.task_txs
+                .incoming
+                .send(IncomingProxyMessage::StealState(self.status.steal))
+                .await;
+            self.task_txs
+                .simple
+                .send(SimpleProxyMessage::FsWrites(self.status.fs_writes))
+                .await;
+        }
+
         loop {
             tokio::select! {
                 Some((task_id, task_update)) = self.background_tasks.next() => {
@@ -139,12 +500,17 @@ impl IntProxy {
                     }
                 },
 
-                _ = time::sleep(idle_timeout), if self.any_connection_accepted && self.task_txs.layers.is_empty() => {
+                _ = time::sleep(idle_timeout), if self.any_connection_accepted && self.task_txs.layers.is_empty() && self.drain_deadline.is_none() => {
                     if self.task_txs.layers.is_empty() {
                         tracing::trace!("intproxy timeout, no active connections. Exiting.");
                         break;
                     }
                 },
+
+                Some(()) = OptionFuture::from(self.drain_deadline.map(time::sleep_until)) => {
+                    tracing::warn!("Agent did not finish draining the steal subscriptions in time");
+                    self.drain_deadline = None;
+                },
             }
         }
 
@@ -170,6 +536,14 @@ impl IntProxy {
                     MainTaskId::LayerConnection(new_layer.id),
                     Self::CHANNEL_SIZE,
                 );
+                // Otherwise the layer would only get the settings with their next change.
+                if self.status.log != LogSettings::default() {
+                    tx.send(LocalMessage {
+                        message_id: 0,
+                        inner: ProxyToLayerMessage::LogSettings(self.status.log),
+                    })
+                    .await;
+                }
                 self.task_txs.layers.insert(new_layer.id, tx);
 
                 if let Some(parent) = new_layer.parent_id {
@@ -191,6 +565,9 @@ impl IntProxy {
             ProxyMessage::FromAgent(msg) => self.handle_agent_message(msg).await?,
             ProxyMessage::FromLayer(msg) => self.handle_layer_message(msg).await?,
             ProxyMessage::ToAgent(msg) => self.task_txs.agent.send(msg).await,
+            ProxyMessage::Control(FromControl { id, request }) => {
+                self.handle_control(id, request).await
+            }
             ProxyMessage::ToLayer(msg) => {
                 let ToLayer {
                     message,
@@ -198,12 +575,17 @@ impl IntProxy {
                     layer_id,
                 } = msg;
 
-                if let Some(tx) = self.task_txs.layers.get(&layer_id) {
-                    tx.send(LocalMessage {
-                        message_id,
-                        inner: message,
-                    })
-                    .await;
+                let message = LocalMessage {
+                    message_id,
+                    inner: message,
+                };
+
+                if layer_id == ConnectProxy::LAYER_ID {
+                    if let Some(tx) = &self.task_txs.connect_proxy {
+                        tx.send(message).await;
+                    }
+                } else if let Some(tx) = self.task_txs.layers.get(&layer_id) {
+                    tx.send(message).await;
                 }
             }
         }
@@ -223,6 +605,13 @@ impl IntProxy {
 
                 let msg = LayerClosed { id: LayerId(id) };
 
+                self.task_txs.layers.remove(&LayerId(id));
+
+                // Has to reach the agent before the port unsubscriptions from the incoming proxy.
+                if self.task_txs.layers.is_empty() {
+                    self.start_drain().await;
+                }
+
                 self.task_txs
                     .simple
                     .send(SimpleProxyMessage::LayerClosed(msg))
@@ -231,8 +620,6 @@ impl IntProxy {
                     .incoming
                     .send(IncomingProxyMessage::LayerClosed(msg))
                     .await;
-
-                self.task_txs.layers.remove(&LayerId(id));
             }
             (task_id, TaskUpdate::Finished(res)) => match res {
                 Ok(()) => {
@@ -248,19 +635,158 @@ impl IntProxy {
                     return Err(IntProxyError::TaskPanic(task_id));
                 }
             },
+            (
+                MainTaskId::ClusterAgentConnection(id),
+                TaskUpdate::Message(ProxyMessage::FromAgent(msg)),
+            ) => self.handle_cluster_agent_message(id, msg).await?,
+            (
+                MainTaskId::ClusterSimpleProxy(id)
+                | MainTaskId::ClusterOutgoingProxy(id)
+                | MainTaskId::ClusterPingPong(id),
+                TaskUpdate::Message(ProxyMessage::ToAgent(msg)),
+            ) => {
+                if let Some(cluster) = self.task_txs.clusters.get(id.0) {
+                    cluster.agent.send(msg).await;
+                }
+            }
+            (task_id, TaskUpdate::Message(ProxyMessage::ToLayer(msg))) => {
+                let cluster = match task_id {
+                    MainTaskId::ClusterSimpleProxy(id) => Some(id),
+                    _ => None,
+                };
+                if let ProxyToLayerMessage::GetAddrInfo(GetAddrInfoResponse(Ok(lookup))) =
+                    &msg.message
+                {
+                    self.router.resolved(cluster, lookup);
+                }
+
+                self.handle(ProxyMessage::ToLayer(msg)).await?
+            }
             (_, TaskUpdate::Message(msg)) => self.handle(msg).await?,
         }
 
         Ok(())
     }
 
+    /// Applies the [`ControlRequest`] and responds with the current [`SessionStatus`].
+    ///
+    /// When the agent supports [`FD_BUDGET_VERSION`], the response waits for the [`FdUsage`] of
+    /// the session.
+    async fn handle_control(&mut self, id: ControlId, request: ControlRequest) {
+        if let ControlRequest::Toggle(toggle) = request {
+            let previous = self.status;
+            self.status.apply(toggle);
+            tracing::info!(?toggle, status = ?self.status, "Feature toggled");
+
+            if previous.steal != self.status.steal {
+                self.task_txs
+                    .incoming
+                    .send(IncomingProxyMessage::StealState(self.status.steal))
+                    .await;
+            }
+
+            if previous.fs_writes != self.status.fs_writes {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::FsWrites(self.status.fs_writes))
+                    .await;
+            }
+
+            if previous.log != self.status.log {
+                self.apply_log_settings().await;
+            }
+        }
+
+        let fd_budget_supported = self
+            .agent_protocol_version
+            .as_ref()
+            .is_some_and(|version| FD_BUDGET_VERSION.matches(version));
+        if fd_budget_supported {
+            self.pending_fd_usage.push_back(id);
+            self.task_txs
+                .agent
+                .send(ClientMessage::FdUsageRequest(FdUsageRequest))
+                .await;
+        } else {
+            self.send_status(id, None).await;
+        }
+    }
+
+    /// Responds to the control request `id` with the current [`SessionStatus`].
+    async fn send_status(&self, id: ControlId, fd_usage: Option<FdUsage>) {
+        if let Some(control) = &self.task_txs.control {
+            control
+                .send(ToControl {
+                    id,
+                    status: SessionStatus {
+                        fd_usage,
+                        ..self.status
+                    },
+                })
+                .await;
+        }
+    }
+
+    /// Applies the [`LogSettings`] of the [`SessionStatus`] to the logs of this proxy, and sends
+    /// them to all layers.
+    async fn apply_log_settings(&self) {
+        if let Some(log_reload) = &self.log_reload {
+            (log_reload.reload)(&self.status.log.filter(&log_reload.configured));
+        }
+
+        for tx in self.task_txs.layers.values() {
+            tx.send(LocalMessage {
+                message_id: 0,
+                inner: ProxyToLayerMessage::LogSettings(self.status.log),
+            })
+            .await;
+        }
+    }
+
+    /// Sends [`LayerTcpSteal::Drain`] to the agent, if enabled with [`Self::with_steal_drain`] and
+    /// supported by the agent.
+    ///
+    /// If a new layer subscribes to a port before the drain is done, the agent cancels it.
+    async fn start_drain(&mut self) {
+        let Some(drain) = self.steal_drain.clone() else {
+            return;
+        };
+
+        let supported = self
+            .agent_protocol_version
+            .as_ref()
+            .is_some_and(|version| STEAL_DRAIN_VERSION.matches(version));
+        if !supported {
+            return;
+        }
+
+        tracing::debug!(?drain, "Draining steal subscriptions");
+
+        self.drain_deadline =
+            Some(Instant::now() + Duration::from_millis(drain.timeout_ms) + Self::DRAIN_GRACE);
+        self.task_txs
+            .agent
+            .send(ClientMessage::TcpSteal(LayerTcpSteal::Drain(drain)))
+            .await;
+    }
+
     /// Routes most messages from the agent to the correct background task.
     /// Some messages are handled here.
     #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
     async fn handle_agent_message(&mut self, message: DaemonMessage) -> Result<(), IntProxyError> {
         match message {
             DaemonMessage::Pong => self.task_txs.ping_pong.send(AgentSentPong).await,
-            DaemonMessage::Close(reason) => return Err(IntProxyError::AgentFailed(reason)),
+            DaemonMessage::Close(reason) => {
+                return Err(match self.agent_protocol_version.take() {
+                    Some(protocol_version) if protocol_version < *mirrord_protocol::VERSION => {
+                        IntProxyError::OutdatedAgentFailed {
+                            reason,
+                            protocol_version,
+                        }
+                    }
+                    _ => IntProxyError::AgentFailed(reason),
+                })
+            }
             DaemonMessage::TcpOutgoing(msg) => {
                 self.task_txs
                     .outgoing
@@ -291,6 +817,10 @@ impl IntProxy {
                     .send(IncomingProxyMessage::AgentMirror(msg))
                     .await
             }
+            DaemonMessage::TcpSteal(DaemonTcp::Drained) => {
+                tracing::debug!("Agent finished draining steal subscriptions");
+                self.drain_deadline = None;
+            }
             DaemonMessage::TcpSteal(msg) => {
                 self.task_txs
                     .incoming
@@ -302,6 +832,100 @@ impl IntProxy {
                     self.task_txs.agent.send(ClientMessage::ReadyForLogs).await;
                 }
 
+                if self.status.read_only {
+                    if READ_ONLY_VERSION.matches(&protocol_version) {
+                        self.task_txs.agent.send(ClientMessage::ReadOnly).await;
+                    } else {
+                        tracing::warn!(
+                            %protocol_version,
+                            "Agent does not support read-only sessions, \
+                            the session is only made read-only by the internal proxy"
+                        );
+                    }
+                }
+
+                if let Some(handoff) = self.steal_handoff.take() {
+                    if STEAL_HANDOFF_VERSION.matches(&protocol_version) {
+                        self.task_txs
+                            .agent
+                            .send(ClientMessage::TcpSteal(LayerTcpSteal::Handoff(handoff)))
+                            .await;
+                    } else {
+                        tracing::warn!(
+                            %protocol_version,
+                            "Agent does not support steal handoff, \
+                            subscriptions will not be handed off between sessions"
+                        );
+                    }
+                }
+
+                if let Some(fallback) = self.steal_fallback.take() {
+                    if STEAL_FALLBACK_VERSION.matches(&protocol_version) {
+                        self.task_txs
+                            .agent
+                            .send(ClientMessage::TcpSteal(LayerTcpSteal::Fallback(fallback)))
+                            .await;
+                    } else {
+                        tracing::warn!(
+                            %protocol_version,
+                            "Agent does not support HTTP filter fallback, \
+                            requests that the local application fails to respond to will fail"
+                        );
+                    }
+                }
+
+                if mem::take(&mut self.freeze_target) {
+                    if FREEZE_TARGET_VERSION.matches(&protocol_version) {
+                        self.task_txs.agent.send(ClientMessage::FreezeTarget).await;
+                    } else {
+                        tracing::warn!(
+                            %protocol_version,
+                            "Agent does not support freezing the target, \
+                            the target will keep running while the traffic is stolen"
+                        );
+                    }
+                }
+
+                if let Some(settings) = self.compression {
+                    if COMPRESSION_VERSION.matches(&protocol_version) {
+                        self.task_txs
+                            .agent
+                            .send(ClientMessage::SwitchCompression(settings))
+                            .await;
+                    } else {
+                        tracing::debug!(
+                            %protocol_version,
+                            "Agent does not support compression, \
+                            file payloads will be sent uncompressed"
+                        );
+                    }
+                }
+
+                if self.steal_drain.is_some() && !STEAL_DRAIN_VERSION.matches(&protocol_version) {
+                    tracing::warn!(
+                        %protocol_version,
+                        "Agent does not support draining steal subscriptions, \
+                        in-flight stolen requests will fail when the session ends"
+                    );
+                }
+
+                if let Some(scratch_dir) = self.scratch_dir.take() {
+                    if SCRATCH_DIR_VERSION.matches(&protocol_version) {
+                        self.task_txs
+                            .agent
+                            .send(ClientMessage::FileRequest(FileRequest::ScratchDir(
+                                scratch_dir,
+                            )))
+                            .await;
+                    } else {
+                        tracing::warn!(
+                            %protocol_version,
+                            "Agent does not support the scratch directory, \
+                            paths in `feature.fs.scratch` will be written to the target"
+                        );
+                    }
+                }
+
                 self.task_txs
                     .simple
                     .send(SimpleProxyMessage::ProtocolVersion(
@@ -309,6 +933,22 @@ impl IntProxy {
                     ))
                     .await;
 
+                self.agent_protocol_version = Some(protocol_version.clone());
+
+                self.task_txs
+                    .outgoing
+                    .send(OutgoingProxyMessage::AgentProtocolVersion(
+                        protocol_version.clone(),
+                    ))
+                    .await;
+
+                self.task_txs
+                    .watch
+                    .send(WatchProxyMessage::AgentProtocolVersion(
+                        protocol_version.clone(),
+                    ))
+                    .await;
+
                 self.task_txs
                     .incoming
                     .send(IncomingProxyMessage::AgentProtocolVersion(protocol_version))
@@ -324,6 +964,109 @@ impl IntProxy {
                     .send(SimpleProxyMessage::GetEnvRes(res))
                     .await
             }
+            DaemonMessage::GetIfAddrsResponse(res) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::GetIfAddrsRes(res))
+                    .await
+            }
+            DaemonMessage::Watch(msg) => {
+                self.task_txs
+                    .watch
+                    .send(WatchProxyMessage::Agent(msg))
+                    .await
+            }
+            DaemonMessage::IdleFds(idle) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::IdleFds(idle))
+                    .await
+            }
+            DaemonMessage::FdUsage(usage) => match self.pending_fd_usage.pop_front() {
+                Some(id) => self.send_status(id, Some(usage)).await,
+                None => {
+                    return Err(IntProxyError::UnexpectedAgentMessage(
+                        DaemonMessage::FdUsage(usage),
+                    ))
+                }
+            },
+            other => {
+                return Err(IntProxyError::UnexpectedAgentMessage(other));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the [`TaskSender`]s of the additional cluster picked by the [`ClusterRouter`].
+    fn cluster_for(&self, id: Option<ClusterId>) -> Option<&ClusterTaskTxs> {
+        let cluster = self.task_txs.clusters.get(id?.0)?;
+        tracing::trace!(cluster = cluster.name, "Routing to an additional cluster");
+        Some(cluster)
+    }
+
+    /// Routes messages from the agent in an additional cluster to its background tasks.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    async fn handle_cluster_agent_message(
+        &mut self,
+        id: ClusterId,
+        message: DaemonMessage,
+    ) -> Result<(), IntProxyError> {
+        let Some(cluster) = self.task_txs.clusters.get(id.0) else {
+            return Ok(());
+        };
+
+        match message {
+            DaemonMessage::Pong => cluster.ping_pong.send(AgentSentPong).await,
+            DaemonMessage::Close(reason) => {
+                return Err(IntProxyError::ClusterAgentFailed {
+                    cluster: cluster.name.clone(),
+                    reason,
+                })
+            }
+            DaemonMessage::TcpOutgoing(msg) => {
+                cluster
+                    .outgoing
+                    .send(OutgoingProxyMessage::AgentStream(msg))
+                    .await
+            }
+            DaemonMessage::UdpOutgoing(msg) => {
+                cluster
+                    .outgoing
+                    .send(OutgoingProxyMessage::AgentDatagrams(msg))
+                    .await
+            }
+            DaemonMessage::GetAddrInfoResponse(msg) => {
+                cluster
+                    .simple
+                    .send(SimpleProxyMessage::AddrInfoRes(msg))
+                    .await
+            }
+            DaemonMessage::IdleFds(idle) => {
+                cluster.simple.send(SimpleProxyMessage::IdleFds(idle)).await
+            }
+            DaemonMessage::SwitchProtocolVersionResponse(protocol_version) => {
+                if CLIENT_READY_FOR_LOGS.matches(&protocol_version) {
+                    cluster.agent.send(ClientMessage::ReadyForLogs).await;
+                }
+
+                if self.status.read_only && READ_ONLY_VERSION.matches(&protocol_version) {
+                    cluster.agent.send(ClientMessage::ReadOnly).await;
+                }
+
+                cluster
+                    .simple
+                    .send(SimpleProxyMessage::ProtocolVersion(protocol_version))
+                    .await;
+            }
+            DaemonMessage::LogMessage(log) => match log.level {
+                LogLevel::Error => {
+                    tracing::error!(cluster = cluster.name, "agent log: {}", log.message)
+                }
+                LogLevel::Warn => {
+                    tracing::warn!(cluster = cluster.name, "agent log: {}", log.message)
+                }
+            },
             other => {
                 return Err(IntProxyError::UnexpectedAgentMessage(other));
             }
@@ -349,14 +1092,21 @@ impl IntProxy {
                     .await;
             }
             LayerToProxyMessage::GetAddrInfo(req) => {
-                self.task_txs
-                    .simple
+                let simple = match self.cluster_for(self.router.route_host(&req.node)) {
+                    Some(cluster) => &cluster.simple,
+                    None => &self.task_txs.simple,
+                };
+                simple
                     .send(SimpleProxyMessage::AddrInfoReq(message_id, layer_id, req))
                     .await
             }
             LayerToProxyMessage::OutgoingConnect(req) => {
-                self.task_txs
-                    .outgoing
+                let outgoing =
+                    match self.cluster_for(self.router.route_address(&req.remote_address)) {
+                        Some(cluster) => &cluster.outgoing,
+                        None => &self.task_txs.outgoing,
+                    };
+                outgoing
                     .send(OutgoingProxyMessage::LayerConnect(
                         req, message_id, layer_id,
                     ))
@@ -370,12 +1120,52 @@ impl IntProxy {
                     ))
                     .await
             }
+            // The remote files are managed by the main agent.
+            LayerToProxyMessage::SendFileToConn(req) => match req.connection {
+                InterceptedConnection::Outgoing(..) => {
+                    self.task_txs
+                        .outgoing
+                        .send(OutgoingProxyMessage::LayerSendFile(
+                            req, message_id, layer_id,
+                        ))
+                        .await
+                }
+                InterceptedConnection::Incoming(..) => {
+                    self.task_txs
+                        .incoming
+                        .send(IncomingProxyMessage::LayerSendFile(
+                            message_id, layer_id, req,
+                        ))
+                        .await
+                }
+            },
             LayerToProxyMessage::GetEnv(req) => {
                 self.task_txs
                     .simple
                     .send(SimpleProxyMessage::GetEnvReq(message_id, layer_id, req))
                     .await
             }
+            LayerToProxyMessage::GetIfAddrs(req) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::GetIfAddrsReq(message_id, layer_id, req))
+                    .await
+            }
+            LayerToProxyMessage::Watch(req) => {
+                self.task_txs
+                    .watch
+                    .send(WatchProxyMessage::LayerRequest(message_id, layer_id, req))
+                    .await
+            }
+            LayerToProxyMessage::Output(..) if self.status.read_only => {
+                tracing::trace!("Session is read-only, dropping the output");
+            }
+            LayerToProxyMessage::Output(req) => {
+                self.task_txs
+                    .simple
+                    .send(SimpleProxyMessage::OutputReq(req))
+                    .await
+            }
             other => return Err(IntProxyError::UnexpectedLayerMessage(other)),
         }
 
@@ -4,6 +4,8 @@ use mirrord_intproxy_protocol::{LayerId, LayerToProxyMessage, MessageId, ProxyTo
 use mirrord_protocol::{ClientMessage, DaemonMessage};
 use tokio::net::TcpStream;
 
+use crate::{clusters::ClusterId, control::FromControl};
+
 /// Messages sent back to the [`IntProxy`](crate::IntProxy) from the main background tasks. See
 /// [`MainTaskId`].
 #[derive(Debug)]
@@ -18,6 +20,8 @@ pub enum ProxyMessage {
     FromLayer(FromLayer),
     /// New layer instance to serve.
     NewLayer(NewLayer),
+    /// Request received on the control socket.
+    Control(FromControl),
 }
 
 #[derive(Debug)]
@@ -72,6 +76,12 @@ impl From<NewLayer> for ProxyMessage {
     }
 }
 
+impl From<FromControl> for ProxyMessage {
+    fn from(value: FromControl) -> Self {
+        Self::Control(value)
+    }
+}
+
 /// Enumerated ids of main [`BackgroundTask`](crate::background_tasks::BackgroundTask)s used by
 /// [`IntProxy`](crate::IntProxy).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -83,6 +93,15 @@ pub enum MainTaskId {
     PingPong,
     AgentConnection,
     LayerConnection(LayerId),
+    ControlServer,
+    ConnectProxy,
+    WatchProxy,
+    /// Tasks that serve the agent in an additional cluster, see
+    /// [`IntProxy::with_cluster`](crate::IntProxy::with_cluster).
+    ClusterAgentConnection(ClusterId),
+    ClusterSimpleProxy(ClusterId),
+    ClusterOutgoingProxy(ClusterId),
+    ClusterPingPong(ClusterId),
 }
 
 impl fmt::Display for MainTaskId {
@@ -95,6 +114,13 @@ impl fmt::Display for MainTaskId {
             Self::AgentConnection => f.write_str("AGENT_CONNECTION"),
             Self::LayerConnection(id) => write!(f, "LAYER_CONNECTION {}", id.0),
             Self::IncomingProxy => f.write_str("INCOMING_PROXY"),
+            Self::ControlServer => f.write_str("CONTROL_SERVER"),
+            Self::ConnectProxy => f.write_str("CONNECT_PROXY"),
+            Self::WatchProxy => f.write_str("WATCH_PROXY"),
+            Self::ClusterAgentConnection(id) => write!(f, "CLUSTER_AGENT_CONNECTION {id}"),
+            Self::ClusterSimpleProxy(id) => write!(f, "CLUSTER_SIMPLE_PROXY {id}"),
+            Self::ClusterOutgoingProxy(id) => write!(f, "CLUSTER_OUTGOING_PROXY {id}"),
+            Self::ClusterPingPong(id) => write!(f, "CLUSTER_PING_PONG {id}"),
         }
     }
 }
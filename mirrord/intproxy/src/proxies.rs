@@ -3,4 +3,6 @@
 
 pub mod incoming;
 pub mod outgoing;
+pub mod send_file;
 pub mod simple;
+pub mod watch;
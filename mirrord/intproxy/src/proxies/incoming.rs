@@ -7,21 +7,24 @@ use std::{
 };
 
 use bytes::Bytes;
-use futures::StreamExt;
+use futures::{future::OptionFuture, StreamExt};
 use http::RETRY_ON_RESET_ATTEMPTS;
 use http_body_util::StreamBody;
-use hyper::body::Frame;
+use hyper::{body::Frame, StatusCode};
+use mirrord_config::feature::network::incoming::{ForwardedHeadersConfig, StartupBufferConfig};
 use mirrord_intproxy_protocol::{
-    ConnMetadataRequest, ConnMetadataResponse, IncomingRequest, IncomingResponse, LayerId,
-    MessageId, PortSubscribe, PortSubscription, PortUnsubscribe, ProxyToLayerMessage,
+    control::StealState, ConnMetadataRequest, ConnMetadataResponse, IncomingRequest,
+    IncomingResponse, InterceptedConnection, LayerId, MessageId, PortRelease, PortReserve,
+    PortSubscribe, PortSubscription, PortUnsubscribe, ProxyToLayerMessage, SendFileToConnRequest,
 };
 use mirrord_protocol::{
     body_chunks::BodyExt,
+    file::SEND_FILE_VERSION,
     tcp::{
         ChunkedHttpBody, ChunkedHttpError, ChunkedRequest, ChunkedResponse, DaemonTcp, HttpRequest,
         HttpRequestFallback, HttpResponse, HttpResponseFallback, InternalHttpBodyFrame,
         InternalHttpRequest, InternalHttpResponse, LayerTcpSteal, NewTcpConnection,
-        ReceiverStreamBody, StreamingBody, TcpData,
+        ReceiverStreamBody, StreamingBody, TcpData, PORT_RESERVATION_VERSION,
     },
     ClientMessage, ConnectionId, RequestId, ResponseError,
 };
@@ -34,19 +37,29 @@ use tokio_stream::{wrappers::ReceiverStream, StreamMap, StreamNotifyClose};
 use tracing::{debug, Level};
 
 use self::{
-    interceptor::{Interceptor, InterceptorError, MessageOut},
+    forwarded::add_forwarded_headers,
+    interceptor::{Interceptor, InterceptorError, MessageIn, MessageOut},
     port_subscription_ext::PortSubscriptionExt,
+    proxy_protocol::ProxyHeader,
+    reservations::{ReservationMessage, ReservationsManager},
+    startup_buffer::StartupBuffer,
     subscriptions::SubscriptionsManager,
 };
 use crate::{
     background_tasks::{BackgroundTask, BackgroundTasks, MessageBus, TaskSender, TaskUpdate},
     main_tasks::{LayerClosed, LayerForked, ToLayer},
+    proxies::send_file::SendFileQueue,
+    request_queue::RequestQueueEmpty,
     ProxyMessage,
 };
 
+mod forwarded;
 mod http;
 mod interceptor;
 pub mod port_subscription_ext;
+mod proxy_protocol;
+mod reservations;
+mod startup_buffer;
 mod subscriptions;
 
 /// Creates and binds a new [`TcpSocket`].
@@ -81,6 +94,25 @@ fn bind_similar(addr: SocketAddr) -> io::Result<TcpSocket> {
     }
 }
 
+/// Creates a new [`TcpSocket`] bound to exactly the given `addr`.
+fn bind_to(addr: SocketAddr) -> io::Result<TcpSocket> {
+    let socket = match addr {
+        SocketAddr::V4(..) => TcpSocket::new_v4()?,
+        SocketAddr::V6(..) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    Ok(socket)
+}
+
+/// Builds the [`ProxyHeader`] of an intercepted connection from its addresses in the cluster.
+fn proxy_header(connection: &NewTcpConnection) -> ProxyHeader {
+    ProxyHeader::Proxy {
+        source: SocketAddr::new(connection.remote_address, connection.source_port),
+        destination: SocketAddr::new(connection.local_address, connection.destination_port),
+    }
+}
+
 /// Id of a single [`Interceptor`] task. Used to manage interceptor tasks with the
 /// [`BackgroundTasks`] struct.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -99,17 +131,30 @@ pub enum IncomingProxyError {
     Io(#[from] io::Error),
     #[error("subscribing port failed: {0}")]
     SubscriptionFailed(ResponseError),
+    /// The agent sent a [`DaemonTcp::SendFile`] response, but there was no request waiting for
+    /// it. This should never happen.
+    #[error("failed to match send file response: {0}")]
+    RequestQueueEmpty(#[from] RequestQueueEmpty),
 }
 
 /// Messages consumed by [`IncomingProxy`] running as a [`BackgroundTask`].
 pub enum IncomingProxyMessage {
     LayerRequest(MessageId, LayerId, IncomingRequest),
+    LayerSendFile(MessageId, LayerId, SendFileToConnRequest),
     LayerForked(LayerForked),
     LayerClosed(LayerClosed),
     AgentMirror(DaemonTcp),
     AgentSteal(DaemonTcp),
     /// Agent responded to [`ClientMessage::SwitchProtocolVersion`].
     AgentProtocolVersion(semver::Version),
+    /// Enables the [`StartupBuffer`].
+    StartupBuffer(StartupBufferConfig),
+    /// Enables sending a [`ProxyHeader`] to the user application.
+    ProxyProtocol,
+    /// Enables adding the forwarding headers to the stolen HTTP requests.
+    ForwardedHeaders(ForwardedHeadersConfig),
+    /// Changes how the steal subscriptions are made in the agent.
+    StealState(StealState),
 }
 
 /// Handle for an [`Interceptor`].
@@ -134,6 +179,7 @@ impl MetadataStore {
             .unwrap_or_else(|| ConnMetadataResponse {
                 remote_source: req.peer_address,
                 local_address: req.listener_address.ip(),
+                local_port: None,
             })
     }
 
@@ -142,6 +188,37 @@ impl MetadataStore {
         self.prepared_responses.insert(req, res);
     }
 
+    /// Expects a [`ConnMetadataRequest`] from the [`Interceptor`] of the given
+    /// [`NewTcpConnection`], and prepares the response with its original addresses.
+    fn expect_connection(
+        &mut self,
+        connection: &NewTcpConnection,
+        listener_address: SocketAddr,
+        interceptor_address: SocketAddr,
+    ) {
+        self.expect(
+            ConnMetadataRequest {
+                listener_address,
+                peer_address: interceptor_address,
+            },
+            InterceptorId(connection.connection_id),
+            ConnMetadataResponse {
+                remote_source: SocketAddr::new(connection.remote_address, connection.source_port),
+                local_address: connection.local_address,
+                local_port: Some(connection.destination_port),
+            },
+        );
+    }
+
+    /// Returns the [`Interceptor`] that connected to the user application from the given
+    /// `address`.
+    fn interceptor_at(&self, address: SocketAddr) -> Option<InterceptorId> {
+        self.expected_requests
+            .iter()
+            .find(|(_, req)| req.peer_address == address)
+            .map(|(id, _)| *id)
+    }
+
     fn no_longer_expect(&mut self, from: InterceptorId) {
         let Some(req) = self.expected_requests.remove(&from) else {
             return;
@@ -164,18 +241,31 @@ impl MetadataStore {
 pub struct IncomingProxy {
     /// Active port subscriptions for all layers.
     subscriptions: SubscriptionsManager,
+    /// Ports reserved in the agent for all layers.
+    reservations: ReservationsManager,
     /// [`TaskSender`]s for active [`Interceptor`]s.
     interceptors: HashMap<InterceptorId, InterceptorHandle>,
     /// For receiving updates from [`Interceptor`]s.
     background_tasks: BackgroundTasks<InterceptorId, MessageOut, InterceptorError>,
     /// For managing intercepted connections metadata.
     metadata_store: MetadataStore,
+    /// Addresses of filtered HTTP connections received in [`DaemonTcp::NewHttpConnection`], until
+    /// they're closed.
+    http_connections: HashMap<ConnectionId, NewTcpConnection>,
     /// For managing streamed [`DaemonTcp::HttpRequestChunked`] request channels.
     request_body_txs: HashMap<(ConnectionId, RequestId), Sender<InternalHttpBodyFrame>>,
     /// For managing streamed [`LayerTcpSteal::HttpResponseChunked`] response streams.
     response_body_rxs: StreamMap<(ConnectionId, RequestId), StreamNotifyClose<ReceiverStreamBody>>,
     /// Version of [`mirrord_protocol`] negotiated with the agent.
     agent_protocol_version: Option<semver::Version>,
+    /// Holds requests until the user application is ready, if enabled.
+    startup_buffer: Option<StartupBuffer>,
+    /// Whether the [`Interceptor`]s send a [`ProxyHeader`] to the user application.
+    proxy_protocol: bool,
+    /// For [`SendFileToConnRequest`]s.
+    send_files: SendFileQueue<InterceptorId>,
+    /// Which forwarding headers are added to the stolen HTTP requests, if enabled.
+    forwarded_headers: Option<ForwardedHeadersConfig>,
 }
 
 impl IncomingProxy {
@@ -185,14 +275,17 @@ impl IncomingProxy {
     const CHANNEL_SIZE: usize = 512;
 
     /// Tries to register the new subscription in the [`SubscriptionsManager`].
-    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    ///
+    /// Releases the requests held in the [`StartupBuffer`] for the subscribed port.
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus), err)]
     async fn handle_port_subscribe(
         &mut self,
         message_id: MessageId,
         layer_id: LayerId,
         subscribe: PortSubscribe,
         message_bus: &mut MessageBus<Self>,
-    ) {
+    ) -> Result<(), IncomingProxyError> {
+        let port = subscribe.subscription.port();
         let msg = self
             .subscriptions
             .layer_subscribed(layer_id, message_id, subscribe);
@@ -200,6 +293,17 @@ impl IncomingProxy {
         if let Some(msg) = msg {
             message_bus.send(msg).await;
         }
+
+        let released = self
+            .startup_buffer
+            .as_mut()
+            .map(|buffer| buffer.release(port))
+            .unwrap_or_default();
+        for request in released {
+            self.handle_http_request(request).await?;
+        }
+
+        Ok(())
     }
 
     /// Tries to unregister the subscription from the [`SubscriptionsManager`].
@@ -217,6 +321,171 @@ impl IncomingProxy {
         }
     }
 
+    /// Reserves a port in the agent, if it supports [`PORT_RESERVATION_VERSION`].
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    async fn handle_port_reserve(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: PortReserve,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let supported = self
+            .agent_protocol_version
+            .as_ref()
+            .is_some_and(|version| PORT_RESERVATION_VERSION.matches(version));
+
+        if supported {
+            let msg = self
+                .reservations
+                .layer_reserved(layer_id, message_id, request);
+            message_bus.send(msg).await;
+        } else {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    layer_id,
+                    message: ProxyToLayerMessage::Incoming(IncomingResponse::PortReserve(Err(
+                        ResponseError::NotImplemented,
+                    ))),
+                })
+                .await;
+        }
+    }
+
+    /// Releases a port reserved in the agent, once no layer holds it.
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    async fn handle_port_release(
+        &mut self,
+        layer_id: LayerId,
+        request: PortRelease,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        if let Some(msg) = self.reservations.layer_released(layer_id, request) {
+            message_bus.send(msg).await;
+        }
+    }
+
+    /// Sends the given [`HttpRequestFallback`] to its [`Interceptor`].
+    ///
+    /// If the request does not belong to an existing connection, and no layer subscribed to its
+    /// port yet, the request is held in the [`StartupBuffer`] (if enabled).
+    #[tracing::instrument(level = Level::TRACE, skip(self), err)]
+    async fn handle_http_request(
+        &mut self,
+        request: HttpRequestFallback,
+    ) -> Result<(), IncomingProxyError> {
+        let unsubscribed = !self
+            .interceptors
+            .contains_key(&InterceptorId(request.connection_id()))
+            && self.subscriptions.get(request.port()).is_none();
+
+        let request = match self.startup_buffer.as_mut() {
+            Some(buffer) if unsubscribed => match buffer.hold(request) {
+                Ok(()) => {
+                    tracing::debug!("holding a request until the local application is ready");
+                    return Ok(());
+                }
+                Err(request) => {
+                    tracing::warn!(
+                        ?request,
+                        "startup buffer is full, the request cannot wait for the local application"
+                    );
+                    request
+                }
+            },
+            _ => request,
+        };
+
+        let mut request = request;
+        if let Some(config) = &self.forwarded_headers {
+            let client = self
+                .http_connections
+                .get(&request.connection_id())
+                .map(|connection| connection.remote_address);
+            add_forwarded_headers(config, &mut request, client);
+        }
+
+        let interceptor = self.get_interceptor_for_http_request(&request)?;
+        if let Some(interceptor) = interceptor {
+            interceptor.send(request).await;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the given body frames of a streamed request.
+    ///
+    /// Nothing reads the frames of a request held in the [`StartupBuffer`], so they're sent
+    /// without waiting for room in the channel. If there is no room, the request is dropped.
+    ///
+    /// Returns whether all frames were sent.
+    async fn send_request_frames(
+        &mut self,
+        key: (ConnectionId, RequestId),
+        frames: Vec<InternalHttpBodyFrame>,
+    ) -> bool {
+        let Some(tx) = self.request_body_txs.get(&key) else {
+            return false;
+        };
+
+        let Some(buffer) = self
+            .startup_buffer
+            .as_mut()
+            .filter(|buffer| buffer.contains(key.0, key.1))
+        else {
+            for frame in frames {
+                if let Err(err) = tx.send(frame).await {
+                    tracing::trace!(?err, "error while sending");
+                    return false;
+                }
+            }
+
+            return true;
+        };
+
+        for frame in frames {
+            if let Err(err) = tx.try_send(frame) {
+                tracing::warn!(
+                    ?err,
+                    "dropping a held request, its body does not fit the buffer"
+                );
+                buffer.remove(key.0, key.1);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Responds to the requests that waited for too long in the [`StartupBuffer`].
+    async fn handle_expired_requests(&mut self, message_bus: &mut MessageBus<Self>) {
+        let expired = self
+            .startup_buffer
+            .as_mut()
+            .map(StartupBuffer::expired)
+            .unwrap_or_default();
+
+        for request in expired {
+            tracing::warn!(
+                ?request,
+                "local application did not subscribe to the port in time"
+            );
+            self.request_body_txs
+                .remove(&(request.connection_id(), request.request_id()));
+
+            let response = HttpResponseFallback::response_from_request(
+                request,
+                StatusCode::SERVICE_UNAVAILABLE,
+                "mirrord: the local application is not listening on the port",
+                self.agent_protocol_version.as_ref(),
+            );
+            if let Some(msg) = self.http_response_message(response).await {
+                message_bus.send(msg).await;
+            }
+        }
+    }
+
     /// Retrieves or creates an [`Interceptor`] for the given [`HttpRequestFallback`].
     /// The request may or may not belong to an existing connection (when stealing with an http
     /// filter, connections are created implicitly).
@@ -242,12 +511,26 @@ impl IncomingProxy {
 
                 let interceptor_socket = bind_similar(subscription.listening_on)?;
 
+                let connection = self.http_connections.get(&id.0);
+                if let Some(connection) = connection {
+                    self.metadata_store.expect_connection(
+                        connection,
+                        subscription.listening_on,
+                        interceptor_socket.local_addr()?,
+                    );
+                }
+                let proxy_header = self
+                    .proxy_protocol
+                    .then(|| connection.map(proxy_header).unwrap_or(ProxyHeader::Local));
+
                 let interceptor = self.background_tasks.register(
                     Interceptor::new(
                         interceptor_socket,
                         subscription.listening_on,
                         self.agent_protocol_version.clone(),
-                    ),
+                    )
+                    .with_connect_wait(self.startup_buffer.as_ref().map(StartupBuffer::max_wait))
+                    .with_proxy_header(proxy_header),
                     id,
                     Self::CHANNEL_SIZE,
                 );
@@ -263,16 +546,24 @@ impl IncomingProxy {
     }
 
     /// Handles all agent messages.
+    ///
+    /// `mirrored` tells whether the message came from the mirror side of the agent, which also
+    /// serves steal subscriptions made in the [`StealState::Mirrored`] state.
     #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
     async fn handle_agent_message(
         &mut self,
         message: DaemonTcp,
+        mirrored: bool,
         message_bus: &mut MessageBus<Self>,
     ) -> Result<(), IncomingProxyError> {
         match message {
             DaemonTcp::Close(close) => {
+                if let Some(buffer) = self.startup_buffer.as_mut() {
+                    buffer.remove_connection(close.connection_id);
+                }
                 self.interceptors
                     .remove(&InterceptorId(close.connection_id));
+                self.http_connections.remove(&close.connection_id);
                 self.request_body_txs
                     .retain(|(connection_id, _), _| *connection_id != close.connection_id);
                 let keys: Vec<(ConnectionId, RequestId)> = self
@@ -297,18 +588,12 @@ impl IncomingProxy {
                 }
             }
             DaemonTcp::HttpRequest(req) => {
-                let req = HttpRequestFallback::Fallback(req);
-                let interceptor = self.get_interceptor_for_http_request(&req)?;
-                if let Some(interceptor) = interceptor {
-                    interceptor.send(req).await;
-                }
+                self.handle_http_request(HttpRequestFallback::Fallback(req))
+                    .await?;
             }
             DaemonTcp::HttpRequestFramed(req) => {
-                let req = HttpRequestFallback::Framed(req);
-                let interceptor = self.get_interceptor_for_http_request(&req)?;
-                if let Some(interceptor) = interceptor {
-                    interceptor.send(req).await;
-                }
+                self.handle_http_request(HttpRequestFallback::Framed(req))
+                    .await?;
             }
             DaemonTcp::HttpRequestChunked(req) => {
                 match req {
@@ -329,53 +614,43 @@ impl IncomingProxy {
                         };
                         let key = (http_req.connection_id, http_req.request_id);
 
-                        self.request_body_txs.insert(key, tx.clone());
+                        self.request_body_txs.insert(key, tx);
 
-                        let http_req = HttpRequestFallback::Streamed {
+                        self.handle_http_request(HttpRequestFallback::Streamed {
                             request: http_req,
                             retries: 0,
-                        };
-                        let interceptor = self.get_interceptor_for_http_request(&http_req)?;
-                        if let Some(interceptor) = interceptor {
-                            interceptor.send(http_req).await;
-                        }
+                        })
+                        .await?;
 
-                        for frame in req.internal_request.body {
-                            if let Err(err) = tx.send(frame).await {
-                                self.request_body_txs.remove(&key);
-                                tracing::trace!(?err, "error while sending");
-                            }
+                        if !self
+                            .send_request_frames(key, req.internal_request.body)
+                            .await
+                        {
+                            self.request_body_txs.remove(&key);
                         }
                     }
                     ChunkedRequest::Body(body) => {
-                        let key = &(body.connection_id, body.request_id);
-                        let mut send_err = false;
-                        if let Some(tx) = self.request_body_txs.get(key) {
-                            for frame in body.frames {
-                                if let Err(err) = tx.send(frame).await {
-                                    send_err = true;
-                                    tracing::trace!(?err, "error while sending");
-                                }
-                            }
-                        }
-                        if send_err || body.is_last {
-                            self.request_body_txs.remove(key);
+                        let key = (body.connection_id, body.request_id);
+                        if !self.send_request_frames(key, body.frames).await || body.is_last {
+                            self.request_body_txs.remove(&key);
                         }
                     }
                     ChunkedRequest::Error(err) => {
+                        if let Some(buffer) = self.startup_buffer.as_mut() {
+                            buffer.remove(err.connection_id, err.request_id);
+                        }
                         self.request_body_txs
                             .remove(&(err.connection_id, err.request_id));
                         tracing::trace!(?err, "ChunkedRequest error received");
                     }
                 };
             }
-            DaemonTcp::NewConnection(NewTcpConnection {
-                connection_id,
-                remote_address,
-                destination_port,
-                source_port,
-                local_address,
-            }) => {
+            DaemonTcp::NewHttpConnection(connection) => {
+                self.http_connections
+                    .insert(connection.connection_id, connection);
+            }
+            DaemonTcp::NewConnection(connection) => {
+                let destination_port = connection.destination_port;
                 let Some(subscription) = self.subscriptions.get(destination_port) else {
                     tracing::trace!("received a new connection for port {destination_port} that is no longer mirrored");
                     return Ok(());
@@ -383,18 +658,12 @@ impl IncomingProxy {
 
                 let interceptor_socket = bind_similar(subscription.listening_on)?;
 
-                let id = InterceptorId(connection_id);
+                let id = InterceptorId(connection.connection_id);
 
-                self.metadata_store.expect(
-                    ConnMetadataRequest {
-                        listener_address: subscription.listening_on,
-                        peer_address: interceptor_socket.local_addr()?,
-                    },
-                    id,
-                    ConnMetadataResponse {
-                        remote_source: SocketAddr::new(remote_address, source_port),
-                        local_address,
-                    },
+                self.metadata_store.expect_connection(
+                    &connection,
+                    subscription.listening_on,
+                    interceptor_socket.local_addr()?,
                 );
 
                 let interceptor = self.background_tasks.register(
@@ -402,19 +671,44 @@ impl IncomingProxy {
                         interceptor_socket,
                         subscription.listening_on,
                         self.agent_protocol_version.clone(),
-                    ),
+                    )
+                    .with_connect_wait(self.startup_buffer.as_ref().map(StartupBuffer::max_wait))
+                    .with_proxy_header(self.proxy_protocol.then(|| proxy_header(&connection))),
                     id,
                     Self::CHANNEL_SIZE,
                 );
 
+                let subscription = if mirrored {
+                    PortSubscription::Mirror(destination_port)
+                } else {
+                    subscription.subscription.clone()
+                };
+
                 self.interceptors.insert(
                     id,
                     InterceptorHandle {
                         tx: interceptor,
-                        subscription: subscription.subscription.clone(),
+                        subscription,
                     },
                 );
             }
+            // Handled by the `IntProxy`.
+            DaemonTcp::Drained => {}
+            DaemonTcp::SendFile(result) => {
+                let (message_id, layer_id) = self.send_files.agent_responded()?;
+                message_bus
+                    .send(ToLayer {
+                        message_id,
+                        layer_id,
+                        message: ProxyToLayerMessage::SendFileToConn(result),
+                    })
+                    .await;
+            }
+            DaemonTcp::PortReserved(result) => match self.reservations.agent_responded(result) {
+                Some(ReservationMessage::ToLayer(msg)) => message_bus.send(msg).await,
+                Some(ReservationMessage::ToAgent(msg)) => message_bus.send(msg).await,
+                None => {}
+            },
             DaemonTcp::SubscribeResult(result) => {
                 let msgs = self.subscriptions.agent_responded(result)?;
 
@@ -430,16 +724,70 @@ impl IncomingProxy {
     fn handle_layer_fork(&mut self, msg: LayerForked) {
         let LayerForked { child, parent } = msg;
         self.subscriptions.layer_forked(parent, child);
+        self.reservations.layer_forked(parent, child);
     }
 
     async fn handle_layer_close(&mut self, msg: LayerClosed, message_bus: &MessageBus<Self>) {
-        let msgs = self.subscriptions.layer_closed(msg.id);
+        let msgs = self
+            .subscriptions
+            .layer_closed(msg.id)
+            .into_iter()
+            .chain(self.reservations.layer_closed(msg.id));
 
         for msg in msgs {
             message_bus.send(msg).await;
         }
     }
 
+    /// Saves the layer's request and asks the [`Interceptor`] of the connection to flush, the
+    /// request is sent to the agent afterwards.
+    ///
+    /// Responds with [`ResponseError::NotImplemented`] when the agent does not support
+    /// [`SEND_FILE_VERSION`], or the connection is not a stolen TCP connection (the data written
+    /// to the mirrored connections is dropped, and the filtered HTTP connections expect HTTP
+    /// responses).
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    async fn handle_send_file_request(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: SendFileToConnRequest,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let supported = self
+            .agent_protocol_version
+            .as_ref()
+            .is_some_and(|version| SEND_FILE_VERSION.matches(version));
+
+        let interceptor = match request.connection {
+            InterceptedConnection::Incoming(address) if supported => self
+                .metadata_store
+                .interceptor_at(address)
+                .filter(|id| !self.http_connections.contains_key(&id.0))
+                .and_then(|id| Some((id, self.interceptors.get(&id)?)))
+                .filter(|(_, handle)| matches!(handle.subscription, PortSubscription::Steal(..))),
+            _ => None,
+        };
+
+        let Some((id, handle)) = interceptor else {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    layer_id,
+                    message: ProxyToLayerMessage::SendFileToConn(Err(
+                        ResponseError::NotImplemented,
+                    )),
+                })
+                .await;
+
+            return;
+        };
+
+        self.send_files
+            .layer_requested(id, id.0, message_id, layer_id, request);
+        handle.tx.send(MessageIn::Flush).await;
+    }
+
     fn get_subscription(&self, interceptor_id: InterceptorId) -> Option<&PortSubscription> {
         self.interceptors
             .get(&interceptor_id)
@@ -503,24 +851,47 @@ impl BackgroundTask for IncomingProxy {
                         break Ok(());
                     },
                     Some(IncomingProxyMessage::LayerRequest(message_id, layer_id, req)) => match req {
-                        IncomingRequest::PortSubscribe(subscribe) => self.handle_port_subscribe(message_id, layer_id, subscribe, message_bus).await,
+                        IncomingRequest::PortSubscribe(subscribe) => self.handle_port_subscribe(message_id, layer_id, subscribe, message_bus).await?,
                         IncomingRequest::PortUnsubscribe(unsubscribe) => self.handle_port_unsubscribe(layer_id, unsubscribe, message_bus).await,
+                        IncomingRequest::PortReserve(reserve) => self.handle_port_reserve(message_id, layer_id, reserve, message_bus).await,
+                        IncomingRequest::PortRelease(release) => self.handle_port_release(layer_id, release, message_bus).await,
                         IncomingRequest::ConnMetadata(req) => {
                             let res = self.metadata_store.get(req);
                             message_bus.send(ToLayer { message_id, layer_id, message: ProxyToLayerMessage::Incoming(IncomingResponse::ConnMetadata(res))  }).await;
                         }
                     },
+                    Some(IncomingProxyMessage::LayerSendFile(message_id, layer_id, req)) => {
+                        self.handle_send_file_request(message_id, layer_id, req, message_bus).await;
+                    }
                     Some(IncomingProxyMessage::AgentMirror(msg)) => {
-                        self.handle_agent_message(msg, message_bus).await?;
+                        self.handle_agent_message(msg, true, message_bus).await?;
                     }
                     Some(IncomingProxyMessage::AgentSteal(msg)) => {
-                        self.handle_agent_message(msg, message_bus).await?;
+                        self.handle_agent_message(msg, false, message_bus).await?;
                     }
                     Some(IncomingProxyMessage::LayerClosed(msg)) => self.handle_layer_close(msg, message_bus).await,
                     Some(IncomingProxyMessage::LayerForked(msg)) => self.handle_layer_fork(msg),
                     Some(IncomingProxyMessage::AgentProtocolVersion(version)) => {
                         self.agent_protocol_version.replace(version);
                     }
+                    Some(IncomingProxyMessage::StartupBuffer(config)) => {
+                        self.startup_buffer.replace(StartupBuffer::new(config));
+                    }
+                    Some(IncomingProxyMessage::ProxyProtocol) => {
+                        self.proxy_protocol = true;
+                    }
+                    Some(IncomingProxyMessage::ForwardedHeaders(config)) => {
+                        self.forwarded_headers.replace(config);
+                    }
+                    Some(IncomingProxyMessage::StealState(state)) => {
+                        for msg in self.subscriptions.set_steal_state(state) {
+                            message_bus.send(msg).await;
+                        }
+                    }
+                },
+
+                Some(()) = OptionFuture::from(self.startup_buffer.as_ref().map(StartupBuffer::next_expiry)) => {
+                    self.handle_expired_requests(message_bus).await;
                 },
 
                 Some(task_update) = self.background_tasks.next() => match task_update {
@@ -535,6 +906,18 @@ impl BackgroundTask for IncomingProxy {
                         }
 
                         self.request_body_txs.retain(|(connection_id, _), _| *connection_id != id.0);
+
+                        for (message_id, layer_id) in self.send_files.interceptor_finished(id) {
+                            message_bus
+                                .send(ToLayer {
+                                    message_id,
+                                    layer_id,
+                                    message: ProxyToLayerMessage::SendFileToConn(Err(
+                                        ResponseError::NotFound(id.0),
+                                    )),
+                                })
+                                .await;
+                        }
                     },
 
                     (id, TaskUpdate::Message(msg)) => {
@@ -548,18 +931,14 @@ impl BackgroundTask for IncomingProxy {
                                     bytes,
                                 }))
                             },
-                            MessageOut::Http(HttpResponseFallback::Fallback(res)) => {
-                                ClientMessage::TcpSteal(LayerTcpSteal::HttpResponse(res))
+                            MessageOut::Http(response) => match self.http_response_message(response).await {
+                                Some(response) => response,
+                                None => continue,
                             },
-                            MessageOut::Http(HttpResponseFallback::Framed(res)) => {
-                                ClientMessage::TcpSteal(LayerTcpSteal::HttpResponseFramed(res))
+                            MessageOut::Flushed => match self.send_files.interceptor_flushed(id) {
+                                Some(request) => ClientMessage::TcpSteal(LayerTcpSteal::SendFile(request)),
+                                None => continue,
                             },
-                            MessageOut::Http(HttpResponseFallback::Streamed(response, request)) => {
-                                match self.streamed_http_response(response, request).await {
-                                    Some(response) => response,
-                                    None => continue,
-                                }
-                            }
                         };
                         message_bus.send(msg).await;
                     },
@@ -570,6 +949,24 @@ impl BackgroundTask for IncomingProxy {
 }
 
 impl IncomingProxy {
+    /// Prepares the message that sends back the given http response to the agent.
+    async fn http_response_message(
+        &mut self,
+        response: HttpResponseFallback,
+    ) -> Option<ClientMessage> {
+        match response {
+            HttpResponseFallback::Fallback(res) => {
+                Some(ClientMessage::TcpSteal(LayerTcpSteal::HttpResponse(res)))
+            }
+            HttpResponseFallback::Framed(res) => Some(ClientMessage::TcpSteal(
+                LayerTcpSteal::HttpResponseFramed(res),
+            )),
+            HttpResponseFallback::Streamed(response, request) => {
+                self.streamed_http_response(response, request).await
+            }
+        }
+    }
+
     /// Sends back the streamed http response to the agent.
     ///
     /// If we cannot get the next frame of the streamed body, then we retry the whole
@@ -0,0 +1,178 @@
+//! Headers that reverse proxies add to the requests they forward, see
+//! [`add_forwarded_headers`].
+
+use std::net::IpAddr;
+
+use hyper::{
+    header::{FORWARDED, HOST},
+    http::{HeaderName, HeaderValue},
+    HeaderMap, Uri,
+};
+use mirrord_config::feature::network::incoming::ForwardedHeadersConfig;
+use mirrord_protocol::tcp::HttpRequestFallback;
+
+/// Non-standard header with the addresses of the clients that the request passed through.
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Non-standard header with the scheme of the original request.
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Adds the headers enabled in the [`ForwardedHeadersConfig`] to the given stolen request.
+///
+/// `client` is the address of the remote peer of the stolen connection, when the agent reported
+/// it.
+pub(super) fn add_forwarded_headers(
+    config: &ForwardedHeadersConfig,
+    request: &mut HttpRequestFallback,
+    client: Option<IpAddr>,
+) {
+    let (headers, uri) = match request {
+        HttpRequestFallback::Framed(request) => (
+            &mut request.internal_request.headers,
+            &request.internal_request.uri,
+        ),
+        HttpRequestFallback::Fallback(request) => (
+            &mut request.internal_request.headers,
+            &request.internal_request.uri,
+        ),
+        HttpRequestFallback::Streamed { request, .. } => (
+            &mut request.internal_request.headers,
+            &request.internal_request.uri,
+        ),
+    };
+
+    add_to_headers(config, headers, uri, client);
+}
+
+/// Adds the headers enabled in the [`ForwardedHeadersConfig`] to the headers of a request with the
+/// given `uri`.
+fn add_to_headers(
+    config: &ForwardedHeadersConfig,
+    headers: &mut HeaderMap,
+    uri: &Uri,
+    client: Option<IpAddr>,
+) {
+    let proto = uri.scheme_str().unwrap_or("http").to_string();
+    // HTTP/2 requests carry the host in the URI.
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| uri.authority().map(|authority| authority.as_str()))
+        .map(ToString::to_string);
+
+    if config.x_forwarded_for {
+        if let Some(client) = client {
+            append(headers, X_FORWARDED_FOR, &client.to_string());
+        }
+    }
+
+    if config.x_forwarded_proto && !headers.contains_key(X_FORWARDED_PROTO) {
+        append(headers, X_FORWARDED_PROTO, &proto);
+    }
+
+    if config.forwarded {
+        let mut element = Vec::new();
+        match client {
+            Some(IpAddr::V4(client)) => element.push(format!("for={client}")),
+            Some(IpAddr::V6(client)) => element.push(format!("for=\"[{client}]\"")),
+            None => {}
+        }
+        if let Some(host) = host.filter(|host| !host.contains(['"', '\\'])) {
+            element.push(format!("host=\"{host}\""));
+        }
+        element.push(format!("proto={proto}"));
+
+        append(headers, FORWARDED, &element.join(";"));
+    }
+}
+
+/// Appends `value` to the list in the `name` header, merging its existing fields into one.
+fn append(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    let merged = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|existing| existing.to_str().ok())
+        .chain([value])
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match HeaderValue::from_str(&merged) {
+        Ok(merged) => {
+            headers.insert(name, merged);
+        }
+        Err(error) => tracing::warn!(%error, %name, "Failed to add a forwarding header"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn adds_all_headers() {
+        let mut headers = headers(&[(HOST, "api.example.com")]);
+
+        add_to_headers(
+            &Default::default(),
+            &mut headers,
+            &"/users".parse().unwrap(),
+            Some("10.0.0.1".parse().unwrap()),
+        );
+
+        assert_eq!(headers.get(X_FORWARDED_FOR).unwrap(), "10.0.0.1");
+        assert_eq!(headers.get(X_FORWARDED_PROTO).unwrap(), "http");
+        assert_eq!(
+            headers.get(FORWARDED).unwrap(),
+            "for=10.0.0.1;host=\"api.example.com\";proto=http"
+        );
+    }
+
+    #[test]
+    fn appends_to_existing_headers() {
+        let mut headers = headers(&[
+            (X_FORWARDED_FOR, "203.0.113.7"),
+            (X_FORWARDED_PROTO, "https"),
+            (FORWARDED, "for=203.0.113.7"),
+            (FORWARDED, "for=198.51.100.1"),
+        ]);
+
+        add_to_headers(
+            &Default::default(),
+            &mut headers,
+            &"https://api.example.com/users".parse().unwrap(),
+            Some("fd00::1".parse().unwrap()),
+        );
+
+        assert_eq!(
+            headers.get(X_FORWARDED_FOR).unwrap(),
+            "203.0.113.7, fd00::1"
+        );
+        assert_eq!(headers.get(X_FORWARDED_PROTO).unwrap(), "https");
+        assert_eq!(
+            headers.get(FORWARDED).unwrap(),
+            "for=203.0.113.7, for=198.51.100.1, for=\"[fd00::1]\";host=\"api.example.com\";proto=https"
+        );
+    }
+
+    #[test]
+    fn respects_config_and_unknown_client() {
+        let mut headers = HeaderMap::new();
+        let config = ForwardedHeadersConfig {
+            x_forwarded_proto: false,
+            ..Default::default()
+        };
+
+        add_to_headers(&config, &mut headers, &"/".parse().unwrap(), None);
+
+        assert!(!headers.contains_key(X_FORWARDED_FOR));
+        assert!(!headers.contains_key(X_FORWARDED_PROTO));
+        assert_eq!(headers.get(FORWARDED).unwrap(), "proto=http");
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::{
     error::Error,
-    io::{self, ErrorKind},
+    io::{self, ErrorKind, Read},
     net::SocketAddr,
     time::Duration,
 };
@@ -16,6 +16,7 @@ use mirrord_protocol::tcp::{
     HttpRequestFallback, HttpResponse, HttpResponseFallback, InternalHttpBody, ReceiverStreamBody,
     HTTP_CHUNKED_RESPONSE_VERSION,
 };
+use socket2::SockRef;
 use thiserror::Error;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -24,7 +25,7 @@ use tokio::{
 };
 use tracing::Level;
 
-use super::http::HttpSender;
+use super::{http::HttpSender, proxy_protocol::ProxyHeader};
 use crate::{
     background_tasks::{BackgroundTask, MessageBus},
     proxies::incoming::http::RETRY_ON_RESET_ATTEMPTS,
@@ -36,6 +37,9 @@ pub enum MessageIn {
     Http(HttpRequestFallback),
     /// Data to be sent to the user application.
     Raw(Vec<u8>),
+    /// Request to send all data that the user application already sent to the [`MessageBus`],
+    /// followed by [`MessageOut::Flushed`]. Makes the interceptor act as a TCP proxy.
+    Flush,
 }
 
 /// Messages produced by the [`Interceptor`] when it runs as a [`BackgroundTask`].
@@ -45,6 +49,8 @@ pub enum MessageOut {
     Http(HttpResponseFallback),
     /// Data received from the user application.
     Raw(Vec<u8>),
+    /// Response to [`MessageIn::Flush`].
+    Flushed,
 }
 
 impl From<HttpRequestFallback> for MessageIn {
@@ -81,6 +87,9 @@ pub enum InterceptorError {
     /// Occurs when [`Interceptor`] receives [`MessageIn::Http`], but it acts as a TCP proxy.
     #[error("received an HTTP request, but expected raw bytes")]
     UnexpectedHttpRequest,
+    /// Occurs when [`Interceptor`] receives [`MessageIn::Flush`], but it acts as an HTTP gateway.
+    #[error("received a flush request, but expected an HTTP request")]
+    UnexpectedFlush,
 
     /// We dig into the [`hyper::Error`] to try and see if it's an [`h2::Error`], checking
     /// for [`h2::Error::is_reset`].
@@ -127,9 +136,17 @@ pub struct Interceptor {
     peer: SocketAddr,
     /// Version of [`mirrord_protocol`] negotiated with the agent.
     agent_protocol_version: Option<semver::Version>,
+    /// How long to keep retrying the first connection when the user app refuses it.
+    connect_wait: Option<Duration>,
+    /// Sent as the first bytes of every connection to the user app.
+    proxy_header: Option<ProxyHeader>,
 }
 
 impl Interceptor {
+    /// How long to wait between attempts to connect to the user app, see
+    /// [`Interceptor::with_connect_wait`].
+    const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
     /// Creates a new instance. When run, this instance will use the given `socket` (must be already
     /// bound) to communicate with the given `peer`.
     ///
@@ -145,6 +162,51 @@ impl Interceptor {
             socket,
             peer,
             agent_protocol_version,
+            connect_wait: None,
+            proxy_header: None,
+        }
+    }
+
+    /// Makes this interceptor retry the first connection for up to `wait`, when the user app
+    /// refuses it (e.g. it's still starting and did not listen on the port yet).
+    pub fn with_connect_wait(mut self, wait: Option<Duration>) -> Self {
+        self.connect_wait = wait;
+        self
+    }
+
+    /// Makes this interceptor send the given PROXY protocol header at the start of its connections
+    /// to the user app.
+    pub fn with_proxy_header(mut self, header: Option<ProxyHeader>) -> Self {
+        self.proxy_header = header;
+        self
+    }
+
+    /// Makes the first connection to the user app, see [`Interceptor::with_connect_wait`].
+    ///
+    /// Retries use a new socket bound to the same address, so that the connection metadata
+    /// prepared by the [`IncomingProxy`](super::IncomingProxy) still matches.
+    async fn connect(
+        mut socket: TcpSocket,
+        peer: SocketAddr,
+        wait: Option<Duration>,
+    ) -> io::Result<TcpStream> {
+        let deadline = wait.map(|wait| time::Instant::now() + wait);
+        let local_addr = socket.local_addr()?;
+
+        loop {
+            match socket.connect(peer).await {
+                Err(error)
+                    if error.kind() == ErrorKind::ConnectionRefused
+                        && deadline.is_some_and(|deadline| {
+                            time::Instant::now() + Self::CONNECT_RETRY_INTERVAL < deadline
+                        }) =>
+                {
+                    tracing::debug!(%error, %peer, "User app refused the connection, retrying");
+                    sleep(Self::CONNECT_RETRY_INTERVAL).await;
+                    socket = super::bind_to(local_addr)?;
+                }
+                result => break result,
+            }
         }
     }
 }
@@ -156,7 +218,10 @@ impl BackgroundTask for Interceptor {
 
     #[tracing::instrument(level = Level::TRACE, skip_all, err)]
     async fn run(self, message_bus: &mut MessageBus<Self>) -> InterceptorResult<(), Self::Error> {
-        let mut stream = self.socket.connect(self.peer).await?;
+        let mut stream = Self::connect(self.socket, self.peer, self.connect_wait).await?;
+        if let Some(header) = self.proxy_header {
+            stream.write_all(&header.encode()).await?;
+        }
 
         // First, we determine whether this is a raw TCP connection or an HTTP connection.
         // If we receive an HTTP request from our parent task, this must be an HTTP connection.
@@ -173,6 +238,11 @@ impl BackgroundTask for Interceptor {
 
                     return RawConnection { stream }.run(message_bus).await;
                 }
+                Some(MessageIn::Flush) => {
+                    let mut raw = RawConnection { stream };
+                    raw.flush(message_bus).await?;
+                    return raw.run(message_bus).await;
+                }
                 Some(MessageIn::Http(request)) => request,
                 None => return Ok(()),
             },
@@ -188,6 +258,7 @@ impl BackgroundTask for Interceptor {
             sender,
             peer: self.peer,
             agent_protocol_version: self.agent_protocol_version.clone(),
+            proxy_header: self.proxy_header,
         };
         let (response, on_upgrade) = http_conn.send(request).await.inspect_err(|fail| {
             tracing::error!(?fail, "Failed getting a filtered http response!")
@@ -231,6 +302,8 @@ struct HttpConnection {
     /// Determines which variant of [`LayerTcpSteal`](mirrord_protocol::tcp::LayerTcpSteal)
     /// we use when sending HTTP responses.
     agent_protocol_version: Option<semver::Version>,
+    /// Sent again when reconnecting.
+    proxy_header: Option<ProxyHeader>,
 }
 
 impl HttpConnection {
@@ -400,7 +473,10 @@ impl HttpConnection {
 
                     // Create a new connection for the next attempt.
                     let socket = super::bind_similar(self.peer)?;
-                    let stream = socket.connect(self.peer).await?;
+                    let mut stream = socket.connect(self.peer).await?;
+                    if let Some(header) = self.proxy_header {
+                        stream.write_all(&header.encode()).await?;
+                    }
                     let new_sender = super::http::handshake(request.version(), stream).await?;
                     self.sender = new_sender;
                 }
@@ -434,6 +510,8 @@ impl HttpConnection {
                     return Err(InterceptorError::UnexpectedRawData);
                 }
 
+                MessageIn::Flush => return Err(InterceptorError::UnexpectedFlush),
+
                 MessageIn::Http(req) => {
                     let (res, on_upgrade) = self.send(req).await.inspect_err(|fail| {
                         tracing::error!(?fail, "Failed getting a filtered http response!")
@@ -471,6 +549,34 @@ struct RawConnection {
 }
 
 impl RawConnection {
+    /// Sends all data that the user application already sent to the [`MessageBus`], without
+    /// waiting for more, followed by [`MessageOut::Flushed`].
+    ///
+    /// Reads from the socket directly, as the readiness cached by [`tokio`] can be stale right
+    /// after the user application sent the data.
+    ///
+    /// Returns whether the user application shut down writing.
+    async fn flush(&mut self, message_bus: &mut MessageBus<Interceptor>) -> io::Result<bool> {
+        let reading_closed = loop {
+            let mut bytes = vec![0; 64 * 1024];
+            let read_amount = match (&*SockRef::from(&self.stream)).read(&mut bytes) {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break false,
+                result => result?,
+            };
+            bytes.truncate(read_amount);
+            message_bus.send(MessageOut::Raw(bytes)).await;
+
+            if read_amount == 0 {
+                tracing::trace!("incoming interceptor -> layer shutdown, sent a 0-sized read to inform the agent");
+                break true;
+            }
+        };
+
+        message_bus.send(MessageOut::Flushed).await;
+
+        Ok(reading_closed)
+    }
+
     /// Proxies raw TCP data until the [`MessageBus`] closes.
     ///
     /// # Notes
@@ -518,6 +624,8 @@ impl RawConnection {
                             self.stream.write_all(&data).await?;
                         }
                     },
+                    Some(MessageIn::Flush) if reading_closed => message_bus.send(MessageOut::Flushed).await,
+                    Some(MessageIn::Flush) => reading_closed = self.flush(message_bus).await?,
                     Some(MessageIn::Http(..)) => break Err(InterceptorError::UnexpectedHttpRequest),
                 },
 
@@ -739,6 +847,98 @@ mod test {
         server_task.await.expect("dummy echo server panicked");
     }
 
+    /// Ensure that [`Interceptor::with_connect_wait`] lets the user app start listening after the
+    /// connection was intercepted.
+    /// Verifies that the data written by the user application before [`MessageIn::Flush`] is
+    /// sent before [`MessageOut::Flushed`].
+    #[tokio::test]
+    async fn flush_sends_written_data_first() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local_destination = listener.local_addr().unwrap();
+
+        let mut tasks: BackgroundTasks<(), MessageOut, InterceptorError> = Default::default();
+        let interceptor = {
+            let socket = TcpSocket::new_v4().unwrap();
+            socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            tasks.register(Interceptor::new(socket, local_destination, None), (), 8)
+        };
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        interceptor.send(MessageIn::Flush).await;
+
+        let mut received = Vec::new();
+        loop {
+            match tasks.next().await.unwrap().1 {
+                TaskUpdate::Message(MessageOut::Raw(bytes)) => received.extend(bytes),
+                TaskUpdate::Message(MessageOut::Flushed) => break,
+                other => panic!("unexpected task update: {other:?}"),
+            }
+        }
+        assert_eq!(received, b"hello");
+    }
+
+    #[tokio::test]
+    async fn connect_waits_for_listener() {
+        let local_destination = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let mut tasks: BackgroundTasks<(), MessageOut, InterceptorError> = Default::default();
+        let interceptor = {
+            let socket = TcpSocket::new_v4().unwrap();
+            socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            tasks.register(
+                Interceptor::new(socket, local_destination, None)
+                    .with_connect_wait(Some(Duration::from_secs(5))),
+                (),
+                8,
+            )
+        };
+
+        interceptor.send(b"hello".to_vec()).await;
+        time::sleep(Duration::from_millis(200)).await;
+
+        let listener = TcpListener::bind(local_destination).await.unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    /// Ensure that [`Interceptor::with_proxy_header`] sends the header before the intercepted
+    /// data.
+    #[tokio::test]
+    async fn sends_proxy_header_first() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local_destination = listener.local_addr().unwrap();
+        let header = ProxyHeader::Proxy {
+            source: "10.0.0.1:51000".parse().unwrap(),
+            destination: "10.0.0.2:80".parse().unwrap(),
+        };
+
+        let mut tasks: BackgroundTasks<(), MessageOut, InterceptorError> = Default::default();
+        let interceptor = {
+            let socket = TcpSocket::new_v4().unwrap();
+            socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            tasks.register(
+                Interceptor::new(socket, local_destination, None).with_proxy_header(Some(header)),
+                (),
+                8,
+            )
+        };
+
+        interceptor.send(b"hello".to_vec()).await;
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let expected = [header.encode(), b"hello".to_vec()].concat();
+        let mut buf = vec![0; expected.len()];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, expected);
+    }
+
     /// Ensure that [`HttpRequestFallback::Streamed`] are received frame by frame
     #[tokio::test]
     async fn receive_request_as_frames() {
@@ -1,6 +1,6 @@
 //! Utilities for handling toggleable `steal` feature in [`IncomingProxy`](super::IncomingProxy).
 
-use mirrord_intproxy_protocol::PortSubscription;
+use mirrord_intproxy_protocol::{control::StealState, PortSubscription};
 use mirrord_protocol::{
     tcp::{LayerTcp, LayerTcpSteal, StealType},
     ClientMessage, ConnectionId, Port,
@@ -12,6 +12,8 @@ fn get_port(steal_type: &StealType) -> Port {
         StealType::All(port) => *port,
         StealType::FilteredHttp(port, _) => *port,
         StealType::FilteredHttpEx(port, _) => *port,
+        StealType::FilteredDatabase(port, _) => *port,
+        StealType::Reserved(port) => *port,
     }
 }
 
@@ -29,6 +31,10 @@ pub trait PortSubscriptionExt {
 
     /// Returns an unsubscribe connection request to be sent to the agent.
     fn wrap_agent_unsubscribe_connection(&self, connection_id: ConnectionId) -> ClientMessage;
+
+    /// Returns the subscription made in the agent when the steal subscriptions are in the given
+    /// [`StealState`], or [`None`] if no subscription is made.
+    fn in_steal_state(&self, state: StealState) -> Option<PortSubscription>;
 }
 
 impl PortSubscriptionExt for PortSubscription {
@@ -68,4 +74,17 @@ impl PortSubscriptionExt for PortSubscription {
             }
         }
     }
+
+    fn in_steal_state(&self, state: StealState) -> Option<PortSubscription> {
+        match (self, state) {
+            // The connections to a reserved port don't belong to the target.
+            (Self::Mirror(..), _)
+            | (Self::Steal(..), StealState::Active)
+            | (Self::Steal(StealType::Reserved(..)), _) => Some(self.clone()),
+            (Self::Steal(..), StealState::Paused) => None,
+            (Self::Steal(steal_type), StealState::Mirrored) => {
+                Some(Self::Mirror(get_port(steal_type)))
+            }
+        }
+    }
 }
@@ -0,0 +1,156 @@
+//! Encoding of the [PROXY protocol v2](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt)
+//! header, see [`ProxyHeader`].
+
+use std::net::SocketAddr;
+
+/// Bytes that start every v2 header.
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Protocol version, in the high nibble of the 13th byte.
+const VERSION: u8 = 0x20;
+
+/// Command of a connection made by the proxy itself, addresses are not sent.
+const COMMAND_LOCAL: u8 = 0x00;
+
+/// Command of a connection made on behalf of another peer.
+const COMMAND_PROXY: u8 = 0x01;
+
+/// Address family and transport of a connection with unknown addresses.
+const UNSPEC: u8 = 0x00;
+
+/// Address family and transport of a TCP over IPv4 connection.
+const TCP_OVER_IPV4: u8 = 0x11;
+
+/// Address family and transport of a TCP over IPv6 connection.
+const TCP_OVER_IPV6: u8 = 0x21;
+
+/// PROXY protocol v2 header that the [`Interceptor`](super::interceptor::Interceptor) sends as
+/// the first bytes of its connections to the user application, when
+/// `feature.network.incoming.proxy_protocol` is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ProxyHeader {
+    /// We don't know the addresses of the intercepted connection, the user application should use
+    /// the addresses of the connection itself.
+    Local,
+    /// Connection made by a remote peer.
+    Proxy {
+        /// Address of the remote peer.
+        source: SocketAddr,
+        /// Address that the remote peer connected to, in the cluster.
+        destination: SocketAddr,
+    },
+}
+
+impl ProxyHeader {
+    /// Encodes this header.
+    ///
+    /// When only one of the addresses is IPv6, the other one is sent as an IPv4-mapped IPv6
+    /// address, as the header has one address family for both.
+    pub(super) fn encode(self) -> Vec<u8> {
+        let mut header = SIGNATURE.to_vec();
+
+        match self {
+            Self::Local => {
+                header.extend([VERSION | COMMAND_LOCAL, UNSPEC]);
+                header.extend(0_u16.to_be_bytes());
+            }
+            Self::Proxy {
+                source,
+                destination,
+            } => {
+                header.push(VERSION | COMMAND_PROXY);
+
+                match (source, destination) {
+                    (SocketAddr::V4(source), SocketAddr::V4(destination)) => {
+                        header.push(TCP_OVER_IPV4);
+                        header.extend(12_u16.to_be_bytes());
+                        header.extend(source.ip().octets());
+                        header.extend(destination.ip().octets());
+                    }
+                    _ => {
+                        let to_ipv6 = |address: SocketAddr| match address {
+                            SocketAddr::V4(address) => address.ip().to_ipv6_mapped(),
+                            SocketAddr::V6(address) => *address.ip(),
+                        };
+
+                        header.push(TCP_OVER_IPV6);
+                        header.extend(36_u16.to_be_bytes());
+                        header.extend(to_ipv6(source).octets());
+                        header.extend(to_ipv6(destination).octets());
+                    }
+                }
+
+                header.extend(source.port().to_be_bytes());
+                header.extend(destination.port().to_be_bytes());
+            }
+        }
+
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_local() {
+        let header = ProxyHeader::Local.encode();
+
+        assert_eq!(header.get(..12), Some(SIGNATURE.as_slice()));
+        assert_eq!(header.get(12..), Some([0x20, 0x00, 0x00, 0x00].as_slice()));
+    }
+
+    #[test]
+    fn encodes_ipv4() {
+        let header = ProxyHeader::Proxy {
+            source: "10.0.0.1:51000".parse().unwrap(),
+            destination: "10.0.0.2:80".parse().unwrap(),
+        }
+        .encode();
+
+        assert_eq!(
+            header.get(12..),
+            Some(
+                [0x21, 0x11, 0x00, 0x0c, 10, 0, 0, 1, 10, 0, 0, 2, 0xc7, 0x38, 0x00, 0x50]
+                    .as_slice()
+            )
+        );
+    }
+
+    #[test]
+    fn maps_mixed_families_to_ipv6() {
+        let header = ProxyHeader::Proxy {
+            source: "10.0.0.1:51000".parse().unwrap(),
+            destination: "[fd00::2]:80".parse().unwrap(),
+        }
+        .encode();
+
+        assert_eq!(header.len(), 16 + 36);
+        assert_eq!(
+            header.get(12..16),
+            Some([0x21, 0x21, 0x00, 0x24].as_slice())
+        );
+        assert_eq!(
+            header.get(16..32),
+            Some(
+                "::ffff:10.0.0.1"
+                    .parse::<std::net::Ipv6Addr>()
+                    .unwrap()
+                    .octets()
+                    .as_slice()
+            )
+        );
+        assert_eq!(
+            header.get(32..48),
+            Some(
+                "fd00::2"
+                    .parse::<std::net::Ipv6Addr>()
+                    .unwrap()
+                    .octets()
+                    .as_slice()
+            )
+        );
+        assert_eq!(header.get(48..), Some([0xc7, 0x38, 0x00, 0x50].as_slice()));
+    }
+}
@@ -0,0 +1,192 @@
+//! Ports reserved in the agent for the layers, see
+//! [`PortReservation`](mirrord_protocol::tcp::PortReservation).
+
+use std::collections::VecDeque;
+
+use mirrord_intproxy_protocol::{
+    IncomingResponse, LayerId, MessageId, PortRelease, PortReserve, ProxyToLayerMessage,
+};
+use mirrord_protocol::{
+    tcp::{LayerTcpSteal, PortReservation},
+    ClientMessage, Port, RemoteResult,
+};
+use tracing::Level;
+
+use crate::{main_tasks::ToLayer, remote_resources::RemoteResources};
+
+/// [`PortReserve`] request waiting for the agent's response.
+#[derive(Debug)]
+struct PendingReservation {
+    /// [`None`] when the layer closed before the agent responded.
+    layer: Option<LayerId>,
+    message: MessageId,
+}
+
+/// Manages the ports reserved in the agent across all connected layers.
+///
+/// The agent responds to the reservations in order, and a reserved port is released once it's
+/// released in every fork of the layer that reserved it.
+#[derive(Default)]
+pub struct ReservationsManager {
+    reserved_ports: RemoteResources<Port>,
+    pending: VecDeque<PendingReservation>,
+}
+
+impl ReservationsManager {
+    /// Registers a new reservation request.
+    /// Returns the message to be sent to the agent.
+    pub fn layer_reserved(
+        &mut self,
+        layer_id: LayerId,
+        message_id: MessageId,
+        request: PortReserve,
+    ) -> ClientMessage {
+        self.pending.push_back(PendingReservation {
+            layer: Some(layer_id),
+            message: message_id,
+        });
+
+        ClientMessage::TcpSteal(LayerTcpSteal::ReservePort(PortReservation {
+            port: request.port,
+        }))
+    }
+
+    /// Releases the port in the given layer.
+    /// Optionally returns a message to be sent to the agent.
+    pub fn layer_released(
+        &mut self,
+        layer_id: LayerId,
+        request: PortRelease,
+    ) -> Option<ClientMessage> {
+        self.reserved_ports
+            .remove(layer_id, request.port)
+            .then_some(ClientMessage::TcpSteal(LayerTcpSteal::ReleasePort(
+                request.port,
+            )))
+    }
+
+    /// Notifies this struct about agent's response.
+    /// Returns the message to be sent to the layer, or to the agent when the layer closed in the
+    /// meantime.
+    #[tracing::instrument(level = Level::TRACE, ret, skip(self))]
+    pub fn agent_responded(&mut self, result: RemoteResult<Port>) -> Option<ReservationMessage> {
+        let pending = self.pending.pop_front()?;
+
+        let Some(layer_id) = pending.layer else {
+            return result.ok().map(|port| {
+                ReservationMessage::ToAgent(ClientMessage::TcpSteal(LayerTcpSteal::ReleasePort(
+                    port,
+                )))
+            });
+        };
+
+        if let Ok(port) = result {
+            self.reserved_ports.add(layer_id, port);
+        }
+
+        Some(ReservationMessage::ToLayer(ToLayer {
+            message_id: pending.message,
+            layer_id,
+            message: ProxyToLayerMessage::Incoming(IncomingResponse::PortReserve(result)),
+        }))
+    }
+
+    /// Notifies this struct about layer closing.
+    /// Returns messages to be sent to the agent.
+    pub fn layer_closed(&mut self, layer_id: LayerId) -> Vec<ClientMessage> {
+        self.pending
+            .iter_mut()
+            .filter(|pending| pending.layer == Some(layer_id))
+            .for_each(|pending| pending.layer = None);
+
+        self.reserved_ports
+            .remove_all(layer_id)
+            .map(|port| ClientMessage::TcpSteal(LayerTcpSteal::ReleasePort(port)))
+            .collect()
+    }
+
+    /// Notifies this struct about layer forking.
+    pub fn layer_forked(&mut self, parent: LayerId, child: LayerId) {
+        self.reserved_ports.clone_all(parent, child);
+    }
+}
+
+/// Message produced by [`ReservationsManager::agent_responded`].
+#[derive(Debug)]
+pub enum ReservationMessage {
+    ToLayer(ToLayer),
+    ToAgent(ClientMessage),
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_protocol::ResponseError;
+
+    use super::*;
+
+    #[test]
+    fn released_in_all_forks() {
+        let mut manager = ReservationsManager::default();
+
+        manager.layer_reserved(LayerId(0), 0, PortReserve { port: 0 });
+        let response = manager.agent_responded(Ok(40123));
+        assert!(
+            matches!(
+                response,
+                Some(ReservationMessage::ToLayer(ToLayer {
+                    message_id: 0,
+                    layer_id: LayerId(0),
+                    message: ProxyToLayerMessage::Incoming(IncomingResponse::PortReserve(Ok(
+                        40123
+                    ))),
+                }))
+            ),
+            "{response:?}"
+        );
+
+        manager.layer_forked(LayerId(0), LayerId(1));
+        assert!(manager
+            .layer_released(LayerId(0), PortRelease { port: 40123 })
+            .is_none());
+        assert_eq!(
+            manager.layer_closed(LayerId(1)),
+            vec![ClientMessage::TcpSteal(LayerTcpSteal::ReleasePort(40123))]
+        );
+    }
+
+    #[test]
+    fn layer_closed_before_response() {
+        let mut manager = ReservationsManager::default();
+
+        manager.layer_reserved(LayerId(0), 0, PortReserve { port: 0 });
+        manager.layer_reserved(LayerId(1), 0, PortReserve { port: 21 });
+        assert!(manager.layer_closed(LayerId(0)).is_empty());
+
+        let response = manager.agent_responded(Ok(40123));
+        assert!(
+            matches!(
+                response,
+                Some(ReservationMessage::ToAgent(ClientMessage::TcpSteal(
+                    LayerTcpSteal::ReleasePort(40123)
+                )))
+            ),
+            "{response:?}"
+        );
+
+        let response = manager.agent_responded(Err(ResponseError::NotImplemented));
+        assert!(
+            matches!(
+                response,
+                Some(ReservationMessage::ToLayer(ToLayer {
+                    layer_id: LayerId(1),
+                    message: ProxyToLayerMessage::Incoming(IncomingResponse::PortReserve(Err(
+                        ResponseError::NotImplemented
+                    ))),
+                    ..
+                }))
+            ),
+            "{response:?}"
+        );
+        assert!(manager.layer_closed(LayerId(1)).is_empty());
+    }
+}
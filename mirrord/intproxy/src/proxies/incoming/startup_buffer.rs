@@ -0,0 +1,155 @@
+//! Holding of stolen requests until the local application is ready, see [`StartupBuffer`].
+
+use std::{collections::VecDeque, future, time::Duration};
+
+use mirrord_config::feature::network::incoming::StartupBufferConfig;
+use mirrord_protocol::{tcp::HttpRequestFallback, ConnectionId, Port, RequestId};
+use tokio::time::{self, Instant};
+
+/// Holds HTTP requests for ports that the local application did not subscribe to yet.
+///
+/// The requests are released when a layer subscribes to their port (see
+/// [`StartupBuffer::release`]), or when they wait for too long (see [`StartupBuffer::expired`]).
+#[derive(Debug)]
+pub(super) struct StartupBuffer {
+    /// How long a request can be held.
+    max_wait: Duration,
+    /// How many requests can be held at the same time.
+    capacity: usize,
+    /// Held requests, together with the instant when they expire, ordered by it.
+    held: VecDeque<(Instant, HttpRequestFallback)>,
+}
+
+impl StartupBuffer {
+    pub(super) fn new(config: StartupBufferConfig) -> Self {
+        Self {
+            max_wait: Duration::from_millis(config.max_wait_ms),
+            capacity: config.buffer_size,
+            held: Default::default(),
+        }
+    }
+
+    /// How long the local application can refuse connections before we give up on a request.
+    pub(super) fn max_wait(&self) -> Duration {
+        self.max_wait
+    }
+
+    /// Holds the given request.
+    ///
+    /// Returns the request back when there is no more room for it.
+    pub(super) fn hold(&mut self, request: HttpRequestFallback) -> Result<(), HttpRequestFallback> {
+        if self.held.len() >= self.capacity {
+            return Err(request);
+        }
+
+        self.held
+            .push_back((Instant::now() + self.max_wait, request));
+
+        Ok(())
+    }
+
+    /// Returns whether the request with the given id is held.
+    pub(super) fn contains(&self, connection_id: ConnectionId, request_id: RequestId) -> bool {
+        self.held.iter().any(|(_, request)| {
+            request.connection_id() == connection_id && request.request_id() == request_id
+        })
+    }
+
+    /// Drops the held requests that belong to the given connection.
+    pub(super) fn remove_connection(&mut self, connection_id: ConnectionId) {
+        self.held
+            .retain(|(_, request)| request.connection_id() != connection_id);
+    }
+
+    /// Drops the held request with the given id.
+    pub(super) fn remove(&mut self, connection_id: ConnectionId, request_id: RequestId) {
+        self.held.retain(|(_, request)| {
+            request.connection_id() != connection_id || request.request_id() != request_id
+        });
+    }
+
+    /// Removes and returns the held requests for the given port, in the order they arrived.
+    pub(super) fn release(&mut self, port: Port) -> Vec<HttpRequestFallback> {
+        let (released, held) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|(_, request)| request.port() == port);
+        self.held = held;
+
+        released.into_iter().map(|(_, request)| request).collect()
+    }
+
+    /// Resolves when the oldest held request expires, never if there are no held requests.
+    pub(super) async fn next_expiry(&self) {
+        match self.held.front() {
+            Some((expires_at, _)) => time::sleep_until(*expires_at).await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Removes and returns the held requests that waited for too long.
+    pub(super) fn expired(&mut self) -> Vec<HttpRequestFallback> {
+        let now = Instant::now();
+        let expired = self
+            .held
+            .iter()
+            .take_while(|(expires_at, _)| *expires_at <= now)
+            .count();
+
+        self.held
+            .drain(..expired)
+            .map(|(_, request)| request)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::{Method, Version};
+    use mirrord_protocol::tcp::{HttpRequest, InternalHttpRequest};
+
+    use super::*;
+
+    fn request(connection_id: ConnectionId, port: Port) -> HttpRequestFallback {
+        HttpRequestFallback::Fallback(HttpRequest {
+            connection_id,
+            request_id: 0,
+            port,
+            internal_request: InternalHttpRequest {
+                method: Method::GET,
+                uri: "/".parse().unwrap(),
+                headers: Default::default(),
+                version: Version::HTTP_11,
+                body: Default::default(),
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn release_and_expire() {
+        let mut buffer = StartupBuffer::new(StartupBufferConfig {
+            max_wait_ms: 100,
+            buffer_size: 3,
+        });
+
+        buffer.hold(request(0, 80)).unwrap();
+        buffer.hold(request(1, 81)).unwrap();
+        buffer.hold(request(2, 80)).unwrap();
+        assert!(buffer.hold(request(3, 80)).is_err());
+
+        let released = buffer
+            .release(80)
+            .iter()
+            .map(HttpRequestFallback::connection_id)
+            .collect::<Vec<_>>();
+        assert_eq!(released, [0, 2]);
+        assert!(buffer.expired().is_empty());
+
+        buffer.next_expiry().await;
+        let expired = buffer
+            .expired()
+            .iter()
+            .map(HttpRequestFallback::connection_id)
+            .collect::<Vec<_>>();
+        assert_eq!(expired, [1]);
+    }
+}
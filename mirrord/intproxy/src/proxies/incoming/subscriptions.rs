@@ -1,10 +1,11 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     net::SocketAddr,
 };
 
 use mirrord_intproxy_protocol::{
-    IncomingResponse, LayerId, MessageId, PortSubscribe, PortUnsubscribe, ProxyToLayerMessage,
+    control::StealState, IncomingResponse, LayerId, MessageId, PortSubscribe, PortSubscription,
+    PortUnsubscribe, ProxyToLayerMessage,
 };
 use mirrord_protocol::{BlockedAction, ClientMessage, Port, RemoteResult, ResponseError};
 use tracing::Level;
@@ -37,9 +38,25 @@ struct Subscription {
 
 impl Subscription {
     /// Creates a new subscription from the given [`Source`].
-    /// Additionally returns a message to be sent to the agent.
-    fn new(source: Source) -> (Self, ClientMessage) {
-        let message = source.request.subscription.agent_subscribe();
+    /// Additionally returns a message to be sent to the agent, or to the layer if no subscription
+    /// is made in the agent in the given [`StealState`].
+    fn new(source: Source, steal_state: StealState) -> (Self, ProxyMessage) {
+        let Some(subscription) = source.request.subscription.in_steal_state(steal_state) else {
+            let message = ProxyMessage::ToLayer(ToLayer {
+                message_id: source.message,
+                layer_id: source.layer,
+                message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(Ok(()))),
+            });
+
+            return (
+                Self {
+                    queued_sources: Default::default(),
+                    active_source: source,
+                    confirmed: true,
+                },
+                message,
+            );
+        };
 
         (
             Self {
@@ -47,7 +64,7 @@ impl Subscription {
                 active_source: source,
                 confirmed: false,
             },
-            message,
+            ProxyMessage::ToAgent(subscription.agent_subscribe()),
         )
     }
 
@@ -69,10 +86,29 @@ impl Subscription {
 
         let previous_source = std::mem::replace(&mut self.active_source, source);
         self.queued_sources.push(previous_source);
+        if self.confirmed {
+            self.dedup_sources();
+        }
 
         message
     }
 
+    /// Keeps only the latest source of each listener, e.g. when a listener inherited through
+    /// `exec` is subscribed again.
+    ///
+    /// Must be called only when this subscription is confirmed, as the requests of the removed
+    /// sources don't get responses anymore.
+    fn dedup_sources(&mut self) {
+        let mut listeners = HashSet::from([self.active_source.request.listening_on]);
+
+        let mut queued_sources = std::mem::take(&mut self.queued_sources);
+        queued_sources.reverse();
+        queued_sources.retain(|source| listeners.insert(source.request.listening_on));
+        queued_sources.reverse();
+
+        self.queued_sources = queued_sources;
+    }
+
     /// Confirms this subscription.
     /// Returns messages to be sent to the layers.
     fn confirm(&mut self) -> Vec<ToLayer> {
@@ -82,7 +118,8 @@ impl Subscription {
 
         self.confirmed = true;
 
-        self.queued_sources
+        let responses = self
+            .queued_sources
             .iter()
             .chain(std::iter::once(&self.active_source))
             .map(|source| ToLayer {
@@ -90,15 +127,18 @@ impl Subscription {
                 message_id: source.message,
                 message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(Ok(()))),
             })
-            .collect()
+            .collect();
+        self.dedup_sources();
+
+        responses
     }
 
     /// Rejects this subscription with the given `reason`.
     /// Returns messages to be sent to the layers.
     /// Returns [`Err`] if this subscription was already confirmed.
-    fn reject(self, reason: ResponseError) -> Result<Vec<ToLayer>, Self> {
+    fn reject(self, reason: ResponseError) -> Result<Vec<ToLayer>, Box<Self>> {
         if self.confirmed {
-            return Err(self);
+            return Err(Box::new(self));
         }
 
         let responses = self
@@ -118,8 +158,9 @@ impl Subscription {
     }
 
     /// Removed a source from this subscription.
-    /// If this source is the last one, returns [`Err`] with a message to be sent to the agent.
-    fn remove_source(mut self, listening_on: SocketAddr) -> Result<Self, Box<ClientMessage>> {
+    /// If this source is the last one, returns [`Err`] with the subscription to remove from the
+    /// agent.
+    fn remove_source(mut self, listening_on: SocketAddr) -> Result<Self, Box<PortSubscription>> {
         let queue_size = self.queued_sources.len();
         self.queued_sources
             .retain(|source| source.request.listening_on != listening_on);
@@ -136,16 +177,22 @@ impl Subscription {
                 self.active_source = next_in_queue;
                 Ok(self)
             }
-            None => Err(Box::new(
-                self.active_source
-                    .request
-                    .subscription
-                    .wrap_agent_unsubscribe(),
-            )),
+            None => Err(Box::new(self.active_source.request.subscription)),
         }
     }
 }
 
+/// Returns the message that removes the given subscription from the agent, if it was made there in
+/// the given [`StealState`].
+fn agent_unsubscribe(
+    subscription: &PortSubscription,
+    steal_state: StealState,
+) -> Option<ClientMessage> {
+    subscription
+        .in_steal_state(steal_state)
+        .map(|subscription| subscription.wrap_agent_unsubscribe())
+}
+
 /// Manages port subscriptions across all connected layers.
 /// Logic of this struct is a bit complicated for several reasons:
 /// 1. Layer can subscribe to a single port multiple times (e.g. with `port_mapping`)
@@ -158,6 +205,8 @@ impl Subscription {
 pub struct SubscriptionsManager {
     remote_ports: RemoteResources<(Port, SocketAddr)>,
     subscriptions: HashMap<Port, Subscription>,
+    /// How the steal subscriptions are made in the agent.
+    steal_state: StealState,
 }
 
 impl SubscriptionsManager {
@@ -195,9 +244,9 @@ impl SubscriptionsManager {
         match self.subscriptions.entry(port) {
             Entry::Occupied(mut e) => e.get_mut().push_source(source).map(ProxyMessage::ToLayer),
             Entry::Vacant(e) => {
-                let (subscription, message) = Subscription::new(source);
+                let (subscription, message) = Subscription::new(source, self.steal_state);
                 e.insert(subscription);
-                Some(ProxyMessage::ToAgent(message))
+                Some(message)
             }
         }
     }
@@ -223,10 +272,34 @@ impl SubscriptionsManager {
                 self.subscriptions.insert(request.port, subscription);
                 None
             }
-            Err(message) => Some(*message),
+            Err(subscription) => agent_unsubscribe(&subscription, self.steal_state),
         }
     }
 
+    /// Changes how the steal subscriptions are made in the agent.
+    /// Returns messages to be sent to the agent.
+    pub fn set_steal_state(&mut self, steal_state: StealState) -> Vec<ClientMessage> {
+        let previous = std::mem::replace(&mut self.steal_state, steal_state);
+
+        self.subscriptions
+            .values()
+            .flat_map(|subscription| {
+                let requested = &subscription.active_source.request.subscription;
+                let before = requested.in_steal_state(previous);
+                let after = requested.in_steal_state(steal_state);
+                if before == after {
+                    return vec![];
+                }
+
+                before
+                    .map(|subscription| subscription.wrap_agent_unsubscribe())
+                    .into_iter()
+                    .chain(after.map(|subscription| subscription.agent_subscribe()))
+                    .collect()
+            })
+            .collect()
+    }
+
     /// Notifies this struct about agent's response.
     /// Returns messages to be sent to the layers.
     #[tracing::instrument(level = Level::TRACE, ret, skip(self))]
@@ -250,7 +323,7 @@ impl SubscriptionsManager {
                 match subscription.reject(ResponseError::PortAlreadyStolen(port)) {
                     Ok(responses) => Ok(responses),
                     Err(subscription) => {
-                        self.subscriptions.insert(port, subscription);
+                        self.subscriptions.insert(port, *subscription);
                         Ok(vec![])
                     }
                 }
@@ -279,6 +352,8 @@ impl SubscriptionsManager {
     /// Notifies this struct about layer closing.
     /// Returns messages to be sent to the agent.
     pub fn layer_closed(&mut self, layer_id: LayerId) -> Vec<ClientMessage> {
+        let steal_state = self.steal_state;
+
         self.remote_ports
             .remove_all(layer_id)
             .filter_map(|(port, listening_on)| {
@@ -288,7 +363,7 @@ impl SubscriptionsManager {
                         self.subscriptions.insert(port, subscription);
                         None
                     }
-                    Err(message) => Some(*message),
+                    Err(subscription) => agent_unsubscribe(&subscription, steal_state),
                 }
             })
             .collect()
@@ -303,7 +378,7 @@ impl SubscriptionsManager {
 #[cfg(test)]
 mod test {
     use mirrord_intproxy_protocol::PortSubscription;
-    use mirrord_protocol::tcp::LayerTcp;
+    use mirrord_protocol::tcp::{LayerTcp, LayerTcpSteal, StealType};
 
     use super::*;
 
@@ -453,6 +528,59 @@ mod test {
         assert!(manager.get(80).is_none());
     }
 
+    /// The listener is inherited through `exec` by a new layer (e.g. gunicorn's `SIGUSR2`
+    /// upgrade), which subscribes it again, and the old layer exits.
+    #[test]
+    fn with_exec() {
+        let listening_on = "127.0.0.1:1111".parse().unwrap();
+        let request = PortSubscribe {
+            listening_on,
+            subscription: PortSubscription::Mirror(80),
+        };
+
+        let mut manager = SubscriptionsManager::default();
+
+        manager.layer_subscribed(LayerId(0), 0, request.clone());
+        manager.agent_responded(Ok(80)).unwrap();
+
+        // Confirmed without the agent, the port is already subscribed.
+        for message_id in 1..=3 {
+            let response = manager.layer_subscribed(LayerId(1), message_id, request.clone());
+            assert!(
+                matches!(
+                    response,
+                    Some(ProxyMessage::ToLayer(ToLayer {
+                        layer_id: LayerId(1),
+                        message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(
+                            Ok(())
+                        )),
+                        ..
+                    }))
+                ),
+                "{response:?}"
+            );
+        }
+        let subscription = manager.subscriptions.get(&80).unwrap();
+        assert!(subscription.queued_sources.is_empty(), "{subscription:?}");
+
+        let messages = manager.layer_closed(LayerId(0));
+        assert!(messages.is_empty(), "{messages:?}");
+        assert_eq!(manager.get(80).unwrap().listening_on, listening_on);
+
+        let response = manager.layer_unsubscribed(
+            LayerId(1),
+            PortUnsubscribe {
+                port: 80,
+                listening_on,
+            },
+        );
+        assert_eq!(
+            response,
+            Some(ClientMessage::Tcp(LayerTcp::PortUnsubscribe(80)))
+        );
+        assert!(manager.get(80).is_none());
+    }
+
     #[test]
     fn with_double_response() {
         let listening_on = "127.0.0.1:1111".parse().unwrap();
@@ -498,4 +626,86 @@ mod test {
             .unwrap();
         assert!(responses.is_empty(), "{responses:?}");
     }
+
+    #[test]
+    fn with_steal_state() {
+        let mut manager = SubscriptionsManager::default();
+
+        let response = manager.layer_subscribed(
+            LayerId(0),
+            0,
+            PortSubscribe {
+                listening_on: "127.0.0.1:1111".parse().unwrap(),
+                subscription: PortSubscription::Steal(StealType::All(80)),
+            },
+        );
+        assert!(
+            matches!(
+                response,
+                Some(ProxyMessage::ToAgent(ClientMessage::TcpSteal(
+                    LayerTcpSteal::PortSubscribe(StealType::All(80))
+                )))
+            ),
+            "{response:?}"
+        );
+        manager.agent_responded(Ok(80)).unwrap();
+
+        let messages = manager.set_steal_state(StealState::Paused);
+        assert_eq!(
+            messages,
+            vec![ClientMessage::TcpSteal(LayerTcpSteal::PortUnsubscribe(80))]
+        );
+
+        // Confirmed without the agent, as there is nothing to subscribe.
+        let response = manager.layer_subscribed(
+            LayerId(0),
+            1,
+            PortSubscribe {
+                listening_on: "127.0.0.1:2222".parse().unwrap(),
+                subscription: PortSubscription::Steal(StealType::All(81)),
+            },
+        );
+        assert!(
+            matches!(
+                response,
+                Some(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 1,
+                    message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(Ok(()))),
+                    ..
+                }))
+            ),
+            "{response:?}"
+        );
+
+        let mut messages = manager.set_steal_state(StealState::Mirrored);
+        messages.sort_by_key(|message| format!("{message:?}"));
+        assert_eq!(
+            messages,
+            vec![
+                ClientMessage::Tcp(LayerTcp::PortSubscribe(80)),
+                ClientMessage::Tcp(LayerTcp::PortSubscribe(81)),
+            ]
+        );
+
+        let response = manager.layer_unsubscribed(
+            LayerId(0),
+            PortUnsubscribe {
+                port: 81,
+                listening_on: "127.0.0.1:2222".parse().unwrap(),
+            },
+        );
+        assert_eq!(
+            response,
+            Some(ClientMessage::Tcp(LayerTcp::PortUnsubscribe(81)))
+        );
+
+        let messages = manager.set_steal_state(StealState::Active);
+        assert_eq!(
+            messages,
+            vec![
+                ClientMessage::Tcp(LayerTcp::PortUnsubscribe(80)),
+                ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::All(80))),
+            ]
+        );
+    }
 }
@@ -3,21 +3,26 @@
 use std::{collections::HashMap, fmt, io};
 
 use mirrord_intproxy_protocol::{
-    LayerId, MessageId, NetProtocol, OutgoingConnectRequest, OutgoingConnectResponse,
-    ProxyToLayerMessage,
+    InterceptedConnection, LayerId, MessageId, NetProtocol, OutgoingConnectRequest,
+    OutgoingConnectResponse, ProxyToLayerMessage, SendFileToConnRequest,
 };
 use mirrord_protocol::{
-    outgoing::{tcp::DaemonTcpOutgoing, udp::DaemonUdpOutgoing, DaemonConnect, DaemonRead},
-    ConnectionId, RemoteResult, ResponseError,
+    file::{SendFileResponse, SEND_FILE_VERSION},
+    outgoing::{
+        tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
+        udp::DaemonUdpOutgoing,
+        DaemonConnect, DaemonRead, SocketAddress,
+    },
+    ClientMessage, ConnectionId, RemoteResult, ResponseError,
 };
 use thiserror::Error;
 use tracing::Level;
 
-use self::interceptor::Interceptor;
+use self::interceptor::{Interceptor, MessageIn, MessageOut};
 use crate::{
     background_tasks::{BackgroundTask, BackgroundTasks, MessageBus, TaskSender, TaskUpdate},
     main_tasks::ToLayer,
-    proxies::outgoing::net_protocol_ext::NetProtocolExt,
+    proxies::{outgoing::net_protocol_ext::NetProtocolExt, send_file::SendFileQueue},
     request_queue::{RequestQueue, RequestQueueEmpty},
     ProxyMessage,
 };
@@ -33,8 +38,8 @@ pub enum OutgoingProxyError {
     /// Originates only from the [`RemoteResult<DaemonRead>`] message.
     #[error("agent error: {0}")]
     ResponseError(#[from] ResponseError),
-    /// The agent sent a [`DaemonConnect`] response, but the [`RequestQueue`] for layer's connec
-    /// requests was empty. This should never happen.
+    /// The agent sent a [`DaemonConnect`] or a [`DaemonTcpOutgoing::SendFile`] response, but the
+    /// [`RequestQueue`] for layer's requests was empty. This should never happen.
     #[error("failed to match connect response: {0}")]
     RequestQueueEmpty(#[from] RequestQueueEmpty),
     /// The proxy failed to prepare a new local socket for the intercepted connection.
@@ -77,6 +82,9 @@ impl fmt::Display for InterceptorId {
 /// 6. The proxy passes the data between the agent and the [`Interceptor`] task.
 /// 7. If the layer closes the connection, the [`Interceptor`] exits and the proxy notifies the
 ///    agent. If the agent closes the connection, the proxy shuts down the [`Interceptor`].
+///
+/// The proxy also handles the layer's [`SendFileToConnRequest`]s for the intercepted
+/// [`NetProtocol::Stream`] connections, see [`SendFileQueue`].
 #[derive(Default)]
 pub struct OutgoingProxy {
     /// For [`OutgoingConnectRequest`]s related to [`NetProtocol::Datagrams`].
@@ -86,7 +94,14 @@ pub struct OutgoingProxy {
     /// [`TaskSender`]s for active [`Interceptor`] tasks.
     txs: HashMap<InterceptorId, TaskSender<Interceptor>>,
     /// For managing [`Interceptor`] tasks.
-    background_tasks: BackgroundTasks<InterceptorId, Vec<u8>, io::Error>,
+    background_tasks: BackgroundTasks<InterceptorId, MessageOut, io::Error>,
+    /// [`OutgoingConnectResponse::layer_address`]es of the active [`NetProtocol::Stream`]
+    /// [`Interceptor`]s.
+    layer_addresses: HashMap<InterceptorId, SocketAddress>,
+    /// For [`SendFileToConnRequest`]s.
+    send_files: SendFileQueue<InterceptorId>,
+    /// Version of [`mirrord_protocol`] negotiated with the agent.
+    agent_protocol_version: Option<semver::Version>,
 }
 
 impl OutgoingProxy {
@@ -179,6 +194,9 @@ impl OutgoingProxy {
             Self::CHANNEL_SIZE,
         );
         self.txs.insert(id, interceptor);
+        if protocol == NetProtocol::Stream {
+            self.layer_addresses.insert(id, layer_address.clone());
+        }
 
         message_bus
             .send(ToLayer {
@@ -208,6 +226,77 @@ impl OutgoingProxy {
         let msg = request.protocol.wrap_agent_connect(request.remote_address);
         message_bus.send(ProxyMessage::ToAgent(msg)).await;
     }
+
+    /// Saves the layer's request and asks the [`Interceptor`] of the connection to flush, the
+    /// request is sent to the agent afterwards.
+    ///
+    /// Responds with [`ResponseError::NotImplemented`] when the agent does not support
+    /// [`SEND_FILE_VERSION`], or the connection is not intercepted by this proxy.
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    async fn handle_send_file_request(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: SendFileToConnRequest,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let supported = self
+            .agent_protocol_version
+            .as_ref()
+            .is_some_and(|version| SEND_FILE_VERSION.matches(version));
+
+        let interceptor = match &request.connection {
+            InterceptedConnection::Outgoing(address) if supported => self
+                .layer_addresses
+                .iter()
+                .find(|(_, layer_address)| *layer_address == address)
+                .map(|(id, _)| *id),
+            _ => None,
+        };
+
+        let Some((id, tx)) = interceptor.and_then(|id| Some((id, self.txs.get(&id)?))) else {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    layer_id,
+                    message: ProxyToLayerMessage::SendFileToConn(Err(
+                        ResponseError::NotImplemented,
+                    )),
+                })
+                .await;
+
+            return;
+        };
+
+        self.send_files
+            .layer_requested(id, id.connection_id, message_id, layer_id, request);
+        tx.send(MessageIn::Flush).await;
+    }
+
+    /// Passes agent's response to a [`LayerTcpOutgoing::SendFile`] to the layer.
+    async fn handle_send_file_response(
+        &mut self,
+        result: RemoteResult<SendFileResponse>,
+        message_bus: &mut MessageBus<Self>,
+    ) -> Result<(), OutgoingProxyError> {
+        let (message_id, layer_id) = self.send_files.agent_responded()?;
+
+        message_bus
+            .send(ToLayer {
+                message_id,
+                layer_id,
+                message: ProxyToLayerMessage::SendFileToConn(result),
+            })
+            .await;
+
+        Ok(())
+    }
+
+    /// Removes the [`Interceptor`] and its address.
+    fn remove_interceptor(&mut self, id: InterceptorId) -> Option<TaskSender<Interceptor>> {
+        self.layer_addresses.remove(&id);
+        self.txs.remove(&id)
+    }
 }
 
 /// Messages consumed by the [`OutgoingProxy`] running as a [`BackgroundTask`].
@@ -215,6 +304,9 @@ pub enum OutgoingProxyMessage {
     AgentStream(DaemonTcpOutgoing),
     AgentDatagrams(DaemonUdpOutgoing),
     LayerConnect(OutgoingConnectRequest, MessageId, LayerId),
+    LayerSendFile(SendFileToConnRequest, MessageId, LayerId),
+    /// Agent responded to [`ClientMessage::SwitchProtocolVersion`].
+    AgentProtocolVersion(semver::Version),
 }
 
 impl BackgroundTask for OutgoingProxy {
@@ -233,15 +325,16 @@ impl BackgroundTask for OutgoingProxy {
                     Some(OutgoingProxyMessage::AgentStream(req)) => match req {
                         DaemonTcpOutgoing::Close(close) => {
                             let id = InterceptorId { connection_id: close, protocol: NetProtocol::Stream};
-                            self.txs.remove(&id);
+                            self.remove_interceptor(id);
                         },
                         DaemonTcpOutgoing::Read(read) => self.handle_agent_read(read, NetProtocol::Stream).await?,
                         DaemonTcpOutgoing::Connect(connect) => self.handle_connect_response(connect, NetProtocol::Stream, message_bus).await?,
+                        DaemonTcpOutgoing::SendFile(result) => self.handle_send_file_response(result, message_bus).await?,
                     }
                     Some(OutgoingProxyMessage::AgentDatagrams(req)) => match req {
                         DaemonUdpOutgoing::Close(close) => {
                             let id = InterceptorId { connection_id: close, protocol: NetProtocol::Datagrams};
-                            self.txs.remove(&id);
+                            self.remove_interceptor(id);
                         }
                         DaemonUdpOutgoing::Read(read) => self.handle_agent_read(read, NetProtocol::Datagrams).await?,
                         DaemonUdpOutgoing::Connect(connect) => self.handle_connect_response(connect, NetProtocol::Datagrams, message_bus).await?,
@@ -252,21 +345,47 @@ impl BackgroundTask for OutgoingProxy {
                         req,
                         message_bus
                     ).await,
+                    Some(OutgoingProxyMessage::LayerSendFile(req, message_id, layer_id)) => self.handle_send_file_request(
+                        message_id,
+                        layer_id,
+                        req,
+                        message_bus,
+                    ).await,
+                    Some(OutgoingProxyMessage::AgentProtocolVersion(version)) => {
+                        self.agent_protocol_version.replace(version);
+                    }
                 },
 
                 Some(task_update) = self.background_tasks.next() => match task_update {
-                    (id, TaskUpdate::Message(bytes)) => {
+                    (id, TaskUpdate::Message(MessageOut::Raw(bytes))) => {
                         let msg = id.protocol.wrap_agent_write(id.connection_id, bytes);
                         message_bus.send(ProxyMessage::ToAgent(msg)).await;
                     }
+                    (id, TaskUpdate::Message(MessageOut::Flushed)) => {
+                        if let Some(request) = self.send_files.interceptor_flushed(id) {
+                            let msg = ClientMessage::TcpOutgoing(LayerTcpOutgoing::SendFile(request));
+                            message_bus.send(ProxyMessage::ToAgent(msg)).await;
+                        }
+                    }
                     (id, TaskUpdate::Finished(res)) => {
                         tracing::trace!("{id} finished: {res:?}");
 
-                        if self.txs.remove(&id).is_some() {
+                        if self.remove_interceptor(id).is_some() {
                             tracing::trace!("local connection closed, notifying the agent");
                             let msg = id.protocol.wrap_agent_close(id.connection_id);
                             let _ = message_bus.send(ProxyMessage::ToAgent(msg)).await;
-                            self.txs.remove(&id);
+                        }
+
+                        for (message_id, layer_id) in self.send_files.interceptor_finished(id) {
+                            message_bus
+                                .send(ToLayer {
+                                    message_id,
+                                    layer_id,
+                                    message: ProxyToLayerMessage::SendFileToConn(Err(
+                                        ResponseError::NotFound(id.connection_id),
+                                    )),
+                                })
+                                .await;
                         }
                     }
                 },
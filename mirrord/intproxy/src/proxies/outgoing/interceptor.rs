@@ -8,6 +8,31 @@ use crate::{
     proxies::outgoing::net_protocol_ext::PreparedSocket,
 };
 
+/// Messages consumed by the [`Interceptor`] when it runs as a [`BackgroundTask`].
+#[derive(Debug)]
+pub enum MessageIn {
+    /// Data to be sent to the layer.
+    Raw(Vec<u8>),
+    /// Request to send all data that the layer already sent to the [`MessageBus`], followed by
+    /// [`MessageOut::Flushed`].
+    Flush,
+}
+
+/// Messages produced by the [`Interceptor`] when it runs as a [`BackgroundTask`].
+#[derive(Debug)]
+pub enum MessageOut {
+    /// Data received from the layer.
+    Raw(Vec<u8>),
+    /// Response to [`MessageIn::Flush`].
+    Flushed,
+}
+
+impl From<Vec<u8>> for MessageIn {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Raw(value)
+    }
+}
+
 /// Manages a single intercepted connection.
 /// Multiple instances are run as [`BackgroundTask`]s by one [`OutgoingProxy`](super::OutgoingProxy)
 /// to manage individual connections.
@@ -25,8 +50,8 @@ impl Interceptor {
 
 impl BackgroundTask for Interceptor {
     type Error = io::Error;
-    type MessageIn = Vec<u8>;
-    type MessageOut = Vec<u8>;
+    type MessageIn = MessageIn;
+    type MessageOut = MessageOut;
 
     /// Accepts one connection the owned [`PreparedSocket`] and transparently proxies bytes between
     /// the [`MessageBus`] and the new
@@ -40,7 +65,9 @@ impl BackgroundTask for Interceptor {
     /// 2. A 0-sized read received from the [`MessageBus`] is treated as a shutdown on the agent
     ///    side. Connection with the peer is shut down as well.
     ///
-    /// 3. This implementation exits only when an error is encountered or the [`MessageBus`] is
+    /// 3. [`MessageIn::Flush`] is handled without waiting for more data from the peer.
+    ///
+    /// 4. This implementation exits only when an error is encountered or the [`MessageBus`] is
     ///    closed.
     async fn run(self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
         let mut connected_socket = self.socket.accept().await?;
@@ -58,12 +85,12 @@ impl BackgroundTask for Interceptor {
                             tracing::trace!("outgoing interceptor -> layer shutdown, sending a 0-sized read to inform the agent");
                             reading_closed = true;
                         }
-                        message_bus.send(bytes).await
+                        message_bus.send(MessageOut::Raw(bytes)).await
                     },
                 },
 
                 msg = message_bus.recv() => match msg {
-                    Some(bytes) => {
+                    Some(MessageIn::Raw(bytes)) => {
                         if bytes.is_empty() {
                             tracing::trace!("outgoing interceptor -> agent shutdown, shutting down connection with layer");
                             connected_socket.shutdown().await?;
@@ -72,6 +99,22 @@ impl BackgroundTask for Interceptor {
                         }
                     }
 
+                    Some(MessageIn::Flush) => {
+                        while !reading_closed {
+                            let Some(bytes) = connected_socket.try_receive()? else {
+                                break;
+                            };
+
+                            if bytes.is_empty() {
+                                tracing::trace!("outgoing interceptor -> layer shutdown, sending a 0-sized read to inform the agent");
+                                reading_closed = true;
+                            }
+                            message_bus.send(MessageOut::Raw(bytes)).await;
+                        }
+
+                        message_bus.send(MessageOut::Flushed).await;
+                    }
+
                     None => {
                         tracing::trace!("outgoing interceptor -> no more messages from the agent, exiting");
                         break Ok(())
@@ -1,26 +1,35 @@
 //! Utilities for handling multiple network protocol stacks within one
 //! [`OutgoingProxy`](super::OutgoingProxy).
 
+#[cfg(unix)]
+use std::{env, path::PathBuf};
 use std::{
-    env, io,
+    io::{self, Read},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
-    path::PathBuf,
 };
 
 use bytes::BytesMut;
 use mirrord_intproxy_protocol::NetProtocol;
+#[cfg(unix)]
+use mirrord_protocol::outgoing::UnixAddr;
 use mirrord_protocol::{
     outgoing::{
         tcp::LayerTcpOutgoing, udp::LayerUdpOutgoing, LayerClose, LayerConnect, LayerWrite,
-        SocketAddress, UnixAddr,
+        SocketAddress,
     },
     ClientMessage, ConnectionId,
 };
+#[cfg(unix)]
 use rand::{distributions::Alphanumeric, Rng};
+use socket2::SockRef;
+#[cfg(unix)]
 use tokio::{
     fs,
+    net::{UnixListener, UnixStream},
+};
+use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream, UdpSocket, UnixListener, UnixStream},
+    net::{TcpListener, TcpStream, UdpSocket},
 };
 
 /// Trait for [`NetProtocol`] that handles differences in [`mirrord_protocol::outgoing`] between
@@ -94,6 +103,7 @@ impl NetProtocolExt for NetProtocol {
                     Self::Stream => PreparedSocket::TcpListener(TcpListener::bind(bind_at).await?),
                 }
             }
+            #[cfg(unix)]
             SocketAddress::Unix(..) => match self {
                 Self::Stream => {
                     let path = PreparedSocket::generate_uds_path().await?;
@@ -104,6 +114,12 @@ impl NetProtocolExt for NetProtocol {
                     panic!("layer requested outgoing datagrams over unix sockets");
                 }
             },
+            // The layer runs inside WSL, where a path bound by us on Windows is meaningless.
+            #[cfg(windows)]
+            SocketAddress::Unix(..) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "intercepting outgoing unix sockets is not supported on Windows",
+            ))?,
         };
 
         Ok(socket)
@@ -115,13 +131,16 @@ pub enum PreparedSocket {
     /// There is no real listening/accepting here, see [`NetProtocol::Datagrams`] for more info.
     UdpSocket(UdpSocket),
     TcpListener(TcpListener),
+    #[cfg(unix)]
     UnixListener(UnixListener),
 }
 
 impl PreparedSocket {
     /// For unix listeners, relative to the temp dir.
+    #[cfg(unix)]
     const UNIX_STREAMS_DIRNAME: &'static str = "mirrord-unix-sockets";
 
+    #[cfg(unix)]
     async fn generate_uds_path() -> io::Result<PathBuf> {
         let tmp_dir = env::temp_dir().join(Self::UNIX_STREAMS_DIRNAME);
         if !tmp_dir.exists() {
@@ -142,6 +161,7 @@ impl PreparedSocket {
         let address = match self {
             Self::TcpListener(listener) => listener.local_addr()?.into(),
             Self::UdpSocket(socket) => socket.local_addr()?.into(),
+            #[cfg(unix)]
             Self::UnixListener(listener) => {
                 let addr = listener.local_addr()?;
                 let pathname = addr.as_pathname().unwrap().to_path_buf();
@@ -161,6 +181,7 @@ impl PreparedSocket {
                 (InnerConnectedSocket::TcpStream(stream), true)
             }
             Self::UdpSocket(socket) => (InnerConnectedSocket::UdpSocket(socket), false),
+            #[cfg(unix)]
             Self::UnixListener(listener) => {
                 let (stream, _) = listener.accept().await?;
                 (InnerConnectedSocket::UnixStream(stream), true)
@@ -178,6 +199,7 @@ impl PreparedSocket {
 enum InnerConnectedSocket {
     UdpSocket(UdpSocket),
     TcpStream(TcpStream),
+    #[cfg(unix)]
     UnixStream(UnixStream),
 }
 
@@ -208,6 +230,7 @@ impl ConnectedSocket {
             InnerConnectedSocket::TcpStream(stream) => {
                 stream.write_all(bytes).await.map_err(Into::into)
             }
+            #[cfg(unix)]
             InnerConnectedSocket::UnixStream(stream) => {
                 stream.write_all(bytes).await.map_err(Into::into)
             }
@@ -235,6 +258,7 @@ impl ConnectedSocket {
                 self.buffer.clear();
                 Ok(bytes)
             }
+            #[cfg(unix)]
             InnerConnectedSocket::UnixStream(stream) => {
                 stream.read_buf(&mut self.buffer).await?;
                 let bytes = self.buffer.to_vec();
@@ -244,6 +268,34 @@ impl ConnectedSocket {
         }
     }
 
+    /// Receives the data that the layer already sent, without waiting for more.
+    ///
+    /// Returns [`None`] when there is no data, an empty [`Vec`] means that the layer shut down
+    /// writing. Always [`None`] for UDP sockets.
+    ///
+    /// # Note
+    ///
+    /// Reads from the socket directly, as the readiness cached by [`tokio`] can be stale right
+    /// after the layer sent the data.
+    pub fn try_receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let socket = match &self.inner {
+            InnerConnectedSocket::TcpStream(stream) => SockRef::from(stream),
+            #[cfg(unix)]
+            InnerConnectedSocket::UnixStream(stream) => SockRef::from(stream),
+            InnerConnectedSocket::UdpSocket(..) => return Ok(None),
+        };
+
+        let mut bytes = vec![0; self.buffer.capacity()];
+        match (&*socket).read(&mut bytes) {
+            Ok(read_amount) => {
+                bytes.truncate(read_amount);
+                Ok(Some(bytes))
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
     /// Shuts the connection down. See [`AsyncWriteExt::shutdown`].
     ///
     /// # Note
@@ -252,6 +304,7 @@ impl ConnectedSocket {
     pub async fn shutdown(&mut self) -> io::Result<()> {
         match &mut self.inner {
             InnerConnectedSocket::TcpStream(stream) => stream.shutdown().await,
+            #[cfg(unix)]
             InnerConnectedSocket::UnixStream(stream) => stream.shutdown().await,
             InnerConnectedSocket::UdpSocket(..) => Ok(()),
         }
@@ -0,0 +1,137 @@
+//! Layers' [`SendFileToConnRequest`]s, shared by the
+//! [`OutgoingProxy`](super::outgoing::OutgoingProxy) and the
+//! [`IncomingProxy`](super::incoming::IncomingProxy).
+//!
+//! The bytes that the user application wrote to the intercepted connection before calling
+//! `sendfile` have to reach the agent before the file does. Because of this, a request waits until
+//! the interceptor of the connection flushes the bytes it could read from the layer, and only then
+//! it's sent to the agent.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+};
+
+use mirrord_intproxy_protocol::{LayerId, MessageId, SendFileToConnRequest};
+use mirrord_protocol::{file::SendFileRequest, ConnectionId};
+use tracing::Level;
+
+use crate::request_queue::{RequestQueue, RequestQueueEmpty};
+
+/// [`SendFileToConnRequest`] waiting for the interceptor to flush.
+#[derive(Debug)]
+struct PendingSendFile {
+    message_id: MessageId,
+    layer_id: LayerId,
+    request: SendFileRequest,
+}
+
+/// Manages the [`SendFileToConnRequest`]s of the connections intercepted by a single proxy,
+/// identified by `I`.
+pub struct SendFileQueue<I> {
+    /// Requests waiting for the interceptors to flush, in order.
+    flushing: HashMap<I, VecDeque<PendingSendFile>>,
+    /// Requests sent to the agent, which responds to them in order.
+    sent: RequestQueue,
+}
+
+impl<I> Default for SendFileQueue<I> {
+    fn default() -> Self {
+        Self {
+            flushing: Default::default(),
+            sent: Default::default(),
+        }
+    }
+}
+
+impl<I: Hash + Eq + Copy + fmt::Debug> SendFileQueue<I> {
+    /// Registers a new request for the connection with the given [`ConnectionId`], the
+    /// interceptor should be asked to flush after this.
+    #[tracing::instrument(level = Level::TRACE, skip(self))]
+    pub fn layer_requested(
+        &mut self,
+        interceptor: I,
+        connection_id: ConnectionId,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: SendFileToConnRequest,
+    ) {
+        self.flushing
+            .entry(interceptor)
+            .or_default()
+            .push_back(PendingSendFile {
+                message_id,
+                layer_id,
+                request: SendFileRequest {
+                    connection_id,
+                    fd: request.fd,
+                    offset: request.offset,
+                    count: request.count,
+                },
+            });
+    }
+
+    /// Notifies this struct about the interceptor flushing.
+    /// Returns the request to be sent to the agent.
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    pub fn interceptor_flushed(&mut self, interceptor: I) -> Option<SendFileRequest> {
+        let pending = self.flushing.get_mut(&interceptor)?.pop_front()?;
+        self.sent.insert(pending.message_id, pending.layer_id);
+
+        Some(pending.request)
+    }
+
+    /// Notifies this struct about the agent's response.
+    /// Returns the request the response belongs to.
+    pub fn agent_responded(&mut self) -> Result<(MessageId, LayerId), RequestQueueEmpty> {
+        self.sent.get()
+    }
+
+    /// Notifies this struct about the interceptor finishing.
+    /// Returns the requests that will never be sent to the agent.
+    pub fn interceptor_finished(&mut self, interceptor: I) -> Vec<(MessageId, LayerId)> {
+        self.flushing
+            .remove(&interceptor)
+            .into_iter()
+            .flatten()
+            .map(|pending| (pending.message_id, pending.layer_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_intproxy_protocol::InterceptedConnection;
+
+    use super::*;
+
+    fn request(fd: u64) -> SendFileToConnRequest {
+        SendFileToConnRequest {
+            connection: InterceptedConnection::Incoming("127.0.0.1:40123".parse().unwrap()),
+            fd,
+            offset: None,
+            count: 4096,
+        }
+    }
+
+    #[test]
+    fn sent_after_flush() {
+        let mut queue = SendFileQueue::<u64>::default();
+
+        queue.layer_requested(0, 7, 0, LayerId(0), request(1));
+        queue.layer_requested(0, 7, 1, LayerId(0), request(2));
+        queue.layer_requested(1, 8, 0, LayerId(1), request(3));
+
+        let sent = queue.interceptor_flushed(1).unwrap();
+        assert_eq!((sent.connection_id, sent.fd), (8, 3));
+        let sent = queue.interceptor_flushed(0).unwrap();
+        assert_eq!((sent.connection_id, sent.fd), (7, 1));
+        assert_eq!(queue.agent_responded().unwrap(), (0, LayerId(1)));
+        assert_eq!(queue.agent_responded().unwrap(), (0, LayerId(0)));
+        assert!(queue.agent_responded().is_err());
+
+        assert_eq!(queue.interceptor_finished(0), vec![(1, LayerId(0))]);
+        assert!(queue.interceptor_flushed(0).is_none());
+    }
+}
@@ -1,18 +1,40 @@
 //! The most basic proxying logic. Handles cases when the only job to do in the internal proxy is to
 //! pass requests and responses between the layer and the agent.
 
-use std::{collections::HashMap, vec::IntoIter};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+    vec::IntoIter,
+};
 
+use dir_snapshots::{DirSnapshot, DirSnapshots};
+use follows::{offset_fd, FileFollows};
+use metadata_cache::{DirListing, MetadataCache};
 use mirrord_intproxy_protocol::{LayerId, MessageId, ProxyToLayerMessage};
 use mirrord_protocol::{
-    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    dns::{GetAddrInfoRequestV2, GetAddrInfoResponse, ADDRINFO_V2_VERSION},
+    fd_budget::{CloseFdsRequest, IdleFds},
     file::{
-        CloseDirRequest, CloseFileRequest, DirEntryInternal, OpenDirResponse, OpenFileResponse,
-        ReadDirBatchRequest, ReadDirBatchResponse, ReadDirRequest, ReadDirResponse,
-        READDIR_BATCH_VERSION,
+        ChmodFileRequest, ChownFileRequest, CloseDirRequest, CloseFileRequest,
+        CopyFileRangeRequest, DirEntryInternal, FallocateFileRequest, FchmodFileRequest,
+        FchownFileRequest, FdOpenDirRequest, FtruncateFileRequest, FutimensFileRequest,
+        LinkFileRequest, OpenAt2Request, OpenDirResponse, OpenFileRequest, OpenFileResponse,
+        OpenRelativeFileRequest, ReadChunkResponse, ReadDirBatchRequest, ReadDirBatchResponse,
+        ReadDirDeltaRequest, ReadDirDeltaResponse, ReadDirPlusBatchRequest,
+        ReadDirPlusBatchResponse, ReadDirRequest, ReadDirResponse, ReadFileRequest,
+        ReadFileResponse, ReadLimitedFileRequest, ReadStreamFileRequest, RemoveXattrRequest,
+        RenameFileRequest, SetXattrRequest, SymlinkRequest, TruncateFileRequest, UnlinkFileRequest,
+        WriteFileRequest, WriteLimitedFileRequest, WriteVFileRequest, FOLLOW_VERSION,
+        PREFETCH_TREE_VERSION, READDIR_BATCH_VERSION, READ_DIR_DELTA_VERSION,
+        READ_DIR_PLUS_VERSION, READ_STREAM_VERSION,
     },
-    ClientMessage, FileRequest, FileResponse, GetEnvVarsRequest, RemoteResult, ResponseError,
+    interfaces::{GetIfAddrsRequest, GetIfAddrsResponse, GET_IFADDRS_VERSION},
+    output::{OutputMessage, OUTPUT_VERSION},
+    ClientMessage, ErrorKindInternal, FileRequest, FileResponse, GetEnvVarsRequest, RemoteIOError,
+    RemoteResult, ResponseError,
 };
+use retry::{file_retry_after, RetryQueue};
 use semver::Version;
 use thiserror::Error;
 
@@ -24,17 +46,36 @@ use crate::{
     ProxyMessage,
 };
 
+mod dir_snapshots;
+mod follows;
+mod metadata_cache;
+mod retry;
+mod versions;
+
 #[derive(Debug)]
 pub enum SimpleProxyMessage {
     FileReq(MessageId, LayerId, FileRequest),
     FileRes(FileResponse),
-    AddrInfoReq(MessageId, LayerId, GetAddrInfoRequest),
+    AddrInfoReq(MessageId, LayerId, GetAddrInfoRequestV2),
     AddrInfoRes(GetAddrInfoResponse),
     LayerForked(LayerForked),
     LayerClosed(LayerClosed),
     GetEnvReq(MessageId, LayerId, GetEnvVarsRequest),
     GetEnvRes(RemoteResult<HashMap<String, String>>),
+    GetIfAddrsReq(MessageId, LayerId, GetIfAddrsRequest),
+    GetIfAddrsRes(GetIfAddrsResponse),
+    OutputReq(OutputMessage),
     ProtocolVersion(Version),
+    /// Whether the file operations can modify the remote filesystem, see
+    /// [`FeatureToggle::DisableFsWrites`](mirrord_intproxy_protocol::control::FeatureToggle::DisableFsWrites).
+    FsWrites(bool),
+    /// Enables the [`MetadataCache`], with the given TTL.
+    MetadataCache(Duration),
+    /// Prefetches the remote trees at the given absolute paths into the [`MetadataCache`], see
+    /// [`FileRequest::PrefetchTree`].
+    Prefetch(Vec<PathBuf>),
+    /// The remote files and directories that were not used for a while, reported by the agent.
+    IdleFds(IdleFds),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -45,9 +86,24 @@ pub(crate) enum RemoteFd {
 
 #[derive(Clone)]
 pub(crate) enum FileResource {
-    File,
+    File {
+        /// Absolute path of the file, when we know it.
+        path: Option<PathBuf>,
+    },
     Dir {
         dirs_iter: IntoIter<DirEntryInternal>,
+        /// Absolute path of the directory, when we know it.
+        path: Option<PathBuf>,
+        /// Entries read from the agent so far, cached once the agent reaches the end of the
+        /// directory. [`None`] when the [`MetadataCache`] is disabled.
+        listing: Option<DirListing>,
+        /// Whether `dirs_iter` holds all the remaining entries, because the directory was listed
+        /// from the [`MetadataCache`] or in a single [`FileRequest::ReadDirDelta`].
+        complete: bool,
+        /// Whether we asked the agent for entries of this directory already.
+        requested: bool,
+        /// The previous listing of the directory, that the agent sent the changes to.
+        snapshot: Option<DirSnapshot>,
     },
 }
 
@@ -69,13 +125,115 @@ impl From<FileError> for ResponseError {
     }
 }
 
+/// `EROFS`, the same on Linux and macOS.
+const READ_ONLY_FS_ERRNO: i32 = 30;
+
+/// Returns the response for `request` if it would modify the remote filesystem, which is what
+/// the layer gets when the writes are disabled.
+fn write_rejection(request: &FileRequest) -> Option<FileResponse> {
+    let error = ResponseError::RemoteIO(RemoteIOError {
+        raw_os_error: Some(READ_ONLY_FS_ERRNO),
+        kind: ErrorKindInternal::ReadOnlyFilesystem,
+    });
+
+    match request {
+        FileRequest::Write(..) => Some(FileResponse::Write(Err(error))),
+        FileRequest::WriteLimited(..) => Some(FileResponse::WriteLimited(Err(error))),
+        FileRequest::WriteV(..) => Some(FileResponse::WriteV(Err(error))),
+        FileRequest::Unlink(..) | FileRequest::UnlinkAt(..) => {
+            Some(FileResponse::Unlink(Err(error)))
+        }
+        FileRequest::Rename(..) | FileRequest::RenameAt(..) => {
+            Some(FileResponse::Rename(Err(error)))
+        }
+        FileRequest::Chmod(..) | FileRequest::Fchmod(..) | FileRequest::ChmodAt(..) => {
+            Some(FileResponse::Chmod(Err(error)))
+        }
+        FileRequest::Chown(..) | FileRequest::Fchown(..) | FileRequest::ChownAt(..) => {
+            Some(FileResponse::Chown(Err(error)))
+        }
+        FileRequest::Truncate(..) | FileRequest::Ftruncate(..) => {
+            Some(FileResponse::Truncate(Err(error)))
+        }
+        FileRequest::Symlink(..) | FileRequest::SymlinkAt(..) => {
+            Some(FileResponse::Symlink(Err(error)))
+        }
+        FileRequest::Link(..) | FileRequest::LinkAt(..) => Some(FileResponse::Link(Err(error))),
+        FileRequest::UtimensAt(..) | FileRequest::Futimens(..) => {
+            Some(FileResponse::Utimens(Err(error)))
+        }
+        FileRequest::SetXattr(..) => Some(FileResponse::SetXattr(Err(error))),
+        FileRequest::RemoveXattr(..) => Some(FileResponse::RemoveXattr(Err(error))),
+        FileRequest::Open(OpenFileRequest { open_options, .. })
+        | FileRequest::OpenRelative(OpenRelativeFileRequest { open_options, .. })
+        | FileRequest::OpenAt2(OpenAt2Request { open_options, .. })
+            if !open_options.is_read_only() =>
+        {
+            Some(FileResponse::Open(Err(error)))
+        }
+        _ => None,
+    }
+}
+
+/// Most bytes that the agent is asked to read in a single message.
+///
+/// Bigger reads of the layer are streamed by agents that support [`READ_STREAM_VERSION`], in
+/// chunks of this size, and cut to this size (a short read) for older agents.
+const READ_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Prepares a [`FileRequest::Read`] or [`FileRequest::ReadLimited`] of the layer for the agent,
+/// see [`READ_CHUNK_SIZE`].
+fn chunked_read(request: FileRequest, protocol_version: Option<&Version>) -> FileRequest {
+    let (fd, length, offset) = match request {
+        FileRequest::Read(ReadFileRequest {
+            remote_fd,
+            buffer_size,
+        }) => (remote_fd, buffer_size, None),
+        FileRequest::ReadLimited(ReadLimitedFileRequest {
+            remote_fd,
+            buffer_size,
+            start_from,
+        }) => (remote_fd, buffer_size, Some(start_from)),
+        request => return request,
+    };
+
+    if length > READ_CHUNK_SIZE
+        && protocol_version.is_some_and(|version| READ_STREAM_VERSION.matches(version))
+    {
+        return FileRequest::ReadStream(ReadStreamFileRequest {
+            fd,
+            length,
+            offset,
+            chunk_size: READ_CHUNK_SIZE,
+        });
+    }
+
+    let buffer_size = length.min(READ_CHUNK_SIZE);
+    match offset {
+        Some(start_from) => FileRequest::ReadLimited(ReadLimitedFileRequest {
+            remote_fd: fd,
+            buffer_size,
+            start_from,
+        }),
+        None => FileRequest::Read(ReadFileRequest {
+            remote_fd: fd,
+            buffer_size,
+        }),
+    }
+}
+
 impl FileResource {
     fn next_dir(&mut self, remote_fd: u64) -> Result<Option<DirEntryInternal>, FileError> {
         match self {
-            FileResource::Dir { dirs_iter } => dirs_iter.next().map(Ok).transpose(),
-            FileResource::File => Err(FileError::DirOnFile(remote_fd)),
+            FileResource::Dir { dirs_iter, .. } => dirs_iter.next().map(Ok).transpose(),
+            FileResource::File { .. } => Err(FileError::DirOnFile(remote_fd)),
         }
     }
+
+    /// Whether there's nothing more to read from the agent for this directory.
+    fn is_complete(&self) -> bool {
+        matches!(self, FileResource::Dir { complete: true, .. })
+    }
 }
 
 /// For passing messages between the layer and the agent without custom internal logic.
@@ -84,12 +242,28 @@ impl FileResource {
 pub struct SimpleProxy {
     /// Remote descriptors for open files and directories. Allows tracking across layer forks.
     remote_fds: RemoteResources<RemoteFd, FileResource>,
-    /// For [`FileRequest`]s.
-    file_reqs: RequestQueue,
-    /// For [`GetAddrInfoRequest`]s.
-    addr_info_reqs: RequestQueue,
+    /// For [`FileRequest`]s, which the agent may throttle.
+    file_reqs: RetryQueue,
+    /// For [`GetAddrInfoRequestV2`]s, which the agent may throttle.
+    addr_info_reqs: RetryQueue,
     /// For [`GetEnvVarsRequest`]s.
     get_env_reqs: RequestQueue,
+    /// For [`GetIfAddrsRequest`]s.
+    ifaddrs_reqs: RequestQueue,
+    /// Whether the [`FileRequest`]s that would modify the remote filesystem are rejected.
+    fs_writes_disabled: bool,
+    /// Chunks of the [`FileRequest::ReadStream`] that the agent is responding to, by their
+    /// sequence numbers.
+    read_stream: BTreeMap<u64, Vec<u8>>,
+    /// Results of the [`FileRequest`]s that the local app repeats, [`None`] when disabled.
+    metadata_cache: Option<MetadataCache>,
+    /// The last listings of remote directories, for [`FileRequest::ReadDirDelta`].
+    dir_snapshots: DirSnapshots,
+    /// The remote files that the layers follow, for [`FileRequest::Follow`].
+    follows: FileFollows,
+    /// Remote trees to prefetch, once the [`MetadataCache`] is enabled and the agent is known to
+    /// support [`PREFETCH_TREE_VERSION`].
+    prefetch: Vec<PathBuf>,
 }
 
 impl SimpleProxy {
@@ -112,38 +286,294 @@ impl SimpleProxy {
             .get_mut(&layer_id, &RemoteFd::Dir(remote_fd))
             .ok_or(FileError::MissingResource(remote_fd))?;
 
-        if let Some(dir) = resource.next_dir(remote_fd)? {
+        let next_dir = resource.next_dir(remote_fd)?;
+        if next_dir.is_some() || resource.is_complete() {
             message_bus
                 .send(ToLayer {
                     message_id,
                     message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
-                        ReadDirResponse {
-                            direntry: Some(dir),
-                        },
+                        ReadDirResponse { direntry: next_dir },
                     ))),
                     layer_id,
                 })
                 .await;
         } else {
-            self.file_reqs.insert(message_id, layer_id);
+            let FileResource::Dir {
+                path,
+                listing,
+                requested,
+                snapshot,
+                ..
+            } = resource
+            else {
+                return Err(FileError::DirOnFile(remote_fd));
+            };
 
-            let request =
-                if protocol_version.is_some_and(|version| READDIR_BATCH_VERSION.matches(version)) {
-                    FileRequest::ReadDirBatch(ReadDirBatchRequest {
-                        remote_fd,
-                        amount: 128,
-                    })
-                } else {
-                    FileRequest::ReadDir(ReadDirRequest { remote_fd })
-                };
+            // When the listing goes to the metadata cache, the metadata of the entries goes with
+            // it, as the app is likely to stat them next (e.g. `ls -l`). Otherwise, the first
+            // time, we get the whole directory in a single response, with only the entries that
+            // changed since we last listed it.
+            let request = if listing.is_some()
+                && protocol_version.is_some_and(|version| READ_DIR_PLUS_VERSION.matches(version))
+            {
+                FileRequest::ReadDirPlusBatch(ReadDirPlusBatchRequest {
+                    remote_fd,
+                    amount: 128,
+                })
+            } else if let Some(path) = path.as_ref().filter(|_| {
+                !*requested
+                    && protocol_version
+                        .is_some_and(|version| READ_DIR_DELTA_VERSION.matches(version))
+            }) {
+                *snapshot = self.dir_snapshots.take(path);
+                FileRequest::ReadDirDelta(ReadDirDeltaRequest {
+                    remote_fd,
+                    since: snapshot.as_ref().map(|snapshot| snapshot.cookie),
+                })
+            } else if protocol_version.is_some_and(|version| READDIR_BATCH_VERSION.matches(version))
+            {
+                FileRequest::ReadDirBatch(ReadDirBatchRequest {
+                    remote_fd,
+                    amount: 128,
+                })
+            } else {
+                FileRequest::ReadDir(ReadDirRequest { remote_fd })
+            };
+            *requested = true;
 
             // Convert it into a `ReadDirBatch` for the agent.
+            if let Some(request) =
+                self.file_reqs
+                    .insert(message_id, layer_id, ClientMessage::FileRequest(request))
+            {
+                message_bus.send(ProxyMessage::ToAgent(request)).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the first of the `dir_entries` that the agent listed from the remote directory `fd`
+    /// to the layer, and keeps the rest for its next `readdir`s.
+    async fn read_dir_batch(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        fd: u64,
+        dir_entries: Vec<DirEntryInternal>,
+        message_bus: &mut MessageBus<SimpleProxy>,
+    ) {
+        let resource = self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd));
+        if let Some(FileResource::Dir {
+            path: Some(path),
+            listing: listing @ Some(..),
+            ..
+        }) = resource
+        {
+            // An empty batch means that the agent reached the end of the directory.
+            if dir_entries.is_empty() {
+                if let (Some(cache), Some(listing)) = (self.metadata_cache.as_mut(), listing.take())
+                {
+                    cache.finish_listing(path.clone(), listing);
+                }
+            } else if let Some(listing) = listing {
+                listing.entries.extend(dir_entries.iter().cloned());
+            }
+        }
+
+        let mut entries_iter = dir_entries.into_iter();
+        let direntry = entries_iter.next();
+
+        message_bus
+            .send(ToLayer {
+                message_id,
+                message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(ReadDirResponse {
+                    direntry,
+                }))),
+                layer_id,
+            })
+            .await;
+
+        if let Some(FileResource::Dir { dirs_iter, .. }) =
+            self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd))
+        {
+            *dirs_iter = entries_iter;
+        }
+    }
+
+    /// Absolute path of the remote file `fd` of the layer, if we know it.
+    fn file_path(&mut self, layer_id: LayerId, fd: u64) -> Option<PathBuf> {
+        match self.remote_fds.get_mut(&layer_id, &RemoteFd::File(fd))? {
+            FileResource::File { path } => path.clone(),
+            FileResource::Dir { .. } => None,
+        }
+    }
+
+    /// Removes from the [`MetadataCache`] the paths that `request` of the layer is about to
+    /// modify.
+    fn invalidate_metadata(&mut self, layer_id: LayerId, request: &FileRequest) {
+        if self.metadata_cache.is_none() {
+            return;
+        }
+
+        let paths = match request {
+            FileRequest::Open(OpenFileRequest { path, open_options })
+                if !open_options.is_read_only() =>
+            {
+                vec![Some(path.clone())]
+            }
+            FileRequest::Unlink(UnlinkFileRequest { path })
+            | FileRequest::Chmod(ChmodFileRequest { path, .. })
+            | FileRequest::Chown(ChownFileRequest { path, .. })
+            | FileRequest::Truncate(TruncateFileRequest { path, .. })
+            | FileRequest::Symlink(SymlinkRequest {
+                link_path: path, ..
+            })
+            | FileRequest::SetXattr(SetXattrRequest {
+                path: Some(path),
+                fd: None,
+                ..
+            })
+            | FileRequest::RemoveXattr(RemoveXattrRequest {
+                path: Some(path),
+                fd: None,
+                ..
+            }) => vec![Some(path.clone())],
+            FileRequest::Rename(RenameFileRequest { old_path, new_path })
+            | FileRequest::Link(LinkFileRequest { old_path, new_path }) => {
+                vec![Some(old_path.clone()), Some(new_path.clone())]
+            }
+            FileRequest::Write(WriteFileRequest { fd, .. })
+            | FileRequest::WriteLimited(WriteLimitedFileRequest { remote_fd: fd, .. })
+            | FileRequest::WriteV(WriteVFileRequest { fd, .. })
+            | FileRequest::Fchmod(FchmodFileRequest { fd, .. })
+            | FileRequest::Fchown(FchownFileRequest { fd, .. })
+            | FileRequest::Ftruncate(FtruncateFileRequest { fd, .. })
+            | FileRequest::Futimens(FutimensFileRequest { fd, .. })
+            | FileRequest::Fallocate(FallocateFileRequest { fd, .. })
+            | FileRequest::CopyFileRange(CopyFileRangeRequest { fd_out: fd, .. }) => {
+                vec![self.file_path(layer_id, *fd)]
+            }
+            // Relative to directory fds, or the other modifications that we can't locate.
+            request if write_rejection(request).is_some() => vec![None],
+            _ => return,
+        };
+
+        let Some(cache) = self.metadata_cache.as_mut() else {
+            return;
+        };
+        for path in paths {
+            match path {
+                Some(path) if path.is_absolute() => cache.invalidate(&path),
+                _ => {
+                    cache.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Sends a [`FileRequest::PrefetchTree`] for each path in [`Self::prefetch`], once the
+    /// [`MetadataCache`] is enabled and the agent supports them.
+    async fn prefetch(
+        &mut self,
+        protocol_version: Option<&Version>,
+        message_bus: &mut MessageBus<SimpleProxy>,
+    ) {
+        let (Some(cache), Some(version)) = (self.metadata_cache.as_mut(), protocol_version) else {
+            return;
+        };
+        if self.prefetch.is_empty() {
+            return;
+        }
+
+        if !PREFETCH_TREE_VERSION.matches(version) {
+            tracing::warn!(
+                %version,
+                "Agent does not support prefetching, \
+                paths in `feature.fs.prefetch` will be read on demand"
+            );
+            self.prefetch.clear();
+            return;
+        }
+
+        for path in std::mem::take(&mut self.prefetch) {
+            let request = cache.prefetch(path);
             message_bus
-                .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(request)))
+                .send(ClientMessage::FileRequest(FileRequest::PrefetchTree(
+                    request,
+                )))
                 .await;
         }
+    }
 
-        Ok(())
+    /// Closes the [`IdleFds`] that no layer holds, the agent would keep them open until the end of
+    /// the session (e.g. the layer was gone when the agent opened them).
+    ///
+    /// The ones that the layers hold are left open, the local app may still use them, we only
+    /// warn that it may be leaking them.
+    async fn idle_fds(&mut self, idle: IdleFds, message_bus: &mut MessageBus<SimpleProxy>) {
+        let (held_files, files): (Vec<_>, Vec<_>) = idle
+            .files
+            .into_iter()
+            .partition(|fd| self.remote_fds.contains(&RemoteFd::File(*fd)));
+        let (held_dirs, dirs): (Vec<_>, Vec<_>) = idle
+            .dirs
+            .into_iter()
+            .partition(|fd| self.remote_fds.contains(&RemoteFd::Dir(*fd)));
+
+        if !held_files.is_empty() || !held_dirs.is_empty() {
+            tracing::warn!(
+                files = held_files.len(),
+                dirs = held_dirs.len(),
+                idle_timeout_secs = idle.idle_timeout_secs,
+                "The local app keeps remote files open without using them, \
+                it may be leaking file descriptors"
+            );
+        }
+
+        if files.is_empty() && dirs.is_empty() {
+            return;
+        }
+
+        tracing::debug!(?files, ?dirs, "Closing leaked remote descriptors");
+        message_bus
+            .send(ClientMessage::CloseFds(CloseFdsRequest { files, dirs }))
+            .await;
+    }
+
+    /// Sends `request` of the layer to the agent, unless the response is in the
+    /// [`MetadataCache`].
+    async fn send_file_request(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: FileRequest,
+        message_bus: &mut MessageBus<SimpleProxy>,
+    ) {
+        let cached = self
+            .metadata_cache
+            .as_mut()
+            .and_then(|cache| cache.request(message_id, layer_id, &request));
+        if let Some(response) = cached {
+            message_bus
+                .send(ToLayer {
+                    message_id,
+                    message: ProxyToLayerMessage::File(response),
+                    layer_id,
+                })
+                .await;
+            return;
+        }
+
+        self.invalidate_metadata(layer_id, &request);
+
+        if let Some(request) =
+            self.file_reqs
+                .insert(message_id, layer_id, ClientMessage::FileRequest(request))
+        {
+            message_bus.send(ProxyMessage::ToAgent(request)).await;
+        }
     }
 }
 
@@ -154,13 +584,52 @@ impl BackgroundTask for SimpleProxy {
 
     async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), RequestQueueEmpty> {
         let mut protocol_version = None;
+        // Whether we already warned that the agent can't handle the output of the local process.
+        let mut output_warned = false;
+
+        loop {
+            let msg = tokio::select! {
+                msg = message_bus.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+
+                _ = self.file_reqs.backoff_finished() => {
+                    for request in self.file_reqs.retry() {
+                        message_bus.send(ProxyMessage::ToAgent(request)).await;
+                    }
+                    continue;
+                }
+
+                _ = self.addr_info_reqs.backoff_finished() => {
+                    for request in self.addr_info_reqs.retry() {
+                        message_bus.send(ProxyMessage::ToAgent(request)).await;
+                    }
+                    continue;
+                }
+            };
 
-        while let Some(msg) = message_bus.recv().await {
             tracing::trace!(?msg, "new message in message_bus");
 
+            if let SimpleProxyMessage::FileReq(_, _, request) = &msg {
+                if let Some(unfollow) = offset_fd(request).and_then(|fd| self.follows.stop(fd)) {
+                    message_bus
+                        .send(ClientMessage::FileRequest(FileRequest::Unfollow(unfollow)))
+                        .await;
+                }
+            }
+
+            if let SimpleProxyMessage::FileRes(response) = &msg {
+                if let Some(retry_after) = file_retry_after(response) {
+                    self.file_reqs.throttled(retry_after)?;
+                    continue;
+                }
+            }
+
             match msg {
                 SimpleProxyMessage::ProtocolVersion(new_protocol_version) => {
                     protocol_version = Some(new_protocol_version);
+                    self.prefetch(protocol_version.as_ref(), message_bus).await;
                 }
                 SimpleProxyMessage::FileReq(
                     _,
@@ -217,43 +686,107 @@ impl BackgroundTask for SimpleProxy {
                             .await;
                     }
                 }
-                // TODO(alex): We can remove this case when users are up-to-date for `readlink`.
+                // Followed files are read from the bytes that the agent sent, see `FileFollows`.
+                SimpleProxyMessage::FileReq(
+                    message_id,
+                    layer_id,
+                    FileRequest::Read(ReadFileRequest {
+                        remote_fd,
+                        buffer_size,
+                    }),
+                ) if self.follows.is_following(remote_fd) => {
+                    let response = self.follows.read(remote_fd, buffer_size);
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::File(FileResponse::Read(Ok(response))),
+                            layer_id,
+                        })
+                        .await;
+                }
+                // Big reads are streamed by the agent, see `READ_CHUNK_SIZE`.
                 SimpleProxyMessage::FileReq(
                     message_id,
                     layer_id,
-                    req @ FileRequest::ReadLink(_),
+                    req @ (FileRequest::Read(..) | FileRequest::ReadLimited(..)),
                 ) => {
-                    if protocol_version
-                        .as_ref()
-                        .is_some_and(|version| READDIR_BATCH_VERSION.matches(version))
+                    let follow_fd = match &req {
+                        FileRequest::Read(ReadFileRequest { remote_fd, .. })
+                            if protocol_version
+                                .as_ref()
+                                .is_some_and(|version| FOLLOW_VERSION.matches(version)) =>
+                        {
+                            Some(*remote_fd)
+                        }
+                        _ => None,
+                    };
+                    let request = chunked_read(req, protocol_version.as_ref());
+
+                    if let Some(request) = self.file_reqs.insert(
+                        message_id,
+                        layer_id,
+                        ClientMessage::FileRequest(request),
+                    ) {
+                        message_bus.send(ProxyMessage::ToAgent(request)).await;
+
+                        if let Some(follow) = follow_fd.and_then(|fd| self.follows.start(fd)) {
+                            message_bus
+                                .send(ClientMessage::FileRequest(FileRequest::Follow(follow)))
+                                .await;
+                        }
+                    }
+                }
+                SimpleProxyMessage::FsWrites(enabled) => {
+                    self.fs_writes_disabled = !enabled;
+                }
+                SimpleProxyMessage::MetadataCache(ttl) => {
+                    self.metadata_cache = Some(MetadataCache::new(ttl));
+                    self.prefetch(protocol_version.as_ref(), message_bus).await;
+                }
+                SimpleProxyMessage::Prefetch(paths) => {
+                    self.prefetch = paths;
+                    self.prefetch(protocol_version.as_ref(), message_bus).await;
+                }
+                SimpleProxyMessage::FileReq(message_id, layer_id, req) => {
+                    if let Some(response) = self
+                        .fs_writes_disabled
+                        .then(|| write_rejection(&req))
+                        .flatten()
                     {
-                        self.file_reqs.insert(message_id, layer_id);
                         message_bus
-                            .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(req)))
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::File(response),
+                                layer_id,
+                            })
                             .await;
-                    } else {
+                    } else if let Some(response) =
+                        versions::not_implemented(&req, protocol_version.as_ref())
+                    {
                         message_bus
                             .send(ToLayer {
                                 message_id,
-                                message: ProxyToLayerMessage::File(FileResponse::ReadLink(Err(
-                                    ResponseError::NotImplemented,
-                                ))),
+                                message: ProxyToLayerMessage::File(response),
                                 layer_id,
                             })
                             .await;
+                    } else {
+                        self.send_file_request(message_id, layer_id, req, message_bus)
+                            .await;
                     }
                 }
-                SimpleProxyMessage::FileReq(message_id, layer_id, req) => {
-                    self.file_reqs.insert(message_id, layer_id);
-                    message_bus
-                        .send(ProxyMessage::ToAgent(ClientMessage::FileRequest(req)))
-                        .await;
-                }
                 SimpleProxyMessage::FileRes(FileResponse::Open(Ok(OpenFileResponse { fd }))) => {
+                    let path = match self.file_reqs.front() {
+                        Some(ClientMessage::FileRequest(FileRequest::Open(OpenFileRequest {
+                            path,
+                            ..
+                        }))) if path.is_absolute() => Some(path.clone()),
+                        _ => None,
+                    };
                     let (message_id, layer_id) = self.file_reqs.get()?;
 
                     self.remote_fds
-                        .add(layer_id, RemoteFd::File(fd), FileResource::File);
+                        .add(layer_id, RemoteFd::File(fd), FileResource::File { path });
 
                     message_bus
                         .send(ToLayer {
@@ -266,15 +799,32 @@ impl BackgroundTask for SimpleProxy {
                         .await;
                 }
                 SimpleProxyMessage::FileRes(FileResponse::OpenDir(Ok(OpenDirResponse { fd }))) => {
+                    let file_fd = match self.file_reqs.front() {
+                        Some(ClientMessage::FileRequest(FileRequest::FdOpenDir(
+                            FdOpenDirRequest { remote_fd },
+                        ))) => Some(*remote_fd),
+                        _ => None,
+                    };
                     let (message_id, layer_id) = self.file_reqs.get()?;
 
-                    self.remote_fds.add(
-                        layer_id,
-                        RemoteFd::Dir(fd),
-                        FileResource::Dir {
-                            dirs_iter: IntoIter::default(),
-                        },
-                    );
+                    let path = file_fd.and_then(|file_fd| self.file_path(layer_id, file_fd));
+                    let (dirs_iter, listing, complete) =
+                        match (self.metadata_cache.as_mut(), path.as_ref()) {
+                            (Some(cache), Some(path)) => match cache.listing(path) {
+                                Some(entries) => (entries.into_iter(), None, true),
+                                None => (IntoIter::default(), Some(cache.start_listing()), false),
+                            },
+                            _ => (IntoIter::default(), None, false),
+                        };
+                    let resource = FileResource::Dir {
+                        dirs_iter,
+                        path,
+                        listing,
+                        complete,
+                        requested: false,
+                        snapshot: None,
+                    };
+                    self.remote_fds.add(layer_id, RemoteFd::Dir(fd), resource);
 
                     message_bus
                         .send(ToLayer {
@@ -290,9 +840,77 @@ impl BackgroundTask for SimpleProxy {
                     ReadDirBatchResponse { fd, dir_entries },
                 ))) => {
                     let (message_id, layer_id) = self.file_reqs.get()?;
+                    self.read_dir_batch(message_id, layer_id, fd, dir_entries, message_bus)
+                        .await;
+                }
+                // The layer gets only the entries, their metadata goes to the cache.
+                SimpleProxyMessage::FileRes(FileResponse::ReadDirPlusBatch(Ok(
+                    ReadDirPlusBatchResponse { fd, dir_entries },
+                ))) => {
+                    let (message_id, layer_id) = self.file_reqs.get()?;
+
+                    if let (
+                        Some(cache),
+                        Some(FileResource::Dir {
+                            path: Some(path),
+                            listing: Some(listing),
+                            ..
+                        }),
+                    ) = (
+                        self.metadata_cache.as_mut(),
+                        self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd)),
+                    ) {
+                        cache.entries_metadata(path, listing, &dir_entries);
+                    }
+
+                    let dir_entries = dir_entries.into_iter().map(|entry| entry.entry).collect();
+                    self.read_dir_batch(message_id, layer_id, fd, dir_entries, message_bus)
+                        .await;
+                }
+                // The whole directory, as the changes since the snapshot of its previous listing.
+                SimpleProxyMessage::FileRes(FileResponse::ReadDirDelta(Ok(
+                    ReadDirDeltaResponse { fd, cookie, delta },
+                ))) => {
+                    let (message_id, layer_id) = self.file_reqs.get()?;
+
+                    let direntry = match self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd)) {
+                        Some(FileResource::Dir {
+                            dirs_iter,
+                            path,
+                            listing,
+                            complete,
+                            snapshot,
+                            ..
+                        }) => {
+                            let previous = snapshot
+                                .take()
+                                .map(|snapshot| snapshot.entries)
+                                .unwrap_or_default();
+                            let entries = delta.apply(previous);
+
+                            if let Some(path) = path {
+                                self.dir_snapshots.insert(
+                                    path.clone(),
+                                    DirSnapshot {
+                                        cookie,
+                                        entries: entries.clone(),
+                                    },
+                                );
 
-                    let mut entries_iter = dir_entries.into_iter();
-                    let direntry = entries_iter.next();
+                                if let (Some(cache), Some(mut listing)) =
+                                    (self.metadata_cache.as_mut(), listing.take())
+                                {
+                                    listing.entries = entries.clone();
+                                    cache.finish_listing(path.clone(), listing);
+                                }
+                            }
+
+                            *dirs_iter = entries.into_iter();
+                            *complete = true;
+                            dirs_iter.next()
+                        }
+                        _ => None,
+                    };
 
                     message_bus
                         .send(ToLayer {
@@ -303,15 +921,106 @@ impl BackgroundTask for SimpleProxy {
                             layer_id,
                         })
                         .await;
+                }
+                SimpleProxyMessage::FileRes(
+                    FileResponse::ReadDirDelta(Err(fail))
+                    | FileResponse::ReadDirPlusBatch(Err(fail)),
+                ) => {
+                    let (message_id, layer_id) = self.file_reqs.get()?;
 
-                    if let Some(FileResource::Dir { dirs_iter }) =
-                        self.remote_fds.get_mut(&layer_id, &RemoteFd::Dir(fd))
-                    {
-                        *dirs_iter = entries_iter;
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::File(FileResponse::ReadDir(Err(fail))),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::FileRes(FileResponse::ReadChunk(Ok(ReadChunkResponse {
+                    sequence,
+                    bytes,
+                    last: false,
+                }))) => {
+                    self.read_stream.insert(sequence, bytes);
+                }
+                // The stream is over, the layer gets a single response for it.
+                SimpleProxyMessage::FileRes(FileResponse::ReadChunk(chunk)) => {
+                    let failure = match chunk {
+                        Ok(ReadChunkResponse {
+                            sequence, bytes, ..
+                        }) => {
+                            self.read_stream.insert(sequence, bytes);
+                            None
+                        }
+                        Err(fail) => Some(fail),
+                    };
+
+                    let bytes = std::mem::take(&mut self.read_stream)
+                        .into_values()
+                        .collect::<Vec<_>>()
+                        .concat();
+                    let response = match failure {
+                        Some(fail) if bytes.is_empty() => Err(fail),
+                        _ => Ok(ReadFileResponse {
+                            read_amount: bytes.len() as u64,
+                            bytes,
+                        }),
+                    };
+
+                    let limited = matches!(
+                        self.file_reqs.front(),
+                        Some(ClientMessage::FileRequest(FileRequest::ReadStream(
+                            ReadStreamFileRequest {
+                                offset: Some(..),
+                                ..
+                            }
+                        )))
+                    );
+                    let response = if limited {
+                        FileResponse::ReadLimited(response)
+                    } else {
+                        FileResponse::Read(response)
+                    };
+
+                    let (message_id, layer_id) = self.file_reqs.get()?;
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::File(response),
+                            layer_id,
+                        })
+                        .await;
+                }
+                SimpleProxyMessage::FileRes(FileResponse::FollowChunk(chunk)) => {
+                    if let Some(unfollow) = self.follows.chunk(chunk) {
+                        message_bus
+                            .send(ClientMessage::FileRequest(FileRequest::Unfollow(unfollow)))
+                            .await;
+                    }
+                }
+                // Not a response to a request of the layer, see `Self::prefetch`.
+                SimpleProxyMessage::FileRes(FileResponse::PrefetchChunk(chunk)) => {
+                    if let Some(cache) = self.metadata_cache.as_mut() {
+                        cache.prefetch_chunk(chunk);
                     }
                 }
                 SimpleProxyMessage::FileRes(res) => {
+                    if let (
+                        FileResponse::Read(Ok(ReadFileResponse { read_amount, .. })),
+                        Some(ClientMessage::FileRequest(FileRequest::Read(ReadFileRequest {
+                            remote_fd,
+                            ..
+                        }))),
+                    ) = (&res, self.file_reqs.front())
+                    {
+                        self.follows.agent_read(*remote_fd, *read_amount);
+                    }
+
                     let (message_id, layer_id) = self.file_reqs.get()?;
+                    if let Some(cache) = self.metadata_cache.as_mut() {
+                        cache.response(message_id, layer_id, &res);
+                    }
+
                     message_bus
                         .send(ToLayer {
                             message_id,
@@ -321,12 +1030,27 @@ impl BackgroundTask for SimpleProxy {
                         .await;
                 }
                 SimpleProxyMessage::AddrInfoReq(message_id, session_id, req) => {
-                    self.addr_info_reqs.insert(message_id, session_id);
-                    message_bus
-                        .send(ProxyMessage::ToAgent(ClientMessage::GetAddrInfoRequest(
-                            req,
-                        )))
-                        .await;
+                    // Older agents don't know about the hints, so we drop them.
+                    let request = if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| ADDRINFO_V2_VERSION.matches(version))
+                    {
+                        ClientMessage::GetAddrInfoRequestV2(req)
+                    } else {
+                        ClientMessage::GetAddrInfoRequest(req.into())
+                    };
+
+                    if let Some(request) =
+                        self.addr_info_reqs.insert(message_id, session_id, request)
+                    {
+                        message_bus.send(ProxyMessage::ToAgent(request)).await;
+                    }
+                }
+                SimpleProxyMessage::AddrInfoRes(GetAddrInfoResponse(Err(
+                    ResponseError::Throttled { retry_after_ms },
+                ))) => {
+                    self.addr_info_reqs
+                        .throttled(Duration::from_millis(retry_after_ms))?;
                 }
                 SimpleProxyMessage::AddrInfoRes(res) => {
                     let (message_id, layer_id) = self.addr_info_reqs.get()?;
@@ -344,7 +1068,17 @@ impl BackgroundTask for SimpleProxy {
                             RemoteFd::Dir(remote_fd) => {
                                 FileRequest::CloseDir(CloseDirRequest { remote_fd })
                             }
-                            RemoteFd::File(fd) => FileRequest::Close(CloseFileRequest { fd }),
+                            RemoteFd::File(fd) => {
+                                if let Some(unfollow) = self.follows.stop(fd) {
+                                    message_bus
+                                        .send(ClientMessage::FileRequest(FileRequest::Unfollow(
+                                            unfollow,
+                                        )))
+                                        .await;
+                                }
+
+                                FileRequest::Close(CloseFileRequest { fd })
+                            }
                         };
 
                         message_bus.send(ClientMessage::FileRequest(req)).await;
@@ -353,6 +1087,7 @@ impl BackgroundTask for SimpleProxy {
                 SimpleProxyMessage::LayerForked(LayerForked { child, parent }) => {
                     self.remote_fds.clone_all(parent, child);
                 }
+                SimpleProxyMessage::IdleFds(idle) => self.idle_fds(idle, message_bus).await,
                 SimpleProxyMessage::GetEnvReq(message_id, layer_id, req) => {
                     self.get_env_reqs.insert(message_id, layer_id);
                     message_bus
@@ -369,6 +1104,53 @@ impl BackgroundTask for SimpleProxy {
                         })
                         .await
                 }
+                SimpleProxyMessage::GetIfAddrsReq(message_id, layer_id, req) => {
+                    if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| GET_IFADDRS_VERSION.matches(version))
+                    {
+                        self.ifaddrs_reqs.insert(message_id, layer_id);
+                        message_bus
+                            .send(ProxyMessage::ToAgent(ClientMessage::GetIfAddrsRequest(req)))
+                            .await;
+                    } else {
+                        // The layer falls back to the local interfaces.
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                message: ProxyToLayerMessage::GetIfAddrs(GetIfAddrsResponse(Err(
+                                    ResponseError::NotImplemented,
+                                ))),
+                                layer_id,
+                            })
+                            .await;
+                    }
+                }
+                SimpleProxyMessage::GetIfAddrsRes(res) => {
+                    let (message_id, layer_id) = self.ifaddrs_reqs.get()?;
+                    message_bus
+                        .send(ToLayer {
+                            message_id,
+                            message: ProxyToLayerMessage::GetIfAddrs(res),
+                            layer_id,
+                        })
+                        .await
+                }
+                SimpleProxyMessage::OutputReq(output) => {
+                    if protocol_version
+                        .as_ref()
+                        .is_some_and(|version| OUTPUT_VERSION.matches(version))
+                    {
+                        message_bus.send(ClientMessage::Output(output)).await;
+                    } else if !output_warned {
+                        tracing::warn!(
+                            ?protocol_version,
+                            "Agent does not support the output of the local process, \
+                            it will not be written to the target"
+                        );
+                        output_warned = true;
+                    }
+                }
             }
         }
 
@@ -380,13 +1162,31 @@ impl BackgroundTask for SimpleProxy {
 #[cfg(test)]
 mod tests {
 
+    use std::time::Duration;
+
     use mirrord_intproxy_protocol::{LayerId, ProxyToLayerMessage};
     use mirrord_protocol::{
+        fd_budget::{CloseFdsRequest, IdleFds},
         file::{
-            FdOpenDirRequest, OpenDirResponse, ReadDirBatchRequest, ReadDirBatchResponse,
-            ReadDirRequest, ReadDirResponse,
+            AccessFileRequest, AccessFileResponse, ChmodFileRequest, ChownFileWithDirRequest,
+            CopyFileRangeRequest, DirEntryInternal, DirEntryPlusInternal, DirListingDelta,
+            FallocateFileRequest, FdOpenDirRequest, FileTimeInternal, FlockRequest,
+            FollowChunkResponse, FollowFileRequest, FsyncFileRequest, FutimensFileRequest,
+            GetXattrRequest, GlobRequest, LinkFileRequest, LockTypeInternal, MetadataInternal,
+            OpenAt2Request, OpenDirResponse, OpenFileRequest, OpenFileResponse,
+            OpenOptionsInternal, PrefetchChunkResponse, PrefetchDirInternal, PrefetchEntryInternal,
+            PrefetchTreeRequest, ReadChunkResponse, ReadDirBatchRequest, ReadDirBatchResponse,
+            ReadDirDeltaRequest, ReadDirDeltaResponse, ReadDirPlusBatchRequest,
+            ReadDirPlusBatchResponse, ReadDirRequest, ReadDirResponse, ReadFileRequest,
+            ReadFileResponse, ReadLimitedFileRequest, ReadStreamFileRequest, ReadVFileRequest,
+            ReadWholeFileRequest, ReadWholeFileResponse, RemoveXattrRequest,
+            RenameFileWithDirRequest, SeekFileRequest, SeekFromInternal, StatxRequest,
+            SymlinkAtRequest, TruncateFileRequest, UnfollowFileRequest, UnlinkFileRequest,
+            WriteFileRequest, WriteVFileRequest, XstatRequest, XstatResponse,
         },
-        ClientMessage, FileRequest, FileResponse,
+        interfaces::{GetIfAddrsRequest, GetIfAddrsResponse},
+        output::{OutputMessage, OutputStream},
+        ClientMessage, ErrorKindInternal, FileRequest, FileResponse, RemoteIOError, ResponseError,
     };
     use semver::Version;
 
@@ -568,4 +1368,1660 @@ mod tests {
             assert!(result.is_ok(), "{result:?}");
         }
     }
+
+    #[tokio::test]
+    async fn throttled_request_is_retried() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 13, 0)).await;
+
+        let request = FileRequest::Access(AccessFileRequest {
+            pathname: "/etc/hosts".into(),
+            mode: 0,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                request.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == request
+            ),
+            "Mismatched message for `AccessFileRequest` {update:?}!"
+        );
+
+        // Throttled requests are not passed to the layer, but sent again after the backoff.
+        let throttled = FileResponse::Access(Err(ResponseError::Throttled { retry_after_ms: 10 }));
+        proxy.send(SimpleProxyMessage::FileRes(throttled)).await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == request
+            ),
+            "Throttled `AccessFileRequest` was not retried {update:?}!"
+        );
+
+        let response = FileResponse::Access(Ok(AccessFileResponse));
+        proxy.send(SimpleProxyMessage::FileRes(response)).await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::File(FileResponse::Access(Ok(_)))
+                })))
+            ),
+            "Mismatched message for `AccessFileResponse` {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn output_is_sent_only_to_new_agents() {
+        let output = OutputMessage {
+            stream: OutputStream::Stdout,
+            bytes: b"hello\n".to_vec(),
+        };
+
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 16, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::OutputReq(output.clone()))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::Output(sent))))
+                    if *sent == output
+            ),
+            "Mismatched message for `OutputMessage` {update:?}!"
+        );
+        drop(proxy);
+        tasks.results().await;
+
+        // Older agents don't get the output, so the next message is the file request.
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 15, 0)).await;
+        proxy.send(SimpleProxyMessage::OutputReq(output)).await;
+        let request = FileRequest::Access(AccessFileRequest {
+            pathname: "/etc/hosts".into(),
+            mode: 0,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                request.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == request
+            ),
+            "`OutputMessage` was sent to an old agent {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn disabled_fs_writes_are_rejected() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 20, 0)).await;
+        proxy.send(SimpleProxyMessage::FsWrites(false)).await;
+
+        let write = FileRequest::Write(WriteFileRequest {
+            fd: 1,
+            write_bytes: b"hello".to_vec(),
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), write))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    message: ProxyToLayerMessage::File(FileResponse::Write(Err(
+                        ResponseError::RemoteIO(RemoteIOError {
+                            kind: ErrorKindInternal::ReadOnlyFilesystem,
+                            ..
+                        })
+                    ))),
+                    ..
+                })))
+            ),
+            "`WriteFileRequest` was not rejected {update:?}!"
+        );
+
+        // Read-only opens still reach the agent.
+        let open = FileRequest::Open(OpenFileRequest {
+            path: "/etc/hosts".into(),
+            open_options: OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                open.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == open
+            ),
+            "Read-only `OpenFileRequest` was rejected {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// Checks that `request` is sent to an agent with `new_version`, and that an agent with
+    /// `old_version` is not sent it, the layer gets `NotImplemented` instead.
+    async fn assert_sent_only_to_new_agents(
+        request: FileRequest,
+        new_version: Version,
+        old_version: Version,
+        is_not_implemented: fn(&FileResponse) -> bool,
+    ) {
+        let (proxy, mut tasks) = setup_proxy(new_version).await;
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                request.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == request
+            ),
+            "Mismatched message for `{request:?}` {update:?}!"
+        );
+        drop(proxy);
+        tasks.results().await;
+
+        let (proxy, mut tasks) = setup_proxy(old_version).await;
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                request.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::File(response)
+                }))) if is_not_implemented(response)
+            ),
+            "`{request:?}` was sent to an old agent {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn unlink_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Unlink(UnlinkFileRequest {
+                path: "/tmp/upload.tmp".into(),
+            }),
+            Version::new(1, 26, 0),
+            Version::new(1, 25, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Unlink(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn chmod_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Chmod(ChmodFileRequest {
+                path: "/tmp/run.sh".into(),
+                mode: 0o755,
+            }),
+            Version::new(1, 28, 0),
+            Version::new(1, 27, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Chmod(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn chown_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::ChownAt(ChownFileWithDirRequest {
+                dirfd: Some(3),
+                path: "data".into(),
+                owner: Some(1000),
+                group: Some(1000),
+                flags: 0,
+            }),
+            Version::new(1, 30, 0),
+            Version::new(1, 29, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Chown(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn openat2_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::OpenAt2(OpenAt2Request {
+                dirfd: Some(3),
+                path: "config/app.json".into(),
+                open_options: OpenOptionsInternal {
+                    read: true,
+                    ..Default::default()
+                },
+                resolve: 0x08,
+            }),
+            Version::new(1, 29, 0),
+            Version::new(1, 28, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Open(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn read_whole_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::ReadWhole(ReadWholeFileRequest {
+                path: "/etc/app/config.yaml".into(),
+                max_size: 64 * 1024,
+            }),
+            Version::new(1, 31, 0),
+            Version::new(1, 30, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::ReadWhole(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn truncate_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Truncate(TruncateFileRequest {
+                path: "/var/log/app.log".into(),
+                length: 0,
+            }),
+            Version::new(1, 32, 0),
+            Version::new(1, 31, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Truncate(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn glob_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Glob(GlobRequest {
+                pattern: "/etc/ssl/certs/*.pem".to_string(),
+                max_results: 1024,
+            }),
+            Version::new(1, 33, 0),
+            Version::new(1, 32, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Glob(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn symlink_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::SymlinkAt(SymlinkAtRequest {
+                target: "releases/1.2.0".into(),
+                new_dirfd: Some(3),
+                link_path: "current".into(),
+            }),
+            Version::new(1, 34, 0),
+            Version::new(1, 33, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Symlink(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn link_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Link(LinkFileRequest {
+                old_path: "/var/backups/app.db".into(),
+                new_path: "/var/backups/app.db.1".into(),
+            }),
+            Version::new(1, 35, 0),
+            Version::new(1, 34, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Link(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn utimens_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Futimens(FutimensFileRequest {
+                fd: 3,
+                access_time: FileTimeInternal::Now,
+                modification_time: FileTimeInternal::Now,
+            }),
+            Version::new(1, 36, 0),
+            Version::new(1, 35, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Utimens(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn fsync_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Fsync(FsyncFileRequest {
+                fd: 3,
+                data_only: false,
+            }),
+            Version::new(1, 37, 0),
+            Version::new(1, 36, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Fsync(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn statx_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Statx(StatxRequest {
+                dirfd: None,
+                path: Some("/app/data.db".into()),
+                follow_symlink: true,
+                // `STATX_BASIC_STATS | STATX_BTIME`.
+                mask: 0xfff,
+            }),
+            Version::new(1, 38, 0),
+            Version::new(1, 37, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Statx(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn xattr_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::GetXattr(GetXattrRequest {
+                path: Some("/app/bin/server".into()),
+                fd: None,
+                follow_symlink: true,
+                name: b"security.capability".to_vec(),
+            }),
+            Version::new(1, 40, 0),
+            Version::new(1, 39, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::GetXattr(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+
+        assert_sent_only_to_new_agents(
+            FileRequest::RemoveXattr(RemoveXattrRequest {
+                path: None,
+                fd: Some(4),
+                follow_symlink: true,
+                name: b"user.checksum".to_vec(),
+            }),
+            Version::new(1, 40, 0),
+            Version::new(1, 39, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::RemoveXattr(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn flock_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Flock(FlockRequest {
+                fd: 7,
+                lock_type: LockTypeInternal::Exclusive,
+            }),
+            Version::new(1, 41, 0),
+            Version::new(1, 40, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Lock(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn fallocate_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Fallocate(FallocateFileRequest {
+                fd: 7,
+                mode: 0,
+                offset: 0,
+                length: 1 << 20,
+                posix: true,
+            }),
+            Version::new(1, 43, 0),
+            Version::new(1, 42, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Fallocate(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn copy_file_range_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::CopyFileRange(CopyFileRangeRequest {
+                fd_in: 7,
+                offset_in: None,
+                fd_out: 8,
+                offset_out: None,
+                length: 1 << 20,
+            }),
+            Version::new(1, 44, 0),
+            Version::new(1, 43, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::CopyFileRange(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn vectored_io_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::ReadV(ReadVFileRequest {
+                fd: 7,
+                lengths: vec![16, 4096],
+                offset: None,
+            }),
+            Version::new(1, 46, 0),
+            Version::new(1, 45, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::ReadV(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+
+        assert_sent_only_to_new_agents(
+            FileRequest::WriteV(WriteVFileRequest {
+                fd: 7,
+                buffers: vec![b"header".to_vec(), b"body".to_vec()],
+                offset: Some(4096),
+            }),
+            Version::new(1, 46, 0),
+            Version::new(1, 45, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::WriteV(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn seek_hole_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::Seek(SeekFileRequest {
+                fd: 7,
+                seek_from: SeekFromInternal::Hole(0),
+            }),
+            Version::new(1, 49, 0),
+            Version::new(1, 48, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Seek(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    /// Older agents decode the paths as strings, so a path that is not valid UTF-8 is rejected
+    /// before it reaches them.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn non_utf8_path_is_sent_only_to_new_agents() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/app/caf\xe9.txt"));
+
+        assert_sent_only_to_new_agents(
+            FileRequest::Xstat(XstatRequest {
+                path: Some(path.clone()),
+                fd: None,
+                follow_symlink: true,
+            }),
+            Version::new(1, 60, 0),
+            Version::new(1, 59, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Xstat(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+
+        assert_sent_only_to_new_agents(
+            FileRequest::RenameAt(RenameFileWithDirRequest {
+                old_dirfd: Some(3),
+                old_path: "upload.tmp".into(),
+                new_dirfd: Some(3),
+                new_path: path,
+                flags: 0,
+            }),
+            Version::new(1, 60, 0),
+            Version::new(1, 59, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Rename(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    /// Older agents can't list their network interfaces, so the layer gets
+    /// [`ResponseError::NotImplemented`] right away.
+    #[tokio::test]
+    async fn get_ifaddrs_is_sent_only_to_new_agents() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 50, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::GetIfAddrsReq(
+                0xbad,
+                LayerId(0xa55),
+                GetIfAddrsRequest,
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::GetIfAddrsRequest(GetIfAddrsRequest)
+                )))
+            ),
+            "{update:?}"
+        );
+
+        proxy
+            .send(SimpleProxyMessage::GetIfAddrsRes(GetIfAddrsResponse(Ok(
+                vec![],
+            ))))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::GetIfAddrs(GetIfAddrsResponse(Ok(..)))
+                })))
+            ),
+            "{update:?}"
+        );
+        drop(proxy);
+        tasks.results().await;
+
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 49, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::GetIfAddrsReq(
+                0xbad,
+                LayerId(0xa55),
+                GetIfAddrsRequest,
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::GetIfAddrs(GetIfAddrsResponse(Err(
+                        ResponseError::NotImplemented
+                    )))
+                })))
+            ),
+            "{update:?}"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// Big reads are streamed by new agents and reassembled for the layer, and cut to a single
+    /// chunk for older agents.
+    #[tokio::test]
+    async fn big_reads_are_streamed() {
+        let request = FileRequest::ReadLimited(ReadLimitedFileRequest {
+            remote_fd: 7,
+            buffer_size: 3 * 1024 * 1024,
+            start_from: 4096,
+        });
+
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 47, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                0xbad,
+                LayerId(0xa55),
+                request.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::ReadStream(ReadStreamFileRequest {
+                        fd: 7,
+                        length: 3145728,
+                        offset: Some(4096),
+                        chunk_size: 1048576,
+                    }))
+                )))
+            ),
+            "{update:?}"
+        );
+
+        for (sequence, bytes, last) in [(0, &b"hello "[..], false), (1, &b"world"[..], true)] {
+            proxy
+                .send(SimpleProxyMessage::FileRes(FileResponse::ReadChunk(Ok(
+                    ReadChunkResponse {
+                        sequence,
+                        bytes: bytes.to_vec(),
+                        last,
+                    },
+                ))))
+                .await;
+        }
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 0xbad,
+                    layer_id: LayerId(0xa55),
+                    message: ProxyToLayerMessage::File(FileResponse::ReadLimited(Ok(
+                        ReadFileResponse {
+                            bytes,
+                            read_amount: 11,
+                        }
+                    ))),
+                }))) if bytes == b"hello world"
+            ),
+            "{update:?}"
+        );
+        drop(proxy);
+        tasks.results().await;
+
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 46, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), request))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::ReadLimited(ReadLimitedFileRequest {
+                        remote_fd: 7,
+                        buffer_size: 1048576,
+                        start_from: 4096,
+                    }))
+                )))
+            ),
+            "{update:?}"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rename_is_sent_only_to_new_agents() {
+        assert_sent_only_to_new_agents(
+            FileRequest::RenameAt(RenameFileWithDirRequest {
+                old_dirfd: Some(3),
+                old_path: "upload.tmp".into(),
+                new_dirfd: None,
+                new_path: "/tmp/upload.json".into(),
+                flags: 0,
+            }),
+            Version::new(1, 27, 0),
+            Version::new(1, 26, 0),
+            |response| {
+                matches!(
+                    response,
+                    FileResponse::Rename(Err(ResponseError::NotImplemented))
+                )
+            },
+        )
+        .await;
+    }
+
+    /// Stats of the same path are answered from the cache, until the local app modifies the path.
+    #[tokio::test]
+    async fn xstat_is_cached_until_modified() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 48, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::MetadataCache(Duration::from_secs(60)))
+            .await;
+
+        let xstat = FileRequest::Xstat(XstatRequest {
+            path: Some("/app/lib/module.py".into()),
+            fd: None,
+            follow_symlink: true,
+        });
+        let not_found = FileResponse::Xstat(Err(ResponseError::RemoteIO(RemoteIOError {
+            raw_os_error: Some(2),
+            kind: ErrorKindInternal::NotFound,
+        })));
+
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                1,
+                LayerId(0xa55),
+                xstat.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == xstat
+            ),
+            "{update:?}"
+        );
+        proxy
+            .send(SimpleProxyMessage::FileRes(not_found.clone()))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 1,
+                    message: ProxyToLayerMessage::File(response),
+                    ..
+                }))) if *response == not_found
+            ),
+            "{update:?}"
+        );
+
+        // The agent is not asked again.
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                2,
+                LayerId(0xa55),
+                xstat.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message_id: 2,
+                    message: ProxyToLayerMessage::File(response),
+                    ..
+                }))) if *response == not_found
+            ),
+            "Cached `XstatRequest` was sent to the agent {update:?}!"
+        );
+
+        // Creating the file removes it from the cache.
+        let create = FileRequest::Open(OpenFileRequest {
+            path: "/app/lib/module.py".into(),
+            open_options: OpenOptionsInternal {
+                write: true,
+                create: true,
+                ..Default::default()
+            },
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(3, LayerId(0xa55), create))
+            .await;
+        tasks.next().await;
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::Open(Ok(
+                OpenFileResponse { fd: 4 },
+            ))))
+            .await;
+        tasks.next().await;
+
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                4,
+                LayerId(0xa55),
+                xstat.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == xstat
+            ),
+            "`XstatRequest` was answered from the cache after a write {update:?}!"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// Opens the remote directory `/app/lib` like the layer does, with an
+    /// [`OpenFileRequest`] and then a [`FdOpenDirRequest`], as `dir_fd`.
+    async fn open_cached_dir(
+        proxy: &TaskSender<SimpleProxy>,
+        tasks: &mut BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
+        dir_fd: u64,
+    ) {
+        let open = FileRequest::Open(OpenFileRequest {
+            path: "/app/lib".into(),
+            open_options: OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(1, LayerId(0xa55), open))
+            .await;
+        tasks.next().await;
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::Open(Ok(
+                OpenFileResponse { fd: dir_fd - 1 },
+            ))))
+            .await;
+        tasks.next().await;
+
+        let open_dir = FileRequest::FdOpenDir(FdOpenDirRequest {
+            remote_fd: dir_fd - 1,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(2, LayerId(0xa55), open_dir))
+            .await;
+        tasks.next().await;
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::OpenDir(Ok(
+                OpenDirResponse { fd: dir_fd },
+            ))))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message: ProxyToLayerMessage::File(FileResponse::OpenDir(Ok(..))),
+                    ..
+                })))
+            ),
+            "{update:?}"
+        );
+    }
+
+    /// Once a directory was listed to the end, the next listings come from the cache.
+    #[tokio::test]
+    async fn dir_listing_is_cached() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 48, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::MetadataCache(Duration::from_secs(60)))
+            .await;
+
+        let entry = DirEntryInternal {
+            inode: 1,
+            position: 1,
+            name: b"module.py".to_vec(),
+            file_type: 8,
+        };
+
+        open_cached_dir(&proxy, &mut tasks, 4).await;
+        for dir_entries in [vec![entry.clone()], Vec::new()] {
+            proxy
+                .send(SimpleProxyMessage::FileReq(
+                    3,
+                    LayerId(0xa55),
+                    FileRequest::ReadDir(ReadDirRequest { remote_fd: 4 }),
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                        ClientMessage::FileRequest(FileRequest::ReadDirBatch(..))
+                    )))
+                ),
+                "{update:?}"
+            );
+            proxy
+                .send(SimpleProxyMessage::FileRes(FileResponse::ReadDirBatch(Ok(
+                    ReadDirBatchResponse { fd: 4, dir_entries },
+                ))))
+                .await;
+            tasks.next().await;
+        }
+
+        // The entries and the end of the directory don't come from the agent.
+        open_cached_dir(&proxy, &mut tasks, 6).await;
+        for expected in [Some(entry), None] {
+            proxy
+                .send(SimpleProxyMessage::FileReq(
+                    3,
+                    LayerId(0xa55),
+                    FileRequest::ReadDir(ReadDirRequest { remote_fd: 6 }),
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    &update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                        message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                            ReadDirResponse { direntry }
+                        ))),
+                        ..
+                    }))) if *direntry == expected
+                ),
+                "Cached listing was not used {update:?}!"
+            );
+        }
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// With the metadata cache, directories are listed with the metadata of their entries, so
+    /// stating the entries doesn't ask the agent.
+    #[tokio::test]
+    async fn dir_listing_gets_metadata() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 57, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::MetadataCache(Duration::from_secs(60)))
+            .await;
+
+        let entry = DirEntryPlusInternal {
+            entry: DirEntryInternal {
+                inode: 1,
+                position: 1,
+                name: b"module.py".to_vec(),
+                file_type: 8,
+            },
+            metadata: MetadataInternal {
+                inode: 1,
+                mode: 0o100644,
+                size: 512,
+                ..Default::default()
+            },
+        };
+
+        open_cached_dir(&proxy, &mut tasks, 4).await;
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                3,
+                LayerId(0xa55),
+                FileRequest::ReadDir(ReadDirRequest { remote_fd: 4 }),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::ReadDirPlusBatch(
+                        ReadDirPlusBatchRequest { remote_fd: 4, .. }
+                    ))
+                )))
+            ),
+            "{update:?}"
+        );
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::ReadDirPlusBatch(
+                Ok(ReadDirPlusBatchResponse {
+                    fd: 4,
+                    dir_entries: vec![entry.clone()],
+                }),
+            )))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                        ReadDirResponse { direntry: Some(direntry) }
+                    ))),
+                    ..
+                }))) if *direntry == entry.entry
+            ),
+            "{update:?}"
+        );
+
+        for follow_symlink in [false, true] {
+            let xstat = FileRequest::Xstat(XstatRequest {
+                path: Some("/app/lib/module.py".into()),
+                fd: None,
+                follow_symlink,
+            });
+            proxy
+                .send(SimpleProxyMessage::FileReq(4, LayerId(0xa55), xstat))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    &update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                        message_id: 4,
+                        message: ProxyToLayerMessage::File(FileResponse::Xstat(Ok(
+                            XstatResponse { metadata }
+                        ))),
+                        ..
+                    }))) if *metadata == entry.metadata
+                ),
+                "`XstatRequest` of a listed entry was sent to the agent {update:?}!"
+            );
+        }
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// A prefetched tree answers the listings, the `stat`s and the whole reads of its files,
+    /// without the agent.
+    #[tokio::test]
+    async fn prefetched_tree_is_cached() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 58, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::MetadataCache(Duration::from_secs(60)))
+            .await;
+        proxy
+            .send(SimpleProxyMessage::Prefetch(vec!["/app/lib".into()]))
+            .await;
+
+        let (_, update) = tasks.next().await.unzip();
+        let Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(
+            FileRequest::PrefetchTree(PrefetchTreeRequest { id, path, .. }),
+        )))) = update
+        else {
+            panic!("Prefetch was not sent to the agent {update:?}!");
+        };
+        assert_eq!(path, std::path::Path::new("/app/lib"));
+
+        let small = PrefetchEntryInternal {
+            entry: DirEntryPlusInternal {
+                entry: DirEntryInternal {
+                    inode: 1,
+                    position: 0,
+                    name: b"module.py".to_vec(),
+                    file_type: 8,
+                },
+                metadata: MetadataInternal {
+                    inode: 1,
+                    mode: 0o100644,
+                    size: 11,
+                    ..Default::default()
+                },
+            },
+            contents: Some(b"import os\n\n".to_vec()),
+        };
+        let big = PrefetchEntryInternal {
+            entry: DirEntryPlusInternal {
+                entry: DirEntryInternal {
+                    inode: 2,
+                    position: 1,
+                    name: b"model.bin".to_vec(),
+                    file_type: 8,
+                },
+                metadata: MetadataInternal {
+                    inode: 2,
+                    mode: 0o100644,
+                    size: 1024 * 1024,
+                    ..Default::default()
+                },
+            },
+            contents: None,
+        };
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::PrefetchChunk(
+                PrefetchChunkResponse {
+                    id,
+                    dirs: vec![PrefetchDirInternal {
+                        path: "/app/lib".into(),
+                        metadata: MetadataInternal {
+                            mode: 0o40755,
+                            ..Default::default()
+                        },
+                        entries: vec![small.clone(), big.clone()],
+                    }],
+                    last: true,
+                },
+            )))
+            .await;
+
+        open_cached_dir(&proxy, &mut tasks, 4).await;
+        for entry in [&small, &big] {
+            proxy
+                .send(SimpleProxyMessage::FileReq(
+                    3,
+                    LayerId(0xa55),
+                    FileRequest::ReadDir(ReadDirRequest { remote_fd: 4 }),
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    &update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                        message: ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(
+                            ReadDirResponse { direntry: Some(direntry) }
+                        ))),
+                        ..
+                    }))) if *direntry == entry.entry.entry
+                ),
+                "Prefetched listing was not used {update:?}!"
+            );
+        }
+
+        let requests = [
+            (
+                FileRequest::Xstat(XstatRequest {
+                    path: Some("/app/lib/module.py".into()),
+                    fd: None,
+                    follow_symlink: true,
+                }),
+                FileResponse::Xstat(Ok(XstatResponse {
+                    metadata: small.entry.metadata,
+                })),
+            ),
+            (
+                FileRequest::ReadWhole(ReadWholeFileRequest {
+                    path: "/app/lib/module.py".into(),
+                    max_size: 64 * 1024,
+                }),
+                FileResponse::ReadWhole(Ok(ReadWholeFileResponse {
+                    metadata: small.entry.metadata,
+                    bytes: small.contents.clone(),
+                })),
+            ),
+            // Too big to read whole, the layer opens it as usual.
+            (
+                FileRequest::ReadWhole(ReadWholeFileRequest {
+                    path: "/app/lib/model.bin".into(),
+                    max_size: 64 * 1024,
+                }),
+                FileResponse::ReadWhole(Ok(ReadWholeFileResponse {
+                    metadata: big.entry.metadata,
+                    bytes: None,
+                })),
+            ),
+        ];
+        for (request, response) in requests {
+            proxy
+                .send(SimpleProxyMessage::FileReq(
+                    4,
+                    LayerId(0xa55),
+                    request.clone(),
+                ))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    &update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                        message_id: 4,
+                        message: ProxyToLayerMessage::File(cached),
+                        ..
+                    }))) if *cached == response
+                ),
+                "`{request:?}` of a prefetched file was sent to the agent {update:?}!"
+            );
+        }
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// Older agents don't get the [`FileRequest::PrefetchTree`], the paths are read on demand.
+    #[tokio::test]
+    async fn prefetch_is_sent_only_to_new_agents() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 57, 0)).await;
+        proxy
+            .send(SimpleProxyMessage::Prefetch(vec!["/app/lib".into()]))
+            .await;
+        proxy
+            .send(SimpleProxyMessage::MetadataCache(Duration::from_secs(60)))
+            .await;
+
+        let xstat = FileRequest::Xstat(XstatRequest {
+            path: Some("/app/lib/module.py".into()),
+            fd: None,
+            follow_symlink: true,
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(
+                1,
+                LayerId(0xa55),
+                xstat.clone(),
+            ))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::FileRequest(sent))))
+                    if *sent == xstat
+            ),
+            "{update:?}"
+        );
+
+        drop(proxy);
+        tasks.results().await;
+    }
+
+    /// Only the idle descriptors that no layer holds are closed.
+    #[tokio::test]
+    async fn leaked_idle_fds_are_closed() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 59, 0)).await;
+        prepare_dir(&proxy, &mut tasks).await;
+
+        proxy
+            .send(SimpleProxyMessage::IdleFds(IdleFds {
+                files: vec![7],
+                dirs: vec![0xdad],
+                idle_timeout_secs: 600,
+            }))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(ClientMessage::CloseFds(
+                    CloseFdsRequest { files, dirs }
+                )))) if *files == [7] && dirs.is_empty()
+            ),
+            "{update:?}"
+        );
+
+        drop(proxy);
+        tasks.results().await;
+    }
+
+    /// Reads the whole directory `dir_fd` with a single [`FileRequest::ReadDirDelta`], expecting
+    /// it to be sent with `since`, and returns the names that the layer got.
+    async fn read_dir_delta(
+        proxy: &TaskSender<SimpleProxy>,
+        tasks: &mut BackgroundTasks<MainTaskId, ProxyMessage, IntProxyError>,
+        dir_fd: u64,
+        since: Option<u64>,
+        response: ReadDirDeltaResponse,
+    ) -> Vec<Vec<u8>> {
+        let mut names = Vec::new();
+
+        loop {
+            proxy
+                .send(SimpleProxyMessage::FileReq(
+                    3,
+                    LayerId(0xa55),
+                    FileRequest::ReadDir(ReadDirRequest { remote_fd: dir_fd }),
+                ))
+                .await;
+
+            if names.is_empty() {
+                let (_, update) = tasks.next().await.unzip();
+                assert!(
+                    matches!(
+                        &update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::FileRequest(FileRequest::ReadDirDelta(
+                                ReadDirDeltaRequest { remote_fd, since: sent }
+                            ))
+                        ))) if *remote_fd == dir_fd && *sent == since
+                    ),
+                    "{update:?}"
+                );
+                proxy
+                    .send(SimpleProxyMessage::FileRes(FileResponse::ReadDirDelta(Ok(
+                        response.clone(),
+                    ))))
+                    .await;
+            }
+
+            let (_, update) = tasks.next().await.unzip();
+            let Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                message:
+                    ProxyToLayerMessage::File(FileResponse::ReadDir(Ok(ReadDirResponse { direntry }))),
+                ..
+            }))) = update
+            else {
+                panic!("Unexpected update {update:?}");
+            };
+
+            match direntry {
+                Some(entry) => names.push(entry.name),
+                None => break names,
+            }
+        }
+    }
+
+    /// Listing a directory again only gets the entries that changed from the agent.
+    #[tokio::test]
+    async fn dir_listing_gets_changes() {
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 51, 0)).await;
+
+        let entry = |inode: u64, name: &str| DirEntryInternal {
+            inode,
+            position: 0,
+            name: name.as_bytes().to_vec(),
+            file_type: 8,
+        };
+
+        open_cached_dir(&proxy, &mut tasks, 4).await;
+        let names = read_dir_delta(
+            &proxy,
+            &mut tasks,
+            4,
+            None,
+            ReadDirDeltaResponse {
+                fd: 4,
+                cookie: 0xc00c1e,
+                delta: DirListingDelta::Full(vec![entry(1, "a.py"), entry(2, "b.py")]),
+            },
+        )
+        .await;
+        assert_eq!(names, [b"a.py".to_vec(), b"b.py".to_vec()]);
+
+        open_cached_dir(&proxy, &mut tasks, 6).await;
+        let names = read_dir_delta(
+            &proxy,
+            &mut tasks,
+            6,
+            Some(0xc00c1e),
+            ReadDirDeltaResponse {
+                fd: 6,
+                cookie: 0xc00c1f,
+                delta: DirListingDelta::Changes {
+                    added: vec![entry(3, "c.py")],
+                    removed: vec![b"a.py".to_vec()],
+                },
+            },
+        )
+        .await;
+        assert_eq!(names, [b"b.py".to_vec(), b"c.py".to_vec()]);
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    /// Reading a file again after its end follows it, and the reads are served from the bytes
+    /// that the agent sends, until the offset is used.
+    #[tokio::test]
+    async fn reads_after_the_end_are_followed() {
+        let read = || {
+            FileRequest::Read(ReadFileRequest {
+                remote_fd: 7,
+                buffer_size: 4,
+            })
+        };
+        let (proxy, mut tasks) = setup_proxy(Version::new(1, 56, 0)).await;
+
+        for follows in [false, true] {
+            proxy
+                .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), read()))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(
+                    update,
+                    Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                        ClientMessage::FileRequest(FileRequest::Read(..))
+                    )))
+                ),
+                "{update:?}"
+            );
+
+            if follows {
+                let (_, update) = tasks.next().await.unzip();
+                assert!(
+                    matches!(
+                        update,
+                        Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                            ClientMessage::FileRequest(FileRequest::Follow(FollowFileRequest {
+                                id: 1,
+                                fd: 7,
+                            }))
+                        )))
+                    ),
+                    "{update:?}"
+                );
+            }
+
+            proxy
+                .send(SimpleProxyMessage::FileRes(FileResponse::Read(Ok(
+                    ReadFileResponse {
+                        bytes: vec![],
+                        read_amount: 0,
+                    },
+                ))))
+                .await;
+            let (_, update) = tasks.next().await.unzip();
+            assert!(
+                matches!(update, Some(TaskUpdate::Message(ProxyMessage::ToLayer(..)))),
+                "{update:?}"
+            );
+        }
+
+        proxy
+            .send(SimpleProxyMessage::FileRes(FileResponse::FollowChunk(
+                FollowChunkResponse {
+                    id: 1,
+                    bytes: b"GET /health 200\n".to_vec(),
+                    last: false,
+                },
+            )))
+            .await;
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), read()))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                &update,
+                Some(TaskUpdate::Message(ProxyMessage::ToLayer(ToLayer {
+                    message: ProxyToLayerMessage::File(FileResponse::Read(Ok(
+                        ReadFileResponse {
+                            bytes,
+                            read_amount: 4,
+                        }
+                    ))),
+                    ..
+                }))) if bytes == b"GET "
+            ),
+            "{update:?}"
+        );
+
+        let seek = FileRequest::Seek(SeekFileRequest {
+            fd: 7,
+            seek_from: SeekFromInternal::Current(0),
+        });
+        proxy
+            .send(SimpleProxyMessage::FileReq(0xbad, LayerId(0xa55), seek))
+            .await;
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::Unfollow(UnfollowFileRequest {
+                        id: 1,
+                        consumed: 4,
+                    }))
+                )))
+            ),
+            "{update:?}"
+        );
+        let (_, update) = tasks.next().await.unzip();
+        assert!(
+            matches!(
+                update,
+                Some(TaskUpdate::Message(ProxyMessage::ToAgent(
+                    ClientMessage::FileRequest(FileRequest::Seek(..))
+                )))
+            ),
+            "{update:?}"
+        );
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
 }
@@ -0,0 +1,59 @@
+//! The last listings of remote directories, that the agent sends the changes to on the next
+//! listing, see [`DirSnapshots`].
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use mirrord_protocol::file::DirEntryInternal;
+
+/// A complete listing of a remote directory, known to the agent by its `cookie`.
+#[derive(Clone, Debug)]
+pub(crate) struct DirSnapshot {
+    pub(super) cookie: u64,
+    pub(super) entries: Vec<DirEntryInternal>,
+}
+
+/// The last listings of remote directories, by their path.
+///
+/// Listing the same directory again only transfers the entries that changed since the snapshot,
+/// see [`ReadDirDeltaRequest`](mirrord_protocol::file::ReadDirDeltaRequest). Snapshots are taken
+/// out when a listing starts, and stored again when the agent responds, so that concurrent
+/// listings of the same directory don't send the same cookie.
+#[derive(Debug, Default)]
+pub(super) struct DirSnapshots {
+    /// Snapshots with the [`Self::inserted`] count when they were stored.
+    snapshots: HashMap<PathBuf, (u64, DirSnapshot)>,
+    /// Number of snapshots stored so far, to evict the oldest one.
+    inserted: u64,
+}
+
+impl DirSnapshots {
+    /// Most snapshots that we keep, the same as the agent.
+    const MAX_SNAPSHOTS: usize = 32;
+
+    /// Removes and returns the snapshot of the directory at `path`.
+    pub(super) fn take(&mut self, path: &Path) -> Option<DirSnapshot> {
+        self.snapshots.remove(path).map(|(_, snapshot)| snapshot)
+    }
+
+    /// Stores the latest listing of the directory at `path`, evicting the oldest snapshot if we
+    /// have too many.
+    pub(super) fn insert(&mut self, path: PathBuf, snapshot: DirSnapshot) {
+        if !self.snapshots.contains_key(&path) && self.snapshots.len() >= Self::MAX_SNAPSHOTS {
+            let oldest = self
+                .snapshots
+                .iter()
+                .min_by_key(|(_, (inserted, _))| *inserted)
+                .map(|(path, _)| path.clone());
+
+            if let Some(oldest) = oldest {
+                self.snapshots.remove(&oldest);
+            }
+        }
+
+        self.inserted += 1;
+        self.snapshots.insert(path, (self.inserted, snapshot));
+    }
+}
@@ -0,0 +1,236 @@
+//! Remote files that the layers read like `tail -f`, see [`FileFollows`].
+
+use std::collections::{HashMap, HashSet};
+
+use mirrord_protocol::{
+    file::{
+        CloseFileRequest, CopyFileRangeRequest, FollowChunkResponse, FollowFileRequest,
+        FtruncateFileRequest, ReadFileResponse, ReadVFileRequest, SeekFileRequest,
+        UnfollowFileRequest, WriteFileRequest, WriteVFileRequest,
+    },
+    FileRequest,
+};
+
+/// Returns the remote file whose offset `request` uses or changes, which can't be followed while
+/// the request is handled.
+pub(super) fn offset_fd(request: &FileRequest) -> Option<u64> {
+    match request {
+        FileRequest::Seek(SeekFileRequest { fd, .. })
+        | FileRequest::Write(WriteFileRequest { fd, .. })
+        | FileRequest::WriteV(WriteVFileRequest {
+            fd, offset: None, ..
+        })
+        | FileRequest::ReadV(ReadVFileRequest {
+            fd, offset: None, ..
+        })
+        | FileRequest::CopyFileRange(CopyFileRangeRequest {
+            fd_in: fd,
+            offset_in: None,
+            ..
+        })
+        | FileRequest::Ftruncate(FtruncateFileRequest { fd, .. })
+        | FileRequest::Close(CloseFileRequest { fd }) => Some(*fd),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct Follow {
+    id: u64,
+    /// Bytes sent by the agent, that the layers did not read yet.
+    buffered: Vec<u8>,
+    /// Bytes that the layers read, see [`UnfollowFileRequest::consumed`].
+    consumed: u64,
+}
+
+/// The remote files that the layers follow, by their remote descriptors.
+///
+/// A layer that reads a file again after reaching its end is following it (e.g. polling a log),
+/// so from then on, the agent sends the bytes appended to the file (see [`FollowFileRequest`]),
+/// and the reads of the layers are served from them, without asking the agent.
+///
+/// Requests that use the file offset (see [`offset_fd`]) stop the follow, and the agent puts the
+/// file offset right after the bytes that the layers read.
+#[derive(Debug, Default)]
+pub(super) struct FileFollows {
+    /// Files whose last read from the agent was at the end of the file.
+    at_end: HashSet<u64>,
+    follows: HashMap<u64, Follow>,
+    last_id: u64,
+}
+
+impl FileFollows {
+    /// Most bytes that we keep for a follow, the follow is stopped when the layers don't read
+    /// them fast enough.
+    const MAX_BUFFERED: usize = 1024 * 1024;
+
+    /// Called with every response of the agent to a read of `fd` at its file offset.
+    pub(super) fn agent_read(&mut self, fd: u64, read_amount: u64) {
+        if read_amount == 0 {
+            self.at_end.insert(fd);
+        } else {
+            self.at_end.remove(&fd);
+        }
+    }
+
+    /// Called when a read of `fd` is sent to the agent, returns the request to follow `fd` when
+    /// the previous read reached the end of the file.
+    ///
+    /// The follow should be sent after the read, so that it starts where the read ends.
+    pub(super) fn start(&mut self, fd: u64) -> Option<FollowFileRequest> {
+        if !self.at_end.remove(&fd) || self.follows.contains_key(&fd) {
+            return None;
+        }
+
+        self.last_id += 1;
+        self.follows.insert(
+            fd,
+            Follow {
+                id: self.last_id,
+                buffered: Vec::new(),
+                consumed: 0,
+            },
+        );
+
+        Some(FollowFileRequest {
+            id: self.last_id,
+            fd,
+        })
+    }
+
+    /// Whether the reads of `fd` are served by [`Self::read`].
+    pub(super) fn is_following(&self, fd: u64) -> bool {
+        self.follows.contains_key(&fd)
+    }
+
+    /// Reads up to `buffer_size` bytes of the followed `fd`.
+    ///
+    /// No bytes means the end of the file, like the agent does, and is all there is for the files
+    /// that are not followed.
+    pub(super) fn read(&mut self, fd: u64, buffer_size: u64) -> ReadFileResponse {
+        let Some(follow) = self.follows.get_mut(&fd) else {
+            return ReadFileResponse {
+                bytes: Vec::new(),
+                read_amount: 0,
+            };
+        };
+
+        let amount = follow
+            .buffered
+            .len()
+            .min(usize::try_from(buffer_size).unwrap_or(usize::MAX));
+        let bytes = follow.buffered.drain(..amount).collect::<Vec<_>>();
+        follow.consumed += amount as u64;
+
+        ReadFileResponse {
+            read_amount: amount as u64,
+            bytes,
+        }
+    }
+
+    /// Stops following `fd`, returns the request to stop the follow in the agent.
+    pub(super) fn stop(&mut self, fd: u64) -> Option<UnfollowFileRequest> {
+        self.at_end.remove(&fd);
+        let Follow { id, consumed, .. } = self.follows.remove(&fd)?;
+
+        Some(UnfollowFileRequest { id, consumed })
+    }
+
+    /// Keeps the bytes of `chunk` for the layers, returns the request to stop the follow when
+    /// the layers don't keep up with it.
+    ///
+    /// Chunks of stopped follows are dropped.
+    pub(super) fn chunk(&mut self, chunk: FollowChunkResponse) -> Option<UnfollowFileRequest> {
+        let FollowChunkResponse { id, bytes, last } = chunk;
+        let (&fd, follow) = self
+            .follows
+            .iter_mut()
+            .find(|(_, follow)| follow.id == id)?;
+
+        if last {
+            tracing::debug!(fd, ?follow, "Agent stopped following a remote file");
+            self.follows.remove(&fd);
+            return None;
+        }
+
+        follow.buffered.extend_from_slice(&bytes);
+        if follow.buffered.len() > Self::MAX_BUFFERED {
+            tracing::debug!(
+                fd,
+                "Stopped following a remote file that is not read fast enough"
+            );
+            return self.stop(fd);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: u64, bytes: &[u8]) -> FollowChunkResponse {
+        FollowChunkResponse {
+            id,
+            bytes: bytes.to_vec(),
+            last: false,
+        }
+    }
+
+    #[test]
+    fn follows_after_reading_at_the_end() {
+        let mut follows = FileFollows::default();
+
+        follows.agent_read(3, 512);
+        assert_eq!(follows.start(3), None);
+        follows.agent_read(3, 0);
+        assert_eq!(follows.start(3), Some(FollowFileRequest { id: 1, fd: 3 }));
+        assert_eq!(follows.start(3), None);
+
+        assert_eq!(follows.chunk(chunk(1, b"GET /health")), None);
+        assert_eq!(follows.chunk(chunk(1, b" 200\n")), None);
+        // A previous follow of the same file.
+        assert_eq!(follows.chunk(chunk(0, b"stale")), None);
+
+        let read = follows.read(3, 4);
+        assert_eq!(read.bytes, b"GET ");
+        assert_eq!(read.read_amount, 4);
+        assert_eq!(follows.read(3, 64).bytes, b"/health 200\n");
+        assert_eq!(follows.read(3, 64).read_amount, 0);
+        assert!(!follows.is_following(4));
+
+        assert_eq!(
+            follows.stop(3),
+            Some(UnfollowFileRequest {
+                id: 1,
+                consumed: 16
+            })
+        );
+        assert!(!follows.is_following(3));
+    }
+
+    #[test]
+    fn stops_when_not_read() {
+        let mut follows = FileFollows::default();
+        follows.agent_read(3, 0);
+        follows.start(3).unwrap();
+
+        let bytes = vec![0; FileFollows::MAX_BUFFERED];
+        assert_eq!(follows.chunk(chunk(1, &bytes)), None);
+        assert_eq!(
+            follows.chunk(chunk(1, b"\n")),
+            Some(UnfollowFileRequest { id: 1, consumed: 0 })
+        );
+
+        follows.agent_read(3, 0);
+        follows.start(3).unwrap();
+        let last = FollowChunkResponse {
+            id: 2,
+            bytes: Vec::new(),
+            last: true,
+        };
+        assert_eq!(follows.chunk(last), None);
+        assert!(!follows.is_following(3));
+    }
+}
@@ -0,0 +1,444 @@
+//! Caching of the remote metadata that the local app asks for over and over, see
+//! [`MetadataCache`].
+
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use mirrord_intproxy_protocol::{LayerId, MessageId};
+use mirrord_protocol::{
+    file::{
+        DirEntryInternal, DirEntryPlusInternal, PrefetchChunkResponse, PrefetchEntryInternal,
+        PrefetchTreeRequest, ReadLinkFileRequest, ReadWholeFileRequest, ReadWholeFileResponse,
+        XstatRequest, XstatResponse,
+    },
+    FileRequest, FileResponse, ResponseError,
+};
+use tokio::time::Instant;
+
+/// Regular files of at most this many bytes are prefetched with their contents, see
+/// [`MetadataCache::prefetch`].
+const PREFETCH_MAX_FILE_SIZE: u64 = 64 * 1024;
+
+/// Most entries prefetched from a single tree, see [`MetadataCache::prefetch`].
+const PREFETCH_MAX_ENTRIES: u64 = 10_000;
+
+/// The raw `name` of a directory entry as a path component. The names that are not valid UTF-8
+/// are kept, like the paths of the layer with them, see
+/// [`NON_UTF8_PATHS_VERSION`](mirrord_protocol::file::NON_UTF8_PATHS_VERSION).
+#[cfg(unix)]
+fn entry_name(name: &[u8]) -> Option<&OsStr> {
+    Some(OsStr::from_bytes(name))
+}
+
+/// The raw `name` of a directory entry as a path component, [`None`] when it's not valid UTF-8.
+#[cfg(not(unix))]
+fn entry_name(name: &[u8]) -> Option<&OsStr> {
+    std::str::from_utf8(name).ok().map(OsStr::new)
+}
+
+/// `S_IFMT`, the same on Linux and macOS.
+const FILE_TYPE_MASK: u32 = 0o170000;
+
+/// `S_IFREG`, the same on Linux and macOS.
+const REGULAR_FILE: u32 = 0o100000;
+
+/// A [`FileRequest`] that can be answered from the [`MetadataCache`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum CacheKey {
+    /// [`FileRequest::Xstat`] of an absolute path.
+    Xstat { path: PathBuf, follow_symlink: bool },
+    /// [`FileRequest::ReadLink`] of an absolute path.
+    ReadLink(PathBuf),
+}
+
+impl CacheKey {
+    fn of(request: &FileRequest) -> Option<Self> {
+        match request {
+            FileRequest::Xstat(XstatRequest {
+                path: Some(path),
+                fd: None,
+                follow_symlink,
+            }) if path.is_absolute() => Some(Self::Xstat {
+                path: path.clone(),
+                follow_symlink: *follow_symlink,
+            }),
+            FileRequest::ReadLink(ReadLinkFileRequest { path }) if path.is_absolute() => {
+                Some(Self::ReadLink(path.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::Xstat { path, .. } | Self::ReadLink(path) => path,
+        }
+    }
+}
+
+/// The entries of a remote directory read so far, stored in the [`MetadataCache`] with
+/// [`MetadataCache::finish_listing`] once the agent reaches the end of the directory.
+#[derive(Clone, Debug)]
+pub(crate) struct DirListing {
+    /// [`MetadataCache::generation`] when the listing started.
+    generation: u64,
+    pub(super) entries: Vec<DirEntryInternal>,
+}
+
+/// Keeps the remote results of `stat`, `readlink` and directory listings for a while, so that the
+/// local app gets them without a round trip to the agent.
+///
+/// Whole trees can also be cached ahead of time with [`MetadataCache::prefetch`], along with the
+/// contents of their small files, which answer the [`FileRequest::ReadWhole`]s.
+///
+/// Language runtimes stat the same files (and the ones that don't exist) hundreds of times at
+/// startup, e.g. when they resolve imports. The results are kept for the configured TTL, and
+/// removed right away when the local app modifies the remote filesystem, see
+/// [`MetadataCache::invalidate`]. Changes made by the remote target are only seen when the
+/// cached results expire.
+#[derive(Debug)]
+pub(super) struct MetadataCache {
+    ttl: Duration,
+    /// Cached responses, with the time they arrived from the agent.
+    responses: HashMap<CacheKey, (Instant, FileResponse)>,
+    /// Complete listings of remote directories, by their path.
+    listings: HashMap<PathBuf, (Instant, Vec<DirEntryInternal>)>,
+    /// Contents of the small remote files of the prefetched trees, by their path.
+    contents: HashMap<PathBuf, (Instant, Vec<u8>)>,
+    /// Prefetches that the agent is still sending, by their ids, with the paths invalidated since
+    /// they were requested.
+    prefetches: HashMap<u64, Vec<PathBuf>>,
+    /// Id of the next [`PrefetchTreeRequest`].
+    next_prefetch_id: u64,
+    /// Requests sent to the agent whose responses can be cached, with the [`Self::generation`]
+    /// when they were sent.
+    pending: HashMap<(LayerId, MessageId), (CacheKey, u64)>,
+    /// Incremented on every invalidation, so that we don't cache the responses to requests sent
+    /// before it, which may no longer be true.
+    generation: u64,
+    /// When the expired entries are purged next.
+    next_purge: Instant,
+}
+
+impl MetadataCache {
+    pub(super) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            responses: Default::default(),
+            listings: Default::default(),
+            contents: Default::default(),
+            prefetches: Default::default(),
+            next_prefetch_id: 0,
+            pending: Default::default(),
+            generation: 0,
+            next_purge: Instant::now() + ttl,
+        }
+    }
+
+    /// Returns the cached response to the `request` of the layer.
+    ///
+    /// Otherwise, the response that the agent sends for it is cached in [`Self::response`], if
+    /// this kind of request can be cached.
+    pub(super) fn request(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: &FileRequest,
+    ) -> Option<FileResponse> {
+        if let FileRequest::ReadWhole(request) = request {
+            return self.read_whole(request);
+        }
+
+        let key = CacheKey::of(request)?;
+
+        match self.responses.get(&key) {
+            Some((cached_at, response)) if cached_at.elapsed() < self.ttl => {
+                return Some(response.clone());
+            }
+            Some(..) => {
+                self.responses.remove(&key);
+            }
+            None => {}
+        }
+
+        self.pending
+            .insert((layer_id, message_id), (key, self.generation));
+        None
+    }
+
+    /// Caches the `response` of the agent to the request that the layer made with
+    /// [`Self::request`].
+    ///
+    /// Only successes and errors of the remote filesystem (e.g. `ENOENT`) are cached.
+    pub(super) fn response(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        response: &FileResponse,
+    ) {
+        let Some((key, generation)) = self.pending.remove(&(layer_id, message_id)) else {
+            return;
+        };
+
+        let cacheable = matches!(
+            response,
+            FileResponse::Xstat(Ok(..) | Err(ResponseError::RemoteIO(..)))
+                | FileResponse::ReadLink(Ok(..) | Err(ResponseError::RemoteIO(..)))
+        );
+        if !cacheable || generation != self.generation {
+            return;
+        }
+
+        self.purge_expired();
+        self.responses
+            .insert(key, (Instant::now(), response.clone()));
+    }
+
+    /// Returns the cached listing of the remote directory at `path`.
+    pub(super) fn listing(&mut self, path: &Path) -> Option<Vec<DirEntryInternal>> {
+        match self.listings.get(path) {
+            Some((cached_at, entries)) if cached_at.elapsed() < self.ttl => Some(entries.clone()),
+            Some(..) => {
+                self.listings.remove(path);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Starts collecting the entries of a remote directory, that the agent is about to list.
+    pub(super) fn start_listing(&self) -> DirListing {
+        DirListing {
+            generation: self.generation,
+            entries: Default::default(),
+        }
+    }
+
+    /// Caches the complete `listing` of the remote directory at `path`.
+    pub(super) fn finish_listing(&mut self, path: PathBuf, listing: DirListing) {
+        if listing.generation != self.generation {
+            return;
+        }
+
+        self.purge_expired();
+        self.listings
+            .insert(path, (Instant::now(), listing.entries));
+    }
+
+    /// Caches the metadata of the `entries` of the remote directory at `dir`, that the agent sent
+    /// with the entries of the `listing`, as the `lstat` of the entries, and the `stat` of the
+    /// ones that are not symbolic links.
+    pub(super) fn entries_metadata(
+        &mut self,
+        dir: &Path,
+        listing: &DirListing,
+        entries: &[DirEntryPlusInternal],
+    ) {
+        if listing.generation != self.generation {
+            return;
+        }
+
+        self.purge_expired();
+        let now = Instant::now();
+        for entry in entries {
+            self.entry_metadata(dir, entry, now);
+        }
+    }
+
+    /// Caches the metadata of an `entry` of the remote directory at `dir`, see
+    /// [`Self::entries_metadata`].
+    ///
+    /// Returns the path of the entry, [`None`] for `.`, `..`, and the names that are not valid
+    /// UTF-8 where paths must be (see [`entry_name`]).
+    fn entry_metadata(
+        &mut self,
+        dir: &Path,
+        DirEntryPlusInternal { entry, metadata }: &DirEntryPlusInternal,
+        now: Instant,
+    ) -> Option<PathBuf> {
+        let name = entry_name(&entry.name)?;
+        if name == "." || name == ".." {
+            return None;
+        }
+
+        let path = dir.join(name);
+        let response = FileResponse::Xstat(Ok(XstatResponse {
+            metadata: *metadata,
+        }));
+        if !entry.is_symlink() {
+            self.responses.insert(
+                CacheKey::Xstat {
+                    path: path.clone(),
+                    follow_symlink: true,
+                },
+                (now, response.clone()),
+            );
+        }
+        self.responses.insert(
+            CacheKey::Xstat {
+                path: path.clone(),
+                follow_symlink: false,
+            },
+            (now, response),
+        );
+
+        Some(path)
+    }
+
+    /// Returns the request that prefetches the remote tree at `path`, whose chunks are cached
+    /// with [`Self::prefetch_chunk`].
+    pub(super) fn prefetch(&mut self, path: PathBuf) -> PrefetchTreeRequest {
+        let id = self.next_prefetch_id;
+        self.next_prefetch_id += 1;
+        self.prefetches.insert(id, Vec::new());
+
+        PrefetchTreeRequest {
+            id,
+            path,
+            max_file_size: PREFETCH_MAX_FILE_SIZE,
+            max_entries: PREFETCH_MAX_ENTRIES,
+        }
+    }
+
+    /// Caches the directories of a `chunk` of a [`Self::prefetch`]: their listings, their
+    /// metadata and the metadata of their entries (see [`Self::entries_metadata`]), and the
+    /// contents of their small files.
+    ///
+    /// The directories that the local app modified since the prefetch was requested are left
+    /// out, like [`Self::invalidate`] would remove them.
+    pub(super) fn prefetch_chunk(&mut self, chunk: PrefetchChunkResponse) {
+        let Some(invalidated) = self.prefetches.get(&chunk.id) else {
+            return;
+        };
+        let dirs = chunk
+            .dirs
+            .into_iter()
+            .filter(|dir| {
+                !invalidated.iter().any(|path| {
+                    dir.path.starts_with(path) || path.parent() == Some(dir.path.as_path())
+                })
+            })
+            .collect::<Vec<_>>();
+        if chunk.last {
+            self.prefetches.remove(&chunk.id);
+        }
+
+        self.purge_expired();
+        let now = Instant::now();
+        for dir in dirs {
+            self.responses.insert(
+                CacheKey::Xstat {
+                    path: dir.path.clone(),
+                    follow_symlink: true,
+                },
+                (
+                    now,
+                    FileResponse::Xstat(Ok(XstatResponse {
+                        metadata: dir.metadata,
+                    })),
+                ),
+            );
+
+            let mut listing = Vec::with_capacity(dir.entries.len());
+            for PrefetchEntryInternal { entry, contents } in dir.entries {
+                let path = self.entry_metadata(&dir.path, &entry, now);
+                if let (Some(path), Some(contents)) = (path, contents) {
+                    self.contents.insert(path, (now, contents));
+                }
+                listing.push(entry.entry);
+            }
+            self.listings.insert(dir.path, (now, listing));
+        }
+    }
+
+    /// Answers the `request` from the cached `stat` of the file, and from its prefetched
+    /// contents, see [`Self::prefetch`].
+    ///
+    /// Like the agent, only the regular files that are no bigger than
+    /// [`ReadWholeFileRequest::max_size`] are read, the others are just stat'ed.
+    fn read_whole(&mut self, request: &ReadWholeFileRequest) -> Option<FileResponse> {
+        let key = CacheKey::Xstat {
+            path: request.path.clone(),
+            follow_symlink: true,
+        };
+        let metadata = match self.responses.get(&key) {
+            Some((cached_at, FileResponse::Xstat(Ok(XstatResponse { metadata }))))
+                if cached_at.elapsed() < self.ttl =>
+            {
+                *metadata
+            }
+            _ => return None,
+        };
+
+        let regular = metadata.mode & FILE_TYPE_MASK == REGULAR_FILE;
+        let bytes = if regular && metadata.size <= request.max_size {
+            match self.contents.get(&request.path) {
+                Some((cached_at, bytes))
+                    if cached_at.elapsed() < self.ttl && bytes.len() as u64 <= request.max_size =>
+                {
+                    Some(bytes.clone())
+                }
+                _ => return None,
+            }
+        } else {
+            None
+        };
+
+        Some(FileResponse::ReadWhole(Ok(ReadWholeFileResponse {
+            metadata,
+            bytes,
+        })))
+    }
+
+    /// Forgets everything cached about `path`, which the local app modified: its metadata, the
+    /// listing of its parent directory, and everything under it, if it's a directory.
+    ///
+    /// Symbolic links that point to `path` keep their cached metadata until it expires.
+    pub(super) fn invalidate(&mut self, path: &Path) {
+        self.generation += 1;
+
+        self.responses
+            .retain(|key, _| !key.path().starts_with(path));
+        self.listings
+            .retain(|dir, _| !dir.starts_with(path) && path.parent() != Some(dir.as_path()));
+        self.contents.retain(|file, _| !file.starts_with(path));
+        for invalidated in self.prefetches.values_mut() {
+            invalidated.push(path.to_path_buf());
+        }
+    }
+
+    /// Forgets everything, when the local app modified a remote path that we don't know.
+    ///
+    /// The prefetches that the agent is still sending are dropped as well.
+    pub(super) fn clear(&mut self) {
+        self.generation += 1;
+
+        self.responses.clear();
+        self.listings.clear();
+        self.contents.clear();
+        self.prefetches.clear();
+    }
+
+    /// Removes the expired entries, at most once per TTL, so that the cache doesn't grow with the
+    /// paths that are never asked for again.
+    fn purge_expired(&mut self) {
+        let now = Instant::now();
+        if now < self.next_purge {
+            return;
+        }
+
+        self.responses
+            .retain(|_, (cached_at, _)| now.duration_since(*cached_at) < self.ttl);
+        self.listings
+            .retain(|_, (cached_at, _)| now.duration_since(*cached_at) < self.ttl);
+        self.contents
+            .retain(|_, (cached_at, _)| now.duration_since(*cached_at) < self.ttl);
+        self.next_purge = now + self.ttl;
+    }
+}
@@ -0,0 +1,156 @@
+//! Handling of requests throttled by the agent, see [`RetryQueue`].
+
+use std::{collections::VecDeque, fmt, future, time::Duration};
+
+use mirrord_intproxy_protocol::{LayerId, MessageId};
+use mirrord_protocol::{ClientMessage, FileResponse, ResponseError};
+use tokio::time::{self, Instant};
+use tracing::Level;
+
+use crate::request_queue::RequestQueueEmpty;
+
+/// A [`RequestQueue`](crate::request_queue::RequestQueue) that also keeps the requests, so that
+/// they can be sent again when the agent throttles them (see [`ResponseError::Throttled`]).
+///
+/// After a request is throttled, the queue backs off: the throttled request, and every request
+/// made until the backoff ends, are held here, and sent again in order by [`RetryQueue::retry`].
+#[derive(Default)]
+pub(super) struct RetryQueue {
+    /// Requests sent to the agent, waiting for a response.
+    in_flight: VecDeque<(MessageId, LayerId, ClientMessage)>,
+    /// Requests to send when the backoff ends.
+    held: VecDeque<(MessageId, LayerId, ClientMessage)>,
+    /// When the backoff ends, [`None`] if we're not backing off.
+    retry_at: Option<Instant>,
+}
+
+impl fmt::Debug for RetryQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryQueue")
+            .field("in_flight", &self.in_flight.len())
+            .field("held", &self.held.len())
+            .field("retry_at", &self.retry_at)
+            .finish()
+    }
+}
+
+impl RetryQueue {
+    /// Saves the request, and returns it if it should be sent to the agent right away.
+    #[tracing::instrument(level = Level::TRACE, ret)]
+    pub(super) fn insert(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: ClientMessage,
+    ) -> Option<ClientMessage> {
+        if self.retry_at.is_some() {
+            self.held.push_back((message_id, layer_id, request));
+            None
+        } else {
+            self.in_flight
+                .push_back((message_id, layer_id, request.clone()));
+            Some(request)
+        }
+    }
+
+    /// Retrieves and removes the request answered by the agent.
+    #[tracing::instrument(level = Level::TRACE, err)]
+    pub(super) fn get(&mut self) -> Result<(MessageId, LayerId), RequestQueueEmpty> {
+        self.in_flight
+            .pop_front()
+            .map(|(message_id, layer_id, _)| (message_id, layer_id))
+            .ok_or(RequestQueueEmpty)
+    }
+
+    /// Returns the request that the agent is going to answer next.
+    pub(super) fn front(&self) -> Option<&ClientMessage> {
+        self.in_flight.front().map(|(_, _, request)| request)
+    }
+
+    /// Holds the request throttled by the agent, and backs off for at least `retry_after`.
+    #[tracing::instrument(level = Level::TRACE, err)]
+    pub(super) fn throttled(&mut self, retry_after: Duration) -> Result<(), RequestQueueEmpty> {
+        let request = self.in_flight.pop_front().ok_or(RequestQueueEmpty)?;
+        self.held.push_back(request);
+
+        let retry_at = Instant::now() + retry_after;
+        self.retry_at = Some(
+            self.retry_at
+                .map_or(retry_at, |current| current.max(retry_at)),
+        );
+
+        Ok(())
+    }
+
+    /// Resolves when the backoff ends, never if we're not backing off.
+    pub(super) async fn backoff_finished(&self) {
+        match self.retry_at {
+            Some(retry_at) => time::sleep_until(retry_at).await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Ends the backoff, and returns the held requests, which should be sent to the agent.
+    #[tracing::instrument(level = Level::TRACE, ret)]
+    pub(super) fn retry(&mut self) -> Vec<ClientMessage> {
+        self.retry_at = None;
+
+        self.held
+            .drain(..)
+            .map(|(message_id, layer_id, request)| {
+                self.in_flight
+                    .push_back((message_id, layer_id, request.clone()));
+                request
+            })
+            .collect()
+    }
+}
+
+/// Returns how long to back off for, if the agent throttled the request of this `response`.
+pub(super) fn file_retry_after(response: &FileResponse) -> Option<Duration> {
+    match response {
+        FileResponse::Open(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Read(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadLimited(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Write(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::WriteLimited(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Seek(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Access(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Xstat(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::XstatFs(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadDir(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::OpenDir(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::GetDEnts64(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadLink(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadDirBatch(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Unlink(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Rename(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Chmod(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Chown(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadWhole(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Truncate(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Glob(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Symlink(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Link(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Utimens(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Fsync(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Fallocate(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::CopyFileRange(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadV(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::WriteV(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadChunk(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Statx(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::GetXattr(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::SetXattr(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ListXattr(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::RemoveXattr(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Lock(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::GetLock(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadDirDelta(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::ReadDirPlusBatch(Err(ResponseError::Throttled { retry_after_ms }))
+        | FileResponse::Canonicalize(Err(ResponseError::Throttled { retry_after_ms })) => {
+            Some(Duration::from_millis(*retry_after_ms))
+        }
+        _ => None,
+    }
+}
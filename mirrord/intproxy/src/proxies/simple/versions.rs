@@ -0,0 +1,235 @@
+//! Requests that older agents can't handle, see [`not_implemented`].
+
+use std::{path::PathBuf, sync::LazyLock};
+
+use mirrord_protocol::{
+    file::{
+        AccessFileRequest, CanonicalizePathRequest, ChmodFileRequest, ChmodFileWithDirRequest,
+        ChownFileRequest, ChownFileWithDirRequest, GetXattrRequest, LinkFileRequest,
+        LinkFileWithDirRequest, ListXattrRequest, OpenAt2Request, OpenFileRequest,
+        OpenRelativeFileRequest, ReadLinkFileRequest, ReadWholeFileRequest, RemoveXattrRequest,
+        RenameFileRequest, RenameFileWithDirRequest, SeekFileRequest, SeekFromInternal,
+        SetXattrRequest, StatFsRequest, StatxRequest, SymlinkAtRequest, SymlinkRequest,
+        TruncateFileRequest, UnlinkFileRequest, UnlinkFileWithDirRequest,
+        UtimensFileWithDirRequest, XstatRequest, CANONICALIZE_VERSION, CHMOD_VERSION,
+        CHOWN_VERSION, COPY_FILE_RANGE_VERSION, FALLOCATE_VERSION, FSYNC_VERSION, GLOB_VERSION,
+        LINK_VERSION, LOCK_VERSION, NON_UTF8_PATHS_VERSION, OPENAT2_VERSION, READDIR_BATCH_VERSION,
+        READ_WHOLE_FILE_VERSION, RENAME_VERSION, SEEK_HOLE_VERSION, STATFS_PATH_VERSION,
+        STATX_VERSION, SYMLINK_VERSION, TRUNCATE_VERSION, UNLINK_VERSION, UTIMENS_VERSION,
+        VECTORED_IO_VERSION, XATTR_VERSION,
+    },
+    FileRequest, FileResponse, ResponseError,
+};
+use semver::{Version, VersionReq};
+
+/// Returns the [`ResponseError::NotImplemented`] response for a `request` that the agent with
+/// `protocol_version` can't handle, [`None`] if the request can be sent to it.
+///
+/// The layer falls back to something else when it gets this response, usually the same operation
+/// on the local file.
+pub(super) fn not_implemented(
+    request: &FileRequest,
+    protocol_version: Option<&Version>,
+) -> Option<FileResponse> {
+    use ResponseError::NotImplemented;
+
+    if !protocol_version.is_some_and(|version| NON_UTF8_PATHS_VERSION.matches(version)) {
+        if let Some(response) = non_utf8_path(request) {
+            return Some(response);
+        }
+    }
+
+    let (version, response): (&LazyLock<VersionReq>, _) = match request {
+        FileRequest::ReadLink(..) => (
+            &READDIR_BATCH_VERSION,
+            FileResponse::ReadLink(Err(NotImplemented)),
+        ),
+        FileRequest::Unlink(..) | FileRequest::UnlinkAt(..) => {
+            (&UNLINK_VERSION, FileResponse::Unlink(Err(NotImplemented)))
+        }
+        FileRequest::Rename(..) | FileRequest::RenameAt(..) => {
+            (&RENAME_VERSION, FileResponse::Rename(Err(NotImplemented)))
+        }
+        FileRequest::Chmod(..) | FileRequest::Fchmod(..) | FileRequest::ChmodAt(..) => {
+            (&CHMOD_VERSION, FileResponse::Chmod(Err(NotImplemented)))
+        }
+        FileRequest::Truncate(..) | FileRequest::Ftruncate(..) => (
+            &TRUNCATE_VERSION,
+            FileResponse::Truncate(Err(NotImplemented)),
+        ),
+        FileRequest::Symlink(..) | FileRequest::SymlinkAt(..) => {
+            (&SYMLINK_VERSION, FileResponse::Symlink(Err(NotImplemented)))
+        }
+        FileRequest::Link(..) | FileRequest::LinkAt(..) => {
+            (&LINK_VERSION, FileResponse::Link(Err(NotImplemented)))
+        }
+        FileRequest::UtimensAt(..) | FileRequest::Futimens(..) => {
+            (&UTIMENS_VERSION, FileResponse::Utimens(Err(NotImplemented)))
+        }
+        FileRequest::Chown(..) | FileRequest::Fchown(..) | FileRequest::ChownAt(..) => {
+            (&CHOWN_VERSION, FileResponse::Chown(Err(NotImplemented)))
+        }
+        // Older agents would ignore the `RESOLVE_*` flags.
+        FileRequest::OpenAt2(..) => (&OPENAT2_VERSION, FileResponse::Open(Err(NotImplemented))),
+        FileRequest::ReadWhole(..) => (
+            &READ_WHOLE_FILE_VERSION,
+            FileResponse::ReadWhole(Err(NotImplemented)),
+        ),
+        FileRequest::Fsync(..) => (&FSYNC_VERSION, FileResponse::Fsync(Err(NotImplemented))),
+        FileRequest::Statx(..) => (&STATX_VERSION, FileResponse::Statx(Err(NotImplemented))),
+        FileRequest::GetXattr(..) => (&XATTR_VERSION, FileResponse::GetXattr(Err(NotImplemented))),
+        FileRequest::ListXattr(..) => {
+            (&XATTR_VERSION, FileResponse::ListXattr(Err(NotImplemented)))
+        }
+        FileRequest::SetXattr(..) => (&XATTR_VERSION, FileResponse::SetXattr(Err(NotImplemented))),
+        FileRequest::RemoveXattr(..) => (
+            &XATTR_VERSION,
+            FileResponse::RemoveXattr(Err(NotImplemented)),
+        ),
+        FileRequest::Flock(..) | FileRequest::SetLock(..) => {
+            (&LOCK_VERSION, FileResponse::Lock(Err(NotImplemented)))
+        }
+        FileRequest::GetLock(..) => (&LOCK_VERSION, FileResponse::GetLock(Err(NotImplemented))),
+        FileRequest::Fallocate(..) => (
+            &FALLOCATE_VERSION,
+            FileResponse::Fallocate(Err(NotImplemented)),
+        ),
+        FileRequest::Canonicalize(..) => (
+            &CANONICALIZE_VERSION,
+            FileResponse::Canonicalize(Err(NotImplemented)),
+        ),
+        FileRequest::StatFs(..) => (
+            &STATFS_PATH_VERSION,
+            FileResponse::XstatFs(Err(NotImplemented)),
+        ),
+        // Only seeking the holes is new, older agents can seek as usual.
+        FileRequest::Seek(SeekFileRequest {
+            seek_from: SeekFromInternal::Data(..) | SeekFromInternal::Hole(..),
+            ..
+        }) => (&SEEK_HOLE_VERSION, FileResponse::Seek(Err(NotImplemented))),
+        FileRequest::CopyFileRange(..) => (
+            &COPY_FILE_RANGE_VERSION,
+            FileResponse::CopyFileRange(Err(NotImplemented)),
+        ),
+        FileRequest::ReadV(..) => (
+            &VECTORED_IO_VERSION,
+            FileResponse::ReadV(Err(NotImplemented)),
+        ),
+        FileRequest::WriteV(..) => (
+            &VECTORED_IO_VERSION,
+            FileResponse::WriteV(Err(NotImplemented)),
+        ),
+        FileRequest::Glob(..) => (&GLOB_VERSION, FileResponse::Glob(Err(NotImplemented))),
+        _ => return None,
+    };
+
+    (!protocol_version.is_some_and(|protocol_version| version.matches(protocol_version)))
+        .then_some(response)
+}
+
+/// Returns the [`ResponseError::NotImplemented`] response for a `request` with a path that is not
+/// valid UTF-8, which agents older than [`NON_UTF8_PATHS_VERSION`] can't decode.
+fn non_utf8_path(request: &FileRequest) -> Option<FileResponse> {
+    use ResponseError::NotImplemented;
+
+    let (paths, response): ([Option<&PathBuf>; 2], _) = match request {
+        FileRequest::Open(OpenFileRequest { path, .. })
+        | FileRequest::OpenRelative(OpenRelativeFileRequest { path, .. })
+        | FileRequest::OpenAt2(OpenAt2Request { path, .. }) => {
+            ([Some(path), None], FileResponse::Open(Err(NotImplemented)))
+        }
+        FileRequest::Access(AccessFileRequest { pathname, .. }) => (
+            [Some(pathname), None],
+            FileResponse::Access(Err(NotImplemented)),
+        ),
+        FileRequest::Xstat(XstatRequest { path, .. }) => (
+            [path.as_ref(), None],
+            FileResponse::Xstat(Err(NotImplemented)),
+        ),
+        FileRequest::StatFs(StatFsRequest { path }) => (
+            [Some(path), None],
+            FileResponse::XstatFs(Err(NotImplemented)),
+        ),
+        FileRequest::Statx(StatxRequest { path, .. }) => (
+            [path.as_ref(), None],
+            FileResponse::Statx(Err(NotImplemented)),
+        ),
+        FileRequest::ReadLink(ReadLinkFileRequest { path }) => (
+            [Some(path), None],
+            FileResponse::ReadLink(Err(NotImplemented)),
+        ),
+        FileRequest::ReadWhole(ReadWholeFileRequest { path, .. }) => (
+            [Some(path), None],
+            FileResponse::ReadWhole(Err(NotImplemented)),
+        ),
+        FileRequest::Canonicalize(CanonicalizePathRequest { path }) => (
+            [Some(path), None],
+            FileResponse::Canonicalize(Err(NotImplemented)),
+        ),
+        FileRequest::Unlink(UnlinkFileRequest { path })
+        | FileRequest::UnlinkAt(UnlinkFileWithDirRequest { path, .. }) => (
+            [Some(path), None],
+            FileResponse::Unlink(Err(NotImplemented)),
+        ),
+        FileRequest::Rename(RenameFileRequest { old_path, new_path })
+        | FileRequest::RenameAt(RenameFileWithDirRequest {
+            old_path, new_path, ..
+        }) => (
+            [Some(old_path), Some(new_path)],
+            FileResponse::Rename(Err(NotImplemented)),
+        ),
+        FileRequest::Link(LinkFileRequest { old_path, new_path })
+        | FileRequest::LinkAt(LinkFileWithDirRequest {
+            old_path, new_path, ..
+        }) => (
+            [Some(old_path), Some(new_path)],
+            FileResponse::Link(Err(NotImplemented)),
+        ),
+        FileRequest::Symlink(SymlinkRequest { target, link_path })
+        | FileRequest::SymlinkAt(SymlinkAtRequest {
+            target, link_path, ..
+        }) => (
+            [Some(target), Some(link_path)],
+            FileResponse::Symlink(Err(NotImplemented)),
+        ),
+        FileRequest::Chmod(ChmodFileRequest { path, .. })
+        | FileRequest::ChmodAt(ChmodFileWithDirRequest { path, .. }) => {
+            ([Some(path), None], FileResponse::Chmod(Err(NotImplemented)))
+        }
+        FileRequest::Chown(ChownFileRequest { path, .. })
+        | FileRequest::ChownAt(ChownFileWithDirRequest { path, .. }) => {
+            ([Some(path), None], FileResponse::Chown(Err(NotImplemented)))
+        }
+        FileRequest::Truncate(TruncateFileRequest { path, .. }) => (
+            [Some(path), None],
+            FileResponse::Truncate(Err(NotImplemented)),
+        ),
+        FileRequest::UtimensAt(UtimensFileWithDirRequest { path, .. }) => (
+            [Some(path), None],
+            FileResponse::Utimens(Err(NotImplemented)),
+        ),
+        FileRequest::GetXattr(GetXattrRequest { path, .. }) => (
+            [path.as_ref(), None],
+            FileResponse::GetXattr(Err(NotImplemented)),
+        ),
+        FileRequest::SetXattr(SetXattrRequest { path, .. }) => (
+            [path.as_ref(), None],
+            FileResponse::SetXattr(Err(NotImplemented)),
+        ),
+        FileRequest::ListXattr(ListXattrRequest { path, .. }) => (
+            [path.as_ref(), None],
+            FileResponse::ListXattr(Err(NotImplemented)),
+        ),
+        FileRequest::RemoveXattr(RemoveXattrRequest { path, .. }) => (
+            [path.as_ref(), None],
+            FileResponse::RemoveXattr(Err(NotImplemented)),
+        ),
+        _ => return None,
+    };
+
+    paths
+        .into_iter()
+        .flatten()
+        .any(|path| path.to_str().is_none())
+        .then_some(response)
+}
@@ -0,0 +1,582 @@
+//! Handles the `inotify` hooks of the layers, see [`WatchProxy`].
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    io,
+    net::Ipv4Addr,
+    path::Path,
+};
+
+use mirrord_intproxy_protocol::{
+    LayerId, MessageId, ProxyToLayerMessage, WatchAddRequest, WatchInit, WatchInstanceId,
+    WatchRemoveRequest, WatchRequest, WatchResponse,
+};
+use mirrord_protocol::{
+    watch::{AddWatchRequest, DaemonWatch, LayerWatch, WatchEvent, WatchId, WATCH_VERSION},
+    ClientMessage, RemoteResult, ResponseError,
+};
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tracing::Level;
+
+use self::{local::LocalWatches, stream::WatchStream};
+use crate::{
+    background_tasks::{BackgroundTask, BackgroundTasks, MessageBus, TaskSender, TaskUpdate},
+    main_tasks::ToLayer,
+    request_queue::RequestQueueEmpty,
+    ProxyMessage,
+};
+
+mod local;
+mod stream;
+
+/// The `IN_*` bits of `inotify(7)` that the [`WatchProxy`] looks at.
+mod bits {
+    pub const IN_ALL_EVENTS: u32 = 0x0000_0fff;
+    pub const IN_UNMOUNT: u32 = 0x0000_2000;
+    pub const IN_Q_OVERFLOW: u32 = 0x0000_4000;
+    pub const IN_IGNORED: u32 = 0x0000_8000;
+    pub const IN_ONLYDIR: u32 = 0x0100_0000;
+    pub const IN_DONT_FOLLOW: u32 = 0x0200_0000;
+    pub const IN_EXCL_UNLINK: u32 = 0x0400_0000;
+    pub const IN_MASK_CREATE: u32 = 0x1000_0000;
+    pub const IN_MASK_ADD: u32 = 0x2000_0000;
+    pub const IN_ONESHOT: u32 = 0x8000_0000;
+}
+
+/// Size of the `struct inotify_event` header, the names are padded to a multiple of it.
+const EVENT_HEADER_LEN: usize = 16;
+
+/// Appends an event in the format of `inotify(7)` to `bytes`.
+fn encode_event(bytes: &mut Vec<u8>, wd: i32, mask: u32, cookie: u32, name: Option<&Path>) {
+    let name = name.map(|name| name.as_os_str().as_encoded_bytes());
+    let len = name.map_or(0, |name| {
+        (name.len() + 1).next_multiple_of(EVENT_HEADER_LEN)
+    });
+
+    bytes.extend_from_slice(&wd.to_ne_bytes());
+    bytes.extend_from_slice(&mask.to_ne_bytes());
+    bytes.extend_from_slice(&cookie.to_ne_bytes());
+    bytes.extend_from_slice(&(len as u32).to_ne_bytes());
+
+    if let Some(name) = name {
+        bytes.extend_from_slice(name);
+        bytes.resize(bytes.len() + len - name.len(), 0);
+    }
+}
+
+/// The mask of the watches that the [`WatchProxy`] makes, the events are filtered for each
+/// instance with the mask of its own watch, see [`Instance::deliver`].
+fn source_mask(mask: u32) -> u32 {
+    bits::IN_ALL_EVENTS | (mask & (bits::IN_DONT_FOLLOW | bits::IN_ONLYDIR | bits::IN_EXCL_UNLINK))
+}
+
+fn errno(raw: i32) -> ResponseError {
+    io::Error::from_raw_os_error(raw).into()
+}
+
+/// Errors of the [`WatchProxy`].
+#[derive(Error, Debug)]
+pub enum WatchProxyError {
+    /// The agent sent a [`DaemonWatch::Added`], but there was no add request waiting for it.
+    #[error("failed to match watch response: {0}")]
+    RequestQueueEmpty(#[from] RequestQueueEmpty),
+    #[error("failed to read the local inotify events: {0}")]
+    LocalEvents(#[from] io::Error),
+}
+
+/// Watched file, shared by the watches of all instances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum WatchSource {
+    /// Watched by the agent.
+    Remote(WatchId),
+    /// Watched by the [`LocalWatches`].
+    Local(WatchId),
+}
+
+#[derive(Debug)]
+struct InstanceWatch {
+    source: WatchSource,
+    /// The `IN_*` bits of `inotify_add_watch`.
+    mask: u32,
+}
+
+/// A single `inotify` instance of a layer.
+struct Instance {
+    tx: TaskSender<WatchStream>,
+    /// Indexed with the watch descriptors of this instance.
+    watches: HashMap<i32, InstanceWatch>,
+    last_descriptor: i32,
+}
+
+impl Instance {
+    fn new(tx: TaskSender<WatchStream>) -> Self {
+        Self {
+            tx,
+            watches: Default::default(),
+            last_descriptor: 0,
+        }
+    }
+
+    /// Returns the watch descriptor of `source` in this instance, adding a new watch when it's not
+    /// watched yet, like `inotify_add_watch`.
+    fn add(&mut self, source: WatchSource, mask: u32) -> RemoteResult<i32> {
+        let existing = self
+            .watches
+            .iter_mut()
+            .find(|(_, watch)| watch.source == source);
+
+        match existing {
+            Some(..) if mask & bits::IN_MASK_CREATE != 0 => Err(errno(libc::EEXIST)),
+            Some((wd, watch)) => {
+                if mask & bits::IN_MASK_ADD != 0 {
+                    watch.mask |= mask;
+                } else {
+                    watch.mask = mask;
+                }
+
+                Ok(*wd)
+            }
+            None => {
+                self.last_descriptor += 1;
+                self.watches
+                    .insert(self.last_descriptor, InstanceWatch { source, mask });
+
+                Ok(self.last_descriptor)
+            }
+        }
+    }
+
+    /// Returns the events of this instance for an `event` of the `source`, encoded.
+    ///
+    /// The watches that are gone after the event are removed.
+    fn deliver(&mut self, source: WatchSource, event: &WatchEvent) -> Vec<u8> {
+        let always = bits::IN_IGNORED | bits::IN_Q_OVERFLOW | bits::IN_UNMOUNT;
+        let mut bytes = vec![];
+
+        self.watches.retain(|&wd, watch| {
+            if watch.source != source
+                || event.mask & ((watch.mask & bits::IN_ALL_EVENTS) | always) == 0
+            {
+                return true;
+            }
+
+            let name = event.name.as_deref();
+            encode_event(&mut bytes, wd, event.mask, event.cookie, name);
+
+            if event.mask & bits::IN_IGNORED != 0 {
+                false
+            } else if watch.mask & bits::IN_ONESHOT != 0 {
+                encode_event(&mut bytes, wd, bits::IN_IGNORED, 0, None);
+                false
+            } else {
+                true
+            }
+        });
+
+        bytes
+    }
+}
+
+/// An add request sent to the agent, which responds to them in order.
+#[derive(Debug)]
+struct PendingAdd {
+    message_id: MessageId,
+    layer_id: LayerId,
+    instance: WatchInstanceId,
+    mask: u32,
+}
+
+/// Handles the `inotify` instances of the layers, run as a [`BackgroundTask`].
+///
+/// Every instance gets its own local connection, and a [`WatchStream`] task writes the events of
+/// the instance to it. The layer hands the connection out as the `inotify` descriptor, so the user
+/// application can read it and poll it like the real thing.
+///
+/// The files are watched once, by the agent for the remote paths, and by the [`LocalWatches`] for
+/// the local ones. Their events are delivered to the instances with the matching watches.
+#[derive(Default)]
+pub struct WatchProxy {
+    instances: HashMap<WatchInstanceId, Instance>,
+    last_instance: WatchInstanceId,
+    /// For managing [`WatchStream`] tasks.
+    background_tasks: BackgroundTasks<WatchInstanceId, Infallible, io::Error>,
+    pending_adds: VecDeque<PendingAdd>,
+    /// Remote watches that are not used anymore, removed once the agent responds to the
+    /// [`PendingAdd`]s (which could return one of them).
+    unused_remote: HashSet<WatchId>,
+    local: LocalWatches,
+    /// Version of [`mirrord_protocol`] negotiated with the agent.
+    agent_protocol_version: Option<semver::Version>,
+}
+
+impl WatchProxy {
+    /// Used when registering new [`WatchStream`] tasks in the [`BackgroundTasks`] struct.
+    const CHANNEL_SIZE: usize = 512;
+
+    /// Starts a new instance, when the agent supports [`WATCH_VERSION`].
+    #[tracing::instrument(level = Level::TRACE, skip(self), ret)]
+    async fn init(&mut self) -> RemoteResult<WatchInit> {
+        let supported = self
+            .agent_protocol_version
+            .as_ref()
+            .is_some_and(|version| WATCH_VERSION.matches(version));
+        if !supported {
+            return Err(ResponseError::NotImplemented);
+        }
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let address = listener.local_addr()?;
+
+        self.last_instance += 1;
+        let instance = self.last_instance;
+        let tx = self.background_tasks.register(
+            WatchStream::new(listener),
+            instance,
+            Self::CHANNEL_SIZE,
+        );
+        self.instances.insert(instance, Instance::new(tx));
+
+        Ok(WatchInit { instance, address })
+    }
+
+    /// Watches the local paths right away, the remote ones are answered when the agent responds.
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus))]
+    async fn handle_add(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: WatchAddRequest,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let WatchAddRequest {
+            instance,
+            path,
+            mask,
+            remote,
+        } = request;
+
+        let result = if !self.instances.contains_key(&instance) {
+            Err(errno(libc::EBADF))
+        } else if remote {
+            self.pending_adds.push_back(PendingAdd {
+                message_id,
+                layer_id,
+                instance,
+                mask,
+            });
+
+            let request = AddWatchRequest {
+                path,
+                mask: source_mask(mask),
+            };
+            message_bus
+                .send(ClientMessage::Watch(LayerWatch::Add(request)))
+                .await;
+
+            return;
+        } else {
+            match self.local.add(&path, source_mask(mask)) {
+                Ok(id) => {
+                    self.attach(instance, WatchSource::Local(id), mask, message_bus)
+                        .await
+                }
+                Err(error) => Err(error),
+            }
+        };
+
+        message_bus
+            .send(ToLayer {
+                message_id,
+                layer_id,
+                message: ProxyToLayerMessage::Watch(WatchResponse::Add(result)),
+            })
+            .await;
+    }
+
+    /// Passes the agent's response to the oldest [`PendingAdd`] to the layer.
+    async fn handle_added(
+        &mut self,
+        result: RemoteResult<WatchId>,
+        message_bus: &mut MessageBus<Self>,
+    ) -> Result<(), WatchProxyError> {
+        let PendingAdd {
+            message_id,
+            layer_id,
+            instance,
+            mask,
+        } = self.pending_adds.pop_front().ok_or(RequestQueueEmpty)?;
+
+        let result = match result {
+            Ok(id) => {
+                self.unused_remote.remove(&id);
+                self.attach(instance, WatchSource::Remote(id), mask, message_bus)
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+
+        if self.pending_adds.is_empty() {
+            for id in std::mem::take(&mut self.unused_remote) {
+                self.release(WatchSource::Remote(id), message_bus).await;
+            }
+        }
+
+        message_bus
+            .send(ToLayer {
+                message_id,
+                layer_id,
+                message: ProxyToLayerMessage::Watch(WatchResponse::Add(result)),
+            })
+            .await;
+
+        Ok(())
+    }
+
+    /// Adds a watch of the `source` to the instance, the `source` is released if this fails.
+    async fn attach(
+        &mut self,
+        instance: WatchInstanceId,
+        source: WatchSource,
+        mask: u32,
+        message_bus: &mut MessageBus<Self>,
+    ) -> RemoteResult<i32> {
+        let result = match self.instances.get_mut(&instance) {
+            Some(instance) => instance.add(source, mask),
+            None => Err(errno(libc::EBADF)),
+        };
+
+        if result.is_err() {
+            self.release(source, message_bus).await;
+        }
+
+        result
+    }
+
+    /// `inotify_rm_watch`, the instance gets the `IN_IGNORED` event of the watch.
+    #[tracing::instrument(level = Level::TRACE, skip(self, message_bus), ret)]
+    async fn remove(
+        &mut self,
+        request: WatchRemoveRequest,
+        message_bus: &mut MessageBus<Self>,
+    ) -> RemoteResult<()> {
+        let instance = self
+            .instances
+            .get_mut(&request.instance)
+            .ok_or_else(|| errno(libc::EBADF))?;
+        let watch = instance
+            .watches
+            .remove(&request.watch_descriptor)
+            .ok_or_else(|| errno(libc::EINVAL))?;
+
+        let mut bytes = vec![];
+        encode_event(
+            &mut bytes,
+            request.watch_descriptor,
+            bits::IN_IGNORED,
+            0,
+            None,
+        );
+        instance.tx.send(bytes).await;
+
+        self.release(watch.source, message_bus).await;
+
+        Ok(())
+    }
+
+    /// Delivers an event of the `source` to the instances.
+    async fn dispatch(
+        &mut self,
+        source: WatchSource,
+        event: WatchEvent,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        for instance in self.instances.values_mut() {
+            let bytes = instance.deliver(source, &event);
+            if !bytes.is_empty() {
+                instance.tx.send(bytes).await;
+            }
+        }
+
+        // `IN_IGNORED` means that the source is already gone.
+        if event.mask & bits::IN_IGNORED == 0 {
+            self.release(source, message_bus).await;
+        }
+    }
+
+    /// Removes the `source` if no instance watches it anymore.
+    async fn release(&mut self, source: WatchSource, message_bus: &mut MessageBus<Self>) {
+        let in_use = self
+            .instances
+            .values()
+            .flat_map(|instance| instance.watches.values())
+            .any(|watch| watch.source == source);
+        if in_use {
+            return;
+        }
+
+        match source {
+            WatchSource::Remote(id) if self.pending_adds.is_empty() => {
+                message_bus
+                    .send(ClientMessage::Watch(LayerWatch::Remove(id)))
+                    .await;
+            }
+            WatchSource::Remote(id) => {
+                self.unused_remote.insert(id);
+            }
+            WatchSource::Local(id) => self.local.remove(id),
+        }
+    }
+
+    /// Called when the layer closes the connection of the instance.
+    async fn instance_closed(
+        &mut self,
+        instance: WatchInstanceId,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let Some(instance) = self.instances.remove(&instance) else {
+            return;
+        };
+
+        for watch in instance.watches.into_values() {
+            self.release(watch.source, message_bus).await;
+        }
+    }
+}
+
+/// Messages consumed by the [`WatchProxy`] running as a [`BackgroundTask`].
+#[derive(Debug)]
+pub enum WatchProxyMessage {
+    LayerRequest(MessageId, LayerId, WatchRequest),
+    Agent(DaemonWatch),
+    /// Agent responded to [`ClientMessage::SwitchProtocolVersion`].
+    AgentProtocolVersion(semver::Version),
+}
+
+impl BackgroundTask for WatchProxy {
+    type Error = WatchProxyError;
+    type MessageIn = WatchProxyMessage;
+    type MessageOut = ProxyMessage;
+
+    async fn run(mut self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        loop {
+            tokio::select! {
+                msg = message_bus.recv() => match msg {
+                    None => {
+                        tracing::trace!("message bus closed, exiting");
+                        break Ok(());
+                    }
+                    Some(WatchProxyMessage::LayerRequest(message_id, layer_id, request)) => {
+                        let response = match request {
+                            WatchRequest::Init(..) => WatchResponse::Init(self.init().await),
+                            WatchRequest::Add(request) => {
+                                self.handle_add(message_id, layer_id, request, message_bus).await;
+                                continue;
+                            }
+                            WatchRequest::Remove(request) => {
+                                WatchResponse::Remove(self.remove(request, message_bus).await)
+                            }
+                        };
+
+                        message_bus
+                            .send(ToLayer {
+                                message_id,
+                                layer_id,
+                                message: ProxyToLayerMessage::Watch(response),
+                            })
+                            .await;
+                    }
+                    Some(WatchProxyMessage::Agent(DaemonWatch::Added(result))) => {
+                        self.handle_added(result, message_bus).await?
+                    }
+                    Some(WatchProxyMessage::Agent(DaemonWatch::Event(event))) => {
+                        let source = WatchSource::Remote(event.watch_id);
+                        self.dispatch(source, event, message_bus).await;
+                    }
+                    Some(WatchProxyMessage::AgentProtocolVersion(version)) => {
+                        self.agent_protocol_version.replace(version);
+                    }
+                },
+
+                event = self.local.next_event() => {
+                    let event = event?;
+                    self.dispatch(WatchSource::Local(event.watch_id), event, message_bus).await;
+                }
+
+                Some(task_update) = self.background_tasks.next() => match task_update {
+                    (instance, TaskUpdate::Finished(result)) => {
+                        tracing::trace!(instance, ?result, "watch stream finished");
+                        self.instance_closed(instance, message_bus).await;
+                    }
+                    (_, TaskUpdate::Message(never)) => match never {},
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(mask: u32, name: Option<&str>) -> WatchEvent {
+        WatchEvent {
+            watch_id: 4,
+            mask,
+            cookie: 0,
+            name: name.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn encodes_like_the_kernel() {
+        let mut bytes = vec![];
+        encode_event(&mut bytes, 1, bits::IN_IGNORED, 0, None);
+        encode_event(&mut bytes, 2, 0x100, 7, Some(Path::new("settings.yaml")));
+
+        let mut expected = vec![];
+        for field in [1, bits::IN_IGNORED, 0, 0, 2, 0x100, 7, 16] {
+            expected.extend_from_slice(&field.to_ne_bytes());
+        }
+        expected.extend_from_slice(b"settings.yaml");
+        expected.resize(EVENT_HEADER_LEN * 3, 0);
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[tokio::test]
+    async fn filters_events_of_each_watch() {
+        let mut tasks = BackgroundTasks::<u64, Infallible, io::Error>::default();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let mut instance = Instance::new(tasks.register(WatchStream::new(listener), 0, 1));
+
+        let source = WatchSource::Remote(4);
+        let created = instance.add(source, 0x100).unwrap();
+        assert_eq!(instance.add(source, 0x200 | bits::IN_MASK_ADD), Ok(created));
+        assert_eq!(
+            instance.add(source, 0x100 | bits::IN_MASK_CREATE),
+            Err(errno(libc::EEXIST))
+        );
+        let oneshot = instance
+            .add(WatchSource::Local(4), 0x2 | bits::IN_ONESHOT)
+            .unwrap();
+
+        // Not in the mask.
+        assert!(instance.deliver(source, &event(0x2, Some("a"))).is_empty());
+        assert_eq!(
+            instance.deliver(source, &event(0x200, Some("a"))).len(),
+            EVENT_HEADER_LEN * 2
+        );
+
+        let local = WatchSource::Local(4);
+        assert_eq!(
+            instance.deliver(local, &event(0x2, None)).len(),
+            EVENT_HEADER_LEN * 2
+        );
+        assert!(!instance.watches.contains_key(&oneshot));
+
+        instance.deliver(source, &event(bits::IN_IGNORED, None));
+        assert!(instance.watches.is_empty());
+    }
+}
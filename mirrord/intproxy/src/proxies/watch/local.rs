@@ -0,0 +1,164 @@
+//! `inotify` of the internal proxy, for the local paths that the layers watch, see
+//! [`LocalWatches`].
+//!
+//! The layer hooks `inotify` only on Linux, the other platforms never watch local paths.
+
+use std::{io, path::Path};
+
+use mirrord_protocol::{
+    watch::{WatchEvent, WatchId},
+    RemoteResult,
+};
+
+#[cfg(target_os = "linux")]
+pub use self::linux::LocalWatches;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        collections::{HashMap, VecDeque},
+        os::fd::{AsFd, AsRawFd, RawFd},
+        path::PathBuf,
+    };
+
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+    use tokio::io::unix::AsyncFd;
+
+    use super::*;
+
+    /// [`Inotify`] that can be polled with [`AsyncFd`].
+    struct RawInotify(Inotify);
+
+    impl AsRawFd for RawInotify {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_fd().as_raw_fd()
+        }
+    }
+
+    /// Local watches, with the same [`WatchId`]s and [`WatchEvent`]s as the agent's watches.
+    ///
+    /// The [`Inotify`] instance is created with the first watch.
+    #[derive(Default)]
+    pub struct LocalWatches {
+        inotify: Option<AsyncFd<RawInotify>>,
+        ids: HashMap<WatchDescriptor, WatchId>,
+        descriptors: HashMap<WatchId, WatchDescriptor>,
+        last_id: WatchId,
+        /// Events that were read from the [`Inotify`], but not returned yet.
+        events: VecDeque<WatchEvent>,
+    }
+
+    impl LocalWatches {
+        /// Watches the `path`, the same file gets the same [`WatchId`].
+        pub fn add(&mut self, path: &Path, mask: u32) -> RemoteResult<WatchId> {
+            let inotify = match &mut self.inotify {
+                Some(inotify) => inotify,
+                None => {
+                    let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+                        .map_err(io::Error::from)?;
+                    self.inotify.insert(AsyncFd::new(RawInotify(inotify))?)
+                }
+            };
+
+            let descriptor = inotify
+                .get_ref()
+                .0
+                .add_watch(path, AddWatchFlags::from_bits_retain(mask))
+                .map_err(io::Error::from)?;
+
+            let id = *self.ids.entry(descriptor).or_insert_with(|| {
+                self.last_id += 1;
+                self.last_id
+            });
+            self.descriptors.insert(id, descriptor);
+
+            Ok(id)
+        }
+
+        /// Removes the watch, its `IN_IGNORED` event is returned afterwards.
+        pub fn remove(&mut self, id: WatchId) {
+            let (Some(inotify), Some(descriptor)) = (&self.inotify, self.descriptors.get(&id))
+            else {
+                return;
+            };
+
+            if let Err(error) = inotify.get_ref().0.rm_watch(*descriptor) {
+                tracing::debug!(id, %error, "Failed to remove the local watch, it's already gone");
+            }
+        }
+
+        /// Returns the next event of the watches, pending forever when nothing is watched.
+        ///
+        /// Cancel safe.
+        pub async fn next_event(&mut self) -> io::Result<WatchEvent> {
+            loop {
+                if let Some(event) = self.events.pop_front() {
+                    return Ok(event);
+                }
+
+                let Some(inotify) = &self.inotify else {
+                    return std::future::pending().await;
+                };
+
+                let mut guard = inotify.readable().await?;
+                let Ok(events) = guard
+                    .try_io(|inotify| inotify.get_ref().0.read_events().map_err(io::Error::from))
+                else {
+                    continue;
+                };
+
+                for event in events? {
+                    self.push_event(event);
+                }
+            }
+        }
+
+        fn push_event(&mut self, event: InotifyEvent) {
+            let InotifyEvent {
+                wd,
+                mask,
+                cookie,
+                name,
+            } = event;
+
+            // `IN_Q_OVERFLOW` is not bound to any watch.
+            let Some(&watch_id) = self.ids.get(&wd) else {
+                tracing::warn!(
+                    ?mask,
+                    "Dropped a local inotify event that is not bound to a watch"
+                );
+                return;
+            };
+
+            if mask.contains(AddWatchFlags::IN_IGNORED) {
+                self.ids.remove(&wd);
+                self.descriptors.remove(&watch_id);
+            }
+
+            self.events.push_back(WatchEvent {
+                watch_id,
+                mask: mask.bits(),
+                cookie,
+                name: name.map(PathBuf::from),
+            });
+        }
+    }
+}
+
+/// Never watches anything.
+#[cfg(not(target_os = "linux"))]
+#[derive(Default)]
+pub struct LocalWatches;
+
+#[cfg(not(target_os = "linux"))]
+impl LocalWatches {
+    pub fn add(&mut self, _: &Path, _: u32) -> RemoteResult<WatchId> {
+        Err(mirrord_protocol::ResponseError::NotImplemented)
+    }
+
+    pub fn remove(&mut self, _: WatchId) {}
+
+    pub async fn next_event(&mut self) -> io::Result<WatchEvent> {
+        std::future::pending().await
+    }
+}
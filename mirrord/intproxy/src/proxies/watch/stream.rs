@@ -0,0 +1,81 @@
+//! [`BackgroundTask`] used by the [`WatchProxy`](super::WatchProxy) to write the events of a
+//! single `inotify` instance to the layer.
+
+use std::{convert::Infallible, io};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use super::{bits, encode_event};
+use crate::background_tasks::{BackgroundTask, MessageBus};
+
+/// Most bytes of events that wait for the layer to read them, like the `max_queued_events` of the
+/// kernel (16384 events, with short names).
+const MAX_QUEUED_BYTES: usize = 16384 * 32;
+
+/// Writes the encoded events of an `inotify` instance to the layer's connection.
+pub struct WatchStream {
+    listener: TcpListener,
+}
+
+impl WatchStream {
+    /// Creates a new instance. This instance will accept the layer's connection on the given
+    /// [`TcpListener`].
+    pub fn new(listener: TcpListener) -> Self {
+        Self { listener }
+    }
+}
+
+impl BackgroundTask for WatchStream {
+    type Error = io::Error;
+    type MessageIn = Vec<u8>;
+    type MessageOut = Infallible;
+
+    /// Accepts one connection and writes the events from the [`MessageBus`] to it, until the
+    /// layer closes it.
+    ///
+    /// The events are queued while the layer doesn't read them, and the ones over
+    /// [`MAX_QUEUED_BYTES`] are dropped for a single `IN_Q_OVERFLOW`, like the kernel does.
+    async fn run(self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        let (mut stream, _) = self.listener.accept().await?;
+        let (mut reader, mut writer) = stream.split();
+        let mut queued = Vec::new();
+        let mut overflowed = false;
+        // The layer never writes to the connection.
+        let mut discard = [0; 64];
+
+        loop {
+            tokio::select! {
+                msg = message_bus.recv() => match msg {
+                    None => {
+                        tracing::trace!("watch stream -> no more events, exiting");
+                        break Ok(());
+                    }
+                    Some(events) if queued.len() + events.len() > MAX_QUEUED_BYTES => {
+                        if !overflowed {
+                            encode_event(&mut queued, -1, bits::IN_Q_OVERFLOW, 0, None);
+                            overflowed = true;
+                        }
+                    }
+                    Some(events) => queued.extend_from_slice(&events),
+                },
+
+                written = writer.write(&queued), if !queued.is_empty() => {
+                    queued.drain(..written?);
+                    if queued.is_empty() {
+                        overflowed = false;
+                    }
+                }
+
+                read = reader.read(&mut discard) => {
+                    if read? == 0 {
+                        tracing::trace!("watch stream -> layer closed the instance, exiting");
+                        break Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
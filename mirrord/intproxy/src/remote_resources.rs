@@ -68,6 +68,11 @@ where
         }
     }
 
+    /// Whether any layer instance holds the given resource.
+    pub(crate) fn contains(&self, resource: &T) -> bool {
+        self.counts.contains_key(resource)
+    }
+
     /// Clones all resources held by the layer instance with id `src` to the layer instance with the
     /// id `dst`.
     ///
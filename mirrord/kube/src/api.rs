@@ -1,6 +1,6 @@
 use actix_codec::{AsyncRead, AsyncWrite};
 use futures::{SinkExt, StreamExt};
-use mirrord_protocol::{ClientCodec, ClientMessage, DaemonMessage};
+use mirrord_protocol::{framing::FRAMING_VERSION, ClientCodec, ClientMessage, DaemonMessage};
 use tokio::sync::mpsc;
 use tracing::Instrument;
 
@@ -12,6 +12,11 @@ const CONNECTION_CHANNEL_SIZE: usize = 1000;
 
 /// Creates the task that handles the messaging between layer/agent.
 /// It does the encoding/decoding of protocol.
+///
+/// Once the agent responds with a protocol version that supports
+/// [`FRAMING_VERSION`], the connection is switched to length-prefixed frames (see
+/// [`mirrord_protocol::framing`]). The agent's [`DaemonMessage::SwitchFramingResponse`] is not
+/// passed on.
 #[tracing::instrument(level = "trace", skip_all)]
 pub fn wrap_raw_connection(
     stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -41,7 +46,21 @@ pub fn wrap_raw_connection(
                     },
 
                     msg = codec.next() => match msg {
+                        Some(Ok(DaemonMessage::SwitchFramingResponse(limits))) => {
+                            tracing::trace!(?limits, "Switched to framed agent messages");
+                        }
                         Some(Ok(msg)) => {
+                            if let DaemonMessage::SwitchProtocolVersionResponse(version) = &msg
+                                && FRAMING_VERSION.matches(version)
+                            {
+                                let limits = codec.codec_ref().limits();
+                                let switch = ClientMessage::SwitchFraming(limits);
+                                if let Err(error) = codec.send(switch).await {
+                                    tracing::error!(?error, "Failed to switch framing");
+                                    break;
+                                }
+                            }
+
                             if let Err(error) = out_tx.send(msg).await {
                                 tracing::error!(?error, "Failed to send agent message");
                                 break;
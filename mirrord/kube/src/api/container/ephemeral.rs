@@ -1,4 +1,4 @@
-use futures::StreamExt;
+use futures::{StreamExt, TryFutureExt};
 use k8s_openapi::api::core::v1::{
     Capabilities, EnvVar, EphemeralContainer as KubeEphemeralContainer, Pod, SecurityContext,
 };
@@ -17,13 +17,14 @@ use super::util::agent_env;
 use crate::{
     api::{
         container::{
-            util::{base_command_line, get_capabilities, wait_for_agent_startup},
+            util::{base_command_line, check_image_pull, get_capabilities, wait_for_agent_startup},
             ContainerParams, ContainerVariant,
         },
         kubernetes::{get_k8s_resource_api, AgentKubernetesConnectInfo},
         runtime::RuntimeData,
     },
     error::{KubeApiError, Result},
+    retry::retry_transient,
 };
 
 fn is_ephemeral_container_running(pod: Pod, container_name: &str) -> bool {
@@ -62,7 +63,10 @@ where
     debug!("Requesting ephemeral_containers_subresource");
 
     let pod_api = get_k8s_resource_api(client, runtime_data.pod_namespace.as_deref());
-    let pod: Pod = pod_api.get(&runtime_data.pod_name).await?;
+    let pod: Pod = retry_transient("get the target pod", || {
+        pod_api.get(&runtime_data.pod_name).err_into()
+    })
+    .await?;
     let container_spec = pod
         .spec
         .as_ref()
@@ -84,10 +88,13 @@ where
         ephemeral_container.env_from = Some(env)
     }
 
-    let mut ephemeral_containers_subresource: Pod = pod_api
-        .get_subresource("ephemeralcontainers", &runtime_data.pod_name)
-        .await
-        .map_err(KubeApiError::KubeError)?;
+    let mut ephemeral_containers_subresource: Pod =
+        retry_transient("get the ephemeral containers of the target pod", || {
+            pod_api
+                .get_subresource("ephemeralcontainers", &runtime_data.pod_name)
+                .err_into()
+        })
+        .await?;
 
     let spec = ephemeral_containers_subresource
         .spec
@@ -125,6 +132,13 @@ where
     pin!(stream);
 
     while let Some(Ok(pod)) = stream.next().await {
+        check_image_pull(
+            pod.status
+                .as_ref()
+                .and_then(|status| status.ephemeral_container_statuses.as_deref()),
+            &params.name,
+        )?;
+
         if is_ephemeral_container_running(pod, &params.name) {
             debug!("container ready");
             break;
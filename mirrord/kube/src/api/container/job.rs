@@ -1,12 +1,14 @@
 use std::collections::BTreeMap;
 
 use futures::StreamExt;
+use http::StatusCode;
 use k8s_openapi::api::{
     batch::v1::{Job, JobSpec},
     core::v1::{Pod, PodTemplateSpec},
 };
 use kube::{
     api::{ObjectMeta, PostParams},
+    core::ErrorResponse,
     runtime::{watcher, WatchStreamExt},
     Api, Client, ResourceExt,
 };
@@ -19,13 +21,14 @@ use crate::{
     api::{
         container::{
             pod::{PodTargetedVariant, PodVariant},
-            util::wait_for_agent_startup,
+            util::{check_image_pull, wait_for_agent_startup},
             ContainerParams, ContainerVariant,
         },
         kubernetes::{get_k8s_resource_api, AgentKubernetesConnectInfo},
         runtime::RuntimeData,
     },
     error::{KubeApiError, Result},
+    retry::retry_transient,
 };
 
 pub async fn create_job_agent<P, V>(
@@ -45,10 +48,27 @@ where
 
     let job_api = get_k8s_resource_api(client, agent.namespace.as_deref());
 
-    job_api
-        .create(&PostParams::default(), &agent_job)
-        .await
-        .map_err(KubeApiError::KubeError)?;
+    let mut attempts = 0;
+    retry_transient("create the agent job", || {
+        attempts += 1;
+        let retrying = attempts > 1;
+        let (job_api, agent_job) = (&job_api, &agent_job);
+
+        async move {
+            match job_api.create(&PostParams::default(), agent_job).await {
+                Ok(..) => Ok(()),
+                // The name of the job is random, so an earlier attempt created it, even though
+                // we didn't get the response.
+                Err(kube::Error::Api(ErrorResponse { code, .. }))
+                    if retrying && code == StatusCode::CONFLICT =>
+                {
+                    Ok(())
+                }
+                Err(error) => Err(KubeApiError::KubeError(error)),
+            }
+        }
+    })
+    .await?;
 
     let watcher_config = watcher::Config::default()
         .labels(&format!("job-name={}", params.name))
@@ -65,6 +85,13 @@ where
 
     let mut agent_pod = None;
     while let Some(Ok(pod)) = stream.next().await {
+        check_image_pull(
+            pod.status
+                .as_ref()
+                .and_then(|status| status.container_statuses.as_deref()),
+            "mirrord-agent",
+        )?;
+
         let Some(phase) = pod.status.as_ref().and_then(|status| status.phase.as_ref()) else {
             continue;
         };
@@ -351,6 +378,7 @@ mod test {
                 container_runtime: ContainerRuntime::Docker,
                 container_name: "foo".to_string(),
                 guessed_container: false,
+                sandbox: None,
             },
         )
         .as_update();
@@ -1,14 +1,17 @@
 use std::sync::LazyLock;
 
 use futures::{AsyncBufReadExt, TryStreamExt};
-use k8s_openapi::api::core::v1::{EnvVar, Pod, Toleration};
+use k8s_openapi::api::core::v1::{ContainerStatus, EnvVar, Pod, Toleration};
 use kube::{api::LogParams, Api};
 use mirrord_config::agent::{AgentConfig, LinuxCapability};
 use mirrord_protocol::{AGENT_NETWORK_INTERFACE_ENV, AGENT_OPERATOR_CERT_ENV};
 use regex::Regex;
 use tracing::warn;
 
-use crate::{api::container::ContainerParams, error::Result};
+use crate::{
+    api::container::ContainerParams,
+    error::{KubeApiError, Result},
+};
 
 static AGENT_READY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new("agent ready( - version (\\S+))?").expect("failed to create regex")
@@ -101,6 +104,46 @@ pub(super) fn base_command_line(agent: &AgentConfig, params: &ContainerParams) -
     command_line
 }
 
+/// Waiting reasons of a container that won't start until its image is fixed.
+const IMAGE_PULL_FAILURES: [&str; 3] =
+    ["ImagePullBackOff", "InvalidImageName", "ErrImageNeverPull"];
+
+/// Fails with [`KubeApiError::AgentImagePullFailed`] when the agent container can't pull its
+/// image, instead of waiting for it to start until the timeout.
+pub(super) fn check_image_pull(
+    statuses: Option<&[ContainerStatus]>,
+    container_name: &str,
+) -> Result<()> {
+    let Some(status) = statuses
+        .unwrap_or_default()
+        .iter()
+        .find(|status| status.name == container_name)
+    else {
+        return Ok(());
+    };
+
+    let Some(waiting) = status
+        .state
+        .as_ref()
+        .and_then(|state| state.waiting.as_ref())
+    else {
+        return Ok(());
+    };
+
+    match waiting.reason.as_deref() {
+        Some(reason) if IMAGE_PULL_FAILURES.contains(&reason) => {
+            Err(KubeApiError::AgentImagePullFailed {
+                image: status.image.clone(),
+                reason: waiting
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| reason.to_string()),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
 /**
  * Wait until the agent prints the "agent ready" message.
  * Return agent version extracted from the message (if found).
@@ -138,6 +181,7 @@ pub(super) async fn wait_for_agent_startup(
 
 #[cfg(test)]
 mod test {
+    use k8s_openapi::api::core::v1::{ContainerState, ContainerStateWaiting};
     use rstest::rstest;
 
     use super::*;
@@ -150,4 +194,30 @@ mod test {
 
         assert_eq!(captures.get(2).map(|c| c.as_str()), version);
     }
+
+    #[rstest]
+    #[case("ContainerCreating", false)]
+    #[case("ImagePullBackOff", true)]
+    #[case("InvalidImageName", true)]
+    fn image_pull_failure(#[case] reason: &str, #[case] failed: bool) {
+        let statuses = [ContainerStatus {
+            name: "mirrord-agent".to_string(),
+            image: "ghcr.io/metalbear-co/mirrord:latest".to_string(),
+            state: Some(ContainerState {
+                waiting: Some(ContainerStateWaiting {
+                    reason: Some(reason.to_string()),
+                    message: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }];
+
+        let result = check_image_pull(Some(&statuses), "mirrord-agent");
+        assert_eq!(
+            matches!(result, Err(KubeApiError::AgentImagePullFailed { .. })),
+            failed
+        );
+        assert!(check_image_pull(Some(&statuses), "other").is_ok());
+    }
 }
@@ -1,6 +1,12 @@
-use std::ops::Deref;
+use std::{collections::BTreeMap, ops::Deref};
 
-use k8s_openapi::{api::core::v1::Namespace, NamespaceResourceScope};
+use k8s_openapi::{
+    api::{
+        core::v1::{Namespace, Pod},
+        networking::v1::NetworkPolicy,
+    },
+    NamespaceResourceScope,
+};
 use kube::{
     api::ListParams,
     config::{KubeConfigOptions, Kubeconfig},
@@ -8,6 +14,7 @@ use kube::{
 };
 use mirrord_config::{
     agent::AgentConfig,
+    feature::network::filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter},
     target::{Target, TargetConfig},
     LayerConfig,
 };
@@ -16,6 +23,7 @@ use mirrord_protocol::MeshVendor;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
+use self::network_policy::{Egress, EgressProtocol};
 use crate::{
     api::{
         container::{
@@ -28,8 +36,11 @@ use crate::{
         runtime::{RuntimeData, RuntimeDataProvider},
     },
     error::{KubeApiError, Result},
+    retry::retry_transient,
 };
 
+pub mod gateway;
+pub mod network_policy;
 #[cfg(not(feature = "incluster"))]
 pub mod portforwarder;
 pub mod rollout;
@@ -42,13 +53,7 @@ pub struct KubernetesAPI {
 
 impl KubernetesAPI {
     pub async fn create(config: &LayerConfig) -> Result<Self> {
-        let client = create_kube_config(
-            config.accept_invalid_certificates,
-            config.kubeconfig.clone(),
-            config.kube_context.clone(),
-        )
-        .await?
-        .try_into()?;
+        let client = create_kube_config(config).await?.try_into()?;
 
         Ok(KubernetesAPI::new(client, config.agent.clone()))
     }
@@ -169,10 +174,11 @@ impl KubernetesAPI {
     ) -> Result<(ContainerParams, Option<RuntimeData>), KubeApiError> {
         let runtime_data = match target.path.as_ref().unwrap_or(&Target::Targetless) {
             Target::Targetless => None,
-            path => path
-                .runtime_data(&self.client, target.namespace.as_deref())
-                .await?
-                .into(),
+            path => retry_transient("resolve the target", || {
+                path.runtime_data(&self.client, target.namespace.as_deref())
+            })
+            .await?
+            .into(),
         };
 
         let pod_ips = runtime_data
@@ -235,9 +241,30 @@ impl KubernetesAPI {
             }
         }
 
-        info!(?params, "Spawning new agent");
+        let sandbox = runtime_data.as_ref().and_then(|data| data.sandbox);
+        if let Some(sandbox) = sandbox {
+            progress.info(&format!("sandboxed runtime detected: {sandbox}"));
+
+            if !self.agent.ephemeral {
+                progress.warning(&format!(
+                    "the target runs in a {sandbox} sandbox, where an agent on the node cannot \
+                    capture its traffic, mirrord will run the agent in an ephemeral container \
+                    instead (set `agent.ephemeral = true` to silence this warning)"
+                ));
+            }
+        }
+
+        if let Some(config) = config
+            && self.agent.check_network_policies
+        {
+            self.check_network_policies(progress, config, runtime_data.as_ref())
+                .await;
+        }
+
+        info!(?params, ?sandbox, "Spawning new agent");
 
-        let agent_connect_info = match (runtime_data, self.agent.ephemeral) {
+        let ephemeral = self.agent.ephemeral || sandbox.is_some();
+        let agent_connect_info = match (runtime_data, ephemeral) {
             (None, false) => {
                 let variant = JobVariant::new(&self.agent, &params);
 
@@ -266,6 +293,114 @@ impl KubernetesAPI {
 
         Ok(agent_connect_info)
     }
+    /// Warns about the [`NetworkPolicy`]s that block the egress the session needs from the target
+    /// (or from the agent, when running targetless): the cluster DNS, and the destinations from
+    /// [`OutgoingConfig::remote_destinations`](mirrord_config::feature::network::outgoing::OutgoingConfig::remote_destinations).
+    ///
+    /// See [`AgentConfig::check_network_policies`]. Errors are only logged, as the check is best
+    /// effort (e.g. the user may not be allowed to list [`NetworkPolicy`]s).
+    #[tracing::instrument(level = "trace", skip_all)]
+    async fn check_network_policies<P>(
+        &self,
+        progress: &mut P,
+        config: &LayerConfig,
+        runtime_data: Option<&RuntimeData>,
+    ) where
+        P: Progress + Send + Sync,
+    {
+        let (namespace, labels) = match runtime_data {
+            Some(runtime_data) => {
+                let pod_api: Api<Pod> =
+                    get_k8s_resource_api(&self.client, runtime_data.pod_namespace.as_deref());
+                match pod_api.get(&runtime_data.pod_name).await {
+                    Ok(pod) => (
+                        runtime_data.pod_namespace.as_deref(),
+                        pod.metadata.labels.unwrap_or_default(),
+                    ),
+                    Err(error) => {
+                        debug!(%error, "Failed to get the target pod, skipping the NetworkPolicy check");
+                        return;
+                    }
+                }
+            }
+            None => {
+                let mut labels = self
+                    .agent
+                    .labels
+                    .clone()
+                    .map(BTreeMap::from_iter)
+                    .unwrap_or_default();
+                labels.insert("app".to_string(), "mirrord".to_string());
+                (self.agent.namespace.as_deref(), labels)
+            }
+        };
+
+        let policy_api: Api<NetworkPolicy> = get_k8s_resource_api(&self.client, namespace);
+        let policies = match policy_api.list(&ListParams::default()).await {
+            Ok(policies) => policies.items,
+            Err(error) => {
+                debug!(%error, "Failed to list NetworkPolicies, skipping the check");
+                return;
+            }
+        };
+        if policies.is_empty() {
+            return;
+        }
+
+        let outgoing = &config.feature.network.outgoing;
+        let mut needed = Vec::new();
+        if config.feature.network.dns.enabled {
+            needed.push(Egress {
+                protocol: EgressProtocol::Udp,
+                ip: None,
+                port: 53,
+                description: "cluster DNS".to_string(),
+            });
+        }
+        for ProtocolAndAddressFilter { protocol, address } in outgoing.remote_destinations() {
+            let (ip, port, description) = match address {
+                AddressFilter::Socket(socket) => {
+                    (Some(socket.ip()), socket.port(), socket.ip().to_string())
+                }
+                AddressFilter::Name(name, port) => (None, port, name),
+                AddressFilter::Port(..) | AddressFilter::Subnet(..) => continue,
+            };
+
+            let protocols = match protocol {
+                ProtocolFilter::Any => [
+                    outgoing.tcp.then_some(EgressProtocol::Tcp),
+                    outgoing.udp.then_some(EgressProtocol::Udp),
+                ],
+                ProtocolFilter::Tcp => [Some(EgressProtocol::Tcp), None],
+                ProtocolFilter::Udp => [Some(EgressProtocol::Udp), None],
+            };
+            needed.extend(protocols.into_iter().flatten().map(|protocol| Egress {
+                protocol,
+                ip,
+                port,
+                description: description.clone(),
+            }));
+        }
+
+        let pod = if runtime_data.is_some() {
+            "target"
+        } else {
+            "agent"
+        };
+        for egress in needed {
+            if let Some(blocking) = network_policy::blocking_policies(&policies, &labels, &egress) {
+                progress.warning(&format!(
+                    "NetworkPolicies {} block the egress of the {pod} to {egress}, connections \
+                    from the session to it will fail",
+                    blocking
+                        .iter()
+                        .map(|name| format!("`{name}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
 }
 
 /// Trait for IO streams returned from [`KubernetesAPI::create_connection`].
@@ -292,21 +427,16 @@ pub struct AgentKubernetesConnectInfo {
     pub agent_version: Option<String>,
 }
 
-pub async fn create_kube_config<P>(
-    accept_invalid_certificates: Option<bool>,
-    kubeconfig: Option<P>,
-    kube_context: Option<String>,
-) -> Result<Config>
-where
-    P: AsRef<str>,
-{
+/// Creates a kube [`Config`] from the kubeconfig selected in the given [`LayerConfig`], and
+/// applies the Kubernetes API connection options (e.g. [`LayerConfig::kube_proxy`]) on top of it.
+pub async fn create_kube_config(layer_config: &LayerConfig) -> Result<Config> {
     let kube_config_opts = KubeConfigOptions {
-        context: kube_context,
+        context: layer_config.kube_context.clone(),
         ..Default::default()
     };
 
-    let mut config = if let Some(kubeconfig) = kubeconfig {
-        let kubeconfig = shellexpand::full(&kubeconfig)
+    let mut config = if let Some(kubeconfig) = &layer_config.kubeconfig {
+        let kubeconfig = shellexpand::full(kubeconfig)
             .map_err(|e| KubeApiError::ConfigPathExpansionError(e.to_string()))?;
         let parsed_kube_config = Kubeconfig::read_from(kubeconfig.deref())?;
         Config::from_custom_kubeconfig(parsed_kube_config, &kube_config_opts).await?
@@ -319,13 +449,51 @@ where
         Config::infer().await?
     };
 
-    if let Some(accept_invalid_certificates) = accept_invalid_certificates {
+    if let Some(accept_invalid_certificates) = layer_config.accept_invalid_certificates {
         config.accept_invalid_certs = accept_invalid_certificates;
     }
 
+    if let Some(proxy) = &layer_config.kube_proxy {
+        let proxy_url = proxy
+            .parse::<http::Uri>()
+            .map_err(|error| KubeApiError::InvalidKubeProxy(proxy.clone(), error))?;
+        config.proxy_url = Some(proxy_url);
+    }
+
+    if let Some(ca_bundle) = &layer_config.kube_ca_bundle {
+        let certificates = read_ca_bundle(ca_bundle)
+            .map_err(|error| KubeApiError::KubeCaBundleError(ca_bundle.clone(), error))?;
+        config
+            .root_cert
+            .get_or_insert_with(Vec::new)
+            .extend(certificates);
+    }
+
+    if let Some(tls_server_name) = &layer_config.kube_tls_server_name {
+        config.tls_server_name = Some(tls_server_name.clone());
+    }
+
     Ok(config)
 }
 
+/// Reads the DER encoded certificates from the PEM file at the given path.
+fn read_ca_bundle(path: &str) -> std::io::Result<Vec<Vec<u8>>> {
+    let path = shellexpand::full(path).map_err(std::io::Error::other)?;
+    let pem = std::fs::read(path.deref())?;
+
+    let certificates = rustls_pemfile::certs(&mut pem.as_slice())
+        .map(|certificate| certificate.map(|certificate| certificate.to_vec()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if certificates.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "no certificates found",
+        ));
+    }
+
+    Ok(certificates)
+}
+
 #[tracing::instrument(level = "trace", skip(client))]
 pub fn get_k8s_resource_api<K>(client: &Client, namespace: Option<&str>) -> Api<K>
 where
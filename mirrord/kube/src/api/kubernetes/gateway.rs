@@ -0,0 +1,397 @@
+//! Discovery of the [`HttpRoute`]s and [`Ingress`]es that route HTTP traffic to a target.
+//!
+//! HTTP filters often depend on headers added by the gateway or the ingress controller, which are
+//! missing from requests that reach the target directly. The routes found here are used to warn
+//! about such filters, and to match stolen requests on route metadata (see [`TargetRoute`]).
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use k8s_openapi::{
+    api::{
+        core::v1::{PodTemplateSpec, Service},
+        networking::v1::{Ingress, IngressBackend},
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    ListableResource, Metadata, NamespaceResourceScope, Resource,
+};
+use kube::{api::ListParams, Client};
+use serde::{Deserialize, Serialize};
+
+use super::get_k8s_resource_api;
+use crate::{error::Result, resolved::ResolvedTarget};
+
+/// Gateway API [`HttpRoute`](https://gateway-api.sigs.k8s.io/api-types/httproute/).
+///
+/// Contains only the fields that we need to find the routes of a target.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HttpRoute {
+    pub metadata: ObjectMeta,
+    pub spec: Option<HttpRouteSpec>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpRouteSpec {
+    pub hostnames: Vec<String>,
+    pub rules: Vec<HttpRouteRule>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpRouteRule {
+    pub filters: Vec<HttpRouteFilter>,
+    pub backend_refs: Vec<HttpBackendRef>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpRouteFilter {
+    pub request_header_modifier: Option<HttpHeaderModifier>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpHeaderModifier {
+    pub set: Vec<HttpHeader>,
+    pub add: Vec<HttpHeader>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HttpBackendRef {
+    pub group: Option<String>,
+    pub kind: Option<String>,
+    pub name: String,
+    pub namespace: Option<String>,
+}
+
+impl Resource for HttpRoute {
+    const API_VERSION: &'static str = "gateway.networking.k8s.io/v1";
+    const GROUP: &'static str = "gateway.networking.k8s.io";
+    const KIND: &'static str = "HTTPRoute";
+    const VERSION: &'static str = "v1";
+    const URL_PATH_SEGMENT: &'static str = "httproutes";
+    type Scope = NamespaceResourceScope;
+}
+
+impl ListableResource for HttpRoute {
+    const LIST_KIND: &'static str = "HTTPRouteList";
+}
+
+impl Metadata for HttpRoute {
+    type Ty = ObjectMeta;
+
+    fn metadata(&self) -> &Self::Ty {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Self::Ty {
+        &mut self.metadata
+    }
+}
+
+impl HttpBackendRef {
+    /// Whether this reference points to one of the given [`Service`]s in the `namespace`.
+    fn points_to(&self, services: &HashSet<String>, namespace: Option<&str>) -> bool {
+        let is_service = self.group.as_deref().unwrap_or_default().is_empty()
+            && self.kind.as_deref().unwrap_or("Service") == "Service";
+        let same_namespace = self.namespace.is_none() || self.namespace.as_deref() == namespace;
+
+        is_service && same_namespace && services.contains(&self.name)
+    }
+}
+
+/// A route that sends HTTP traffic to the target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetRoute {
+    /// Kind and name of the route resource, e.g. `HTTPRoute/api`.
+    pub name: String,
+    /// Hostnames matched by the route, empty when the route matches any host.
+    pub hostnames: Vec<String>,
+    /// Names of the request headers that the route sets, lowercase.
+    pub set_headers: BTreeSet<String>,
+}
+
+impl TargetRoute {
+    /// Returns the route if the [`HttpRoute`] sends traffic to one of the given [`Service`]s.
+    ///
+    /// Only the headers set by the rules that send traffic to the services are included.
+    pub fn from_http_route(route: &HttpRoute, services: &HashSet<String>) -> Option<Self> {
+        let spec = route.spec.as_ref()?;
+        let namespace = route.metadata.namespace.as_deref();
+
+        let rules = spec
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.backend_refs
+                    .iter()
+                    .any(|backend| backend.points_to(services, namespace))
+            })
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return None;
+        }
+
+        let set_headers = rules
+            .into_iter()
+            .flat_map(|rule| &rule.filters)
+            .filter_map(|filter| filter.request_header_modifier.as_ref())
+            .flat_map(|modifier| modifier.set.iter().chain(&modifier.add))
+            .map(|header| header.name.to_lowercase())
+            .collect();
+
+        Some(Self {
+            name: format!(
+                "{}/{}",
+                HttpRoute::KIND,
+                route.metadata.name.as_deref().unwrap_or_default()
+            ),
+            hostnames: spec.hostnames.clone(),
+            set_headers,
+        })
+    }
+
+    /// Returns the route if the [`Ingress`] sends traffic to one of the given [`Service`]s.
+    ///
+    /// Ingresses don't have a standard way of setting request headers, so
+    /// [`TargetRoute::set_headers`] is always empty.
+    pub fn from_ingress(ingress: &Ingress, services: &HashSet<String>) -> Option<Self> {
+        let spec = ingress.spec.as_ref()?;
+
+        let points_to = |backend: &IngressBackend| {
+            backend
+                .service
+                .as_ref()
+                .is_some_and(|service| services.contains(&service.name))
+        };
+
+        let default_backend = spec.default_backend.as_ref().is_some_and(points_to);
+        let rules = spec
+            .rules
+            .iter()
+            .flatten()
+            .filter(|rule| {
+                rule.http
+                    .iter()
+                    .flat_map(|http| &http.paths)
+                    .any(|path| points_to(&path.backend))
+            })
+            .collect::<Vec<_>>();
+        if !default_backend && rules.is_empty() {
+            return None;
+        }
+
+        // The default backend and rules without a host accept any host.
+        let any_host = default_backend || rules.iter().any(|rule| rule.host.is_none());
+        let hostnames = if any_host {
+            Vec::new()
+        } else {
+            rules
+                .into_iter()
+                .filter_map(|rule| rule.host.clone())
+                .collect()
+        };
+
+        Some(Self {
+            name: format!(
+                "{}/{}",
+                Ingress::KIND,
+                ingress.metadata.name.as_deref().unwrap_or_default()
+            ),
+            hostnames,
+            set_headers: Default::default(),
+        })
+    }
+}
+
+/// Returns the labels of the pods of the given target, used to find the [`Service`]s that select
+/// them.
+pub fn pod_labels<const CHECKED: bool>(
+    target: &ResolvedTarget<CHECKED>,
+) -> Option<&BTreeMap<String, String>> {
+    match target {
+        ResolvedTarget::Deployment(resolved) => {
+            template_labels(&resolved.resource.spec.as_ref()?.template)
+        }
+        ResolvedTarget::Rollout(resolved) => resolved
+            .resource
+            .spec
+            .as_ref()?
+            .template
+            .as_ref()
+            .and_then(template_labels)
+            .or(resolved.resource.metadata.labels.as_ref()),
+        ResolvedTarget::StatefulSet(resolved) => {
+            template_labels(&resolved.resource.spec.as_ref()?.template)
+        }
+        ResolvedTarget::Job(resolved) => {
+            template_labels(&resolved.resource.spec.as_ref()?.template)
+        }
+        ResolvedTarget::CronJob(resolved) => template_labels(
+            &resolved
+                .resource
+                .spec
+                .as_ref()?
+                .job_template
+                .spec
+                .as_ref()?
+                .template,
+        ),
+        ResolvedTarget::Pod(resolved) => resolved.resource.metadata.labels.as_ref(),
+        ResolvedTarget::Targetless(..) => None,
+    }
+}
+
+fn template_labels(template: &PodTemplateSpec) -> Option<&BTreeMap<String, String>> {
+    template.metadata.as_ref()?.labels.as_ref()
+}
+
+/// Whether the given [`Service`] selects pods with the given labels.
+fn selects(service: &Service, labels: &BTreeMap<String, String>) -> bool {
+    service
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.selector.as_ref())
+        .filter(|selector| !selector.is_empty())
+        .is_some_and(|selector| {
+            selector
+                .iter()
+                .all(|(key, value)| labels.get(key) == Some(value))
+        })
+}
+
+/// Finds the [`HttpRoute`]s and [`Ingress`]es in the `namespace` that send traffic to the pods
+/// with the given labels, through a [`Service`].
+///
+/// Clusters without the Gateway API are fine, only [`Ingress`]es are returned then.
+pub async fn target_routes(
+    client: &Client,
+    namespace: Option<&str>,
+    pod_labels: &BTreeMap<String, String>,
+) -> Result<Vec<TargetRoute>> {
+    let services = get_k8s_resource_api::<Service>(client, namespace)
+        .list(&ListParams::default())
+        .await?
+        .items
+        .into_iter()
+        .filter(|service| selects(service, pod_labels))
+        .filter_map(|service| service.metadata.name)
+        .collect::<HashSet<_>>();
+    if services.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut routes = get_k8s_resource_api::<Ingress>(client, namespace)
+        .list(&ListParams::default())
+        .await?
+        .items
+        .iter()
+        .filter_map(|ingress| TargetRoute::from_ingress(ingress, &services))
+        .collect::<Vec<_>>();
+
+    match get_k8s_resource_api::<HttpRoute>(client, namespace)
+        .list(&ListParams::default())
+        .await
+    {
+        Ok(list) => routes.extend(
+            list.items
+                .iter()
+                .filter_map(|route| TargetRoute::from_http_route(route, &services)),
+        ),
+        // Gateway API CRDs are not installed.
+        Err(kube::Error::Api(response)) if response.code == 404 => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    Ok(routes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashSet};
+
+    use k8s_openapi::api::networking::v1::Ingress;
+
+    use super::{HttpRoute, TargetRoute};
+
+    #[test]
+    fn http_route_to_service() {
+        let route: HttpRoute = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "api", "namespace": "default" },
+            "spec": {
+                "hostnames": ["api.example.com"],
+                "rules": [
+                    {
+                        "filters": [{
+                            "type": "RequestHeaderModifier",
+                            "requestHeaderModifier": {
+                                "set": [{ "name": "X-Tenant", "value": "a" }],
+                                "add": [{ "name": "x-route", "value": "api" }]
+                            }
+                        }],
+                        "backendRefs": [{ "name": "api", "port": 80 }]
+                    },
+                    {
+                        "filters": [{
+                            "type": "RequestHeaderModifier",
+                            "requestHeaderModifier": {
+                                "set": [{ "name": "x-other", "value": "b" }]
+                            }
+                        }],
+                        "backendRefs": [{ "name": "other", "port": 80 }]
+                    }
+                ]
+            }
+        }))
+        .unwrap();
+
+        let services = HashSet::from(["api".to_string()]);
+        assert_eq!(
+            TargetRoute::from_http_route(&route, &services),
+            Some(TargetRoute {
+                name: "HTTPRoute/api".to_string(),
+                hostnames: vec!["api.example.com".to_string()],
+                set_headers: BTreeSet::from(["x-tenant".to_string(), "x-route".to_string()]),
+            })
+        );
+
+        let services = HashSet::from(["unrelated".to_string()]);
+        assert_eq!(TargetRoute::from_http_route(&route, &services), None);
+    }
+
+    #[test]
+    fn ingress_to_service() {
+        let ingress: Ingress = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "web" },
+            "spec": {
+                "rules": [{
+                    "host": "web.example.com",
+                    "http": {
+                        "paths": [{
+                            "path": "/",
+                            "pathType": "Prefix",
+                            "backend": { "service": { "name": "web", "port": { "number": 80 } } }
+                        }]
+                    }
+                }]
+            }
+        }))
+        .unwrap();
+
+        let services = HashSet::from(["web".to_string()]);
+        assert_eq!(
+            TargetRoute::from_ingress(&ingress, &services),
+            Some(TargetRoute {
+                name: "Ingress/web".to_string(),
+                hostnames: vec!["web.example.com".to_string()],
+                set_headers: Default::default(),
+            })
+        );
+    }
+}
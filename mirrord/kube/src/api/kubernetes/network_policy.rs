@@ -0,0 +1,338 @@
+//! Analysis of the [`NetworkPolicy`]s that select a pod, used to warn about the egress that they
+//! block before the session starts.
+//!
+//! The analysis is conservative: egress is reported as blocked only when no rule of the policies
+//! could allow it. Rules that depend on things we can't know from the client (e.g. which pods a
+//! host name resolves to, or named ports) are assumed to allow the egress.
+
+use std::{collections::BTreeMap, fmt, net::IpAddr};
+
+use k8s_openapi::{
+    api::networking::v1::{
+        IPBlock, NetworkPolicy, NetworkPolicyEgressRule, NetworkPolicyPeer, NetworkPolicyPort,
+        NetworkPolicySpec,
+    },
+    apimachinery::pkg::{apis::meta::v1::LabelSelector, util::intstr::IntOrString},
+};
+
+/// Transport protocol of an [`Egress`], named like in the [`NetworkPolicyPort::protocol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EgressProtocol {
+    Tcp,
+    Udp,
+}
+
+impl EgressProtocol {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "TCP",
+            Self::Udp => "UDP",
+        }
+    }
+}
+
+/// Egress that the session needs from the pod.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Egress {
+    pub protocol: EgressProtocol,
+    /// [`None`] when the destination is only known by name (e.g. the cluster DNS, or a host name
+    /// from the outgoing filter).
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+    /// Shown to the user instead of the [`Self::ip`], e.g. the host name.
+    pub description: String,
+}
+
+impl fmt::Display for Egress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} (port {})",
+            self.protocol.as_str(),
+            self.description,
+            self.port
+        )
+    }
+}
+
+/// Returns the names of the [`NetworkPolicy`]s that select a pod with the given `labels` and
+/// restrict its egress, when none of them allows the given [`Egress`].
+///
+/// Returns [`None`] when the [`Egress`] may be allowed.
+pub fn blocking_policies<'a>(
+    policies: &'a [NetworkPolicy],
+    labels: &BTreeMap<String, String>,
+    egress: &Egress,
+) -> Option<Vec<&'a str>> {
+    let selecting = policies
+        .iter()
+        .filter_map(|policy| Some((policy, policy.spec.as_ref()?)))
+        .filter(|(_, spec)| restricts_egress(spec) && selector_matches(&spec.pod_selector, labels))
+        .collect::<Vec<_>>();
+
+    if selecting.is_empty() {
+        return None;
+    }
+
+    let allowed = selecting
+        .iter()
+        .flat_map(|(_, spec)| spec.egress.iter().flatten())
+        .any(|rule| rule_allows(rule, egress));
+    if allowed {
+        return None;
+    }
+
+    Some(
+        selecting
+            .into_iter()
+            .map(|(policy, _)| policy.metadata.name.as_deref().unwrap_or_default())
+            .collect(),
+    )
+}
+
+/// Whether the policy restricts egress. When the `policyTypes` are not set, it does so only if it
+/// has any `egress` rules.
+fn restricts_egress(spec: &NetworkPolicySpec) -> bool {
+    match &spec.policy_types {
+        Some(types) => types.iter().any(|policy_type| policy_type == "Egress"),
+        None => spec.egress.is_some(),
+    }
+}
+
+fn selector_matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let labels_match = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+
+    let expressions_match = selector
+        .match_expressions
+        .iter()
+        .flatten()
+        .all(|requirement| {
+            let values = requirement.values.as_deref().unwrap_or_default();
+            let value = labels.get(&requirement.key);
+
+            match requirement.operator.as_str() {
+                "In" => value.is_some_and(|value| values.contains(value)),
+                "NotIn" => value.is_none_or(|value| !values.contains(value)),
+                "Exists" => value.is_some(),
+                "DoesNotExist" => value.is_none(),
+                // Unknown operators don't select anything.
+                _ => false,
+            }
+        });
+
+    labels_match && expressions_match
+}
+
+fn rule_allows(rule: &NetworkPolicyEgressRule, egress: &Egress) -> bool {
+    let port_allowed = match rule.ports.as_deref() {
+        None | Some([]) => true,
+        Some(ports) => ports.iter().any(|port| port_allows(port, egress)),
+    };
+
+    let peer_allowed = match rule.to.as_deref() {
+        None | Some([]) => true,
+        Some(peers) => peers.iter().any(|peer| peer_allows(peer, egress)),
+    };
+
+    port_allowed && peer_allowed
+}
+
+fn port_allows(port: &NetworkPolicyPort, egress: &Egress) -> bool {
+    let protocol = port.protocol.as_deref().unwrap_or("TCP");
+    if protocol != egress.protocol.as_str() {
+        return false;
+    }
+
+    match &port.port {
+        None => true,
+        Some(IntOrString::Int(start)) => {
+            let end = port.end_port.unwrap_or(*start);
+            (*start..=end).contains(&i32::from(egress.port))
+        }
+        // Named ports depend on the destination pod.
+        Some(IntOrString::String(..)) => true,
+    }
+}
+
+fn peer_allows(peer: &NetworkPolicyPeer, egress: &Egress) -> bool {
+    match (&peer.ip_block, egress.ip) {
+        (Some(ip_block), Some(ip)) => ip_block_contains(ip_block, ip),
+        // We don't know where a name resolves to, or which pods the selectors pick.
+        _ => true,
+    }
+}
+
+fn ip_block_contains(ip_block: &IPBlock, ip: IpAddr) -> bool {
+    // Invalid blocks are rejected by the API server, so we don't expect them here.
+    let contained = cidr_contains(&ip_block.cidr, ip).unwrap_or(true);
+    let excepted = ip_block
+        .except
+        .iter()
+        .flatten()
+        .any(|except| cidr_contains(except, ip).unwrap_or(false));
+
+    contained && !excepted
+}
+
+/// Whether the `cidr` (e.g. `10.0.0.0/8`) contains the `ip`, [`None`] when it fails to parse.
+fn cidr_contains(cidr: &str, ip: IpAddr) -> Option<bool> {
+    let (network, prefix) = cidr.split_once('/')?;
+    let network = network.parse::<IpAddr>().ok()?;
+    let prefix = prefix.parse::<u32>().ok()?;
+
+    let contains = match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX
+                .checked_shl(32_u32.checked_sub(prefix)?)
+                .unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX
+                .checked_shl(128_u32.checked_sub(prefix)?)
+                .unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    };
+
+    Some(contains)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::networking::v1::NetworkPolicy;
+
+    use super::{blocking_policies, cidr_contains, Egress, EgressProtocol};
+
+    fn policy(name: &str, spec: serde_json::Value) -> NetworkPolicy {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": name, "namespace": "default" },
+            "spec": spec,
+        }))
+        .unwrap()
+    }
+
+    fn labels() -> BTreeMap<String, String> {
+        BTreeMap::from([("app".to_string(), "api".to_string())])
+    }
+
+    fn dns() -> Egress {
+        Egress {
+            protocol: EgressProtocol::Udp,
+            ip: None,
+            port: 53,
+            description: "cluster DNS".to_string(),
+        }
+    }
+
+    fn database() -> Egress {
+        Egress {
+            protocol: EgressProtocol::Tcp,
+            ip: Some("10.0.3.7".parse().unwrap()),
+            port: 5432,
+            description: "10.0.3.7".to_string(),
+        }
+    }
+
+    #[test]
+    fn deny_all_egress() {
+        let policies = [policy(
+            "deny-egress",
+            serde_json::json!({
+                "podSelector": {},
+                "policyTypes": ["Egress"],
+            }),
+        )];
+
+        assert_eq!(
+            blocking_policies(&policies, &labels(), &dns()),
+            Some(vec!["deny-egress"])
+        );
+        assert_eq!(
+            blocking_policies(&policies, &labels(), &database()),
+            Some(vec!["deny-egress"])
+        );
+    }
+
+    #[test]
+    fn other_pods_and_ingress_only() {
+        let policies = [
+            policy(
+                "other-pods",
+                serde_json::json!({
+                    "podSelector": { "matchLabels": { "app": "web" } },
+                    "policyTypes": ["Egress"],
+                }),
+            ),
+            policy(
+                "ingress-only",
+                serde_json::json!({
+                    "podSelector": {},
+                    "ingress": [],
+                }),
+            ),
+        ];
+
+        assert_eq!(blocking_policies(&policies, &labels(), &database()), None);
+    }
+
+    #[test]
+    fn allowed_ports_and_blocks() {
+        let policies = [policy(
+            "egress",
+            serde_json::json!({
+                "podSelector": {
+                    "matchExpressions": [{ "key": "app", "operator": "In", "values": ["api"] }],
+                },
+                "policyTypes": ["Egress"],
+                "egress": [
+                    {
+                        "to": [{ "namespaceSelector": {} }],
+                        "ports": [{ "protocol": "UDP", "port": 53 }],
+                    },
+                    {
+                        "to": [{ "ipBlock": { "cidr": "10.0.0.0/16", "except": ["10.0.3.0/24"] } }],
+                        "ports": [{ "port": 5000, "endPort": 6000 }],
+                    },
+                ],
+            }),
+        )];
+
+        assert_eq!(blocking_policies(&policies, &labels(), &dns()), None);
+        assert_eq!(
+            blocking_policies(&policies, &labels(), &database()),
+            Some(vec!["egress"])
+        );
+
+        let allowed = Egress {
+            ip: Some("10.0.4.7".parse().unwrap()),
+            ..database()
+        };
+        assert_eq!(blocking_policies(&policies, &labels(), &allowed), None);
+
+        // Only the port can be checked for names.
+        let name = Egress {
+            ip: None,
+            description: "db.svc".to_string(),
+            ..database()
+        };
+        assert_eq!(blocking_policies(&policies, &labels(), &name), None);
+    }
+
+    #[test]
+    fn cidr() {
+        let ip = "192.168.1.20".parse().unwrap();
+        assert_eq!(cidr_contains("192.168.0.0/16", ip), Some(true));
+        assert_eq!(cidr_contains("192.168.2.0/24", ip), Some(false));
+        assert_eq!(cidr_contains("0.0.0.0/0", ip), Some(true));
+        assert_eq!(cidr_contains("fd00::/8", ip), Some(false));
+        assert_eq!(cidr_contains("not a cidr", ip), None);
+    }
+}
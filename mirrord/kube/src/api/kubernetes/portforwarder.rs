@@ -11,12 +11,13 @@ use pin_project_lite::pin_project;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio_retry::{
     strategy::{jitter, ExponentialBackoff},
-    Retry,
+    RetryIf,
 };
 
 use crate::{
     api::kubernetes::{get_k8s_resource_api, AgentKubernetesConnectInfo, UnpinStream},
     error::{KubeApiError, Result},
+    retry::retry_transient,
 };
 
 pin_project! {
@@ -83,20 +84,67 @@ where
 
 type RetryStrategy = dyn Iterator<Item = Duration> + Send;
 
-async fn create_portforward_streams(
+/// How often [`SinglePortForwarder`] makes a request to the Kubernetes API, to refresh the
+/// credentials of the [`Client`] (e.g. tokens from an exec plugin like `aws eks get-token`)
+/// before they're needed to re-establish the port-forward.
+const CREDENTIALS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+fn default_retry_strategy() -> Box<RetryStrategy> {
+    Box::new(ExponentialBackoff::from_millis(10).map(jitter).take(5))
+}
+
+/// Whether the [`kube::Error`] comes from the authentication of the [`Client`], e.g. the exec
+/// plugin failed because the user has to log in again. Retrying does not help with these.
+fn is_auth_error(error: &kube::Error) -> bool {
+    matches!(error, kube::Error::Auth(..))
+}
+
+/// Periodically refreshes the credentials of the [`Client`], see [`CREDENTIALS_REFRESH_INTERVAL`].
+///
+/// The [`Client`] refreshes expired credentials only when it makes a request, so without this a
+/// long idle port-forward would find out that the user has to log in again only when it needs to
+/// reconnect.
+async fn refresh_credentials(client: Client, connect_info: AgentKubernetesConnectInfo) {
+    let mut interval = tokio::time::interval(CREDENTIALS_REFRESH_INTERVAL);
+    // The credentials were just used to create the port-forward.
+    interval.reset();
+    let mut failing = false;
+
+    loop {
+        interval.tick().await;
+
+        match client.apiserver_version().await {
+            Ok(..) if failing => {
+                tracing::info!(?connect_info, "Kubernetes credentials refreshed");
+                failing = false;
+            }
+            Err(error) if is_auth_error(&error) && !failing => {
+                tracing::error!(
+                    ?connect_info,
+                    %error,
+                    "failed to refresh Kubernetes credentials, the port-forward to the agent \
+                    cannot be re-established until you log in again (e.g. `aws sso login`, \
+                    `gcloud auth login` or `az login`)"
+                );
+                failing = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Establishes the port-forward to the agent, without retrying.
+async fn portforward_streams(
     pod_api: &Api<Pod>,
     connect_info: &AgentKubernetesConnectInfo,
-    retry_strategy: &mut RetryStrategy,
 ) -> Result<(
     Box<dyn UnpinStream>,
     Box<dyn Future<Output = Option<String>> + Unpin + Send>,
 )> {
-    let ports = &[connect_info.agent_port];
-    let mut port_forwarder = Retry::spawn(retry_strategy, || {
-        tracing::trace!("port-forward to pod {:?}", &connect_info);
-        pod_api.portforward(&connect_info.pod_name, ports)
-    })
-    .await?;
+    tracing::trace!("port-forward to pod {:?}", connect_info);
+    let mut port_forwarder = pod_api
+        .portforward(&connect_info.pod_name, &[connect_info.agent_port])
+        .await?;
 
     let stream = Box::new(
         port_forwarder
@@ -113,7 +161,27 @@ async fn create_portforward_streams(
     Ok((stream, error_future))
 }
 
+async fn create_portforward_streams(
+    pod_api: &Api<Pod>,
+    connect_info: &AgentKubernetesConnectInfo,
+    retry_strategy: &mut RetryStrategy,
+) -> Result<(
+    Box<dyn UnpinStream>,
+    Box<dyn Future<Output = Option<String>> + Unpin + Send>,
+)> {
+    RetryIf::spawn(
+        retry_strategy,
+        || portforward_streams(pod_api, connect_info),
+        |error: &KubeApiError| {
+            matches!(error, KubeApiError::KubeError(error) if !is_auth_error(error))
+        },
+    )
+    .await
+}
+
 pub struct SinglePortForwarder {
+    client: Client,
+
     connect_info: AgentKubernetesConnectInfo,
 
     retry_strategy: Box<RetryStrategy>,
@@ -133,16 +201,19 @@ impl SinglePortForwarder {
         connect_info: AgentKubernetesConnectInfo,
         sink: Box<dyn UnpinStream>,
     ) -> Result<Self> {
-        let mut retry_strategy = Box::new(ExponentialBackoff::from_millis(10).map(jitter).take(5));
+        let retry_strategy = default_retry_strategy();
 
         let pod_api: Api<Pod> = get_k8s_resource_api(client, connect_info.namespace.as_deref());
 
-        let (stream, error_future) =
-            create_portforward_streams(&pod_api, &connect_info, &mut retry_strategy).await?;
+        let (stream, error_future) = retry_transient("port-forward to the agent", || {
+            portforward_streams(&pod_api, &connect_info)
+        })
+        .await?;
 
         let sink = ManualShutdown::new(sink);
 
         Ok(SinglePortForwarder {
+            client: client.clone(),
             connect_info,
             retry_strategy,
             pod_api,
@@ -154,6 +225,7 @@ impl SinglePortForwarder {
 
     pub async fn into_retry_future(self) {
         let SinglePortForwarder {
+            client,
             mut error_future,
             mut stream,
             mut sink,
@@ -164,6 +236,8 @@ impl SinglePortForwarder {
 
         let mut retry_strategy = retry_strategy.peekable();
 
+        let credentials_refresher = tokio::spawn(refresh_credentials(client, connect_info.clone()));
+
         loop {
             tokio::select! {
                 error = error_future.as_mut() => {
@@ -184,9 +258,22 @@ impl SinglePortForwarder {
 
                             stream = next_stream;
                             error_future = next_error_future;
+                            // Every failure of the port-forward gets the full retry budget.
+                            retry_strategy = default_retry_strategy().peekable();
 
                             tracing::trace!(?connect_info, "retry connect successful");
                         }
+                        Err(KubeApiError::KubeError(error)) if is_auth_error(&error) => {
+                            tracing::error!(
+                                ?connect_info,
+                                %error,
+                                "retry connect failed, Kubernetes credentials could not be \
+                                refreshed, you might need to log in again (e.g. `aws sso login`, \
+                                `gcloud auth login` or `az login`)"
+                            );
+
+                            break;
+                        }
                         Err(error) => {
                             tracing::error!(?connect_info, %error, "retry connect failed");
 
@@ -204,6 +291,7 @@ impl SinglePortForwarder {
             }
         }
 
+        credentials_refresher.abort();
         let _ = sink.manual_shutdown().await;
     }
 }
@@ -68,6 +68,43 @@ impl Display for ContainerRuntime {
     }
 }
 
+/// Sandboxed container runtime of a pod.
+///
+/// The containers of these pods don't run directly on the node's kernel (gVisor runs its own
+/// network stack in userspace, Kata runs the pod in a lightweight VM), so an agent on the node
+/// can't capture their traffic by entering their network namespace. The agent has to run inside
+/// the sandbox, as an ephemeral container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxRuntime {
+    GVisor,
+    Kata,
+}
+
+impl SandboxRuntime {
+    /// Detects the sandbox from the name of the pod's `RuntimeClass`, e.g. `gvisor` (GKE
+    /// Sandbox), `runsc`, `kata` or `kata-qemu`.
+    pub fn from_runtime_class(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+
+        if name.contains("gvisor") || name.contains("runsc") {
+            Some(Self::GVisor)
+        } else if name.contains("kata") {
+            Some(Self::Kata)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for SandboxRuntime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SandboxRuntime::GVisor => write!(f, "gVisor"),
+            SandboxRuntime::Kata => write!(f, "Kata Containers"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RuntimeData {
     pub pod_name: String,
@@ -83,6 +120,9 @@ pub struct RuntimeData {
 
     /// Used to check if we're running with a mesh/sidecar in `detect_mesh_mirror_mode`.
     pub mesh: Option<MeshVendor>,
+
+    /// Set when the pod runs in a sandboxed runtime, see [`SandboxRuntime`].
+    pub sandbox: Option<SandboxRuntime>,
 }
 
 impl RuntimeData {
@@ -194,6 +234,12 @@ impl RuntimeData {
 
         let mesh = check_mesh_vendor(pod);
 
+        let sandbox = pod
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.runtime_class_name.as_deref())
+            .and_then(SandboxRuntime::from_runtime_class);
+
         Ok(RuntimeData {
             pod_ips,
             pod_name,
@@ -204,6 +250,7 @@ impl RuntimeData {
             container_name,
             guessed_container,
             mesh,
+            sandbox,
         })
     }
 
@@ -383,6 +430,16 @@ mod tests {
         assert_eq!(target, expected)
     }
 
+    #[rstest]
+    #[case("gvisor", Some(SandboxRuntime::GVisor))]
+    #[case("runsc-kvm", Some(SandboxRuntime::GVisor))]
+    #[case("kata-qemu", Some(SandboxRuntime::Kata))]
+    #[case("Kata", Some(SandboxRuntime::Kata))]
+    #[case("nvidia", None)]
+    fn sandbox_from_runtime_class(#[case] name: &str, #[case] expected: Option<SandboxRuntime>) {
+        assert_eq!(SandboxRuntime::from_runtime_class(name), expected);
+    }
+
     #[allow(clippy::duplicated_attributes)]
     #[rstest]
     #[should_panic(expected = "InvalidTarget")]
@@ -35,6 +35,12 @@ pub enum KubeApiError {
     #[error("Path expansion for kubeconfig failed: {0}")]
     ConfigPathExpansionError(String),
 
+    #[error("Invalid `kube_proxy` URL `{0}`: {1}")]
+    InvalidKubeProxy(String, http::uri::InvalidUri),
+
+    #[error("Failed to load CA certificates from `kube_ca_bundle` `{0}`: {1}")]
+    KubeCaBundleError(String, std::io::Error),
+
     /// We fetched a malformed resource using [`kube`] (should not happen).
     /// Construct with [`Self::missing_field`] or [`Self::invalid_value`] for consistent error
     /// messages.
@@ -49,6 +55,10 @@ pub enum KubeApiError {
     #[error("Agent Job was created, but Pod is not running")]
     AgentPodNotRunning,
 
+    /// The agent container is waiting for an image that can't be pulled.
+    #[error("Failed to pull agent image `{image}`: {reason}")]
+    AgentImagePullFailed { image: String, reason: String },
+
     /// Attempted to create an `OperatorTarget` from a resource that cannot be an immediate target.
     ///
     /// Create this variant with the [`KubeApiError::requires_copy`] method.
@@ -57,6 +67,15 @@ pub enum KubeApiError {
         /// Should be plural name of the resource
         String,
     ),
+
+    /// A setup step kept failing with [transient](KubeApiError::is_transient) errors, see
+    /// [`retry_transient`](crate::retry::retry_transient).
+    #[error("Failed to {operation} after {attempts} attempts, last error: {source}")]
+    RetriesExhausted {
+        operation: &'static str,
+        attempts: usize,
+        source: Box<KubeApiError>,
+    },
 }
 
 impl KubeApiError {
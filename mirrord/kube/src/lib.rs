@@ -14,3 +14,4 @@
 pub mod api;
 pub mod error;
 pub mod resolved;
+pub mod retry;
@@ -0,0 +1,247 @@
+//! Retries of the Kubernetes API requests that set up a session, see [`retry_transient`].
+//!
+//! A single 5xx response or timeout from the API server (e.g. on a flaky corporate network)
+//! should not kill the whole startup, so resolving the target, creating the agent and
+//! establishing the port-forward are retried with a jittered exponential backoff, as long as the
+//! errors look [transient](KubeApiError::is_transient).
+
+use std::{error::Error, future::Future, io, time::Duration};
+
+use http::StatusCode;
+use kube::{client::UpgradeConnectionError, core::ErrorResponse};
+use tokio_retry::{
+    strategy::{jitter, ExponentialBackoff},
+    RetryIf,
+};
+
+use crate::error::{KubeApiError, Result};
+
+/// Most times that a setup step is retried after the first attempt.
+const SETUP_RETRIES: usize = 4;
+
+/// Longest delay between two attempts of a setup step.
+const SETUP_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Delays between the attempts of a setup step: around 0.5s, 1s, 2s and 4s.
+pub fn setup_retry_strategy() -> impl Iterator<Item = Duration> + Send {
+    ExponentialBackoff::from_millis(2)
+        .factor(250)
+        .max_delay(SETUP_MAX_DELAY)
+        .map(jitter)
+        .take(SETUP_RETRIES)
+}
+
+/// Runs `action` until it succeeds, fails with an error that is not
+/// [transient](KubeApiError::is_transient), or [`setup_retry_strategy`] runs out.
+///
+/// In the last case the error is [`KubeApiError::RetriesExhausted`], with the number of attempts
+/// and the last error, `operation` describes the step (e.g. "resolve the target").
+pub async fn retry_transient<T, A, F>(operation: &'static str, action: A) -> Result<T>
+where
+    A: FnMut() -> F,
+    F: Future<Output = Result<T>>,
+{
+    retry_transient_with(setup_retry_strategy(), operation, action).await
+}
+
+async fn retry_transient_with<T, A, F, S>(
+    strategy: S,
+    operation: &'static str,
+    mut action: A,
+) -> Result<T>
+where
+    A: FnMut() -> F,
+    F: Future<Output = Result<T>>,
+    S: IntoIterator<Item = Duration>,
+{
+    let mut attempts = 0;
+
+    let result = RetryIf::spawn(
+        strategy,
+        || {
+            attempts += 1;
+            if attempts > 1 {
+                tracing::warn!(
+                    operation,
+                    attempt = attempts,
+                    "retrying after a transient error"
+                );
+            }
+
+            action()
+        },
+        |error: &KubeApiError| {
+            tracing::debug!(operation, %error, "Kubernetes API request failed");
+            error.is_transient()
+        },
+    )
+    .await;
+
+    result.map_err(|error| {
+        if error.is_transient() {
+            KubeApiError::RetriesExhausted {
+                operation,
+                attempts,
+                source: Box::new(error),
+            }
+        } else {
+            error
+        }
+    })
+}
+
+impl KubeApiError {
+    /// Whether retrying the request that failed with this error could succeed, e.g. the API server
+    /// responded with a 5xx status, or the request timed out.
+    ///
+    /// Authentication errors are never transient, retrying does not help the user log in again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::KubeError(error) => is_transient_kube_error(error),
+            Self::KubeConnectionError(error) => is_transient_io_error(error),
+            _ => false,
+        }
+    }
+}
+
+fn is_transient_kube_error(error: &kube::Error) -> bool {
+    match error {
+        kube::Error::Api(ErrorResponse { code, .. }) => {
+            *code >= 500 || *code == StatusCode::TOO_MANY_REQUESTS
+        }
+        kube::Error::HyperError(error)
+        | kube::Error::UpgradeConnection(UpgradeConnectionError::GetPendingUpgrade(error)) => {
+            error.is_timeout()
+                || error.is_closed()
+                || error.is_incomplete_message()
+                || has_transient_io_source(error)
+        }
+        kube::Error::UpgradeConnection(UpgradeConnectionError::ProtocolSwitch(status)) => {
+            status.is_server_error()
+        }
+        // Connection errors and timeouts of the client, type erased.
+        kube::Error::Service(error) => has_transient_io_source(error.as_ref()),
+        _ => false,
+    }
+}
+
+/// Whether `error` was caused by a transient [`io::Error`], TLS errors (e.g. an invalid
+/// certificate) are not.
+fn has_transient_io_source(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+
+    while let Some(error) = source {
+        source = match error.downcast_ref::<io::Error>() {
+            Some(error) if is_transient_io_error(error) => return true,
+            // `io::Error::source` skips the error that it wraps.
+            Some(error) => error.get_ref().map(|inner| inner as &(dyn Error + 'static)),
+            None => error.source(),
+        };
+    }
+
+    false
+}
+
+fn is_transient_io_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use kube::client::AuthError;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn api_error(code: u16) -> KubeApiError {
+        KubeApiError::KubeError(kube::Error::Api(ErrorResponse {
+            status: "Failure".into(),
+            message: "failed".into(),
+            reason: "Failed".into(),
+            code,
+        }))
+    }
+
+    #[rstest]
+    #[case(api_error(500), true)]
+    #[case(api_error(503), true)]
+    #[case(api_error(429), true)]
+    #[case(api_error(403), false)]
+    #[case(api_error(404), false)]
+    #[case(io::Error::from(io::ErrorKind::TimedOut).into(), true)]
+    #[case(io::Error::from(io::ErrorKind::InvalidData).into(), false)]
+    #[case(
+        KubeApiError::KubeError(kube::Error::Service(
+            io::Error::other(io::Error::from(io::ErrorKind::ConnectionReset)).into()
+        )),
+        true
+    )]
+    #[case(
+        KubeApiError::KubeError(kube::Error::Service(
+            io::Error::from(io::ErrorKind::InvalidData).into()
+        )),
+        false
+    )]
+    #[case(
+        KubeApiError::KubeError(kube::Error::Auth(AuthError::AuthExec("expired".into()))),
+        false
+    )]
+    #[case(KubeApiError::AgentPodNotRunning, false)]
+    fn transient_errors(#[case] error: KubeApiError, #[case] transient: bool) {
+        assert_eq!(error.is_transient(), transient, "{error:?}");
+    }
+
+    #[test]
+    fn summarizes_attempts() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let mut calls = 0;
+        let error = runtime
+            .block_on(retry_transient_with(
+                [Duration::ZERO; 2],
+                "resolve the target",
+                || {
+                    calls += 1;
+                    async { Err::<(), _>(api_error(503)) }
+                },
+            ))
+            .unwrap_err();
+
+        assert_eq!(calls, 3);
+        assert!(matches!(
+            error,
+            KubeApiError::RetriesExhausted {
+                operation: "resolve the target",
+                attempts: 3,
+                ..
+            }
+        ));
+
+        // Not transient, not retried.
+        let mut calls = 0;
+        let error = runtime
+            .block_on(retry_transient_with(
+                [Duration::ZERO; 2],
+                "resolve the target",
+                || {
+                    calls += 1;
+                    async { Err::<(), _>(api_error(404)) }
+                },
+            ))
+            .unwrap_err();
+
+        assert_eq!(calls, 1);
+        assert!(matches!(error, KubeApiError::KubeError(..)));
+    }
+}
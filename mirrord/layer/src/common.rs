@@ -1,5 +1,11 @@
 //! Shared place for a few types and functions that are used everywhere by the layer.
-use std::{ffi::CStr, fmt::Debug, ops::Not, path::PathBuf};
+use std::{
+    ffi::{CStr, OsStr},
+    fmt::Debug,
+    ops::Not,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+};
 
 use libc::c_char;
 use mirrord_intproxy_protocol::{IsLayerRequest, IsLayerRequestWithResponse, MessageId};
@@ -98,26 +104,41 @@ pub fn strip_mirrord_path(path_str: &str) -> Option<&str> {
 }
 
 impl CheckedInto<PathBuf> for *const c_char {
-    /// Do the checked conversion to str, bypass if the str starts with temp dir's path, construct
-    /// a `PathBuf` out of the str.
+    /// Do the checked conversion to bytes, bypass if the path starts with temp dir's path,
+    /// construct a `PathBuf` out of the bytes.
+    ///
+    /// Paths that are not shorter than `PATH_MAX` fail with [`HookError::PathTooLong`], like they
+    /// would in the local filesystem. Paths that are not valid UTF-8 are kept as they are, it's up
+    /// to the internal proxy to reject them if the agent can't handle them.
     fn checked_into(self) -> Detour<PathBuf> {
-        let str_det = CheckedInto::<&str>::checked_into(self);
+        let bytes_det = (!self.is_null())
+            .then(|| unsafe { CStr::from_ptr(self) })
+            .map(CStr::to_bytes)
+            .map(|path_bytes| {
+                if path_bytes.len() >= libc::PATH_MAX as usize {
+                    Detour::Error(HookError::PathTooLong)
+                } else {
+                    Detour::Success(path_bytes)
+                }
+            })?;
         #[cfg(target_os = "macos")]
-        let str_det = str_det.and_then(|path_str| {
-            let optional_stripped_path = strip_mirrord_path(path_str);
+        let bytes_det = bytes_det.and_then(|path_bytes| {
+            let optional_stripped_path = std::str::from_utf8(path_bytes)
+                .ok()
+                .and_then(strip_mirrord_path);
             if let Some(stripped_path) = optional_stripped_path {
                 // actually stripped, so bypass and provide a pointer to after the temp dir.
                 // `stripped_path` is a reference to a later character in the same string as
-                // `path_str`, `stripped_path.as_ptr()` returns a pointer to a later index
+                // `path_bytes`, `stripped_path.as_ptr()` returns a pointer to a later index
                 // in the same string owned by the caller (the hooked program).
                 Detour::Bypass(Bypass::FileOperationInMirrordBinTempDir(
                     stripped_path.as_ptr() as _,
                 ))
             } else {
-                Detour::Success(path_str) // strip is None, path not in temp dir.
+                Detour::Success(path_bytes) // strip is None, path not in temp dir.
             }
         });
-        str_det.map(From::from)
+        bytes_det.map(|path_bytes| OsStr::from_bytes(path_bytes).into())
     }
 }
 
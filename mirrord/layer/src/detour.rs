@@ -126,6 +126,9 @@ pub(crate) enum Bypass {
     /// Either an invalid socket domain, or one that we don't handle.
     Domain(i32),
 
+    /// A socket protocol that we don't handle, e.g. `IPPROTO_ICMP` used for unprivileged ping.
+    Protocol(i32),
+
     /// Unix socket to address that was not configured to be connected remotely.
     UnixSocket(Option<String>),
 
@@ -168,6 +171,12 @@ pub(crate) enum Bypass {
     /// through the agent).
     ReadOnly(PathBuf),
 
+    /// Started mirrord with
+    /// [`FsModeConfig::CopyOnWrite`](mirrord_config::feature::fs::FsModeConfig::CopyOnWrite), and
+    /// the file has a copy in the local overlay, so the operation is done locally on the copy,
+    /// see [`Overlay`](crate::file::overlay::Overlay).
+    Overlay(CString),
+
     /// Called [`write`](crate::file::ops::write) with `write_bytes` set to [`None`].
     EmptyBuffer,
 
@@ -210,6 +219,13 @@ pub(crate) enum Bypass {
     /// DNS query should be done locally.
     LocalDns,
 
+    /// `getifaddrs` should list the local network interfaces.
+    LocalInterfaces,
+
+    /// Output of the local process should be written locally, see
+    /// [`OutputMode`](mirrord_config::feature::output::OutputMode).
+    LocalOutput,
+
     /// Operation is not implemented, but it should not be a hard error.
     ///
     /// Useful for operations that are version gated, and we want to bypass when the protocol
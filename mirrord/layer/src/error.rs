@@ -18,11 +18,16 @@ mod ignore_codes {
     /// Error codes from [`libc`] that are **not** hard errors, meaning the operation may progress.
     ///
     /// Prefer using [`is_ignored_code`] instead of relying on this constant.
-    const IGNORE_ERROR_CODES: [i32; 4] = [
+    const IGNORE_ERROR_CODES: [i32; 6] = [
         libc::EINPROGRESS,
         libc::EAFNOSUPPORT,
         libc::EADDRINUSE,
         libc::EPERM,
+        // Renames between local and remote paths, apps fall back to copying.
+        libc::EXDEV,
+        // `openat2` or `chown` with agents that don't support them, apps fall back (to `openat`)
+        // or report it.
+        libc::ENOSYS,
     ];
 
     /// Checks if an error code from some [`libc`] function should be treated as a hard error, or
@@ -98,6 +103,11 @@ pub(crate) enum HookError {
     #[error("mirrord-layer: Ignored file")]
     FileNotFound,
 
+    /// When the user's application tries to access a file in a way that a `block` rule of the
+    /// `feature.fs.rules` forbids.
+    #[error("mirrord-layer: Blocked file")]
+    FileBlocked,
+
     #[error("mirrord-layer: Proxy connection failed: `{0}`")]
     ProxyError(#[from] ProxyError),
 
@@ -113,6 +123,10 @@ pub(crate) enum HookError {
     #[error("mirrord-layer: Empty file path passed in argument")]
     EmptyPath,
 
+    /// The path is not shorter than `PATH_MAX`, we don't send it to the agent.
+    #[error("mirrord-layer: File path passed in argument is too long")]
+    PathTooLong,
+
     #[error("mirrord-layer: address passed to `bind` is not valid for the socket domain")]
     InvalidBindAddressForDomain,
 
@@ -205,6 +219,7 @@ impl From<HookError> for i64 {
     fn from(fail: HookError) -> Self {
         match fail {
             HookError::AddressAlreadyBound(_)
+            | HookError::PathTooLong
             | HookError::ResponseError(
                 ResponseError::NotFound(_)
                 | ResponseError::NotFile(_)
@@ -221,6 +236,9 @@ impl From<HookError> for i64 {
             HookError::FileNotFound => {
                 info!("mirrord file not found triggered")
             }
+            HookError::FileBlocked => {
+                info!("mirrord fs rule blocked a file operation")
+            }
             HookError::SocketUnsuportedIpv6 => {
                 info!("{fail}")
             }
@@ -279,6 +297,11 @@ impl From<HookError> for i64 {
                 ResponseError::PortAlreadyStolen(_port) => libc::EINVAL,
                 ResponseError::NotImplemented => libc::EINVAL,
                 ResponseError::StripPrefix(_) => libc::EINVAL,
+                // The internal proxy retries throttled file and DNS requests, and `sendfile` falls
+                // back to reading the file, so this should not happen.
+                ResponseError::Throttled { .. } => libc::EAGAIN,
+                ResponseError::ReadOnlySession(..) => libc::EPERM,
+                ResponseError::FdLimitReached { .. } => libc::EMFILE,
                 err @ ResponseError::Forbidden { .. } => {
                     graceful_exit!(
                         "Stopping mirrord run. Please adjust your mirrord configuration.\n{err}"
@@ -297,12 +320,14 @@ impl From<HookError> for i64 {
             HookError::BadPointer => libc::EFAULT,
             HookError::AddressAlreadyBound(_) => libc::EADDRINUSE,
             HookError::FileNotFound => libc::ENOENT,
+            HookError::FileBlocked => libc::EACCES,
             #[cfg(target_os = "linux")]
             HookError::BadDescriptor => libc::EBADF,
             #[cfg(target_os = "linux")]
             HookError::BadFlag => libc::EINVAL,
             #[cfg(target_os = "linux")]
             HookError::EmptyPath => libc::ENOENT,
+            HookError::PathTooLong => libc::ENAMETOOLONG,
             HookError::InvalidBindAddressForDomain => libc::EINVAL,
         };
 
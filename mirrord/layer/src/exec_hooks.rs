@@ -4,6 +4,7 @@ use std::{
 };
 
 pub(crate) mod hooks;
+pub(crate) mod spawn;
 
 /// Hold a vector of new CStrings to use instead of the original argv.
 #[derive(Default, Debug, Clone)]
@@ -1,17 +1,21 @@
+use std::ffi::c_void;
+
 use base64::prelude::*;
+#[cfg(not(target_os = "macos"))]
+use libc::pid_t;
 use libc::{c_char, c_int};
 #[cfg(not(target_os = "macos"))]
 use mirrord_layer_macro::hook_fn;
-#[cfg(target_os = "macos")]
 use mirrord_layer_macro::hook_guard_fn;
 
-use super::*;
+use super::{spawn::spawned_fds, *};
 #[cfg(not(target_os = "macos"))]
 use crate::common::CheckedInto;
 #[cfg(target_os = "macos")]
 use crate::exec_utils::*;
 use crate::{
     detour::{Bypass, Detour},
+    file::root_view::ROOT_ENV,
     hooks::HookManager,
     replace,
     socket::{UserSocket, SHARED_SOCKETS_ENV_VAR},
@@ -36,12 +40,31 @@ fn shared_sockets() -> Detour<Vec<(i32, UserSocket)>> {
 /// The check for [`libc::FD_CLOEXEC`] is performed during the [`SOCKETS`] initialization
 /// by the child process.
 pub(crate) fn prepare_execve_envp(env_vars: Detour<Argv>) -> Detour<Argv> {
+    insert_shared_sockets(env_vars, shared_sockets()?).and_then(insert_root)
+}
+
+/// Like [`prepare_execve_envp`], for a `posix_spawn` call. Only the [`SOCKETS`] that the child
+/// process gets after its `file_actions` and `attrp` are passed, at their fd in the child.
+pub(crate) unsafe fn prepare_spawn_envp(
+    env_vars: Detour<Argv>,
+    file_actions: *const c_void,
+    attrp: *const c_void,
+) -> Detour<Argv> {
+    insert_shared_sockets(
+        env_vars,
+        spawned_fds(shared_sockets()?, file_actions, attrp)?,
+    )
+    .and_then(insert_root)
+}
+
+/// Extends `env_vars` with an encoded version of `sockets`.
+fn insert_shared_sockets(env_vars: Detour<Argv>, sockets: Vec<(i32, UserSocket)>) -> Detour<Argv> {
     let mut env_vars = env_vars.or_bypass(|reason| match reason {
         Bypass::EmptyOption => Detour::Success(Argv(Vec::new())),
         other => Detour::Bypass(other),
     })?;
 
-    let encoded = bincode::encode_to_vec(shared_sockets()?, bincode::config::standard())
+    let encoded = bincode::encode_to_vec(sockets, bincode::config::standard())
         .map(|bytes| BASE64_URL_SAFE.encode(bytes))?;
 
     env_vars.insert_env(SHARED_SOCKETS_ENV_VAR, &encoded)?;
@@ -49,6 +72,16 @@ pub(crate) fn prepare_execve_envp(env_vars: Detour<Argv>) -> Detour<Argv> {
     Detour::Success(env_vars)
 }
 
+/// Extends `env_vars` with the [`ROOT_ENV`] of this process when it chrooted, so the new process
+/// keeps its view of the filesystem.
+fn insert_root(mut env_vars: Argv) -> Detour<Argv> {
+    if let Some(root) = crate::setup().root_view().root() {
+        env_vars.insert_env(ROOT_ENV, &root.to_string_lossy())?;
+    }
+
+    Detour::Success(env_vars)
+}
+
 #[cfg(not(target_os = "macos"))]
 unsafe fn environ() -> *const *const c_char {
     extern "C" {
@@ -90,6 +123,46 @@ pub(crate) unsafe extern "C" fn execve_detour(
     }
 }
 
+/// Hook for `libc::posix_spawn` for linux only, see [`prepare_spawn_envp`].
+///
+/// On macos this is handled in `exec_utils::posix_spawn_detour`, which also patches SIP binaries.
+#[cfg(not(target_os = "macos"))]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_detour(
+    pid: *const pid_t,
+    path: *const c_char,
+    file_actions: *const c_void,
+    attrp: *const c_void,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> c_int {
+    if let Detour::Success(new_envp) = prepare_spawn_envp(envp.checked_into(), file_actions, attrp)
+    {
+        FN_POSIX_SPAWN(pid, path, file_actions, attrp, argv, new_envp.leak())
+    } else {
+        FN_POSIX_SPAWN(pid, path, file_actions, attrp, argv, envp)
+    }
+}
+
+/// Hook for `libc::posix_spawnp` for linux only, same as [`posix_spawn_detour`].
+#[cfg(not(target_os = "macos"))]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawnp_detour(
+    pid: *const pid_t,
+    file: *const c_char,
+    file_actions: *const c_void,
+    attrp: *const c_void,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> c_int {
+    if let Detour::Success(new_envp) = prepare_spawn_envp(envp.checked_into(), file_actions, attrp)
+    {
+        FN_POSIX_SPAWNP(pid, file, file_actions, attrp, argv, new_envp.leak())
+    } else {
+        FN_POSIX_SPAWNP(pid, file, file_actions, attrp, argv, envp)
+    }
+}
+
 /// Hook for `libc::execve`.
 ///
 /// We can't change the pointers, to get around that we create our own and **leak** them.
@@ -139,4 +212,24 @@ pub(crate) unsafe fn enable_exec_hooks(hook_manager: &mut HookManager) {
     replace!(hook_manager, "execv", execv_detour, FnExecv, FN_EXECV);
 
     replace!(hook_manager, "execve", execve_detour, FnExecve, FN_EXECVE);
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        replace!(
+            hook_manager,
+            "posix_spawn",
+            posix_spawn_detour,
+            FnPosix_spawn,
+            FN_POSIX_SPAWN
+        );
+        replace!(
+            hook_manager,
+            "posix_spawnp",
+            posix_spawnp_detour,
+            FnPosix_spawnp,
+            FN_POSIX_SPAWNP
+        );
+    }
+
+    spawn::enable_file_actions_hooks(hook_manager);
 }
@@ -0,0 +1,326 @@
+//! Bookkeeping of the `posix_spawn` file actions, so that a spawned process knows which of our
+//! [`SOCKETS`](crate::SOCKETS) it inherits, and at which fds.
+//!
+//! macOS frameworks (and `std::process::Command`) set up the stdio of the child process with file
+//! actions, e.g. `dup2` a pipe to its stdout, and often pass `POSIX_SPAWN_CLOEXEC_DEFAULT` so that
+//! only those fds are inherited. The file actions are opaque, so we record them as the app adds
+//! them, and replay them on the sockets that we pass to the child in
+//! [`SHARED_SOCKETS_ENV_VAR`](crate::socket::SHARED_SOCKETS_ENV_VAR).
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    os::fd::RawFd,
+    sync::{LazyLock, Mutex},
+};
+
+use libc::{c_char, c_int, mode_t};
+use mirrord_layer_macro::hook_guard_fn;
+
+use crate::{detour::Detour, hooks::HookManager, replace};
+
+/// A file action added to a `posix_spawn_file_actions_t`, replayed in the child before it execs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileAction {
+    /// `posix_spawn_file_actions_addclose`.
+    Close(RawFd),
+    /// `posix_spawn_file_actions_adddup2`, `fd` is duplicated to `new_fd`.
+    Dup2 { fd: RawFd, new_fd: RawFd },
+    /// `posix_spawn_file_actions_addopen`, a file (never one of our sockets) is opened at the fd.
+    Open(RawFd),
+    /// `posix_spawn_file_actions_addinherit_np` (macOS), the fd is inherited even with
+    /// `POSIX_SPAWN_CLOEXEC_DEFAULT`.
+    Inherit(RawFd),
+}
+
+/// The [`FileAction`]s added to each `posix_spawn_file_actions_t`, by its address.
+static FILE_ACTIONS: LazyLock<Mutex<HashMap<usize, Vec<FileAction>>>> =
+    LazyLock::new(Default::default);
+
+/// Records `action` in the `file_actions`, if the original function succeeded.
+fn record(file_actions: *mut c_void, action: FileAction, result: c_int) -> c_int {
+    if result == 0 {
+        if let Ok(mut all_actions) = FILE_ACTIONS.lock() {
+            all_actions
+                .entry(file_actions as usize)
+                .or_default()
+                .push(action);
+        }
+    }
+
+    result
+}
+
+/// Applies the `file_actions` of a `posix_spawn` call to the parent's `fds`, returning the ones
+/// that the child process gets, at their fd in the child.
+///
+/// `close_on_exec` are the parent's fds with [`libc::FD_CLOEXEC`], which the child gets only when
+/// they're duplicated. With `cloexec_default` (`POSIX_SPAWN_CLOEXEC_DEFAULT`), the child gets only
+/// the fds that the file actions set up.
+pub(crate) fn child_fds<T: Clone>(
+    fds: Vec<(RawFd, T)>,
+    close_on_exec: &HashSet<RawFd>,
+    file_actions: &[FileAction],
+    cloexec_default: bool,
+) -> Vec<(RawFd, T)> {
+    let mut child = HashMap::<RawFd, T>::from_iter(fds);
+    // The fds that the file actions set up, kept open when the child execs.
+    let mut inherited = HashSet::new();
+
+    for action in file_actions {
+        match *action {
+            FileAction::Close(fd) => {
+                child.remove(&fd);
+                inherited.remove(&fd);
+            }
+            FileAction::Dup2 { fd, new_fd } => {
+                match child.get(&fd).cloned() {
+                    Some(value) => child.insert(new_fd, value),
+                    None => child.remove(&new_fd),
+                };
+                inherited.insert(new_fd);
+            }
+            FileAction::Open(fd) => {
+                child.remove(&fd);
+                inherited.insert(fd);
+            }
+            FileAction::Inherit(fd) => {
+                inherited.insert(fd);
+            }
+        }
+    }
+
+    child.retain(|fd, _| {
+        inherited.contains(fd) || (!cloexec_default && !close_on_exec.contains(fd))
+    });
+
+    let mut child = Vec::from_iter(child);
+    child.sort_unstable_by_key(|(fd, _)| *fd);
+    child
+}
+
+/// Applies the file actions and attributes of a `posix_spawn` call to the parent's `fds`, see
+/// [`child_fds`].
+pub(crate) unsafe fn spawned_fds<T: Clone>(
+    fds: Vec<(RawFd, T)>,
+    file_actions: *const c_void,
+    attrp: *const c_void,
+) -> Detour<Vec<(RawFd, T)>> {
+    let close_on_exec = fds
+        .iter()
+        .map(|(fd, _)| *fd)
+        .filter(|fd| {
+            let flags = libc::fcntl(*fd, libc::F_GETFD);
+            flags != -1 && flags & libc::FD_CLOEXEC != 0
+        })
+        .collect::<HashSet<_>>();
+
+    let file_actions = if file_actions.is_null() {
+        Vec::new()
+    } else {
+        FILE_ACTIONS
+            .lock()?
+            .get(&(file_actions as usize))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    Detour::Success(child_fds(
+        fds,
+        &close_on_exec,
+        &file_actions,
+        cloexec_default(attrp),
+    ))
+}
+
+/// Whether the `posix_spawnattr_t` at `attrp` has the `POSIX_SPAWN_CLOEXEC_DEFAULT` flag.
+#[cfg(target_os = "macos")]
+unsafe fn cloexec_default(attrp: *const c_void) -> bool {
+    let mut flags = 0;
+
+    !attrp.is_null()
+        && libc::posix_spawnattr_getflags(attrp.cast(), &mut flags) == 0
+        && c_int::from(flags) & libc::POSIX_SPAWN_CLOEXEC_DEFAULT != 0
+}
+
+/// `POSIX_SPAWN_CLOEXEC_DEFAULT` is macOS only.
+#[cfg(not(target_os = "macos"))]
+unsafe fn cloexec_default(_attrp: *const c_void) -> bool {
+    false
+}
+
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_file_actions_init_detour(
+    file_actions: *mut c_void,
+) -> c_int {
+    let result = FN_POSIX_SPAWN_FILE_ACTIONS_INIT(file_actions);
+    if result == 0 {
+        if let Ok(mut all_actions) = FILE_ACTIONS.lock() {
+            all_actions.remove(&(file_actions as usize));
+        }
+    }
+
+    result
+}
+
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_file_actions_destroy_detour(
+    file_actions: *mut c_void,
+) -> c_int {
+    if let Ok(mut all_actions) = FILE_ACTIONS.lock() {
+        all_actions.remove(&(file_actions as usize));
+    }
+
+    FN_POSIX_SPAWN_FILE_ACTIONS_DESTROY(file_actions)
+}
+
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_file_actions_addclose_detour(
+    file_actions: *mut c_void,
+    fd: c_int,
+) -> c_int {
+    let result = FN_POSIX_SPAWN_FILE_ACTIONS_ADDCLOSE(file_actions, fd);
+    record(file_actions, FileAction::Close(fd), result)
+}
+
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_file_actions_adddup2_detour(
+    file_actions: *mut c_void,
+    fd: c_int,
+    new_fd: c_int,
+) -> c_int {
+    let result = FN_POSIX_SPAWN_FILE_ACTIONS_ADDDUP2(file_actions, fd, new_fd);
+    record(file_actions, FileAction::Dup2 { fd, new_fd }, result)
+}
+
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_file_actions_addopen_detour(
+    file_actions: *mut c_void,
+    fd: c_int,
+    path: *const c_char,
+    oflag: c_int,
+    mode: mode_t,
+) -> c_int {
+    let result = FN_POSIX_SPAWN_FILE_ACTIONS_ADDOPEN(file_actions, fd, path, oflag, mode);
+    record(file_actions, FileAction::Open(fd), result)
+}
+
+#[cfg(target_os = "macos")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawn_file_actions_addinherit_np_detour(
+    file_actions: *mut c_void,
+    fd: c_int,
+) -> c_int {
+    let result = FN_POSIX_SPAWN_FILE_ACTIONS_ADDINHERIT_NP(file_actions, fd);
+    record(file_actions, FileAction::Inherit(fd), result)
+}
+
+/// Enables the hooks that record the `posix_spawn` file actions.
+pub(crate) unsafe fn enable_file_actions_hooks(hook_manager: &mut HookManager) {
+    replace!(
+        hook_manager,
+        "posix_spawn_file_actions_init",
+        posix_spawn_file_actions_init_detour,
+        FnPosix_spawn_file_actions_init,
+        FN_POSIX_SPAWN_FILE_ACTIONS_INIT
+    );
+    replace!(
+        hook_manager,
+        "posix_spawn_file_actions_destroy",
+        posix_spawn_file_actions_destroy_detour,
+        FnPosix_spawn_file_actions_destroy,
+        FN_POSIX_SPAWN_FILE_ACTIONS_DESTROY
+    );
+    replace!(
+        hook_manager,
+        "posix_spawn_file_actions_addclose",
+        posix_spawn_file_actions_addclose_detour,
+        FnPosix_spawn_file_actions_addclose,
+        FN_POSIX_SPAWN_FILE_ACTIONS_ADDCLOSE
+    );
+    replace!(
+        hook_manager,
+        "posix_spawn_file_actions_adddup2",
+        posix_spawn_file_actions_adddup2_detour,
+        FnPosix_spawn_file_actions_adddup2,
+        FN_POSIX_SPAWN_FILE_ACTIONS_ADDDUP2
+    );
+    replace!(
+        hook_manager,
+        "posix_spawn_file_actions_addopen",
+        posix_spawn_file_actions_addopen_detour,
+        FnPosix_spawn_file_actions_addopen,
+        FN_POSIX_SPAWN_FILE_ACTIONS_ADDOPEN
+    );
+
+    #[cfg(target_os = "macos")]
+    replace!(
+        hook_manager,
+        "posix_spawn_file_actions_addinherit_np",
+        posix_spawn_file_actions_addinherit_np_detour,
+        FnPosix_spawn_file_actions_addinherit_np,
+        FN_POSIX_SPAWN_FILE_ACTIONS_ADDINHERIT_NP
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Like `std::process::Command` with a piped stdout: the pipe's write end is duplicated to
+    /// stdout, and both ends are closed.
+    #[test]
+    fn dup2_to_stdio() {
+        let fds = vec![(5, "socket"), (7, "pipe")];
+        let actions = [
+            FileAction::Dup2 { fd: 5, new_fd: 1 },
+            FileAction::Close(5),
+            FileAction::Close(7),
+        ];
+
+        assert_eq!(
+            child_fds(fds, &HashSet::new(), &actions, false),
+            vec![(1, "socket")]
+        );
+    }
+
+    /// A file opened over an inherited socket's fd replaces it.
+    #[test]
+    fn open_over_socket() {
+        let fds = vec![(0, "socket"), (6, "other")];
+        let actions = [FileAction::Open(0)];
+
+        assert_eq!(
+            child_fds(fds, &HashSet::new(), &actions, false),
+            vec![(6, "other")]
+        );
+    }
+
+    /// Sockets with `FD_CLOEXEC` are inherited only when they're duplicated.
+    #[test]
+    fn close_on_exec() {
+        let fds = vec![(3, "cloexec"), (4, "inherited")];
+        let actions = [FileAction::Dup2 { fd: 3, new_fd: 9 }];
+
+        assert_eq!(
+            child_fds(fds, &HashSet::from([3]), &actions, false),
+            vec![(4, "inherited"), (9, "cloexec")]
+        );
+    }
+
+    /// Like `NSTask` and `libxpc`: with `POSIX_SPAWN_CLOEXEC_DEFAULT`, the child only gets the fds
+    /// set up by the file actions.
+    #[test]
+    fn cloexec_default() {
+        let fds = vec![(3, "stdout"), (4, "inherited"), (5, "leaked")];
+        let actions = [
+            FileAction::Open(0),
+            FileAction::Dup2 { fd: 3, new_fd: 1 },
+            FileAction::Inherit(4),
+        ];
+
+        assert_eq!(
+            child_fds(fds, &HashSet::new(), &actions, true),
+            vec![(1, "stdout"), (4, "inherited")]
+        );
+    }
+}
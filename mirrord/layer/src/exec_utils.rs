@@ -1,6 +1,7 @@
 use std::{
     env,
     ffi::{c_void, CStr, CString},
+    os::unix::{ffi::OsStringExt, fs::MetadataExt},
     path::PathBuf,
     sync::OnceLock,
 };
@@ -47,6 +48,13 @@ pub(crate) unsafe fn enable_macos_hooks(
         FnPosix_spawn,
         FN_POSIX_SPAWN
     );
+    replace!(
+        hook_manager,
+        "posix_spawnp",
+        posix_spawnp_detour,
+        FnPosix_spawnp,
+        FN_POSIX_SPAWNP
+    );
     replace!(
         hook_manager,
         "_NSGetExecutablePath",
@@ -193,8 +201,9 @@ pub(crate) unsafe fn patch_sip_for_new_process(
 }
 
 /// Hook for `libc::posix_spawn`.
-/// Same as `execve_detour`, with all the extra arguments present here being passed untouched.
-// TODO: do we also need to hook posix_spawnp?
+/// Same as `execve_detour`, with the extra arguments present here being passed untouched. The
+/// sockets passed to the new process are the ones it gets after the `file_actions` and `attrp`,
+/// see [`hooks::prepare_spawn_envp`].
 #[hook_guard_fn]
 pub(crate) unsafe extern "C" fn posix_spawn_detour(
     pid: *const pid_t,
@@ -203,29 +212,86 @@ pub(crate) unsafe extern "C" fn posix_spawn_detour(
     attrp: *const c_void,
     argv: *const *const c_char,
     envp: *const *const c_char,
+) -> c_int {
+    spawn_patched(FN_POSIX_SPAWN, pid, path, file_actions, attrp, argv, envp)
+}
+
+/// Hook for `libc::posix_spawnp`.
+/// Same as [`posix_spawn_detour`], `file` is searched in `PATH` first, so that we can check it for
+/// SIP. The patched binary is spawned by its path, skipping the search in the original function.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_spawnp_detour(
+    pid: *const pid_t,
+    file: *const c_char,
+    file_actions: *const c_void,
+    attrp: *const c_void,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> c_int {
+    match search_path(file) {
+        Detour::Success(path) => spawn_patched(
+            FN_POSIX_SPAWNP,
+            pid,
+            path.as_ptr(),
+            file_actions,
+            attrp,
+            argv,
+            envp,
+        ),
+        _ => spawn_patched(FN_POSIX_SPAWNP, pid, file, file_actions, attrp, argv, envp),
+    }
+}
+
+/// Calls `spawn` (the original `posix_spawn` or `posix_spawnp`) with the new process patched for
+/// SIP, and with the mirrord environment, see [`patch_sip_for_new_process`].
+unsafe fn spawn_patched(
+    spawn: FnPosix_spawn,
+    pid: *const pid_t,
+    path: *const c_char,
+    file_actions: *const c_void,
+    attrp: *const c_void,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
 ) -> c_int {
     match patch_sip_for_new_process(path, argv, envp) {
         Detour::Success((path, argv, envp)) => {
-            match hooks::prepare_execve_envp(Detour::Success(envp.clone())) {
-                Detour::Success(envp) => FN_POSIX_SPAWN(
-                    pid,
-                    path.into_raw().cast_const(),
-                    file_actions,
-                    attrp,
-                    argv.leak(),
-                    envp.leak(),
-                ),
-                _ => FN_POSIX_SPAWN(
-                    pid,
-                    path.into_raw().cast_const(),
-                    file_actions,
-                    attrp,
-                    argv.leak(),
-                    envp.leak(),
-                ),
-            }
+            let envp =
+                hooks::prepare_spawn_envp(Detour::Success(envp.clone()), file_actions, attrp)
+                    .unwrap_or(envp);
+
+            spawn(
+                pid,
+                path.into_raw().cast_const(),
+                file_actions,
+                attrp,
+                argv.leak(),
+                envp.leak(),
+            )
         }
-        _ => FN_POSIX_SPAWN(pid, path, file_actions, attrp, argv, envp),
+        _ => spawn(pid, path, file_actions, attrp, argv, envp),
+    }
+}
+
+/// Finds the executable `file` like `posix_spawnp` does: names that contain a `/` are paths, the
+/// others are searched in the directories of `PATH`.
+fn search_path(file: *const c_char) -> Detour<CString> {
+    let file: &str = file.checked_into()?;
+    if file.contains('/') {
+        return Success(CString::new(file)?);
+    }
+
+    let path_var = env::var_os("PATH").unwrap_or_else(|| "/usr/bin:/bin:/usr/sbin:/sbin".into());
+    let executable = env::split_paths(&path_var)
+        .map(|dir| dir.join(file))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .is_ok_and(|metadata| metadata.is_file() && metadata.mode() & 0o111 != 0)
+        });
+
+    match executable {
+        Some(executable) => Success(CString::new(executable.into_os_string().into_vec())?),
+        None => Bypass(ExecOnNonExistingFile(file.to_string())),
     }
 }
 
@@ -27,6 +27,10 @@ pub(crate) mod hooks;
 pub(crate) mod mapper;
 pub(crate) mod open_dirs;
 pub(crate) mod ops;
+pub(crate) mod overlay;
+pub(crate) mod root_view;
+#[cfg(target_os = "linux")]
+pub(crate) mod watch;
 
 type RemoteFd = u64;
 type LocalFd = RawFd;
@@ -9,7 +9,7 @@
 use std::env;
 
 use mirrord_config::{
-    feature::fs::{FsConfig, FsModeConfig},
+    feature::fs::{FsConfig, FsModeConfig, FsRuleAction, FsRules},
     util::VecOrSingle,
 };
 use regex::{RegexSet, RegexSetBuilder};
@@ -47,6 +47,15 @@ fn generate_remote_ro_set() -> RegexSet {
         .expect("Building remote readonly path regex set failed")
 }
 
+/// List of files that Kubernetes mounts in the pod, see [`FsConfig::pod_files`].
+fn generate_pod_set() -> RegexSet {
+    let patterns = read_remote_by_default::POD_PATHS;
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(true)
+        .build()
+        .expect("Building pod path regex set failed")
+}
+
 fn generate_not_found_set() -> RegexSet {
     let Ok(home) = env::var("HOME") else {
         tracing::warn!("Unable to resolve $HOME directory, generating empty not-found set");
@@ -67,13 +76,19 @@ fn generate_not_found_set() -> RegexSet {
 
 #[derive(Debug)]
 pub struct FileFilter {
+    /// Checked before all the patterns, see [`FsConfig::rules`].
+    rules: FsRules,
     read_only: RegexSet,
     read_write: RegexSet,
     local: RegexSet,
     not_found: RegexSet,
+    scratch: RegexSet,
     default_local: RegexSet,
     default_remote_ro: RegexSet,
     default_not_found: RegexSet,
+    default_pod: RegexSet,
+    /// Whether [`Self::default_pod`] paths are read remotely, otherwise they're always local.
+    pod_files: bool,
     mode: FsModeConfig,
 }
 
@@ -98,9 +113,15 @@ impl FileFilter {
             local,
             mode,
             not_found,
+            scratch,
+            rules,
+            pod_files,
             ..
         } = fs_config;
 
+        let rules =
+            FsRules::new(rules.as_deref().unwrap_or_default()).expect("building fs rules failed");
+
         let read_write =
             Self::make_regex_set(read_write).expect("building read-write regex set failed");
         let read_only =
@@ -108,19 +129,25 @@ impl FileFilter {
         let local = Self::make_regex_set(local).expect("building local path regex set failed");
         let not_found =
             Self::make_regex_set(not_found).expect("building not-found regex set failed");
+        let scratch = Self::make_regex_set(scratch).expect("building scratch regex set failed");
 
         let default_local = generate_local_set();
         let default_remote_ro = generate_remote_ro_set();
         let default_not_found = generate_not_found_set();
+        let default_pod = generate_pod_set();
 
         Self {
+            rules,
             read_only,
             read_write,
             local,
             not_found,
+            scratch,
             default_local,
             default_remote_ro,
             default_not_found,
+            default_pod,
+            pod_files,
             mode,
         }
     }
@@ -133,10 +160,19 @@ impl FileFilter {
     where
         F: FnOnce() -> Bypass,
     {
+        let rule = self.rules.find(text, write).map(|(.., action)| action);
+
         match self.mode {
             FsModeConfig::Local => Detour::Bypass(op()),
+            _ if rule == Some(FsRuleAction::Remote) => Detour::Success(()),
+            _ if rule == Some(FsRuleAction::Local) => Detour::Bypass(op()),
+            _ if rule == Some(FsRuleAction::Block) => Detour::Error(HookError::FileBlocked),
             _ if self.not_found.is_match(text) => Detour::Error(HookError::FileNotFound),
-            _ if self.read_write.is_match(text) => Detour::Success(()),
+            // Scratch paths are redirected by the agent, so they're always read and written
+            // remotely.
+            _ if self.read_write.is_match(text) || self.scratch.is_match(text) => {
+                Detour::Success(())
+            }
             _ if self.read_only.is_match(text) => {
                 if write {
                     Detour::Bypass(op())
@@ -146,14 +182,40 @@ impl FileFilter {
             }
             _ if self.local.is_match(text) => Detour::Bypass(op()),
             _ if self.default_not_found.is_match(text) => Detour::Error(HookError::FileNotFound),
+            _ if self.default_pod.is_match(text) => {
+                if self.pod_files && !write {
+                    Detour::Success(())
+                } else {
+                    Detour::Bypass(op())
+                }
+            }
             _ if self.default_remote_ro.is_match(text) && !write => Detour::Success(()),
             _ if self.default_local.is_match(text) => Detour::Bypass(op()),
             FsModeConfig::LocalWithOverrides => Detour::Bypass(op()),
             FsModeConfig::Write => Detour::Success(()),
-            FsModeConfig::Read if write => Detour::Bypass(Bypass::ReadOnly(text.into())),
-            FsModeConfig::Read => Detour::Success(()),
+            FsModeConfig::Read | FsModeConfig::CopyOnWrite if write => {
+                Detour::Bypass(Bypass::ReadOnly(text.into()))
+            }
+            FsModeConfig::Read | FsModeConfig::CopyOnWrite => Detour::Success(()),
         }
     }
+
+    /// Whether writes to `text` go to the remote even in the [`FsModeConfig::CopyOnWrite`] mode,
+    /// as the user asked for it with the `read_write` or `scratch` patterns, or a rule.
+    pub fn writes_remotely(&self, text: &str) -> bool {
+        match self.rules.find(text, true) {
+            Some((.., action)) => action == FsRuleAction::Remote,
+            None => self.read_write.is_match(text) || self.scratch.is_match(text),
+        }
+    }
+
+    /// Whether a rule blocks the writes to `text`, checked in the [`FsModeConfig::CopyOnWrite`]
+    /// mode, where the writes are not checked with [`Self::continue_or_bypass_with`].
+    pub fn blocks_writes(&self, text: &str) -> bool {
+        self.rules
+            .find(text, true)
+            .is_some_and(|(.., action)| action == FsRuleAction::Block)
+    }
 }
 
 impl Default for FileFilter {
@@ -164,7 +226,10 @@ impl Default for FileFilter {
 
 #[cfg(test)]
 mod tests {
-    use mirrord_config::{feature::fs::FsConfig, util::VecOrSingle};
+    use mirrord_config::{
+        feature::fs::{FsConfig, FsRule},
+        util::VecOrSingle,
+    };
     use rstest::*;
 
     use super::*;
@@ -380,6 +445,28 @@ mod tests {
         false,
         DetourKind::Error
     )]
+    #[case(FsModeConfig::Read, "/pain/scratch/test.a", true, DetourKind::Success)]
+    #[case(
+        FsModeConfig::LocalWithOverrides,
+        "/pain/scratch/test.a",
+        true,
+        DetourKind::Success
+    )]
+    #[case(FsModeConfig::Local, "/pain/scratch/test.a", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::CopyOnWrite, "/a/test.a", false, DetourKind::Success)]
+    #[case(FsModeConfig::CopyOnWrite, "/a/test.a", true, DetourKind::Bypass)]
+    #[case(
+        FsModeConfig::CopyOnWrite,
+        "/pain/read_write/test.a",
+        true,
+        DetourKind::Success
+    )]
+    #[case(
+        FsModeConfig::CopyOnWrite,
+        "/pain/local/test.a",
+        false,
+        DetourKind::Bypass
+    )]
     fn include_complex_configuration(
         #[case] mode: FsModeConfig,
         #[case] path: &str,
@@ -394,12 +481,20 @@ mod tests {
         ]));
         let local = Some(VecOrSingle::Multiple(vec![r"/pain/local.*\.a".to_string()]));
         let not_found = Some(VecOrSingle::Single(r"/pain/not_found.*\.a".to_string()));
+        let scratch = Some(VecOrSingle::Single(r"/pain/scratch.*\.a".to_string()));
         let fs_config = FsConfig {
             read_write,
             read_only,
             local,
             not_found,
+            scratch,
+            rules: None,
             mode,
+            overlay: None,
+            pod_files: true,
+            compression_threshold: None,
+            metadata_cache_ttl_ms: None,
+            prefetch: None,
             mapping: None,
         };
 
@@ -446,6 +541,129 @@ mod tests {
         assert_eq!(res.kind(), expected);
     }
 
+    /// The files that Kubernetes mounts in the pod are read remotely, unless disabled, and never
+    /// written remotely.
+    #[rstest]
+    #[case(
+        FsModeConfig::LocalWithOverrides,
+        "/var/run/secrets/kubernetes.io/serviceaccount/token",
+        true,
+        DetourKind::Success
+    )]
+    #[case(
+        FsModeConfig::Read,
+        "/run/secrets/kubernetes.io/serviceaccount/..data/ca.crt",
+        true,
+        DetourKind::Success
+    )]
+    #[case(
+        FsModeConfig::LocalWithOverrides,
+        "/var/run/secrets/eks.amazonaws.com/serviceaccount/token",
+        true,
+        DetourKind::Success
+    )]
+    #[case(FsModeConfig::Read, "/etc/podinfo/labels", true, DetourKind::Success)]
+    #[case(FsModeConfig::Read, "/etc/podinfo/labels", false, DetourKind::Bypass)]
+    #[case(
+        FsModeConfig::LocalWithOverrides,
+        "/var/run/secrets/kubernetes.io/serviceaccount/token",
+        false,
+        DetourKind::Bypass
+    )]
+    #[case(
+        FsModeConfig::Read,
+        "/var/run/secrets/kubernetes.io/serviceaccount/token",
+        false,
+        DetourKind::Bypass
+    )]
+    #[case(
+        FsModeConfig::Write,
+        "/var/run/secrets/azure/tokens/azure-identity-token",
+        false,
+        DetourKind::Bypass
+    )]
+    #[case(FsModeConfig::Local, "/etc/podinfo/labels", true, DetourKind::Bypass)]
+    fn pod_files_read_remotely(
+        #[case] mode: FsModeConfig,
+        #[case] path: &str,
+        #[case] pod_files: bool,
+        #[case] expected: DetourKind,
+    ) {
+        let filter = FileFilter::new(FsConfig {
+            mode,
+            pod_files,
+            ..Default::default()
+        });
+
+        let res = filter.continue_or_bypass_with(path, false, || Bypass::ignored_file(""));
+        println!("filter result: {res:?}");
+        assert_eq!(res.kind(), expected);
+
+        let res = filter.continue_or_bypass_with(path, true, || Bypass::ignored_file(""));
+        assert_eq!(res.kind(), DetourKind::Bypass);
+    }
+
+    /// The rules take precedence over the patterns and the default sets, separately for reads and
+    /// writes, except in the local mode.
+    #[rstest]
+    #[case(FsModeConfig::Read, "/app/config/db.yaml", false, DetourKind::Success)]
+    #[case(FsModeConfig::Read, "/app/config/db.yaml", true, DetourKind::Error)]
+    #[case(FsModeConfig::Write, "/app/config/db.yaml", true, DetourKind::Error)]
+    #[case(FsModeConfig::Read, "/tmp/cache", false, DetourKind::Bypass)]
+    #[case(FsModeConfig::Write, "/tmp/cache", true, DetourKind::Bypass)]
+    #[case(
+        FsModeConfig::Read,
+        "/etc/app/settings.toml",
+        false,
+        DetourKind::Success
+    )]
+    #[case(
+        FsModeConfig::Read,
+        "/etc/app/settings.toml",
+        true,
+        DetourKind::Success
+    )]
+    #[case(FsModeConfig::Read, "/etc/hostname", false, DetourKind::Success)]
+    #[case(FsModeConfig::Read, "/etc/hostname", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::Read, "/app/main.rs", false, DetourKind::Success)]
+    #[case(FsModeConfig::Read, "/app/main.rs", true, DetourKind::Bypass)]
+    #[case(FsModeConfig::Local, "/app/config/db.yaml", true, DetourKind::Bypass)]
+    fn rules(
+        #[case] mode: FsModeConfig,
+        #[case] path: &str,
+        #[case] write: bool,
+        #[case] expected: DetourKind,
+    ) {
+        let rule = |path: &str, read, write| FsRule {
+            path: path.into(),
+            read,
+            write,
+        };
+        let filter = FileFilter::new(FsConfig {
+            mode,
+            local: Some(VecOrSingle::Single("^/etc/hostname$".into())),
+            rules: Some(vec![
+                rule(
+                    "/app/config/**",
+                    Some(FsRuleAction::Remote),
+                    Some(FsRuleAction::Block),
+                ),
+                rule(
+                    "/tmp/**",
+                    Some(FsRuleAction::Local),
+                    Some(FsRuleAction::Local),
+                ),
+                rule("/etc/app/*", None, Some(FsRuleAction::Remote)),
+                rule("/etc/**", Some(FsRuleAction::Remote), None),
+            ]),
+            ..Default::default()
+        });
+
+        let res = filter.continue_or_bypass_with(path, write, || Bypass::ignored_file(""));
+        println!("filter result: {res:?}");
+        assert_eq!(res.kind(), expected);
+    }
+
     /// Sanity test for empty [`RegexSet`] behaviour.
     #[test]
     fn empty_regex_set() {
@@ -469,4 +687,30 @@ mod tests {
 
         assert_eq!(res.kind(), expected);
     }
+
+    /// Files that the .NET, Deno and Bun runtimes load before and while starting the app.
+    #[rstest]
+    #[case("/app/publish/WebApp.deps.json")]
+    #[case("/app/publish/WebApp.runtimeconfig.json")]
+    #[case("/app/bin/Debug/net8.0/WebApp.runtimeconfig.dev.json")]
+    #[case("/root/.dotnet/shared/Microsoft.NETCore.App/8.0.0/libcoreclr.dylib")]
+    #[case("/root/.net/WebApp/Ul3YKHbC9DjwBSDh/libSystem.Native.dylib")]
+    #[case("/app/src/main.ts")]
+    #[case("/app/src/component.tsx")]
+    #[case("/app/deno.jsonc")]
+    #[case("/deno.json")]
+    #[case("/root/.cache/deno/remote/https/deno.land/3a8f7a5c")]
+    #[case("/app/bunfig.toml")]
+    #[case("/app/bun.lockb")]
+    #[case("/root/.bun/install/cache/hono@4.6.3/package.json")]
+    fn runtime_files_read_local(#[case] path: &str) {
+        let filter = FileFilter::new(FsConfig {
+            mode: FsModeConfig::Read,
+            ..Default::default()
+        });
+        let res = filter.continue_or_bypass_with(path, false, || Bypass::ignored_file(""));
+        println!("filter result: {res:?}");
+
+        assert_eq!(res.kind(), DetourKind::Bypass);
+    }
 }
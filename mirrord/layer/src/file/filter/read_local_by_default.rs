@@ -15,6 +15,10 @@ pub fn regex_set_builder() -> RegexSetBuilder {
         r"^.+\.jar$",
         r"^.+\.class$",
         r"^.+\.js$",
+        r"^.+\.[cm]js$",
+        // deno and bun: TypeScript and JSX sources.
+        r"^.+\.[cm]?ts$",
+        r"^.+\.[jt]sx$",
         r"^.+\.pth$",
         r"^.+\.plist$",
         r"^.*venv\.cfg$",
@@ -42,6 +46,14 @@ pub fn regex_set_builder() -> RegexSetBuilder {
         r"^.*\.pdb$",
         // dotnet: `/home/{username}/{project}.dll`
         r"^.*\.dll$",
+        // dotnet: `{project}.deps.json`, `{project}.runtimeconfig.json` and
+        // `{project}.runtimeconfig.dev.json`, read by the host before the app starts.
+        r"^.+\.deps\.json$",
+        r"^.+\.runtimeconfig(\.dev)?\.json$",
+        // dotnet: sdk and runtimes installed with `dotnet-install.sh`, `$HOME/.dotnet/`.
+        r"/\.dotnet(/|$)",
+        // dotnet: native libraries extracted from single-file apps, `$HOME/.net/{app}/{hash}/`.
+        r"/\.net(/|$)",
         // jvm.cfg or ANYTHING/jvm.cfg
         r".*(^|/)jvm\.cfg$",
         // TODO: `node` searches for this file in multiple directories, bypassing some of our
@@ -53,6 +65,14 @@ pub fn regex_set_builder() -> RegexSetBuilder {
         r"/node_modules",
         // asdf
         r".*/\.tool-versions$",
+        // deno: configuration, lock file, modules cache (`$HOME/.cache/deno`) and installation.
+        r"(^|/)deno\.(json|jsonc|lock)$",
+        r"/\.cache/deno(/|$)",
+        r"/\.deno(/|$)",
+        // bun: configuration, lock files, installation and packages cache (`$HOME/.bun`).
+        r"(^|/)bunfig\.toml$",
+        r"(^|/)bun\.lockb?$",
+        r"/\.bun(/|$)",
         // macOS
         #[cfg(target_os = "macos")]
         "^/Volumes(/|$)",
@@ -87,5 +107,22 @@ pub fn regex_set_builder() -> RegexSetBuilder {
         patterns.push(format!("^.*{}.*$", executable.to_string_lossy()));
     }
 
+    // Runtime installations and caches that can be moved with env vars.
+    for var in [
+        // dotnet: custom runtime location and single-file apps extraction directory.
+        "DOTNET_ROOT",
+        "DOTNET_BUNDLE_EXTRACT_BASE_DIR",
+        // deno: modules cache and installation.
+        "DENO_DIR",
+        "DENO_INSTALL",
+        // bun: installation and packages cache.
+        "BUN_INSTALL",
+        "BUN_INSTALL_CACHE_DIR",
+    ] {
+        if let Some(dir) = env::var_os(var).filter(|dir| !dir.is_empty()) {
+            patterns.push(format!("^{}", regex::escape(&dir.to_string_lossy())));
+        }
+    }
+
     RegexSetBuilder::new(patterns)
 }
@@ -6,3 +6,15 @@ pub const PATHS: [&str; 3] = [
     r"^/etc/hosts$",
     r"^/etc/hostname$",
 ];
+
+/// Files that Kubernetes mounts in the pod, read remotely in all modes unless
+/// `fs.feature.pod_files` is disabled, then they're read locally.
+pub const POD_PATHS: [&str; 4] = [
+    // service account token, CA certificate and namespace
+    r"^(/var)?/run/secrets/kubernetes\.io/serviceaccount(/|$)",
+    // projected tokens of the EKS and AKS workload identities
+    r"^(/var)?/run/secrets/eks\.amazonaws\.com/serviceaccount(/|$)",
+    r"^(/var)?/run/secrets/azure/tokens(/|$)",
+    // downward API volume, as mounted in the Kubernetes documentation
+    r"^/etc/podinfo(/|$)",
+];
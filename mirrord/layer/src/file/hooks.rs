@@ -6,7 +6,7 @@ use core::ffi::{c_size_t, c_ssize_t};
 /// NOTICE: If a file operation fails, it might be because it depends on some `libc` function
 /// that is not being hooked (`strace` the program to check).
 use std::{
-    ffi::CString,
+    ffi::{CStr, CString, OsStr},
     os::unix::{ffi::OsStrExt, io::RawFd},
     ptr, slice,
     time::Duration,
@@ -14,22 +14,30 @@ use std::{
 
 use errno::{set_errno, Errno};
 use libc::{
-    self, c_char, c_int, c_void, dirent, iovec, off_t, size_t, ssize_t, stat, statfs, AT_EACCESS,
-    AT_FDCWD, DIR, EINVAL, O_DIRECTORY, O_RDONLY,
+    self, c_char, c_int, c_void, dirent, gid_t, iovec, mode_t, off_t, size_t, ssize_t, stat,
+    statfs, statvfs, timespec, uid_t, AT_EACCESS, AT_FDCWD, DIR, EINVAL, O_DIRECTORY, O_RDONLY,
 };
 #[cfg(target_os = "linux")]
-use libc::{dirent64, stat64, statx, EBADF, ENOENT, ENOTDIR};
+use libc::{
+    c_uint, dirent64, glob_t, open_how, stat64, statx, EBADF, ENOENT, ENOTDIR, GLOB_ABORTED,
+    GLOB_NOCHECK, GLOB_NOMATCH, GLOB_NOSORT, GLOB_NOSPACE,
+};
 use mirrord_layer_macro::{hook_fn, hook_guard_fn};
 use mirrord_protocol::file::{
     FsMetadataInternal, MetadataInternal, ReadFileResponse, ReadLinkFileResponse, WriteFileResponse,
 };
 #[cfg(target_os = "linux")]
-use mirrord_protocol::ResponseError::{NotDirectory, NotFound};
+use mirrord_protocol::{
+    file::GlobResponse,
+    ResponseError::{NotDirectory, NotFound},
+};
 use num_traits::Bounded;
 use tracing::trace;
 #[cfg(target_os = "linux")]
 use tracing::{error, info, warn};
 
+#[cfg(target_os = "linux")]
+use super::watch;
 use super::{open_dirs, ops::*, OpenOptionsInternalExt};
 #[cfg(target_os = "linux")]
 use crate::error::HookError::ResponseError;
@@ -53,7 +61,7 @@ type stat64 = stat;
 /// Take the original raw c_char pointer and a resulting bypass, and either the original pointer or
 /// a different one according to the bypass.
 /// We pass reference to bypass to make sure the bypass lives with the pointer.
-fn update_ptr_from_bypass(ptr: *const c_char, bypass: &Bypass) -> *const c_char {
+pub(crate) fn update_ptr_from_bypass(ptr: *const c_char, bypass: &Bypass) -> *const c_char {
     match bypass {
         // For some reason, the program is trying to carry out an operation on a path that is
         // inside mirrord's temp bin dir. The detour has returned us the original path of the file
@@ -61,7 +69,9 @@ fn update_ptr_from_bypass(ptr: *const c_char, bypass: &Bypass) -> *const c_char
         // path.
         #[cfg(target_os = "macos")]
         Bypass::FileOperationInMirrordBinTempDir(stripped_ptr) => *stripped_ptr,
-        Bypass::RelativePath(path) | Bypass::IgnoredFile(path) => path.as_ptr(),
+        Bypass::RelativePath(path) | Bypass::IgnoredFile(path) | Bypass::Overlay(path) => {
+            path.as_ptr()
+        }
         _ => ptr,
     }
 }
@@ -344,6 +354,41 @@ pub(crate) unsafe extern "C" fn openat64_detour(
     })
 }
 
+/// Separated out logic for `openat2`, so that it can be used by go, which makes the syscall
+/// directly.
+#[cfg(target_os = "linux")]
+pub(crate) unsafe fn openat2_logic(
+    fd: RawFd,
+    raw_path: *const c_char,
+    how: *const open_how,
+    size: size_t,
+) -> Detour<RawFd> {
+    // A bigger `open_how` (from newer kernel headers) may have fields that we don't know.
+    let how = how
+        .as_ref()
+        .filter(|_| size == std::mem::size_of::<open_how>())
+        .ok_or(Bypass::EmptyOption)?;
+    let open_options = OpenOptionsInternalExt::from_flags(how.flags as c_int);
+
+    openat2(fd, raw_path.checked_into(), open_options, how.resolve)
+}
+
+/// Hook for `openat2`, only on Linux, where few libcs have a wrapper for it (see the go hooks for
+/// the syscall).
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn openat2_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    how: *const open_how,
+    size: size_t,
+) -> RawFd {
+    openat2_logic(fd, raw_path, how, size).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_OPENAT2(fd, raw_path, how, size)
+    })
+}
+
 #[hook_guard_fn]
 pub(crate) unsafe extern "C" fn _openat_nocancel_detour(
     fd: RawFd,
@@ -358,6 +403,29 @@ pub(crate) unsafe extern "C" fn _openat_nocancel_detour(
     })
 }
 
+/// Hook for `libc::openat$NOCANCEL`, which Bun calls directly on macOS.
+#[hook_fn]
+pub(crate) unsafe extern "C" fn openat_nocancel_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    open_flags: c_int,
+    mut args: ...
+) -> RawFd {
+    let mode: c_int = args.arg();
+
+    let guard = DetourGuard::new();
+    if guard.is_none() {
+        FN_OPENAT_NOCANCEL(fd, raw_path, open_flags, mode)
+    } else {
+        let open_options = OpenOptionsInternalExt::from_flags(open_flags);
+
+        openat(fd, raw_path.checked_into(), open_options).unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_OPENAT_NOCANCEL(fd, raw_path, open_flags, mode)
+        })
+    }
+}
+
 /// Hook for getdents64, for Go's `os.ReadDir` on Linux.
 #[cfg(target_os = "linux")]
 #[hook_guard_fn]
@@ -433,29 +501,33 @@ pub(crate) unsafe extern "C" fn getdents64_detour(
 /// Hook for `libc::read`.
 ///
 /// Reads `count` bytes into `out_buffer`, only for `fd`s that are being managed by mirrord-layer.
+/// The `inotify` descriptors of mirrord-layer (see [`watch`]) get their events here too.
 #[hook_guard_fn]
 pub(crate) unsafe extern "C" fn read_detour(
     fd: RawFd,
     out_buffer: *mut c_void,
     count: size_t,
 ) -> ssize_t {
-    read(fd, count as u64)
-        .map(|read_file| {
-            let ReadFileResponse { bytes, read_amount } = read_file;
+    let result = read(fd, count as u64).map(|read_file| {
+        let ReadFileResponse { bytes, read_amount } = read_file;
 
-            // There is no distinction between reading 0 bytes or if we hit EOF, but we only copy to
-            // buffer if we have something to copy.
-            if read_amount > 0 {
-                let read_ptr = bytes.as_ptr();
-                let out_buffer = out_buffer.cast();
-                ptr::copy(read_ptr, out_buffer, read_amount as usize);
-            }
+        // There is no distinction between reading 0 bytes or if we hit EOF, but we only copy to
+        // buffer if we have something to copy.
+        if read_amount > 0 {
+            let read_ptr = bytes.as_ptr();
+            let out_buffer = out_buffer.cast();
+            ptr::copy(read_ptr, out_buffer, read_amount as usize);
+        }
 
-            // WARN: Must be careful when it comes to `EOF`, incorrect handling may appear as the
-            // `read` call being repeated.
-            ssize_t::try_from(read_amount).unwrap()
-        })
-        .unwrap_or_bypass_with(|_| FN_READ(fd, out_buffer, count))
+        // WARN: Must be careful when it comes to `EOF`, incorrect handling may appear as the
+        // `read` call being repeated.
+        ssize_t::try_from(read_amount).unwrap()
+    });
+
+    #[cfg(target_os = "linux")]
+    let result = result.or_bypass(|_| watch::read(fd, out_buffer, count));
+
+    result.unwrap_or_bypass_with(|_| FN_READ(fd, out_buffer, count))
 }
 
 #[hook_guard_fn]
@@ -564,6 +636,35 @@ pub(crate) unsafe extern "C" fn _pread_nocancel_detour(
         .unwrap_or_bypass_with(|_| FN__PREAD_NOCANCEL(fd, out_buffer, amount_to_read, offset))
 }
 
+/// Hook for `libc::pread$NOCANCEL`, which Bun calls directly on macOS.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn pread_nocancel_detour(
+    fd: RawFd,
+    out_buffer: *mut c_void,
+    amount_to_read: size_t,
+    offset: off_t,
+) -> ssize_t {
+    pread(fd, amount_to_read as u64, offset as u64)
+        .map(|read_file| {
+            let ReadFileResponse { bytes, read_amount } = read_file;
+            let fixed_read = (amount_to_read as u64).min(read_amount);
+
+            // There is no distinction between reading 0 bytes or if we hit EOF, but we only
+            // copy to buffer if we have something to copy.
+            //
+            // Callers can check for EOF by using `ferror`.
+            if read_amount > 0 {
+                let bytes_slice = bytes
+                    .get(..fixed_read as usize)
+                    .expect("read_amount exceeds bytes length in ReadFileResponse");
+
+                ptr::copy(bytes_slice.as_ptr().cast(), out_buffer, bytes_slice.len());
+            }
+            fixed_read as ssize_t
+        })
+        .unwrap_or_bypass_with(|_| FN_PREAD_NOCANCEL(fd, out_buffer, amount_to_read, offset))
+}
+
 /// Common code between the `pwrite` detours.
 ///
 /// Handle the `.unwrap_or_bypass` in their respective functions though.
@@ -609,6 +710,18 @@ pub(crate) unsafe extern "C" fn _pwrite_nocancel_detour(
         .unwrap_or_bypass_with(|_| FN__PWRITE_NOCANCEL(fd, in_buffer, amount_to_write, offset))
 }
 
+/// Hook for `libc::pwrite$NOCANCEL`, which Bun calls directly on macOS.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn pwrite_nocancel_detour(
+    fd: RawFd,
+    in_buffer: *const c_void,
+    amount_to_write: size_t,
+    offset: off_t,
+) -> ssize_t {
+    pwrite_logic(fd, in_buffer, amount_to_write, offset)
+        .unwrap_or_bypass_with(|_| FN_PWRITE_NOCANCEL(fd, in_buffer, amount_to_write, offset))
+}
+
 /// Hook for `libc::lseek`.
 ///
 /// **Bypassed** by `fd`s that are not managed by us (not found in `OPEN_FILES`).
@@ -659,6 +772,21 @@ pub(crate) unsafe extern "C" fn _write_nocancel_detour(
     write(fd, write_bytes).unwrap_or_bypass_with(|_| FN__WRITE_NOCANCEL(fd, buffer, count))
 }
 
+/// Hook for `libc::write$NOCANCEL`, which Bun calls directly on macOS.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn write_nocancel_detour(
+    fd: RawFd,
+    buffer: *const c_void,
+    count: size_t,
+) -> ssize_t {
+    // WARN: Be veeery careful here, you cannot construct the `Vec` directly, as the buffer
+    // allocation is handled on the C side.
+    let write_bytes =
+        (!buffer.is_null()).then(|| slice::from_raw_parts(buffer as *const u8, count).to_vec());
+
+    write(fd, write_bytes).unwrap_or_bypass_with(|_| FN_WRITE_NOCANCEL(fd, buffer, count))
+}
+
 /// Implementation of access_detour, used in access_detour and faccessat_detour
 unsafe fn access_logic(raw_path: *const c_char, mode: c_int) -> c_int {
     access(raw_path.checked_into(), mode as u8).unwrap_or_bypass_with(|bypass| {
@@ -691,13 +819,19 @@ pub(crate) unsafe extern "C" fn faccessat_detour(
 /// Hook for `libc::fsync`.
 #[hook_guard_fn]
 pub(crate) unsafe extern "C" fn fsync_detour(fd: RawFd) -> c_int {
-    fsync(fd).unwrap_or_bypass_with(|_| FN_FSYNC(fd))
+    fsync(fd, false).unwrap_or_bypass_with(|_| FN_FSYNC(fd))
 }
 
 /// Hook for `libc::fdatasync`.
 #[hook_guard_fn]
 pub(crate) unsafe extern "C" fn fdatasync_detour(fd: RawFd) -> c_int {
-    fsync(fd).unwrap_or_bypass_with(|_| FN_FDATASYNC(fd))
+    fsync(fd, true).unwrap_or_bypass_with(|_| FN_FDATASYNC(fd))
+}
+
+/// Hook for `libc::flock`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn flock_detour(fd: RawFd, operation: c_int) -> c_int {
+    flock(fd, operation).unwrap_or_bypass_with(|_| FN_FLOCK(fd, operation))
 }
 
 /// Tries to convert input to type O, if it fails it returns the max value of O.
@@ -712,6 +846,28 @@ fn nano_to_secs(nano: i64) -> i64 {
     best_effort_cast(Duration::from_nanos(best_effort_cast(nano)).as_secs())
 }
 
+/// Returns the sub-second part of time in nano seconds, for the `st_*time_nsec` fields.
+fn nano_to_subsec_nanos(nano: i64) -> i64 {
+    nano.rem_euclid(1_000_000_000)
+}
+
+/// Converts a device id of the remote (always Linux) machine to a local `dev_t`.
+///
+/// On macOS `dev_t` encodes major and minor numbers differently, so we decode the Linux
+/// `makedev` encoding and re-encode it, otherwise `major`/`minor` of remote device files are
+/// garbage.
+#[cfg(target_os = "macos")]
+fn local_device_id(remote: u64) -> libc::dev_t {
+    let major = ((remote >> 32) & 0xffff_f000) | ((remote >> 8) & 0xfff);
+    let minor = ((remote >> 12) & 0xffff_ff00) | (remote & 0xff);
+    libc::makedev(best_effort_cast(major), best_effort_cast(minor))
+}
+
+#[cfg(target_os = "linux")]
+fn local_device_id(remote: u64) -> libc::dev_t {
+    remote
+}
+
 /// Fills the `stat` struct with the metadata
 unsafe extern "C" fn fill_stat(out_stat: *mut stat64, metadata: &MetadataInternal) {
     out_stat.write_bytes(0, 1);
@@ -719,18 +875,18 @@ unsafe extern "C" fn fill_stat(out_stat: *mut stat64, metadata: &MetadataInterna
     // on macOS the types might be different, so we try to cast and do our best..
     out.st_mode = best_effort_cast(metadata.mode);
     out.st_size = best_effort_cast(metadata.size);
-    out.st_atime_nsec = metadata.access_time;
-    out.st_mtime_nsec = metadata.modification_time;
-    out.st_ctime_nsec = metadata.creation_time;
+    out.st_atime_nsec = nano_to_subsec_nanos(metadata.access_time);
+    out.st_mtime_nsec = nano_to_subsec_nanos(metadata.modification_time);
+    out.st_ctime_nsec = nano_to_subsec_nanos(metadata.creation_time);
     out.st_atime = nano_to_secs(metadata.access_time);
     out.st_mtime = nano_to_secs(metadata.modification_time);
     out.st_ctime = nano_to_secs(metadata.creation_time);
     out.st_nlink = best_effort_cast(metadata.hard_links);
     out.st_uid = metadata.user_id;
     out.st_gid = metadata.group_id;
-    out.st_dev = best_effort_cast(metadata.device_id);
+    out.st_dev = local_device_id(metadata.device_id);
     out.st_ino = best_effort_cast(metadata.inode);
-    out.st_rdev = best_effort_cast(metadata.rdevice_id);
+    out.st_rdev = local_device_id(metadata.rdevice_id);
     out.st_blksize = best_effort_cast(metadata.block_size);
     out.st_blocks = best_effort_cast(metadata.blocks);
 }
@@ -751,6 +907,21 @@ unsafe extern "C" fn fill_statfs(out_stat: *mut statfs, metadata: &FsMetadataInt
     out.f_ffree = metadata.files_free;
 }
 
+/// Fills the `statvfs` struct with the metadata, the fields that `statfs` doesn't have are set
+/// like glibc sets them.
+unsafe extern "C" fn fill_statvfs(out_stat: *mut statvfs, metadata: &FsMetadataInternal) {
+    out_stat.write_bytes(0, 1);
+    let out = &mut *out_stat;
+    out.f_bsize = best_effort_cast(metadata.block_size);
+    out.f_frsize = best_effort_cast(metadata.block_size);
+    out.f_blocks = best_effort_cast(metadata.blocks);
+    out.f_bfree = best_effort_cast(metadata.blocks_free);
+    out.f_bavail = best_effort_cast(metadata.blocks_available);
+    out.f_files = best_effort_cast(metadata.files);
+    out.f_ffree = best_effort_cast(metadata.files_free);
+    out.f_favail = best_effort_cast(metadata.files_free);
+}
+
 fn stat_logic<const FOLLOW_SYMLINK: bool>(
     _ver: c_int,
     fd: Option<RawFd>,
@@ -815,125 +986,534 @@ unsafe extern "C" fn statx_detour(
     })
 }
 
-/// Hook for libc's stat syscall wrapper.
+/// Hook for `libc::getxattr`.
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn __xstat_detour(
-    ver: c_int,
+pub(crate) unsafe extern "C" fn getxattr_detour(
     raw_path: *const c_char,
-    out_stat: *mut stat,
-) -> c_int {
-    stat_logic::<true>(ver, None, Some(raw_path), out_stat as *mut _).unwrap_or_bypass_with(
+    name: *const c_char,
+    value: *mut c_void,
+    size: size_t,
+) -> ssize_t {
+    get_xattr(Some(raw_path.checked_into()), None, true, name, value, size).unwrap_or_bypass_with(
         |bypass| {
             let raw_path = update_ptr_from_bypass(raw_path, &bypass);
-            FN___XSTAT(ver, raw_path, out_stat)
+            FN_GETXATTR(raw_path, name, value, size)
         },
     )
 }
 
-/// Hook for libc's stat syscall wrapper.
+/// Hook for `libc::lgetxattr`.
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn __lxstat_detour(
-    ver: c_int,
+pub(crate) unsafe extern "C" fn lgetxattr_detour(
     raw_path: *const c_char,
-    out_stat: *mut stat,
-) -> c_int {
-    stat_logic::<true>(ver, None, Some(raw_path), out_stat as *mut _).unwrap_or_bypass_with(
-        |bypass| {
-            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
-            FN___LXSTAT(ver, raw_path, out_stat)
-        },
+    name: *const c_char,
+    value: *mut c_void,
+    size: size_t,
+) -> ssize_t {
+    get_xattr(
+        Some(raw_path.checked_into()),
+        None,
+        false,
+        name,
+        value,
+        size,
     )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_LGETXATTR(raw_path, name, value, size)
+    })
 }
 
-/// Hook for libc's stat syscall wrapper.
+/// Hook for `libc::fgetxattr`.
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn __xstat64_detour(
-    ver: c_int,
+pub(crate) unsafe extern "C" fn fgetxattr_detour(
+    fd: RawFd,
+    name: *const c_char,
+    value: *mut c_void,
+    size: size_t,
+) -> ssize_t {
+    get_xattr(None, Some(fd), true, name, value, size)
+        .unwrap_or_bypass_with(|_| FN_FGETXATTR(fd, name, value, size))
+}
+
+/// Hook for `libc::setxattr`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn setxattr_detour(
     raw_path: *const c_char,
-    out_stat: *mut stat64,
+    name: *const c_char,
+    value: *const c_void,
+    size: size_t,
+    flags: c_int,
 ) -> c_int {
-    stat_logic::<true>(ver, None, Some(raw_path), out_stat).unwrap_or_bypass_with(|bypass| {
+    set_xattr(
+        Some(raw_path.checked_into()),
+        None,
+        true,
+        name,
+        value,
+        size,
+        flags,
+    )
+    .unwrap_or_bypass_with(|bypass| {
         let raw_path = update_ptr_from_bypass(raw_path, &bypass);
-        FN___XSTAT64(ver, raw_path, out_stat)
+        FN_SETXATTR(raw_path, name, value, size, flags)
     })
 }
 
-/// Hook for libc's stat syscall wrapper.
+/// Hook for `libc::lsetxattr`.
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn __lxstat64_detour(
-    ver: c_int,
+pub(crate) unsafe extern "C" fn lsetxattr_detour(
     raw_path: *const c_char,
-    out_stat: *mut stat64,
+    name: *const c_char,
+    value: *const c_void,
+    size: size_t,
+    flags: c_int,
 ) -> c_int {
-    stat_logic::<true>(ver, None, Some(raw_path), out_stat).unwrap_or_bypass_with(|bypass| {
+    set_xattr(
+        Some(raw_path.checked_into()),
+        None,
+        false,
+        name,
+        value,
+        size,
+        flags,
+    )
+    .unwrap_or_bypass_with(|bypass| {
         let raw_path = update_ptr_from_bypass(raw_path, &bypass);
-        FN___LXSTAT64(ver, raw_path, out_stat)
+        FN_LSETXATTR(raw_path, name, value, size, flags)
     })
 }
 
-/// Separated out logic for `fstatat` so that it can be used by go to match on the xstat result.
-pub(crate) unsafe fn fstatat_logic(
+/// Hook for `libc::fsetxattr`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fsetxattr_detour(
     fd: RawFd,
+    name: *const c_char,
+    value: *const c_void,
+    size: size_t,
+    flags: c_int,
+) -> c_int {
+    set_xattr(None, Some(fd), true, name, value, size, flags)
+        .unwrap_or_bypass_with(|_| FN_FSETXATTR(fd, name, value, size, flags))
+}
+
+/// Hook for `libc::listxattr`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn listxattr_detour(
     raw_path: *const c_char,
-    out_stat: *mut stat,
-    flag: c_int,
-) -> Detour<i32> {
-    if out_stat.is_null() {
-        return Detour::Error(HookError::BadPointer);
-    }
+    list: *mut c_char,
+    size: size_t,
+) -> ssize_t {
+    list_xattr(Some(raw_path.checked_into()), None, true, list, size).unwrap_or_bypass_with(
+        |bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_LISTXATTR(raw_path, list, size)
+        },
+    )
+}
 
-    let follow_symlink = (flag & libc::AT_SYMLINK_NOFOLLOW) == 0;
-    xstat(Some(raw_path.checked_into()), Some(fd), follow_symlink).map(|res| {
-        let res = res.metadata;
-        fill_stat(out_stat as *mut _, &res);
-        0
-    })
+/// Hook for `libc::llistxattr`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn llistxattr_detour(
+    raw_path: *const c_char,
+    list: *mut c_char,
+    size: size_t,
+) -> ssize_t {
+    list_xattr(Some(raw_path.checked_into()), None, false, list, size).unwrap_or_bypass_with(
+        |bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_LLISTXATTR(raw_path, list, size)
+        },
+    )
 }
 
-/// Hook for `libc::fstatat`.
+/// Hook for `libc::flistxattr`.
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-unsafe extern "C" fn fstatat_detour(
+pub(crate) unsafe extern "C" fn flistxattr_detour(
     fd: RawFd,
+    list: *mut c_char,
+    size: size_t,
+) -> ssize_t {
+    list_xattr(None, Some(fd), true, list, size)
+        .unwrap_or_bypass_with(|_| FN_FLISTXATTR(fd, list, size))
+}
+
+/// Hook for `libc::removexattr`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn removexattr_detour(
     raw_path: *const c_char,
-    out_stat: *mut stat,
-    flag: c_int,
+    name: *const c_char,
 ) -> c_int {
-    fstatat_logic(fd, raw_path, out_stat, flag).unwrap_or_bypass_with(|bypass| {
+    remove_xattr(Some(raw_path.checked_into()), None, true, name).unwrap_or_bypass_with(|bypass| {
         let raw_path = update_ptr_from_bypass(raw_path, &bypass);
-        FN_FSTATAT(fd, raw_path, out_stat, flag)
+        FN_REMOVEXATTR(raw_path, name)
     })
 }
 
+/// Hook for `libc::lremovexattr`.
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-unsafe extern "C" fn fstatfs_detour(fd: c_int, out_stat: *mut statfs) -> c_int {
-    if out_stat.is_null() {
-        return HookError::BadPointer.into();
-    }
+pub(crate) unsafe extern "C" fn lremovexattr_detour(
+    raw_path: *const c_char,
+    name: *const c_char,
+) -> c_int {
+    remove_xattr(Some(raw_path.checked_into()), None, false, name).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_LREMOVEXATTR(raw_path, name)
+    })
+}
 
-    xstatfs(fd)
-        .map(|res| {
-            let res = res.metadata;
-            fill_statfs(out_stat, &res);
-            0
-        })
-        .unwrap_or_bypass_with(|_| FN_FSTATFS(fd, out_stat))
+/// Hook for `libc::fremovexattr`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fremovexattr_detour(fd: RawFd, name: *const c_char) -> c_int {
+    remove_xattr(None, Some(fd), true, name).unwrap_or_bypass_with(|_| FN_FREMOVEXATTR(fd, name))
 }
 
-unsafe fn realpath_logic(
-    source_path: *const c_char,
-    output_path: *mut c_char,
-) -> Detour<*mut c_char> {
-    let path = source_path.checked_into();
+/// Hook for `libc::fallocate`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fallocate_detour(
+    fd: RawFd,
+    mode: c_int,
+    offset: off_t,
+    length: off_t,
+) -> c_int {
+    fallocate(fd, mode, offset, length, false)
+        .unwrap_or_bypass_with(|_| FN_FALLOCATE(fd, mode, offset, length))
+}
 
-    realpath(path).map(|res| {
-        let path = CString::new(res.to_string_lossy().to_string()).unwrap();
-        let path_len = path.as_bytes_with_nul().len();
-        let output = if output_path.is_null() {
-            let res = libc::malloc(path_len) as *mut c_char;
-            if res.is_null() {
-                return std::ptr::null_mut();
-            }
-            res
+/// Hook for `libc::posix_fallocate`.
+///
+/// Unlike `fallocate`, it returns the error number and leaves `errno` as is.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn posix_fallocate_detour(
+    fd: RawFd,
+    offset: off_t,
+    length: off_t,
+) -> c_int {
+    match fallocate(fd, 0, offset, length, true) {
+        Detour::Success(result) => result,
+        Detour::Bypass(..) => FN_POSIX_FALLOCATE(fd, offset, length),
+        Detour::Error(fail) => {
+            let previous = errno::errno();
+            let _: c_int = fail.into();
+            let error = errno::errno().0;
+            set_errno(previous);
+            error
+        }
+    }
+}
+
+/// Hook for `libc::copy_file_range`.
+///
+/// As in the kernel, the given offsets are advanced instead of the file offsets.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn copy_file_range_detour(
+    fd_in: RawFd,
+    off_in: *mut off_t,
+    fd_out: RawFd,
+    off_out: *mut off_t,
+    len: size_t,
+    flags: c_uint,
+) -> ssize_t {
+    copy_file_range(
+        fd_in,
+        off_in.as_ref().copied(),
+        fd_out,
+        off_out.as_ref().copied(),
+        len,
+        flags,
+    )
+    .map(|copied_amount| {
+        let copied_amount = ssize_t::try_from(copied_amount).unwrap_or(ssize_t::MAX);
+
+        for offset in [off_in, off_out] {
+            if let Some(offset) = offset.as_mut() {
+                *offset += copied_amount as off_t;
+            }
+        }
+
+        copied_amount
+    })
+    .unwrap_or_bypass_with(|_| FN_COPY_FILE_RANGE(fd_in, off_in, fd_out, off_out, len, flags))
+}
+
+/// Hook for `libc::sendfile`.
+///
+/// As in the kernel, the given `offset` is advanced instead of the file offset.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn sendfile_detour(
+    out_fd: RawFd,
+    in_fd: RawFd,
+    offset: *mut off_t,
+    count: size_t,
+) -> ssize_t {
+    sendfile(out_fd, in_fd, offset.as_ref().copied(), count)
+        .map(|sent_amount| {
+            let sent_amount = ssize_t::try_from(sent_amount).unwrap_or(ssize_t::MAX);
+
+            if let Some(offset) = offset.as_mut() {
+                *offset += sent_amount as off_t;
+            }
+
+            sent_amount
+        })
+        .unwrap_or_bypass_with(|_| FN_SENDFILE(out_fd, in_fd, offset, count))
+}
+
+/// Flags of `glob` that we handle, any other flag (or an `errfunc`) calls the original `glob`.
+#[cfg(target_os = "linux")]
+const SUPPORTED_GLOB_FLAGS: c_int = GLOB_NOSORT | GLOB_NOCHECK;
+
+/// Hook for `libc::glob`.
+///
+/// Absolute patterns are expanded in the agent (see [`glob`]), and `pglob` is filled with
+/// `malloc`ed copies of the paths, so the original `globfree` releases them.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn glob_detour(
+    raw_pattern: *const c_char,
+    flags: c_int,
+    errfunc: Option<extern "C" fn(*const c_char, c_int) -> c_int>,
+    pglob: *mut glob_t,
+) -> c_int {
+    if flags & !SUPPORTED_GLOB_FLAGS != 0 || errfunc.is_some() || pglob.is_null() {
+        return FN_GLOB(raw_pattern, flags, errfunc, pglob);
+    }
+
+    (*pglob).gl_pathc = 0;
+    (*pglob).gl_pathv = ptr::null_mut();
+    (*pglob).gl_offs = 0;
+    (*pglob).gl_flags = flags;
+
+    match glob(raw_pattern.checked_into()) {
+        Detour::Success(GlobResponse {
+            truncated: true, ..
+        }) => GLOB_NOSPACE,
+        Detour::Success(GlobResponse { paths, .. }) if paths.is_empty() => {
+            if flags & GLOB_NOCHECK != 0 {
+                fill_glob(
+                    pglob,
+                    [std::ffi::CStr::from_ptr(raw_pattern).to_bytes()].into_iter(),
+                )
+            } else {
+                GLOB_NOMATCH
+            }
+        }
+        Detour::Success(GlobResponse { paths, .. }) => {
+            fill_glob(pglob, paths.iter().map(|path| path.as_os_str().as_bytes()))
+        }
+        // The ignored path in the bypass is only the pattern's prefix.
+        Detour::Bypass(_) => FN_GLOB(raw_pattern, flags, errfunc, pglob),
+        Detour::Error(fail) => {
+            let _ = i64::from(fail);
+            GLOB_ABORTED
+        }
+    }
+}
+
+/// Fills `pglob` (already reset) with `malloc`ed copies of `paths`, like the original `glob`.
+///
+/// When an allocation fails, `pglob` holds the paths copied so far, and can still be freed with
+/// `globfree`.
+#[cfg(target_os = "linux")]
+unsafe fn fill_glob<'a>(
+    pglob: *mut glob_t,
+    paths: impl ExactSizeIterator<Item = &'a [u8]>,
+) -> c_int {
+    // `calloc` leaves the terminating null pointer in place.
+    let pathv =
+        libc::calloc(paths.len() + 1, std::mem::size_of::<*mut c_char>()).cast::<*mut c_char>();
+    if pathv.is_null() {
+        return GLOB_NOSPACE;
+    }
+    (*pglob).gl_pathv = pathv;
+
+    for path in paths {
+        let copy = libc::malloc(path.len() + 1).cast::<c_char>();
+        if copy.is_null() {
+            return GLOB_NOSPACE;
+        }
+
+        ptr::copy_nonoverlapping(path.as_ptr().cast::<c_char>(), copy, path.len());
+        *copy.add(path.len()) = 0;
+
+        *pathv.add((*pglob).gl_pathc) = copy;
+        (*pglob).gl_pathc += 1;
+    }
+
+    0
+}
+
+/// Hook for libc's stat syscall wrapper.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn __xstat_detour(
+    ver: c_int,
+    raw_path: *const c_char,
+    out_stat: *mut stat,
+) -> c_int {
+    stat_logic::<true>(ver, None, Some(raw_path), out_stat as *mut _).unwrap_or_bypass_with(
+        |bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN___XSTAT(ver, raw_path, out_stat)
+        },
+    )
+}
+
+/// Hook for libc's stat syscall wrapper.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn __lxstat_detour(
+    ver: c_int,
+    raw_path: *const c_char,
+    out_stat: *mut stat,
+) -> c_int {
+    stat_logic::<false>(ver, None, Some(raw_path), out_stat as *mut _).unwrap_or_bypass_with(
+        |bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN___LXSTAT(ver, raw_path, out_stat)
+        },
+    )
+}
+
+/// Hook for libc's stat syscall wrapper.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn __xstat64_detour(
+    ver: c_int,
+    raw_path: *const c_char,
+    out_stat: *mut stat64,
+) -> c_int {
+    stat_logic::<true>(ver, None, Some(raw_path), out_stat).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN___XSTAT64(ver, raw_path, out_stat)
+    })
+}
+
+/// Hook for libc's stat syscall wrapper.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn __lxstat64_detour(
+    ver: c_int,
+    raw_path: *const c_char,
+    out_stat: *mut stat64,
+) -> c_int {
+    stat_logic::<false>(ver, None, Some(raw_path), out_stat).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN___LXSTAT64(ver, raw_path, out_stat)
+    })
+}
+
+/// Separated out logic for `fstatat` so that it can be used by go to match on the xstat result.
+pub(crate) unsafe fn fstatat_logic(
+    fd: RawFd,
+    raw_path: *const c_char,
+    out_stat: *mut stat,
+    flag: c_int,
+) -> Detour<i32> {
+    if out_stat.is_null() {
+        return Detour::Error(HookError::BadPointer);
+    }
+
+    let follow_symlink = (flag & libc::AT_SYMLINK_NOFOLLOW) == 0;
+    xstat(Some(raw_path.checked_into()), Some(fd), follow_symlink).map(|res| {
+        let res = res.metadata;
+        fill_stat(out_stat as *mut _, &res);
+        0
+    })
+}
+
+/// Hook for `libc::fstatat`.
+#[hook_guard_fn]
+unsafe extern "C" fn fstatat_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    out_stat: *mut stat,
+    flag: c_int,
+) -> c_int {
+    fstatat_logic(fd, raw_path, out_stat, flag).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_FSTATAT(fd, raw_path, out_stat, flag)
+    })
+}
+
+#[hook_guard_fn]
+unsafe extern "C" fn fstatfs_detour(fd: c_int, out_stat: *mut statfs) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    xstatfs(fd)
+        .map(|res| {
+            let res = res.metadata;
+            fill_statfs(out_stat, &res);
+            0
+        })
+        .unwrap_or_bypass_with(|_| FN_FSTATFS(fd, out_stat))
+}
+
+/// Hook for `libc::statfs`, for paths that were never opened (e.g. checking the free space).
+#[hook_guard_fn]
+unsafe extern "C" fn statfs_detour(raw_path: *const c_char, out_stat: *mut statfs) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    statfs_path(raw_path.checked_into())
+        .map(|res| {
+            fill_statfs(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_STATFS(raw_path, out_stat)
+        })
+}
+
+/// Hook for `libc::statvfs`, which doesn't call our [`statfs_detour`].
+#[hook_guard_fn]
+unsafe extern "C" fn statvfs_detour(raw_path: *const c_char, out_stat: *mut statvfs) -> c_int {
+    if out_stat.is_null() {
+        return HookError::BadPointer.into();
+    }
+
+    statfs_path(raw_path.checked_into())
+        .map(|res| {
+            fill_statvfs(out_stat, &res.metadata);
+            0
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_STATVFS(raw_path, out_stat)
+        })
+}
+
+unsafe fn realpath_logic(
+    source_path: *const c_char,
+    output_path: *mut c_char,
+) -> Detour<*mut c_char> {
+    let path = source_path.checked_into();
+
+    realpath(path).map(|res| {
+        let path = CString::new(res.to_string_lossy().to_string()).unwrap();
+        let path_len = path.as_bytes_with_nul().len();
+        let output = if output_path.is_null() {
+            let res = libc::malloc(path_len) as *mut c_char;
+            if res.is_null() {
+                return std::ptr::null_mut();
+            }
+            res
         } else {
             output_path
         };
@@ -944,122 +1524,477 @@ unsafe fn realpath_logic(
     })
 }
 
-/// When path is handled by us, just make it absolute and return, since resolving it remotely
-/// doesn't really matter for our case atm (might be in the future)
+/// When path is handled by us, just make it absolute and return, since resolving it remotely
+/// doesn't really matter for our case atm (might be in the future)
+#[hook_guard_fn]
+unsafe extern "C" fn realpath_detour(
+    source_path: *const c_char,
+    output_path: *mut c_char,
+) -> *mut c_char {
+    realpath_logic(source_path, output_path).unwrap_or_bypass_with(|bypass| {
+        let source_path = update_ptr_from_bypass(source_path, &bypass);
+        FN_REALPATH(source_path, output_path)
+    })
+}
+
+#[hook_guard_fn]
+unsafe extern "C" fn realpath_darwin_extsn_detour(
+    source_path: *const c_char,
+    output_path: *mut c_char,
+) -> *mut c_char {
+    realpath_logic(source_path, output_path).unwrap_or_bypass_with(|bypass| {
+        let source_path = update_ptr_from_bypass(source_path, &bypass);
+        FN_REALPATH_DARWIN_EXTSN(source_path, output_path)
+    })
+}
+
+/// Hook for `canonicalize_file_name`, the GNU `realpath(path, NULL)`, that doesn't call our
+/// `realpath_detour`.
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+unsafe extern "C" fn canonicalize_file_name_detour(source_path: *const c_char) -> *mut c_char {
+    realpath_logic(source_path, std::ptr::null_mut()).unwrap_or_bypass_with(|bypass| {
+        let source_path = update_ptr_from_bypass(source_path, &bypass);
+        FN_CANONICALIZE_FILE_NAME(source_path)
+    })
+}
+
+fn vec_to_iovec(bytes: &[u8], iovecs: &[iovec]) {
+    let mut copied = 0;
+    let mut iov_index = 0;
+
+    while copied < bytes.len() {
+        let iov = &iovecs.get(iov_index).expect("ioevec out of bounds");
+        let read_ptr = unsafe { bytes.as_ptr().add(copied) };
+        let copy_amount = std::cmp::min(bytes.len() - copied, iov.iov_len);
+        let out_buffer = iov.iov_base.cast();
+        unsafe { ptr::copy(read_ptr, out_buffer, copy_amount) };
+        copied += copy_amount;
+        // we trust iov_index to be in correct size since we checked it before
+        iov_index += 1;
+    }
+}
+
+/// Common code between [`readv_detour`] and [`preadv_detour`].
+unsafe fn readv_logic(
+    fd: RawFd,
+    iovecs: *const iovec,
+    iovec_count: c_int,
+    offset: Option<u64>,
+) -> Detour<ssize_t> {
+    let iovs = (!iovecs.is_null() && iovec_count >= 0)
+        .then(|| slice::from_raw_parts(iovecs, iovec_count as usize));
+    let bytes = read_vectored(fd, iovs, offset)?;
+
+    vec_to_iovec(&bytes, iovs?);
+
+    // WARN: Must be careful when it comes to `EOF`, incorrect handling may appear as the
+    // `read` call being repeated.
+    Detour::Success(ssize_t::try_from(bytes.len()).unwrap())
+}
+
+/// Hook for `libc::readv`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn readv_detour(
+    fd: RawFd,
+    iovecs: *const iovec,
+    iovec_count: c_int,
+) -> ssize_t {
+    readv_logic(fd, iovecs, iovec_count, None)
+        .unwrap_or_bypass_with(|_| FN_READV(fd, iovecs, iovec_count))
+}
+
+/// Hook for `libc::preadv`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn preadv_detour(
+    fd: RawFd,
+    iovecs: *const iovec,
+    iovec_count: c_int,
+    offset: off_t,
+) -> ssize_t {
+    readv_logic(fd, iovecs, iovec_count, Some(offset as u64))
+        .unwrap_or_bypass_with(|_| FN_PREADV(fd, iovecs, iovec_count, offset))
+}
+
+/// Common code between [`writev_detour`] and [`pwritev_detour`].
+unsafe fn writev_logic(
+    fd: RawFd,
+    iovecs: *const iovec,
+    iovec_count: c_int,
+    offset: Option<u64>,
+) -> Detour<ssize_t> {
+    let iovs = (!iovecs.is_null() && iovec_count >= 0)
+        .then(|| slice::from_raw_parts(iovecs, iovec_count as usize));
+
+    write_vectored(fd, iovs, offset)
+}
+
+/// Hook for `libc::writev`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn writev_detour(
+    fd: RawFd,
+    iovecs: *const iovec,
+    iovec_count: c_int,
+) -> ssize_t {
+    writev_logic(fd, iovecs, iovec_count, None)
+        .unwrap_or_bypass_with(|_| FN_WRITEV(fd, iovecs, iovec_count))
+}
+
+/// Hook for `libc::pwritev`.
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn pwritev_detour(
+    fd: RawFd,
+    iovecs: *const iovec,
+    iovec_count: c_int,
+    offset: off_t,
+) -> ssize_t {
+    writev_logic(fd, iovecs, iovec_count, Some(offset as u64))
+        .unwrap_or_bypass_with(|_| FN_PWRITEV(fd, iovecs, iovec_count, offset))
+}
+
+/// Hook for [`libc::readlink`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn readlink_detour(
+    raw_path: *const c_char,
+    out_buffer: *mut c_char,
+    buffer_size: size_t,
+) -> ssize_t {
+    read_link(raw_path.checked_into())
+        .map(|ReadLinkFileResponse { path }| {
+            let path_bytes = path.as_os_str().as_bytes();
+
+            let path_ptr = path_bytes.as_ptr();
+            let out_buffer = out_buffer.cast();
+
+            ptr::copy(path_ptr, out_buffer, buffer_size);
+
+            ssize_t::try_from(path_bytes.len().min(buffer_size)).unwrap()
+        })
+        .unwrap_or_bypass_with(|bypass| {
+            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+            FN_READLINK(raw_path, out_buffer, buffer_size)
+        })
+}
+
+/// Hook for [`libc::unlink`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn unlink_detour(raw_path: *const c_char) -> c_int {
+    unlink(raw_path.checked_into()).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UNLINK(raw_path)
+    })
+}
+
+/// Hook for [`libc::unlinkat`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn unlinkat_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    flags: c_int,
+) -> c_int {
+    unlinkat(fd, raw_path.checked_into(), flags).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UNLINKAT(fd, raw_path, flags)
+    })
+}
+
+/// Hook for [`libc::chmod`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn chmod_detour(raw_path: *const c_char, mode: mode_t) -> c_int {
+    chmod(raw_path.checked_into(), mode as u32).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_CHMOD(raw_path, mode)
+    })
+}
+
+/// Hook for [`libc::fchmod`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchmod_detour(fd: RawFd, mode: mode_t) -> c_int {
+    fchmod(fd, mode as u32).unwrap_or_bypass_with(|_| FN_FCHMOD(fd, mode))
+}
+
+/// Hook for [`libc::fchmodat`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchmodat_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    mode: mode_t,
+    flags: c_int,
+) -> c_int {
+    fchmodat(fd, raw_path.checked_into(), mode as u32, flags).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_FCHMODAT(fd, raw_path, mode, flags)
+    })
+}
+
+/// Hook for [`libc::truncate`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn truncate_detour(raw_path: *const c_char, length: off_t) -> c_int {
+    truncate(raw_path.checked_into(), length).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_TRUNCATE(raw_path, length)
+    })
+}
+
+/// Hook for [`libc::ftruncate`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn ftruncate_detour(fd: RawFd, length: off_t) -> c_int {
+    ftruncate(fd, length).unwrap_or_bypass_with(|_| FN_FTRUNCATE(fd, length))
+}
+
+/// Hook for [`libc::chown`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn chown_detour(
+    raw_path: *const c_char,
+    owner: uid_t,
+    group: gid_t,
+) -> c_int {
+    chown(raw_path.checked_into(), owner, group).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_CHOWN(raw_path, owner, group)
+    })
+}
+
+/// Hook for [`libc::lchown`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn lchown_detour(
+    raw_path: *const c_char,
+    owner: uid_t,
+    group: gid_t,
+) -> c_int {
+    fchownat(
+        AT_FDCWD,
+        raw_path.checked_into(),
+        owner,
+        group,
+        libc::AT_SYMLINK_NOFOLLOW,
+    )
+    .unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_LCHOWN(raw_path, owner, group)
+    })
+}
+
+/// Hook for [`libc::fchown`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchown_detour(fd: RawFd, owner: uid_t, group: gid_t) -> c_int {
+    fchown(fd, owner, group).unwrap_or_bypass_with(|_| FN_FCHOWN(fd, owner, group))
+}
+
+/// Hook for [`libc::fchownat`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn fchownat_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    owner: uid_t,
+    group: gid_t,
+    flags: c_int,
+) -> c_int {
+    fchownat(fd, raw_path.checked_into(), owner, group, flags).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_FCHOWNAT(fd, raw_path, owner, group, flags)
+    })
+}
+
+/// Hook for [`libc::utimensat`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn utimensat_detour(
+    fd: RawFd,
+    raw_path: *const c_char,
+    times: *const timespec,
+    flags: c_int,
+) -> c_int {
+    let file_times = times.cast::<[timespec; 2]>().as_ref();
+
+    utimensat(fd, raw_path.checked_into(), file_times, flags).unwrap_or_bypass_with(|bypass| {
+        let raw_path = update_ptr_from_bypass(raw_path, &bypass);
+        FN_UTIMENSAT(fd, raw_path, times, flags)
+    })
+}
+
+/// Hook for [`libc::futimens`].
 #[hook_guard_fn]
-unsafe extern "C" fn realpath_detour(
-    source_path: *const c_char,
-    output_path: *mut c_char,
-) -> *mut c_char {
-    realpath_logic(source_path, output_path).unwrap_or_bypass_with(|bypass| {
-        let source_path = update_ptr_from_bypass(source_path, &bypass);
-        FN_REALPATH(source_path, output_path)
-    })
+pub(crate) unsafe extern "C" fn futimens_detour(fd: RawFd, times: *const timespec) -> c_int {
+    futimens(fd, times.cast::<[timespec; 2]>().as_ref())
+        .unwrap_or_bypass_with(|_| FN_FUTIMENS(fd, times))
 }
 
+/// Hook for [`libc::link`].
 #[hook_guard_fn]
-unsafe extern "C" fn realpath_darwin_extsn_detour(
-    source_path: *const c_char,
-    output_path: *mut c_char,
-) -> *mut c_char {
-    realpath_logic(source_path, output_path).unwrap_or_bypass_with(|bypass| {
-        let source_path = update_ptr_from_bypass(source_path, &bypass);
-        FN_REALPATH_DARWIN_EXTSN(source_path, output_path)
-    })
+pub(crate) unsafe extern "C" fn link_detour(
+    raw_old_path: *const c_char,
+    raw_new_path: *const c_char,
+) -> c_int {
+    link(raw_old_path.checked_into(), raw_new_path.checked_into())
+        .unwrap_or_bypass_with(|_| FN_LINK(raw_old_path, raw_new_path))
 }
 
-fn vec_to_iovec(bytes: &[u8], iovecs: &[iovec]) {
-    let mut copied = 0;
-    let mut iov_index = 0;
+/// Hook for [`libc::linkat`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn linkat_detour(
+    old_fd: RawFd,
+    raw_old_path: *const c_char,
+    new_fd: RawFd,
+    raw_new_path: *const c_char,
+    flags: c_int,
+) -> c_int {
+    linkat(
+        old_fd,
+        raw_old_path.checked_into(),
+        new_fd,
+        raw_new_path.checked_into(),
+        flags,
+    )
+    .unwrap_or_bypass_with(|_| FN_LINKAT(old_fd, raw_old_path, new_fd, raw_new_path, flags))
+}
 
-    while copied < bytes.len() {
-        let iov = &iovecs.get(iov_index).expect("ioevec out of bounds");
-        let read_ptr = unsafe { bytes.as_ptr().add(copied) };
-        let copy_amount = std::cmp::min(bytes.len(), iov.iov_len);
-        let out_buffer = iov.iov_base.cast();
-        unsafe { ptr::copy(read_ptr, out_buffer, copy_amount) };
-        copied += copy_amount;
-        // we trust iov_index to be in correct size since we checked it before
-        iov_index += 1;
-    }
+/// Hook for [`libc::symlink`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn symlink_detour(
+    raw_target: *const c_char,
+    raw_link_path: *const c_char,
+) -> c_int {
+    symlink(raw_target.checked_into(), raw_link_path.checked_into()).unwrap_or_bypass_with(
+        |bypass| {
+            let raw_link_path = update_ptr_from_bypass(raw_link_path, &bypass);
+            FN_SYMLINK(raw_target, raw_link_path)
+        },
+    )
 }
 
-/// Hook for `libc::readv`.
+/// Hook for [`libc::symlinkat`].
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn readv_detour(
+pub(crate) unsafe extern "C" fn symlinkat_detour(
+    raw_target: *const c_char,
     fd: RawFd,
-    iovecs: *const iovec,
-    iovec_count: c_int,
-) -> ssize_t {
-    if iovec_count < 0 {
-        return FN_READV(fd, iovecs, iovec_count);
-    }
-
-    let iovs = (!iovecs.is_null()).then(|| slice::from_raw_parts(iovecs, iovec_count as usize));
+    raw_link_path: *const c_char,
+) -> c_int {
+    symlinkat(raw_target.checked_into(), fd, raw_link_path.checked_into()).unwrap_or_bypass_with(
+        |bypass| {
+            let raw_link_path = update_ptr_from_bypass(raw_link_path, &bypass);
+            FN_SYMLINKAT(raw_target, fd, raw_link_path)
+        },
+    )
+}
 
-    readv(iovs)
-        .and_then(|(iovs, read_size)| Detour::Success((read(fd, read_size)?, iovs)))
-        .map(|(read_file, iovs)| {
-            let ReadFileResponse { bytes, .. } = read_file;
+/// Hook for [`libc::chroot`], the fs rules keep being matched in the view of the user after it,
+/// see [`RootView`](super::root_view::RootView).
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn chroot_detour(raw_path: *const c_char) -> c_int {
+    // Resolved before the root changes, relative paths and links are in the old root.
+    let root = (!raw_path.is_null())
+        .then(|| std::fs::canonicalize(OsStr::from_bytes(CStr::from_ptr(raw_path).to_bytes())))
+        .and_then(Result::ok);
+
+    let result = FN_CHROOT(raw_path);
+    if let (0, Some(root)) = (result, root) {
+        let setup = crate::setup();
+        setup.root_view().chroot(&root, !setup.exec_hooks_enabled());
+    }
 
-            vec_to_iovec(&bytes, iovs);
-            // WARN: Must be careful when it comes to `EOF`, incorrect handling may appear as the
-            // `read` call being repeated.
-            ssize_t::try_from(bytes.len()).unwrap()
-        })
-        .unwrap_or_bypass_with(|_| FN_READV(fd, iovecs, iovec_count))
+    result
 }
 
-/// Hook for `libc::readv`.
+/// Hook for [`libc::rename`].
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn preadv_detour(
-    fd: RawFd,
-    iovecs: *const iovec,
-    iovec_count: c_int,
-    offset: off_t,
-) -> ssize_t {
-    if iovec_count < 0 {
-        return FN_PREADV(fd, iovecs, iovec_count, offset);
-    }
+pub(crate) unsafe extern "C" fn rename_detour(
+    raw_old_path: *const c_char,
+    raw_new_path: *const c_char,
+) -> c_int {
+    rename(raw_old_path.checked_into(), raw_new_path.checked_into())
+        .unwrap_or_bypass_with(|_| FN_RENAME(raw_old_path, raw_new_path))
+}
 
-    let iovs = (!iovecs.is_null()).then(|| slice::from_raw_parts(iovecs, iovec_count as usize));
+/// Hook for [`libc::renameat`].
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn renameat_detour(
+    old_fd: RawFd,
+    raw_old_path: *const c_char,
+    new_fd: RawFd,
+    raw_new_path: *const c_char,
+) -> c_int {
+    renameat(
+        old_fd,
+        raw_old_path.checked_into(),
+        new_fd,
+        raw_new_path.checked_into(),
+        0,
+    )
+    .unwrap_or_bypass_with(|_| FN_RENAMEAT(old_fd, raw_old_path, new_fd, raw_new_path))
+}
 
-    readv(iovs)
-        .and_then(|(iovs, read_size)| Detour::Success((pread(fd, read_size, offset as u64)?, iovs)))
-        .map(|(read_file, iovs)| {
-            let ReadFileResponse { bytes, .. } = read_file;
+/// Hook for `renameat2`, only on Linux (glibc 2.28+).
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn renameat2_detour(
+    old_fd: RawFd,
+    raw_old_path: *const c_char,
+    new_fd: RawFd,
+    raw_new_path: *const c_char,
+    flags: c_uint,
+) -> c_int {
+    renameat(
+        old_fd,
+        raw_old_path.checked_into(),
+        new_fd,
+        raw_new_path.checked_into(),
+        flags,
+    )
+    .unwrap_or_bypass_with(|_| FN_RENAMEAT2(old_fd, raw_old_path, new_fd, raw_new_path, flags))
+}
 
-            vec_to_iovec(&bytes, iovs);
+/// Hook for `inotify_init`, see [`watch`].
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_init_detour() -> c_int {
+    watch::init(0).unwrap_or_bypass_with(|_| FN_INOTIFY_INIT())
+}
 
-            // WARN: Must be careful when it comes to `EOF`, incorrect handling may appear as the
-            // `read` call being repeated.
-            ssize_t::try_from(bytes.len()).unwrap()
-        })
-        .unwrap_or_bypass_with(|_| FN_PREADV(fd, iovecs, iovec_count, offset))
+/// Hook for `inotify_init1`, see [`watch`].
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_init1_detour(flags: c_int) -> c_int {
+    watch::init(flags).unwrap_or_bypass_with(|_| FN_INOTIFY_INIT1(flags))
 }
 
-/// Hook for [`libc::readlink`].
+/// Hook for `inotify_add_watch`, only for the instances of [`inotify_init1_detour`].
+#[cfg(target_os = "linux")]
 #[hook_guard_fn]
-pub(crate) unsafe extern "C" fn readlink_detour(
+pub(crate) unsafe extern "C" fn inotify_add_watch_detour(
+    fd: RawFd,
     raw_path: *const c_char,
-    out_buffer: *mut c_char,
-    buffer_size: size_t,
-) -> ssize_t {
-    read_link(raw_path.checked_into())
-        .map(|ReadLinkFileResponse { path }| {
-            let path_bytes = path.as_os_str().as_bytes();
-
-            let path_ptr = path_bytes.as_ptr();
-            let out_buffer = out_buffer.cast();
+    mask: u32,
+) -> c_int {
+    watch::add_watch(fd, raw_path.checked_into(), mask)
+        .unwrap_or_bypass_with(|_| FN_INOTIFY_ADD_WATCH(fd, raw_path, mask))
+}
 
-            ptr::copy(path_ptr, out_buffer, buffer_size);
+/// Hook for `inotify_rm_watch`, only for the instances of [`inotify_init1_detour`].
+#[cfg(target_os = "linux")]
+#[hook_guard_fn]
+pub(crate) unsafe extern "C" fn inotify_rm_watch_detour(fd: RawFd, wd: c_int) -> c_int {
+    watch::rm_watch(fd, wd).unwrap_or_bypass_with(|_| FN_INOTIFY_RM_WATCH(fd, wd))
+}
 
-            ssize_t::try_from(path_bytes.len().min(buffer_size)).unwrap()
-        })
-        .unwrap_or_bypass_with(|bypass| {
-            let raw_path = update_ptr_from_bypass(raw_path, &bypass);
-            FN_READLINK(raw_path, out_buffer, buffer_size)
-        })
+/// Sets up only the `write` hooks, for when the output of the local process goes to the target
+/// (see [`LayerSetup::output_mode`](crate::setup::LayerSetup::output_mode)), but file operations
+/// are not enabled.
+pub(crate) unsafe fn enable_write_hooks(hook_manager: &mut HookManager) {
+    replace!(hook_manager, "write", write_detour, FnWrite, FN_WRITE);
+    replace!(
+        hook_manager,
+        "_write$NOCANCEL",
+        _write_nocancel_detour,
+        Fn_write_nocancel,
+        FN__WRITE_NOCANCEL
+    );
+    replace!(
+        hook_manager,
+        "write$NOCANCEL",
+        write_nocancel_detour,
+        FnWrite_nocancel,
+        FN_WRITE_NOCANCEL
+    );
 }
 
 /// Convenience function to setup file hooks (`x_detour`) with `frida_gum`.
@@ -1082,6 +2017,16 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         FnOpenat64,
         FN_OPENAT64
     );
+    #[cfg(target_os = "linux")]
+    {
+        replace!(
+            hook_manager,
+            "openat2",
+            openat2_detour,
+            FnOpenat2,
+            FN_OPENAT2
+        );
+    }
     replace!(
         hook_manager,
         "_openat$NOCANCEL",
@@ -1089,6 +2034,13 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         Fn_openat_nocancel,
         FN__OPENAT_NOCANCEL
     );
+    replace!(
+        hook_manager,
+        "openat$NOCANCEL",
+        openat_nocancel_detour,
+        FnOpenat_nocancel,
+        FN_OPENAT_NOCANCEL
+    );
 
     replace!(hook_manager, "read", read_detour, FnRead, FN_READ);
     replace!(
@@ -1120,6 +2072,14 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
     replace!(hook_manager, "pread", pread_detour, FnPread, FN_PREAD);
     replace!(hook_manager, "readv", readv_detour, FnReadv, FN_READV);
     replace!(hook_manager, "preadv", preadv_detour, FnPreadv, FN_PREADV);
+    replace!(hook_manager, "writev", writev_detour, FnWritev, FN_WRITEV);
+    replace!(
+        hook_manager,
+        "pwritev",
+        pwritev_detour,
+        FnPwritev,
+        FN_PWRITEV
+    );
     replace!(
         hook_manager,
         "_pread$NOCANCEL",
@@ -1127,6 +2087,13 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         Fn_pread_nocancel,
         FN__PREAD_NOCANCEL
     );
+    replace!(
+        hook_manager,
+        "pread$NOCANCEL",
+        pread_nocancel_detour,
+        FnPread_nocancel,
+        FN_PREAD_NOCANCEL
+    );
 
     replace!(
         hook_manager,
@@ -1136,16 +2103,129 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         FN_READLINK
     );
 
-    replace!(hook_manager, "lseek", lseek_detour, FnLseek, FN_LSEEK);
-
-    replace!(hook_manager, "write", write_detour, FnWrite, FN_WRITE);
+    replace!(hook_manager, "unlink", unlink_detour, FnUnlink, FN_UNLINK);
     replace!(
         hook_manager,
-        "_write$NOCANCEL",
-        _write_nocancel_detour,
-        Fn_write_nocancel,
-        FN__WRITE_NOCANCEL
+        "unlinkat",
+        unlinkat_detour,
+        FnUnlinkat,
+        FN_UNLINKAT
+    );
+    replace!(hook_manager, "chmod", chmod_detour, FnChmod, FN_CHMOD);
+    replace!(hook_manager, "fchmod", fchmod_detour, FnFchmod, FN_FCHMOD);
+    replace!(
+        hook_manager,
+        "fchmodat",
+        fchmodat_detour,
+        FnFchmodat,
+        FN_FCHMODAT
+    );
+    replace!(
+        hook_manager,
+        "truncate",
+        truncate_detour,
+        FnTruncate,
+        FN_TRUNCATE
+    );
+    replace!(
+        hook_manager,
+        "ftruncate",
+        ftruncate_detour,
+        FnFtruncate,
+        FN_FTRUNCATE
+    );
+    replace!(hook_manager, "chown", chown_detour, FnChown, FN_CHOWN);
+    replace!(hook_manager, "lchown", lchown_detour, FnLchown, FN_LCHOWN);
+    replace!(hook_manager, "fchown", fchown_detour, FnFchown, FN_FCHOWN);
+    replace!(
+        hook_manager,
+        "fchownat",
+        fchownat_detour,
+        FnFchownat,
+        FN_FCHOWNAT
+    );
+    replace!(
+        hook_manager,
+        "utimensat",
+        utimensat_detour,
+        FnUtimensat,
+        FN_UTIMENSAT
+    );
+    replace!(
+        hook_manager,
+        "futimens",
+        futimens_detour,
+        FnFutimens,
+        FN_FUTIMENS
+    );
+    replace!(hook_manager, "link", link_detour, FnLink, FN_LINK);
+    replace!(hook_manager, "linkat", linkat_detour, FnLinkat, FN_LINKAT);
+    replace!(
+        hook_manager,
+        "symlink",
+        symlink_detour,
+        FnSymlink,
+        FN_SYMLINK
+    );
+    replace!(
+        hook_manager,
+        "symlinkat",
+        symlinkat_detour,
+        FnSymlinkat,
+        FN_SYMLINKAT
+    );
+    replace!(hook_manager, "chroot", chroot_detour, FnChroot, FN_CHROOT);
+    replace!(hook_manager, "rename", rename_detour, FnRename, FN_RENAME);
+    replace!(
+        hook_manager,
+        "renameat",
+        renameat_detour,
+        FnRenameat,
+        FN_RENAMEAT
     );
+    #[cfg(target_os = "linux")]
+    {
+        replace!(
+            hook_manager,
+            "renameat2",
+            renameat2_detour,
+            FnRenameat2,
+            FN_RENAMEAT2
+        );
+
+        replace!(
+            hook_manager,
+            "inotify_init",
+            inotify_init_detour,
+            FnInotify_init,
+            FN_INOTIFY_INIT
+        );
+        replace!(
+            hook_manager,
+            "inotify_init1",
+            inotify_init1_detour,
+            FnInotify_init1,
+            FN_INOTIFY_INIT1
+        );
+        replace!(
+            hook_manager,
+            "inotify_add_watch",
+            inotify_add_watch_detour,
+            FnInotify_add_watch,
+            FN_INOTIFY_ADD_WATCH
+        );
+        replace!(
+            hook_manager,
+            "inotify_rm_watch",
+            inotify_rm_watch_detour,
+            FnInotify_rm_watch,
+            FN_INOTIFY_RM_WATCH
+        );
+    }
+
+    replace!(hook_manager, "lseek", lseek_detour, FnLseek, FN_LSEEK);
+
+    enable_write_hooks(hook_manager);
 
     replace!(hook_manager, "pwrite", pwrite_detour, FnPwrite, FN_PWRITE);
     replace!(
@@ -1155,6 +2235,13 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         Fn_pwrite_nocancel,
         FN__PWRITE_NOCANCEL
     );
+    replace!(
+        hook_manager,
+        "pwrite$NOCANCEL",
+        pwrite_nocancel_detour,
+        FnPwrite_nocancel,
+        FN_PWRITE_NOCANCEL
+    );
 
     replace!(hook_manager, "access", access_detour, FnAccess, FN_ACCESS);
     replace!(
@@ -1174,6 +2261,8 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
         FN_FDATASYNC
     );
 
+    replace!(hook_manager, "flock", flock_detour, FnFlock, FN_FLOCK);
+
     replace!(
         hook_manager,
         "realpath",
@@ -1193,6 +2282,126 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
     #[cfg(target_os = "linux")]
     {
         replace!(hook_manager, "statx", statx_detour, FnStatx, FN_STATX);
+        replace!(
+            hook_manager,
+            "canonicalize_file_name",
+            canonicalize_file_name_detour,
+            FnCanonicalize_file_name,
+            FN_CANONICALIZE_FILE_NAME
+        );
+        replace!(hook_manager, "glob", glob_detour, FnGlob, FN_GLOB);
+        replace!(
+            hook_manager,
+            "getxattr",
+            getxattr_detour,
+            FnGetxattr,
+            FN_GETXATTR
+        );
+        replace!(
+            hook_manager,
+            "lgetxattr",
+            lgetxattr_detour,
+            FnLgetxattr,
+            FN_LGETXATTR
+        );
+        replace!(
+            hook_manager,
+            "fgetxattr",
+            fgetxattr_detour,
+            FnFgetxattr,
+            FN_FGETXATTR
+        );
+        replace!(
+            hook_manager,
+            "setxattr",
+            setxattr_detour,
+            FnSetxattr,
+            FN_SETXATTR
+        );
+        replace!(
+            hook_manager,
+            "lsetxattr",
+            lsetxattr_detour,
+            FnLsetxattr,
+            FN_LSETXATTR
+        );
+        replace!(
+            hook_manager,
+            "fsetxattr",
+            fsetxattr_detour,
+            FnFsetxattr,
+            FN_FSETXATTR
+        );
+        replace!(
+            hook_manager,
+            "listxattr",
+            listxattr_detour,
+            FnListxattr,
+            FN_LISTXATTR
+        );
+        replace!(
+            hook_manager,
+            "llistxattr",
+            llistxattr_detour,
+            FnLlistxattr,
+            FN_LLISTXATTR
+        );
+        replace!(
+            hook_manager,
+            "flistxattr",
+            flistxattr_detour,
+            FnFlistxattr,
+            FN_FLISTXATTR
+        );
+        replace!(
+            hook_manager,
+            "removexattr",
+            removexattr_detour,
+            FnRemovexattr,
+            FN_REMOVEXATTR
+        );
+        replace!(
+            hook_manager,
+            "lremovexattr",
+            lremovexattr_detour,
+            FnLremovexattr,
+            FN_LREMOVEXATTR
+        );
+        replace!(
+            hook_manager,
+            "fremovexattr",
+            fremovexattr_detour,
+            FnFremovexattr,
+            FN_FREMOVEXATTR
+        );
+        replace!(
+            hook_manager,
+            "fallocate",
+            fallocate_detour,
+            FnFallocate,
+            FN_FALLOCATE
+        );
+        replace!(
+            hook_manager,
+            "posix_fallocate",
+            posix_fallocate_detour,
+            FnPosix_fallocate,
+            FN_POSIX_FALLOCATE
+        );
+        replace!(
+            hook_manager,
+            "copy_file_range",
+            copy_file_range_detour,
+            FnCopy_file_range,
+            FN_COPY_FILE_RANGE
+        );
+        replace!(
+            hook_manager,
+            "sendfile",
+            sendfile_detour,
+            FnSendfile,
+            FN_SENDFILE
+        );
     }
 
     #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
@@ -1250,6 +2459,14 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
             FnFstatfs,
             FN_FSTATFS
         );
+        replace!(hook_manager, "statfs", statfs_detour, FnStatfs, FN_STATFS);
+        replace!(
+            hook_manager,
+            "statvfs",
+            statvfs_detour,
+            FnStatvfs,
+            FN_STATVFS
+        );
         replace!(
             hook_manager,
             "fdopendir",
@@ -1332,6 +2549,13 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager) {
             FnFstatfs,
             FN_FSTATFS
         );
+        replace!(
+            hook_manager,
+            "statfs$INODE64",
+            statfs_detour,
+            FnStatfs,
+            FN_STATFS
+        );
         replace!(
             hook_manager,
             "fdopendir$INODE64",
@@ -1,21 +1,55 @@
+use std::{
+    env,
+    ffi::CString,
+    fmt::Debug,
+    fs::{FileTimes, Permissions},
+    io::Write,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{OpenOptionsExt, PermissionsExt},
+        io::{IntoRawFd, RawFd},
+    },
+    path::PathBuf,
+    slice, thread,
+    time::{Duration, UNIX_EPOCH},
+};
 #[cfg(target_os = "linux")]
-use std::time::Duration;
-use std::{env, ffi::CString, io::SeekFrom, os::unix::io::RawFd, path::PathBuf};
+use std::{ffi::CStr, time::Instant};
 
 #[cfg(target_os = "linux")]
-use libc::{c_char, statx, statx_timestamp};
-use libc::{c_int, iovec, unlink, AT_FDCWD};
+use libc::{c_char, c_uint, c_void, size_t, ssize_t, statx, statx_timestamp};
+use libc::{c_int, iovec, off_t, timespec, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_FOLLOW};
+use mirrord_intproxy_protocol::IsLayerRequestWithResponse;
+#[cfg(target_os = "linux")]
+use mirrord_intproxy_protocol::{InterceptedConnection, SendFileToConnRequest};
+#[cfg(target_os = "linux")]
+use mirrord_protocol::file::{
+    CanonicalizePathRequest, CanonicalizePathResponse, CopyFileRangeRequest, CopyFileRangeResponse,
+    FallocateFileRequest, GetXattrRequest, GetXattrResponse, GlobRequest, GlobResponse,
+    ListXattrRequest, ListXattrResponse, OpenAt2Request, RemoveXattrRequest, SendFileResponse,
+    SetXattrRequest, StatxMetadataInternal, StatxRequest, StatxResponse,
+};
 use mirrord_protocol::{
     file::{
-        OpenFileRequest, OpenFileResponse, OpenOptionsInternal, ReadFileResponse,
-        ReadLinkFileRequest, ReadLinkFileResponse, SeekFileResponse, WriteFileResponse,
-        XstatFsResponse, XstatResponse,
+        ChmodFileRequest, ChmodFileWithDirRequest, ChownFileRequest, ChownFileWithDirRequest,
+        FchmodFileRequest, FchownFileRequest, FileLockInternal, FileTimeInternal, FlockRequest,
+        FsyncFileRequest, FtruncateFileRequest, FutimensFileRequest, GetLockRequest,
+        GetLockResponse, LinkFileRequest, LinkFileWithDirRequest, LockTypeInternal,
+        MetadataInternal, OpenFileRequest, OpenFileResponse, OpenOptionsInternal, ReadFileResponse,
+        ReadLinkFileRequest, ReadLinkFileResponse, ReadVFileRequest, ReadVFileResponse,
+        ReadWholeFileRequest, ReadWholeFileResponse, RenameFileRequest, RenameFileWithDirRequest,
+        SeekFileResponse, SeekFromInternal, SetLockRequest, StatFsRequest, SymlinkAtRequest,
+        SymlinkRequest, TruncateFileRequest, UnlinkFileRequest, UnlinkFileWithDirRequest,
+        UtimensFileWithDirRequest, WriteFileResponse, WriteVFileRequest, XstatFsResponse,
+        XstatResponse,
     },
-    ResponseError,
+    ErrorKindInternal, RemoteIOError, RemoteResult, ResponseError,
 };
 use rand::distributions::{Alphanumeric, DistString};
 use tracing::{error, trace, Level};
 
+#[cfg(target_os = "linux")]
+use super::hooks::FN_WRITE;
 use super::{hooks::FN_OPEN, open_dirs::OPEN_DIRS, *};
 #[cfg(target_os = "linux")]
 use crate::common::CheckedInto;
@@ -26,11 +60,19 @@ use crate::{
 };
 
 /// 1 Megabyte. Large read requests can lead to timeouts.
-const MAX_READ_SIZE: u64 = 1024 * 1024;
+pub(crate) const MAX_READ_SIZE: u64 = 1024 * 1024;
+
+/// Most paths we ask the agent for in a single [`GlobRequest`], `glob` fails with `GLOB_NOSPACE`
+/// when the pattern matches more.
+#[cfg(target_os = "linux")]
+const MAX_GLOB_RESULTS: u64 = 16 * 1024;
 
 /// Helper macro for checking if the given path should be handled remotely.
 /// Uses global [`crate::setup()`].
 ///
+/// The path is matched in the view of the user, see
+/// [`RootView`](super::root_view::RootView).
+///
 /// Should the file be ignored, this macro exists current context with [`Bypass::IgnoredFile`].
 ///
 /// # Arguments
@@ -40,24 +82,62 @@ const MAX_READ_SIZE: u64 = 1024 * 1024;
 macro_rules! ensure_not_ignored {
     ($path:expr, $write:expr) => {
         $crate::setup().file_filter().continue_or_bypass_with(
-            $path.to_str().unwrap_or_default(),
+            $crate::setup()
+                .root_view()
+                .user_path(&$path)
+                .to_string_lossy()
+                .as_ref(),
             $write,
-            || Bypass::ignored_file($path.to_str().unwrap_or_default()),
+            || Bypass::ignored_file($path.as_os_str().as_bytes()),
         )?;
     };
 }
 
+/// Helper macro for the
+/// [`FsModeConfig::CopyOnWrite`](mirrord_config::feature::fs::FsModeConfig::CopyOnWrite) mode,
+/// should be used before [`ensure_not_ignored`].
+///
+/// Should the operation be done on the local copy of the file, this macro exits current context
+/// with [`Bypass::Overlay`], see [`Overlay::redirect`](super::overlay::Overlay::redirect).
+///
+/// # Arguments
+///
+/// * `path` - [`PathBuf`]
+/// * `write` - [`bool`], stating whether the operation modifies the file
+macro_rules! redirect_to_overlay {
+    ($path:expr, $write:expr) => {
+        if let Some(overlay) = $crate::setup().fs_overlay() {
+            ensure_not_ignored!($path, false);
+
+            let user_path = $crate::setup().root_view().user_path(&$path);
+            let text = user_path.to_string_lossy();
+            let text = text.as_ref();
+            if $write && $crate::setup().file_filter().blocks_writes(text) {
+                Detour::Error(HookError::FileBlocked)?
+            }
+
+            if !$crate::setup().file_filter().writes_remotely(text) {
+                overlay.redirect(&$path, $write)?;
+            }
+        }
+    };
+}
+
 macro_rules! check_relative_paths {
     ($path:expr) => {
         if $path.is_relative() {
-            Detour::Bypass(Bypass::relative_path($path.to_str().unwrap_or_default()))?
+            Detour::Bypass(Bypass::relative_path($path.as_os_str().as_bytes()))?
         };
     };
 }
 
+/// Applies the `mapping` of the fs config to the given path, in the view of the user (see
+/// [`RootView`](super::root_view::RootView)).
 macro_rules! remap_path {
     ($path:expr) => {
-        $crate::setup().file_remapper().change_path($path)
+        $crate::setup()
+            .root_view()
+            .change_path($crate::setup().file_remapper(), $path)
     };
 }
 
@@ -80,19 +160,15 @@ impl RemoteFile {
     ) -> Detour<OpenFileResponse> {
         let requesting_file = OpenFileRequest { path, open_options };
 
-        let response = common::make_proxy_request_with_response(requesting_file)??;
-
-        Detour::Success(response)
+        fallback_on_not_implemented(requesting_file)
     }
 
     /// Sends a [`ReadFileRequest`] message, reading the file in the agent.
     ///
-    /// Blocking request and wait on already found remote_fd
+    /// Blocking request and wait on already found remote_fd. Big reads are split by the internal
+    /// proxy, and streamed by the agents that support it.
     #[mirrord_layer_macro::instrument(level = "trace")]
     pub(crate) fn remote_read(remote_fd: u64, read_amount: u64) -> Detour<ReadFileResponse> {
-        // Limit read size because if we read too much it can lead to a timeout
-        // Seems also that bincode doesn't do well with large buffers
-        let read_amount = std::cmp::min(read_amount, MAX_READ_SIZE);
         let reading_file = ReadFileRequest {
             remote_fd,
             buffer_size: read_amount,
@@ -139,6 +215,23 @@ fn get_remote_fd(local_fd: RawFd) -> Detour<u64> {
     )
 }
 
+/// Makes the `request` to the agent, [`Bypass::NotImplemented`] when the agent doesn't support it
+/// (the internal proxy responds with [`ResponseError::NotImplemented`] then).
+///
+/// The bypass runs the libc call on the local file, calls that need another fallback replace it
+/// with [`Detour::or_bypass`].
+fn fallback_on_not_implemented<T, R>(request: T) -> Detour<R>
+where
+    T: IsLayerRequestWithResponse<Response = RemoteResult<R>> + Debug,
+    R: Debug,
+{
+    match common::make_proxy_request_with_response(request)? {
+        Ok(response) => Detour::Success(response),
+        Err(ResponseError::NotImplemented) => Detour::Bypass(Bypass::NotImplemented),
+        Err(fail) => Detour::Error(fail.into()),
+    }
+}
+
 /// Create temporary local file to get a valid local fd.
 #[mirrord_layer_macro::instrument(level = "trace", ret)]
 fn create_local_fake_file(remote_fd: u64) -> Detour<RawFd> {
@@ -157,7 +250,7 @@ fn create_local_fake_file(remote_fd: u64) -> Detour<RawFd> {
         close_remote_file_on_failure(remote_fd)?;
         Detour::Error(HookError::LocalFileCreation(remote_fd, error.0))
     } else {
-        unsafe { unlink(file_path_ptr) };
+        unsafe { libc::unlink(file_path_ptr) };
         Detour::Success(local_file_fd)
     }
 }
@@ -204,8 +297,16 @@ pub(crate) fn open(path: Detour<PathBuf>, open_options: OpenOptionsInternal) ->
 
     let path = remap_path!(path);
 
+    redirect_to_overlay!(path, open_options.is_write());
+
     ensure_not_ignored!(path, open_options.is_write());
 
+    if open_options.is_read_only() {
+        if let Some(local_fd) = read_whole(path.clone())? {
+            return Detour::Success(local_fd);
+        }
+    }
+
     let OpenFileResponse { fd: remote_fd } = RemoteFile::remote_open(path.clone(), open_options)?;
 
     // TODO: Need a way to say "open a directory", right now `is_dir` always returns false.
@@ -221,6 +322,69 @@ pub(crate) fn open(path: Detour<PathBuf>, open_options: OpenOptionsInternal) ->
     Detour::Success(local_file_fd)
 }
 
+/// Reads the whole remote file at `path` in a single round trip, when it's small enough (see
+/// [`ExperimentalConfig::read_whole_max_size`](mirrord_config::experimental::ExperimentalConfig::read_whole_max_size)),
+/// and returns a local copy of it, so that the app doesn't make any more requests for it.
+///
+/// Returns [`None`] when the file should be opened remotely as usual: it's not a regular file,
+/// it's too big, or the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+fn read_whole(path: PathBuf) -> Detour<Option<RawFd>> {
+    let max_size = crate::setup().experimental().read_whole_max_size;
+    if max_size == 0 {
+        return Detour::Success(None);
+    }
+
+    let requesting_file = ReadWholeFileRequest { path, max_size };
+
+    let response = fallback_on_not_implemented(requesting_file)
+        .map(Some)
+        .or_bypass(|_| Detour::Success(None))?;
+
+    match response {
+        Some(ReadWholeFileResponse {
+            metadata,
+            bytes: Some(bytes),
+        }) => create_local_copy(&bytes, &metadata).map(Some),
+        _ => Detour::Success(None),
+    }
+}
+
+/// Creates an unlinked temporary local file with `bytes`, and the mode and times of the remote
+/// file from `metadata`, and opens it read-only.
+fn create_local_copy(bytes: &[u8], metadata: &MetadataInternal) -> Detour<RawFd> {
+    let random_string = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    let file_path = env::temp_dir().join(format!("mirrord-{random_string}"));
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&file_path)?;
+
+    let local_copy = file
+        .write_all(bytes)
+        .and_then(|()| {
+            let time = |nanos: i64| {
+                UNIX_EPOCH + Duration::from_nanos(u64::try_from(nanos).unwrap_or_default())
+            };
+            file.set_times(
+                FileTimes::new()
+                    .set_accessed(time(metadata.access_time))
+                    .set_modified(time(metadata.modification_time)),
+            )
+        })
+        .and_then(|()| std::fs::File::open(&file_path))
+        .and_then(|local_copy| {
+            file.set_permissions(Permissions::from_mode(metadata.mode & 0o7777))?;
+            Ok(local_copy)
+        });
+
+    std::fs::remove_file(&file_path)?;
+
+    Detour::Success(local_copy?.into_raw_fd())
+}
+
 /// creates a directory stream for the `remote_fd` in the agent
 #[mirrord_layer_macro::instrument(level = "trace", ret)]
 pub(crate) fn fdopendir(fd: RawFd) -> Detour<usize> {
@@ -271,8 +435,7 @@ pub(crate) fn openat(
             open_options,
         };
 
-        let OpenFileResponse { fd: remote_fd } =
-            common::make_proxy_request_with_response(requesting_file)??;
+        let OpenFileResponse { fd: remote_fd } = fallback_on_not_implemented(requesting_file)?;
 
         let local_file_fd = create_local_fake_file(remote_fd)?;
 
@@ -285,6 +448,69 @@ pub(crate) fn openat(
     }
 }
 
+/// Opens `path` like [`openat`], but the agent makes the kernel enforce the `RESOLVE_*` flags in
+/// `resolve` (e.g. `RESOLVE_BENEATH`) on the remote path.
+///
+/// Without `resolve` flags this is just [`openat`]. Agents that don't support it fail it with
+/// `ENOSYS`, like kernels older than 5.6 do, so that the app falls back to resolving the path
+/// itself, instead of us dropping the flags.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn openat2(
+    fd: RawFd,
+    path: Detour<PathBuf>,
+    open_options: OpenOptionsInternal,
+    resolve: u64,
+) -> Detour<RawFd> {
+    if resolve == 0 {
+        return openat(fd, path, open_options);
+    }
+
+    let path = path?;
+
+    // An absolute path ignores `fd`, unless it's resolved in it.
+    let requesting_file =
+        if fd == AT_FDCWD || (path.is_absolute() && resolve & libc::RESOLVE_IN_ROOT == 0) {
+            check_relative_paths!(path);
+
+            let path = remap_path!(path);
+
+            redirect_to_overlay!(path, open_options.is_write());
+
+            ensure_not_ignored!(path, open_options.is_write());
+
+            OpenAt2Request {
+                dirfd: None,
+                path,
+                open_options,
+                resolve,
+            }
+        } else {
+            OpenAt2Request {
+                dirfd: Some(get_remote_fd(fd)?),
+                path,
+                open_options,
+                resolve,
+            }
+        };
+    let path = requesting_file.path.display().to_string();
+
+    let OpenFileResponse { fd: remote_fd } = fallback_on_not_implemented(requesting_file)
+        .or_bypass(|_| {
+            Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+                libc::ENOSYS,
+            )))
+        })?;
+
+    let local_file_fd = create_local_fake_file(remote_fd)?;
+
+    OPEN_FILES
+        .lock()?
+        .insert(local_file_fd, Arc::new(RemoteFile::new(remote_fd, path)));
+
+    Detour::Success(local_file_fd)
+}
+
 /// Blocking wrapper around [`libc::read`] call.
 ///
 /// **Bypassed** when trying to load system files, and files from the current working directory, see
@@ -293,13 +519,108 @@ pub(crate) fn read(local_fd: RawFd, read_amount: u64) -> Detour<ReadFileResponse
     get_remote_fd(local_fd).and_then(|remote_fd| RemoteFile::remote_read(remote_fd, read_amount))
 }
 
-/// Helper for dealing with a potential null pointer being passed to `*const iovec` from
-/// `readv_detour` and `preadv_detour`.
-pub(crate) fn readv(iovs: Option<&[iovec]>) -> Detour<(&[iovec], u64)> {
+/// Reads the remote file `local_fd` into the buffers `iovs`, like `readv` (or `preadv`), with a
+/// single request. [`None`] offset uses (and advances) the file offset.
+///
+/// Returns the bytes read, to be copied to the buffers in order. Reads at most [`MAX_READ_SIZE`]
+/// bytes, and agents that don't support [`ReadVFileRequest`] get a single read of the whole
+/// size.
+///
+/// **Bypassed** when the file is local, or `iovs` is null.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, skip(iovs))]
+pub(crate) fn read_vectored(
+    local_fd: RawFd,
+    iovs: Option<&[iovec]>,
+    offset: Option<u64>,
+) -> Detour<Vec<u8>> {
+    let iovs = iovs?;
+    let fd = get_remote_fd(local_fd)?;
+
+    // Limit read size, see `RemoteFile::remote_read`.
+    let mut remaining = MAX_READ_SIZE;
+    let lengths = iovs
+        .iter()
+        .map(|iov| {
+            let length = (iov.iov_len as u64).min(remaining);
+            remaining -= length;
+            length
+        })
+        .collect::<Vec<_>>();
+    let read_size = MAX_READ_SIZE - remaining;
+
+    let requesting_read = ReadVFileRequest {
+        fd,
+        lengths,
+        offset,
+    };
+
+    fallback_on_not_implemented(requesting_read)
+        .map(|ReadVFileResponse { buffers }| buffers.concat())
+        .or_bypass(|_| {
+            let ReadFileResponse { bytes, .. } = match offset {
+                Some(offset) => pread(local_fd, read_size, offset)?,
+                None => read(local_fd, read_size)?,
+            };
+
+            Detour::Success(bytes)
+        })
+}
+
+/// Writes the buffers `iovs` to the remote file `local_fd`, like `writev` (or `pwritev`), with a
+/// single request. [`None`] offset uses (and advances) the file offset.
+///
+/// Agents that don't support [`WriteVFileRequest`] get a single write of all the buffers. Writes
+/// to stdout and stderr are handled like in [`write`].
+///
+/// **Bypassed** when the file is local, or `iovs` is null.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, skip(iovs))]
+pub(crate) fn write_vectored(
+    local_fd: RawFd,
+    iovs: Option<&[iovec]>,
+    offset: Option<u64>,
+) -> Detour<isize> {
     let iovs = iovs?;
-    let read_size: u64 = iovs.iter().fold(0, |sum, iov| sum + iov.iov_len as u64);
+    let buffers = || {
+        iovs.iter()
+            .map(|iov| match iov.iov_len {
+                0 => Vec::new(),
+                length => {
+                    unsafe { slice::from_raw_parts(iov.iov_base.cast::<u8>(), length) }.to_vec()
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let fd = match get_remote_fd(local_fd) {
+        Detour::Bypass(Bypass::LocalFdNotFound(..))
+            if offset.is_none()
+                && matches!(local_fd, libc::STDOUT_FILENO | libc::STDERR_FILENO) =>
+        {
+            return crate::output::write(local_fd, Some(buffers().concat()));
+        }
+        fd => fd?,
+    };
+
+    let requesting_write = WriteVFileRequest {
+        fd,
+        buffers: buffers(),
+        offset,
+    };
 
-    Detour::Success((iovs, read_size))
+    fallback_on_not_implemented(requesting_write)
+        .and_then(|WriteFileResponse { written_amount }| {
+            Detour::Success(written_amount.try_into()?)
+        })
+        .or_bypass(|_| {
+            let bytes = buffers().concat();
+            match offset {
+                Some(offset) => {
+                    let WriteFileResponse { written_amount } = pwrite(local_fd, &bytes, offset)?;
+                    Detour::Success(written_amount.try_into()?)
+                }
+                None => write(local_fd, Some(bytes)),
+            }
+        })
 }
 
 #[mirrord_layer_macro::instrument(level = "trace")]
@@ -325,181 +646,1446 @@ pub(crate) fn read_link(path: Detour<PathBuf>) -> Detour<ReadLinkFileResponse> {
 
     check_relative_paths!(path);
 
+    redirect_to_overlay!(path, false);
+
     ensure_not_ignored!(path, false);
 
     let requesting_path = ReadLinkFileRequest { path };
 
-    // `NotImplemented` error here means that the protocol doesn't support it.
-    match common::make_proxy_request_with_response(requesting_path)? {
-        Ok(response) => Detour::Success(response),
-        Err(ResponseError::NotImplemented) => Detour::Bypass(Bypass::NotImplemented),
-        Err(fail) => Detour::Error(fail.into()),
-    }
+    fallback_on_not_implemented(requesting_path)
 }
 
-pub(crate) fn pwrite(local_fd: RawFd, buffer: &[u8], offset: u64) -> Detour<WriteFileResponse> {
-    let remote_fd = get_remote_fd(local_fd)?;
-    trace!("pwrite: local_fd {local_fd}");
+/// Removes the remote file at `path`.
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn unlink(path: Detour<PathBuf>) -> Detour<c_int> {
+    let path = path?;
 
-    let writing_file = WriteLimitedFileRequest {
-        remote_fd,
-        write_bytes: buffer.to_vec(),
-        start_from: offset,
-    };
+    check_relative_paths!(path);
 
-    let response = common::make_proxy_request_with_response(writing_file)??;
+    let path = remap_path!(path);
 
-    Detour::Success(response)
-}
+    redirect_to_overlay!(path, false);
 
-#[mirrord_layer_macro::instrument(level = "trace")]
-pub(crate) fn lseek(local_fd: RawFd, offset: i64, whence: i32) -> Detour<u64> {
-    let remote_fd = get_remote_fd(local_fd)?;
+    ensure_not_ignored!(path, true);
 
-    let seek_from = match whence {
-        libc::SEEK_SET => SeekFrom::Start(offset as u64),
-        libc::SEEK_CUR => SeekFrom::Current(offset),
-        libc::SEEK_END => SeekFrom::End(offset),
-        invalid => {
-            tracing::warn!(
-                "lseek -> potential invalid value {:#?} for whence {:#?}",
-                invalid,
-                whence
-            );
-            return Detour::Bypass(Bypass::CStrConversion);
-        }
-    };
+    let requesting_unlink = UnlinkFileRequest { path };
 
-    let seeking_file = SeekFileRequest {
-        fd: remote_fd,
-        seek_from: seek_from.into(),
-    };
+    fallback_on_not_implemented(requesting_unlink).map(|()| 0)
+}
 
-    let SeekFileResponse { result_offset } =
-        common::make_proxy_request_with_response(seeking_file)??;
+/// Removes the remote file (or the empty directory, when `flags` has [`AT_REMOVEDIR`]) at `path`.
+///
+/// Like [`openat`], a relative `path` is resolved by the agent against the remote directory `fd`,
+/// unless `fd` is [`AT_FDCWD`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn unlinkat(fd: RawFd, path: Detour<PathBuf>, flags: c_int) -> Detour<c_int> {
+    let path = path?;
+    let remove_dir = flags & AT_REMOVEDIR != 0;
 
-    Detour::Success(result_offset)
-}
+    let requesting_unlink = if path.is_absolute() || fd == AT_FDCWD {
+        check_relative_paths!(path);
 
-pub(crate) fn write(local_fd: RawFd, write_bytes: Option<Vec<u8>>) -> Detour<isize> {
-    let remote_fd = get_remote_fd(local_fd)?;
+        let path = remap_path!(path);
 
-    let writing_file = WriteFileRequest {
-        fd: remote_fd,
-        write_bytes: write_bytes.ok_or(Bypass::EmptyBuffer)?,
+        redirect_to_overlay!(path, false);
+
+        ensure_not_ignored!(path, true);
+
+        UnlinkFileWithDirRequest {
+            dirfd: None,
+            path,
+            remove_dir,
+        }
+    } else {
+        UnlinkFileWithDirRequest {
+            dirfd: Some(get_remote_fd(fd)?),
+            path,
+            remove_dir,
+        }
     };
 
-    let WriteFileResponse { written_amount } =
-        common::make_proxy_request_with_response(writing_file)??;
-    Detour::Success(written_amount.try_into()?)
+    fallback_on_not_implemented(requesting_unlink).map(|()| 0)
 }
 
-#[mirrord_layer_macro::instrument(level = "trace")]
-pub(crate) fn access(path: Detour<PathBuf>, mode: u8) -> Detour<c_int> {
+/// Changes the mode of the remote file at `path`.
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn chmod(path: Detour<PathBuf>, mode: u32) -> Detour<c_int> {
     let path = path?;
 
     check_relative_paths!(path);
 
     let path = remap_path!(path);
 
-    ensure_not_ignored!(path, false);
+    redirect_to_overlay!(path, true);
 
-    let access = AccessFileRequest {
-        pathname: path,
-        mode,
-    };
+    ensure_not_ignored!(path, true);
 
-    let _ = common::make_proxy_request_with_response(access)??;
+    let requesting_chmod = ChmodFileRequest { path, mode };
 
-    Detour::Success(0)
+    fallback_on_not_implemented(requesting_chmod).map(|()| 0)
 }
 
-/// Original function _flushes_ data from `fd` to disk, but we don't really do any of this
-/// for our managed fds, so we just return `0` which means success.
-#[mirrord_layer_macro::instrument(level = "trace", ret)]
-pub(crate) fn fsync(fd: RawFd) -> Detour<c_int> {
-    get_remote_fd(fd)?;
-    Detour::Success(0)
+/// Changes the mode of the remote file `local_fd`.
+///
+/// **Bypassed** when the file is local, or when it should not be written remotely.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fchmod(local_fd: RawFd, mode: u32) -> Detour<c_int> {
+    let (fd, path) = OPEN_FILES
+        .lock()?
+        .get(&local_fd)
+        .map(|remote_file| (remote_file.fd, PathBuf::from(&remote_file.path)))
+        .ok_or(Bypass::LocalFdNotFound(local_fd))?;
+
+    ensure_not_ignored!(path, true);
+
+    let requesting_chmod = FchmodFileRequest { fd, mode };
+
+    fallback_on_not_implemented(requesting_chmod).map(|()| 0)
 }
 
-/// General stat function that can be used for lstat, fstat, stat and fstatat.
-/// Note: We treat cases of `AT_SYMLINK_NOFOLLOW_ANY` as `AT_SYMLINK_NOFOLLOW` because even Go does
-/// that.
-/// rawish_path is Option<Option<&CStr>> because we need to differentiate between null pointer
-/// and non existing argument (For error handling)
-#[mirrord_layer_macro::instrument(level = "trace", ret)]
-pub(crate) fn xstat(
-    rawish_path: Option<Detour<PathBuf>>,
-    fd: Option<RawFd>,
-    follow_symlink: bool,
-) -> Detour<XstatResponse> {
-    // Can't use map because we need to propagate captured error
-    let (path, fd) = match (rawish_path, fd) {
-        // fstatat
-        (Some(path), Some(fd)) => {
-            let mut path = path?;
-            let fd = {
-                if fd == AT_FDCWD {
-                    check_relative_paths!(path);
+/// Changes the mode of the remote file at `path`, `flags` are the `fchmodat` ones.
+///
+/// Like [`unlinkat`], a relative `path` is resolved by the agent against the remote directory
+/// `fd`, unless `fd` is [`AT_FDCWD`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fchmodat(fd: RawFd, path: Detour<PathBuf>, mode: u32, flags: c_int) -> Detour<c_int> {
+    let path = path?;
+    let flags = flags as u32;
 
-                    path = remap_path!(path);
-                    ensure_not_ignored!(path, false);
-                    None
-                } else {
-                    Some(get_remote_fd(fd)?)
-                }
-            };
-            (Some(path), fd)
-        }
-        // lstat/stat
-        (Some(path), None) => {
-            let path = path?;
+    let requesting_chmod = if path.is_absolute() || fd == AT_FDCWD {
+        check_relative_paths!(path);
 
-            check_relative_paths!(path);
+        let path = remap_path!(path);
 
-            let path = remap_path!(path);
+        redirect_to_overlay!(path, true);
 
-            ensure_not_ignored!(path, false);
-            (Some(path), None)
+        ensure_not_ignored!(path, true);
+
+        ChmodFileWithDirRequest {
+            dirfd: None,
+            path,
+            mode,
+            flags,
+        }
+    } else {
+        ChmodFileWithDirRequest {
+            dirfd: Some(get_remote_fd(fd)?),
+            path,
+            mode,
+            flags,
         }
-        // fstat
-        (None, Some(fd)) => (None, Some(get_remote_fd(fd)?)),
-        // can't happen
-        (None, None) => return Detour::Error(HookError::NullPointer),
     };
 
-    let lstat = XstatRequest {
-        fd,
+    fallback_on_not_implemented(requesting_chmod).map(|()| 0)
+}
+
+/// `truncate` and `ftruncate` fail with `EINVAL` for a negative `length`.
+fn truncate_length(length: off_t) -> Detour<u64> {
+    match u64::try_from(length) {
+        Ok(length) => Detour::Success(length),
+        Err(..) => Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::EINVAL,
+        ))),
+    }
+}
+
+/// Truncates (or extends) the remote file at `path` to `length` bytes.
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn truncate(path: Detour<PathBuf>, length: off_t) -> Detour<c_int> {
+    let path = path?;
+
+    check_relative_paths!(path);
+
+    let path = remap_path!(path);
+
+    redirect_to_overlay!(path, true);
+
+    ensure_not_ignored!(path, true);
+
+    let requesting_truncate = TruncateFileRequest {
         path,
-        follow_symlink,
+        length: truncate_length(length)?,
     };
 
-    let response = common::make_proxy_request_with_response(lstat)??;
+    fallback_on_not_implemented(requesting_truncate).map(|()| 0)
+}
 
-    Detour::Success(response)
+/// Truncates (or extends) the remote file `local_fd` to `length` bytes.
+///
+/// **Bypassed** when the file is local, or when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn ftruncate(local_fd: RawFd, length: off_t) -> Detour<c_int> {
+    let fd = get_remote_fd(local_fd)?;
+
+    let requesting_truncate = FtruncateFileRequest {
+        fd,
+        length: truncate_length(length)?,
+    };
+
+    fallback_on_not_implemented(requesting_truncate).map(|()| 0)
 }
 
-/// Logic for the `libc::statx` function.
-/// See [manual](https://man7.org/linux/man-pages/man2/statx.2.html) for reference.
+/// Expands the absolute glob `pattern` in the agent, returning the sorted matching paths.
 ///
-/// # Warning
+/// Only the leading components without wildcards are checked against the file filter, the
+/// matches themselves are not.
 ///
-/// Due to backwards compatibility on the [`mirrord_protocol`] level, we use [`XstatRequest`] to get
-/// the remote file metadata.
-/// Because of this, we're not able to fill all field of the [`struct@statx`] structure. Missing
-/// fields are:
-/// 1. [`statx::stx_attributes`]
-/// 2. [`statx::stx_ctime`]
-/// 3. [`statx::stx_mnt_id`]
-/// 4. [`statx::stx_dio_mem_align`] and [`statx::stx_dio_offset_align`]
-///
-/// Luckily, [`statx::stx_mask`] and [`statx::stx_attributes_mask`] fields allow us to inform the
-/// caller about respective fields being skipped.
+/// **Bypassed** when the pattern is relative or ends with a `/` (which only matches directories),
+/// when its leading components are ignored, or when the agent doesn't support it.
 #[cfg(target_os = "linux")]
-pub(crate) fn statx_logic(
-    dir_fd: RawFd,
-    path_name: *const c_char,
-    flags: c_int,
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn glob(pattern: Detour<PathBuf>) -> Detour<GlobResponse> {
+    let pattern = pattern?;
+
+    check_relative_paths!(pattern);
+
+    if pattern.as_os_str().as_bytes().ends_with(b"/") {
+        return Detour::Bypass(Bypass::NotImplemented);
+    }
+
+    let prefix = pattern
+        .components()
+        .take_while(|component| {
+            !component
+                .as_os_str()
+                .as_bytes()
+                .iter()
+                .any(|byte| matches!(byte, b'*' | b'?' | b'['))
+        })
+        .collect::<PathBuf>();
+
+    ensure_not_ignored!(prefix, false);
+
+    let requesting_glob = GlobRequest {
+        pattern: pattern.to_string_lossy().into_owned(),
+        max_results: MAX_GLOB_RESULTS,
+    };
+
+    fallback_on_not_implemented(requesting_glob)
+}
+
+/// `-1` (as `uid_t` or `gid_t`) leaves the owner (or group) as is, [`None`] in the protocol.
+fn owner_id(id: u32) -> Option<u32> {
+    (id != u32::MAX).then_some(id)
+}
+
+/// Result of a chown request for the hook.
+///
+/// When the agent doesn't support it, we fail with `ENOSYS` instead of changing the owner of the
+/// local file.
+fn chown_result(response: Detour<()>) -> Detour<c_int> {
+    response.map(|()| 0).or_bypass(|_| {
+        Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::ENOSYS,
+        )))
+    })
+}
+
+/// Changes the owner and group of the remote file at `path`.
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`).
+/// Fails with `ENOSYS` when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn chown(path: Detour<PathBuf>, owner: u32, group: u32) -> Detour<c_int> {
+    let path = path?;
+
+    check_relative_paths!(path);
+
+    let path = remap_path!(path);
+
+    redirect_to_overlay!(path, true);
+
+    ensure_not_ignored!(path, true);
+
+    let requesting_chown = ChownFileRequest {
+        path,
+        owner: owner_id(owner),
+        group: owner_id(group),
+    };
+
+    chown_result(fallback_on_not_implemented(requesting_chown))
+}
+
+/// Changes the owner and group of the remote file `local_fd`.
+///
+/// **Bypassed** when the file is local, or when it should not be written remotely.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fchown(local_fd: RawFd, owner: u32, group: u32) -> Detour<c_int> {
+    let (fd, path) = OPEN_FILES
+        .lock()?
+        .get(&local_fd)
+        .map(|remote_file| (remote_file.fd, PathBuf::from(&remote_file.path)))
+        .ok_or(Bypass::LocalFdNotFound(local_fd))?;
+
+    ensure_not_ignored!(path, true);
+
+    let requesting_chown = FchownFileRequest {
+        fd,
+        owner: owner_id(owner),
+        group: owner_id(group),
+    };
+
+    chown_result(fallback_on_not_implemented(requesting_chown))
+}
+
+/// Changes the owner and group of the remote file at `path`, `flags` are the `fchownat` ones
+/// (`lchown` is `AT_SYMLINK_NOFOLLOW`).
+///
+/// Like [`fchmodat`], a relative `path` is resolved by the agent against the remote directory
+/// `fd`, unless `fd` is [`AT_FDCWD`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fchownat(
+    fd: RawFd,
+    path: Detour<PathBuf>,
+    owner: u32,
+    group: u32,
+    flags: c_int,
+) -> Detour<c_int> {
+    let path = path?;
+    let (owner, group, flags) = (owner_id(owner), owner_id(group), flags as u32);
+
+    let requesting_chown = if path.is_absolute() || fd == AT_FDCWD {
+        check_relative_paths!(path);
+
+        let path = remap_path!(path);
+
+        redirect_to_overlay!(path, true);
+
+        ensure_not_ignored!(path, true);
+
+        ChownFileWithDirRequest {
+            dirfd: None,
+            path,
+            owner,
+            group,
+            flags,
+        }
+    } else {
+        ChownFileWithDirRequest {
+            dirfd: Some(get_remote_fd(fd)?),
+            path,
+            owner,
+            group,
+            flags,
+        }
+    };
+
+    chown_result(fallback_on_not_implemented(requesting_chown))
+}
+
+/// The access and modification times for the `times` of `utimensat` and `futimens`, `NULL`
+/// `times` set both to the current time.
+///
+/// Fails with `EINVAL` for nanoseconds out of range that are not `UTIME_NOW` or `UTIME_OMIT`.
+fn file_times(times: Option<&[timespec; 2]>) -> Detour<(FileTimeInternal, FileTimeInternal)> {
+    let file_time = |time: &timespec| match time.tv_nsec {
+        libc::UTIME_OMIT => Detour::Success(FileTimeInternal::Omit),
+        libc::UTIME_NOW => Detour::Success(FileTimeInternal::Now),
+        nanos @ 0..=999_999_999 => Detour::Success(FileTimeInternal::At(
+            time.tv_sec
+                .saturating_mul(1_000_000_000)
+                .saturating_add(nanos),
+        )),
+        _ => Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::EINVAL,
+        ))),
+    };
+
+    match times {
+        Some([access_time, modification_time]) => {
+            Detour::Success((file_time(access_time)?, file_time(modification_time)?))
+        }
+        None => Detour::Success((FileTimeInternal::Now, FileTimeInternal::Now)),
+    }
+}
+
+/// Sets the access and modification times of the remote file at `path`, `flags` are the
+/// `utimensat` ones.
+///
+/// Like [`fchmodat`], a relative `path` is resolved by the agent against the remote directory
+/// `fd`, unless `fd` is [`AT_FDCWD`].
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret, skip(times))]
+pub(crate) fn utimensat(
+    fd: RawFd,
+    path: Detour<PathBuf>,
+    times: Option<&[timespec; 2]>,
+    flags: c_int,
+) -> Detour<c_int> {
+    let path = path?;
+
+    let (dirfd, path) = if path.is_absolute() || fd == AT_FDCWD {
+        check_relative_paths!(path);
+
+        let path = remap_path!(path);
+
+        redirect_to_overlay!(path, true);
+
+        ensure_not_ignored!(path, true);
+
+        (None, path)
+    } else {
+        (Some(get_remote_fd(fd)?), path)
+    };
+
+    let (access_time, modification_time) = file_times(times)?;
+    let requesting_utimens = UtimensFileWithDirRequest {
+        dirfd,
+        path,
+        access_time,
+        modification_time,
+        flags: flags as u32,
+    };
+
+    fallback_on_not_implemented(requesting_utimens).map(|()| 0)
+}
+
+/// Sets the access and modification times of the remote file `local_fd`.
+///
+/// **Bypassed** when the file is local, when it should not be written remotely, or when the
+/// agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret, skip(times))]
+pub(crate) fn futimens(local_fd: RawFd, times: Option<&[timespec; 2]>) -> Detour<c_int> {
+    let (fd, path) = OPEN_FILES
+        .lock()?
+        .get(&local_fd)
+        .map(|remote_file| (remote_file.fd, PathBuf::from(&remote_file.path)))
+        .ok_or(Bypass::LocalFdNotFound(local_fd))?;
+
+    ensure_not_ignored!(path, true);
+
+    let (access_time, modification_time) = file_times(times)?;
+    let requesting_utimens = FutimensFileRequest {
+        fd,
+        access_time,
+        modification_time,
+    };
+
+    fallback_on_not_implemented(requesting_utimens).map(|()| 0)
+}
+
+/// Which side of the [`rename`] or [`renameat`] (or [`link`] and [`linkat`]) a path is on (also
+/// the link path of [`symlink`] and [`symlinkat`]): [`Detour::Success`] with the remote directory
+/// fd and the path to send to the agent, or [`Detour::Bypass`] when the path is local.
+fn rename_path(fd: RawFd, path: PathBuf) -> Detour<(Option<u64>, PathBuf)> {
+    if path.is_absolute() || fd == AT_FDCWD {
+        check_relative_paths!(path);
+
+        let path = remap_path!(path);
+
+        ensure_not_ignored!(path, true);
+
+        Detour::Success((None, path))
+    } else {
+        Detour::Success((Some(get_remote_fd(fd)?), path))
+    }
+}
+
+/// Checks that both paths of a rename (or a hard link) are remote.
+///
+/// **Bypassed** when both are local. A rename between a local and a remote path fails with
+/// `EXDEV`, like a rename across mount points, so that the app falls back to copying the file.
+fn rename_paths(
+    old: Detour<(Option<u64>, PathBuf)>,
+    new: Detour<(Option<u64>, PathBuf)>,
+) -> Detour<((Option<u64>, PathBuf), (Option<u64>, PathBuf))> {
+    match (old, new) {
+        (Detour::Success(old), Detour::Success(new)) => Detour::Success((old, new)),
+        (Detour::Error(fail), _) | (_, Detour::Error(fail)) => Detour::Error(fail),
+        (Detour::Bypass(bypass), Detour::Bypass(..)) => Detour::Bypass(bypass),
+        (Detour::Bypass(..), Detour::Success(..)) | (Detour::Success(..), Detour::Bypass(..)) => {
+            Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+                libc::EXDEV,
+            )))
+        }
+    }
+}
+
+/// Renames the remote file at `old_path` to `new_path`.
+///
+/// **Bypassed** when the files should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn rename(old_path: Detour<PathBuf>, new_path: Detour<PathBuf>) -> Detour<c_int> {
+    let ((_, old_path), (_, new_path)) = rename_paths(
+        rename_path(AT_FDCWD, old_path?),
+        rename_path(AT_FDCWD, new_path?),
+    )?;
+
+    let requesting_rename = RenameFileRequest { old_path, new_path };
+
+    fallback_on_not_implemented(requesting_rename).map(|()| 0)
+}
+
+/// Renames the remote file at `old_path` to `new_path`, `flags` are the `renameat2` ones.
+///
+/// Like [`unlinkat`], each relative path is resolved by the agent against its remote directory
+/// fd, unless the fd is [`AT_FDCWD`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn renameat(
+    old_fd: RawFd,
+    old_path: Detour<PathBuf>,
+    new_fd: RawFd,
+    new_path: Detour<PathBuf>,
+    flags: u32,
+) -> Detour<c_int> {
+    let ((old_dirfd, old_path), (new_dirfd, new_path)) = rename_paths(
+        rename_path(old_fd, old_path?),
+        rename_path(new_fd, new_path?),
+    )?;
+
+    let requesting_rename = RenameFileWithDirRequest {
+        old_dirfd,
+        old_path,
+        new_dirfd,
+        new_path,
+        flags,
+    };
+
+    fallback_on_not_implemented(requesting_rename).map(|()| 0)
+}
+
+/// Creates a remote hard link at `new_path` to the file at `old_path`.
+///
+/// **Bypassed** when the files should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it. Like [`rename`], linking a local and a remote path fails
+/// with `EXDEV`.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn link(old_path: Detour<PathBuf>, new_path: Detour<PathBuf>) -> Detour<c_int> {
+    let ((_, old_path), (_, new_path)) = rename_paths(
+        rename_path(AT_FDCWD, old_path?),
+        rename_path(AT_FDCWD, new_path?),
+    )?;
+
+    let requesting_link = LinkFileRequest { old_path, new_path };
+
+    fallback_on_not_implemented(requesting_link).map(|()| 0)
+}
+
+/// Creates a remote hard link at `new_path` to the file at `old_path`, following a symbolic link
+/// there when `flags` has [`AT_SYMLINK_FOLLOW`].
+///
+/// Like [`renameat`], each relative path is resolved by the agent against its remote directory
+/// fd, unless the fd is [`AT_FDCWD`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn linkat(
+    old_fd: RawFd,
+    old_path: Detour<PathBuf>,
+    new_fd: RawFd,
+    new_path: Detour<PathBuf>,
+    flags: c_int,
+) -> Detour<c_int> {
+    let ((old_dirfd, old_path), (new_dirfd, new_path)) = rename_paths(
+        rename_path(old_fd, old_path?),
+        rename_path(new_fd, new_path?),
+    )?;
+
+    let requesting_link = LinkFileWithDirRequest {
+        old_dirfd,
+        old_path,
+        new_dirfd,
+        new_path,
+        follow_symlinks: flags & AT_SYMLINK_FOLLOW != 0,
+    };
+
+    fallback_on_not_implemented(requesting_link).map(|()| 0)
+}
+
+/// Creates a remote symbolic link at `link_path` that points to `target`.
+///
+/// `target` is only the contents of the link, so it's sent as is, without checking it against
+/// the file filter.
+///
+/// **Bypassed** when the link should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn symlink(target: Detour<String>, link_path: Detour<PathBuf>) -> Detour<c_int> {
+    let target = PathBuf::from(target?);
+    let (_, link_path) = rename_path(AT_FDCWD, link_path?)?;
+
+    let requesting_symlink = SymlinkRequest { target, link_path };
+
+    fallback_on_not_implemented(requesting_symlink).map(|()| 0)
+}
+
+/// Like [`symlink`], but a relative `link_path` is resolved by the agent against the remote
+/// directory `fd`, unless `fd` is [`AT_FDCWD`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn symlinkat(
+    target: Detour<String>,
+    fd: RawFd,
+    link_path: Detour<PathBuf>,
+) -> Detour<c_int> {
+    let target = PathBuf::from(target?);
+    let (new_dirfd, link_path) = rename_path(fd, link_path?)?;
+
+    let requesting_symlink = SymlinkAtRequest {
+        target,
+        new_dirfd,
+        link_path,
+    };
+
+    fallback_on_not_implemented(requesting_symlink).map(|()| 0)
+}
+
+pub(crate) fn pwrite(local_fd: RawFd, buffer: &[u8], offset: u64) -> Detour<WriteFileResponse> {
+    let remote_fd = get_remote_fd(local_fd)?;
+    trace!("pwrite: local_fd {local_fd}");
+
+    let writing_file = WriteLimitedFileRequest {
+        remote_fd,
+        write_bytes: buffer.to_vec(),
+        start_from: offset,
+    };
+
+    let response = common::make_proxy_request_with_response(writing_file)??;
+
+    Detour::Success(response)
+}
+
+/// Moves the offset of the remote file `local_fd`.
+///
+/// `SEEK_DATA` and `SEEK_HOLE` find the data and the holes of sparse files in the agent. Agents
+/// that don't support them treat the file as if it had no holes, like the filesystems that don't
+/// track them: `SEEK_DATA` stays at `offset`, `SEEK_HOLE` goes to the end of the file, and both
+/// fail with `ENXIO` past the end.
+#[mirrord_layer_macro::instrument(level = "trace")]
+pub(crate) fn lseek(local_fd: RawFd, offset: i64, whence: i32) -> Detour<u64> {
+    let remote_fd = get_remote_fd(local_fd)?;
+
+    let no_such_offset = || {
+        Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::ENXIO,
+        )))
+    };
+
+    let seek_from = match whence {
+        libc::SEEK_SET => SeekFromInternal::Start(offset as u64),
+        libc::SEEK_CUR => SeekFromInternal::Current(offset),
+        libc::SEEK_END => SeekFromInternal::End(offset),
+        libc::SEEK_DATA | libc::SEEK_HOLE if offset < 0 => return no_such_offset(),
+        libc::SEEK_DATA => SeekFromInternal::Data(offset as u64),
+        libc::SEEK_HOLE => SeekFromInternal::Hole(offset as u64),
+        invalid => {
+            tracing::warn!(
+                "lseek -> potential invalid value {:#?} for whence {:#?}",
+                invalid,
+                whence
+            );
+            return Detour::Bypass(Bypass::CStrConversion);
+        }
+    };
+
+    let seeking_file = SeekFileRequest {
+        fd: remote_fd,
+        seek_from,
+    };
+
+    // Agents that don't support `SEEK_DATA` and `SEEK_HOLE` treat the whole file as data.
+    fallback_on_not_implemented(seeking_file)
+        .map(|SeekFileResponse { result_offset }| result_offset)
+        .or_bypass(|_| {
+            let XstatResponse { metadata } =
+                common::make_proxy_request_with_response(XstatRequest {
+                    path: None,
+                    fd: Some(remote_fd),
+                    follow_symlink: true,
+                })??;
+
+            let offset = offset as u64;
+            if offset >= metadata.size {
+                return no_such_offset();
+            }
+
+            let target = match seek_from {
+                SeekFromInternal::Data(..) => offset,
+                _ => metadata.size,
+            };
+
+            let SeekFileResponse { result_offset } =
+                common::make_proxy_request_with_response(SeekFileRequest {
+                    fd: remote_fd,
+                    seek_from: SeekFromInternal::Start(target),
+                })??;
+
+            Detour::Success(result_offset)
+        })
+}
+
+pub(crate) fn write(local_fd: RawFd, write_bytes: Option<Vec<u8>>) -> Detour<isize> {
+    let remote_fd = match get_remote_fd(local_fd) {
+        Detour::Bypass(Bypass::LocalFdNotFound(..)) => {
+            return crate::output::write(local_fd, write_bytes)
+        }
+        remote_fd => remote_fd?,
+    };
+
+    let writing_file = WriteFileRequest {
+        fd: remote_fd,
+        write_bytes: write_bytes.ok_or(Bypass::EmptyBuffer)?,
+    };
+
+    let WriteFileResponse { written_amount } =
+        common::make_proxy_request_with_response(writing_file)??;
+    Detour::Success(written_amount.try_into()?)
+}
+
+#[mirrord_layer_macro::instrument(level = "trace")]
+pub(crate) fn access(path: Detour<PathBuf>, mode: u8) -> Detour<c_int> {
+    let path = path?;
+
+    check_relative_paths!(path);
+
+    let path = remap_path!(path);
+
+    redirect_to_overlay!(path, false);
+
+    ensure_not_ignored!(path, false);
+
+    let access = AccessFileRequest {
+        pathname: path,
+        mode,
+    };
+
+    fallback_on_not_implemented(access).map(|_| 0)
+}
+
+/// Flushes the remote file `local_fd` to the disk of the agent, only its data when `data_only`
+/// (`fdatasync`).
+///
+/// Agents that don't support it keep the old behavior, returning `0` without flushing anything.
+///
+/// **Bypassed** when the file is local.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fsync(local_fd: RawFd, data_only: bool) -> Detour<c_int> {
+    let fd = get_remote_fd(local_fd)?;
+
+    let requesting_fsync = FsyncFileRequest { fd, data_only };
+
+    fallback_on_not_implemented(requesting_fsync)
+        .or_bypass(|_| Detour::Success(()))
+        .map(|()| 0)
+}
+
+/// Allocates `length` bytes at `offset` of the remote file `local_fd`, `posix` for
+/// `posix_fallocate`, which ignores the `mode`.
+///
+/// Agents that don't support it only extend the file with a truncate for `posix_fallocate`, as
+/// there's nothing to preallocate without the disk space guarantee, and `fallocate` fails with
+/// `EOPNOTSUPP`, the same as on filesystems that can't preallocate.
+///
+/// **Bypassed** when the file is local.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn fallocate(
+    local_fd: RawFd,
+    mode: c_int,
+    offset: off_t,
+    length: off_t,
+    posix: bool,
+) -> Detour<c_int> {
+    let fd = get_remote_fd(local_fd)?;
+
+    // Same as `truncate`, negative values fail with `EINVAL`.
+    let offset = truncate_length(offset)?;
+    let length = truncate_length(length)?;
+
+    let requesting_fallocate = FallocateFileRequest {
+        fd,
+        mode: if posix { 0 } else { mode as u32 },
+        offset,
+        length,
+        posix,
+    };
+
+    let not_supported = |_: Bypass| {
+        Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::EOPNOTSUPP,
+        )))
+    };
+
+    fallback_on_not_implemented(requesting_fallocate)
+        .or_bypass(|bypass| {
+            if !posix {
+                return not_supported(bypass);
+            }
+
+            let XstatResponse { metadata } =
+                common::make_proxy_request_with_response(XstatRequest {
+                    path: None,
+                    fd: Some(fd),
+                    follow_symlink: true,
+                })??;
+
+            let end = offset.saturating_add(length);
+            if end <= metadata.size {
+                return Detour::Success(());
+            }
+
+            fallback_on_not_implemented(FtruncateFileRequest { fd, length: end })
+                .or_bypass(not_supported)
+        })
+        .map(|()| 0)
+}
+
+/// Copies up to `length` bytes from the remote file `local_fd_in` to the remote file
+/// `local_fd_out` in the agent, without reading the data into the app. [`None`] offsets use (and
+/// advance) the file offsets.
+///
+/// Fails with `EXDEV` when only one of the files is remote, and with `ENOSYS` when the agent
+/// doesn't support it, the apps fall back to reading and writing on both.
+///
+/// **Bypassed** when both files are local.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn copy_file_range(
+    local_fd_in: RawFd,
+    offset_in: Option<off_t>,
+    local_fd_out: RawFd,
+    offset_out: Option<off_t>,
+    length: size_t,
+    flags: c_uint,
+) -> Detour<u64> {
+    let (fd_in, fd_out) = match (get_remote_fd(local_fd_in), get_remote_fd(local_fd_out)) {
+        (Detour::Success(fd_in), Detour::Success(fd_out)) => (fd_in, fd_out),
+        (Detour::Bypass(bypass), Detour::Bypass(..)) => return Detour::Bypass(bypass),
+        (Detour::Error(fail), _) | (_, Detour::Error(fail)) => return Detour::Error(fail),
+        _ => {
+            return Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+                libc::EXDEV,
+            )))
+        }
+    };
+
+    // No flags are defined yet, and negative offsets fail the same as in `truncate`.
+    if flags != 0 {
+        return Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::EINVAL,
+        )));
+    }
+    let offset_in = match offset_in {
+        Some(offset) => Some(truncate_length(offset)?),
+        None => None,
+    };
+    let offset_out = match offset_out {
+        Some(offset) => Some(truncate_length(offset)?),
+        None => None,
+    };
+
+    let requesting_copy = CopyFileRangeRequest {
+        fd_in,
+        offset_in,
+        fd_out,
+        offset_out,
+        length: length as u64,
+    };
+
+    fallback_on_not_implemented(requesting_copy)
+        .map(|CopyFileRangeResponse { copied_amount }| copied_amount)
+        .or_bypass(|_| {
+            Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+                libc::ENOSYS,
+            )))
+        })
+}
+
+/// How long [`sendfile`] waits for the data already written to an intercepted connection to leave
+/// the socket, before it falls back to writing the file itself.
+#[cfg(target_os = "linux")]
+const SEND_QUEUE_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sends up to `count` bytes from the remote file `local_in_fd` to `out_fd`, like `sendfile`.
+/// [`None`] offset uses (and advances) the file offset.
+///
+/// When possible, the data never reaches the app:
+///
+/// - to a remote file, it's copied in the agent like in [`copy_file_range`];
+/// - to a TCP connection intercepted by the internal proxy, the agent writes it to the connection
+///   (see [`SendFileToConnRequest`]).
+///
+/// Otherwise (or when the agent can't do it), the remote file is read and written to `out_fd`.
+/// Sends at most [`MAX_READ_SIZE`] bytes at once, which `sendfile` callers handle as any partial
+/// transfer.
+///
+/// **Bypassed** when `local_in_fd` is local.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn sendfile(
+    out_fd: RawFd,
+    local_in_fd: RawFd,
+    offset: Option<off_t>,
+    count: size_t,
+) -> Detour<u64> {
+    let fd = get_remote_fd(local_in_fd)?;
+    let offset = match offset {
+        Some(offset) => Some(truncate_length(offset)?),
+        None => None,
+    };
+    let count = (count as u64).min(MAX_READ_SIZE);
+
+    // `NotImplemented` errors here mean that the agent can't do it.
+    if let Detour::Success(fd_out) = get_remote_fd(out_fd) {
+        let requesting_copy = CopyFileRangeRequest {
+            fd_in: fd,
+            offset_in: offset,
+            fd_out,
+            offset_out: None,
+            length: count,
+        };
+
+        let copied = fallback_on_not_implemented(requesting_copy)
+            .map(Some)
+            .or_bypass(|_| Detour::Success(None))?;
+        if let Some(CopyFileRangeResponse { copied_amount }) = copied {
+            return Detour::Success(copied_amount);
+        }
+    } else if let Some(connection) = intercepted_connection(out_fd) {
+        let requesting_send = SendFileToConnRequest {
+            connection,
+            fd,
+            offset,
+            count,
+        };
+
+        match common::make_proxy_request_with_response(requesting_send)? {
+            Ok(SendFileResponse { sent_amount }) => return Detour::Success(sent_amount),
+            // The connection is not intercepted by the main agent, or it's already closed, or the
+            // agent throttled the request (the reads of the fallback are retried by the internal
+            // proxy, and an app writing to a blocking socket doesn't expect `EAGAIN`).
+            Err(
+                ResponseError::NotImplemented
+                | ResponseError::NotFound(..)
+                | ResponseError::Throttled { .. },
+            ) => {}
+            Err(fail) => return Detour::Error(fail.into()),
+        }
+    }
+
+    sendfile_with_write(out_fd, local_in_fd, offset, count)
+}
+
+/// Returns the [`InterceptedConnection`] of the socket `fd`, once the data that the app already
+/// wrote to it left the socket, so that it reaches the agent before the file.
+///
+/// [`None`] when the socket is not intercepted, or the data didn't leave it in
+/// [`SEND_QUEUE_DRAIN_TIMEOUT`].
+#[cfg(target_os = "linux")]
+fn intercepted_connection(fd: RawFd) -> Option<InterceptedConnection> {
+    let connection = crate::socket::SOCKETS
+        .lock()
+        .ok()?
+        .get(&fd)?
+        .intercepted_connection(fd)?;
+
+    let deadline = Instant::now() + SEND_QUEUE_DRAIN_TIMEOUT;
+    loop {
+        let mut queued: c_int = 0;
+        if unsafe { libc::ioctl(fd, libc::TIOCOUTQ, &mut queued) } != 0 {
+            return None;
+        }
+
+        if queued == 0 {
+            break Some(connection);
+        }
+
+        if Instant::now() >= deadline {
+            trace!(fd, queued, "Data did not leave the socket in time");
+            break None;
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Fallback of [`sendfile`], reads from the remote file and writes to `out_fd` like the app would.
+///
+/// When not all bytes are written, the file offset is moved back to the first byte not written.
+#[cfg(target_os = "linux")]
+fn sendfile_with_write(
+    out_fd: RawFd,
+    local_in_fd: RawFd,
+    offset: Option<u64>,
+    count: u64,
+) -> Detour<u64> {
+    let ReadFileResponse { bytes, .. } = match offset {
+        Some(offset) => pread(local_in_fd, count, offset)?,
+        None => read(local_in_fd, count)?,
+    };
+    if bytes.is_empty() {
+        return Detour::Success(0);
+    }
+    let read_amount = bytes.len();
+
+    let written = match write(out_fd, Some(bytes.clone())) {
+        Detour::Success(written) => Ok(written),
+        Detour::Bypass(..) => {
+            match unsafe { FN_WRITE(out_fd, bytes.as_ptr().cast(), read_amount) } {
+                -1 => Err(HookError::IO(std::io::Error::last_os_error())),
+                written => Ok(written),
+            }
+        }
+        Detour::Error(fail) => Err(fail),
+    };
+
+    let written_amount = written.as_ref().map_or(0, |written| *written as usize);
+    if offset.is_none() && written_amount < read_amount {
+        let unwritten = (read_amount - written_amount) as i64;
+        let _ = lseek(local_in_fd, -unwritten, libc::SEEK_CUR);
+    }
+
+    match written {
+        Ok(..) => Detour::Success(written_amount as u64),
+        Err(fail) => Detour::Error(fail),
+    }
+}
+
+/// The first wait before we ask the agent again for a lock that someone else holds, it doubles up
+/// to [`MAX_LOCK_RETRY_INTERVAL`].
+const MIN_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Longest wait between two attempts to place a lock, see [`place_lock`].
+const MAX_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sends the lock `request` to the agent, and keeps sending it while someone else holds the lock
+/// when `wait` is set (`F_SETLKW`, or `flock` without `LOCK_NB`), the agent never waits for the
+/// locks itself.
+fn place_lock<T>(request: T, wait: bool) -> Detour<c_int>
+where
+    T: IsLayerRequestWithResponse<Response = RemoteResult<()>> + Clone + Debug,
+{
+    let mut retry_interval = MIN_LOCK_RETRY_INTERVAL;
+
+    loop {
+        // `NotImplemented` error here means that the protocol doesn't support it.
+        match common::make_proxy_request_with_response(request.clone())? {
+            Ok(()) => return Detour::Success(0),
+            Err(ResponseError::NotImplemented) => return Detour::Bypass(Bypass::NotImplemented),
+            Err(ResponseError::RemoteIO(RemoteIOError {
+                kind: ErrorKindInternal::WouldBlock,
+                ..
+            })) if wait => {
+                thread::sleep(retry_interval);
+                retry_interval = (retry_interval * 2).min(MAX_LOCK_RETRY_INTERVAL);
+            }
+            Err(fail) => return Detour::Error(fail.into()),
+        }
+    }
+}
+
+/// Places the `flock` lock of `operation` on the remote file `local_fd`, in the agent, so it
+/// conflicts with the locks of the other clients and of the target's processes.
+///
+/// **Bypassed** when the file is local, or when the agent doesn't support it (the local file is
+/// locked then).
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn flock(local_fd: RawFd, operation: c_int) -> Detour<c_int> {
+    let fd = get_remote_fd(local_fd)?;
+
+    let lock_type = match operation & !libc::LOCK_NB {
+        libc::LOCK_SH => LockTypeInternal::Shared,
+        libc::LOCK_EX => LockTypeInternal::Exclusive,
+        libc::LOCK_UN => LockTypeInternal::Unlock,
+        _ => {
+            return Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+                libc::EINVAL,
+            )))
+        }
+    };
+
+    place_lock(
+        FlockRequest { fd, lock_type },
+        operation & libc::LOCK_NB == 0,
+    )
+}
+
+/// Whether [`record_lock`] handles the `fcntl` command `cmd`.
+pub(crate) fn is_record_lock_command(cmd: c_int) -> bool {
+    #[cfg(target_os = "linux")]
+    let ofd_lock = matches!(
+        cmd,
+        libc::F_OFD_SETLK | libc::F_OFD_SETLKW | libc::F_OFD_GETLK
+    );
+    #[cfg(not(target_os = "linux"))]
+    let ofd_lock = false;
+
+    ofd_lock || matches!(cmd, libc::F_SETLK | libc::F_SETLKW | libc::F_GETLK)
+}
+
+/// The `F_SETLK`, `F_SETLKW` and `F_GETLK` commands of `fcntl` on the remote file `local_fd`,
+/// `lock` is the `fcntl` argument.
+///
+/// The locks are placed in the agent, and they belong to the remote file like `F_OFD_SETLK` ones,
+/// not to the process: closing another descriptor of the file doesn't release them, and the
+/// conflicting locks of `F_GETLK` have an `l_pid` of `-1`.
+///
+/// **Bypassed** when the file is local, or when the agent doesn't support it.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn record_lock(local_fd: RawFd, cmd: c_int, lock: *mut libc::flock) -> Detour<c_int> {
+    let fd = get_remote_fd(local_fd)?;
+    // SAFETY: we don't check pointers passed as arguments to hooked functions
+    let lock = unsafe { lock.as_mut().ok_or(HookError::BadPointer)? };
+
+    let invalid = || HookError::IO(std::io::Error::from_raw_os_error(libc::EINVAL));
+
+    let lock_type = match c_int::from(lock.l_type) {
+        libc::F_RDLCK => LockTypeInternal::Shared,
+        libc::F_WRLCK => LockTypeInternal::Exclusive,
+        libc::F_UNLCK => LockTypeInternal::Unlock,
+        _ => return Detour::Error(invalid()),
+    };
+    let start = match c_int::from(lock.l_whence) {
+        libc::SEEK_SET => SeekFromInternal::Start(lock.l_start.try_into().map_err(|_| invalid())?),
+        libc::SEEK_CUR => SeekFromInternal::Current(lock.l_start),
+        libc::SEEK_END => SeekFromInternal::End(lock.l_start),
+        _ => return Detour::Error(invalid()),
+    };
+    let requested = FileLockInternal {
+        lock_type,
+        start,
+        len: lock.l_len,
+    };
+
+    #[cfg(target_os = "linux")]
+    let get_lock = matches!(cmd, libc::F_GETLK | libc::F_OFD_GETLK);
+    #[cfg(not(target_os = "linux"))]
+    let get_lock = cmd == libc::F_GETLK;
+
+    if !get_lock {
+        #[cfg(target_os = "linux")]
+        let wait = matches!(cmd, libc::F_SETLKW | libc::F_OFD_SETLKW);
+        #[cfg(not(target_os = "linux"))]
+        let wait = cmd == libc::F_SETLKW;
+
+        return place_lock(
+            SetLockRequest {
+                fd,
+                lock: requested,
+            },
+            wait,
+        );
+    }
+
+    let requesting_lock = GetLockRequest {
+        fd,
+        lock: requested,
+    };
+
+    let GetLockResponse { conflict } = fallback_on_not_implemented(requesting_lock)?;
+
+    match conflict {
+        None => {
+            lock.l_type = libc::F_UNLCK as _;
+        }
+        Some(conflict) => {
+            lock.l_type = match conflict.lock_type {
+                LockTypeInternal::Shared => libc::F_RDLCK,
+                LockTypeInternal::Exclusive | LockTypeInternal::Unlock => libc::F_WRLCK,
+            } as _;
+            lock.l_whence = libc::SEEK_SET as _;
+            lock.l_start = match conflict.start {
+                SeekFromInternal::Start(start)
+                | SeekFromInternal::Data(start)
+                | SeekFromInternal::Hole(start) => start.try_into().unwrap_or(off_t::MAX),
+                // The agent only sends the start from the beginning of the file.
+                SeekFromInternal::Current(start) | SeekFromInternal::End(start) => start,
+            };
+            lock.l_len = conflict.len;
+            // The lock belongs to a remote file, not to a local process.
+            lock.l_pid = -1;
+        }
+    }
+
+    Detour::Success(0)
+}
+
+/// The `path` or the remote fd of the `local_fd` that the `*xattr` calls operate on, `write` is
+/// set for `setxattr` and `removexattr`.
+///
+/// **Bypassed** when the file is local, or when it should not be read (or written) remotely.
+#[cfg(target_os = "linux")]
+fn xattr_file(
+    path: Option<Detour<PathBuf>>,
+    local_fd: Option<RawFd>,
+    write: bool,
+) -> Detour<(Option<PathBuf>, Option<u64>)> {
+    match (path, local_fd) {
+        (Some(path), _) => {
+            let path = path?;
+
+            check_relative_paths!(path);
+
+            let path = remap_path!(path);
+
+            redirect_to_overlay!(path, write);
+
+            ensure_not_ignored!(path, write);
+
+            Detour::Success((Some(path), None))
+        }
+        (None, Some(local_fd)) => {
+            let (fd, path) = OPEN_FILES
+                .lock()?
+                .get(&local_fd)
+                .map(|remote_file| (remote_file.fd, PathBuf::from(&remote_file.path)))
+                .ok_or(Bypass::LocalFdNotFound(local_fd))?;
+
+            if write {
+                ensure_not_ignored!(path, true);
+            }
+
+            Detour::Success((None, Some(fd)))
+        }
+        (None, None) => Detour::Error(HookError::NullPointer),
+    }
+}
+
+/// The attribute name of the `*xattr` calls.
+#[cfg(target_os = "linux")]
+fn xattr_name(name: *const c_char) -> Detour<Vec<u8>> {
+    if name.is_null() {
+        return Detour::Error(HookError::BadPointer);
+    }
+
+    // SAFETY: we don't check pointers passed as arguments to hooked functions
+    Detour::Success(unsafe { CStr::from_ptr(name) }.to_bytes().to_vec())
+}
+
+/// Copies `data` to the caller's `buffer` of `size` bytes, like the `*getxattr` and `*listxattr`
+/// calls do: a `size` of `0` only asks for the length of `data`, and a smaller `buffer` fails with
+/// `ERANGE`.
+#[cfg(target_os = "linux")]
+fn copy_xattr_data(data: &[u8], buffer: *mut c_void, size: size_t) -> Detour<ssize_t> {
+    // A `Vec` is never longer than `isize::MAX`.
+    let length = data.len() as ssize_t;
+
+    if size == 0 {
+        return Detour::Success(length);
+    } else if data.len() > size {
+        return Detour::Error(HookError::IO(std::io::Error::from_raw_os_error(
+            libc::ERANGE,
+        )));
+    } else if buffer.is_null() {
+        return Detour::Error(HookError::BadPointer);
+    }
+
+    // SAFETY: we don't check pointers passed as arguments to hooked functions
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), buffer.cast(), data.len()) };
+
+    Detour::Success(length)
+}
+
+/// Reads the extended attribute `name` of the remote file at `path`, or of the remote file
+/// `local_fd`, into the caller's `value` buffer of `size` bytes.
+///
+/// **Bypassed** when the file is local, or when the agent doesn't support it (the local
+/// attributes are read then).
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn get_xattr(
+    path: Option<Detour<PathBuf>>,
+    local_fd: Option<RawFd>,
+    follow_symlink: bool,
+    name: *const c_char,
+    value: *mut c_void,
+    size: size_t,
+) -> Detour<ssize_t> {
+    let (path, fd) = xattr_file(path, local_fd, false)?;
+
+    let requesting_xattr = GetXattrRequest {
+        path,
+        fd,
+        follow_symlink,
+        name: xattr_name(name)?,
+    };
+
+    fallback_on_not_implemented(requesting_xattr)
+        .and_then(|GetXattrResponse { value: data }| copy_xattr_data(&data, value, size))
+}
+
+/// Sets the extended attribute `name` of the remote file at `path`, or of the remote file
+/// `local_fd`, `flags` are the `setxattr` ones.
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn set_xattr(
+    path: Option<Detour<PathBuf>>,
+    local_fd: Option<RawFd>,
+    follow_symlink: bool,
+    name: *const c_char,
+    value: *const c_void,
+    size: size_t,
+    flags: c_int,
+) -> Detour<c_int> {
+    let (path, fd) = xattr_file(path, local_fd, true)?;
+
+    let value = if size == 0 {
+        Vec::new()
+    } else if value.is_null() {
+        return Detour::Error(HookError::BadPointer);
+    } else {
+        // SAFETY: we don't check pointers passed as arguments to hooked functions
+        unsafe { slice::from_raw_parts(value.cast::<u8>(), size) }.to_vec()
+    };
+
+    let requesting_xattr = SetXattrRequest {
+        path,
+        fd,
+        follow_symlink,
+        name: xattr_name(name)?,
+        value,
+        flags,
+    };
+
+    fallback_on_not_implemented(requesting_xattr).map(|()| 0)
+}
+
+/// Lists the names of the extended attributes of the remote file at `path`, or of the remote file
+/// `local_fd`, into the caller's `list` buffer of `size` bytes, each name nul terminated.
+///
+/// **Bypassed** when the file is local, or when the agent doesn't support it.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn list_xattr(
+    path: Option<Detour<PathBuf>>,
+    local_fd: Option<RawFd>,
+    follow_symlink: bool,
+    list: *mut c_char,
+    size: size_t,
+) -> Detour<ssize_t> {
+    let (path, fd) = xattr_file(path, local_fd, false)?;
+
+    let requesting_xattr = ListXattrRequest {
+        path,
+        fd,
+        follow_symlink,
+    };
+
+    fallback_on_not_implemented(requesting_xattr).and_then(|ListXattrResponse { names }| {
+        let data = names
+            .into_iter()
+            .flat_map(|mut name| {
+                name.push(0);
+                name
+            })
+            .collect::<Vec<_>>();
+
+        copy_xattr_data(&data, list.cast(), size)
+    })
+}
+
+/// Removes the extended attribute `name` of the remote file at `path`, or of the remote file
+/// `local_fd`.
+///
+/// **Bypassed** when the file should not be written remotely (e.g. `fs` mode is not `write`), or
+/// when the agent doesn't support it.
+#[cfg(target_os = "linux")]
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn remove_xattr(
+    path: Option<Detour<PathBuf>>,
+    local_fd: Option<RawFd>,
+    follow_symlink: bool,
+    name: *const c_char,
+) -> Detour<c_int> {
+    let (path, fd) = xattr_file(path, local_fd, true)?;
+
+    let requesting_xattr = RemoveXattrRequest {
+        path,
+        fd,
+        follow_symlink,
+        name: xattr_name(name)?,
+    };
+
+    fallback_on_not_implemented(requesting_xattr).map(|()| 0)
+}
+
+/// General stat function that can be used for lstat, fstat, stat and fstatat.
+/// Note: We treat cases of `AT_SYMLINK_NOFOLLOW_ANY` as `AT_SYMLINK_NOFOLLOW` because even Go does
+/// that.
+/// rawish_path is Option<Option<&CStr>> because we need to differentiate between null pointer
+/// and non existing argument (For error handling)
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(crate) fn xstat(
+    rawish_path: Option<Detour<PathBuf>>,
+    fd: Option<RawFd>,
+    follow_symlink: bool,
+) -> Detour<XstatResponse> {
+    // Can't use map because we need to propagate captured error
+    let (path, fd) = match (rawish_path, fd) {
+        // fstatat
+        (Some(path), Some(fd)) => {
+            let mut path = path?;
+            let fd = {
+                if fd == AT_FDCWD {
+                    check_relative_paths!(path);
+
+                    path = remap_path!(path);
+                    redirect_to_overlay!(path, false);
+                    ensure_not_ignored!(path, false);
+                    None
+                } else {
+                    Some(get_remote_fd(fd)?)
+                }
+            };
+            (Some(path), fd)
+        }
+        // lstat/stat
+        (Some(path), None) => {
+            let path = path?;
+
+            check_relative_paths!(path);
+
+            let path = remap_path!(path);
+
+            redirect_to_overlay!(path, false);
+
+            ensure_not_ignored!(path, false);
+            (Some(path), None)
+        }
+        // fstat
+        (None, Some(fd)) => (None, Some(get_remote_fd(fd)?)),
+        // can't happen
+        (None, None) => return Detour::Error(HookError::NullPointer),
+    };
+
+    let lstat = XstatRequest {
+        fd,
+        path,
+        follow_symlink,
+    };
+
+    fallback_on_not_implemented(lstat)
+}
+
+/// Logic for the `libc::statx` function.
+/// See [manual](https://man7.org/linux/man-pages/man2/statx.2.html) for reference.
+///
+/// The agent runs `statx` itself, so the extended fields (e.g. [`statx::stx_btime`] and
+/// [`statx::stx_mnt_id`]) are the remote ones.
+///
+/// # Warning
+///
+/// Agents that don't support [`StatxRequest`] only have the classic stat, so we use
+/// [`XstatRequest`] with them instead, and fill only the basic fields of the [`struct@statx`]
+/// structure. Luckily, [`statx::stx_mask`] allows us to inform the caller about the other
+/// fields being skipped.
+#[cfg(target_os = "linux")]
+pub(crate) fn statx_logic(
+    dir_fd: RawFd,
+    path_name: *const c_char,
+    flags: c_int,
     mask: c_int,
     statx_buf: *mut statx,
 ) -> Detour<c_int> {
@@ -516,12 +2102,11 @@ pub(crate) fn statx_logic(
     }
 
     let (fd, path) = if path_name.is_absolute() {
+        redirect_to_overlay!(path_name, false);
         ensure_not_ignored!(path_name, false);
         (None, Some(path_name))
     } else if !path_name.as_os_str().is_empty() && dir_fd == libc::AT_FDCWD {
-        return Detour::Bypass(Bypass::relative_path(
-            path_name.to_str().unwrap_or_default(),
-        ));
+        return Detour::Bypass(Bypass::relative_path(path_name.as_os_str().as_bytes()));
     } else if !path_name.as_os_str().is_empty() {
         (Some(get_remote_fd(dir_fd)?), Some(path_name))
     } else if (flags & libc::AT_EMPTY_PATH) != 0 {
@@ -530,22 +2115,36 @@ pub(crate) fn statx_logic(
         return Detour::Error(HookError::EmptyPath);
     };
 
-    let response = {
-        let fd = fd
-            .map(u64::try_from)
-            .transpose()
-            .map_err(|_| HookError::BadDescriptor)?;
-        let follow_symlink = (flags & libc::AT_SYMLINK_NOFOLLOW) == 0;
-
-        let request = XstatRequest {
-            fd,
-            path,
-            follow_symlink,
-        };
+    let fd = fd
+        .map(u64::try_from)
+        .transpose()
+        .map_err(|_| HookError::BadDescriptor)?;
+    let follow_symlink = (flags & libc::AT_SYMLINK_NOFOLLOW) == 0;
 
-        common::make_proxy_request_with_response(request)??.metadata
+    let request = StatxRequest {
+        dirfd: fd,
+        path: path.clone(),
+        follow_symlink,
+        mask: mask as u32,
     };
 
+    let statx_response = fallback_on_not_implemented(request)
+        .map(|StatxResponse { metadata }| *metadata)
+        .or_bypass(|_| {
+            let request = XstatRequest {
+                fd,
+                path,
+                follow_symlink,
+            };
+
+            Detour::Success(StatxMetadataInternal {
+                metadata: fallback_on_not_implemented(request)?.metadata,
+                mask: libc::STATX_BASIC_STATS,
+                ..Default::default()
+            })
+        })?;
+    let response = statx_response.metadata;
+
     /// Converts a nanosecond timestamp from
     /// [`MetadataInternal`](mirrord_protocol::file::MetadataInternal) to [`statx_timestamp`]
     /// format.
@@ -568,18 +2167,9 @@ pub(crate) fn statx_logic(
 
     // SAFETY: all-zero statx struct is valid
     *statx_buf = unsafe { std::mem::zeroed() };
-    statx_buf.stx_mask = libc::STATX_TYPE
-        & libc::STATX_MODE
-        & libc::STATX_NLINK
-        & libc::STATX_UID
-        & libc::STATX_GID
-        & libc::STATX_ATIME
-        & libc::STATX_MTIME
-        & libc::STATX_CTIME
-        & libc::STATX_INO
-        & libc::STATX_SIZE
-        & libc::STATX_BLOCKS;
-    statx_buf.stx_attributes_mask = 0;
+    statx_buf.stx_mask = statx_response.mask;
+    statx_buf.stx_attributes = statx_response.attributes;
+    statx_buf.stx_attributes_mask = statx_response.attributes_mask;
 
     statx_buf.stx_blksize = response.block_size.try_into().unwrap_or(u32::MAX);
     statx_buf.stx_nlink = response.hard_links.try_into().unwrap_or(u32::MAX);
@@ -592,6 +2182,10 @@ pub(crate) fn statx_logic(
     statx_buf.stx_atime = nanos_to_statx(response.access_time);
     statx_buf.stx_ctime = nanos_to_statx(response.creation_time);
     statx_buf.stx_mtime = nanos_to_statx(response.modification_time);
+    statx_buf.stx_btime = nanos_to_statx(statx_response.birth_time);
+    statx_buf.stx_mnt_id = statx_response.mount_id;
+    statx_buf.stx_dio_mem_align = statx_response.dio_mem_align;
+    statx_buf.stx_dio_offset_align = statx_response.dio_offset_align;
     let (major, minor) = device_id_to_statx(response.rdevice_id);
     statx_buf.stx_rdev_major = major;
     statx_buf.stx_rdev_minor = minor;
@@ -613,6 +2207,37 @@ pub(crate) fn xstatfs(fd: RawFd) -> Detour<XstatFsResponse> {
     Detour::Success(response)
 }
 
+/// `statfs` of the remote filesystem that `path` is on, the local one when the agent doesn't
+/// support [`StatFsRequest`].
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
+pub(crate) fn statfs_path(path: Detour<PathBuf>) -> Detour<XstatFsResponse> {
+    let path = path?;
+
+    check_relative_paths!(path);
+
+    let path = remap_path!(path);
+
+    redirect_to_overlay!(path, false);
+
+    ensure_not_ignored!(path, false);
+
+    fallback_on_not_implemented(StatFsRequest { path })
+}
+
+/// The path to watch remotely, bypassed when `path` stays local, see [`super::watch`].
+#[cfg(target_os = "linux")]
+pub(crate) fn watch_path(path: PathBuf) -> Detour<PathBuf> {
+    check_relative_paths!(path);
+
+    let path = remap_path!(path);
+
+    redirect_to_overlay!(path, false);
+
+    ensure_not_ignored!(path, false);
+
+    Detour::Success(path)
+}
+
 #[cfg(target_os = "linux")]
 #[mirrord_layer_macro::instrument(level = "trace")]
 pub(crate) fn getdents64(fd: RawFd, buffer_size: u64) -> Detour<GetDEnts64Response> {
@@ -648,6 +2273,10 @@ fn absolute_path(path: PathBuf) -> PathBuf {
     temp_path
 }
 
+/// Resolves `path` in the remote filesystem, like `realpath` does.
+///
+/// Agents that don't support [`CanonicalizePathRequest`] only check that the file exists, with
+/// `.` and `..` resolved locally, and the symbolic links left as they are.
 #[mirrord_layer_macro::instrument(level = "trace")]
 pub(crate) fn realpath(path: Detour<PathBuf>) -> Detour<PathBuf> {
     let path = path?;
@@ -656,14 +2285,18 @@ pub(crate) fn realpath(path: Detour<PathBuf>) -> Detour<PathBuf> {
 
     let path = remap_path!(path);
 
-    let realpath = absolute_path(path);
+    let realpath = absolute_path(path.clone());
 
     ensure_not_ignored!(realpath, false);
 
-    // check that file exists
-    xstat(Some(Detour::Success(realpath.clone())), None, true)?;
+    fallback_on_not_implemented(CanonicalizePathRequest { path })
+        .map(|CanonicalizePathResponse { path }| path)
+        .or_bypass(|_| {
+            // check that file exists
+            xstat(Some(Detour::Success(realpath.clone())), None, true)?;
 
-    Detour::Success(realpath)
+            Detour::Success(realpath)
+        })
 }
 
 #[cfg(test)]
@@ -0,0 +1,134 @@
+//! Local overlay for the
+//! [`FsModeConfig::CopyOnWrite`](mirrord_config::feature::fs::FsModeConfig::CopyOnWrite) mode, see
+//! [`Overlay`].
+
+use std::{
+    ffi::CString,
+    fs,
+    io::Write,
+    os::unix::{
+        ffi::OsStringExt,
+        fs::{OpenOptionsExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use mirrord_protocol::{
+    file::{
+        MetadataInternal, OpenFileResponse, OpenOptionsInternal, ReadFileResponse, XstatRequest,
+        XstatResponse,
+    },
+    ErrorKindInternal, RemoteIOError, ResponseError,
+};
+
+use super::ops::{RemoteFile, MAX_READ_SIZE};
+use crate::{
+    common,
+    detour::{Bypass, Detour},
+};
+
+/// Local directory that takes the writes to remote files, so that they never reach the remote.
+///
+/// A remote path is mirrored under the overlay directory, e.g. `/var/app/data.json` is
+/// `<dir>/var/app/data.json`. The first write to a remote file copies it to the overlay, and from
+/// then on every operation on the path is done locally on the copy (see [`Bypass::Overlay`]).
+#[derive(Debug)]
+pub(crate) struct Overlay {
+    dir: PathBuf,
+}
+
+impl Overlay {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Decides where the operation on the absolute remote `path` is done.
+    ///
+    /// Exits with [`Bypass::Overlay`] when `path` already has a copy in the overlay, or when the
+    /// operation `write`s to it, copying the remote file first. Otherwise the operation goes to the
+    /// remote as usual.
+    pub(crate) fn redirect(&self, path: &Path, write: bool) -> Detour<()> {
+        let overlay_path = self.dir.join(path.strip_prefix("/").unwrap_or(path));
+
+        let redirect = overlay_path.symlink_metadata().is_ok()
+            || (write && Self::copy_up(path, &overlay_path)?);
+
+        if redirect {
+            let overlay_path = CString::new(overlay_path.into_os_string().into_vec())?;
+            Detour::Bypass(Bypass::Overlay(overlay_path))
+        } else {
+            Detour::Success(())
+        }
+    }
+
+    /// Copies the remote `path` to `overlay_path`.
+    ///
+    /// Returns whether the operation can be done in the overlay: a path that doesn't exist in the
+    /// remote is created there by the operation, but only regular files and directories are
+    /// copied, anything else is left to the remote.
+    fn copy_up(path: &Path, overlay_path: &Path) -> Detour<bool> {
+        if let Some(parent) = overlay_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let stat = XstatRequest {
+            path: Some(path.to_path_buf()),
+            fd: None,
+            follow_symlink: true,
+        };
+
+        let metadata = match common::make_proxy_request_with_response(stat)? {
+            Ok(XstatResponse { metadata }) => metadata,
+            Err(ResponseError::RemoteIO(RemoteIOError {
+                kind: ErrorKindInternal::NotFound,
+                ..
+            })) => return Detour::Success(true),
+            Err(fail) => return Detour::Error(fail.into()),
+        };
+
+        match metadata.mode & libc::S_IFMT as u32 {
+            mode if mode == libc::S_IFDIR as u32 => fs::create_dir_all(overlay_path)?,
+            mode if mode == libc::S_IFREG as u32 => {
+                let copied = Self::copy_file(path, overlay_path, &metadata);
+                if copied.is_err() {
+                    // Don't leave a partial copy behind, it would be used from now on.
+                    let _ = fs::remove_file(overlay_path);
+                }
+                copied?
+            }
+            _ => return Detour::Success(false),
+        }
+
+        Detour::Success(true)
+    }
+
+    /// Copies the contents and the mode of the remote regular file at `path`.
+    fn copy_file(path: &Path, overlay_path: &Path, metadata: &MetadataInternal) -> Detour<()> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(overlay_path)?;
+
+        let open_options = OpenOptionsInternal {
+            read: true,
+            ..Default::default()
+        };
+        let OpenFileResponse { fd } = RemoteFile::remote_open(path.to_path_buf(), open_options)?;
+
+        let copied = (|| loop {
+            let ReadFileResponse { bytes, .. } = RemoteFile::remote_read(fd, MAX_READ_SIZE)?;
+            if bytes.is_empty() {
+                break Detour::Success(());
+            }
+            file.write_all(&bytes)?;
+        })();
+
+        RemoteFile::remote_close(fd)?;
+        copied?;
+
+        file.set_permissions(fs::Permissions::from_mode(metadata.mode & 0o7777))?;
+
+        Detour::Success(())
+    }
+}
@@ -0,0 +1,348 @@
+//! The view of the filesystem that the local process has, when it's not the one of the user that
+//! ran mirrord, see [`RootView`].
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::{RwLock, RwLockReadGuard},
+};
+
+use super::mapper::FileRemapper;
+
+/// Path of the root of the process in the view of the user, after the [`chroot`] hook. The exec
+/// hooks add it to the environment of the processes it execs (see [`RootView::root`]), without
+/// them it's set in the environment of the process.
+///
+/// [`chroot`]: super::hooks::chroot_detour
+pub(crate) const ROOT_ENV: &str = "MIRRORD_LAYER_ROOT";
+
+/// Mount namespace of the first process that loaded the layer.
+#[cfg(target_os = "linux")]
+const MOUNT_NS_ENV: &str = "MIRRORD_LAYER_MOUNT_NS";
+
+/// Mounts of the first process that loaded the layer, see [`mounts::Mount`].
+#[cfg(target_os = "linux")]
+const MOUNTS_ENV: &str = "MIRRORD_LAYER_MOUNTS";
+
+/// Translates the paths of a process that chroots (or runs in a sandbox like `bwrap`, with a
+/// different mount namespace) between its view of the filesystem and the one of the user that
+/// ran mirrord.
+///
+/// The fs rules (and the `mapping`) are written by the user, so they're evaluated in the view of
+/// the user, while the agent gets the paths as the process sees them.
+#[derive(Debug)]
+pub(crate) struct RootView {
+    /// Directories of the process, with the paths of the same directories in the view of the
+    /// user, longest first. Empty when the process sees what the user sees.
+    binds: RwLock<Vec<(PathBuf, PathBuf)>>,
+    /// Root of the process in the view of the user, when it (or one of its parents) chrooted.
+    root: RwLock<Option<PathBuf>>,
+}
+
+impl RootView {
+    /// Picks the view that this process inherited, [`ROOT_ENV`] when one of its parents chrooted,
+    /// or the mounts it sees when it's in another mount namespace (only on Linux).
+    pub(crate) fn from_env() -> Self {
+        let root = std::env::var_os(ROOT_ENV).map(PathBuf::from);
+        let binds = match &root {
+            Some(root) => vec![(PathBuf::from("/"), root.clone())],
+            #[cfg(target_os = "linux")]
+            None => mounts::binds(),
+            #[cfg(not(target_os = "linux"))]
+            None => Vec::new(),
+        };
+
+        if !binds.is_empty() {
+            tracing::debug!(?binds, "Process has its own view of the filesystem");
+        }
+
+        Self {
+            root: RwLock::new(root),
+            ..Self::new(binds)
+        }
+    }
+
+    fn new(mut binds: Vec<(PathBuf, PathBuf)>) -> Self {
+        binds.sort_by_key(|(path, _)| std::cmp::Reverse(path.as_os_str().len()));
+        if binds.iter().all(|(path, user_path)| path == user_path) {
+            binds.clear();
+        }
+
+        Self {
+            binds: RwLock::new(binds),
+            root: RwLock::new(None),
+        }
+    }
+
+    fn binds(&self) -> RwLockReadGuard<'_, Vec<(PathBuf, PathBuf)>> {
+        self.binds.read().unwrap_or_else(|error| error.into_inner())
+    }
+
+    /// The process chrooted to `root`, an absolute path in its view.
+    ///
+    /// Called from a libc hook, where changing the environment races with the other threads, so
+    /// the exec hooks give the root to the processes it execs when they're enabled, see
+    /// [`RootView::root`]. Otherwise `set_env` sets [`ROOT_ENV`], the only way for the root to
+    /// reach them.
+    pub(crate) fn chroot(&self, root: &Path, set_env: bool) {
+        let root = self.user_path(root).into_owned();
+        if set_env {
+            std::env::set_var(ROOT_ENV, &root);
+        }
+
+        let mut binds = self
+            .binds
+            .write()
+            .unwrap_or_else(|error| error.into_inner());
+        *binds = Vec::from([(PathBuf::from("/"), root.clone())]);
+
+        *self.root.write().unwrap_or_else(|error| error.into_inner()) = Some(root);
+    }
+
+    /// Root of the process in the view of the user, for the [`ROOT_ENV`] of the processes it
+    /// execs. [`None`] when neither it nor its parents chrooted.
+    pub(crate) fn root(&self) -> Option<PathBuf> {
+        self.root
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+
+    /// `path` of the process in the view of the user.
+    pub(crate) fn user_path<'p>(&self, path: &'p Path) -> Cow<'p, Path> {
+        self.binds()
+            .iter()
+            .find_map(|(dir, user_dir)| {
+                let rest = path.strip_prefix(dir).ok()?;
+                Some(Cow::Owned(join(user_dir, rest)))
+            })
+            .unwrap_or(Cow::Borrowed(path))
+    }
+
+    /// `path` in the view of the user, in the view of the process. Paths that the process can't
+    /// see are kept as they are.
+    fn process_path(&self, path: PathBuf) -> PathBuf {
+        self.binds()
+            .iter()
+            .filter_map(|(dir, user_dir)| Some((dir, user_dir, path.strip_prefix(user_dir).ok()?)))
+            .max_by_key(|(_, user_dir, _)| user_dir.as_os_str().len())
+            .map(|(dir, _, rest)| join(dir, rest))
+            .unwrap_or(path)
+    }
+
+    /// Applies the `mapping` to `path` of the process in the view of the user, the mapped path is
+    /// translated back to the view of the process.
+    pub(crate) fn change_path(&self, remapper: &FileRemapper, path: PathBuf) -> PathBuf {
+        if self.binds().is_empty() {
+            return remapper.change_path(path);
+        }
+
+        let user_path = self.user_path(&path).into_owned();
+        let mapped = remapper.change_path(user_path.clone());
+        if mapped == user_path {
+            path
+        } else {
+            self.process_path(mapped)
+        }
+    }
+}
+
+/// `dir` joined with `rest`, without a trailing `/` when `rest` is empty.
+fn join(dir: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        dir.to_path_buf()
+    } else {
+        dir.join(rest)
+    }
+}
+
+/// Views of processes in other mount namespaces, from `/proc/self/mountinfo`.
+#[cfg(target_os = "linux")]
+mod mounts {
+    use std::{
+        ffi::OsString,
+        os::unix::ffi::OsStringExt,
+        path::{Path, PathBuf},
+    };
+
+    use super::{join, MOUNTS_ENV, MOUNT_NS_ENV};
+
+    /// A line of `/proc/self/mountinfo`.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Mount {
+        /// `major:minor` of the mounted device.
+        device: String,
+        /// The directory of the device that is mounted.
+        root: PathBuf,
+        mount_point: PathBuf,
+    }
+
+    /// Parses the lines of `/proc/self/mountinfo`.
+    pub(super) fn parse(mountinfo: &str) -> Vec<Mount> {
+        mountinfo
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ').skip(2);
+                Some(Mount {
+                    device: fields.next()?.to_string(),
+                    root: unescape(fields.next()?),
+                    mount_point: unescape(fields.next()?),
+                })
+            })
+            .collect()
+    }
+
+    /// Paths in `mountinfo` have ` `, `\t`, `\n` and `\` as octal escapes.
+    fn unescape(field: &str) -> PathBuf {
+        let mut bytes = Vec::with_capacity(field.len());
+        let mut rest = field.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let escaped = (byte == b'\\')
+                .then(|| tail.get(..3))
+                .flatten()
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+
+            match escaped {
+                Some(escaped) => {
+                    bytes.push(escaped);
+                    rest = tail.get(3..).unwrap_or_default();
+                }
+                None => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+
+        PathBuf::from(OsString::from_vec(bytes))
+    }
+
+    /// Directories of `mounts` (of the process) with their paths in the view of the user, that
+    /// has `user_mounts`.
+    ///
+    /// Only the mounts of devices that the user has mounted can be translated. The last mount of a
+    /// directory is the one the process sees.
+    pub(super) fn translate(mounts: &[Mount], user_mounts: &[Mount]) -> Vec<(PathBuf, PathBuf)> {
+        mounts
+            .iter()
+            .rev()
+            .filter_map(|mount| {
+                let user_path = user_mounts.iter().find_map(|user_mount| {
+                    if user_mount.device != mount.device {
+                        return None;
+                    }
+
+                    let rest = mount.root.strip_prefix(&user_mount.root).ok()?;
+                    Some(join(&user_mount.mount_point, rest))
+                })?;
+
+                Some((mount.mount_point.clone(), user_path))
+            })
+            .collect()
+    }
+
+    /// Compares the mounts of this process with the ones of the first process that loaded the
+    /// layer, when it's in another mount namespace. The first one saves its mounts in
+    /// [`MOUNTS_ENV`].
+    pub(super) fn binds() -> Vec<(PathBuf, PathBuf)> {
+        let Ok(namespace) = std::fs::read_link("/proc/self/ns/mnt") else {
+            return Vec::new();
+        };
+        let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+            return Vec::new();
+        };
+
+        match std::env::var_os(MOUNT_NS_ENV) {
+            None => {
+                std::env::set_var(MOUNT_NS_ENV, &namespace);
+                std::env::set_var(MOUNTS_ENV, mountinfo);
+                Vec::new()
+            }
+            Some(user_namespace) if Path::new(&user_namespace) == namespace => Vec::new(),
+            Some(..) => {
+                let user_mounts = std::env::var(MOUNTS_ENV).unwrap_or_default();
+                translate(&parse(&mountinfo), &parse(&user_mounts))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn chroot_view() -> RootView {
+        RootView::new(vec![(PathBuf::from("/"), PathBuf::from("/sandbox"))])
+    }
+
+    #[rstest]
+    #[case("/", "/sandbox")]
+    #[case("/etc/hosts", "/sandbox/etc/hosts")]
+    fn chroot_user_path(#[case] path: &str, #[case] expected: &str) {
+        assert_eq!(
+            chroot_view().user_path(Path::new(path)),
+            Path::new(expected)
+        );
+    }
+
+    #[rstest]
+    #[case("/app/config.yaml", "/config.yaml")]
+    #[case("/app/nested/file", "/nested/file")]
+    #[case("/unmapped", "/unmapped")]
+    #[case("/outside", "/opt/outside")]
+    fn chroot_change_path(#[case] path: &str, #[case] expected: &str) {
+        let remapper = FileRemapper::new(HashMap::from([
+            ("^/sandbox/app".to_string(), "/sandbox".to_string()),
+            ("^/sandbox/outside".to_string(), "/opt/outside".to_string()),
+        ]));
+
+        assert_eq!(
+            chroot_view().change_path(&remapper, PathBuf::from(path)),
+            Path::new(expected)
+        );
+    }
+
+    #[test]
+    fn nested_chroot() {
+        let view = chroot_view();
+        view.chroot(Path::new("/inner"), false);
+
+        assert_eq!(
+            view.user_path(Path::new("/file")),
+            Path::new("/sandbox/inner/file")
+        );
+        assert_eq!(view.root().as_deref(), Some(Path::new("/sandbox/inner")));
+        assert!(std::env::var_os(ROOT_ENV).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bwrap_mounts() {
+        let user = mounts::parse(
+            "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+             40 22 259:3 / /home rw,relatime shared:20 - ext4 /dev/nvme0n1p3 rw\n",
+        );
+        // `bwrap --ro-bind /usr /usr --bind "/home/me/my project" /src --tmpfs /tmp`
+        let sandbox = mounts::parse(
+            "300 250 0:60 / / rw,nosuid - tmpfs tmpfs rw\n\
+             301 300 259:2 /usr /usr ro,relatime - ext4 /dev/nvme0n1p2 rw\n\
+             302 300 259:3 /me/my\\040project /src rw,relatime - ext4 /dev/nvme0n1p3 rw\n\
+             303 300 0:61 / /tmp rw,nosuid - tmpfs tmpfs rw\n",
+        );
+
+        let view = RootView::new(mounts::translate(&sandbox, &user));
+        assert_eq!(
+            view.user_path(Path::new("/src/config.yaml")),
+            Path::new("/home/me/my project/config.yaml")
+        );
+        assert_eq!(view.user_path(Path::new("/usr/lib")), Path::new("/usr/lib"));
+        assert_eq!(
+            view.user_path(Path::new("/tmp/file")),
+            Path::new("/tmp/file")
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! `inotify` of the remote files, for the hot-reload tools that wait for the changes of the files
+//! they watch.
+//!
+//! Every `inotify` instance is a connection to the internal proxy (see [`WatchInit`]), which
+//! writes the events of the instance to it, in the format of `inotify(7)`. The layer hands the
+//! connected socket out as the `inotify` descriptor, so the user application can poll it like the
+//! real thing, and only `read` needs a hook (see [`read`]).
+//!
+//! When the agent can't watch files, the instances are made by the local kernel.
+
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    io,
+    net::TcpStream,
+    os::unix::{ffi::OsStringExt, io::IntoRawFd},
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use libc::{
+    c_int, c_void, size_t, ssize_t, EAGAIN, EINVAL, IN_CLOEXEC, IN_NONBLOCK, MSG_PEEK, MSG_WAITALL,
+    O_NONBLOCK,
+};
+use mirrord_intproxy_protocol::{
+    WatchAddRequest, WatchInit, WatchInitRequest, WatchInstanceId, WatchRemoveRequest,
+};
+use mirrord_protocol::ResponseError;
+
+use super::{ops::watch_path, LocalFd};
+use crate::{
+    common,
+    detour::{Bypass, Detour},
+    error::HookError,
+};
+
+/// Size of the `struct inotify_event` header, followed by `len` bytes of name.
+const EVENT_HEADER_LEN: usize = 16;
+
+/// The `inotify` instances made through the internal proxy, by their local descriptor.
+pub(crate) static WATCH_INSTANCES: LazyLock<Mutex<HashMap<LocalFd, WatchInstanceId>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn instance(fd: LocalFd) -> Detour<WatchInstanceId> {
+    match WATCH_INSTANCES.lock()?.get(&fd) {
+        Some(instance) => Detour::Success(*instance),
+        None => Detour::Bypass(Bypass::LocalFdNotFound(fd)),
+    }
+}
+
+fn errno(raw: c_int) -> HookError {
+    io::Error::from_raw_os_error(raw).into()
+}
+
+/// `inotify_init1`, bypassed when the agent doesn't support
+/// [`WATCH_VERSION`](mirrord_protocol::watch::WATCH_VERSION).
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(crate) fn init(flags: c_int) -> Detour<LocalFd> {
+    if flags & !(IN_NONBLOCK | IN_CLOEXEC) != 0 {
+        return Detour::Error(errno(EINVAL));
+    }
+
+    let WatchInit { instance, address } =
+        match common::make_proxy_request_with_response(WatchInitRequest)? {
+            Ok(init) => init,
+            Err(ResponseError::NotImplemented) => return Detour::Bypass(Bypass::NotImplemented),
+            Err(fail) => return Detour::Error(fail.into()),
+        };
+
+    // `std` sockets are made with `SOCK_CLOEXEC`.
+    let stream = TcpStream::connect(address)?;
+    stream.set_nonblocking(flags & IN_NONBLOCK != 0)?;
+    let fd = stream.into_raw_fd();
+    if flags & IN_CLOEXEC == 0 && unsafe { libc::fcntl(fd, libc::F_SETFD, 0) } == -1 {
+        let error = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Detour::Error(error.into());
+    }
+
+    WATCH_INSTANCES.lock()?.insert(fd, instance);
+
+    Detour::Success(fd)
+}
+
+/// `inotify_add_watch`, the local paths are watched by the internal proxy.
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(crate) fn add_watch(fd: LocalFd, path: Detour<PathBuf>, mask: u32) -> Detour<c_int> {
+    let instance = instance(fd)?;
+    let path = path?;
+
+    let (path, remote) = match watch_path(path.clone()) {
+        Detour::Success(path) => (path, true),
+        Detour::Bypass(Bypass::Overlay(copy)) => {
+            (OsString::from_vec(copy.into_bytes()).into(), false)
+        }
+        // The internal proxy doesn't run in the directory of the user application.
+        Detour::Bypass(..) if path.is_relative() => (env::current_dir()?.join(path), false),
+        Detour::Bypass(..) => (path, false),
+        Detour::Error(error) => return Detour::Error(error),
+    };
+
+    let request = WatchAddRequest {
+        instance,
+        path,
+        mask,
+        remote,
+    };
+    let wd = common::make_proxy_request_with_response(request)??;
+
+    Detour::Success(wd)
+}
+
+/// `inotify_rm_watch`.
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(crate) fn rm_watch(fd: LocalFd, wd: c_int) -> Detour<c_int> {
+    let instance = instance(fd)?;
+
+    let request = WatchRemoveRequest {
+        instance,
+        watch_descriptor: wd,
+    };
+    common::make_proxy_request_with_response(request)??;
+
+    Detour::Success(0)
+}
+
+/// Returns the length of the whole events at the start of `bytes`, and the length of the first
+/// event that is not whole.
+fn whole_events(bytes: &[u8]) -> (usize, Option<usize>) {
+    let mut whole = 0;
+
+    while let Some(header) = bytes.get(whole..whole + EVENT_HEADER_LEN) {
+        let mut len = [0; 4];
+        len.copy_from_slice(&header[12..]);
+        let event = EVENT_HEADER_LEN + u32::from_ne_bytes(len) as usize;
+
+        if bytes.len() < whole + event {
+            return (whole, Some(event));
+        }
+        whole += event;
+    }
+
+    let partial = (bytes.len() > whole).then_some(EVENT_HEADER_LEN);
+    (whole, partial)
+}
+
+/// `read` of an `inotify` descriptor, returns whole events only, like the kernel.
+///
+/// The events come from a stream, so they're peeked first, and only the ones that are whole and
+/// fit in the buffer are read.
+pub(crate) unsafe fn read(fd: LocalFd, out_buffer: *mut c_void, count: size_t) -> Detour<ssize_t> {
+    instance(fd)?;
+
+    let mut wanted = count;
+    let mut flags = MSG_PEEK;
+    loop {
+        let peeked = libc::recv(fd, out_buffer, wanted, flags);
+        if peeked <= 0 {
+            // Errors (e.g. `EAGAIN`) and the end of the stream are the same as for the socket.
+            return Detour::Success(peeked);
+        }
+
+        let peeked = std::slice::from_raw_parts(out_buffer.cast::<u8>(), peeked as usize);
+        match whole_events(peeked) {
+            (0, Some(event)) if event > count => return Detour::Error(errno(EINVAL)),
+            (0, _) if libc::fcntl(fd, libc::F_GETFL) & O_NONBLOCK != 0 => {
+                return Detour::Error(errno(EAGAIN))
+            }
+            // The rest of the event is on the way, wait for it.
+            (0, event) => {
+                wanted = event.unwrap_or(EVENT_HEADER_LEN);
+                flags = MSG_PEEK | MSG_WAITALL;
+            }
+            (whole, _) => return Detour::Success(libc::recv(fd, out_buffer, whole, 0)),
+        }
+    }
+}
+
+/// Called when `fd` is closed, the internal proxy drops the instance when its connection is gone.
+pub(crate) fn close(fd: LocalFd) {
+    if let Ok(mut instances) = WATCH_INSTANCES.lock() {
+        instances.remove(&fd);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(name_len: u32) -> Vec<u8> {
+        let mut bytes = vec![0; EVENT_HEADER_LEN];
+        bytes[12..].copy_from_slice(&name_len.to_ne_bytes());
+        bytes.resize(bytes.len() + name_len as usize, 0);
+        bytes
+    }
+
+    #[test]
+    fn whole_events_only() {
+        let mut bytes = event(0);
+        bytes.extend(event(32));
+        assert_eq!(whole_events(&bytes), (64, None));
+
+        assert_eq!(whole_events(&bytes[..40]), (16, Some(48)));
+        assert_eq!(whole_events(&bytes[..20]), (16, Some(16)));
+        assert_eq!(whole_events(&bytes[..8]), (0, Some(16)));
+    }
+}
@@ -8,8 +8,13 @@ use crate::{
 };
 /*
  * Reference for which syscalls are managed by the handlers:
- * SYS_openat: Syscall6
- * SYS_read, SYS_write, SYS_lseek, SYS_faccessat: Syscall
+ * SYS_openat, SYS_renameat, SYS_openat2, SYS_fchownat, SYS_linkat, SYS_utimensat,
+ * SYS_statx, SYS_getxattr, SYS_lgetxattr, SYS_fgetxattr, SYS_setxattr, SYS_lsetxattr,
+ * SYS_fsetxattr, SYS_fallocate, SYS_copy_file_range, SYS_sendfile: Syscall6
+ * SYS_read, SYS_write, SYS_lseek, SYS_faccessat, SYS_unlinkat, SYS_fchmodat, SYS_fchmod,
+ * SYS_fchown, SYS_truncate, SYS_ftruncate, SYS_symlinkat, SYS_listxattr, SYS_llistxattr,
+ * SYS_flistxattr, SYS_removexattr, SYS_lremovexattr, SYS_fremovexattr, SYS_flock,
+ * SYS_fcntl: Syscall
  *
  * SYS_socket, SYS_bind, SYS_listen, SYS_accept, SYS_close: Syscall
  * SYS_accept4: Syscall6
@@ -341,6 +346,7 @@ unsafe extern "C" fn c_abi_syscall_handler(
             }
             libc::SYS_fstat => fstat_detour(param1 as _, param2 as _) as i64,
             libc::SYS_getdents64 => getdents64_detour(param1 as _, param2 as _, param3 as _) as i64,
+            libc::SYS_unlinkat => unlinkat_detour(param1 as _, param2 as _, param3 as _) as i64,
             _ => {
                 let (Ok(result) | Err(result)) = syscalls::syscall!(
                     syscalls::Sysno::from(syscall as i32),
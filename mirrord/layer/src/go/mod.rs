@@ -5,7 +5,14 @@
 use errno::errno;
 use tracing::trace;
 
-use crate::{close_detour, file::hooks::*, socket::hooks::*};
+use crate::{
+    close_detour,
+    file::{
+        hooks::*,
+        ops::{is_record_lock_command, statx_logic},
+    },
+    socket::hooks::*,
+};
 
 #[cfg_attr(
     all(target_os = "linux", target_arch = "x86_64"),
@@ -76,10 +83,69 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                 //   SYS_NEWFSTATAT (262)
                 // - SYS_lstat: maps to fstatat with AT_FDCWD and AT_SYMLINK_NOFOLLOW in go - no
                 //   additional hook needed
-                // - SYS_statx: not supported in go
+                // - SYS_statx: not used by the go runtime, only by `golang.org/x/sys/unix.Statx`
                 libc::SYS_newfstatat => {
                     fstatat_logic(param1 as _, param2 as _, param3 as _, param4 as _)
-                        .unwrap_or_bypass_with(|_| {
+                        .unwrap_or_bypass_with(|bypass| {
+                            // The file might be somewhere else locally, e.g. in the overlay.
+                            let param2 = update_ptr_from_bypass(param2 as _, &bypass) as i64;
+                            let (Ok(result) | Err(result)) = syscalls::syscall!(
+                                syscalls::Sysno::from(syscall as i32),
+                                param1,
+                                param2,
+                                param3,
+                                param4,
+                                param5,
+                                param6
+                            )
+                            .map(|success| success as i64)
+                            .map_err(|fail| {
+                                let raw_errno = fail.into_raw();
+                                errno::set_errno(errno::Errno(raw_errno));
+
+                                -(raw_errno as i64)
+                            });
+                            result as i32
+                        })
+                        .into()
+                }
+                libc::SYS_statx => {
+                    statx_logic(
+                        param1 as _,
+                        param2 as _,
+                        param3 as _,
+                        param4 as _,
+                        param5 as _,
+                    )
+                    .unwrap_or_bypass_with(|bypass| {
+                        // The file might be somewhere else locally, e.g. in the overlay.
+                        let param2 = update_ptr_from_bypass(param2 as _, &bypass) as i64;
+                        let (Ok(result) | Err(result)) = syscalls::syscall!(
+                            syscalls::Sysno::from(syscall as i32),
+                            param1,
+                            param2,
+                            param3,
+                            param4,
+                            param5,
+                            param6
+                        )
+                        .map(|success| success as i64)
+                        .map_err(|fail| {
+                            let raw_errno = fail.into_raw();
+                            errno::set_errno(errno::Errno(raw_errno));
+
+                            -(raw_errno as i64)
+                        });
+                        result as i32
+                    })
+                    .into()
+                }
+                // Not every libc has an `openat2` wrapper, so we bypass with the syscall.
+                libc::SYS_openat2 => {
+                    openat2_logic(param1 as _, param2 as _, param3 as _, param4 as _)
+                        .unwrap_or_bypass_with(|bypass| {
+                            // The file might be somewhere else locally, e.g. in the overlay.
+                            let param2 = update_ptr_from_bypass(param2 as _, &bypass) as i64;
                             let (Ok(result) | Err(result)) = syscalls::syscall!(
                                 syscalls::Sysno::from(syscall as i32),
                                 param1,
@@ -101,8 +167,23 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                         .into()
                 }
                 libc::SYS_fstat => fstat_detour(param1 as _, param2 as _) as i64,
+                // Go's `fsnotify` is `inotify_init1`, `inotify_add_watch` and `inotify_rm_watch`,
+                // and then plain `read`s of the instance.
+                libc::SYS_inotify_init1 => inotify_init1_detour(param1 as _) as i64,
+                libc::SYS_inotify_add_watch => {
+                    inotify_add_watch_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                libc::SYS_inotify_rm_watch => {
+                    inotify_rm_watch_detour(param1 as _, param2 as _) as i64
+                }
                 libc::SYS_fsync => fsync_detour(param1 as _) as i64,
-                libc::SYS_fdatasync => fsync_detour(param1 as _) as i64,
+                // Go's `syscall.Flock`, and `syscall.FcntlFlock` for the record locks, the other
+                // `fcntl` commands go straight to the kernel.
+                libc::SYS_flock => flock_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_fcntl if is_record_lock_command(param2 as _) => {
+                    fcntl_detour(param1 as _, param2 as _, param3 as usize) as i64
+                }
+                libc::SYS_fdatasync => fdatasync_detour(param1 as _) as i64,
                 libc::SYS_openat => {
                     openat_detour(param1 as _, param2 as _, param3 as _, param4 as libc::c_int)
                         as i64
@@ -110,6 +191,116 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                 libc::SYS_getdents64 => {
                     getdents64_detour(param1 as _, param2 as _, param3 as _) as i64
                 }
+                libc::SYS_unlinkat => unlinkat_detour(param1 as _, param2 as _, param3 as _) as i64,
+                // Go's `os.Chmod` is `fchmodat`, which (like `faccessat`) takes no flags in the
+                // kernel, and `os.File.Chmod` is `fchmod`.
+                libc::SYS_fchmodat => {
+                    fchmodat_detour(param1 as _, param2 as _, param3 as _, 0) as i64
+                }
+                libc::SYS_fchmod => fchmod_detour(param1 as _, param2 as _) as i64,
+                // Go's `os.Truncate` is `truncate`, and `os.File.Truncate` is `ftruncate`.
+                libc::SYS_truncate => truncate_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_ftruncate => ftruncate_detour(param1 as _, param2 as _) as i64,
+                // Not used by the go runtime, only by `syscall.Fallocate`.
+                libc::SYS_fallocate => {
+                    fallocate_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                // Go's `io.Copy` from one `os.File` to another is `copy_file_range`.
+                libc::SYS_copy_file_range => copy_file_range_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                    param6 as _,
+                ) as i64,
+                // Go's `io.Copy` from an `os.File` to a `net.TCPConn` is `sendfile`.
+                libc::SYS_sendfile => {
+                    sendfile_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                // Go's `os.Chown` and `os.Lchown` are `fchownat`, and `os.File.Chown` is
+                // `fchown`.
+                libc::SYS_fchownat => fchownat_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                libc::SYS_fchown => fchown_detour(param1 as _, param2 as _, param3 as _) as i64,
+                // Go's `os.Chtimes` is `utimensat` with `AT_FDCWD` and no flags.
+                libc::SYS_utimensat => {
+                    utimensat_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                // Go's `os.Link` is `linkat` with `AT_FDCWD` and no flags.
+                libc::SYS_linkat => linkat_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                // Go's `os.Symlink` is `symlinkat` with `AT_FDCWD`.
+                libc::SYS_symlinkat => {
+                    symlinkat_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                // Go's `os.Rename` is `renameat` on x86_64, and `renameat2` without flags on
+                // aarch64, which has no `renameat`.
+                #[cfg(target_arch = "x86_64")]
+                libc::SYS_renameat => {
+                    renameat_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                libc::SYS_renameat2 => renameat2_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                // Not used by the go runtime, only by `golang.org/x/sys/unix` (e.g.
+                // `unix.Getxattr`).
+                libc::SYS_getxattr => {
+                    getxattr_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                libc::SYS_lgetxattr => {
+                    lgetxattr_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                libc::SYS_fgetxattr => {
+                    fgetxattr_detour(param1 as _, param2 as _, param3 as _, param4 as _) as i64
+                }
+                libc::SYS_setxattr => setxattr_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                libc::SYS_lsetxattr => lsetxattr_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                libc::SYS_fsetxattr => fsetxattr_detour(
+                    param1 as _,
+                    param2 as _,
+                    param3 as _,
+                    param4 as _,
+                    param5 as _,
+                ) as i64,
+                libc::SYS_listxattr => {
+                    listxattr_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                libc::SYS_llistxattr => {
+                    llistxattr_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                libc::SYS_flistxattr => {
+                    flistxattr_detour(param1 as _, param2 as _, param3 as _) as i64
+                }
+                libc::SYS_removexattr => removexattr_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_lremovexattr => lremovexattr_detour(param1 as _, param2 as _) as i64,
+                libc::SYS_fremovexattr => fremovexattr_detour(param1 as _, param2 as _) as i64,
                 _ => {
                     let (Ok(result) | Err(result)) = syscalls::syscall!(
                         syscalls::Sysno::from(syscall as i32),
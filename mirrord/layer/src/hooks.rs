@@ -36,8 +36,10 @@ impl HookManager {
         detour: *mut libc::c_void,
     ) -> Result<NativePointer> {
         for module in &self.modules {
-            // In this case we only want libs, no "main binaries"
-            if !module.starts_with("lib") {
+            // In this case we only want libs, no "main binaries".
+            //
+            // On musl, libc is the dynamic loader itself (`ld-musl-<arch>.so.1`).
+            if !module.starts_with("lib") && !module.starts_with("ld-musl") {
                 continue;
             }
             if let Ok(function) = get_export_by_name(Some(module), symbol) {
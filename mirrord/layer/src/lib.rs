@@ -71,7 +71,7 @@ use std::{
     net::SocketAddr,
     os::unix::process::parent_id,
     panic,
-    sync::OnceLock,
+    sync::{Arc, OnceLock},
     time::Duration,
 };
 
@@ -82,18 +82,19 @@ use hooks::HookManager;
 use libc::{c_int, pid_t};
 use load::ExecuteArgs;
 #[cfg(target_os = "macos")]
-use mirrord_config::feature::fs::FsConfig;
+use mirrord_config::feature::{fs::FsConfig, output::OutputMode};
 use mirrord_config::{
     feature::{fs::FsModeConfig, network::incoming::IncomingMode},
+    internal_proxy::MIRRORD_INTPROXY_AUTH_TOKEN_ENV,
     LayerConfig,
 };
-use mirrord_intproxy_protocol::NewSessionRequest;
+use mirrord_intproxy_protocol::{control::LogSettings, NewSessionRequest};
 use mirrord_layer_macro::{hook_fn, hook_guard_fn};
 use mirrord_protocol::{EnvVars, GetEnvVarsRequest};
 use proxy_connection::ProxyConnection;
 use setup::LayerSetup;
 use socket::SOCKETS;
-use tracing_subscriber::{fmt::format::FmtSpan, prelude::*};
+use tracing_subscriber::{fmt::format::FmtSpan, prelude::*, reload, EnvFilter, Registry};
 
 use crate::{
     common::make_proxy_request_with_response, debugger_ports::DebuggerPorts, detour::DetourGuard,
@@ -111,6 +112,7 @@ mod file;
 mod hooks;
 mod load;
 mod macros;
+mod output;
 mod proxy_connection;
 mod setup;
 mod socket;
@@ -160,6 +162,28 @@ static EXECUTABLE_ARGS: OnceLock<ExecuteArgs> = OnceLock::new();
 /// Executable path we're loaded to
 static EXECUTABLE_PATH: OnceLock<String> = OnceLock::new();
 
+/// Changes the filter of the logs, set in [`init_tracing`] unless the logs go to mirrord-console.
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// See [`LOG_FILTER`].
+struct LogFilter {
+    /// The filter from `RUST_LOG`.
+    configured: String,
+    reload: reload::Handle<EnvFilter, Registry>,
+}
+
+/// Applies the [`LogSettings`] changed through the control socket of the internal proxy.
+pub(crate) fn apply_log_settings(settings: LogSettings) {
+    let Some(log_filter) = LOG_FILTER.get() else {
+        return;
+    };
+
+    let filter = EnvFilter::builder().parse_lossy(settings.filter(&log_filter.configured));
+    if let Err(error) = log_filter.reload.reload(filter) {
+        tracing::warn!(%error, "Failed to change the log filter");
+    }
+}
+
 /// Read/write timeout for layer<->intproxy TCP sockets.
 /// Can be configured in the [`LayerConfig`].
 static PROXY_CONNECTION_TIMEOUT: OnceLock<Duration> = OnceLock::new();
@@ -232,6 +256,7 @@ fn load_only_layer_start(config: &LayerConfig) {
 
     let new_connection = ProxyConnection::new(
         address,
+        std::env::var(MIRRORD_INTPROXY_AUTH_TOKEN_ENV).ok(),
         NewSessionRequest::New(
             EXECUTABLE_ARGS
                 .get()
@@ -283,7 +308,13 @@ fn init_tracing() {
     if let Ok(console_addr) = std::env::var("MIRRORD_CONSOLE_ADDR") {
         mirrord_console::init_logger(&console_addr).expect("logger initialization failed");
     } else {
+        let configured = std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_default();
+        let (filter, reload) = reload::Layer::new(
+            EnvFilter::builder().parse_lossy(LogSettings::default().filter(&configured)),
+        );
+
         tracing_subscriber::registry()
+            .with(filter)
             .with(
                 tracing_subscriber::fmt::layer()
                     .with_thread_ids(true)
@@ -291,8 +322,9 @@ fn init_tracing() {
                     .compact()
                     .with_writer(std::io::stderr),
             )
-            .with(tracing_subscriber::EnvFilter::from_default_env())
             .init();
+
+        let _ = LOG_FILTER.set(LogFilter { configured, reload });
     };
 }
 
@@ -331,6 +363,7 @@ fn layer_start(mut config: LayerConfig) {
     if trace_only {
         config.feature.fs.mode = FsModeConfig::Local;
         config.feature.network.dns.enabled = false;
+        config.feature.network.interfaces = false;
         config.feature.network.incoming.mode = IncomingMode::Off;
         config.feature.network.outgoing.tcp = false;
         config.feature.network.outgoing.udp = false;
@@ -351,6 +384,7 @@ fn layer_start(mut config: LayerConfig) {
     SETUP.set(state).unwrap();
 
     let state = setup();
+    adjust_runtimes(state);
     enable_hooks(state);
 
     let _detour_guard = DetourGuard::new();
@@ -374,6 +408,7 @@ fn layer_start(mut config: LayerConfig) {
         let address = setup().proxy_address();
         let new_connection = ProxyConnection::new(
             address,
+            std::env::var(MIRRORD_INTPROXY_AUTH_TOKEN_ENV).ok(),
             NewSessionRequest::New(process_info),
             proxy_connection_timeout,
         )
@@ -383,6 +418,8 @@ fn layer_start(mut config: LayerConfig) {
             .expect("setting PROXY_CONNECTION singleton")
     }
 
+    socket::ops::subscribe_inherited_listeners();
+
     let fetch_env = setup().env_config().load_from_process.unwrap_or(false)
         && !std::env::var(REMOTE_ENV_FETCHED)
             .unwrap_or_default()
@@ -441,6 +478,8 @@ fn fetch_env_vars() -> HashMap<String, String> {
         .expect("failed to make request to proxy")
         .expect("failed to fetch remote env");
 
+        setup().env_config().rewrite_values(&mut remote_env);
+
         if let Some(overrides) = setup().env_config().r#override.as_ref() {
             remote_env.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
         }
@@ -468,8 +507,17 @@ fn sip_only_layer_start(mut config: LayerConfig, patch_binaries: Vec<String>) {
         read_only: None,
         local: None,
         not_found: None,
+        scratch: None,
+        rules: None,
+        overlay: None,
+        pod_files: false,
+        compression_threshold: None,
+        metadata_cache_ttl_ms: None,
+        prefetch: None,
         mapping: None,
     };
+    // Skipped processes keep their output.
+    config.feature.output = OutputMode::Local;
     let debugger_ports = DebuggerPorts::from_env();
     let setup = LayerSetup::new(config, debugger_ports, true);
 
@@ -478,6 +526,29 @@ fn sip_only_layer_start(mut config: LayerConfig, patch_binaries: Vec<String>) {
     unsafe { file::hooks::enable_file_hooks(&mut hook_manager) };
 }
 
+/// Adjusts runtimes that have file operation paths our hooks can't see, when file operations are
+/// enabled.
+fn adjust_runtimes(state: &LayerSetup) {
+    if !state.fs_config().is_active() {
+        return;
+    }
+
+    // libuv (node) does file operations with io_uring when it's available, unless told otherwise.
+    if std::env::var_os("UV_USE_IO_URING").is_none() {
+        std::env::set_var("UV_USE_IO_URING", "0");
+    }
+
+    // Bun does file operations with raw syscalls on linux, there's no libc call for us to hook.
+    #[cfg(target_os = "linux")]
+    if EXECUTABLE_ARGS.get().is_some_and(ExecuteArgs::is_bun) {
+        tracing::warn!(
+            "Bun does file operations with direct system calls on Linux, which mirrord can't \
+            intercept, so Bun's files will be accessed locally. Sockets and DNS still go through \
+            mirrord."
+        );
+    }
+}
+
 /// Prepares the [`HookManager`] and [`replace!`]s [`libc`] calls with our hooks, according to what
 /// the user configured.
 ///
@@ -489,10 +560,14 @@ fn sip_only_layer_start(mut config: LayerConfig, patch_binaries: Vec<String>) {
 /// - `enabled_remote_dns`: replaces [`libc::getaddrinfo`] and [`libc::freeaddrinfo`] when this is
 ///   `true`, see [`NetworkConfig`](mirrord_config::feature::network::NetworkConfig), and
 ///   [`hooks::enable_socket_hooks`](socket::hooks::enable_socket_hooks).
+///
+/// - `enabled_remote_interfaces`: replaces [`libc::getifaddrs`] when this is `true`, see
+///   [`NetworkConfig::interfaces`](mirrord_config::feature::network::NetworkConfig::interfaces).
 #[mirrord_layer_macro::instrument(level = tracing::Level::TRACE)]
 fn enable_hooks(state: &LayerSetup) {
     let enabled_file_ops = state.fs_config().is_active();
     let enabled_remote_dns = state.remote_dns_enabled();
+    let enabled_remote_interfaces = state.remote_interfaces();
 
     let mut hook_manager = HookManager::default();
 
@@ -541,11 +616,12 @@ fn enable_hooks(state: &LayerSetup) {
         socket::hooks::enable_socket_hooks(
             &mut hook_manager,
             enabled_remote_dns,
+            enabled_remote_interfaces,
             state.experimental(),
         )
     };
 
-    if cfg!(target_os = "macos") || state.experimental().enable_exec_hooks_linux {
+    if state.exec_hooks_enabled() {
         unsafe { exec_hooks::hooks::enable_exec_hooks(&mut hook_manager) };
     }
 
@@ -563,6 +639,8 @@ fn enable_hooks(state: &LayerSetup) {
 
     if enabled_file_ops {
         unsafe { file::hooks::enable_file_hooks(&mut hook_manager) };
+    } else if state.output_mode().is_remote() {
+        unsafe { file::hooks::enable_write_hooks(&mut hook_manager) };
     }
 
     #[cfg(all(
@@ -585,15 +663,29 @@ fn enable_hooks(state: &LayerSetup) {
 #[mirrord_layer_macro::instrument(level = "trace", fields(pid = std::process::id()))]
 pub(crate) fn close_layer_fd(fd: c_int) {
     // Remove from sockets.
-    if let Some(socket) = SOCKETS.lock().expect("SOCKETS lock failed").remove(&fd) {
+    let socket = {
+        let mut sockets = SOCKETS.lock().expect("SOCKETS lock failed");
+        sockets.remove(&fd).map(|socket| {
+            // The socket stays open while any fd duplicated from it is.
+            let last_fd = !sockets.values().any(|other| Arc::ptr_eq(other, &socket));
+            (socket, last_fd)
+        })
+    };
+
+    if let Some((socket, last_fd)) = socket {
         // Closed file is a socket, so if it's already bound to a port - notify agent to stop
         // mirroring/stealing that port.
-        socket.close();
+        if last_fd {
+            socket.close();
+        }
     } else if setup().fs_config().is_active() {
         OPEN_FILES
             .lock()
             .expect("OPEN_FILES lock failed")
             .remove(&fd);
+
+        #[cfg(target_os = "linux")]
+        file::watch::close(fd);
     }
 }
 
@@ -637,6 +729,7 @@ pub(crate) unsafe extern "C" fn fork_detour() -> pid_t {
 
             let new_connection = ProxyConnection::new(
                 parent_connection.proxy_addr(),
+                parent_connection.auth_token().map(ToOwned::to_owned),
                 NewSessionRequest::Forked(parent_connection.layer_id()),
                 PROXY_CONNECTION_TIMEOUT
                     .get()
@@ -83,6 +83,12 @@ impl ExecuteArgs {
         self.exec_name.ends_with(suffix) || self.invoked_as.ends_with(suffix)
     }
 
+    /// Checks if this process is the [Bun](https://bun.sh) runtime (also invoked as `bunx`).
+    #[cfg(target_os = "linux")]
+    pub(crate) fn is_bun(&self) -> bool {
+        self.exec_name == "bun" || self.invoked_as == "bun" || self.invoked_as == "bunx"
+    }
+
     fn is_build_tool(&self) -> bool {
         BUILD_TOOL_PROCESSES.contains(self.exec_name.as_str())
             || BUILD_TOOL_PROCESSES.contains(self.invoked_as.as_str())
@@ -0,0 +1,38 @@
+//! Sends the output of the local process to the target, see [`OutputMode`].
+
+use std::os::unix::io::RawFd;
+
+use mirrord_config::feature::output::OutputMode;
+use mirrord_protocol::output::{OutputMessage, OutputStream};
+
+use crate::{
+    common,
+    detour::{Bypass, Detour},
+};
+
+/// Sends the bytes written to the stdout/stderr of the local process to the agent.
+///
+/// **Bypassed** by other `fd`s, when the output stays local, and with [`OutputMode::Tee`] (the
+/// bytes are written locally as well).
+pub(crate) fn write(fd: RawFd, write_bytes: Option<Vec<u8>>) -> Detour<isize> {
+    let output_mode = crate::setup().output_mode();
+    if !output_mode.is_remote() {
+        return Detour::Bypass(Bypass::LocalOutput);
+    }
+
+    let stream = match fd {
+        libc::STDOUT_FILENO => OutputStream::Stdout,
+        libc::STDERR_FILENO => OutputStream::Stderr,
+        _ => return Detour::Bypass(Bypass::LocalFdNotFound(fd)),
+    };
+
+    let bytes = write_bytes.ok_or(Bypass::EmptyBuffer)?;
+    let written = bytes.len();
+
+    common::make_proxy_request_no_response(OutputMessage { stream, bytes })?;
+
+    match output_mode {
+        OutputMode::Remote => Detour::Success(written.try_into()?),
+        _ => Detour::Bypass(Bypass::LocalOutput),
+    }
+}
@@ -12,6 +12,7 @@ use std::{
 
 use mirrord_intproxy_protocol::{
     codec::{self, CodecError, SyncDecoder, SyncEncoder},
+    control::PROTOCOL_TRACE_TARGET,
     IsLayerRequest, IsLayerRequestWithResponse, LayerId, LayerToProxyMessage, LocalMessage,
     MessageId, NewSessionRequest, ProxyToLayerMessage,
 };
@@ -46,11 +47,14 @@ pub struct ProxyConnection {
     next_message_id: AtomicU64,
     layer_id: LayerId,
     proxy_addr: SocketAddr,
+    /// Sent in [`LayerToProxyMessage::Authenticate`], when the internal proxy requires it.
+    auth_token: Option<String>,
 }
 
 impl ProxyConnection {
     pub fn new(
         proxy_addr: SocketAddr,
+        auth_token: Option<String>,
         session: NewSessionRequest,
         timeout: Duration,
     ) -> Result<Self> {
@@ -63,6 +67,12 @@ impl ProxyConnection {
             LocalMessage<ProxyToLayerMessage>,
         >(connection)?;
 
+        if let Some(token) = &auth_token {
+            sender.send(&LocalMessage {
+                message_id: 0,
+                inner: LayerToProxyMessage::Authenticate(token.clone()),
+            })?;
+        }
         sender.send(&LocalMessage {
             message_id: 0,
             inner: LayerToProxyMessage::NewSession(session),
@@ -80,6 +90,7 @@ impl ProxyConnection {
             next_message_id: AtomicU64::new(1),
             layer_id: *layer_id,
             proxy_addr,
+            auth_token,
         })
     }
 
@@ -94,6 +105,8 @@ impl ProxyConnection {
             inner: message,
         };
 
+        tracing::trace!(target: PROTOCOL_TRACE_TARGET, ?message, "Sending to the internal proxy");
+
         let mut guard = self.sender.lock()?;
         guard.send(&message)?;
         guard.flush()?;
@@ -131,6 +144,10 @@ impl ProxyConnection {
     pub fn proxy_addr(&self) -> SocketAddr {
         self.proxy_addr
     }
+
+    pub fn auth_token(&self) -> Option<&str> {
+        self.auth_token.as_deref()
+    }
 }
 
 #[derive(Debug)]
@@ -158,6 +175,18 @@ impl ResponseManager {
                 .receive()?
                 .ok_or(ProxyError::ConnectionClosed)?;
 
+            tracing::trace!(
+                target: PROTOCOL_TRACE_TARGET,
+                ?response,
+                "Received from the internal proxy"
+            );
+
+            // Not a response, the internal proxy sends these whenever they change.
+            if let ProxyToLayerMessage::LogSettings(settings) = response.inner {
+                crate::apply_log_settings(settings);
+                continue;
+            }
+
             if response.message_id == response_id {
                 break Ok(response.inner);
             }
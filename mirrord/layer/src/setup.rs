@@ -4,28 +4,30 @@ use mirrord_config::{
     experimental::ExperimentalConfig,
     feature::{
         env::EnvConfig,
-        fs::FsConfig,
+        fs::{FsConfig, FsModeConfig},
         network::{
             incoming::{
                 http_filter::{HttpFilterConfig, InnerFilter},
-                IncomingConfig,
+                DatabaseFilterConfig, DatabaseProtocol as DatabaseProtocolConfig, IncomingConfig,
+                IncomingMode as IncomingModeConfig,
             },
             outgoing::OutgoingConfig,
         },
+        output::OutputMode,
     },
     target::Target,
     LayerConfig,
 };
 use mirrord_intproxy_protocol::PortSubscription;
 use mirrord_protocol::{
-    tcp::{Filter, HttpFilter, StealType},
+    tcp::{DatabaseFilter, DatabaseProtocol, Filter, HttpFilter, StealType},
     Port,
 };
 use regex::RegexSet;
 
 use crate::{
     debugger_ports::DebuggerPorts,
-    file::{filter::FileFilter, mapper::FileRemapper},
+    file::{filter::FileFilter, mapper::FileRemapper, overlay::Overlay, root_view::RootView},
     socket::{dns_selector::DnsSelector, OutgoingSelector},
 };
 
@@ -37,6 +39,8 @@ pub struct LayerSetup {
     config: LayerConfig,
     file_filter: FileFilter,
     file_remapper: FileRemapper,
+    root_view: RootView,
+    fs_overlay: Option<Overlay>,
     debugger_ports: DebuggerPorts,
     remote_unix_streams: RegexSet,
     outgoing_selector: OutgoingSelector,
@@ -50,10 +54,30 @@ pub struct LayerSetup {
 }
 
 impl LayerSetup {
-    pub fn new(config: LayerConfig, debugger_ports: DebuggerPorts, local_hostname: bool) -> Self {
+    pub fn new(
+        mut config: LayerConfig,
+        debugger_ports: DebuggerPorts,
+        local_hostname: bool,
+    ) -> Self {
+        if config.mode.is_read_only() {
+            restrict_to_read_only(&mut config);
+        }
+
         let file_filter = FileFilter::new(config.feature.fs.clone());
         let file_remapper =
             FileRemapper::new(config.feature.fs.mapping.clone().unwrap_or_default());
+        let root_view = RootView::from_env();
+        // The CLI picks the overlay directory when it's not set.
+        let fs_overlay = config.feature.fs.mode.is_copy_on_write().then(|| {
+            Overlay::new(
+                config
+                    .feature
+                    .fs
+                    .overlay
+                    .clone()
+                    .unwrap_or_else(|| std::env::temp_dir().join("mirrord-overlay")),
+            )
+        });
 
         let remote_unix_streams = config
             .feature
@@ -87,6 +111,8 @@ impl LayerSetup {
             config,
             file_filter,
             file_remapper,
+            root_view,
+            fs_overlay,
             debugger_ports,
             remote_unix_streams,
             outgoing_selector,
@@ -119,6 +145,14 @@ impl LayerSetup {
         &self.file_remapper
     }
 
+    pub(crate) fn root_view(&self) -> &RootView {
+        &self.root_view
+    }
+
+    pub(crate) fn fs_overlay(&self) -> Option<&Overlay> {
+        self.fs_overlay.as_ref()
+    }
+
     pub fn incoming_config(&self) -> &IncomingConfig {
         &self.config.feature.network.incoming
     }
@@ -131,10 +165,19 @@ impl LayerSetup {
         &self.config.experimental
     }
 
+    /// Whether the `exec` family is hooked, always on macOS (to patch SIP binaries).
+    pub(crate) fn exec_hooks_enabled(&self) -> bool {
+        cfg!(target_os = "macos") || self.experimental().enable_exec_hooks_linux
+    }
+
     pub fn remote_dns_enabled(&self) -> bool {
         self.config.feature.network.dns.enabled
     }
 
+    pub fn remote_interfaces(&self) -> bool {
+        self.config.feature.network.interfaces
+    }
+
     pub fn targetless(&self) -> bool {
         self.config
             .target
@@ -144,6 +187,16 @@ impl LayerSetup {
             .unwrap_or(true)
     }
 
+    /// Where the output of the local process goes, always [`OutputMode::Local`] when running
+    /// without a target.
+    pub fn output_mode(&self) -> OutputMode {
+        if self.targetless() {
+            OutputMode::Local
+        } else {
+            self.config.feature.output
+        }
+    }
+
     #[cfg(target_os = "macos")]
     pub fn sip_binaries(&self) -> Vec<String> {
         self.config
@@ -187,6 +240,22 @@ impl LayerSetup {
     }
 }
 
+/// Turns off the features that would modify the target, for the read-only
+/// [`mode`](LayerConfig::mode).
+///
+/// The CLI rejects configs that enable them, but we don't rely on it here.
+fn restrict_to_read_only(config: &mut LayerConfig) {
+    let fs = &mut config.feature.fs;
+    if fs.mode == FsModeConfig::Write {
+        fs.mode = FsModeConfig::Read;
+    }
+    fs.read_write = None;
+    fs.scratch = None;
+
+    config.feature.network.incoming.mode = IncomingModeConfig::Mirror;
+    config.feature.output = OutputMode::Local;
+}
+
 /// HTTP filter used by the layer with the `steal` feature.
 #[derive(Debug)]
 pub enum StealHttpFilter {
@@ -205,6 +274,36 @@ pub struct StealHttpSettings {
     pub ports: HashSet<Port>,
 }
 
+/// Settings for handling database connections with the `steal` feature.
+#[derive(Debug)]
+pub struct StealDatabaseSettings {
+    /// The database filter to use.
+    pub filter: DatabaseFilter,
+    /// Ports to filter database connections on.
+    pub ports: HashSet<Port>,
+}
+
+impl StealDatabaseSettings {
+    /// Creates a new instance from the given [`DatabaseFilterConfig`].
+    fn new(config: &DatabaseFilterConfig) -> Self {
+        let protocol = match config.protocol {
+            DatabaseProtocolConfig::Postgres => DatabaseProtocol::Postgres,
+            DatabaseProtocolConfig::Tds => DatabaseProtocol::Tds,
+        };
+        let make_filter =
+            |filter: &String| Filter::new(filter.clone()).expect("invalid filter expression");
+
+        Self {
+            filter: DatabaseFilter {
+                protocol,
+                database: config.database.as_ref().map(make_filter),
+                user: config.user.as_ref().map(make_filter),
+            },
+            ports: config.get_filtered_ports(),
+        }
+    }
+}
+
 /// Operation mode for the `incoming` feature.
 #[derive(Debug)]
 pub enum IncomingMode {
@@ -213,7 +312,7 @@ pub enum IncomingMode {
     Mirror,
     /// The agent sends data only to the user application.
     /// Data coming from the layer is sent to the agent.
-    Steal(StealHttpSettings),
+    Steal(StealHttpSettings, Option<StealDatabaseSettings>),
 }
 
 impl IncomingMode {
@@ -235,6 +334,11 @@ impl IncomingMode {
                 all_of: None,
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(HttpFilter::Path(
                 Filter::new(path.into()).expect("invalid filter expression"),
             )),
@@ -245,6 +349,11 @@ impl IncomingMode {
                 all_of: None,
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(HttpFilter::Header(
                 Filter::new(header.into()).expect("invalid filter expression"),
             )),
@@ -255,6 +364,11 @@ impl IncomingMode {
                 all_of: Some(filters),
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(Self::make_composite_filter(true, filters)),
 
             HttpFilterConfig {
@@ -263,6 +377,11 @@ impl IncomingMode {
                 all_of: None,
                 any_of: Some(filters),
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::Filter(Self::make_composite_filter(false, filters)),
 
             HttpFilterConfig {
@@ -271,12 +390,22 @@ impl IncomingMode {
                 all_of: None,
                 any_of: None,
                 ports: _ports,
+                fallback: _,
+                response_timeout_ms: _,
+                response_idle_timeout_ms: _,
+                response_total_timeout_ms: _,
+                match_route_hosts: _,
             } => StealHttpFilter::None,
 
             _ => panic!("multiple HTTP filters specified, this is a bug"),
         };
 
-        Self::Steal(StealHttpSettings { filter, ports })
+        let database = config
+            .database_filter
+            .as_ref()
+            .map(StealDatabaseSettings::new);
+
+        Self::Steal(StealHttpSettings { filter, ports }, database)
     }
 
     fn make_composite_filter(all: bool, filters: &[InnerFilter]) -> HttpFilter {
@@ -297,10 +426,20 @@ impl IncomingMode {
 
     /// Returns [`PortSubscription`] request to be used for the given port.
     pub fn subscription(&self, port: Port) -> PortSubscription {
-        let Self::Steal(steal) = self else {
+        let Self::Steal(steal, database) = self else {
             return PortSubscription::Mirror(port);
         };
 
+        if let Some(database) = database
+            .as_ref()
+            .filter(|database| database.ports.contains(&port))
+        {
+            return PortSubscription::Steal(StealType::FilteredDatabase(
+                port,
+                database.filter.clone(),
+            ));
+        }
+
         let steal_type = match &steal.filter {
             _ if !steal.ports.contains(&port) => StealType::All(port),
             StealHttpFilter::None => StealType::All(port),
@@ -3,7 +3,7 @@
 use std::{
     collections::HashMap,
     net::{SocketAddr, ToSocketAddrs},
-    os::unix::io::RawFd,
+    os::unix::io::{BorrowedFd, RawFd},
     str::FromStr,
     sync::{Arc, LazyLock, Mutex},
 };
@@ -17,11 +17,11 @@ use mirrord_config::feature::network::{
     filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter},
     outgoing::{OutgoingConfig, OutgoingFilterConfig},
 };
-use mirrord_intproxy_protocol::{NetProtocol, PortUnsubscribe};
+use mirrord_intproxy_protocol::{InterceptedConnection, NetProtocol, PortRelease, PortUnsubscribe};
 use mirrord_protocol::{
-    outgoing::SocketAddress, DnsLookupError, ResolveErrorKindInternal, ResponseError,
+    outgoing::SocketAddress, DnsLookupError, Port, ResolveErrorKindInternal, ResponseError,
 };
-use socket2::SockAddr;
+use socket2::{SockAddr, SockRef};
 use tracing::warn;
 
 use crate::{
@@ -36,6 +36,7 @@ mod apple_dnsinfo;
 pub(crate) mod dns_selector;
 pub(super) mod hooks;
 pub(crate) mod ops;
+mod udp_dns;
 
 pub(crate) const SHARED_SOCKETS_ENV_VAR: &str = "MIRRORD_SHARED_SOCKETS";
 
@@ -144,6 +145,12 @@ pub struct Bound {
     /// Actual bound address that we use to communicate between the user's listener socket and our
     /// interceptor socket.
     address: SocketAddr,
+
+    /// Port reserved in the agent for this socket, that the remote peers connect to (see
+    /// [`PortReserve`](mirrord_intproxy_protocol::PortReserve)).
+    ///
+    /// When set, it's the port that the user sees in [`libc::getsockname`].
+    reserved_port: Option<Port>,
 }
 
 #[derive(Debug, Default, Clone, Encode, Decode)]
@@ -180,12 +187,18 @@ impl TryFrom<c_int> for SocketKind {
     type Error = Bypass;
 
     fn try_from(type_: c_int) -> Result<Self, Self::Error> {
-        if (type_ & libc::SOCK_STREAM) > 0 {
-            Ok(SocketKind::Tcp(type_))
-        } else if (type_ & libc::SOCK_DGRAM) > 0 {
-            Ok(SocketKind::Udp(type_))
-        } else {
-            Err(Bypass::Type(type_))
+        // On linux, `SOCK_NONBLOCK` and `SOCK_CLOEXEC` may be or'ed into the type (.NET always
+        // passes `SOCK_CLOEXEC`). We can't just test bits, as `SOCK_RAW` contains both
+        // `SOCK_STREAM` and `SOCK_DGRAM` bits.
+        #[cfg(target_os = "linux")]
+        let kind = type_ & !(libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC);
+        #[cfg(not(target_os = "linux"))]
+        let kind = type_;
+
+        match kind {
+            libc::SOCK_STREAM => Ok(SocketKind::Tcp(type_)),
+            libc::SOCK_DGRAM => Ok(SocketKind::Udp(type_)),
+            _ => Err(Bypass::Type(type_)),
         }
     }
 }
@@ -221,7 +234,8 @@ impl UserSocket {
         }
     }
 
-    /// Inform internal proxy about closing a listening port.
+    /// Inform internal proxy about closing a listening port, and releasing the port reserved in
+    /// the agent.
     #[mirrord_layer_macro::instrument(level = "trace", fields(pid = std::process::id()), ret)]
     pub(crate) fn close(&self) {
         if let Self {
@@ -231,10 +245,45 @@ impl UserSocket {
         } = self
         {
             let _ = common::make_proxy_request_no_response(PortUnsubscribe {
-                port: bound.requested_address.port(),
+                port: bound
+                    .reserved_port
+                    .unwrap_or_else(|| bound.requested_address.port()),
                 listening_on: bound.address,
             });
         }
+
+        if let Self {
+            state: SocketState::Bound(bound) | SocketState::Listening(bound),
+            ..
+        } = self
+            && let Some(port) = bound.reserved_port
+        {
+            let _ = common::make_proxy_request_no_response(PortRelease { port });
+        }
+    }
+
+    /// Returns the [`InterceptedConnection`] of this socket, `fd`, when it's a TCP connection
+    /// made (or accepted) through the internal proxy.
+    pub(crate) fn intercepted_connection(&self, fd: RawFd) -> Option<InterceptedConnection> {
+        let Self {
+            state: SocketState::Connected(connected),
+            kind: SocketKind::Tcp(..),
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        match &connected.layer_address {
+            Some(layer_address) => Some(InterceptedConnection::Outgoing(layer_address.clone())),
+            // Accepted from the incoming interceptor, which is the real peer.
+            None => {
+                let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+                let peer = SockRef::from(&fd).peer_addr().ok()?.as_socket()?;
+
+                Some(InterceptedConnection::Incoming(peer))
+            }
+        }
     }
 }
 
@@ -442,7 +491,7 @@ impl ProtocolAndAddressFilterExt for ProtocolAndAddressFilter {
         match &self.address {
             AddressFilter::Name(name, port) => {
                 let resolved_ips = if crate::setup().remote_dns_enabled() && !force_local_dns {
-                    match remote_getaddrinfo(name.to_string()) {
+                    match remote_getaddrinfo(name.to_string(), None) {
                         Ok(res) => res.into_iter().map(|(_, ip)| ip).collect(),
                         Err(HookError::ResponseError(ResponseError::DnsLookup(
                             DnsLookupError {
@@ -552,3 +601,34 @@ impl SocketAddrExt for SocketAddr {
             .and_then(|address| address.as_socket().bypass(Bypass::AddressConversion))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(libc::SOCK_STREAM, Ok(SocketKind::Tcp(libc::SOCK_STREAM)))]
+    #[case(libc::SOCK_DGRAM, Ok(SocketKind::Udp(libc::SOCK_DGRAM)))]
+    #[case(libc::SOCK_RAW, Err(Bypass::Type(libc::SOCK_RAW)))]
+    #[case(libc::SOCK_SEQPACKET, Err(Bypass::Type(libc::SOCK_SEQPACKET)))]
+    fn socket_kind_from_type(#[case] type_: c_int, #[case] expected: Result<SocketKind, Bypass>) {
+        assert_eq!(SocketKind::try_from(type_), expected);
+    }
+
+    /// .NET creates its sockets with `SOCK_CLOEXEC`.
+    #[cfg(target_os = "linux")]
+    #[rstest]
+    #[case(
+        libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+        SocketKind::Tcp(libc::SOCK_STREAM | libc::SOCK_CLOEXEC)
+    )]
+    #[case(
+        libc::SOCK_DGRAM | libc::SOCK_NONBLOCK,
+        SocketKind::Udp(libc::SOCK_DGRAM | libc::SOCK_NONBLOCK)
+    )]
+    fn socket_kind_from_type_with_flags(#[case] type_: c_int, #[case] expected: SocketKind) {
+        assert_eq!(SocketKind::try_from(type_), Ok(expected));
+    }
+}
@@ -1,7 +1,7 @@
-use std::{net::IpAddr, ops::Deref};
+use std::{collections::HashMap, net::IpAddr, ops::Deref};
 
 use mirrord_config::feature::network::{
-    dns::{DnsConfig, DnsFilterConfig},
+    dns::{DnsConfig, DnsFilterConfig, DnsOverride},
     filter::AddressFilter,
 };
 use tracing::Level;
@@ -16,9 +16,25 @@ pub struct DnsSelector {
     filters: Vec<AddressFilter>,
     /// Whether a query matching one of [`Self::filters`] should be done locally.
     filter_is_local: bool,
+    /// Hostnames with a fixed resolution, taken from [`DnsConfig::overrides`].
+    overrides: HashMap<String, DnsOverride>,
 }
 
 impl DnsSelector {
+    /// Checks if the query for `node` was overridden in the config.
+    ///
+    /// Bypasses queries for hostnames overridden with [`DnsOverride::Local`], and returns the
+    /// address for hostnames overridden with [`DnsOverride::Address`]. Should be called before
+    /// [`Self::check_query`], as overrides take precedence over the filters.
+    #[tracing::instrument(level = Level::DEBUG, ret)]
+    pub fn check_override(&self, node: &str) -> Detour<Option<IpAddr>> {
+        match self.overrides.get(node) {
+            Some(DnsOverride::Local) => Detour::Bypass(Bypass::LocalDns),
+            Some(DnsOverride::Address(address)) => Detour::Success(Some(*address)),
+            None => Detour::Success(None),
+        }
+    }
+
     /// Bypasses queries that should be done locally.
     #[tracing::instrument(level = Level::DEBUG, ret)]
     pub fn check_query(&self, node: &str, port: u16) -> Detour<()> {
@@ -59,6 +75,7 @@ impl From<&DnsConfig> for DnsSelector {
             return Self {
                 filters: Default::default(),
                 filter_is_local: false,
+                overrides: Default::default(),
             };
         }
 
@@ -78,9 +95,22 @@ impl From<&DnsConfig> for DnsSelector {
             })
             .collect();
 
+        let overrides = value
+            .overrides
+            .iter()
+            .flatten()
+            .map(|(node, value)| {
+                let value = value
+                    .parse::<DnsOverride>()
+                    .expect("bad DNS override, should be verified in the CLI");
+                (node.clone(), value)
+            })
+            .collect();
+
         Self {
             filters,
             filter_is_local,
+            overrides,
         }
     }
 }
@@ -7,14 +7,19 @@ use std::{
 };
 
 use errno::{set_errno, Errno};
-use libc::{c_char, c_int, c_void, hostent, size_t, sockaddr, socklen_t, ssize_t, EINVAL};
+use libc::{c_char, c_int, c_void, flock, hostent, size_t, sockaddr, socklen_t, ssize_t, EINVAL};
 use mirrord_config::experimental::ExperimentalConfig;
 use mirrord_layer_macro::{hook_fn, hook_guard_fn};
 
 #[cfg(target_os = "macos")]
 use super::apple_dnsinfo::*;
 use super::ops::*;
-use crate::{detour::DetourGuard, hooks::HookManager, replace};
+use crate::{
+    detour::DetourGuard,
+    file::ops::{is_record_lock_command, record_lock},
+    hooks::HookManager,
+    replace,
+};
 
 /// Here we keep addr infos that we allocated so we'll know when to use the original
 /// freeaddrinfo function and when to use our implementation
@@ -193,6 +198,17 @@ pub(super) unsafe extern "C" fn _accept_nocancel_detour(
 #[hook_fn]
 pub(crate) unsafe extern "C" fn fcntl_detour(fd: c_int, cmd: c_int, mut arg: ...) -> c_int {
     let arg = arg.arg::<usize>();
+
+    // The record locks of remote files are placed in the agent.
+    if is_record_lock_command(cmd) {
+        let Some(_guard) = DetourGuard::new() else {
+            return FN_FCNTL(fd, cmd, arg);
+        };
+
+        return record_lock(fd, cmd, arg as *mut flock)
+            .unwrap_or_bypass_with(|_| FN_FCNTL(fd, cmd, arg));
+    }
+
     let fcntl_result = FN_FCNTL(fd, cmd, arg);
     let guard = DetourGuard::new();
     if guard.is_none() {
@@ -314,7 +330,9 @@ unsafe extern "C" fn freeaddrinfo_detour(addrinfo: *mut libc::addrinfo) {
         while !current.is_null() {
             let current_box = Box::from_raw(current);
             let ai_addr = Box::from_raw(current_box.ai_addr);
-            let ai_canonname = CString::from_raw(current_box.ai_canonname);
+            // Only set when the user requested `AI_CANONNAME`.
+            let ai_canonname = (!current_box.ai_canonname.is_null())
+                .then(|| CString::from_raw(current_box.ai_canonname));
 
             current = (*current).ai_next;
 
@@ -476,20 +494,34 @@ unsafe extern "C" fn dns_configuration_free_detour(config: *mut dns_config_t) {
         .for_each(|resolver| free_dns_resolver_t(resolver));
 }
 
+/// Lists the network interfaces of the target, see [`remote_getifaddrs`], or the local ones
+/// without the IPv6 addresses, see [`getifaddrs`].
 #[hook_guard_fn]
 pub(crate) unsafe extern "C" fn getifaddrs_detour(ifaddrs: *mut *mut libc::ifaddrs) -> c_int {
-    match getifaddrs() {
-        Ok(got_ifaddrs) => {
-            *ifaddrs = got_ifaddrs;
+    remote_getifaddrs()
+        .map(|remote_ifaddrs| {
+            *ifaddrs = remote_ifaddrs;
             0
-        }
-        Err(error) => error.into(),
-    }
+        })
+        .unwrap_or_bypass_with(|_| {
+            if !crate::setup().experimental().hide_ipv6_interfaces {
+                return FN_GETIFADDRS(ifaddrs);
+            }
+
+            match getifaddrs() {
+                Ok(got_ifaddrs) => {
+                    *ifaddrs = got_ifaddrs;
+                    0
+                }
+                Err(error) => error.into(),
+            }
+        })
 }
 
 pub(crate) unsafe fn enable_socket_hooks(
     hook_manager: &mut HookManager,
     enabled_remote_dns: bool,
+    enabled_remote_interfaces: bool,
     experimental: &ExperimentalConfig,
 ) {
     replace!(hook_manager, "socket", socket_detour, FnSocket, FN_SOCKET);
@@ -643,7 +675,7 @@ pub(crate) unsafe fn enable_socket_hooks(
         }
     }
 
-    if experimental.hide_ipv6_interfaces {
+    if enabled_remote_interfaces || experimental.hide_ipv6_interfaces {
         replace!(
             hook_manager,
             "getifaddrs",
@@ -18,11 +18,13 @@ use libc::{c_int, c_void, hostent, sockaddr, socklen_t, AF_UNIX};
 use mirrord_config::feature::network::incoming::{IncomingConfig, IncomingMode};
 use mirrord_intproxy_protocol::{
     ConnMetadataRequest, ConnMetadataResponse, NetProtocol, OutgoingConnectRequest,
-    OutgoingConnectResponse, PortSubscribe,
+    OutgoingConnectResponse, PortReserve, PortSubscribe, PortSubscription,
 };
 use mirrord_protocol::{
-    dns::{GetAddrInfoRequest, LookupRecord},
+    dns::{AddrInfoFamily, AddrInfoHint, GetAddrInfoRequestV2, LookupRecord},
     file::{OpenFileResponse, OpenOptionsInternal, ReadFileResponse},
+    interfaces::{GetIfAddrsRequest, GetIfAddrsResponse, InterfaceAddress, InterfaceFlags},
+    tcp::StealType,
 };
 use nix::sys::socket::{sockopt, SockaddrIn, SockaddrIn6, SockaddrLike, SockaddrStorage};
 use socket2::SockAddr;
@@ -129,6 +131,12 @@ pub(super) fn socket(domain: c_int, type_: c_int, protocol: c_int) -> Detour<Raw
         Ok(())
     }?;
 
+    // ICMP sockets (`SOCK_DGRAM` with `IPPROTO_ICMP`) are used for ping, e.g. by .NET's
+    // `System.Net.NetworkInformation.Ping`, and must not be treated as UDP.
+    if domain != libc::AF_UNIX && !matches!(protocol, 0 | libc::IPPROTO_TCP | libc::IPPROTO_UDP) {
+        return Detour::Bypass(Bypass::Protocol(protocol));
+    }
+
     if domain == libc::AF_INET6 {
         return Detour::Error(HookError::SocketUnsuportedIpv6);
     }
@@ -217,7 +225,7 @@ pub(super) fn bind(
     let requested_address = SocketAddr::try_from_raw(raw_address, address_length)?;
     let requested_port = requested_address.port();
     let incoming_config = crate::setup().incoming_config();
-    let mut socket = {
+    let socket = {
         SOCKETS
             .lock()?
             .remove(&sockfd)
@@ -231,18 +239,27 @@ pub(super) fn bind(
             })?
     };
 
+    // Remote peers reach the socket through the port reserved in the agent, so the incoming
+    // config doesn't apply to it.
+    let port_to_reserve = match socket.kind {
+        SocketKind::Tcp(..) => port_to_reserve(&requested_address)?,
+        SocketKind::Udp(..) => None,
+    };
+
     // we don't use `is_localhost` here since unspecified means to listen
     // on all IPs.
-    if incoming_config.ignore_localhost && requested_address.ip().is_loopback() {
+    let ignored_localhost =
+        incoming_config.ignore_localhost && requested_address.ip().is_loopback();
+    if port_to_reserve.is_none() && ignored_localhost {
         return Detour::Bypass(Bypass::IgnoreLocalhost(requested_port));
     }
 
     // To handle #1458, we don't ignore port `0` for UDP.
-    if (matches!(socket.kind, SocketKind::Tcp(_)))
+    let ignored_port = (matches!(socket.kind, SocketKind::Tcp(_)))
         && is_ignored_tcp_port(&requested_address, incoming_config)
         || crate::setup().is_debugger_port(&requested_address)
-        || incoming_config.ignore_ports.contains(&requested_port)
-    {
+        || incoming_config.ignore_ports.contains(&requested_port);
+    if port_to_reserve.is_none() && ignored_port {
         Err(Bypass::Port(requested_address.port()))?;
     }
 
@@ -327,12 +344,26 @@ pub(super) fn bind(
     .and_then(|(_, address)| address.as_socket())
     .bypass(Bypass::AddressConversion)?;
 
-    Arc::get_mut(&mut socket).unwrap().state = SocketState::Bound(Bound {
-        requested_address,
-        address,
-    });
+    let reserved_port = match port_to_reserve {
+        Some(port) => reserve_port(port)?,
+        None => None,
+    };
 
-    SOCKETS.lock()?.insert(sockfd, socket);
+    // The socket is already bound, so it's left alone as if it was ignored.
+    if reserved_port.is_none() && (ignored_localhost || ignored_port) {
+        errno::set_errno(errno::Errno(0));
+        return Detour::Success(0);
+    }
+
+    update_socket_state(
+        sockfd,
+        &socket,
+        SocketState::Bound(Bound {
+            requested_address,
+            address,
+            reserved_port,
+        }),
+    )?;
 
     // node reads errno to check if bind was successful and doesn't care about the return value
     // (???)
@@ -340,6 +371,55 @@ pub(super) fn bind(
     Detour::Success(0)
 }
 
+/// Returns the remote port that should be reserved in the agent for a TCP socket bound to the
+/// `requested_address`, if any (`0` lets the agent pick one).
+///
+/// Ports are reserved for the sockets in
+/// [`IncomingConfig::reserved_ports`](mirrord_config::feature::network::incoming::IncomingConfig::reserved_ports),
+/// and for the sockets bound to the local address of a remote connection. That address belongs to
+/// the pod, so the application can only advertise it to the servers in the cluster, which connect
+/// back to it (e.g. FTP active mode).
+fn port_to_reserve(requested_address: &SocketAddr) -> Detour<Option<Port>> {
+    let reserved_ports = &crate::setup().incoming_config().reserved_ports;
+    if let Some(port) = reserved_ports.get(&requested_address.port()) {
+        return Detour::Success(Some(*port));
+    }
+
+    let ip = requested_address.ip();
+    if ip.is_loopback() || ip.is_unspecified() {
+        return Detour::Success(None);
+    }
+
+    let remote_ip = SOCKETS.lock()?.values().any(|socket| match &socket.state {
+        SocketState::Connected(Connected { local_address, .. }) => {
+            SocketAddr::try_from(local_address.clone()).is_ok_and(|local| local.ip() == ip)
+        }
+        _ => false,
+    });
+
+    Detour::Success(remote_ip.then_some(requested_address.port()))
+}
+
+/// Reserves the given `port` in the agent, returning the reserved port.
+///
+/// Returns [`None`] when the agent can't reserve ports, in which case the socket is reachable only
+/// locally.
+fn reserve_port(port: Port) -> Detour<Option<Port>> {
+    match common::make_proxy_request_with_response(PortReserve { port })? {
+        Ok(port) => Detour::Success(Some(port)),
+        Err(error @ (ResponseError::NotImplemented | ResponseError::ReadOnlySession(..))) => {
+            warn!(
+                %error,
+                port,
+                "Could not reserve the port in the agent, the listener will only be reachable \
+                locally."
+            );
+            Detour::Success(None)
+        }
+        Err(error) => Detour::Error(error.into()),
+    }
+}
+
 /// Warn the user if they are filtering HTTP, and it looks like they might have intended to also
 /// steal another port unfiltered, but didn't know they had to set `feature.network.incoming.ports`
 /// for that.
@@ -393,18 +473,25 @@ fn warn_on_suspected_unintentional_ignore(sockfd: RawFd) {
 /// later be routed to the fake local port.
 #[mirrord_layer_macro::instrument(level = Level::TRACE, fields(pid = std::process::id()), ret)]
 pub(super) fn listen(sockfd: RawFd, backlog: c_int) -> Detour<i32> {
-    let Some(mut socket) = SOCKETS.lock()?.remove(&sockfd) else {
+    let Some(socket) = SOCKETS.lock()?.remove(&sockfd) else {
         warn_on_suspected_unintentional_ignore(sockfd);
         return Detour::Bypass(Bypass::LocalFdNotFound(sockfd));
     };
 
     let setup = crate::setup();
+    let reserved = matches!(
+        socket.state,
+        SocketState::Bound(Bound {
+            reserved_port: Some(..),
+            ..
+        })
+    );
 
-    if matches!(setup.incoming_config().mode, IncomingMode::Off) {
+    if !reserved && matches!(setup.incoming_config().mode, IncomingMode::Off) {
         return Detour::Bypass(Bypass::DisabledIncoming);
     }
 
-    if setup.targetless() {
+    if !reserved && setup.targetless() {
         warn!(
             "Listening while running targetless. A targetless agent is not exposed by \
         any service. Therefore, letting this port bind happen locally instead of on the \
@@ -414,10 +501,7 @@ pub(super) fn listen(sockfd: RawFd, backlog: c_int) -> Detour<i32> {
     }
 
     match socket.state {
-        SocketState::Bound(Bound {
-            requested_address,
-            address,
-        }) => {
+        SocketState::Bound(bound) => {
             let listen_result = unsafe { FN_LISTEN(sockfd, backlog) };
             if listen_result != 0 {
                 let error = io::Error::last_os_error();
@@ -427,34 +511,111 @@ pub(super) fn listen(sockfd: RawFd, backlog: c_int) -> Detour<i32> {
                 Err(error)?
             }
 
-            let mapped_port = setup
-                .incoming_config()
-                .port_mapping
-                .get_by_left(&requested_address.port())
-                .copied()
-                .unwrap_or_else(|| requested_address.port());
-
-            common::make_proxy_request_with_response(PortSubscribe {
-                listening_on: address,
-                subscription: setup.incoming_mode().subscription(mapped_port),
-            })??;
+            subscribe_port(&bound)?;
 
             // this log message is expected by some E2E tests
-            tracing::debug!("daemon subscribed port {}", requested_address.port());
+            tracing::debug!("daemon subscribed port {}", bound.requested_address.port());
 
-            Arc::get_mut(&mut socket).unwrap().state = SocketState::Listening(Bound {
-                requested_address,
-                address,
-            });
+            update_socket_state(sockfd, &socket, SocketState::Listening(bound))?;
+
+            Detour::Success(listen_result)
+        }
+        // Listening again only changes the backlog, and the port is already subscribed. Prefork
+        // servers do this with the listeners that they inherit through `exec` (e.g. gunicorn's
+        // `SIGUSR2` upgrade).
+        SocketState::Listening(..) => {
+            let listen_result = unsafe { FN_LISTEN(sockfd, backlog) };
+            let error = io::Error::last_os_error();
 
             SOCKETS.lock()?.insert(sockfd, socket);
 
+            if listen_result != 0 {
+                Err(error)?
+            }
+
             Detour::Success(listen_result)
         }
         _ => Detour::Bypass(Bypass::InvalidState(sockfd)),
     }
 }
 
+/// Subscribes to the remote port of a listening socket, so that its connections are routed to
+/// the [`Bound::address`].
+fn subscribe_port(bound: &Bound) -> Detour<()> {
+    if let Some(port) = bound.reserved_port {
+        common::make_proxy_request_with_response(PortSubscribe {
+            listening_on: bound.address,
+            subscription: PortSubscription::Steal(StealType::Reserved(port)),
+        })??;
+
+        return Detour::Success(());
+    }
+
+    let setup = crate::setup();
+
+    let mapped_port = setup
+        .incoming_config()
+        .port_mapping
+        .get_by_left(&bound.requested_address.port())
+        .copied()
+        .unwrap_or_else(|| bound.requested_address.port());
+
+    common::make_proxy_request_with_response(PortSubscribe {
+        listening_on: bound.address,
+        subscription: setup.incoming_mode().subscription(mapped_port),
+    })??;
+
+    Detour::Success(())
+}
+
+/// Subscribes again to the ports of the listening sockets inherited through `exec`.
+///
+/// The internal proxy only knows that the parent process holds them, and would unsubscribe the
+/// ports when it exits, while this process is still listening.
+pub(crate) fn subscribe_inherited_listeners() {
+    let listeners = SOCKETS
+        .lock()
+        .map(|sockets| {
+            sockets
+                .values()
+                .filter_map(|socket| match (&socket.state, socket.kind) {
+                    (SocketState::Listening(bound), SocketKind::Tcp(..)) => Some(*bound),
+                    _ => None,
+                })
+                // Duplicated fds share the listener.
+                .map(|bound| (bound.address, bound))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+
+    for bound in listeners.into_values() {
+        if let Detour::Error(error) = subscribe_port(&bound) {
+            tracing::warn!(
+                %error,
+                port = bound.requested_address.port(),
+                "Failed to subscribe the port of an inherited listener"
+            );
+        }
+    }
+}
+
+/// Replaces the `socket` in [`SOCKETS`] with a copy in the given `state`, under the `sockfd` and
+/// under every fd duplicated from it, so that they don't see a stale state.
+fn update_socket_state(sockfd: RawFd, socket: &Arc<UserSocket>, state: SocketState) -> Detour<()> {
+    let mut updated = socket.as_ref().clone();
+    updated.state = state;
+    let updated = Arc::new(updated);
+
+    let mut sockets = SOCKETS.lock()?;
+    sockets
+        .values_mut()
+        .filter(|other| Arc::ptr_eq(other, socket))
+        .for_each(|other| *other = updated.clone());
+    sockets.insert(sockfd, updated);
+
+    Detour::Success(())
+}
+
 /// Common logic between Tcp/Udp `connect`, when used for the outgoing traffic feature.
 ///
 /// Sends a hook message that will be handled by `(Tcp|Udp)OutgoingHandler`, starting the request
@@ -470,11 +631,20 @@ fn connect_outgoing<const CALL_CONNECT: bool>(
 ) -> Detour<ConnectResult> {
     // Closure that performs the connection with mirrord messaging.
     let remote_connection = |remote_address: SockAddr| {
+        // DNS queries sent directly to the local nameservers must be resolved in the cluster.
+        // The user application still sees the original `remote_address`.
+        let agent_address = match (protocol, remote_address.as_socket()) {
+            (NetProtocol::Datagrams, Some(address)) => {
+                SocketAddress::from(udp_dns::redirect_dns_query(address))
+            }
+            _ => SocketAddress::try_from(remote_address.clone()).unwrap(),
+        };
+
         // Prepare this socket to be intercepted.
         let remote_address = SocketAddress::try_from(remote_address).unwrap();
 
         let request = OutgoingConnectRequest {
-            remote_address: remote_address.clone(),
+            remote_address: agent_address,
             protocol,
         };
         let response = common::make_proxy_request_with_response(request)??;
@@ -567,6 +737,7 @@ fn connect_to_local_address(
                     SocketState::Listening(Bound {
                         requested_address,
                         address,
+                        ..
                     }) => (requested_address.port() == ip_address.port()
                         && socket.protocol == user_socket_info.protocol)
                         .then(|| SockAddr::from(address)),
@@ -758,9 +929,20 @@ pub(super) fn getsockname(
                 SocketState::Connected(connected) => {
                     Detour::Success(connected.local_address.clone())
                 }
+                SocketState::Bound(Bound {
+                    requested_address,
+                    reserved_port: Some(port),
+                    ..
+                })
+                | SocketState::Listening(Bound {
+                    requested_address,
+                    reserved_port: Some(port),
+                    ..
+                }) => Detour::Success(SocketAddr::new(requested_address.ip(), *port).into()),
                 SocketState::Bound(Bound {
                     requested_address,
                     address,
+                    ..
                 }) => {
                     if requested_address.port() == 0 {
                         Detour::Success(
@@ -798,6 +980,7 @@ pub(super) fn accept(
                 SocketState::Listening(Bound {
                     requested_address,
                     address,
+                    ..
                 }) => Detour::Success((
                     socket.domain,
                     socket.protocol,
@@ -819,6 +1002,7 @@ pub(super) fn accept(
     let ConnMetadataResponse {
         remote_source,
         local_address,
+        local_port,
     } = common::make_proxy_request_with_response(ConnMetadataRequest {
         listener_address,
         peer_address,
@@ -826,7 +1010,7 @@ pub(super) fn accept(
 
     let state = SocketState::Connected(Connected {
         remote_address: remote_source.into(),
-        local_address: SocketAddr::new(local_address, port).into(),
+        local_address: SocketAddr::new(local_address, local_port.unwrap_or(port)).into(),
         layer_address: None,
     });
 
@@ -889,8 +1073,12 @@ pub(super) fn dup<const SWITCH_MAP: bool>(fd: c_int, dup_fd: i32) -> Result<(),
 ///
 /// This function updates the mapping in [`REMOTE_DNS_REVERSE_MAPPING`].
 #[mirrord_layer_macro::instrument(level = Level::TRACE, ret, err)]
-pub(super) fn remote_getaddrinfo(node: String) -> HookResult<Vec<(String, IpAddr)>> {
-    let addr_info_list = common::make_proxy_request_with_response(GetAddrInfoRequest { node })?.0?;
+pub(super) fn remote_getaddrinfo(
+    node: String,
+    hints: Option<AddrInfoHint>,
+) -> HookResult<Vec<(String, IpAddr)>> {
+    let addr_info_list =
+        common::make_proxy_request_with_response(GetAddrInfoRequestV2 { node, hints })?.0?;
 
     let mut remote_dns_reverse_mapping = REMOTE_DNS_REVERSE_MAPPING.lock()?;
     addr_info_list.iter().for_each(|lookup| {
@@ -903,6 +1091,37 @@ pub(super) fn remote_getaddrinfo(node: String) -> HookResult<Vec<(String, IpAddr
         .collect())
 }
 
+/// Converts the raw `getaddrinfo` hints into the platform independent [`AddrInfoHint`].
+fn addr_info_hint(raw_hints: &libc::addrinfo) -> AddrInfoHint {
+    let ai_family = match raw_hints.ai_family {
+        libc::AF_INET => AddrInfoFamily::Ipv4,
+        libc::AF_INET6 => AddrInfoFamily::Ipv6,
+        _ => AddrInfoFamily::Unspecified,
+    };
+
+    AddrInfoHint {
+        ai_family,
+        ai_socktype: raw_hints.ai_socktype,
+        ai_protocol: raw_hints.ai_protocol,
+        ai_flags: raw_hints.ai_flags,
+    }
+}
+
+/// Resolves `node` to the `address` set in the DNS overrides config, skipping the agent.
+///
+/// # Note
+///
+/// Like [`remote_getaddrinfo`], this function updates the mapping in
+/// [`REMOTE_DNS_REVERSE_MAPPING`], so that outgoing filters by hostname still apply.
+#[mirrord_layer_macro::instrument(level = Level::TRACE, ret, err)]
+fn overridden_getaddrinfo(node: String, address: IpAddr) -> HookResult<Vec<(String, IpAddr)>> {
+    REMOTE_DNS_REVERSE_MAPPING
+        .lock()?
+        .insert(address, node.clone());
+
+    Ok(vec![(node, address)])
+}
+
 /// Retrieves the result of calling `getaddrinfo` from a remote host (resolves remote DNS),
 /// converting the result into a `Box` allocated raw pointer of `libc::addrinfo` (which is basically
 /// a linked list of such type).
@@ -948,46 +1167,92 @@ pub(super) fn getaddrinfo(
         .and_then(|service| service.parse::<u16>().ok())
         .unwrap_or(0);
 
-    crate::setup().dns_selector().check_query(&node, service)?;
+    let dns_selector = crate::setup().dns_selector();
+    let overridden = dns_selector.check_override(&node)?;
+    if overridden.is_none() {
+        dns_selector.check_query(&node, service)?;
+    }
 
-    let raw_hints = raw_hints
-        .cloned()
-        .unwrap_or_else(|| unsafe { mem::zeroed() });
+    let hints = raw_hints.map(addr_info_hint);
 
-    // TODO(alex): Use more fields from `raw_hints` to respect the user's `getaddrinfo` call.
     let libc::addrinfo {
+        ai_family,
         ai_socktype,
         ai_protocol,
+        ai_flags,
         ..
-    } = raw_hints;
+    } = raw_hints
+        .cloned()
+        .unwrap_or_else(|| unsafe { mem::zeroed() });
 
     // Some apps (gRPC on Python) use `::` to listen on all interfaces, and usually that just means
-    // resolve on unspecified. So we just return that in IpV4 because we don't support ipv6.
-    let resolved_addr = if node == "::" {
+    // resolve on unspecified. So we just return that in IpV4 because we don't support ipv6, unless
+    // the user explicitly asked for an IPv6 address.
+    let resolved_addr = if let Some(address) = overridden {
+        overridden_getaddrinfo(node.clone(), address)?
+    } else if node == "::" {
+        let address = if ai_family == libc::AF_INET6 {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        };
+
         // name is "" because that's what happens in real flow.
-        vec![("".to_string(), IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
+        vec![("".to_string(), address)]
     } else {
-        remote_getaddrinfo(node.clone())?
+        remote_getaddrinfo(node.clone(), hints)?
     };
 
+    // When the user does not ask for a specific socket type, we return an entry for each type,
+    // like the original `getaddrinfo` does.
+    let socket_types = match ai_socktype {
+        0 => vec![
+            (libc::SOCK_STREAM, libc::IPPROTO_TCP),
+            (libc::SOCK_DGRAM, libc::IPPROTO_UDP),
+        ],
+        libc::SOCK_STREAM if ai_protocol == 0 => vec![(libc::SOCK_STREAM, libc::IPPROTO_TCP)],
+        libc::SOCK_DGRAM if ai_protocol == 0 => vec![(libc::SOCK_DGRAM, libc::IPPROTO_UDP)],
+        _ => vec![(ai_socktype, ai_protocol)],
+    };
+
+    // Older agents ignore the hints, so the addresses have to be filtered here as well.
+    let entries = resolved_addr
+        .into_iter()
+        .filter(|(_, address)| match ai_family {
+            libc::AF_INET => address.is_ipv4(),
+            libc::AF_INET6 => address.is_ipv6(),
+            _ => true,
+        })
+        .flat_map(|(name, address)| {
+            socket_types.iter().map(move |&(ai_socktype, ai_protocol)| {
+                (name.clone(), address, ai_socktype, ai_protocol)
+            })
+        })
+        .collect::<Vec<_>>();
+
     let mut managed_addr_info = MANAGED_ADDRINFO.lock()?;
-    // Only care about: `ai_family`, `ai_socktype`, `ai_protocol`.
-    let result = resolved_addr
+    let result = entries
         .into_iter()
-        .map(|(name, address)| {
+        .enumerate()
+        .map(|(index, (name, address, ai_socktype, ai_protocol))| {
             let rawish_sock_addr = SockAddr::from(SocketAddr::new(address, service));
             let ai_addrlen = rawish_sock_addr.len();
             let ai_family = rawish_sock_addr.family() as _;
 
             // Must outlive this function, as it is stored as a pointer in `libc::addrinfo`.
             let ai_addr = Box::into_raw(Box::new(unsafe { *rawish_sock_addr.as_ptr() }));
-            let ai_canonname = CString::new(name).unwrap().into_raw();
+
+            // Only the first entry carries the canonical name, and only if it was requested.
+            let ai_canonname = if index == 0 && ai_flags & libc::AI_CANONNAME != 0 {
+                CString::new(name).unwrap().into_raw()
+            } else {
+                ptr::null_mut()
+            };
 
             libc::addrinfo {
                 ai_flags: 0,
                 ai_family,
                 ai_socktype,
-                // TODO(alex): Don't just reuse whatever the user passed to us.
                 ai_protocol,
                 ai_addrlen,
                 ai_addr,
@@ -1064,9 +1329,14 @@ pub(super) fn gethostbyname(raw_name: Option<&CStr>) -> Detour<*mut hostent> {
         })?
         .into();
 
-    crate::setup().dns_selector().check_query(&name, 0)?;
-
-    let hosts_and_ips = remote_getaddrinfo(name.clone())?;
+    let dns_selector = crate::setup().dns_selector();
+    let hosts_and_ips = match dns_selector.check_override(&name)? {
+        Some(address) => overridden_getaddrinfo(name.clone(), address)?,
+        None => {
+            dns_selector.check_query(&name, 0)?;
+            remote_getaddrinfo(name.clone(), None)?
+        }
+    };
 
     // We could `unwrap` here, as this would have failed on the previous conversion.
     let host_name = CString::new(name)?;
@@ -1137,7 +1407,6 @@ pub(super) fn gethostname() -> Detour<&'static CString> {
 }
 
 /// Retrieves the contents of remote's `/etc/resolv.conf`
-#[cfg(target_os = "macos")]
 #[mirrord_layer_macro::instrument(level = "trace")]
 pub(super) fn read_remote_resolv_conf() -> Detour<Vec<u8>> {
     let resolv_path = PathBuf::from("/etc/resolv.conf");
@@ -1234,6 +1503,7 @@ fn send_dns_patch(
             SocketState::Bound(Bound {
                 requested_address,
                 address,
+                ..
             }) => {
                 // Special case for port `0`, see `getsockname`.
                 if requested_address.port() == 0 {
@@ -1666,3 +1936,130 @@ pub(super) fn getifaddrs() -> HookResult<*mut libc::ifaddrs> {
 
     Ok(new_list_start)
 }
+
+/// Lists the network interfaces of the target for `getifaddrs`, see
+/// [`NetworkConfig::interfaces`](mirrord_config::feature::network::NetworkConfig::interfaces).
+///
+/// The list is allocated in a single block with `malloc`, like the one of libc, so that the
+/// original `freeifaddrs` frees it.
+#[mirrord_layer_macro::instrument(level = "trace", ret)]
+pub(super) fn remote_getifaddrs() -> Detour<*mut libc::ifaddrs> {
+    if !crate::setup().remote_interfaces() {
+        return Detour::Bypass(Bypass::LocalInterfaces);
+    }
+
+    let addresses = match common::make_proxy_request_with_response(GetIfAddrsRequest)? {
+        GetIfAddrsResponse(Ok(addresses)) => addresses,
+        GetIfAddrsResponse(Err(ResponseError::NotImplemented)) => {
+            warn!(
+                "The agent can't list the network interfaces of the target, using the local ones"
+            );
+            return Detour::Bypass(Bypass::NotImplemented);
+        }
+        GetIfAddrsResponse(Err(fail)) => return Detour::Error(fail.into()),
+    };
+
+    let hide_ipv6 = crate::setup().experimental().hide_ipv6_interfaces;
+    let addresses = addresses
+        .into_iter()
+        .filter(|interface| !(hide_ipv6 && interface.address.is_ipv6()))
+        .collect::<Vec<_>>();
+    if addresses.is_empty() {
+        return Detour::Success(ptr::null_mut());
+    }
+
+    // The entries, then 3 `sockaddr`s for each of them (address, netmask and broadcast), then
+    // their names.
+    let entries_size = mem::size_of::<libc::ifaddrs>() * addresses.len();
+    let sockaddrs_size = mem::size_of::<libc::sockaddr_storage>() * addresses.len() * 3;
+    let names_size = addresses
+        .iter()
+        .map(|interface| interface.name.len() + 1)
+        .sum::<usize>();
+
+    // Safety: We assume `libc::malloc` is the same allocator as the user's system. The zeroed
+    // memory is a valid list of `ifaddrs` with null pointers, and null terminates the names.
+    let block = unsafe { libc::calloc(1, entries_size + sockaddrs_size + names_size) };
+    if block.is_null() {
+        return Detour::Error(io::Error::from_raw_os_error(libc::ENOMEM).into());
+    }
+
+    let entries = block.cast::<libc::ifaddrs>();
+    let mut next_sockaddr =
+        unsafe { block.byte_add(entries_size) }.cast::<libc::sockaddr_storage>();
+    let mut next_name =
+        unsafe { block.byte_add(entries_size + sockaddrs_size) }.cast::<libc::c_char>();
+
+    let mut copy_sockaddr = |address: Option<IpAddr>| {
+        let Some(address) = address else {
+            return ptr::null_mut();
+        };
+
+        let raw_address = SockAddr::from(SocketAddr::new(address, 0));
+        let slot = next_sockaddr;
+        // Safety: there are 3 slots for each entry, each big enough for any address.
+        unsafe {
+            copy_nonoverlapping(
+                raw_address.as_ptr().cast::<u8>(),
+                slot.cast::<u8>(),
+                raw_address.len() as usize,
+            );
+            next_sockaddr = next_sockaddr.add(1);
+        }
+
+        slot.cast::<sockaddr>()
+    };
+
+    let count = addresses.len();
+    for (index, interface) in addresses.into_iter().enumerate() {
+        let InterfaceAddress {
+            name,
+            flags,
+            address,
+            netmask,
+            broadcast,
+        } = interface;
+
+        // Safety: all the pointers are within the block, see the sizes above.
+        unsafe {
+            let entry = &mut *entries.add(index);
+
+            copy_nonoverlapping(name.as_ptr().cast::<libc::c_char>(), next_name, name.len());
+            entry.ifa_name = next_name;
+            next_name = next_name.add(name.len() + 1);
+
+            entry.ifa_flags = interface_flags(flags);
+            entry.ifa_addr = copy_sockaddr(Some(address));
+            entry.ifa_netmask = copy_sockaddr(netmask);
+            #[cfg(target_os = "linux")]
+            {
+                entry.ifa_ifu = copy_sockaddr(broadcast);
+            }
+            #[cfg(target_os = "macos")]
+            {
+                entry.ifa_dstaddr = copy_sockaddr(broadcast);
+            }
+
+            if index + 1 < count {
+                entry.ifa_next = entries.add(index + 1);
+            }
+        }
+    }
+
+    Detour::Success(entries)
+}
+
+/// Converts the [`InterfaceFlags`] of the agent to the `IFF_*` flags of this platform.
+fn interface_flags(flags: InterfaceFlags) -> libc::c_uint {
+    [
+        (flags.up, libc::IFF_UP),
+        (flags.running, libc::IFF_RUNNING),
+        (flags.loopback, libc::IFF_LOOPBACK),
+        (flags.point_to_point, libc::IFF_POINTOPOINT),
+        (flags.broadcast, libc::IFF_BROADCAST),
+        (flags.multicast, libc::IFF_MULTICAST),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .fold(0, |all, (_, flag)| all | flag as libc::c_uint)
+}
@@ -0,0 +1,110 @@
+//! Redirection of DNS queries that are sent directly over UDP, by resolvers that bypass
+//! `getaddrinfo` (Go's pure-Go resolver, musl, c-ares).
+//!
+//! These resolvers read the local `/etc/resolv.conf`, and send their queries to the nameservers of
+//! the user's machine. When remote DNS is enabled, we redirect the queries sent to these local
+//! nameservers to the nameserver of the target (taken from the remote `/etc/resolv.conf`), so that
+//! they're resolved in the cluster.
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{LazyLock, OnceLock},
+};
+
+use tracing::{trace, warn};
+
+use super::ops::read_remote_resolv_conf;
+use crate::detour::{Detour, DetourGuard};
+
+/// Port used by DNS servers.
+const DNS_PORT: u16 = 53;
+
+/// Nameservers from the local `/etc/resolv.conf`.
+static LOCAL_NAMESERVERS: LazyLock<Vec<IpAddr>> = LazyLock::new(|| {
+    let _guard = DetourGuard::new();
+
+    std::fs::read("/etc/resolv.conf")
+        .map(|resolv_conf| nameservers(&resolv_conf))
+        .unwrap_or_default()
+});
+
+/// First nameserver from the remote `/etc/resolv.conf`, [`None`] if we failed to read it.
+static REMOTE_NAMESERVER: OnceLock<Option<IpAddr>> = OnceLock::new();
+
+/// Addresses of the nameservers of a `resolv.conf` file, empty if it's not valid.
+fn nameservers(resolv_conf: &[u8]) -> Vec<IpAddr> {
+    match resolv_conf::Config::parse(resolv_conf) {
+        Ok(config) => config.nameservers.into_iter().map(Into::into).collect(),
+        Err(error) => {
+            warn!(%error, "Failed to parse `/etc/resolv.conf`");
+            Vec::new()
+        }
+    }
+}
+
+/// Reads the remote `/etc/resolv.conf` and returns its first nameserver.
+fn remote_nameserver() -> Detour<Option<IpAddr>> {
+    let resolv_conf = read_remote_resolv_conf()?;
+
+    Detour::Success(nameservers(&resolv_conf).into_iter().next())
+}
+
+/// Returns the address where a UDP packet sent to `destination` should be sent in the cluster.
+///
+/// Packets sent to port `53` of one of the local nameservers are redirected to the remote
+/// nameserver, every other `destination` is returned as is.
+pub(super) fn redirect_dns_query(destination: SocketAddr) -> SocketAddr {
+    if destination.port() != DNS_PORT
+        || !crate::setup().remote_dns_enabled()
+        || !LOCAL_NAMESERVERS.contains(&destination.ip())
+    {
+        return destination;
+    }
+
+    let remote_nameserver = REMOTE_NAMESERVER.get_or_init(|| match remote_nameserver() {
+        Detour::Success(nameserver) => nameserver,
+        Detour::Bypass(bypass) => {
+            warn!(?bypass, "Failed to read the remote `/etc/resolv.conf`");
+            None
+        }
+        Detour::Error(error) => {
+            warn!(%error, "Failed to read the remote `/etc/resolv.conf`");
+            None
+        }
+    });
+
+    match remote_nameserver {
+        Some(nameserver) => {
+            trace!(%destination, %nameserver, "Redirecting DNS query to the remote nameserver");
+            SocketAddr::new(*nameserver, DNS_PORT)
+        }
+        None => destination,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::nameservers;
+
+    #[test]
+    fn parse_resolv_conf_nameservers() {
+        let resolv_conf = "\
+# Generated by NetworkManager
+search cluster.local svc.cluster.local
+nameserver 10.96.0.10 # kube-dns
+nameserver   ::1
+nameserver fe80::1%eth0
+options ndots:5 timeout:2
+";
+
+        assert_eq!(
+            nameservers(resolv_conf.as_bytes()),
+            vec![
+                IpAddr::V4(Ipv4Addr::new(10, 96, 0, 10)),
+                IpAddr::V6(Ipv6Addr::LOCALHOST),
+                IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            ]
+        );
+    }
+}
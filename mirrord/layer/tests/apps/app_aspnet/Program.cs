@@ -0,0 +1,35 @@
+// ASP.NET Core version of `app_node.js`, used by the `aspnet_mirroring` test.
+//
+// Build with `dotnet publish --use-current-runtime -o publish`.
+
+var done = new HashSet<string>();
+
+var builder = WebApplication.CreateBuilder(args);
+builder.Logging.ClearProviders();
+var app = builder.Build();
+
+app.MapMethods("/", new[] { "GET", "POST", "PUT", "DELETE" }, async (HttpContext context) =>
+{
+    var method = context.Request.Method;
+    // Reading the body goes through `recvmsg` on the accepted socket.
+    using var reader = new StreamReader(context.Request.Body);
+    await reader.ReadToEndAsync();
+
+    Console.WriteLine($"{method}: Request completed");
+
+    lock (done)
+    {
+        done.Add(method);
+        if (done.Count == 4)
+        {
+            app.Lifetime.StopApplication();
+        }
+    }
+
+    return method;
+});
+
+// For `*`, Kestrel tries the unspecified IPv6 address first, and falls back to IPv4 when the socket
+// can't be created.
+app.Urls.Add("http://*:9999");
+app.Run();
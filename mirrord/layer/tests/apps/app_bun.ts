@@ -0,0 +1,22 @@
+// Bun version of `app_node.js`, used by the `mirroring_with_http` test.
+const done = new Set<string>();
+
+const server = Bun.serve({
+  port: 80,
+  hostname: "0.0.0.0",
+  async fetch(request) {
+    const method = request.method;
+    await request.text();
+    console.log(`${method}: Request completed`);
+
+    done.add(method);
+    if (done.size === 4) {
+      // Let the response go out first.
+      setTimeout(() => server.stop());
+    }
+
+    return new Response(method);
+  },
+});
+
+console.log(`Server listening on port ${server.port}`);
@@ -0,0 +1,16 @@
+// Deno version of `app_node.js`, used by the `mirroring_with_http` test.
+const done = new Set<string>();
+
+const server = Deno.serve({ port: 80, hostname: "0.0.0.0" }, async (request) => {
+  const method = request.method;
+  await request.text();
+  console.log(`${method}: Request completed`);
+
+  done.add(method);
+  if (done.size === 4) {
+    // Let the response go out first.
+    setTimeout(() => server.shutdown());
+  }
+
+  return new Response(method);
+});
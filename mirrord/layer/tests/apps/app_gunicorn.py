@@ -0,0 +1,11 @@
+import sys
+
+
+def app(environ, start_response):
+    print(
+        f"{environ['REQUEST_METHOD']} {environ['PATH_INFO']}: Request completed",
+        file=sys.stderr,
+        flush=True,
+    )
+    start_response("200 OK", [("Content-Type", "text/plain")])
+    return [b"OK"]
@@ -0,0 +1,20 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+
+/// Test `chmod` and `fchmodat`.
+///
+/// Changes the mode of files that only exist in the remote target, the test
+/// answers the requests, so both calls succeed.
+int main() {
+  char *script_path = "/app/run.sh";
+  assert(chmod(script_path, 0755) == 0);
+  printf("changed the mode of '%s'\n", script_path);
+
+  char *socket_path = "/app/app.sock";
+  assert(fchmodat(AT_FDCWD, socket_path, 0660, 0) == 0);
+  printf("changed the mode of '%s'\n", socket_path);
+
+  return 0;
+}
@@ -0,0 +1,19 @@
+#include <assert.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `chown` and `lchown`.
+///
+/// Changes the owner of files that only exist in the remote target, the test
+/// answers the requests, so both calls succeed.
+int main() {
+  char *data_path = "/app/data";
+  assert(chown(data_path, 1000, 1000) == 0);
+  printf("changed the owner of '%s'\n", data_path);
+
+  char *link_path = "/app/data/current";
+  assert(lchown(link_path, -1, 1000) == 0);
+  printf("changed the group of '%s'\n", link_path);
+
+  return 0;
+}
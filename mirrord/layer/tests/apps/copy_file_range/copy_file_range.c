@@ -0,0 +1,36 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `copy_file_range` between remote files.
+///
+/// The copy is made in the agent, and the given offset is advanced. Then the
+/// test acts as an agent that doesn't support it, so the copy fails with
+/// `ENOSYS`, and copying to a local file fails with `EXDEV`.
+int main() {
+  printf("test copy_file_range: START\n");
+
+  int fd_in = open("/app/source.db", O_RDONLY);
+  assert(fd_in >= 0);
+  int fd_out = open("/app/copy.db", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  assert(fd_out >= 0);
+
+  off_t offset_in = 4096;
+  assert(copy_file_range(fd_in, &offset_in, fd_out, NULL, 65536, 0) == 8192);
+  assert(offset_in == 4096 + 8192);
+
+  assert(copy_file_range(fd_in, NULL, fd_out, NULL, 65536, 0) == -1);
+  assert(errno == ENOSYS);
+
+  assert(copy_file_range(fd_in, NULL, STDOUT_FILENO, NULL, 65536, 0) == -1);
+  assert(errno == EXDEV);
+
+  assert(close(fd_out) == 0);
+  assert(close(fd_in) == 0);
+
+  printf("test copy_file_range: SUCCESS\n");
+  return 0;
+}
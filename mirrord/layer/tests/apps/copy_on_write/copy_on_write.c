@@ -0,0 +1,37 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+/// Test the `copyonwrite` fs mode.
+///
+/// Overwrites a file that exists in the remote target, reads it back, and
+/// creates a file that doesn't exist there. The writes go to the local overlay,
+/// so only the copies of the remote files are requested.
+int main() {
+  char *config_path = "/app/config.json";
+  char *contents = "{\"local\":true}";
+
+  int fd = open(config_path, O_WRONLY | O_TRUNC);
+  assert(fd >= 0);
+  assert(write(fd, contents, strlen(contents)) == (ssize_t)strlen(contents));
+  assert(close(fd) == 0);
+  printf("wrote '%s'\n", config_path);
+
+  char buffer[64] = {0};
+  fd = open(config_path, O_RDONLY);
+  assert(fd >= 0);
+  assert(read(fd, buffer, sizeof(buffer) - 1) == (ssize_t)strlen(contents));
+  assert(strcmp(buffer, contents) == 0);
+  assert(close(fd) == 0);
+  printf("read back '%s'\n", config_path);
+
+  char *log_path = "/app/new.log";
+  fd = open(log_path, O_WRONLY | O_CREAT | O_EXCL, 0644);
+  assert(fd >= 0);
+  assert(close(fd) == 0);
+  printf("created '%s'\n", log_path);
+
+  return 0;
+}
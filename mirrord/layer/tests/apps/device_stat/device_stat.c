@@ -0,0 +1,29 @@
+#include <assert.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <sys/sysmacros.h>
+
+/// Test `stat` and `lstat` on remote files in a database data directory.
+///
+/// Database engines check the device files and symlinks in their data directories, so the
+/// device numbers, block size and timestamps must match the remote ones.
+int main() {
+  printf("test device stat: START\n");
+
+  struct stat device;
+  assert(stat("/var/lib/postgresql/data/disk", &device) == 0);
+  assert(S_ISBLK(device.st_mode));
+  assert(major(device.st_rdev) == 8);
+  assert(minor(device.st_rdev) == 16);
+  assert(device.st_blksize == 4096);
+  assert(device.st_mtim.tv_sec == 1700000000);
+  assert(device.st_mtim.tv_nsec == 123);
+
+  // `pg_wal` is a symlink, `lstat` must not follow it.
+  struct stat wal;
+  assert(lstat("/var/lib/postgresql/data/pg_wal", &wal) == 0);
+  assert(S_ISLNK(wal.st_mode));
+
+  printf("test device stat: SUCCESS\n");
+  return 0;
+}
@@ -0,0 +1,35 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `fallocate` and `posix_fallocate` of a remote file.
+///
+/// The test answers the first `fallocate`, then acts as an agent that doesn't
+/// support it, so `posix_fallocate` extends the file with a truncate, and
+/// punching a hole fails with `EOPNOTSUPP`.
+int main() {
+  printf("test fallocate: START\n");
+
+  int fd = open("/app/data.db", O_RDWR);
+  assert(fd >= 0);
+
+  assert(fallocate(fd, FALLOC_FL_KEEP_SIZE, 4096, 8192) == 0);
+  assert(posix_fallocate(fd, 0, 1 << 20) == 0);
+
+  assert(fallocate(fd, FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE, 0, 4096) ==
+         -1);
+  assert(errno == EOPNOTSUPP);
+
+  // Returns the error instead of setting `errno`.
+  errno = 0;
+  assert(posix_fallocate(fd, 0, -1) == EINVAL);
+  assert(errno == 0);
+
+  assert(close(fd) == 0);
+
+  printf("test fallocate: SUCCESS\n");
+  return 0;
+}
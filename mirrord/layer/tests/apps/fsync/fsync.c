@@ -0,0 +1,20 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `fsync` and `fdatasync`.
+///
+/// Flushes a file that only exists in the remote target, the test answers the
+/// requests, so all calls succeed.
+int main() {
+  char *db_path = "/app/data.db";
+  int fd = open(db_path, O_RDWR);
+  assert(fd >= 0);
+  assert(fsync(fd) == 0);
+  assert(fdatasync(fd) == 0);
+  assert(close(fd) == 0);
+  printf("synced '%s'\n", db_path);
+
+  return 0;
+}
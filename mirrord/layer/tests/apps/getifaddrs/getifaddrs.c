@@ -0,0 +1,53 @@
+#include <arpa/inet.h>
+#include <assert.h>
+#include <ifaddrs.h>
+#include <net/if.h>
+#include <netinet/in.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+
+/// Returns the IPv4 address in `address` as text, in `buffer`.
+static const char *ipv4(struct sockaddr *address, char *buffer) {
+  assert(address != NULL && address->sa_family == AF_INET);
+  return inet_ntop(AF_INET, &((struct sockaddr_in *)address)->sin_addr, buffer,
+                   INET_ADDRSTRLEN);
+}
+
+/// Test that `getifaddrs` lists the network interfaces of the target, the way
+/// apps find the address they advertise.
+int main() {
+  printf("test getifaddrs: START\n");
+
+  struct ifaddrs *interfaces;
+  assert(getifaddrs(&interfaces) == 0);
+
+  int count = 0;
+  char buffer[INET_ADDRSTRLEN];
+  for (struct ifaddrs *interface = interfaces; interface != NULL;
+       interface = interface->ifa_next) {
+    count++;
+
+    if (strcmp(interface->ifa_name, "lo") == 0) {
+      assert(interface->ifa_flags & IFF_LOOPBACK);
+      assert(strcmp(ipv4(interface->ifa_addr, buffer), "127.0.0.1") == 0);
+      assert(strcmp(ipv4(interface->ifa_netmask, buffer), "255.0.0.0") == 0);
+    } else {
+      assert(strcmp(interface->ifa_name, "eth0") == 0);
+      assert(interface->ifa_flags & IFF_UP);
+      assert(interface->ifa_flags & IFF_BROADCAST);
+      assert(!(interface->ifa_flags & IFF_LOOPBACK));
+      assert(strcmp(ipv4(interface->ifa_addr, buffer), "10.244.1.17") == 0);
+      assert(strcmp(ipv4(interface->ifa_netmask, buffer), "255.255.255.0") ==
+             0);
+      assert(strcmp(ipv4(interface->ifa_broadaddr, buffer), "10.244.1.255") ==
+             0);
+    }
+  }
+  assert(count == 2);
+
+  freeifaddrs(interfaces);
+
+  printf("test getifaddrs: SUCCESS\n");
+  return 0;
+}
@@ -0,0 +1,31 @@
+#include <assert.h>
+#include <glob.h>
+#include <stdio.h>
+#include <string.h>
+
+/// Test `glob`.
+///
+/// Expands patterns in directories that only exist in the remote target, the
+/// test answers the requests with the matching paths.
+int main() {
+  glob_t certs;
+  assert(glob("/app/certs/*.pem", 0, NULL, &certs) == 0);
+  assert(certs.gl_pathc == 2);
+  assert(strcmp(certs.gl_pathv[0], "/app/certs/ca.pem") == 0);
+  assert(strcmp(certs.gl_pathv[1], "/app/certs/server.pem") == 0);
+  assert(certs.gl_pathv[2] == NULL);
+  printf("found %zu certificates\n", certs.gl_pathc);
+  globfree(&certs);
+
+  glob_t missing;
+  assert(glob("/app/missing/*.pem", 0, NULL, &missing) == GLOB_NOMATCH);
+  globfree(&missing);
+
+  glob_t unmatched;
+  assert(glob("/app/missing/*.pem", GLOB_NOCHECK, NULL, &unmatched) == 0);
+  assert(unmatched.gl_pathc == 1);
+  assert(strcmp(unmatched.gl_pathv[0], "/app/missing/*.pem") == 0);
+  globfree(&unmatched);
+
+  return 0;
+}
@@ -0,0 +1,21 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `link` and `linkat`.
+///
+/// Creates hard links to files that only exist in the remote target, the test
+/// answers the requests, so all calls succeed.
+int main() {
+  char *backup_path = "/app/data.db.1";
+  assert(link("/app/data.db", backup_path) == 0);
+  printf("linked '%s'\n", backup_path);
+
+  char *release_path = "/app/release.db";
+  assert(linkat(AT_FDCWD, "/app/current", AT_FDCWD, release_path,
+                AT_SYMLINK_FOLLOW) == 0);
+  printf("linked '%s'\n", release_path);
+
+  return 0;
+}
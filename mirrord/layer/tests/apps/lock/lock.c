@@ -0,0 +1,46 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/file.h>
+#include <unistd.h>
+
+/// Test the advisory locks of a remote file.
+///
+/// The locks are placed in the agent, a blocking `flock` waits until the agent
+/// gets the lock, and `F_GETLK` reports the remote conflicting lock.
+int main() {
+  printf("test lock: START\n");
+
+  int fd = open("/app/jobs.db", O_RDONLY);
+  assert(fd >= 0);
+
+  assert(flock(fd, LOCK_EX | LOCK_NB) == -1);
+  assert(errno == EWOULDBLOCK);
+  assert(flock(fd, LOCK_EX) == 0);
+
+  struct flock lock = {
+      .l_type = F_RDLCK,
+      .l_whence = SEEK_SET,
+      .l_start = 0x40000002,
+      .l_len = 510,
+  };
+  assert(fcntl(fd, F_SETLK, &lock) == 0);
+
+  struct flock query = {
+      .l_type = F_WRLCK,
+      .l_whence = SEEK_SET,
+      .l_start = 0,
+      .l_len = 0,
+  };
+  assert(fcntl(fd, F_GETLK, &query) == 0);
+  assert(query.l_type == F_RDLCK);
+  assert(query.l_start == 0x40000000);
+  assert(query.l_len == 1);
+  assert(query.l_pid == -1);
+
+  assert(close(fd) == 0);
+
+  printf("test lock: SUCCESS\n");
+  return 0;
+}
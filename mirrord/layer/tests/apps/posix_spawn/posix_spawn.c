@@ -0,0 +1,82 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <spawn.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+extern char **environ;
+
+/// Reads everything from `fd` into `buffer`, and closes it.
+static void read_all(int fd, char *buffer, size_t size) {
+  size_t total = 0;
+  ssize_t amount;
+  while ((amount = read(fd, buffer + total, size - total - 1)) > 0) {
+    total += amount;
+  }
+  buffer[total] = '\0';
+  close(fd);
+}
+
+/// Waits for `pid`, and checks that it succeeded.
+static void wait_success(pid_t pid) {
+  int status;
+  assert(waitpid(pid, &status, 0) == pid);
+  assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+/// Spawns processes that read `/app/test.txt` the way macOS frameworks do.
+///
+/// 1. `posix_spawnp` with file actions that set up the stdio of the child, like
+///    `NSTask` and `std::process::Command`: stdin from `/dev/null`, stdout to a
+///    pipe. On macOS, only these fds are inherited
+///    (`POSIX_SPAWN_CLOEXEC_DEFAULT`), and stderr with
+///    `posix_spawn_file_actions_addinherit_np`.
+/// 2. `posix_spawn` of `/bin/sh` without file actions or attributes.
+///
+/// The output of the children must reach the pipes, so that we know their fds
+/// were not mangled.
+int main() {
+  printf("test posix_spawn: START\n");
+
+  int stdout_pipe[2];
+  assert(pipe(stdout_pipe) == 0);
+
+  posix_spawn_file_actions_t actions;
+  assert(posix_spawn_file_actions_init(&actions) == 0);
+  assert(posix_spawn_file_actions_addopen(&actions, STDIN_FILENO, "/dev/null",
+                                          O_RDONLY, 0) == 0);
+  assert(posix_spawn_file_actions_adddup2(&actions, stdout_pipe[1],
+                                          STDOUT_FILENO) == 0);
+  assert(posix_spawn_file_actions_addclose(&actions, stdout_pipe[0]) == 0);
+  assert(posix_spawn_file_actions_addclose(&actions, stdout_pipe[1]) == 0);
+
+  posix_spawnattr_t attributes;
+  assert(posix_spawnattr_init(&attributes) == 0);
+#ifdef __APPLE__
+  assert(posix_spawnattr_setflags(&attributes, POSIX_SPAWN_CLOEXEC_DEFAULT) ==
+         0);
+  assert(posix_spawn_file_actions_addinherit_np(&actions, STDERR_FILENO) == 0);
+#endif
+
+  char *cat_argv[] = {"cat", "/app/test.txt", NULL};
+  pid_t pid;
+  assert(posix_spawnp(&pid, "cat", &actions, &attributes, cat_argv, environ) ==
+         0);
+  close(stdout_pipe[1]);
+  posix_spawn_file_actions_destroy(&actions);
+  posix_spawnattr_destroy(&attributes);
+
+  char output[64];
+  read_all(stdout_pipe[0], output, sizeof(output));
+  wait_success(pid);
+  assert(strcmp(output, "metalbear-hostname") == 0);
+
+  char *sh_argv[] = {"sh", "-c", "cat /app/test.txt", NULL};
+  assert(posix_spawn(&pid, "/bin/sh", NULL, NULL, sh_argv, environ) == 0);
+  wait_success(pid);
+
+  printf("\ntest posix_spawn: SUCCESS\n");
+  return 0;
+}
@@ -0,0 +1,33 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+/// Test that a small file opened read-only is read in one request.
+///
+/// Opens, stats, reads and closes a file that only exists in the remote
+/// target, the test answers a single request with its contents.
+int main() {
+  char *config_path = "/app/config.yaml";
+  char *expected = "debug: true\n";
+
+  int fd = open(config_path, O_RDONLY);
+  assert(fd >= 0);
+
+  struct stat file_stat;
+  assert(fstat(fd, &file_stat) == 0);
+  assert(file_stat.st_size == strlen(expected));
+  assert((file_stat.st_mode & 0777) == 0644);
+
+  char buffer[64] = {0};
+  assert(read(fd, buffer, sizeof(buffer)) == strlen(expected));
+  assert(strcmp(buffer, expected) == 0);
+  assert(read(fd, buffer, sizeof(buffer)) == 0);
+
+  assert(close(fd) == 0);
+  printf("read '%s'\n", config_path);
+
+  return 0;
+}
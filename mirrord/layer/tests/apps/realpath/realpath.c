@@ -1,3 +1,4 @@
+#define _GNU_SOURCE
 #include <stdlib.h>
 #include <assert.h>
 #include <err.h>
@@ -57,6 +58,27 @@ void absolute_remotely_doesnt_exist() {
     assert(errno == ENOENT);
 }
 
+// sixth case: `..` after a symbolic link is applied to the destination of the link, so the path
+// can only be resolved remotely
+void symlink_parent_remotely() {
+    const char path[] = "/app/current/../config.yaml";
+    char *resolved_path = realpath(path, NULL);
+    assert(resolved_path != NULL);
+    assert(strcmp(resolved_path, "/srv/releases/config.yaml") == 0);
+    free(resolved_path);
+}
+
+#ifdef __linux__
+// seventh case: the GNU variant, that always allocates
+void canonicalize_file_name_remotely() {
+    const char path[] = "/app/current/../config.yaml";
+    char *resolved_path = canonicalize_file_name(path);
+    assert(resolved_path != NULL);
+    assert(strcmp(resolved_path, "/srv/releases/config.yaml") == 0);
+    free(resolved_path);
+}
+#endif
+
 /// Test few cases of using realpath
 int main() {
     relative_locally();
@@ -64,4 +86,8 @@ int main() {
     absolute_remotely();
     absolute_remotely_malloc();
     absolute_remotely_doesnt_exist();
+    symlink_parent_remotely();
+#ifdef __linux__
+    canonicalize_file_name_remotely();
+#endif
 }
@@ -0,0 +1,27 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+
+/// Test `rename` and `renameat`.
+///
+/// Renames files that only exist in the remote target, the test answers the
+/// requests, so both calls succeed. A rename from the remote target to a local
+/// path fails with `EXDEV` without reaching the agent.
+int main() {
+  char *old_path = "/app/upload.tmp";
+  char *new_path = "/app/upload.json";
+  assert(rename(old_path, new_path) == 0);
+  printf("renamed '%s' to '%s'\n", old_path, new_path);
+
+  char *old_dir_path = "/app/uploads.tmp";
+  char *new_dir_path = "/app/uploads";
+  assert(renameat(AT_FDCWD, old_dir_path, AT_FDCWD, new_dir_path) == 0);
+  printf("renamed '%s' to '%s'\n", old_dir_path, new_dir_path);
+
+  char *local_path = "/tmp/upload.json";
+  assert(rename(new_path, local_path) == -1 && errno == EXDEV);
+  printf("could not rename '%s' to '%s'\n", new_path, local_path);
+
+  return 0;
+}
@@ -0,0 +1,40 @@
+#include <arpa/inet.h>
+#include <assert.h>
+#include <netinet/in.h>
+#include <stdio.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+/// Test a listener on a port reserved in the agent.
+///
+/// The port is picked by the agent, and `getsockname` reports it, so that it
+/// can be passed to a remote server that connects back to the application.
+int main() {
+  printf("test reserved port: START\n");
+
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  assert(fd >= 0);
+
+  struct sockaddr_in addr = {
+      .sin_family = AF_INET,
+      .sin_port = 0,
+      .sin_addr.s_addr = htonl(INADDR_ANY),
+  };
+  assert(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+
+  struct sockaddr_in bound = {0};
+  socklen_t len = sizeof(bound);
+  assert(getsockname(fd, (struct sockaddr *)&bound, &len) == 0);
+  assert(ntohs(bound.sin_port) == 40123);
+
+  assert(listen(fd, 16) == 0);
+
+  len = sizeof(bound);
+  assert(getsockname(fd, (struct sockaddr *)&bound, &len) == 0);
+  assert(ntohs(bound.sin_port) == 40123);
+
+  assert(close(fd) == 0);
+
+  printf("test reserved port: SUCCESS\n");
+  return 0;
+}
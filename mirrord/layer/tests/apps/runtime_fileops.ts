@@ -0,0 +1,13 @@
+// Reads a remote file with the file API of the runtime (Deno or Bun), used by the
+// `deno_read_file` and `bun_read_file` tests.
+const path = "/app/test.txt";
+
+const data =
+  typeof Deno !== "undefined"
+    ? Deno.readTextFileSync(path)
+    : await Bun.file(path).text();
+console.log(data);
+
+if (data !== "hello") {
+  throw new Error(`unexpected contents: ${data}`);
+}
@@ -0,0 +1,36 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `SEEK_DATA` and `SEEK_HOLE` on a remote sparse file.
+///
+/// The test answers the first seeks with the layout of the file, then acts as
+/// an agent that doesn't support them, so the file is treated as if it had no
+/// holes.
+int main() {
+  printf("test seek_hole: START\n");
+
+  int fd = open("/app/sparse.db", O_RDONLY);
+  assert(fd >= 0);
+
+  assert(lseek(fd, 0, SEEK_DATA) == 4096);
+  assert(lseek(fd, 4096, SEEK_HOLE) == 8192);
+
+  assert(lseek(fd, 100, SEEK_HOLE) == 16384);
+  assert(lseek(fd, 100, SEEK_DATA) == 100);
+
+  assert(lseek(fd, 16384, SEEK_DATA) == -1);
+  assert(errno == ENXIO);
+
+  errno = 0;
+  assert(lseek(fd, -1, SEEK_HOLE) == -1);
+  assert(errno == ENXIO);
+
+  assert(close(fd) == 0);
+
+  printf("test seek_hole: SUCCESS\n");
+  return 0;
+}
@@ -0,0 +1,51 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <assert.h>
+#include <fcntl.h>
+#include <netinet/in.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/sendfile.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+/// Test `sendfile` from a remote file to an outgoing connection.
+///
+/// The data written before `sendfile` reaches the agent first, then the file is
+/// sent by the agent, and the given offset is advanced. Then the test acts as
+/// an agent that doesn't support it, so the file is read and written to the
+/// connection.
+int main() {
+  printf("test sendfile: START\n");
+
+  int fd_in = open("/app/source.db", O_RDONLY);
+  assert(fd_in >= 0);
+
+  int sock = socket(AF_INET, SOCK_STREAM, 0);
+  assert(sock >= 0);
+  struct sockaddr_in peer = {
+      .sin_family = AF_INET,
+      .sin_port = htons(80),
+  };
+  assert(inet_pton(AF_INET, "1.1.1.1", &peer.sin_addr) == 1);
+  assert(connect(sock, (struct sockaddr *)&peer, sizeof(peer)) == 0);
+
+  const char *header = "header";
+  assert(write(sock, header, strlen(header)) == (ssize_t)strlen(header));
+
+  off_t offset = 4096;
+  assert(sendfile(sock, fd_in, &offset, 65536) == 8192);
+  assert(offset == 4096 + 8192);
+
+  assert(sendfile(sock, fd_in, NULL, 65536) == 5);
+
+  // Wait for the agent to close the connection.
+  char buffer[16];
+  assert(read(sock, buffer, sizeof(buffer)) == 0);
+
+  assert(close(fd_in) == 0);
+  assert(close(sock) == 0);
+
+  printf("test sendfile: SUCCESS\n");
+  return 0;
+}
@@ -0,0 +1,35 @@
+#include <assert.h>
+#include <stdio.h>
+#include <sys/statvfs.h>
+#if defined(__APPLE__)
+#include <sys/mount.h>
+#else
+#include <sys/vfs.h>
+#endif
+
+/// Test `statfs` and `statvfs` on a remote path that is never opened.
+///
+/// The test answers with the numbers of the remote filesystem: 1000 blocks of
+/// 4096 bytes, 250 of them available, and 40 free inodes.
+int main() {
+  char *data_path = "/app/data";
+
+  struct statfs fs;
+  assert(statfs(data_path, &fs) == 0);
+  assert(fs.f_bsize == 4096);
+  assert(fs.f_blocks == 1000);
+  assert(fs.f_bavail == 250);
+  assert(fs.f_ffree == 40);
+
+  struct statvfs vfs;
+  assert(statvfs(data_path, &vfs) == 0);
+  assert(vfs.f_frsize == 4096);
+  assert(vfs.f_blocks == 1000);
+  assert(vfs.f_bavail == 250);
+  assert(vfs.f_favail == 40);
+
+  printf("'%s' has %llu bytes available\n", data_path,
+         (unsigned long long)vfs.f_bavail * vfs.f_frsize);
+
+  return 0;
+}
@@ -0,0 +1,26 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+
+/// Test `statx` on a remote file.
+///
+/// The extended fields (birth time and mount id) must be the remote ones, not
+/// only the classic stat fields.
+int main() {
+  printf("test statx: START\n");
+
+  struct statx stx;
+  assert(statx(AT_FDCWD, "/app/data.db", AT_SYMLINK_NOFOLLOW,
+               STATX_BASIC_STATS | STATX_BTIME, &stx) == 0);
+  assert(S_ISREG(stx.stx_mode));
+  assert(stx.stx_size == 4096);
+  assert(stx.stx_mask & STATX_BTIME);
+  assert(stx.stx_btime.tv_sec == 1700000000);
+  assert(stx.stx_btime.tv_nsec == 123456789);
+  assert(stx.stx_mnt_id == 412);
+
+  printf("test statx: SUCCESS\n");
+  return 0;
+}
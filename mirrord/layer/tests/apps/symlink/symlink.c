@@ -0,0 +1,20 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `symlink` and `symlinkat`.
+///
+/// Creates links in directories that only exist in the remote target, the test
+/// answers the requests, so all calls succeed.
+int main() {
+  char *current_path = "/app/current";
+  assert(symlink("releases/1.2.0", current_path) == 0);
+  printf("linked '%s'\n", current_path);
+
+  char *config_path = "/app/config.yaml";
+  assert(symlinkat("/etc/app/config.yaml", AT_FDCWD, config_path) == 0);
+  printf("linked '%s'\n", config_path);
+
+  return 0;
+}
@@ -0,0 +1,23 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `truncate` and `ftruncate`.
+///
+/// Truncates files that only exist in the remote target, the test answers the
+/// requests, so all calls succeed.
+int main() {
+  char *log_path = "/app/app.log";
+  assert(truncate(log_path, 0) == 0);
+  printf("truncated '%s'\n", log_path);
+
+  char *db_path = "/app/data.db";
+  int fd = open(db_path, O_RDWR);
+  assert(fd >= 0);
+  assert(ftruncate(fd, 4096) == 0);
+  assert(close(fd) == 0);
+  printf("truncated '%s'\n", db_path);
+
+  return 0;
+}
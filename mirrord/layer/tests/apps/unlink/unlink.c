@@ -0,0 +1,20 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/// Test `unlink` and `unlinkat`.
+///
+/// Removes a file and a directory that only exist in the remote target, the
+/// test answers the requests, so both calls succeed.
+int main() {
+  char *file_path = "/app/upload.tmp";
+  assert(unlink(file_path) == 0);
+  printf("removed '%s'\n", file_path);
+
+  char *dir_path = "/app/uploads";
+  assert(unlinkat(AT_FDCWD, dir_path, AT_REMOVEDIR) == 0);
+  printf("removed '%s'\n", dir_path);
+
+  return 0;
+}
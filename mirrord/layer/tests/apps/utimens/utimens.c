@@ -0,0 +1,22 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+
+/// Test `utimensat`.
+///
+/// Sets the times of files that only exist in the remote target, the test
+/// answers the requests, so both calls succeed.
+int main() {
+  char *object_path = "/app/build/main.o";
+  struct timespec times[2] = {{.tv_sec = 0, .tv_nsec = UTIME_OMIT},
+                              {.tv_sec = 1700000000, .tv_nsec = 5}};
+  assert(utimensat(AT_FDCWD, object_path, times, 0) == 0);
+  printf("set the modification time of '%s'\n", object_path);
+
+  char *link_path = "/app/current";
+  assert(utimensat(AT_FDCWD, link_path, NULL, AT_SYMLINK_NOFOLLOW) == 0);
+  printf("touched '%s'\n", link_path);
+
+  return 0;
+}
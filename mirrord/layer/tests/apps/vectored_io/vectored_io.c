@@ -0,0 +1,41 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/uio.h>
+#include <unistd.h>
+
+/// Test `readv`, `preadv`, `writev` and `pwritev` on a remote file.
+///
+/// The first read and write carry all the buffers in a single request. Then
+/// the test acts as an agent that doesn't support it, and the buffers are
+/// read and written with a single plain request each.
+int main() {
+  printf("test vectored_io: START\n");
+
+  int fd = open("/app/vectored.db", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  assert(fd >= 0);
+
+  char first[4] = {0}, second[8] = {0};
+  struct iovec read_iov[2] = {{first, sizeof(first)}, {second, sizeof(second)}};
+  assert(readv(fd, read_iov, 2) == 7);
+  assert(memcmp(first, "abcd", 4) == 0);
+  assert(memcmp(second, "efg", 3) == 0);
+
+  struct iovec write_iov[2] = {{"hello ", 6}, {"world", 5}};
+  assert(pwritev(fd, write_iov, 2, 16) == 11);
+
+  // From here on, the agent doesn't support it.
+  char third[3] = {0}, fourth[3] = {0};
+  struct iovec pread_iov[2] = {{third, sizeof(third)}, {fourth, sizeof(fourth)}};
+  assert(preadv(fd, pread_iov, 2, 0) == 6);
+  assert(memcmp(third, "abc", 3) == 0);
+  assert(memcmp(fourth, "def", 3) == 0);
+
+  assert(writev(fd, write_iov, 2) == 11);
+
+  assert(close(fd) == 0);
+
+  printf("test vectored_io: SUCCESS\n");
+  return 0;
+}
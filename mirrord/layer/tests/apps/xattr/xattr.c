@@ -0,0 +1,44 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/xattr.h>
+#include <unistd.h>
+
+/// Test the extended attributes of a remote file.
+///
+/// The values and the names must be the remote ones, copied to the buffer the
+/// way `libc` does it.
+int main() {
+  printf("test xattr: START\n");
+
+  // Asks for the size first, like most callers do.
+  assert(getxattr("/app/config.yaml", "user.checksum", NULL, 0) == 6);
+
+  char small[2];
+  assert(getxattr("/app/config.yaml", "user.checksum", small, sizeof(small)) ==
+         -1);
+  assert(errno == ERANGE);
+
+  int fd = open("/app/config.yaml", O_RDONLY);
+  assert(fd >= 0);
+
+  char value[16];
+  assert(fgetxattr(fd, "user.checksum", value, sizeof(value)) == 6);
+  assert(memcmp(value, "abc123", 6) == 0);
+  assert(close(fd) == 0);
+
+  char list[64];
+  assert(llistxattr("/app/config.yaml", list, sizeof(list)) ==
+         sizeof("user.checksum") + sizeof("security.selinux"));
+  assert(strcmp(list, "user.checksum") == 0);
+  assert(strcmp(list + sizeof("user.checksum"), "security.selinux") == 0);
+
+  assert(setxattr("/app/config.yaml", "user.owner", "team-a", 6,
+                  XATTR_CREATE) == 0);
+  assert(removexattr("/app/config.yaml", "user.checksum") == 0);
+
+  printf("test xattr: SUCCESS\n");
+  return 0;
+}
@@ -0,0 +1,28 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::chmod`] and [`libc::fchmodat`] functions.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn chmod(dylib_path: &Path) {
+    let application = Application::Chmod;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy.expect_chmod("/app/run.sh", 0o755).await;
+    intproxy.expect_chmod_at("/app/app.sock", 0o660).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
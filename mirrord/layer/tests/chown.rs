@@ -0,0 +1,37 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::chown`] and [`libc::lchown`] functions.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn chown(dylib_path: &Path) {
+    let application = Application::Chown;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy
+        .expect_chown("/app/data", Some(1000), Some(1000))
+        .await;
+    intproxy
+        .expect_chown_at(
+            "/app/data/current",
+            None,
+            Some(1000),
+            libc::AT_SYMLINK_NOFOLLOW as u32,
+        )
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
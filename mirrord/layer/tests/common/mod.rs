@@ -16,11 +16,12 @@ use futures::{SinkExt, StreamExt};
 use mirrord_intproxy::{agent_conn::AgentConnection, IntProxy};
 use mirrord_protocol::{
     file::{
-        AccessFileRequest, AccessFileResponse, OpenFileRequest, OpenOptionsInternal,
-        ReadFileRequest, SeekFromInternal, XstatRequest, XstatResponse,
+        AccessFileRequest, AccessFileResponse, MetadataInternal, OpenFileRequest,
+        OpenOptionsInternal, ReadFileRequest, SeekFromInternal, XstatRequest, XstatResponse,
     },
     tcp::{DaemonTcp, LayerTcp, NewTcpConnection, TcpClose, TcpData},
-    ClientMessage, DaemonCodec, DaemonMessage, FileRequest, FileResponse,
+    ClientMessage, DaemonCodec, DaemonMessage, ErrorKindInternal, FileRequest, FileResponse,
+    RemoteIOError, ResponseError,
 };
 #[cfg(target_os = "macos")]
 use mirrord_sip::sip_patch;
@@ -109,7 +110,7 @@ impl TestIntProxy {
             let agent_conn = AgentConnection::new_for_raw_address(fake_agent_address)
                 .await
                 .unwrap();
-            let intproxy = IntProxy::new_with_connection(agent_conn, listener);
+            let intproxy = IntProxy::new_with_connection(agent_conn, listener, None);
             intproxy
                 .run(Duration::from_secs(5), Duration::from_secs(5))
                 .await
@@ -143,6 +144,11 @@ impl TestIntProxy {
                         .await;
                 }
                 ClientMessage::ReadyForLogs => {}
+                ClientMessage::SwitchFraming(..) => {
+                    let limits = self.codec.codec_ref().limits();
+                    self.send(DaemonMessage::SwitchFramingResponse(limits))
+                        .await;
+                }
                 other => break Some(other),
             }
         }
@@ -468,6 +474,505 @@ impl TestIntProxy {
             .unwrap();
     }
 
+    /// Makes a [`FileRequest::Unlink`], and answers it.
+    pub async fn expect_unlink(&mut self, file_name: &str) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Unlink(
+                mirrord_protocol::file::UnlinkFileRequest { path }
+            )) if path.to_str().unwrap() == file_name
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Unlink(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::UnlinkAt`] with `AT_FDCWD`, and answers it.
+    pub async fn expect_unlink_at(&mut self, file_name: &str, expected_remove_dir: bool) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::UnlinkAt(
+                mirrord_protocol::file::UnlinkFileWithDirRequest {
+                    dirfd: None,
+                    path,
+                    remove_dir,
+                }
+            )) if path.to_str().unwrap() == file_name && remove_dir == expected_remove_dir
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Unlink(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Rename`], and answers it.
+    pub async fn expect_rename(&mut self, old_file_name: &str, new_file_name: &str) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Rename(
+                mirrord_protocol::file::RenameFileRequest { old_path, new_path }
+            )) if old_path.to_str().unwrap() == old_file_name
+                && new_path.to_str().unwrap() == new_file_name
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Rename(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::RenameAt`] with `AT_FDCWD` and no flags, and answers it.
+    pub async fn expect_rename_at(&mut self, old_file_name: &str, new_file_name: &str) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::RenameAt(
+                mirrord_protocol::file::RenameFileWithDirRequest {
+                    old_dirfd: None,
+                    old_path,
+                    new_dirfd: None,
+                    new_path,
+                    flags: 0,
+                }
+            )) if old_path.to_str().unwrap() == old_file_name
+                && new_path.to_str().unwrap() == new_file_name
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Rename(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Chmod`], and answers it.
+    pub async fn expect_chmod(&mut self, file_name: &str, expected_mode: u32) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Chmod(
+                mirrord_protocol::file::ChmodFileRequest { path, mode }
+            )) if path.to_str().unwrap() == file_name && mode == expected_mode
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Chmod(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::ChmodAt`] with `AT_FDCWD` and no flags, and answers it.
+    pub async fn expect_chmod_at(&mut self, file_name: &str, expected_mode: u32) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::ChmodAt(
+                mirrord_protocol::file::ChmodFileWithDirRequest {
+                    dirfd: None,
+                    path,
+                    mode,
+                    flags: 0,
+                }
+            )) if path.to_str().unwrap() == file_name && mode == expected_mode
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Chmod(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Truncate`], and answers it.
+    pub async fn expect_truncate(&mut self, file_name: &str, expected_length: u64) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Truncate(
+                mirrord_protocol::file::TruncateFileRequest { path, length }
+            )) if path.to_str().unwrap() == file_name && length == expected_length
+        );
+
+        self.codec
+            .send(DaemonMessage::File(
+                mirrord_protocol::FileResponse::Truncate(Ok(())),
+            ))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Link`], and answers it.
+    pub async fn expect_link(&mut self, expected_old_path: &str, expected_new_path: &str) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Link(
+                mirrord_protocol::file::LinkFileRequest {
+                    old_path: expected_old_path.into(),
+                    new_path: expected_new_path.into(),
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Link(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::LinkAt`] with absolute paths, and answers it.
+    pub async fn expect_link_at(
+        &mut self,
+        expected_old_path: &str,
+        expected_new_path: &str,
+        expected_follow_symlinks: bool,
+    ) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::LinkAt(
+                mirrord_protocol::file::LinkFileWithDirRequest {
+                    old_dirfd: None,
+                    old_path: expected_old_path.into(),
+                    new_dirfd: None,
+                    new_path: expected_new_path.into(),
+                    follow_symlinks: expected_follow_symlinks,
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Link(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::UtimensAt`] with `AT_FDCWD`, and answers it.
+    pub async fn expect_utimens_at(
+        &mut self,
+        expected_path: &str,
+        expected_access_time: mirrord_protocol::file::FileTimeInternal,
+        expected_modification_time: mirrord_protocol::file::FileTimeInternal,
+        expected_flags: u32,
+    ) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::UtimensAt(
+                mirrord_protocol::file::UtimensFileWithDirRequest {
+                    dirfd: None,
+                    path: expected_path.into(),
+                    access_time: expected_access_time,
+                    modification_time: expected_modification_time,
+                    flags: expected_flags,
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(
+                mirrord_protocol::FileResponse::Utimens(Ok(())),
+            ))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Symlink`], and answers it.
+    pub async fn expect_symlink(&mut self, expected_target: &str, expected_link_path: &str) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Symlink(
+                mirrord_protocol::file::SymlinkRequest {
+                    target: expected_target.into(),
+                    link_path: expected_link_path.into(),
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(
+                mirrord_protocol::FileResponse::Symlink(Ok(())),
+            ))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::SymlinkAt`], and answers it.
+    pub async fn expect_symlink_at(
+        &mut self,
+        expected_target: &str,
+        expected_dirfd: Option<u64>,
+        expected_link_path: &str,
+    ) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::SymlinkAt(
+                mirrord_protocol::file::SymlinkAtRequest {
+                    target: expected_target.into(),
+                    new_dirfd: expected_dirfd,
+                    link_path: expected_link_path.into(),
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(
+                mirrord_protocol::FileResponse::Symlink(Ok(())),
+            ))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Glob`], and answers it with `paths`.
+    pub async fn expect_glob(&mut self, expected_pattern: &str, paths: &[&str]) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Glob(
+                mirrord_protocol::file::GlobRequest { pattern, .. }
+            )) if pattern == expected_pattern
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Glob(
+                Ok(mirrord_protocol::file::GlobResponse {
+                    paths: paths.iter().map(PathBuf::from).collect(),
+                    truncated: false,
+                }),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Canonicalize`], and answers it with `resolved`, or with the file not
+    /// existing when it's [`None`].
+    pub async fn expect_canonicalize(&mut self, expected_path: &str, resolved: Option<&str>) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Canonicalize(
+                mirrord_protocol::file::CanonicalizePathRequest { path }
+            )) if path == Path::new(expected_path)
+        );
+
+        let response = match resolved {
+            Some(path) => {
+                Ok(mirrord_protocol::file::CanonicalizePathResponse { path: path.into() })
+            }
+            None => Err(ResponseError::RemoteIO(RemoteIOError {
+                raw_os_error: Some(libc::ENOENT),
+                kind: ErrorKindInternal::NotFound,
+            })),
+        };
+        self.codec
+            .send(DaemonMessage::File(FileResponse::Canonicalize(response)))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::StatFs`] for `expected_path`, and answers it with a filesystem of
+    /// 1000 blocks of 4096 bytes, 250 of them available, and 40 free inodes.
+    pub async fn expect_statfs(&mut self, expected_path: &str) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::StatFs(
+                mirrord_protocol::file::StatFsRequest {
+                    path: expected_path.into(),
+                }
+            ))
+        );
+
+        let metadata = mirrord_protocol::file::FsMetadataInternal {
+            filesystem_type: 0xef53,
+            block_size: 4096,
+            blocks: 1000,
+            blocks_free: 300,
+            blocks_available: 250,
+            files: 100,
+            files_free: 40,
+        };
+        self.codec
+            .send(DaemonMessage::File(FileResponse::XstatFs(Ok(
+                mirrord_protocol::file::XstatFsResponse { metadata },
+            ))))
+            .await
+            .unwrap();
+    }
+
+    /// Makes the requests that copy the remote file `file_name` to the overlay in the
+    /// copy-on-write mode, and answers them with `contents`, or with the file not existing when
+    /// it's [`None`].
+    pub async fn expect_copy_up(&mut self, file_name: &str, contents: Option<&str>) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+                path: Some(file_name.into()),
+                fd: None,
+                follow_symlink: true,
+            }))
+        );
+
+        let Some(contents) = contents else {
+            self.codec
+                .send(DaemonMessage::File(FileResponse::Xstat(Err(
+                    ResponseError::RemoteIO(RemoteIOError {
+                        raw_os_error: Some(libc::ENOENT),
+                        kind: ErrorKindInternal::NotFound,
+                    }),
+                ))))
+                .await
+                .unwrap();
+            return;
+        };
+
+        self.codec
+            .send(DaemonMessage::File(FileResponse::Xstat(Ok(
+                XstatResponse {
+                    metadata: MetadataInternal {
+                        mode: libc::S_IFREG as u32 | 0o644,
+                        size: contents.len() as u64,
+                        ..Default::default()
+                    },
+                },
+            ))))
+            .await
+            .unwrap();
+
+        self.expect_file_open_for_reading(file_name, 0xc0).await;
+        self.expect_file_read(contents, 0xc0).await;
+        self.expect_file_close(0xc0).await;
+    }
+
+    /// Makes a [`FileRequest::Ftruncate`], and answers it.
+    pub async fn expect_ftruncate(&mut self, expected_fd: u64, expected_length: u64) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Ftruncate(
+                mirrord_protocol::file::FtruncateFileRequest {
+                    fd: expected_fd,
+                    length: expected_length,
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(
+                mirrord_protocol::FileResponse::Truncate(Ok(())),
+            ))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Fsync`], and answers it.
+    pub async fn expect_fsync(&mut self, expected_fd: u64, expected_data_only: bool) {
+        assert_eq!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Fsync(
+                mirrord_protocol::file::FsyncFileRequest {
+                    fd: expected_fd,
+                    data_only: expected_data_only,
+                }
+            ))
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Fsync(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::ReadWhole`], and answers it with `contents`.
+    pub async fn expect_read_whole(&mut self, file_name: &str, contents: &[u8]) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::ReadWhole(
+                mirrord_protocol::file::ReadWholeFileRequest { path, max_size }
+            )) if path.to_str().unwrap() == file_name && max_size >= contents.len() as u64
+        );
+
+        self.codec
+            .send(DaemonMessage::File(
+                mirrord_protocol::FileResponse::ReadWhole(Ok(
+                    mirrord_protocol::file::ReadWholeFileResponse {
+                        metadata: mirrord_protocol::file::MetadataInternal {
+                            mode: 0o100644,
+                            size: contents.len() as u64,
+                            ..Default::default()
+                        },
+                        bytes: Some(contents.to_vec()),
+                    },
+                )),
+            ))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::Chown`], and answers it.
+    pub async fn expect_chown(
+        &mut self,
+        file_name: &str,
+        expected_owner: Option<u32>,
+        expected_group: Option<u32>,
+    ) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::Chown(
+                mirrord_protocol::file::ChownFileRequest { path, owner, group }
+            )) if path.to_str().unwrap() == file_name
+                && owner == expected_owner
+                && group == expected_group
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Chown(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
+    /// Makes a [`FileRequest::ChownAt`] with `AT_FDCWD`, and answers it.
+    pub async fn expect_chown_at(
+        &mut self,
+        file_name: &str,
+        expected_owner: Option<u32>,
+        expected_group: Option<u32>,
+        expected_flags: u32,
+    ) {
+        assert_matches!(
+            self.recv().await,
+            ClientMessage::FileRequest(FileRequest::ChownAt(
+                mirrord_protocol::file::ChownFileWithDirRequest {
+                    dirfd: None,
+                    path,
+                    owner,
+                    group,
+                    flags,
+                }
+            )) if path.to_str().unwrap() == file_name
+                && owner == expected_owner
+                && group == expected_group
+                && flags == expected_flags
+        );
+
+        self.codec
+            .send(DaemonMessage::File(mirrord_protocol::FileResponse::Chown(
+                Ok(()),
+            )))
+            .await
+            .unwrap();
+    }
+
     /// Verify that the passed message (not the next message from self.codec!) is a file read.
     /// Return buffer size.
     pub async fn expect_message_file_read(message: ClientMessage, expected_fd: u64) -> u64 {
@@ -687,9 +1192,19 @@ pub enum Application {
     Go22HTTP,
     Go23HTTP,
     NodeHTTP,
+    /// ASP.NET Core app, published as a single-file app.
+    DotNetAspNetHTTP,
+    DenoHTTP,
+    BunHTTP,
+    /// Reads a remote file with `Deno.readTextFileSync`.
+    DenoFileOps,
+    /// Reads a remote file with `Bun.file`.
+    BunFileOps,
     PythonFastApiHTTP,
     /// Shared sockets [#864](https://github.com/metalbear-co/mirrord/issues/864).
     PythonIssue864,
+    /// Prefork server that replaces its workers and itself on reload.
+    PythonGunicorn,
     PythonFlaskHTTP,
     PythonSelfConnect,
     PythonDontLoad,
@@ -743,9 +1258,34 @@ pub enum Application {
     RustListenPorts,
     Fork,
     ReadLink,
+    Unlink,
+    Rename,
+    Chmod,
+    Chown,
+    ReadWhole,
+    Truncate,
+    Glob,
+    Symlink,
+    Link,
+    Utimens,
+    Fsync,
+    CopyOnWrite,
     OpenFile,
     CIssue2055,
     CIssue2178,
+    CDeviceStat,
+    CStatx,
+    CStatfs,
+    CXattr,
+    CLock,
+    CFallocate,
+    CCopyFileRange,
+    CSendfile,
+    CVectoredIo,
+    CPosixSpawn,
+    CSeekHole,
+    CGetifaddrs,
+    CReservedPort,
     RustIssue2058,
     Realpath,
     NodeIssue2283,
@@ -794,8 +1334,40 @@ impl Application {
             | Application::PythonDontLoad
             | Application::PythonListen => Self::get_python3_executable().await,
             Application::PythonFastApiHTTP | Application::PythonIssue864 => String::from("uvicorn"),
+            Application::PythonGunicorn => String::from("gunicorn"),
+            Application::DotNetAspNetHTTP => {
+                String::from("tests/apps/app_aspnet/publish/app_aspnet")
+            }
+            Application::DenoHTTP | Application::DenoFileOps => String::from("deno"),
+            Application::BunHTTP | Application::BunFileOps => String::from("bun"),
             Application::Fork => String::from("tests/apps/fork/out.c_test_app"),
             Application::ReadLink => String::from("tests/apps/readlink/out.c_test_app"),
+            Application::Unlink => String::from("tests/apps/unlink/out.c_test_app"),
+            Application::Rename => String::from("tests/apps/rename/out.c_test_app"),
+            Application::Chmod => String::from("tests/apps/chmod/out.c_test_app"),
+            Application::Chown => String::from("tests/apps/chown/out.c_test_app"),
+            Application::ReadWhole => String::from("tests/apps/read_whole/out.c_test_app"),
+            Application::Truncate => String::from("tests/apps/truncate/out.c_test_app"),
+            Application::Glob => String::from("tests/apps/glob/out.c_test_app"),
+            Application::Symlink => String::from("tests/apps/symlink/out.c_test_app"),
+            Application::Link => String::from("tests/apps/link/out.c_test_app"),
+            Application::Utimens => String::from("tests/apps/utimens/out.c_test_app"),
+            Application::Fsync => String::from("tests/apps/fsync/out.c_test_app"),
+            Application::CStatx => String::from("tests/apps/statx/out.c_test_app"),
+            Application::CStatfs => String::from("tests/apps/statfs/out.c_test_app"),
+            Application::CXattr => String::from("tests/apps/xattr/out.c_test_app"),
+            Application::CLock => String::from("tests/apps/lock/out.c_test_app"),
+            Application::CFallocate => String::from("tests/apps/fallocate/out.c_test_app"),
+            Application::CCopyFileRange => {
+                String::from("tests/apps/copy_file_range/out.c_test_app")
+            }
+            Application::CSendfile => String::from("tests/apps/sendfile/out.c_test_app"),
+            Application::CVectoredIo => String::from("tests/apps/vectored_io/out.c_test_app"),
+            Application::CPosixSpawn => String::from("tests/apps/posix_spawn/out.c_test_app"),
+            Application::CSeekHole => String::from("tests/apps/seek_hole/out.c_test_app"),
+            Application::CGetifaddrs => String::from("tests/apps/getifaddrs/out.c_test_app"),
+            Application::CReservedPort => String::from("tests/apps/reserved_port/out.c_test_app"),
+            Application::CopyOnWrite => String::from("tests/apps/copy_on_write/out.c_test_app"),
             Application::Realpath => String::from("tests/apps/realpath/out.c_test_app"),
             Application::NodeHTTP | Application::NodeIssue2283 | Application::NodeIssue2807 => {
                 String::from("node")
@@ -928,6 +1500,11 @@ impl Application {
                 env!("CARGO_MANIFEST_DIR"),
                 "tests/apps/gethostbyname/out.c_test_app",
             ),
+            Application::CDeviceStat => format!(
+                "{}/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                "tests/apps/device_stat/out.c_test_app",
+            ),
             Application::CIssue2178 => format!(
                 "{}/{}",
                 env!("CARGO_MANIFEST_DIR"),
@@ -978,10 +1555,42 @@ impl Application {
                     String::from("shared_sockets:app"),
                 ]
             }
+            Application::PythonGunicorn => {
+                vec![
+                    String::from("--workers=2"),
+                    String::from("--bind=0.0.0.0:9999"),
+                    String::from("--chdir=tests/apps/"),
+                    String::from("app_gunicorn:app"),
+                ]
+            }
             Application::NodeHTTP => {
                 app_path.push("app_node.js");
                 vec![app_path.to_string_lossy().to_string()]
             }
+            Application::DenoHTTP => {
+                app_path.push("app_deno.ts");
+                vec![
+                    String::from("run"),
+                    String::from("--allow-net"),
+                    app_path.to_string_lossy().to_string(),
+                ]
+            }
+            Application::BunHTTP => {
+                app_path.push("app_bun.ts");
+                vec![String::from("run"), app_path.to_string_lossy().to_string()]
+            }
+            Application::DenoFileOps => {
+                app_path.push("runtime_fileops.ts");
+                vec![
+                    String::from("run"),
+                    String::from("--allow-read"),
+                    app_path.to_string_lossy().to_string(),
+                ]
+            }
+            Application::BunFileOps => {
+                app_path.push("runtime_fileops.ts");
+                vec![String::from("run"), app_path.to_string_lossy().to_string()]
+            }
             Application::NodeFileOps => {
                 app_path.push("fileops.js");
                 vec![app_path.to_string_lossy().to_string()]
@@ -1032,6 +1641,18 @@ impl Application {
             | Application::Go23FAccessAt
             | Application::Fork
             | Application::ReadLink
+            | Application::Unlink
+            | Application::Rename
+            | Application::Chmod
+            | Application::Chown
+            | Application::ReadWhole
+            | Application::Truncate
+            | Application::Glob
+            | Application::Symlink
+            | Application::Link
+            | Application::Utimens
+            | Application::Fsync
+            | Application::CopyOnWrite
             | Application::Realpath
             | Application::RustFileOps
             | Application::RustIssue1123
@@ -1057,9 +1678,23 @@ impl Application {
             | Application::OpenFile
             | Application::CIssue2055
             | Application::CIssue2178
+            | Application::CDeviceStat
+            | Application::CStatx
+            | Application::CStatfs
+            | Application::CXattr
+            | Application::CLock
+            | Application::CFallocate
+            | Application::CCopyFileRange
+            | Application::CSendfile
+            | Application::CVectoredIo
+            | Application::CPosixSpawn
+            | Application::CSeekHole
+            | Application::CGetifaddrs
+            | Application::CReservedPort
             | Application::RustIssue2204
             | Application::RustRebind0
-            | Application::RustIssue2438 => vec![],
+            | Application::RustIssue2438
+            | Application::DotNetAspNetHTTP => vec![],
             Application::RustOutgoingUdp => ["--udp", RUST_OUTGOING_LOCAL, RUST_OUTGOING_PEERS]
                 .into_iter()
                 .map(Into::into)
@@ -1091,10 +1726,15 @@ impl Application {
             | Application::Go22FileOps
             | Application::Go23FileOps
             | Application::NodeHTTP
+            | Application::DenoHTTP
+            | Application::BunHTTP
             | Application::RustIssue1054
             | Application::PythonFlaskHTTP => 80,
             // mapped from 9999 in `configs/port_mapping.json`
-            Application::PythonFastApiHTTP | Application::PythonIssue864 => 1234,
+            Application::PythonFastApiHTTP
+            | Application::PythonIssue864
+            | Application::PythonGunicorn
+            | Application::DotNetAspNetHTTP => 1234,
             Application::RustIssue1123 => 41222,
             Application::PythonListen => 21232,
             Application::PythonDontLoad
@@ -1103,11 +1743,25 @@ impl Application {
             | Application::JavaTemurinSip
             | Application::EnvBashCat
             | Application::NodeFileOps
+            | Application::DenoFileOps
+            | Application::BunFileOps
             | Application::NodeSpawn
             | Application::NodeIssue2903
             | Application::BashShebang
             | Application::Fork
             | Application::ReadLink
+            | Application::Unlink
+            | Application::Rename
+            | Application::Chmod
+            | Application::Chown
+            | Application::ReadWhole
+            | Application::Truncate
+            | Application::Glob
+            | Application::Symlink
+            | Application::Link
+            | Application::Utimens
+            | Application::Fsync
+            | Application::CopyOnWrite
             | Application::Realpath
             | Application::Go21Issue834
             | Application::Go22Issue834
@@ -1146,6 +1800,19 @@ impl Application {
             | Application::OpenFile
             | Application::CIssue2055
             | Application::CIssue2178
+            | Application::CDeviceStat
+            | Application::CStatx
+            | Application::CStatfs
+            | Application::CXattr
+            | Application::CLock
+            | Application::CFallocate
+            | Application::CCopyFileRange
+            | Application::CSendfile
+            | Application::CVectoredIo
+            | Application::CPosixSpawn
+            | Application::CSeekHole
+            | Application::CGetifaddrs
+            | Application::CReservedPort
             | Application::NodeIssue2283
             | Application::RustIssue2204
             | Application::RustIssue2438
@@ -1259,6 +1926,8 @@ pub fn get_env<'a>(
     env.insert("MIRRORD_IMPERSONATED_TARGET", "pod/mock-target"); // Just pass some value.
     env.insert("MIRRORD_CONNECT_TCP", addr);
     env.insert("MIRRORD_REMOTE_DNS", "false");
+    // Most tests expect read-only files to be opened remotely, see the `read_whole` test.
+    env.insert("MIRRORD_READ_WHOLE_MAX_SIZE", "0");
     if let Some(config) = config {
         println!("using config file: {config}");
         env.insert("MIRRORD_CONFIG_FILE", config);
@@ -0,0 +1,79 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{CopyFileRangeRequest, CopyFileRangeResponse},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, ResponseError,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that `copy_file_range` between two remote files is made in the agent, and that it
+/// fails so the app falls back to reading and writing when the agent doesn't support it, or when
+/// one of the files is local.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn copy_file_range(dylib_path: &Path) {
+    let application = Application::CCopyFileRange;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy
+        .expect_file_open_for_reading("/app/source.db", 3)
+        .await;
+    intproxy
+        .expect_file_open_for_writing("/app/copy.db", 4)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::CopyFileRange(CopyFileRangeRequest {
+            fd_in: 3,
+            offset_in: Some(4096),
+            fd_out: 4,
+            offset_out: None,
+            length: 65536,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::CopyFileRange(Ok(
+            CopyFileRangeResponse {
+                copied_amount: 8192,
+            },
+        ))))
+        .await;
+
+    // From here on, the agent doesn't support it.
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::CopyFileRange(CopyFileRangeRequest {
+            fd_in: 3,
+            offset_in: None,
+            fd_out: 4,
+            offset_out: None,
+            length: 65536,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::CopyFileRange(Err(
+            ResponseError::NotImplemented,
+        ))))
+        .await;
+
+    intproxy.expect_file_close(4).await;
+    intproxy.expect_file_close(3).await;
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
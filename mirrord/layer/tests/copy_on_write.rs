@@ -0,0 +1,46 @@
+#![feature(assert_matches)]
+use std::{fs, path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the `copyonwrite` fs mode, the writes go to the overlay and never reach the remote.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn copy_on_write(dylib_path: &Path) {
+    let application = Application::CopyOnWrite;
+
+    let overlay = std::env::temp_dir().join(format!("mirrord-overlay-test-{}", std::process::id()));
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "copyonwrite"),
+                ("MIRRORD_FILE_OVERLAY_DIR", overlay.to_str().unwrap()),
+            ],
+            None,
+        )
+        .await;
+
+    intproxy
+        .expect_copy_up("/app/config.json", Some("{\"remote\":true}"))
+        .await;
+    intproxy.expect_copy_up("/app/new.log", None).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+
+    let config = fs::read_to_string(overlay.join("app/config.json")).unwrap();
+    let log_exists = overlay.join("app/new.log").exists();
+    fs::remove_dir_all(&overlay).unwrap();
+
+    assert_eq!(config, "{\"local\":true}");
+    assert!(log_exists);
+}
@@ -0,0 +1,76 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{MetadataInternal, XstatRequest, XstatResponse},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that `stat` and `lstat` of remote device files and symlinks return the remote
+/// metadata, as database engines check them in their data directories.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn device_stat(
+    #[values(Application::CDeviceStat)] application: Application,
+    dylib_path: &Path,
+) {
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, Default::default(), None)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+            path: Some("/var/lib/postgresql/data/disk".into()),
+            fd: None,
+            follow_symlink: true,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Xstat(Ok(
+            XstatResponse {
+                metadata: MetadataInternal {
+                    mode: libc::S_IFBLK | 0o660,
+                    // Linux `makedev(8, 16)`.
+                    rdevice_id: 0x810,
+                    block_size: 4096,
+                    modification_time: 1_700_000_000_000_000_123,
+                    ..Default::default()
+                },
+            },
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+            path: Some("/var/lib/postgresql/data/pg_wal".into()),
+            fd: None,
+            follow_symlink: false,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Xstat(Ok(
+            XstatResponse {
+                metadata: MetadataInternal {
+                    mode: libc::S_IFLNK | 0o777,
+                    ..Default::default()
+                },
+            },
+        ))))
+        .await;
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("test device stat: SUCCESS")
+        .await;
+}
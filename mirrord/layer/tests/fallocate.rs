@@ -0,0 +1,99 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{FallocateFileRequest, OpenOptionsInternal},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, ResponseError,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that `fallocate` and `posix_fallocate` of a remote file are sent to the agent, and
+/// that `posix_fallocate` falls back to extending the file when the agent doesn't support it.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn fallocate(dylib_path: &Path) {
+    let application = Application::CFallocate;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy
+        .expect_file_open_with_options(
+            "/app/data.db",
+            3,
+            OpenOptionsInternal {
+                read: true,
+                write: true,
+                append: false,
+                truncate: false,
+                create: false,
+                create_new: false,
+            },
+        )
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Fallocate(FallocateFileRequest {
+            fd: 3,
+            mode: libc::FALLOC_FL_KEEP_SIZE as u32,
+            offset: 4096,
+            length: 8192,
+            posix: false,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Fallocate(Ok(()))))
+        .await;
+
+    // From here on, the agent doesn't support it.
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Fallocate(FallocateFileRequest {
+            fd: 3,
+            mode: 0,
+            offset: 0,
+            length: 1 << 20,
+            posix: true,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Fallocate(Err(
+            ResponseError::NotImplemented,
+        ))))
+        .await;
+    intproxy.expect_xstat(None, Some(3)).await;
+    intproxy.expect_ftruncate(3, 1 << 20).await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Fallocate(FallocateFileRequest {
+            fd: 3,
+            mode: (libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE) as u32,
+            offset: 0,
+            length: 4096,
+            posix: false,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Fallocate(Err(
+            ResponseError::NotImplemented,
+        ))))
+        .await;
+
+    intproxy.expect_file_close(3).await;
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
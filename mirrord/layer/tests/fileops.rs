@@ -426,13 +426,13 @@ async fn go_dir(
                 fd: dir_fd,
                 dir_entries: vec![
                     DirEntryInternal {
-                        name: "a".to_string(),
+                        name: b"a".to_vec(),
                         inode: 1,
                         position: 1,
                         file_type: libc::DT_REG,
                     },
                     DirEntryInternal {
-                        name: "b".to_string(),
+                        name: b"b".to_vec(),
                         inode: 2,
                         position: 2,
                         file_type: libc::DT_REG,
@@ -505,13 +505,13 @@ async fn go_dir_on_linux(
         DirEntryInternal {
             inode: 1,
             position: 1,
-            name: "a".to_string(),
+            name: b"a".to_vec(),
             file_type: libc::DT_REG,
         },
         DirEntryInternal {
             inode: 2,
             position: 2,
-            name: "b".to_string(),
+            name: b"b".to_vec(),
             file_type: libc::DT_REG,
         },
     ];
@@ -736,3 +736,138 @@ async fn faccessat_go(
     test_process.wait_assert_success().await;
     test_process.assert_no_error_in_stderr().await;
 }
+
+/// Answers the requests for reading `contents` from the remote file opened as `fd`, until the app
+/// closes it.
+///
+/// Deno and Bun get the size of the file (and maybe its position) before reading it, and stop
+/// either at EOF or when they have read the whole size.
+async fn answer_remote_file_reads(intproxy: &mut TestIntProxy, contents: &str, fd: u64) {
+    let contents = contents.as_bytes();
+    let mut position = 0_u64;
+
+    let read_at = |start: u64, buffer_size: u64| {
+        let start = (start as usize).min(contents.len());
+        let end = start
+            .saturating_add(buffer_size as usize)
+            .min(contents.len());
+        let bytes = contents.get(start..end).unwrap().to_vec();
+        ReadFileResponse {
+            read_amount: bytes.len() as u64,
+            bytes,
+        }
+    };
+
+    loop {
+        let response = match intproxy.recv().await {
+            ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+                fd: Some(requested_fd),
+                ..
+            })) => {
+                assert_eq!(requested_fd, fd);
+                let metadata = MetadataInternal {
+                    // Regular file, `rw-r--r--`.
+                    mode: 0o100644,
+                    size: contents.len() as u64,
+                    ..Default::default()
+                };
+                FileResponse::Xstat(Ok(XstatResponse { metadata }))
+            }
+            ClientMessage::FileRequest(FileRequest::Seek(SeekFileRequest {
+                fd: requested_fd,
+                seek_from,
+            })) => {
+                assert_eq!(requested_fd, fd);
+                position = match seek_from {
+                    SeekFromInternal::Start(offset) => offset,
+                    SeekFromInternal::Current(offset) => position.saturating_add_signed(offset),
+                    SeekFromInternal::End(offset) => {
+                        (contents.len() as u64).saturating_add_signed(offset)
+                    }
+                    // The file has no holes.
+                    SeekFromInternal::Data(offset) => offset,
+                    SeekFromInternal::Hole(..) => contents.len() as u64,
+                };
+                FileResponse::Seek(Ok(SeekFileResponse {
+                    result_offset: position,
+                }))
+            }
+            ClientMessage::FileRequest(FileRequest::Read(ReadFileRequest {
+                remote_fd,
+                buffer_size,
+            })) => {
+                assert_eq!(remote_fd, fd);
+                let response = read_at(position, buffer_size);
+                position += response.read_amount;
+                FileResponse::Read(Ok(response))
+            }
+            ClientMessage::FileRequest(FileRequest::ReadLimited(ReadLimitedFileRequest {
+                remote_fd,
+                buffer_size,
+                start_from,
+            })) => {
+                assert_eq!(remote_fd, fd);
+                FileResponse::ReadLimited(Ok(read_at(start_from, buffer_size)))
+            }
+            ClientMessage::FileRequest(FileRequest::Close(CloseFileRequest {
+                fd: requested_fd,
+            })) => {
+                assert_eq!(requested_fd, fd);
+                break;
+            }
+            other => panic!("unexpected message while reading the remote file: {other:?}"),
+        };
+
+        intproxy.send(DaemonMessage::File(response)).await;
+    }
+}
+
+/// Read a remote file with Deno's own file API.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn deno_read_file(dylib_path: &Path) {
+    let _tracing = init_tracing().unwrap();
+
+    let mut env = get_rw_test_file_env_vars();
+    env.push(("DENO_NO_UPDATE_CHECK", "1"));
+    let (mut test_process, mut intproxy) = Application::DenoFileOps
+        .start_process_with_layer(dylib_path, env, None)
+        .await;
+
+    let fd = 1;
+    intproxy
+        .expect_file_open_with_read_flag("/app/test.txt", fd)
+        .await;
+    answer_remote_file_reads(&mut intproxy, "hello", fd).await;
+
+    test_process.wait_assert_success().await;
+    test_process.assert_stdout_contains("hello").await;
+    test_process.assert_no_error_in_stderr().await;
+}
+
+/// Read a remote file with `Bun.file`.
+///
+/// Bun calls the `$NOCANCEL` variants of the file functions on macOS. On Linux it makes direct
+/// syscalls, which we can't hook.
+#[cfg(target_os = "macos")]
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn bun_read_file(dylib_path: &Path) {
+    let _tracing = init_tracing().unwrap();
+
+    let (mut test_process, mut intproxy) = Application::BunFileOps
+        .start_process_with_layer(dylib_path, get_rw_test_file_env_vars(), None)
+        .await;
+
+    let fd = 1;
+    intproxy
+        .expect_file_open_with_read_flag("/app/test.txt", fd)
+        .await;
+    answer_remote_file_reads(&mut intproxy, "hello", fd).await;
+
+    test_process.wait_assert_success().await;
+    test_process.assert_stdout_contains("hello").await;
+    test_process.assert_no_error_in_stderr().await;
+}
@@ -0,0 +1,44 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::file::OpenOptionsInternal;
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::fsync`] and [`libc::fdatasync`] functions.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn fsync(dylib_path: &Path) {
+    let application = Application::Fsync;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy
+        .expect_file_open_with_options(
+            "/app/data.db",
+            3,
+            OpenOptionsInternal {
+                read: true,
+                write: true,
+                append: false,
+                truncate: false,
+                create: false,
+                create_new: false,
+            },
+        )
+        .await;
+    intproxy.expect_fsync(3, false).await;
+    intproxy.expect_fsync(3, true).await;
+    intproxy.expect_file_close(3).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
@@ -0,0 +1,74 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    interfaces::{GetIfAddrsRequest, GetIfAddrsResponse, InterfaceAddress, InterfaceFlags},
+    ClientMessage, DaemonMessage,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that `getifaddrs` lists the network interfaces of the target, with
+/// `feature.network.interfaces`.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn getifaddrs(dylib_path: &Path) {
+    let application = Application::CGetifaddrs;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![("MIRRORD_REMOTE_INTERFACES", "true")],
+            None,
+        )
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::GetIfAddrsRequest(GetIfAddrsRequest)
+    );
+    intproxy
+        .send(DaemonMessage::GetIfAddrsResponse(GetIfAddrsResponse(Ok(
+            vec![
+                InterfaceAddress {
+                    name: "lo".to_string(),
+                    flags: InterfaceFlags {
+                        up: true,
+                        running: true,
+                        loopback: true,
+                        ..Default::default()
+                    },
+                    address: "127.0.0.1".parse().unwrap(),
+                    netmask: Some("255.0.0.0".parse().unwrap()),
+                    broadcast: None,
+                },
+                InterfaceAddress {
+                    name: "eth0".to_string(),
+                    flags: InterfaceFlags {
+                        up: true,
+                        running: true,
+                        broadcast: true,
+                        multicast: true,
+                        ..Default::default()
+                    },
+                    address: "10.244.1.17".parse().unwrap(),
+                    netmask: Some("255.255.255.0".parse().unwrap()),
+                    broadcast: Some("10.244.1.255".parse().unwrap()),
+                },
+            ],
+        ))))
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
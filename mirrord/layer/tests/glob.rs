@@ -0,0 +1,35 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::glob`] function, expanded in the agent.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn glob(dylib_path: &Path) {
+    let application = Application::Glob;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
+        .await;
+
+    intproxy
+        .expect_glob(
+            "/app/certs/*.pem",
+            &["/app/certs/ca.pem", "/app/certs/server.pem"],
+        )
+        .await;
+    intproxy.expect_glob("/app/missing/*.pem", &[]).await;
+    intproxy.expect_glob("/app/missing/*.pem", &[]).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
@@ -0,0 +1,134 @@
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    tcp::{LayerTcp, LayerTcpSteal},
+    ClientMessage,
+};
+use nix::{
+    sys::{signal, signal::Signal},
+    unistd::Pid,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Sends a GET request for `path` to the app, and waits until a worker handles it.
+async fn request(intproxy: &mut TestIntProxy, test_process: &TestProcess, path: &str) {
+    intproxy
+        .send_connection_then_data(
+            &format!("GET {path} HTTP/1.1\r\nhost: localhost\r\n\r\n"),
+            Application::PythonGunicorn.get_app_port(),
+        )
+        .await;
+
+    test_process
+        .wait_for_line(
+            Duration::from_secs(30),
+            &format!("GET {path}: Request completed"),
+        )
+        .await;
+}
+
+/// Asserts that the port was not unsubscribed in the agent since the last call.
+async fn assert_still_subscribed(intproxy: &mut TestIntProxy) {
+    while let Ok(message) = tokio::time::timeout(Duration::from_millis(500), intproxy.recv()).await
+    {
+        assert!(
+            !matches!(
+                message,
+                ClientMessage::Tcp(LayerTcp::PortUnsubscribe(..))
+                    | ClientMessage::TcpSteal(LayerTcpSteal::PortUnsubscribe(..))
+            ),
+            "port unsubscribed while gunicorn is listening: {message:?}"
+        );
+    }
+}
+
+/// Reads the pid of the gunicorn master from the `pid_file`, waiting until it's not `previous`.
+async fn master_pid(pid_file: &Path, previous: Option<i32>) -> i32 {
+    loop {
+        let pid = tokio::fs::read_to_string(pid_file)
+            .await
+            .ok()
+            .and_then(|pid| pid.trim().parse().ok());
+
+        match pid {
+            Some(pid) if Some(pid) != previous => break pid,
+            _ => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+/// Run gunicorn through reload cycles, and verify that its port stays subscribed:
+///
+/// 1. `SIGHUP` replaces the workers, which inherit the listener with `fork`.
+/// 2. `SIGUSR2` starts a new master with `exec`, which inherits the listener, duplicates it, closes
+///    the original fd and calls `listen` again. Then the old master exits.
+///
+/// The port used to be unsubscribed when the old master exited, or when the new one closed the
+/// original fd.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(120))]
+async fn gunicorn_reload(dylib_path: &Path, config_dir: &Path) {
+    let pid_dir = tempfile::tempdir().unwrap();
+    let pid_file = pid_dir.path().join("gunicorn.pid");
+    let gunicorn_args = format!("--pid={}", pid_file.display());
+
+    let application = Application::PythonGunicorn;
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer_and_port(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "local"),
+                ("MIRRORD_UDP_OUTGOING", "false"),
+                ("GUNICORN_CMD_ARGS", gunicorn_args.as_str()),
+            ],
+            Some(
+                config_dir
+                    .join("port_mapping_shared_sockets.json")
+                    .to_str()
+                    .unwrap(),
+            ),
+        )
+        .await;
+
+    let old_master = master_pid(&pid_file, None).await;
+    request(&mut intproxy, &test_process, "/start").await;
+
+    for cycle in 0..3 {
+        signal::kill(Pid::from_raw(old_master), Signal::SIGHUP).unwrap();
+        request(&mut intproxy, &test_process, &format!("/hup-{cycle}")).await;
+        assert_still_subscribed(&mut intproxy).await;
+    }
+
+    signal::kill(Pid::from_raw(old_master), Signal::SIGUSR2).unwrap();
+    let new_master = master_pid(&pid_file, Some(old_master)).await;
+    // Both masters log this once they have their listeners.
+    while test_process
+        .get_stderr()
+        .await
+        .matches("Listening at")
+        .count()
+        < 2
+    {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_still_subscribed(&mut intproxy).await;
+
+    // The new master keeps our stdout and stderr, so we can't wait for them to close.
+    signal::kill(Pid::from_raw(old_master), Signal::SIGTERM).unwrap();
+    assert!(test_process.child.wait().await.unwrap().success());
+    assert_still_subscribed(&mut intproxy).await;
+
+    request(&mut intproxy, &test_process, "/upgraded").await;
+
+    signal::kill(Pid::from_raw(new_master), Signal::SIGTERM).unwrap();
+    test_process.wait().await;
+    test_process.assert_no_error_in_stderr().await;
+}
@@ -22,6 +22,9 @@ async fn mirroring_with_http(
         Application::PythonFlaskHTTP,
         Application::PythonFastApiHTTP,
         Application::NodeHTTP,
+        Application::DotNetAspNetHTTP,
+        Application::DenoHTTP,
+        Application::BunHTTP,
         Application::Go21HTTP,
         Application::Go22HTTP,
         Application::Go23HTTP
@@ -38,6 +41,8 @@ async fn mirroring_with_http(
                 ("MIRRORD_FILE_MODE", "local"),
                 ("MIRRORD_UDP_OUTGOING", "false"),
                 ("OBJC_DISABLE_INITIALIZE_FORK_SAFETY", "YES"),
+                // Deno checks for updates in the background, with an outgoing connection.
+                ("DENO_NO_UPDATE_CHECK", "1"),
             ],
             Some(config_dir.join("port_mapping.json").to_str().unwrap()),
         )
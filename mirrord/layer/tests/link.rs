@@ -0,0 +1,30 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::link`] and [`libc::linkat`] functions.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn link(dylib_path: &Path) {
+    let application = Application::Link;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy.expect_link("/app/data.db", "/app/data.db.1").await;
+    intproxy
+        .expect_link_at("/app/current", "/app/release.db", true)
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
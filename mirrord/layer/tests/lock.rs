@@ -0,0 +1,111 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{
+        FileLockInternal, FlockRequest, GetLockRequest, GetLockResponse, LockTypeInternal,
+        SeekFromInternal, SetLockRequest,
+    },
+    ClientMessage, DaemonMessage, ErrorKindInternal, FileRequest, FileResponse, RemoteIOError,
+    ResponseError,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// The error of the agent when someone else holds the lock.
+fn would_block() -> ResponseError {
+    ResponseError::RemoteIO(RemoteIOError {
+        raw_os_error: Some(libc::EWOULDBLOCK),
+        kind: ErrorKindInternal::WouldBlock,
+    })
+}
+
+/// Verifies that `flock` and the `fcntl` record locks of a remote file are placed in the agent,
+/// and that the blocking ones are retried until the agent gets the lock.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn lock(dylib_path: &Path) {
+    let application = Application::CLock;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, Default::default(), None)
+        .await;
+
+    intproxy
+        .expect_file_open_for_reading("/app/jobs.db", 3)
+        .await;
+
+    let exclusive_flock = ClientMessage::FileRequest(FileRequest::Flock(FlockRequest {
+        fd: 3,
+        lock_type: LockTypeInternal::Exclusive,
+    }));
+
+    // `LOCK_NB`, the layer gives up at once.
+    assert_eq!(intproxy.recv().await, exclusive_flock);
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Lock(Err(would_block()))))
+        .await;
+
+    // The blocking one is sent again.
+    assert_eq!(intproxy.recv().await, exclusive_flock);
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Lock(Err(would_block()))))
+        .await;
+    assert_eq!(intproxy.recv().await, exclusive_flock);
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Lock(Ok(()))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::SetLock(SetLockRequest {
+            fd: 3,
+            lock: FileLockInternal {
+                lock_type: LockTypeInternal::Shared,
+                start: SeekFromInternal::Start(0x4000_0002),
+                len: 510,
+            },
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Lock(Ok(()))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::GetLock(GetLockRequest {
+            fd: 3,
+            lock: FileLockInternal {
+                lock_type: LockTypeInternal::Exclusive,
+                start: SeekFromInternal::Start(0),
+                len: 0,
+            },
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::GetLock(Ok(
+            GetLockResponse {
+                conflict: Some(FileLockInternal {
+                    lock_type: LockTypeInternal::Shared,
+                    start: SeekFromInternal::Start(0x4000_0000),
+                    len: 1,
+                }),
+            },
+        ))))
+        .await;
+
+    intproxy.expect_file_close(3).await;
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("test lock: SUCCESS")
+        .await;
+}
@@ -0,0 +1,35 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test that a small file opened read-only is read with a single
+/// [`FileRequest::ReadWhole`](mirrord_protocol::FileRequest::ReadWhole), and then stat'ed, read
+/// and closed locally.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn read_whole(dylib_path: &Path) {
+    let application = Application::ReadWhole;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            vec![("MIRRORD_READ_WHOLE_MAX_SIZE", "65536")],
+            None,
+        )
+        .await;
+
+    intproxy
+        .expect_read_whole("/app/config.yaml", b"debug: true\n")
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
@@ -0,0 +1,47 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::realpath`] function, resolved in the agent.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn realpath(dylib_path: &Path) {
+    let application = Application::Realpath;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
+        .await;
+
+    intproxy
+        .expect_canonicalize("/etc/../etc/hosts", Some("/etc/hosts"))
+        .await;
+    intproxy
+        .expect_canonicalize("/etc/../etc/hosts", Some("/etc/hosts"))
+        .await;
+    intproxy
+        .expect_canonicalize("/etc/../etc/hosts", None)
+        .await;
+    intproxy
+        .expect_canonicalize(
+            "/app/current/../config.yaml",
+            Some("/srv/releases/config.yaml"),
+        )
+        .await;
+    #[cfg(target_os = "linux")]
+    intproxy
+        .expect_canonicalize(
+            "/app/current/../config.yaml",
+            Some("/srv/releases/config.yaml"),
+        )
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+}
@@ -0,0 +1,68 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    tcp::{DaemonTcp, LayerTcpSteal, PortReservation, StealType},
+    ClientMessage, DaemonMessage,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that a listener bound to a port configured in
+/// `feature.network.incoming.reserved_ports` reserves a port in the agent, reports it in
+/// `getsockname`, and releases it when closed.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn reserved_port(dylib_path: &Path, config_dir: &Path) {
+    let application = Application::CReservedPort;
+    let config_path = config_dir.join("reserved_port.json");
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(
+            dylib_path,
+            Default::default(),
+            Some(config_path.to_str().unwrap()),
+        )
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpSteal(LayerTcpSteal::ReservePort(PortReservation { port: 0 }))
+    );
+    intproxy
+        .send(DaemonMessage::TcpSteal(DaemonTcp::PortReserved(Ok(40123))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::Reserved(40123)))
+    );
+    intproxy
+        .send(DaemonMessage::TcpSteal(DaemonTcp::SubscribeResult(Ok(
+            40123,
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpSteal(LayerTcpSteal::PortUnsubscribe(40123))
+    );
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpSteal(LayerTcpSteal::ReleasePort(40123))
+    );
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("test reserved port: SUCCESS")
+        .await;
+    test_process.assert_no_error_in_stderr().await;
+}
@@ -0,0 +1,213 @@
+#![cfg(target_os = "linux")]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, process::Command, time::Duration};
+
+use mirrord_protocol::{
+    file::{MetadataInternal, XstatRequest, XstatResponse},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse,
+};
+use rstest::rstest;
+use tokio::net::TcpListener;
+
+mod common;
+
+pub use common::*;
+
+/// Whether `bwrap` is installed and can create sandboxes here (it needs unprivileged user
+/// namespaces).
+fn bwrap_works() -> bool {
+    Command::new("bwrap")
+        .args(["--dev-bind", "/", "/", "true"])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Runs `cat` in a `bwrap` sandbox, where the directory of the `local` pattern is mounted in
+/// another path. The fs rules are written for the paths of the user, so the file in that
+/// directory is read locally, while the other one is read remotely, with the path that `cat`
+/// sees.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn bwrap_local_pattern(dylib_path: &Path) {
+    if !bwrap_works() {
+        println!("Skipping, bwrap can't create a sandbox here");
+        return;
+    }
+
+    let local_dir = tempfile::tempdir().unwrap();
+    std::fs::write(local_dir.path().join("local_file"), "Local contents.\n").unwrap();
+    let local_dir = local_dir.path().to_str().unwrap();
+    let local_pattern = format!("^{local_dir}");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let env = get_env(
+        dylib_path.to_str().unwrap(),
+        &addr,
+        vec![
+            ("MIRRORD_FILE_MODE", "read"),
+            ("MIRRORD_FILE_LOCAL_PATTERN", &local_pattern),
+        ],
+        None,
+    );
+
+    let args = [
+        "--dev-bind",
+        "/",
+        "/",
+        "--bind",
+        local_dir,
+        "/mirrord-sandbox",
+        "cat",
+        "/mirrord-sandbox/local_file",
+        "/very_interesting_file",
+    ]
+    .map(String::from)
+    .to_vec();
+    let mut test_process = TestProcess::start_process("bwrap".to_string(), args, env).await;
+
+    let mut intproxy = TestIntProxy::new(listener).await;
+
+    let fd: u64 = 1;
+    intproxy
+        .expect_file_open_for_reading("/very_interesting_file", fd)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+            path: None,
+            fd: Some(fd),
+            follow_symlink: true
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Xstat(Ok(
+            XstatResponse {
+                metadata: MetadataInternal {
+                    size: 100,
+                    blocks: 2,
+                    ..Default::default()
+                },
+            },
+        ))))
+        .await;
+
+    intproxy
+        .expect_file_read("Very interesting contents.", fd)
+        .await;
+    intproxy.expect_file_close(fd).await;
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("Local contents.\nVery interesting contents.")
+        .await;
+    test_process.assert_no_error_in_stderr().await;
+}
+
+/// Whether `unshare` can create user and mount namespaces here, to `chroot` without privileges.
+fn unshare_works() -> bool {
+    Command::new("unshare")
+        .args(["--user", "--map-root-user", "--mount", "true"])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Runs `cat` after a `chroot` to a directory, that has the system directories mounted in it. The
+/// exec hooks are not enabled, so `cat` gets the root from the environment. The fs rules are
+/// written for the paths of the user, so the file in the `local` directory of the new root is read
+/// locally, while the other one is read remotely, with the path that `cat` sees.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn chroot_exec_local_pattern(dylib_path: &Path) {
+    if !unshare_works() {
+        println!("Skipping, unshare can't create a user namespace here");
+        return;
+    }
+
+    let root_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(root_dir.path().join("local")).unwrap();
+    std::fs::write(
+        root_dir.path().join("local/local_file"),
+        "Local contents.\n",
+    )
+    .unwrap();
+    let root = root_dir.path().to_str().unwrap();
+    let local_pattern = format!("^{root}/local");
+
+    let dylib = dylib_path.to_str().unwrap();
+    let dylib_dir = dylib_path.parent().unwrap().to_str().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let mut env = get_env(
+        dylib,
+        &addr,
+        vec![
+            ("MIRRORD_FILE_MODE", "read"),
+            ("MIRRORD_FILE_LOCAL_PATTERN", &local_pattern),
+        ],
+        None,
+    );
+    // Only `chroot` and `cat` load the layer, not the commands that prepare the new root.
+    env.remove("LD_PRELOAD");
+
+    let script = format!(
+        r#"
+for dir in /usr /lib /lib64 /bin /etc /dev /proc {dylib_dir}; do
+    [ -e "$dir" ] || continue
+    if [ -L "$dir" ]; then
+        ln -s "$(readlink "$dir")" "{root}$dir"
+    else
+        mkdir -p "{root}$dir" && mount --rbind "$dir" "{root}$dir"
+    fi
+done
+exec env LD_PRELOAD={dylib} chroot {root} cat /local/local_file /very_interesting_file
+"#
+    );
+    let args = ["--user", "--map-root-user", "--mount", "sh", "-c", &script]
+        .map(String::from)
+        .to_vec();
+    let mut test_process = TestProcess::start_process("unshare".to_string(), args, env).await;
+
+    let mut intproxy = TestIntProxy::new(listener).await;
+
+    let fd: u64 = 1;
+    intproxy
+        .expect_file_open_for_reading("/very_interesting_file", fd)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+            path: None,
+            fd: Some(fd),
+            follow_symlink: true
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Xstat(Ok(
+            XstatResponse {
+                metadata: MetadataInternal {
+                    size: 100,
+                    blocks: 2,
+                    ..Default::default()
+                },
+            },
+        ))))
+        .await;
+
+    intproxy
+        .expect_file_read("Very interesting contents.", fd)
+        .await;
+    intproxy.expect_file_close(fd).await;
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("Local contents.\nVery interesting contents.")
+        .await;
+    test_process.assert_no_error_in_stderr().await;
+}
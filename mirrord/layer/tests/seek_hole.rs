@@ -0,0 +1,112 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{
+        MetadataInternal, SeekFileRequest, SeekFileResponse, SeekFromInternal, XstatRequest,
+        XstatResponse,
+    },
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, RemoteResult, ResponseError,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Expects a seek of the remote file 3, and answers it with `response`.
+async fn expect_seek(
+    intproxy: &mut TestIntProxy,
+    seek_from: SeekFromInternal,
+    response: RemoteResult<u64>,
+) {
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Seek(SeekFileRequest { fd: 3, seek_from }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Seek(
+            response.map(|result_offset| SeekFileResponse { result_offset }),
+        )))
+        .await;
+}
+
+/// Expects a stat of the remote file 3, and answers that it has `size` bytes.
+async fn expect_size(intproxy: &mut TestIntProxy, size: u64) {
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+            path: None,
+            fd: Some(3),
+            follow_symlink: true,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Xstat(Ok(
+            XstatResponse {
+                metadata: MetadataInternal {
+                    size,
+                    ..Default::default()
+                },
+            },
+        ))))
+        .await;
+}
+
+/// Verifies that `SEEK_DATA` and `SEEK_HOLE` of a remote file are sent to the agent, and that the
+/// file is treated as if it had no holes when the agent doesn't support them.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn seek_hole(dylib_path: &Path) {
+    let application = Application::CSeekHole;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
+        .await;
+
+    intproxy
+        .expect_file_open_for_reading("/app/sparse.db", 3)
+        .await;
+
+    expect_seek(&mut intproxy, SeekFromInternal::Data(0), Ok(4096)).await;
+    expect_seek(&mut intproxy, SeekFromInternal::Hole(4096), Ok(8192)).await;
+
+    // From here on, the agent doesn't support it.
+    expect_seek(
+        &mut intproxy,
+        SeekFromInternal::Hole(100),
+        Err(ResponseError::NotImplemented),
+    )
+    .await;
+    expect_size(&mut intproxy, 16384).await;
+    expect_seek(&mut intproxy, SeekFromInternal::Start(16384), Ok(16384)).await;
+
+    expect_seek(
+        &mut intproxy,
+        SeekFromInternal::Data(100),
+        Err(ResponseError::NotImplemented),
+    )
+    .await;
+    expect_size(&mut intproxy, 16384).await;
+    expect_seek(&mut intproxy, SeekFromInternal::Start(100), Ok(100)).await;
+
+    // Past the end of the file.
+    expect_seek(
+        &mut intproxy,
+        SeekFromInternal::Data(16384),
+        Err(ResponseError::NotImplemented),
+    )
+    .await;
+    expect_size(&mut intproxy, 16384).await;
+
+    intproxy.expect_file_close(3).await;
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
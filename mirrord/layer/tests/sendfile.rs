@@ -0,0 +1,112 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{net::SocketAddr, path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{SendFileRequest, SendFileResponse},
+    outgoing::{
+        tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
+        DaemonConnect, LayerConnect, LayerWrite, SocketAddress,
+    },
+    ClientMessage, DaemonMessage, ResponseError,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that `sendfile` from a remote file to an outgoing connection is made in the agent,
+/// after the data that the app wrote before it, and that the app falls back to reading and
+/// writing when the agent doesn't support it.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn sendfile(dylib_path: &Path) {
+    let application = Application::CSendfile;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![], None)
+        .await;
+
+    intproxy
+        .expect_file_open_for_reading("/app/source.db", 3)
+        .await;
+
+    let peer: SocketAddr = "1.1.1.1:80".parse().unwrap();
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpOutgoing(LayerTcpOutgoing::Connect(LayerConnect {
+            remote_address: SocketAddress::Ip(peer),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Connect(Ok(
+            DaemonConnect {
+                connection_id: 0,
+                remote_address: peer.into(),
+                local_address: "10.0.0.1:40000".parse::<SocketAddr>().unwrap().into(),
+            },
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+            connection_id: 0,
+            bytes: b"header".to_vec(),
+        }))
+    );
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpOutgoing(LayerTcpOutgoing::SendFile(SendFileRequest {
+            connection_id: 0,
+            fd: 3,
+            offset: Some(4096),
+            count: 65536,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::SendFile(Ok(
+            SendFileResponse { sent_amount: 8192 },
+        ))))
+        .await;
+
+    // From here on, the agent doesn't support it.
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpOutgoing(LayerTcpOutgoing::SendFile(SendFileRequest {
+            connection_id: 0,
+            fd: 3,
+            offset: None,
+            count: 65536,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::SendFile(
+            Err(ResponseError::NotImplemented),
+        )))
+        .await;
+
+    intproxy.expect_single_file_read("hello", 3).await;
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+            connection_id: 0,
+            bytes: b"hello".to_vec(),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Close(0)))
+        .await;
+
+    intproxy.expect_file_close(3).await;
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
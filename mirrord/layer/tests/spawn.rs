@@ -15,24 +15,10 @@ mod common;
 
 pub use common::*;
 
-/// Some versions of node use `posix_spawn` and not `execve`, so make sure we load into processes
-/// that are created by node, with the node version installed where this test is executed.
-///
-/// The app starts the process `["/bin/sh", "-c", "cat /app/test.txt"]`.
-///
-/// Since the new process started by the app is bash, it is SIP on mac, so if we don't hook it's
-/// spawning we won't load to it. So if we get file requests for `/app/test.txt`, it means we
-/// successfully hooked the spawning and patched bash (or we're not on macOS).
-#[rstest]
-#[tokio::test]
-#[timeout(Duration::from_secs(60))]
-async fn node_spawn(dylib_path: &Path) {
-    let application = Application::NodeSpawn;
-    let (mut test_process, mut intproxy) = application
-        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
-        .await;
-
-    let mut opened_paths = HashSet::new();
+/// Serves the file requests of the app and its children until they exit, and returns the paths
+/// they opened. Every file reads as `metalbear-hostname`.
+async fn serve_file_requests(intproxy: &mut TestIntProxy) -> Vec<String> {
+    let mut opened_paths = Vec::new();
     let mut next_remote_fd = 0;
 
     let mut read_files = HashSet::new();
@@ -44,7 +30,7 @@ async fn node_spawn(dylib_path: &Path) {
 
         match msg {
             FileRequest::Open(OpenFileRequest { path, .. }) => {
-                opened_paths.insert(path.to_str().unwrap().to_string());
+                opened_paths.push(path.to_str().unwrap().to_string());
                 intproxy
                     .send(DaemonMessage::File(FileResponse::Open(Ok(
                         OpenFileResponse { fd: next_remote_fd },
@@ -77,8 +63,30 @@ async fn node_spawn(dylib_path: &Path) {
         }
     }
 
+    opened_paths
+}
+
+/// Some versions of node use `posix_spawn` and not `execve`, so make sure we load into processes
+/// that are created by node, with the node version installed where this test is executed.
+///
+/// The app starts the process `["/bin/sh", "-c", "cat /app/test.txt"]`.
+///
+/// Since the new process started by the app is bash, it is SIP on mac, so if we don't hook it's
+/// spawning we won't load to it. So if we get file requests for `/app/test.txt`, it means we
+/// successfully hooked the spawning and patched bash (or we're not on macOS).
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn node_spawn(dylib_path: &Path) {
+    let application = Application::NodeSpawn;
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
+        .await;
+
+    let opened_paths = serve_file_requests(&mut intproxy).await;
+
     assert!(
-        opened_paths.contains("/app/test.txt"),
+        opened_paths.iter().any(|path| path == "/app/test.txt"),
         "opened files: {opened_paths:?}"
     );
 
@@ -86,3 +94,35 @@ async fn node_spawn(dylib_path: &Path) {
     test_process.assert_no_error_in_stdout().await;
     test_process.assert_no_error_in_stderr().await;
 }
+
+/// Spawns `cat` with `posix_spawnp`, with file actions and attributes like the ones of macOS
+/// frameworks, and `sh` with `posix_spawn`, see `tests/apps/posix_spawn`.
+///
+/// Both children must load the layer (on macOS, `cat` and `sh` are SIP binaries that we patch),
+/// and the output of `cat` must reach the app through the pipe set up by the file actions.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn c_posix_spawn(dylib_path: &Path) {
+    let application = Application::CPosixSpawn;
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
+        .await;
+
+    let opened_paths = serve_file_requests(&mut intproxy).await;
+
+    assert_eq!(
+        opened_paths
+            .iter()
+            .filter(|path| *path == "/app/test.txt")
+            .count(),
+        2,
+        "opened files: {opened_paths:?}"
+    );
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("test posix_spawn: SUCCESS")
+        .await;
+    test_process.assert_no_error_in_stderr().await;
+}
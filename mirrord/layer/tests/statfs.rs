@@ -0,0 +1,28 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::statfs`] and [`libc::statvfs`] functions on a remote path that is never
+/// opened.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn statfs(dylib_path: &Path) {
+    let application = Application::CStatfs;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "read")], None)
+        .await;
+
+    intproxy.expect_statfs("/app/data").await;
+    intproxy.expect_statfs("/app/data").await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+}
@@ -0,0 +1,62 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{MetadataInternal, StatxMetadataInternal, StatxRequest, StatxResponse},
+    ClientMessage, DaemonMessage, FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that `statx` of a remote file returns the extended remote metadata, as coreutils and
+/// other modern runtimes read the birth time and the mount id.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn statx(dylib_path: &Path) {
+    let application = Application::CStatx;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, Default::default(), None)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Statx(StatxRequest {
+            dirfd: None,
+            path: Some("/app/data.db".into()),
+            follow_symlink: false,
+            mask: libc::STATX_BASIC_STATS | libc::STATX_BTIME,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Statx(Ok(
+            StatxResponse {
+                metadata: Box::new(StatxMetadataInternal {
+                    metadata: MetadataInternal {
+                        mode: libc::S_IFREG | 0o600,
+                        size: 4096,
+                        ..Default::default()
+                    },
+                    mask: libc::STATX_BASIC_STATS | libc::STATX_BTIME | libc::STATX_MNT_ID,
+                    birth_time: 1_700_000_000_123_456_789,
+                    mount_id: 412,
+                    ..Default::default()
+                }),
+            },
+        ))))
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("test statx: SUCCESS")
+        .await;
+}
@@ -0,0 +1,43 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::file::FileTimeInternal;
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test for the [`libc::utimensat`] function.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn utimens(dylib_path: &Path) {
+    let application = Application::Utimens;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy
+        .expect_utimens_at(
+            "/app/build/main.o",
+            FileTimeInternal::Omit,
+            FileTimeInternal::At(1_700_000_000_000_000_005),
+            0,
+        )
+        .await;
+    intproxy
+        .expect_utimens_at(
+            "/app/current",
+            FileTimeInternal::Now,
+            FileTimeInternal::Now,
+            libc::AT_SYMLINK_NOFOLLOW as u32,
+        )
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
@@ -0,0 +1,132 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{
+        ReadFileResponse, ReadLimitedFileRequest, ReadVFileRequest, ReadVFileResponse,
+        WriteFileRequest, WriteFileResponse, WriteVFileRequest,
+    },
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, ResponseError,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that the vectored reads and writes of a remote file send all the buffers in a single
+/// request, and that they're made with a single plain read or write when the agent doesn't
+/// support it.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn vectored_io(dylib_path: &Path) {
+    let application = Application::CVectoredIo;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    intproxy
+        .expect_file_open_for_writing("/app/vectored.db", 3)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::ReadV(ReadVFileRequest {
+            fd: 3,
+            lengths: vec![4, 8],
+            offset: None,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::ReadV(Ok(
+            ReadVFileResponse {
+                buffers: vec![b"abcd".to_vec(), b"efg".to_vec()],
+            },
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::WriteV(WriteVFileRequest {
+            fd: 3,
+            buffers: vec![b"hello ".to_vec(), b"world".to_vec()],
+            offset: Some(16),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::WriteV(Ok(
+            WriteFileResponse { written_amount: 11 },
+        ))))
+        .await;
+
+    // From here on, the agent doesn't support it.
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::ReadV(ReadVFileRequest {
+            fd: 3,
+            lengths: vec![3, 3],
+            offset: Some(0),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::ReadV(Err(
+            ResponseError::NotImplemented,
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::ReadLimited(ReadLimitedFileRequest {
+            remote_fd: 3,
+            buffer_size: 6,
+            start_from: 0,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::ReadLimited(Ok(
+            ReadFileResponse {
+                bytes: b"abcdef".to_vec(),
+                read_amount: 6,
+            },
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::WriteV(WriteVFileRequest {
+            fd: 3,
+            buffers: vec![b"hello ".to_vec(), b"world".to_vec()],
+            offset: None,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::WriteV(Err(
+            ResponseError::NotImplemented,
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Write(WriteFileRequest {
+            fd: 3,
+            write_bytes: b"hello world".to_vec(),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Write(Ok(
+            WriteFileResponse { written_amount: 11 },
+        ))))
+        .await;
+
+    intproxy.expect_file_close(3).await;
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process.assert_no_error_in_stdout().await;
+}
@@ -0,0 +1,123 @@
+#![cfg(target_os = "linux")]
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    file::{
+        GetXattrRequest, GetXattrResponse, ListXattrRequest, ListXattrResponse, RemoveXattrRequest,
+        SetXattrRequest,
+    },
+    ClientMessage, DaemonMessage, FileRequest, FileResponse,
+};
+use rstest::rstest;
+
+mod common;
+
+pub use common::*;
+
+/// Verifies that the `*xattr` calls on a remote file get the remote extended attributes, as
+/// container tooling and security scanners read them.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn xattr(dylib_path: &Path) {
+    let application = Application::CXattr;
+
+    let (mut test_process, mut intproxy) = application
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "write")], None)
+        .await;
+
+    // The size query, and the read into a buffer that is too small.
+    for _ in 0..2 {
+        assert_eq!(
+            intproxy.recv().await,
+            ClientMessage::FileRequest(FileRequest::GetXattr(GetXattrRequest {
+                path: Some("/app/config.yaml".into()),
+                fd: None,
+                follow_symlink: true,
+                name: b"user.checksum".to_vec(),
+            }))
+        );
+        intproxy
+            .send(DaemonMessage::File(FileResponse::GetXattr(Ok(
+                GetXattrResponse {
+                    value: b"abc123".to_vec(),
+                },
+            ))))
+            .await;
+    }
+
+    intproxy
+        .expect_file_open_for_reading("/app/config.yaml", 5)
+        .await;
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::GetXattr(GetXattrRequest {
+            path: None,
+            fd: Some(5),
+            follow_symlink: true,
+            name: b"user.checksum".to_vec(),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::GetXattr(Ok(
+            GetXattrResponse {
+                value: b"abc123".to_vec(),
+            },
+        ))))
+        .await;
+    intproxy.expect_file_close(5).await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::ListXattr(ListXattrRequest {
+            path: Some("/app/config.yaml".into()),
+            fd: None,
+            follow_symlink: false,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::ListXattr(Ok(
+            ListXattrResponse {
+                names: vec![b"user.checksum".to_vec(), b"security.selinux".to_vec()],
+            },
+        ))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::SetXattr(SetXattrRequest {
+            path: Some("/app/config.yaml".into()),
+            fd: None,
+            follow_symlink: true,
+            name: b"user.owner".to_vec(),
+            value: b"team-a".to_vec(),
+            flags: libc::XATTR_CREATE,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::SetXattr(Ok(()))))
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::RemoveXattr(RemoveXattrRequest {
+            path: Some("/app/config.yaml".into()),
+            fd: None,
+            follow_symlink: true,
+            name: b"user.checksum".to_vec(),
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::RemoveXattr(Ok(()))))
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process
+        .assert_stdout_contains("test xattr: SUCCESS")
+        .await;
+}
@@ -4,6 +4,7 @@ use base64::{engine::general_purpose, Engine};
 use chrono::{DateTime, Utc};
 use conn_wrapper::ConnectionWrapper;
 use error::{OperatorApiError, OperatorApiResult, OperatorOperation};
+use extensions::SessionExtensions;
 use http::{request::Request, HeaderName, HeaderValue};
 use kube::{
     api::{ListParams, PostParams},
@@ -14,6 +15,7 @@ use mirrord_auth::{
     certificate::Certificate,
     credential_store::{CredentialStoreSync, UserIdentity},
     credentials::LicenseValidity,
+    error::CredentialStoreError,
 };
 use mirrord_config::{feature::split_queues::SplitQueuesConfig, target::Target, LayerConfig};
 use mirrord_kube::{
@@ -27,21 +29,24 @@ use semver::Version;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::Level;
+use upgrade::ConnectWsError;
 
 use crate::{
     crd::{
-        CopyTargetCrd, CopyTargetSpec, MirrordOperatorCrd, NewOperatorFeature, TargetCrd,
-        OPERATOR_STATUS_NAME,
+        CopyTargetCrd, CopyTargetSpec, MirrordOperatorCrd, NewOperatorFeature, SessionShareCrd,
+        SessionShareLink, TargetCrd, OPERATOR_STATUS_NAME,
     },
     types::{
-        CLIENT_CERT_HEADER, CLIENT_HOSTNAME_HEADER, CLIENT_NAME_HEADER, MIRRORD_CLI_VERSION_HEADER,
-        SESSION_ID_HEADER,
+        AGENT_POOL_DISABLED, AGENT_POOL_HEADER, CLIENT_CERT_HEADER, CLIENT_HOSTNAME_HEADER,
+        CLIENT_NAME_HEADER, MIRRORD_CLI_VERSION_HEADER, SESSION_ID_HEADER,
+        SESSION_SHARE_TOKEN_HEADER,
     },
 };
 
 mod conn_wrapper;
 mod discovery;
 pub mod error;
+pub mod extensions;
 mod upgrade;
 
 /// State of client's [`Certificate`] the should be attached to some operator requests.
@@ -113,6 +118,29 @@ pub struct OperatorSession {
     /// Version of [`mirrord_protocol`] used by the operator.
     /// Used to create [`ConnectionWrapper`].
     pub operator_protocol_version: Option<Version>,
+    /// Metadata exchanged with the operator when connecting to the target, updated with each
+    /// connection.
+    #[serde(default)]
+    pub extensions: SessionExtensions,
+    /// Token of the shared session this session joined, see [`SessionShareCrd`].
+    /// Sent with each connection, as the operator checks it wasn't revoked.
+    #[serde(default)]
+    share_token: Option<String>,
+    /// Whether the operator should start a new agent for this session instead of handing it a
+    /// pre-warmed one, see [`AGENT_POOL_HEADER`].
+    #[serde(default)]
+    skip_agent_pool: bool,
+    /// Name of the [`MirrordAgentPool`](crate::crd::agent_pool::MirrordAgentPool) that the agent
+    /// of this session was claimed from, set from the operator response.
+    #[serde(default)]
+    pub agent_pool: Option<String>,
+}
+
+impl OperatorSession {
+    /// Hex id of this session, as shown by the operator.
+    pub fn id(&self) -> String {
+        format!("{:X}", self.id)
+    }
 }
 
 impl fmt::Debug for OperatorSession {
@@ -126,6 +154,10 @@ impl fmt::Debug for OperatorSession {
                 &self.operator_license_fingerprint,
             )
             .field("operator_protocol_version", &self.operator_protocol_version)
+            .field("extensions", &self.extensions)
+            .field("joined_shared_session", &self.share_token.is_some())
+            .field("skip_agent_pool", &self.skip_agent_pool)
+            .field("agent_pool", &self.agent_pool)
             .finish()
     }
 }
@@ -398,14 +430,10 @@ where
     /// 2. [`CLIENT_NAME_HEADER`]
     /// 3. [`CLIENT_HOSTNAME_HEADER`]
     async fn base_client_config(layer_config: &LayerConfig) -> OperatorApiResult<Config> {
-        let mut client_config = create_kube_config(
-            layer_config.accept_invalid_certificates,
-            layer_config.kubeconfig.clone(),
-            layer_config.kube_context.clone(),
-        )
-        .await
-        .map_err(KubeApiError::from)
-        .map_err(OperatorApiError::CreateKubeClient)?;
+        let mut client_config = create_kube_config(layer_config)
+            .await
+            .map_err(KubeApiError::from)
+            .map_err(OperatorApiError::CreateKubeClient)?;
 
         client_config.headers.push((
             HeaderName::from_static(MIRRORD_CLI_VERSION_HEADER),
@@ -631,7 +659,56 @@ impl OperatorApi<PreparedClientCert> {
 
         tracing::debug!("connect_url {connect_url:?}");
 
-        let session = OperatorSession {
+        let mut session = self.new_session(connect_url, None).await;
+        session.skip_agent_pool = !layer_config.agent.pool;
+
+        let mut connection_subtask = progress.subtask("connecting to the target");
+        let (tx, rx) = Self::connect_target(&self.client, &mut session).await?;
+        match &session.agent_pool {
+            Some(pool) => connection_subtask.success(Some(&format!(
+                "connected to the target, with a pre-warmed agent from pool `{pool}`"
+            ))),
+            None => connection_subtask.success(Some("connected to the target")),
+        }
+
+        Ok(OperatorSessionConnection { session, tx, rx })
+    }
+
+    /// Starts a new operator session that joins the session shared with the
+    /// [`SessionShareLink`] `link`, to observe the traffic it steals.
+    ///
+    /// The operator only sends mirrored copies of the stolen traffic to this session.
+    #[tracing::instrument(level = Level::TRACE, skip(link), ret, err)]
+    pub async fn join_shared_session(
+        &self,
+        link: &str,
+    ) -> OperatorApiResult<OperatorSessionConnection> {
+        self.operator
+            .spec
+            .require_feature(NewOperatorFeature::SessionSharing)?;
+
+        let SessionShareLink { session_id, token } = link.parse()?;
+
+        let use_proxy_api = self
+            .operator
+            .spec
+            .supported_features()
+            .contains(&NewOperatorFeature::ProxyApi);
+        let connect_url = SessionShareCrd::connect_url(&session_id, use_proxy_api);
+
+        let mut session = self.new_session(connect_url, Some(token)).await;
+        let (tx, rx) = Self::connect_target(&self.client, &mut session).await?;
+
+        Ok(OperatorSessionConnection { session, tx, rx })
+    }
+
+    /// Creates a new [`OperatorSession`] with a random id, connecting to `connect_url`.
+    async fn new_session(
+        &self,
+        connect_url: String,
+        share_token: Option<String>,
+    ) -> OperatorSession {
+        OperatorSession {
             id: rand::random(),
             connect_url,
             client_cert: self.client_cert.cert.clone(),
@@ -642,13 +719,35 @@ impl OperatorApi<PreparedClientCert> {
                 .protocol_version
                 .as_ref()
                 .and_then(|version| version.parse().ok()),
+            extensions: self.load_cached_extensions().await,
+            share_token,
+            skip_agent_pool: false,
+            agent_pool: None,
+        }
+    }
+
+    /// Returns the [`SessionExtensions`] cached for the operator license, if any.
+    ///
+    /// Failing to access the local credential store is not fatal, the operator will grant the
+    /// extensions again.
+    async fn load_cached_extensions(&self) -> SessionExtensions {
+        let Some(fingerprint) = self.operator.spec.license.fingerprint.as_deref() else {
+            return Default::default();
         };
 
-        let mut connection_subtask = progress.subtask("connecting to the target");
-        let (tx, rx) = Self::connect_target(&self.client, &session).await?;
-        connection_subtask.success(Some("connected to the target"));
+        let result: Result<_, CredentialStoreError> = try {
+            CredentialStoreSync::open()
+                .await?
+                .cached_extensions(fingerprint)
+                .await?
+        };
 
-        Ok(OperatorSessionConnection { session, tx, rx })
+        result
+            .inspect_err(
+                |error| tracing::warn!(%error, "Failed to load cached operator session extensions"),
+            )
+            .map(SessionExtensions::from)
+            .unwrap_or_default()
     }
 
     /// Returns client cert's public key in a base64 encoded string (no padding same like in
@@ -722,7 +821,7 @@ impl OperatorApi<PreparedClientCert> {
     #[tracing::instrument(level = Level::TRACE, skip(layer_config, reporter), ret, err)]
     pub async fn connect_in_existing_session<R>(
         layer_config: &LayerConfig,
-        session: OperatorSession,
+        mut session: OperatorSession,
         reporter: &mut R,
     ) -> OperatorApiResult<OperatorSessionConnection>
     where
@@ -748,33 +847,80 @@ impl OperatorApi<PreparedClientCert> {
             .map_err(KubeApiError::from)
             .map_err(OperatorApiError::CreateKubeClient)?;
 
-        let (tx, rx) = Self::connect_target(&client, &session).await?;
+        let (tx, rx) = Self::connect_target(&client, &mut session).await?;
 
         Ok(OperatorSessionConnection { tx, rx, session })
     }
 
     /// Creates websocket connection to the operator target.
+    ///
+    /// Exchanges the [`OperatorSession::extensions`] with the operator, and caches the ones it
+    /// updated in the local credential store.
     #[tracing::instrument(level = Level::TRACE, skip(client), err)]
     async fn connect_target(
         client: &Client,
-        session: &OperatorSession,
+        session: &mut OperatorSession,
     ) -> OperatorApiResult<(Sender<ClientMessage>, Receiver<DaemonMessage>)> {
-        let request = Request::builder()
+        let mut request = Request::builder()
             .uri(&session.connect_url)
-            .header(SESSION_ID_HEADER, session.id.to_string())
+            .header(SESSION_ID_HEADER, session.id.to_string());
+        if let Some(token) = &session.share_token {
+            request = request.header(SESSION_SHARE_TOKEN_HEADER, token);
+        }
+        if session.skip_agent_pool {
+            request = request.header(AGENT_POOL_HEADER, AGENT_POOL_DISABLED);
+        }
+        for (name, value) in session.extensions.request_headers() {
+            request = request.header(name, value);
+        }
+        let request = request
             .body(vec![])
             .map_err(OperatorApiError::ConnectRequestBuildError)?;
 
-        let connection = upgrade::connect_ws(client, request)
-            .await
-            .map_err(|error| OperatorApiError::KubeError {
-                error,
-                operation: OperatorOperation::WebsocketConnection,
-            })?;
+        let (connection, response_headers) =
+            upgrade::connect_ws(client, request)
+                .await
+                .map_err(|error| match error {
+                    ConnectWsError::Kube(error) => OperatorApiError::KubeError {
+                        error,
+                        operation: OperatorOperation::WebsocketConnection,
+                    },
+                    ConnectWsError::TargetNotAllowed(denied) => denied.into(),
+                })?;
+
+        if let Some(pool) = response_headers
+            .get(AGENT_POOL_HEADER)
+            .and_then(|pool| pool.to_str().ok())
+        {
+            session.agent_pool = Some(pool.to_string());
+        }
+
+        if session.extensions.update_from_response(&response_headers) {
+            Self::cache_extensions(session).await;
+        }
 
         Ok(ConnectionWrapper::wrap(
             connection,
             session.operator_protocol_version.clone(),
         ))
     }
+
+    /// Stores the [`OperatorSession::extensions`] in the local credential store, for the next
+    /// sessions with the same operator license.
+    async fn cache_extensions(session: &OperatorSession) {
+        let Some(fingerprint) = session.operator_license_fingerprint.clone() else {
+            return;
+        };
+
+        let result: Result<(), CredentialStoreError> = try {
+            CredentialStoreSync::open()
+                .await?
+                .cache_extensions(fingerprint, session.extensions.clone().into())
+                .await?
+        };
+
+        if let Err(error) = result {
+            tracing::warn!(%error, "Failed to cache operator session extensions");
+        }
+    }
 }
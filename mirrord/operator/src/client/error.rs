@@ -4,7 +4,10 @@ pub use http::Error as HttpError;
 use mirrord_kube::error::KubeApiError;
 use thiserror::Error;
 
-use crate::crd::{kube_target::UnknownTargetType, NewOperatorFeature};
+use crate::crd::{
+    kube_target::UnknownTargetType, target_allowlist::TargetNotAllowed, InvalidSessionShareLink,
+    NewOperatorFeature,
+};
 
 /// Operations performed on the operator via [`kube`] API.
 #[derive(Debug)]
@@ -16,6 +19,8 @@ pub enum OperatorOperation {
     GettingStatus,
     SessionManagement,
     ListingTargets,
+    GettingUsage,
+    SharingSession,
 }
 
 impl fmt::Display for OperatorOperation {
@@ -28,6 +33,8 @@ impl fmt::Display for OperatorOperation {
             Self::GettingStatus => "getting status",
             Self::SessionManagement => "session management",
             Self::ListingTargets => "listing targets",
+            Self::GettingUsage => "getting usage",
+            Self::SharingSession => "sharing session",
         };
 
         f.write_str(as_str)
@@ -71,6 +78,13 @@ pub enum OperatorApiError {
 
     #[error("mirrord operator failed KubeApi operation: {0}")]
     KubeApi(#[from] KubeApiError),
+
+    #[error(transparent)]
+    InvalidSessionShareLink(#[from] InvalidSessionShareLink),
+
+    /// The target is denied by the user's `MirrordTargetAllowlist`s.
+    #[error(transparent)]
+    TargetNotAllowed(#[from] TargetNotAllowed),
 }
 
 pub type OperatorApiResult<T, E = OperatorApiError> = Result<T, E>;
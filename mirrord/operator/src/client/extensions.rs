@@ -0,0 +1,146 @@
+//! Key-value metadata exchanged with the operator in the target connection handshake, see
+//! [`SessionExtensions`].
+
+use std::collections::BTreeMap;
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+use crate::types::EXTENSION_HEADER_PREFIX;
+
+/// Extensible metadata of an operator session (e.g. feature grants of operator add-ons), so that
+/// new operator features don't need new handshake messages.
+///
+/// Each entry travels as an [`EXTENSION_HEADER_PREFIX`] header, both ways:
+///
+/// 1. The CLI sends the entries it has (cached from previous sessions, see
+///    [`CredentialStoreSync::cached_extensions`](mirrord_auth::credential_store::CredentialStoreSync::cached_extensions))
+///    with the target connection request;
+/// 2. The operator sets the up-to-date entries on the target connection response, an empty value
+///    removes the entry.
+///
+/// Keys are lowercase, as they come from header names.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SessionExtensions(BTreeMap<String, String>);
+
+impl SessionExtensions {
+    /// Returns the value of the entry `key`, if the operator granted it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(&key.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the headers to send with the target connection request.
+    ///
+    /// Entries that can't be sent as headers are skipped.
+    pub(crate) fn request_headers(&self) -> impl '_ + Iterator<Item = (HeaderName, HeaderValue)> {
+        self.0.iter().filter_map(|(key, value)| {
+            let name = HeaderName::try_from(format!("{EXTENSION_HEADER_PREFIX}{key}"));
+            let value = HeaderValue::from_str(value);
+
+            match (name, value) {
+                (Ok(name), Ok(value)) => Some((name, value)),
+                _ => {
+                    tracing::debug!(key, "Invalid session extension header");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Updates the entries with the [`EXTENSION_HEADER_PREFIX`] headers of the target connection
+    /// response.
+    ///
+    /// Returns whether any entry changed.
+    pub(crate) fn update_from_response(&mut self, headers: &HeaderMap) -> bool {
+        let mut changed = false;
+
+        for (name, value) in headers {
+            let Some(key) = name.as_str().strip_prefix(EXTENSION_HEADER_PREFIX) else {
+                continue;
+            };
+
+            let Ok(value) = value.to_str() else {
+                tracing::debug!(key, "Non-ASCII session extension header");
+                continue;
+            };
+
+            changed |= if value.is_empty() {
+                self.0.remove(key).is_some()
+            } else {
+                self.0.insert(key.to_string(), value.to_string()).as_deref() != Some(value)
+            };
+        }
+
+        changed
+    }
+}
+
+impl From<BTreeMap<String, String>> for SessionExtensions {
+    fn from(entries: BTreeMap<String, String>) -> Self {
+        Self(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_ascii_lowercase(), value))
+                .collect(),
+        )
+    }
+}
+
+impl From<SessionExtensions> for BTreeMap<String, String> {
+    fn from(extensions: SessionExtensions) -> Self {
+        extensions.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use http::{HeaderMap, HeaderValue};
+
+    use super::SessionExtensions;
+
+    #[test]
+    fn update_from_response() {
+        let mut extensions = SessionExtensions::from(BTreeMap::from([
+            ("sqs-splitting".to_string(), "granted".to_string()),
+            ("trial".to_string(), "3d".to_string()),
+        ]));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-mirrord-ext-db-branching",
+            HeaderValue::from_static("granted"),
+        );
+        headers.insert("x-mirrord-ext-trial", HeaderValue::from_static(""));
+        headers.insert("x-session-id", HeaderValue::from_static("42"));
+
+        assert!(extensions.update_from_response(&headers));
+        assert_eq!(extensions.get("db-branching"), Some("granted"));
+        assert_eq!(extensions.get("SQS-Splitting"), Some("granted"));
+        assert_eq!(extensions.get("trial"), None);
+        assert_eq!(extensions.get("x-session-id"), None);
+
+        assert!(!extensions.update_from_response(&headers));
+    }
+
+    #[test]
+    fn request_headers_round_trip() {
+        let extensions = SessionExtensions::from(BTreeMap::from([
+            ("Kafka-Splitting".to_string(), "granted".to_string()),
+            ("bad key".to_string(), "granted".to_string()),
+        ]));
+
+        let headers = extensions.request_headers().collect::<HeaderMap>();
+        assert_eq!(headers.len(), 1);
+
+        let mut received = SessionExtensions::default();
+        assert!(received.update_from_response(&headers));
+        assert_eq!(received.get("kafka-splitting"), Some("granted"));
+    }
+}
@@ -6,19 +6,32 @@
 //! [`UpgradeConnectionError`]. [`connect_ws`] attempts to
 //! recover the [`ErrorResponse`] - if operator response code is not
 //! [`StatusCode::SWITCHING_PROTOCOLS`], it tries to read
-//! response body and deserialize it.
+//! response body and deserialize it. A [`TargetNotAllowed`] denial
+//! is recovered from the details of the [`Status`] in the body.
 
 use base64::Engine;
-use http::{HeaderValue, Request, Response, StatusCode};
+use http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
 use http_body_util::BodyExt;
 use hyper_util::rt::TokioIo;
 use kube::{
     client::{Body, UpgradeConnectionError},
-    core::ErrorResponse,
+    core::{ErrorResponse, Status},
     Client, Error, Result,
 };
 use tokio_tungstenite::{tungstenite::protocol::Role, WebSocketStream};
 
+use crate::crd::target_allowlist::TargetNotAllowed;
+
+/// Errors of [`connect_ws`].
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectWsError {
+    #[error(transparent)]
+    Kube(#[from] Error),
+
+    #[error(transparent)]
+    TargetNotAllowed(#[from] TargetNotAllowed),
+}
+
 const WS_PROTOCOL: &str = "v4.channel.k8s.io";
 
 // Verify upgrade response according to RFC6455.
@@ -27,20 +40,6 @@ async fn verify_response(res: Response<Body>, key: &HeaderValue) -> Result<Respo
     let status = res.status();
 
     if status != StatusCode::SWITCHING_PROTOCOLS {
-        if status.is_client_error() || status.is_server_error() {
-            let error_response = res
-                .into_body()
-                .collect()
-                .await
-                .ok()
-                .map(|body| body.to_bytes())
-                .and_then(|body_bytes| serde_json::from_slice::<ErrorResponse>(&body_bytes).ok());
-
-            if let Some(error_response) = error_response {
-                return Err(Error::Api(error_response));
-            }
-        }
-
         return Err(Error::UpgradeConnection(
             UpgradeConnectionError::ProtocolSwitch(status),
         ));
@@ -94,6 +93,29 @@ async fn verify_response(res: Response<Body>, key: &HeaderValue) -> Result<Respo
     Ok(res)
 }
 
+/// Recovers the error sent by the operator in the body of a failed upgrade response.
+async fn error_response(res: Response<Body>) -> ConnectWsError {
+    let status = res.status();
+    let body = res
+        .into_body()
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .unwrap_or_default();
+
+    if let Some(denied) = serde_json::from_slice::<Status>(&body)
+        .ok()
+        .and_then(|status| TargetNotAllowed::from_status(&status))
+    {
+        return denied.into();
+    }
+
+    match serde_json::from_slice::<ErrorResponse>(&body) {
+        Ok(error_response) => Error::Api(error_response).into(),
+        Err(..) => Error::UpgradeConnection(UpgradeConnectionError::ProtocolSwitch(status)).into(),
+    }
+}
+
 /// Generate a random key for the `Sec-WebSocket-Key` header.
 /// This must be nonce consisting of a randomly selected 16-byte value in base64.
 fn sec_websocket_key() -> HeaderValue {
@@ -104,10 +126,17 @@ fn sec_websocket_key() -> HeaderValue {
         .expect("should be valid")
 }
 
+/// Also returns the headers of the operator's upgrade response.
 pub async fn connect_ws(
     client: &Client,
     request: Request<Vec<u8>>,
-) -> kube::Result<WebSocketStream<TokioIo<hyper::upgrade::Upgraded>>> {
+) -> Result<
+    (
+        WebSocketStream<TokioIo<hyper::upgrade::Upgraded>>,
+        HeaderMap,
+    ),
+    ConnectWsError,
+> {
     let (mut parts, body) = request.into_parts();
     parts.headers.insert(
         http::header::CONNECTION,
@@ -137,14 +166,19 @@ pub async fn connect_ws(
     let res = client
         .send(Request::from_parts(parts, Body::from(body)))
         .await?;
+    if res.status().is_client_error() || res.status().is_server_error() {
+        return Err(error_response(res).await);
+    }
     let res = verify_response(res, &key).await?;
+    let headers = res.headers().clone();
     match hyper::upgrade::on(res).await {
-        Ok(upgraded) => {
-            Ok(WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Client, None).await)
-        }
-
-        Err(e) => Err(Error::UpgradeConnection(
-            UpgradeConnectionError::GetPendingUpgrade(e),
+        Ok(upgraded) => Ok((
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Client, None).await,
+            headers,
         )),
+
+        Err(e) => {
+            Err(Error::UpgradeConnection(UpgradeConnectionError::GetPendingUpgrade(e)).into())
+        }
     }
 }
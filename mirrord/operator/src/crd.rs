@@ -1,6 +1,7 @@
 use std::{
     collections::{BTreeMap, HashMap},
     fmt::{Display, Formatter},
+    str::FromStr,
 };
 
 use chrono::{DateTime, Utc};
@@ -15,14 +16,16 @@ use schemars::JsonSchema;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
-use self::label_selector::LabelSelector;
+use self::{agent_pool::MirrordAgentPoolStatus, label_selector::LabelSelector};
 #[cfg(feature = "client")]
 use crate::client::error::OperatorApiError;
 use crate::types::LicenseInfoOwned;
 
+pub mod agent_pool;
 pub mod kafka;
 pub mod kube_target;
 pub mod label_selector;
+pub mod target_allowlist;
 
 pub const TARGETLESS_TARGET_NAME: &str = "targetless";
 
@@ -210,6 +213,10 @@ pub struct MirrordOperatorStatus {
     /// Option because added later.
     /// (copy-target pod name, copy-target resource)
     pub copy_targets: Option<Vec<(String, CopyTargetCrd)>>,
+
+    /// Option because added later.
+    /// (agent pool name, agent pool status)
+    pub agent_pools: Option<Vec<(String, MirrordAgentPoolStatus)>>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
@@ -248,6 +255,136 @@ pub struct Session {
 )]
 pub struct SessionSpec;
 
+/// Resource used to share an operator session with other users, so that they can observe the
+/// traffic the session steals (pair debugging).
+///
+/// The owner of the session creates it with the session id as its name, and the operator issues
+/// a [`SessionShareStatus::token`]. A client that connects with [`SessionShareCrd::connect_url`]
+/// and the token (see [`SessionShareLink`]) joins the session in observe-only mode, receiving
+/// mirrored copies of the stolen traffic. Deleting the resource revokes the share, and disconnects
+/// the observers.
+///
+/// Requires [`NewOperatorFeature::SessionSharing`].
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "operator.metalbear.co",
+    version = "v1",
+    kind = "SessionShare",
+    root = "SessionShareCrd",
+    status = "SessionShareStatus"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionShareSpec {
+    /// How long the token stays valid, the operator's default when [`None`].
+    pub ttl_secs: Option<u64>,
+}
+
+impl SessionShareCrd {
+    /// URL for joining the session shared under `name`.
+    pub fn connect_url(name: &str, use_proxy: bool) -> String {
+        let api_version = SessionShareCrd::api_version(&());
+        let plural = SessionShareCrd::plural(&());
+        let url_path = SessionShareCrd::url_path(&(), None);
+
+        if use_proxy {
+            format!("/apis/{api_version}/proxy/{plural}/{name}?connect=true")
+        } else {
+            format!("{url_path}/{name}?connect=true")
+        }
+    }
+}
+
+/// This is the `status` field for [`SessionShareCrd`].
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionShareStatus {
+    /// Secret that lets a client join the shared session.
+    pub token: String,
+    /// When the token stops being valid.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Users currently observing the session.
+    #[serde(default)]
+    pub observers: Vec<String>,
+}
+
+/// What a user needs to join a shared session, printed as `<session id>:<token>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionShareLink {
+    /// Name of the [`SessionShareCrd`], which is the hex id of the shared session.
+    pub session_id: String,
+    /// The [`SessionShareStatus::token`].
+    pub token: String,
+}
+
+impl Display for SessionShareLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.session_id, self.token)
+    }
+}
+
+impl FromStr for SessionShareLink {
+    type Err = InvalidSessionShareLink;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        match link.split_once(':') {
+            Some((session_id, token))
+                if !token.is_empty() && u64::from_str_radix(session_id, 16).is_ok() =>
+            {
+                Ok(Self {
+                    session_id: session_id.to_string(),
+                    token: token.to_string(),
+                })
+            }
+            _ => Err(InvalidSessionShareLink),
+        }
+    }
+}
+
+/// Error returned when parsing a malformed [`SessionShareLink`].
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("invalid session share link, expected `<session id>:<token>`")]
+pub struct InvalidSessionShareLink;
+
+/// Resource used to fetch aggregated usage statistics from the operator.
+///
+/// The operator computes the statistics from its session history when the resource is fetched.
+/// The name of the fetched resource is the length of the reported period in seconds, e.g.
+/// `usages/2592000` contains the usage from the last 30 days.
+///
+/// Requires [`NewOperatorFeature::UsageReporting`].
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "operator.metalbear.co",
+    version = "v1",
+    kind = "Usage",
+    root = "UsageCrd"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSpec {
+    /// Start of the reported period.
+    pub since: DateTime<Utc>,
+    /// Usage of each team, as configured in the operator.
+    pub teams: Vec<UsageEntry>,
+    /// Usage of each target, sorted from the most used.
+    pub targets: Vec<UsageEntry>,
+}
+
+/// Usage of a team or a target, see [`UsageSpec`].
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageEntry {
+    /// Name of the team, or the target (e.g. `deploy/api`).
+    pub name: String,
+    /// Namespace of the target, [`None`] for teams.
+    pub namespace: Option<String>,
+    /// Number of sessions started in the reported period.
+    pub sessions: u64,
+    /// Total duration of the sessions that stole traffic.
+    pub steal_seconds: u64,
+    /// Total duration of the sessions that only mirrored traffic.
+    pub mirror_seconds: u64,
+}
+
 /// Features supported by operator
 ///
 /// Since this enum does not have a variant marked with `#[serde(other)]`, and is present like that
@@ -268,6 +405,9 @@ pub enum NewOperatorFeature {
     SessionManagement,
     SqsQueueSplitting,
     KafkaQueueSplitting,
+    UsageReporting,
+    SessionSharing,
+    AgentPool,
     /// This variant is what a client sees when the operator includes a feature the client is not
     /// yet aware of, because it was introduced in a version newer than the client's.
     #[schemars(skip)]
@@ -283,6 +423,9 @@ impl Display for NewOperatorFeature {
             NewOperatorFeature::SessionManagement => "session management",
             NewOperatorFeature::SqsQueueSplitting => "SQS queue splitting",
             NewOperatorFeature::KafkaQueueSplitting => "Kafka queue splitting",
+            NewOperatorFeature::UsageReporting => "usage reporting",
+            NewOperatorFeature::SessionSharing => "session sharing",
+            NewOperatorFeature::AgentPool => "agent pool",
             NewOperatorFeature::Unknown => "unknown feature",
         };
         f.write_str(name)
@@ -387,6 +530,37 @@ pub struct MirrordPolicySpec {
     // TODO: make the k8s list type be set/map to prevent duplicates.
     /// List of features and operations blocked by this policy.
     pub block: Vec<BlockedFeature>,
+
+    /// Only allow read-only sessions (`mode: read_only` in the mirrord config), that can't
+    /// modify the target. Sessions that don't send `ClientMessage::ReadOnly` before any other
+    /// request are closed by the operator.
+    #[serde(default)]
+    pub require_read_only: bool,
+
+    /// What the agents of the sessions do when the user application reads the service account
+    /// token of the target. When more than one policy applies to a target, the strictest one is
+    /// used. The operator passes it to the agents in `MIRRORD_AGENT_SERVICE_ACCOUNT_TOKENS`.
+    #[serde(default)]
+    pub service_account_tokens: ServiceAccountTokenPolicy,
+}
+
+/// What the agent does when the user application reads the service account token of the target,
+/// set in a `MirrordPolicy`. Ordered from the least to the most strict.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Ord, PartialOrd, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServiceAccountTokenPolicy {
+    /// The token is read like any other remote file.
+    #[default]
+    Allow,
+    /// The token is read, and the agent logs the access.
+    Audit,
+    /// The application reads a short-lived token with narrow permissions, minted by the operator
+    /// for the session, instead of the target's token.
+    Substitute,
+    /// Reading the token fails with `EACCES`, and the agent logs the attempt.
+    Block,
 }
 
 /// Set where the application reads the name of the queue from, so that mirrord can find that queue,
@@ -688,3 +862,28 @@ pub struct MirrordOperatorUserSpec {
     /// Last session's target.
     pub last_target: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::SessionShareLink;
+
+    #[test]
+    fn session_share_link_roundtrip() {
+        let link = SessionShareLink {
+            session_id: "5A2C9B10F3E4D768".to_string(),
+            token: "9f1c:2e7a".to_string(),
+        };
+
+        assert_eq!(link.to_string().parse::<SessionShareLink>().unwrap(), link);
+    }
+
+    #[rstest]
+    #[case("5A2C9B10F3E4D768")]
+    #[case("5A2C9B10F3E4D768:")]
+    #[case("session:9f1c2e7a")]
+    fn invalid_session_share_link(#[case] link: &str) {
+        assert!(link.parse::<SessionShareLink>().is_err());
+    }
+}
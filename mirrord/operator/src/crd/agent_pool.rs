@@ -0,0 +1,224 @@
+//! `MirrordAgentPool`, keeps pre-warmed agent pods that new sessions claim instead of waiting for
+//! a new agent to be scheduled and started.
+//!
+//! The operator keeps [`MirrordAgentPoolSpec::size`] idle agents in every slot of the pool, a slot
+//! being a node or a namespace depending on the [`AgentPoolScope`]:
+//!
+//! - targeted sessions claim an idle agent from the slot of the node where their target runs, and
+//!   the agent enters the namespaces of the target once claimed;
+//! - targetless sessions claim an idle agent from the slot of their namespace.
+//!
+//! Claimed agents are replaced right away, and are recycled (deleted) after they served
+//! [`AgentPoolRecycle::after_sessions`] sessions. Sessions that find no idle agent, or that opt
+//! out with `agent.pool: false`, get a new agent as before.
+use std::{collections::BTreeMap, time::Duration};
+
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::target_allowlist::wildcard_match;
+
+/// Custom resource for a pool of pre-warmed agents, managed by the operator.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "operator.metalbear.co",
+    version = "v1",
+    kind = "MirrordAgentPool",
+    status = "MirrordAgentPoolStatus",
+    printcolumn = r#"{"name":"SIZE", "type":"integer", "description":"Idle agents kept in each slot.", "jsonPath":".spec.size"}"#
+)]
+#[serde(rename_all = "camelCase")] // max_size -> maxSize in yaml.
+pub struct MirrordAgentPoolSpec {
+    /// Whether the idle agents are kept per node or per namespace.
+    pub scope: AgentPoolScope,
+
+    /// Namespaces of the sessions that can claim agents from this pool. Namespaces can be matched
+    /// using `*` and `?` where `?` matches exactly one occurrence of any character and `*`
+    /// matches arbitrary many (including zero) occurrences of any character, e.g. `dev-*`.
+    ///
+    /// With [`AgentPoolScope::Namespace`], these are also the namespaces where the idle agents
+    /// are kept. If not specified, any namespace.
+    pub namespaces: Option<Vec<String>>,
+
+    /// Number of idle agents to keep in each slot.
+    pub size: u32,
+
+    /// Maximum number of agents, idle and claimed, in each slot. Once reached, claimed agents are
+    /// not replaced until they are recycled. If not specified, there is no limit.
+    pub max_size: Option<u32>,
+
+    /// When the agents of the pool are replaced.
+    #[serde(default)]
+    pub recycle: AgentPoolRecycle,
+}
+
+/// The slots of a [`MirrordAgentPool`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")] // Node -> node in yaml.
+pub enum AgentPoolScope {
+    /// Idle agents are kept on every node, for targeted sessions.
+    Node {
+        /// Only keeps idle agents on the nodes with these labels. If not specified, all nodes.
+        #[serde(rename = "nodeSelector")]
+        node_selector: Option<BTreeMap<String, String>>,
+    },
+    /// Idle agents are kept in every namespace of
+    /// [`MirrordAgentPoolSpec::namespaces`], for targetless sessions.
+    Namespace,
+}
+
+/// When the agents of a [`MirrordAgentPool`] are replaced.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")] // after_sessions -> afterSessions in yaml.
+pub struct AgentPoolRecycle {
+    /// Number of sessions that an agent serves before it is recycled. Defaults to 1, an agent is
+    /// never reused by another session.
+    pub after_sessions: Option<u32>,
+
+    /// Idle agents older than this many seconds are recycled, e.g. to pick up a new agent image.
+    /// If not specified, idle agents are not recycled.
+    pub max_idle_seconds: Option<u64>,
+}
+
+/// The `status` of a [`MirrordAgentPool`], set by the operator.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+pub struct MirrordAgentPoolStatus {
+    pub slots: Vec<AgentPoolSlot>,
+}
+
+/// The agents of a [`MirrordAgentPool`] in a node or in a namespace.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+pub struct AgentPoolSlot {
+    /// Name of the node or of the namespace, see [`AgentPoolScope`].
+    pub name: String,
+    /// Idle agents that are ready to be claimed.
+    pub ready: u32,
+    /// Idle agents that are not ready yet.
+    pub starting: u32,
+    /// Agents claimed by sessions.
+    pub claimed: u32,
+}
+
+impl MirrordAgentPoolSpec {
+    /// Whether sessions in `namespace` can claim agents from this pool.
+    pub fn allows_namespace(&self, namespace: &str) -> bool {
+        self.namespaces.as_ref().is_none_or(|patterns| {
+            patterns
+                .iter()
+                .any(|pattern| wildcard_match(pattern, namespace))
+        })
+    }
+
+    /// Number of agents to start in `slot`, to get back to [`Self::size`] idle agents without
+    /// going over [`Self::max_size`].
+    pub fn agents_to_start(&self, slot: &AgentPoolSlot) -> u32 {
+        let idle = slot.ready + slot.starting;
+        let missing = self.size.saturating_sub(idle);
+
+        match self.max_size {
+            Some(max_size) => missing.min(max_size.saturating_sub(idle + slot.claimed)),
+            None => missing,
+        }
+    }
+}
+
+impl AgentPoolRecycle {
+    /// Whether an agent that served `sessions` sessions, and has been idle for `idle_for` (if
+    /// it's idle), should be recycled.
+    pub fn should_recycle(&self, sessions: u32, idle_for: Option<Duration>) -> bool {
+        sessions >= self.after_sessions.unwrap_or(1).max(1)
+            || self
+                .max_idle_seconds
+                .zip(idle_for)
+                .is_some_and(|(max_idle, idle_for)| idle_for.as_secs() >= max_idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn pool(json: &str) -> MirrordAgentPoolSpec {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn parse_spec() {
+        let spec = pool(
+            r#"{
+                "scope": {"node": {"nodeSelector": {"pool": "dev"}}},
+                "namespaces": ["dev-*"],
+                "size": 2,
+                "maxSize": 5,
+                "recycle": {"maxIdleSeconds": 3600}
+            }"#,
+        );
+
+        assert_eq!(
+            spec.scope,
+            AgentPoolScope::Node {
+                node_selector: Some(BTreeMap::from([("pool".into(), "dev".into())])),
+            }
+        );
+        assert!(spec.allows_namespace("dev-payments"));
+        assert!(!spec.allows_namespace("prod"));
+        assert_eq!(spec.recycle.after_sessions, None);
+        assert_eq!(spec.recycle.max_idle_seconds, Some(3600));
+
+        let spec = pool(r#"{"scope": "namespace", "size": 1}"#);
+        assert_eq!(spec.scope, AgentPoolScope::Namespace);
+        assert!(spec.allows_namespace("prod"));
+    }
+
+    #[rstest]
+    #[case::fills_up(None, 0, 0, 0, 3)]
+    #[case::counts_starting(None, 1, 1, 4, 1)]
+    #[case::full(None, 3, 0, 0, 0)]
+    #[case::over_size(None, 4, 0, 0, 0)]
+    #[case::capped(Some(5), 0, 1, 3, 1)]
+    #[case::at_max_size(Some(5), 1, 0, 4, 0)]
+    fn agents_to_start(
+        #[case] max_size: Option<u32>,
+        #[case] ready: u32,
+        #[case] starting: u32,
+        #[case] claimed: u32,
+        #[case] expected: u32,
+    ) {
+        let spec = MirrordAgentPoolSpec {
+            scope: AgentPoolScope::Namespace,
+            namespaces: None,
+            size: 3,
+            max_size,
+            recycle: Default::default(),
+        };
+        let slot = AgentPoolSlot {
+            name: "default".into(),
+            ready,
+            starting,
+            claimed,
+        };
+
+        assert_eq!(spec.agents_to_start(&slot), expected);
+    }
+
+    #[test]
+    fn should_recycle() {
+        let once = AgentPoolRecycle::default();
+        assert!(!once.should_recycle(0, Some(Duration::from_secs(86400))));
+        assert!(once.should_recycle(1, None));
+
+        let reused = AgentPoolRecycle {
+            after_sessions: Some(3),
+            max_idle_seconds: Some(600),
+        };
+        assert!(!reused.should_recycle(2, Some(Duration::from_secs(10))));
+        assert!(!reused.should_recycle(2, None));
+        assert!(reused.should_recycle(2, Some(Duration::from_secs(600))));
+        assert!(reused.should_recycle(3, None));
+    }
+}
@@ -0,0 +1,361 @@
+//! `MirrordTargetAllowlist`, restricts the targets that operator users can use based on their
+//! identity, e.g. "team-payments can only target namespaces `payments-*`".
+//!
+//! The operator identifies the user by the common name of their client certificate, or by the
+//! user name, groups and OIDC claims that the Kubernetes API server authenticated. An allowlist
+//! applies to a user when any of its [`AllowlistSubject`]s matches them. Users with no allowlists
+//! that apply to them are not restricted, the others can only use the targets that at least one of
+//! their allowlists allows.
+//!
+//! Denials are sent to the CLI in the details of a [`Status`], see [`TargetNotAllowed`].
+use std::collections::BTreeMap;
+
+use kube::{
+    core::{
+        response::{StatusCause, StatusDetails},
+        Status,
+    },
+    CustomResource, ResourceExt,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// [`StatusCause::reason`] of the causes that carry the names of the allowlists that denied a
+/// target.
+pub const TARGET_NOT_ALLOWED_REASON: &str = "TargetNotAllowed";
+
+/// Custom resource for the targets that a group of operator users is allowed to use.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    // Like `MirrordPolicy`, handled by k8s.
+    group = "policies.mirrord.metalbear.co",
+    version = "v1alpha",
+    kind = "MirrordTargetAllowlist"
+)]
+#[serde(rename_all = "camelCase")] // target_paths -> targetPaths in yaml.
+pub struct MirrordTargetAllowlistSpec {
+    /// The users that this allowlist applies to.
+    pub subjects: Vec<AllowlistSubject>,
+
+    /// Namespaces that the subjects can target. Namespaces can be matched using `*` and `?` where
+    /// `?` matches exactly one occurrence of any character and `*` matches arbitrary many
+    /// (including zero) occurrences of any character, e.g. `payments-*`.
+    pub namespaces: Vec<String>,
+
+    /// Targets that the subjects can use in those namespaces, in the pod/my-pod deploy/my-deploy
+    /// notation, matched like the namespaces. If not specified, any target in the namespaces is
+    /// allowed. `targetless` allows targetless sessions.
+    pub target_paths: Option<Vec<String>>,
+}
+
+/// Matches operator users in a [`MirrordTargetAllowlistSpec`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")] // Claim -> claim in yaml.
+pub enum AllowlistSubject {
+    /// The common name of the user's client certificate, or their Kubernetes user name.
+    User(String),
+    /// A Kubernetes group of the user, e.g. from the `groups` OIDC claim.
+    Group(String),
+    /// An OIDC claim of the user, that the API server passes to the operator as extra user info.
+    Claim {
+        /// Name of the claim, e.g. `department`.
+        name: String,
+        /// The claim matches when it has this value, or contains it if it's a list.
+        value: String,
+    },
+}
+
+/// Who is using the operator, matched against the [`AllowlistSubject`]s.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserIdentity {
+    /// Common name of the client certificate, or the Kubernetes user name.
+    pub name: String,
+    /// Kubernetes groups of the user.
+    pub groups: Vec<String>,
+    /// OIDC claims of the user, by name.
+    pub claims: BTreeMap<String, Vec<String>>,
+}
+
+impl AllowlistSubject {
+    pub fn matches(&self, identity: &UserIdentity) -> bool {
+        match self {
+            Self::User(name) => identity.name == *name,
+            Self::Group(group) => identity.groups.contains(group),
+            Self::Claim { name, value } => identity
+                .claims
+                .get(name)
+                .is_some_and(|values| values.contains(value)),
+        }
+    }
+}
+
+impl MirrordTargetAllowlistSpec {
+    /// Whether this allowlist restricts the targets of the user.
+    pub fn applies_to(&self, identity: &UserIdentity) -> bool {
+        self.subjects
+            .iter()
+            .any(|subject| subject.matches(identity))
+    }
+
+    /// Whether the target at `target_path` in `namespace` is allowed by this allowlist.
+    pub fn allows(&self, namespace: &str, target_path: &str) -> bool {
+        self.namespaces
+            .iter()
+            .any(|pattern| wildcard_match(pattern, namespace))
+            && self.target_paths.as_ref().is_none_or(|patterns| {
+                patterns
+                    .iter()
+                    .any(|pattern| wildcard_match(pattern, target_path))
+            })
+    }
+}
+
+/// Checks the target at `target_path` in `namespace` against the `allowlists` that apply to the
+/// user.
+///
+/// The target is allowed when no allowlist applies to the user, or when any of the ones that
+/// apply allows it.
+pub fn check_target_allowed<'a, I>(
+    allowlists: I,
+    identity: &UserIdentity,
+    namespace: &str,
+    target_path: &str,
+) -> Result<(), TargetNotAllowed>
+where
+    I: IntoIterator<Item = &'a MirrordTargetAllowlist>,
+{
+    let mut denied_by = Vec::new();
+
+    for allowlist in allowlists {
+        if !allowlist.spec.applies_to(identity) {
+            continue;
+        }
+
+        if allowlist.spec.allows(namespace, target_path) {
+            return Ok(());
+        }
+
+        denied_by.push(allowlist.name_any());
+    }
+
+    if denied_by.is_empty() {
+        Ok(())
+    } else {
+        Err(TargetNotAllowed {
+            namespace: namespace.to_string(),
+            target_path: target_path.to_string(),
+            denied_by,
+        })
+    }
+}
+
+/// The operator denied a target to the user, because none of the [`MirrordTargetAllowlist`]s that
+/// apply to them allows it.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "target `{target_path}` in namespace `{namespace}` is not allowed by the target allowlists \
+    that apply to you: {}",
+    .denied_by.join(", ")
+)]
+pub struct TargetNotAllowed {
+    pub namespace: String,
+    pub target_path: String,
+    /// Names of the allowlists that apply to the user.
+    pub denied_by: Vec<String>,
+}
+
+impl TargetNotAllowed {
+    /// Recovers the denial from a [`Status`] sent by the operator, [`None`] if it's a different
+    /// failure.
+    pub fn from_status(status: &Status) -> Option<Self> {
+        let details = status.details.as_ref()?;
+
+        let denied_by = details
+            .causes
+            .iter()
+            .filter(|cause| cause.reason == TARGET_NOT_ALLOWED_REASON)
+            .map(|cause| cause.field.clone())
+            .collect::<Vec<_>>();
+        if denied_by.is_empty() {
+            return None;
+        }
+
+        let (namespace, target_path) = details.name.split_once('/')?;
+
+        Some(Self {
+            namespace: namespace.to_string(),
+            target_path: target_path.to_string(),
+            denied_by,
+        })
+    }
+}
+
+impl From<TargetNotAllowed> for Status {
+    /// `403 Forbidden` for `<namespace>/<target path>`, with one [`StatusCause`] for every
+    /// allowlist that denied the target.
+    fn from(denied: TargetNotAllowed) -> Self {
+        let message = denied.to_string();
+        let causes = denied
+            .denied_by
+            .into_iter()
+            .map(|allowlist| StatusCause {
+                reason: TARGET_NOT_ALLOWED_REASON.to_string(),
+                message: format!("not allowed by the target allowlist `{allowlist}`"),
+                field: allowlist,
+            })
+            .collect();
+
+        Status::failure(&message, "Forbidden")
+            .with_code(403)
+            .with_details(StatusDetails {
+                name: format!("{}/{}", denied.namespace, denied.target_path),
+                kind: "targets".to_string(),
+                causes,
+                ..Default::default()
+            })
+    }
+}
+
+/// Matches `value` with a `pattern` where `?` matches any character and `*` any (possibly empty)
+/// sequence of characters.
+pub(crate) fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let value = value.chars().collect::<Vec<_>>();
+
+    // Positions right after the last `*`, in the pattern and in the value, to backtrack to.
+    let mut star = None;
+    let (mut p, mut v) = (0, 0);
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || Some(&c) == value.get(v) => {
+                p += 1;
+                v += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character.
+                Some((star_p, star_v)) => {
+                    star = Some((star_p, star_v + 1));
+                    p = star_p;
+                    v = star_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern
+        .get(p..)
+        .is_some_and(|rest| rest.iter().all(|c| *c == '*'))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn allowlist(
+        name: &str,
+        subjects: Vec<AllowlistSubject>,
+        namespaces: &[&str],
+    ) -> MirrordTargetAllowlist {
+        MirrordTargetAllowlist::new(
+            name,
+            MirrordTargetAllowlistSpec {
+                subjects,
+                namespaces: namespaces.iter().map(ToString::to_string).collect(),
+                target_paths: None,
+            },
+        )
+    }
+
+    fn payments_dev() -> UserIdentity {
+        UserIdentity {
+            name: "alice".to_string(),
+            groups: vec!["team-payments".to_string()],
+            claims: BTreeMap::from([("department".to_string(), vec!["finance".to_string()])]),
+        }
+    }
+
+    #[rstest]
+    #[case("payments-*", "payments-eu", true)]
+    #[case("payments-*", "payments-", true)]
+    #[case("payments-*", "checkout", false)]
+    #[case("payments-??", "payments-eu", true)]
+    #[case("payments-??", "payments-eu1", false)]
+    #[case("*-prod", "payments-eu-prod", true)]
+    #[case("*", "", true)]
+    #[case("", "payments", false)]
+    fn wildcard(#[case] pattern: &str, #[case] value: &str, #[case] expected: bool) {
+        assert_eq!(wildcard_match(pattern, value), expected);
+    }
+
+    #[test]
+    fn subjects() {
+        let identity = payments_dev();
+
+        assert!(AllowlistSubject::User("alice".to_string()).matches(&identity));
+        assert!(AllowlistSubject::Group("team-payments".to_string()).matches(&identity));
+        assert!(AllowlistSubject::Claim {
+            name: "department".to_string(),
+            value: "finance".to_string(),
+        }
+        .matches(&identity));
+        assert!(!AllowlistSubject::Group("team-search".to_string()).matches(&identity));
+    }
+
+    #[test]
+    fn denied_by_applying_allowlists() {
+        let allowlists = [
+            allowlist(
+                "payments",
+                vec![AllowlistSubject::Group("team-payments".to_string())],
+                &["payments-*"],
+            ),
+            allowlist(
+                "search",
+                vec![AllowlistSubject::Group("team-search".to_string())],
+                &["search"],
+            ),
+        ];
+        let identity = payments_dev();
+
+        check_target_allowed(&allowlists, &identity, "payments-eu", "deploy/api").unwrap();
+
+        let denied =
+            check_target_allowed(&allowlists, &identity, "search", "deploy/api").unwrap_err();
+        assert_eq!(denied.denied_by, vec!["payments".to_string()]);
+
+        // Users with no allowlists are not restricted.
+        check_target_allowed(
+            &allowlists,
+            &UserIdentity::default(),
+            "search",
+            "deploy/api",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn status_roundtrip() {
+        let denied = TargetNotAllowed {
+            namespace: "search".to_string(),
+            target_path: "deploy/api".to_string(),
+            denied_by: vec!["payments".to_string(), "finance".to_string()],
+        };
+
+        let status = Status::from(denied.clone());
+        assert_eq!(status.code, 403);
+        assert_eq!(TargetNotAllowed::from_status(&status), Some(denied));
+
+        assert_eq!(
+            TargetNotAllowed::from_status(&Status::failure("forbidden", "Forbidden")),
+            None
+        );
+    }
+}
@@ -27,8 +27,11 @@ use kube::{CustomResourceExt, Resource};
 use thiserror::Error;
 
 use crate::crd::{
+    agent_pool::MirrordAgentPool,
     kafka::{MirrordKafkaClientConfig, MirrordKafkaEphemeralTopic, MirrordKafkaTopicsConsumer},
-    MirrordOperatorUser, MirrordPolicy, MirrordSqsSession, MirrordWorkloadQueueRegistry, TargetCrd,
+    target_allowlist::MirrordTargetAllowlist,
+    MirrordOperatorUser, MirrordPolicy, MirrordSqsSession, MirrordWorkloadQueueRegistry,
+    SessionShareCrd, TargetCrd,
 };
 
 pub static OPERATOR_NAME: &str = "mirrord-operator";
@@ -212,6 +215,12 @@ impl OperatorSetup for Operator {
         writer.write_all(b"---\n")?;
         MirrordPolicy::crd().to_writer(&mut writer)?;
 
+        writer.write_all(b"---\n")?;
+        MirrordTargetAllowlist::crd().to_writer(&mut writer)?;
+
+        writer.write_all(b"---\n")?;
+        MirrordAgentPool::crd().to_writer(&mut writer)?;
+
         if self.sqs_splitting {
             writer.write_all(b"---\n")?;
             MirrordWorkloadQueueRegistry::crd().to_writer(&mut writer)?;
@@ -526,6 +535,27 @@ impl OperatorRole {
                 verbs: vec!["list".to_owned(), "get".to_owned()],
                 ..Default::default()
             },
+            // Allow the operator to list+get the target allowlists of the users.
+            PolicyRule {
+                api_groups: Some(vec![MirrordTargetAllowlist::group(&()).into_owned()]),
+                resources: Some(vec![MirrordTargetAllowlist::plural(&()).into_owned()]),
+                verbs: vec!["list".to_owned(), "get".to_owned()],
+                ..Default::default()
+            },
+            // Allow the operator to watch the agent pools, to keep their pre-warmed agents.
+            PolicyRule {
+                api_groups: Some(vec![MirrordAgentPool::group(&()).into_owned()]),
+                resources: Some(vec![MirrordAgentPool::plural(&()).into_owned()]),
+                verbs: vec!["get".to_owned(), "list".to_owned(), "watch".to_owned()],
+                ..Default::default()
+            },
+            // Allow the operator to report the agents of the pools.
+            PolicyRule {
+                api_groups: Some(vec![MirrordAgentPool::group(&()).into_owned()]),
+                resources: Some(vec![format!("{}/status", MirrordAgentPool::plural(&()))]),
+                verbs: vec!["update".to_owned()],
+                ..Default::default()
+            },
         ];
 
         if sqs_splitting || kafka_splitting {
@@ -787,6 +817,8 @@ impl OperatorClusterUserRole {
                         "mirrordoperators".to_owned(),
                         "targets".to_owned(),
                         "targets/port-locks".to_owned(),
+                        "usages".to_owned(),
+                        SessionShareCrd::plural(&()).into_owned(),
                         MirrordOperatorUser::plural(&()).into_owned(),
                     ]),
                     verbs: vec!["get".to_owned(), "list".to_owned()],
@@ -797,19 +829,27 @@ impl OperatorClusterUserRole {
                     resources: Some(vec![
                         "mirrordoperators/certificate".to_owned(),
                         "copytargets".to_owned(),
+                        SessionShareCrd::plural(&()).into_owned(),
                     ]),
                     verbs: vec!["create".to_owned()],
                     ..Default::default()
                 },
                 PolicyRule {
                     api_groups: Some(vec!["operator.metalbear.co".to_owned()]),
-                    resources: Some(vec!["targets".to_owned(), "copytargets".to_owned()]),
+                    resources: Some(vec![
+                        "targets".to_owned(),
+                        "copytargets".to_owned(),
+                        SessionShareCrd::plural(&()).into_owned(),
+                    ]),
                     verbs: vec!["proxy".to_owned()],
                     ..Default::default()
                 },
                 PolicyRule {
                     api_groups: Some(vec!["operator.metalbear.co".to_owned()]),
-                    resources: Some(vec!["sessions".to_owned()]),
+                    resources: Some(vec![
+                        "sessions".to_owned(),
+                        SessionShareCrd::plural(&()).into_owned(),
+                    ]),
                     verbs: vec!["deletecollection".to_owned(), "delete".to_owned()],
                     ..Default::default()
                 },
@@ -36,3 +36,24 @@ pub const CLIENT_NAME_HEADER: &str = "x-client-name";
 /// Name of HTTP header containing operator session id.
 /// Sent with target connection request.
 pub const SESSION_ID_HEADER: &str = "x-session-id";
+
+/// Name of HTTP header containing the
+/// [`SessionShareStatus::token`](crate::crd::SessionShareStatus::token) of a shared session.
+/// Sent with the connection request that joins the session.
+pub const SESSION_SHARE_TOKEN_HEADER: &str = "x-session-share-token";
+
+/// Name of HTTP header about the [`MirrordAgentPool`](crate::crd::agent_pool::MirrordAgentPool)s.
+/// Sent with target connection request with [`AGENT_POOL_DISABLED`] when the user doesn't want a
+/// pre-warmed agent. In the operator response to it, contains the name of the pool the agent was
+/// claimed from.
+pub const AGENT_POOL_HEADER: &str = "x-agent-pool";
+
+/// Value of [`AGENT_POOL_HEADER`] in the target connection request, when the session should get a
+/// new agent.
+pub const AGENT_POOL_DISABLED: &str = "disabled";
+
+/// Prefix of HTTP headers containing
+/// [`SessionExtensions`](crate::client::extensions::SessionExtensions) entries, followed by the
+/// entry key.
+/// Sent with target connection request, and in the operator response to it.
+pub const EXTENSION_HEADER_PREFIX: &str = "x-mirrord-ext-";
@@ -1,8 +1,17 @@
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
+use console::style;
 use enum_dispatch::enum_dispatch;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{to_string, Value};
 
 pub mod messages;
@@ -22,6 +31,12 @@ pub trait Progress: Sized {
     /// Create a subtask report from this task.
     fn subtask(&self, text: &str) -> Self;
 
+    /// Create a subtask that reports the readiness of a feature of the session.
+    ///
+    /// Warnings issued on the group or any of its subtasks are attached to the group, and shown
+    /// when it's done.
+    fn group(&self, group: ProgressGroup) -> Self;
+
     /// When task is done successfully
     fn success(&mut self, msg: Option<&str>);
 
@@ -46,6 +61,77 @@ pub trait Progress: Sized {
     fn set_fail_on_drop(&mut self, fail: bool);
 }
 
+/// Features of a mirrord session that are initialized at startup, see [`Progress::group`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressGroup {
+    Agent,
+    Network,
+    Fs,
+    Env,
+    Operator,
+}
+
+impl ProgressGroup {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Agent => "agent",
+            Self::Network => "network",
+            Self::Fs => "fs",
+            Self::Env => "env",
+            Self::Operator => "operator",
+        }
+    }
+}
+
+impl fmt::Display for ProgressGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// State shared by a [`ProgressGroup`] task and all of its subtasks.
+#[derive(Debug, Clone)]
+struct GroupState {
+    group: ProgressGroup,
+    /// Warnings issued on the group or its subtasks.
+    warnings: Arc<AtomicUsize>,
+}
+
+impl GroupState {
+    fn new(group: ProgressGroup) -> Self {
+        Self {
+            group,
+            warnings: Default::default(),
+        }
+    }
+
+    fn add_warning(&self) {
+        self.warnings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Describes how long the group took to get ready, and how many warnings it got.
+    fn summary(&self, elapsed: Duration) -> String {
+        match self.warnings.load(Ordering::Relaxed) {
+            0 => format_elapsed(elapsed),
+            1 => format!("{}, 1 warning", format_elapsed(elapsed)),
+            warnings => format!("{}, {warnings} warnings", format_elapsed(elapsed)),
+        }
+    }
+
+    fn has_warnings(&self) -> bool {
+        self.warnings.load(Ordering::Relaxed) > 0
+    }
+}
+
+/// Formats the duration of a task, e.g. `250ms` or `1.2s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    if elapsed < Duration::from_secs(1) {
+        format!("{}ms", elapsed.as_millis())
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    }
+}
+
 /// `ProgressMode` specifies the way progress is reported by `TaskProgress`.
 // TODO: update outdated comment
 #[derive(Debug)]
@@ -71,6 +157,10 @@ impl Progress for NullProgress {
         NullProgress
     }
 
+    fn group(&self, _: ProgressGroup) -> NullProgress {
+        NullProgress
+    }
+
     fn set_fail_on_drop(&mut self, _: bool) {}
 
     fn success(&mut self, _: Option<&str>) {}
@@ -136,6 +226,11 @@ impl Progress for JsonProgress {
         task
     }
 
+    /// The IDEs show groups as regular tasks, named after the group.
+    fn group(&self, group: ProgressGroup) -> JsonProgress {
+        self.subtask(group.as_str())
+    }
+
     fn print(&self, _: &str) {}
 
     fn info(&self, msg: &str) {
@@ -190,20 +285,77 @@ impl Drop for JsonProgress {
     }
 }
 
+/// Plain text progress, one line per event, without colors or spinners.
+///
+/// Lines of a [`ProgressGroup`] are prefixed with the group name, e.g. `[agent] ...`.
 #[derive(Debug)]
-pub struct SimpleProgress;
+pub struct SimpleProgress {
+    done: bool,
+    fail_on_drop: bool,
+    started: Instant,
+    group: Option<GroupState>,
+    /// Whether this is the task created with [`Progress::group`].
+    is_group: bool,
+}
 
 impl SimpleProgress {
     fn new(text: &str) -> SimpleProgress {
         println!("{text}");
-        SimpleProgress {}
+        SimpleProgress {
+            done: false,
+            fail_on_drop: true,
+            started: Instant::now(),
+            group: None,
+            is_group: false,
+        }
+    }
+
+    fn println(&self, msg: &str) {
+        match &self.group {
+            Some(state) => println!("[{}] {msg}", state.group),
+            None => println!("{msg}"),
+        }
+    }
+
+    fn finish(&mut self, status: &str, msg: Option<&str>) {
+        self.done = true;
+
+        let elapsed = self.started.elapsed();
+        let summary = match &self.group {
+            Some(state) if self.is_group => state.summary(elapsed),
+            _ => format_elapsed(elapsed),
+        };
+
+        match msg {
+            Some(msg) => self.println(&format!("{status}: {msg} ({summary})")),
+            None => self.println(&format!("{status} ({summary})")),
+        }
     }
 }
 
 impl Progress for SimpleProgress {
     fn subtask(&self, text: &str) -> SimpleProgress {
-        println!("{text}");
-        SimpleProgress {}
+        let task = SimpleProgress {
+            done: false,
+            fail_on_drop: true,
+            started: Instant::now(),
+            group: self.group.clone(),
+            is_group: false,
+        };
+        task.println(text);
+        task
+    }
+
+    fn group(&self, group: ProgressGroup) -> SimpleProgress {
+        let task = SimpleProgress {
+            done: false,
+            fail_on_drop: true,
+            started: Instant::now(),
+            group: Some(GroupState::new(group)),
+            is_group: true,
+        };
+        task.println("initializing");
+        task
     }
 
     fn print(&self, text: &str) {
@@ -211,28 +363,49 @@ impl Progress for SimpleProgress {
     }
 
     fn warning(&self, msg: &str) {
-        println!("{msg}");
+        if let Some(state) = &self.group {
+            state.add_warning();
+        }
+        self.println(&format!("warning: {msg}"));
     }
 
     fn info(&self, msg: &str) {
-        println!("{msg}");
+        self.println(msg);
     }
 
     fn ide(&self, _: serde_json::Value) {}
 
     fn failure(&mut self, msg: Option<&str>) {
-        println!("{msg:?}");
+        self.finish("failed", msg);
     }
 
     fn success(&mut self, msg: Option<&str>) {
-        println!("{msg:?}");
+        let status = if self.is_group { "ready" } else { "done" };
+        self.finish(status, msg);
     }
 
-    fn set_fail_on_drop(&mut self, _: bool) {}
+    fn set_fail_on_drop(&mut self, fail: bool) {
+        self.fail_on_drop = fail;
+    }
+}
+
+impl Drop for SimpleProgress {
+    fn drop(&mut self) {
+        if !self.done {
+            if self.fail_on_drop {
+                self.failure(None);
+            } else {
+                self.success(None);
+            }
+        }
+    }
 }
 
 fn spinner_template(indent: usize) -> String {
-    format!("{indent}{{spinner}} {{msg}}", indent = "  ".repeat(indent))
+    format!(
+        "{indent}{{spinner:.cyan}} {{msg}}",
+        indent = "  ".repeat(indent)
+    )
 }
 
 fn spinner(indent: usize) -> ProgressBar {
@@ -250,6 +423,10 @@ pub struct SpinnerProgress {
     root_progress: MultiProgress,
     progress: ProgressBar,
     indent: usize,
+    started: Instant,
+    group: Option<GroupState>,
+    /// Whether this is the task created with [`Progress::group`].
+    is_group: bool,
 }
 
 impl SpinnerProgress {
@@ -266,15 +443,16 @@ impl SpinnerProgress {
             indent: 0,
             root_progress,
             progress,
+            started: Instant::now(),
+            group: None,
+            is_group: false,
         }
     }
-}
 
-impl Progress for SpinnerProgress {
-    fn subtask(&self, text: &str) -> SpinnerProgress {
+    fn child(&self, text: String, group: Option<GroupState>, is_group: bool) -> SpinnerProgress {
         let indent = self.indent + 1;
         let progress = spinner(indent);
-        progress.set_message(text.to_string());
+        progress.set_message(text);
         self.root_progress.add(progress.clone());
         progress.enable_steady_tick(Duration::from_millis(60));
         SpinnerProgress {
@@ -283,21 +461,69 @@ impl Progress for SpinnerProgress {
             root_progress: self.root_progress.clone(),
             indent,
             progress,
+            started: Instant::now(),
+            group,
+            is_group,
+        }
+    }
+
+    /// Message shown when the task is done, e.g. `agent: ready (1.2s, 1 warning)` for a group.
+    fn finished_message(&self, msg: Option<&str>, default: &str) -> String {
+        let elapsed = self.started.elapsed();
+
+        match &self.group {
+            Some(state) if self.is_group => format!(
+                "{}: {} {}",
+                style(state.group).bold().for_stderr(),
+                msg.unwrap_or(default),
+                style(format!("({})", state.summary(elapsed)))
+                    .dim()
+                    .for_stderr(),
+            ),
+            _ => format!(
+                "{} {}",
+                msg.map(ToString::to_string)
+                    .unwrap_or_else(|| self.progress.message()),
+                style(format!("({})", format_elapsed(elapsed)))
+                    .dim()
+                    .for_stderr(),
+            ),
         }
     }
+}
+
+impl Progress for SpinnerProgress {
+    fn subtask(&self, text: &str) -> SpinnerProgress {
+        self.child(text.to_string(), self.group.clone(), false)
+    }
+
+    fn group(&self, group: ProgressGroup) -> SpinnerProgress {
+        let text = format!("{} initializing...", style(group).bold().for_stderr());
+        self.child(text, Some(GroupState::new(group)), true)
+    }
 
     fn print(&self, msg: &str) {
         let _ = self.root_progress.println(msg);
     }
 
     fn warning(&self, msg: &str) {
-        let formatted_message = format!("! {msg}");
+        let formatted_message = match &self.group {
+            Some(state) => {
+                state.add_warning();
+                format!(
+                    "{} [{}] {msg}",
+                    style("!").yellow().for_stderr(),
+                    state.group
+                )
+            }
+            None => format!("{} {msg}", style("!").yellow().for_stderr()),
+        };
         self.print(&formatted_message);
         self.progress.set_message(formatted_message);
     }
 
     fn info(&self, msg: &str) {
-        let formatted_message = format!("* {msg}");
+        let formatted_message = format!("{} {msg}", style("*").blue().for_stderr());
         self.print(&formatted_message);
         self.progress.set_message(formatted_message);
     }
@@ -306,22 +532,24 @@ impl Progress for SpinnerProgress {
 
     fn failure(&mut self, msg: Option<&str>) {
         self.done = true;
-        if let Some(msg) = msg {
-            self.progress.abandon_with_message(format!("x {msg}"));
-        } else {
-            self.progress
-                .abandon_with_message(format!("x {}", self.progress.message()));
-        }
+        let message = self.finished_message(msg, "failed");
+        self.progress.abandon_with_message(format!(
+            "{} {message}",
+            style("x").red().bold().for_stderr()
+        ));
     }
 
     fn success(&mut self, msg: Option<&str>) {
         self.done = true;
-        if let Some(msg) = msg {
-            self.progress.finish_with_message(format!("✓ {msg}"));
-        } else {
-            self.progress
-                .finish_with_message(format!("✓ {}", self.progress.message()));
-        }
+        let message = self.finished_message(msg, "ready");
+        let symbol = match &self.group {
+            Some(state) if self.is_group && state.has_warnings() => {
+                style("!").yellow().bold().for_stderr()
+            }
+            _ => style("✓").green().bold().for_stderr(),
+        };
+        self.progress
+            .finish_with_message(format!("{symbol} {message}"));
     }
 
     fn set_fail_on_drop(&mut self, fail: bool) {
@@ -350,7 +578,7 @@ impl ProgressTracker {
     /// Get the progress tracker from environment.
     pub fn try_from_env(text: &str) -> Option<Self> {
         let progress = match std::env::var(MIRRORD_PROGRESS_ENV).as_deref() {
-            Ok("dumb" | "simple") => SimpleProgress::new(text).into(),
+            Ok("dumb" | "simple" | "plain") => SimpleProgress::new(text).into(),
             Ok("json") => JsonProgress::new(text).into(),
             Ok("off") => NullProgress.into(),
             Ok("std" | "standard") => SpinnerProgress::new(text).into(),
@@ -362,30 +590,30 @@ impl ProgressTracker {
 }
 
 /// Message sent when a new task is created using subtask/new
-#[derive(Serialize, Debug, Clone, Default)]
-struct NewTaskMessage {
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NewTaskMessage {
     /// Task name (indentifier)
-    name: String,
+    pub name: String,
     /// Parent task name, if subtask.
-    parent: Option<String>,
+    pub parent: Option<String>,
 }
 
 /// Message sent when a task is finished.
-#[derive(Serialize, Debug, Clone, Default)]
-struct FinishedTaskMessage {
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FinishedTaskMessage {
     /// Finished task name
-    name: String,
+    pub name: String,
     /// Was the task successful?
-    success: bool,
+    pub success: bool,
     /// Finish message
-    message: Option<String>,
+    pub message: Option<String>,
 }
 
 /// Message sent when a task is finished.
-#[derive(Serialize, Debug, Clone, Default)]
-struct WarningMessage {
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WarningMessage {
     /// Warning message
-    message: String,
+    pub message: String,
 }
 
 /// Indicates what type of notification should appear in the IDEs.
@@ -429,10 +657,11 @@ pub struct IdeMessage {
 
 /// The message types that we report on [`Progress`].
 ///
-/// These are used by the extensions (vscode and intellij) to show nice notifications.
-#[derive(Serialize, Debug, Clone)]
+/// These are used by the extensions (vscode and intellij) to show nice notifications, and parsed
+/// back by `mirrord-sdk`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
-enum ProgressMessage {
+pub enum ProgressMessage {
     NewTask(NewTaskMessage),
     Warning(WarningMessage),
     FinishedTask(FinishedTaskMessage),
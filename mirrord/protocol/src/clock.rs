@@ -0,0 +1,44 @@
+//! Comparing the clock of the agent with the local one.
+//!
+//! Peers that support [`CLOCK_PROBE_VERSION`] can send
+//! [`ClientMessage::ClockProbe`](crate::ClientMessage::ClockProbe), and the agent responds with
+//! [`DaemonMessage::ClockProbeResponse`](crate::DaemonMessage::ClockProbeResponse), carrying its
+//! time at the moment it handled the probe. The client can then estimate the offset between the
+//! clocks, assuming that the probe and the response took the same time on the wire.
+use std::{
+    sync::LazyLock,
+    time::{Duration, SystemTime},
+};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::ClockProbe`](crate::ClientMessage::ClockProbe).
+pub static CLOCK_PROBE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.22.0".parse().expect("Bad Identifier"));
+
+/// Response to [`ClientMessage::ClockProbe`](crate::ClientMessage::ClockProbe).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ClockProbeResponse {
+    /// Time of the agent when it handled the probe, in nanoseconds since the UNIX epoch.
+    pub unix_time_nanos: u64,
+}
+
+impl ClockProbeResponse {
+    /// Reads the current time of this machine.
+    pub fn now() -> Self {
+        let unix_time_nanos = SystemTime::UNIX_EPOCH
+            .elapsed()
+            .map(|elapsed| u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX))
+            .unwrap_or_default();
+
+        Self { unix_time_nanos }
+    }
+
+    /// Time of the agent, as a [`SystemTime`].
+    pub fn time(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(self.unix_time_nanos)
+    }
+}
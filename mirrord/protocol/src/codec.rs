@@ -12,25 +12,35 @@ use mirrord_macros::protocol_break;
 use semver::VersionReq;
 
 use crate::{
-    dns::{GetAddrInfoRequest, GetAddrInfoResponse},
+    clock::ClockProbeResponse,
+    compression::{self, CompressedMessage, Compressible, CompressionSettings},
+    dns::{GetAddrInfoRequest, GetAddrInfoRequestV2, GetAddrInfoResponse},
+    fd_budget::{CloseFdsRequest, FdUsage, FdUsageRequest, IdleFds},
     file::*,
+    framing::{FrameLimits, Framing, FramingSwitch, FRAME_HEADER_SIZE},
+    interfaces::{GetIfAddrsRequest, GetIfAddrsResponse},
     outgoing::{
         tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
         udp::{DaemonUdpOutgoing, LayerUdpOutgoing},
     },
+    output::OutputMessage,
     pause::DaemonPauseTarget,
+    reachability::{ReachabilityRequest, ReachabilityResponse},
     tcp::{DaemonTcp, LayerTcp, LayerTcpSteal},
     vpn::{ClientVpn, ServerVpn},
+    watch::{DaemonWatch, LayerWatch},
     ResponseError,
 };
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum LogLevel {
     Warn,
     Error,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct LogMessage {
     pub message: String,
     pub level: LogLevel,
@@ -53,12 +63,14 @@ impl LogMessage {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct GetEnvVarsRequest {
     pub env_vars_filter: HashSet<String>,
     pub env_vars_select: HashSet<String>,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum FileRequest {
     Open(OpenFileRequest),
     OpenRelative(OpenRelativeFileRequest),
@@ -83,14 +95,249 @@ pub enum FileRequest {
     /// only. [`ReadDirRequest`]s that come from the layer are transformed into this
     /// batched form when the protocol version supports it. See [`READDIR_BATCH_VERSION`].
     ReadDirBatch(ReadDirBatchRequest),
+
+    /// Sent once by the intproxy, when the agent supports
+    /// [`SCRATCH_DIR_VERSION`](crate::file::SCRATCH_DIR_VERSION).
+    ScratchDir(ScratchDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`UNLINK_VERSION`](crate::file::UNLINK_VERSION).
+    Unlink(UnlinkFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`UNLINK_VERSION`](crate::file::UNLINK_VERSION).
+    UnlinkAt(UnlinkFileWithDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`RENAME_VERSION`](crate::file::RENAME_VERSION).
+    Rename(RenameFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`RENAME_VERSION`](crate::file::RENAME_VERSION).
+    RenameAt(RenameFileWithDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`CHMOD_VERSION`](crate::file::CHMOD_VERSION).
+    Chmod(ChmodFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`CHMOD_VERSION`](crate::file::CHMOD_VERSION).
+    Fchmod(FchmodFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`CHMOD_VERSION`](crate::file::CHMOD_VERSION).
+    ChmodAt(ChmodFileWithDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`OPENAT2_VERSION`](crate::file::OPENAT2_VERSION).
+    OpenAt2(OpenAt2Request),
+
+    /// Should only be sent to agents that support
+    /// [`CHOWN_VERSION`](crate::file::CHOWN_VERSION).
+    Chown(ChownFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`CHOWN_VERSION`](crate::file::CHOWN_VERSION).
+    Fchown(FchownFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`CHOWN_VERSION`](crate::file::CHOWN_VERSION).
+    ChownAt(ChownFileWithDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`READ_WHOLE_FILE_VERSION`](crate::file::READ_WHOLE_FILE_VERSION).
+    ReadWhole(ReadWholeFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`TRUNCATE_VERSION`](crate::file::TRUNCATE_VERSION).
+    Truncate(TruncateFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`TRUNCATE_VERSION`](crate::file::TRUNCATE_VERSION).
+    Ftruncate(FtruncateFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`GLOB_VERSION`](crate::file::GLOB_VERSION).
+    Glob(GlobRequest),
+
+    /// Should only be sent to agents that support
+    /// [`SYMLINK_VERSION`](crate::file::SYMLINK_VERSION).
+    Symlink(SymlinkRequest),
+
+    /// Should only be sent to agents that support
+    /// [`SYMLINK_VERSION`](crate::file::SYMLINK_VERSION).
+    SymlinkAt(SymlinkAtRequest),
+
+    /// Should only be sent to agents that support
+    /// [`LINK_VERSION`](crate::file::LINK_VERSION).
+    Link(LinkFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`LINK_VERSION`](crate::file::LINK_VERSION).
+    LinkAt(LinkFileWithDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`UTIMENS_VERSION`](crate::file::UTIMENS_VERSION).
+    UtimensAt(UtimensFileWithDirRequest),
+
+    /// Should only be sent to agents that support
+    /// [`UTIMENS_VERSION`](crate::file::UTIMENS_VERSION).
+    Futimens(FutimensFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`FSYNC_VERSION`](crate::file::FSYNC_VERSION).
+    Fsync(FsyncFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`STATX_VERSION`](crate::file::STATX_VERSION).
+    Statx(StatxRequest),
+
+    /// Should only be sent to agents that support
+    /// [`XATTR_VERSION`](crate::file::XATTR_VERSION).
+    GetXattr(GetXattrRequest),
+
+    /// Should only be sent to agents that support
+    /// [`XATTR_VERSION`](crate::file::XATTR_VERSION).
+    SetXattr(SetXattrRequest),
+
+    /// Should only be sent to agents that support
+    /// [`XATTR_VERSION`](crate::file::XATTR_VERSION).
+    ListXattr(ListXattrRequest),
+
+    /// Should only be sent to agents that support
+    /// [`XATTR_VERSION`](crate::file::XATTR_VERSION).
+    RemoveXattr(RemoveXattrRequest),
+
+    /// Should only be sent to agents that support
+    /// [`LOCK_VERSION`](crate::file::LOCK_VERSION).
+    Flock(FlockRequest),
+
+    /// Should only be sent to agents that support
+    /// [`LOCK_VERSION`](crate::file::LOCK_VERSION).
+    SetLock(SetLockRequest),
+
+    /// Should only be sent to agents that support
+    /// [`LOCK_VERSION`](crate::file::LOCK_VERSION).
+    GetLock(GetLockRequest),
+
+    /// Should only be sent to agents that support
+    /// [`FALLOCATE_VERSION`](crate::file::FALLOCATE_VERSION).
+    Fallocate(FallocateFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`COPY_FILE_RANGE_VERSION`](crate::file::COPY_FILE_RANGE_VERSION).
+    CopyFileRange(CopyFileRangeRequest),
+
+    /// Should only be sent to agents that support
+    /// [`VECTORED_IO_VERSION`](crate::file::VECTORED_IO_VERSION).
+    ReadV(ReadVFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`VECTORED_IO_VERSION`](crate::file::VECTORED_IO_VERSION).
+    WriteV(WriteVFileRequest),
+
+    /// Should only be sent to agents that support
+    /// [`READ_STREAM_VERSION`](crate::file::READ_STREAM_VERSION).
+    ///
+    /// Unlike the other requests, the agent responds with many [`FileResponse::ReadChunk`]s.
+    ReadStream(ReadStreamFileRequest),
+
+    /// Lists a directory, like [`FileRequest::ReadDirBatch`] does, but only sends the changes
+    /// since a previous listing, see [`ReadDirDeltaRequest`].
+    ///
+    /// Intproxy only, and should only be sent to agents that support
+    /// [`READ_DIR_DELTA_VERSION`](crate::file::READ_DIR_DELTA_VERSION).
+    ReadDirDelta(ReadDirDeltaRequest),
+
+    /// Should only be sent to agents that support
+    /// [`CANONICALIZE_VERSION`](crate::file::CANONICALIZE_VERSION).
+    Canonicalize(CanonicalizePathRequest),
+
+    /// Should only be sent to agents that support
+    /// [`STATFS_PATH_VERSION`](crate::file::STATFS_PATH_VERSION).
+    StatFs(StatFsRequest),
+
+    /// Intproxy only, and should only be sent to agents that support
+    /// [`FOLLOW_VERSION`](crate::file::FOLLOW_VERSION).
+    ///
+    /// Unlike the other requests, the agent responds with [`FileResponse::FollowChunk`]s as
+    /// long as the file is followed.
+    Follow(FollowFileRequest),
+
+    /// Intproxy only, and should only be sent to agents that support
+    /// [`FOLLOW_VERSION`](crate::file::FOLLOW_VERSION).
+    ///
+    /// Gets no response.
+    Unfollow(UnfollowFileRequest),
+
+    /// Lists a directory, like [`FileRequest::ReadDirBatch`] does, with the metadata of the
+    /// entries.
+    ///
+    /// Intproxy only, and should only be sent to agents that support
+    /// [`READ_DIR_PLUS_VERSION`](crate::file::READ_DIR_PLUS_VERSION).
+    ReadDirPlusBatch(ReadDirPlusBatchRequest),
+
+    /// Intproxy only, and should only be sent to agents that support
+    /// [`PREFETCH_TREE_VERSION`](crate::file::PREFETCH_TREE_VERSION).
+    ///
+    /// Unlike the other requests, the agent responds with [`FileResponse::PrefetchChunk`]s until
+    /// it walked the whole tree.
+    PrefetchTree(PrefetchTreeRequest),
+}
+
+impl FileRequest {
+    /// Whether this request would modify the remote filesystem.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Self::Write(..)
+            | Self::WriteLimited(..)
+            | Self::ScratchDir(..)
+            | Self::Unlink(..)
+            | Self::UnlinkAt(..)
+            | Self::Rename(..)
+            | Self::RenameAt(..)
+            | Self::Chmod(..)
+            | Self::Fchmod(..)
+            | Self::ChmodAt(..)
+            | Self::Chown(..)
+            | Self::Fchown(..)
+            | Self::ChownAt(..)
+            | Self::Truncate(..)
+            | Self::Ftruncate(..)
+            | Self::Symlink(..)
+            | Self::SymlinkAt(..)
+            | Self::Link(..)
+            | Self::LinkAt(..)
+            | Self::UtimensAt(..)
+            | Self::Futimens(..)
+            | Self::SetXattr(..)
+            | Self::RemoveXattr(..)
+            | Self::Fallocate(..)
+            | Self::CopyFileRange(..)
+            | Self::WriteV(..) => true,
+            Self::Open(OpenFileRequest { open_options, .. })
+            | Self::OpenRelative(OpenRelativeFileRequest { open_options, .. })
+            | Self::OpenAt2(OpenAt2Request { open_options, .. }) => !open_options.is_read_only(),
+            _ => false,
+        }
+    }
 }
 
 /// Minimal mirrord-protocol version that allows `ClientMessage::ReadyForLogs` message.
 pub static CLIENT_READY_FOR_LOGS: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.3.1".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`ClientMessage::ReadOnly`].
+pub static READ_ONLY_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.23.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ClientMessage::FreezeTarget`].
+pub static FREEZE_TARGET_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.25.0".parse().expect("Bad Identifier"));
+
 /// `-layer` --> `-agent` messages.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ClientMessage {
     Close,
     Tcp(LayerTcp),
@@ -103,15 +350,136 @@ pub enum ClientMessage {
     GetAddrInfoRequest(GetAddrInfoRequest),
     /// Whether to pause or unpause the target container.
     PauseTargetRequest(bool),
-    SwitchProtocolVersion(#[bincode(with_serde)] semver::Version),
+    SwitchProtocolVersion(
+        #[bincode(with_serde)]
+        #[cfg_attr(
+            any(test, feature = "testing"),
+            proptest(strategy = "crate::testing::semver_version()")
+        )]
+        semver::Version,
+    ),
     ReadyForLogs,
     Vpn(ClientVpn),
+    /// Like [`ClientMessage::GetAddrInfoRequest`], but carries the `getaddrinfo` hints.
+    ///
+    /// Should only be sent to agents that support
+    /// [`ADDRINFO_V2_VERSION`](crate::dns::ADDRINFO_V2_VERSION).
+    GetAddrInfoRequestV2(GetAddrInfoRequestV2),
+    /// Output of the local process, to be written to the output of the target.
+    ///
+    /// Should only be sent to agents that support
+    /// [`OUTPUT_VERSION`](crate::output::OUTPUT_VERSION).
+    Output(OutputMessage),
+    /// Switches the messages sent after this one to length-prefixed frames, see
+    /// [`framing`](crate::framing).
+    ///
+    /// Should only be sent to agents that support
+    /// [`FRAMING_VERSION`](crate::framing::FRAMING_VERSION).
+    SwitchFraming(FrameLimits),
+    /// Asks for the current time of the agent, see [`clock`](crate::clock).
+    ///
+    /// Should only be sent to agents that support
+    /// [`CLOCK_PROBE_VERSION`](crate::clock::CLOCK_PROBE_VERSION).
+    ClockProbe,
+    /// Makes the rest of this client's session read-only: the agent rejects the steal
+    /// subscriptions and the file operations that would modify the remote filesystem, and drops
+    /// the output of the local process. Can't be reverted.
+    ///
+    /// Should only be sent to agents that support [`READ_ONLY_VERSION`].
+    ReadOnly,
+    /// Asks the agent to connect to the given destinations, see
+    /// [`reachability`](crate::reachability).
+    ///
+    /// Should only be sent to agents that support
+    /// [`REACHABILITY_CHECK_VERSION`](crate::reachability::REACHABILITY_CHECK_VERSION).
+    CheckReachability(ReachabilityRequest),
+    /// Makes the agent freeze the target container while this client steals all traffic from
+    /// any port (with [`StealType::All`](crate::tcp::StealType::All)), so that the remote
+    /// replica does not do the same background work as the local process. The container is
+    /// thawed when the client unsubscribes from the last such port, or exits.
+    ///
+    /// Should only be sent to agents that support [`FREEZE_TARGET_VERSION`].
+    FreezeTarget,
+    /// Switches on the compression of the file payloads in both directions, see
+    /// [`compression`](crate::compression).
+    ///
+    /// Should only be sent to agents that support
+    /// [`COMPRESSION_VERSION`](crate::compression::COMPRESSION_VERSION).
+    SwitchCompression(CompressionSettings),
+    /// A compressed message, unwrapped by the codec.
+    ///
+    /// Only sent after [`ClientMessage::SwitchCompression`].
+    #[cfg_attr(any(test, feature = "testing"), proptest(skip))]
+    Compressed(CompressedMessage),
+    /// Asks for the addresses of the network interfaces of the target, see
+    /// [`interfaces`](crate::interfaces).
+    ///
+    /// Should only be sent to agents that support
+    /// [`GET_IFADDRS_VERSION`](crate::interfaces::GET_IFADDRS_VERSION).
+    GetIfAddrsRequest(GetIfAddrsRequest),
+    /// Asks for the remote descriptors that the agent keeps open for this client, see
+    /// [`fd_budget`](crate::fd_budget).
+    ///
+    /// Should only be sent to agents that support
+    /// [`FD_BUDGET_VERSION`](crate::fd_budget::FD_BUDGET_VERSION).
+    FdUsageRequest(FdUsageRequest),
+    /// Watches remote paths, see [`watch`](crate::watch).
+    ///
+    /// Should only be sent to agents that support
+    /// [`WATCH_VERSION`](crate::watch::WATCH_VERSION).
+    Watch(LayerWatch),
+    /// Closes the remote descriptors that the local app leaked, see
+    /// [`DaemonMessage::IdleFds`].
+    ///
+    /// Should only be sent to agents that support
+    /// [`FD_GC_VERSION`](crate::fd_budget::FD_GC_VERSION).
+    CloseFds(CloseFdsRequest),
+}
+
+impl FramingSwitch for ClientMessage {
+    fn framing_switch(&self) -> Option<FrameLimits> {
+        match self {
+            Self::SwitchFraming(limits) => Some(*limits),
+            _ => None,
+        }
+    }
+}
+
+impl Compressible for ClientMessage {
+    fn compression_switch(&self) -> Option<CompressionSettings> {
+        match self {
+            Self::SwitchCompression(settings) => Some(*settings),
+            _ => None,
+        }
+    }
+
+    fn payload_size(&self) -> Option<usize> {
+        match self {
+            Self::FileRequest(FileRequest::Write(request)) => Some(request.write_bytes.len()),
+            Self::FileRequest(FileRequest::WriteLimited(request)) => {
+                Some(request.write_bytes.len())
+            }
+            _ => None,
+        }
+    }
+
+    fn compressed(message: CompressedMessage) -> Self {
+        Self::Compressed(message)
+    }
+
+    fn into_compressed(self) -> Result<CompressedMessage, Self> {
+        match self {
+            Self::Compressed(message) => Ok(message),
+            other => Err(other),
+        }
+    }
 }
 
 /// Type alias for `Result`s that should be returned from mirrord-agent to mirrord-layer.
 pub type RemoteResult<T> = Result<T, ResponseError>;
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum FileResponse {
     Open(RemoteResult<OpenFileResponse>),
     Read(RemoteResult<ReadFileResponse>),
@@ -127,10 +495,56 @@ pub enum FileResponse {
     GetDEnts64(RemoteResult<GetDEnts64Response>),
     ReadLink(RemoteResult<ReadLinkFileResponse>),
     ReadDirBatch(RemoteResult<ReadDirBatchResponse>),
+    /// Response to both [`FileRequest::Unlink`] and [`FileRequest::UnlinkAt`].
+    Unlink(RemoteResult<()>),
+    /// Response to both [`FileRequest::Rename`] and [`FileRequest::RenameAt`].
+    Rename(RemoteResult<()>),
+    /// Response to [`FileRequest::Chmod`], [`FileRequest::Fchmod`] and [`FileRequest::ChmodAt`].
+    Chmod(RemoteResult<()>),
+    /// Response to [`FileRequest::Chown`], [`FileRequest::Fchown`] and [`FileRequest::ChownAt`].
+    Chown(RemoteResult<()>),
+    ReadWhole(RemoteResult<ReadWholeFileResponse>),
+    /// Response to [`FileRequest::Truncate`] and [`FileRequest::Ftruncate`].
+    Truncate(RemoteResult<()>),
+    Glob(RemoteResult<GlobResponse>),
+    /// Response to both [`FileRequest::Symlink`] and [`FileRequest::SymlinkAt`].
+    Symlink(RemoteResult<()>),
+    /// Response to both [`FileRequest::Link`] and [`FileRequest::LinkAt`].
+    Link(RemoteResult<()>),
+    /// Response to both [`FileRequest::UtimensAt`] and [`FileRequest::Futimens`].
+    Utimens(RemoteResult<()>),
+    /// Response to [`FileRequest::Fsync`].
+    Fsync(RemoteResult<()>),
+    Statx(RemoteResult<StatxResponse>),
+    GetXattr(RemoteResult<GetXattrResponse>),
+    /// Response to [`FileRequest::SetXattr`].
+    SetXattr(RemoteResult<()>),
+    ListXattr(RemoteResult<ListXattrResponse>),
+    /// Response to [`FileRequest::RemoveXattr`].
+    RemoveXattr(RemoteResult<()>),
+    /// Response to both [`FileRequest::Flock`] and [`FileRequest::SetLock`].
+    Lock(RemoteResult<()>),
+    GetLock(RemoteResult<GetLockResponse>),
+    /// Response to [`FileRequest::Fallocate`].
+    Fallocate(RemoteResult<()>),
+    CopyFileRange(RemoteResult<CopyFileRangeResponse>),
+    ReadV(RemoteResult<ReadVFileResponse>),
+    /// Response to [`FileRequest::WriteV`].
+    WriteV(RemoteResult<WriteFileResponse>),
+    /// Response to [`FileRequest::ReadStream`], see [`ReadChunkResponse`].
+    ReadChunk(RemoteResult<ReadChunkResponse>),
+    ReadDirDelta(RemoteResult<ReadDirDeltaResponse>),
+    Canonicalize(RemoteResult<CanonicalizePathResponse>),
+    /// Sent while following a file, see [`FileRequest::Follow`].
+    FollowChunk(FollowChunkResponse),
+    ReadDirPlusBatch(RemoteResult<ReadDirPlusBatchResponse>),
+    /// Sent while walking a tree, see [`FileRequest::PrefetchTree`].
+    PrefetchChunk(PrefetchChunkResponse),
 }
 
 /// `-agent` --> `-layer` messages.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 #[protocol_break(2)]
 pub enum DaemonMessage {
     /// Kills the intproxy, no guarantee that messages that were sent before a `Close` will be
@@ -148,12 +562,88 @@ pub enum DaemonMessage {
     GetAddrInfoResponse(GetAddrInfoResponse),
     /// Pause is deprecated but we don't want to break protocol
     PauseTarget(DaemonPauseTarget),
-    SwitchProtocolVersionResponse(#[bincode(with_serde)] semver::Version),
+    SwitchProtocolVersionResponse(
+        #[bincode(with_serde)]
+        #[cfg_attr(
+            any(test, feature = "testing"),
+            proptest(strategy = "crate::testing::semver_version()")
+        )]
+        semver::Version,
+    ),
     Vpn(ServerVpn),
+    /// Response to [`ClientMessage::SwitchFraming`], switches the messages sent after this one to
+    /// length-prefixed frames.
+    SwitchFramingResponse(FrameLimits),
+    /// Response to [`ClientMessage::ClockProbe`].
+    ClockProbeResponse(ClockProbeResponse),
+    /// Response to [`ClientMessage::CheckReachability`].
+    ReachabilityResponse(ReachabilityResponse),
+    /// A compressed message, unwrapped by the codec.
+    ///
+    /// Only sent after [`ClientMessage::SwitchCompression`].
+    #[cfg_attr(any(test, feature = "testing"), proptest(skip))]
+    Compressed(CompressedMessage),
+    /// Response to [`ClientMessage::GetIfAddrsRequest`].
+    GetIfAddrsResponse(GetIfAddrsResponse),
+    /// Response to [`ClientMessage::FdUsageRequest`].
+    FdUsage(FdUsage),
+    /// Responses to [`ClientMessage::Watch`], and the events of the watches.
+    Watch(DaemonWatch),
+    /// The remote descriptors of the client that were not used for a while, see
+    /// [`fd_budget`](crate::fd_budget).
+    ///
+    /// Only sent to clients that support [`FD_GC_VERSION`](crate::fd_budget::FD_GC_VERSION).
+    IdleFds(IdleFds),
+}
+
+impl FramingSwitch for DaemonMessage {
+    fn framing_switch(&self) -> Option<FrameLimits> {
+        match self {
+            Self::SwitchFramingResponse(limits) => Some(*limits),
+            _ => None,
+        }
+    }
+}
+
+impl Compressible for DaemonMessage {
+    /// The compression is switched on by the client only.
+    fn compression_switch(&self) -> Option<CompressionSettings> {
+        None
+    }
+
+    fn payload_size(&self) -> Option<usize> {
+        match self {
+            Self::File(FileResponse::Read(Ok(response)))
+            | Self::File(FileResponse::ReadLimited(Ok(response))) => Some(response.bytes.len()),
+            Self::File(FileResponse::ReadChunk(Ok(response))) => Some(response.bytes.len()),
+            _ => None,
+        }
+    }
+
+    fn compressed(message: CompressedMessage) -> Self {
+        Self::Compressed(message)
+    }
+
+    fn into_compressed(self) -> Result<CompressedMessage, Self> {
+        match self {
+            Self::Compressed(message) => Ok(message),
+            other => Err(other),
+        }
+    }
 }
 
 pub struct ProtocolCodec<I, O> {
     config: bincode::config::Configuration,
+    /// Limits of the frames that this codec decodes, once the peer switches to framed messages.
+    limits: FrameLimits,
+    /// Framing of the decoded messages.
+    incoming: Framing,
+    /// Framing of the encoded messages.
+    outgoing: Framing,
+    /// Limits of the peer, known once it switches to framed messages.
+    peer_limits: Option<FrameLimits>,
+    /// Compression of the encoded messages, set once the client switches it on.
+    compression: Option<CompressionSettings>,
     /// Phantom fields to make this struct generic over message types.
     _phantom_incoming_message: PhantomData<I>,
     _phantom_outgoing_message: PhantomData<O>,
@@ -170,40 +660,222 @@ impl<I, O> Default for ProtocolCodec<I, O> {
     fn default() -> Self {
         Self {
             config: bincode::config::standard(),
+            limits: Default::default(),
+            incoming: Default::default(),
+            outgoing: Default::default(),
+            peer_limits: None,
+            compression: None,
             _phantom_incoming_message: Default::default(),
             _phantom_outgoing_message: Default::default(),
         }
     }
 }
 
-impl<I: bincode::Decode, O> Decoder for ProtocolCodec<I, O> {
+impl<I, O> ProtocolCodec<I, O> {
+    /// Sets the limits of the frames that this codec accepts, sent to the peer in the
+    /// [`FramingSwitch`] message.
+    pub fn with_limits(mut self, limits: FrameLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Limits of the frames that this codec accepts.
+    pub fn limits(&self) -> FrameLimits {
+        self.limits
+    }
+
+    /// Limits of the frames that the peer accepts, if it already switched to framed messages.
+    pub fn peer_limits(&self) -> Option<FrameLimits> {
+        self.peer_limits
+    }
+
+    /// Settings of the compression, if it was switched on.
+    pub fn compression(&self) -> Option<CompressionSettings> {
+        self.compression
+    }
+
+    /// Decodes the message compressed in `message`, which can't be compressed again.
+    fn decode_compressed<T: bincode::Decode + Compressible>(
+        &self,
+        message: CompressedMessage,
+    ) -> io::Result<T> {
+        let decompressed = compression::decompress(&message.bytes, self.limits.max_frame_size)?;
+
+        let message = match bincode::decode_from_slice::<T, _>(&decompressed, self.config) {
+            Ok((message, read)) if read == decompressed.len() => message,
+            Ok((_, read)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "compressed message of {} bytes has {read} bytes of message",
+                        decompressed.len()
+                    ),
+                ))
+            }
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+        };
+
+        match message.into_compressed() {
+            Ok(..) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "compressed message contains another compressed message",
+            )),
+            Err(message) => Ok(message),
+        }
+    }
+
+    /// Decodes a length-prefixed frame from `src`.
+    fn decode_frame<T: bincode::Decode>(&mut self, src: &mut BytesMut) -> io::Result<Option<T>> {
+        let Some(header) = src.get(..FRAME_HEADER_SIZE) else {
+            return Ok(None);
+        };
+        let length = u64::from_be_bytes(header.try_into().expect("header has the right size"));
+
+        if length > self.limits.max_frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame of {length} bytes exceeds the maximum frame size of {} bytes",
+                    self.limits.max_frame_size
+                ),
+            ));
+        }
+
+        let frame_size = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(FRAME_HEADER_SIZE))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("frame of {length} bytes does not fit in memory"),
+                )
+            })?;
+
+        let Some(frame) = src.get(FRAME_HEADER_SIZE..frame_size) else {
+            src.reserve(frame_size - src.len());
+            return Ok(None);
+        };
+
+        let message = match bincode::decode_from_slice(frame, self.config) {
+            Ok((message, read)) if read == frame.len() => message,
+            Ok((_, read)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("frame of {length} bytes has {read} bytes of message"),
+                ))
+            }
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+        };
+        src.advance(frame_size);
+
+        Ok(Some(message))
+    }
+}
+
+impl<I: bincode::Decode + FramingSwitch + Compressible, O> Decoder for ProtocolCodec<I, O> {
     type Item = I;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
-        match bincode::decode_from_slice(&src[..], self.config) {
-            Ok((message, read)) => {
-                src.advance(read);
-                Ok(Some(message))
-            }
-            Err(DecodeError::UnexpectedEnd { .. }) => Ok(None),
-            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+        let message: I = match self.incoming {
+            Framing::Unframed => match bincode::decode_from_slice(&src[..], self.config) {
+                Ok((message, read)) => {
+                    src.advance(read);
+                    message
+                }
+                Err(DecodeError::UnexpectedEnd { .. }) => return Ok(None),
+                Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+            },
+            Framing::LengthPrefixed => match self.decode_frame(src)? {
+                Some(message) => message,
+                None => return Ok(None),
+            },
+        };
+
+        let message = match message.into_compressed() {
+            Ok(compressed) => self.decode_compressed(compressed)?,
+            Err(message) => message,
+        };
+
+        if let Some(peer_limits) = message.framing_switch() {
+            self.incoming = Framing::LengthPrefixed;
+            self.peer_limits = Some(peer_limits);
+        }
+
+        if let Some(settings) = message.compression_switch() {
+            self.compression = Some(settings);
         }
+
+        Ok(Some(message))
     }
 }
 
-impl<I, O: bincode::Encode> Encoder<O> for ProtocolCodec<I, O> {
+impl<I, O: bincode::Encode + FramingSwitch + Compressible> Encoder<O> for ProtocolCodec<I, O> {
     type Error = io::Error;
 
     fn encode(&mut self, msg: O, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let encoded = match bincode::encode_to_vec(msg, self.config) {
+        let switches_framing = msg.framing_switch().is_some();
+        let compression_switch = msg.compression_switch();
+        let compress = self
+            .compression
+            .is_some_and(|CompressionSettings { threshold }| {
+                msg.payload_size()
+                    .is_some_and(|size| size as u64 >= threshold)
+            });
+
+        let mut encoded = match bincode::encode_to_vec(msg, self.config) {
             Ok(encoded) => encoded,
             Err(err) => {
                 return Err(io::Error::new(io::ErrorKind::Other, err.to_string()));
             }
         };
-        dst.reserve(encoded.len());
-        dst.put(&encoded[..]);
+
+        if compress {
+            let message = O::compressed(CompressedMessage {
+                bytes: compression::compress(&encoded)?,
+            });
+            let compressed = bincode::encode_to_vec(message, self.config)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+            if compressed.len() < encoded.len() {
+                encoded = compressed;
+            }
+        }
+
+        match self.outgoing {
+            Framing::Unframed => {
+                dst.reserve(encoded.len());
+                dst.put(&encoded[..]);
+            }
+            Framing::LengthPrefixed => {
+                let length = encoded.len() as u64;
+
+                // Until the peer responds with its limits, they're enforced only by the peer.
+                if let Some(FrameLimits { max_frame_size }) = self.peer_limits {
+                    if length > max_frame_size {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "message of {length} bytes exceeds the maximum frame size of the \
+                                peer, {max_frame_size} bytes"
+                            ),
+                        ));
+                    }
+                }
+
+                dst.reserve(FRAME_HEADER_SIZE + encoded.len());
+                dst.put_u64(length);
+                dst.put(&encoded[..]);
+            }
+        }
+
+        if switches_framing {
+            self.outgoing = Framing::LengthPrefixed;
+        }
+
+        if let Some(settings) = compression_switch {
+            self.compression = Some(settings);
+        }
 
         Ok(())
     }
@@ -273,6 +945,236 @@ mod tests {
         assert!(codec.decode(&mut buf).unwrap().is_none());
     }
 
+    /// Switches the framing of both directions, like the client and agent would.
+    fn switch_framing(client_codec: &mut ClientCodec, daemon_codec: &mut DaemonCodec) {
+        let mut buf = BytesMut::new();
+
+        client_codec
+            .encode(
+                ClientMessage::SwitchFraming(client_codec.limits()),
+                &mut buf,
+            )
+            .unwrap();
+        daemon_codec.decode(&mut buf).unwrap().unwrap();
+        daemon_codec
+            .encode(
+                DaemonMessage::SwitchFramingResponse(daemon_codec.limits()),
+                &mut buf,
+            )
+            .unwrap();
+        client_codec.decode(&mut buf).unwrap().unwrap();
+
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn framed_encode_decode() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default().with_limits(FrameLimits {
+            max_frame_size: 1024,
+        });
+        switch_framing(&mut client_codec, &mut daemon_codec);
+        assert_eq!(client_codec.peer_limits(), Some(daemon_codec.limits()));
+
+        let mut buf = BytesMut::new();
+        let msg = ClientMessage::Tcp(LayerTcp::PortSubscribe(1));
+        client_codec.encode(msg.clone(), &mut buf).unwrap();
+        client_codec.encode(ClientMessage::Ping, &mut buf).unwrap();
+        // `Ping` takes a single byte.
+        assert_eq!(
+            buf.get(..FRAME_HEADER_SIZE).unwrap(),
+            (buf.len() as u64 - 2 * FRAME_HEADER_SIZE as u64 - 1).to_be_bytes()
+        );
+
+        assert_eq!(daemon_codec.decode(&mut buf).unwrap(), Some(msg));
+        assert_eq!(
+            daemon_codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Ping)
+        );
+        assert!(buf.is_empty());
+
+        let msg = DaemonMessage::Close("bye".to_string());
+        daemon_codec.encode(msg.clone(), &mut buf).unwrap();
+        assert_eq!(client_codec.decode(&mut buf).unwrap(), Some(msg));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn messages_after_switch_in_the_same_read() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default();
+        let mut buf = BytesMut::new();
+
+        client_codec
+            .encode(
+                ClientMessage::SwitchFraming(FrameLimits::default()),
+                &mut buf,
+            )
+            .unwrap();
+        client_codec.encode(ClientMessage::Ping, &mut buf).unwrap();
+
+        assert_eq!(
+            daemon_codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::SwitchFraming(FrameLimits::default()))
+        );
+        assert_eq!(
+            daemon_codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Ping)
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn framed_partial_data() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default();
+        switch_framing(&mut client_codec, &mut daemon_codec);
+
+        let mut buf = BytesMut::new();
+        client_codec
+            .encode(ClientMessage::Tcp(LayerTcp::PortSubscribe(1)), &mut buf)
+            .unwrap();
+        let mut rest = buf.split_off(FRAME_HEADER_SIZE + 1);
+
+        assert!(daemon_codec.decode(&mut buf).unwrap().is_none());
+        buf.unsplit(rest.split());
+        assert!(daemon_codec.decode(&mut buf).unwrap().is_some());
+    }
+
+    #[test]
+    fn decode_frame_over_limit() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec =
+            DaemonCodec::default().with_limits(FrameLimits { max_frame_size: 4 });
+        // The daemon didn't respond yet, so the client doesn't know its limits.
+        let mut buf = BytesMut::new();
+        client_codec
+            .encode(
+                ClientMessage::SwitchFraming(client_codec.limits()),
+                &mut buf,
+            )
+            .unwrap();
+        client_codec
+            .encode(
+                ClientMessage::Tcp(LayerTcp::PortUnsubscribe(8080)),
+                &mut buf,
+            )
+            .unwrap();
+
+        daemon_codec.decode(&mut buf).unwrap().unwrap();
+        let error = daemon_codec.decode(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn encode_frame_over_peer_limit() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec =
+            DaemonCodec::default().with_limits(FrameLimits { max_frame_size: 4 });
+        switch_framing(&mut client_codec, &mut daemon_codec);
+
+        let mut buf = BytesMut::new();
+        let error = client_codec
+            .encode(
+                ClientMessage::Tcp(LayerTcp::PortUnsubscribe(8080)),
+                &mut buf,
+            )
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+
+        client_codec.encode(ClientMessage::Ping, &mut buf).unwrap();
+        assert_eq!(
+            daemon_codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Ping)
+        );
+    }
+
+    #[test]
+    fn compressed_encode_decode() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default();
+        switch_framing(&mut client_codec, &mut daemon_codec);
+
+        let mut buf = BytesMut::new();
+        let settings = CompressionSettings { threshold: 1024 };
+        client_codec
+            .encode(ClientMessage::SwitchCompression(settings), &mut buf)
+            .unwrap();
+        assert_eq!(
+            daemon_codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::SwitchCompression(settings))
+        );
+        assert_eq!(daemon_codec.compression(), Some(settings));
+
+        let msg = ClientMessage::FileRequest(FileRequest::Write(WriteFileRequest {
+            fd: 1,
+            write_bytes: vec![b'a'; 4096],
+        }));
+        client_codec.encode(msg.clone(), &mut buf).unwrap();
+        assert!(buf.len() < 1024);
+        assert_eq!(daemon_codec.decode(&mut buf).unwrap(), Some(msg));
+
+        let msg = DaemonMessage::File(FileResponse::Read(Ok(ReadFileResponse {
+            bytes: vec![b'a'; 4096],
+            read_amount: 4096,
+        })));
+        daemon_codec.encode(msg.clone(), &mut buf).unwrap();
+        assert!(buf.len() < 1024);
+        assert_eq!(client_codec.decode(&mut buf).unwrap(), Some(msg));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn not_compressed_under_threshold() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default();
+        let mut buf = BytesMut::new();
+        client_codec
+            .encode(
+                ClientMessage::SwitchCompression(CompressionSettings { threshold: 1024 }),
+                &mut buf,
+            )
+            .unwrap();
+        daemon_codec.decode(&mut buf).unwrap().unwrap();
+
+        let msg = DaemonMessage::File(FileResponse::Read(Ok(ReadFileResponse {
+            bytes: vec![b'a'; 512],
+            read_amount: 512,
+        })));
+        daemon_codec.encode(msg.clone(), &mut buf).unwrap();
+        assert!(buf.len() > 512);
+        assert_eq!(client_codec.decode(&mut buf).unwrap(), Some(msg));
+    }
+
+    #[test]
+    fn decompressed_over_limit() {
+        let mut client_codec = ClientCodec::default();
+        let mut daemon_codec = DaemonCodec::default().with_limits(FrameLimits {
+            max_frame_size: 1024,
+        });
+        let mut buf = BytesMut::new();
+        client_codec
+            .encode(
+                ClientMessage::SwitchCompression(CompressionSettings { threshold: 0 }),
+                &mut buf,
+            )
+            .unwrap();
+        client_codec
+            .encode(
+                ClientMessage::FileRequest(FileRequest::Write(WriteFileRequest {
+                    fd: 1,
+                    write_bytes: vec![0; 4096],
+                })),
+                &mut buf,
+            )
+            .unwrap();
+
+        daemon_codec.decode(&mut buf).unwrap().unwrap();
+        let error = daemon_codec.decode(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
     #[test]
     fn decode_daemon_invalid_data() {
         let mut codec = DaemonCodec::default();
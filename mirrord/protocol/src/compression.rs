@@ -0,0 +1,92 @@
+//! Compression of the file payloads on the wire.
+//!
+//! Peers that support [`COMPRESSION_VERSION`] can compress the bytes read from and written to
+//! remote files with zstd, which pays off on slow links to the cluster (e.g. through a VPN):
+//!
+//! 1. The client sends
+//!    [`ClientMessage::SwitchCompression`](crate::ClientMessage::SwitchCompression) with its
+//!    [`CompressionSettings`];
+//! 2. From then on, both peers send the messages that carry a file payload of at least
+//!    [`CompressionSettings::threshold`] bytes in
+//!    [`ClientMessage::Compressed`](crate::ClientMessage::Compressed) and
+//!    [`DaemonMessage::Compressed`](crate::DaemonMessage::Compressed).
+//!
+//! Like the [`framing`](crate::framing) switch, this is handled by the codec: after a peer encodes
+//! or decodes the switch message, it compresses what it sends. Compressed messages are decoded
+//! into the original ones, so a proxy in the middle that just forwards the messages compresses
+//! both of its connections at the same points of the streams.
+//!
+//! A message is sent uncompressed when compressing it doesn't make it smaller.
+use std::{
+    fmt,
+    io::{self, Read},
+    sync::LazyLock,
+};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::SwitchCompression`](crate::ClientMessage::SwitchCompression).
+pub static COMPRESSION_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.48.0".parse().expect("Bad Identifier"));
+
+/// Settings of the compression of a connection, sent by the client when switching it on.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct CompressionSettings {
+    /// Messages with smaller file payloads are sent uncompressed.
+    pub threshold: u64,
+}
+
+/// A message encoded and compressed with zstd, see the [module docs](self).
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+pub struct CompressedMessage {
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Debug for CompressedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedMessage")
+            .field("bytes", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Implemented by the messages that can be compressed, see the [module docs](self).
+pub trait Compressible: Sized {
+    /// Returns the [`CompressionSettings`] of the sender, if this message switches the
+    /// compression on.
+    fn compression_switch(&self) -> Option<CompressionSettings>;
+
+    /// Size of the file payload of this message, [`None`] if it doesn't carry one.
+    fn payload_size(&self) -> Option<usize>;
+
+    /// Wraps a [`CompressedMessage`].
+    fn compressed(message: CompressedMessage) -> Self;
+
+    /// Unwraps a [`CompressedMessage`], returns any other message as it is.
+    fn into_compressed(self) -> Result<CompressedMessage, Self>;
+}
+
+/// Compresses an encoded message.
+pub(crate) fn compress(encoded: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(encoded, zstd::DEFAULT_COMPRESSION_LEVEL)
+}
+
+/// Decompresses an encoded message, failing if it's bigger than `limit` bytes.
+pub(crate) fn decompress(compressed: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    zstd::stream::read::Decoder::new(compressed)?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut decompressed)?;
+
+    if decompressed.len() as u64 > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("compressed message exceeds the maximum frame size of {limit} bytes"),
+        ));
+    }
+
+    Ok(decompressed)
+}
@@ -1,19 +1,26 @@
 extern crate alloc;
 use core::ops::Deref;
-use std::net::IpAddr;
+use std::{net::IpAddr, sync::LazyLock};
 
 use bincode::{Decode, Encode};
 use hickory_resolver::{lookup_ip::LookupIp, proto::rr::resource::RecordParts};
+use semver::VersionReq;
 
 use crate::RemoteResult;
 
+/// Minimal mirrord-protocol version that allows [`GetAddrInfoRequestV2`].
+pub static ADDRINFO_V2_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.12.0".parse().expect("Bad Identifier"));
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct LookupRecord {
     pub name: String,
     pub ip: IpAddr,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct DnsLookup(pub Vec<LookupRecord>);
 
 impl From<LookupIp> for DnsLookup {
@@ -60,6 +67,7 @@ impl IntoIterator for DnsLookup {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct GetAddrInfoResponse(pub RemoteResult<DnsLookup>);
 
 impl Deref for GetAddrInfoResponse {
@@ -72,6 +80,61 @@ impl Deref for GetAddrInfoResponse {
 
 /// Triggered by the `mirrord-layer` hook of `getaddrinfo_detour`.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct GetAddrInfoRequest {
     pub node: String,
 }
+
+/// Address family requested in the `ai_family` field of the `getaddrinfo` hints.
+///
+/// Kept platform independent, as the values of `AF_INET6` differ between Linux and macOS.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum AddrInfoFamily {
+    /// `AF_UNSPEC`.
+    #[default]
+    Unspecified,
+    /// `AF_INET`.
+    Ipv4,
+    /// `AF_INET6`.
+    Ipv6,
+}
+
+/// The hints passed by the user application to `getaddrinfo`.
+///
+/// `ai_socktype` and `ai_protocol` are the raw values used by the user application, which are
+/// the same on all supported platforms. `ai_flags` are also passed raw, and only the flags that
+/// share values across platforms (such as `AI_CANONNAME`) should be relied upon.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct AddrInfoHint {
+    pub ai_family: AddrInfoFamily,
+    pub ai_socktype: i32,
+    pub ai_protocol: i32,
+    pub ai_flags: i32,
+}
+
+/// Triggered by the `mirrord-layer` hook of `getaddrinfo_detour`, when the agent supports
+/// [`ADDRINFO_V2_VERSION`].
+///
+/// Unlike [`GetAddrInfoRequest`], carries the hints passed by the user application, so that the
+/// agent can resolve only the requested address family.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetAddrInfoRequestV2 {
+    pub node: String,
+    /// [`None`] when the user application did not pass any hints.
+    pub hints: Option<AddrInfoHint>,
+}
+
+impl From<GetAddrInfoRequest> for GetAddrInfoRequestV2 {
+    fn from(GetAddrInfoRequest { node }: GetAddrInfoRequest) -> Self {
+        Self { node, hints: None }
+    }
+}
+
+impl From<GetAddrInfoRequestV2> for GetAddrInfoRequest {
+    fn from(GetAddrInfoRequestV2 { node, .. }: GetAddrInfoRequestV2) -> Self {
+        Self { node }
+    }
+}
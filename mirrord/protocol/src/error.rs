@@ -3,16 +3,19 @@ use std::{
     io,
     net::AddrParseError,
     path::StripPrefixError,
+    sync::LazyLock,
 };
 
 use bincode::{Decode, Encode};
 use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use semver::VersionReq;
 use thiserror::Error;
 use tracing::warn;
 
 use crate::{
+    fd_budget::FdKind,
     outgoing::SocketAddress,
-    tcp::{Filter, HttpFilter, StealType},
+    tcp::{DatabaseFilter, Filter, HttpFilter, StealType},
     Port,
 };
 
@@ -27,7 +30,12 @@ pub enum SerializationError {
 #[error("Failed parsing `MeshVendor` from `{0}`!")]
 pub struct MeshVendorParseError(pub String);
 
+/// Minimal mirrord-protocol version that allows [`ResponseError::Throttled`].
+pub static THROTTLE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.13.0".parse().expect("Bad Identifier"));
+
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq, Error)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ResponseError {
     #[error("File/connection ids exhausted, operation `{0}` failed!")]
     IdsExhausted(String),
@@ -64,6 +72,146 @@ pub enum ResponseError {
 
     #[error("Failed stripping path with `{0}`!")]
     StripPrefix(String),
+
+    /// The agent is rate limiting this kind of request for the client, which should retry after
+    /// `retry_after_ms`.
+    ///
+    /// Only sent to clients that support [`THROTTLE_VERSION`].
+    #[error("Request was throttled, retry after {retry_after_ms}ms!")]
+    Throttled { retry_after_ms: u64 },
+
+    /// The client made its session read-only with
+    /// [`ClientMessage::ReadOnly`](crate::ClientMessage::ReadOnly).
+    #[error("{0} is not allowed in a read-only session.")]
+    ReadOnlySession(BlockedAction),
+
+    /// Opening a remote `kind` would go over the descriptors that the agent keeps open for the
+    /// client, see [`fd_budget`](crate::fd_budget).
+    ///
+    /// Only sent to clients that support
+    /// [`FD_BUDGET_VERSION`](crate::fd_budget::FD_BUDGET_VERSION).
+    #[error(
+        "Could not open a remote {kind}, the session already has the {limit} remote files, \
+        directories and sockets that the agent allows!"
+    )]
+    FdLimitReached { kind: FdKind, limit: u64 },
+}
+
+impl ResponseError {
+    /// Classifies this error for the tools that wrap mirrord, see [`ErrorCode`].
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Forbidden { .. } => ErrorCode::PolicyForbidden,
+            Self::PortAlreadyStolen(..) => ErrorCode::PortAlreadyStolen,
+            Self::NotImplemented => ErrorCode::ProtocolVersionSkew,
+            Self::ReadOnlySession(..) => ErrorCode::ReadOnlySession,
+            Self::IdsExhausted(..)
+            | Self::NotFound(..)
+            | Self::NotDirectory(..)
+            | Self::NotFile(..)
+            | Self::RemoteIO(..)
+            | Self::DnsLookup(..)
+            | Self::Remote(..)
+            | Self::StripPrefix(..)
+            | Self::Throttled { .. }
+            | Self::FdLimitReached { .. } => ErrorCode::RemoteOperationFailed,
+        }
+    }
+}
+
+/// Stable, machine-readable classification of the failures of a mirrord session, for the tools
+/// that wrap mirrord (IDE plugins, CI) to tell them apart without parsing the messages.
+///
+/// The codes are reported by the CLI, e.g. in the JSON output of the errors. Once released, a
+/// code is never renamed or reused for a different failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The kubeconfig could not be loaded.
+    KubeConfigInvalid,
+    /// Authenticating with the cluster failed, e.g. the cloud session expired.
+    KubeAuthFailed,
+    /// The certificate of the cluster is missing or invalid.
+    KubeCertificateInvalid,
+    /// The Kubernetes API denied a request of the user.
+    RbacDenied,
+    /// A request to the Kubernetes API failed.
+    KubeApiFailed,
+    /// The target could not be found or is in an invalid state.
+    TargetUnavailable,
+    /// The agent image could not be pulled.
+    AgentImagePullFailed,
+    /// The agent was created, but did not start.
+    AgentStartFailed,
+    /// Connecting to the agent failed.
+    AgentConnectionFailed,
+    /// The agent failed or closed the connection.
+    AgentFailed,
+    /// The agent or the operator is too old for the features in use.
+    ProtocolVersionSkew,
+    /// The mirrord operator is not installed in the cluster.
+    OperatorNotInstalled,
+    /// The license of the mirrord operator expired.
+    OperatorLicenseInvalid,
+    /// A request to the mirrord operator failed.
+    OperatorFailed,
+    /// A feature in use requires the mirrord operator.
+    OperatorRequired,
+    /// A mirrord policy forbids the operation for the target.
+    PolicyForbidden,
+    /// The port is already stolen by another mirrord session.
+    PortAlreadyStolen,
+    /// The operation is not allowed in a read-only session.
+    ReadOnlySession,
+    /// An operation of the agent failed in the target.
+    RemoteOperationFailed,
+    /// The mirrord config is invalid.
+    ConfigInvalid,
+    /// The local process could not be executed.
+    ExecFailed,
+    /// A local file or resource could not be accessed.
+    LocalIoFailed,
+    /// The operator credentials could not be loaded or issued.
+    CredentialsFailed,
+    /// An internal error of mirrord, which should be reported.
+    Internal,
+}
+
+impl ErrorCode {
+    /// The stable name of the code, e.g. `rbac_denied`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::KubeConfigInvalid => "kube_config_invalid",
+            Self::KubeAuthFailed => "kube_auth_failed",
+            Self::KubeCertificateInvalid => "kube_certificate_invalid",
+            Self::RbacDenied => "rbac_denied",
+            Self::KubeApiFailed => "kube_api_failed",
+            Self::TargetUnavailable => "target_unavailable",
+            Self::AgentImagePullFailed => "agent_image_pull_failed",
+            Self::AgentStartFailed => "agent_start_failed",
+            Self::AgentConnectionFailed => "agent_connection_failed",
+            Self::AgentFailed => "agent_failed",
+            Self::ProtocolVersionSkew => "protocol_version_skew",
+            Self::OperatorNotInstalled => "operator_not_installed",
+            Self::OperatorLicenseInvalid => "operator_license_invalid",
+            Self::OperatorFailed => "operator_failed",
+            Self::OperatorRequired => "operator_required",
+            Self::PolicyForbidden => "policy_forbidden",
+            Self::PortAlreadyStolen => "port_already_stolen",
+            Self::ReadOnlySession => "read_only_session",
+            Self::RemoteOperationFailed => "remote_operation_failed",
+            Self::ConfigInvalid => "config_invalid",
+            Self::ExecFailed => "exec_failed",
+            Self::LocalIoFailed => "local_io_failed",
+            Self::CredentialsFailed => "credentials_failed",
+            Self::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl From<StripPrefixError> for ResponseError {
@@ -84,6 +232,7 @@ fn policy_name_string(policy_name: Option<String>) -> String {
 /// All the actions that can be blocked by the operator, to identify the blocked feature in a
 /// [`ResponseError::Forbidden`] message.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq, Error)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum BlockedAction {
     Steal(StealType),
 }
@@ -107,11 +256,21 @@ impl fmt::Display for BlockedAction {
                     "Stealing traffic from port {port} with http request filter: {filter}"
                 )
             }
+            BlockedAction::Steal(StealType::FilteredDatabase(port, filter)) => {
+                write!(
+                    f,
+                    "Stealing traffic from port {port} with database filter: {filter}"
+                )
+            }
+            BlockedAction::Steal(StealType::Reserved(port)) => {
+                write!(f, "Reserving port {port} in the target")
+            }
         }
     }
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq, Error)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum RemoteError {
     #[error("Failed to find a nameserver when resolving DNS!")]
     NameserverNotFound,
@@ -132,6 +291,9 @@ pub enum RemoteError {
 
     #[error(r#"Got bad regex "{0:?}" for http filter subscriptions. Regex error: `{1}`."#)]
     BadHttpFilterExRegex(HttpFilter, String),
+
+    #[error(r#"Got bad regex in "{0}" for database filter subscriptions. Regex error: `{1}`."#)]
+    BadDatabaseFilterRegex(DatabaseFilter, String),
 }
 
 impl From<AddrParseError> for RemoteError {
@@ -143,6 +305,7 @@ impl From<AddrParseError> for RemoteError {
 /// Our internal version of Rust's `std::io::Error` that can be passed between mirrord-layer and
 /// mirrord-agent.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq, Error)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 #[error("Failed performing `getaddrinfo` with {raw_os_error:?} and kind {kind:?}!")]
 pub struct RemoteIOError {
     pub raw_os_error: Option<i32>,
@@ -155,6 +318,7 @@ pub struct RemoteIOError {
 /// [`ResolveErrorKindInternal`] has a nice [`core::fmt::Display`] implementation that
 /// should be user friendly, and can be appended to the generic error message here.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq, Error)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 #[error("Failed performing `getaddrinfo`: {kind}")]
 pub struct DnsLookupError {
     pub kind: ResolveErrorKindInternal,
@@ -181,6 +345,7 @@ impl From<ResolveError> for ResponseError {
 }
 /// Alternative to `std::io::ErrorKind`, used to implement `bincode::Encode` and `bincode::Decode`.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ErrorKindInternal {
     NotFound,
     PermissionDenied,
@@ -228,6 +393,7 @@ pub enum ErrorKindInternal {
 
 /// Alternative to `std::io::ErrorKind`, used to implement `bincode::Encode` and `bincode::Decode`.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Eq)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ResolveErrorKindInternal {
     Message(String),
     NoConnections,
@@ -0,0 +1,108 @@
+//! Per-client budget of the remote files, directories and sockets that the agent keeps open.
+//!
+//! A local app that leaks descriptors would exhaust the limit of the agent, and break the other
+//! clients of a shared agent. The operator can cap the descriptors that the agent keeps open for
+//! each client with [`AGENT_FD_LIMIT_ENV`](crate::AGENT_FD_LIMIT_ENV). Requests that would go over
+//! it fail with [`ResponseError::FdLimitReached`](crate::ResponseError::FdLimitReached) for peers
+//! that support [`FD_BUDGET_VERSION`], and with `EMFILE` for older ones.
+//!
+//! Peers that support [`FD_BUDGET_VERSION`] can also send
+//! [`ClientMessage::FdUsageRequest`](crate::ClientMessage::FdUsageRequest), and the agent
+//! responds with [`DaemonMessage::FdUsage`](crate::DaemonMessage::FdUsage).
+//!
+//! The agent reports the files and directories that were not used for a while to peers that
+//! support [`FD_GC_VERSION`], with [`DaemonMessage::IdleFds`](crate::DaemonMessage::IdleFds), and
+//! the peer closes the ones that the local app leaked with
+//! [`ClientMessage::CloseFds`](crate::ClientMessage::CloseFds).
+use std::{fmt, sync::LazyLock};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ResponseError::FdLimitReached`](crate::ResponseError::FdLimitReached) and
+/// [`ClientMessage::FdUsageRequest`](crate::ClientMessage::FdUsageRequest).
+pub static FD_BUDGET_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.53.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows
+/// [`DaemonMessage::IdleFds`](crate::DaemonMessage::IdleFds) and
+/// [`ClientMessage::CloseFds`](crate::ClientMessage::CloseFds).
+pub static FD_GC_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.59.0".parse().expect("Bad Identifier"));
+
+/// The kind of a remote descriptor that the agent keeps open for a client.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum FdKind {
+    File,
+    Dir,
+    /// An outgoing TCP connection, or an outgoing UDP socket.
+    Socket,
+}
+
+impl fmt::Display for FdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File => f.write_str("file"),
+            Self::Dir => f.write_str("directory"),
+            Self::Socket => f.write_str("socket"),
+        }
+    }
+}
+
+/// Asks for the [`FdUsage`] of the client.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FdUsageRequest;
+
+/// The remote descriptors that the agent keeps open for the client, response to
+/// [`FdUsageRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FdUsage {
+    pub files: u64,
+    pub dirs: u64,
+    pub sockets: u64,
+    /// Most descriptors of all kinds that the client can have open at once, [`None`] if there's
+    /// no limit.
+    pub limit: Option<u64>,
+}
+
+impl FdUsage {
+    /// Descriptors of all kinds, counted against [`Self::limit`].
+    pub fn total(&self) -> u64 {
+        self.files + self.dirs + self.sockets
+    }
+}
+
+/// Remote files and directories of the client that were not used for
+/// [`IdleFds::idle_timeout_secs`], reported periodically by the agent.
+///
+/// Each descriptor is reported once, and again only after it's used and goes idle again.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct IdleFds {
+    /// Opened with [`FileRequest::Open`](crate::FileRequest::Open) and friends.
+    pub files: Vec<u64>,
+    /// Opened with [`FileRequest::FdOpenDir`](crate::FileRequest::FdOpenDir).
+    pub dirs: Vec<u64>,
+    /// Idle timeout of the agent.
+    pub idle_timeout_secs: u64,
+}
+
+impl IdleFds {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.dirs.is_empty()
+    }
+}
+
+/// Closes remote files and directories at once, like
+/// [`CloseFileRequest`](crate::file::CloseFileRequest) and
+/// [`CloseDirRequest`](crate::file::CloseDirRequest) would, see [`IdleFds`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct CloseFdsRequest {
+    pub files: Vec<u64>,
+    pub dirs: Vec<u64>,
+}
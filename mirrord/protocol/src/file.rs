@@ -1,26 +1,176 @@
 use core::fmt;
 #[cfg(target_os = "linux")]
 use std::fs::DirEntry;
-#[cfg(target_os = "linux")]
-use std::io;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
 #[cfg(target_os = "linux")]
 use std::os::unix::fs::DirEntryExt;
 use std::{
-    fs::Metadata, io::SeekFrom, os::unix::prelude::MetadataExt, path::PathBuf, sync::LazyLock,
+    collections::{HashMap, HashSet},
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+#[cfg(unix)]
+use std::{
+    ffi::OsString,
+    fs::Metadata,
+    os::unix::{ffi::OsStrExt, prelude::MetadataExt},
 };
 
-use bincode::{Decode, Encode};
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
 #[cfg(target_os = "linux")]
 use nix::sys::statfs::Statfs;
 use semver::VersionReq;
 
+use crate::ConnectionId;
+
 /// Minimal mirrord-protocol version that allows [`ReadDirBatchRequest`].
 pub static READDIR_BATCH_VERSION: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.9.0".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`ScratchDirRequest`].
+pub static SCRATCH_DIR_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.17.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`UnlinkFileRequest`] and
+/// [`UnlinkFileWithDirRequest`].
+pub static UNLINK_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.26.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`RenameFileRequest`] and
+/// [`RenameFileWithDirRequest`].
+pub static RENAME_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.27.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ChmodFileRequest`], [`FchmodFileRequest`] and
+/// [`ChmodFileWithDirRequest`].
+pub static CHMOD_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.28.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`OpenAt2Request`].
+pub static OPENAT2_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.29.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ChownFileRequest`], [`FchownFileRequest`] and
+/// [`ChownFileWithDirRequest`].
+pub static CHOWN_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.30.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ReadWholeFileRequest`].
+pub static READ_WHOLE_FILE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.31.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`TruncateFileRequest`] and
+/// [`FtruncateFileRequest`].
+pub static TRUNCATE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.32.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`GlobRequest`].
+pub static GLOB_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.33.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`SymlinkRequest`] and [`SymlinkAtRequest`].
+pub static SYMLINK_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.34.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`LinkFileRequest`] and
+/// [`LinkFileWithDirRequest`].
+pub static LINK_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.35.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`UtimensFileWithDirRequest`] and
+/// [`FutimensFileRequest`].
+pub static UTIMENS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.36.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`FsyncFileRequest`].
+pub static FSYNC_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.37.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`StatxRequest`].
+pub static STATX_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.38.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows a [`DirEntryInternal::name`] that is not valid
+/// UTF-8.
+pub static NON_UTF8_NAMES_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.39.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`GetXattrRequest`], [`SetXattrRequest`],
+/// [`ListXattrRequest`] and [`RemoveXattrRequest`].
+pub static XATTR_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.40.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`FlockRequest`], [`SetLockRequest`] and
+/// [`GetLockRequest`].
+pub static LOCK_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.41.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`FallocateFileRequest`].
+pub static FALLOCATE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.43.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`CopyFileRangeRequest`].
+pub static COPY_FILE_RANGE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.44.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`SendFileRequest`].
+pub static SEND_FILE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.45.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ReadVFileRequest`] and [`WriteVFileRequest`].
+pub static VECTORED_IO_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.46.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ReadStreamFileRequest`].
+pub static READ_STREAM_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.47.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`SeekFromInternal::Data`] and
+/// [`SeekFromInternal::Hole`].
+pub static SEEK_HOLE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.49.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ReadDirDeltaRequest`].
+pub static READ_DIR_DELTA_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.51.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`CanonicalizePathRequest`].
+pub static CANONICALIZE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.52.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`StatFsRequest`].
+pub static STATFS_PATH_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.54.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`FollowFileRequest`] and
+/// [`UnfollowFileRequest`].
+pub static FOLLOW_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.56.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`ReadDirPlusBatchRequest`].
+pub static READ_DIR_PLUS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.57.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`PrefetchTreeRequest`].
+pub static PREFETCH_TREE_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.58.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows paths of the requests that are not valid UTF-8,
+/// see [`RawPathCodec`].
+pub static NON_UTF8_PATHS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.60.0".parse().expect("Bad Identifier"));
+
 /// Internal version of Metadata across operating system (macOS, Linux)
 /// Only mutual attributes
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct MetadataInternal {
     /// dev_id, st_dev
     pub device_id: u64,
@@ -51,6 +201,13 @@ pub struct MetadataInternal {
     pub blocks: u64,
 }
 
+/// Joins the seconds and nanoseconds parts of a [`Metadata`] timestamp into nanoseconds.
+#[cfg(unix)]
+fn timestamp_nanos(secs: i64, nsecs: i64) -> i64 {
+    secs.saturating_mul(1_000_000_000).saturating_add(nsecs)
+}
+
+#[cfg(unix)]
 impl From<Metadata> for MetadataInternal {
     fn from(metadata: Metadata) -> Self {
         Self {
@@ -62,16 +219,40 @@ impl From<Metadata> for MetadataInternal {
             group_id: metadata.gid(),
             rdevice_id: metadata.rdev(),
             size: metadata.size(),
-            access_time: metadata.atime_nsec(),
-            modification_time: metadata.mtime_nsec(),
-            creation_time: metadata.ctime_nsec(),
+            access_time: timestamp_nanos(metadata.atime(), metadata.atime_nsec()),
+            modification_time: timestamp_nanos(metadata.mtime(), metadata.mtime_nsec()),
+            creation_time: timestamp_nanos(metadata.ctime(), metadata.ctime_nsec()),
             block_size: metadata.blksize(),
             blocks: metadata.blocks(),
         }
     }
 }
 
+/// Metadata of the Linux `statx`, the fields that are not in [`MetadataInternal`].
+///
+/// Which of the fields are valid is in [`StatxMetadataInternal::mask`], as in `stx_mask`.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct StatxMetadataInternal {
+    pub metadata: MetadataInternal,
+    /// `STATX_*` flags of the filled fields, stx_mask
+    pub mask: u32,
+    /// `STATX_ATTR_*` flags, stx_attributes
+    pub attributes: u64,
+    /// `STATX_ATTR_*` flags supported by the filesystem, stx_attributes_mask
+    pub attributes_mask: u64,
+    /// birth time in nanoseconds, stx_btime
+    pub birth_time: i64,
+    /// mount id, stx_mnt_id
+    pub mount_id: u64,
+    /// memory buffer alignment for direct I/O, stx_dio_mem_align
+    pub dio_mem_align: u32,
+    /// file offset alignment for direct I/O, stx_dio_offset_align
+    pub dio_offset_align: u32,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct FsMetadataInternal {
     /// f_type
     pub filesystem_type: i64,
@@ -105,13 +286,28 @@ impl From<Statfs> for FsMetadataInternal {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct DirEntryInternal {
     pub inode: u64,
     pub position: u64,
-    pub name: String,
+    /// The raw bytes of the name, which (on Linux) don't have to be valid UTF-8.
+    ///
+    /// Encoded just like a [`String`], so a valid UTF-8 name is understood by older clients too,
+    /// only [`NON_UTF8_NAMES_VERSION`] clients get the other names as they are.
+    pub name: Vec<u8>,
     pub file_type: u8,
 }
 
+impl DirEntryInternal {
+    /// The [`DirEntryInternal::file_type`] of symbolic links, `DT_LNK` of the agent's platform
+    /// (Linux), whatever the platform of the client is.
+    pub const SYMLINK_FILE_TYPE: u8 = 10;
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == Self::SYMLINK_FILE_TYPE
+    }
+}
+
 #[cfg(target_os = "linux")]
 impl TryFrom<(usize, io::Result<DirEntry>)> for DirEntryInternal {
     type Error = io::Error;
@@ -120,10 +316,17 @@ impl TryFrom<(usize, io::Result<DirEntry>)> for DirEntryInternal {
         let (offset, entry) = offset_entry_pair;
         let entry = entry?;
 
-        let mode = entry.metadata()?.mode();
+        Ok(Self::new(offset, &entry, &entry.metadata()?))
+    }
+}
 
-        let file_type = match mode & libc::S_IFMT {
-            libc::S_IFLNK => libc::DT_LNK,
+#[cfg(target_os = "linux")]
+impl DirEntryInternal {
+    /// The entry at `offset` of a directory, with the `metadata` of the entry itself (not of the
+    /// file that it links to).
+    fn new(offset: usize, entry: &DirEntry, metadata: &Metadata) -> Self {
+        let file_type = match metadata.mode() & libc::S_IFMT {
+            libc::S_IFLNK => Self::SYMLINK_FILE_TYPE,
             libc::S_IFREG => libc::DT_REG,
             libc::S_IFBLK => libc::DT_BLK,
             libc::S_IFDIR => libc::DT_DIR,
@@ -133,11 +336,36 @@ impl TryFrom<(usize, io::Result<DirEntry>)> for DirEntryInternal {
             _ => libc::DT_UNKNOWN,
         };
 
-        Ok(DirEntryInternal {
+        DirEntryInternal {
             inode: entry.ino(),
             position: offset as u64,
-            name: entry.file_name().to_string_lossy().into(),
+            name: entry.file_name().into_vec(),
             file_type,
+        }
+    }
+}
+
+/// A [`DirEntryInternal`] with the metadata of the entry, see [`ReadDirPlusBatchRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct DirEntryPlusInternal {
+    pub entry: DirEntryInternal,
+    /// Metadata of the entry itself, like `lstat` gets it.
+    pub metadata: MetadataInternal,
+}
+
+#[cfg(target_os = "linux")]
+impl TryFrom<(usize, io::Result<DirEntry>)> for DirEntryPlusInternal {
+    type Error = io::Error;
+
+    fn try_from(offset_entry_pair: (usize, io::Result<DirEntry>)) -> Result<Self, Self::Error> {
+        let (offset, entry) = offset_entry_pair;
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        Ok(DirEntryPlusInternal {
+            entry: DirEntryInternal::new(offset, &entry, &metadata),
+            metadata: metadata.into(),
         })
     }
 }
@@ -171,13 +399,119 @@ impl DirEntryInternal {
     }
 }
 
-#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+/// Encoding of the paths of the requests, used by [`impl_raw_paths_codec`].
+///
+/// A path is encoded like a [`String`], but with the raw bytes of the path (on unix), that don't
+/// have to be valid UTF-8. Valid UTF-8 paths are understood by all agents, the other ones only by
+/// [`NON_UTF8_PATHS_VERSION`] agents.
+trait RawPathCodec: Sized {
+    fn encode_raw<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError>;
+
+    fn decode_raw<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError>;
+}
+
+/// A path encoded with [`RawPathCodec`].
+struct RawPath<'a>(&'a Path);
+
+impl Encode for RawPath<'_> {
+    #[cfg(unix)]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.as_os_str().as_bytes().encode(encoder)
+    }
+
+    #[cfg(not(unix))]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.encode(encoder)
+    }
+}
+
+/// A path decoded with [`RawPathCodec`].
+struct RawPathBuf(PathBuf);
+
+impl Decode for RawPathBuf {
+    #[cfg(unix)]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let bytes = Vec::<u8>::decode(decoder)?;
+        Ok(Self(OsString::from_vec(bytes).into()))
+    }
+
+    #[cfg(not(unix))]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        PathBuf::decode(decoder).map(Self)
+    }
+}
+
+impl RawPathCodec for PathBuf {
+    fn encode_raw<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        RawPath(self).encode(encoder)
+    }
+
+    fn decode_raw<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        RawPathBuf::decode(decoder).map(|RawPathBuf(path)| path)
+    }
+}
+
+impl RawPathCodec for Option<PathBuf> {
+    fn encode_raw<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.as_deref().map(RawPath).encode(encoder)
+    }
+
+    fn decode_raw<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Option::<RawPathBuf>::decode(decoder).map(|path| path.map(|RawPathBuf(path)| path))
+    }
+}
+
+/// Implements [`Encode`] and [`Decode`] of a request like the derive macros do, except for the
+/// fields marked with `raw`, the paths of the request, which use [`RawPathCodec`].
+///
+/// The fields must be listed in the order of the struct, which is the order of the encoding.
+macro_rules! impl_raw_paths_codec {
+    ($name:ident { $($field:ident $(: $raw:ident)?),* $(,)? }) => {
+        impl Encode for $name {
+            fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+                $(impl_raw_paths_codec!(@encode self.$field, encoder $(, $raw)?);)*
+                Ok(())
+            }
+        }
+
+        impl Decode for $name {
+            fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+                Ok(Self {
+                    $($field: impl_raw_paths_codec!(@decode decoder $(, $raw)?),)*
+                })
+            }
+        }
+
+        bincode::impl_borrow_decode!($name);
+    };
+    (@encode $value:expr, $encoder:ident) => {
+        Encode::encode(&$value, $encoder)?
+    };
+    (@encode $value:expr, $encoder:ident, raw) => {
+        RawPathCodec::encode_raw(&$value, $encoder)?
+    };
+    (@decode $decoder:ident) => {
+        Decode::decode($decoder)?
+    };
+    (@decode $decoder:ident, raw) => {
+        RawPathCodec::decode_raw($decoder)?
+    };
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct OpenFileRequest {
     pub path: PathBuf,
     pub open_options: OpenOptionsInternal,
 }
 
+impl_raw_paths_codec!(OpenFileRequest {
+    path: raw,
+    open_options
+});
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct OpenFileResponse {
     pub fd: u64,
 }
@@ -187,6 +521,7 @@ pub struct OpenFileResponse {
 //
 // TODO: Should probably live in a separate place (same reasoning as `AddrInfoHint`).
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct OpenOptionsInternal {
     pub read: bool,
     pub write: bool,
@@ -228,20 +563,49 @@ impl From<OpenOptionsInternal> for std::fs::OpenOptions {
     }
 }
 
-#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct OpenRelativeFileRequest {
     pub relative_fd: u64,
     pub path: PathBuf,
     pub open_options: OpenOptionsInternal,
 }
 
+impl_raw_paths_codec!(OpenRelativeFileRequest {
+    relative_fd,
+    path: raw,
+    open_options
+});
+
+/// `openat2` of `path`, relative to the remote directory `dirfd`, with the `RESOLVE_*` flags that
+/// restrict how the path is resolved.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct OpenAt2Request {
+    /// Remote fd of the directory, [`None`] stands for `AT_FDCWD`.
+    pub dirfd: Option<u64>,
+    pub path: PathBuf,
+    pub open_options: OpenOptionsInternal,
+    /// Linux `RESOLVE_*` flags, e.g. `RESOLVE_BENEATH`.
+    pub resolve: u64,
+}
+
+impl_raw_paths_codec!(OpenAt2Request {
+    dirfd,
+    path: raw,
+    open_options,
+    resolve
+});
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadFileRequest {
     pub remote_fd: u64,
     pub buffer_size: u64,
 }
 
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadFileResponse {
     pub bytes: Vec<u8>,
     pub read_amount: u64,
@@ -256,13 +620,70 @@ impl fmt::Debug for ReadFileResponse {
     }
 }
 
+/// Opens, stats, reads and closes the file at `path` in a single round trip, for small files
+/// that are opened read-only (e.g. certificates, tokens and configuration files).
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadWholeFileRequest {
+    pub path: PathBuf,
+    /// Files bigger than this are not read, see [`ReadWholeFileResponse::bytes`].
+    pub max_size: u64,
+}
+
+impl_raw_paths_codec!(ReadWholeFileRequest {
+    path: raw,
+    max_size
+});
+
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadWholeFileResponse {
+    pub metadata: MetadataInternal,
+    /// The whole contents of the file, [`None`] when it's not a regular file, or when it's
+    /// bigger than [`ReadWholeFileRequest::max_size`], then it should be opened as usual.
+    pub bytes: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for ReadWholeFileResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadWholeFileResponse")
+            .field("metadata", &self.metadata)
+            .field("bytes (length)", &self.bytes.as_ref().map(Vec::len))
+            .finish()
+    }
+}
+
+/// Expands the absolute `pattern` in the agent, like `glob(3)` does, instead of listing every
+/// directory it goes through.
+///
+/// `*`, `?` and `[...]` can be used in any path component, and `\` escapes them.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GlobRequest {
+    pub pattern: String,
+    /// See [`GlobResponse::truncated`].
+    pub max_results: u64,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GlobResponse {
+    /// The matching paths, sorted.
+    pub paths: Vec<PathBuf>,
+    /// Whether more than [`GlobRequest::max_results`] paths matched, then only the first ones
+    /// are in [`GlobResponse::paths`].
+    pub truncated: bool,
+}
+
 /// The contents of the symbolic link.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadLinkFileResponse {
     pub path: PathBuf,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadLimitedFileRequest {
     pub remote_fd: u64,
     pub buffer_size: u64,
@@ -270,36 +691,546 @@ pub struct ReadLimitedFileRequest {
 }
 
 /// `path` of the symbolic link we want to resolve.
-#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadLinkFileRequest {
     pub path: PathBuf,
 }
 
+impl_raw_paths_codec!(ReadLinkFileRequest { path: raw });
+
+/// `unlink` of the file at `path`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct UnlinkFileRequest {
+    pub path: PathBuf,
+}
+
+impl_raw_paths_codec!(UnlinkFileRequest { path: raw });
+
+/// `unlinkat` of `path`, relative to the remote directory `dirfd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct UnlinkFileWithDirRequest {
+    /// Remote fd of the directory, [`None`] stands for `AT_FDCWD`.
+    pub dirfd: Option<u64>,
+    pub path: PathBuf,
+    /// Whether to remove a directory instead of a file (the `AT_REMOVEDIR` flag, which has a
+    /// different value on each platform).
+    pub remove_dir: bool,
+}
+
+impl_raw_paths_codec!(UnlinkFileWithDirRequest {
+    dirfd,
+    path: raw,
+    remove_dir
+});
+
+/// `rename` of the file at `old_path` to `new_path`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct RenameFileRequest {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+}
+
+impl_raw_paths_codec!(RenameFileRequest {
+    old_path: raw,
+    new_path: raw
+});
+
+/// `renameat2` (or `renameat`, with no `flags`) of `old_path`, relative to the remote directory
+/// `old_dirfd`, to `new_path`, relative to the remote directory `new_dirfd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct RenameFileWithDirRequest {
+    /// Remote fd of the old directory, [`None`] stands for `AT_FDCWD`.
+    pub old_dirfd: Option<u64>,
+    pub old_path: PathBuf,
+    /// Remote fd of the new directory, [`None`] stands for `AT_FDCWD`.
+    pub new_dirfd: Option<u64>,
+    pub new_path: PathBuf,
+    /// Linux `renameat2` flags, e.g. `RENAME_NOREPLACE`.
+    pub flags: u32,
+}
+
+impl_raw_paths_codec!(RenameFileWithDirRequest {
+    old_dirfd,
+    old_path: raw,
+    new_dirfd,
+    new_path: raw,
+    flags
+});
+
+/// `link` creating a hard link at `new_path` to the file at `old_path`, without following a
+/// symbolic link at `old_path`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct LinkFileRequest {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+}
+
+impl_raw_paths_codec!(LinkFileRequest {
+    old_path: raw,
+    new_path: raw
+});
+
+/// `linkat` of `old_path`, relative to the remote directory `old_dirfd`, to `new_path`, relative
+/// to the remote directory `new_dirfd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct LinkFileWithDirRequest {
+    /// Remote fd of the old directory, [`None`] stands for `AT_FDCWD`.
+    pub old_dirfd: Option<u64>,
+    pub old_path: PathBuf,
+    /// Remote fd of the new directory, [`None`] stands for `AT_FDCWD`.
+    pub new_dirfd: Option<u64>,
+    pub new_path: PathBuf,
+    /// Whether to link the file a symbolic link at `old_path` points to (the `AT_SYMLINK_FOLLOW`
+    /// flag, which has a different value on each platform).
+    pub follow_symlinks: bool,
+}
+
+impl_raw_paths_codec!(LinkFileWithDirRequest {
+    old_dirfd,
+    old_path: raw,
+    new_dirfd,
+    new_path: raw,
+    follow_symlinks
+});
+
+/// `symlink` creating a symbolic link at `link_path` that points to `target`.
+///
+/// `target` is stored as is, so a relative one is relative to the link's directory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct SymlinkRequest {
+    pub target: PathBuf,
+    pub link_path: PathBuf,
+}
+
+impl_raw_paths_codec!(SymlinkRequest {
+    target: raw,
+    link_path: raw
+});
+
+/// `symlinkat` creating a symbolic link at `link_path`, relative to the remote directory
+/// `new_dirfd`, that points to `target`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct SymlinkAtRequest {
+    pub target: PathBuf,
+    /// Remote fd of the link's directory, [`None`] stands for `AT_FDCWD`.
+    pub new_dirfd: Option<u64>,
+    pub link_path: PathBuf,
+}
+
+impl_raw_paths_codec!(SymlinkAtRequest {
+    target: raw,
+    new_dirfd,
+    link_path: raw
+});
+
+/// `chmod` of the file at `path`, following symbolic links.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ChmodFileRequest {
+    pub path: PathBuf,
+    pub mode: u32,
+}
+
+impl_raw_paths_codec!(ChmodFileRequest { path: raw, mode });
+
+/// `fchmod` of the remote file `fd`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FchmodFileRequest {
+    pub fd: u64,
+    pub mode: u32,
+}
+
+/// `fchmodat` of `path`, relative to the remote directory `dirfd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ChmodFileWithDirRequest {
+    /// Remote fd of the directory, [`None`] stands for `AT_FDCWD`.
+    pub dirfd: Option<u64>,
+    pub path: PathBuf,
+    pub mode: u32,
+    /// Linux `fchmodat` flags, i.e. `AT_SYMLINK_NOFOLLOW`.
+    pub flags: u32,
+}
+
+impl_raw_paths_codec!(ChmodFileWithDirRequest {
+    dirfd,
+    path: raw,
+    mode,
+    flags
+});
+
+/// `truncate` of the file at `path` to `length` bytes, following symbolic links.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct TruncateFileRequest {
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+impl_raw_paths_codec!(TruncateFileRequest { path: raw, length });
+
+/// `ftruncate` of the remote file `fd` to `length` bytes.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FtruncateFileRequest {
+    pub fd: u64,
+    pub length: u64,
+}
+
+/// `fallocate` of `length` bytes at `offset` of the remote file `fd`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FallocateFileRequest {
+    pub fd: u64,
+    /// Linux `fallocate` flags, e.g. `FALLOC_FL_KEEP_SIZE` or `FALLOC_FL_PUNCH_HOLE`.
+    pub mode: u32,
+    pub offset: u64,
+    pub length: u64,
+    /// The range is filled with zeros when the filesystem doesn't support preallocation, as
+    /// `posix_fallocate` does. `mode` is `0` then.
+    pub posix: bool,
+}
+
+/// `copy_file_range` of up to `length` bytes from the remote file `fd_in` to the remote file
+/// `fd_out`, the data never leaves the agent.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct CopyFileRangeRequest {
+    pub fd_in: u64,
+    /// [`None`] reads from the file offset of `fd_in`, and advances it.
+    pub offset_in: Option<u64>,
+    pub fd_out: u64,
+    /// [`None`] writes at the file offset of `fd_out`, and advances it.
+    pub offset_out: Option<u64>,
+    pub length: u64,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct CopyFileRangeResponse {
+    /// Can be less than the requested length, `0` at the end of `fd_in`.
+    pub copied_amount: u64,
+}
+
+/// `sendfile` of up to `count` bytes from the remote file `fd` to the connection `connection_id`.
+///
+/// The connection is one that the agent made or stole for the client. The data never leaves the
+/// agent, it's written to the connection like the client's own writes.
+///
+/// Sent in [`LayerTcpOutgoing::SendFile`](crate::outgoing::tcp::LayerTcpOutgoing::SendFile)
+/// and [`LayerTcpSteal::SendFile`](crate::tcp::LayerTcpSteal::SendFile).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct SendFileRequest {
+    pub connection_id: ConnectionId,
+    pub fd: u64,
+    /// [`None`] reads from the file offset of `fd`, and advances it.
+    pub offset: Option<u64>,
+    pub count: u64,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct SendFileResponse {
+    /// Can be less than the requested count, `0` at the end of the file.
+    pub sent_amount: u64,
+}
+
+/// `readv` (or `preadv`) of the remote file `fd` into buffers of the given `lengths`, with a
+/// single read in the agent.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadVFileRequest {
+    pub fd: u64,
+    pub lengths: Vec<u64>,
+    /// [`None`] reads from the file offset of `fd`, and advances it.
+    pub offset: Option<u64>,
+}
+
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadVFileResponse {
+    /// The filled buffers, in order. Only the last one can be shorter than its requested length,
+    /// and there are fewer buffers than requested when the read is short, none at the end of the
+    /// file.
+    pub buffers: Vec<Vec<u8>>,
+}
+
+impl fmt::Debug for ReadVFileResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadVFileResponse")
+            .field(
+                "buffers (lengths)",
+                &self.buffers.iter().map(Vec::len).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// `writev` (or `pwritev`) of `buffers` to the remote file `fd`, with a single write in the agent.
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct WriteVFileRequest {
+    pub fd: u64,
+    pub buffers: Vec<Vec<u8>>,
+    /// [`None`] writes at the file offset of `fd`, and advances it.
+    pub offset: Option<u64>,
+}
+
+impl fmt::Debug for WriteVFileRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteVFileRequest")
+            .field("fd", &self.fd)
+            .field(
+                "buffers (lengths)",
+                &self.buffers.iter().map(Vec::len).collect::<Vec<_>>(),
+            )
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+/// Read of up to `length` bytes of the remote file `fd`, streamed in chunks.
+///
+/// The agent sends the bytes back as [`ReadChunkResponse`]s of up to `chunk_size` bytes, so that
+/// big reads are never held in memory (or sent) as a whole.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadStreamFileRequest {
+    pub fd: u64,
+    pub length: u64,
+    /// [`None`] reads from the file offset of `fd`, and advances it.
+    pub offset: Option<u64>,
+    /// The agent may send smaller chunks, e.g. to fit in the frames of the client.
+    pub chunk_size: u64,
+}
+
+/// One piece of the stream of a [`ReadStreamFileRequest`].
+///
+/// The stream ends with the chunk marked as `last`, or with an error, which leaves the bytes of
+/// the previous chunks as a short read.
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadChunkResponse {
+    /// Position of this chunk in the stream, starting from `0`.
+    pub sequence: u64,
+    pub bytes: Vec<u8>,
+    /// Set after the requested length is read, or at the end of the file.
+    pub last: bool,
+}
+
+impl fmt::Debug for ReadChunkResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadChunkResponse")
+            .field("sequence", &self.sequence)
+            .field("bytes (length)", &self.bytes.len())
+            .field("last", &self.last)
+            .finish()
+    }
+}
+
+/// Follows the remote file `fd` like `tail -f`, from its file offset.
+///
+/// The agent keeps sending the bytes appended to the file as [`FollowChunkResponse`]s, until the
+/// follow is stopped with [`UnfollowFileRequest`]. The file offset of `fd` is left as is while
+/// following, as the client may not use all the bytes.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FollowFileRequest {
+    /// Chosen by the client, tells the chunks of this follow apart from the ones of a previous
+    /// follow of the same `fd`.
+    pub id: u64,
+    pub fd: u64,
+}
+
+/// Stops the follow `id`, see [`FollowFileRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct UnfollowFileRequest {
+    pub id: u64,
+    /// Bytes of the chunks that the client used, the file offset is advanced by this much.
+    pub consumed: u64,
+}
+
+/// Bytes appended to the file of the follow `id`, see [`FollowFileRequest`].
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FollowChunkResponse {
+    pub id: u64,
+    pub bytes: Vec<u8>,
+    /// Set when the agent stopped following on its own (e.g. the file was closed, or failed to
+    /// read), the client should read from the agent again.
+    pub last: bool,
+}
+
+impl fmt::Debug for FollowChunkResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FollowChunkResponse")
+            .field("id", &self.id)
+            .field("bytes (length)", &self.bytes.len())
+            .field("last", &self.last)
+            .finish()
+    }
+}
+
+/// `chown` of the file at `path`, following symbolic links.
+///
+/// [`None`] leaves the owner (or group) as is, like `-1` does.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ChownFileRequest {
+    pub path: PathBuf,
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+}
+
+impl_raw_paths_codec!(ChownFileRequest {
+    path: raw,
+    owner,
+    group
+});
+
+/// `fchown` of the remote file `fd`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FchownFileRequest {
+    pub fd: u64,
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+}
+
+/// `fchownat` (or `lchown`) of `path`, relative to the remote directory `dirfd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ChownFileWithDirRequest {
+    /// Remote fd of the directory, [`None`] stands for `AT_FDCWD`.
+    pub dirfd: Option<u64>,
+    pub path: PathBuf,
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+    /// Linux `fchownat` flags, e.g. `AT_SYMLINK_NOFOLLOW`.
+    pub flags: u32,
+}
+
+impl_raw_paths_codec!(ChownFileWithDirRequest {
+    dirfd,
+    path: raw,
+    owner,
+    group,
+    flags
+});
+
+/// New access (or modification) time of a file, like a `timespec` of `utimensat`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum FileTimeInternal {
+    /// Leaves the time as is, `UTIME_OMIT`.
+    Omit,
+    /// Current time of the remote, `UTIME_NOW`.
+    Now,
+    /// Nanoseconds since the epoch, like the times of [`MetadataInternal`].
+    At(i64),
+}
+
+/// `utimensat` of `path`, relative to the remote directory `dirfd`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct UtimensFileWithDirRequest {
+    /// Remote fd of the directory, [`None`] stands for `AT_FDCWD`.
+    pub dirfd: Option<u64>,
+    pub path: PathBuf,
+    pub access_time: FileTimeInternal,
+    pub modification_time: FileTimeInternal,
+    /// Linux `utimensat` flags, i.e. `AT_SYMLINK_NOFOLLOW`.
+    pub flags: u32,
+}
+
+impl_raw_paths_codec!(UtimensFileWithDirRequest {
+    dirfd,
+    path: raw,
+    access_time,
+    modification_time,
+    flags
+});
+
+/// `futimens` of the remote file `fd`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FutimensFileRequest {
+    pub fd: u64,
+    pub access_time: FileTimeInternal,
+    pub modification_time: FileTimeInternal,
+}
+
+/// `fsync` of the remote file `fd`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FsyncFileRequest {
+    pub fd: u64,
+    /// Only the data is flushed, as in `fdatasync`.
+    pub data_only: bool,
+}
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct SeekFileRequest {
     pub fd: u64,
     pub seek_from: SeekFromInternal,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct SeekFileResponse {
     pub result_offset: u64,
 }
 
 /// Alternative to `std::io::SeekFrom`, used to implement `bincode::Encode` and `bincode::Decode`.
+///
+/// Also has the `SEEK_DATA` and `SEEK_HOLE` whences of `lseek`, that skip over the holes of sparse
+/// files.
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum SeekFromInternal {
     Start(u64),
     End(i64),
     Current(i64),
+    /// `SEEK_DATA`, the start of the first data at or after the offset.
+    ///
+    /// Should only be sent to agents that support [`SEEK_HOLE_VERSION`], and is not valid in a
+    /// [`FileLockInternal`].
+    Data(u64),
+    /// `SEEK_HOLE`, the start of the first hole at or after the offset. The end of the file counts
+    /// as a hole.
+    ///
+    /// Should only be sent to agents that support [`SEEK_HOLE_VERSION`], and is not valid in a
+    /// [`FileLockInternal`].
+    Hole(u64),
 }
 
-impl From<SeekFromInternal> for SeekFrom {
-    fn from(seek_from: SeekFromInternal) -> Self {
+impl TryFrom<SeekFromInternal> for SeekFrom {
+    type Error = io::Error;
+
+    /// Fails for [`SeekFromInternal::Data`] and [`SeekFromInternal::Hole`], that have no
+    /// [`SeekFrom`].
+    fn try_from(seek_from: SeekFromInternal) -> Result<Self, Self::Error> {
         match seek_from {
-            SeekFromInternal::Start(start) => SeekFrom::Start(start),
-            SeekFromInternal::End(end) => SeekFrom::End(end),
-            SeekFromInternal::Current(current) => SeekFrom::Current(current),
+            SeekFromInternal::Start(start) => Ok(SeekFrom::Start(start)),
+            SeekFromInternal::End(end) => Ok(SeekFrom::End(end)),
+            SeekFromInternal::Current(current) => Ok(SeekFrom::Current(current)),
+            SeekFromInternal::Data(..) | SeekFromInternal::Hole(..) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SEEK_DATA and SEEK_HOLE have no std::io::SeekFrom",
+            )),
         }
     }
 }
@@ -315,6 +1246,7 @@ impl From<SeekFrom> for SeekFromInternal {
 }
 
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct WriteFileRequest {
     pub fd: u64,
     pub write_bytes: Vec<u8>,
@@ -330,11 +1262,13 @@ impl fmt::Debug for WriteFileRequest {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct WriteFileResponse {
     pub written_amount: u64,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct WriteLimitedFileRequest {
     pub remote_fd: u64,
     pub start_from: u64,
@@ -342,58 +1276,266 @@ pub struct WriteLimitedFileRequest {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct CloseFileRequest {
     pub fd: u64,
 }
 
-#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct AccessFileRequest {
     pub pathname: PathBuf,
     pub mode: u8,
 }
 
+impl_raw_paths_codec!(AccessFileRequest {
+    pathname: raw,
+    mode
+});
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct AccessFileResponse;
 
-#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct XstatRequest {
     pub path: Option<PathBuf>,
     pub fd: Option<u64>,
     pub follow_symlink: bool,
 }
 
+impl_raw_paths_codec!(XstatRequest {
+    path: raw,
+    fd,
+    follow_symlink
+});
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct XstatFsRequest {
     pub fd: u64,
 }
 
+/// `statfs` of the filesystem that `path` is on, for paths that were not opened (e.g. checking
+/// the free space before writing). The agent responds with [`FileResponse::XstatFs`].
+///
+/// [`FileResponse::XstatFs`]: crate::FileResponse::XstatFs
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct StatFsRequest {
+    pub path: PathBuf,
+}
+
+impl_raw_paths_codec!(StatFsRequest { path: raw });
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct XstatResponse {
     pub metadata: MetadataInternal,
 }
 
+/// `statx` of `path`, relative to the remote directory `dirfd`, or of `dirfd` itself when there's
+/// no `path` (`AT_EMPTY_PATH`).
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct StatxRequest {
+    /// Remote fd, [`None`] stands for `AT_FDCWD`.
+    pub dirfd: Option<u64>,
+    pub path: Option<PathBuf>,
+    pub follow_symlink: bool,
+    /// Linux `STATX_*` flags of the fields the caller wants.
+    pub mask: u32,
+}
+
+impl_raw_paths_codec!(StatxRequest {
+    dirfd,
+    path: raw,
+    follow_symlink,
+    mask
+});
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct StatxResponse {
+    /// Boxed, it's much bigger than the other responses.
+    pub metadata: Box<StatxMetadataInternal>,
+}
+
+/// `getxattr` of the remote `path`, or of the remote file `fd` when there's no `path`.
+///
+/// With `follow_symlink` unset it's `lgetxattr`, and the attribute of the link itself is read.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetXattrRequest {
+    pub path: Option<PathBuf>,
+    pub fd: Option<u64>,
+    pub follow_symlink: bool,
+    /// Full name of the attribute, with the namespace, e.g. `user.mime_type`.
+    pub name: Vec<u8>,
+}
+
+impl_raw_paths_codec!(GetXattrRequest {
+    path: raw,
+    fd,
+    follow_symlink,
+    name
+});
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetXattrResponse {
+    pub value: Vec<u8>,
+}
+
+/// `setxattr` of the remote `path`, or of the remote file `fd` when there's no `path`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct SetXattrRequest {
+    pub path: Option<PathBuf>,
+    pub fd: Option<u64>,
+    pub follow_symlink: bool,
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+    /// `XATTR_CREATE` or `XATTR_REPLACE`, `0` for neither.
+    pub flags: i32,
+}
+
+impl_raw_paths_codec!(SetXattrRequest {
+    path: raw,
+    fd,
+    follow_symlink,
+    name,
+    value,
+    flags
+});
+
+/// `listxattr` of the remote `path`, or of the remote file `fd` when there's no `path`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ListXattrRequest {
+    pub path: Option<PathBuf>,
+    pub fd: Option<u64>,
+    pub follow_symlink: bool,
+}
+
+impl_raw_paths_codec!(ListXattrRequest {
+    path: raw,
+    fd,
+    follow_symlink
+});
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ListXattrResponse {
+    /// Names of the attributes, without the nul terminators.
+    pub names: Vec<Vec<u8>>,
+}
+
+/// `removexattr` of the remote `path`, or of the remote file `fd` when there's no `path`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct RemoveXattrRequest {
+    pub path: Option<PathBuf>,
+    pub fd: Option<u64>,
+    pub follow_symlink: bool,
+    pub name: Vec<u8>,
+}
+
+impl_raw_paths_codec!(RemoveXattrRequest {
+    path: raw,
+    fd,
+    follow_symlink,
+    name
+});
+
+/// The kind of an advisory lock, the `LOCK_SH`/`LOCK_EX`/`LOCK_UN` of `flock` and the
+/// `F_RDLCK`/`F_WRLCK`/`F_UNLCK` of `fcntl`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum LockTypeInternal {
+    Shared,
+    Exclusive,
+    Unlock,
+}
+
+/// A byte range lock of `fcntl`, like `struct flock`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FileLockInternal {
+    pub lock_type: LockTypeInternal,
+    /// `l_whence` and `l_start`, the position of the remote file is used for
+    /// [`SeekFromInternal::Current`].
+    pub start: SeekFromInternal,
+    /// `0` locks up to the end of the file, however long it gets.
+    pub len: i64,
+}
+
+/// `flock` of the remote file `fd`.
+///
+/// The agent never waits for the lock, a lock held by someone else fails with `EWOULDBLOCK`,
+/// and the client retries when it wants to wait.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FlockRequest {
+    pub fd: u64,
+    pub lock_type: LockTypeInternal,
+}
+
+/// `fcntl` `F_SETLK` of the remote file `fd`.
+///
+/// The lock belongs to the remote file, not to a process (as with `F_OFD_SETLK`), and like in
+/// [`FlockRequest`] the agent never waits for it.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct SetLockRequest {
+    pub fd: u64,
+    pub lock: FileLockInternal,
+}
+
+/// `fcntl` `F_GETLK` of the remote file `fd`, the first lock that would conflict with `lock`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetLockRequest {
+    pub fd: u64,
+    pub lock: FileLockInternal,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetLockResponse {
+    /// [`None`] when `lock` could be placed, the start of a conflicting lock is always a
+    /// [`SeekFromInternal::Start`].
+    pub conflict: Option<FileLockInternal>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct XstatFsResponse {
     pub metadata: FsMetadataInternal,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct FdOpenDirRequest {
     pub remote_fd: u64,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct OpenDirResponse {
     pub fd: u64,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadDirRequest {
     pub remote_fd: u64,
 }
 
 /// `readdir` message that requests an iterable with `amount` items from the agent.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadDirBatchRequest {
     /// The fd of the dir in the agent.
     pub remote_fd: u64,
@@ -401,7 +1543,20 @@ pub struct ReadDirBatchRequest {
     pub amount: usize,
 }
 
+/// Redirects the file operations on paths that match any of the `patterns` into a scratch
+/// directory in the target, which the agent creates for the session and deletes when the session
+/// ends.
+///
+/// The agent does not respond to this request.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ScratchDirRequest {
+    /// Case insensitive regexes, matched against absolute paths.
+    pub patterns: Vec<String>,
+}
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadDirResponse {
     pub direntry: Option<DirEntryInternal>,
 }
@@ -409,6 +1564,7 @@ pub struct ReadDirResponse {
 /// `readdir` response with the list of items (length depends on the [`ReadDirBatchRequest`]'s
 /// `amount`), and the `remote_fd` of the dir (for convenience).
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ReadDirBatchResponse {
     /// Remote fd of the dir.
     pub fd: u64,
@@ -417,20 +1573,366 @@ pub struct ReadDirBatchResponse {
     pub dir_entries: Vec<DirEntryInternal>,
 }
 
+/// [`ReadDirBatchRequest`] that also gets the metadata of every entry.
+///
+/// Listing a directory and stating its entries (e.g. `ls -l`, globbing, classpath scans) takes a
+/// single round trip, instead of one more per entry.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadDirPlusBatchRequest {
+    /// The fd of the dir in the agent.
+    pub remote_fd: u64,
+    /// Max amount to take from the agent's iterator of dirs.
+    pub amount: usize,
+}
+
+/// [`ReadDirBatchResponse`] with the metadata of the entries.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadDirPlusBatchResponse {
+    /// Remote fd of the dir.
+    pub fd: u64,
+    /// At most [`ReadDirPlusBatchRequest::amount`] entries, none at the end of the directory.
+    pub dir_entries: Vec<DirEntryPlusInternal>,
+}
+
+/// Reads the whole remote directory tree at `path` in a single pass: the entries of every
+/// directory, with their metadata, and the contents of the small regular files.
+///
+/// The agent responds with [`PrefetchChunkResponse`]s, as it walks the tree. Symbolic links are
+/// not followed. Apps that scan big trees at startup (e.g. a JVM classpath, Python packages)
+/// then find them in the client, instead of making a round trip per file.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct PrefetchTreeRequest {
+    /// Chosen by the client, tells the chunks of this prefetch apart from the ones of the others.
+    pub id: u64,
+    /// Absolute path of the root of the tree.
+    pub path: PathBuf,
+    /// Regular files of at most this many bytes are sent with their contents, `0` sends none.
+    pub max_file_size: u64,
+    /// The agent stops walking the tree before it would send more entries than this, the
+    /// directories are only sent whole.
+    pub max_entries: u64,
+}
+
+impl_raw_paths_codec!(PrefetchTreeRequest {
+    id,
+    path: raw,
+    max_file_size,
+    max_entries
+});
+
+/// A directory of the tree of a [`PrefetchTreeRequest`], with all of its entries.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct PrefetchDirInternal {
+    /// Absolute path of the directory, under [`PrefetchTreeRequest::path`].
+    pub path: PathBuf,
+    /// Metadata of the directory itself.
+    pub metadata: MetadataInternal,
+    pub entries: Vec<PrefetchEntryInternal>,
+}
+
+/// An entry of a [`PrefetchDirInternal`].
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct PrefetchEntryInternal {
+    pub entry: DirEntryPlusInternal,
+    /// Whole contents of the entry, when it's a regular file of at most
+    /// [`PrefetchTreeRequest::max_file_size`] bytes.
+    pub contents: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for PrefetchEntryInternal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrefetchEntryInternal")
+            .field("entry", &self.entry)
+            .field("contents (length)", &self.contents.as_ref().map(Vec::len))
+            .finish()
+    }
+}
+
+/// Directories of the tree of the prefetch `id`, see [`PrefetchTreeRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct PrefetchChunkResponse {
+    pub id: u64,
+    /// Complete directories, the ones that failed to read are left out.
+    pub dirs: Vec<PrefetchDirInternal>,
+    /// Set on the last chunk of the prefetch, which is the only one when the root can't be read.
+    pub last: bool,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct CloseDirRequest {
     pub remote_fd: u64,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct GetDEnts64Request {
     pub remote_fd: u64,
     pub buffer_size: u64,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct GetDEnts64Response {
     pub fd: u64,
     pub entries: Vec<DirEntryInternal>,
     pub result_size: u64,
 }
+
+/// Lists the remaining entries of the remote directory `remote_fd` at once, sending only the
+/// changes since the listing identified by `since`.
+///
+/// Tools that poll a big directory (e.g. file watchers) list it over and over, while only a few
+/// entries change between the listings. The agent keeps the last listings it sent, and responds
+/// with a [`DirListingDelta::Changes`] when it still has the one identified by `since`, or with
+/// the [`DirListingDelta::Full`] listing otherwise.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadDirDeltaRequest {
+    /// The fd of the dir in the agent.
+    pub remote_fd: u64,
+    /// [`ReadDirDeltaResponse::cookie`] of a previous listing of the same directory.
+    pub since: Option<u64>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReadDirDeltaResponse {
+    /// Remote fd of the dir.
+    pub fd: u64,
+    /// Identifies this listing, for the [`ReadDirDeltaRequest::since`] of the next one.
+    pub cookie: u64,
+    pub delta: DirListingDelta,
+}
+
+/// The entries of a directory, or how they changed since a previous listing, see
+/// [`ReadDirDeltaRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum DirListingDelta {
+    /// All the entries of the directory.
+    Full(Vec<DirEntryInternal>),
+    /// The entries that were added, and the names of the entries that were removed.
+    ///
+    /// An entry that was replaced (e.g. a file that was deleted and created again) is both
+    /// removed and added.
+    Changes {
+        added: Vec<DirEntryInternal>,
+        removed: Vec<Vec<u8>>,
+    },
+}
+
+impl DirListingDelta {
+    /// The changes from the `previous` entries of a directory to the `current` ones.
+    pub fn between(previous: &[DirEntryInternal], current: &[DirEntryInternal]) -> Self {
+        let same_entry = |entry: &DirEntryInternal, other: &DirEntryInternal| {
+            entry.inode == other.inode && entry.file_type == other.file_type
+        };
+
+        let previous_by_name = previous
+            .iter()
+            .map(|entry| (entry.name.as_slice(), entry))
+            .collect::<HashMap<_, _>>();
+        let current_by_name = current
+            .iter()
+            .map(|entry| (entry.name.as_slice(), entry))
+            .collect::<HashMap<_, _>>();
+
+        let added = current
+            .iter()
+            .filter(|entry| {
+                previous_by_name
+                    .get(entry.name.as_slice())
+                    .is_none_or(|old| !same_entry(old, entry))
+            })
+            .cloned()
+            .collect();
+        let removed = previous
+            .iter()
+            .filter(|old| {
+                current_by_name
+                    .get(old.name.as_slice())
+                    .is_none_or(|entry| !same_entry(old, entry))
+            })
+            .map(|old| old.name.clone())
+            .collect();
+
+        Self::Changes { added, removed }
+    }
+
+    /// Applies these changes to the `previous` entries of the directory, and returns its current
+    /// entries, with their [`DirEntryInternal::position`]s in the returned order.
+    pub fn apply(self, previous: Vec<DirEntryInternal>) -> Vec<DirEntryInternal> {
+        let mut entries = match self {
+            Self::Full(entries) => entries,
+            Self::Changes { added, removed } => {
+                let removed = removed.into_iter().collect::<HashSet<_>>();
+
+                previous
+                    .into_iter()
+                    .filter(|entry| !removed.contains(&entry.name))
+                    .chain(added)
+                    .collect()
+            }
+        };
+
+        for (position, entry) in entries.iter_mut().enumerate() {
+            entry.position = position as u64;
+        }
+
+        entries
+    }
+}
+
+/// `realpath` of the absolute `path`, resolved in the remote filesystem.
+///
+/// The `.` and `..` components, the symbolic links (and the ones they point to), and the mount
+/// points of the target are resolved by the agent, as `..` after a symbolic link can't be
+/// resolved without it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct CanonicalizePathRequest {
+    pub path: PathBuf,
+}
+
+impl_raw_paths_codec!(CanonicalizePathRequest { path: raw });
+
+/// The canonical absolute path, fails like `realpath` when a component doesn't exist, isn't a
+/// directory, or there are too many symbolic links.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct CanonicalizePathResponse {
+    pub path: PathBuf,
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        ffi::OsStr,
+        os::unix::{ffi::OsStrExt, fs::MetadataExt},
+        path::PathBuf,
+    };
+
+    use super::{
+        DirEntryInternal, DirListingDelta, MetadataInternal, OpenFileRequest, OpenOptionsInternal,
+        XstatRequest,
+    };
+
+    fn entry(name: &str, inode: u64) -> DirEntryInternal {
+        DirEntryInternal {
+            inode,
+            position: 0,
+            name: name.as_bytes().to_vec(),
+            file_type: libc::DT_REG,
+        }
+    }
+
+    /// Timestamps must carry the whole time in nanoseconds, not only the sub-second part.
+    #[test]
+    fn metadata_timestamps_in_nanos() {
+        let metadata = std::fs::metadata(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let internal = MetadataInternal::from(metadata.clone());
+
+        assert_eq!(internal.modification_time / 1_000_000_000, metadata.mtime());
+        assert_eq!(
+            internal.modification_time % 1_000_000_000,
+            metadata.mtime_nsec()
+        );
+        assert_eq!(internal.access_time / 1_000_000_000, metadata.atime());
+        assert_eq!(internal.creation_time / 1_000_000_000, metadata.ctime());
+        assert_eq!(internal.rdevice_id, metadata.rdev());
+        assert_eq!(internal.block_size, metadata.blksize());
+    }
+
+    /// Only the changed entries are sent, and applying them to the previous listing gives the
+    /// current one.
+    #[test]
+    fn dir_listing_delta() {
+        let previous = vec![entry("a.log", 1), entry("b.log", 2), entry("c.log", 3)];
+        let current = vec![entry("a.log", 1), entry("c.log", 30), entry("d.log", 4)];
+
+        let delta = DirListingDelta::between(&previous, &current);
+        assert_eq!(
+            delta,
+            DirListingDelta::Changes {
+                added: vec![entry("c.log", 30), entry("d.log", 4)],
+                removed: vec![b"b.log".to_vec(), b"c.log".to_vec()],
+            }
+        );
+
+        let entries = delta.apply(previous.clone());
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.name.as_slice(), entry.inode, entry.position))
+                .collect::<Vec<_>>(),
+            [
+                (b"a.log".as_slice(), 1, 0),
+                (b"c.log".as_slice(), 30, 1),
+                (b"d.log".as_slice(), 4, 2),
+            ]
+        );
+
+        assert_eq!(
+            DirListingDelta::between(&previous, &previous),
+            DirListingDelta::Changes {
+                added: vec![],
+                removed: vec![],
+            }
+        );
+        assert_eq!(
+            DirListingDelta::Full(current.clone()).apply(previous),
+            DirListingDelta::Full(current).apply(vec![])
+        );
+    }
+
+    /// Paths that are valid UTF-8 are encoded like the [`String`]s that older agents decode.
+    #[test]
+    fn utf8_path_encoded_as_string() {
+        let config = bincode::config::standard();
+        let open_options = OpenOptionsInternal {
+            read: true,
+            ..Default::default()
+        };
+        let request = OpenFileRequest {
+            path: PathBuf::from("/etc/hosts"),
+            open_options,
+        };
+
+        assert_eq!(
+            bincode::encode_to_vec(&request, config).unwrap(),
+            bincode::encode_to_vec(("/etc/hosts".to_string(), open_options), config).unwrap()
+        );
+    }
+
+    #[test]
+    fn non_utf8_path_round_trip() {
+        let config = bincode::config::standard();
+        let path = PathBuf::from(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        let requests = [
+            XstatRequest {
+                path: Some(path),
+                fd: None,
+                follow_symlink: true,
+            },
+            XstatRequest {
+                path: None,
+                fd: Some(3),
+                follow_symlink: false,
+            },
+        ];
+
+        for request in requests {
+            let encoded = bincode::encode_to_vec(&request, config).unwrap();
+            let (decoded, _): (XstatRequest, _) =
+                bincode::decode_from_slice(&encoded, config).unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+}
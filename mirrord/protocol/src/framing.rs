@@ -0,0 +1,124 @@
+//! Framing of the messages on the wire.
+//!
+//! Initially, the [`ProtocolCodec`](crate::ProtocolCodec) writes messages back to back, and the
+//! end of a message is known only after decoding it. This means that a big message is decoded
+//! over and over until all of it arrives, and there's no upper bound on what the peer can make us
+//! buffer.
+//!
+//! Peers that support [`FRAMING_VERSION`] can switch to length-prefixed frames:
+//!
+//! 1. The client sends [`ClientMessage::SwitchFraming`](crate::ClientMessage::SwitchFraming) with
+//!    its [`FrameLimits`];
+//! 2. The agent responds with
+//!    [`DaemonMessage::SwitchFramingResponse`](crate::DaemonMessage::SwitchFramingResponse) and its
+//!    own [`FrameLimits`].
+//!
+//! The switch messages themselves are not framed. After a peer encodes one, everything it sends
+//! is framed, and after it decodes one, everything it receives is framed. This is handled by the
+//! codec, so a proxy in the middle that just forwards the messages switches both of its
+//! connections at the same points of the streams.
+//!
+//! Each frame is the length of the encoded message, as [`FRAME_HEADER_SIZE`] big-endian bytes,
+//! followed by the message. The length can go past 4GB, up to the [`FrameLimits::max_frame_size`]
+//! of the receiving peer, which rejects bigger frames. Payloads that can be bigger than that
+//! should be sent in pieces, see [`FrameLimits::chunks`].
+use std::sync::LazyLock;
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::SwitchFraming`](crate::ClientMessage::SwitchFraming).
+pub static FRAMING_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.18.0".parse().expect("Bad Identifier"));
+
+/// Size of the length prefix of a frame.
+pub const FRAME_HEADER_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Default for [`FrameLimits::max_frame_size`], 8GiB.
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+
+/// How much of a frame [`FrameLimits::max_chunk_size`] leaves for the message that carries the
+/// chunk (e.g. the file descriptor of a write request).
+pub const CHUNK_ENVELOPE_SIZE: u64 = 4 * 1024;
+
+/// Limits of the frames that a peer accepts, sent when switching to framed messages.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct FrameLimits {
+    /// Maximum length of a frame, without the [`FRAME_HEADER_SIZE`].
+    pub max_frame_size: u64,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl FrameLimits {
+    /// Maximum size of a payload that fits in a single message, leaving [`CHUNK_ENVELOPE_SIZE`]
+    /// for the rest of the message.
+    pub fn max_chunk_size(&self) -> usize {
+        let size = self
+            .max_frame_size
+            .saturating_sub(CHUNK_ENVELOPE_SIZE)
+            .max(1);
+
+        usize::try_from(size).unwrap_or(usize::MAX)
+    }
+
+    /// Splits `payload` into pieces that fit in the frames of the peer, see
+    /// [`Self::max_chunk_size`].
+    pub fn chunks<'a>(&self, payload: &'a [u8]) -> std::slice::Chunks<'a, u8> {
+        payload.chunks(self.max_chunk_size())
+    }
+}
+
+/// Implemented by the messages that switch the framing of a connection, see the
+/// [module docs](self).
+pub trait FramingSwitch {
+    /// Returns the [`FrameLimits`] of the sender, if this message switches the framing.
+    fn framing_switch(&self) -> Option<FrameLimits>;
+}
+
+/// How a [`ProtocolCodec`](crate::ProtocolCodec) writes or reads one direction of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Framing {
+    /// Messages are written back to back.
+    #[default]
+    Unframed,
+    /// Messages are written in length-prefixed frames.
+    LengthPrefixed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_fit_in_frames() {
+        let limits = FrameLimits {
+            max_frame_size: CHUNK_ENVELOPE_SIZE + 10,
+        };
+        let payload = [7u8; 25];
+
+        let chunks = limits.chunks(&payload).collect::<Vec<_>>();
+
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(),
+            [10, 10, 5]
+        );
+        assert_eq!(chunks.concat(), payload);
+    }
+
+    #[test]
+    fn tiny_frames_still_chunk() {
+        let limits = FrameLimits { max_frame_size: 1 };
+
+        assert_eq!(limits.max_chunk_size(), 1);
+        assert_eq!(limits.chunks(&[1, 2, 3]).count(), 3);
+    }
+}
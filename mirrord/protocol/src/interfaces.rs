@@ -0,0 +1,65 @@
+//! Listing the network interfaces of the target, for `getifaddrs` in the local process.
+//!
+//! Apps that advertise their own address (e.g. to a service mesh or a cluster membership
+//! protocol) find it with `getifaddrs`. Peers that support [`GET_IFADDRS_VERSION`] can send
+//! [`ClientMessage::GetIfAddrsRequest`](crate::ClientMessage::GetIfAddrsRequest), and the agent
+//! responds with [`DaemonMessage::GetIfAddrsResponse`](crate::DaemonMessage::GetIfAddrsResponse),
+//! carrying the addresses of the interfaces in the network namespace of the target.
+use std::{net::IpAddr, sync::LazyLock};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+use crate::RemoteResult;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::GetIfAddrsRequest`](crate::ClientMessage::GetIfAddrsRequest).
+pub static GET_IFADDRS_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.50.0".parse().expect("Bad Identifier"));
+
+/// Triggered by the `mirrord-layer` hook of `getifaddrs_detour`.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetIfAddrsRequest;
+
+/// The flags of a network interface (`ifa_flags`) that apps look at when they pick an address.
+///
+/// Kept platform independent, as the value of `IFF_MULTICAST` differs between Linux and macOS.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct InterfaceFlags {
+    /// `IFF_UP`.
+    pub up: bool,
+    /// `IFF_RUNNING`.
+    pub running: bool,
+    /// `IFF_LOOPBACK`.
+    pub loopback: bool,
+    /// `IFF_POINTOPOINT`.
+    pub point_to_point: bool,
+    /// `IFF_BROADCAST`.
+    pub broadcast: bool,
+    /// `IFF_MULTICAST`.
+    pub multicast: bool,
+}
+
+/// An IP address of a network interface of the target, one entry of the list returned by
+/// `getifaddrs`.
+///
+/// The link-layer entries are not sent.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct InterfaceAddress {
+    /// Name of the interface, e.g. `eth0`.
+    pub name: String,
+    pub flags: InterfaceFlags,
+    pub address: IpAddr,
+    pub netmask: Option<IpAddr>,
+    /// The broadcast address of the interface, or the address of the other end of a
+    /// point-to-point interface, depending on [`InterfaceFlags`] (`ifa_ifu`).
+    pub broadcast: Option<IpAddr>,
+}
+
+/// Response to [`GetIfAddrsRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct GetIfAddrsResponse(pub RemoteResult<Vec<InterfaceAddress>>);
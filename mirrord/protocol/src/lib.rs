@@ -3,14 +3,25 @@
 #![warn(clippy::indexing_slicing)]
 
 pub mod body_chunks;
+pub mod clock;
 pub mod codec;
+pub mod compression;
 pub mod dns;
 pub mod error;
+pub mod fd_budget;
 pub mod file;
+pub mod framing;
+pub mod interfaces;
 pub mod outgoing;
+pub mod output;
 pub mod pause;
+pub mod reachability;
 pub mod tcp;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod versioned;
 pub mod vpn;
+pub mod watch;
 
 use core::fmt;
 use std::{collections::HashSet, ops::Deref, str::FromStr, sync::LazyLock};
@@ -110,3 +121,36 @@ impl FromStr for MeshVendor {
 pub const AGENT_OPERATOR_CERT_ENV: &str = "MIRRORD_AGENT_OPERATOR_CERT";
 
 pub const AGENT_NETWORK_INTERFACE_ENV: &str = "MIRRORD_AGENT_INTERFACE";
+
+/// Name of environment variable that can be used to limit the file operations per second that
+/// the agent serves for each client. Set by the operator.
+pub const AGENT_FILE_OPS_RATE_LIMIT_ENV: &str = "MIRRORD_AGENT_FILE_OPS_RATE_LIMIT";
+
+/// Name of environment variable that can be used to limit the DNS requests per second that the
+/// agent serves for each client. Set by the operator.
+pub const AGENT_DNS_RATE_LIMIT_ENV: &str = "MIRRORD_AGENT_DNS_RATE_LIMIT";
+
+/// Name of environment variable that can be used to limit the remote files, directories and
+/// sockets that the agent keeps open for each client, see [`fd_budget`]. Set by the operator.
+pub const AGENT_FD_LIMIT_ENV: &str = "MIRRORD_AGENT_FD_LIMIT";
+
+/// Name of environment variable that can be used to set the seconds after which the remote files
+/// and directories that a client doesn't use are reported to it, see [`fd_budget`]. Set by the
+/// operator.
+pub const AGENT_FD_IDLE_TIMEOUT_ENV: &str = "MIRRORD_AGENT_FD_IDLE_TIMEOUT";
+
+/// Name of environment variable that can be used to set the bytes of free space that the remote
+/// writes of the clients must leave in the filesystems of the target. Set by the operator.
+pub const AGENT_WRITE_SPACE_MARGIN_ENV: &str = "MIRRORD_AGENT_WRITE_SPACE_MARGIN";
+
+/// Name of environment variable that sets what the agent does when a client opens the service
+/// account token of the target.
+///
+/// One of `allow` (the default), `audit`, `block` or `substitute`. Set by the operator.
+pub const AGENT_SERVICE_ACCOUNT_TOKENS_ENV: &str = "MIRRORD_AGENT_SERVICE_ACCOUNT_TOKENS";
+
+/// Name of environment variable with the token that the agent serves in place of the service
+/// account token of the target.
+///
+/// Used when [`AGENT_SERVICE_ACCOUNT_TOKENS_ENV`] is `substitute`. Minted by the operator.
+pub const AGENT_SUBSTITUTE_TOKEN_ENV: &str = "MIRRORD_AGENT_SUBSTITUTE_TOKEN";
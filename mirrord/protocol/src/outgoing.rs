@@ -20,8 +20,15 @@ pub mod udp;
 
 /// A serializable socket address type that can represent IP addresses or addresses of unix sockets.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum SocketAddress {
-    Ip(StdIpSocketAddr),
+    Ip(
+        #[cfg_attr(
+            any(test, feature = "testing"),
+            proptest(strategy = "crate::testing::socket_addr()")
+        )]
+        StdIpSocketAddr,
+    ),
     Unix(UnixAddr),
 }
 
@@ -36,6 +43,7 @@ impl SocketAddress {
 
 /// A unix socket address type with rust member types (not libc stuff).
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum UnixAddr {
     Pathname(PathBuf),
     Abstract(Vec<u8>),
@@ -123,12 +131,14 @@ impl Display for SocketAddress {
 
 /// `user` wants to connect to `remote_address`.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct LayerConnect {
     pub remote_address: SocketAddress,
 }
 
 /// `user` wants to write `bytes` to remote host identified by `connection_id`.
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct LayerWrite {
     pub connection_id: ConnectionId,
     pub bytes: Vec<u8>,
@@ -145,11 +155,13 @@ impl fmt::Debug for LayerWrite {
 
 /// `layer` interceptor socket closed or failed.
 #[derive(Debug, Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct LayerClose {
     pub connection_id: ConnectionId,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct DaemonConnect {
     pub connection_id: ConnectionId,
     pub remote_address: SocketAddress,
@@ -157,6 +169,7 @@ pub struct DaemonConnect {
 }
 
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct DaemonRead {
     pub connection_id: ConnectionId,
     pub bytes: Vec<u8>,
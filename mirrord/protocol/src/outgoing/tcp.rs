@@ -1,16 +1,29 @@
 use super::*;
-use crate::RemoteResult;
+use crate::{
+    file::{SendFileRequest, SendFileResponse},
+    RemoteResult,
+};
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum LayerTcpOutgoing {
     Connect(LayerConnect),
     Write(LayerWrite),
     Close(LayerClose),
+    /// Writes the contents of a remote file to the connection.
+    ///
+    /// Should only be sent to agents that support
+    /// [`SEND_FILE_VERSION`](crate::file::SEND_FILE_VERSION), the agent responds with
+    /// [`DaemonTcpOutgoing::SendFile`].
+    SendFile(SendFileRequest),
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum DaemonTcpOutgoing {
     Connect(RemoteResult<DaemonConnect>),
     Read(RemoteResult<DaemonRead>),
     Close(ConnectionId),
+    /// Response to [`LayerTcpOutgoing::SendFile`], the agent responds in order.
+    SendFile(RemoteResult<SendFileResponse>),
 }
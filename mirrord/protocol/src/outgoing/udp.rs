@@ -2,6 +2,7 @@ use super::*;
 use crate::RemoteResult;
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum LayerUdpOutgoing {
     Connect(LayerConnect),
     Write(LayerWrite),
@@ -9,6 +10,7 @@ pub enum LayerUdpOutgoing {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum DaemonUdpOutgoing {
     Connect(RemoteResult<DaemonConnect>),
     Read(RemoteResult<DaemonRead>),
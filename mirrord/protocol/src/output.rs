@@ -0,0 +1,29 @@
+use std::sync::LazyLock;
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::Output`](crate::ClientMessage::Output).
+pub static OUTPUT_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.16.0".parse().expect("Bad Identifier"));
+
+/// Standard stream of a process.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// `-layer` --> `-agent` message with bytes that the local process wrote to one of its
+/// [`OutputStream`]s.
+///
+/// The agent writes them to the same stream of the target, so that they end up in the target
+/// container's log stream.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct OutputMessage {
+    pub stream: OutputStream,
+    pub bytes: Vec<u8>,
+}
@@ -3,6 +3,7 @@ use bincode::{Decode, Encode};
 /// `-agent` --> `-layer` messages regarding the pause feature.
 /// TODO add asynchronous notifications when the target container has changed its state
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum DaemonPauseTarget {
     /// Response for the client's request to pause or unpause the container.
     PauseResponse {
@@ -0,0 +1,52 @@
+//! Checking which destinations the agent can reach, before the session starts.
+//!
+//! NetworkPolicies and firewalls can block the egress of the target, and the agent shares the
+//! network namespace of the target. Peers that support [`REACHABILITY_CHECK_VERSION`] can send
+//! [`ClientMessage::CheckReachability`](crate::ClientMessage::CheckReachability) with the
+//! destinations the session is expected to reach, and the agent responds with
+//! [`DaemonMessage::ReachabilityResponse`](crate::DaemonMessage::ReachabilityResponse) once it
+//! tried to connect to each of them.
+use std::{net::SocketAddr, sync::LazyLock};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::CheckReachability`](crate::ClientMessage::CheckReachability).
+pub static REACHABILITY_CHECK_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.24.0".parse().expect("Bad Identifier"));
+
+/// Destinations that the agent should try to connect to over TCP.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReachabilityRequest {
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "proptest::collection::vec(crate::testing::socket_addr(), 0..4)")
+    )]
+    pub addresses: Vec<SocketAddr>,
+    /// How long the agent waits for each connection, in milliseconds.
+    ///
+    /// The agent may use a shorter timeout, so that the check does not hold the session for long.
+    pub timeout_ms: u64,
+}
+
+/// Response to [`ReachabilityRequest`], with one result for each of the
+/// [`ReachabilityRequest::addresses`], in the same order.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReachabilityResponse {
+    pub results: Vec<ReachabilityResult>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct ReachabilityResult {
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::socket_addr()")
+    )]
+    pub address: SocketAddr,
+    /// Why the connection failed, [`None`] when it succeeded.
+    pub error: Option<String>,
+}
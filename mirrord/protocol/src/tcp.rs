@@ -25,9 +25,14 @@ use tokio::sync::mpsc::Receiver;
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{error, Level};
 
-use crate::{body_chunks::BodyExt as _, ConnectionId, Port, RemoteResult, RequestId};
+use crate::{
+    body_chunks::BodyExt as _,
+    file::{SendFileRequest, SendFileResponse},
+    ConnectionId, Port, RemoteResult, RequestId,
+};
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct NewTcpConnection {
     pub connection_id: ConnectionId,
     pub remote_address: IpAddr,
@@ -37,6 +42,7 @@ pub struct NewTcpConnection {
 }
 
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct TcpData {
     pub connection_id: ConnectionId,
     pub bytes: Vec<u8>,
@@ -52,12 +58,14 @@ impl fmt::Debug for TcpData {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct TcpClose {
     pub connection_id: ConnectionId,
 }
 
 /// Messages related to Tcp handler from client.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum LayerTcp {
     PortSubscribe(Port),
     ConnectionUnsubscribe(ConnectionId),
@@ -66,6 +74,7 @@ pub enum LayerTcp {
 
 /// Messages related to Tcp handler from server.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum DaemonTcp {
     NewConnection(NewTcpConnection),
     Data(TcpData),
@@ -76,10 +85,23 @@ pub enum DaemonTcp {
     HttpRequest(HttpRequest<Vec<u8>>),
     HttpRequestFramed(HttpRequest<InternalHttpBody>),
     HttpRequestChunked(ChunkedRequest),
+    /// Addresses of a filtered connection, sent before its first HTTP request (see
+    /// [`HTTP_CONNECTION_METADATA_VERSION`]), so that the client can expose the original peer
+    /// instead of its own proxy.
+    NewHttpConnection(NewTcpConnection),
+    /// The agent finished draining the client's steal subscriptions, in response to
+    /// [`LayerTcpSteal::Drain`]. The subscriptions are removed, and the client can exit without
+    /// dropping any stolen request.
+    Drained,
+    /// The port reserved in response to [`LayerTcpSteal::ReservePort`].
+    PortReserved(RemoteResult<Port>),
+    /// Response to [`LayerTcpSteal::SendFile`], the agent responds in order.
+    SendFile(RemoteResult<SendFileResponse>),
 }
 
 /// Contents of a chunked message from server.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ChunkedRequest {
     Start(HttpRequest<Vec<InternalHttpBodyFrame>>),
     Body(ChunkedHttpBody),
@@ -88,6 +110,7 @@ pub enum ChunkedRequest {
 
 /// Contents of a chunked message body frame from server.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ChunkedHttpBody {
     #[bincode(with_serde)]
     pub frames: Vec<InternalHttpBodyFrame>,
@@ -107,6 +130,7 @@ impl From<InternalHttpBodyFrame> for Frame<Bytes> {
 
 /// An error occurred while processing chunked data from server.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct ChunkedHttpError {
     pub connection_id: ConnectionId,
     pub request_id: RequestId,
@@ -115,6 +139,7 @@ pub struct ChunkedHttpError {
 /// Wraps the string that will become a [`fancy_regex::Regex`], providing a nice API in
 /// `Filter::new` that validates the regex in mirrord-layer.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct Filter(String);
 
 impl Filter {
@@ -196,8 +221,55 @@ impl Display for HttpFilter {
     }
 }
 
+/// Wire protocol of the database connections filtered with a [`DatabaseFilter`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum DatabaseProtocol {
+    /// PostgreSQL frontend/backend protocol.
+    Postgres,
+    /// Tabular Data Stream, used by Microsoft SQL Server.
+    Tds,
+}
+
+impl Display for DatabaseProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Postgres => f.write_str("postgres"),
+            Self::Tds => f.write_str("tds"),
+        }
+    }
+}
+
+/// Filter for database connections, matched against the database and the user from the startup
+/// message of the connection.
+///
+/// A connection matches when all of the given filters match, and is stolen whole.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct DatabaseFilter {
+    pub protocol: DatabaseProtocol,
+    /// Filter for the name of the database ("^orders$").
+    pub database: Option<Filter>,
+    /// Filter for the name of the user ("^reporting").
+    pub user: Option<Filter>,
+}
+
+impl Display for DatabaseFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.protocol)?;
+        if let Some(database) = &self.database {
+            write!(f, " database={database}")?;
+        }
+        if let Some(user) = &self.user {
+            write!(f, " user={user}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Describes the stealing subscription to a port:
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 #[protocol_break(2)]
 pub enum StealType {
     /// Steal all traffic to this port.
@@ -207,19 +279,29 @@ pub enum StealType {
     FilteredHttp(Port, Filter),
     /// Steal HTTP traffic matching a given filter - supporting more than once kind of filter
     FilteredHttpEx(Port, HttpFilter),
+    /// Steal database connections matching a given filter.
+    FilteredDatabase(Port, DatabaseFilter),
+    /// Steal all connections accepted on a port that the client reserved with
+    /// [`LayerTcpSteal::ReservePort`].
+    ///
+    /// Should only be sent to agents that support [`PORT_RESERVATION_VERSION`].
+    Reserved(Port),
 }
 
 impl StealType {
     pub fn get_port(&self) -> Port {
         let (StealType::All(port)
         | StealType::FilteredHttpEx(port, ..)
-        | StealType::FilteredHttp(port, ..)) = self;
+        | StealType::FilteredHttp(port, ..)
+        | StealType::FilteredDatabase(port, ..)
+        | StealType::Reserved(port)) = self;
         *port
     }
 }
 
 /// Messages related to Steal Tcp handler from client.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum LayerTcpSteal {
     PortSubscribe(StealType),
     ConnectionUnsubscribe(ConnectionId),
@@ -228,9 +310,139 @@ pub enum LayerTcpSteal {
     HttpResponse(HttpResponse<Vec<u8>>),
     HttpResponseFramed(HttpResponse<InternalHttpBody>),
     HttpResponseChunked(ChunkedResponse),
+    /// Allows a later session with the same [`StealHandoff::key`] to adopt this client's port
+    /// subscriptions, and adopts the subscriptions of a previous session with this key, if there
+    /// is one.
+    ///
+    /// Should only be sent to agents that support [`STEAL_HANDOFF_VERSION`], before any
+    /// [`LayerTcpSteal::PortSubscribe`].
+    Handoff(StealHandoff),
+    /// Sets what the agent does with the requests stolen with this client's HTTP filters, when
+    /// the client fails to respond to them.
+    ///
+    /// Should only be sent to agents that support [`STEAL_FALLBACK_VERSION`].
+    Fallback(StealFallback),
+    /// Starts draining this client's steal subscriptions before it exits, see [`StealDrain`].
+    ///
+    /// Should only be sent to agents that support [`STEAL_DRAIN_VERSION`], the agent responds
+    /// with [`DaemonTcp::Drained`].
+    Drain(StealDrain),
+    /// Reserves a port in the agent, see [`PortReservation`].
+    ///
+    /// Should only be sent to agents that support [`PORT_RESERVATION_VERSION`], the agent
+    /// responds with [`DaemonTcp::PortReserved`].
+    ReservePort(PortReservation),
+    /// Releases a port reserved with [`LayerTcpSteal::ReservePort`], closing its listener.
+    ///
+    /// Should only be sent to agents that support [`PORT_RESERVATION_VERSION`].
+    ReleasePort(Port),
+    /// Writes the contents of a remote file to a stolen connection, like
+    /// [`LayerTcpSteal::Data`].
+    ///
+    /// Should only be sent to agents that support
+    /// [`SEND_FILE_VERSION`](crate::file::SEND_FILE_VERSION), the agent responds with
+    /// [`DaemonTcp::SendFile`].
+    SendFile(SendFileRequest),
+}
+
+crate::extensible_message! {
+    /// Identifies the session in [`LayerTcpSteal::Handoff`].
+    ///
+    /// When a client that sent this message exits, the agent keeps its port subscriptions for
+    /// `timeout_ms`, buffering the stolen requests. If a new client sends a handoff with the same
+    /// `key` in the meantime, it adopts the subscriptions, and receives the buffered requests once
+    /// it subscribes to the ports again.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    #[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+    pub struct StealHandoff {
+        /// Identifies the sessions that can adopt each other's subscriptions (e.g. a secret of the
+        /// user and the target), so it should not be guessable.
+        pub key: String,
+        /// How long the agent keeps the subscriptions after the client exits.
+        pub timeout_ms: u64,
+    }
+    extensions {}
+}
+
+crate::extensible_message! {
+    /// Sent in [`LayerTcpSteal::ReservePort`].
+    ///
+    /// The agent listens on the port in the target's network namespace, until the client releases
+    /// it with [`LayerTcpSteal::ReleasePort`] or exits. Once the client subscribes to the port
+    /// with [`StealType::Reserved`], the agent sends it the connections accepted on the port, as
+    /// stolen connections.
+    ///
+    /// Used for the protocols where a server connects back to a port advertised by the client
+    /// (e.g. FTP active mode), which is reachable from the cluster only through the agent.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    #[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+    pub struct PortReservation {
+        /// The port to reserve, `0` lets the agent pick a free one.
+        pub port: Port,
+    }
+    extensions {}
+}
+
+crate::extensible_message! {
+    /// Sent in [`LayerTcpSteal::Drain`].
+    ///
+    /// The agent stops stealing new requests and connections on behalf of the client, letting
+    /// them through to their original destination, but keeps the port redirections in place
+    /// until the requests that the client did not respond to yet and its stolen TCP connections
+    /// are done, or until `timeout_ms` elapses. Then it removes the subscriptions, as if the
+    /// client had exited. The drain also ends when the client subscribes to a port again.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    #[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+    pub struct StealDrain {
+        /// How long the agent waits for the in-flight requests and connections.
+        pub timeout_ms: u64,
+    }
+    extensions {}
+}
+
+/// What the agent does with a stolen HTTP request when the client fails to respond to it, see
+/// [`StealFallback`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum StealFallbackPolicy {
+    /// Respond to the request with `502 Bad Gateway`.
+    #[default]
+    Error,
+    /// Pass the request to its original destination.
+    PassthroughToRemote,
+    /// Send the request to the client again, a limited number of times.
+    RetryLocal,
+}
+
+crate::extensible_message! {
+    /// Sent in [`LayerTcpSteal::Fallback`].
+    ///
+    /// The client fails to respond to a request when it unsubscribes from the request's
+    /// connection (e.g. the local application crashed), or when `response_timeout_ms` elapses.
+    ///
+    /// Only requests with a body of known and limited size can fall back to
+    /// [`StealFallbackPolicy::PassthroughToRemote`] or [`StealFallbackPolicy::RetryLocal`], as the
+    /// agent has to keep a copy of the request.
+    #[derive(Debug, PartialEq, Eq, Clone, Default)]
+    #[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+    pub struct StealFallback {
+        pub policy: StealFallbackPolicy,
+        /// How long the agent waits for the client's response, [`None`] to wait indefinitely.
+        pub response_timeout_ms: Option<u64>,
+    }
+    extensions {
+        /// How long the client can go without sending the next frame of a streamed response body
+        /// (e.g. server-sent events), before the agent aborts the response.
+        pub idle_timeout_ms: Option<u64>,
+        /// How long the client has to send the whole response, body included, counted from when
+        /// the agent received the request. When it elapses before the response head arrives,
+        /// the [`StealFallback::policy`] is applied, otherwise the response is aborted.
+        pub total_timeout_ms: Option<u64>,
+    }
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ChunkedResponse {
     Start(HttpResponse<Vec<InternalHttpBodyFrame>>),
     Body(ChunkedHttpBody),
@@ -239,17 +451,34 @@ pub enum ChunkedResponse {
 
 /// (De-)Serializable HTTP request.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct InternalHttpRequest<Body> {
     #[serde(with = "http_serde::method")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::method()")
+    )]
     pub method: Method,
 
     #[serde(with = "http_serde::uri")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::uri()")
+    )]
     pub uri: Uri,
 
     #[serde(with = "http_serde::header_map")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::header_map()")
+    )]
     pub headers: HeaderMap,
 
     #[serde(with = "http_serde::version")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::http_version()")
+    )]
     pub version: Version,
 
     pub body: Body,
@@ -458,9 +687,35 @@ pub static HTTP_FILTERED_UPGRADE_VERSION: LazyLock<VersionReq> =
 pub static HTTP_COMPOSITE_FILTER_VERSION: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.11.0".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`LayerTcpSteal::Handoff`].
+pub static STEAL_HANDOFF_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.14.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`LayerTcpSteal::Fallback`].
+pub static STEAL_FALLBACK_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.15.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`DaemonTcp::NewHttpConnection`].
+pub static HTTP_CONNECTION_METADATA_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.19.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`LayerTcpSteal::Drain`].
+pub static STEAL_DRAIN_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.20.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`StealType::FilteredDatabase`].
+pub static DATABASE_FILTER_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.21.0".parse().expect("Bad Identifier"));
+
+/// Minimal mirrord-protocol version that allows [`LayerTcpSteal::ReservePort`],
+/// [`LayerTcpSteal::ReleasePort`] and [`StealType::Reserved`].
+pub static PORT_RESERVATION_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.42.0".parse().expect("Bad Identifier"));
+
 /// Protocol break - on version 2, please add source port, dest/src IP to the message
 /// so we can avoid losing this information.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 #[protocol_break(2)]
 #[bincode(bounds = "for<'de> Body: Serialize + Deserialize<'de>")]
 pub struct HttpRequest<Body> {
@@ -482,14 +737,27 @@ impl<B> HttpRequest<B> {
 
 /// (De-)Serializable HTTP response.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct InternalHttpResponse<Body> {
     #[serde(with = "http_serde::status_code")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::status_code()")
+    )]
     pub status: StatusCode,
 
     #[serde(with = "http_serde::version")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::http_version()")
+    )]
     pub version: Version,
 
     #[serde(with = "http_serde::header_map")]
+    #[cfg_attr(
+        any(test, feature = "testing"),
+        proptest(strategy = "crate::testing::header_map()")
+    )]
     pub headers: HeaderMap,
 
     pub body: Body,
@@ -517,6 +785,7 @@ impl<B> InternalHttpResponse<B> {
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct InternalHttpBody(VecDeque<InternalHttpBodyFrame>);
 
 impl InternalHttpBody {
@@ -558,9 +827,17 @@ impl Body for InternalHttpBody {
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum InternalHttpBodyFrame {
     Data(Vec<u8>),
-    Trailers(#[serde(with = "http_serde::header_map")] HeaderMap),
+    Trailers(
+        #[serde(with = "http_serde::header_map")]
+        #[cfg_attr(
+            any(test, feature = "testing"),
+            proptest(strategy = "crate::testing::header_map()")
+        )]
+        HeaderMap,
+    ),
 }
 
 impl From<Frame<Bytes>> for InternalHttpBodyFrame {
@@ -699,6 +976,7 @@ impl HttpResponseFallback {
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 #[bincode(bounds = "for<'de> Body: Serialize + Deserialize<'de>")]
 pub struct HttpResponse<Body> {
     /// This is used to make sure the response is sent in its turn, after responses to all earlier
@@ -0,0 +1,1152 @@
+//! Property testing support for implementations of the protocol, enabled with the `testing`
+//! feature.
+//!
+//! Every message type (and everything it carries) implements [`Arbitrary`], so
+//! `any::<ClientMessage>()` and `any::<DaemonMessage>()` generate valid messages. The strategies
+//! here cover the foreign types used in the messages, and produce only values that survive an
+//! encode/decode round trip.
+//!
+//! [`golden`] holds a fixed set of messages, together with their encoding in
+//! `tests/fixtures`, which can be used to check compatibility with other versions of the
+//! protocol.
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use hyper::{HeaderMap, Method, StatusCode, Uri, Version};
+use proptest::{
+    arbitrary::Arbitrary,
+    collection::vec,
+    prelude::{any, Just, Strategy},
+    prop_oneof,
+    strategy::BoxedStrategy,
+};
+
+use crate::tcp::{Filter, HttpFilter};
+
+/// Generates [`semver::Version`]s without pre-release or build metadata.
+pub fn semver_version() -> impl Strategy<Value = semver::Version> {
+    (any::<u16>(), any::<u16>(), any::<u16>()).prop_map(|(major, minor, patch)| {
+        semver::Version::new(major.into(), minor.into(), patch.into())
+    })
+}
+
+/// Generates [`SocketAddr`]s, IPv6 ones without flow info and scope id, as these are not encoded.
+pub fn socket_addr() -> impl Strategy<Value = SocketAddr> {
+    (any::<IpAddr>(), any::<u16>()).prop_map(SocketAddr::from)
+}
+
+/// Generates the standard HTTP methods, and extension ones.
+pub fn method() -> impl Strategy<Value = Method> {
+    prop_oneof![
+        Just(Method::GET),
+        Just(Method::POST),
+        Just(Method::PUT),
+        Just(Method::DELETE),
+        Just(Method::HEAD),
+        Just(Method::OPTIONS),
+        Just(Method::CONNECT),
+        Just(Method::PATCH),
+        Just(Method::TRACE),
+        "[A-Z]{1,12}".prop_map(|method| Method::from_str(&method).expect("valid method")),
+    ]
+}
+
+/// Generates origin-form [`Uri`]s (`/path?query`).
+pub fn uri() -> impl Strategy<Value = Uri> {
+    (
+        "/[a-zA-Z0-9/._-]{0,32}",
+        proptest::option::of("[a-z0-9=&]{0,16}"),
+    )
+        .prop_map(|(path, query)| {
+            let uri = match query {
+                Some(query) => format!("{path}?{query}"),
+                None => path,
+            };
+
+            Uri::from_str(&uri).expect("valid uri")
+        })
+}
+
+/// Generates [`HeaderMap`]s with lowercase names and printable ASCII values.
+pub fn header_map() -> impl Strategy<Value = HeaderMap> {
+    vec(("[a-z][a-z0-9-]{0,15}", "[!-~]([ -~]{0,30}[!-~])?"), 0..8).prop_map(|headers| {
+        headers
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    name.parse().expect("valid header name"),
+                    value.parse().expect("valid header value"),
+                )
+            })
+            .collect()
+    })
+}
+
+/// Generates every HTTP [`Version`].
+pub fn http_version() -> impl Strategy<Value = Version> {
+    prop_oneof![
+        Just(Version::HTTP_09),
+        Just(Version::HTTP_10),
+        Just(Version::HTTP_11),
+        Just(Version::HTTP_2),
+        Just(Version::HTTP_3),
+    ]
+}
+
+/// Generates every valid [`StatusCode`].
+pub fn status_code() -> impl Strategy<Value = StatusCode> {
+    (100u16..1000).prop_map(|code| StatusCode::from_u16(code).expect("valid status code"))
+}
+
+/// Implemented by hand, as the derived implementation would recurse infinitely through
+/// [`HttpFilter::Composite`].
+impl Arbitrary for HttpFilter {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        let leaf = prop_oneof![
+            any::<Filter>().prop_map(HttpFilter::Header),
+            any::<Filter>().prop_map(HttpFilter::Path),
+        ];
+
+        leaf.prop_recursive(3, 16, 4, |inner| {
+            (any::<bool>(), vec(inner, 0..4))
+                .prop_map(|(all, filters)| HttpFilter::Composite { all, filters })
+        })
+        .boxed()
+    }
+}
+
+/// Fixed messages, whose encoding is stored in `tests/fixtures/<name>.bin` of this crate.
+///
+/// Messages are only ever added here, and fixtures never change, so that an implementation
+/// can be checked against the encoding of every version of the protocol.
+pub mod golden {
+    use std::path::PathBuf;
+
+    use hyper::{HeaderMap, Method, Version};
+
+    use crate::{
+        clock::ClockProbeResponse,
+        compression::CompressionSettings,
+        dns::{
+            AddrInfoFamily, AddrInfoHint, DnsLookup, GetAddrInfoRequestV2, GetAddrInfoResponse,
+            LookupRecord,
+        },
+        fd_budget::{CloseFdsRequest, FdKind, FdUsage, FdUsageRequest, IdleFds},
+        file::{
+            CanonicalizePathRequest, CanonicalizePathResponse, ChmodFileWithDirRequest,
+            ChownFileWithDirRequest, CopyFileRangeRequest, CopyFileRangeResponse, DirEntryInternal,
+            DirEntryPlusInternal, DirListingDelta, FallocateFileRequest, FileLockInternal,
+            FileTimeInternal, FlockRequest, FollowChunkResponse, FollowFileRequest,
+            FsyncFileRequest, FtruncateFileRequest, GetLockRequest, GetLockResponse,
+            GetXattrRequest, GetXattrResponse, GlobRequest, GlobResponse, LinkFileWithDirRequest,
+            ListXattrRequest, ListXattrResponse, LockTypeInternal, MetadataInternal,
+            OpenAt2Request, OpenFileRequest, OpenOptionsInternal, PrefetchChunkResponse,
+            PrefetchDirInternal, PrefetchEntryInternal, PrefetchTreeRequest, ReadChunkResponse,
+            ReadDirBatchResponse, ReadDirDeltaRequest, ReadDirDeltaResponse,
+            ReadDirPlusBatchRequest, ReadDirPlusBatchResponse, ReadFileResponse,
+            ReadStreamFileRequest, ReadVFileRequest, ReadVFileResponse, ReadWholeFileRequest,
+            ReadWholeFileResponse, RenameFileWithDirRequest, ScratchDirRequest, SeekFileRequest,
+            SeekFromInternal, SendFileRequest, SendFileResponse, SetLockRequest, SetXattrRequest,
+            StatFsRequest, StatxMetadataInternal, StatxRequest, StatxResponse, SymlinkAtRequest,
+            UnfollowFileRequest, UnlinkFileWithDirRequest, UtimensFileWithDirRequest,
+            WriteFileResponse, WriteVFileRequest,
+        },
+        framing::FrameLimits,
+        interfaces::{GetIfAddrsRequest, GetIfAddrsResponse, InterfaceAddress, InterfaceFlags},
+        outgoing::{
+            tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
+            LayerConnect, SocketAddress,
+        },
+        output::{OutputMessage, OutputStream},
+        reachability::{ReachabilityRequest, ReachabilityResponse, ReachabilityResult},
+        tcp::{
+            DaemonTcp, DatabaseFilter, DatabaseProtocol, Filter, HttpFilter, HttpRequest,
+            InternalHttpBody, InternalHttpRequest, LayerTcp, LayerTcpSteal, NewTcpConnection,
+            PortReservation, StealDrain, StealType, TcpData,
+        },
+        watch::{AddWatchRequest, DaemonWatch, LayerWatch, WatchEvent},
+        BlockedAction, ClientMessage, DaemonMessage, ErrorKindInternal, FileRequest, FileResponse,
+        RemoteIOError, ResponseError,
+    };
+
+    /// [`ClientMessage`]s, with the name of their fixture.
+    pub fn client_messages() -> Vec<(&'static str, ClientMessage)> {
+        vec![
+            ("client_ping", ClientMessage::Ping),
+            (
+                "client_switch_protocol_version",
+                ClientMessage::SwitchProtocolVersion(semver::Version::new(1, 12, 0)),
+            ),
+            (
+                "client_tcp_port_subscribe",
+                ClientMessage::Tcp(LayerTcp::PortSubscribe(80)),
+            ),
+            (
+                "client_tcp_steal_filtered",
+                ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::FilteredHttpEx(
+                    8080,
+                    HttpFilter::Composite {
+                        all: true,
+                        filters: vec![
+                            HttpFilter::Header(Filter::new("x-user: me".to_string()).unwrap()),
+                            HttpFilter::Path(Filter::new("^/api".to_string()).unwrap()),
+                        ],
+                    },
+                ))),
+            ),
+            (
+                "client_file_open",
+                ClientMessage::FileRequest(FileRequest::Open(OpenFileRequest {
+                    path: PathBuf::from("/etc/hosts"),
+                    open_options: OpenOptionsInternal {
+                        read: true,
+                        ..Default::default()
+                    },
+                })),
+            ),
+            (
+                "client_get_addr_info_v2",
+                ClientMessage::GetAddrInfoRequestV2(GetAddrInfoRequestV2 {
+                    node: "example.com".to_string(),
+                    hints: Some(AddrInfoHint {
+                        ai_family: AddrInfoFamily::Ipv4,
+                        ai_socktype: 1,
+                        ai_protocol: 6,
+                        ai_flags: 0,
+                    }),
+                }),
+            ),
+            (
+                "client_tcp_outgoing_connect",
+                ClientMessage::TcpOutgoing(LayerTcpOutgoing::Connect(LayerConnect {
+                    remote_address: SocketAddress::Ip("10.0.0.1:5432".parse().unwrap()),
+                })),
+            ),
+            (
+                "client_output",
+                ClientMessage::Output(OutputMessage {
+                    stream: OutputStream::Stdout,
+                    bytes: b"started\n".to_vec(),
+                }),
+            ),
+            (
+                "client_file_scratch_dir",
+                ClientMessage::FileRequest(FileRequest::ScratchDir(ScratchDirRequest {
+                    patterns: vec!["^/var/app/uploads/".to_string()],
+                })),
+            ),
+            (
+                "client_tcp_steal_drain",
+                ClientMessage::TcpSteal(LayerTcpSteal::Drain(StealDrain { timeout_ms: 10_000 })),
+            ),
+            (
+                "client_switch_framing",
+                ClientMessage::SwitchFraming(FrameLimits {
+                    max_frame_size: 8 * 1024 * 1024 * 1024,
+                }),
+            ),
+            (
+                "client_tcp_steal_database_filtered",
+                ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::FilteredDatabase(
+                    5432,
+                    DatabaseFilter {
+                        protocol: DatabaseProtocol::Postgres,
+                        database: Some(Filter::new("^orders$".to_string()).unwrap()),
+                        user: None,
+                    },
+                ))),
+            ),
+            (
+                "client_tcp_steal_reserve_port",
+                ClientMessage::TcpSteal(LayerTcpSteal::ReservePort(PortReservation { port: 0 })),
+            ),
+            (
+                "client_tcp_steal_reserved",
+                ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::Reserved(40123))),
+            ),
+            (
+                "client_tcp_steal_release_port",
+                ClientMessage::TcpSteal(LayerTcpSteal::ReleasePort(40123)),
+            ),
+            ("client_clock_probe", ClientMessage::ClockProbe),
+            ("client_read_only", ClientMessage::ReadOnly),
+            ("client_freeze_target", ClientMessage::FreezeTarget),
+            (
+                "client_file_unlink_at",
+                ClientMessage::FileRequest(FileRequest::UnlinkAt(UnlinkFileWithDirRequest {
+                    dirfd: Some(3),
+                    path: PathBuf::from("upload.tmp"),
+                    remove_dir: false,
+                })),
+            ),
+            (
+                "client_file_rename_at",
+                ClientMessage::FileRequest(FileRequest::RenameAt(RenameFileWithDirRequest {
+                    old_dirfd: Some(3),
+                    old_path: PathBuf::from("upload.tmp"),
+                    new_dirfd: None,
+                    new_path: PathBuf::from("/app/upload.json"),
+                    flags: 1,
+                })),
+            ),
+            (
+                "client_file_chmod_at",
+                ClientMessage::FileRequest(FileRequest::ChmodAt(ChmodFileWithDirRequest {
+                    dirfd: Some(3),
+                    path: PathBuf::from("run.sh"),
+                    mode: 0o755,
+                    flags: 0,
+                })),
+            ),
+            (
+                "client_file_open_at2",
+                ClientMessage::FileRequest(FileRequest::OpenAt2(OpenAt2Request {
+                    dirfd: Some(3),
+                    path: PathBuf::from("config/app.json"),
+                    open_options: OpenOptionsInternal {
+                        read: true,
+                        ..Default::default()
+                    },
+                    // `RESOLVE_BENEATH | RESOLVE_NO_SYMLINKS`
+                    resolve: 0x0c,
+                })),
+            ),
+            (
+                "client_file_chown_at",
+                ClientMessage::FileRequest(FileRequest::ChownAt(ChownFileWithDirRequest {
+                    dirfd: None,
+                    path: PathBuf::from("/var/lib/app"),
+                    owner: Some(1000),
+                    group: None,
+                    flags: 0x100,
+                })),
+            ),
+            (
+                "client_file_read_whole",
+                ClientMessage::FileRequest(FileRequest::ReadWhole(ReadWholeFileRequest {
+                    path: PathBuf::from("/etc/app/config.yaml"),
+                    max_size: 64 * 1024,
+                })),
+            ),
+            (
+                "client_file_ftruncate",
+                ClientMessage::FileRequest(FileRequest::Ftruncate(FtruncateFileRequest {
+                    fd: 3,
+                    length: 4096,
+                })),
+            ),
+            (
+                "client_file_glob",
+                ClientMessage::FileRequest(FileRequest::Glob(GlobRequest {
+                    pattern: "/etc/ssl/certs/*.pem".to_string(),
+                    max_results: 1024,
+                })),
+            ),
+            (
+                "client_file_symlink_at",
+                ClientMessage::FileRequest(FileRequest::SymlinkAt(SymlinkAtRequest {
+                    target: PathBuf::from("releases/1.2.0"),
+                    new_dirfd: Some(3),
+                    link_path: PathBuf::from("current"),
+                })),
+            ),
+            (
+                "client_file_link_at",
+                ClientMessage::FileRequest(FileRequest::LinkAt(LinkFileWithDirRequest {
+                    old_dirfd: None,
+                    old_path: PathBuf::from("/var/backups/app.db"),
+                    new_dirfd: Some(3),
+                    new_path: PathBuf::from("app.db.1"),
+                    follow_symlinks: true,
+                })),
+            ),
+            (
+                "client_file_utimens_at",
+                ClientMessage::FileRequest(FileRequest::UtimensAt(UtimensFileWithDirRequest {
+                    dirfd: None,
+                    path: PathBuf::from("/app/build/main.o"),
+                    access_time: FileTimeInternal::Omit,
+                    modification_time: FileTimeInternal::At(1_700_000_000_123_456_789),
+                    flags: 0,
+                })),
+            ),
+            (
+                "client_file_fsync",
+                ClientMessage::FileRequest(FileRequest::Fsync(FsyncFileRequest {
+                    fd: 12,
+                    data_only: true,
+                })),
+            ),
+            (
+                "client_file_fallocate",
+                ClientMessage::FileRequest(FileRequest::Fallocate(FallocateFileRequest {
+                    fd: 12,
+                    mode: 0,
+                    offset: 4096,
+                    length: 1 << 20,
+                    posix: true,
+                })),
+            ),
+            (
+                "client_file_copy_file_range",
+                ClientMessage::FileRequest(FileRequest::CopyFileRange(CopyFileRangeRequest {
+                    fd_in: 12,
+                    offset_in: Some(4096),
+                    fd_out: 13,
+                    offset_out: None,
+                    length: 1 << 20,
+                })),
+            ),
+            (
+                "client_tcp_outgoing_send_file",
+                ClientMessage::TcpOutgoing(LayerTcpOutgoing::SendFile(SendFileRequest {
+                    connection_id: 3,
+                    fd: 12,
+                    offset: Some(4096),
+                    count: 1 << 20,
+                })),
+            ),
+            (
+                "client_tcp_steal_send_file",
+                ClientMessage::TcpSteal(LayerTcpSteal::SendFile(SendFileRequest {
+                    connection_id: 7,
+                    fd: 12,
+                    offset: None,
+                    count: 65536,
+                })),
+            ),
+            (
+                "client_file_readv",
+                ClientMessage::FileRequest(FileRequest::ReadV(ReadVFileRequest {
+                    fd: 12,
+                    lengths: vec![16, 4096],
+                    offset: Some(4096),
+                })),
+            ),
+            (
+                "client_file_writev",
+                ClientMessage::FileRequest(FileRequest::WriteV(WriteVFileRequest {
+                    fd: 12,
+                    buffers: vec![b"header\n".to_vec(), b"body".to_vec()],
+                    offset: None,
+                })),
+            ),
+            (
+                "client_file_read_stream",
+                ClientMessage::FileRequest(FileRequest::ReadStream(ReadStreamFileRequest {
+                    fd: 12,
+                    length: 512 * 1024 * 1024,
+                    offset: None,
+                    chunk_size: 1024 * 1024,
+                })),
+            ),
+            (
+                "client_file_statx",
+                ClientMessage::FileRequest(FileRequest::Statx(StatxRequest {
+                    dirfd: Some(4),
+                    path: Some(PathBuf::from("pg_wal/000000010000000000000001")),
+                    follow_symlink: false,
+                    // `STATX_BASIC_STATS | STATX_BTIME`.
+                    mask: 0xfff,
+                })),
+            ),
+            (
+                "client_file_get_xattr",
+                ClientMessage::FileRequest(FileRequest::GetXattr(GetXattrRequest {
+                    path: Some(PathBuf::from("/usr/bin/ping")),
+                    fd: None,
+                    follow_symlink: true,
+                    name: b"security.capability".to_vec(),
+                })),
+            ),
+            (
+                "client_file_set_xattr",
+                ClientMessage::FileRequest(FileRequest::SetXattr(SetXattrRequest {
+                    path: None,
+                    fd: Some(9),
+                    follow_symlink: true,
+                    name: b"user.mime_type".to_vec(),
+                    value: b"text/plain".to_vec(),
+                    // `XATTR_CREATE`.
+                    flags: 1,
+                })),
+            ),
+            (
+                "client_file_list_xattr",
+                ClientMessage::FileRequest(FileRequest::ListXattr(ListXattrRequest {
+                    path: Some(PathBuf::from("/etc/localtime")),
+                    fd: None,
+                    follow_symlink: false,
+                })),
+            ),
+            (
+                "client_file_flock",
+                ClientMessage::FileRequest(FileRequest::Flock(FlockRequest {
+                    fd: 7,
+                    lock_type: LockTypeInternal::Exclusive,
+                })),
+            ),
+            (
+                "client_file_set_lock",
+                ClientMessage::FileRequest(FileRequest::SetLock(SetLockRequest {
+                    fd: 7,
+                    lock: FileLockInternal {
+                        lock_type: LockTypeInternal::Shared,
+                        // SQLite's shared lock range.
+                        start: SeekFromInternal::Start(0x4000_0002),
+                        len: 510,
+                    },
+                })),
+            ),
+            (
+                "client_file_get_lock",
+                ClientMessage::FileRequest(FileRequest::GetLock(GetLockRequest {
+                    fd: 7,
+                    lock: FileLockInternal {
+                        lock_type: LockTypeInternal::Exclusive,
+                        start: SeekFromInternal::End(-16),
+                        len: 0,
+                    },
+                })),
+            ),
+            (
+                "client_file_seek_data",
+                ClientMessage::FileRequest(FileRequest::Seek(SeekFileRequest {
+                    fd: 5,
+                    seek_from: SeekFromInternal::Data(1 << 20),
+                })),
+            ),
+            (
+                "client_file_seek_hole",
+                ClientMessage::FileRequest(FileRequest::Seek(SeekFileRequest {
+                    fd: 5,
+                    seek_from: SeekFromInternal::Hole(0),
+                })),
+            ),
+            (
+                "client_check_reachability",
+                ClientMessage::CheckReachability(ReachabilityRequest {
+                    addresses: vec![
+                        "10.96.0.10:5432".parse().unwrap(),
+                        "[fd00::1]:443".parse().unwrap(),
+                    ],
+                    timeout_ms: 3000,
+                }),
+            ),
+            (
+                "client_switch_compression",
+                ClientMessage::SwitchCompression(CompressionSettings {
+                    threshold: 64 * 1024,
+                }),
+            ),
+            (
+                "client_get_ifaddrs",
+                ClientMessage::GetIfAddrsRequest(GetIfAddrsRequest),
+            ),
+            (
+                "client_file_read_dir_delta",
+                ClientMessage::FileRequest(FileRequest::ReadDirDelta(ReadDirDeltaRequest {
+                    remote_fd: 5,
+                    since: Some(0xc00c1e),
+                })),
+            ),
+            (
+                "client_file_canonicalize",
+                ClientMessage::FileRequest(FileRequest::Canonicalize(CanonicalizePathRequest {
+                    path: PathBuf::from("/app/current/../config.yaml"),
+                })),
+            ),
+            (
+                "client_fd_usage_request",
+                ClientMessage::FdUsageRequest(FdUsageRequest),
+            ),
+            (
+                "client_file_statfs",
+                ClientMessage::FileRequest(FileRequest::StatFs(StatFsRequest {
+                    path: PathBuf::from("/var/lib/data"),
+                })),
+            ),
+            (
+                "client_watch_add",
+                ClientMessage::Watch(LayerWatch::Add(AddWatchRequest {
+                    path: PathBuf::from("/app/config"),
+                    // `IN_MODIFY | IN_CREATE | IN_DELETE | IN_ONLYDIR`
+                    mask: 0x0100_0302,
+                })),
+            ),
+            (
+                "client_watch_remove",
+                ClientMessage::Watch(LayerWatch::Remove(4)),
+            ),
+            (
+                "client_file_follow",
+                ClientMessage::FileRequest(FileRequest::Follow(FollowFileRequest { id: 2, fd: 9 })),
+            ),
+            (
+                "client_file_unfollow",
+                ClientMessage::FileRequest(FileRequest::Unfollow(UnfollowFileRequest {
+                    id: 2,
+                    consumed: 4096,
+                })),
+            ),
+            (
+                "client_file_read_dir_plus_batch",
+                ClientMessage::FileRequest(FileRequest::ReadDirPlusBatch(
+                    ReadDirPlusBatchRequest {
+                        remote_fd: 5,
+                        amount: 128,
+                    },
+                )),
+            ),
+            (
+                "client_file_prefetch_tree",
+                ClientMessage::FileRequest(FileRequest::PrefetchTree(PrefetchTreeRequest {
+                    id: 3,
+                    path: PathBuf::from("/app/config"),
+                    max_file_size: 64 * 1024,
+                    max_entries: 10_000,
+                })),
+            ),
+            (
+                "client_close_fds",
+                ClientMessage::CloseFds(CloseFdsRequest {
+                    files: vec![4, 9],
+                    dirs: vec![12],
+                }),
+            ),
+        ]
+    }
+
+    /// [`DaemonMessage`]s, with the name of their fixture.
+    pub fn daemon_messages() -> Vec<(&'static str, DaemonMessage)> {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "app.default".parse().unwrap());
+
+        vec![
+            ("daemon_pong", DaemonMessage::Pong),
+            ("daemon_close", DaemonMessage::Close("bye".to_string())),
+            (
+                "daemon_tcp_data",
+                DaemonMessage::Tcp(DaemonTcp::Data(TcpData {
+                    connection_id: 7,
+                    bytes: b"hello".to_vec(),
+                })),
+            ),
+            (
+                "daemon_file_read",
+                DaemonMessage::File(FileResponse::Read(Ok(ReadFileResponse {
+                    bytes: b"127.0.0.1 localhost\n".to_vec(),
+                    read_amount: 20,
+                }))),
+            ),
+            (
+                "daemon_file_not_found",
+                DaemonMessage::File(FileResponse::Read(Err(ResponseError::NotFound(3)))),
+            ),
+            (
+                "daemon_get_addr_info",
+                DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Ok(DnsLookup(vec![
+                    LookupRecord {
+                        name: "example.com".to_string(),
+                        ip: "93.184.215.14".parse().unwrap(),
+                    },
+                ])))),
+            ),
+            (
+                "daemon_http_request_framed",
+                DaemonMessage::TcpSteal(DaemonTcp::HttpRequestFramed(HttpRequest {
+                    internal_request: InternalHttpRequest {
+                        method: Method::POST,
+                        uri: "/api/v1/users".parse().unwrap(),
+                        headers,
+                        version: Version::HTTP_11,
+                        body: InternalHttpBody::from_bytes(b"{}"),
+                    },
+                    connection_id: 7,
+                    request_id: 0,
+                    port: 80,
+                })),
+            ),
+            (
+                "daemon_new_http_connection",
+                DaemonMessage::TcpSteal(DaemonTcp::NewHttpConnection(NewTcpConnection {
+                    connection_id: 7,
+                    remote_address: "10.0.0.12".parse().unwrap(),
+                    destination_port: 80,
+                    source_port: 51234,
+                    local_address: "10.0.0.7".parse().unwrap(),
+                })),
+            ),
+            (
+                "daemon_tcp_steal_drained",
+                DaemonMessage::TcpSteal(DaemonTcp::Drained),
+            ),
+            (
+                "daemon_tcp_steal_port_reserved",
+                DaemonMessage::TcpSteal(DaemonTcp::PortReserved(Ok(40123))),
+            ),
+            (
+                "daemon_switch_framing_response",
+                DaemonMessage::SwitchFramingResponse(FrameLimits {
+                    max_frame_size: 64 * 1024 * 1024,
+                }),
+            ),
+            (
+                "daemon_clock_probe_response",
+                DaemonMessage::ClockProbeResponse(ClockProbeResponse {
+                    unix_time_nanos: 1_760_000_000_123_456_789,
+                }),
+            ),
+            (
+                "daemon_steal_read_only_session",
+                DaemonMessage::TcpSteal(DaemonTcp::SubscribeResult(Err(
+                    ResponseError::ReadOnlySession(BlockedAction::Steal(StealType::All(80))),
+                ))),
+            ),
+            (
+                "daemon_reachability_response",
+                DaemonMessage::ReachabilityResponse(ReachabilityResponse {
+                    results: vec![
+                        ReachabilityResult {
+                            address: "10.96.0.10:5432".parse().unwrap(),
+                            error: None,
+                        },
+                        ReachabilityResult {
+                            address: "[fd00::1]:443".parse().unwrap(),
+                            error: Some("timed out after 3000ms".into()),
+                        },
+                    ],
+                }),
+            ),
+            (
+                "daemon_file_unlink",
+                DaemonMessage::File(FileResponse::Unlink(Ok(()))),
+            ),
+            (
+                "daemon_file_rename",
+                DaemonMessage::File(FileResponse::Rename(Ok(()))),
+            ),
+            (
+                "daemon_file_chmod",
+                DaemonMessage::File(FileResponse::Chmod(Ok(()))),
+            ),
+            (
+                "daemon_file_read_whole",
+                DaemonMessage::File(FileResponse::ReadWhole(Ok(ReadWholeFileResponse {
+                    metadata: MetadataInternal {
+                        mode: 0o100644,
+                        size: 12,
+                        ..Default::default()
+                    },
+                    bytes: Some(b"debug: true\n".to_vec()),
+                }))),
+            ),
+            (
+                "daemon_file_glob",
+                DaemonMessage::File(FileResponse::Glob(Ok(GlobResponse {
+                    paths: vec![PathBuf::from("/etc/ssl/certs/ca.pem")],
+                    truncated: false,
+                }))),
+            ),
+            (
+                "daemon_file_truncate",
+                DaemonMessage::File(FileResponse::Truncate(Ok(()))),
+            ),
+            (
+                "daemon_file_symlink",
+                DaemonMessage::File(FileResponse::Symlink(Ok(()))),
+            ),
+            (
+                "daemon_file_link",
+                DaemonMessage::File(FileResponse::Link(Ok(()))),
+            ),
+            (
+                "daemon_file_utimens",
+                DaemonMessage::File(FileResponse::Utimens(Ok(()))),
+            ),
+            (
+                "daemon_file_read_dir_batch",
+                DaemonMessage::File(FileResponse::ReadDirBatch(Ok(ReadDirBatchResponse {
+                    fd: 5,
+                    dir_entries: vec![
+                        DirEntryInternal {
+                            inode: 1,
+                            position: 0,
+                            name: b".".to_vec(),
+                            file_type: 4,
+                        },
+                        DirEntryInternal {
+                            inode: 131,
+                            position: 1,
+                            name: b"postgresql.conf".to_vec(),
+                            file_type: 8,
+                        },
+                    ],
+                }))),
+            ),
+            (
+                "daemon_file_fsync",
+                DaemonMessage::File(FileResponse::Fsync(Ok(()))),
+            ),
+            (
+                "daemon_file_fallocate",
+                DaemonMessage::File(FileResponse::Fallocate(Ok(()))),
+            ),
+            (
+                "daemon_file_copy_file_range",
+                DaemonMessage::File(FileResponse::CopyFileRange(Ok(CopyFileRangeResponse {
+                    copied_amount: 65536,
+                }))),
+            ),
+            (
+                "daemon_tcp_outgoing_send_file",
+                DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::SendFile(Ok(SendFileResponse {
+                    sent_amount: 65536,
+                }))),
+            ),
+            (
+                "daemon_tcp_steal_send_file",
+                DaemonMessage::TcpSteal(DaemonTcp::SendFile(Err(ResponseError::NotFound(12)))),
+            ),
+            (
+                "daemon_file_readv",
+                DaemonMessage::File(FileResponse::ReadV(Ok(ReadVFileResponse {
+                    buffers: vec![b"header\n".to_vec(), b"body".to_vec()],
+                }))),
+            ),
+            (
+                "daemon_file_writev",
+                DaemonMessage::File(FileResponse::WriteV(Ok(WriteFileResponse {
+                    written_amount: 11,
+                }))),
+            ),
+            (
+                "daemon_file_read_chunk",
+                DaemonMessage::File(FileResponse::ReadChunk(Ok(ReadChunkResponse {
+                    sequence: 3,
+                    bytes: b"chunk".to_vec(),
+                    last: true,
+                }))),
+            ),
+            (
+                "daemon_file_statx",
+                DaemonMessage::File(FileResponse::Statx(Ok(StatxResponse {
+                    metadata: Box::new(StatxMetadataInternal {
+                        metadata: MetadataInternal {
+                            mode: 0o100600,
+                            size: 16 * 1024 * 1024,
+                            ..Default::default()
+                        },
+                        mask: 0xfff,
+                        birth_time: 1_700_000_000_123_456_789,
+                        mount_id: 412,
+                        ..Default::default()
+                    }),
+                }))),
+            ),
+            (
+                "daemon_file_get_xattr",
+                DaemonMessage::File(FileResponse::GetXattr(Ok(GetXattrResponse {
+                    value: b"\x01\x00\x00\x02\x00\x20\x00\x00".to_vec(),
+                }))),
+            ),
+            (
+                "daemon_file_list_xattr",
+                DaemonMessage::File(FileResponse::ListXattr(Ok(ListXattrResponse {
+                    names: vec![b"security.selinux".to_vec(), b"user.mime_type".to_vec()],
+                }))),
+            ),
+            (
+                "daemon_file_lock",
+                DaemonMessage::File(FileResponse::Lock(Err(ResponseError::RemoteIO(
+                    RemoteIOError {
+                        raw_os_error: Some(11),
+                        kind: ErrorKindInternal::WouldBlock,
+                    },
+                )))),
+            ),
+            (
+                "daemon_file_get_lock",
+                DaemonMessage::File(FileResponse::GetLock(Ok(GetLockResponse {
+                    conflict: Some(FileLockInternal {
+                        lock_type: LockTypeInternal::Exclusive,
+                        start: SeekFromInternal::Start(0x4000_0000),
+                        len: 1,
+                    }),
+                }))),
+            ),
+            (
+                "daemon_file_chown",
+                DaemonMessage::File(FileResponse::Chown(Err(ResponseError::NotImplemented))),
+            ),
+            (
+                "daemon_get_ifaddrs",
+                DaemonMessage::GetIfAddrsResponse(GetIfAddrsResponse(Ok(vec![
+                    InterfaceAddress {
+                        name: "lo".into(),
+                        flags: InterfaceFlags {
+                            up: true,
+                            running: true,
+                            loopback: true,
+                            ..Default::default()
+                        },
+                        address: "127.0.0.1".parse().unwrap(),
+                        netmask: Some("255.0.0.0".parse().unwrap()),
+                        broadcast: None,
+                    },
+                    InterfaceAddress {
+                        name: "eth0".into(),
+                        flags: InterfaceFlags {
+                            up: true,
+                            running: true,
+                            broadcast: true,
+                            multicast: true,
+                            ..Default::default()
+                        },
+                        address: "10.244.1.17".parse().unwrap(),
+                        netmask: Some("255.255.255.0".parse().unwrap()),
+                        broadcast: Some("10.244.1.255".parse().unwrap()),
+                    },
+                ]))),
+            ),
+            (
+                "daemon_file_read_dir_delta",
+                DaemonMessage::File(FileResponse::ReadDirDelta(Ok(ReadDirDeltaResponse {
+                    fd: 5,
+                    cookie: 0xc00c1f,
+                    delta: DirListingDelta::Changes {
+                        added: vec![DirEntryInternal {
+                            inode: 1042,
+                            position: 7,
+                            name: b"app-7.log".to_vec(),
+                            file_type: 8,
+                        }],
+                        removed: vec![b"app-1.log".to_vec()],
+                    },
+                }))),
+            ),
+            (
+                "daemon_file_canonicalize",
+                DaemonMessage::File(FileResponse::Canonicalize(Ok(CanonicalizePathResponse {
+                    path: PathBuf::from("/srv/releases/config.yaml"),
+                }))),
+            ),
+            (
+                "daemon_fd_usage",
+                DaemonMessage::FdUsage(FdUsage {
+                    files: 12,
+                    dirs: 3,
+                    sockets: 5,
+                    limit: Some(256),
+                }),
+            ),
+            (
+                "daemon_file_open_fd_limit_reached",
+                DaemonMessage::File(FileResponse::Open(Err(ResponseError::FdLimitReached {
+                    kind: FdKind::File,
+                    limit: 256,
+                }))),
+            ),
+            (
+                "daemon_watch_added",
+                DaemonMessage::Watch(DaemonWatch::Added(Ok(4))),
+            ),
+            (
+                "daemon_watch_event",
+                DaemonMessage::Watch(DaemonWatch::Event(WatchEvent {
+                    watch_id: 4,
+                    // `IN_MOVED_TO`
+                    mask: 0x80,
+                    cookie: 0x5eed,
+                    name: Some(PathBuf::from("settings.yaml")),
+                })),
+            ),
+            (
+                "daemon_file_follow_chunk",
+                DaemonMessage::File(FileResponse::FollowChunk(FollowChunkResponse {
+                    id: 2,
+                    bytes: b"GET /health 200\n".to_vec(),
+                    last: false,
+                })),
+            ),
+            (
+                "daemon_file_read_dir_plus_batch",
+                DaemonMessage::File(FileResponse::ReadDirPlusBatch(Ok(
+                    ReadDirPlusBatchResponse {
+                        fd: 5,
+                        dir_entries: vec![DirEntryPlusInternal {
+                            entry: DirEntryInternal {
+                                inode: 131,
+                                position: 1,
+                                name: b"postgresql.conf".to_vec(),
+                                file_type: 8,
+                            },
+                            metadata: MetadataInternal {
+                                inode: 131,
+                                mode: 0o100644,
+                                size: 29_344,
+                                ..Default::default()
+                            },
+                        }],
+                    },
+                ))),
+            ),
+            (
+                "daemon_file_prefetch_chunk",
+                DaemonMessage::File(FileResponse::PrefetchChunk(PrefetchChunkResponse {
+                    id: 3,
+                    dirs: vec![PrefetchDirInternal {
+                        path: PathBuf::from("/app/config"),
+                        metadata: MetadataInternal {
+                            inode: 130,
+                            mode: 0o40755,
+                            ..Default::default()
+                        },
+                        entries: vec![PrefetchEntryInternal {
+                            entry: DirEntryPlusInternal {
+                                entry: DirEntryInternal {
+                                    inode: 131,
+                                    position: 0,
+                                    name: b"app.yaml".to_vec(),
+                                    file_type: 8,
+                                },
+                                metadata: MetadataInternal {
+                                    inode: 131,
+                                    mode: 0o100644,
+                                    size: 12,
+                                    ..Default::default()
+                                },
+                            },
+                            contents: Some(b"port: 8080\n".to_vec()),
+                        }],
+                    }],
+                    last: true,
+                })),
+            ),
+            (
+                "daemon_idle_fds",
+                DaemonMessage::IdleFds(IdleFds {
+                    files: vec![4, 9],
+                    dirs: vec![12],
+                    idle_timeout_secs: 600,
+                }),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use actix_codec::{Decoder, Encoder};
+    use bytes::BytesMut;
+    use proptest::{
+        prelude::*,
+        test_runner::{TestCaseError, TestRunner},
+    };
+
+    use super::golden;
+    use crate::{ClientCodec, ClientMessage, DaemonCodec, DaemonMessage};
+
+    /// Set to rewrite the fixtures from [`golden`], only when adding new messages.
+    const BLESS_ENV: &str = "MIRRORD_PROTOCOL_BLESS";
+
+    /// The strategies for the whole message enums are deeply nested, and overflow the default
+    /// stack of test threads in debug builds.
+    const ROUND_TRIP_STACK_SIZE: usize = 128 * 1024 * 1024;
+
+    /// Runs `test` for every generated `T`, on a thread with a bigger stack.
+    fn run_round_trip<T: Arbitrary + 'static>(test: fn(T) -> Result<(), TestCaseError>) {
+        std::thread::Builder::new()
+            .stack_size(ROUND_TRIP_STACK_SIZE)
+            .spawn(move || {
+                TestRunner::default()
+                    .run(&any::<T>(), test)
+                    .unwrap_or_else(|error| panic!("{error}"))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn client_message_round_trip() {
+        run_round_trip(|message: ClientMessage| {
+            let mut buf = BytesMut::new();
+            ClientCodec::default()
+                .encode(message.clone(), &mut buf)
+                .unwrap();
+            let decoded = DaemonCodec::default().decode(&mut buf).unwrap();
+
+            prop_assert_eq!(decoded, Some(message));
+            prop_assert!(buf.is_empty());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn daemon_message_round_trip() {
+        run_round_trip(|message: DaemonMessage| {
+            let mut buf = BytesMut::new();
+            DaemonCodec::default()
+                .encode(message.clone(), &mut buf)
+                .unwrap();
+            let decoded = ClientCodec::default().decode(&mut buf).unwrap();
+
+            prop_assert_eq!(decoded, Some(message));
+            prop_assert!(buf.is_empty());
+
+            Ok(())
+        });
+    }
+
+    /// Checks `encoded` against the fixture `name`, or writes it when [`BLESS_ENV`] is set.
+    fn check_fixture(name: &str, encoded: &[u8]) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("{name}.bin"));
+
+        if std::env::var_os(BLESS_ENV).is_some() && !path.exists() {
+            std::fs::write(&path, encoded).unwrap();
+        }
+
+        let fixture = std::fs::read(&path)
+            .unwrap_or_else(|error| panic!("missing fixture {}: {error}", path.display()));
+        assert_eq!(encoded, fixture, "encoding of {name} changed");
+    }
+
+    #[test]
+    fn golden_client_messages() {
+        for (name, message) in golden::client_messages() {
+            let mut buf = BytesMut::new();
+            ClientCodec::default()
+                .encode(message.clone(), &mut buf)
+                .unwrap();
+            check_fixture(name, &buf);
+
+            let decoded = DaemonCodec::default().decode(&mut buf).unwrap();
+            assert_eq!(decoded, Some(message), "decoding of {name} changed");
+        }
+    }
+
+    #[test]
+    fn golden_daemon_messages() {
+        for (name, message) in golden::daemon_messages() {
+            let mut buf = BytesMut::new();
+            DaemonCodec::default()
+                .encode(message.clone(), &mut buf)
+                .unwrap();
+            check_fixture(name, &buf);
+
+            let decoded = ClientCodec::default().decode(&mut buf).unwrap();
+            assert_eq!(decoded, Some(message), "decoding of {name} changed");
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Messages that can gain new fields without breaking older peers.
+//!
+//! Adding a field to a regular message changes its encoding, so today it requires a new message
+//! (or variant), together with a [`VersionReq`](semver::VersionReq) that tells when the peer
+//! understands it. Messages declared with [`extensible_message!`] avoid this: they're encoded as
+//! a length-prefixed payload, made of the base fields followed by the extension fields.
+//!
+//! - A peer that knows fewer extensions skips the trailing fields it doesn't know about;
+//! - A peer that knows more extensions fills the ones missing from the payload with their
+//!   [`Default`].
+//!
+//! So extensions can only be **appended**, never removed or reordered, and their [`Default`] must
+//! mean "the peer didn't send this" (usually an [`Option`]).
+//!
+//! The envelope changes the encoding of the message, so an existing message can't be turned into
+//! an extensible one without a new variant, like any other breaking change.
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+
+pub use crate::extensible_message;
+
+/// Declares a struct whose encoding tolerates extension fields added after it was released.
+///
+/// See the [module docs](self) for the rules extensions must follow.
+///
+/// ```
+/// use mirrord_protocol::extensible_message;
+///
+/// extensible_message! {
+///     #[derive(Debug, Clone, PartialEq, Eq)]
+///     pub struct PortInfo {
+///         pub port: u16,
+///     }
+///     extensions {
+///         /// Added in a later version, older peers don't send it.
+///         pub protocol: Option<String>,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! extensible_message {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $( $(#[$field_meta:meta])* $field_vis:vis $field:ident : $field_ty:ty ),* $(,)?
+        }
+        extensions {
+            $( $(#[$ext_meta:meta])* $ext_vis:vis $ext:ident : $ext_ty:ty ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $( $(#[$field_meta])* $field_vis $field: $field_ty, )*
+            $( $(#[$ext_meta])* $ext_vis $ext: $ext_ty, )*
+        }
+
+        impl $crate::versioned::__private::Encode for $name {
+            fn encode<E: $crate::versioned::__private::Encoder>(
+                &self,
+                encoder: &mut E,
+            ) -> ::core::result::Result<(), $crate::versioned::__private::EncodeError> {
+                let mut payload = $crate::versioned::PayloadEncoder::default();
+                $( payload.field(&self.$field)?; )*
+                $( payload.field(&self.$ext)?; )*
+                payload.finish(encoder)
+            }
+        }
+
+        impl $crate::versioned::__private::Decode for $name {
+            fn decode<D: $crate::versioned::__private::Decoder>(
+                decoder: &mut D,
+            ) -> ::core::result::Result<Self, $crate::versioned::__private::DecodeError> {
+                let mut payload = $crate::versioned::PayloadDecoder::new(decoder)?;
+
+                Ok(Self {
+                    $( $field: payload.field()?, )*
+                    $( $ext: payload.extension()?, )*
+                })
+            }
+        }
+
+        impl<'de> $crate::versioned::__private::BorrowDecode<'de> for $name {
+            fn borrow_decode<D: $crate::versioned::__private::BorrowDecoder<'de>>(
+                decoder: &mut D,
+            ) -> ::core::result::Result<Self, $crate::versioned::__private::DecodeError> {
+                $crate::versioned::__private::Decode::decode(decoder)
+            }
+        }
+    };
+}
+
+/// Used by [`extensible_message!`], so that users don't need to depend on `bincode` themselves.
+#[doc(hidden)]
+pub mod __private {
+    pub use bincode::{
+        de::{BorrowDecoder, Decoder},
+        enc::Encoder,
+        error::{DecodeError, EncodeError},
+        BorrowDecode, Decode, Encode,
+    };
+}
+
+/// Encodes the fields of an [`extensible_message!`] into its payload.
+///
+/// The payload is always encoded with the standard configuration (the one used by the
+/// [`ProtocolCodec`](crate::ProtocolCodec)), independently of the outer encoder.
+#[derive(Default)]
+pub struct PayloadEncoder {
+    payload: Vec<u8>,
+}
+
+impl PayloadEncoder {
+    pub fn field<T: Encode>(&mut self, field: &T) -> Result<(), EncodeError> {
+        bincode::encode_into_std_write(field, &mut self.payload, bincode::config::standard())
+            .map(|_| ())
+    }
+
+    /// Writes the length-prefixed payload into `encoder`.
+    pub fn finish<E: Encoder>(self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.payload.encode(encoder)
+    }
+}
+
+/// Decodes the fields of an [`extensible_message!`] from its payload.
+pub struct PayloadDecoder {
+    payload: Vec<u8>,
+    /// How much of [`Self::payload`] was already decoded.
+    read: usize,
+}
+
+impl PayloadDecoder {
+    /// Reads the whole length-prefixed payload from `decoder`.
+    pub fn new<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self {
+            payload: Vec::decode(decoder)?,
+            read: 0,
+        })
+    }
+
+    /// Decodes a base field, which every version of the message carries.
+    ///
+    /// The whole payload was already read, so running out of bytes here means that the message is
+    /// malformed. [`DecodeError::UnexpectedEnd`] is not returned in this case, as the
+    /// [`ProtocolCodec`](crate::ProtocolCodec) would wait for more data that never comes.
+    pub fn field<T: Decode>(&mut self) -> Result<T, DecodeError> {
+        let remaining = self.payload.get(self.read..).unwrap_or_default();
+        let (field, read) = bincode::decode_from_slice(remaining, bincode::config::standard())
+            .map_err(|error| match error {
+                DecodeError::UnexpectedEnd { additional } => DecodeError::OtherString(format!(
+                    "extensible message payload is missing {additional} bytes"
+                )),
+                other => other,
+            })?;
+        self.read += read;
+
+        Ok(field)
+    }
+
+    /// Decodes an extension field, or returns its [`Default`] when the peer's version of the
+    /// message ends before it.
+    pub fn extension<T: Decode + Default>(&mut self) -> Result<T, DecodeError> {
+        if self.read >= self.payload.len() {
+            Ok(T::default())
+        } else {
+            self.field()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::{error::DecodeError, Decode, Encode};
+
+    /// The message as released first.
+    mod v1 {
+        crate::extensible_message! {
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct Message {
+                pub id: u64,
+                pub name: String,
+            }
+            extensions {}
+        }
+    }
+
+    /// The message after gaining extensions.
+    mod v2 {
+        crate::extensible_message! {
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct Message {
+                pub id: u64,
+                pub name: String,
+            }
+            extensions {
+                pub port: Option<u16>,
+                pub tags: Vec<String>,
+            }
+        }
+    }
+
+    /// Wraps the message with other data, to check that the envelope is fully consumed.
+    #[derive(Encode, Decode, Debug, PartialEq, Eq)]
+    struct Wrapper<T> {
+        message: T,
+        trailer: u32,
+    }
+
+    fn reencode<T: Encode, U: Decode>(message: T) -> Wrapper<U> {
+        let config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(
+            Wrapper {
+                message,
+                trailer: 0xdead,
+            },
+            config,
+        )
+        .unwrap();
+
+        let (decoded, read) = bincode::decode_from_slice(&encoded, config).unwrap();
+        assert_eq!(read, encoded.len());
+
+        decoded
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = v2::Message {
+            id: 7,
+            name: "app".to_string(),
+            port: Some(80),
+            tags: vec!["a".to_string()],
+        };
+
+        let decoded: Wrapper<v2::Message> = reencode(message.clone());
+        assert_eq!(decoded.message, message);
+        assert_eq!(decoded.trailer, 0xdead);
+    }
+
+    #[test]
+    fn older_peer_skips_extensions() {
+        let decoded: Wrapper<v1::Message> = reencode(v2::Message {
+            id: 7,
+            name: "app".to_string(),
+            port: Some(80),
+            tags: vec!["a".to_string()],
+        });
+
+        assert_eq!(
+            decoded.message,
+            v1::Message {
+                id: 7,
+                name: "app".to_string()
+            }
+        );
+        assert_eq!(decoded.trailer, 0xdead);
+    }
+
+    #[test]
+    fn newer_peer_defaults_extensions() {
+        let decoded: Wrapper<v2::Message> = reencode(v1::Message {
+            id: 7,
+            name: "app".to_string(),
+        });
+
+        assert_eq!(
+            decoded.message,
+            v2::Message {
+                id: 7,
+                name: "app".to_string(),
+                port: None,
+                tags: vec![],
+            }
+        );
+        assert_eq!(decoded.trailer, 0xdead);
+    }
+
+    #[test]
+    fn truncated_base_field_fails() {
+        let config = bincode::config::standard();
+        // Payload of a single byte: `id` is there, but `name` isn't.
+        let encoded = bincode::encode_to_vec(vec![7u8], config).unwrap();
+
+        assert!(matches!(
+            bincode::decode_from_slice::<v1::Message, _>(&encoded, config),
+            Err(DecodeError::OtherString(..))
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use std::{fmt, net::IpAddr};
 use bincode::{Decode, Encode};
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct NetworkConfiguration {
     pub ip: IpAddr,
     pub net_mask: IpAddr,
@@ -10,6 +11,7 @@ pub struct NetworkConfiguration {
 }
 
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ClientVpn {
     GetNetworkConfiguration,
     OpenSocket,
@@ -31,6 +33,7 @@ impl fmt::Debug for ClientVpn {
 
 /// Messages related to Tcp handler from server.
 #[derive(Encode, Decode, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub enum ServerVpn {
     NetworkConfiguration(NetworkConfiguration),
     Packet(Vec<u8>),
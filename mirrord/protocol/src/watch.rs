@@ -0,0 +1,70 @@
+//! Watching remote files and directories, for `inotify` in the local process.
+//!
+//! Hot-reload tools (webpack, air, nodemon and the like) wait for `inotify` events to pick up
+//! changes to the files they watch. Peers that support [`WATCH_VERSION`] can send
+//! [`LayerWatch::Add`] to watch a remote path, the agent responds with [`DaemonWatch::Added`],
+//! and then sends a [`DaemonWatch::Event`] for every event of the watch, until the watch is
+//! removed with [`LayerWatch::Remove`] (or the path is deleted).
+//!
+//! The events and the masks carry the `IN_*` bits of Linux `inotify`.
+use std::{path::PathBuf, sync::LazyLock};
+
+use bincode::{Decode, Encode};
+use semver::VersionReq;
+
+use crate::RemoteResult;
+
+/// Minimal mirrord-protocol version that allows
+/// [`ClientMessage::Watch`](crate::ClientMessage::Watch).
+pub static WATCH_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.55.0".parse().expect("Bad Identifier"));
+
+/// Identifies a watch of the client, assigned by the agent.
+///
+/// Watching the same file (or directory) again gives the same id.
+pub type WatchId = u64;
+
+/// `inotify_add_watch` of a remote path.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct AddWatchRequest {
+    pub path: PathBuf,
+    /// The `IN_*` bits of the events to watch, and of the flags of `inotify_add_watch` (e.g.
+    /// `IN_ONLYDIR`).
+    ///
+    /// Replaces the mask of the watch, when the path is already watched.
+    pub mask: u32,
+}
+
+/// An `inotify` event of a remote watch.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub struct WatchEvent {
+    pub watch_id: WatchId,
+    /// The `IN_*` bits of the event, `IN_IGNORED` when the watch is gone.
+    pub mask: u32,
+    /// Connects the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a rename.
+    pub cookie: u32,
+    /// Name of the file in the watched directory that the event is about.
+    pub name: Option<PathBuf>,
+}
+
+/// Watch requests of the client.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum LayerWatch {
+    /// Responded with [`DaemonWatch::Added`].
+    Add(AddWatchRequest),
+    /// `inotify_rm_watch`, not responded to. The agent sends the `IN_IGNORED` event of the watch
+    /// afterwards.
+    Remove(WatchId),
+}
+
+/// Watch messages of the agent.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
+pub enum DaemonWatch {
+    /// Response to [`LayerWatch::Add`].
+    Added(RemoteResult<WatchId>),
+    Event(WatchEvent),
+}
@@ -0,0 +1,143 @@
+//! Control sockets of the running sessions.
+//!
+//! Each internal proxy started by the CLI accepts [`ControlRequest`]s on a localhost socket, and
+//! advertises it in a [`SessionFile`], so that `mirrord toggle` and [`Session`](crate::Session)
+//! can find the running sessions.
+use std::{
+    env, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use mirrord_intproxy_protocol::{
+    codec::{self, CodecError},
+    control::{ControlRequest, SessionStatus},
+};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+/// How long we wait for the internal proxy to respond.
+pub const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Directory that holds the [`SessionFile`]s of the running sessions.
+pub fn sessions_dir() -> PathBuf {
+    env::temp_dir().join("mirrord-sessions")
+}
+
+/// What we need to know about a running session to control it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// Process id of the internal proxy, identifies the session.
+    pub pid: u32,
+    /// Address of the control socket of the internal proxy.
+    pub control_address: SocketAddr,
+    /// Target of the session, as displayed to the user.
+    pub target: Option<String>,
+    /// How far ahead of the local clock the clock of the target was when the session started, in
+    /// milliseconds. [`None`] if it was not measured.
+    #[serde(default)]
+    pub clock_offset_ms: Option<i64>,
+}
+
+impl SessionInfo {
+    /// Reads the [`SessionInfo`] of the session whose internal proxy has the given `pid`.
+    pub fn read(pid: u32) -> io::Result<Self> {
+        let contents = fs::read(session_path(&sessions_dir(), pid))?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+}
+
+/// Path of the [`SessionFile`] of the session with the given `pid`.
+fn session_path(dir: &Path, pid: u32) -> PathBuf {
+    dir.join(format!("{pid}.json"))
+}
+
+/// File that advertises a running session in [`sessions_dir`], removed when dropped.
+pub struct SessionFile(PathBuf);
+
+impl SessionFile {
+    pub fn create(info: &SessionInfo) -> io::Result<Self> {
+        let dir = sessions_dir();
+        fs::create_dir_all(&dir)?;
+
+        let path = session_path(&dir, info.pid);
+        fs::write(&path, serde_json::to_vec(info)?)?;
+
+        Ok(Self(path))
+    }
+}
+
+impl Drop for SessionFile {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.0) {
+            tracing::warn!(%error, path = %self.0.display(), "Failed to remove the session file");
+        }
+    }
+}
+
+/// Reads the [`SessionInfo`]s from the given directory, skipping the files we can't parse.
+pub fn read_sessions(dir: &Path) -> Vec<(PathBuf, SessionInfo)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let info = fs::read(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_slice(&contents).ok())?;
+            Some((path, info))
+        })
+        .collect()
+}
+
+/// Sends the [`ControlRequest`] to the internal proxy, and returns the [`SessionStatus`] that it
+/// responds with.
+pub async fn send_request(
+    address: SocketAddr,
+    request: ControlRequest,
+) -> Result<SessionStatus, CodecError> {
+    let stream = TcpStream::connect(address).await?;
+    let (mut encoder, mut decoder) =
+        codec::make_async_framed::<ControlRequest, SessionStatus>(stream);
+
+    encoder.send(&request).await?;
+    encoder.flush().await?;
+
+    decoder.receive().await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "internal proxy closed the connection",
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_sessions_skips_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let info = SessionInfo {
+            pid: 42,
+            control_address: "127.0.0.1:4242".parse().unwrap(),
+            target: Some("deployment/api".to_string()),
+            clock_offset_ms: Some(-1_250),
+        };
+        fs::write(
+            session_path(dir.path(), info.pid),
+            serde_json::to_vec(&info).unwrap(),
+        )
+        .unwrap();
+        fs::write(dir.path().join("43.json"), "garbage").unwrap();
+
+        let sessions = read_sessions(dir.path());
+
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions.first().map(|(_, session)| session), Some(&info));
+    }
+}
@@ -0,0 +1,55 @@
+use std::{io, path::PathBuf, process::ExitStatus};
+
+use mirrord_config::config::ConfigError;
+use mirrord_intproxy_protocol::codec::CodecError;
+use thiserror::Error;
+
+pub type SdkResult<T, E = SdkError> = core::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum SdkError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("failed to run `{}`: {1}", .0.display())]
+    Spawn(PathBuf, #[source] io::Error),
+
+    #[error("failed to read the output of mirrord: {0}")]
+    Output(#[source] io::Error),
+
+    #[error("failed to parse the output of mirrord: {0}")]
+    OutputParse(#[from] serde_json::Error),
+
+    #[error("mirrord failed ({status}): {stderr}")]
+    CommandFailed { status: ExitStatus, stderr: String },
+
+    #[error("mirrord failed to start the session: {0}")]
+    StartFailed(String),
+
+    #[error("no target matches `{0}`")]
+    TargetNotFound(String),
+
+    #[error("`{0}` matches multiple targets: {1}")]
+    TargetAmbiguous(String, String),
+
+    #[error("the session does not expose a control socket")]
+    ControlUnavailable,
+
+    #[error("failed to find the control socket of the session: {0}")]
+    SessionFile(#[source] io::Error),
+
+    #[error("control request failed: {0}")]
+    Control(#[from] CodecError),
+
+    #[error("timed out waiting for {0}")]
+    Timeout(&'static str),
+
+    #[error("failed to stop the session: {0}")]
+    Stop(#[source] io::Error),
+
+    #[error("failed to label the agent of the test session, `agent.labels` is not an object")]
+    TestLabel,
+
+    #[error("failed to write the config of the test session: {0}")]
+    TestConfig(#[source] io::Error),
+}
@@ -0,0 +1,195 @@
+#![warn(clippy::indexing_slicing)]
+
+//! Typed async API for driving mirrord sessions programmatically.
+//!
+//! Sessions are started with the `mirrord` binary, the same way the IDE extensions do (`mirrord
+//! ext`), and controlled through the control socket of their internal proxy, the same way
+//! `mirrord toggle` does.
+//!
+//! ```no_run
+//! # async fn example() -> mirrord_sdk::SdkResult<()> {
+//! use mirrord_sdk::Mirrord;
+//!
+//! let mirrord = Mirrord::default();
+//! let target = mirrord
+//!     .resolve_target(Some("mirrord.json".as_ref()), "deployment/api")
+//!     .await?;
+//!
+//! let session = mirrord
+//!     .session()
+//!     .config_file("mirrord.json")
+//!     .target(target)
+//!     .start()
+//!     .await?;
+//!
+//! let status = session.command("cargo").arg("test").status().await;
+//! println!("{:?}", session.status().await?);
+//!
+//! session.stop().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use mirrord_config::{
+    config::{ConfigContext, MirrordConfig},
+    LayerConfig, LayerFileConfig,
+};
+pub use mirrord_intproxy_protocol::control::{FeatureToggle, SessionStatus};
+use tokio::process::Command;
+
+pub mod control;
+mod error;
+mod session;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use error::{SdkError, SdkResult};
+pub use session::{Session, SessionBuilder, SessionEnv};
+
+/// Target that the sessions without a target use.
+const TARGETLESS: &str = "targetless";
+
+/// Loads the config file at `path`, the same way the CLI does, and verifies it.
+///
+/// The [`ConfigContext`] holds the warnings issued while loading the config.
+pub fn load_config<P: AsRef<Path>>(path: P) -> SdkResult<(LayerConfig, ConfigContext)> {
+    let mut context = ConfigContext::default();
+    let config = LayerFileConfig::from_path(path)?.generate_config(&mut context)?;
+    config.verify(&mut context)?;
+
+    Ok((config, context))
+}
+
+/// Entry point of the SDK, runs the `mirrord` binary.
+#[derive(Debug, Clone)]
+pub struct Mirrord {
+    binary: PathBuf,
+}
+
+impl Default for Mirrord {
+    /// Uses the `mirrord` binary from `PATH`.
+    fn default() -> Self {
+        Self::new("mirrord")
+    }
+}
+
+impl Mirrord {
+    /// Uses the `mirrord` binary at the given path.
+    pub fn new<P: Into<PathBuf>>(binary: P) -> Self {
+        Self {
+            binary: binary.into(),
+        }
+    }
+
+    /// Lists the targets available in the cluster, see `mirrord ls`.
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub async fn list_targets(
+        &self,
+        config_file: Option<&Path>,
+        namespace: Option<&str>,
+    ) -> SdkResult<Vec<String>> {
+        let mut command = Command::new(&self.binary);
+        command.arg("ls");
+        if let Some(config_file) = config_file {
+            command.arg("-f").arg(config_file);
+        }
+        if let Some(namespace) = namespace {
+            command.arg("-n").arg(namespace);
+        }
+
+        let output = command
+            .output()
+            .await
+            .map_err(|error| SdkError::Spawn(self.binary.clone(), error))?;
+
+        if !output.status.success() {
+            return Err(SdkError::CommandFailed {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    /// Resolves `target` to a target available in the cluster, e.g. `deployment/api` to
+    /// `deployment/api/container/api` when the deployment has a single container.
+    pub async fn resolve_target(
+        &self,
+        config_file: Option<&Path>,
+        target: &str,
+    ) -> SdkResult<String> {
+        if target == TARGETLESS {
+            return Ok(target.to_string());
+        }
+
+        let targets = self.list_targets(config_file, None).await?;
+        match_target(&targets, target)
+    }
+
+    /// Prepares a new session, see [`SessionBuilder::start`].
+    pub fn session(&self) -> SessionBuilder {
+        SessionBuilder::new(self.binary.clone())
+    }
+}
+
+/// Picks the target that `target` refers to, either exactly, or as the only container of a
+/// listed target.
+fn match_target(targets: &[String], target: &str) -> SdkResult<String> {
+    if targets.iter().any(|listed| listed == target) {
+        return Ok(target.to_string());
+    }
+
+    let prefix = format!("{target}/");
+    let mut matching = targets.iter().filter(|listed| listed.starts_with(&prefix));
+
+    match (matching.next(), matching.next()) {
+        (Some(listed), None) => Ok(listed.clone()),
+        (None, _) => Err(SdkError::TargetNotFound(target.to_string())),
+        (Some(first), Some(second)) => {
+            let targets = [first, second]
+                .into_iter()
+                .chain(matching)
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(SdkError::TargetAmbiguous(target.to_string(), targets))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn match_target_picks_exact_or_only_container() {
+        let targets = [
+            "deployment/api",
+            "deployment/api/container/api",
+            "pod/worker-7f9/container/worker",
+            "pod/db-0/container/db",
+            "pod/db-0/container/backup",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            match_target(&targets, "deployment/api").unwrap(),
+            "deployment/api"
+        );
+        assert_eq!(
+            match_target(&targets, "pod/worker-7f9").unwrap(),
+            "pod/worker-7f9/container/worker"
+        );
+        assert!(matches!(
+            match_target(&targets, "pod/db-0"),
+            Err(SdkError::TargetAmbiguous(..))
+        ));
+        assert!(matches!(
+            match_target(&targets, "pod/missing"),
+            Err(SdkError::TargetNotFound(..))
+        ));
+    }
+}
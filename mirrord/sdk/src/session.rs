@@ -0,0 +1,432 @@
+//! Sessions started with `mirrord ext`, see [`SessionBuilder`].
+use std::{
+    collections::HashMap, ffi::OsStr, future::Future, io, path::PathBuf, process::Stdio,
+    time::Duration,
+};
+
+use mirrord_intproxy_protocol::control::{ControlRequest, FeatureToggle, SessionStatus};
+use mirrord_progress::{
+    FinishedTaskMessage, ProgressMessage, WarningMessage, MIRRORD_PROGRESS_ENV,
+};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader, Lines},
+    process::{Child, Command},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    control::{self, SessionInfo, CONTROL_TIMEOUT},
+    error::{SdkError, SdkResult},
+};
+
+/// How long we wait for `mirrord ext` to exit once its internal proxy is gone.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often we look for the [`SessionFile`](control::SessionFile) of a session that was just
+/// started.
+const SESSION_FILE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What a process needs to run inside of a session, as reported by `mirrord ext`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionEnv {
+    /// Environment to set in the process.
+    pub environment: HashMap<String, String>,
+
+    /// Environment to unset in the process.
+    #[serde(default)]
+    pub env_to_unset: Vec<String>,
+
+    /// Path to the SIP-patched binary to run in place of [`SessionBuilder::executable`], only
+    /// on macOS.
+    #[serde(default)]
+    pub patched_path: Option<String>,
+
+    /// Whether the session goes through the mirrord operator.
+    #[serde(default)]
+    pub uses_operator: bool,
+
+    /// Process id of the internal proxy of the session.
+    ///
+    /// [`None`] when the session has no control socket, e.g. with an external proxy.
+    #[serde(default)]
+    pub intproxy_pid: Option<u32>,
+}
+
+impl SessionEnv {
+    /// Prepares a [`Command`] that runs `program` inside of the session.
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+        let mut command = Command::new(program);
+        self.apply(&mut command);
+        command
+    }
+
+    /// Makes the given [`Command`] run inside of the session.
+    pub fn apply(&self, command: &mut Command) {
+        command.envs(&self.environment);
+
+        for key in &self.env_to_unset {
+            command.env_remove(key);
+        }
+    }
+}
+
+/// Starts a [`Session`], see [`Mirrord::session`](crate::Mirrord::session).
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+    binary: PathBuf,
+    pub(crate) config_file: Option<PathBuf>,
+    target: Option<String>,
+    executable: Option<PathBuf>,
+    env: HashMap<String, String>,
+}
+
+impl SessionBuilder {
+    pub(crate) fn new(binary: PathBuf) -> Self {
+        Self {
+            binary,
+            config_file: None,
+            target: None,
+            executable: None,
+            env: HashMap::new(),
+        }
+    }
+
+    /// Config file of the session.
+    pub fn config_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// Target of the session, overrides the one from the config file.
+    pub fn target<S: Into<String>>(mut self, target: S) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Executable that will run inside of the session, so that it can be patched for SIP on
+    /// macOS, see [`SessionEnv::patched_path`].
+    pub fn executable<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.executable = Some(path.into());
+        self
+    }
+
+    /// Sets an environment variable for mirrord itself, e.g. to override a config value.
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Starts the session, returns once it's ready for the processes to run inside of it.
+    #[tracing::instrument(level = "trace", skip(self), fields(target = ?self.target), err)]
+    pub async fn start(self) -> SdkResult<Session> {
+        let mut command = Command::new(&self.binary);
+        command.arg("ext");
+        if let Some(config_file) = &self.config_file {
+            command.arg("-f").arg(config_file);
+        }
+        if let Some(target) = &self.target {
+            command.arg("-t").arg(target);
+        }
+        if let Some(executable) = &self.executable {
+            command.arg("-e").arg(executable);
+        }
+
+        command
+            .envs(&self.env)
+            .env(MIRRORD_PROGRESS_ENV, "json")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut process = command
+            .spawn()
+            .map_err(|error| SdkError::Spawn(self.binary.clone(), error))?;
+
+        let stdout = process.stdout.take().expect("stdout was piped");
+        let mut stderr = process.stderr.take().expect("stderr was piped");
+        // Drained in the background, so that mirrord never blocks on a full pipe.
+        let stderr: JoinHandle<String> = tokio::spawn(async move {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output).await;
+            output
+        });
+
+        let mut lines = BufReader::new(stdout).lines();
+        let mut warnings = Vec::new();
+
+        match read_progress(&mut lines, &mut warnings).await {
+            Ok(env) => {
+                tokio::spawn(async move {
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        tracing::trace!(line, "mirrord output after the session started");
+                    }
+                });
+
+                Ok(Session {
+                    process,
+                    env,
+                    warnings,
+                })
+            }
+            Err(SdkError::StartFailed(message)) => {
+                // The error report of the CLI goes to stderr, which is closed once it exits.
+                let _ = time::timeout(STOP_TIMEOUT, process.wait()).await;
+                let stderr = time::timeout(STOP_TIMEOUT, stderr)
+                    .await
+                    .ok()
+                    .and_then(Result::ok)
+                    .unwrap_or_default();
+
+                Err(match stderr.trim() {
+                    "" => SdkError::StartFailed(message),
+                    stderr => SdkError::StartFailed(format!("{message}: {stderr}")),
+                })
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Starts the session, runs `f` inside of it, and stops the session.
+    pub async fn run<F, Fut, T>(self, f: F) -> SdkResult<T>
+    where
+        F: FnOnce(SessionEnv) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let session = self.start().await?;
+        let output = f(session.env.clone()).await;
+        session.stop().await?;
+
+        Ok(output)
+    }
+}
+
+/// Reads the [`ProgressMessage`]s printed by `mirrord ext` until its root task finishes, and
+/// returns the [`SessionEnv`] that it reports.
+async fn read_progress<R>(lines: &mut Lines<R>, warnings: &mut Vec<String>) -> SdkResult<SessionEnv>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut root = None;
+
+    while let Some(line) = lines.next_line().await.map_err(SdkError::Output)? {
+        let message = match serde_json::from_str::<ProgressMessage>(&line) {
+            Ok(message) => message,
+            Err(error) => {
+                tracing::debug!(%error, line, "Skipping unexpected output of mirrord");
+                continue;
+            }
+        };
+
+        match message {
+            ProgressMessage::NewTask(task) if task.parent.is_none() => {
+                root.get_or_insert(task.name);
+            }
+            ProgressMessage::Warning(WarningMessage { message }) => {
+                tracing::warn!(message, "mirrord issued a warning");
+                warnings.push(message);
+            }
+            ProgressMessage::FinishedTask(FinishedTaskMessage {
+                name,
+                success,
+                message,
+            }) if root.as_ref() == Some(&name) => {
+                return match (success, message) {
+                    (true, Some(message)) => Ok(serde_json::from_str(&message)?),
+                    (true, None) => Err(SdkError::StartFailed(
+                        "mirrord did not report the environment of the session".to_string(),
+                    )),
+                    (false, message) => Err(SdkError::StartFailed(
+                        message.unwrap_or_else(|| "mirrord failed".to_string()),
+                    )),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Err(SdkError::StartFailed(
+        "mirrord exited before the session started".to_string(),
+    ))
+}
+
+/// A running mirrord session, started with [`SessionBuilder::start`].
+///
+/// Should be stopped with [`Session::stop`], dropping it only kills `mirrord ext`, and leaves the
+/// internal proxy to exit on its own once it's idle.
+#[derive(Debug)]
+pub struct Session {
+    /// The `mirrord ext` process, lives as long as the internal proxy.
+    process: Child,
+    env: SessionEnv,
+    warnings: Vec<String>,
+}
+
+impl Session {
+    pub fn env(&self) -> &SessionEnv {
+        &self.env
+    }
+
+    /// Warnings that mirrord issued while starting the session.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Prepares a [`Command`] that runs `program` inside of the session.
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+        self.env.command(program)
+    }
+
+    /// Reads the [`SessionInfo`] that the internal proxy advertises, waiting for it to be written
+    /// if the session was just started.
+    pub async fn info(&self) -> SdkResult<SessionInfo> {
+        let pid = self.env.intproxy_pid.ok_or(SdkError::ControlUnavailable)?;
+
+        let read = async {
+            loop {
+                match SessionInfo::read(pid) {
+                    Ok(info) => break Ok(info),
+                    // Not written yet, or only partially.
+                    Err(error)
+                        if matches!(
+                            error.kind(),
+                            io::ErrorKind::NotFound
+                                | io::ErrorKind::InvalidData
+                                | io::ErrorKind::UnexpectedEof
+                        ) =>
+                    {
+                        time::sleep(SESSION_FILE_POLL_INTERVAL).await
+                    }
+                    Err(error) => break Err(SdkError::SessionFile(error)),
+                }
+            }
+        };
+
+        time::timeout(CONTROL_TIMEOUT, read)
+            .await
+            .map_err(|_| SdkError::Timeout("the session file"))?
+    }
+
+    /// Current [`SessionStatus`], see `mirrord toggle status`.
+    pub async fn status(&self) -> SdkResult<SessionStatus> {
+        self.control(ControlRequest::Status).await
+    }
+
+    /// Toggles a feature of the session, and returns the resulting [`SessionStatus`].
+    pub async fn toggle(&self, toggle: FeatureToggle) -> SdkResult<SessionStatus> {
+        self.control(ControlRequest::Toggle(toggle)).await
+    }
+
+    async fn control(&self, request: ControlRequest) -> SdkResult<SessionStatus> {
+        let info = self.info().await?;
+
+        let status = time::timeout(
+            CONTROL_TIMEOUT,
+            control::send_request(info.control_address, request),
+        )
+        .await
+        .map_err(|_| SdkError::Timeout("the internal proxy"))??;
+
+        Ok(status)
+    }
+
+    /// Stops the internal proxy, which ends the session, and waits for `mirrord ext` to exit.
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub async fn stop(mut self) -> SdkResult<()> {
+        self.terminate_intproxy();
+
+        #[cfg(not(unix))]
+        self.process.start_kill().map_err(SdkError::Stop)?;
+
+        match time::timeout(STOP_TIMEOUT, self.process.wait()).await {
+            Ok(result) => {
+                result.map_err(SdkError::Stop)?;
+            }
+            Err(..) => {
+                tracing::debug!("mirrord did not exit in time, killing it");
+                self.process.kill().await.map_err(SdkError::Stop)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stops the internal proxy and kills `mirrord ext`, without waiting for them to exit.
+    ///
+    /// For when the session can't be stopped with [`Session::stop`], e.g. in [`Drop`].
+    pub fn start_kill(&mut self) -> io::Result<()> {
+        self.terminate_intproxy();
+        self.process.start_kill()
+    }
+
+    /// Sends `SIGTERM` to the internal proxy, `mirrord ext` exits once it's gone.
+    fn terminate_intproxy(&self) {
+        #[cfg(unix)]
+        if let Some(pid) = self
+            .env
+            .intproxy_pid
+            .and_then(|pid| i32::try_from(pid).ok())
+        {
+            use nix::{
+                sys::signal::{self, Signal},
+                unistd::Pid,
+            };
+
+            if let Err(error) = signal::kill(Pid::from_raw(pid), Signal::SIGTERM) {
+                tracing::debug!(%error, pid, "Failed to signal the internal proxy");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_progress_returns_env_of_root_task() {
+        let output = [
+            r#"{"type":"NewTask","name":"mirrord preparing to launch","parent":null}"#,
+            r#"{"type":"NewTask","name":"agent","parent":"mirrord preparing to launch"}"#,
+            r#"{"type":"Warning","message":"steal is paused"}"#,
+            r#"{"type":"FinishedTask","name":"agent","success":true,"message":null}"#,
+            "not a progress message",
+            r#"{"type":"FinishedTask","name":"mirrord preparing to launch","success":true,"message":"{\"environment\":{\"A\":\"1\"},\"patched_path\":null,\"env_to_unset\":[\"B\"],\"uses_operator\":false,\"intproxy_pid\":42}"}"#,
+        ]
+        .join("\n");
+
+        let mut lines = BufReader::new(output.as_bytes()).lines();
+        let mut warnings = Vec::new();
+        let env = read_progress(&mut lines, &mut warnings).await.unwrap();
+
+        assert_eq!(
+            env,
+            SessionEnv {
+                environment: HashMap::from([("A".to_string(), "1".to_string())]),
+                env_to_unset: vec!["B".to_string()],
+                patched_path: None,
+                uses_operator: false,
+                intproxy_pid: Some(42),
+            }
+        );
+        assert_eq!(warnings, ["steal is paused"]);
+    }
+
+    #[tokio::test]
+    async fn read_progress_fails_with_root_task() {
+        let output = [
+            r#"{"type":"NewTask","name":"mirrord preparing to launch","parent":null}"#,
+            r#"{"type":"FinishedTask","name":"mirrord preparing to launch","success":false,"message":"no such target"}"#,
+        ]
+        .join("\n");
+
+        let mut lines = BufReader::new(output.as_bytes()).lines();
+        let result = read_progress(&mut lines, &mut Vec::new()).await;
+
+        assert!(
+            matches!(result, Err(SdkError::StartFailed(message)) if message == "no such target")
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Support for integration tests that run each test in its own session, see [`TestSession`].
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use mirrord_sdk::{testing, Mirrord};
+//!
+//! let port = testing::reserve_port()?;
+//! let session = Mirrord::default()
+//!     .session()
+//!     .config_file("mirrord.json")
+//!     .target("deployment/api")
+//!     .start_test()
+//!     .await?;
+//!
+//! let status = session
+//!     .command("./server")
+//!     .env("PORT", port.port().to_string())
+//!     .status()
+//!     .await;
+//!
+//! // Also stopped if the test panics before getting here.
+//! session.stop().await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::BTreeSet,
+    env, fs, io,
+    net::{Ipv4Addr, TcpListener},
+    ops::Deref,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::SystemTime,
+};
+
+use mirrord_config::LayerFileConfig;
+use serde_json::{Map, Value};
+
+use crate::{
+    error::{SdkError, SdkResult},
+    session::{Session, SessionBuilder},
+};
+
+/// Label of the agents of the [`TestSession`]s, its value is the [`TestSession::label`].
+///
+/// Agents leaked by an aborted test run can be collected with
+/// `kubectl delete jobs -l mirrord-test-session`.
+pub const SESSION_LABEL: &str = "mirrord-test-session";
+
+/// Kubernetes limit on the length of label values.
+const LABEL_VALUE_MAX_LEN: usize = 63;
+
+/// How many ports we try before giving up in [`reserve_port`].
+const PORT_ATTEMPTS: usize = 64;
+
+/// Counts the [`TestSession`]s started by this process, makes their labels unique.
+static SESSION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Ports handed out by [`reserve_port`] and not released yet.
+static RESERVED_PORTS: Mutex<BTreeSet<u16>> = Mutex::new(BTreeSet::new());
+
+impl SessionBuilder {
+    /// Starts the session like [`SessionBuilder::start`], for the test running on this thread.
+    ///
+    /// The agent is labeled with [`SESSION_LABEL`], and the session is stopped when the returned
+    /// [`TestSession`] is dropped, even if the test panics.
+    pub async fn start_test(mut self) -> SdkResult<TestSession> {
+        let test = std::thread::current()
+            .name()
+            .unwrap_or_default()
+            .to_string();
+        let started_at = SystemTime::UNIX_EPOCH
+            .elapsed()
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let label = session_label(
+            &test,
+            started_at,
+            process::id(),
+            SESSION_COUNTER.fetch_add(1, Ordering::Relaxed),
+        );
+
+        let mut config = match &self.config_file {
+            Some(path) => LayerFileConfig::json_from_path(path)?,
+            None => Value::Object(Map::new()),
+        };
+        label_agent(&mut config, &label).ok_or(SdkError::TestLabel)?;
+
+        let config_file = TestConfigFile::create(&label, &config).map_err(SdkError::TestConfig)?;
+        self.config_file = Some(config_file.0.clone());
+
+        tracing::debug!(test, label, "Starting a test session");
+        let session = self.start().await?;
+
+        Ok(TestSession {
+            session: Some(session),
+            label,
+            _config_file: config_file,
+        })
+    }
+}
+
+/// A [`Session`] of a single test, stopped when dropped.
+///
+/// Prefer [`TestSession::stop`] when the test finishes normally, dropping it can't wait for the
+/// session to end.
+#[derive(Debug)]
+pub struct TestSession {
+    /// Only [`None`] once stopped.
+    session: Option<Session>,
+    label: String,
+    /// The session reads it until it ends, so it goes after [`TestSession::session`].
+    _config_file: TestConfigFile,
+}
+
+impl TestSession {
+    /// Value of the [`SESSION_LABEL`] of the agent of this session.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Stops the session, see [`Session::stop`].
+    pub async fn stop(mut self) -> SdkResult<()> {
+        match self.session.take() {
+            Some(session) => session.stop().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for TestSession {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        self.session
+            .as_ref()
+            .expect("session is only taken when the test session is consumed")
+    }
+}
+
+impl Drop for TestSession {
+    fn drop(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            tracing::debug!(
+                label = self.label,
+                "Killing a test session that was not stopped"
+            );
+
+            if let Err(error) = session.start_kill() {
+                tracing::warn!(%error, label = self.label, "Failed to kill the test session");
+            }
+        }
+    }
+}
+
+/// Config file derived for a [`TestSession`], removed when dropped.
+#[derive(Debug)]
+struct TestConfigFile(PathBuf);
+
+impl TestConfigFile {
+    fn create(label: &str, config: &Value) -> io::Result<Self> {
+        let path = env::temp_dir().join(format!("mirrord-test-{label}.json"));
+        fs::write(&path, serde_json::to_vec(config)?)?;
+
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TestConfigFile {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.0) {
+            tracing::warn!(%error, path = %self.0.display(), "Failed to remove the test config");
+        }
+    }
+}
+
+/// Unique value of the [`SESSION_LABEL`], made of the name of the `test`, and of what identifies
+/// the session in this run.
+///
+/// Trimmed to a valid label value, keeping the end of the test name, which is the most specific.
+fn session_label(test: &str, started_at: u64, pid: u32, counter: u64) -> String {
+    let suffix = format!("-{started_at}-{pid}-{counter}");
+
+    let name = test
+        .replace("::", ".")
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '-',
+        })
+        .collect::<String>();
+    let skip = name
+        .chars()
+        .count()
+        .saturating_sub(LABEL_VALUE_MAX_LEN - suffix.len());
+    let name = name
+        .chars()
+        .skip(skip)
+        .skip_while(|c| !c.is_ascii_alphanumeric())
+        .collect::<String>();
+
+    match name.is_empty() {
+        true => format!("test{suffix}"),
+        false => format!("{name}{suffix}"),
+    }
+}
+
+/// Sets the [`SESSION_LABEL`] in `agent.labels` of the `config`, [`None`] if the config has
+/// something else there.
+fn label_agent(config: &mut Value, label: &str) -> Option<()> {
+    config
+        .as_object_mut()?
+        .entry("agent")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()?
+        .entry("labels")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()?
+        .insert(SESSION_LABEL.to_string(), label.into());
+
+    Some(())
+}
+
+/// A local port reserved with [`reserve_port`], released when dropped.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReservedPort(u16);
+
+impl ReservedPort {
+    /// The reserved port.
+    pub fn port(&self) -> u16 {
+        self.0
+    }
+}
+
+impl Drop for ReservedPort {
+    fn drop(&mut self) {
+        RESERVED_PORTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.0);
+    }
+}
+
+/// Finds a free local port, that no other test in this process gets until the [`ReservedPort`]
+/// is dropped.
+///
+/// The port is not held open, so that the test can bind it, which means that a process other
+/// than the tests may still take it in the meantime.
+pub fn reserve_port() -> io::Result<ReservedPort> {
+    // Tests that panicked while holding the lock don't matter here.
+    let mut reserved = RESERVED_PORTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    // Kept open until we're done, so that we're not given the same ports again.
+    let mut listeners = Vec::new();
+    for _ in 0..PORT_ATTEMPTS {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+
+        if reserved.insert(port) {
+            return Ok(ReservedPort(port));
+        }
+
+        listeners.push(listener);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        "all the free ports are reserved by other tests",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn session_label_is_valid_and_unique() {
+        let first = session_label("api::tests::steals_http", 1_760_000_000, 4242, 0);
+        let second = session_label("api::tests::steals_http", 1_760_000_000, 4242, 1);
+        assert_eq!(first, "api.tests.steals_http-1760000000-4242-0");
+        assert_ne!(first, second);
+
+        let long = session_label(&"very_long_module::".repeat(8), 1_760_000_000, 4242, 7);
+        assert!(long.len() <= LABEL_VALUE_MAX_LEN);
+        assert!(long.starts_with(|c: char| c.is_ascii_alphanumeric()));
+        assert!(long.ends_with("-1760000000-4242-7"));
+
+        assert_eq!(
+            session_label("tokio-runtime-worker", 1, 2, 3),
+            "tokio-runtime-worker-1-2-3"
+        );
+        assert_eq!(session_label("", 1, 2, 3), "test-1-2-3");
+    }
+
+    #[test]
+    fn label_agent_keeps_the_config() {
+        let mut config = serde_json::json!({
+            "target": "deployment/api",
+            "agent": { "labels": { "team": "payments" } },
+        });
+        label_agent(&mut config, "label").unwrap();
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "target": "deployment/api",
+                "agent": { "labels": { "team": "payments", SESSION_LABEL: "label" } },
+            })
+        );
+
+        let mut config = serde_json::json!({ "agent": { "labels": null } });
+        assert!(label_agent(&mut config, "label").is_none());
+    }
+
+    #[test]
+    fn test_config_file_is_removed_on_drop() {
+        let file = TestConfigFile::create("removed-on-drop", &serde_json::json!({})).unwrap();
+        let path = file.0.clone();
+        assert!(path.exists());
+
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn reserved_ports_are_not_reused_until_dropped() {
+        let ports = (0..16)
+            .map(|_| reserve_port().unwrap())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(ports.len(), 16);
+
+        let port = ports.first().unwrap().port();
+        drop(ports);
+        assert!(!RESERVED_PORTS.lock().unwrap().contains(&port));
+    }
+}
@@ -105,6 +105,7 @@ fn block_steal_without_qualifiers() -> PolicyTestCase {
                 target_path: None,
                 selector: None,
                 block: vec![BlockedFeature::Steal],
+                require_read_only: false,
             },
         ),
         service_b_can_steal: No,
@@ -123,6 +124,7 @@ fn block_steal_with_path_pattern() -> PolicyTestCase {
                 target_path: Some("*-service-a*".into()),
                 selector: None,
                 block: vec![BlockedFeature::Steal],
+                require_read_only: false,
             },
         ),
         service_b_can_steal: EvenWithoutFilter,
@@ -141,6 +143,7 @@ fn block_unfiltered_steal_with_path_pattern() -> PolicyTestCase {
                 target_path: Some("*-service-a*".into()),
                 selector: None,
                 block: vec![BlockedFeature::StealWithoutFilter],
+                require_read_only: false,
             },
         ),
         service_b_can_steal: EvenWithoutFilter,
@@ -159,6 +162,7 @@ fn block_unfiltered_steal_with_deployment_path_pattern() -> PolicyTestCase {
                 target_path: Some("deploy/*service-a*".into()),
                 selector: None,
                 block: vec![BlockedFeature::StealWithoutFilter],
+                require_read_only: false,
             },
         ),
         service_a_can_steal: OnlyWithFilter,
@@ -183,6 +187,7 @@ fn block_steal_with_label_selector() -> PolicyTestCase {
                     )])),
                 }),
                 block: vec![BlockedFeature::Steal],
+                require_read_only: false,
             },
         ),
         service_b_can_steal: EvenWithoutFilter,
@@ -208,6 +213,7 @@ fn block_steal_with_unmatching_policy() -> PolicyTestCase {
                     )])),
                 }),
                 block: vec![BlockedFeature::Steal],
+                require_read_only: false,
             },
         ),
         service_b_can_steal: EvenWithoutFilter,